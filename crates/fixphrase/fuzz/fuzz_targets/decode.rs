@@ -0,0 +1,20 @@
+//! Decode arbitrary strings: decoding must never panic, and any phrase it
+//! accepts must name a valid coordinate that its canonical phrase decodes to
+//! as well.
+//!
+//! Run from `crates/fixphrase` with `cargo +nightly fuzz run decode`.
+
+#![no_main]
+
+use fixphrase::FixPhrase;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|phrase: &str| {
+    if let Ok((lat, lon, _, canonical)) = FixPhrase::decode(phrase) {
+        assert!((-90.0..=90.0).contains(&lat), "latitude {} from {:?}", lat, phrase);
+        assert!((-180.0..=180.0).contains(&lon), "longitude {} from {:?}", lon, phrase);
+        let (canonical_lat, canonical_lon, _, _) =
+            FixPhrase::decode(&canonical).expect("canonical phrase decodes");
+        assert_eq!((lat, lon), (canonical_lat, canonical_lon));
+    }
+});
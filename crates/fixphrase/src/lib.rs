@@ -145,6 +145,12 @@ impl FixPhrase {
         let longitude =
             (lon.parse::<f64>().map_err(|_| FixPhraseError::InvalidPhrase)? / divby) - 180.0;
 
+        // Each group has room for more values than the coordinate range uses,
+        // so a phrase can name a point past the poles or the antimeridian.
+        if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            return Err(FixPhraseError::InvalidPhrase);
+        }
+
         let accuracy = match divby {
             10.0 => 0.1,
             100.0 => 0.01,
@@ -157,6 +163,8 @@ impl FixPhrase {
 
 #[cfg(test)]
 mod tests {
+    use proptest::prelude::*;
+
     use super::*;
 
     #[test]
@@ -203,4 +211,105 @@ mod tests {
         assert!((decoded_lon - lon).abs() < accuracy);
         assert_eq!(phrase, "corrode ground slacks washbasin");
     }
+
+    #[test]
+    fn test_hyphenated_words_round_trip() {
+        // -90, -64.1171 encodes with "t-shirt" as its last word.
+        let phrase = FixPhrase::encode(-90.0, -64.1171).unwrap();
+        assert!(phrase.ends_with(" t-shirt"), "{}", phrase);
+
+        let (lat, lon, accuracy, canonical) = FixPhrase::decode(&phrase).unwrap();
+        assert_eq!(accuracy, 0.0001);
+        assert!((lat + 90.0).abs() < accuracy);
+        assert!((lon + 64.1171).abs() < accuracy);
+        assert_eq!(canonical, phrase);
+    }
+
+    #[test]
+    fn test_decode_rejects_coordinates_out_of_range() {
+        // A valid first word, but one naming latitude 90.1.
+        assert!(matches!(FixPhrase::decode("driver dimmer"), Err(FixPhraseError::InvalidPhrase)));
+    }
+
+    /// Half the finest step: a full phrase is the coordinate rounded to four
+    /// decimal places, plus a little floating-point slack.
+    const FULL_PHRASE_TOLERANCE: f64 = 0.00005 + 1e-9;
+
+    fn latitude() -> impl Strategy<Value = f64> {
+        prop_oneof![
+            -90.0..=90.0f64,
+            prop::sample::select(vec![-90.0, 90.0, 89.99995, -89.99995, 89.99994, 0.0, -0.0]),
+        ]
+    }
+
+    fn longitude() -> impl Strategy<Value = f64> {
+        prop_oneof![
+            -180.0..=180.0f64,
+            prop::sample::select(vec![-180.0, 180.0, 179.99995, -179.99995, 179.99994, 0.0]),
+        ]
+    }
+
+    /// Up to six words from the wordlist, in any order, occasionally
+    /// misspelled.
+    fn wordlist_phrase() -> impl Strategy<Value = String> {
+        prop::collection::vec(
+            (0..WORDLIST.len(), any::<bool>()).prop_map(|(i, typo)| {
+                if typo {
+                    format!("{}x", WORDLIST[i])
+                } else {
+                    WORDLIST[i].to_uppercase()
+                }
+            }),
+            0..6,
+        )
+        .prop_map(|words| words.join(" "))
+    }
+
+    proptest! {
+        #[test]
+        fn prop_full_phrase_round_trips(lat in latitude(), lon in longitude()) {
+            let phrase = FixPhrase::encode(lat, lon).unwrap();
+            let (decoded_lat, decoded_lon, accuracy, canonical) =
+                FixPhrase::decode(&phrase).unwrap();
+
+            prop_assert_eq!(accuracy, 0.0001);
+            prop_assert!((decoded_lat - lat).abs() <= FULL_PHRASE_TOLERANCE, "{} -> {}", lat, decoded_lat);
+            prop_assert!((decoded_lon - lon).abs() <= FULL_PHRASE_TOLERANCE, "{} -> {}", lon, decoded_lon);
+            prop_assert_eq!(canonical, phrase);
+        }
+
+        #[test]
+        fn prop_shortened_phrase_is_within_accuracy(lat in latitude(), lon in longitude(), words in 2..4usize) {
+            let phrase = FixPhrase::encode(lat, lon).unwrap();
+            let shortened: Vec<&str> = phrase.split(' ').take(words).collect();
+            let (decoded_lat, decoded_lon, accuracy, _) =
+                FixPhrase::decode(&shortened.join(" ")).unwrap();
+
+            // Dropping words truncates the rounded coordinate.
+            prop_assert!((decoded_lat - lat).abs() < accuracy + FULL_PHRASE_TOLERANCE);
+            prop_assert!((decoded_lon - lon).abs() < accuracy + FULL_PHRASE_TOLERANCE);
+        }
+
+        #[test]
+        fn prop_decode_returns_valid_coordinates(phrase in wordlist_phrase()) {
+            if let Ok((lat, lon, _, canonical)) = FixPhrase::decode(&phrase) {
+                prop_assert!((-90.0..=90.0).contains(&lat), "latitude {} from {:?}", lat, phrase);
+                prop_assert!((-180.0..=180.0).contains(&lon), "longitude {} from {:?}", lon, phrase);
+                // The canonical phrase names the same place.
+                let (lat2, lon2, _, _) = FixPhrase::decode(&canonical).unwrap();
+                prop_assert_eq!((lat, lon), (lat2, lon2));
+            }
+        }
+
+        #[test]
+        fn prop_decode_never_panics(phrase in "\\PC*") {
+            let _ = FixPhrase::decode(&phrase);
+        }
+
+        #[test]
+        fn prop_encode_rejects_out_of_range(lat in any::<f64>(), lon in any::<f64>()) {
+            let valid = (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon);
+            prop_assert_eq!(FixPhrase::encode(lat, lon).is_ok(), valid);
+        }
+    }
 }
@@ -2007,7 +2007,7 @@ pub static WORDLIST: [&str; 7610] = [
     "drone",
     "drool",
     "droop",
-    "drop - down",
+    "drop-down",
     "dropbox",
     "dropkick",
     "droplet",
@@ -2489,7 +2489,7 @@ pub static WORDLIST: [&str; 7610] = [
     "failing",
     "falcon",
     "fall",
-    "0",
+    "false",
     "falsify",
     "fame",
     "familiar",
@@ -2526,7 +2526,7 @@ pub static WORDLIST: [&str; 7610] = [
     "feel",
     "feisty",
     "feline",
-    "felt - tip",
+    "felt-tip",
     "feminine",
     "feminism",
     "feminist",
@@ -6638,7 +6638,7 @@ pub static WORDLIST: [&str; 7610] = [
     "synthetic",
     "syrup",
     "system",
-    "t - shirt",
+    "t-shirt",
     "tabasco",
     "tabby",
     "tableful",
@@ -9,12 +9,13 @@ use diesel::sqlite::SqliteConnection;
 use neems_api::{
     models::UserInput,
     orm::{
+        api_key::{create_api_key, delete_api_key, get_user_api_keys, rotate_api_key},
         company::get_company_by_id,
         entity_activity::get_created_at,
         role::get_role_by_name,
         user::{
-            delete_user_with_cleanup, get_user, get_user_by_email, insert_user, list_all_users,
-            update_user,
+            delete_user_with_cleanup, get_user, get_user_by_email, insert_service_account,
+            insert_user, list_all_users, set_must_change_password, update_user,
         },
         user_role::{
             assign_user_role_by_name, get_user_roles, remove_all_user_roles,
@@ -44,6 +45,39 @@ pub enum UserAction {
         #[arg(long, help = "TOTP secret (optional)")]
         totp_secret: Option<String>,
     },
+    #[command(about = "Add a service account, which authenticates with API keys only")]
+    AddServiceAccount {
+        #[arg(short, long, help = "Account name (used in place of an email address)")]
+        name: String,
+        #[arg(short, long, help = "Company ID or name")]
+        company_id: String,
+    },
+    #[command(about = "Issue an API key for a service account")]
+    AddApiKey {
+        #[arg(short, long, help = "Service account name")]
+        name: String,
+        #[arg(short, long, help = "What the key is for")]
+        description: Option<String>,
+    },
+    #[command(about = "List a service account's API keys")]
+    LsApiKeys {
+        #[arg(short, long, help = "Service account name")]
+        name: String,
+    },
+    #[command(about = "Replace an API key with a new one; the old key stops working")]
+    RotateApiKey {
+        #[arg(short, long, help = "Service account name")]
+        name: String,
+        #[arg(short, long, help = "API key ID")]
+        key_id: i32,
+    },
+    #[command(about = "Revoke an API key")]
+    RmApiKey {
+        #[arg(short, long, help = "Service account name")]
+        name: String,
+        #[arg(short, long, help = "API key ID")]
+        key_id: i32,
+    },
     #[command(about = "Change user password")]
     ChangePassword {
         #[arg(short, long, help = "Email address")]
@@ -55,6 +89,13 @@ pub enum UserAction {
         )]
         password: Option<String>,
     },
+    #[command(about = "Require a user to change their password before doing anything else")]
+    ForcePasswordChange {
+        #[arg(short, long, help = "Email address")]
+        email: String,
+        #[arg(long, help = "Cancel a pending forced password change instead")]
+        clear: bool,
+    },
     #[command(about = "List users, optionally filtered by search term")]
     Ls {
         #[arg(help = "Search term (regex by default, use -F for fixed string)")]
@@ -125,9 +166,28 @@ pub fn handle_user_command_with_conn(
             let resolved_company_id = resolve_company_id(conn, &company_id)?;
             add_user_impl(conn, &email, password, resolved_company_id, totp_secret, admin_user_id)?;
         }
+        UserAction::AddServiceAccount { name, company_id } => {
+            let resolved_company_id = resolve_company_id(conn, &company_id)?;
+            add_service_account_impl(conn, &name, resolved_company_id, admin_user_id)?;
+        }
+        UserAction::AddApiKey { name, description } => {
+            add_api_key_impl(conn, &name, description)?;
+        }
+        UserAction::LsApiKeys { name } => {
+            list_api_keys_impl(conn, &name)?;
+        }
+        UserAction::RotateApiKey { name, key_id } => {
+            rotate_api_key_impl(conn, &name, key_id)?;
+        }
+        UserAction::RmApiKey { name, key_id } => {
+            remove_api_key_impl(conn, &name, key_id)?;
+        }
         UserAction::ChangePassword { email, password } => {
             change_password_impl(conn, &email, password, admin_user_id)?;
         }
+        UserAction::ForcePasswordChange { email, clear } => {
+            force_password_change_impl(conn, &email, !clear, admin_user_id)?;
+        }
         UserAction::Ls { search_term, fixed_string } => {
             list_users_impl(conn, search_term, fixed_string)?;
         }
@@ -197,12 +257,127 @@ pub fn add_user_impl(
     Ok(())
 }
 
+pub fn add_service_account_impl(
+    conn: &mut SqliteConnection,
+    name: &str,
+    company_id: i32,
+    admin_user_id: i32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if name.is_empty() || name.contains('@') || name.chars().any(char::is_whitespace) {
+        return Err("Service account names must be non-empty, without whitespace or '@'".into());
+    }
+    if get_user_by_email(conn, name)?.is_some() {
+        return Err(format!("User '{}' already exists", name).into());
+    }
+
+    let account = insert_service_account(conn, name, company_id, Some(admin_user_id))?;
+
+    println!("Service account created successfully!");
+    println!("ID: {}", account.id);
+    println!("Name: {}", account.email);
+    println!("Company ID: {}", account.company_id);
+    println!("Add roles with `user add-role`, then issue a key with `user add-api-key`.");
+
+    Ok(())
+}
+
+/// Looks up a service account by name
+fn get_service_account(
+    conn: &mut SqliteConnection,
+    name: &str,
+) -> Result<neems_api::models::User, Box<dyn std::error::Error>> {
+    let user = get_user_by_email(conn, name)?
+        .ok_or_else(|| format!("Service account '{}' not found", name))?;
+    if !user.service_account {
+        return Err(format!("User '{}' is not a service account", name).into());
+    }
+    Ok(user)
+}
+
+pub fn add_api_key_impl(
+    conn: &mut SqliteConnection,
+    name: &str,
+    description: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let account = get_service_account(conn, name)?;
+    let (api_key, key) = create_api_key(conn, account.id, description)?;
+
+    println!("API key {} created for {}. It won't be shown again:", api_key.id, name);
+    println!("{}", key);
+    Ok(())
+}
+
+pub fn list_api_keys_impl(
+    conn: &mut SqliteConnection,
+    name: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let account = get_service_account(conn, name)?;
+    let api_keys = get_user_api_keys(conn, account.id)?;
+
+    if api_keys.is_empty() {
+        println!("No API keys found.");
+    } else {
+        println!("API keys for {}:", name);
+        for api_key in api_keys {
+            println!(
+                "  ID: {}, Prefix: {}, Created: {}, Rotated: {}, Description: {}",
+                api_key.id,
+                api_key.prefix,
+                api_key.created_at,
+                api_key
+                    .rotated_at
+                    .map(|dt| dt.to_string())
+                    .unwrap_or_else(|| "Never".to_string()),
+                api_key.description.as_deref().unwrap_or("")
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn rotate_api_key_impl(
+    conn: &mut SqliteConnection,
+    name: &str,
+    key_id: i32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let account = get_service_account(conn, name)?;
+    let (_, key) = rotate_api_key(conn, account.id, key_id)?
+        .ok_or_else(|| format!("API key {} not found for {}", key_id, name))?;
+
+    println!(
+        "API key {} rotated; the old key no longer works. It won't be shown again:",
+        key_id
+    );
+    println!("{}", key);
+    Ok(())
+}
+
+pub fn remove_api_key_impl(
+    conn: &mut SqliteConnection,
+    name: &str,
+    key_id: i32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let account = get_service_account(conn, name)?;
+    if !delete_api_key(conn, account.id, key_id)? {
+        return Err(format!("API key {} not found for {}", key_id, name).into());
+    }
+
+    println!("API key {} revoked for {}", key_id, name);
+    Ok(())
+}
+
 pub fn change_password_impl(
     conn: &mut SqliteConnection,
     email: &str,
     password: Option<String>,
     admin_user_id: i32,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let user = get_user_by_email(conn, email)?
+        .ok_or_else(|| format!("User with email '{}' not found", email))?;
+    if user.service_account {
+        return Err("Service accounts have no password; use API keys".into());
+    }
+
     let password = match password {
         Some(p) => p,
         None => prompt_for_password()?,
@@ -210,14 +385,30 @@ pub fn change_password_impl(
 
     let password_hash =
         hash_password(&password).map_err(|e| format!("Failed to hash password: {}", e))?;
-    let user = get_user_by_email(conn, email)?
-        .ok_or_else(|| format!("User with email '{}' not found", email))?;
     update_user(conn, user.id, None, Some(password_hash), None, None, Some(admin_user_id))?;
 
     println!("Password changed successfully for user: {}", email);
     Ok(())
 }
 
+pub fn force_password_change_impl(
+    conn: &mut SqliteConnection,
+    email: &str,
+    must_change: bool,
+    admin_user_id: i32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let user = get_user_by_email(conn, email)?
+        .ok_or_else(|| format!("User with email '{}' not found", email))?;
+    set_must_change_password(conn, user.id, must_change, Some(admin_user_id))?;
+
+    if must_change {
+        println!("User {} must change their password before doing anything else", email);
+    } else {
+        println!("Forced password change cleared for user: {}", email);
+    }
+    Ok(())
+}
+
 pub fn list_users_impl(
     conn: &mut SqliteConnection,
     search_term: Option<String>,
@@ -257,8 +448,19 @@ pub fn list_users_impl(
                 .unwrap_or_else(|_| "Error loading roles".to_string());
 
             println!(
-                "  ID: {}, Email: {}, Company ID: {}, Created: {}, Roles: {}",
-                user.id, user.email, user.company_id, created_at, roles
+                "  ID: {}, Email: {}, Company ID: {}, Created: {}, Roles: {}{}",
+                user.id,
+                user.email,
+                user.company_id,
+                created_at,
+                roles,
+                if user.service_account {
+                    " (service account)"
+                } else if user.must_change_password {
+                    " (must change password)"
+                } else {
+                    ""
+                }
             );
         }
     }
@@ -374,7 +576,7 @@ pub fn user_edit_impl(
         new_email,
         None,
         new_company_id,
-        new_totp_secret,
+        new_totp_secret.map(Some),
         Some(admin_user_id),
     )?;
 
@@ -794,6 +996,74 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_force_password_change_impl() {
+        let mut conn = setup_test_db();
+
+        let company = insert_company(&mut conn, "Test Company".to_string(), None)
+            .expect("Failed to create test company");
+        add_user_impl(
+            &mut conn,
+            "rotate@example.com",
+            Some("password".to_string()),
+            company.id,
+            None,
+            1,
+        )
+        .expect("Failed to create user");
+
+        let action = UserAction::ForcePasswordChange {
+            email: "rotate@example.com".to_string(),
+            clear: false,
+        };
+        handle_user_command_with_conn(&mut conn, action, 1).expect("Failed to force change");
+        let user = get_user_by_email(&mut conn, "rotate@example.com").unwrap().unwrap();
+        assert!(user.must_change_password);
+
+        force_password_change_impl(&mut conn, "rotate@example.com", false, 1)
+            .expect("Failed to clear forced change");
+        let user = get_user_by_email(&mut conn, "rotate@example.com").unwrap().unwrap();
+        assert!(!user.must_change_password);
+
+        assert!(force_password_change_impl(&mut conn, "nobody@example.com", true, 1).is_err());
+    }
+
+    #[test]
+    fn test_service_account_api_keys() {
+        let mut conn = setup_test_db();
+
+        let company = insert_company(&mut conn, "Test Company".to_string(), None)
+            .expect("Failed to create test company");
+        let action = UserAction::AddServiceAccount {
+            name: "rtac-bridge".to_string(),
+            company_id: company.id.to_string(),
+        };
+        handle_user_command_with_conn(&mut conn, action, 1).expect("Failed to add account");
+        let account = get_user_by_email(&mut conn, "rtac-bridge").unwrap().unwrap();
+        assert!(account.service_account);
+        assert!(add_service_account_impl(&mut conn, "rtac-bridge", company.id, 1).is_err());
+        assert!(add_service_account_impl(&mut conn, "bot@example.com", company.id, 1).is_err());
+
+        add_api_key_impl(&mut conn, "rtac-bridge", Some("site 3".to_string()))
+            .expect("Failed to add API key");
+        let api_keys = get_user_api_keys(&mut conn, account.id).unwrap();
+        assert_eq!(api_keys.len(), 1);
+        assert_eq!(api_keys[0].description.as_deref(), Some("site 3"));
+
+        rotate_api_key_impl(&mut conn, "rtac-bridge", api_keys[0].id).expect("Failed to rotate");
+        let rotated = get_user_api_keys(&mut conn, account.id).unwrap();
+        assert!(rotated[0].rotated_at.is_some());
+        assert!(list_api_keys_impl(&mut conn, "rtac-bridge").is_ok());
+
+        remove_api_key_impl(&mut conn, "rtac-bridge", api_keys[0].id).expect("Failed to remove");
+        assert!(remove_api_key_impl(&mut conn, "rtac-bridge", api_keys[0].id).is_err());
+
+        // People don't get API keys
+        add_user_impl(&mut conn, "person@example.com", Some("pw".to_string()), company.id, None, 1)
+            .expect("Failed to create user");
+        assert!(add_api_key_impl(&mut conn, "person@example.com", None).is_err());
+    }
+
     #[test]
     fn test_list_users_impl_empty() {
         let mut conn = setup_test_db();
@@ -2,7 +2,7 @@ use argon2::{
     Argon2, PasswordHasher,
     password_hash::{SaltString, rand_core::OsRng},
 };
-use diesel::{prelude::*, sqlite::SqliteConnection};
+use diesel::sqlite::SqliteConnection;
 use dotenvy::dotenv;
 use neems_api::{
     models::{CompanyInput, UserInput},
@@ -16,7 +16,7 @@ use neems_api::{
 pub fn establish_connection() -> Result<SqliteConnection, Box<dyn std::error::Error>> {
     dotenv().ok();
     let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let conn = SqliteConnection::establish(&database_url)?;
+    let conn = neems_data::encryption::establish(&database_url)?;
     Ok(conn)
 }
 
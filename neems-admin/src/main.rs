@@ -18,6 +18,7 @@
 //! - Secure password prompting without echo
 //! - Cascading deletes to maintain data consistency
 //! - Interactive confirmation prompts for destructive operations
+//! - Redacted support bundles for sharing diagnostics
 //!
 //! For detailed usage information and available commands, run with --help.
 
@@ -30,6 +31,8 @@ mod admin_cli {
     pub mod utils;
 }
 
+use std::{collections::BTreeMap, path::PathBuf};
+
 use admin_cli::{
     company_commands::{CompanyAction, handle_company_command_with_conn},
     device_commands::{DeviceAction, handle_device_command_with_conn},
@@ -38,7 +41,17 @@ use admin_cli::{
     user_commands::{UserAction, handle_user_command_with_conn},
     utils::{establish_connection, get_or_create_admin_user},
 };
+use chrono::{Duration, Utc};
 use clap::{Parser, Subcommand};
+use diesel::sqlite::SqliteConnection;
+use neems_api::orm::migration_preflight::{
+    PreflightReport, database_file, pending_migrations, preflight, run_migrations_safely,
+};
+use neems_data::{
+    logs::{self, LogQuery, LogsConfig, fetch_edge_logs},
+    maintenance::run_maintenance,
+    support::{SupportBundle, redact_config, redact_log_entry, redact_text, schema_stats},
+};
 use serde::Deserialize;
 
 pub mod built_info {
@@ -86,14 +99,51 @@ enum Commands {
         #[command(subcommand)]
         action: SystemAction,
     },
+    #[cfg(feature = "test-staging")]
+    #[command(about = "Create a golden test database from the test fixtures")]
+    GoldenDb {
+        /// Database file to create; must not already exist
+        #[arg(long)]
+        path: PathBuf,
+    },
 }
 
 #[derive(Subcommand)]
 enum SystemAction {
     #[command(about = "Display system status")]
     Status,
-    #[command(about = "Run maintenance tasks")]
-    Maintenance,
+    #[command(about = "Vacuum, ANALYZE and checkpoint the API and site databases")]
+    Maintenance {
+        /// Rewrite each database with VACUUM and switch it to incremental
+        /// auto-vacuum; needs free space for a copy of the largest database
+        #[arg(long)]
+        full: bool,
+        /// Skip the site database (SITE_DATABASE_URL)
+        #[arg(long)]
+        skip_site: bool,
+    },
+    #[command(about = "Run pending API database migrations, checking destructive ones first")]
+    Migrate {
+        /// Only back up the database and check the pending migrations on a
+        /// copy of it; the database itself is left unmigrated
+        #[arg(long)]
+        check: bool,
+    },
+    #[command(
+        about = "Write configuration, schema statistics and recent logs, with emails, secrets \
+                 and coordinates redacted, to a JSON file for support"
+    )]
+    SupportBundle {
+        /// File to write; defaults to neems-support-<timestamp>.json
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Hours of monitor logs to include
+        #[arg(long, default_value_t = 24)]
+        log_hours: i64,
+        /// Skip the site database (SITE_DATABASE_URL)
+        #[arg(long)]
+        skip_site: bool,
+    },
 }
 
 #[derive(Deserialize)]
@@ -125,6 +175,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Some(Commands::Device { action }) => handle_device_command(action)?,
         Some(Commands::Role { action }) => handle_role_command(action)?,
         Some(Commands::System { action }) => handle_system_command(action).await?,
+        #[cfg(feature = "test-staging")]
+        Some(Commands::GoldenDb { path }) => handle_golden_db_command(&path)?,
         None => {
             eprintln!("No command provided. Use --help for usage information.");
             std::process::exit(1);
@@ -164,6 +216,19 @@ fn handle_role_command(action: RoleAction) -> Result<(), Box<dyn std::error::Err
     handle_role_command_with_conn(&mut conn, action, admin_user_id)
 }
 
+#[cfg(feature = "test-staging")]
+fn handle_golden_db_command(path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+    use neems_api::orm::testing::create_golden_db;
+
+    let fixtures = create_golden_db(path)?;
+    println!("Golden database created at: {}", path.display());
+    println!(
+        "Admin user: superadmin@example.com (ID: {})",
+        fixtures.user_id("superadmin@example.com")
+    );
+    Ok(())
+}
+
 async fn handle_system_command(action: SystemAction) -> Result<(), Box<dyn std::error::Error>> {
     match action {
         SystemAction::Status => {
@@ -185,15 +250,177 @@ async fn handle_system_command(action: SystemAction) -> Result<(), Box<dyn std::
                 }
             }
         }
-        SystemAction::Maintenance => {
-            println!("Running maintenance tasks...");
-            // TODO: Implement maintenance tasks
+        SystemAction::Maintenance { full, skip_site } => {
+            let mut conn = establish_connection()?;
+            run_database_maintenance("API database", &mut conn, full)?;
+
+            if !skip_site {
+                match std::env::var("SITE_DATABASE_URL") {
+                    Ok(site_url) => {
+                        let mut site_conn = neems_data::encryption::establish(&site_url)?;
+                        run_database_maintenance("Site database", &mut site_conn, full)?;
+                    }
+                    Err(_) => println!("Site database: SITE_DATABASE_URL not set, skipped"),
+                }
+            }
+        }
+        SystemAction::Migrate { check } => {
+            let mut conn = establish_connection()?;
+            let pending = pending_migrations(&mut conn)?;
+            if pending.is_empty() {
+                println!("No pending migrations");
+                return Ok(());
+            }
+            for migration in &pending {
+                let note = if migration.destructive {
+                    " (destructive)"
+                } else {
+                    ""
+                };
+                println!("Pending: {}{}", migration.name, note);
+            }
+            if check {
+                let database = database_file(&mut conn)?
+                    .ok_or("The database is in memory; there is nothing to check")?;
+                print_preflight_report(&preflight(&mut conn, &database)?);
+                println!("Pre-flight passed; the database was not migrated");
+            } else {
+                if let Some(report) = run_migrations_safely(&mut conn)? {
+                    print_preflight_report(&report);
+                }
+                println!("Applied {} migrations", pending.len());
+            }
+        }
+        SystemAction::SupportBundle { output, log_hours, skip_site } => {
+            let bundle = collect_support_bundle(log_hours, skip_site).await?;
+            let output = output.unwrap_or_else(|| {
+                PathBuf::from(format!(
+                    "neems-support-{}.json",
+                    bundle.generated_at.format("%Y%m%dT%H%M%SZ")
+                ))
+            });
+            std::fs::write(&output, serde_json::to_vec_pretty(&bundle)?)?;
+            println!("Support bundle written to {}", output.display());
+            for note in &bundle.notes {
+                println!("  Note: {}", note);
+            }
+            println!("Redaction is pattern based; look the bundle over before sending it");
+        }
+    }
+
+    Ok(())
+}
+
+/// Gather configuration, schema statistics and the monitor's recent logs,
+/// redacted (see [`neems_data::support`]).
+async fn collect_support_bundle(
+    log_hours: i64,
+    skip_site: bool,
+) -> Result<SupportBundle, Box<dyn std::error::Error>> {
+    let mut bundle = SupportBundle {
+        generated_at: Utc::now(),
+        versions: BTreeMap::from([(
+            "neems-admin".to_string(),
+            built_info::PKG_VERSION.to_string(),
+        )]),
+        config: redact_config(std::env::vars()),
+        databases: Vec::new(),
+        logs: Vec::new(),
+        notes: Vec::new(),
+    };
+    if let Some(commit) = built_info::GIT_COMMIT_HASH {
+        bundle.versions.insert("neems-admin git commit".to_string(), commit.to_string());
+    }
+    if let Ok(api_status) = get_api_status().await {
+        bundle.versions.insert("neems-api".to_string(), api_status.version);
+    }
+
+    let mut conn = establish_connection()?;
+    bundle
+        .databases
+        .push(schema_stats(&mut conn, "api").map_err(|e| e.to_string())?);
+    if !skip_site {
+        match std::env::var("SITE_DATABASE_URL") {
+            Ok(site_url) => {
+                let mut site_conn = neems_data::encryption::establish(&site_url)?;
+                bundle
+                    .databases
+                    .push(schema_stats(&mut site_conn, "site").map_err(|e| e.to_string())?);
+            }
+            Err(_) => bundle.notes.push("SITE_DATABASE_URL not set; site database skipped".into()),
         }
     }
 
+    match LogsConfig::from_env() {
+        Some(config) => {
+            // The listener is usually bound to every interface; ask it locally
+            let mut address = config.listen;
+            if address.ip().is_unspecified() {
+                address.set_ip(std::net::Ipv4Addr::LOCALHOST.into());
+            }
+            let query = LogQuery {
+                since: Some(Utc::now() - Duration::hours(log_hours)),
+                level: None,
+                limit: logs::MAX_LIMIT,
+            };
+            match fetch_edge_logs(&format!("http://{}", address), &config.token, &query).await {
+                Ok(page) => {
+                    if page.truncated {
+                        bundle.notes.push(format!(
+                            "Only the newest {} log entries are included",
+                            logs::MAX_LIMIT
+                        ));
+                    }
+                    bundle.logs = page.entries.into_iter().map(redact_log_entry).collect();
+                }
+                Err(e) => bundle
+                    .notes
+                    .push(format!("Monitor logs unavailable: {}", redact_text(&e.to_string()))),
+            }
+        }
+        None => bundle
+            .notes
+            .push("NEEMS_LOGS_LISTEN or NEEMS_LOGS_TOKEN not set; no monitor logs included".into()),
+    }
+    Ok(bundle)
+}
+
+/// Run neems-data's maintenance routine on one database and summarize it.
+fn run_database_maintenance(
+    label: &str,
+    conn: &mut SqliteConnection,
+    full: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}: running maintenance{}...", label, if full { " (full vacuum)" } else { "" });
+    let report = run_maintenance(conn, full).map_err(|e| e.to_string())?;
+    println!(
+        "  {} -> {} pages, {} bytes reclaimed, {} free pages",
+        report.pages_before,
+        report.pages_after,
+        report.reclaimed_bytes(),
+        report.free_pages
+    );
+    if let Some((busy, log, checkpointed)) = report.checkpoint {
+        println!(
+            "  WAL checkpoint: {} of {} frames{}",
+            checkpointed,
+            log,
+            if busy != 0 { " (busy)" } else { "" }
+        );
+    }
+    if report.auto_vacuum != 2 {
+        println!("  auto_vacuum is not incremental; run with --full once to enable it");
+    }
     Ok(())
 }
 
+fn print_preflight_report(report: &PreflightReport) {
+    println!("Backup: {}", report.backup.display());
+    for warning in &report.warnings {
+        println!("  Warning: {}", warning);
+    }
+}
+
 async fn get_api_status() -> Result<ApiStatus, Box<dyn std::error::Error>> {
     // Default to localhost:8000, could be made configurable
     let url = "http://localhost:8000/api/1/status";
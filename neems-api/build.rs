@@ -1,3 +1,31 @@
+use std::{env, fs, path::Path};
+
 fn main() {
     built::write_built_file().expect("Failed to acquire build-time information");
+    write_migration_sql();
+}
+
+/// Embeds each migration's `up.sql` by name, so the migration pre-flight
+/// (see `orm::migration_preflight`) can tell which pending migrations are
+/// destructive; Diesel's embedded migrations don't expose their SQL.
+fn write_migration_sql() {
+    println!("cargo:rerun-if-changed=migrations");
+    let dir = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("migrations");
+    let mut names: Vec<String> = fs::read_dir(&dir)
+        .expect("Failed to read migrations directory")
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().join("up.sql").is_file())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+
+    let mut out = String::from("pub const MIGRATION_SQL: &[(&str, &str)] = &[\n");
+    for name in names {
+        let up = dir.join(&name).join("up.sql");
+        println!("cargo:rerun-if-changed={}", up.display());
+        out.push_str(&format!("    ({:?}, include_str!({:?})),\n", name, up.display().to_string()));
+    }
+    out.push_str("];\n");
+    let path = Path::new(&env::var("OUT_DIR").unwrap()).join("migration_sql.rs");
+    fs::write(path, out).expect("Failed to write migration SQL");
 }
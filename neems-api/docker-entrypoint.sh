@@ -28,7 +28,7 @@ export NEEMS_ADMIN_BIN=/usr/src/app/target/debug/neems-admin
 
 # Generate TypeScript types synchronously on startup (before neems-react starts)
 echo "Generating TypeScript types (initial)..."
-cargo test --features test-staging generate_typescript_types --quiet || true
+cargo run --quiet -p neems-api -- export-types --out "$NEEMS_TS_OUTPUT_DIR" || true
 /usr/src/app/bin/build-local-types-package.sh "$NEEMS_TS_OUTPUT_DIR"
 
 # Run TypeScript generation in the background, watching for Rust file changes
@@ -36,7 +36,7 @@ cargo watch \
   --features test-staging \
   -w neems-api/src \
   -w neems-data/src \
-  -s 'cargo test --features test-staging generate_typescript_types --quiet && /usr/src/app/bin/build-local-types-package.sh "${NEEMS_TS_OUTPUT_DIR}"' &
+  -s 'cargo run --quiet -p neems-api -- export-types --out "${NEEMS_TS_OUTPUT_DIR}" && /usr/src/app/bin/build-local-types-package.sh "${NEEMS_TS_OUTPUT_DIR}"' &
 
 # Run the main API server with live reload
 exec cargo watch \
@@ -0,0 +1,15 @@
+-- Client certificates accepted in place of a session cookie when the API
+-- terminates TLS itself with client certificate verification enabled.
+-- A certificate is identified by the SHA-256 fingerprint of its DER
+-- encoding (lowercase hex, no separators) and authenticates as one user.
+CREATE TABLE client_certificates (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL,
+    fingerprint TEXT NOT NULL UNIQUE,
+    subject TEXT,
+    description TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_client_certificates_user ON client_certificates(user_id);
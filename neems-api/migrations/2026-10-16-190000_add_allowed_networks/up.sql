@@ -0,0 +1,16 @@
+-- CIDR networks a company's users, or a client certificate, may connect
+-- from. Each row belongs to exactly one of the two; a company or certificate
+-- with no rows is unrestricted.
+CREATE TABLE allowed_networks (
+    id INTEGER PRIMARY KEY NOT NULL,
+    company_id INTEGER,
+    client_certificate_id INTEGER,
+    network TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(company_id) REFERENCES companies(id) ON DELETE CASCADE,
+    FOREIGN KEY(client_certificate_id) REFERENCES client_certificates(id) ON DELETE CASCADE,
+    CHECK ((company_id IS NULL) != (client_certificate_id IS NULL))
+);
+
+CREATE INDEX idx_allowed_networks_company ON allowed_networks(company_id);
+CREATE INDEX idx_allowed_networks_client_certificate ON allowed_networks(client_certificate_id);
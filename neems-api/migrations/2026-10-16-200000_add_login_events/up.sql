@@ -0,0 +1,19 @@
+-- Every login attempt, successful or not. user_id is NULL when the email
+-- matched no user, and is cleared when the user is deleted so the history
+-- outlives them. anomaly holds comma-separated flags raised when the attempt
+-- was recorded (new_country, failures_then_success).
+CREATE TABLE login_events (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER,
+    email TEXT NOT NULL,
+    success BOOLEAN NOT NULL,
+    ip_address TEXT,
+    user_agent TEXT,
+    country TEXT,
+    anomaly TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX idx_login_events_user ON login_events(user_id, id);
+CREATE INDEX idx_login_events_anomaly ON login_events(anomaly) WHERE anomaly IS NOT NULL;
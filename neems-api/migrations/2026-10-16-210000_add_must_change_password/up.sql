@@ -0,0 +1,3 @@
+-- Set by an administrator to force a password rotation. While set, the user's
+-- sessions may only change the password; doing so clears it.
+ALTER TABLE users ADD COLUMN must_change_password BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,20 @@
+-- Service accounts are users for machine clients such as the RTAC bridge
+-- and report jobs. They can't log in with a password and authenticate with
+-- API keys instead; their email column holds an account name, not an
+-- address.
+ALTER TABLE users ADD COLUMN service_account BOOLEAN NOT NULL DEFAULT 0;
+
+-- API keys don't expire but can be rotated in place. Only the SHA-256 of
+-- the key is stored (lowercase hex); the prefix identifies it in listings.
+CREATE TABLE api_keys (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    prefix TEXT NOT NULL,
+    description TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    rotated_at TIMESTAMP,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_api_keys_user ON api_keys(user_id);
@@ -0,0 +1,17 @@
+-- Tokens for subscribing calendar apps to a site's schedule feed
+-- (Schedule.ics), which can't log in. A token reads one site's schedule
+-- as the user who created it, and nothing else. Only the SHA-256 of the
+-- token is stored (lowercase hex); the prefix identifies it in listings.
+CREATE TABLE schedule_feed_tokens (
+    id INTEGER PRIMARY KEY NOT NULL,
+    site_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    prefix TEXT NOT NULL,
+    description TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(site_id) REFERENCES sites(id) ON DELETE CASCADE,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_schedule_feed_tokens_site_user ON schedule_feed_tokens(site_id, user_id);
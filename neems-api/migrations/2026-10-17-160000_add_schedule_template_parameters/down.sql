@@ -0,0 +1 @@
+ALTER TABLE schedule_templates DROP COLUMN parameters;
@@ -0,0 +1,3 @@
+-- Parameters a schedule template declares for its commands to bind, as a
+-- JSON array; NULL when it has none
+ALTER TABLE schedule_templates ADD COLUMN parameters TEXT;
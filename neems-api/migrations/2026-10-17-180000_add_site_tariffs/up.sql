@@ -0,0 +1,14 @@
+-- Electricity prices for a site, for the economics report. Energy costs
+-- off_peak_price_per_kwh in the site's off-peak window, peak_price_per_kwh in
+-- its peak revenue window and standard_price_per_kwh otherwise; the demand
+-- charge is per kW of a month's peak demand.
+CREATE TABLE site_tariffs (
+    site_id INTEGER PRIMARY KEY NOT NULL,
+    currency TEXT NOT NULL DEFAULT 'USD',
+    standard_price_per_kwh DOUBLE NOT NULL,
+    off_peak_price_per_kwh DOUBLE NOT NULL,
+    peak_price_per_kwh DOUBLE NOT NULL,
+    demand_charge_per_kw DOUBLE NOT NULL DEFAULT 0,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(site_id) REFERENCES sites(id) ON DELETE CASCADE
+);
@@ -0,0 +1 @@
+ALTER TABLE application_rules DROP COLUMN priority;
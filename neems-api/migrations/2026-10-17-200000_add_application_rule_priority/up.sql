@@ -0,0 +1,3 @@
+-- Override priority layer of a rule: 'operator', 'utility_dr' or 'safety'.
+-- A higher layer preempts lower ones whatever their specificity.
+ALTER TABLE application_rules ADD COLUMN priority TEXT NOT NULL DEFAULT 'operator';
@@ -0,0 +1,11 @@
+-- The scheduler's last evaluation of each site's active command, so a restart
+-- can tell which command starts it missed. caught_up_at and missed_commands
+-- record the last catch-up after a restart.
+CREATE TABLE scheduler_ticks (
+    site_id INTEGER PRIMARY KEY NOT NULL,
+    evaluated_at TIMESTAMP NOT NULL,
+    command_id INTEGER,
+    caught_up_at TIMESTAMP,
+    missed_commands INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY(site_id) REFERENCES sites(id) ON DELETE CASCADE
+);
@@ -0,0 +1,34 @@
+-- Groups of a site's devices (e.g. "BESS containers 1-4") controlled
+-- together. A total power setpoint dispatched to a group is split across its
+-- members by the group's allocation strategy ('equal' or 'soc_balancing').
+-- At most one group per site takes the scheduler's site-wide power commands
+-- (schedule_dispatch).
+CREATE TABLE device_groups (
+    id INTEGER PRIMARY KEY NOT NULL,
+    site_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    allocation TEXT NOT NULL DEFAULT 'equal',
+    schedule_dispatch BOOLEAN NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(site_id) REFERENCES sites(id) ON DELETE CASCADE,
+    UNIQUE(site_id, name)
+);
+
+CREATE UNIQUE INDEX idx_device_groups_schedule_dispatch
+    ON device_groups(site_id) WHERE schedule_dispatch;
+
+-- A group's devices. rated_power_kw caps the member's share of a setpoint;
+-- source_id is the site database source whose readings report the device's
+-- SoC and power.
+CREATE TABLE device_group_members (
+    group_id INTEGER NOT NULL,
+    device_id INTEGER NOT NULL,
+    rated_power_kw DOUBLE,
+    source_id INTEGER,
+    PRIMARY KEY(group_id, device_id),
+    FOREIGN KEY(group_id) REFERENCES device_groups(id) ON DELETE CASCADE,
+    FOREIGN KEY(device_id) REFERENCES devices(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_device_group_members_device ON device_group_members(device_id);
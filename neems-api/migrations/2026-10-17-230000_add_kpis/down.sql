@@ -0,0 +1,2 @@
+DROP TABLE kpi_results;
+DROP TABLE kpi_definitions;
@@ -0,0 +1,31 @@
+-- Customer-defined metrics of a site (e.g. "availability %"): an arithmetic
+-- expression over a day's economics and readings, evaluated once a day.
+-- A KPI meets its target when its value is at least the target, or at most
+-- it when lower is better.
+CREATE TABLE kpi_definitions (
+    id INTEGER PRIMARY KEY NOT NULL,
+    site_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    expression TEXT NOT NULL,
+    target DOUBLE,
+    higher_is_better BOOLEAN NOT NULL DEFAULT 1,
+    unit TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(site_id) REFERENCES sites(id) ON DELETE CASCADE,
+    UNIQUE(site_id, name)
+);
+
+-- A KPI's value for a UTC day. value is null, and error says why, when the
+-- expression couldn't be evaluated (no readings of a field, no tariff, a
+-- division by zero); met is null without a value or a target.
+CREATE TABLE kpi_results (
+    kpi_id INTEGER NOT NULL,
+    day DATE NOT NULL,
+    value DOUBLE,
+    met BOOLEAN,
+    error TEXT,
+    evaluated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY(kpi_id, day),
+    FOREIGN KEY(kpi_id) REFERENCES kpi_definitions(id) ON DELETE CASCADE
+);
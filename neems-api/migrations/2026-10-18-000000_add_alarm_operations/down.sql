@@ -0,0 +1,3 @@
+DROP INDEX idx_alarm_actions_alarm_num;
+DROP TABLE alarm_actions;
+DROP TABLE alarm_states;
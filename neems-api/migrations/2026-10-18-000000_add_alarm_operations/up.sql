@@ -0,0 +1,27 @@
+-- Operator state of RTAC alarms, by alarm number (ISA-18.2). An
+-- acknowledgement holds until the alarm is next seen cleared; a shelved alarm
+-- is hidden from active views until shelved_until.
+CREATE TABLE alarm_states (
+    alarm_num INTEGER PRIMARY KEY NOT NULL,
+    acknowledged_at TIMESTAMP,
+    acknowledged_by TEXT,
+    shelved_until TIMESTAMP,
+    shelved_by TEXT,
+    shelve_reason TEXT
+);
+
+-- Every acknowledgement, shelving, unshelving and comment, by whom. The
+-- email is kept so the log reads the same after the user is deleted.
+CREATE TABLE alarm_actions (
+    id INTEGER PRIMARY KEY NOT NULL,
+    alarm_num INTEGER NOT NULL,
+    action TEXT NOT NULL,
+    user_id INTEGER,
+    user_email TEXT NOT NULL,
+    comment TEXT,
+    shelved_until TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX idx_alarm_actions_alarm_num ON alarm_actions(alarm_num, created_at);
@@ -0,0 +1,4 @@
+DROP TABLE alarm_escalations;
+DROP TABLE escalation_tiers;
+DROP TABLE escalation_policies;
+ALTER TABLE alarm_states DROP COLUMN raised_at;
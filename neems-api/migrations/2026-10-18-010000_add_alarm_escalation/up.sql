@@ -0,0 +1,45 @@
+-- When each alarm was last raised, so escalation delays count from it
+ALTER TABLE alarm_states ADD COLUMN raised_at TIMESTAMP;
+
+-- Per-company escalation of alarms at or above min_severity that nobody has
+-- acknowledged: each tier is notified once the alarm has been unacknowledged
+-- for its delay.
+CREATE TABLE escalation_policies (
+    id INTEGER PRIMARY KEY NOT NULL,
+    company_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    min_severity TEXT NOT NULL DEFAULT 'critical',
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(company_id) REFERENCES companies(id) ON DELETE CASCADE,
+    UNIQUE(company_id, name)
+);
+
+-- A policy's tiers, notified through a notification channel in the site
+-- database; contacts is a JSON array of the people the tier reaches.
+CREATE TABLE escalation_tiers (
+    policy_id INTEGER NOT NULL,
+    tier INTEGER NOT NULL,
+    delay_minutes INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL,
+    contacts TEXT NOT NULL DEFAULT '[]',
+    PRIMARY KEY(policy_id, tier),
+    FOREIGN KEY(policy_id) REFERENCES escalation_policies(id) ON DELETE CASCADE
+);
+
+-- Each escalation sent, once per raise of the alarm, policy and tier. The
+-- policy is not a foreign key so the history outlives it.
+CREATE TABLE alarm_escalations (
+    id INTEGER PRIMARY KEY NOT NULL,
+    alarm_num INTEGER NOT NULL,
+    raised_at TIMESTAMP NOT NULL,
+    policy_id INTEGER NOT NULL,
+    policy_name TEXT NOT NULL,
+    tier INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL,
+    contacts TEXT NOT NULL DEFAULT '[]',
+    error TEXT,
+    escalated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(alarm_num, raised_at, policy_id, tier)
+);
@@ -0,0 +1,6 @@
+ALTER TABLE alarm_escalations DROP COLUMN on_call_email;
+ALTER TABLE escalation_tiers DROP COLUMN rotation_id;
+DROP INDEX idx_oncall_overrides_rotation;
+DROP TABLE oncall_overrides;
+DROP TABLE oncall_members;
+DROP TABLE oncall_rotations;
@@ -0,0 +1,43 @@
+-- Per-company on-call rotations: members take shifts of shift_hours in
+-- order, the first starting at handoff_at, and the pattern repeats.
+CREATE TABLE oncall_rotations (
+    id INTEGER PRIMARY KEY NOT NULL,
+    company_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    handoff_at TIMESTAMP NOT NULL,
+    shift_hours INTEGER NOT NULL DEFAULT 168,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(company_id) REFERENCES companies(id) ON DELETE CASCADE,
+    UNIQUE(company_id, name)
+);
+
+CREATE TABLE oncall_members (
+    rotation_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    PRIMARY KEY(rotation_id, position),
+    FOREIGN KEY(rotation_id) REFERENCES oncall_rotations(id) ON DELETE CASCADE,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- Time windows in which someone covers the rotation instead, e.g. a swap
+-- or holiday cover
+CREATE TABLE oncall_overrides (
+    id INTEGER PRIMARY KEY NOT NULL,
+    rotation_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    starts_at TIMESTAMP NOT NULL,
+    ends_at TIMESTAMP NOT NULL,
+    reason TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(rotation_id) REFERENCES oncall_rotations(id) ON DELETE CASCADE,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_oncall_overrides_rotation ON oncall_overrides(rotation_id, ends_at);
+
+-- An escalation tier can notify whoever is on call on a rotation, in
+-- addition to its contacts; the escalation records who that was
+ALTER TABLE escalation_tiers ADD COLUMN rotation_id INTEGER;
+ALTER TABLE alarm_escalations ADD COLUMN on_call_email TEXT;
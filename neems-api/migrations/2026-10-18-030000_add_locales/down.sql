@@ -0,0 +1,2 @@
+DROP TABLE user_locales;
+DROP TABLE company_locales;
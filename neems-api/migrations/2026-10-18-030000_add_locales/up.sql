@@ -0,0 +1,16 @@
+-- The language a company's users read the API and reports in, and
+-- per-user preferences that override it. Without either, the request's
+-- Accept-Language header decides.
+CREATE TABLE company_locales (
+    company_id INTEGER PRIMARY KEY NOT NULL,
+    locale TEXT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(company_id) REFERENCES companies(id) ON DELETE CASCADE
+);
+
+CREATE TABLE user_locales (
+    user_id INTEGER PRIMARY KEY NOT NULL,
+    locale TEXT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
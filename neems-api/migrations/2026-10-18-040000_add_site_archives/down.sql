@@ -0,0 +1,2 @@
+ALTER TABLE sites DROP COLUMN archive_key;
+ALTER TABLE sites DROP COLUMN archived_at;
@@ -0,0 +1,5 @@
+-- Set when a site is decommissioned: its history has been exported to the
+-- blob at archive_key and removed from the site database, and the site is
+-- read-only from then on.
+ALTER TABLE sites ADD COLUMN archived_at TIMESTAMP;
+ALTER TABLE sites ADD COLUMN archive_key TEXT;
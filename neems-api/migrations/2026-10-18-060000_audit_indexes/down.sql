@@ -0,0 +1,4 @@
+DROP INDEX idx_user_roles_role;
+DROP INDEX idx_sessions_user;
+DROP INDEX idx_devices_site;
+DROP INDEX idx_users_company;
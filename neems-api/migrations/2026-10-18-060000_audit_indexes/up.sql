@@ -0,0 +1,6 @@
+-- Foreign keys that list endpoints and cascading deletes filter on, which
+-- had no index of their own and so scanned the whole table
+CREATE INDEX IF NOT EXISTS idx_users_company ON users (company_id);
+CREATE INDEX IF NOT EXISTS idx_devices_site ON devices (site_id);
+CREATE INDEX IF NOT EXISTS idx_sessions_user ON sessions (user_id);
+CREATE INDEX IF NOT EXISTS idx_user_roles_role ON user_roles (role_id);
@@ -0,0 +1,218 @@
+//! Server-rendered status page.
+//!
+//! `GET /admin/status` shows database sizes and migration versions, the
+//! neems-data monitor's scheduler and queue status (see
+//! [`neems_data::status`]) and the health of each active collector as plain
+//! HTML, for diagnosing installs where the SPA isn't deployed. It needs the
+//! `newtown-admin` role, like the rest of the site's diagnostics; without the
+//! SPA, sign in with `POST /api/1/login` or send an API key as a bearer token.
+//! The page refreshes itself every 30 seconds.
+
+use std::fmt::Write;
+
+use chrono::{NaiveDateTime, Utc};
+use neems_data::status::{
+    CollectorState, DatabaseInfo, MonitorState, MonitorStatus, SourceHealth, database_info,
+    load_status, source_health,
+};
+use rocket::{Route, http::Status, response::content::RawHtml};
+
+use crate::{
+    DbConn, SiteDbConn, orm::neems_data::db::SITE_MIGRATIONS, session_guards::NewtownAdminUser,
+};
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:2em}\
+th,td{border:1px solid #ccc;padding:4px 10px;text-align:left}\
+th{background:#f3f3f3}.ok{color:#080}.warn{color:#b60}.bad{color:#c00}";
+
+/// Admin Status page.
+///
+/// - **URL:** `/admin/status`
+/// - **Method:** `GET`
+/// - **Purpose:** Diagnostics for an install, as HTML
+/// - **Authentication:** Required; `newtown-admin`
+#[get("/status")]
+pub async fn status_page(
+    user: NewtownAdminUser,
+    db: DbConn,
+    site_db: SiteDbConn,
+) -> Result<RawHtml<String>, Status> {
+    let now = Utc::now().naive_utc();
+    let api_db = db.run(|conn| database_info(conn, crate::MIGRATIONS)).await;
+    let site = site_db
+        .run(move |conn| {
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>((
+                database_info(conn, SITE_MIGRATIONS)?,
+                load_status(conn)?,
+                source_health(conn, now)?,
+            ))
+        })
+        .await;
+    let (api_db, (site_db, monitor, sources)) = match (api_db, site) {
+        (Ok(api_db), Ok(site)) => (api_db, site),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("Error gathering status page: {}", e);
+            return Err(Status::InternalServerError);
+        }
+    };
+
+    let mut page = String::new();
+    write!(
+        page,
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+         <meta http-equiv=\"refresh\" content=\"30\"><title>NEEMS status</title>\
+         <style>{}</style></head><body><h1>NEEMS status</h1>\
+         <p>neems-api {} &middot; {} UTC &middot; signed in as {}</p>",
+        STYLE,
+        env!("CARGO_PKG_VERSION"),
+        now.format("%Y-%m-%d %H:%M:%S"),
+        escape(&user.user.email)
+    )
+    .unwrap();
+    render_databases(&mut page, &[("API", &api_db), ("Site data", &site_db)]);
+    render_monitor(&mut page, monitor.as_ref(), now);
+    render_collectors(&mut page, &sources, now);
+    page.push_str("</body></html>");
+    Ok(RawHtml(page))
+}
+
+fn render_databases(page: &mut String, databases: &[(&str, &DatabaseInfo)]) {
+    page.push_str(
+        "<h2>Databases</h2><table><tr><th>Database</th><th>Size</th><th>Free</th>\
+         <th>Migration version</th><th>Pending migrations</th></tr>",
+    );
+    for (name, info) in databases {
+        let pending = if info.pending_migrations.is_empty() {
+            "<span class=\"ok\">none</span>".to_string()
+        } else {
+            format!("<span class=\"bad\">{}</span>", escape(&info.pending_migrations.join(", ")))
+        };
+        write!(
+            page,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            name,
+            format_bytes(info.size_bytes),
+            format_bytes(info.free_bytes),
+            escape(info.migration_version.as_deref().unwrap_or("none")),
+            pending
+        )
+        .unwrap();
+    }
+    page.push_str("</table>");
+}
+
+fn render_monitor(page: &mut String, monitor: Option<&MonitorStatus>, now: NaiveDateTime) {
+    page.push_str("<h2>Data monitor</h2>");
+    let Some(status) = monitor else {
+        page.push_str(
+            "<p class=\"bad\">neems-data monitor has never reported its status: it hasn't \
+             run since this version was installed.</p>",
+        );
+        return;
+    };
+    let (class, state) = match status.state(now) {
+        MonitorState::Running => ("ok", "running"),
+        MonitorState::Stalled => ("bad", "stalled: the scheduler isn't ticking"),
+        MonitorState::NotReporting => ("bad", "not reporting: the monitor isn't running"),
+    };
+    let rows = [
+        ("State", format!("<span class=\"{}\">{}</span>", class, state)),
+        ("Last report", format_time(Some(status.updated_at), now)),
+        ("Scheduler tick", format_time(status.last_tick_at, now)),
+        ("Started", format_time(Some(status.started_at), now)),
+        ("Version / PID", format!("{} / {}", escape(&status.version), status.pid)),
+        ("Active sources", status.active_sources.to_string()),
+        (
+            "Collectors",
+            format!("{} running, {} queued", status.collectors_running, status.collectors_queued),
+        ),
+        (
+            "Writer queue",
+            format!(
+                "{} readings (high water {})",
+                status.writer_queue_depth, status.writer_high_water_mark
+            ),
+        ),
+        ("Readings dropped", status.readings_dropped.to_string()),
+    ];
+    page.push_str("<table>");
+    for (label, value) in rows {
+        write!(page, "<tr><th>{}</th><td>{}</td></tr>", label, value).unwrap();
+    }
+    page.push_str("</table>");
+}
+
+fn render_collectors(page: &mut String, sources: &[SourceHealth], now: NaiveDateTime) {
+    page.push_str("<h2>Collectors</h2>");
+    if sources.is_empty() {
+        page.push_str("<p>No active sources.</p>");
+        return;
+    }
+    page.push_str(
+        "<table><tr><th>Source</th><th>Test type</th><th>Interval</th><th>Last run</th>\
+         <th>Last reading</th><th>Health</th></tr>",
+    );
+    for health in sources {
+        let (class, state) = match health.state {
+            CollectorState::Ok => ("ok", "ok"),
+            CollectorState::Failing => ("bad", "failing"),
+            CollectorState::Late => ("warn", "late"),
+            CollectorState::NeverRun => ("warn", "never run"),
+        };
+        write!(
+            page,
+            "<tr><td>{}</td><td>{}</td><td>{}s</td><td>{}</td><td>{}</td>\
+             <td class=\"{}\">{}</td></tr>",
+            escape(&health.source.name),
+            escape(health.source.test_type.as_deref().unwrap_or("")),
+            health.source.interval_seconds,
+            format_time(health.source.last_run, now),
+            format_time(health.last_reading_at, now),
+            class,
+            state
+        )
+        .unwrap();
+    }
+    page.push_str("</table>");
+}
+
+fn format_time(time: Option<NaiveDateTime>, now: NaiveDateTime) -> String {
+    let Some(time) = time else {
+        return "never".to_string();
+    };
+    let secs = (now - time).num_seconds().max(0);
+    let age = match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    };
+    format!("{} ({} ago)", time.format("%Y-%m-%d %H:%M:%S"), age)
+}
+
+fn format_bytes(bytes: i64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![status_page]
+}
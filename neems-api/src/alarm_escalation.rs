@@ -0,0 +1,406 @@
+//! Escalation of alarms nobody acknowledges.
+//!
+//! Every [`ESCALATION_INTERVAL_SECONDS`] the active alarms are read from the
+//! latest RTAC reading, as for `/Alarms/Active`, and checked against the
+//! escalation policies of the company the RTAC source belongs to (its own, or
+//! its site's). Each tier of a policy whose delay has passed since the alarm
+//! was raised, with the alarm still unacknowledged and not shelved, is sent
+//! a notification through the tier's channel (see
+//! [`neems_data::notifications::send_event`]) naming its contacts, and the
+//! escalation is recorded in the alarm's history. A tier on an on-call
+//! rotation names whoever is on call on it at the time first (see
+//! [`crate::oncall`]). A tier is notified once per
+//! raise of the alarm; a notification the channel's service can't take yet is
+//! retried on the next check.
+//!
+//! Demo-forced alarms keep their acknowledgements but are never escalated.
+//!
+//! The notification is written in the channel's locale (see
+//! [`neems_data::i18n`]).
+
+use std::collections::HashSet;
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use neems_data::{
+    i18n::{Locale, fill, tr},
+    models::Source,
+    notifications::{
+        AlarmEvent, NotificationSeverity, get_channel, resolve_destination, send_event,
+    },
+    prometheus::Delivery,
+    rtac::{AlarmFlags, AlarmSeverity, alarm_definitions::ALARM_DEFINITIONS, sld_meta_for},
+};
+use rocket::fairing::AdHoc;
+
+use crate::{
+    api::alarm::{DemoForcedAlarms, latest_alarm_reading},
+    models::{AlarmState, EscalationPolicy, EscalationTier, NewAlarmEscalation},
+    oncall::on_call_at,
+    orm::{
+        DbConn, SiteDbConn,
+        alarm::{list_alarm_states, sync_active_alarms},
+        escalation::{list_alarm_escalations, list_escalation_policies, record_alarm_escalation},
+        oncall::get_oncall_rotation,
+        site::get_site_by_id,
+    },
+};
+
+/// How often unacknowledged alarms are checked
+pub const ESCALATION_INTERVAL_SECONDS: u64 = 60;
+
+/// A tier to notify of an alarm.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DueEscalation<'a> {
+    pub alarm_num: i32,
+    pub raised_at: NaiveDateTime,
+    pub policy: &'a EscalationPolicy,
+    pub tier: &'a EscalationTier,
+}
+
+/// The tiers to notify at `now` of the `active` alarms, with their
+/// severities, given their `states` and the `sent` escalations, as (alarm,
+/// raised at, policy, tier).
+pub fn due_escalations<'a>(
+    active: &[(i32, NotificationSeverity)],
+    states: &[AlarmState],
+    policies: &'a [EscalationPolicy],
+    sent: &HashSet<(i32, NaiveDateTime, i32, i32)>,
+    now: NaiveDateTime,
+) -> Vec<DueEscalation<'a>> {
+    let mut due = Vec::new();
+    for (alarm_num, severity) in active {
+        let Some(state) = states.iter().find(|state| state.alarm_num == *alarm_num) else {
+            continue;
+        };
+        let Some(raised_at) = state.raised_at else {
+            continue;
+        };
+        if state.acknowledged_at.is_some() || state.is_shelved(now) {
+            continue;
+        }
+        for policy in policies {
+            if !policy.enabled || *severity < policy.min_severity {
+                continue;
+            }
+            for tier in &policy.tiers {
+                if raised_at + Duration::minutes(tier.delay_minutes as i64) <= now
+                    && !sent.contains(&(*alarm_num, raised_at, policy.id, tier.tier))
+                {
+                    due.push(DueEscalation {
+                        alarm_num: *alarm_num,
+                        raised_at,
+                        policy,
+                        tier,
+                    });
+                }
+            }
+        }
+    }
+    due
+}
+
+/// The notification for `due`, sent at `now` with `on_call` on call, in
+/// `locale`.
+fn escalation_event(
+    due: &DueEscalation,
+    locale: Locale,
+    on_call: Option<&str>,
+    source: &str,
+    site_id: Option<i32>,
+    company_id: i32,
+    now: NaiveDateTime,
+) -> Option<AlarmEvent> {
+    let def = ALARM_DEFINITIONS.iter().find(|def| def.alarm_num as i32 == due.alarm_num)?;
+    let mut message = fill(
+        tr(
+            locale,
+            "Unacknowledged for {minutes} minutes; escalated to tier {tier} of '{policy}'",
+        ),
+        &[
+            ("minutes", &(now - due.raised_at).num_minutes().to_string()),
+            ("tier", &due.tier.tier.to_string()),
+            ("policy", &due.policy.name),
+        ],
+    );
+    let contacts: Vec<&str> = on_call
+        .into_iter()
+        .chain(due.tier.contacts.iter().map(String::as_str))
+        .collect();
+    if !contacts.is_empty() {
+        message.push_str(&format!(" ({})", contacts.join(", ")));
+    }
+    if let Some(text) = sld_meta_for(def.alarm_num).and_then(|meta| meta.message_opt()) {
+        message.push_str(&format!(". {}", text));
+    }
+    Some(AlarmEvent {
+        source: source.to_string(),
+        site_id,
+        company_id: Some(company_id),
+        alarm_num: def.alarm_num,
+        name: def.name,
+        zone: def.zone.to_string(),
+        severity: AlarmSeverity::from_level(def.level).into(),
+        active: true,
+        message: Some(message),
+        timestamp: now.and_utc(),
+    })
+}
+
+/// Check the active alarms once, escalating those due. Returns the number
+/// of escalations recorded.
+pub async fn escalate_alarms(
+    db: &DbConn,
+    site_db: &SiteDbConn,
+    forced: &HashSet<u16>,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let latest = site_db
+        .run(|conn| {
+            use diesel::prelude::*;
+
+            let Some((reading, registers)) = latest_alarm_reading(conn)? else {
+                return Ok(None);
+            };
+            use neems_data::schema::sources;
+
+            let source = sources::table
+                .filter(sources::id.eq(reading.source_id))
+                .first::<Source>(conn)
+                .optional()?;
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(Some((registers, source)))
+        })
+        .await?;
+    let (active, source) = match latest {
+        Some((registers, source)) => {
+            let active: Vec<(i32, NotificationSeverity)> = AlarmFlags::from_registers(&registers)
+                .active_alarms()
+                .iter()
+                .map(|def| (def.alarm_num as i32, AlarmSeverity::from_level(def.level).into()))
+                .collect();
+            (active, source)
+        }
+        None => (Vec::new(), None),
+    };
+
+    let now = Utc::now().naive_utc();
+    let mut synced: Vec<i32> = active.iter().map(|(num, _)| *num).collect();
+    synced.extend(forced.iter().map(|num| *num as i32));
+    let (source_name, site_id, source_company) =
+        source.map(|s| (s.name, s.site_id, s.company_id)).unwrap_or_default();
+    let nums: Vec<i32> = active.iter().map(|(num, _)| *num).collect();
+    let loaded = db
+        .run(move |conn| {
+            sync_active_alarms(conn, &synced, now)?;
+            if nums.is_empty() {
+                return Ok(None);
+            }
+            let company_id = match (source_company, site_id) {
+                (Some(company_id), _) => Some(company_id),
+                (None, Some(site_id)) => get_site_by_id(conn, site_id)?.map(|site| site.company_id),
+                (None, None) => None,
+            };
+            let Some(company_id) = company_id else {
+                return Ok(None);
+            };
+            let states = list_alarm_states(conn)?;
+            let policies = list_escalation_policies(conn, company_id)?;
+            let sent = list_alarm_escalations(conn, &nums)?;
+            Ok::<_, diesel::result::Error>(Some((company_id, states, policies, sent)))
+        })
+        .await?;
+    let Some((company_id, states, policies, sent)) = loaded else {
+        return Ok(0);
+    };
+    let sent: HashSet<_> =
+        sent.iter().map(|e| (e.alarm_num, e.raised_at, e.policy_id, e.tier)).collect();
+
+    let mut recorded = 0;
+    for due in due_escalations(&active, &states, &policies, &sent, now) {
+        let on_call = match due.tier.rotation_id {
+            Some(rotation_id) => db
+                .run(move |conn| get_oncall_rotation(conn, rotation_id, now))
+                .await?
+                .and_then(|rotation| on_call_at(&rotation, now))
+                .map(|shift| shift.email),
+            None => None,
+        };
+        let channel_id = due.tier.channel_id;
+        let channel = site_db
+            .run(move |conn| {
+                let Some(channel) =
+                    get_channel(conn, channel_id)?.filter(|c| c.company_id == company_id)
+                else {
+                    return Ok(None);
+                };
+                let destination = resolve_destination(conn, &channel)?;
+                Ok::<_, Box<dyn std::error::Error + Send + Sync>>(Some((channel, destination)))
+            })
+            .await;
+        let locale = match &channel {
+            Ok(Some((channel, _))) => channel.locale(),
+            _ => Locale::default(),
+        };
+        let Some(event) = escalation_event(
+            &due,
+            locale,
+            on_call.as_deref(),
+            &source_name,
+            site_id,
+            company_id,
+            now,
+        ) else {
+            continue;
+        };
+        let error = match channel {
+            Ok(Some((channel, destination))) => {
+                match send_event(&channel, destination, &event).await {
+                    Ok(Delivery::Accepted) => None,
+                    Ok(Delivery::Rejected(message)) => Some(message),
+                    Err(e) => {
+                        // The service may take it later
+                        error!(
+                            "[escalation] Alarm {} tier {} of '{}': {}",
+                            due.alarm_num, due.tier.tier, due.policy.name, e
+                        );
+                        continue;
+                    }
+                }
+            }
+            Ok(None) => Some(format!("Notification channel {} not found", channel_id)),
+            Err(e) => Some(e.to_string()),
+        };
+        let escalation = NewAlarmEscalation {
+            alarm_num: due.alarm_num,
+            raised_at: due.raised_at,
+            policy_id: due.policy.id,
+            policy_name: due.policy.name.clone(),
+            tier: due.tier.tier,
+            channel_id,
+            contacts: serde_json::to_string(&due.tier.contacts)?,
+            error,
+            escalated_at: now,
+            on_call_email: on_call,
+        };
+        db.run(move |conn| record_alarm_escalation(conn, &escalation)).await?;
+        recorded += 1;
+    }
+    Ok(recorded)
+}
+
+/// Check for alarms to escalate every [`ESCALATION_INTERVAL_SECONDS`].
+pub fn alarm_escalation_fairing() -> AdHoc {
+    AdHoc::on_liftoff("Alarm Escalation", |rocket| {
+        Box::pin(async move {
+            let (Some(db_pool), Some(site_pool)) =
+                (DbConn::pool(rocket).cloned(), SiteDbConn::pool(rocket).cloned())
+            else {
+                error!("[escalation] No database pools; alarms won't be escalated");
+                return;
+            };
+            let forced = rocket.state::<DemoForcedAlarms>().cloned().unwrap_or_default();
+            rocket::tokio::spawn(async move {
+                let interval = std::time::Duration::from_secs(ESCALATION_INTERVAL_SECONDS);
+                loop {
+                    rocket::tokio::time::sleep(interval).await;
+                    let connections = (
+                        DbConn::from_pool(&db_pool).await,
+                        SiteDbConn::from_pool(&site_pool).await,
+                    );
+                    let (Some(db), Some(site_db)) = connections else {
+                        error!("[escalation] Could not get DB connections to escalate alarms");
+                        continue;
+                    };
+                    match escalate_alarms(&db, &site_db, &forced.snapshot()).await {
+                        Ok(0) => {}
+                        Ok(count) => info!("[escalation] Escalated {} alarm tier(s)", count),
+                        Err(e) => error!("[escalation] Failed to escalate alarms: {}", e),
+                    }
+                }
+            });
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(id: i32, min_severity: NotificationSeverity, delays: &[i32]) -> EscalationPolicy {
+        let at = NaiveDateTime::default();
+        EscalationPolicy {
+            id,
+            company_id: 1,
+            name: format!("policy {}", id),
+            min_severity,
+            enabled: true,
+            tiers: delays
+                .iter()
+                .zip(1..)
+                .map(|(delay, tier)| EscalationTier {
+                    tier,
+                    delay_minutes: *delay,
+                    channel_id: 1,
+                    contacts: vec![],
+                    rotation_id: None,
+                })
+                .collect(),
+            created_at: at,
+            updated_at: at,
+        }
+    }
+
+    fn raised(alarm_num: i32, raised_at: NaiveDateTime) -> AlarmState {
+        AlarmState {
+            alarm_num,
+            raised_at: Some(raised_at),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn tiers_are_due_once_their_delay_passes() {
+        let now = Utc::now().naive_utc();
+        let raised_at = now - Duration::minutes(20);
+        let policies = [policy(1, NotificationSeverity::Critical, &[15, 30])];
+        let active = [(103, NotificationSeverity::Critical)];
+        let states = [raised(103, raised_at)];
+
+        let due = due_escalations(&active, &states, &policies, &HashSet::new(), now);
+        assert_eq!(due.len(), 1);
+        assert_eq!((due[0].alarm_num, due[0].tier.tier), (103, 1));
+
+        // Already sent for this raise, but not for a later one
+        let sent = HashSet::from([(103, raised_at, 1, 1)]);
+        assert!(due_escalations(&active, &states, &policies, &sent, now).is_empty());
+        let states = [raised(103, now - Duration::minutes(16))];
+        assert_eq!(due_escalations(&active, &states, &policies, &sent, now).len(), 1);
+    }
+
+    #[test]
+    fn acknowledged_shelved_and_minor_alarms_are_not_escalated() {
+        let now = Utc::now().naive_utc();
+        let raised_at = now - Duration::hours(2);
+        let policies = [policy(1, NotificationSeverity::Critical, &[5])];
+        let none = HashSet::new();
+
+        let warning = [(104, NotificationSeverity::Warning)];
+        let states = [raised(104, raised_at)];
+        assert!(due_escalations(&warning, &states, &policies, &none, now).is_empty());
+
+        let critical = [(103, NotificationSeverity::Critical)];
+        let acknowledged = [AlarmState {
+            acknowledged_at: Some(now),
+            ..raised(103, raised_at)
+        }];
+        assert!(due_escalations(&critical, &acknowledged, &policies, &none, now).is_empty());
+        let shelved = [AlarmState {
+            shelved_until: Some(now + Duration::hours(1)),
+            ..raised(103, raised_at)
+        }];
+        assert!(due_escalations(&critical, &shelved, &policies, &none, now).is_empty());
+
+        // An emergency policy leaves critical alarms alone
+        let policies = [policy(2, NotificationSeverity::Emergency, &[5])];
+        let states = [raised(103, raised_at)];
+        assert!(due_escalations(&critical, &states, &policies, &none, now).is_empty());
+    }
+}
@@ -0,0 +1,145 @@
+//! Network allowlists for credentials.
+//!
+//! A company, and each client certificate, can carry a list of CIDR networks.
+//! When a list is set, [`crate::session_guards::AuthenticatedUser`] only
+//! accepts requests from clients inside one of its networks, so a machine
+//! credential copied off a gateway is useless from outside the site network.
+//! An empty list places no restriction.
+//!
+//! The client address is Rocket's [`client_ip`](rocket::Request::client_ip),
+//! which trusts the `X-Real-IP` header by default. When the API is exposed
+//! without a reverse proxy that sets that header, set `ip_header = false` in
+//! `Rocket.toml` (or `ROCKET_IP_HEADER=false`) so clients can't claim an
+//! address.
+
+use std::{fmt, net::IpAddr, str::FromStr};
+
+/// An IPv4 or IPv6 network such as `10.20.0.0/16`. A bare address is a
+/// single-host network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.prefix)).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.prefix)).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid network {:?}; expected e.g. 10.0.0.0/8", s);
+        let (address, prefix) = match s.trim().split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s.trim(), None),
+        };
+        let network = IpAddr::from_str(address).map_err(|_| invalid())?.to_canonical();
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().ok().filter(|&p| p <= max).ok_or_else(invalid)?,
+            None => max,
+        };
+        Ok(Self { network, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// Parse a list of networks, normalizing each to `address/prefix`.
+pub fn parse_networks(networks: &[String]) -> Result<Vec<Cidr>, String> {
+    networks.iter().map(|n| n.parse()).collect()
+}
+
+/// Validate a list of networks for storage: each normalized to
+/// `address/prefix`, duplicates dropped.
+pub fn normalize_networks(networks: &[String]) -> Result<Vec<String>, String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for network in parse_networks(networks)? {
+        let network = network.to_string();
+        if !normalized.contains(&network) {
+            normalized.push(network);
+        }
+    }
+    Ok(normalized)
+}
+
+/// Whether a client at `ip` passes `networks`. An empty list allows
+/// everyone; otherwise a client with no known address is refused.
+pub fn allows(networks: &[Cidr], ip: Option<IpAddr>) -> bool {
+    networks.is_empty() || ip.is_some_and(|ip| networks.iter().any(|n| n.contains(ip)))
+}
+
+/// [`allows`] for networks as stored. Entries that no longer parse are
+/// skipped, but a list with no valid entries still refuses everyone.
+pub fn stored_allows(networks: &[String], ip: Option<IpAddr>) -> bool {
+    if networks.is_empty() {
+        return true;
+    }
+    let parsed: Vec<Cidr> = networks.iter().filter_map(|n| n.parse().ok()).collect();
+    !parsed.is_empty() && allows(&parsed, ip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_cidr_parsing_and_matching() {
+        let site: Cidr = "10.20.0.0/16".parse().unwrap();
+        assert!(site.contains(ip("10.20.3.4")));
+        assert!(!site.contains(ip("10.21.0.1")));
+        assert!(site.contains(ip("::ffff:10.20.0.9")), "IPv4-mapped addresses match");
+
+        let host: Cidr = "192.168.1.5".parse().unwrap();
+        assert_eq!(host.to_string(), "192.168.1.5/32");
+        assert!(host.contains(ip("192.168.1.5")));
+        assert!(!host.contains(ip("192.168.1.6")));
+
+        let v6: Cidr = "fd00::/8".parse().unwrap();
+        assert!(v6.contains(ip("fd12::1")));
+        assert!(!v6.contains(ip("10.20.3.4")));
+
+        let any: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains(ip("8.8.8.8")));
+
+        for bad in ["10.0.0.0/33", "10.0.0/8", "example.com", "10.0.0.0/x", ""] {
+            assert!(bad.parse::<Cidr>().is_err(), "{:?} should not parse", bad);
+        }
+    }
+
+    #[test]
+    fn test_allows() {
+        let networks = parse_networks(&["10.0.0.0/8".into()]).unwrap();
+        assert!(allows(&[], None));
+        assert!(allows(&networks, Some(ip("10.1.2.3"))));
+        assert!(!allows(&networks, Some(ip("203.0.113.7"))));
+        assert!(!allows(&networks, None));
+        assert!(!stored_allows(&["garbage".into()], Some(ip("10.1.2.3"))));
+        assert_eq!(
+            normalize_networks(&["10.0.0.0/8".into(), " 10.0.0.0/8".into(), "::1".into()]).unwrap(),
+            vec!["10.0.0.0/8", "::1/128"]
+        );
+    }
+}
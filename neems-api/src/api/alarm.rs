@@ -1,26 +1,68 @@
 //! API endpoints for alarm data.
 //!
 //! This module provides HTTP endpoints for accessing alarm information
-//! derived from RTAC readings stored in the site database.
-
-use std::{collections::HashSet, sync::Mutex};
+//! derived from RTAC readings stored in the site database, and for the
+//! operator workflow on top of it (ISA-18.2):
+//!
+//! - **Acknowledge**: any user can acknowledge an active alarm. The
+//!   acknowledgement holds until the alarm is next seen cleared by
+//!   `/Alarms/Active`, so a recurrence needs acknowledging again.
+//! - **Shelve**: admins and Newtown staff can hide an alarm from active views
+//!   for up to [`MAX_SHELVE_MINUTES`], giving a reason; it returns by itself
+//!   when the shelving expires, or when unshelved.
+//! - **Comment**: any user can comment on an alarm.
+//!
+//! Each is recorded with the user who did it, in the alarm's action log.
+//! Alarms left unacknowledged are escalated under their company's policies
+//! (see [`crate::alarm_escalation`]), with each escalation in the alarm's
+//! `Escalations`.
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
 
-use chrono::{DateTime, Utc};
-use neems_data::rtac::{
-    alarm_definitions::{ALARM_DEFINITIONS, ALARM_REGISTER_COUNT, AlarmDefinition, AlarmZone},
-    alarm_sld_meta::sld_meta_for,
-    state::AlarmFlags,
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use neems_data::{
+    models::Reading,
+    rtac::{
+        alarm_definitions::{ALARM_DEFINITIONS, ALARM_REGISTER_COUNT, AlarmDefinition, AlarmZone},
+        alarm_sld_meta::sld_meta_for,
+        state::AlarmFlags,
+    },
 };
-use rocket::{FromForm, Route, State, http::Status, serde::json::Json};
+use rocket::{FromForm, Route, State, http::Status, response::status, serde::json::Json};
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
-use crate::{orm::neems_data::db::SiteDbConn, session_guards::AuthenticatedUser};
+use crate::{
+    logged_json::LoggedJson,
+    models::{AlarmAction, AlarmEscalation, AlarmState},
+    orm::{
+        DbConn,
+        alarm::{
+            acknowledge_alarm, comment_on_alarm, get_alarm_state, list_alarm_actions,
+            list_alarm_states, shelve_alarm, sync_active_alarms, unshelve_alarm,
+        },
+        escalation::list_alarm_escalations,
+        neems_data::db::SiteDbConn,
+    },
+    session_guards::AuthenticatedUser,
+};
 
 /// Roles allowed to control the demo forced-alarm set — mirrors the
 /// frontend Demo Controls drawer's gate.
 const DEMO_CONTROL_ROLES: &[&str] = &["admin", "newtown-admin", "newtown-staff"];
 
+/// Roles allowed to shelve and unshelve alarms
+const SHELVE_ROLES: &[&str] = &["admin", "newtown-admin", "newtown-staff"];
+
+/// Longest an alarm can be shelved for
+pub const MAX_SHELVE_MINUTES: u32 = 72 * 60;
+
+/// Longest comment on an alarm
+const MAX_COMMENT_LENGTH: usize = 2000;
+
 /// In-memory set of alarm numbers the demo drawer has forced on. Unioned
 /// into [`get_active_alarms`] responses so the SLD, alarms page, and
 /// anything else polling `/Alarms/Active` see them as if they were real.
@@ -28,13 +70,13 @@ const DEMO_CONTROL_ROLES: &[&str] = &["admin", "newtown-admin", "newtown-staff"]
 /// Temporary scaffolding for the demo — meant to be deleted once the
 /// real RTAC feed is hooked up. Lives in memory only; resets on server
 /// restart, which is the desired demo behavior.
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct DemoForcedAlarms {
-    inner: Mutex<HashSet<u16>>,
+    inner: Arc<Mutex<HashSet<u16>>>,
 }
 
 impl DemoForcedAlarms {
-    fn snapshot(&self) -> HashSet<u16> {
+    pub(crate) fn snapshot(&self) -> HashSet<u16> {
         self.inner.lock().map(|g| g.clone()).unwrap_or_default()
     }
 
@@ -162,6 +204,14 @@ pub struct ActiveAlarmDto {
     pub message: Option<String>,
     /// Target SLD object tokens (spreadsheet "Related SLD Object").
     pub sld_targets: Vec<String>,
+    /// ISO 8601 timestamp of the acknowledgement; null while unacknowledged.
+    pub acknowledged_at: Option<String>,
+    /// Email of the user who acknowledged the alarm.
+    pub acknowledged_by: Option<String>,
+    /// ISO 8601 timestamp the alarm is shelved until; null unless shelved.
+    pub shelved_until: Option<String>,
+    /// ISO 8601 timestamp the alarm was first seen active.
+    pub raised_at: Option<String>,
 }
 
 impl From<&AlarmDefinition> for ActiveAlarmDto {
@@ -173,10 +223,18 @@ impl From<&AlarmDefinition> for ActiveAlarmDto {
             severity: AlarmSeverityDto::from_level(def.level),
             message: message_for(def.alarm_num),
             sld_targets: sld_targets_for(def.alarm_num),
+            acknowledged_at: None,
+            acknowledged_by: None,
+            shelved_until: None,
+            raised_at: None,
         }
     }
 }
 
+fn format_timestamp(timestamp: NaiveDateTime) -> String {
+    timestamp.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
 /// Response for active alarms endpoint
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export)]
@@ -188,6 +246,10 @@ pub struct ActiveAlarmsResponse {
     pub timestamp: Option<String>,
     /// How many seconds old the reading data is (null if no data)
     pub data_age_seconds: Option<i64>,
+    /// Active alarms left out because they are shelved
+    pub shelved_count: usize,
+    /// Listed alarms no one has acknowledged
+    pub unacknowledged_count: usize,
 }
 
 /// Response for alarm definitions endpoint
@@ -215,69 +277,61 @@ pub fn parse_alarm_registers(data_json: &str) -> Option<[u16; ALARM_REGISTER_COU
     Some(registers)
 }
 
-/// Get currently active alarms.
-///
-/// - **URL:** `/api/1/Alarms/Active`
-/// - **Method:** `GET`
-/// - **Authentication:** Required
-///
-/// Reads the most recent RTAC reading from the site database, decodes
-/// the alarm register bitfield, and returns all currently active alarms.
-#[get("/1/Alarms/Active")]
-pub async fn get_active_alarms(
-    _user: AuthenticatedUser,
+/// The most recent of the last few readings that has alarm registers, with
+/// them decoded.
+pub(crate) fn latest_alarm_reading(
+    conn: &mut diesel::SqliteConnection,
+) -> Result<Option<(Reading, [u16; ALARM_REGISTER_COUNT])>, diesel::result::Error> {
+    use diesel::prelude::*;
+    use neems_data::schema::readings::dsl::*;
+
+    let recent: Vec<Reading> = readings.order(timestamp.desc()).limit(10).load(conn)?;
+    Ok(recent.into_iter().find_map(|reading| {
+        let registers = parse_alarm_registers(&reading.data)?;
+        Some((reading, registers))
+    }))
+}
+
+/// The active alarms in the most recent RTAC reading, with the demo-forced
+/// ones, before operator state is applied.
+async fn current_alarms(
     site_db: SiteDbConn,
-    forced: &State<DemoForcedAlarms>,
-) -> Result<Json<ActiveAlarmsResponse>, Status> {
+    forced: &DemoForcedAlarms,
+) -> Result<ActiveAlarmsResponse, Status> {
     let forced_nums = forced.snapshot();
-    let mut response: ActiveAlarmsResponse = site_db
-        .run(|conn| {
-            use diesel::prelude::*;
-            use neems_data::schema::readings::dsl::*;
-
-            // Get the most recent readings and find one with alarm_registers
-            let recent: Vec<neems_data::models::Reading> =
-                readings.order(timestamp.desc()).limit(10).load(conn).map_err(|e| {
-                    eprintln!("Error loading readings for alarms: {:?}", e);
-                    Status::InternalServerError
-                })?;
-
-            // Find the first reading that contains alarm_registers
-            for reading in &recent {
-                if let Some(registers) = parse_alarm_registers(&reading.data) {
-                    let flags = AlarmFlags::from_registers(&registers);
-                    let active_defs = flags.active_alarms();
-
-                    let alarms: Vec<ActiveAlarmDto> =
-                        active_defs.iter().map(|def| ActiveAlarmDto::from(*def)).collect();
-
-                    let has_emergency = flags.has_emergency_alarm();
-                    let has_critical = flags.has_critical_alarm();
-
-                    let reading_timestamp = reading.timestamp;
-                    let now = Utc::now().naive_utc();
-                    let age_seconds = (now - reading_timestamp).num_seconds();
-
-                    return Ok(ActiveAlarmsResponse {
-                        alarms,
-                        has_critical,
-                        has_emergency,
-                        timestamp: Some(reading_timestamp.format("%Y-%m-%dT%H:%M:%SZ").to_string()),
-                        data_age_seconds: Some(age_seconds),
-                    });
-                }
+    let latest = site_db.run(latest_alarm_reading).await.map_err(|e| {
+        eprintln!("Error loading readings for alarms: {:?}", e);
+        Status::InternalServerError
+    })?;
+    let mut response = match latest {
+        Some((reading, registers)) => {
+            let flags = AlarmFlags::from_registers(&registers);
+            let age_seconds = (Utc::now().naive_utc() - reading.timestamp).num_seconds();
+            ActiveAlarmsResponse {
+                alarms: flags
+                    .active_alarms()
+                    .iter()
+                    .map(|def| ActiveAlarmDto::from(*def))
+                    .collect(),
+                has_critical: flags.has_critical_alarm(),
+                has_emergency: flags.has_emergency_alarm(),
+                timestamp: Some(format_timestamp(reading.timestamp)),
+                data_age_seconds: Some(age_seconds),
+                shelved_count: 0,
+                unacknowledged_count: 0,
             }
-
-            // No readings with alarm data found — return empty response
-            Ok(ActiveAlarmsResponse {
-                alarms: vec![],
-                has_critical: false,
-                has_emergency: false,
-                timestamp: None,
-                data_age_seconds: None,
-            })
-        })
-        .await?;
+        }
+        // No readings with alarm data found — return empty response
+        None => ActiveAlarmsResponse {
+            alarms: vec![],
+            has_critical: false,
+            has_emergency: false,
+            timestamp: None,
+            data_age_seconds: None,
+            shelved_count: 0,
+            unacknowledged_count: 0,
+        },
+    };
 
     // Overlay demo-forced alarms. We dedupe by alarm_num so a forced
     // alarm that's also currently active in the real feed doesn't
@@ -304,6 +358,72 @@ pub async fn get_active_alarms(
         }
     }
 
+    Ok(response)
+}
+
+/// Get currently active alarms.
+///
+/// - **URL:** `/api/1/Alarms/Active?include_shelved=<bool>`
+/// - **Method:** `GET`
+/// - **Authentication:** Required
+///
+/// Reads the most recent RTAC reading from the site database, decodes
+/// the alarm register bitfield, and returns all currently active alarms with
+/// their acknowledgement. Shelved alarms are left out, and only counted,
+/// unless `include_shelved` is set; `has_critical` and `has_emergency` cover
+/// the alarms listed. Alarms newly active are marked raised, and the
+/// acknowledgements of alarms no longer active are dropped.
+#[get("/1/Alarms/Active?<include_shelved>")]
+pub async fn get_active_alarms(
+    _user: AuthenticatedUser,
+    db: DbConn,
+    site_db: SiteDbConn,
+    forced: &State<DemoForcedAlarms>,
+    include_shelved: Option<bool>,
+) -> Result<Json<ActiveAlarmsResponse>, Status> {
+    let mut response = current_alarms(site_db, forced).await?;
+    let active: Vec<i32> = response.alarms.iter().map(|a| a.alarm_num as i32).collect();
+    let states = db
+        .run(move |conn| {
+            sync_active_alarms(conn, &active, Utc::now().naive_utc())?;
+            list_alarm_states(conn)
+        })
+        .await
+        .map_err(|e| {
+            eprintln!("Error loading alarm states: {:?}", e);
+            Status::InternalServerError
+        })?;
+
+    let now = Utc::now().naive_utc();
+    let include_shelved = include_shelved.unwrap_or(false);
+    let mut shelved_count = 0;
+    response.alarms.retain_mut(|alarm| {
+        let Some(state) = states.iter().find(|s| s.alarm_num == alarm.alarm_num as i32) else {
+            return true;
+        };
+        alarm.acknowledged_at = state.acknowledged_at.map(format_timestamp);
+        alarm.acknowledged_by = state.acknowledged_by.clone();
+        alarm.raised_at = state.raised_at.map(format_timestamp);
+        if state.is_shelved(now) {
+            alarm.shelved_until = state.shelved_until.map(format_timestamp);
+            shelved_count += 1;
+            return include_shelved;
+        }
+        true
+    });
+    response.shelved_count = shelved_count;
+    response.unacknowledged_count =
+        response.alarms.iter().filter(|a| a.acknowledged_at.is_none()).count();
+    response.has_emergency = response
+        .alarms
+        .iter()
+        .any(|a| matches!(a.severity, AlarmSeverityDto::Emergency));
+    // As AlarmFlags::has_critical_alarm, level 2 or above
+    response.has_critical = response
+        .alarms
+        .iter()
+        .any(|a| matches!(a.severity, AlarmSeverityDto::Emergency | AlarmSeverityDto::Critical));
+
     Ok(Json(response))
 }
 
@@ -525,6 +645,243 @@ pub async fn get_alarm_history(
     Ok(Json(AlarmHistoryResponse { entries, from: from_str, to: to_str }))
 }
 
+// --- Acknowledgement, shelving and comments ---
+
+/// Body for `POST /1/Alarms/<alarm_num>/Acknowledge`.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct AlarmAcknowledgeRequest {
+    pub comment: Option<String>,
+}
+
+/// Body for `POST /1/Alarms/<alarm_num>/Shelve`.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct AlarmShelveRequest {
+    /// How long to shelve the alarm for, at most [`MAX_SHELVE_MINUTES`]
+    pub minutes: u32,
+    pub reason: String,
+}
+
+/// Body for `POST /1/Alarms/<alarm_num>/Comments`.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct AlarmCommentRequest {
+    pub comment: String,
+}
+
+/// The alarm number, if it is a defined alarm; 404 otherwise.
+fn defined_alarm(alarm_num: u16) -> Result<i32, Status> {
+    if ALARM_DEFINITIONS.iter().any(|d| d.alarm_num == alarm_num) {
+        Ok(alarm_num as i32)
+    } else {
+        Err(Status::NotFound)
+    }
+}
+
+/// The trimmed comment, or 400 if it is empty or too long.
+fn valid_comment(comment: &str) -> Result<String, Status> {
+    let comment = comment.trim();
+    if comment.is_empty() || comment.len() > MAX_COMMENT_LENGTH {
+        Err(Status::BadRequest)
+    } else {
+        Ok(comment.to_string())
+    }
+}
+
+fn alarm_database_error(e: diesel::result::Error) -> Status {
+    eprintln!("Error recording alarm action: {:?}", e);
+    Status::InternalServerError
+}
+
+/// Acknowledge an active alarm.
+///
+/// - **URL:** `/api/1/Alarms/<alarm_num>/Acknowledge`
+/// - **Method:** `POST`
+/// - **Body:** `{ "comment": "Crew dispatched" }`; the comment is optional
+/// - **Authentication:** Required
+///
+/// Records the acknowledgement with the user and returns the alarm's state.
+/// Responds 404 for an unknown alarm, 409 if the alarm isn't active and 400
+/// for an empty or overlong comment.
+#[post("/1/Alarms/<alarm_num>/Acknowledge", data = "<body>")]
+pub async fn post_acknowledge_alarm(
+    alarm_num: u16,
+    body: LoggedJson<AlarmAcknowledgeRequest>,
+    user: AuthenticatedUser,
+    db: DbConn,
+    site_db: SiteDbConn,
+    forced: &State<DemoForcedAlarms>,
+) -> Result<Json<AlarmState>, Status> {
+    let num = defined_alarm(alarm_num)?;
+    let comment = body.into_inner().comment.as_deref().map(valid_comment).transpose()?;
+    let current = current_alarms(site_db, forced).await?;
+    if !current.alarms.iter().any(|a| a.alarm_num == alarm_num) {
+        return Err(Status::Conflict);
+    }
+    db.run(move |conn| acknowledge_alarm(conn, num, &user.user, comment, Utc::now().naive_utc()))
+        .await
+        .map(Json)
+        .map_err(alarm_database_error)
+}
+
+/// Shelve an alarm.
+///
+/// - **URL:** `/api/1/Alarms/<alarm_num>/Shelve`
+/// - **Method:** `POST`
+/// - **Body:** `{ "minutes": 120, "reason": "Chattering sensor, tech on site"
+///   }`
+/// - **Authentication:** Required; one of `admin`, `newtown-admin`,
+///   `newtown-staff`.
+///
+/// Hides the alarm, active or not, from `/Alarms/Active` for `minutes`,
+/// replacing any earlier shelving, and returns the alarm's state. Responds
+/// 404 for an unknown alarm and 400 for no reason or a duration of zero or
+/// over [`MAX_SHELVE_MINUTES`].
+#[post("/1/Alarms/<alarm_num>/Shelve", data = "<body>")]
+pub async fn post_shelve_alarm(
+    alarm_num: u16,
+    body: LoggedJson<AlarmShelveRequest>,
+    user: AuthenticatedUser,
+    db: DbConn,
+) -> Result<Json<AlarmState>, Status> {
+    if !user.has_any_role(SHELVE_ROLES) {
+        return Err(Status::Forbidden);
+    }
+    let num = defined_alarm(alarm_num)?;
+    let request = body.into_inner();
+    if request.minutes == 0 || request.minutes > MAX_SHELVE_MINUTES {
+        return Err(Status::BadRequest);
+    }
+    let reason = valid_comment(&request.reason)?;
+    db.run(move |conn| {
+        let now = Utc::now().naive_utc();
+        let until = now + Duration::minutes(request.minutes as i64);
+        shelve_alarm(conn, num, &user.user, until, reason, now)
+    })
+    .await
+    .map(Json)
+    .map_err(alarm_database_error)
+}
+
+/// Unshelve an alarm.
+///
+/// - **URL:** `/api/1/Alarms/<alarm_num>/Shelve`
+/// - **Method:** `DELETE`
+/// - **Authentication:** Required; one of `admin`, `newtown-admin`,
+///   `newtown-staff`.
+///
+/// Returns the alarm to active views ahead of its shelving's expiry, and
+/// returns its state. Responds 404 for an unknown alarm and 409 if it isn't
+/// shelved.
+#[delete("/1/Alarms/<alarm_num>/Shelve")]
+pub async fn delete_shelve_alarm(
+    alarm_num: u16,
+    user: AuthenticatedUser,
+    db: DbConn,
+) -> Result<Json<AlarmState>, Status> {
+    if !user.has_any_role(SHELVE_ROLES) {
+        return Err(Status::Forbidden);
+    }
+    let num = defined_alarm(alarm_num)?;
+    db.run(move |conn| {
+        let now = Utc::now().naive_utc();
+        if !get_alarm_state(conn, num).map_err(alarm_database_error)?.is_shelved(now) {
+            return Err(Status::Conflict);
+        }
+        unshelve_alarm(conn, num, &user.user, now).map_err(alarm_database_error)
+    })
+    .await
+    .map(Json)
+}
+
+/// Comment on an alarm.
+///
+/// - **URL:** `/api/1/Alarms/<alarm_num>/Comments`
+/// - **Method:** `POST`
+/// - **Body:** `{ "comment": "Breaker reset at 14:05" }`
+/// - **Authentication:** Required
+///
+/// Adds the comment to the alarm's action log and returns it (HTTP 201).
+/// Responds 404 for an unknown alarm and 400 for an empty or overlong
+/// comment.
+#[post("/1/Alarms/<alarm_num>/Comments", data = "<body>")]
+pub async fn post_alarm_comment(
+    alarm_num: u16,
+    body: LoggedJson<AlarmCommentRequest>,
+    user: AuthenticatedUser,
+    db: DbConn,
+) -> Result<status::Created<Json<AlarmAction>>, Status> {
+    let num = defined_alarm(alarm_num)?;
+    let comment = valid_comment(&body.into_inner().comment)?;
+    let action = db
+        .run(move |conn| comment_on_alarm(conn, num, &user.user, comment, Utc::now().naive_utc()))
+        .await
+        .map_err(alarm_database_error)?;
+    Ok(status::Created::new(format!("/api/1/Alarms/{}/Actions", alarm_num)).body(Json(action)))
+}
+
+/// Get an alarm's action log.
+///
+/// - **URL:** `/api/1/Alarms/<alarm_num>/Actions`
+/// - **Method:** `GET`
+/// - **Authentication:** Required
+///
+/// Returns the alarm's acknowledgements, shelvings, unshelvings and
+/// comments, newest first. Responds 404 for an unknown alarm.
+#[get("/1/Alarms/<alarm_num>/Actions")]
+pub async fn get_alarm_actions(
+    alarm_num: u16,
+    _user: AuthenticatedUser,
+    db: DbConn,
+) -> Result<Json<Vec<AlarmAction>>, Status> {
+    let num = defined_alarm(alarm_num)?;
+    db.run(move |conn| list_alarm_actions(conn, num))
+        .await
+        .map(Json)
+        .map_err(alarm_database_error)
+}
+
+/// Get an alarm's escalations.
+///
+/// - **URL:** `/api/1/Alarms/<alarm_num>/Escalations`
+/// - **Method:** `GET`
+/// - **Authentication:** Required
+///
+/// Returns each tier notified because the alarm went unacknowledged, under
+/// the escalation policies (see [`crate::alarm_escalation`]), newest first.
+/// Responds 404 for an unknown alarm.
+#[get("/1/Alarms/<alarm_num>/Escalations")]
+pub async fn get_alarm_escalations(
+    alarm_num: u16,
+    _user: AuthenticatedUser,
+    db: DbConn,
+) -> Result<Json<Vec<AlarmEscalation>>, Status> {
+    let num = defined_alarm(alarm_num)?;
+    db.run(move |conn| list_alarm_escalations(conn, &[num]))
+        .await
+        .map(Json)
+        .map_err(alarm_database_error)
+}
+
+/// Get the shelved alarms.
+///
+/// - **URL:** `/api/1/Alarms/Shelved`
+/// - **Method:** `GET`
+/// - **Authentication:** Required
+///
+/// Returns the state of each alarm whose shelving hasn't expired, active or
+/// not.
+#[get("/1/Alarms/Shelved")]
+pub async fn get_shelved_alarms(
+    _user: AuthenticatedUser,
+    db: DbConn,
+) -> Result<Json<Vec<AlarmState>>, Status> {
+    let now = Utc::now().naive_utc();
+    let states = db.run(list_alarm_states).await.map_err(alarm_database_error)?;
+    Ok(Json(states.into_iter().filter(|s| s.is_shelved(now)).collect()))
+}
+
 /// Returns all routes defined in this module.
 pub fn routes() -> Vec<Route> {
     routes![
@@ -532,6 +889,13 @@ pub fn routes() -> Vec<Route> {
         get_alarm_definitions,
         get_alarm_history,
         get_forced_alarms,
-        put_forced_alarms
+        put_forced_alarms,
+        post_acknowledge_alarm,
+        post_shelve_alarm,
+        delete_shelve_alarm,
+        post_alarm_comment,
+        get_alarm_actions,
+        get_alarm_escalations,
+        get_shelved_alarms
     ]
 }
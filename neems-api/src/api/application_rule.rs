@@ -2,25 +2,32 @@
 
 use std::collections::HashMap;
 
+use neems_data::maintenance_windows::open_window;
 use rocket::{Route, http::Status, response::status, serde::json::Json};
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
 use crate::{
+    SiteDbConn,
+    api::device_group::{group_dispatch, group_telemetry, latest_member_readings},
     logged_json::LoggedJson,
     models::{
-        ActiveCommandResponse, ActiveScheduleCommand, ApplicationRule, CalendarDaySchedule,
-        CalendarDayScheduleMatches, CreateApplicationRuleRequest, EffectiveScheduleResponse,
+        ActiveCommandResponse, ApplicationRule, CalendarDaySchedule, CalendarDayScheduleMatches,
+        CommandSource, CommandType, CreateApplicationRuleRequest, EffectiveScheduleResponse,
+        OverridePriority, RuleType,
     },
     orm::{
         DbConn,
         application_rule::{
-            create_application_rule, delete_application_rule, get_application_rule_by_id,
-            get_application_rules_for_site, get_application_rules_for_template,
-            get_calendar_schedules, get_calendar_schedules_with_matches, get_effective_schedule,
+            create_application_rule, delete_application_rule, get_active_command,
+            get_application_rule_by_id, get_application_rules_for_site,
+            get_application_rules_for_template, get_calendar_schedules,
+            get_calendar_schedules_with_matches, get_effective_schedule, preempting_overrides,
             season_fill_application_rule,
         },
+        device_group::get_schedule_dispatch_group,
         schedule_library::get_library_item,
+        scheduler_tick::record_scheduler_tick,
         site::get_site_by_id,
     },
     session_guards::AuthenticatedUser,
@@ -38,6 +45,13 @@ fn can_manage_schedule(
     site_id: i32,
     conn: &mut diesel::SqliteConnection,
 ) -> bool {
+    let site = get_site_by_id(conn, site_id).ok().flatten();
+
+    // Archived sites' schedules are read-only
+    if site.as_ref().is_some_and(|site_data| site_data.archived_at.is_some()) {
+        return false;
+    }
+
     // newtown-admin and newtown-staff can manage any schedule
     if user.has_any_role(&["newtown-admin", "newtown-staff"]) {
         return true;
@@ -45,7 +59,7 @@ fn can_manage_schedule(
 
     // Company admins can manage schedules for their company's sites
     if user.has_role("admin") {
-        if let Ok(Some(site_data)) = get_site_by_id(conn, site_id) {
+        if let Some(site_data) = site {
             return site_data.company_id == user.user.company_id;
         }
     }
@@ -53,6 +67,12 @@ fn can_manage_schedule(
     false
 }
 
+// Helper function to check if user can create or remove rules above the
+// operator priority layer (utility demand response and safety holds)
+fn can_set_priority(user: &AuthenticatedUser, priority: OverridePriority) -> bool {
+    priority == OverridePriority::Operator || user.has_any_role(&["newtown-admin", "newtown-staff"])
+}
+
 // Helper function to check if user can view schedules for a site
 fn can_view_schedule(
     user: &AuthenticatedUser,
@@ -145,6 +165,12 @@ pub async fn get_rules_for_site(
 }
 
 /// Create a new application rule
+///
+/// A specific-date rule can be given a `priority` layer above `operator`
+/// (`utility_dr` or `safety`, by Newtown staff only), which preempts every
+/// lower-layer rule on its dates. A rule whose dates are already held by a
+/// higher layer is refused with 409 Conflict, naming the rules and dates, since
+/// it would never take effect there; within a layer the newest rule still wins.
 #[post("/1/ScheduleLibraryItems/<id>/ApplicationRules", data = "<request>")]
 pub async fn create_application_rule_endpoint(
     db: DbConn,
@@ -179,7 +205,52 @@ pub async fn create_application_rule_endpoint(
             return Err(status::Custom(Status::Forbidden, err));
         }
 
-        match create_application_rule(conn, id, request.into_inner(), Some(auth_user.user.id)) {
+        let request = request.into_inner();
+        let priority = request.priority.unwrap_or_default();
+        if priority != OverridePriority::Operator && request.rule_type != RuleType::SpecificDate {
+            let err = Json(ErrorResponse {
+                error: "Only specific-date rules can have a priority above operator".to_string(),
+            });
+            return Err(status::Custom(Status::BadRequest, err));
+        }
+        if !can_set_priority(&auth_user, priority) {
+            let err = Json(ErrorResponse {
+                error: format!("Only Newtown staff can create {} rules", priority.as_str()),
+            });
+            return Err(status::Custom(Status::Forbidden, err));
+        }
+
+        if let Some(dates) = request.specific_dates.as_ref() {
+            let preempting = match preempting_overrides(conn, item.site_id, dates, priority) {
+                Ok(preempting) => preempting,
+                Err(e) => {
+                    eprintln!("Error checking override priorities: {:?}", e);
+                    let err = Json(ErrorResponse {
+                        error: "Internal server error".to_string(),
+                    });
+                    return Err(status::Custom(Status::InternalServerError, err));
+                }
+            };
+            if !preempting.is_empty() {
+                let held: Vec<String> = preempting
+                    .iter()
+                    .map(|(rule, dates)| {
+                        format!(
+                            "{} (rule {}, {})",
+                            dates.join(", "),
+                            rule.id,
+                            rule.priority.as_str()
+                        )
+                    })
+                    .collect();
+                let err = Json(ErrorResponse {
+                    error: format!("Dates held by a higher-priority override: {}", held.join("; ")),
+                });
+                return Err(status::Custom(Status::Conflict, err));
+            }
+        }
+
+        match create_application_rule(conn, id, request, Some(auth_user.user.id)) {
             Ok(rule) => {
                 let location = format!("/api/1/ApplicationRules/{}", rule.id);
                 Ok(status::Created::new(location).body(Json(rule)))
@@ -249,6 +320,12 @@ pub async fn delete_application_rule_endpoint(
             });
             return Err(status::Custom(Status::Forbidden, err));
         }
+        if !can_set_priority(&auth_user, rule.priority) {
+            let err = Json(ErrorResponse {
+                error: format!("Only Newtown staff can remove {} rules", rule.priority.as_str()),
+            });
+            return Err(status::Custom(Status::Forbidden, err));
+        }
 
         match delete_application_rule(conn, id, Some(auth_user.user.id), change_reason.as_deref()) {
             Ok(_) => Ok(Status::NoContent),
@@ -320,82 +397,95 @@ pub async fn get_effective_schedule_endpoint(
 /// command (which carries over from the previous day, since schedules are
 /// daily-cyclic). Returns `command: None` when the site has no effective
 /// schedule, so the consumer should fall back to standby.
-#[get("/1/Sites/<site_id>/ActiveCommand")]
+///
+/// While a maintenance window is open on the site, or on `device_id` when
+/// given, the response has `command: None`, `source: "maintenance"` and the
+/// window, whatever the schedule says.
+///
+/// When the command sets a site-wide `power_kw` and the site has a device
+/// group taking the scheduler's dispatch, `dispatch` has the power split
+/// across the group's members, negative for charging.
+#[get("/1/Sites/<site_id>/ActiveCommand?<device_id>")]
 pub async fn get_site_active_command(
     db: DbConn,
+    site_db: SiteDbConn,
     site_id: i32,
+    device_id: Option<i32>,
     auth_user: AuthenticatedUser,
 ) -> Result<Json<ActiveCommandResponse>, status::Custom<Json<ErrorResponse>>> {
-    db.run(move |conn| {
-        if !can_view_schedule(&auth_user, site_id, conn) {
+    let allowed = db.run(move |conn| can_view_schedule(&auth_user, site_id, conn)).await;
+    if !allowed {
+        let err = Json(ErrorResponse {
+            error: "Forbidden: insufficient permissions".to_string(),
+        });
+        return Err(status::Custom(Status::Forbidden, err));
+    }
+
+    let now = chrono::Utc::now();
+    let window = site_db
+        .run(move |conn| open_window(conn, site_id, device_id, now.naive_utc()))
+        .await
+        .map_err(|e| {
+            eprintln!("Error checking maintenance windows: {}", e);
             let err = Json(ErrorResponse {
-                error: "Forbidden: insufficient permissions".to_string(),
+                error: "Internal server error".to_string(),
             });
-            return Err(status::Custom(Status::Forbidden, err));
-        }
-
-        let now = chrono::Utc::now();
-        let today = now.date_naive();
+            status::Custom(Status::InternalServerError, err)
+        })?;
+    if let Some(window) = window {
+        return Ok(Json(ActiveCommandResponse {
+            site_id,
+            command: None,
+            source: CommandSource::Maintenance,
+            priority: None,
+            maintenance: Some(window),
+            dispatch: None,
+        }));
+    }
 
-        let effective = match get_effective_schedule(conn, site_id, today) {
-            Ok(schedule) => schedule,
-            // No schedule configured for today: no active command.
-            Err(diesel::result::Error::NotFound) => {
-                return Ok(Json(ActiveCommandResponse { site_id, command: None }));
-            }
-            Err(e) => {
-                eprintln!("Error getting effective schedule: {:?}", e);
-                let err = Json(ErrorResponse {
-                    error: "Internal server error".to_string(),
-                });
-                return Err(status::Custom(Status::InternalServerError, err));
+    let (mut response, group) = db
+        .run(move |conn| {
+            let response = get_active_command(conn, site_id, now.naive_utc())?;
+            let command_id = response.command.as_ref().map(|c| c.command_id);
+            if let Err(e) = record_scheduler_tick(conn, site_id, now.naive_utc(), command_id) {
+                eprintln!("Error recording scheduler tick for site {}: {:?}", site_id, e);
             }
-        };
-
-        let mut commands = effective.library_item.commands;
-        commands.sort_by_key(|c| c.execution_offset_seconds);
-        if commands.is_empty() {
-            return Ok(Json(ActiveCommandResponse { site_id, command: None }));
-        }
-
-        let now_secs = chrono::Timelike::num_seconds_from_midnight(&now.time()) as i32;
-
-        // The active command is the latest one whose offset is at or before the
-        // current time of day. Before the day's first command, the previous
-        // day's last command carries over.
-        let (active, carried_over) =
-            match commands.iter().rev().find(|c| c.execution_offset_seconds <= now_secs) {
-                Some(c) => (c.clone(), false),
-                None => (commands.last().expect("non-empty checked above").clone(), true),
+            // A site-wide power command is split across the dispatch group
+            let site_wide_power = response
+                .command
+                .as_ref()
+                .is_some_and(|c| c.power_kw.is_some() && c.device_id.is_none());
+            let group = if site_wide_power {
+                get_schedule_dispatch_group(conn, site_id)?
+            } else {
+                None
             };
+            Ok::<_, diesel::result::Error>((response, group))
+        })
+        .await
+        .map_err(|e| {
+            eprintln!("Error getting active command: {:?}", e);
+            let err = Json(ErrorResponse {
+                error: "Internal server error".to_string(),
+            });
+            status::Custom(Status::InternalServerError, err)
+        })?;
 
-        let ramp_duration_seconds = get_site_by_id(conn, site_id)
-            .ok()
-            .flatten()
-            .map(|s| s.ramp_duration_seconds)
-            .unwrap_or(120);
-
-        let start_day = if carried_over {
-            today.pred_opt().unwrap_or(today)
-        } else {
-            today
+    if let (Some(group), Some(command)) = (group, response.command.as_ref()) {
+        let power_kw = command.power_kw.unwrap_or_default();
+        let power_kw = match command.command_type {
+            CommandType::Discharge => power_kw,
+            CommandType::Charge | CommandType::TrickleCharge => -power_kw,
         };
-        let starts_at = start_day.and_hms_opt(0, 0, 0).unwrap_or_default()
-            + chrono::Duration::seconds(active.execution_offset_seconds as i64);
-
-        Ok(Json(ActiveCommandResponse {
-            site_id,
-            command: Some(ActiveScheduleCommand {
-                command_id: active.id,
-                command_type: active.command_type,
-                target_soc_percent: active.target_soc_percent,
-                duration_seconds: active.duration_seconds,
-                ramp_duration_seconds,
-                starts_at,
-            }),
-        }))
-    })
-    .await
+        match latest_member_readings(&site_db, &group).await {
+            Ok(latest) => {
+                let telemetry = group_telemetry(&group, &latest);
+                response.dispatch = Some(group_dispatch(&group, &telemetry, power_kw));
+            }
+            Err(e) => eprintln!("Error loading device group readings: {:?}", e),
+        }
+    }
+    Ok(Json(response))
 }
 
 /// Get calendar schedules for a month
@@ -0,0 +1,189 @@
+//! API endpoints for site and device availability.
+//!
+//! `Availability` reports how much of each day or month a site and its devices
+//! were available, rather than in fault or offline, leaving out approved
+//! maintenance windows (see [`crate::availability`]). The site's figure is
+//! also included in its `Economics` report, so monthly reports carry it.
+//!
+//! # Authorization Rules
+//! - newtown-admin and newtown-staff can view any site's availability
+//! - Other users can view their own company's sites' availability
+
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use neems_data::{DataResult, maintenance_windows::list_windows};
+use rocket::{Route, http::Status, response::status, serde::json::Json};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{
+    availability::{AvailabilityRow, MAX_OFFLINE_AFTER_SECONDS, SourceTimeline, availability},
+    economics::EconomicsPeriod,
+    orm::{
+        DbConn, SiteReadConn,
+        device_group::list_device_sources,
+        neems_data::{load_equipment_sources, load_site_readings},
+        site::get_site_by_id,
+    },
+    session_guards::AuthenticatedUser,
+};
+
+/// Days a report covers unless `from` is given
+const DEFAULT_AVAILABILITY_DAYS: i64 = 30;
+
+/// Most days one report can cover
+const MAX_AVAILABILITY_DAYS: i64 = 366;
+
+/// Error response structure for availability API failures.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+type AvailabilityError = status::Custom<Json<ErrorResponse>>;
+
+fn error(status: Status, error: impl Into<String>) -> AvailabilityError {
+    status::Custom(status, Json(ErrorResponse { error: error.into() }))
+}
+
+fn database_error(action: &str, e: impl std::fmt::Debug) -> AvailabilityError {
+    eprintln!("Error {}: {:?}", action, e);
+    error(Status::InternalServerError, "Internal server error")
+}
+
+/// A site's and its devices' availability by day or month.
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct AvailabilityResponse {
+    pub site_id: i32,
+    pub period: EconomicsPeriod,
+    #[ts(type = "string")]
+    pub from: NaiveDate,
+    #[ts(type = "string")]
+    pub to: NaiveDate,
+    /// Periods up to now, in order
+    pub rows: Vec<AvailabilityRow>,
+}
+
+/// The site's availability from `start` until `end` by `period`, from
+/// `readings` as loaded by [`load_site_readings`] from at least
+/// [`MAX_OFFLINE_AFTER_SECONDS`] before `start`, so that a source reporting
+/// just before `start` counts as online.
+pub(crate) async fn site_availability(
+    db: &DbConn,
+    site_db: &SiteReadConn,
+    site_id: i32,
+    readings: &[(i32, NaiveDateTime, String)],
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    period: EconomicsPeriod,
+) -> DataResult<Vec<AvailabilityRow>> {
+    let devices = db.run(move |conn| list_device_sources(conn, site_id)).await?;
+    let (sources, windows) = site_db
+        .run(move |conn| {
+            let sources = load_equipment_sources(conn, site_id)?;
+            let windows = list_windows(conn, site_id, false)?;
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>((sources, windows))
+        })
+        .await?;
+    let timelines: Vec<SourceTimeline> = sources
+        .iter()
+        .map(|(source_id, interval_seconds)| {
+            SourceTimeline::from_readings(*source_id, *interval_seconds, readings)
+        })
+        .collect();
+    Ok(availability(
+        &timelines,
+        &devices,
+        &windows,
+        start,
+        end,
+        Utc::now().naive_utc(),
+        period,
+    ))
+}
+
+/// Site Availability endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/Availability?from=...&to=...&period=...`
+/// - **Method:** `GET`
+/// - **Purpose:** Reports the minutes the site and each of its devices were
+///   available, in fault (a level 1 or 2 alarm) and offline (not reporting),
+///   and their availability percentage, per `day` (the default) or `month`.
+///   Time in the site's maintenance windows, or a device's, is counted as
+///   excluded instead. A device is followed through the source a device group
+///   names for it; devices without one have no figures. `from` and `to` are
+///   inclusive `YYYY-MM-DD` dates, in UTC, at most a year apart; they default
+///   to the last 30 days.
+/// - **Authentication:** Required; the user must be able to view the site
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** the `rows`, one per period up to now, each with
+/// its `period_start`, the `site` figures and the `devices`' figures
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Bad dates or period
+/// - **403 Forbidden**: User can't view the site
+/// - **404 Not Found**: No such site
+#[get("/1/Sites/<site_id>/Availability?<from>&<to>&<period>")]
+pub async fn get_site_availability(
+    db: DbConn,
+    site_db: SiteReadConn,
+    site_id: i32,
+    from: Option<String>,
+    to: Option<String>,
+    period: Option<String>,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<AvailabilityResponse>, AvailabilityError> {
+    let parse_date = |s: &str| {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .map_err(|_| error(Status::BadRequest, format!("Invalid date: {}", s)))
+    };
+    let to = match to {
+        Some(to) => parse_date(&to)?,
+        None => Utc::now().date_naive(),
+    };
+    let from = match from {
+        Some(from) => parse_date(&from)?,
+        None => to - Duration::days(DEFAULT_AVAILABILITY_DAYS - 1),
+    };
+    if from > to {
+        return Err(error(Status::BadRequest, "from must be on or before to"));
+    }
+    if (to - from).num_days() >= MAX_AVAILABILITY_DAYS {
+        return Err(error(Status::BadRequest, "The date range is longer than a year"));
+    }
+    let period = EconomicsPeriod::from_str(period.as_deref().unwrap_or("day"))
+        .map_err(|e| error(Status::BadRequest, e))?;
+
+    let site = db
+        .run(move |conn| get_site_by_id(conn, site_id))
+        .await
+        .map_err(|e| database_error("getting site", e))?
+        .ok_or_else(|| error(Status::NotFound, "Site not found"))?;
+    // newtown-admin and newtown-staff can view any site; other users their
+    // own company's
+    if !auth_user.has_any_role(&["newtown-admin", "newtown-staff"])
+        && site.company_id != auth_user.user.company_id
+    {
+        return Err(error(Status::Forbidden, "Forbidden: insufficient permissions"));
+    }
+
+    let start = from.and_hms_opt(0, 0, 0).unwrap_or_default();
+    let end = (to + Duration::days(1)).and_hms_opt(0, 0, 0).unwrap_or_default();
+    let readings_start = start - Duration::seconds(MAX_OFFLINE_AFTER_SECONDS);
+    let readings = site_db
+        .run(move |conn| load_site_readings(conn, site_id, readings_start, end))
+        .await
+        .map_err(|e| database_error("loading readings", e))?;
+    let rows = site_availability(&db, &site_db, site_id, &readings, start, end, period)
+        .await
+        .map_err(|e| database_error("computing availability", e))?;
+    Ok(Json(AvailabilityResponse { site_id, period, from, to, rows }))
+}
+
+/// Returns a vector of all routes defined in this module.
+pub fn routes() -> Vec<Route> {
+    routes![get_site_availability]
+}
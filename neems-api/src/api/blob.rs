@@ -0,0 +1,85 @@
+//! API endpoint downloading exports from local blob storage.
+//!
+//! Exports (see [`crate::storage`]) are downloaded from signed links rather
+//! than with a session, so they can be handed to a browser, a spreadsheet or
+//! a colleague. With S3 storage the links go to S3 itself; with local
+//! storage they come here.
+//!
+//! # Authorization Rules
+//! - Anyone with an unexpired link can download its blob; the link's signature
+//!   is checked instead of a session
+
+use std::path::PathBuf;
+
+use chrono::Utc;
+use rocket::{
+    Route, State,
+    http::{ContentType, Status},
+    response::status,
+    serde::json::Json,
+};
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::storage::Storage;
+
+/// Error response structure for blob API failures.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+type BlobError = status::Custom<Json<ErrorResponse>>;
+
+fn error(status: Status, error: impl Into<String>) -> BlobError {
+    status::Custom(status, Json(ErrorResponse { error: error.into() }))
+}
+
+/// Download Blob endpoint.
+///
+/// - **URL:** `/api/1/Blobs/<key>?expires=...&signature=...`
+/// - **Method:** `GET`
+/// - **Purpose:** Downloads a stored export, from the `url` an export endpoint
+///   returned
+/// - **Authentication:** The link's signature, which covers the key and the
+///   expiry time
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** the blob, typed by its extension
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: The signature is wrong or the link has expired, or
+///   blobs are stored in S3
+/// - **404 Not Found**: No such blob
+#[get("/1/Blobs/<key..>?<expires>&<signature>")]
+pub async fn download_blob(
+    key: PathBuf,
+    expires: i64,
+    signature: &str,
+    storage: &State<Storage>,
+) -> Result<(ContentType, Vec<u8>), BlobError> {
+    let key = key
+        .iter()
+        .map(|segment| segment.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    if !storage.verify(&key, expires, signature, Utc::now()) {
+        return Err(error(Status::Forbidden, "Forbidden"));
+    }
+    let data = storage.store().get(&key).await.map_err(|e| {
+        eprintln!("Error reading blob {}: {}", key, e);
+        error(Status::InternalServerError, "Internal server error")
+    })?;
+    let data = data.ok_or_else(|| error(Status::NotFound, "Not Found"))?;
+    let content_type = key
+        .rsplit_once('.')
+        .and_then(|(_, extension)| ContentType::from_extension(extension))
+        .unwrap_or(ContentType::Binary);
+    Ok((content_type, data))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![download_blob]
+}
@@ -5,30 +5,37 @@
 //! be associated with users and roles.
 
 use rocket::{
-    Route,
-    http::Status,
-    response::{self, status},
+    Route, State,
+    http::{Status, uri::Origin},
+    response::{self, content::RawJson, status},
     serde::json::Json,
 };
 use serde::Serialize;
 use ts_rs::TS;
 
 use crate::{
+    allowlist,
     company::{get_company_by_name_case_insensitive, insert_company},
-    models::{Company, CompanyInput, Site, UserWithRoles},
+    models::{AllowedNetworks, Company, CompanyInput, Site, UserWithRoles},
     odata_query::{
         ODataCollectionResponse, ODataField, ODataQuery, apply_query, apply_select,
-        build_context_url,
+        build_context_url, next_link,
     },
     orm::{
         DbConn,
-        company::{delete_company, get_all_companies},
+        allowed_network::{NetworkOwner, get_allowed_networks, set_allowed_networks},
+        company::{delete_company, get_all_companies, get_company_by_id},
         site::get_sites_by_company,
         user::get_users_by_company_with_roles,
     },
+    response_cache::{CachedEndpoint, ResponseCache},
     session_guards::AuthenticatedUser,
 };
 
+/// Cached responses that include companies, invalidated by company writes
+/// (deleting a company deletes its sites)
+const COMPANY_RESPONSES: [CachedEndpoint; 2] = [CachedEndpoint::Companies, CachedEndpoint::Sites];
+
 /// Error response structure for company API failures.
 #[derive(Serialize, TS)]
 #[ts(export)]
@@ -77,6 +84,7 @@ pub struct ErrorResponse {
 #[post("/1/Companies", data = "<new_company>")]
 pub async fn create_company(
     db: DbConn,
+    cache: &State<ResponseCache>,
     new_company: Json<CompanyInput>,
     auth_user: AuthenticatedUser,
 ) -> Result<status::Created<Json<Company>>, response::status::Custom<Json<ErrorResponse>>> {
@@ -114,6 +122,7 @@ pub async fn create_company(
             })
     })
     .await
+    .inspect(|_| cache.invalidate(&COMPANY_RESPONSES))
 }
 
 /// List Companies endpoint.
@@ -149,18 +158,45 @@ pub async fn create_company(
 /// # Arguments
 /// * `db` - Database connection pool
 ///
+/// Responses are cached per query (see [`crate::response_cache`]), except
+/// those expanding `Users`, which change with every user write.
+///
 /// # Returns
-/// * `Ok(Json<Vec<Company>>)` - List of all companies
+/// * `Ok(RawJson<String>)` - List of all companies
 /// * `Err(Status)` - Error during retrieval (typically InternalServerError)
 #[get("/1/Companies?<query..>")]
 pub async fn list_companies(
     db: DbConn,
+    cache: &State<ResponseCache>,
+    uri: &Origin<'_>,
     _auth_user: AuthenticatedUser,
     query: ODataQuery,
-) -> Result<Json<serde_json::Value>, Status> {
+) -> Result<RawJson<String>, Status> {
     // Validate query options
     query.validate().map_err(|_| Status::BadRequest)?;
 
+    let expands_users = query
+        .parse_expand()
+        .is_some_and(|expansions| expansions.iter().any(|e| e.eq_ignore_ascii_case("users")));
+    let load = async {
+        let response = load_companies(&db, &query, uri).await?;
+        serde_json::to_string(&response).map_err(|_| Status::InternalServerError)
+    };
+
+    if expands_users {
+        load.await.map(RawJson)
+    } else {
+        let key = uri.query().map(|q| q.as_str().to_string()).unwrap_or_default();
+        cache.get_or_load(CachedEndpoint::Companies, key, load).await.map(RawJson)
+    }
+}
+
+/// Builds the `/api/1/Companies` response for `query`, requested as `uri`.
+async fn load_companies(
+    db: &DbConn,
+    query: &ODataQuery,
+    uri: &Origin<'_>,
+) -> Result<serde_json::Value, Status> {
     let companies = db
         .run(|conn| get_all_companies(conn).map_err(|_| Status::InternalServerError))
         .await?;
@@ -170,7 +206,7 @@ pub async fn list_companies(
         ODataField::str("name", |c: &Company| c.name.clone()),
         ODataField::int("id", |c: &Company| c.id as i64),
     ];
-    let (filtered_companies, total_count) = apply_query(companies, &query, &fields);
+    let (filtered_companies, total_count) = apply_query(companies, query, &fields);
 
     // Handle $expand first, then $select
     let expand_props = query.parse_expand();
@@ -237,8 +273,11 @@ pub async fn list_companies(
     if query.count.unwrap_or(false) {
         response = response.with_count(total_count);
     }
+    if let Some(link) = next_link(uri, query, total_count) {
+        response = response.with_next_link(link);
+    }
 
-    Ok(Json(serde_json::to_value(response).map_err(|_| Status::InternalServerError)?))
+    serde_json::to_value(response).map_err(|_| Status::InternalServerError)
 }
 
 /// List Company Sites endpoint.
@@ -410,6 +449,7 @@ pub async fn list_company_users(
 #[delete("/1/Companies/<company_id>")]
 pub async fn delete_company_endpoint(
     db: DbConn,
+    cache: &State<ResponseCache>,
     company_id: i32,
     auth_user: AuthenticatedUser,
 ) -> Result<Status, Status> {
@@ -428,6 +468,116 @@ pub async fn delete_company_endpoint(
             })
     })
     .await
+    .inspect(|_| cache.invalidate(&COMPANY_RESPONSES))
+}
+
+/// Get Company Allowed Networks endpoint.
+///
+/// - **URL:** `/api/1/Companies/<company_id>/AllowedNetworks`
+/// - **Method:** `GET`
+/// - **Purpose:** Retrieves the networks the company's users may connect from
+/// - **Authentication:** Required (users in the company, or newtown-admin and
+///   newtown-staff)
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// {
+///   "networks": ["10.20.0.0/16", "192.168.50.7/32"]
+/// }
+/// ```
+///
+/// An empty list means the company is unrestricted.
+#[get("/1/Companies/<company_id>/AllowedNetworks")]
+pub async fn get_company_allowed_networks(
+    db: DbConn,
+    company_id: i32,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<AllowedNetworks>, Status> {
+    if auth_user.user.company_id != company_id
+        && !auth_user.has_any_role(&["newtown-admin", "newtown-staff"])
+    {
+        return Err(Status::Forbidden);
+    }
+
+    db.run(move |conn| {
+        let result = get_company_by_id(conn, company_id).and_then(|company| match company {
+            Some(_) => get_allowed_networks(conn, NetworkOwner::Company(company_id)).map(Some),
+            None => Ok(None),
+        });
+        match result {
+            Ok(Some(networks)) => Ok(Json(AllowedNetworks { networks })),
+            Ok(None) => Err(Status::NotFound),
+            Err(e) => {
+                eprintln!("Error getting allowed networks: {:?}", e);
+                Err(Status::InternalServerError)
+            }
+        }
+    })
+    .await
+}
+
+/// Set Company Allowed Networks endpoint.
+///
+/// - **URL:** `/api/1/Companies/<company_id>/AllowedNetworks`
+/// - **Method:** `PUT`
+/// - **Purpose:** Replaces the networks the company's users may connect from
+/// - **Authentication:** Required (newtown-admin, or admin in the company)
+///
+/// Once set, every request authenticated as one of the company's users must
+/// come from one of these networks, or it is refused with 403. An empty list
+/// removes the restriction. Take care not to leave out the network you are
+/// connecting from.
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "networks": ["10.20.0.0/16", "192.168.50.7"]
+/// }
+/// ```
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** the stored list, normalized to CIDR form
+///
+/// **Failure (HTTP 400 Bad Request):** A network didn't parse
+#[put("/1/Companies/<company_id>/AllowedNetworks", data = "<request>")]
+pub async fn set_company_allowed_networks(
+    db: DbConn,
+    company_id: i32,
+    request: Json<AllowedNetworks>,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<AllowedNetworks>, response::status::Custom<Json<ErrorResponse>>> {
+    let error =
+        |status, error: String| response::status::Custom(status, Json(ErrorResponse { error }));
+
+    let allowed = auth_user.has_role("newtown-admin")
+        || (auth_user.has_role("admin") && auth_user.user.company_id == company_id);
+    if !allowed {
+        return Err(error(Status::Forbidden, "Forbidden".to_string()));
+    }
+    let networks = allowlist::normalize_networks(&request.networks)
+        .map_err(|e| error(Status::BadRequest, e))?;
+
+    db.run(move |conn| {
+        match get_company_by_id(conn, company_id) {
+            Ok(Some(_)) => {}
+            Ok(None) => return Err(error(Status::NotFound, "Company not found".to_string())),
+            Err(e) => {
+                eprintln!("Error getting company: {:?}", e);
+                return Err(error(Status::InternalServerError, "Database error".to_string()));
+            }
+        }
+        set_allowed_networks(conn, NetworkOwner::Company(company_id), &networks)
+            .map(|_| Json(AllowedNetworks { networks }))
+            .map_err(|e| {
+                eprintln!("Error setting allowed networks: {:?}", e);
+                error(Status::InternalServerError, "Database error".to_string())
+            })
+    })
+    .await
 }
 
 /// Get Company Users Navigation endpoint.
@@ -468,6 +618,8 @@ pub fn routes() -> Vec<Route> {
         list_companies,
         list_company_sites,
         list_company_users,
+        get_company_allowed_networks,
+        set_company_allowed_networks,
         delete_company_endpoint
     ]
 }
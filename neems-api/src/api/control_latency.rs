@@ -0,0 +1,110 @@
+//! API endpoint for a site's control-path latency.
+//!
+//! A site's RTAC collector times each stage of carrying out a scheduled
+//! command, from the control logic's schedule lookup and interlock checks to
+//! the Modbus write and the RTAC acknowledging it, against an end-to-end
+//! budget (see [`neems_data::rtac::latency`]). It publishes the histograms to
+//! the site database, and raises the `rtac_control_latency_budget` alarm when
+//! a command goes over budget. This endpoint serves the published report.
+//!
+//! # Authorization Rules
+//! - Only newtown-admin and newtown-staff can read it
+
+use neems_data::rtac::latency::{LatencyReport, load_report};
+use rocket::{Route, http::Status, response::status, serde::json::Json};
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{DbConn, SiteDbConn, orm::site::get_site_by_id, session_guards::AuthenticatedUser};
+
+/// Error response structure for control latency API failures.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+type ControlLatencyError = status::Custom<Json<ErrorResponse>>;
+
+fn error(status: Status, error: impl Into<String>) -> ControlLatencyError {
+    status::Custom(status, Json(ErrorResponse { error: error.into() }))
+}
+
+fn database_error(e: impl std::fmt::Display) -> ControlLatencyError {
+    eprintln!("Error loading control latency: {}", e);
+    error(Status::InternalServerError, "Database error while loading control latency")
+}
+
+/// Get Site Control Latency endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/ControlLatency`
+/// - **Method:** `GET`
+/// - **Purpose:** Shows how long the site's RTAC commands take at each stage,
+///   and how often they went over the latency budget
+/// - **Authentication:** Required; Newtown staff
+///
+/// Stages are `schedule_eval`, `interlock_check`, `dispatch_wait`,
+/// `modbus_write`, `ack` and `end_to_end`, counted since the collector
+/// started. Quantiles are the upper bound of the histogram bucket they fall
+/// in. The collector publishes every 10 seconds; `updated_at` says when it
+/// last did.
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// {
+///   "budget_ms": 500,
+///   "over_budget": 2,
+///   "last_over_budget_at": "2026-10-17T14:03:22.150Z",
+///   "alarm_active": false,
+///   "stages": [
+///     {
+///       "stage": "end_to_end",
+///       "count": 48,
+///       "mean_ms": 212.4,
+///       "p50_ms": 250.0,
+///       "p95_ms": 500.0,
+///       "p99_ms": 731.0,
+///       "max_ms": 731.0,
+///       "buckets": [{ "le_ms": 1, "count": 0 }, { "le_ms": null, "count": 0 }]
+///     }
+///   ],
+///   "updated_at": "2026-10-17T14:05:00"
+/// }
+/// ```
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User is not Newtown staff
+/// - **404 Not Found**: No such site, or its collector hasn't published a
+///   report
+#[get("/1/Sites/<site_id>/ControlLatency")]
+pub async fn get_site_control_latency(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+) -> Result<Json<LatencyReport>, ControlLatencyError> {
+    if !auth_user.has_any_role(&["newtown-admin", "newtown-staff"]) {
+        return Err(error(
+            Status::Forbidden,
+            "Only Newtown staff can read a site's control latency",
+        ));
+    }
+    db.run(move |conn| get_site_by_id(conn, site_id))
+        .await
+        .map_err(database_error)?
+        .ok_or_else(|| error(Status::NotFound, "Site not found"))?;
+    site_db
+        .run(move |conn| load_report(conn, site_id))
+        .await
+        .map_err(database_error)?
+        .map(Json)
+        .ok_or_else(|| {
+            error(Status::NotFound, "The site's collector hasn't published its control latency")
+        })
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![get_site_control_latency]
+}
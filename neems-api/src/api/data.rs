@@ -8,11 +8,18 @@
 //! feature to prevent exposure in production environments.
 
 use chrono::NaiveDateTime;
+use diesel::sqlite::Sqlite;
+use neems_data::{models::Reading, schema::readings};
 use rocket::{Route, form::FromForm, http::Status, serde::json::Json};
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
-use crate::{orm::neems_data::db::SiteDbConn, session_guards::AuthenticatedUser};
+use crate::{
+    conditional_get::{Conditional, IfModifiedSince},
+    ndjson::{self, NdjsonSender, NdjsonStream, WantsNdjson},
+    orm::neems_data::{SiteReadConn, db::SiteDbConn},
+    session_guards::AuthenticatedUser,
+};
 
 /// Response structure for data sources list
 #[derive(Serialize, Deserialize, TS)]
@@ -142,6 +149,8 @@ impl ReadingsQuery {
 /// This endpoint queries the sources table and returns all configured data
 /// sources with their metadata including name, description, active status, and
 /// timing information.
+/// Argument values that look like credentials (passwords, tokens, community
+/// strings) are returned as `***`; `secret://` references are returned as is.
 ///
 /// # Response
 ///
@@ -170,7 +179,9 @@ pub async fn list_data_sources(site_db: SiteDbConn) -> Result<Json<DataSourcesRe
             use neems_data::schema::sources::dsl::*;
 
             match sources.load::<neems_data::models::Source>(conn) {
-                Ok(source_list) => Ok(Json(DataSourcesResponse { sources: source_list })),
+                Ok(source_list) => Ok(Json(DataSourcesResponse {
+                    sources: source_list.into_iter().map(|s| s.redacted()).collect(),
+                })),
                 Err(e) => {
                     eprintln!("Error loading data sources: {:?}", e);
                     Err(Status::InternalServerError)
@@ -180,6 +191,224 @@ pub async fn list_data_sources(site_db: SiteDbConn) -> Result<Json<DataSourcesRe
         .await
 }
 
+/// Readings as one JSON document, or streamed one per line as NDJSON.
+#[derive(Responder)]
+pub enum ReadingsBody {
+    Json(Json<ReadingsResponse>),
+    Ndjson(NdjsonStream),
+}
+
+/// Check that every source exists and the caller may read it.
+///
+/// newtown-staff/newtown-admin can read any source; other users only their
+/// company's, so sources without a company are for Newtown roles only.
+fn check_source_access(
+    conn: &mut diesel::SqliteConnection,
+    source_ids: &[i32],
+    user_company_id: i32,
+    has_newtown_access: bool,
+) -> Result<(), Status> {
+    use diesel::prelude::*;
+    use neems_data::schema::sources;
+
+    for src_id in source_ids {
+        let source = match sources::table
+            .filter(sources::id.eq(*src_id))
+            .first::<neems_data::models::Source>(conn)
+        {
+            Ok(s) => s,
+            Err(diesel::result::Error::NotFound) => return Err(Status::NotFound),
+            Err(e) => {
+                eprintln!("Error checking source existence: {:?}", e);
+                return Err(Status::InternalServerError);
+            }
+        };
+        if !has_newtown_access && source.company_id != Some(user_company_id) {
+            return Err(Status::Forbidden);
+        }
+    }
+    Ok(())
+}
+
+/// Build the query selecting `source_ids`' readings for `query`, ordered by
+/// source, then time.
+///
+/// Also returns whether the rows come back newest first and must be sorted
+/// afterwards, which only `to_time` with `count` needs (the newest `count`
+/// readings up to `to_time`).
+fn readings_query(
+    query: &ReadingsQuery,
+    source_ids: &[i32],
+) -> Result<(readings::BoxedQuery<'static, Sqlite>, bool), Status> {
+    use diesel::prelude::*;
+    use neems_data::schema::readings::dsl::*;
+
+    let mut query_builder = readings.filter(source_id.eq_any(source_ids.to_vec())).into_boxed();
+    let mut newest_first = false;
+
+    if let Some(since_time) = query.parse_since().map_err(|_| Status::BadRequest)? {
+        query_builder = query_builder.filter(timestamp.ge(since_time));
+    }
+
+    if let Some(until_time) = query.parse_until().map_err(|_| Status::BadRequest)? {
+        query_builder = query_builder.filter(timestamp.le(until_time));
+    }
+
+    if let Some(from_time) = query.parse_from_time().map_err(|_| Status::BadRequest)? {
+        query_builder = query_builder
+            .filter(timestamp.ge(from_time))
+            .order((source_id.asc(), timestamp.asc()));
+        if let Some(count) = query.count {
+            // For multiple sources this is a global count, not per source
+            query_builder = query_builder.limit(count);
+        }
+    } else if let Some(to_time) = query.parse_to_time().map_err(|_| Status::BadRequest)? {
+        query_builder = query_builder.filter(timestamp.le(to_time));
+        if let Some(count) = query.count {
+            query_builder = query_builder.order((source_id.asc(), timestamp.desc())).limit(count);
+            newest_first = true;
+        } else {
+            query_builder = query_builder.order((source_id.asc(), timestamp.asc()));
+        }
+    } else if let Some(latest_count) = query.latest {
+        // For multiple sources this gets the globally latest, not
+        // latest_count per source
+        query_builder = query_builder
+            .order((source_id.asc(), timestamp.desc()))
+            .limit(latest_count * source_ids.len() as i64);
+    } else {
+        // Default ordering by source_id then newest first
+        query_builder = query_builder.order((source_id.asc(), timestamp.desc()));
+    }
+
+    Ok((query_builder, newest_first))
+}
+
+/// Put readings loaded newest first back in source, then time, order.
+fn sort_readings(readings_list: &mut [Reading]) {
+    readings_list.sort_by(|a, b| a.source_id.cmp(&b.source_id).then(a.timestamp.cmp(&b.timestamp)));
+}
+
+/// Send the readings `query` selects down an NDJSON stream as SQLite
+/// returns them.
+fn stream_readings(
+    conn: &mut diesel::SqliteConnection,
+    query: readings::BoxedQuery<'static, Sqlite>,
+    newest_first: bool,
+    sender: &NdjsonSender,
+) {
+    use diesel::{connection::DefaultLoadingMode, prelude::*};
+
+    if newest_first {
+        // At most `count` rows, which have to be reordered before sending
+        match query.load::<Reading>(conn) {
+            Ok(mut readings_list) => {
+                sort_readings(&mut readings_list);
+                for reading in &readings_list {
+                    if !sender.send(reading) {
+                        return;
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("Error loading readings: {:?}", e);
+                sender.send_error("Error loading readings");
+            }
+        }
+        return;
+    }
+
+    let rows = match query.load_iter::<Reading, DefaultLoadingMode>(conn) {
+        Ok(rows) => rows,
+        Err(e) => {
+            eprintln!("Error loading readings: {:?}", e);
+            sender.send_error("Error loading readings");
+            return;
+        }
+    };
+    for row in rows {
+        match row {
+            Ok(reading) => {
+                if !sender.send(&reading) {
+                    return;
+                }
+            }
+            Err(e) => {
+                eprintln!("Error streaming readings: {:?}", e);
+                sender.send_error("Error loading readings");
+                return;
+            }
+        }
+    }
+}
+
+/// Serve the readings of `source_ids` selected by `query`, once the caller's
+/// access to every source is checked. `response_source_id` fills in the JSON
+/// response's `source_id`.
+async fn readings_response(
+    site_db: SiteReadConn,
+    user: &AuthenticatedUser,
+    source_ids: Vec<i32>,
+    response_source_id: Option<i32>,
+    query: ReadingsQuery,
+    if_modified_since: IfModifiedSince,
+    ndjson: WantsNdjson,
+) -> Result<Conditional<ReadingsBody>, Status> {
+    use diesel::prelude::*;
+
+    let (readings_query, newest_first) = readings_query(&query, &source_ids)?;
+    let user_company_id = user.user.company_id;
+    let has_newtown_access = user.has_any_role(&["newtown-staff", "newtown-admin"]);
+
+    let newest = site_db
+        .run(move |conn| {
+            check_source_access(conn, &source_ids, user_company_id, has_newtown_access)?;
+
+            // Nothing to send if no reading has arrived since the client's copy
+            readings::table
+                .filter(readings::source_id.eq_any(&source_ids))
+                .select(diesel::dsl::max(readings::timestamp))
+                .first::<Option<NaiveDateTime>>(conn)
+                .map_err(|e| {
+                    eprintln!("Error finding newest reading: {:?}", e);
+                    Status::InternalServerError
+                })
+        })
+        .await?;
+    if let Some(modified) = newest
+        && if_modified_since.is_current(newest)
+    {
+        return Ok(Conditional::NotModified(modified));
+    }
+
+    let body = if ndjson.0 {
+        let (sender, stream) = ndjson::channel();
+        rocket::tokio::spawn(async move {
+            site_db
+                .run(move |conn| stream_readings(conn, readings_query, newest_first, &sender))
+                .await
+        });
+        ReadingsBody::Ndjson(stream)
+    } else {
+        let mut readings_list = site_db
+            .run(move |conn| readings_query.load::<Reading>(conn))
+            .await
+            .map_err(|e| {
+                eprintln!("Error loading readings: {:?}", e);
+                Status::InternalServerError
+            })?;
+        if newest_first {
+            sort_readings(&mut readings_list);
+        }
+        ReadingsBody::Json(Json(ReadingsResponse {
+            readings: readings_list,
+            source_id: response_source_id,
+            total_count: None,
+        }))
+    };
+    Ok(Conditional::Modified(body, newest))
+}
+
 /// Get Readings for Single Data Source endpoint.
 ///
 /// - **URL:** `/api/1/data/<source_id>`
@@ -212,6 +441,18 @@ pub async fn list_data_sources(site_db: SiteDbConn) -> Result<Json<DataSourcesRe
 /// **Latest readings (mutually exclusive):**
 /// - `latest`: Number of most recent readings (1-10000)
 ///
+/// # Conditional Requests
+///
+/// Responses carry `Last-Modified`, the time of the newest reading from the
+/// requested sources. Send it back as `If-Modified-Since` to get HTTP 304 Not
+/// Modified, with no body, when no reading has arrived since.
+///
+/// # Streaming
+///
+/// With `Accept: application/x-ndjson` the readings are streamed one JSON
+/// object per line, in the same order, as they are read from the database
+/// (see [`crate::ndjson`]). Use this for large exports.
+///
 /// # Authorization
 ///
 /// - **Company Users**: Can only access readings from sources in their company
@@ -244,105 +485,27 @@ pub async fn list_data_sources(site_db: SiteDbConn) -> Result<Json<DataSourcesRe
 pub async fn get_source_readings(
     source_id: i32,
     query: ReadingsQuery,
+    if_modified_since: IfModifiedSince,
+    ndjson: WantsNdjson,
     user: AuthenticatedUser,
-    site_db: SiteDbConn,
-) -> Result<Json<ReadingsResponse>, Status> {
+    site_db: SiteReadConn,
+) -> Result<Conditional<ReadingsBody>, Status> {
     // Validate query parameters
     if let Err(e) = query.validate() {
         eprintln!("Invalid query parameters: {}", e);
         return Err(Status::BadRequest);
     }
 
-    let req_source_id = source_id;
-    let user_company_id = user.user.company_id;
-    let has_newtown_access = user.has_any_role(&["newtown-staff", "newtown-admin"]);
-
-    site_db
-        .run(move |conn| {
-            use diesel::prelude::*;
-            use neems_data::schema::{readings::dsl::*, sources};
-
-            // First verify the source exists and check company access
-            let source = match sources::dsl::sources
-                .filter(sources::dsl::id.eq(req_source_id))
-                .first::<neems_data::models::Source>(conn)
-            {
-                Ok(s) => s,
-                Err(diesel::result::Error::NotFound) => return Err(Status::NotFound),
-                Err(e) => {
-                    eprintln!("Error checking source existence: {:?}", e);
-                    return Err(Status::InternalServerError);
-                }
-            };
-
-            // Check company access unless user has Newtown roles
-            if !has_newtown_access {
-                match source.company_id {
-                    Some(source_company_id) if source_company_id == user_company_id => {
-                        // User can access - source is in their company
-                    }
-                    Some(_) => {
-                        // Source belongs to a different company - forbidden
-                        return Err(Status::Forbidden);
-                    }
-                    None => {
-                        // Source has no company - only Newtown roles can access
-                        return Err(Status::Forbidden);
-                    }
-                }
-            }
-
-            // Build the base query
-            let mut query_builder = readings.filter(source_id.eq(req_source_id)).into_boxed();
-
-            // Apply time-based filtering
-            if let Some(since_time) = query.parse_since().map_err(|_| Status::BadRequest)? {
-                query_builder = query_builder.filter(timestamp.ge(since_time));
-            }
-
-            if let Some(until_time) = query.parse_until().map_err(|_| Status::BadRequest)? {
-                query_builder = query_builder.filter(timestamp.le(until_time));
-            }
-
-            if let Some(from_time) = query.parse_from_time().map_err(|_| Status::BadRequest)? {
-                query_builder =
-                    query_builder.filter(timestamp.ge(from_time)).order(timestamp.asc());
-                if let Some(count) = query.count {
-                    query_builder = query_builder.limit(count);
-                }
-            } else if let Some(to_time) = query.parse_to_time().map_err(|_| Status::BadRequest)? {
-                query_builder = query_builder.filter(timestamp.le(to_time)).order(timestamp.desc());
-                if let Some(count) = query.count {
-                    query_builder = query_builder.limit(count);
-                }
-            } else if let Some(latest_count) = query.latest {
-                query_builder = query_builder.order(timestamp.desc()).limit(latest_count);
-            } else {
-                // Default ordering by timestamp if no specific time parameters
-                query_builder = query_builder.order(timestamp.desc());
-            }
-
-            // Execute query
-            match query_builder.load::<neems_data::models::Reading>(conn) {
-                Ok(mut readings_list) => {
-                    // If we ordered desc for to_time queries, reverse to get chronological order
-                    if query.to_time.is_some() {
-                        readings_list.reverse();
-                    }
-
-                    Ok(Json(ReadingsResponse {
-                        readings: readings_list,
-                        source_id: Some(req_source_id),
-                        total_count: None,
-                    }))
-                }
-                Err(e) => {
-                    eprintln!("Error loading readings: {:?}", e);
-                    Err(Status::InternalServerError)
-                }
-            }
-        })
-        .await
+    readings_response(
+        site_db,
+        &user,
+        vec![source_id],
+        Some(source_id),
+        query,
+        if_modified_since,
+        ndjson,
+    )
+    .await
 }
 
 /// Get Readings for Multiple Data Sources endpoint.
@@ -369,6 +532,18 @@ pub async fn get_source_readings(
 /// - `to_time`/`count`: Count-based to timestamp
 /// - `latest`: Number of most recent readings per source
 ///
+/// # Conditional Requests
+///
+/// Responses carry `Last-Modified`, the time of the newest reading from the
+/// requested sources. Send it back as `If-Modified-Since` to get HTTP 304 Not
+/// Modified, with no body, when no reading has arrived since.
+///
+/// # Streaming
+///
+/// With `Accept: application/x-ndjson` the readings are streamed one JSON
+/// object per line, in the same order, as they are read from the database
+/// (see [`crate::ndjson`]). Use this for large exports.
+///
 /// # Authorization
 ///
 /// - **Company Users**: Can only access readings from sources in their company
@@ -409,9 +584,11 @@ pub async fn get_source_readings(
 #[get("/1/Readings?<query..>")]
 pub async fn get_multi_source_readings(
     query: ReadingsQuery,
+    if_modified_since: IfModifiedSince,
+    ndjson: WantsNdjson,
     user: AuthenticatedUser,
-    site_db: SiteDbConn,
-) -> Result<Json<ReadingsResponse>, Status> {
+    site_db: SiteReadConn,
+) -> Result<Conditional<ReadingsBody>, Status> {
     // Validate query parameters
     if let Err(e) = query.validate() {
         eprintln!("Invalid query parameters: {}", e);
@@ -431,110 +608,8 @@ pub async fn get_multi_source_readings(
         }
     };
 
-    let user_company_id = user.user.company_id;
-    let has_newtown_access = user.has_any_role(&["newtown-staff", "newtown-admin"]);
-
-    site_db
-        .run(move |conn| {
-            use diesel::prelude::*;
-            use neems_data::schema::{readings::dsl::*, sources};
-
-            // Verify all sources exist and check company access
-            for src_id in &source_ids {
-                let source = match sources::dsl::sources
-                    .filter(sources::dsl::id.eq(*src_id))
-                    .first::<neems_data::models::Source>(conn)
-                {
-                    Ok(s) => s,
-                    Err(diesel::result::Error::NotFound) => return Err(Status::NotFound),
-                    Err(e) => {
-                        eprintln!("Error checking source existence: {:?}", e);
-                        return Err(Status::InternalServerError);
-                    }
-                };
-
-                // Check company access for each source unless user has Newtown roles
-                if !has_newtown_access {
-                    match source.company_id {
-                        Some(source_company_id) if source_company_id == user_company_id => {
-                            // User can access - source is in their company
-                        }
-                        Some(_) => {
-                            // Source belongs to a different company - forbidden
-                            return Err(Status::Forbidden);
-                        }
-                        None => {
-                            // Source has no company - only Newtown roles can access
-                            return Err(Status::Forbidden);
-                        }
-                    }
-                }
-            }
-
-            // Build the base query for multiple sources
-            let mut query_builder = readings.filter(source_id.eq_any(&source_ids)).into_boxed();
-
-            // Apply time-based filtering (same logic as single source)
-            if let Some(since_time) = query.parse_since().map_err(|_| Status::BadRequest)? {
-                query_builder = query_builder.filter(timestamp.ge(since_time));
-            }
-
-            if let Some(until_time) = query.parse_until().map_err(|_| Status::BadRequest)? {
-                query_builder = query_builder.filter(timestamp.le(until_time));
-            }
-
-            if let Some(from_time) = query.parse_from_time().map_err(|_| Status::BadRequest)? {
-                query_builder = query_builder
-                    .filter(timestamp.ge(from_time))
-                    .order((source_id.asc(), timestamp.asc()));
-                if let Some(count) = query.count {
-                    // For multi-source, apply count per source using window functions would be
-                    // complex For now, apply global count with note in
-                    // documentation
-                    query_builder = query_builder.limit(count);
-                }
-            } else if let Some(to_time) = query.parse_to_time().map_err(|_| Status::BadRequest)? {
-                query_builder = query_builder
-                    .filter(timestamp.le(to_time))
-                    .order((source_id.asc(), timestamp.desc()));
-                if let Some(count) = query.count {
-                    query_builder = query_builder.limit(count);
-                }
-            } else if let Some(latest_count) = query.latest {
-                // For latest with multiple sources, we need to get latest_count per source
-                // This requires a more complex query - for now, get globally latest
-                query_builder = query_builder
-                    .order((source_id.asc(), timestamp.desc()))
-                    .limit(latest_count * source_ids.len() as i64);
-            } else {
-                // Default ordering by source_id then timestamp
-                query_builder = query_builder.order((source_id.asc(), timestamp.desc()));
-            }
-
-            // Execute query
-            match query_builder.load::<neems_data::models::Reading>(conn) {
-                Ok(mut readings_list) => {
-                    // If we ordered desc for to_time queries, reverse within each source group
-                    if query.to_time.is_some() {
-                        // Group by source and reverse each group
-                        readings_list.sort_by(|a, b| {
-                            a.source_id.cmp(&b.source_id).then(a.timestamp.cmp(&b.timestamp))
-                        });
-                    }
-
-                    Ok(Json(ReadingsResponse {
-                        readings: readings_list,
-                        source_id: None, // Multi-source query
-                        total_count: None,
-                    }))
-                }
-                Err(e) => {
-                    eprintln!("Error loading readings: {:?}", e);
-                    Err(Status::InternalServerError)
-                }
-            }
-        })
-        .await
+    // Multi-source query
+    readings_response(site_db, &user, source_ids, None, query, if_modified_since, ndjson).await
 }
 
 /// Get Site Database Schema endpoint.
@@ -627,6 +702,119 @@ pub struct SocHistoryResponse {
     pub points: Vec<SocHistoryPoint>,
 }
 
+/// Response for `GET /api/1/DataSources/<id>/Aggregate`.
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct AggregateResponse {
+    pub source_id: i32,
+    pub field: String,
+    pub bucket_seconds: i64,
+    /// Whether rollups, the indexed `reading_points` table or the JSON data
+    /// was read
+    pub backend: neems_data::points::AggregateBackend,
+    pub buckets: Vec<neems_data::points::AggregateBucket>,
+}
+
+/// Largest number of buckets one aggregation may produce.
+const MAX_AGGREGATE_BUCKETS: i64 = 10_000;
+
+/// Aggregate a numeric reading field over time.
+///
+/// - **URL:** `/api/1/DataSources/<source_id>/Aggregate?field=...&from=...&to=.
+///   ..&bucket_seconds=...`
+/// - **Method:** `GET`
+/// - **Authentication:** Required; same company access rules as the readings
+///   endpoints
+///
+/// Returns count/min/max/avg of `field` per `bucket_seconds` window (default
+/// 3600, aligned to the Unix epoch) over `[from, to)`, which defaults to
+/// the last 24 hours. Fields the source declares as point fields are read
+/// from the indexed `reading_points` table, or from its hourly and daily
+/// rollups when the buckets are whole hours or days (see
+/// [`neems_data::points::aggregate`]); any other top-level numeric field is
+/// extracted from the readings' JSON, which is slower over long windows.
+///
+/// **Error (HTTP 400 Bad Request):** Invalid field name, timestamps, or more
+/// than 10,000 buckets
+#[get("/1/DataSources/<source_id>/Aggregate?<field>&<from>&<to>&<bucket_seconds>")]
+pub async fn get_source_aggregate(
+    source_id: i32,
+    field: String,
+    from: Option<String>,
+    to: Option<String>,
+    bucket_seconds: Option<i64>,
+    user: AuthenticatedUser,
+    site_db: SiteReadConn,
+) -> Result<Json<AggregateResponse>, Status> {
+    let parse_ts = |s: &str| -> Result<NaiveDateTime, Status> {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%SZ")
+            .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S"))
+            .map_err(|_| Status::BadRequest)
+    };
+    let to_ts = match to.as_deref() {
+        Some(t) => parse_ts(t)?,
+        None => chrono::Utc::now().naive_utc(),
+    };
+    let from_ts = match from.as_deref() {
+        Some(f) => parse_ts(f)?,
+        None => to_ts - chrono::Duration::hours(24),
+    };
+    let bucket_seconds = bucket_seconds.unwrap_or(3600);
+    if !neems_data::points::is_valid_field(&field)
+        || bucket_seconds <= 0
+        || from_ts >= to_ts
+        || (to_ts - from_ts).num_seconds() / bucket_seconds > MAX_AGGREGATE_BUCKETS
+    {
+        return Err(Status::BadRequest);
+    }
+
+    let user_company_id = user.user.company_id;
+    let has_newtown_access = user.has_any_role(&["newtown-staff", "newtown-admin"]);
+
+    site_db
+        .run(move |conn| {
+            use diesel::prelude::*;
+            use neems_data::{models::Source, schema::sources};
+
+            let source = match sources::table
+                .filter(sources::id.eq(source_id))
+                .select(Source::as_select())
+                .first::<Source>(conn)
+            {
+                Ok(s) => s,
+                Err(diesel::result::Error::NotFound) => return Err(Status::NotFound),
+                Err(e) => {
+                    eprintln!("Error checking source existence: {:?}", e);
+                    return Err(Status::InternalServerError);
+                }
+            };
+            if !has_newtown_access && source.company_id != Some(user_company_id) {
+                return Err(Status::Forbidden);
+            }
+
+            let (backend, buckets) = neems_data::points::aggregate(
+                conn,
+                &source,
+                &field,
+                from_ts,
+                to_ts,
+                bucket_seconds,
+            )
+            .map_err(|e| {
+                eprintln!("Error aggregating {} for source {}: {}", field, source_id, e);
+                Status::InternalServerError
+            })?;
+            Ok(Json(AggregateResponse {
+                source_id,
+                field,
+                bucket_seconds,
+                backend,
+                buckets,
+            }))
+        })
+        .await
+}
+
 /// Extract the battery SoC percentage from a reading's JSON `data` blob.
 ///
 /// The `charging_state` collector writes `{ "level": <number>, ... }`. We
@@ -657,7 +845,7 @@ pub async fn get_site_soc_history(
     from: Option<String>,
     to: Option<String>,
     _user: AuthenticatedUser,
-    site_db: SiteDbConn,
+    site_db: SiteReadConn,
 ) -> Result<Json<SocHistoryResponse>, Status> {
     let parse_ts = |s: &str| -> Option<NaiveDateTime> {
         NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%SZ")
@@ -763,7 +951,7 @@ pub async fn get_site_charge_discharge_summary(
     from: Option<String>,
     to: Option<String>,
     _user: AuthenticatedUser,
-    site_db: SiteDbConn,
+    site_db: SiteReadConn,
 ) -> Result<Json<ChargeDischargeSummary>, Status> {
     let parse_ts = |s: &str| -> Option<NaiveDateTime> {
         NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%SZ")
@@ -869,6 +1057,7 @@ pub fn routes() -> Vec<Route> {
             list_data_sources,
             get_source_readings,
             get_multi_source_readings,
+            get_source_aggregate,
             get_site_soc_history,
             get_site_charge_discharge_summary,
         ];
@@ -882,6 +1071,7 @@ pub fn routes() -> Vec<Route> {
             list_data_sources,
             get_source_readings,
             get_multi_source_readings,
+            get_source_aggregate,
             get_site_soc_history,
             get_site_charge_discharge_summary,
         ]
@@ -0,0 +1,126 @@
+//! Runtime settings for the neems-data monitor.
+//!
+//! neems-data applies its log level, write batching and collector
+//! concurrency limits without restarting, from overrides saved in the site
+//! database (see [`neems_data::runtime`]). These endpoints read and replace
+//! those overrides; the running monitor picks them up within a few seconds.
+//!
+//! # Authorization Rules
+//! - newtown-admin and newtown-staff can read the overrides
+//! - Only newtown-admin can change them
+
+use neems_data::runtime::{RuntimeOverrides, load_overrides, save_overrides};
+use rocket::{Route, http::Status, response::status, serde::json::Json};
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{orm::neems_data::db::SiteDbConn, session_guards::AuthenticatedUser};
+
+/// Error response structure for data runtime API failures.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+type RuntimeError = status::Custom<Json<ErrorResponse>>;
+
+fn error(status: Status, error: impl Into<String>) -> RuntimeError {
+    status::Custom(status, Json(ErrorResponse { error: error.into() }))
+}
+
+/// Get Data Runtime Overrides endpoint.
+///
+/// - **URL:** `/api/1/DataRuntime`
+/// - **Method:** `GET`
+/// - **Purpose:** Returns the runtime overrides neems-data is applying
+/// - **Authentication:** Required; `newtown-admin` or `newtown-staff`
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// {
+///   "log_level": "info,neems_data=debug",
+///   "flush_interval_ms": null,
+///   "batch_size": 200,
+///   "max_concurrent_collectors": null,
+///   "test_type_concurrency": { "ping": 8 },
+///   "updated_at": "2026-10-17T09:30:00",
+///   "updated_by": "ops@newtown.com"
+/// }
+/// ```
+///
+/// Fields that are `null` use neems-data's configured value.
+#[get("/1/DataRuntime")]
+pub async fn get_data_runtime(
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<RuntimeOverrides>, RuntimeError> {
+    if !auth_user.has_any_role(&["newtown-admin", "newtown-staff"]) {
+        return Err(error(Status::Forbidden, "Only Newtown staff can view runtime settings"));
+    }
+
+    site_db
+        .run(|conn| {
+            load_overrides(conn).map(Json).map_err(|e| {
+                eprintln!("Error loading runtime overrides: {}", e);
+                error(Status::InternalServerError, "Database error while loading runtime settings")
+            })
+        })
+        .await
+}
+
+/// Update Data Runtime Overrides endpoint.
+///
+/// - **URL:** `/api/1/DataRuntime`
+/// - **Method:** `PUT`
+/// - **Purpose:** Replaces the runtime overrides neems-data applies
+/// - **Authentication:** Required; `newtown-admin`
+///
+/// The request replaces the overrides as a whole: omitted or `null` fields
+/// go back to neems-data's configured value, so `{}` clears every override.
+/// `test_type_concurrency` replaces the configured per-test-type limits.
+///
+/// # Request Format
+///
+/// ```json
+/// { "log_level": "debug", "batch_size": 200, "test_type_concurrency": { "ping": 8 } }
+/// ```
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** The saved overrides, as from `GET`
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: An invalid log filter, a zero value or an unknown
+///   test type (every problem is listed in `error`)
+/// - **403 Forbidden**: User lacks the newtown-admin role
+#[put("/1/DataRuntime", data = "<overrides>")]
+pub async fn update_data_runtime(
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    overrides: Json<RuntimeOverrides>,
+) -> Result<Json<RuntimeOverrides>, RuntimeError> {
+    if !auth_user.has_role("newtown-admin") {
+        return Err(error(Status::Forbidden, "Only Newtown admins can change runtime settings"));
+    }
+    let overrides = overrides.into_inner();
+    overrides
+        .validate()
+        .map_err(|problems| error(Status::BadRequest, problems.join("; ")))?;
+
+    let updated_by = auth_user.user.email.clone();
+    site_db
+        .run(move |conn| {
+            save_overrides(conn, &overrides, &updated_by).map(Json).map_err(|e| {
+                eprintln!("Error saving runtime overrides: {}", e);
+                error(Status::InternalServerError, "Database error while saving runtime settings")
+            })
+        })
+        .await
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![get_data_runtime, update_data_runtime]
+}
@@ -15,17 +15,20 @@ use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
 use crate::{
+    api::site::ARCHIVED_SITE,
+    merge_patch::Patch,
     models::{Device, DeviceInput},
     odata_query::{
         ODataCollectionResponse, ODataField, ODataQuery, apply_query, apply_select,
         build_context_url,
     },
     orm::{
-        DbConn,
+        DbConn, SiteDbConn,
         device::{
             delete_device, get_all_devices, get_device_by_id, get_device_by_site_and_name,
-            get_devices_by_company, insert_device, update_device,
+            get_devices_by_company, get_devices_by_site, insert_device, update_device,
         },
+        device_group::get_device_source_id,
         site::get_site_by_id,
     },
     session_guards::AuthenticatedUser,
@@ -73,6 +76,57 @@ pub struct UpdateDeviceRequest {
     pub site_id: Option<i32>,
 }
 
+/// JSON Merge Patch for a device (see [`crate::merge_patch`]): leave a field
+/// out to keep it, send `null` to clear it. Only `description`, `serial`,
+/// `ip_address` and `install_date` can be cleared.
+#[derive(Default, Deserialize, Serialize, TS)]
+#[ts(export)]
+pub struct DevicePatch {
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<String>", optional)]
+    pub name: Patch<String>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<String>", optional = nullable)]
+    pub description: Patch<String>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<String>", optional)]
+    pub type_: Patch<String>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<String>", optional)]
+    pub model: Patch<String>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<String>", optional = nullable)]
+    pub serial: Patch<String>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<String>", optional = nullable)]
+    pub ip_address: Patch<String>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<chrono::NaiveDateTime>", optional = nullable)]
+    pub install_date: Patch<chrono::NaiveDateTime>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<i32>", optional)]
+    pub company_id: Patch<i32>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<i32>", optional)]
+    pub site_id: Patch<i32>,
+}
+
+impl From<UpdateDeviceRequest> for DevicePatch {
+    fn from(request: UpdateDeviceRequest) -> Self {
+        DevicePatch {
+            name: request.name.into(),
+            description: request.description.into(),
+            type_: request.type_.into(),
+            model: request.model.into(),
+            serial: request.serial.into(),
+            ip_address: request.ip_address.into(),
+            install_date: request.install_date.into(),
+            company_id: request.company_id.into(),
+            site_id: request.site_id.into(),
+        }
+    }
+}
+
 /// Helper function to check if user can view devices for a company
 fn can_view_devices(user: &AuthenticatedUser, company_id: i32) -> bool {
     // newtown-admin and newtown-staff can view devices in any company
@@ -103,6 +157,26 @@ fn can_crud_device(user: &AuthenticatedUser, device_company_id: i32) -> bool {
     false
 }
 
+/// 409 if the site is archived, as archived sites' devices are read-only.
+fn ensure_site_editable(
+    conn: &mut diesel::SqliteConnection,
+    site_id: i32,
+) -> Result<(), status::Custom<Json<ErrorResponse>>> {
+    match get_site_by_id(conn, site_id) {
+        Ok(Some(site)) if site.archived_at.is_some() => Err(status::Custom(
+            Status::Conflict,
+            Json(ErrorResponse { error: ARCHIVED_SITE.to_string() }),
+        )),
+        Ok(_) => Ok(()),
+        Err(_) => Err(status::Custom(
+            Status::InternalServerError,
+            Json(ErrorResponse {
+                error: "Database error while fetching site".to_string(),
+            }),
+        )),
+    }
+}
+
 /// Create Device endpoint.
 ///
 /// - **URL:** `/api/1/Devices`
@@ -185,6 +259,7 @@ pub async fn create_device(
                 }),
             ));
         }
+        ensure_site_editable(conn, site.id)?;
 
         // Check if device name already exists at this site (if name provided)
         let device_name = request.name.clone().unwrap_or_else(|| request.type_.clone());
@@ -368,121 +443,163 @@ pub async fn update_device_endpoint(
     device_id: i32,
     request: Json<UpdateDeviceRequest>,
 ) -> Result<Json<Device>, status::Custom<Json<ErrorResponse>>> {
-    let request = request.into_inner();
+    let patch = DevicePatch::from(request.into_inner());
+    db.run(move |conn| apply_device_patch(conn, &auth_user, device_id, patch)).await
+}
 
-    db.run(move |conn| {
-        // Get current device to check permissions
-        let current_device = match get_device_by_id(conn, device_id) {
-            Ok(Some(device)) => device,
+/// Patch Device endpoint.
+///
+/// - **URL:** `/api/1/Devices/{id}`
+/// - **Method:** `PATCH`
+/// - **Purpose:** Updates a device with a JSON Merge Patch (RFC 7396)
+/// - **Authentication:** Required
+/// - **Authorization:** As for `PUT`
+///
+/// Takes a [`DevicePatch`]: fields left out are kept, fields set to `null`
+/// are cleared, and the rest are set, with the same checks as `PUT`.
+///
+/// ```json
+/// { "ip_address": "10.0.0.12", "serial": null }
+/// ```
+///
+/// **Error Responses:** as for `PUT`, plus **422 Unprocessable Entity** if
+/// the patch sets a field that can't be null to `null`.
+#[patch("/1/Devices/<device_id>", data = "<patch>")]
+pub async fn patch_device_endpoint(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    device_id: i32,
+    patch: Json<DevicePatch>,
+) -> Result<Json<Device>, status::Custom<Json<ErrorResponse>>> {
+    let patch = patch.into_inner();
+    db.run(move |conn| apply_device_patch(conn, &auth_user, device_id, patch)).await
+}
+
+/// Checks and applies a device update for the `PUT` and `PATCH` endpoints.
+fn apply_device_patch(
+    conn: &mut diesel::SqliteConnection,
+    auth_user: &AuthenticatedUser,
+    device_id: i32,
+    patch: DevicePatch,
+) -> Result<Json<Device>, status::Custom<Json<ErrorResponse>>> {
+    let unprocessable =
+        |error| status::Custom(Status::UnprocessableEntity, Json(ErrorResponse { error }));
+    let name = patch.name.into_required("name").map_err(unprocessable)?;
+    let type_ = patch.type_.into_required("type_").map_err(unprocessable)?;
+    let model = patch.model.into_required("model").map_err(unprocessable)?;
+    let company_id = patch.company_id.into_required("company_id").map_err(unprocessable)?;
+    let site_id = patch.site_id.into_required("site_id").map_err(unprocessable)?;
+
+    // Get current device to check permissions
+    let current_device = match get_device_by_id(conn, device_id) {
+        Ok(Some(device)) => device,
+        Ok(None) => {
+            return Err(status::Custom(
+                Status::NotFound,
+                Json(ErrorResponse { error: "Device not found".to_string() }),
+            ));
+        }
+        Err(_) => {
+            return Err(status::Custom(
+                Status::InternalServerError,
+                Json(ErrorResponse {
+                    error: "Database error while fetching device".to_string(),
+                }),
+            ));
+        }
+    };
+
+    // Check if user can modify this device
+    if !can_crud_device(auth_user, current_device.company_id) {
+        return Err(status::Custom(
+            Status::Forbidden,
+            Json(ErrorResponse {
+                error: "Insufficient permissions to update this device".to_string(),
+            }),
+        ));
+    }
+    ensure_site_editable(conn, current_device.site_id)?;
+
+    // If changing company/site, validate the new values
+    if let (Some(new_company_id), Some(new_site_id)) = (company_id, site_id) {
+        // Verify site exists and belongs to the specified company
+        let site = match get_site_by_id(conn, new_site_id) {
+            Ok(Some(site)) => site,
             Ok(None) => {
                 return Err(status::Custom(
                     Status::NotFound,
-                    Json(ErrorResponse { error: "Device not found".to_string() }),
+                    Json(ErrorResponse { error: "Site not found".to_string() }),
                 ));
             }
             Err(_) => {
                 return Err(status::Custom(
                     Status::InternalServerError,
                     Json(ErrorResponse {
-                        error: "Database error while fetching device".to_string(),
+                        error: "Database error while fetching site".to_string(),
                     }),
                 ));
             }
         };
 
-        // Check if user can modify this device
-        if !can_crud_device(&auth_user, current_device.company_id) {
+        if site.company_id != new_company_id {
             return Err(status::Custom(
-                Status::Forbidden,
+                Status::BadRequest,
                 Json(ErrorResponse {
-                    error: "Insufficient permissions to update this device".to_string(),
+                    error: "Site does not belong to the specified company".to_string(),
                 }),
             ));
         }
+        ensure_site_editable(conn, site.id)?;
 
-        // If changing company/site, validate the new values
-        if let (Some(new_company_id), Some(new_site_id)) = (request.company_id, request.site_id) {
-            // Verify site exists and belongs to the specified company
-            let site = match get_site_by_id(conn, new_site_id) {
-                Ok(Some(site)) => site,
-                Ok(None) => {
-                    return Err(status::Custom(
-                        Status::NotFound,
-                        Json(ErrorResponse { error: "Site not found".to_string() }),
-                    ));
-                }
-                Err(_) => {
-                    return Err(status::Custom(
-                        Status::InternalServerError,
-                        Json(ErrorResponse {
-                            error: "Database error while fetching site".to_string(),
-                        }),
-                    ));
-                }
-            };
-
-            if site.company_id != new_company_id {
-                return Err(status::Custom(
-                    Status::BadRequest,
-                    Json(ErrorResponse {
-                        error: "Site does not belong to the specified company".to_string(),
-                    }),
-                ));
-            }
-
-            // Check if user can create devices for the new company
-            if !can_crud_device(&auth_user, new_company_id) {
-                return Err(status::Custom(
-                    Status::Forbidden,
-                    Json(ErrorResponse {
-                        error: "Insufficient permissions to move device to this company"
-                            .to_string(),
-                    }),
-                ));
-            }
-        }
-
-        // Check for name conflicts if name is being changed
-        if let Some(ref new_name) = request.name {
-            let target_site_id = request.site_id.unwrap_or(current_device.site_id);
-            if let Ok(Some(existing_device)) =
-                get_device_by_site_and_name(conn, target_site_id, new_name)
-                && existing_device.id != device_id
-            {
-                return Err(status::Custom(
-                    Status::BadRequest,
-                    Json(ErrorResponse {
-                        error: "Device with this name already exists at the target site"
-                            .to_string(),
-                    }),
-                ));
-            }
+        // Check if user can create devices for the new company
+        if !can_crud_device(auth_user, new_company_id) {
+            return Err(status::Custom(
+                Status::Forbidden,
+                Json(ErrorResponse {
+                    error: "Insufficient permissions to move device to this company".to_string(),
+                }),
+            ));
         }
+    }
 
-        match update_device(
-            conn,
-            device_id,
-            request.name,
-            request.description.map(Some), // Convert Option<String> to Option<Option<String>>
-            request.type_,
-            request.model,
-            request.serial.map(Some),
-            request.ip_address.map(Some),
-            request.install_date.map(Some),
-            request.company_id,
-            request.site_id,
-            Some(auth_user.user.id),
-        ) {
-            Ok(device) => Ok(Json(device)),
-            Err(_) => Err(status::Custom(
-                Status::InternalServerError,
+    // Check for name conflicts if name is being changed
+    if let Some(ref new_name) = name {
+        let target_site_id = site_id.unwrap_or(current_device.site_id);
+        if let Ok(Some(existing_device)) =
+            get_device_by_site_and_name(conn, target_site_id, new_name)
+            && existing_device.id != device_id
+        {
+            return Err(status::Custom(
+                Status::BadRequest,
                 Json(ErrorResponse {
-                    error: "Failed to update device".to_string(),
+                    error: "Device with this name already exists at the target site".to_string(),
                 }),
-            )),
+            ));
         }
-    })
-    .await
+    }
+
+    match update_device(
+        conn,
+        device_id,
+        name,
+        patch.description.into_nullable(),
+        type_,
+        model,
+        patch.serial.into_nullable(),
+        patch.ip_address.into_nullable(),
+        patch.install_date.into_nullable(),
+        company_id,
+        site_id,
+        Some(auth_user.user.id),
+    ) {
+        Ok(device) => Ok(Json(device)),
+        Err(_) => Err(status::Custom(
+            Status::InternalServerError,
+            Json(ErrorResponse {
+                error: "Failed to update device".to_string(),
+            }),
+        )),
+    }
 }
 
 /// Delete Device endpoint.
@@ -528,6 +645,7 @@ pub async fn delete_device_endpoint(
                 }),
             ));
         }
+        ensure_site_editable(conn, current_device.site_id)?;
 
         match delete_device(conn, device_id, Some(auth_user.user.id)) {
             Ok(_) => Ok(Status::NoContent),
@@ -579,6 +697,103 @@ pub async fn get_device_site(
     .await
 }
 
+/// Navigation: List Devices for Site endpoint.
+///
+/// - **URL:** `/api/1/Sites/{id}/Devices`
+/// - **Method:** `GET`
+/// - **Purpose:** Lists the devices at a site, by id
+/// - **Authentication:** Required
+/// - **Authorization:** Users can view devices in their company; newtown roles
+///   can view all
+///
+/// Returns 404 if there is no such site.
+#[get("/1/Sites/<site_id>/Devices")]
+pub async fn list_site_devices(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+) -> Result<Json<Vec<Device>>, Status> {
+    db.run(move |conn| {
+        let site = match get_site_by_id(conn, site_id) {
+            Ok(Some(site)) => site,
+            Ok(None) => return Err(Status::NotFound),
+            Err(_) => return Err(Status::InternalServerError),
+        };
+
+        // Check if user can view this site's devices
+        if !can_view_devices(&auth_user, site.company_id) {
+            return Err(Status::Forbidden);
+        }
+
+        get_devices_by_site(conn, site_id)
+            .map(Json)
+            .map_err(|_| Status::InternalServerError)
+    })
+    .await
+}
+
+/// Navigation: Get Data Source for Device endpoint.
+///
+/// - **URL:** `/api/1/Devices/{id}/Source`
+/// - **Method:** `GET`
+/// - **Purpose:** Gets the data source that reports a device's readings
+/// - **Authentication:** Required
+/// - **Authorization:** Users can view devices in their company; newtown roles
+///   can view all
+///
+/// A device's source is the one a device group names for it as a member. If
+/// more than one group does, the group with the lowest id wins. Returns 404
+/// if the device isn't in a group that names a source. Credential-like
+/// arguments are redacted as in `GET /api/1/DataSources`.
+#[get("/1/Devices/<device_id>/Source")]
+pub async fn get_device_source(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    device_id: i32,
+) -> Result<Json<neems_data::models::Source>, Status> {
+    let source_id = db
+        .run(move |conn| {
+            let device = match get_device_by_id(conn, device_id) {
+                Ok(Some(device)) => device,
+                Ok(None) => return Err(Status::NotFound),
+                Err(_) => return Err(Status::InternalServerError),
+            };
+
+            // Check if user can view this device
+            if !can_view_devices(&auth_user, device.company_id) {
+                return Err(Status::Forbidden);
+            }
+
+            match get_device_source_id(conn, device_id) {
+                Ok(Some(source_id)) => Ok(source_id),
+                Ok(None) => Err(Status::NotFound),
+                Err(_) => Err(Status::InternalServerError),
+            }
+        })
+        .await?;
+
+    site_db
+        .run(move |conn| {
+            use diesel::prelude::*;
+            use neems_data::schema::sources;
+
+            match sources::table
+                .filter(sources::id.eq(source_id))
+                .first::<neems_data::models::Source>(conn)
+                .optional()
+            {
+                Ok(Some(source)) => Ok(Json(source.redacted())),
+                Ok(None) => Err(Status::NotFound),
+                Err(e) => {
+                    eprintln!("Error loading data source: {:?}", e);
+                    Err(Status::InternalServerError)
+                }
+            }
+        })
+        .await
+}
+
 /// Returns a vector of all routes defined in this module.
 pub fn routes() -> Vec<Route> {
     routes![
@@ -586,7 +801,10 @@ pub fn routes() -> Vec<Route> {
         list_devices,
         get_device,
         update_device_endpoint,
+        patch_device_endpoint,
         delete_device_endpoint,
-        get_device_site
+        get_device_site,
+        list_site_devices,
+        get_device_source
     ]
 }
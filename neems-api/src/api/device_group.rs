@@ -0,0 +1,622 @@
+//! API endpoints for a site's device groups.
+//!
+//! A device group (e.g. "BESS containers 1-4") is a set of a site's devices
+//! controlled together. Its telemetry is rolled up from the latest readings
+//! of each member's source, and a total power setpoint dispatched to it is
+//! split across the members by the group's allocation strategy (see
+//! [`crate::power_allocation`]). On a site with several inverters, one group
+//! can take the scheduler's site-wide power commands, which the
+//! `ActiveCommand` endpoint then splits the same way.
+//!
+//! # Authorization Rules
+//! - newtown-admin and newtown-staff can see and manage any site's groups
+//! - Company admins can manage groups for their own company's sites
+//! - Other users can see their own company's sites' groups
+
+use std::collections::HashMap;
+
+use neems_data::models::Reading;
+use rocket::{Route, http::Status, response::status, serde::json::Json};
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{
+    DbConn, SiteDbConn,
+    api::{
+        data::parse_soc_level,
+        site::{ARCHIVED_SITE, can_crud_site},
+    },
+    models::{
+        DeviceGroup, DeviceGroupRequest, DeviceGroupTelemetry, DispatchRequest, GroupDispatch,
+        MemberTelemetry,
+    },
+    orm::{
+        device::get_device_by_id,
+        device_group::{
+            create_device_group, delete_device_group, get_device_group, list_device_groups,
+            update_device_group,
+        },
+        site::get_site_by_id,
+    },
+    power_allocation::{AllocationMember, allocate},
+    session_guards::AuthenticatedUser,
+};
+
+/// Error response structure for device group API failures.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+type DeviceGroupError = status::Custom<Json<ErrorResponse>>;
+
+fn error(status: Status, error: impl Into<String>) -> DeviceGroupError {
+    status::Custom(status, Json(ErrorResponse { error: error.into() }))
+}
+
+fn database_error(action: &str, e: impl std::fmt::Display) -> DeviceGroupError {
+    eprintln!("Error {} device groups: {}", action, e);
+    error(
+        Status::InternalServerError,
+        format!("Database error while {} device groups", action),
+    )
+}
+
+/// 404 unless the site exists, 403 unless `auth_user` may see it (or, with
+/// `manage`, manage it).
+async fn authorize_site(
+    db: &DbConn,
+    auth_user: &AuthenticatedUser,
+    site_id: i32,
+    manage: bool,
+) -> Result<(), DeviceGroupError> {
+    let site = db
+        .run(move |conn| get_site_by_id(conn, site_id))
+        .await
+        .map_err(|e| database_error("loading", e))?
+        .ok_or_else(|| error(Status::NotFound, "Site not found"))?;
+    if manage {
+        if !can_crud_site(auth_user, site.company_id) {
+            return Err(error(
+                Status::Forbidden,
+                "Only Newtown staff or the site's company admins can manage its device groups",
+            ));
+        }
+        if site.archived_at.is_some() {
+            return Err(error(Status::Conflict, ARCHIVED_SITE));
+        }
+    } else if !auth_user.has_any_role(&["newtown-admin", "newtown-staff"])
+        && site.company_id != auth_user.user.company_id
+    {
+        return Err(error(Status::Forbidden, "Forbidden: insufficient permissions"));
+    }
+    Ok(())
+}
+
+/// The site's group `id`, or 404.
+async fn site_group(db: &DbConn, site_id: i32, id: i32) -> Result<DeviceGroup, DeviceGroupError> {
+    db.run(move |conn| get_device_group(conn, id))
+        .await
+        .map_err(|e| database_error("loading", e))?
+        .filter(|group| group.site_id == site_id)
+        .ok_or_else(|| error(Status::NotFound, "Device group not found"))
+}
+
+/// 400 unless the request is valid and its devices and sources are on the
+/// site.
+async fn check_request(
+    db: &DbConn,
+    site_db: &SiteDbConn,
+    site_id: i32,
+    request: &DeviceGroupRequest,
+) -> Result<(), DeviceGroupError> {
+    let mut problems = request.validate().err().unwrap_or_default();
+
+    let device_ids: Vec<i32> = request.members.iter().map(|m| m.device_id).collect();
+    let devices = db
+        .run(move |conn| {
+            device_ids
+                .into_iter()
+                .map(|id| Ok((id, get_device_by_id(conn, id)?)))
+                .collect::<Result<Vec<_>, diesel::result::Error>>()
+        })
+        .await
+        .map_err(|e| database_error("loading", e))?;
+    for (id, device) in devices {
+        if device.is_none_or(|device| device.site_id != site_id) {
+            problems.push(format!("members: site has no device {}", id));
+        }
+    }
+
+    let source_ids: Vec<i32> = request.members.iter().filter_map(|m| m.source_id).collect();
+    if !source_ids.is_empty() {
+        let site_sources: Vec<i32> = site_db
+            .run(move |conn| {
+                use diesel::prelude::*;
+                use neems_data::schema::sources;
+
+                sources::table
+                    .filter(sources::id.eq_any(&source_ids))
+                    .filter(sources::site_id.eq(site_id))
+                    .select(sources::id.assume_not_null())
+                    .load::<i32>(conn)
+            })
+            .await
+            .map_err(|e| database_error("loading", e))?;
+        for member in &request.members {
+            if let Some(source_id) = member.source_id {
+                if !site_sources.contains(&source_id) {
+                    problems.push(format!("members: site has no source {}", source_id));
+                }
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(error(Status::BadRequest, problems.join("; ")))
+    }
+}
+
+/// Maps a save error to a response, a duplicate name being a bad request.
+fn save_error(e: diesel::result::Error) -> DeviceGroupError {
+    match e {
+        diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::UniqueViolation,
+            _,
+        ) => error(Status::BadRequest, "A device group with this name already exists"),
+        e => database_error("saving", e),
+    }
+}
+
+/// A member's SoC, from a reading's `level` (the charging-state collectors)
+/// or `soc_percent` (RTAC).
+fn reading_soc(data: &str) -> Option<f64> {
+    parse_soc_level(data).or_else(|| {
+        let parsed: serde_json::Value = serde_json::from_str(data).ok()?;
+        let soc = parsed.get("soc_percent")?.as_f64()?;
+        soc.is_finite().then(|| soc.clamp(0.0, 100.0))
+    })
+}
+
+/// A member's power, from a reading's `battery_power_kw` (the simulator) or
+/// `power_kw` (RTAC), positive when discharging.
+fn reading_power(data: &str) -> Option<f64> {
+    let parsed: serde_json::Value = serde_json::from_str(data).ok()?;
+    let power = parsed.get("battery_power_kw").or_else(|| parsed.get("power_kw"))?.as_f64()?;
+    power.is_finite().then_some(power)
+}
+
+/// Loads the latest reading of each of a group's member sources.
+pub(crate) async fn latest_member_readings(
+    site_db: &SiteDbConn,
+    group: &DeviceGroup,
+) -> Result<HashMap<i32, Reading>, diesel::result::Error> {
+    let source_ids: Vec<i32> = group.members.iter().filter_map(|m| m.source_id).collect();
+    site_db
+        .run(move |conn| {
+            use diesel::prelude::*;
+            use neems_data::schema::readings;
+
+            // One indexed lookup per source (idx_readings_source_recent)
+            let mut latest = HashMap::new();
+            for source_id in source_ids {
+                if let Some(reading) = readings::table
+                    .filter(readings::source_id.eq(source_id))
+                    .order(readings::timestamp.desc())
+                    .first::<Reading>(conn)
+                    .optional()?
+                {
+                    latest.insert(source_id, reading);
+                }
+            }
+            Ok(latest)
+        })
+        .await
+}
+
+/// Rolls a group's telemetry up from its members' latest readings.
+pub fn group_telemetry(
+    group: &DeviceGroup,
+    latest: &HashMap<i32, Reading>,
+) -> DeviceGroupTelemetry {
+    let members: Vec<MemberTelemetry> = group
+        .members
+        .iter()
+        .map(|member| {
+            let reading = member.source_id.and_then(|id| latest.get(&id));
+            MemberTelemetry {
+                device_id: member.device_id,
+                device_name: member.device_name.clone(),
+                soc_percent: reading.and_then(|r| reading_soc(&r.data)),
+                power_kw: reading.and_then(|r| reading_power(&r.data)),
+                timestamp: reading.map(|r| r.timestamp),
+            }
+        })
+        .collect();
+
+    let powers: Vec<f64> = members.iter().filter_map(|m| m.power_kw).collect();
+    let rated: Option<Vec<f64>> = group.members.iter().map(|m| m.rated_power_kw).collect();
+    // SoC weighted by rated power when every reporting member has one
+    let socs: Vec<(f64, Option<f64>)> = members
+        .iter()
+        .zip(&group.members)
+        .filter_map(|(t, m)| Some((t.soc_percent?, m.rated_power_kw)))
+        .collect();
+    let soc_percent = if socs.is_empty() {
+        None
+    } else if socs.iter().all(|(_, rated)| rated.is_some()) {
+        let total: f64 = socs.iter().filter_map(|(_, rated)| *rated).sum();
+        Some(socs.iter().map(|(soc, rated)| soc * rated.unwrap_or_default()).sum::<f64>() / total)
+    } else {
+        Some(socs.iter().map(|(soc, _)| soc).sum::<f64>() / socs.len() as f64)
+    };
+
+    DeviceGroupTelemetry {
+        group_id: group.id,
+        reporting_count: members.iter().filter(|m| m.timestamp.is_some()).count(),
+        total_power_kw: (!powers.is_empty()).then(|| powers.iter().sum()),
+        soc_percent,
+        rated_power_kw: rated.map(|rated| rated.iter().sum()),
+        last_reading_at: members.iter().filter_map(|m| m.timestamp).max(),
+        members,
+    }
+}
+
+/// Splits `power_kw` across a group by its allocation strategy, going by the
+/// members' SoC in `telemetry`.
+pub fn group_dispatch(
+    group: &DeviceGroup,
+    telemetry: &DeviceGroupTelemetry,
+    power_kw: f64,
+) -> GroupDispatch {
+    let members: Vec<AllocationMember> = group
+        .members
+        .iter()
+        .zip(&telemetry.members)
+        .map(|(member, t)| AllocationMember {
+            device_id: member.device_id,
+            rated_power_kw: member.rated_power_kw,
+            soc_percent: t.soc_percent,
+        })
+        .collect();
+    let setpoints = allocate(&members, power_kw, group.allocation);
+    GroupDispatch {
+        group_id: group.id,
+        allocation: group.allocation,
+        requested_power_kw: power_kw,
+        allocated_power_kw: setpoints.iter().map(|s| s.power_kw).sum(),
+        setpoints,
+    }
+}
+
+/// List Site Device Groups endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/DeviceGroups`
+/// - **Method:** `GET`
+/// - **Purpose:** Lists a site's device groups with their members, by name
+/// - **Authentication:** Required; Newtown staff or the site's company
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// [
+///   {
+///     "id": 3,
+///     "site_id": 2,
+///     "name": "BESS containers 1-4",
+///     "allocation": "soc_balancing",
+///     "schedule_dispatch": true,
+///     "members": [
+///       {
+///         "device_id": 7,
+///         "device_name": "BESS 1",
+///         "rated_power_kw": 250.0,
+///         "source_id": 12
+///       }
+///     ],
+///     "created_at": "2026-10-17T08:00:00",
+///     "updated_at": "2026-10-17T08:00:00"
+///   }
+/// ]
+/// ```
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't see the site
+/// - **404 Not Found**: No such site
+#[get("/1/Sites/<site_id>/DeviceGroups")]
+pub async fn list_site_device_groups(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+) -> Result<Json<Vec<DeviceGroup>>, DeviceGroupError> {
+    authorize_site(&db, &auth_user, site_id, false).await?;
+    db.run(move |conn| list_device_groups(conn, site_id))
+        .await
+        .map(Json)
+        .map_err(|e| database_error("loading", e))
+}
+
+/// Create Site Device Group endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/DeviceGroups`
+/// - **Method:** `POST`
+/// - **Purpose:** Groups some of the site's devices for aggregate telemetry and
+///   control
+/// - **Authentication:** Required; Newtown staff or the site's company admin
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "name": "BESS containers 1-4",
+///   "allocation": "soc_balancing",
+///   "schedule_dispatch": true,
+///   "members": [
+///     { "device_id": 7, "rated_power_kw": 250.0, "source_id": 12 },
+///     { "device_id": 8, "rated_power_kw": 250.0, "source_id": 13 }
+///   ]
+/// }
+/// ```
+///
+/// `allocation` is `equal` (the default) or `soc_balancing`. A member's
+/// `rated_power_kw` caps its share of a setpoint, and `source_id` is the site
+/// database source reporting its SoC and power; both are optional. With
+/// `schedule_dispatch`, the scheduler's site-wide power commands are split
+/// across this group, and any other group on the site stops taking them.
+///
+/// # Response
+///
+/// **Success (HTTP 201 Created):** The group, as from `GET`
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Invalid group (every problem is listed in `error`), a
+///   device or source that isn't on the site, or a name already in use
+/// - **403 Forbidden**: User can't manage the site
+/// - **404 Not Found**: No such site
+#[post("/1/Sites/<site_id>/DeviceGroups", data = "<request>")]
+pub async fn create_site_device_group(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    request: Json<DeviceGroupRequest>,
+) -> Result<status::Created<Json<DeviceGroup>>, DeviceGroupError> {
+    authorize_site(&db, &auth_user, site_id, true).await?;
+    let request = request.into_inner();
+    check_request(&db, &site_db, site_id, &request).await?;
+
+    let created = db
+        .run(move |conn| create_device_group(conn, site_id, request))
+        .await
+        .map_err(save_error)?;
+    let location = format!("/api/1/Sites/{}/DeviceGroups/{}", site_id, created.id);
+    Ok(status::Created::new(location).body(Json(created)))
+}
+
+/// Get Site Device Group endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/DeviceGroups/<id>`
+/// - **Method:** `GET`
+/// - **Purpose:** Gets one of the site's device groups
+/// - **Authentication:** Required; Newtown staff or the site's company
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** The group, as from the list
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't see the site
+/// - **404 Not Found**: No such site, or no such group on it
+#[get("/1/Sites/<site_id>/DeviceGroups/<id>")]
+pub async fn get_site_device_group(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    id: i32,
+) -> Result<Json<DeviceGroup>, DeviceGroupError> {
+    authorize_site(&db, &auth_user, site_id, false).await?;
+    site_group(&db, site_id, id).await.map(Json)
+}
+
+/// Update Site Device Group endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/DeviceGroups/<id>`
+/// - **Method:** `PUT`
+/// - **Purpose:** Replaces a group's name, allocation, dispatch setting and
+///   members
+/// - **Authentication:** Required; Newtown staff or the site's company admin
+///
+/// The request is as for `POST`.
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** The group as it now stands
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: As for `POST`
+/// - **403 Forbidden**: User can't manage the site
+/// - **404 Not Found**: No such site, or no such group on it
+#[put("/1/Sites/<site_id>/DeviceGroups/<id>", data = "<request>")]
+pub async fn update_site_device_group(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    id: i32,
+    request: Json<DeviceGroupRequest>,
+) -> Result<Json<DeviceGroup>, DeviceGroupError> {
+    authorize_site(&db, &auth_user, site_id, true).await?;
+    site_group(&db, site_id, id).await?;
+    let request = request.into_inner();
+    check_request(&db, &site_db, site_id, &request).await?;
+
+    db.run(move |conn| update_device_group(conn, id, request))
+        .await
+        .map_err(save_error)?
+        .map(Json)
+        .ok_or_else(|| error(Status::NotFound, "Device group not found"))
+}
+
+/// Delete Site Device Group endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/DeviceGroups/<id>`
+/// - **Method:** `DELETE`
+/// - **Purpose:** Deletes a group; its devices are left alone
+/// - **Authentication:** Required; Newtown staff or the site's company admin
+///
+/// # Response
+///
+/// **Success (HTTP 204 No Content)**
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't manage the site
+/// - **404 Not Found**: No such site, or no such group on it
+#[delete("/1/Sites/<site_id>/DeviceGroups/<id>")]
+pub async fn delete_site_device_group(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    id: i32,
+) -> Result<Status, DeviceGroupError> {
+    authorize_site(&db, &auth_user, site_id, true).await?;
+    site_group(&db, site_id, id).await?;
+    db.run(move |conn| delete_device_group(conn, id))
+        .await
+        .map_err(|e| database_error("deleting", e))?;
+    Ok(Status::NoContent)
+}
+
+/// Device Group Telemetry endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/DeviceGroups/<id>/Telemetry`
+/// - **Method:** `GET`
+/// - **Purpose:** Returns each member's latest SoC and power and the group's
+///   totals
+/// - **Authentication:** Required; Newtown staff or the site's company
+///
+/// A member's SoC comes from its source's latest reading's `level` or
+/// `soc_percent`, and its power from `battery_power_kw` or `power_kw`,
+/// positive when discharging. Members without a source, or whose source has
+/// no readings, don't count towards the totals. The group's SoC is the mean
+/// of its members', weighted by rated power when they all have one.
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// {
+///   "group_id": 3,
+///   "members": [
+///     {
+///       "device_id": 7,
+///       "device_name": "BESS 1",
+///       "soc_percent": 62.0,
+///       "power_kw": 120.0,
+///       "timestamp": "2026-10-17T12:00:00"
+///     }
+///   ],
+///   "reporting_count": 1,
+///   "total_power_kw": 120.0,
+///   "soc_percent": 62.0,
+///   "rated_power_kw": 250.0,
+///   "last_reading_at": "2026-10-17T12:00:00"
+/// }
+/// ```
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't see the site
+/// - **404 Not Found**: No such site, or no such group on it
+#[get("/1/Sites/<site_id>/DeviceGroups/<id>/Telemetry")]
+pub async fn get_device_group_telemetry(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    id: i32,
+) -> Result<Json<DeviceGroupTelemetry>, DeviceGroupError> {
+    authorize_site(&db, &auth_user, site_id, false).await?;
+    let group = site_group(&db, site_id, id).await?;
+    let latest = latest_member_readings(&site_db, &group)
+        .await
+        .map_err(|e| database_error("loading readings for", e))?;
+    Ok(Json(group_telemetry(&group, &latest)))
+}
+
+/// Device Group Dispatch endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/DeviceGroups/<id>/Dispatch`
+/// - **Method:** `POST`
+/// - **Purpose:** Splits a total power setpoint across the group's members
+/// - **Authentication:** Required; Newtown staff or the site's company admin
+///
+/// # Request Format
+///
+/// ```json
+/// { "power_kw": 400.0 }
+/// ```
+///
+/// `power_kw` is positive to discharge and negative to charge. It is split
+/// by the group's allocation strategy, going by the members' latest SoC (as
+/// from `Telemetry`), and capped at each member's rated power; what a capped
+/// member can't take goes to the others. The caller applies the setpoints.
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// {
+///   "group_id": 3,
+///   "allocation": "soc_balancing",
+///   "requested_power_kw": 400.0,
+///   "allocated_power_kw": 400.0,
+///   "setpoints": [
+///     { "device_id": 7, "power_kw": 248.0 },
+///     { "device_id": 8, "power_kw": 152.0 }
+///   ]
+/// }
+/// ```
+///
+/// `allocated_power_kw` is less than requested when the setpoint exceeds the
+/// members' rated power.
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: `power_kw` isn't a finite number
+/// - **403 Forbidden**: User can't manage the site
+/// - **404 Not Found**: No such site, or no such group on it
+#[post("/1/Sites/<site_id>/DeviceGroups/<id>/Dispatch", data = "<request>")]
+pub async fn dispatch_device_group(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    id: i32,
+    request: Json<DispatchRequest>,
+) -> Result<Json<GroupDispatch>, DeviceGroupError> {
+    authorize_site(&db, &auth_user, site_id, true).await?;
+    let power_kw = request.power_kw;
+    if !power_kw.is_finite() {
+        return Err(error(Status::BadRequest, "power_kw: must be a number"));
+    }
+    let group = site_group(&db, site_id, id).await?;
+    let latest = latest_member_readings(&site_db, &group)
+        .await
+        .map_err(|e| database_error("loading readings for", e))?;
+    let telemetry = group_telemetry(&group, &latest);
+    Ok(Json(group_dispatch(&group, &telemetry, power_kw)))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        list_site_device_groups,
+        create_site_device_group,
+        get_site_device_group,
+        update_site_device_group,
+        delete_site_device_group,
+        get_device_group_telemetry,
+        dispatch_device_group
+    ]
+}
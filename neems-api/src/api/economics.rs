@@ -0,0 +1,486 @@
+//! API endpoints for site economics and tariffs.
+//!
+//! A site's tariff prices the energy its batteries charge and discharge:
+//! `Economics` reports the energy, round-trip efficiency, arbitrage revenue
+//! and demand-charge savings per day or month from the site's readings (see
+//! [`crate::economics`]), as JSON or, from `Economics.csv`, as CSV. Without a
+//! tariff the energy is still reported, and the money left out. When the site
+//! has a `carbon_intensity` source, the emissions the batteries avoided are
+//! reported too (see [`crate::carbon`]), as is the site's availability (see
+//! [`crate::api::availability`]). `Economics/Exports` saves the CSV to blob
+//! storage and returns a link to download it (see [`crate::storage`]).
+//!
+//! # Authorization Rules
+//! - newtown-admin and newtown-staff can view and set any site's tariff and
+//!   economics
+//! - Company admins can set their own company's sites' tariffs
+//! - Other users can view their own company's sites' tariffs and economics
+
+use chrono::{Duration, NaiveDate, Utc};
+use rocket::{
+    Route, State,
+    http::{ContentType, Status},
+    response::status,
+    serde::json::Json,
+};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{
+    api::{
+        availability::site_availability,
+        site::{ARCHIVED_SITE, can_crud_site},
+    },
+    availability::MAX_OFFLINE_AFTER_SECONDS,
+    carbon::MAX_CARBON_AGE_MINUTES,
+    economics::{EconomicsPeriod, EconomicsRow, Pricing, account, split_readings, to_csv},
+    i18n::RequestLocale,
+    logged_json::LoggedJson,
+    models::{SetSiteTariffRequest, Site, SiteTariff},
+    orm::{
+        DbConn, SiteReadConn,
+        neems_data::load_site_readings,
+        site::get_site_by_id,
+        site_tariff::{delete_site_tariff, get_site_tariff, set_site_tariff},
+    },
+    session_guards::AuthenticatedUser,
+    storage::{BlobLink, Storage},
+};
+
+/// Days a report covers unless `from` is given
+const DEFAULT_ECONOMICS_DAYS: i64 = 30;
+
+/// Most days one report can cover
+const MAX_ECONOMICS_DAYS: i64 = 366;
+
+/// Error response structure for economics API failures.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+type EconomicsError = status::Custom<Json<ErrorResponse>>;
+
+fn error(status: Status, error: impl Into<String>) -> EconomicsError {
+    status::Custom(status, Json(ErrorResponse { error: error.into() }))
+}
+
+fn database_error(action: &str, e: impl std::fmt::Debug) -> EconomicsError {
+    eprintln!("Error {}: {:?}", action, e);
+    error(Status::InternalServerError, "Internal server error")
+}
+
+/// A site's energy and revenue by day or month.
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct EconomicsResponse {
+    pub site_id: i32,
+    pub period: EconomicsPeriod,
+    #[ts(type = "string")]
+    pub from: NaiveDate,
+    #[ts(type = "string")]
+    pub to: NaiveDate,
+    /// The tariff's currency; absent without a tariff
+    pub currency: Option<String>,
+    /// Periods with readings, in order
+    pub rows: Vec<EconomicsRow>,
+}
+
+/// The site, if `user` can view it; 404 or 403 otherwise.
+fn viewable_site(
+    conn: &mut diesel::SqliteConnection,
+    user: &AuthenticatedUser,
+    site_id: i32,
+) -> Result<Site, EconomicsError> {
+    let site = get_site_by_id(conn, site_id)
+        .map_err(|e| database_error("getting site", e))?
+        .ok_or_else(|| error(Status::NotFound, "Site not found"))?;
+
+    // newtown-admin and newtown-staff can view any site; other users their
+    // own company's
+    if user.has_any_role(&["newtown-admin", "newtown-staff"])
+        || site.company_id == user.user.company_id
+    {
+        Ok(site)
+    } else {
+        Err(error(Status::Forbidden, "Forbidden: insufficient permissions"))
+    }
+}
+
+fn validate_tariff(request: &SetSiteTariffRequest) -> Result<(), EconomicsError> {
+    let currency = &request.currency;
+    if currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_uppercase()) {
+        return Err(error(Status::BadRequest, "currency must be a three-letter code, like USD"));
+    }
+    let prices = [
+        request.standard_price_per_kwh,
+        request.off_peak_price_per_kwh,
+        request.peak_price_per_kwh,
+    ];
+    // Wholesale energy prices can go negative; demand charges can't
+    if prices.iter().any(|p| !p.is_finite()) {
+        return Err(error(Status::BadRequest, "Prices must be numbers"));
+    }
+    if !request.demand_charge_per_kw.is_finite() || request.demand_charge_per_kw < 0.0 {
+        return Err(error(Status::BadRequest, "demand_charge_per_kw must not be negative"));
+    }
+    Ok(())
+}
+
+/// The report's date range and grouping from the query.
+fn report_range(
+    from: Option<&str>,
+    to: Option<&str>,
+    period: Option<&str>,
+) -> Result<(NaiveDate, NaiveDate, EconomicsPeriod), EconomicsError> {
+    let parse_date = |s: &str| {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .map_err(|_| error(Status::BadRequest, format!("Invalid date: {}", s)))
+    };
+    let to = match to {
+        Some(to) => parse_date(to)?,
+        None => Utc::now().date_naive(),
+    };
+    let from = match from {
+        Some(from) => parse_date(from)?,
+        None => to - Duration::days(DEFAULT_ECONOMICS_DAYS - 1),
+    };
+    if from > to {
+        return Err(error(Status::BadRequest, "from must be on or before to"));
+    }
+    if (to - from).num_days() >= MAX_ECONOMICS_DAYS {
+        return Err(error(Status::BadRequest, "The date range is longer than a year"));
+    }
+    let period = EconomicsPeriod::from_str(period.unwrap_or("day"))
+        .map_err(|e| error(Status::BadRequest, e))?;
+    Ok((from, to, period))
+}
+
+/// The economics of the site's readings from `from` to `to`.
+async fn site_economics(
+    db: DbConn,
+    site_db: SiteReadConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    from: Option<String>,
+    to: Option<String>,
+    period: Option<String>,
+) -> Result<EconomicsResponse, EconomicsError> {
+    let (from, to, period) = report_range(from.as_deref(), to.as_deref(), period.as_deref())?;
+    let (site, tariff) = db
+        .run(move |conn| {
+            let site = viewable_site(conn, &auth_user, site_id)?;
+            let tariff =
+                get_site_tariff(conn, site_id).map_err(|e| database_error("getting tariff", e))?;
+            Ok::<_, EconomicsError>((site, tariff))
+        })
+        .await?;
+
+    let start = from.and_hms_opt(0, 0, 0).unwrap_or_default();
+    let end = (to + Duration::days(1)).and_hms_opt(0, 0, 0).unwrap_or_default();
+    // The carbon intensity in force at the start, and whether sources were
+    // online, depend on readings taken earlier
+    let readings_start = start
+        - Duration::minutes(MAX_CARBON_AGE_MINUTES)
+            .max(Duration::seconds(MAX_OFFLINE_AFTER_SECONDS));
+    let readings = site_db
+        .run(move |conn| load_site_readings(conn, site_id, readings_start, end))
+        .await
+        .map_err(|e| database_error("loading readings", e))?;
+    let (sources, carbon) = split_readings(&readings, start);
+    let availability = site_availability(&db, &site_db, site_id, &readings, start, end, period)
+        .await
+        .map_err(|e| database_error("computing availability", e))?;
+
+    let currency = tariff.as_ref().map(|t| t.currency.clone());
+    let pricing = tariff.map(|tariff| Pricing::new(&site, tariff));
+    let mut rows =
+        account(&sources, period, pricing.as_ref(), (!carbon.is_empty()).then_some(&carbon));
+    for row in &mut rows {
+        row.availability_percent = availability
+            .iter()
+            .find(|a| a.period_start == row.period_start)
+            .and_then(|a| a.site.as_ref()?.availability_percent);
+    }
+    Ok(EconomicsResponse {
+        site_id,
+        period,
+        from,
+        to,
+        currency,
+        rows,
+    })
+}
+
+/// Site Economics endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/Economics?from=...&to=...&period=...`
+/// - **Method:** `GET`
+/// - **Purpose:** Reports the energy the site's batteries charged and
+///   discharged, their round-trip efficiency, the arbitrage revenue and the
+///   estimated demand-charge savings, per `day` (the default) or `month`, from
+///   the site's readings and tariff. `from` and `to` are inclusive `YYYY-MM-DD`
+///   dates, in UTC, at most a year apart; they default to the last 30 days.
+/// - **Authentication:** Required; the user must be able to view the site
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** the `rows`, one per period with readings, each
+/// with its `period_start`, `charged_kwh`, `discharged_kwh`,
+/// `round_trip_efficiency_percent`, `charge_cost`, `discharge_value`,
+/// `arbitrage_revenue`, `peak_demand_kw`, `peak_net_demand_kw`,
+/// `demand_charge_savings`, `charge_emissions_kg`, `avoided_emissions_kg` and
+/// `availability_percent`; money is in `currency`, and absent when the site
+/// has no tariff, and emissions are absent when it has no carbon intensity
+/// readings
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Bad dates or period
+/// - **403 Forbidden**: User can't view the site
+/// - **404 Not Found**: No such site
+#[get("/1/Sites/<site_id>/Economics?<from>&<to>&<period>")]
+pub async fn get_site_economics(
+    db: DbConn,
+    site_db: SiteReadConn,
+    site_id: i32,
+    from: Option<String>,
+    to: Option<String>,
+    period: Option<String>,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<EconomicsResponse>, EconomicsError> {
+    site_economics(db, site_db, auth_user, site_id, from, to, period)
+        .await
+        .map(Json)
+}
+
+/// Site Economics CSV endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/Economics.csv?from=...&to=...&period=...`
+/// - **Method:** `GET`
+/// - **Purpose:** The `Economics` report's rows as CSV, for spreadsheets, with
+///   a header line; money columns are empty when the site has no tariff
+/// - **Authentication:** Required; the user must be able to view the site
+///
+/// The header names the columns as the JSON report's fields, or in Spanish or
+/// French for requests in those locales (see [`crate::i18n`]).
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** a `text/csv` document
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Bad dates or period
+/// - **403 Forbidden**: User can't view the site
+/// - **404 Not Found**: No such site
+#[get("/1/Sites/<site_id>/Economics.csv?<from>&<to>&<period>")]
+pub async fn get_site_economics_csv(
+    db: DbConn,
+    site_db: SiteReadConn,
+    site_id: i32,
+    from: Option<String>,
+    to: Option<String>,
+    period: Option<String>,
+    auth_user: AuthenticatedUser,
+    locale: RequestLocale,
+) -> Result<(ContentType, String), EconomicsError> {
+    let report = site_economics(db, site_db, auth_user, site_id, from, to, period).await?;
+    let csv = to_csv(&report.rows, locale.0).map_err(|e| database_error("writing CSV", e))?;
+    Ok((ContentType::CSV, csv))
+}
+
+/// Export Site Economics endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/Economics/Exports?from=...&to=...&
+///   period=...`
+/// - **Method:** `POST`
+/// - **Purpose:** Saves the `Economics.csv` report to blob storage, for
+///   downloading later or sharing, and returns a link to it
+/// - **Authentication:** Required; the user must be able to view the site
+///
+/// # Response
+///
+/// **Success (HTTP 201 Created):**
+/// ```json
+/// {
+///   "key": "reports/site-3/economics-2026-09-01-2026-09-30-day-0f6c...csv",
+///   "url": "/api/1/Blobs/reports/site-3/economics-...csv?expires=1792...&signature=9a1c...",
+///   "expires_at": "2026-10-17T13:00:00"
+/// }
+/// ```
+///
+/// Anyone with the `url` can download the CSV until `expires_at`; with S3
+/// storage it is a presigned S3 URL.
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Bad dates or period
+/// - **403 Forbidden**: User can't view the site
+/// - **404 Not Found**: No such site
+#[post("/1/Sites/<site_id>/Economics/Exports?<from>&<to>&<period>")]
+pub async fn export_site_economics(
+    db: DbConn,
+    site_db: SiteReadConn,
+    site_id: i32,
+    from: Option<String>,
+    to: Option<String>,
+    period: Option<String>,
+    auth_user: AuthenticatedUser,
+    locale: RequestLocale,
+    storage: &State<Storage>,
+) -> Result<status::Created<Json<BlobLink>>, EconomicsError> {
+    let report = site_economics(db, site_db, auth_user, site_id, from, to, period).await?;
+    let csv = to_csv(&report.rows, locale.0).map_err(|e| database_error("writing CSV", e))?;
+    let period = match report.period {
+        EconomicsPeriod::Day => "day",
+        EconomicsPeriod::Month => "month",
+    };
+    let key = format!(
+        "reports/site-{}/economics-{}-{}-{}-{}.csv",
+        site_id,
+        report.from,
+        report.to,
+        period,
+        uuid::Uuid::new_v4()
+    );
+    storage
+        .store()
+        .put(&key, csv.into_bytes(), "text/csv")
+        .await
+        .map_err(|e| database_error("storing export", e))?;
+    let link = storage
+        .link(&key, Utc::now())
+        .map_err(|e| database_error("signing export URL", e))?;
+    Ok(status::Created::new(link.url.clone()).body(Json(link)))
+}
+
+/// Get Site Tariff endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/Tariff`
+/// - **Method:** `GET`
+/// - **Purpose:** Returns the site's electricity prices
+/// - **Authentication:** Required; the user must be able to view the site
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** the tariff
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't view the site
+/// - **404 Not Found**: No such site, or it has no tariff
+#[get("/1/Sites/<site_id>/Tariff")]
+pub async fn get_site_tariff_endpoint(
+    db: DbConn,
+    site_id: i32,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<SiteTariff>, EconomicsError> {
+    db.run(move |conn| {
+        viewable_site(conn, &auth_user, site_id)?;
+        get_site_tariff(conn, site_id)
+            .map_err(|e| database_error("getting tariff", e))?
+            .map(Json)
+            .ok_or_else(|| error(Status::NotFound, "The site has no tariff"))
+    })
+    .await
+}
+
+/// Set Site Tariff endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/Tariff`
+/// - **Method:** `PUT`
+/// - **Purpose:** Sets the site's electricity prices, replacing any it has.
+///   Energy costs the off-peak price in the site's off-peak window, the peak
+///   price in its peak revenue window and the standard price otherwise.
+/// - **Authentication:** Required; the user must be able to edit the site
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "currency": "USD",
+///   "standard_price_per_kwh": 0.15,
+///   "off_peak_price_per_kwh": 0.08,
+///   "peak_price_per_kwh": 0.32,
+///   "demand_charge_per_kw": 14.5
+/// }
+/// ```
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** the tariff
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Bad currency or prices
+/// - **403 Forbidden**: User can't edit the site
+/// - **404 Not Found**: No such site
+#[put("/1/Sites/<site_id>/Tariff", data = "<request>")]
+pub async fn set_site_tariff_endpoint(
+    db: DbConn,
+    site_id: i32,
+    request: LoggedJson<SetSiteTariffRequest>,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<SiteTariff>, EconomicsError> {
+    db.run(move |conn| {
+        let site = viewable_site(conn, &auth_user, site_id)?;
+        if !can_crud_site(&auth_user, site.company_id) {
+            return Err(error(Status::Forbidden, "Forbidden: insufficient permissions"));
+        }
+        if site.archived_at.is_some() {
+            return Err(error(Status::Conflict, ARCHIVED_SITE));
+        }
+        let req = request.into_inner();
+        validate_tariff(&req)?;
+        set_site_tariff(conn, site_id, req)
+            .map(Json)
+            .map_err(|e| database_error("setting tariff", e))
+    })
+    .await
+}
+
+/// Delete Site Tariff endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/Tariff`
+/// - **Method:** `DELETE`
+/// - **Purpose:** Removes the site's electricity prices
+/// - **Authentication:** Required; the user must be able to edit the site
+///
+/// # Response
+///
+/// **Success (HTTP 204 No Content):** the tariff was removed
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't edit the site
+/// - **404 Not Found**: No such site, or it has no tariff
+#[delete("/1/Sites/<site_id>/Tariff")]
+pub async fn delete_site_tariff_endpoint(
+    db: DbConn,
+    site_id: i32,
+    auth_user: AuthenticatedUser,
+) -> Result<Status, EconomicsError> {
+    db.run(move |conn| {
+        let site = viewable_site(conn, &auth_user, site_id)?;
+        if !can_crud_site(&auth_user, site.company_id) {
+            return Err(error(Status::Forbidden, "Forbidden: insufficient permissions"));
+        }
+        if site.archived_at.is_some() {
+            return Err(error(Status::Conflict, ARCHIVED_SITE));
+        }
+        match delete_site_tariff(conn, site_id) {
+            Ok(true) => Ok(Status::NoContent),
+            Ok(false) => Err(error(Status::NotFound, "The site has no tariff")),
+            Err(e) => Err(database_error("deleting tariff", e)),
+        }
+    })
+    .await
+}
+
+/// Returns a vector of all routes defined in this module.
+pub fn routes() -> Vec<Route> {
+    routes![
+        get_site_economics,
+        get_site_economics_csv,
+        export_site_economics,
+        get_site_tariff_endpoint,
+        set_site_tariff_endpoint,
+        delete_site_tariff_endpoint
+    ]
+}
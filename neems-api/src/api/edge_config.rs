@@ -0,0 +1,492 @@
+//! Configuration push endpoints for edge instances.
+//!
+//! Operators store versioned bundles of sources and secrets and roll them out
+//! to edge neems-data instances stage by stage; the edges pull the bundle
+//! targeted at their origin and report back whether it applied. Bundles,
+//! rollouts and the apply logic live in [`neems_data::edge_config`] so the
+//! server and the edge agent agree on the format; this module only adds
+//! routing and access control.
+
+use neems_data::{
+    edge_config::{
+        ConfigAgent, ConfigBundle, ConfigBundleRequest, ConfigBundleSummary, ConfigError,
+        ConfigReport, ConfigRollout, RolloutRequest, advance_rollout, create_bundle,
+        desired_bundle, get_bundle, get_rollout, list_agents, list_bundles, list_rollouts,
+        record_report, rollback_rollout, start_rollout,
+    },
+    secrets::MasterKey,
+};
+use rocket::{Responder, Route, http::Status, response::status, serde::json::Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{orm::neems_data::db::SiteDbConn, session_guards::AuthenticatedUser};
+
+/// Roles allowed to manage bundles and to fetch them on behalf of an edge.
+const EDGE_CONFIG_ROLES: &[&str] = &["newtown-admin", "newtown-staff"];
+
+/// Error response structure for edge configuration API failures.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+type EdgeConfigError = status::Custom<Json<ErrorResponse>>;
+
+fn error(status: Status, error: impl Into<String>) -> EdgeConfigError {
+    status::Custom(status, Json(ErrorResponse { error: error.into() }))
+}
+
+fn config_error(e: ConfigError) -> EdgeConfigError {
+    match e {
+        ConfigError::NotFound(e) => error(Status::NotFound, e),
+        ConfigError::Invalid(e) => error(Status::BadRequest, e),
+        ConfigError::Conflict(e) => error(Status::Conflict, e),
+        ConfigError::Data(e) => {
+            eprintln!("Edge config: database error: {}", e);
+            error(Status::InternalServerError, "Database error")
+        }
+    }
+}
+
+fn require_edge_config_role(user: &AuthenticatedUser) -> Result<(), EdgeConfigError> {
+    if user.has_any_role(EDGE_CONFIG_ROLES) {
+        Ok(())
+    } else {
+        Err(error(Status::Forbidden, "Only Newtown staff can manage edge configuration"))
+    }
+}
+
+fn master_key() -> Result<Option<&'static MasterKey>, EdgeConfigError> {
+    MasterKey::from_env().map_err(|e| {
+        eprintln!("Edge config: secrets master key unavailable: {}", e);
+        error(Status::InternalServerError, "Secrets master key unavailable")
+    })
+}
+
+/// A bundle for the edge, or nothing when none is targeted at it.
+#[derive(Responder)]
+pub enum DesiredConfig {
+    Bundle(Json<ConfigBundle>),
+    #[response(status = 204)]
+    NoBundle(()),
+}
+
+fn check_origin(origin: &str) -> Result<(), EdgeConfigError> {
+    if origin.trim().is_empty() || origin.contains('/') {
+        Err(error(Status::BadRequest, format!("Invalid origin: {:?}", origin)))
+    } else {
+        Ok(())
+    }
+}
+
+/// Create Bundle endpoint.
+///
+/// - **URL:** `/api/1/EdgeConfig/Bundles`
+/// - **Method:** `POST`
+/// - **Purpose:** Stores a new configuration bundle under the next version
+/// - **Authentication:** Required; `newtown-admin` or `newtown-staff`
+///
+/// Secret values are sealed under the server's master key
+/// (`NEEMS_SECRETS_KEY`) and never returned by the bundle endpoints. Every
+/// `secret://<name>` argument must name a secret in the bundle.
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "description": "Move the gateway to its new address",
+///   "sources": [
+///     {
+///       "name": "gateway_ping",
+///       "test_type": "ping",
+///       "interval_seconds": 30,
+///       "arguments": { "target": "secret://gateway-host" }
+///     }
+///   ],
+///   "secrets": { "gateway-host": "10.0.0.5" }
+/// }
+/// ```
+///
+/// # Response
+///
+/// **Success (HTTP 201 Created):** The bundle's summary, with secrets named
+/// only
+///
+/// **Error Responses:**
+/// - `400 Bad Request`: Invalid sources or secrets, or secrets without a master
+///   key configured
+/// - `403 Forbidden`: Not Newtown staff
+#[post("/1/EdgeConfig/Bundles", data = "<request>")]
+pub async fn create_config_bundle(
+    user: AuthenticatedUser,
+    site_db: SiteDbConn,
+    request: Json<ConfigBundleRequest>,
+) -> Result<status::Created<Json<ConfigBundleSummary>>, EdgeConfigError> {
+    require_edge_config_role(&user)?;
+    let key = master_key()?;
+    let created_by = user.user.email.clone();
+
+    let summary = site_db
+        .run(move |conn| create_bundle(conn, key, &request, Some(&created_by)))
+        .await
+        .map_err(config_error)?;
+    let location = format!("/api/1/EdgeConfig/Bundles/{}", summary.version);
+    Ok(status::Created::new(location).body(Json(summary)))
+}
+
+/// List Bundles endpoint.
+///
+/// - **URL:** `/api/1/EdgeConfig/Bundles`
+/// - **Method:** `GET`
+/// - **Purpose:** Lists every configuration bundle, newest first
+/// - **Authentication:** Required; `newtown-admin` or `newtown-staff`
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** Bundle summaries; credential-looking arguments
+/// stored in plaintext are masked
+#[get("/1/EdgeConfig/Bundles")]
+pub async fn list_config_bundles(
+    user: AuthenticatedUser,
+    site_db: SiteDbConn,
+) -> Result<Json<Vec<ConfigBundleSummary>>, EdgeConfigError> {
+    require_edge_config_role(&user)?;
+
+    site_db
+        .run(list_bundles)
+        .await
+        .map(Json)
+        .map_err(|e| config_error(ConfigError::Data(e)))
+}
+
+/// Get Bundle endpoint.
+///
+/// - **URL:** `/api/1/EdgeConfig/Bundles/<version>`
+/// - **Method:** `GET`
+/// - **Purpose:** Returns one configuration bundle's summary
+/// - **Authentication:** Required; `newtown-admin` or `newtown-staff`
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** The bundle's summary
+///
+/// **Error Responses:**
+/// - `404 Not Found`: No such version
+#[get("/1/EdgeConfig/Bundles/<version>")]
+pub async fn get_config_bundle(
+    version: i32,
+    user: AuthenticatedUser,
+    site_db: SiteDbConn,
+) -> Result<Json<ConfigBundleSummary>, EdgeConfigError> {
+    require_edge_config_role(&user)?;
+
+    site_db
+        .run(move |conn| get_bundle(conn, version))
+        .await
+        .map_err(|e| config_error(ConfigError::Data(e)))?
+        .map(Json)
+        .ok_or_else(|| error(Status::NotFound, format!("Bundle {} not found", version)))
+}
+
+/// Start Rollout endpoint.
+///
+/// - **URL:** `/api/1/EdgeConfig/Rollouts`
+/// - **Method:** `POST`
+/// - **Purpose:** Starts rolling a bundle out to edge origins, targeting the
+///   first stage only
+/// - **Authentication:** Required; `newtown-admin` or `newtown-staff`
+///
+/// # Request Format
+///
+/// ```json
+/// { "version": 4, "stages": [["site-12"], ["site-13", "site-14"]] }
+/// ```
+///
+/// # Response
+///
+/// **Success (HTTP 201 Created):** The rollout with the origins it has
+/// targeted
+///
+/// **Error Responses:**
+/// - `400 Bad Request`: Empty stages, or an origin listed twice
+/// - `404 Not Found`: No such bundle version
+/// - `409 Conflict`: Another rollout is still in progress or halted
+#[post("/1/EdgeConfig/Rollouts", data = "<request>")]
+pub async fn start_config_rollout(
+    user: AuthenticatedUser,
+    site_db: SiteDbConn,
+    request: Json<RolloutRequest>,
+) -> Result<status::Created<Json<ConfigRollout>>, EdgeConfigError> {
+    require_edge_config_role(&user)?;
+    let created_by = user.user.email.clone();
+
+    let rollout = site_db
+        .run(move |conn| start_rollout(conn, &request, Some(&created_by)))
+        .await
+        .map_err(config_error)?;
+    let location = format!("/api/1/EdgeConfig/Rollouts/{}", rollout.id);
+    Ok(status::Created::new(location).body(Json(rollout)))
+}
+
+/// List Rollouts endpoint.
+///
+/// - **URL:** `/api/1/EdgeConfig/Rollouts`
+/// - **Method:** `GET`
+/// - **Purpose:** Lists every rollout, newest first
+/// - **Authentication:** Required; `newtown-admin` or `newtown-staff`
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** Rollouts with the origins each has reached
+#[get("/1/EdgeConfig/Rollouts")]
+pub async fn list_config_rollouts(
+    user: AuthenticatedUser,
+    site_db: SiteDbConn,
+) -> Result<Json<Vec<ConfigRollout>>, EdgeConfigError> {
+    require_edge_config_role(&user)?;
+
+    site_db.run(list_rollouts).await.map(Json).map_err(config_error)
+}
+
+/// Get Rollout endpoint.
+///
+/// - **URL:** `/api/1/EdgeConfig/Rollouts/<id>`
+/// - **Method:** `GET`
+/// - **Purpose:** Returns a rollout and where each origin it reached stands
+/// - **Authentication:** Required; `newtown-admin` or `newtown-staff`
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// {
+///   "id": 2,
+///   "version": 4,
+///   "stages": [["site-12"], ["site-13", "site-14"]],
+///   "current_stage": 0,
+///   "status": "in_progress",
+///   "created_by": "ops@newtown.energy",
+///   "created_at": "2024-01-01T12:00:00",
+///   "updated_at": "2024-01-01T12:00:00",
+///   "agents": [
+///     {
+///       "origin": "site-12",
+///       "target_version": 4,
+///       "previous_version": 3,
+///       "rollout_id": 2,
+///       "applied_version": 4,
+///       "last_error": null,
+///       "last_seen_at": "2024-01-01T12:04:00",
+///       "updated_at": "2024-01-01T12:04:00"
+///     }
+///   ]
+/// }
+/// ```
+///
+/// **Error Responses:**
+/// - `404 Not Found`: No such rollout
+#[get("/1/EdgeConfig/Rollouts/<id>")]
+pub async fn get_config_rollout(
+    id: i32,
+    user: AuthenticatedUser,
+    site_db: SiteDbConn,
+) -> Result<Json<ConfigRollout>, EdgeConfigError> {
+    require_edge_config_role(&user)?;
+
+    site_db
+        .run(move |conn| get_rollout(conn, id))
+        .await
+        .map_err(config_error)?
+        .map(Json)
+        .ok_or_else(|| error(Status::NotFound, format!("Rollout {} not found", id)))
+}
+
+/// Advance Rollout endpoint.
+///
+/// - **URL:** `/api/1/EdgeConfig/Rollouts/<id>/Advance`
+/// - **Method:** `POST`
+/// - **Purpose:** Targets the rollout's next stage, or completes it after the
+///   last one
+/// - **Authentication:** Required; `newtown-admin` or `newtown-staff`
+///
+/// Every origin targeted so far must have applied the bundle. A halted
+/// rollout resumes once the failed origins have.
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** The updated rollout
+///
+/// **Error Responses:**
+/// - `404 Not Found`: No such rollout
+/// - `409 Conflict`: Origins still to apply the bundle, or the rollout is
+///   completed or rolled back
+#[post("/1/EdgeConfig/Rollouts/<id>/Advance")]
+pub async fn advance_config_rollout(
+    id: i32,
+    user: AuthenticatedUser,
+    site_db: SiteDbConn,
+) -> Result<Json<ConfigRollout>, EdgeConfigError> {
+    require_edge_config_role(&user)?;
+
+    site_db
+        .run(move |conn| advance_rollout(conn, id))
+        .await
+        .map(Json)
+        .map_err(config_error)
+}
+
+/// Roll Back Rollout endpoint.
+///
+/// - **URL:** `/api/1/EdgeConfig/Rollouts/<id>/Rollback`
+/// - **Method:** `POST`
+/// - **Purpose:** Points every origin the rollout reached back at the bundle it
+///   was targeted at before
+/// - **Authentication:** Required; `newtown-admin` or `newtown-staff`
+///
+/// Origins the rollout gave their first bundle keep running it.
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** The rolled back rollout
+///
+/// **Error Responses:**
+/// - `404 Not Found`: No such rollout
+/// - `409 Conflict`: Already rolled back, or a later rollout exists
+#[post("/1/EdgeConfig/Rollouts/<id>/Rollback")]
+pub async fn rollback_config_rollout(
+    id: i32,
+    user: AuthenticatedUser,
+    site_db: SiteDbConn,
+) -> Result<Json<ConfigRollout>, EdgeConfigError> {
+    require_edge_config_role(&user)?;
+
+    site_db
+        .run(move |conn| rollback_rollout(conn, id))
+        .await
+        .map(Json)
+        .map_err(config_error)
+}
+
+/// List Agents endpoint.
+///
+/// - **URL:** `/api/1/EdgeConfig/Agents`
+/// - **Method:** `GET`
+/// - **Purpose:** Lists each edge origin's target and applied bundle
+/// - **Authentication:** Required; `newtown-admin` or `newtown-staff`
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** Agents by origin
+#[get("/1/EdgeConfig/Agents")]
+pub async fn list_config_agents(
+    user: AuthenticatedUser,
+    site_db: SiteDbConn,
+) -> Result<Json<Vec<ConfigAgent>>, EdgeConfigError> {
+    require_edge_config_role(&user)?;
+
+    site_db
+        .run(list_agents)
+        .await
+        .map(Json)
+        .map_err(|e| config_error(ConfigError::Data(e)))
+}
+
+/// Desired Config endpoint.
+///
+/// - **URL:** `/api/1/EdgeConfig/Agents/<origin>/Desired`
+/// - **Method:** `GET`
+/// - **Purpose:** Returns the bundle an edge origin should run, with its
+///   secrets, and records that the origin checked in
+/// - **Authentication:** Required; `newtown-admin` or `newtown-staff`
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// {
+///   "version": 4,
+///   "checksum": "9f86d0...",
+///   "description": "Move the gateway to its new address",
+///   "sources": [{ "name": "gateway_ping", "test_type": "ping", "active": true, "...": "..." }],
+///   "secrets": { "gateway-host": "10.0.0.5" }
+/// }
+/// ```
+///
+/// **Success (HTTP 204 No Content):** No bundle is targeted at the origin
+///
+/// **Error Responses:**
+/// - `400 Bad Request`: Invalid origin
+#[get("/1/EdgeConfig/Agents/<origin>/Desired")]
+pub async fn get_desired_config(
+    origin: String,
+    user: AuthenticatedUser,
+    site_db: SiteDbConn,
+) -> Result<DesiredConfig, EdgeConfigError> {
+    require_edge_config_role(&user)?;
+    check_origin(&origin)?;
+    let key = master_key()?;
+
+    site_db
+        .run(move |conn| desired_bundle(conn, key, &origin))
+        .await
+        .map(|bundle| match bundle {
+            Some(bundle) => DesiredConfig::Bundle(Json(bundle)),
+            None => DesiredConfig::NoBundle(()),
+        })
+        .map_err(|e| config_error(ConfigError::Data(e)))
+}
+
+/// Report Config Status endpoint.
+///
+/// - **URL:** `/api/1/EdgeConfig/Agents/<origin>/Status`
+/// - **Method:** `POST`
+/// - **Purpose:** Records whether an edge origin applied a bundle
+/// - **Authentication:** Required; `newtown-admin` or `newtown-staff`
+///
+/// A failure halts the rollout that targeted the bundle at the origin; the
+/// last origin of a rollout's final stage succeeding completes it.
+///
+/// # Request Format
+///
+/// ```json
+/// { "version": 4, "error": null }
+/// ```
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** The origin's updated agent record
+///
+/// **Error Responses:**
+/// - `400 Bad Request`: Invalid origin
+#[post("/1/EdgeConfig/Agents/<origin>/Status", data = "<report>")]
+pub async fn report_config_status(
+    origin: String,
+    user: AuthenticatedUser,
+    site_db: SiteDbConn,
+    report: Json<ConfigReport>,
+) -> Result<Json<ConfigAgent>, EdgeConfigError> {
+    require_edge_config_role(&user)?;
+    check_origin(&origin)?;
+
+    site_db
+        .run(move |conn| record_report(conn, &origin, &report))
+        .await
+        .map(Json)
+        .map_err(config_error)
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        create_config_bundle,
+        list_config_bundles,
+        get_config_bundle,
+        start_config_rollout,
+        list_config_rollouts,
+        get_config_rollout,
+        advance_config_rollout,
+        rollback_config_rollout,
+        list_config_agents,
+        get_desired_config,
+        report_config_status
+    ]
+}
@@ -0,0 +1,269 @@
+//! API endpoints for pulling recent logs from a site's edge instance.
+//!
+//! Each site's neems-data monitor can serve the logs it keeps in memory (see
+//! [`neems_data::logs`]). These endpoints store where to reach that listener
+//! and proxy support's requests to it, so logs can be read without a VPN and
+//! an SSH session. Tokens are write-only: responses show `***` for a stored
+//! token, or the `secret://` reference it names.
+//!
+//! # Authorization Rules
+//! - Only newtown-admin and newtown-staff can configure endpoints or read logs,
+//!   which may mention devices, addresses and other companies' data
+
+use neems_data::logs::{
+    EdgeEndpointInfo, EdgeEndpointSettings, LogPage, LogQuery, delete_edge_endpoint,
+    fetch_edge_logs, get_edge_endpoint, resolve_edge_token, set_edge_endpoint,
+};
+use rocket::{Route, http::Status, response::status, serde::json::Json};
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{DbConn, SiteDbConn, orm::site::get_site_by_id, session_guards::AuthenticatedUser};
+
+/// Error response structure for edge log API failures.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+type EdgeLogsError = status::Custom<Json<ErrorResponse>>;
+
+fn error(status: Status, error: impl Into<String>) -> EdgeLogsError {
+    status::Custom(status, Json(ErrorResponse { error: error.into() }))
+}
+
+fn database_error(action: &str, e: impl std::fmt::Display) -> EdgeLogsError {
+    eprintln!("Error {} edge endpoint: {}", action, e);
+    error(
+        Status::InternalServerError,
+        format!("Database error while {} edge endpoint", action),
+    )
+}
+
+/// 403 unless `auth_user` is Newtown staff, 404 unless the site exists.
+async fn authorize_site(
+    db: &DbConn,
+    auth_user: &AuthenticatedUser,
+    site_id: i32,
+) -> Result<(), EdgeLogsError> {
+    if !auth_user.has_any_role(&["newtown-admin", "newtown-staff"]) {
+        return Err(error(Status::Forbidden, "Only Newtown staff can read a site's edge logs"));
+    }
+    db.run(move |conn| get_site_by_id(conn, site_id))
+        .await
+        .map_err(|e| database_error("loading", e))?
+        .ok_or_else(|| error(Status::NotFound, "Site not found"))?;
+    Ok(())
+}
+
+/// Get Site Edge Endpoint endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/EdgeEndpoint`
+/// - **Method:** `GET`
+/// - **Purpose:** Shows where the site's log listener is reached
+/// - **Authentication:** Required; Newtown staff
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// {
+///   "site_id": 3,
+///   "url": "https://site-3.example.com:9180",
+///   "token": "***",
+///   "updated_at": "2026-10-17T09:30:00"
+/// }
+/// ```
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User is not Newtown staff
+/// - **404 Not Found**: No such site, or no endpoint is configured for it
+#[get("/1/Sites/<site_id>/EdgeEndpoint")]
+pub async fn get_site_edge_endpoint(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+) -> Result<Json<EdgeEndpointInfo>, EdgeLogsError> {
+    authorize_site(&db, &auth_user, site_id).await?;
+    site_db
+        .run(move |conn| get_edge_endpoint(conn, site_id))
+        .await
+        .map_err(|e| database_error("loading", e))?
+        .map(|endpoint| Json(EdgeEndpointInfo::from(&endpoint)))
+        .ok_or_else(|| error(Status::NotFound, "No edge endpoint is configured for this site"))
+}
+
+/// Set Site Edge Endpoint endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/EdgeEndpoint`
+/// - **Method:** `PUT`
+/// - **Purpose:** Sets or replaces where the site's log listener is reached
+/// - **Authentication:** Required; Newtown staff
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "url": "https://site-3.example.com:9180",
+///   "token": "secret://site-3-logs"
+/// }
+/// ```
+///
+/// `url` is the base URL of the monitor's `NEEMS_LOGS_LISTEN` address (or a
+/// proxy in front of it) and `token` its `NEEMS_LOGS_TOKEN`. A `null` or
+/// omitted `token` keeps the stored one.
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** The endpoint, as from `GET`
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Invalid settings, or no token for a new endpoint
+/// - **403 Forbidden**: User is not Newtown staff
+/// - **404 Not Found**: No such site
+#[put("/1/Sites/<site_id>/EdgeEndpoint", data = "<settings>")]
+pub async fn set_site_edge_endpoint(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    settings: Json<EdgeEndpointSettings>,
+) -> Result<Json<EdgeEndpointInfo>, EdgeLogsError> {
+    authorize_site(&db, &auth_user, site_id).await?;
+    let settings = settings.into_inner();
+    settings
+        .validate()
+        .map_err(|problems| error(Status::BadRequest, problems.join("; ")))?;
+
+    site_db
+        .run(move |conn| {
+            let existing =
+                get_edge_endpoint(conn, site_id).map_err(|e| database_error("loading", e))?;
+            if existing.is_none() && settings.token.is_none() {
+                return Err(error(Status::BadRequest, "token: required for a new endpoint"));
+            }
+            let endpoint = set_edge_endpoint(conn, site_id, &settings)
+                .map_err(|e| database_error("saving", e))?;
+            Ok(Json(EdgeEndpointInfo::from(&endpoint)))
+        })
+        .await
+}
+
+/// Delete Site Edge Endpoint endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/EdgeEndpoint`
+/// - **Method:** `DELETE`
+/// - **Purpose:** Forgets where the site's log listener is reached
+/// - **Authentication:** Required; Newtown staff
+///
+/// # Response
+///
+/// **Success (HTTP 204 No Content)**
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User is not Newtown staff
+/// - **404 Not Found**: No such site, or no endpoint is configured for it
+#[delete("/1/Sites/<site_id>/EdgeEndpoint")]
+pub async fn delete_site_edge_endpoint(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+) -> Result<Status, EdgeLogsError> {
+    authorize_site(&db, &auth_user, site_id).await?;
+    let deleted = site_db
+        .run(move |conn| delete_edge_endpoint(conn, site_id))
+        .await
+        .map_err(|e| database_error("deleting", e))?;
+    if deleted {
+        Ok(Status::NoContent)
+    } else {
+        Err(error(Status::NotFound, "No edge endpoint is configured for this site"))
+    }
+}
+
+/// Get Site Edge Logs endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/EdgeLogs?since=&level=&limit=`
+/// - **Method:** `GET`
+/// - **Purpose:** Fetches recent structured logs from the site's monitor
+/// - **Authentication:** Required; Newtown staff
+///
+/// # Query Parameters
+///
+/// - `since`: only entries after this time (RFC 3339)
+/// - `level`: only entries at least this severe (`error`, `warn`, `info`,
+///   `debug` or `trace`)
+/// - `limit`: the newest this many matching entries (default 500, at most 5000)
+///
+/// The monitor only has what it has logged since it started, up to its
+/// `NEEMS_LOGS_BUFFER_SIZE` most recent entries, at its configured log level.
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// {
+///   "entries": [
+///     {
+///       "timestamp": "2026-10-17T10:02:11.482Z",
+///       "level": "warn",
+///       "target": "neems_data::rtac::worker",
+///       "message": "RTAC connection lost",
+///       "fields": { "host": "10.0.0.5" }
+///     }
+///   ],
+///   "truncated": false
+/// }
+/// ```
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Invalid query parameters
+/// - **403 Forbidden**: User is not Newtown staff
+/// - **404 Not Found**: No such site, or no endpoint is configured for it
+/// - **502 Bad Gateway**: The site's listener couldn't be reached or refused
+///   the request
+#[get("/1/Sites/<site_id>/EdgeLogs?<since>&<level>&<limit>")]
+pub async fn get_site_edge_logs(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    since: Option<String>,
+    level: Option<String>,
+    limit: Option<String>,
+) -> Result<Json<LogPage>, EdgeLogsError> {
+    authorize_site(&db, &auth_user, site_id).await?;
+    let query = LogQuery::from_params(since.as_deref(), level.as_deref(), limit.as_deref())
+        .map_err(|e| error(Status::BadRequest, e))?;
+
+    let (url, token) = site_db
+        .run(move |conn| {
+            let endpoint = get_edge_endpoint(conn, site_id)
+                .map_err(|e| database_error("loading", e))?
+                .ok_or_else(|| {
+                    error(Status::NotFound, "No edge endpoint is configured for this site")
+                })?;
+            let token = resolve_edge_token(conn, &endpoint).map_err(|e| {
+                error(Status::BadGateway, format!("Cannot resolve edge token: {}", e))
+            })?;
+            Ok((endpoint.url, token))
+        })
+        .await?;
+
+    fetch_edge_logs(&url, &token, &query).await.map(Json).map_err(|e| {
+        eprintln!("Error fetching edge logs for site {} from {}: {}", site_id, url, e);
+        error(Status::BadGateway, format!("Cannot fetch logs from the site: {}", e))
+    })
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        get_site_edge_endpoint,
+        set_site_edge_endpoint,
+        delete_site_edge_endpoint,
+        get_site_edge_logs
+    ]
+}
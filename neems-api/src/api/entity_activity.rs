@@ -6,12 +6,21 @@
 //! `update_latest_activity_user` helper); this module just exposes it
 //! over HTTP with the acting user's email resolved.
 
+use std::collections::HashMap;
+
+use diesel::{SqliteConnection, connection::DefaultLoadingMode, prelude::*};
 use rocket::{Route, http::Status, response::status, serde::json::Json};
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
 use crate::{
-    orm::{DbConn, entity_activity::get_activity_history, user::get_user},
+    models::EntityActivity,
+    ndjson::{self, NdjsonSender, NdjsonStream, WantsNdjson},
+    orm::{
+        DbConn,
+        entity_activity::{activity_history_query, get_activity_history, get_activity_user_emails},
+        user::get_user,
+    },
     session_guards::AuthenticatedUser,
 };
 
@@ -48,6 +57,70 @@ pub struct EntityActivityQuery {
     pub entity_id: i32,
 }
 
+/// The audit log for one entity, as one JSON array or streamed one row per
+/// line as NDJSON.
+#[derive(Responder)]
+pub enum EntityActivityBody {
+    Json(Json<Vec<EntityActivityWithUser>>),
+    Ndjson(NdjsonStream),
+}
+
+impl EntityActivityWithUser {
+    /// Resolves the acting user's email from `emails`. A user who has since
+    /// been deleted just renders without an email.
+    fn new(row: EntityActivity, emails: &HashMap<i32, String>) -> Self {
+        EntityActivityWithUser {
+            id: row.id,
+            table_name: row.table_name,
+            entity_id: row.entity_id,
+            operation_type: row.operation_type,
+            timestamp: row.timestamp.and_utc().to_rfc3339(),
+            user_id: row.user_id,
+            user_email: row.user_id.and_then(|uid| emails.get(&uid).cloned()),
+            change_reason: row.change_reason,
+        }
+    }
+}
+
+/// Send an entity's audit log down an NDJSON stream as SQLite returns it.
+fn stream_entity_activity(
+    conn: &mut SqliteConnection,
+    table_name: &str,
+    entity_id: i32,
+    sender: &NdjsonSender,
+) {
+    // Emails first: the row iterator holds the connection
+    let emails = match get_activity_user_emails(conn, table_name, entity_id) {
+        Ok(emails) => emails,
+        Err(e) => {
+            sender.send_error(&e.to_string());
+            return;
+        }
+    };
+    let rows = match activity_history_query(table_name, entity_id)
+        .load_iter::<EntityActivity, DefaultLoadingMode>(conn)
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            sender.send_error(&e.to_string());
+            return;
+        }
+    };
+    for row in rows {
+        match row {
+            Ok(row) => {
+                if !sender.send(&EntityActivityWithUser::new(row, &emails)) {
+                    return;
+                }
+            }
+            Err(e) => {
+                sender.send_error(&e.to_string());
+                return;
+            }
+        }
+    }
+}
+
 /// Return the audit log for a single entity, oldest first.
 ///
 /// - **URL:** `/api/1/EntityActivity?table_name=<table>&entity_id=<id>`
@@ -57,47 +130,40 @@ pub struct EntityActivityQuery {
 /// Any authenticated user can read; the audit surface is intentionally
 /// broad because the demo's "Resulting Schedule" pane needs it visible
 /// across roles. Tighten if this becomes a real-world endpoint.
+///
+/// With `Accept: application/x-ndjson` the rows are streamed one per line
+/// instead (see [`crate::ndjson`]).
 #[get("/1/EntityActivity?<query..>")]
 pub async fn get_entity_activity(
     db: DbConn,
     query: EntityActivityQuery,
+    ndjson: WantsNdjson,
     _auth_user: AuthenticatedUser,
-) -> Result<Json<Vec<EntityActivityWithUser>>, status::Custom<Json<ErrorResponse>>> {
+) -> Result<EntityActivityBody, status::Custom<Json<ErrorResponse>>> {
     let table_name = query.table_name.clone();
     let entity_id = query.entity_id;
 
+    if ndjson.0 {
+        let (sender, stream) = ndjson::channel();
+        rocket::tokio::spawn(async move {
+            db.run(move |conn| stream_entity_activity(conn, &table_name, entity_id, &sender))
+                .await
+        });
+        return Ok(EntityActivityBody::Ndjson(stream));
+    }
+
     db.run(move |conn| {
-        let rows = match get_activity_history(conn, &table_name, entity_id) {
-            Ok(r) => r,
+        let history = get_activity_user_emails(conn, &table_name, entity_id).and_then(|emails| {
+            let rows = get_activity_history(conn, &table_name, entity_id)?;
+            Ok(rows.into_iter().map(|row| EntityActivityWithUser::new(row, &emails)).collect())
+        });
+        match history {
+            Ok(out) => Ok(EntityActivityBody::Json(Json(out))),
             Err(e) => {
                 let err = Json(ErrorResponse { error: e.to_string() });
-                return Err(status::Custom(Status::InternalServerError, err));
+                Err(status::Custom(Status::InternalServerError, err))
             }
-        };
-
-        let mut out: Vec<EntityActivityWithUser> = Vec::with_capacity(rows.len());
-        for row in rows {
-            // Look up the user email if we have one. Failures here are
-            // not fatal — the row just renders without an email.
-            let email = match row.user_id {
-                Some(uid) => match get_user(conn, uid) {
-                    Ok(Some(u)) => Some(u.email),
-                    _ => None,
-                },
-                None => None,
-            };
-            out.push(EntityActivityWithUser {
-                id: row.id,
-                table_name: row.table_name,
-                entity_id: row.entity_id,
-                operation_type: row.operation_type,
-                timestamp: row.timestamp.and_utc().to_rfc3339(),
-                user_id: row.user_id,
-                user_email: email,
-                change_reason: row.change_reason,
-            });
         }
-        Ok(Json(out))
     })
     .await
 }
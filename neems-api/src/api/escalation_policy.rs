@@ -0,0 +1,333 @@
+//! API endpoints for managing a company's alarm escalation policies.
+//!
+//! A policy escalates the company's alarms of at least its severity that
+//! nobody acknowledges: each of its tiers is notified, through one of the
+//! company's notification channels, once the alarm has gone unacknowledged
+//! for the tier's delay (see [`crate::alarm_escalation`]). Each escalation is
+//! kept in the alarm's history, at `/api/1/Alarms/<alarm_num>/Escalations`.
+//!
+//! # Authorization Rules
+//! - newtown-admin and newtown-staff can manage policies for any company
+//! - Company admins can manage their own company's policies
+
+use chrono::Utc;
+use neems_data::notifications::list_channels;
+use rocket::{Route, http::Status, response::status, serde::json::Json};
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{
+    DbConn, SiteDbConn,
+    models::{EscalationPolicy, EscalationPolicyRequest},
+    orm::{
+        company::get_company_by_id,
+        escalation::{
+            create_escalation_policy, delete_escalation_policy, get_escalation_policy,
+            list_escalation_policies, update_escalation_policy,
+        },
+        oncall::list_oncall_rotations,
+    },
+    session_guards::AuthenticatedUser,
+};
+
+/// Error response structure for escalation policy API failures.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+type PolicyError = status::Custom<Json<ErrorResponse>>;
+
+fn error(status: Status, error: impl Into<String>) -> PolicyError {
+    status::Custom(status, Json(ErrorResponse { error: error.into() }))
+}
+
+fn database_error(action: &str, e: impl std::fmt::Display) -> PolicyError {
+    eprintln!("Error {} escalation policies: {}", action, e);
+    error(
+        Status::InternalServerError,
+        format!("Database error while {} escalation policies", action),
+    )
+}
+
+/// Whether `user` can manage `company_id`'s escalation policies.
+fn can_crud_policies(user: &AuthenticatedUser, company_id: i32) -> bool {
+    // newtown-admin and newtown-staff can manage any company's policies
+    if user.has_any_role(&["newtown-admin", "newtown-staff"]) {
+        return true;
+    }
+
+    // Company admins can manage their own company's policies
+    user.has_role("admin") && user.user.company_id == company_id
+}
+
+/// 404 unless the company exists, 403 unless `auth_user` may manage its
+/// policies.
+async fn authorize_company(
+    db: &DbConn,
+    auth_user: &AuthenticatedUser,
+    company_id: i32,
+) -> Result<(), PolicyError> {
+    db.run(move |conn| get_company_by_id(conn, company_id))
+        .await
+        .map_err(|e| database_error("loading", e))?
+        .ok_or_else(|| error(Status::NotFound, "Company not found"))?;
+    if !can_crud_policies(auth_user, company_id) {
+        return Err(error(
+            Status::Forbidden,
+            "Only Newtown staff or the company's admins can manage its escalation policies",
+        ));
+    }
+    Ok(())
+}
+
+/// 400 unless the request is valid and every tier's channel, and rotation,
+/// is one of the company's.
+async fn validate_request(
+    db: &DbConn,
+    site_db: &SiteDbConn,
+    company_id: i32,
+    request: &EscalationPolicyRequest,
+) -> Result<(), PolicyError> {
+    let mut problems = request.validate().err().unwrap_or_default();
+    let channels = site_db
+        .run(move |conn| list_channels(conn, Some(company_id)))
+        .await
+        .map_err(|e| database_error("loading", e))?;
+    let rotations = db
+        .run(move |conn| list_oncall_rotations(conn, company_id, Utc::now().naive_utc()))
+        .await
+        .map_err(|e| database_error("loading", e))?;
+    for (i, tier) in request.tiers.iter().enumerate() {
+        if let Some(rotation_id) = tier.rotation_id {
+            if !rotations.iter().any(|rotation| rotation.id == rotation_id) {
+                problems.push(format!(
+                    "tiers: tier {} rotation {} is not one of the company's on-call rotations",
+                    i + 1,
+                    rotation_id
+                ));
+            }
+        }
+        if !channels.iter().any(|channel| channel.id == tier.channel_id) {
+            problems.push(format!(
+                "tiers: tier {} channel {} is not one of the company's notification channels",
+                i + 1,
+                tier.channel_id
+            ));
+        }
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(error(Status::BadRequest, problems.join("; ")))
+    }
+}
+
+/// 409 for a policy name the company already uses.
+fn name_conflict(name: &str) -> impl FnOnce(diesel::result::Error) -> PolicyError + '_ {
+    move |e| match e {
+        diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::UniqueViolation,
+            _,
+        ) => error(
+            Status::Conflict,
+            format!("Escalation policy with name '{}' already exists", name.trim()),
+        ),
+        e => database_error("saving", e),
+    }
+}
+
+/// The policy `id` of `company_id`.
+async fn company_policy(
+    db: &DbConn,
+    company_id: i32,
+    id: i32,
+) -> Result<EscalationPolicy, PolicyError> {
+    db.run(move |conn| get_escalation_policy(conn, id))
+        .await
+        .map_err(|e| database_error("loading", e))?
+        .filter(|policy| policy.company_id == company_id)
+        .ok_or_else(|| error(Status::NotFound, "Escalation policy not found"))
+}
+
+/// List Company Escalation Policies endpoint.
+///
+/// - **URL:** `/api/1/Companies/<company_id>/EscalationPolicies`
+/// - **Method:** `GET`
+/// - **Purpose:** Lists the company's escalation policies by name
+/// - **Authentication:** Required; Newtown staff or the company's admin
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// [
+///   {
+///     "id": 1,
+///     "company_id": 2,
+///     "name": "critical alarms",
+///     "min_severity": "critical",
+///     "enabled": true,
+///     "tiers": [
+///       { "tier": 1, "delay_minutes": 15, "channel_id": 3, "contacts": ["Site lead"] },
+///       { "tier": 2, "delay_minutes": 45, "channel_id": 4, "contacts": ["Operations manager"] }
+///     ],
+///     "created_at": "2026-10-18T09:30:00",
+///     "updated_at": "2026-10-18T09:30:00"
+///   }
+/// ]
+/// ```
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't manage the company's policies
+/// - **404 Not Found**: No such company
+#[get("/1/Companies/<company_id>/EscalationPolicies")]
+pub async fn list_company_policies(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    company_id: i32,
+) -> Result<Json<Vec<EscalationPolicy>>, PolicyError> {
+    authorize_company(&db, &auth_user, company_id).await?;
+    db.run(move |conn| list_escalation_policies(conn, company_id))
+        .await
+        .map(Json)
+        .map_err(|e| database_error("loading", e))
+}
+
+/// Create Company Escalation Policy endpoint.
+///
+/// - **URL:** `/api/1/Companies/<company_id>/EscalationPolicies`
+/// - **Method:** `POST`
+/// - **Purpose:** Adds a policy, applied from the next check of active alarms
+/// - **Authentication:** Required; Newtown staff or the company's admin
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "name": "critical alarms",
+///   "min_severity": "critical",
+///   "enabled": true,
+///   "tiers": [
+///     { "delay_minutes": 15, "channel_id": 3, "contacts": ["Site lead"] },
+///     { "delay_minutes": 45, "channel_id": 4, "contacts": ["Operations manager"] }
+///   ]
+/// }
+/// ```
+///
+/// `min_severity` (default `critical`) is `info`, `warning`, `critical` or
+/// `emergency`. Tiers, numbered in order from 1, need delays that increase,
+/// of at most a week, and one of the company's notification channels each;
+/// `contacts` are named in the notification, after whoever is on call on the
+/// tier's optional `rotation_id`, one of the company's on-call rotations.
+///
+/// # Response
+///
+/// **Success (HTTP 201 Created):** The policy, as from `GET`
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Invalid policy (every problem is listed in `error`)
+/// - **403 Forbidden**: User can't manage the company's policies
+/// - **404 Not Found**: No such company
+/// - **409 Conflict**: The company already has a policy with this name
+#[post("/1/Companies/<company_id>/EscalationPolicies", data = "<request>")]
+pub async fn create_company_policy(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    company_id: i32,
+    request: Json<EscalationPolicyRequest>,
+) -> Result<status::Created<Json<EscalationPolicy>>, PolicyError> {
+    authorize_company(&db, &auth_user, company_id).await?;
+    let request = request.into_inner();
+    validate_request(&db, &site_db, company_id, &request).await?;
+    let name = request.name.clone();
+    let policy = db
+        .run(move |conn| create_escalation_policy(conn, company_id, request))
+        .await
+        .map_err(name_conflict(&name))?;
+    let location = format!("/api/1/Companies/{}/EscalationPolicies/{}", company_id, policy.id);
+    Ok(status::Created::new(location).body(Json(policy)))
+}
+
+/// Update Company Escalation Policy endpoint.
+///
+/// - **URL:** `/api/1/Companies/<company_id>/EscalationPolicies/<id>`
+/// - **Method:** `PUT`
+/// - **Purpose:** Replaces a policy's settings and tiers. Tiers already
+///   notified of an alarm aren't notified again for the same raise.
+/// - **Authentication:** Required; Newtown staff or the company's admin
+///
+/// The request is as for `POST`.
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** The policy, as from `GET`
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Invalid policy (every problem is listed in `error`)
+/// - **403 Forbidden**: User can't manage the company's policies
+/// - **404 Not Found**: No such company, or no such policy for it
+/// - **409 Conflict**: The company has another policy with this name
+#[put(
+    "/1/Companies/<company_id>/EscalationPolicies/<id>",
+    data = "<request>"
+)]
+pub async fn update_company_policy(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    company_id: i32,
+    id: i32,
+    request: Json<EscalationPolicyRequest>,
+) -> Result<Json<EscalationPolicy>, PolicyError> {
+    authorize_company(&db, &auth_user, company_id).await?;
+    company_policy(&db, company_id, id).await?;
+    let request = request.into_inner();
+    validate_request(&db, &site_db, company_id, &request).await?;
+    let name = request.name.clone();
+    db.run(move |conn| update_escalation_policy(conn, id, request))
+        .await
+        .map_err(name_conflict(&name))?
+        .map(Json)
+        .ok_or_else(|| error(Status::NotFound, "Escalation policy not found"))
+}
+
+/// Delete Company Escalation Policy endpoint.
+///
+/// - **URL:** `/api/1/Companies/<company_id>/EscalationPolicies/<id>`
+/// - **Method:** `DELETE`
+/// - **Purpose:** Removes a policy; its escalations stay in the alarms' history
+/// - **Authentication:** Required; Newtown staff or the company's admin
+///
+/// # Response
+///
+/// **Success (HTTP 204 No Content)**
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't manage the company's policies
+/// - **404 Not Found**: No such company, or no such policy for it
+#[delete("/1/Companies/<company_id>/EscalationPolicies/<id>")]
+pub async fn delete_company_policy(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    company_id: i32,
+    id: i32,
+) -> Result<Status, PolicyError> {
+    authorize_company(&db, &auth_user, company_id).await?;
+    company_policy(&db, company_id, id).await?;
+    db.run(move |conn| delete_escalation_policy(conn, id))
+        .await
+        .map_err(|e| database_error("deleting", e))?;
+    Ok(Status::NoContent)
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        list_company_policies,
+        create_company_policy,
+        update_company_policy,
+        delete_company_policy
+    ]
+}
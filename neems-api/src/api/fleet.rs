@@ -0,0 +1,392 @@
+//! Fleet-wide views across all sites visible to the caller.
+//!
+//! The NOC dashboard shows every site at once. Rather than making it issue
+//! SoC, alarm and readings requests per site on each refresh, these endpoints
+//! roll the latest state of every visible site into one response.
+//!
+//! Visibility follows readings access in [`crate::api::data`]:
+//! newtown-admin/newtown-staff see every site, everyone else sees their own
+//! company's sites.
+
+use std::collections::HashMap;
+
+use chrono::{NaiveDateTime, Utc};
+use neems_data::{models::Reading, rtac::state::AlarmFlags};
+use rocket::{Route, http::Status, serde::json::Json};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{
+    api::{
+        alarm::parse_alarm_registers,
+        data::{parse_soc_level, parse_soc_state},
+    },
+    conditional_get::{Conditional, IfModifiedSince},
+    models::Site,
+    orm::{
+        DbConn,
+        neems_data::SiteReadConn,
+        site::{get_all_sites, get_sites_by_company},
+    },
+    session_guards::AuthenticatedUser,
+};
+
+/// Data newer than this counts as online.
+const ONLINE_MAX_AGE_SECONDS: i64 = 5 * 60;
+/// Data newer than this (but not online) counts as stale; older is offline.
+const STALE_MAX_AGE_SECONDS: i64 = 60 * 60;
+
+/// How recently a site has reported data.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "lowercase")]
+pub enum Connectivity {
+    /// Reported within the last 5 minutes
+    Online,
+    /// Last report between 5 minutes and an hour ago
+    Stale,
+    /// No report for over an hour
+    Offline,
+    /// No readings at all
+    Unknown,
+}
+
+impl Connectivity {
+    pub fn from_age_seconds(age_seconds: Option<i64>) -> Self {
+        match age_seconds {
+            None => Connectivity::Unknown,
+            Some(age) if age <= ONLINE_MAX_AGE_SECONDS => Connectivity::Online,
+            Some(age) if age <= STALE_MAX_AGE_SECONDS => Connectivity::Stale,
+            Some(_) => Connectivity::Offline,
+        }
+    }
+}
+
+/// Latest known state of one site.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FleetSiteStatus {
+    pub site_id: i32,
+    pub site_name: String,
+    pub company_id: i32,
+    /// Latest battery state of charge, 0–100
+    pub soc_percent: Option<f64>,
+    /// Latest battery state ("charging", "discharging", "hold", ...)
+    pub battery_state: Option<String>,
+    pub soc_timestamp: Option<NaiveDateTime>,
+    pub active_alarm_count: usize,
+    pub has_critical: bool,
+    pub has_emergency: bool,
+    pub alarms_timestamp: Option<NaiveDateTime>,
+    /// Most recent reading from any of the site's sources
+    pub last_reading_at: Option<NaiveDateTime>,
+    pub data_age_seconds: Option<i64>,
+    pub connectivity: Connectivity,
+}
+
+/// Response for `GET /api/1/Fleet/Status`.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FleetStatusResponse {
+    pub sites: Vec<FleetSiteStatus>,
+    pub online_count: usize,
+    pub alarm_site_count: usize,
+}
+
+/// The latest reading of one source.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FleetLatestReading {
+    pub site_id: i32,
+    pub source_id: i32,
+    pub source_name: String,
+    pub test_type: Option<String>,
+    pub reading: Reading,
+}
+
+/// Response for `GET /api/1/Fleet/Readings/latest`.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FleetLatestReadingsResponse {
+    pub readings: Vec<FleetLatestReading>,
+}
+
+/// Sites the caller may see.
+async fn visible_sites(db: &DbConn, user: &AuthenticatedUser) -> Result<Vec<Site>, Status> {
+    let all = user.has_any_role(&["newtown-admin", "newtown-staff"]);
+    let company_id = user.user.company_id;
+    db.run(move |conn| {
+        if all {
+            get_all_sites(conn)
+        } else {
+            get_sites_by_company(conn, company_id)
+        }
+    })
+    .await
+    .map_err(|e| {
+        eprintln!("Error loading sites for fleet view: {:?}", e);
+        Status::InternalServerError
+    })
+}
+
+/// Time of the newest of the latest readings, for `Last-Modified`.
+fn newest_reading(latest: &[SourceLatest]) -> Option<NaiveDateTime> {
+    latest
+        .iter()
+        .filter_map(|source| source.reading.as_ref())
+        .map(|r| r.timestamp)
+        .max()
+}
+
+/// A site source with its latest reading, if any.
+struct SourceLatest {
+    site_id: i32,
+    source_id: i32,
+    source_name: String,
+    test_type: Option<String>,
+    reading: Option<Reading>,
+}
+
+/// Load the latest reading of every source belonging to `site_ids`,
+/// optionally limited to one test type.
+async fn latest_by_source(
+    site_db: &SiteReadConn,
+    site_ids: Vec<i32>,
+    test_type_filter: Option<String>,
+) -> Result<Vec<SourceLatest>, Status> {
+    site_db
+        .run(move |conn| {
+            use diesel::prelude::*;
+            use neems_data::schema::{readings, sources};
+
+            let mut query = sources::table
+                .filter(sources::site_id.eq_any(&site_ids))
+                .select((
+                    sources::id.assume_not_null(),
+                    sources::site_id.assume_not_null(),
+                    sources::name,
+                    sources::test_type,
+                ))
+                .order(sources::id.asc())
+                .into_boxed();
+            if let Some(test_type) = &test_type_filter {
+                query = query.filter(sources::test_type.eq(test_type));
+            }
+            let site_sources: Vec<(i32, i32, String, Option<String>)> = query.load(conn)?;
+
+            // One indexed lookup per source (idx_readings_source_recent)
+            site_sources
+                .into_iter()
+                .map(|(source_id, site_id, source_name, test_type)| {
+                    let reading = readings::table
+                        .filter(readings::source_id.eq(source_id))
+                        .order(readings::timestamp.desc())
+                        .first::<Reading>(conn)
+                        .optional()?;
+                    Ok(SourceLatest {
+                        site_id,
+                        source_id,
+                        source_name,
+                        test_type,
+                        reading,
+                    })
+                })
+                .collect::<Result<Vec<_>, diesel::result::Error>>()
+        })
+        .await
+        .map_err(|e| {
+            eprintln!("Error loading latest readings for fleet view: {:?}", e);
+            Status::InternalServerError
+        })
+}
+
+/// Summarize one site from the latest readings of its sources.
+pub fn site_status(
+    site: &Site,
+    latest: &[&Reading],
+    test_types: &[Option<&str>],
+) -> FleetSiteStatus {
+    let now = Utc::now().naive_utc();
+
+    let soc = latest
+        .iter()
+        .zip(test_types)
+        .filter(|(_, test_type)| **test_type == Some("charging_state"))
+        .map(|(reading, _)| *reading)
+        .filter(|reading| parse_soc_level(&reading.data).is_some())
+        .max_by_key(|reading| reading.timestamp);
+
+    let alarms = latest
+        .iter()
+        .filter_map(|reading| Some((*reading, parse_alarm_registers(&reading.data)?)))
+        .max_by_key(|(reading, _)| reading.timestamp)
+        .map(|(reading, registers)| (reading.timestamp, AlarmFlags::from_registers(&registers)));
+
+    let last_reading_at = latest.iter().map(|reading| reading.timestamp).max();
+    let data_age_seconds = last_reading_at.map(|t| (now - t).num_seconds().max(0));
+
+    FleetSiteStatus {
+        site_id: site.id,
+        site_name: site.name.clone(),
+        company_id: site.company_id,
+        soc_percent: soc.and_then(|r| parse_soc_level(&r.data)),
+        battery_state: soc.and_then(|r| parse_soc_state(&r.data)),
+        soc_timestamp: soc.map(|r| r.timestamp),
+        active_alarm_count: alarms.as_ref().map(|(_, f)| f.active_alarms().len()).unwrap_or(0),
+        has_critical: alarms.as_ref().is_some_and(|(_, f)| f.has_critical_alarm()),
+        has_emergency: alarms.as_ref().is_some_and(|(_, f)| f.has_emergency_alarm()),
+        alarms_timestamp: alarms.as_ref().map(|(t, _)| *t),
+        last_reading_at,
+        data_age_seconds,
+        connectivity: Connectivity::from_age_seconds(data_age_seconds),
+    }
+}
+
+/// Fleet Status endpoint.
+///
+/// - **URL:** `/api/1/Fleet/Status`
+/// - **Method:** `GET`
+/// - **Purpose:** Returns the latest SoC, alarm and connectivity state of every
+///   site visible to the caller
+/// - **Authentication:** Required
+/// - **Authorization:** newtown-admin/newtown-staff see all sites; other users
+///   see their company's sites
+///
+/// SoC comes from the site's latest `charging_state` reading and alarms from
+/// its latest reading carrying `alarm_registers`. Connectivity is based on
+/// the age of the newest reading from any of the site's sources: `online`
+/// within 5 minutes, `stale` within an hour, then `offline` (`unknown` when
+/// the site has no readings).
+///
+/// Supports `If-Modified-Since` against the newest reading of any visible
+/// site, answering HTTP 304 when nothing has arrived since. Clients that
+/// revalidate should work out data age from `last_reading_at` themselves, as
+/// `data_age_seconds` and `connectivity` in a cached copy go out of date.
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// {
+///   "sites": [
+///     {
+///       "site_id": 1,
+///       "site_name": "Main Office",
+///       "company_id": 1,
+///       "soc_percent": 62.5,
+///       "battery_state": "charging",
+///       "soc_timestamp": "2024-01-01T12:00:00",
+///       "active_alarm_count": 0,
+///       "has_critical": false,
+///       "has_emergency": false,
+///       "alarms_timestamp": "2024-01-01T12:00:00",
+///       "last_reading_at": "2024-01-01T12:00:00",
+///       "data_age_seconds": 42,
+///       "connectivity": "online"
+///     }
+///   ],
+///   "online_count": 1,
+///   "alarm_site_count": 0
+/// }
+/// ```
+#[get("/1/Fleet/Status")]
+pub async fn get_fleet_status(
+    if_modified_since: IfModifiedSince,
+    user: AuthenticatedUser,
+    db: DbConn,
+    site_db: SiteReadConn,
+) -> Result<Conditional<Json<FleetStatusResponse>>, Status> {
+    let sites = visible_sites(&db, &user).await?;
+    let latest = latest_by_source(&site_db, sites.iter().map(|s| s.id).collect(), None).await?;
+    Conditional::respond(if_modified_since, newest_reading(&latest), || {
+        Ok(Json(fleet_status(&sites, &latest)))
+    })
+}
+
+/// Summarize every site from the latest readings of its sources.
+fn fleet_status(sites: &[Site], latest: &[SourceLatest]) -> FleetStatusResponse {
+    let mut by_site: HashMap<i32, (Vec<&Reading>, Vec<Option<&str>>)> = HashMap::new();
+    for source in latest {
+        if let Some(reading) = &source.reading {
+            let entry = by_site.entry(source.site_id).or_default();
+            entry.0.push(reading);
+            entry.1.push(source.test_type.as_deref());
+        }
+    }
+
+    let statuses: Vec<FleetSiteStatus> = sites
+        .iter()
+        .map(|site| match by_site.get(&site.id) {
+            Some((readings, test_types)) => site_status(site, readings, test_types),
+            None => site_status(site, &[], &[]),
+        })
+        .collect();
+
+    FleetStatusResponse {
+        online_count: statuses.iter().filter(|s| s.connectivity == Connectivity::Online).count(),
+        alarm_site_count: statuses.iter().filter(|s| s.active_alarm_count > 0).count(),
+        sites: statuses,
+    }
+}
+
+/// Fleet Latest Readings endpoint.
+///
+/// - **URL:** `/api/1/Fleet/Readings/latest?test_type=...`
+/// - **Method:** `GET`
+/// - **Purpose:** Returns the most recent reading of every source at every site
+///   visible to the caller
+/// - **Authentication:** Required
+/// - **Authorization:** Same site visibility as `/api/1/Fleet/Status`
+///
+/// # Query Parameters
+///
+/// - `test_type` (optional): only include sources of this test type, e.g.
+///   `charging_state`
+///
+/// Sources that have never reported are omitted. Supports `If-Modified-Since`
+/// like `/api/1/Fleet/Status`.
+#[get("/1/Fleet/Readings/latest?<test_type>")]
+pub async fn get_fleet_latest_readings(
+    test_type: Option<String>,
+    if_modified_since: IfModifiedSince,
+    user: AuthenticatedUser,
+    db: DbConn,
+    site_db: SiteReadConn,
+) -> Result<Conditional<Json<FleetLatestReadingsResponse>>, Status> {
+    let sites = visible_sites(&db, &user).await?;
+    let latest =
+        latest_by_source(&site_db, sites.iter().map(|s| s.id).collect(), test_type).await?;
+    Conditional::respond(if_modified_since, newest_reading(&latest), || {
+        let readings = latest
+            .into_iter()
+            .filter_map(|source| {
+                Some(FleetLatestReading {
+                    site_id: source.site_id,
+                    source_id: source.source_id,
+                    source_name: source.source_name,
+                    test_type: source.test_type,
+                    reading: source.reading?,
+                })
+            })
+            .collect();
+        Ok(Json(FleetLatestReadingsResponse { readings }))
+    })
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![get_fleet_status, get_fleet_latest_readings]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connectivity_thresholds() {
+        assert_eq!(Connectivity::from_age_seconds(None), Connectivity::Unknown);
+        assert_eq!(Connectivity::from_age_seconds(Some(0)), Connectivity::Online);
+        assert_eq!(Connectivity::from_age_seconds(Some(300)), Connectivity::Online);
+        assert_eq!(Connectivity::from_age_seconds(Some(301)), Connectivity::Stale);
+        assert_eq!(Connectivity::from_age_seconds(Some(3601)), Connectivity::Offline);
+    }
+}
@@ -0,0 +1,313 @@
+//! API endpoints for managing a site's historian forwarders.
+//!
+//! A forwarder mirrors a site's readings to the customer's InfluxDB or
+//! Timescale historian. Forwarders live in the site database and are run by
+//! neems-data's monitor, which picks up changes within a few seconds (see
+//! [`neems_data::forward`]). Tokens are write-only: responses show `***` for
+//! a stored token, or the `secret://` reference it names.
+//!
+//! # Authorization Rules
+//! - newtown-admin and newtown-staff can manage forwarders for any site
+//! - Company admins can manage forwarders for their own company's sites
+
+use neems_data::forward::{
+    Forwarder, ForwarderInfo, ForwarderSettings, create_forwarder, delete_forwarder,
+    forwarder_info, get_forwarder, list_forwarders, update_forwarder,
+};
+use rocket::{Route, http::Status, response::status, serde::json::Json};
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{
+    DbConn, SiteDbConn, api::site::can_crud_site, orm::site::get_site_by_id,
+    session_guards::AuthenticatedUser,
+};
+
+/// Error response structure for forwarder API failures.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+type ForwarderError = status::Custom<Json<ErrorResponse>>;
+
+fn error(status: Status, error: impl Into<String>) -> ForwarderError {
+    status::Custom(status, Json(ErrorResponse { error: error.into() }))
+}
+
+fn database_error(action: &str, e: impl std::fmt::Display) -> ForwarderError {
+    eprintln!("Error {} forwarders: {}", action, e);
+    error(
+        Status::InternalServerError,
+        format!("Database error while {} forwarders", action),
+    )
+}
+
+/// 404 unless the site exists, 403 unless `auth_user` may manage it.
+async fn authorize_site(
+    db: &DbConn,
+    auth_user: &AuthenticatedUser,
+    site_id: i32,
+) -> Result<(), ForwarderError> {
+    let site = db
+        .run(move |conn| get_site_by_id(conn, site_id))
+        .await
+        .map_err(|e| database_error("loading", e))?
+        .ok_or_else(|| error(Status::NotFound, "Site not found"))?;
+    if !can_crud_site(auth_user, site.company_id) {
+        return Err(error(
+            Status::Forbidden,
+            "Only Newtown staff or the site's company admins can manage its forwarders",
+        ));
+    }
+    Ok(())
+}
+
+/// The forwarder `id` of `site_id`, as shown by the API.
+fn site_forwarder(
+    conn: &mut diesel::SqliteConnection,
+    site_id: i32,
+    id: i32,
+) -> Result<Forwarder, ForwarderError> {
+    get_forwarder(conn, id)
+        .map_err(|e| database_error("loading", e))?
+        .filter(|forwarder| forwarder.site_id == site_id)
+        .ok_or_else(|| error(Status::NotFound, "Forwarder not found"))
+}
+
+fn name_taken(
+    conn: &mut diesel::SqliteConnection,
+    site_id: i32,
+    name: &str,
+    except_id: Option<i32>,
+) -> Result<bool, ForwarderError> {
+    Ok(list_forwarders(conn, Some(site_id))
+        .map_err(|e| database_error("loading", e))?
+        .iter()
+        .any(|forwarder| forwarder.name == name.trim() && Some(forwarder.id) != except_id))
+}
+
+fn info(
+    conn: &mut diesel::SqliteConnection,
+    forwarder: &Forwarder,
+) -> Result<ForwarderInfo, ForwarderError> {
+    forwarder_info(conn, forwarder).map_err(|e| database_error("loading", e))
+}
+
+/// List Site Forwarders endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/Forwarders`
+/// - **Method:** `GET`
+/// - **Purpose:** Lists the site's forwarders and how far each has got
+/// - **Authentication:** Required; Newtown staff or the site's company admin
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// [
+///   {
+///     "id": 1,
+///     "site_id": 3,
+///     "name": "historian",
+///     "kind": "influxdb",
+///     "url": "https://influx.example.com:8086",
+///     "organization": "acme",
+///     "destination": "neems",
+///     "token": "***",
+///     "enabled": true,
+///     "batch_size": 500,
+///     "created_at": "2026-10-17T09:30:00",
+///     "updated_at": "2026-10-17T09:30:00",
+///     "last_reading_id": 48210,
+///     "last_forwarded_at": "2026-10-17T10:02:11",
+///     "last_error": null,
+///     "pending_readings": 12
+///   }
+/// ]
+/// ```
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't manage the site
+/// - **404 Not Found**: No such site
+#[get("/1/Sites/<site_id>/Forwarders")]
+pub async fn list_site_forwarders(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+) -> Result<Json<Vec<ForwarderInfo>>, ForwarderError> {
+    authorize_site(&db, &auth_user, site_id).await?;
+    site_db
+        .run(move |conn| {
+            let forwarders =
+                list_forwarders(conn, Some(site_id)).map_err(|e| database_error("loading", e))?;
+            forwarders
+                .iter()
+                .map(|forwarder| info(conn, forwarder))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Json)
+        })
+        .await
+}
+
+/// Create Site Forwarder endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/Forwarders`
+/// - **Method:** `POST`
+/// - **Purpose:** Adds a forwarder; the monitor starts it within a few seconds
+///   and sends the site's readings from the first one
+/// - **Authentication:** Required; Newtown staff or the site's company admin
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "name": "historian",
+///   "kind": "timescale",
+///   "url": "postgres://neems@historian.example.com/metrics?sslmode=require",
+///   "destination": "public.neems_readings",
+///   "token": "secret://historian-password",
+///   "enabled": true
+/// }
+/// ```
+///
+/// For `influxdb`, `url` is the server's base URL, `organization` its
+/// organization and `destination` the bucket. For `timescale`, `url` is a
+/// PostgreSQL URL without a password and `destination` a table, which is
+/// created (as a hypertable, where the extension is installed) if missing.
+/// `token` is the InfluxDB token or PostgreSQL password.
+///
+/// # Response
+///
+/// **Success (HTTP 201 Created):** The forwarder, as from `GET`
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Invalid settings (every problem is listed in `error`)
+/// - **403 Forbidden**: User can't manage the site
+/// - **404 Not Found**: No such site
+/// - **409 Conflict**: The site already has a forwarder with this name
+#[post("/1/Sites/<site_id>/Forwarders", data = "<settings>")]
+pub async fn create_site_forwarder(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    settings: Json<ForwarderSettings>,
+) -> Result<status::Created<Json<ForwarderInfo>>, ForwarderError> {
+    authorize_site(&db, &auth_user, site_id).await?;
+    let settings = settings.into_inner();
+    settings
+        .validate()
+        .map_err(|problems| error(Status::BadRequest, problems.join("; ")))?;
+
+    site_db
+        .run(move |conn| {
+            if name_taken(conn, site_id, &settings.name, None)? {
+                return Err(error(
+                    Status::Conflict,
+                    format!("Forwarder with name '{}' already exists", settings.name.trim()),
+                ));
+            }
+            let forwarder = create_forwarder(conn, site_id, &settings)
+                .map_err(|e| database_error("saving", e))?;
+            let location = format!("/api/1/Sites/{}/Forwarders/{}", site_id, forwarder.id);
+            Ok(status::Created::new(location).body(Json(info(conn, &forwarder)?)))
+        })
+        .await
+}
+
+/// Update Site Forwarder endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/Forwarders/<id>`
+/// - **Method:** `PUT`
+/// - **Purpose:** Replaces a forwarder's settings; the monitor restarts it with
+///   them within a few seconds, carrying on from where it got to
+/// - **Authentication:** Required; Newtown staff or the site's company admin
+///
+/// The request is as for `POST`, except that a `null` or omitted `token`
+/// keeps the stored one and `""` removes it.
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** The forwarder, as from `GET`
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Invalid settings (every problem is listed in `error`)
+/// - **403 Forbidden**: User can't manage the site
+/// - **404 Not Found**: No such site, or no such forwarder on it
+/// - **409 Conflict**: The site has another forwarder with this name
+#[put("/1/Sites/<site_id>/Forwarders/<id>", data = "<settings>")]
+pub async fn update_site_forwarder(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    id: i32,
+    settings: Json<ForwarderSettings>,
+) -> Result<Json<ForwarderInfo>, ForwarderError> {
+    authorize_site(&db, &auth_user, site_id).await?;
+    let settings = settings.into_inner();
+    settings
+        .validate()
+        .map_err(|problems| error(Status::BadRequest, problems.join("; ")))?;
+
+    site_db
+        .run(move |conn| {
+            site_forwarder(conn, site_id, id)?;
+            if name_taken(conn, site_id, &settings.name, Some(id))? {
+                return Err(error(
+                    Status::Conflict,
+                    format!("Forwarder with name '{}' already exists", settings.name.trim()),
+                ));
+            }
+            let forwarder = update_forwarder(conn, id, &settings)
+                .map_err(|e| database_error("saving", e))?
+                .ok_or_else(|| error(Status::NotFound, "Forwarder not found"))?;
+            info(conn, &forwarder).map(Json)
+        })
+        .await
+}
+
+/// Delete Site Forwarder endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/Forwarders/<id>`
+/// - **Method:** `DELETE`
+/// - **Purpose:** Removes a forwarder and its progress; the monitor stops it
+///   within a few seconds. Data already in the historian is left alone.
+/// - **Authentication:** Required; Newtown staff or the site's company admin
+///
+/// # Response
+///
+/// **Success (HTTP 204 No Content)**
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't manage the site
+/// - **404 Not Found**: No such site, or no such forwarder on it
+#[delete("/1/Sites/<site_id>/Forwarders/<id>")]
+pub async fn delete_site_forwarder(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    id: i32,
+) -> Result<Status, ForwarderError> {
+    authorize_site(&db, &auth_user, site_id).await?;
+    site_db
+        .run(move |conn| {
+            site_forwarder(conn, site_id, id)?;
+            delete_forwarder(conn, id).map_err(|e| database_error("deleting", e))?;
+            Ok(Status::NoContent)
+        })
+        .await
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        list_site_forwarders,
+        create_site_forwarder,
+        update_site_forwarder,
+        delete_site_forwarder
+    ]
+}
@@ -0,0 +1,246 @@
+//! API endpoint for importing utility meter data.
+//!
+//! A Green Button download (see [`crate::green_button`]) is stored in the site
+//! database as readings of the site's `green_button` source, created on the
+//! first import and never polled, one reading per interval at its start:
+//!
+//! ```json
+//! {
+//!   "delivered_kwh": 0.52,
+//!   "received_kwh": 0.0,
+//!   "net_kwh": 0.52,
+//!   "average_kw": 2.08,
+//!   "interval_seconds": 900,
+//!   "cost": 0.07
+//! }
+//! ```
+//!
+//! `delivered_kwh`, `received_kwh` and `average_kw` (the net demand) are point
+//! fields, so they aggregate quickly. Readings are keyed by their interval's
+//! start, so importing overlapping downloads replaces intervals rather than
+//! counting them twice.
+//!
+//! # Authorization Rules
+//! - newtown-admin and newtown-staff can import for any site
+//! - Company admins can import for their own company's sites
+
+use chrono::NaiveDateTime;
+use diesel::{SqliteConnection, prelude::*};
+use neems_data::models::{NewReading, NewSource};
+use rocket::{Route, data::Capped, http::Status, response::status, serde::json::Json};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use ts_rs::TS;
+
+use crate::{
+    api::{
+        schedule_library::ImportErrorResponse,
+        site::{ARCHIVED_SITE, can_crud_site},
+    },
+    green_button::{MeterInterval, parse_green_button},
+    orm::{DbConn, SiteDbConn, site::get_site_by_id},
+    schedule_import::ImportError,
+    session_guards::AuthenticatedUser,
+};
+
+/// Test type of the sources imported meter data is stored under
+pub const GREEN_BUTTON_TEST_TYPE: &str = "green_button";
+
+/// Reading fields copied into `reading_points`
+const GREEN_BUTTON_POINT_FIELDS: &str = r#"["delivered_kwh","received_kwh","average_kw"]"#;
+
+/// Summary of an imported Green Button file.
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct GreenButtonImportResponse {
+    /// The site's `green_button` source the readings are stored under
+    pub source_id: i32,
+    pub intervals: usize,
+    #[ts(type = "string")]
+    pub start: NaiveDateTime,
+    #[ts(type = "string")]
+    pub end: NaiveDateTime,
+    pub delivered_kwh: f64,
+    pub received_kwh: f64,
+}
+
+fn import_error(
+    status: Status,
+    error: impl Into<String>,
+) -> status::Custom<Json<ImportErrorResponse>> {
+    status::Custom(
+        status,
+        Json(ImportErrorResponse {
+            error: error.into(),
+            line_errors: Vec::new(),
+        }),
+    )
+}
+
+/// The site's `green_button` source, created if it has none.
+fn green_button_source(
+    conn: &mut SqliteConnection,
+    site_id: i32,
+    company_id: i32,
+    interval_seconds: i32,
+) -> Result<i32, Box<dyn std::error::Error + Send + Sync>> {
+    use neems_data::schema::sources;
+
+    let existing: Option<Option<i32>> = sources::table
+        .filter(sources::site_id.eq(site_id))
+        .filter(sources::test_type.eq(GREEN_BUTTON_TEST_TYPE))
+        .select(sources::id)
+        .first(conn)
+        .optional()?;
+    if let Some(Some(id)) = existing {
+        return Ok(id);
+    }
+
+    let new_source = NewSource {
+        name: format!("{}_site_{}", GREEN_BUTTON_TEST_TYPE, site_id),
+        description: Some(format!("Utility meter data for site {} (Green Button)", site_id)),
+        // Imported, not polled
+        active: Some(false),
+        interval_seconds: Some(interval_seconds),
+        test_type: Some(GREEN_BUTTON_TEST_TYPE.to_string()),
+        arguments: None,
+        site_id: Some(site_id),
+        company_id: Some(company_id),
+        priority: None,
+        point_fields: Some(GREEN_BUTTON_POINT_FIELDS.to_string()),
+    };
+    let source = neems_data::create_source(conn, new_source)?;
+    source.id.ok_or_else(|| "created Green Button source has no id".into())
+}
+
+/// Store `intervals` as readings of the site's `green_button` source,
+/// returning its id.
+fn store_intervals(
+    conn: &mut SqliteConnection,
+    site_id: i32,
+    company_id: i32,
+    intervals: &[MeterInterval],
+) -> Result<i32, Box<dyn std::error::Error + Send + Sync>> {
+    let interval_seconds = intervals.first().map_or(3600, |i| i.duration_seconds as i32);
+    let source_id = green_button_source(conn, site_id, company_id, interval_seconds)?;
+    let readings = intervals
+        .iter()
+        .map(|interval| {
+            let net_kwh = interval.delivered_kwh - interval.received_kwh;
+            let mut data = json!({
+                "delivered_kwh": interval.delivered_kwh,
+                "received_kwh": interval.received_kwh,
+                "net_kwh": net_kwh,
+                "average_kw": net_kwh * 3600.0 / interval.duration_seconds.max(1) as f64,
+                "interval_seconds": interval.duration_seconds,
+            });
+            if let Some(cost) = interval.cost {
+                data["cost"] = json!(cost);
+            }
+            NewReading {
+                source_id,
+                timestamp: Some(interval.start),
+                data: data.to_string(),
+                quality_flags: None,
+                device_timestamp: Some(interval.start),
+            }
+        })
+        .collect();
+    neems_data::insert_readings_batch(conn, readings)?;
+    Ok(source_id)
+}
+
+/// Import Green Button endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/GreenButton?utc_offset_minutes=<offset>`
+/// - **Method:** `POST`
+/// - **Purpose:** Stores a utility's Green Button interval data (ESPI XML or
+///   CSV, see [`crate::green_button`]) as readings of the site's `green_button`
+///   source, for baseline and savings analysis. CSV times are local to the
+///   utility, `utc_offset_minutes` from UTC (default 0, e.g. `-420` for Pacific
+///   Daylight Time); XML times are already UTC.
+/// - **Authentication:** Required; Newtown staff or the company's admin
+///
+/// The request body is the file itself; its format is detected from the
+/// content.
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** the `source_id`, the number of `intervals`, the
+/// `start` and `end` of the data and its total `delivered_kwh` and
+/// `received_kwh`
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Not a readable Green Button file
+/// - **403 Forbidden**: User can't manage the site
+/// - **404 Not Found**: No such site
+/// - **413 Payload Too Large**: The file is over the `bytes` limit
+/// - **422 Unprocessable Entity**: Invalid CSV rows, listed in `line_errors`
+#[post("/1/Sites/<site_id>/GreenButton?<utc_offset_minutes>", data = "<file>")]
+pub async fn import_green_button(
+    db: DbConn,
+    site_db: SiteDbConn,
+    site_id: i32,
+    utc_offset_minutes: Option<i32>,
+    file: Capped<Vec<u8>>,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<GreenButtonImportResponse>, status::Custom<Json<ImportErrorResponse>>> {
+    if !file.is_complete() {
+        return Err(import_error(Status::PayloadTooLarge, "The file is too large"));
+    }
+    let utc_offset_minutes = utc_offset_minutes.unwrap_or(0);
+    if utc_offset_minutes.abs() > 14 * 60 {
+        return Err(import_error(Status::BadRequest, "utc_offset_minutes must be within 14 hours"));
+    }
+
+    let site = db
+        .run(move |conn| get_site_by_id(conn, site_id))
+        .await
+        .map_err(|e| {
+            eprintln!("Error getting site: {:?}", e);
+            import_error(Status::InternalServerError, "Internal server error")
+        })?
+        .ok_or_else(|| import_error(Status::NotFound, "Site not found"))?;
+    if !can_crud_site(&auth_user, site.company_id) {
+        return Err(import_error(Status::Forbidden, "Forbidden: insufficient permissions"));
+    }
+    if site.archived_at.is_some() {
+        return Err(import_error(Status::Conflict, ARCHIVED_SITE));
+    }
+
+    let intervals = match parse_green_button(&file, utc_offset_minutes) {
+        Ok(intervals) => intervals,
+        Err(ImportError::Unreadable(e)) => return Err(import_error(Status::BadRequest, e)),
+        Err(ImportError::Lines(line_errors)) => {
+            let error = match line_errors.len() {
+                1 => "1 line of the file is invalid".to_string(),
+                n => format!("{} lines of the file are invalid", n),
+            };
+            let err = Json(ImportErrorResponse { error, line_errors });
+            return Err(status::Custom(Status::UnprocessableEntity, err));
+        }
+    };
+
+    let company_id = site.company_id;
+    site_db
+        .run(move |conn| {
+            let source_id =
+                store_intervals(conn, site_id, company_id, &intervals).map_err(|e| {
+                    eprintln!("Error storing Green Button readings: {:?}", e);
+                    import_error(Status::InternalServerError, "Internal server error")
+                })?;
+            Ok(Json(GreenButtonImportResponse {
+                source_id,
+                intervals: intervals.len(),
+                start: intervals.iter().map(|i| i.start).min().unwrap_or_default(),
+                end: intervals.iter().map(MeterInterval::end).max().unwrap_or_default(),
+                delivered_kwh: intervals.iter().map(|i| i.delivered_kwh).sum(),
+                received_kwh: intervals.iter().map(|i| i.received_kwh).sum(),
+            }))
+        })
+        .await
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![import_green_button]
+}
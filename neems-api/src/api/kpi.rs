@@ -0,0 +1,400 @@
+//! API endpoints for a site's custom KPIs and their daily results.
+//!
+//! A KPI (e.g. "availability %") is an arithmetic expression over a day's
+//! economics and readings (see [`crate::kpi`]), with an optional target and
+//! unit. Each site's KPIs are evaluated once a day over the day before (see
+//! [`crate::kpi_evaluation`]); dashboards read the results from `KpiResults`.
+//!
+//! # Authorization Rules
+//! - newtown-admin and newtown-staff can see and manage any site's KPIs
+//! - Company admins can manage KPIs for their own company's sites
+//! - Other users can see their own company's sites' KPIs and results
+
+use chrono::{Duration, NaiveDate, Utc};
+use rocket::{Route, http::Status, response::status, serde::json::Json};
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{
+    DbConn, SiteDbConn,
+    api::site::{ARCHIVED_SITE, can_crud_site},
+    kpi_evaluation::evaluate_site_day,
+    models::{KpiDefinition, KpiDefinitionRequest, KpiResult},
+    orm::{
+        kpi::{
+            create_kpi_definition, delete_kpi_definition, get_kpi_definition, list_kpi_definitions,
+            list_kpi_results, update_kpi_definition,
+        },
+        site::get_site_by_id,
+    },
+    session_guards::AuthenticatedUser,
+};
+
+/// Days of results returned unless `from` is given
+const DEFAULT_RESULT_DAYS: i64 = 30;
+
+/// Most days of results one request can cover
+const MAX_RESULT_DAYS: i64 = 366;
+
+/// Error response structure for KPI API failures.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+type KpiError = status::Custom<Json<ErrorResponse>>;
+
+fn error(status: Status, error: impl Into<String>) -> KpiError {
+    status::Custom(status, Json(ErrorResponse { error: error.into() }))
+}
+
+fn database_error(action: &str, e: impl std::fmt::Display) -> KpiError {
+    eprintln!("Error {} KPIs: {}", action, e);
+    error(Status::InternalServerError, format!("Database error while {} KPIs", action))
+}
+
+/// 404 unless the site exists; 403 unless `auth_user` can see it, or with
+/// `manage`, manage it.
+async fn authorize_site(
+    db: &DbConn,
+    auth_user: &AuthenticatedUser,
+    site_id: i32,
+    manage: bool,
+) -> Result<(), KpiError> {
+    let site = db
+        .run(move |conn| get_site_by_id(conn, site_id))
+        .await
+        .map_err(|e| database_error("loading", e))?
+        .ok_or_else(|| error(Status::NotFound, "Site not found"))?;
+    if manage {
+        if !can_crud_site(auth_user, site.company_id) {
+            return Err(error(
+                Status::Forbidden,
+                "Only Newtown staff or the site's company admins can manage its KPIs",
+            ));
+        }
+        if site.archived_at.is_some() {
+            return Err(error(Status::Conflict, ARCHIVED_SITE));
+        }
+    } else if !auth_user.has_any_role(&["newtown-admin", "newtown-staff"])
+        && site.company_id != auth_user.user.company_id
+    {
+        return Err(error(Status::Forbidden, "Forbidden: insufficient permissions"));
+    }
+    Ok(())
+}
+
+/// The site's KPI `id`, or 404.
+async fn site_kpi(db: &DbConn, site_id: i32, id: i32) -> Result<KpiDefinition, KpiError> {
+    db.run(move |conn| get_kpi_definition(conn, id))
+        .await
+        .map_err(|e| database_error("loading", e))?
+        .filter(|kpi| kpi.site_id == site_id)
+        .ok_or_else(|| error(Status::NotFound, "KPI not found"))
+}
+
+/// Maps a save error to a response, a duplicate name being a conflict.
+fn save_error(e: diesel::result::Error) -> KpiError {
+    match e {
+        diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::UniqueViolation,
+            _,
+        ) => error(Status::Conflict, "A KPI with this name already exists on the site"),
+        e => database_error("saving", e),
+    }
+}
+
+fn parse_date(name: &str, value: &str) -> Result<NaiveDate, KpiError> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| error(Status::BadRequest, format!("{}: invalid date '{}'", name, value)))
+}
+
+/// List Site KPIs endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/Kpis`
+/// - **Method:** `GET`
+/// - **Purpose:** Lists a site's KPIs by name
+/// - **Authentication:** Required; Newtown staff or the site's company
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// [
+///   {
+///     "id": 4,
+///     "site_id": 2,
+///     "name": "Availability",
+///     "expression": "100 * covered_hours / 24",
+///     "target": 99.0,
+///     "higher_is_better": true,
+///     "unit": "%",
+///     "created_at": "2026-10-17T08:00:00",
+///     "updated_at": "2026-10-17T08:00:00"
+///   }
+/// ]
+/// ```
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't see the site
+/// - **404 Not Found**: No such site
+#[get("/1/Sites/<site_id>/Kpis")]
+pub async fn list_site_kpis(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+) -> Result<Json<Vec<KpiDefinition>>, KpiError> {
+    authorize_site(&db, &auth_user, site_id, false).await?;
+    db.run(move |conn| list_kpi_definitions(conn, site_id))
+        .await
+        .map(Json)
+        .map_err(|e| database_error("loading", e))
+}
+
+/// Create Site KPI endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/Kpis`
+/// - **Method:** `POST`
+/// - **Purpose:** Defines a KPI to evaluate daily for the site
+/// - **Authentication:** Required; Newtown staff or the site's company admin
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "name": "Availability",
+///   "expression": "100 * covered_hours / 24",
+///   "target": 99.0,
+///   "higher_is_better": true,
+///   "unit": "%"
+/// }
+/// ```
+///
+/// `expression` combines numbers, `+ - * /` and parentheses with the day's
+/// economics (`charged_kwh`, `discharged_kwh`, `round_trip_efficiency_percent`,
+/// `charge_cost`, `discharge_value`, `arbitrage_revenue`, `peak_demand_kw`,
+/// `peak_net_demand_kw`, `demand_charge_savings`, `charge_emissions_kg`,
+/// `avoided_emissions_kg`), `readings`, `covered_hours`, and `avg`, `min`,
+/// `max`, `sum` and `count` of a reading field, e.g. `avg(soc_percent)`.
+/// `target` and `unit` are optional; `higher_is_better` defaults to true.
+///
+/// # Response
+///
+/// **Success (HTTP 201 Created):** The KPI, as from `GET`
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Invalid KPI (every problem is listed in `error`)
+/// - **403 Forbidden**: User can't manage the site
+/// - **404 Not Found**: No such site
+/// - **409 Conflict**: The site has a KPI with this name
+#[post("/1/Sites/<site_id>/Kpis", data = "<request>")]
+pub async fn create_site_kpi(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    request: Json<KpiDefinitionRequest>,
+) -> Result<status::Created<Json<KpiDefinition>>, KpiError> {
+    authorize_site(&db, &auth_user, site_id, true).await?;
+    let request = request.into_inner();
+    request
+        .validate()
+        .map_err(|problems| error(Status::BadRequest, problems.join("; ")))?;
+
+    let created = db
+        .run(move |conn| create_kpi_definition(conn, site_id, request))
+        .await
+        .map_err(save_error)?;
+    let location = format!("/api/1/Sites/{}/Kpis/{}", site_id, created.id);
+    Ok(status::Created::new(location).body(Json(created)))
+}
+
+/// Update Site KPI endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/Kpis/<id>`
+/// - **Method:** `PUT`
+/// - **Purpose:** Replaces a KPI's definition. Results already recorded are
+///   kept; evaluate a day again to recompute it.
+/// - **Authentication:** Required; Newtown staff or the site's company admin
+///
+/// # Request Format
+///
+/// As for `POST`
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** The KPI, as from `GET`
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Invalid KPI (every problem is listed in `error`)
+/// - **403 Forbidden**: User can't manage the site
+/// - **404 Not Found**: No such site, or no such KPI on it
+/// - **409 Conflict**: The site has another KPI with this name
+#[put("/1/Sites/<site_id>/Kpis/<id>", data = "<request>")]
+pub async fn update_site_kpi(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    id: i32,
+    request: Json<KpiDefinitionRequest>,
+) -> Result<Json<KpiDefinition>, KpiError> {
+    authorize_site(&db, &auth_user, site_id, true).await?;
+    site_kpi(&db, site_id, id).await?;
+    let request = request.into_inner();
+    request
+        .validate()
+        .map_err(|problems| error(Status::BadRequest, problems.join("; ")))?;
+
+    db.run(move |conn| update_kpi_definition(conn, id, request))
+        .await
+        .map_err(save_error)?
+        .map(Json)
+        .ok_or_else(|| error(Status::NotFound, "KPI not found"))
+}
+
+/// Delete Site KPI endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/Kpis/<id>`
+/// - **Method:** `DELETE`
+/// - **Purpose:** Deletes a KPI and its results
+/// - **Authentication:** Required; Newtown staff or the site's company admin
+///
+/// # Response
+///
+/// **Success (HTTP 204 No Content)**
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't manage the site
+/// - **404 Not Found**: No such site, or no such KPI on it
+#[delete("/1/Sites/<site_id>/Kpis/<id>")]
+pub async fn delete_site_kpi(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    id: i32,
+) -> Result<Status, KpiError> {
+    authorize_site(&db, &auth_user, site_id, true).await?;
+    site_kpi(&db, site_id, id).await?;
+    db.run(move |conn| delete_kpi_definition(conn, id))
+        .await
+        .map_err(|e| database_error("deleting", e))?;
+    Ok(Status::NoContent)
+}
+
+/// Evaluate Site KPIs endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/Kpis/Evaluate?day=YYYY-MM-DD`
+/// - **Method:** `POST`
+/// - **Purpose:** Evaluates the site's KPIs over a UTC day now, replacing any
+///   results for it, e.g. after late readings arrive or a KPI changes. `day`
+///   defaults to yesterday; today's results cover the readings so far.
+/// - **Authentication:** Required; Newtown staff or the site's company admin
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** The day's results, as from `KpiResults`
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Invalid or future day
+/// - **403 Forbidden**: User can't manage the site
+/// - **404 Not Found**: No such site
+#[post("/1/Sites/<site_id>/Kpis/Evaluate?<day>")]
+pub async fn evaluate_site_kpis(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    day: Option<String>,
+) -> Result<Json<Vec<KpiResult>>, KpiError> {
+    authorize_site(&db, &auth_user, site_id, true).await?;
+    let today = Utc::now().date_naive();
+    let day = match day {
+        Some(day) => parse_date("day", &day)?,
+        None => today - Duration::days(1),
+    };
+    if day > today {
+        return Err(error(Status::BadRequest, "day: must not be in the future"));
+    }
+    evaluate_site_day(&db, &site_db, site_id, day)
+        .await
+        .map(Json)
+        .map_err(|e| database_error("evaluating", e))
+}
+
+/// Site KPI Results endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/KpiResults?from=&to=&kpi_id=`
+/// - **Method:** `GET`
+/// - **Purpose:** Returns the site's daily KPI results, by day and KPI
+/// - **Authentication:** Required; Newtown staff or the site's company
+///
+/// # Query Parameters
+///
+/// - `from`, `to`: inclusive `YYYY-MM-DD` UTC days, at most a year apart; they
+///   default to the last 30 days
+/// - `kpi_id`: only this KPI's results
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// [
+///   {
+///     "kpi_id": 4,
+///     "day": "2026-10-16",
+///     "value": 95.83,
+///     "met": false,
+///     "error": null,
+///     "evaluated_at": "2026-10-17T00:15:02"
+///   }
+/// ]
+/// ```
+///
+/// `value` is null, and `error` says why, when the expression couldn't be
+/// evaluated, e.g. when no readings report a field it uses. `met` is null
+/// without a value or a target.
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Invalid dates
+/// - **403 Forbidden**: User can't see the site
+/// - **404 Not Found**: No such site
+#[get("/1/Sites/<site_id>/KpiResults?<from>&<to>&<kpi_id>")]
+pub async fn list_site_kpi_results(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    from: Option<String>,
+    to: Option<String>,
+    kpi_id: Option<i32>,
+) -> Result<Json<Vec<KpiResult>>, KpiError> {
+    authorize_site(&db, &auth_user, site_id, false).await?;
+    let to = match to {
+        Some(to) => parse_date("to", &to)?,
+        None => Utc::now().date_naive(),
+    };
+    let from = match from {
+        Some(from) => parse_date("from", &from)?,
+        None => to - Duration::days(DEFAULT_RESULT_DAYS - 1),
+    };
+    if from > to {
+        return Err(error(Status::BadRequest, "from must be on or before to"));
+    }
+    if (to - from).num_days() >= MAX_RESULT_DAYS {
+        return Err(error(Status::BadRequest, "The date range is longer than a year"));
+    }
+    db.run(move |conn| list_kpi_results(conn, site_id, from, to, kpi_id))
+        .await
+        .map(Json)
+        .map_err(|e| database_error("loading", e))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        list_site_kpis,
+        create_site_kpi,
+        update_site_kpi,
+        delete_site_kpi,
+        evaluate_site_kpis,
+        list_site_kpi_results
+    ]
+}
@@ -0,0 +1,284 @@
+//! API endpoints for locale preferences.
+//!
+//! Error messages, alarm notifications and reports can be read in English,
+//! Spanish or French (see [`crate::i18n`]). A company can set the locale its
+//! users get by default, and each user can set their own; without either, a
+//! request's `Accept-Language` header decides. New notification channels
+//! take the company's locale unless they name one.
+//!
+//! # Authorization Rules
+//! - newtown-admin and newtown-staff can view and set any company's or user's
+//!   locale
+//! - Company admins can set their own company's locale and its users'
+//! - Users can view their own company's locale, and view and set their own
+
+use neems_data::i18n::Locale;
+use rocket::{Route, http::Status, response::status, serde::json::Json};
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{
+    DbConn,
+    models::{LocaleInfo, LocaleRequest, LocaleSetting, User},
+    orm::{
+        company::get_company_by_id,
+        locale::{get_company_locale, get_user_locale, set_company_locale, set_user_locale},
+        user::get_user,
+    },
+    session_guards::AuthenticatedUser,
+};
+
+/// Error response structure for locale API failures.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+type LocaleError = status::Custom<Json<ErrorResponse>>;
+
+fn error(status: Status, error: impl Into<String>) -> LocaleError {
+    status::Custom(status, Json(ErrorResponse { error: error.into() }))
+}
+
+fn database_error(action: &str, e: impl std::fmt::Display) -> LocaleError {
+    eprintln!("Error {} locale: {}", action, e);
+    error(Status::InternalServerError, format!("Database error while {} locale", action))
+}
+
+/// 404 unless the company exists, 403 unless `auth_user` may view its
+/// locale, or with `manage`, set it.
+async fn authorize_company(
+    db: &DbConn,
+    auth_user: &AuthenticatedUser,
+    company_id: i32,
+    manage: bool,
+) -> Result<(), LocaleError> {
+    db.run(move |conn| get_company_by_id(conn, company_id))
+        .await
+        .map_err(|e| database_error("loading", e))?
+        .ok_or_else(|| error(Status::NotFound, "Company not found"))?;
+    let allowed = auth_user.has_any_role(&["newtown-admin", "newtown-staff"])
+        || (auth_user.user.company_id == company_id && (!manage || auth_user.has_role("admin")));
+    if !allowed {
+        return Err(error(Status::Forbidden, "Forbidden: insufficient permissions"));
+    }
+    Ok(())
+}
+
+/// The user `user_id`, 404 unless they exist and 403 unless `auth_user` is
+/// them or may manage them.
+async fn authorize_user(
+    db: &DbConn,
+    auth_user: &AuthenticatedUser,
+    user_id: i32,
+) -> Result<User, LocaleError> {
+    let user = db
+        .run(move |conn| get_user(conn, user_id))
+        .await
+        .map_err(|e| database_error("loading", e))?
+        .ok_or_else(|| error(Status::NotFound, "User not found"))?;
+    let allowed = auth_user.user.id == user.id
+        || auth_user.has_any_role(&["newtown-admin", "newtown-staff"])
+        || (auth_user.has_role("admin") && auth_user.user.company_id == user.company_id);
+    if !allowed {
+        return Err(error(Status::Forbidden, "Forbidden: insufficient permissions"));
+    }
+    Ok(user)
+}
+
+/// List Locales endpoint.
+///
+/// - **URL:** `/api/1/Locales`
+/// - **Method:** `GET`
+/// - **Purpose:** Lists the locales messages and reports can be read in
+/// - **Authentication:** Required
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// [
+///   { "locale": "en", "name": "English" },
+///   { "locale": "es", "name": "Español" },
+///   { "locale": "fr", "name": "Français" }
+/// ]
+/// ```
+#[get("/1/Locales")]
+pub fn list_locales(_auth_user: AuthenticatedUser) -> Json<Vec<LocaleInfo>> {
+    Json(
+        Locale::ALL
+            .into_iter()
+            .map(|locale| LocaleInfo { locale, name: locale.name().to_string() })
+            .collect(),
+    )
+}
+
+/// Get Company Locale endpoint.
+///
+/// - **URL:** `/api/1/Companies/<company_id>/Locale`
+/// - **Method:** `GET`
+/// - **Purpose:** Shows the company's locale
+/// - **Authentication:** Required; Newtown staff or the company's users
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// { "locale": "es", "effective": "es" }
+/// ```
+///
+/// `locale` is `null` when the company hasn't set one.
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't view the company's locale
+/// - **404 Not Found**: No such company
+#[get("/1/Companies/<company_id>/Locale")]
+pub async fn get_company_locale_endpoint(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    company_id: i32,
+) -> Result<Json<LocaleSetting>, LocaleError> {
+    authorize_company(&db, &auth_user, company_id, false).await?;
+    let locale = db
+        .run(move |conn| get_company_locale(conn, company_id))
+        .await
+        .map_err(|e| database_error("loading", e))?;
+    Ok(Json(LocaleSetting {
+        locale,
+        effective: locale.unwrap_or_default(),
+    }))
+}
+
+/// Set Company Locale endpoint.
+///
+/// - **URL:** `/api/1/Companies/<company_id>/Locale`
+/// - **Method:** `PUT`
+/// - **Purpose:** Sets or clears the company's locale
+/// - **Authentication:** Required; Newtown staff or the company's admins
+///
+/// # Request Format
+///
+/// ```json
+/// { "locale": "fr" }
+/// ```
+///
+/// `null` clears it.
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** as for `GET`
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't set the company's locale
+/// - **404 Not Found**: No such company
+/// - **422 Unprocessable Entity**: Unsupported locale
+#[put("/1/Companies/<company_id>/Locale", data = "<request>")]
+pub async fn set_company_locale_endpoint(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    company_id: i32,
+    request: Json<LocaleRequest>,
+) -> Result<Json<LocaleSetting>, LocaleError> {
+    authorize_company(&db, &auth_user, company_id, true).await?;
+    let locale = request.into_inner().locale;
+    db.run(move |conn| set_company_locale(conn, company_id, locale))
+        .await
+        .map_err(|e| database_error("saving", e))?;
+    Ok(Json(LocaleSetting {
+        locale,
+        effective: locale.unwrap_or_default(),
+    }))
+}
+
+/// A user's locale setting: theirs, falling back to their company's.
+async fn user_setting(db: &DbConn, user: User) -> Result<LocaleSetting, LocaleError> {
+    db.run(move |conn| {
+        let locale = get_user_locale(conn, user.id)?;
+        let company = get_company_locale(conn, user.company_id)?;
+        Ok::<_, diesel::result::Error>(LocaleSetting {
+            locale,
+            effective: locale.or(company).unwrap_or_default(),
+        })
+    })
+    .await
+    .map_err(|e| database_error("loading", e))
+}
+
+/// Get User Locale endpoint.
+///
+/// - **URL:** `/api/1/Users/<user_id>/Locale`
+/// - **Method:** `GET`
+/// - **Purpose:** Shows the user's locale, and the one in effect for them after
+///   falling back to their company's
+/// - **Authentication:** Required; the user, Newtown staff or the user's
+///   company admins
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// { "locale": null, "effective": "es" }
+/// ```
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't view this user's locale
+/// - **404 Not Found**: No such user
+#[get("/1/Users/<user_id>/Locale")]
+pub async fn get_user_locale_endpoint(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    user_id: i32,
+) -> Result<Json<LocaleSetting>, LocaleError> {
+    let user = authorize_user(&db, &auth_user, user_id).await?;
+    user_setting(&db, user).await.map(Json)
+}
+
+/// Set User Locale endpoint.
+///
+/// - **URL:** `/api/1/Users/<user_id>/Locale`
+/// - **Method:** `PUT`
+/// - **Purpose:** Sets or clears the user's locale
+/// - **Authentication:** Required; the user, Newtown staff or the user's
+///   company admins
+///
+/// # Request Format
+///
+/// ```json
+/// { "locale": "es" }
+/// ```
+///
+/// `null` clears it, so the company's applies.
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** as for `GET`
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't set this user's locale
+/// - **404 Not Found**: No such user
+/// - **422 Unprocessable Entity**: Unsupported locale
+#[put("/1/Users/<user_id>/Locale", data = "<request>")]
+pub async fn set_user_locale_endpoint(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    user_id: i32,
+    request: Json<LocaleRequest>,
+) -> Result<Json<LocaleSetting>, LocaleError> {
+    let user = authorize_user(&db, &auth_user, user_id).await?;
+    let locale = request.into_inner().locale;
+    db.run(move |conn| set_user_locale(conn, user_id, locale))
+        .await
+        .map_err(|e| database_error("saving", e))?;
+    user_setting(&db, user).await.map(Json)
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        list_locales,
+        get_company_locale_endpoint,
+        set_company_locale_endpoint,
+        get_user_locale_endpoint,
+        set_user_locale_endpoint
+    ]
+}
@@ -4,10 +4,14 @@
 //! management, and secure API access. It handles user login requests, generates
 //! session tokens, and provides authenticated endpoints.
 
+use std::convert::Infallible;
+
 use rocket::{
-    Route, get,
-    http::CookieJar,
-    post, response,
+    Request, Route, get,
+    http::{CookieJar, Status},
+    post,
+    request::{FromRequest, Outcome},
+    response,
     serde::{Deserialize, Serialize, json::Json},
 };
 use ts_rs::TS;
@@ -15,7 +19,13 @@ use ts_rs::TS;
 use crate::{
     DbConn,
     logged_json::LoggedJson,
-    orm::{company::get_company_by_id, login::process_login, user_role::get_user_roles},
+    models::{LoginEvent, NewLoginEvent},
+    orm::{
+        company::get_company_by_id,
+        login::{find_user_by_email, process_login},
+        login_event::{get_login_anomalies, record_login_event},
+        user_role::get_user_roles,
+    },
     session_guards::AuthenticatedUser,
 };
 
@@ -27,13 +37,16 @@ pub struct ErrorResponse {
 }
 
 /// Login success response structure containing user information.
-#[derive(Serialize, TS)]
+#[derive(Serialize, Deserialize, TS)]
 #[ts(export)]
 pub struct LoginSuccessResponse {
     pub user_id: i32,
     pub email: String,
     pub company_name: String,
     pub roles: Vec<String>,
+    /// The user must change their password (`PUT /api/1/Users/<id>/Password`)
+    /// before anything else is allowed
+    pub must_change_password: bool,
 }
 
 /// Creates a standardized user response structure for login and hello
@@ -74,6 +87,7 @@ async fn build_user_response(
         email: user.email,
         company_name,
         roles,
+        must_change_password: user.must_change_password,
     })
 }
 
@@ -85,6 +99,76 @@ pub struct LoginRequest {
     pub password: String,
 }
 
+/// Where a login attempt came from, as recorded in the login audit log.
+///
+/// The address is Rocket's [`client_ip`](Request::client_ip). The country is
+/// read from the request header named by the `country_header` setting
+/// (`ROCKET_COUNTRY_HEADER`, e.g. `CF-IPCountry`), for deployments whose
+/// reverse proxy geolocates clients; without it no country is recorded and
+/// `new_country` is never flagged.
+pub struct LoginClient {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub country: Option<String>,
+}
+
+/// Longest user agent kept, in characters
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LoginClient {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let header =
+            |name: &str| request.headers().get_one(name).map(str::trim).filter(|v| !v.is_empty());
+        let country = request
+            .rocket()
+            .figment()
+            .extract_inner::<String>("country_header")
+            .ok()
+            .and_then(|name| header(&name).map(|c| c.to_uppercase()));
+
+        Outcome::Success(LoginClient {
+            ip_address: request.client_ip().map(|ip| ip.to_canonical().to_string()),
+            user_agent: header("User-Agent")
+                .map(|ua| ua.chars().take(MAX_USER_AGENT_LENGTH).collect()),
+            country,
+        })
+    }
+}
+
+/// Record a login attempt in the audit log. Failing to record it is logged
+/// but doesn't fail the login.
+async fn record_attempt(
+    db: &DbConn,
+    client: LoginClient,
+    email: &str,
+    user_id: Option<i32>,
+    success: bool,
+) {
+    let event = NewLoginEvent {
+        user_id,
+        email: email.to_string(),
+        success,
+        ip_address: client.ip_address,
+        user_agent: client.user_agent,
+        country: client.country,
+    };
+    match db.run(move |conn| record_login_event(conn, event)).await {
+        Ok(LoginEvent {
+            anomaly: Some(anomaly),
+            email,
+            ip_address,
+            ..
+        }) => {
+            warn!("Anomalous login ({}) for {} from {:?}", anomaly, email, ip_address);
+        }
+        Ok(_) => {}
+        Err(e) => error!("Failed to record login event: {:?}", e),
+    }
+}
+
 /// Login endpoint that authenticates users and creates sessions.
 ///
 /// - **URL:** `/api/1/login`
@@ -109,7 +193,8 @@ pub struct LoginRequest {
 /// # Response
 ///
 /// **Success (HTTP 200 OK):**
-/// - No response body
+/// - The user, as returned by `/api/1/hello`; when `must_change_password` is
+///   set, the session can only change the password
 /// - Sets session cookie named `session` (HTTP-only, secure, SameSite=Lax)
 ///
 /// **Failure (HTTP 401 Unauthorized):**
@@ -131,6 +216,7 @@ pub struct LoginRequest {
 /// - Session cookies are HTTP-only, secure, and use SameSite=Lax
 /// - Passwords are verified using Argon2 hashing
 /// - Invalid credentials return generic error messages to prevent enumeration
+/// - Every attempt is recorded in the login audit log (see [`LoginClient`])
 ///
 /// # Example
 ///
@@ -149,14 +235,20 @@ pub struct LoginRequest {
 pub async fn login(
     db: DbConn,
     cookies: &CookieJar<'_>,
+    client: LoginClient,
     login: LoggedJson<LoginRequest>,
 ) -> Result<Json<LoginSuccessResponse>, response::status::Custom<Json<ErrorResponse>>> {
     match process_login(&db, cookies, &login).await {
-        Ok((_status, user)) => match build_user_response(&db, user).await {
-            Ok(response) => Ok(Json(response)),
-            Err(err_response) => Err(err_response),
-        },
+        Ok((_status, user)) => {
+            record_attempt(&db, client, &login.email, Some(user.id), true).await;
+            match build_user_response(&db, user).await {
+                Ok(response) => Ok(Json(response)),
+                Err(err_response) => Err(err_response),
+            }
+        }
         Err(status) => {
+            let user_id = find_user_by_email(&db, &login.email).await.ok().flatten().map(|u| u.id);
+            record_attempt(&db, client, &login.email, user_id, false).await;
             let err_json = Json(ErrorResponse { error: "Invalid credentials".to_string() });
             Err(response::status::Custom(status, err_json))
         }
@@ -214,6 +306,60 @@ pub async fn secure_hello(
     build_user_response(&db, auth_user.user).await.map(Json)
 }
 
+/// Login Anomalies endpoint.
+///
+/// - **URL:** `/api/1/LoginAnomalies?<limit>`
+/// - **Method:** `GET`
+/// - **Purpose:** Lists successful logins flagged as anomalous, newest first
+/// - **Authentication:** Required (newtown-admin and newtown-staff see every
+///   company's users; admin sees their own company's)
+///
+/// Flags are `new_country` (a country the user hasn't logged in from before)
+/// and `failures_then_success` (a success after repeated failures; see
+/// [`crate::orm::login_event`]). Default limit is 50; max is 500.
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// [
+///   {
+///     "id": 812,
+///     "user_id": 5,
+///     "email": "user@example.com",
+///     "success": true,
+///     "ip_address": "203.0.113.9",
+///     "user_agent": "Mozilla/5.0 ...",
+///     "country": "RO",
+///     "anomaly": "new_country",
+///     "created_at": "2026-10-16T20:00:00"
+///   }
+/// ]
+/// ```
+#[get("/1/LoginAnomalies?<limit>")]
+pub async fn list_login_anomalies(
+    db: DbConn,
+    limit: Option<i64>,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<Vec<LoginEvent>>, Status> {
+    let company_id = if auth_user.has_any_role(&["newtown-admin", "newtown-staff"]) {
+        None
+    } else if auth_user.has_role("admin") {
+        Some(auth_user.user.company_id)
+    } else {
+        return Err(Status::Forbidden);
+    };
+    let limit = limit.unwrap_or(50).clamp(1, 500);
+
+    db.run(move |conn| {
+        get_login_anomalies(conn, company_id, limit).map(Json).map_err(|e| {
+            eprintln!("Error getting login anomalies: {:?}", e);
+            Status::InternalServerError
+        })
+    })
+    .await
+}
+
 /// Returns all login-related API routes.
 ///
 /// This function collects all login and authentication endpoints for
@@ -222,5 +368,5 @@ pub async fn secure_hello(
 /// # Returns
 /// Vector of Route objects for login endpoints
 pub fn routes() -> Vec<Route> {
-    routes![login, secure_hello]
+    routes![login, secure_hello, list_login_anomalies]
 }
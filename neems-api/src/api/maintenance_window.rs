@@ -0,0 +1,241 @@
+//! API endpoints for a site's maintenance windows.
+//!
+//! A maintenance window marks a period when a site, or one of its devices, is
+//! being worked on. While one is open, alarm notifications for the site are
+//! held back and the site's active command is standby, reported with
+//! `source: "maintenance"` (see [`neems_data::maintenance_windows`]). Windows
+//! live in the site database.
+//!
+//! # Authorization Rules
+//! - newtown-admin and newtown-staff can see and manage any site's windows
+//! - Company admins can manage windows for their own company's sites
+//! - Other users can see their own company's sites' windows
+
+use neems_data::maintenance_windows::{
+    MaintenanceWindow, NewMaintenanceWindow, create_window, end_window, get_window, list_windows,
+};
+use rocket::{Route, http::Status, response::status, serde::json::Json};
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{
+    DbConn, SiteDbConn,
+    api::site::{ARCHIVED_SITE, can_crud_site},
+    orm::{device::get_device_by_id, site::get_site_by_id},
+    session_guards::AuthenticatedUser,
+};
+
+/// Error response structure for maintenance window API failures.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+type MaintenanceError = status::Custom<Json<ErrorResponse>>;
+
+fn error(status: Status, error: impl Into<String>) -> MaintenanceError {
+    status::Custom(status, Json(ErrorResponse { error: error.into() }))
+}
+
+fn database_error(action: &str, e: impl std::fmt::Display) -> MaintenanceError {
+    eprintln!("Error {} maintenance windows: {}", action, e);
+    error(
+        Status::InternalServerError,
+        format!("Database error while {} maintenance windows", action),
+    )
+}
+
+/// 404 unless the site exists, 403 unless `auth_user` may see it (or, with
+/// `manage`, manage it).
+async fn authorize_site(
+    db: &DbConn,
+    auth_user: &AuthenticatedUser,
+    site_id: i32,
+    manage: bool,
+) -> Result<(), MaintenanceError> {
+    let site = db
+        .run(move |conn| get_site_by_id(conn, site_id))
+        .await
+        .map_err(|e| database_error("loading", e))?
+        .ok_or_else(|| error(Status::NotFound, "Site not found"))?;
+    if manage {
+        if !can_crud_site(auth_user, site.company_id) {
+            return Err(error(
+                Status::Forbidden,
+                "Only Newtown staff or the site's company admins can manage its maintenance windows",
+            ));
+        }
+        if site.archived_at.is_some() {
+            return Err(error(Status::Conflict, ARCHIVED_SITE));
+        }
+    } else if !auth_user.has_any_role(&["newtown-admin", "newtown-staff"])
+        && site.company_id != auth_user.user.company_id
+    {
+        return Err(error(Status::Forbidden, "Forbidden: insufficient permissions"));
+    }
+    Ok(())
+}
+
+/// List Site Maintenance Windows endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/MaintenanceWindows`
+/// - **Method:** `GET`
+/// - **Purpose:** Lists a site's maintenance windows, most recent first
+/// - **Authentication:** Required; Newtown staff or the site's company
+///
+/// # Query Parameters
+///
+/// - `current` (optional): `true` for only windows that are open or still to
+///   come
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// [
+///   {
+///     "id": 4,
+///     "site_id": 2,
+///     "device_id": null,
+///     "starts_at": "2026-10-17T08:00:00",
+///     "ends_at": "2026-10-17T12:00:00",
+///     "reason": "Inverter firmware upgrade",
+///     "created_by": "tech@example.com",
+///     "created_at": "2026-10-16T16:41:09"
+///   }
+/// ]
+/// ```
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't see the site
+/// - **404 Not Found**: No such site
+#[get("/1/Sites/<site_id>/MaintenanceWindows?<current>")]
+pub async fn list_site_maintenance_windows(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    current: Option<bool>,
+) -> Result<Json<Vec<MaintenanceWindow>>, MaintenanceError> {
+    authorize_site(&db, &auth_user, site_id, false).await?;
+    site_db
+        .run(move |conn| list_windows(conn, site_id, current.unwrap_or(false)))
+        .await
+        .map(Json)
+        .map_err(|e| database_error("loading", e))
+}
+
+/// Create Site Maintenance Window endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/MaintenanceWindows`
+/// - **Method:** `POST`
+/// - **Purpose:** Opens a maintenance window on the site, or on one of its
+///   devices, now or later
+/// - **Authentication:** Required; Newtown staff or the site's company admin
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "device_id": null,
+///   "starts_at": "2026-10-17T08:00:00",
+///   "ends_at": "2026-10-17T12:00:00",
+///   "reason": "Inverter firmware upgrade"
+/// }
+/// ```
+///
+/// `starts_at` defaults to now. Times are UTC. A window can last at most 31
+/// days.
+///
+/// # Response
+///
+/// **Success (HTTP 201 Created):** The window, as from `GET`, with the
+/// requesting user's email as `created_by`
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Invalid window (every problem is listed in `error`),
+///   or the device isn't on the site
+/// - **403 Forbidden**: User can't manage the site
+/// - **404 Not Found**: No such site
+#[post("/1/Sites/<site_id>/MaintenanceWindows", data = "<window>")]
+pub async fn create_site_maintenance_window(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    window: Json<NewMaintenanceWindow>,
+) -> Result<status::Created<Json<MaintenanceWindow>>, MaintenanceError> {
+    authorize_site(&db, &auth_user, site_id, true).await?;
+    let window = window.into_inner();
+    window
+        .validate(chrono::Utc::now().naive_utc())
+        .map_err(|problems| error(Status::BadRequest, problems.join("; ")))?;
+    if let Some(device_id) = window.device_id {
+        let device = db
+            .run(move |conn| get_device_by_id(conn, device_id))
+            .await
+            .map_err(|e| database_error("loading", e))?;
+        if device.is_none_or(|device| device.site_id != site_id) {
+            return Err(error(
+                Status::BadRequest,
+                format!("device_id: site has no device {}", device_id),
+            ));
+        }
+    }
+
+    let created_by = auth_user.user.email.clone();
+    let created = site_db
+        .run(move |conn| create_window(conn, site_id, &window, &created_by))
+        .await
+        .map_err(|e| database_error("saving", e))?;
+    let location = format!("/api/1/Sites/{}/MaintenanceWindows/{}", site_id, created.id);
+    Ok(status::Created::new(location).body(Json(created)))
+}
+
+/// End Site Maintenance Window endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/MaintenanceWindows/<id>`
+/// - **Method:** `DELETE`
+/// - **Purpose:** Ends an open window now, keeping it for the record, or
+///   cancels one that hasn't started. A window that is already over is left
+///   alone.
+/// - **Authentication:** Required; Newtown staff or the site's company admin
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** The window as it now stands
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't manage the site
+/// - **404 Not Found**: No such site, or no such window on it
+#[delete("/1/Sites/<site_id>/MaintenanceWindows/<id>")]
+pub async fn end_site_maintenance_window(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    id: i32,
+) -> Result<Json<MaintenanceWindow>, MaintenanceError> {
+    authorize_site(&db, &auth_user, site_id, true).await?;
+    site_db
+        .run(move |conn| {
+            get_window(conn, id)
+                .map_err(|e| database_error("loading", e))?
+                .filter(|window| window.site_id == site_id)
+                .ok_or_else(|| error(Status::NotFound, "Maintenance window not found"))?;
+            end_window(conn, id)
+                .map_err(|e| database_error("saving", e))?
+                .ok_or_else(|| error(Status::NotFound, "Maintenance window not found"))
+                .map(Json)
+        })
+        .await
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        list_site_maintenance_windows,
+        create_site_maintenance_window,
+        end_site_maintenance_window
+    ]
+}
@@ -6,30 +6,58 @@
 
 pub mod alarm;
 pub mod application_rule;
+pub mod availability;
+pub mod blob;
 pub mod company;
+pub mod control_latency;
 pub mod data;
+pub mod data_runtime;
 pub mod demo;
 pub mod device;
+pub mod device_group;
+pub mod economics;
+pub mod edge_config;
+pub mod edge_logs;
 pub mod entity_activity;
+pub mod escalation_policy;
 #[cfg(feature = "fixphrase")]
 pub mod fixphrase;
+pub mod fleet;
+pub mod forwarder;
+pub mod green_button;
+pub mod kpi;
+pub mod locale;
 pub mod login;
 pub mod logout;
+pub mod maintenance_window;
+pub mod notification_channel;
 pub mod odata;
+pub mod oncall;
+pub mod power_quality;
+pub mod reading_batch;
 pub mod role;
+pub mod schedule_feed;
 pub mod schedule_library;
+pub mod scheduler;
 pub mod secure_test;
 pub mod site;
+pub mod site_archive;
+pub mod source;
 pub mod status;
+pub mod sync;
 pub mod user;
+pub mod v2;
 
 use rocket::Route;
 
+use crate::api_version::inherit_routes;
+
 /// Collects all routes from all API submodules.
 ///
 /// This function gathers route handlers from all API submodules (fixphrase,
 /// company, data, login, logout, role, secure_test, site, status, and user) and
 /// returns them as a single vector for registration with the Rocket framework.
+/// Version 2's routes are version 1's with [`v2`]'s replacing some of them.
 ///
 /// # Returns
 /// A vector containing all route handlers from all API submodules
@@ -39,19 +67,45 @@ pub fn routes() -> Vec<Route> {
     routes.extend(fixphrase::routes());
     routes.extend(alarm::routes());
     routes.extend(application_rule::routes());
+    routes.extend(availability::routes());
+    routes.extend(blob::routes());
     routes.extend(company::routes());
+    routes.extend(control_latency::routes());
     routes.extend(data::routes());
+    routes.extend(data_runtime::routes());
     routes.extend(demo::routes());
     routes.extend(device::routes());
+    routes.extend(economics::routes());
+    routes.extend(edge_config::routes());
+    routes.extend(edge_logs::routes());
     routes.extend(entity_activity::routes());
+    routes.extend(escalation_policy::routes());
+    routes.extend(fleet::routes());
+    routes.extend(forwarder::routes());
+    routes.extend(green_button::routes());
+    routes.extend(kpi::routes());
+    routes.extend(locale::routes());
     routes.extend(login::routes());
     routes.extend(logout::routes());
+    routes.extend(maintenance_window::routes());
+    routes.extend(device_group::routes());
+    routes.extend(notification_channel::routes());
     routes.extend(odata::routes());
+    routes.extend(oncall::routes());
+    routes.extend(power_quality::routes());
+    routes.extend(reading_batch::routes());
     routes.extend(role::routes());
+    routes.extend(schedule_feed::routes());
     routes.extend(schedule_library::routes());
+    routes.extend(scheduler::routes());
     routes.extend(secure_test::routes());
     routes.extend(site::routes());
+    routes.extend(site_archive::routes());
+    routes.extend(source::routes());
     routes.extend(status::routes());
+    routes.extend(sync::routes());
     routes.extend(user::routes());
+    let v2_routes = inherit_routes(&routes, 1, 2, v2::routes());
+    routes.extend(v2_routes);
     routes
 }
@@ -0,0 +1,366 @@
+//! API endpoints for managing a company's alarm notification channels.
+//!
+//! A channel sends the alarms raised and cleared at the company's sites to a
+//! Slack or Microsoft Teams webhook, or to a PagerDuty service, filtered by
+//! severity. Channels live in the site database and are run by neems-data's
+//! monitor, which picks up changes within a few seconds (see
+//! [`neems_data::notifications`]). Destinations are write-only: responses
+//! show `***` for a stored webhook URL or routing key, or the `secret://`
+//! reference it names.
+//!
+//! # Authorization Rules
+//! - newtown-admin and newtown-staff can manage channels for any company
+//! - Company admins can manage their own company's channels
+
+use neems_data::{
+    notifications::{
+        ChannelInfo, ChannelSettings, NotificationChannel, channel_info, check_destination,
+        create_channel, delete_channel, get_channel, list_channels, update_channel,
+    },
+    secrets,
+};
+use rocket::{Route, http::Status, response::status, serde::json::Json};
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{
+    DbConn, SiteDbConn,
+    orm::{company::get_company_by_id, locale::get_company_locale},
+    session_guards::AuthenticatedUser,
+};
+
+/// Error response structure for notification channel API failures.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+type ChannelError = status::Custom<Json<ErrorResponse>>;
+
+fn error(status: Status, error: impl Into<String>) -> ChannelError {
+    status::Custom(status, Json(ErrorResponse { error: error.into() }))
+}
+
+fn database_error(action: &str, e: impl std::fmt::Display) -> ChannelError {
+    eprintln!("Error {} notification channels: {}", action, e);
+    error(
+        Status::InternalServerError,
+        format!("Database error while {} notification channels", action),
+    )
+}
+
+/// Whether `user` can manage `company_id`'s notification channels.
+fn can_crud_channels(user: &AuthenticatedUser, company_id: i32) -> bool {
+    // newtown-admin and newtown-staff can manage any company's channels
+    if user.has_any_role(&["newtown-admin", "newtown-staff"]) {
+        return true;
+    }
+
+    // Company admins can manage their own company's channels
+    user.has_role("admin") && user.user.company_id == company_id
+}
+
+/// 404 unless the company exists, 403 unless `auth_user` may manage its
+/// channels.
+async fn authorize_company(
+    db: &DbConn,
+    auth_user: &AuthenticatedUser,
+    company_id: i32,
+) -> Result<(), ChannelError> {
+    db.run(move |conn| get_company_by_id(conn, company_id))
+        .await
+        .map_err(|e| database_error("loading", e))?
+        .ok_or_else(|| error(Status::NotFound, "Company not found"))?;
+    if !can_crud_channels(auth_user, company_id) {
+        return Err(error(
+            Status::Forbidden,
+            "Only Newtown staff or the company's admins can manage its notification channels",
+        ));
+    }
+    Ok(())
+}
+
+/// The channel `id` of `company_id`.
+fn company_channel(
+    conn: &mut diesel::SqliteConnection,
+    company_id: i32,
+    id: i32,
+) -> Result<NotificationChannel, ChannelError> {
+    get_channel(conn, id)
+        .map_err(|e| database_error("loading", e))?
+        .filter(|channel| channel.company_id == company_id)
+        .ok_or_else(|| error(Status::NotFound, "Notification channel not found"))
+}
+
+fn name_taken(
+    conn: &mut diesel::SqliteConnection,
+    company_id: i32,
+    name: &str,
+    except_id: Option<i32>,
+) -> Result<bool, ChannelError> {
+    Ok(list_channels(conn, Some(company_id))
+        .map_err(|e| database_error("loading", e))?
+        .iter()
+        .any(|channel| channel.name == name.trim() && Some(channel.id) != except_id))
+}
+
+fn info(
+    conn: &mut diesel::SqliteConnection,
+    channel: &NotificationChannel,
+) -> Result<ChannelInfo, ChannelError> {
+    channel_info(conn, channel).map_err(|e| database_error("loading", e))
+}
+
+/// List Company Notification Channels endpoint.
+///
+/// - **URL:** `/api/1/Companies/<company_id>/NotificationChannels`
+/// - **Method:** `GET`
+/// - **Purpose:** Lists the company's alarm notification channels and how far
+///   each has got
+/// - **Authentication:** Required; Newtown staff or the company's admin
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// [
+///   {
+///     "id": 1,
+///     "company_id": 2,
+///     "name": "on-call",
+///     "kind": "pagerduty",
+///     "destination": "secret://pagerduty-key",
+///     "severities": ["critical", "emergency"],
+///     "send_cleared": true,
+///     "enabled": true,
+///     "locale": "en",
+///     "created_at": "2026-10-17T09:30:00",
+///     "updated_at": "2026-10-17T09:30:00",
+///     "last_reading_id": 48210,
+///     "last_checked_at": "2026-10-17T10:02:11",
+///     "last_error": null
+///   }
+/// ]
+/// ```
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't manage the company's channels
+/// - **404 Not Found**: No such company
+#[get("/1/Companies/<company_id>/NotificationChannels")]
+pub async fn list_company_channels(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    company_id: i32,
+) -> Result<Json<Vec<ChannelInfo>>, ChannelError> {
+    authorize_company(&db, &auth_user, company_id).await?;
+    site_db
+        .run(move |conn| {
+            let channels =
+                list_channels(conn, Some(company_id)).map_err(|e| database_error("loading", e))?;
+            channels
+                .iter()
+                .map(|channel| info(conn, channel))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Json)
+        })
+        .await
+}
+
+/// Create Company Notification Channel endpoint.
+///
+/// - **URL:** `/api/1/Companies/<company_id>/NotificationChannels`
+/// - **Method:** `POST`
+/// - **Purpose:** Adds a channel; the monitor starts it within a few seconds,
+///   from the latest reading, so alarms already active are announced with each
+///   source's next reading
+/// - **Authentication:** Required; Newtown staff or the company's admin
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "name": "operations chat",
+///   "kind": "slack",
+///   "destination": "https://hooks.slack.com/services/T000/B000/XXXX",
+///   "severities": ["info", "warning"],
+///   "send_cleared": false,
+///   "enabled": true,
+///   "locale": "es"
+/// }
+/// ```
+///
+/// `kind` is `slack`, `teams` or `pagerduty`. `destination` is the Slack or
+/// Teams webhook URL, or the PagerDuty integration's 32-character routing
+/// key, and may be a `secret://` reference. `severities` lists the alarm
+/// severities sent: `info`, `warning`, `critical` and `emergency`.
+/// `send_cleared` (default `true`) also sends cleared alarms, which resolve
+/// the PagerDuty incident the alarm opened. `locale` is the language
+/// notifications are written in: `en`, `es` or `fr`, defaulting to the
+/// company's locale (see [`crate::api::locale`]).
+///
+/// # Response
+///
+/// **Success (HTTP 201 Created):** The channel, as from `GET`
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Invalid settings (every problem is listed in `error`)
+/// - **403 Forbidden**: User can't manage the company's channels
+/// - **404 Not Found**: No such company
+/// - **409 Conflict**: The company already has a channel with this name
+#[post("/1/Companies/<company_id>/NotificationChannels", data = "<settings>")]
+pub async fn create_company_channel(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    company_id: i32,
+    settings: Json<ChannelSettings>,
+) -> Result<status::Created<Json<ChannelInfo>>, ChannelError> {
+    authorize_company(&db, &auth_user, company_id).await?;
+    let mut settings = settings.into_inner();
+    if settings.locale.is_none() {
+        settings.locale = db
+            .run(move |conn| get_company_locale(conn, company_id))
+            .await
+            .map_err(|e| database_error("loading", e))?;
+    }
+    let mut problems = settings.validate().err().unwrap_or_default();
+    if settings.destination.as_deref().is_none_or(str::is_empty) {
+        problems.push("destination: required".to_string());
+    }
+    if !problems.is_empty() {
+        return Err(error(Status::BadRequest, problems.join("; ")));
+    }
+
+    site_db
+        .run(move |conn| {
+            if name_taken(conn, company_id, &settings.name, None)? {
+                return Err(error(
+                    Status::Conflict,
+                    format!(
+                        "Notification channel with name '{}' already exists",
+                        settings.name.trim()
+                    ),
+                ));
+            }
+            let channel = create_channel(conn, company_id, &settings)
+                .map_err(|e| database_error("saving", e))?;
+            let location =
+                format!("/api/1/Companies/{}/NotificationChannels/{}", company_id, channel.id);
+            Ok(status::Created::new(location).body(Json(info(conn, &channel)?)))
+        })
+        .await
+}
+
+/// Update Company Notification Channel endpoint.
+///
+/// - **URL:** `/api/1/Companies/<company_id>/NotificationChannels/<id>`
+/// - **Method:** `PUT`
+/// - **Purpose:** Replaces a channel's settings; the monitor restarts it with
+///   them within a few seconds, carrying on from where it got to
+/// - **Authentication:** Required; Newtown staff or the company's admin
+///
+/// The request is as for `POST`, except that a `null`, omitted
+/// or empty `destination` keeps the stored one, which must then suit `kind`,
+/// and a `null` or omitted `locale` keeps the stored one.
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** The channel, as from `GET`
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Invalid settings (every problem is listed in `error`)
+/// - **403 Forbidden**: User can't manage the company's channels
+/// - **404 Not Found**: No such company, or no such channel for it
+/// - **409 Conflict**: The company has another channel with this name
+#[put(
+    "/1/Companies/<company_id>/NotificationChannels/<id>",
+    data = "<settings>"
+)]
+pub async fn update_company_channel(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    company_id: i32,
+    id: i32,
+    settings: Json<ChannelSettings>,
+) -> Result<Json<ChannelInfo>, ChannelError> {
+    authorize_company(&db, &auth_user, company_id).await?;
+    let settings = settings.into_inner();
+    settings
+        .validate()
+        .map_err(|problems| error(Status::BadRequest, problems.join("; ")))?;
+
+    site_db
+        .run(move |conn| {
+            let existing = company_channel(conn, company_id, id)?;
+            if settings.destination.as_deref().is_none_or(str::is_empty)
+                && existing.kind != settings.kind.as_str()
+                && secrets::reference_name(&existing.destination).is_none()
+            {
+                check_destination(settings.kind, &existing.destination).map_err(|e| {
+                    error(
+                        Status::BadRequest,
+                        format!("destination: {} for {}", e, settings.kind.as_str()),
+                    )
+                })?;
+            }
+            if name_taken(conn, company_id, &settings.name, Some(id))? {
+                return Err(error(
+                    Status::Conflict,
+                    format!(
+                        "Notification channel with name '{}' already exists",
+                        settings.name.trim()
+                    ),
+                ));
+            }
+            let channel = update_channel(conn, id, &settings)
+                .map_err(|e| database_error("saving", e))?
+                .ok_or_else(|| error(Status::NotFound, "Notification channel not found"))?;
+            info(conn, &channel).map(Json)
+        })
+        .await
+}
+
+/// Delete Company Notification Channel endpoint.
+///
+/// - **URL:** `/api/1/Companies/<company_id>/NotificationChannels/<id>`
+/// - **Method:** `DELETE`
+/// - **Purpose:** Removes a channel and its progress; the monitor stops it
+///   within a few seconds. Open PagerDuty incidents are left alone.
+/// - **Authentication:** Required; Newtown staff or the company's admin
+///
+/// # Response
+///
+/// **Success (HTTP 204 No Content)**
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't manage the company's channels
+/// - **404 Not Found**: No such company, or no such channel for it
+#[delete("/1/Companies/<company_id>/NotificationChannels/<id>")]
+pub async fn delete_company_channel(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    company_id: i32,
+    id: i32,
+) -> Result<Status, ChannelError> {
+    authorize_company(&db, &auth_user, company_id).await?;
+    site_db
+        .run(move |conn| {
+            company_channel(conn, company_id, id)?;
+            delete_channel(conn, id).map_err(|e| database_error("deleting", e))?;
+            Ok(Status::NoContent)
+        })
+        .await
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        list_company_channels,
+        create_company_channel,
+        update_company_channel,
+        delete_company_channel
+    ]
+}
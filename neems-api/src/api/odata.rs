@@ -1,12 +1,20 @@
 //! OData-specific endpoints and functionality.
 //!
 //! This module provides OData standard endpoints including metadata service
-//! and service document, as well as support for OData query options.
+//! and service document, as well as support for OData query options, and the
+//! caller's capabilities on each entity set.
 
-use rocket::{Route, response::content::RawXml, serde::json::Json};
-use serde::Serialize;
+use std::convert::Infallible;
+
+use rocket::{Route, State, response::content::RawXml, serde::json::Json};
+use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
+use crate::{
+    response_cache::{CachedEndpoint, ResponseCache},
+    session_guards::AuthenticatedUser,
+};
+
 /// Service document listing available entity sets
 #[derive(Serialize, TS)]
 #[ts(export)]
@@ -25,6 +33,124 @@ pub struct EntitySet {
     pub url: String,
 }
 
+/// How far one of the caller's capabilities on an entity set reaches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum CapabilityScope {
+    /// Entities of every company
+    Any,
+    /// Entities of the caller's own company
+    Company,
+    /// Only the caller's own record
+    Own,
+}
+
+/// What the caller may do with one entity set.
+///
+/// A `None` scope means the operation is refused outright; otherwise the
+/// server may still refuse it for entities outside the scope.
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct EntitySetCapabilities {
+    pub name: String,
+    pub insertable: Option<CapabilityScope>,
+    pub updatable: Option<CapabilityScope>,
+    pub deletable: Option<CapabilityScope>,
+}
+
+/// The caller's capabilities on every entity set in the service document.
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Capabilities {
+    pub value: Vec<EntitySetCapabilities>,
+}
+
+impl EntitySetCapabilities {
+    fn new(
+        name: &str,
+        insertable: Option<CapabilityScope>,
+        updatable: Option<CapabilityScope>,
+        deletable: Option<CapabilityScope>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            insertable,
+            updatable,
+            deletable,
+        }
+    }
+}
+
+/// The caller's capabilities, following the checks in each entity set's
+/// handlers; keep the two in step.
+pub fn capabilities_for(user: &AuthenticatedUser) -> Capabilities {
+    use CapabilityScope::{Any, Company, Own};
+
+    let newtown = user.has_any_role(&["newtown-admin", "newtown-staff"]);
+    // Sites, devices and users: newtown roles anywhere, admins in their own
+    // company
+    let company_managed = if newtown {
+        Some(Any)
+    } else if user.has_role("admin") {
+        Some(Company)
+    } else {
+        None
+    };
+    // Everyone may update their own profile
+    let user_updatable = company_managed.or(Some(Own));
+    let newtown_only = newtown.then_some(Any);
+    let roles = user.has_role("newtown-admin").then_some(Any);
+
+    Capabilities {
+        value: vec![
+            EntitySetCapabilities::new("Users", company_managed, user_updatable, company_managed),
+            EntitySetCapabilities::new("Companies", Some(Any), None, Some(Any)),
+            EntitySetCapabilities::new("Sites", company_managed, company_managed, company_managed),
+            EntitySetCapabilities::new(
+                "Devices",
+                company_managed,
+                company_managed,
+                company_managed,
+            ),
+            EntitySetCapabilities::new("Roles", roles, roles, roles),
+            EntitySetCapabilities::new("DataSources", newtown_only, newtown_only, None),
+            // Readings arrive from the collector and edge sync, not through
+            // the entity set
+            EntitySetCapabilities::new("Readings", None, None, None),
+        ],
+    }
+}
+
+/// Capabilities endpoint.
+///
+/// - **URL:** `/api/1/capabilities`
+/// - **Method:** `GET`
+/// - **Purpose:** Returns which entity sets the caller may insert into, update
+///   and delete from, given their roles
+/// - **Authentication:** Required
+///
+/// Lets the UI disable actions the caller can't take instead of finding out
+/// from a 403. Each operation's scope is `any`, `company` (the caller's own
+/// company only), `own` (the caller's own record only) or `null` (not
+/// allowed).
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// {
+///   "value": [
+///     { "name": "Sites", "insertable": "company", "updatable": "company", "deletable": "company" },
+///     { "name": "Roles", "insertable": null, "updatable": null, "deletable": null }
+///   ]
+/// }
+/// ```
+#[get("/1/capabilities")]
+pub fn capabilities(auth_user: AuthenticatedUser) -> Json<Capabilities> {
+    Json(capabilities_for(&auth_user))
+}
+
 /// OData Service Document endpoint.
 ///
 /// - **URL:** `/api/1/`
@@ -89,7 +215,15 @@ pub fn service_document() -> Json<ServiceDocument> {
 /// This endpoint provides machine-readable metadata about the data model
 /// including entity types, relationships, and operations.
 #[get("/1/$metadata")]
-pub fn metadata_document() -> RawXml<String> {
+pub async fn metadata_document(cache: &State<ResponseCache>) -> RawXml<String> {
+    let load = async { Ok::<_, Infallible>(build_metadata_document()) };
+    match cache.get_or_load(CachedEndpoint::Metadata, String::new(), load).await {
+        Ok(metadata) => RawXml(metadata),
+        Err(never) => match never {},
+    }
+}
+
+fn build_metadata_document() -> String {
     let metadata = r#"<?xml version="1.0" encoding="utf-8"?>
 <edmx:Edmx Version="4.0" xmlns:edmx="http://docs.oasis-open.org/odata/ns/edmx">
   <edmx:DataServices>
@@ -105,6 +239,8 @@ pub fn metadata_document() -> RawXml<String> {
         <Property Name="password_hash" Type="Edm.String" Nullable="false"/>
         <Property Name="company_id" Type="Edm.Int32" Nullable="false"/>
         <Property Name="totp_secret" Type="Edm.String" Nullable="true"/>
+        <Property Name="must_change_password" Type="Edm.Boolean" Nullable="false"/>
+        <Property Name="service_account" Type="Edm.Boolean" Nullable="false"/>
         <Property Name="created_at" Type="Edm.DateTimeOffset" Nullable="false"/>
         <Property Name="updated_at" Type="Edm.DateTimeOffset" Nullable="false"/>
         <Property Name="activity_created_at" Type="Edm.DateTimeOffset" Nullable="true"/>
@@ -144,6 +280,7 @@ pub fn metadata_document() -> RawXml<String> {
         <NavigationProperty Name="Company" Type="NeemsAPI.Company" Nullable="false">
           <ReferentialConstraint Property="company_id" ReferencedProperty="id"/>
         </NavigationProperty>
+        <NavigationProperty Name="Devices" Type="Collection(NeemsAPI.Device)"/>
       </EntityType>
 
       <!-- Role Entity Type -->
@@ -200,6 +337,7 @@ pub fn metadata_document() -> RawXml<String> {
         <NavigationProperty Name="Site" Type="NeemsAPI.Site" Nullable="false">
           <ReferentialConstraint Property="site_id" ReferencedProperty="id"/>
         </NavigationProperty>
+        <NavigationProperty Name="Source" Type="NeemsAPI.DataSource" Nullable="true"/>
       </EntityType>
 
       <!-- Reading Entity Type -->
@@ -229,10 +367,12 @@ pub fn metadata_document() -> RawXml<String> {
         </EntitySet>
         <EntitySet Name="Sites" EntityType="NeemsAPI.Site">
           <NavigationPropertyBinding Path="Company" Target="Companies"/>
+          <NavigationPropertyBinding Path="Devices" Target="Devices"/>
         </EntitySet>
         <EntitySet Name="Devices" EntityType="NeemsAPI.Device">
           <NavigationPropertyBinding Path="Company" Target="Companies"/>
           <NavigationPropertyBinding Path="Site" Target="Sites"/>
+          <NavigationPropertyBinding Path="Source" Target="DataSources"/>
         </EntitySet>
         <EntitySet Name="Roles" EntityType="NeemsAPI.Role">
           <NavigationPropertyBinding Path="Users" Target="Users"/>
@@ -250,10 +390,10 @@ pub fn metadata_document() -> RawXml<String> {
   </edmx:DataServices>
 </edmx:Edmx>"#;
 
-    RawXml(metadata.to_string())
+    metadata.to_string()
 }
 
 /// Returns a vector of all OData-related routes.
 pub fn routes() -> Vec<Route> {
-    routes![service_document, metadata_document]
+    routes![service_document, metadata_document, capabilities]
 }
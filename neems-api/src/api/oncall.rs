@@ -0,0 +1,494 @@
+//! API endpoints for a company's on-call rotations.
+//!
+//! A rotation's members take shifts in turn, and overrides put someone else
+//! on call for a time window, such as a swap or holiday cover (see
+//! [`crate::oncall`]). An escalation policy's tier can name a rotation, so an
+//! alarm nobody acknowledges reaches whoever is on call at the time rather
+//! than a fixed list (see [`crate::alarm_escalation`]).
+//!
+//! # Authorization Rules
+//! - newtown-admin and newtown-staff can view and manage any company's
+//!   rotations
+//! - Company admins can manage their own company's rotations
+//! - Other users can view their own company's rotations and who is on call
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rocket::{Route, http::Status, response::status, serde::json::Json};
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{
+    DbConn,
+    models::{
+        NewOnCallOverride, OnCallOverride, OnCallOverrideRequest, OnCallRotation,
+        OnCallRotationRequest, OnCallShift,
+    },
+    oncall::on_call_at,
+    orm::{
+        company::get_company_by_id,
+        oncall::{
+            add_oncall_override, create_oncall_rotation, delete_oncall_override,
+            delete_oncall_rotation, get_oncall_rotation, list_oncall_rotations,
+            policies_using_rotation, update_oncall_rotation,
+        },
+        user::get_user,
+    },
+    session_guards::AuthenticatedUser,
+};
+
+/// Error response structure for on-call API failures.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+type OnCallError = status::Custom<Json<ErrorResponse>>;
+
+fn error(status: Status, error: impl Into<String>) -> OnCallError {
+    status::Custom(status, Json(ErrorResponse { error: error.into() }))
+}
+
+fn database_error(action: &str, e: impl std::fmt::Display) -> OnCallError {
+    eprintln!("Error {} on-call rotations: {}", action, e);
+    error(
+        Status::InternalServerError,
+        format!("Database error while {} on-call rotations", action),
+    )
+}
+
+/// Whether `user` can manage `company_id`'s rotations.
+fn can_crud_rotations(user: &AuthenticatedUser, company_id: i32) -> bool {
+    // newtown-admin and newtown-staff can manage any company's rotations
+    if user.has_any_role(&["newtown-admin", "newtown-staff"]) {
+        return true;
+    }
+
+    // Company admins can manage their own company's rotations
+    user.has_role("admin") && user.user.company_id == company_id
+}
+
+/// 404 unless the company exists, 403 unless `auth_user` may view its
+/// rotations, or with `manage`, manage them.
+async fn authorize_company(
+    db: &DbConn,
+    auth_user: &AuthenticatedUser,
+    company_id: i32,
+    manage: bool,
+) -> Result<(), OnCallError> {
+    db.run(move |conn| get_company_by_id(conn, company_id))
+        .await
+        .map_err(|e| database_error("loading", e))?
+        .ok_or_else(|| error(Status::NotFound, "Company not found"))?;
+    let allowed = if manage {
+        can_crud_rotations(auth_user, company_id)
+    } else {
+        auth_user.has_any_role(&["newtown-admin", "newtown-staff"])
+            || auth_user.user.company_id == company_id
+    };
+    if !allowed {
+        return Err(error(
+            Status::Forbidden,
+            if manage {
+                "Only Newtown staff or the company's admins can manage its on-call rotations"
+            } else {
+                "Forbidden: insufficient permissions"
+            },
+        ));
+    }
+    Ok(())
+}
+
+/// 400 unless every user is one of the company's.
+async fn check_users(db: &DbConn, company_id: i32, user_ids: Vec<i32>) -> Result<(), OnCallError> {
+    let problems = db
+        .run(move |conn| {
+            let mut problems = Vec::new();
+            for user_id in user_ids {
+                let user = get_user(conn, user_id)?;
+                if user.is_none_or(|user| user.company_id != company_id) {
+                    problems.push(format!("user {} is not one of the company's users", user_id));
+                }
+            }
+            Ok::<_, diesel::result::Error>(problems)
+        })
+        .await
+        .map_err(|e| database_error("loading", e))?;
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(error(Status::BadRequest, problems.join("; ")))
+    }
+}
+
+/// 409 for a rotation name the company already uses.
+fn name_conflict(name: &str) -> impl FnOnce(diesel::result::Error) -> OnCallError + '_ {
+    move |e| match e {
+        diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::UniqueViolation,
+            _,
+        ) => error(
+            Status::Conflict,
+            format!("On-call rotation with name '{}' already exists", name.trim()),
+        ),
+        e => database_error("saving", e),
+    }
+}
+
+/// The rotation `id` of `company_id`, with overrides ending after
+/// `overrides_after`.
+async fn company_rotation(
+    db: &DbConn,
+    company_id: i32,
+    id: i32,
+    overrides_after: NaiveDateTime,
+) -> Result<OnCallRotation, OnCallError> {
+    db.run(move |conn| get_oncall_rotation(conn, id, overrides_after))
+        .await
+        .map_err(|e| database_error("loading", e))?
+        .filter(|rotation| rotation.company_id == company_id)
+        .ok_or_else(|| error(Status::NotFound, "On-call rotation not found"))
+}
+
+/// List Company On-Call Rotations endpoint.
+///
+/// - **URL:** `/api/1/Companies/<company_id>/OnCallRotations`
+/// - **Method:** `GET`
+/// - **Purpose:** Lists the company's rotations by name, with their members in
+///   shift order and the overrides that haven't ended
+/// - **Authentication:** Required; Newtown staff or the company's users
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// [
+///   {
+///     "id": 1,
+///     "company_id": 2,
+///     "name": "operations",
+///     "handoff_at": "2026-10-19T09:00:00",
+///     "shift_hours": 168,
+///     "members": [
+///       { "user_id": 5, "email": "alex@example.com" },
+///       { "user_id": 6, "email": "sam@example.com" }
+///     ],
+///     "overrides": [],
+///     "created_at": "2026-10-18T09:30:00",
+///     "updated_at": "2026-10-18T09:30:00"
+///   }
+/// ]
+/// ```
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't view the company's rotations
+/// - **404 Not Found**: No such company
+#[get("/1/Companies/<company_id>/OnCallRotations")]
+pub async fn list_company_rotations(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    company_id: i32,
+) -> Result<Json<Vec<OnCallRotation>>, OnCallError> {
+    authorize_company(&db, &auth_user, company_id, false).await?;
+    db.run(move |conn| list_oncall_rotations(conn, company_id, Utc::now().naive_utc()))
+        .await
+        .map(Json)
+        .map_err(|e| database_error("loading", e))
+}
+
+/// Create Company On-Call Rotation endpoint.
+///
+/// - **URL:** `/api/1/Companies/<company_id>/OnCallRotations`
+/// - **Method:** `POST`
+/// - **Purpose:** Adds a rotation
+/// - **Authentication:** Required; Newtown staff or the company's admin
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "name": "operations",
+///   "handoff_at": "2026-10-19T09:00:00",
+///   "shift_hours": 168,
+///   "members": [5, 6]
+/// }
+/// ```
+///
+/// `members` are the company's user ids in shift order; the first starts at
+/// `handoff_at` (UTC), and each shift lasts `shift_hours` (default a week, at
+/// most four).
+///
+/// # Response
+///
+/// **Success (HTTP 201 Created):** The rotation, as from `GET`
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Invalid rotation (every problem is listed in `error`)
+/// - **403 Forbidden**: User can't manage the company's rotations
+/// - **404 Not Found**: No such company
+/// - **409 Conflict**: The company already has a rotation with this name
+#[post("/1/Companies/<company_id>/OnCallRotations", data = "<request>")]
+pub async fn create_company_rotation(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    company_id: i32,
+    request: Json<OnCallRotationRequest>,
+) -> Result<status::Created<Json<OnCallRotation>>, OnCallError> {
+    authorize_company(&db, &auth_user, company_id, true).await?;
+    let request = request.into_inner();
+    request
+        .validate()
+        .map_err(|problems| error(Status::BadRequest, problems.join("; ")))?;
+    check_users(&db, company_id, request.members.clone()).await?;
+    let name = request.name.clone();
+    let rotation = db
+        .run(move |conn| create_oncall_rotation(conn, company_id, request, Utc::now().naive_utc()))
+        .await
+        .map_err(name_conflict(&name))?;
+    let location = format!("/api/1/Companies/{}/OnCallRotations/{}", company_id, rotation.id);
+    Ok(status::Created::new(location).body(Json(rotation)))
+}
+
+/// Update Company On-Call Rotation endpoint.
+///
+/// - **URL:** `/api/1/Companies/<company_id>/OnCallRotations/<id>`
+/// - **Method:** `PUT`
+/// - **Purpose:** Replaces a rotation's settings and members, keeping its
+///   overrides
+/// - **Authentication:** Required; Newtown staff or the company's admin
+///
+/// The request is as for `POST`.
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** The rotation, as from `GET`
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Invalid rotation (every problem is listed in `error`)
+/// - **403 Forbidden**: User can't manage the company's rotations
+/// - **404 Not Found**: No such company, or no such rotation for it
+/// - **409 Conflict**: The company has another rotation with this name
+#[put("/1/Companies/<company_id>/OnCallRotations/<id>", data = "<request>")]
+pub async fn update_company_rotation(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    company_id: i32,
+    id: i32,
+    request: Json<OnCallRotationRequest>,
+) -> Result<Json<OnCallRotation>, OnCallError> {
+    authorize_company(&db, &auth_user, company_id, true).await?;
+    let now = Utc::now().naive_utc();
+    company_rotation(&db, company_id, id, now).await?;
+    let request = request.into_inner();
+    request
+        .validate()
+        .map_err(|problems| error(Status::BadRequest, problems.join("; ")))?;
+    check_users(&db, company_id, request.members.clone()).await?;
+    let name = request.name.clone();
+    db.run(move |conn| update_oncall_rotation(conn, id, request, now))
+        .await
+        .map_err(name_conflict(&name))?
+        .map(Json)
+        .ok_or_else(|| error(Status::NotFound, "On-call rotation not found"))
+}
+
+/// Delete Company On-Call Rotation endpoint.
+///
+/// - **URL:** `/api/1/Companies/<company_id>/OnCallRotations/<id>`
+/// - **Method:** `DELETE`
+/// - **Purpose:** Removes a rotation with its overrides
+/// - **Authentication:** Required; Newtown staff or the company's admin
+///
+/// # Response
+///
+/// **Success (HTTP 204 No Content)**
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't manage the company's rotations
+/// - **404 Not Found**: No such company, or no such rotation for it
+/// - **409 Conflict**: An escalation policy's tier uses the rotation
+#[delete("/1/Companies/<company_id>/OnCallRotations/<id>")]
+pub async fn delete_company_rotation(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    company_id: i32,
+    id: i32,
+) -> Result<Status, OnCallError> {
+    authorize_company(&db, &auth_user, company_id, true).await?;
+    company_rotation(&db, company_id, id, Utc::now().naive_utc()).await?;
+    db.run(move |conn| {
+        let policies =
+            policies_using_rotation(conn, id).map_err(|e| database_error("loading", e))?;
+        if !policies.is_empty() {
+            return Err(error(
+                Status::Conflict,
+                format!("The rotation is used by escalation policies: {}", policies.join(", ")),
+            ));
+        }
+        delete_oncall_rotation(conn, id).map_err(|e| database_error("deleting", e))?;
+        Ok(Status::NoContent)
+    })
+    .await
+}
+
+/// Get Who Is On Call endpoint.
+///
+/// - **URL:** `/api/1/Companies/<company_id>/OnCallRotations/<id>/OnCall?at=...
+///   `
+/// - **Method:** `GET`
+/// - **Purpose:** Reports who is on call on the rotation at `at`, an RFC 3339
+///   time (default now), whether on shift or through an override, and from when
+///   until when
+/// - **Authentication:** Required; Newtown staff or the company's users
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// {
+///   "rotation_id": 1,
+///   "user_id": 5,
+///   "email": "alex@example.com",
+///   "overridden": false,
+///   "from": "2026-10-19T09:00:00",
+///   "until": "2026-10-26T09:00:00"
+/// }
+/// ```
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Bad `at`
+/// - **403 Forbidden**: User can't view the company's rotations
+/// - **404 Not Found**: No such company, or no such rotation for it, or nobody
+///   on call
+#[get("/1/Companies/<company_id>/OnCallRotations/<id>/OnCall?<at>")]
+pub async fn get_on_call(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    company_id: i32,
+    id: i32,
+    at: Option<String>,
+) -> Result<Json<OnCallShift>, OnCallError> {
+    authorize_company(&db, &auth_user, company_id, false).await?;
+    let at = match at {
+        Some(at) => DateTime::parse_from_rfc3339(&at)
+            .map_err(|_| error(Status::BadRequest, format!("Invalid time: {}", at)))?
+            .naive_utc(),
+        None => Utc::now().naive_utc(),
+    };
+    let rotation = company_rotation(&db, company_id, id, at).await?;
+    on_call_at(&rotation, at)
+        .map(Json)
+        .ok_or_else(|| error(Status::NotFound, "Nobody is on call"))
+}
+
+/// Create On-Call Override endpoint.
+///
+/// - **URL:** `/api/1/Companies/<company_id>/OnCallRotations/<id>/Overrides`
+/// - **Method:** `POST`
+/// - **Purpose:** Puts one of the company's users on call on the rotation from
+///   `starts_at` until `ends_at` (UTC, at most 90 days), instead of the member
+///   on shift; a later override wins where they overlap
+/// - **Authentication:** Required; Newtown staff or the company's admin
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "user_id": 6,
+///   "starts_at": "2026-10-24T18:00:00",
+///   "ends_at": "2026-10-25T09:00:00",
+///   "reason": "Covering Alex's weekend"
+/// }
+/// ```
+///
+/// # Response
+///
+/// **Success (HTTP 201 Created):** The override
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Invalid override (every problem is listed in `error`)
+/// - **403 Forbidden**: User can't manage the company's rotations
+/// - **404 Not Found**: No such company, or no such rotation for it
+#[post(
+    "/1/Companies/<company_id>/OnCallRotations/<id>/Overrides",
+    data = "<request>"
+)]
+pub async fn create_rotation_override(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    company_id: i32,
+    id: i32,
+    request: Json<OnCallOverrideRequest>,
+) -> Result<status::Created<Json<OnCallOverride>>, OnCallError> {
+    authorize_company(&db, &auth_user, company_id, true).await?;
+    company_rotation(&db, company_id, id, Utc::now().naive_utc()).await?;
+    let request = request.into_inner();
+    request
+        .validate()
+        .map_err(|problems| error(Status::BadRequest, problems.join("; ")))?;
+    check_users(&db, company_id, vec![request.user_id]).await?;
+    let new = NewOnCallOverride {
+        rotation_id: id,
+        user_id: request.user_id,
+        starts_at: request.starts_at,
+        ends_at: request.ends_at,
+        reason: request.reason.map(|r| r.trim().to_string()).filter(|r| !r.is_empty()),
+    };
+    let created = db
+        .run(move |conn| add_oncall_override(conn, new))
+        .await
+        .map_err(|e| database_error("saving", e))?;
+    let location = format!(
+        "/api/1/Companies/{}/OnCallRotations/{}/Overrides/{}",
+        company_id, id, created.id
+    );
+    Ok(status::Created::new(location).body(Json(created)))
+}
+
+/// Delete On-Call Override endpoint.
+///
+/// - **URL:** `/api/1/Companies/<company_id>/OnCallRotations/<id>/Overrides/
+///   <override_id>`
+/// - **Method:** `DELETE`
+/// - **Purpose:** Removes an override, returning the rotation to its shifts
+/// - **Authentication:** Required; Newtown staff or the company's admin
+///
+/// # Response
+///
+/// **Success (HTTP 204 No Content)**
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't manage the company's rotations
+/// - **404 Not Found**: No such company, rotation or override
+#[delete("/1/Companies/<company_id>/OnCallRotations/<id>/Overrides/<override_id>")]
+pub async fn delete_rotation_override(
+    db: DbConn,
+    auth_user: AuthenticatedUser,
+    company_id: i32,
+    id: i32,
+    override_id: i32,
+) -> Result<Status, OnCallError> {
+    authorize_company(&db, &auth_user, company_id, true).await?;
+    company_rotation(&db, company_id, id, Utc::now().naive_utc()).await?;
+    let deleted = db
+        .run(move |conn| delete_oncall_override(conn, id, override_id))
+        .await
+        .map_err(|e| database_error("deleting", e))?;
+    if deleted {
+        Ok(Status::NoContent)
+    } else {
+        Err(error(Status::NotFound, "On-call override not found"))
+    }
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        list_company_rotations,
+        create_company_rotation,
+        update_company_rotation,
+        delete_company_rotation,
+        get_on_call,
+        create_rotation_override,
+        delete_rotation_override
+    ]
+}
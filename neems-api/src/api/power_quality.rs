@@ -0,0 +1,310 @@
+//! API endpoints for a site's power-quality events.
+//!
+//! When its RTAC collector has power-quality capture enabled, a site's
+//! monitor records a window of full-rate samples around each voltage sag or
+//! frequency excursion (see [`neems_data::rtac::power_quality`]). These
+//! endpoints list those events and return their waveforms, as JSON or, from
+//! `Waveform.csv`, as CSV, or saved to blob storage by `Waveform/Exports`
+//! for a download link (see [`crate::storage`]). Events live in the site
+//! database.
+//!
+//! # Authorization Rules
+//! - newtown-admin and newtown-staff can see any site's events
+//! - Other users can see their own company's sites' events
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use neems_data::rtac::power_quality::{
+    PowerQualityEvent, PowerQualityEventKind, PowerQualitySample, PowerQualityWaveform,
+    get_waveform, list_events,
+};
+use rocket::{
+    Route, State,
+    http::{ContentType, Status},
+    response::status,
+    serde::json::Json,
+};
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{
+    DbConn, SiteDbConn,
+    orm::site::get_site_by_id,
+    session_guards::AuthenticatedUser,
+    storage::{BlobLink, Storage},
+};
+
+/// Events returned when no `limit` is given.
+const DEFAULT_LIMIT: i64 = 100;
+
+/// Error response structure for power-quality API failures.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+type PowerQualityError = status::Custom<Json<ErrorResponse>>;
+
+fn error(status: Status, error: impl Into<String>) -> PowerQualityError {
+    status::Custom(status, Json(ErrorResponse { error: error.into() }))
+}
+
+fn database_error(action: &str, e: impl std::fmt::Display) -> PowerQualityError {
+    eprintln!("Error {} power-quality events: {}", action, e);
+    error(
+        Status::InternalServerError,
+        format!("Database error while {} power-quality events", action),
+    )
+}
+
+/// 404 unless the site exists, 403 unless `auth_user` can see it.
+async fn authorize_site(
+    db: &DbConn,
+    auth_user: &AuthenticatedUser,
+    site_id: i32,
+) -> Result<(), PowerQualityError> {
+    let site = db
+        .run(move |conn| get_site_by_id(conn, site_id))
+        .await
+        .map_err(|e| database_error("loading", e))?
+        .ok_or_else(|| error(Status::NotFound, "Site not found"))?;
+    if !auth_user.has_any_role(&["newtown-admin", "newtown-staff"])
+        && site.company_id != auth_user.user.company_id
+    {
+        return Err(error(Status::Forbidden, "Forbidden: insufficient permissions"));
+    }
+    Ok(())
+}
+
+/// RFC 3339, or a naive UTC `YYYY-MM-DDTHH:MM:SS`.
+fn parse_since(s: &str) -> Result<NaiveDateTime, PowerQualityError> {
+    DateTime::parse_from_rfc3339(s)
+        .map(|t| t.naive_utc())
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S"))
+        .map_err(|_| error(Status::BadRequest, format!("since: invalid time '{}'", s)))
+}
+
+/// The site's event `id` with its samples, or 404.
+async fn site_waveform(
+    site_db: &SiteDbConn,
+    site_id: i32,
+    id: i32,
+) -> Result<PowerQualityWaveform, PowerQualityError> {
+    site_db
+        .run(move |conn| get_waveform(conn, site_id, id))
+        .await
+        .map_err(|e| database_error("loading", e))?
+        .ok_or_else(|| error(Status::NotFound, "Power-quality event not found"))
+}
+
+fn to_csv(samples: &[PowerQualitySample]) -> Result<String, csv::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for sample in samples {
+        writer.serialize(sample)?;
+    }
+    let bytes = writer.into_inner().map_err(|e| e.into_error())?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// List Site Power-Quality Events endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/PowerQualityEvents?since=&kind=&limit=`
+/// - **Method:** `GET`
+/// - **Purpose:** Lists the site's captured events, most recent first
+/// - **Authentication:** Required; the user must be able to view the site
+///
+/// # Query Parameters
+///
+/// - `since`: only events triggered at or after this time (RFC 3339)
+/// - `kind`: only `voltage_sag` or `frequency_excursion` events
+/// - `limit`: at most this many events (default 100, at most 1000)
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// [
+///   {
+///     "id": 12,
+///     "source_id": 4,
+///     "kind": "voltage_sag",
+///     "triggered_at": "2026-10-17T14:03:22.150",
+///     "window_start": "2026-10-17T14:03:20.150",
+///     "window_end": "2026-10-17T14:03:27.150",
+///     "nominal_value": 480.0,
+///     "threshold": 432.0,
+///     "extreme_value": 401.5,
+///     "sample_count": 70
+///   }
+/// ]
+/// ```
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Invalid query parameters
+/// - **403 Forbidden**: User can't view the site
+/// - **404 Not Found**: No such site
+#[get("/1/Sites/<site_id>/PowerQualityEvents?<since>&<kind>&<limit>")]
+pub async fn list_site_power_quality_events(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    since: Option<String>,
+    kind: Option<String>,
+    limit: Option<i64>,
+) -> Result<Json<Vec<PowerQualityEvent>>, PowerQualityError> {
+    authorize_site(&db, &auth_user, site_id).await?;
+    let since = since.as_deref().map(parse_since).transpose()?;
+    let kind = kind
+        .as_deref()
+        .map(str::parse::<PowerQualityEventKind>)
+        .transpose()
+        .map_err(|e| error(Status::BadRequest, format!("kind: {}", e)))?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT);
+    if limit < 1 {
+        return Err(error(Status::BadRequest, "limit: must be at least 1"));
+    }
+
+    site_db
+        .run(move |conn| list_events(conn, site_id, since, kind, limit))
+        .await
+        .map(Json)
+        .map_err(|e| database_error("listing", e))
+}
+
+/// Get Site Power-Quality Event endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/PowerQualityEvents/<id>`
+/// - **Method:** `GET`
+/// - **Purpose:** Returns an event with its full-rate samples
+/// - **Authentication:** Required; the user must be able to view the site
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** The event, as listed, with its `samples`:
+/// ```json
+/// {
+///   "id": 12,
+///   "kind": "voltage_sag",
+///   "...": "...",
+///   "samples": [
+///     {
+///       "timestamp": "2026-10-17T14:03:20.150Z",
+///       "voltage_v": 479.6,
+///       "current_a": 120.2,
+///       "grid_frequency_hz": 60.01,
+///       "power_kw": 99.8
+///     }
+///   ]
+/// }
+/// ```
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't view the site
+/// - **404 Not Found**: No such site, or no such event at it
+#[get("/1/Sites/<site_id>/PowerQualityEvents/<id>")]
+pub async fn get_site_power_quality_event(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    id: i32,
+) -> Result<Json<PowerQualityWaveform>, PowerQualityError> {
+    authorize_site(&db, &auth_user, site_id).await?;
+    site_waveform(&site_db, site_id, id).await.map(Json)
+}
+
+/// Download Power-Quality Waveform endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/PowerQualityEvents/<id>/Waveform.csv`
+/// - **Method:** `GET`
+/// - **Purpose:** Returns an event's samples as CSV, one row per sample
+/// - **Authentication:** Required; the user must be able to view the site
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** a `text/csv` document with columns
+/// `timestamp`, `voltage_v`, `current_a`, `grid_frequency_hz` and `power_kw`
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't view the site
+/// - **404 Not Found**: No such site, or no such event at it
+#[get("/1/Sites/<site_id>/PowerQualityEvents/<id>/Waveform.csv")]
+pub async fn get_site_power_quality_waveform_csv(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    id: i32,
+) -> Result<(ContentType, String), PowerQualityError> {
+    authorize_site(&db, &auth_user, site_id).await?;
+    let waveform = site_waveform(&site_db, site_id, id).await?;
+    let csv = to_csv(&waveform.samples).map_err(|e| database_error("writing CSV of", e))?;
+    Ok((ContentType::CSV, csv))
+}
+
+/// Export Power-Quality Waveform endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/PowerQualityEvents/<id>/Waveform/Exports`
+/// - **Method:** `POST`
+/// - **Purpose:** Saves the event's `Waveform.csv` to blob storage, for
+///   downloading later or sharing, and returns a link to it. An event's samples
+///   never change, so it is saved once and later exports link to the same file.
+/// - **Authentication:** Required; the user must be able to view the site
+///
+/// # Response
+///
+/// **Success (HTTP 201 Created):**
+/// ```json
+/// {
+///   "key": "waveforms/site-3/event-12.csv",
+///   "url": "/api/1/Blobs/waveforms/site-3/event-12.csv?expires=1792...&signature=9a1c...",
+///   "expires_at": "2026-10-17T13:00:00"
+/// }
+/// ```
+///
+/// Anyone with the `url` can download the CSV until `expires_at`; with S3
+/// storage it is a presigned S3 URL.
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't view the site
+/// - **404 Not Found**: No such site, or no such event at it
+#[post("/1/Sites/<site_id>/PowerQualityEvents/<id>/Waveform/Exports")]
+pub async fn export_site_power_quality_waveform(
+    db: DbConn,
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    site_id: i32,
+    id: i32,
+    storage: &State<Storage>,
+) -> Result<status::Created<Json<BlobLink>>, PowerQualityError> {
+    authorize_site(&db, &auth_user, site_id).await?;
+    let waveform = site_waveform(&site_db, site_id, id).await?;
+    let key = format!("waveforms/site-{}/event-{}.csv", site_id, waveform.event.id);
+    let stored = storage
+        .store()
+        .exists(&key)
+        .await
+        .map_err(|e| database_error("checking exports of", e))?;
+    if !stored {
+        let csv = to_csv(&waveform.samples).map_err(|e| database_error("writing CSV of", e))?;
+        storage
+            .store()
+            .put(&key, csv.into_bytes(), "text/csv")
+            .await
+            .map_err(|e| database_error("exporting", e))?;
+    }
+    let link = storage
+        .link(&key, Utc::now())
+        .map_err(|e| database_error("signing export URL of", e))?;
+    Ok(status::Created::new(link.url.clone()).body(Json(link)))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        list_site_power_quality_events,
+        get_site_power_quality_event,
+        get_site_power_quality_waveform_csv,
+        export_site_power_quality_waveform
+    ]
+}
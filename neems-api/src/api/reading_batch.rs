@@ -0,0 +1,265 @@
+//! API endpoint for uploading readings in bulk.
+//!
+//! Gateways that buffer readings while their uplink is down flush them here
+//! once it's back, thousands at a time. Each reading is upserted on its source
+//! and timestamp (see [`neems_data::insert_readings_each`]), so a flush that
+//! is retried after a dropped connection replaces rather than duplicates what
+//! got through. Readings are checked and stored one by one: those that can't
+//! be are reported by their position in the batch and the rest are kept.
+//!
+//! # Authorization Rules
+//! - newtown-admin and newtown-staff can upload readings for any source
+//! - Company admins can upload readings for their own company's sources
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use neems_data::{
+    models::{NewReading, Source},
+    schema::sources,
+};
+use rocket::{Route, http::Status, response::status, serde::json::Json};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use ts_rs::TS;
+
+use crate::{
+    api::site::{ARCHIVED_SITE, can_crud_site},
+    orm::{DbConn, SiteDbConn, site::get_site_by_id},
+    session_guards::AuthenticatedUser,
+};
+
+/// Upper bound on readings per batch, so one request can't hold the site
+/// database's write lock for long.
+pub const MAX_BATCH_READINGS: usize = 5000;
+
+/// Error response structure for reading batch failures.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+type BatchError = status::Custom<Json<ErrorResponse>>;
+
+fn error(status: Status, error: impl Into<String>) -> BatchError {
+    status::Custom(status, Json(ErrorResponse { error: error.into() }))
+}
+
+/// One reading of a batch.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct BatchReading {
+    pub source_id: i32,
+    /// When the reading was taken
+    #[ts(type = "string")]
+    pub timestamp: NaiveDateTime,
+    /// The reading itself, a JSON object
+    #[ts(type = "Record<string, unknown>")]
+    pub data: JsonValue,
+    pub quality_flags: Option<i32>,
+}
+
+/// Request payload for uploading a batch of readings.
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ReadingBatch {
+    pub readings: Vec<BatchReading>,
+}
+
+/// A reading of a batch that wasn't stored, and why.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct BatchReadingError {
+    /// Position of the reading in the batch, from 0
+    pub index: usize,
+    pub source_id: i32,
+    pub error: String,
+}
+
+/// Outcome of uploading a batch of readings.
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ReadingBatchResponse {
+    /// Readings stored, new or replacing one with the same source and
+    /// timestamp
+    pub stored: usize,
+    pub errors: Vec<BatchReadingError>,
+}
+
+/// Why readings of `source` can't be uploaded by `auth_user`, if they can't.
+fn source_problem(
+    auth_user: &AuthenticatedUser,
+    source: Option<&Source>,
+    archived_sites: &HashSet<i32>,
+) -> Option<&'static str> {
+    let Some(source) = source else {
+        return Some("Source not found");
+    };
+    let permitted = match source.company_id {
+        Some(company_id) => can_crud_site(auth_user, company_id),
+        None => auth_user.has_any_role(&["newtown-admin", "newtown-staff"]),
+    };
+    if !permitted {
+        return Some("Forbidden: insufficient permissions for this source");
+    }
+    if source.site_id.is_some_and(|site_id| archived_sites.contains(&site_id)) {
+        return Some(ARCHIVED_SITE);
+    }
+    None
+}
+
+/// Upload Readings Batch endpoint.
+///
+/// - **URL:** `/api/1/Readings/batch`
+/// - **Method:** `POST`
+/// - **Purpose:** Stores up to 5000 readings, of any number of sources, in one
+///   call
+/// - **Authentication:** Required; Newtown staff, or an admin of the sources'
+///   company
+///
+/// Readings are keyed by source and `timestamp`: one with the same source and
+/// timestamp as a stored reading replaces it. A reading is refused if its
+/// source doesn't exist, the caller may not write to it, its site is
+/// archived or its `data` isn't a JSON object. Refused readings are listed in
+/// `errors` and the others are stored regardless, so a batch needn't be
+/// resent whole because of one bad reading.
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "readings": [
+///     {
+///       "source_id": 3,
+///       "timestamp": "2024-01-01T12:00:00",
+///       "data": { "level": 42.5 },
+///       "quality_flags": 0
+///     }
+///   ]
+/// }
+/// ```
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// {
+///   "stored": 4998,
+///   "errors": [
+///     { "index": 17, "source_id": 9, "error": "Source not found" },
+///     { "index": 52, "source_id": 3, "error": "data must be a JSON object" }
+///   ]
+/// }
+/// ```
+///
+/// **Error Responses:**
+/// - **413 Payload Too Large**: More than 5000 readings, or a body over the
+///   `json` limit (1 MiB unless configured otherwise)
+#[post("/1/Readings/batch", data = "<batch>")]
+pub async fn upload_reading_batch(
+    db: DbConn,
+    site_db: SiteDbConn,
+    batch: Json<ReadingBatch>,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<ReadingBatchResponse>, BatchError> {
+    let readings = batch.into_inner().readings;
+    if readings.len() > MAX_BATCH_READINGS {
+        return Err(error(
+            Status::PayloadTooLarge,
+            format!("At most {} readings may be uploaded at once", MAX_BATCH_READINGS),
+        ));
+    }
+
+    let source_ids: Vec<i32> = readings
+        .iter()
+        .map(|r| r.source_id)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    let sources: HashMap<i32, Source> = site_db
+        .run(move |conn| {
+            sources::table.filter(sources::id.eq_any(&source_ids)).load::<Source>(conn)
+        })
+        .await
+        .map_err(|e| {
+            eprintln!("Error loading sources for reading batch: {:?}", e);
+            error(Status::InternalServerError, "Internal server error")
+        })?
+        .into_iter()
+        .filter_map(|source| source.id.map(|id| (id, source)))
+        .collect();
+
+    let site_ids: HashSet<i32> = sources.values().filter_map(|s| s.site_id).collect();
+    let archived_sites: HashSet<i32> = db
+        .run(move |conn| {
+            let mut archived = HashSet::new();
+            for site_id in site_ids {
+                if get_site_by_id(conn, site_id)?.is_some_and(|site| site.archived_at.is_some()) {
+                    archived.insert(site_id);
+                }
+            }
+            Ok::<_, diesel::result::Error>(archived)
+        })
+        .await
+        .map_err(|e| {
+            eprintln!("Error loading sites for reading batch: {:?}", e);
+            error(Status::InternalServerError, "Internal server error")
+        })?;
+
+    let mut errors = Vec::new();
+    let mut accepted = Vec::new();
+    for (index, reading) in readings.into_iter().enumerate() {
+        let problem = source_problem(&auth_user, sources.get(&reading.source_id), &archived_sites)
+            .or_else(|| (!reading.data.is_object()).then_some("data must be a JSON object"));
+        match problem {
+            Some(problem) => errors.push(BatchReadingError {
+                index,
+                source_id: reading.source_id,
+                error: problem.to_string(),
+            }),
+            None => accepted.push((index, reading)),
+        }
+    }
+
+    let new_readings: Vec<NewReading> = accepted
+        .iter()
+        .map(|(_, reading)| NewReading {
+            source_id: reading.source_id,
+            timestamp: Some(reading.timestamp),
+            data: reading.data.to_string(),
+            quality_flags: reading.quality_flags,
+            device_timestamp: Some(reading.timestamp),
+        })
+        .collect();
+    let outcomes = site_db
+        .run(move |conn| neems_data::insert_readings_each(conn, &new_readings))
+        .await
+        .map_err(|e| {
+            eprintln!("Error storing reading batch: {:?}", e);
+            error(Status::InternalServerError, "Internal server error")
+        })?;
+
+    let mut stored = 0;
+    for ((index, reading), outcome) in accepted.into_iter().zip(outcomes) {
+        match outcome {
+            Ok(()) => stored += 1,
+            Err(e) => {
+                eprintln!("Error storing reading {} of batch: {:?}", index, e);
+                errors.push(BatchReadingError {
+                    index,
+                    source_id: reading.source_id,
+                    error: "The reading couldn't be stored".to_string(),
+                });
+            }
+        }
+    }
+    errors.sort_by_key(|e| e.index);
+
+    Ok(Json(ReadingBatchResponse { stored, errors }))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![upload_reading_batch]
+}
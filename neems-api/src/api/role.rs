@@ -6,7 +6,7 @@
 
 use rocket::{
     Route,
-    http::Status,
+    http::{Status, uri::Origin},
     response::{self},
     serde::json::Json,
 };
@@ -16,6 +16,10 @@ use ts_rs::TS;
 use crate::{
     logged_json::LoggedJson,
     models::{NewRole, Role},
+    odata_query::{
+        ODataCollectionResponse, ODataField, ODataQuery, apply_query, apply_select,
+        build_context_url, next_link,
+    },
     orm::{
         DbConn,
         role::{delete_role, get_all_roles, get_role, insert_role, update_role},
@@ -105,50 +109,89 @@ pub async fn create_role(
 /// - **Authentication:** Required
 /// - **Authorization:** All authenticated users can list roles
 ///
-/// This endpoint retrieves all roles from the database and returns them
-/// as a JSON array.
+/// This endpoint retrieves the roles in the system as an OData collection,
+/// with `$select`, `$filter` and `$orderby` on `id` and `name`, `$top`,
+/// `$skip` and `$count`. When `$top` leaves roles out, `@odata.nextLink`
+/// requests the next page.
 ///
 /// # Response
 ///
 /// **Success (HTTP 200 OK):**
 /// ```json
-/// [
-///   {
-///     "id": 1,
-///     "name": "Administrator",
-///     "description": "Full system access"
-///   },
-///   {
-///     "id": 2,
-///     "name": "User",
-///     "description": "Basic user access"
-///   }
-/// ]
+/// {
+///   "@odata.context": "http://localhost/api/1/$metadata#Roles",
+///   "@odata.nextLink": "/api/1/Roles?$top=2&$skip=2",
+///   "value": [
+///     {
+///       "id": 1,
+///       "name": "Administrator",
+///       "description": "Full system access"
+///     },
+///     {
+///       "id": 2,
+///       "name": "User",
+///       "description": "Basic user access"
+///     }
+///   ]
+/// }
 /// ```
 ///
 /// # Arguments
 /// * `db` - Database connection pool
 /// * `auth_user` - Authenticated user for authorization
+/// * `query` - OData query options
 ///
 /// # Returns
-/// * `Ok(Json<Vec<Role>>)` - List of all roles
-/// * `Err(Status)` - Error during retrieval (typically InternalServerError)
-#[get("/1/Roles")]
+/// * `Ok(Json<serde_json::Value>)` - The roles, in an OData envelope
+/// * `Err(Status)` - Invalid query options (BadRequest) or a database error
+#[get("/1/Roles?<query..>")]
 pub async fn list_roles(
     db: DbConn,
+    uri: &Origin<'_>,
     _auth_user: AuthenticatedUser,
-) -> Result<Json<Vec<Role>>, response::status::Custom<Json<ErrorResponse>>> {
+    query: ODataQuery,
+) -> Result<Json<serde_json::Value>, response::status::Custom<Json<ErrorResponse>>> {
+    let error = |status: Status, message: &str| {
+        response::status::Custom(status, Json(ErrorResponse { error: message.to_string() }))
+    };
+    query.validate().map_err(|e| error(Status::BadRequest, &e))?;
+
     // All authenticated users can list roles (needed for role assignment UIs)
-    db.run(|conn| {
-        get_all_roles(conn).map(Json).map_err(|e| {
-            eprintln!("Error listing roles: {:?}", e);
-            let err = Json(ErrorResponse {
-                error: "Internal server error while listing roles".to_string(),
-            });
-            response::status::Custom(Status::InternalServerError, err)
+    let roles = db
+        .run(move |conn| {
+            get_all_roles(conn).map_err(|e| {
+                eprintln!("Error listing roles: {:?}", e);
+                error(Status::InternalServerError, "Internal server error while listing roles")
+            })
         })
+        .await?;
+
+    let fields = [
+        ODataField::str("name", |r: &Role| r.name.clone()),
+        ODataField::int("id", |r: &Role| r.id as i64),
+    ];
+    let (roles, total_count) = apply_query(roles, &query, &fields);
+
+    let select_props = query.parse_select();
+    let roles = roles
+        .iter()
+        .map(|role| apply_select(role, select_props.as_deref()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| {
+            error(Status::InternalServerError, "Internal server error while listing roles")
+        })?;
+
+    let context = build_context_url("http://localhost/api/1", "Roles", select_props.as_deref());
+    let mut response = ODataCollectionResponse::new(context, roles);
+    if query.count.unwrap_or(false) {
+        response = response.with_count(total_count);
+    }
+    if let Some(link) = next_link(uri, &query, total_count) {
+        response = response.with_next_link(link);
+    }
+    serde_json::to_value(response).map(Json).map_err(|_| {
+        error(Status::InternalServerError, "Internal server error while listing roles")
     })
-    .await
 }
 
 /// Update Role Request structure for partial updates.
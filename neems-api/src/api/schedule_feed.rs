@@ -0,0 +1,378 @@
+//! iCalendar export of site schedules.
+//!
+//! `Schedule.ics` lists a site's planned charge and discharge windows day by
+//! day, from the effective schedule for each date, so operations teams can
+//! overlay dispatch plans on their calendars. Days whose schedule comes from
+//! a specific-date rule are overrides: they get an all-day event with the
+//! override reason, and their windows are tagged `Override`.
+//!
+//! Calendar apps can't log in, so a user can create feed tokens for a site
+//! and subscribe to `Schedule.ics?token=<token>`. A token reads that one
+//! site's schedule as the user who created it, while they still can, and
+//! nothing else. Signed-in users can fetch the feed without one.
+//!
+//! # Authorization Rules
+//! - newtown-admin and newtown-staff can read any site's feed
+//! - Other users can read their own company's sites' feeds
+
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use rocket::{
+    Request, Route,
+    http::{ContentType, Status},
+    request::{FromRequest, Outcome},
+    response::status,
+    serde::json::Json,
+};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{
+    ical::{Calendar, Event, EventTime},
+    models::{CommandType, RuleType, ScheduleCommandDto, ScheduleFeedToken, Site},
+    orm::{
+        DbConn,
+        application_rule::get_effective_schedule,
+        schedule_feed_token::{
+            create_feed_token, delete_feed_token, get_feed_token_by_token, get_user_feed_tokens,
+        },
+        site::get_site_by_id,
+    },
+    session_guards::{AuthenticatedUser, authorize_user},
+};
+
+/// Days before today a feed starts, so recent history stays visible
+const FEED_DAYS_BEFORE: i64 = 7;
+
+/// Days after today a feed covers unless `days` is given
+const DEFAULT_FEED_DAYS: u32 = 60;
+
+/// Most days after today a feed can cover
+const MAX_FEED_DAYS: u32 = 366;
+
+/// Request structure for creating a feed token.
+#[derive(Deserialize, TS)]
+#[ts(export)]
+pub struct CreateFeedTokenRequest {
+    pub description: Option<String>,
+}
+
+/// A newly created feed token. `token` is shown only this once; `url` is the
+/// path to subscribe to.
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FeedTokenSecret {
+    pub feed_token: ScheduleFeedToken,
+    pub token: String,
+    pub url: String,
+}
+
+/// Who's reading a schedule feed: a calendar app with a feed token for one
+/// site, or a signed-in user.
+pub struct FeedReader {
+    user: AuthenticatedUser,
+    token_site_id: Option<i32>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for FeedReader {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(token) = request.query_value::<&str>("token").and_then(Result::ok) else {
+            return request
+                .guard::<AuthenticatedUser>()
+                .await
+                .map(|user| FeedReader { user, token_site_id: None });
+        };
+
+        let db = match request.guard::<DbConn>().await {
+            Outcome::Success(db) => db,
+            _ => return Outcome::Error((Status::InternalServerError, ())),
+        };
+        let token = token.to_string();
+        let feed_token = match db.run(move |conn| get_feed_token_by_token(conn, &token)).await {
+            Ok(Some(feed_token)) => feed_token,
+            Ok(None) => return Outcome::Error((Status::Unauthorized, ())),
+            Err(e) => {
+                eprintln!("Error finding schedule feed token: {:?}", e);
+                return Outcome::Error((Status::InternalServerError, ()));
+            }
+        };
+        authorize_user(request, feed_token.user_id).await.map(|user| FeedReader {
+            user,
+            token_site_id: Some(feed_token.site_id),
+        })
+    }
+}
+
+/// The site, if `user` can view its schedule; 404 or 403 otherwise.
+fn viewable_site(
+    conn: &mut diesel::SqliteConnection,
+    user: &AuthenticatedUser,
+    site_id: i32,
+) -> Result<Site, Status> {
+    let site = get_site_by_id(conn, site_id)
+        .map_err(|e| {
+            eprintln!("Error getting site: {:?}", e);
+            Status::InternalServerError
+        })?
+        .ok_or(Status::NotFound)?;
+
+    // newtown-admin and newtown-staff can view any schedule; other users
+    // their own company's
+    if user.has_any_role(&["newtown-admin", "newtown-staff"])
+        || site.company_id == user.user.company_id
+    {
+        Ok(site)
+    } else {
+        Err(Status::Forbidden)
+    }
+}
+
+fn command_label(command_type: &CommandType) -> &'static str {
+    match command_type {
+        CommandType::Charge => "Charge",
+        CommandType::Discharge => "Discharge",
+        CommandType::TrickleCharge => "Trickle charge",
+    }
+}
+
+fn command_summary(command: &ScheduleCommandDto) -> String {
+    let mut summary = command_label(&command.command_type).to_string();
+    if let Some(power_kw) = command.power_kw {
+        summary.push_str(&format!(" at {} kW", power_kw));
+    }
+    if let Some(soc) = command.target_soc_percent {
+        summary.push_str(&format!(" to {}% SoC", soc));
+    }
+    summary
+}
+
+/// The calendar of `site`'s schedule for the `days` days from `first_day`.
+fn site_calendar(
+    conn: &mut diesel::SqliteConnection,
+    site: &Site,
+    first_day: NaiveDate,
+    days: u32,
+) -> Result<Calendar, diesel::result::Error> {
+    let mut calendar = Calendar {
+        name: format!("{} schedule", site.name),
+        events: Vec::new(),
+    };
+
+    for day in first_day.iter_days().take(days as usize) {
+        let schedule = match get_effective_schedule(conn, site.id, day) {
+            Ok(schedule) => schedule,
+            // No schedule applies to this day
+            Err(diesel::result::Error::NotFound) => continue,
+            Err(e) => return Err(e),
+        };
+        let item = schedule.library_item;
+        let is_override = schedule.rule.rule_type == RuleType::SpecificDate;
+        let day_key = day.format("%Y%m%d");
+        let midnight = day.and_hms_opt(0, 0, 0).unwrap_or_default();
+        let next_midnight = midnight + Duration::days(1);
+
+        if is_override {
+            calendar.events.push(Event {
+                uid: format!("{}-{}-override@neems", site.id, day_key),
+                start: EventTime::Date(day),
+                end: EventTime::Date(next_midnight.date()),
+                summary: format!("Override: {}", item.name),
+                description: schedule.rule.override_reason.clone(),
+                categories: vec!["Override".to_string()],
+            });
+        }
+
+        let mut commands = item.commands;
+        commands.sort_by_key(|c| c.execution_offset_seconds);
+        for (i, command) in commands.iter().enumerate() {
+            let start = midnight + Duration::seconds(command.execution_offset_seconds as i64);
+            // A command runs for its duration or until the next one starts;
+            // the day's last one is shown until midnight unless it has a
+            // duration, since the next day's schedule may differ
+            let next_start: Option<NaiveDateTime> = commands
+                .get(i + 1)
+                .map(|next| midnight + Duration::seconds(next.execution_offset_seconds as i64));
+            let end = match (command.duration_seconds, next_start) {
+                (Some(duration), Some(next)) => {
+                    (start + Duration::seconds(duration as i64)).min(next)
+                }
+                (Some(duration), None) => start + Duration::seconds(duration as i64),
+                (None, Some(next)) => next,
+                (None, None) => next_midnight,
+            };
+
+            let mut categories = vec![command_label(&command.command_type).to_string()];
+            if is_override {
+                categories.push("Override".to_string());
+            }
+            calendar.events.push(Event {
+                uid: format!("{}-{}-{}@neems", site.id, day_key, command.id),
+                start: EventTime::Utc(start),
+                end: EventTime::Utc(end),
+                summary: command_summary(command),
+                description: Some(format!("Schedule: {}", item.name)),
+                categories,
+            });
+        }
+    }
+
+    Ok(calendar)
+}
+
+/// Site Schedule Feed endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/Schedule.ics`
+/// - **Method:** `GET`
+/// - **Purpose:** Exports the site's planned charge/discharge windows and
+///   overrides as an iCalendar feed, from a week ago through `days` days from
+///   today (default 60, at most 366). Times are UTC.
+/// - **Authentication:** A feed token for the site as `?token=<token>`, or a
+///   signed-in user who can view the site's schedules
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** a `text/calendar` document
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: `days` out of range
+/// - **401 Unauthorized**: Unknown token and not signed in
+/// - **403 Forbidden**: The token is for another site, or its user can no
+///   longer view this one
+/// - **404 Not Found**: No such site
+#[get("/1/Sites/<site_id>/Schedule.ics?<days>")]
+pub async fn schedule_feed(
+    db: DbConn,
+    site_id: i32,
+    days: Option<u32>,
+    reader: FeedReader,
+) -> Result<(ContentType, String), Status> {
+    let days = days.unwrap_or(DEFAULT_FEED_DAYS);
+    if !(1..=MAX_FEED_DAYS).contains(&days) {
+        return Err(Status::BadRequest);
+    }
+    if reader.token_site_id.is_some_and(|token_site_id| token_site_id != site_id) {
+        return Err(Status::Forbidden);
+    }
+
+    db.run(move |conn| {
+        let site = viewable_site(conn, &reader.user, site_id)?;
+        let now = Utc::now().naive_utc();
+        let first_day = now.date() - Duration::days(FEED_DAYS_BEFORE);
+        let calendar = site_calendar(conn, &site, first_day, days + FEED_DAYS_BEFORE as u32 + 1)
+            .map_err(|e| {
+                eprintln!("Error building schedule feed: {:?}", e);
+                Status::InternalServerError
+            })?;
+        Ok((ContentType::Calendar, calendar.render(now)))
+    })
+    .await
+}
+
+/// List Feed Tokens endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/ScheduleFeedTokens`
+/// - **Method:** `GET`
+/// - **Purpose:** Lists the signed-in user's feed tokens for the site
+/// - **Authentication:** Required
+#[get("/1/Sites/<site_id>/ScheduleFeedTokens")]
+pub async fn list_feed_tokens(
+    db: DbConn,
+    site_id: i32,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<Vec<ScheduleFeedToken>>, Status> {
+    db.run(move |conn| {
+        viewable_site(conn, &auth_user, site_id)?;
+        get_user_feed_tokens(conn, site_id, auth_user.user.id).map(Json).map_err(|e| {
+            eprintln!("Error listing schedule feed tokens: {:?}", e);
+            Status::InternalServerError
+        })
+    })
+    .await
+}
+
+/// Create Feed Token endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/ScheduleFeedTokens`
+/// - **Method:** `POST`
+/// - **Purpose:** Creates a token for subscribing a calendar app to the site's
+///   schedule feed as the signed-in user
+/// - **Authentication:** Required; the user must be able to view the site's
+///   schedules
+///
+/// # Request Format
+///
+/// ```json
+/// { "description": "Operations calendar" }
+/// ```
+///
+/// # Response
+///
+/// **Success (HTTP 201 Created):**
+/// ```json
+/// {
+///   "feed_token": { "id": 1, "site_id": 3, "user_id": 9, "prefix": "ical_3f9a0c1d", ... },
+///   "token": "ical_3f9a0c1d...",
+///   "url": "/api/1/Sites/3/Schedule.ics?token=ical_3f9a0c1d..."
+/// }
+/// ```
+///
+/// The token is only ever returned here; subscribe with it right away.
+#[post("/1/Sites/<site_id>/ScheduleFeedTokens", data = "<request>")]
+pub async fn create_feed_token_endpoint(
+    db: DbConn,
+    site_id: i32,
+    request: Json<CreateFeedTokenRequest>,
+    auth_user: AuthenticatedUser,
+) -> Result<status::Created<Json<FeedTokenSecret>>, Status> {
+    let description = request.into_inner().description;
+    db.run(move |conn| {
+        viewable_site(conn, &auth_user, site_id)?;
+        let (feed_token, token) = create_feed_token(conn, site_id, auth_user.user.id, description)
+            .map_err(|e| {
+                eprintln!("Error creating schedule feed token: {:?}", e);
+                Status::InternalServerError
+            })?;
+
+        let url = format!("/api/1/Sites/{}/Schedule.ics?token={}", site_id, token);
+        let location = format!("/api/1/Sites/{}/ScheduleFeedTokens", site_id);
+        Ok(status::Created::new(location).body(Json(FeedTokenSecret { feed_token, token, url })))
+    })
+    .await
+}
+
+/// Delete Feed Token endpoint.
+///
+/// - **URL:** `/api/1/ScheduleFeedTokens/<token_id>`
+/// - **Method:** `DELETE`
+/// - **Purpose:** Revokes one of the signed-in user's feed tokens; calendars
+///   subscribed with it stop updating
+/// - **Authentication:** Required
+///
+/// **Failure (HTTP 404 Not Found):** No such token of the user's
+#[delete("/1/ScheduleFeedTokens/<token_id>")]
+pub async fn delete_feed_token_endpoint(
+    db: DbConn,
+    token_id: i32,
+    auth_user: AuthenticatedUser,
+) -> Status {
+    let user_id = auth_user.user.id;
+    match db.run(move |conn| delete_feed_token(conn, user_id, token_id)).await {
+        Ok(true) => Status::NoContent,
+        Ok(false) => Status::NotFound,
+        Err(e) => {
+            eprintln!("Error deleting schedule feed token: {:?}", e);
+            Status::InternalServerError
+        }
+    }
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        schedule_feed,
+        list_feed_tokens,
+        create_feed_token_endpoint,
+        delete_feed_token_endpoint,
+    ]
+}
@@ -1,23 +1,26 @@
 //! API endpoints for managing schedule library items.
 
-use rocket::{Route, http::Status, response::status, serde::json::Json};
+use rocket::{Route, data::Capped, http::Status, response::status, serde::json::Json};
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
 use crate::{
     logged_json::LoggedJson,
     models::{
-        CloneLibraryItemRequest, CreateLibraryItemRequest, ScheduleLibraryItem,
-        UpdateLibraryItemRequest,
+        CloneLibraryItemRequest, CreateLibraryItemRequest, InstantiateTemplateRequest,
+        InstantiateTemplateResponse, ScheduleLibraryItem, UpdateLibraryItemRequest,
     },
     orm::{
         DbConn,
+        device::get_device_by_id,
         schedule_library::{
             clone_library_item, create_library_item, create_library_item_from_site_defaults,
-            delete_library_item, get_library_item, get_library_items_for_site, update_library_item,
+            delete_library_item, get_library_item, get_library_items_for_site,
+            instantiate_template, update_library_item,
         },
         site::get_site_by_id,
     },
+    schedule_import::{ImportError, ImportLineError, parse_schedule_file},
     session_guards::AuthenticatedUser,
 };
 
@@ -33,6 +36,13 @@ fn can_manage_schedule(
     site_id: i32,
     conn: &mut diesel::SqliteConnection,
 ) -> bool {
+    let site = get_site_by_id(conn, site_id).ok().flatten();
+
+    // Archived sites' schedules are read-only
+    if site.as_ref().is_some_and(|site_data| site_data.archived_at.is_some()) {
+        return false;
+    }
+
     // newtown-admin and newtown-staff can manage any schedule
     if user.has_any_role(&["newtown-admin", "newtown-staff"]) {
         return true;
@@ -40,7 +50,7 @@ fn can_manage_schedule(
 
     // Company admins can manage schedules for their company's sites
     if user.has_role("admin") {
-        if let Ok(Some(site_data)) = get_site_by_id(conn, site_id) {
+        if let Some(site_data) = site {
             return site_data.company_id == user.user.company_id;
         }
     }
@@ -448,6 +458,254 @@ pub async fn create_library_item_from_site_defaults_endpoint(
     .await
 }
 
+/// Error response for schedule imports, with the file's invalid lines.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ImportErrorResponse {
+    pub error: String,
+    /// Problems with individual lines; empty when the file couldn't be read
+    /// at all or the import failed for another reason
+    pub line_errors: Vec<ImportLineError>,
+}
+
+fn import_error(
+    status: Status,
+    error: impl Into<String>,
+) -> status::Custom<Json<ImportErrorResponse>> {
+    status::Custom(
+        status,
+        Json(ImportErrorResponse {
+            error: error.into(),
+            line_errors: Vec::new(),
+        }),
+    )
+}
+
+/// Import Schedule Commands endpoint.
+///
+/// - **URL:** `/api/1/Devices/<device_id>/ScheduleCommands/import?name=<name>`
+/// - **Method:** `POST`
+/// - **Purpose:** Creates a library item on the device's site from a CSV or
+///   XLSX file of time/action/type/power rows (see [`crate::schedule_import`]),
+///   each command for the device
+/// - **Authentication:** Required; Newtown staff or the company's admin
+///
+/// The request body is the file itself; its format is detected from the
+/// content. `description` may be given as a query parameter too.
+///
+/// # Response
+///
+/// **Success (HTTP 201 Created):** the created library item
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Not a readable CSV or XLSX file, or the name is taken
+/// - **403 Forbidden**: User can't manage the site's schedules
+/// - **404 Not Found**: No such device
+/// - **413 Payload Too Large**: The file is over the `bytes` limit
+/// - **422 Unprocessable Entity**: Invalid rows, listed in `line_errors`:
+///
+/// ```json
+/// {
+///   "error": "2 lines of the file are invalid",
+///   "line_errors": [
+///     { "line": 3, "error": "type 'boost' isn't charge, discharge or trickle_charge" },
+///     { "line": 5, "error": "power is required to start a command" }
+///   ]
+/// }
+/// ```
+#[post(
+    "/1/Devices/<device_id>/ScheduleCommands/import?<name>&<description>",
+    data = "<file>"
+)]
+pub async fn import_schedule_commands(
+    db: DbConn,
+    device_id: i32,
+    name: String,
+    description: Option<String>,
+    file: Capped<Vec<u8>>,
+    auth_user: AuthenticatedUser,
+) -> Result<status::Created<Json<ScheduleLibraryItem>>, status::Custom<Json<ImportErrorResponse>>> {
+    if !file.is_complete() {
+        return Err(import_error(Status::PayloadTooLarge, "The file is too large"));
+    }
+
+    db.run(move |conn| {
+        let device = match get_device_by_id(conn, device_id) {
+            Ok(Some(device)) => device,
+            Ok(None) => return Err(import_error(Status::NotFound, "Device not found")),
+            Err(e) => {
+                eprintln!("Error getting device: {:?}", e);
+                return Err(import_error(Status::InternalServerError, "Internal server error"));
+            }
+        };
+
+        if !can_manage_schedule(&auth_user, device.site_id, conn) {
+            return Err(import_error(Status::Forbidden, "Forbidden: insufficient permissions"));
+        }
+
+        let commands = match parse_schedule_file(&file, device.id) {
+            Ok(commands) => commands,
+            Err(ImportError::Unreadable(e)) => return Err(import_error(Status::BadRequest, e)),
+            Err(ImportError::Lines(line_errors)) => {
+                let error = match line_errors.len() {
+                    1 => "1 line of the file is invalid".to_string(),
+                    n => format!("{} lines of the file are invalid", n),
+                };
+                let err = Json(ImportErrorResponse { error, line_errors });
+                return Err(status::Custom(Status::UnprocessableEntity, err));
+            }
+        };
+
+        let request = CreateLibraryItemRequest {
+            name,
+            description,
+            commands,
+            parameters: Vec::new(),
+            change_reason: Some(format!("Imported for device '{}'", device.name)),
+        };
+        match create_library_item(conn, device.site_id, request, Some(auth_user.user.id)) {
+            Ok(item) => {
+                let location = format!("/api/1/ScheduleLibraryItems/{}", item.id);
+                Ok(status::Created::new(location).body(Json(item)))
+            }
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            )) => Err(import_error(Status::BadRequest, "A schedule with this name already exists")),
+            Err(diesel::result::Error::DeserializationError(e)) => {
+                Err(import_error(Status::BadRequest, e.to_string()))
+            }
+            Err(e) => {
+                eprintln!("Error importing schedule commands: {:?}", e);
+                Err(import_error(Status::InternalServerError, "Internal server error"))
+            }
+        }
+    })
+    .await
+}
+
+/// Most days one instantiation can schedule
+const MAX_INSTANTIATION_DAYS: i64 = 366;
+
+/// Instantiate Template endpoint.
+///
+/// - **URL:** `/api/1/ScheduleLibraryItems/<id>/Instantiate`
+/// - **Method:** `POST`
+/// - **Purpose:** Applies a template to a site or device for a date range:
+///   creates a schedule from the template's commands with its parameters set to
+///   the given values (or their defaults), and a specific-date rule applying it
+///   to every day from `start_date` to `end_date`
+/// - **Authentication:** Required; the user must be able to view the template's
+///   site and manage the target site's schedules
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "name": "Heatwave week",
+///   "device_id": 12,
+///   "start_date": "2026-07-20",
+///   "end_date": "2026-07-24",
+///   "values": { "discharge_kw": 400, "start_hour": 16.5 },
+///   "override_reason": "Heatwave"
+/// }
+/// ```
+///
+/// # Response
+///
+/// **Success (HTTP 201 Created):** the created `library_item`, the `rule`
+/// applying it and its `applied_dates`
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Bad dates, missing, unknown or out-of-range parameter
+///   values, a device not at the site, or the name is taken
+/// - **403 Forbidden**: User can't use the template or schedule the site
+/// - **404 Not Found**: No such template, site or device
+#[post("/1/ScheduleLibraryItems/<id>/Instantiate", data = "<request>")]
+pub async fn instantiate_library_item_endpoint(
+    db: DbConn,
+    id: i32,
+    request: LoggedJson<InstantiateTemplateRequest>,
+    auth_user: AuthenticatedUser,
+) -> Result<status::Created<Json<InstantiateTemplateResponse>>, status::Custom<Json<ErrorResponse>>>
+{
+    let error = |status, message: &str| {
+        status::Custom(status, Json(ErrorResponse { error: message.to_string() }))
+    };
+
+    db.run(move |conn| {
+        let template = match get_library_item(conn, id) {
+            Ok(item) => item,
+            Err(diesel::result::Error::NotFound) => {
+                return Err(error(Status::NotFound, "Library item not found"));
+            }
+            Err(e) => {
+                eprintln!("Error getting library item: {:?}", e);
+                return Err(error(Status::InternalServerError, "Internal server error"));
+            }
+        };
+        if !can_view_schedule(&auth_user, template.site_id, conn) {
+            return Err(error(Status::Forbidden, "Forbidden: insufficient permissions"));
+        }
+
+        let req = request.into_inner();
+        if req.start_date > req.end_date {
+            return Err(error(Status::BadRequest, "start_date must be on or before end_date"));
+        }
+        if (req.end_date - req.start_date).num_days() >= MAX_INSTANTIATION_DAYS {
+            return Err(error(Status::BadRequest, "The date range is longer than a year"));
+        }
+
+        // The target site: as given, else the device's, else the template's
+        let device_site_id = match req.device_id {
+            Some(device_id) => match get_device_by_id(conn, device_id) {
+                Ok(Some(device)) => Some(device.site_id),
+                Ok(None) => return Err(error(Status::NotFound, "Device not found")),
+                Err(e) => {
+                    eprintln!("Error getting device: {:?}", e);
+                    return Err(error(Status::InternalServerError, "Internal server error"));
+                }
+            },
+            None => None,
+        };
+        let site_id = req.site_id.or(device_site_id).unwrap_or(template.site_id);
+        match get_site_by_id(conn, site_id) {
+            Ok(Some(_)) => {}
+            Ok(None) => return Err(error(Status::NotFound, "Site not found")),
+            Err(e) => {
+                eprintln!("Error getting site: {:?}", e);
+                return Err(error(Status::InternalServerError, "Internal server error"));
+            }
+        }
+        if !can_manage_schedule(&auth_user, site_id, conn) {
+            return Err(error(Status::Forbidden, "Forbidden: insufficient permissions"));
+        }
+
+        match instantiate_template(conn, id, site_id, req, Some(auth_user.user.id)) {
+            Ok((library_item, rule, applied_dates)) => {
+                let location = format!("/api/1/ScheduleLibraryItems/{}", library_item.id);
+                Ok(status::Created::new(location).body(Json(InstantiateTemplateResponse {
+                    library_item,
+                    rule,
+                    applied_dates,
+                })))
+            }
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            )) => Err(error(Status::BadRequest, "A schedule with this name already exists")),
+            Err(diesel::result::Error::DeserializationError(e)) => {
+                Err(error(Status::BadRequest, &e.to_string()))
+            }
+            Err(e) => {
+                eprintln!("Error instantiating library item: {:?}", e);
+                Err(error(Status::InternalServerError, "Internal server error"))
+            }
+        }
+    })
+    .await
+}
+
 pub fn routes() -> Vec<Route> {
     routes![
         list_library_items,
@@ -457,5 +715,7 @@ pub fn routes() -> Vec<Route> {
         delete_library_item_endpoint,
         clone_library_item_endpoint,
         create_library_item_from_site_defaults_endpoint,
+        import_schedule_commands,
+        instantiate_library_item_endpoint,
     ]
 }
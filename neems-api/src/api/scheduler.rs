@@ -0,0 +1,640 @@
+//! API endpoints for checking a site's scheduler plan.
+//!
+//! The conflict check looks at every day of a window: the schedule that
+//! applies to it (or a candidate schedule, such as one just imported, as
+//! though it were applied as an override), and the specific-date overrides
+//! that match it, and reports contradictions before anything is activated.
+//! See [`crate::schedule_check`] for the command checks.
+//!
+//! The SoC simulation projects a battery's state of charge through a
+//! schedule, or commands not yet saved, from a given starting SoC, for the UI
+//! to chart (see [`crate::soc_simulation`]).
+//!
+//! The status shows the scheduler's last evaluation of the site's active
+//! command and its last catch-up on command starts missed while the service
+//! was down (see [`crate::scheduler_catch_up`]).
+//!
+//! The carbon profile averages the site's grid carbon intensity readings by
+//! hour of day and names the cleanest hours, for schedules that charge when
+//! the grid is clean (see [`crate::carbon`]).
+//!
+//! # Authorization Rules
+//! - newtown-admin and newtown-staff can check any site
+//! - Other users can check their own company's sites
+
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use neems_data::collectors::TestType;
+use rocket::{Route, http::Status, response::status, serde::json::Json};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{
+    carbon::{CarbonSample, HourlyIntensity, cleanest_hours, hourly_profile},
+    logged_json::LoggedJson,
+    models::{
+        CreateCommandRequest, OverridePriority, RuleType, ScheduleCommandDto, ScheduleLibraryItem,
+        SchedulerTick, Site,
+    },
+    orm::{
+        DbConn, SiteDbConn,
+        application_rule::get_all_matching_schedules,
+        schedule_library::{get_library_item, get_library_items_for_site},
+        scheduler_tick::get_scheduler_tick,
+        site::get_site_by_id,
+    },
+    schedule_check::{BatteryLimits, ConflictKind, ScheduleConflict, check_commands},
+    session_guards::AuthenticatedUser,
+    soc_simulation::{SimulationParams, SocSimulation, simulate_soc},
+};
+
+/// Most days one check can cover
+const MAX_CHECK_DAYS: i64 = 366;
+
+/// Round-trip efficiency assumed when a simulation doesn't give one
+const DEFAULT_ROUND_TRIP_EFFICIENCY_PERCENT: f64 = 90.0;
+
+/// Most hours one simulation can cover
+const MAX_SIMULATION_HOURS: u32 = 7 * 24;
+
+/// Days of readings a carbon profile averages unless `days` is given
+const DEFAULT_CARBON_DAYS: u32 = 7;
+
+/// Most days of readings a carbon profile can average
+const MAX_CARBON_DAYS: u32 = 90;
+
+/// Cleanest hours named unless `charge_hours` is given
+const DEFAULT_CHARGE_HOURS: usize = 4;
+
+/// Error response structure for scheduler API failures.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+type SchedulerError = status::Custom<Json<ErrorResponse>>;
+
+fn error(status: Status, error: impl Into<String>) -> SchedulerError {
+    status::Custom(status, Json(ErrorResponse { error: error.into() }))
+}
+
+fn database_error(action: &str, e: impl std::fmt::Debug) -> SchedulerError {
+    eprintln!("Error {}: {:?}", action, e);
+    error(Status::InternalServerError, "Internal server error")
+}
+
+/// Request to check a site's plan for a date range.
+#[derive(Debug, Deserialize, Serialize, TS)]
+#[ts(export)]
+pub struct SchedulerCheckRequest {
+    #[ts(type = "string")]
+    pub start_date: NaiveDate,
+    #[ts(type = "string")]
+    pub end_date: NaiveDate,
+    /// A schedule of the site's to check as though it were applied to every
+    /// day of the range; omitted to check the schedules already applied
+    pub library_item_id: Option<i32>,
+}
+
+/// The conflicts found in a site's plan, by date.
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SchedulerCheckResponse {
+    pub site_id: i32,
+    #[ts(type = "string")]
+    pub start_date: NaiveDate,
+    #[ts(type = "string")]
+    pub end_date: NaiveDate,
+    /// False when the site has no capacity or power set, so plans couldn't
+    /// be checked for SoC feasibility
+    pub soc_checked: bool,
+    pub conflicts: Vec<ScheduleConflict>,
+}
+
+/// Request to simulate a battery's SoC through a schedule. Give either a
+/// `library_item_id` or `commands`; the battery defaults to the site's.
+#[derive(Debug, Deserialize, Serialize, TS)]
+#[ts(export)]
+pub struct SocSimulationRequest {
+    /// SoC the battery starts at
+    pub initial_soc_percent: f64,
+    /// A schedule of the site's to simulate
+    pub library_item_id: Option<i32>,
+    /// Commands to simulate, e.g. a schedule not yet saved
+    pub commands: Option<Vec<CreateCommandRequest>>,
+    /// Simulate only this device's commands and the whole site's; omitted
+    /// for the whole site's alone
+    pub device_id: Option<i32>,
+    /// Defaults to the site's capacity
+    pub capacity_kwh: Option<f64>,
+    /// Defaults to 90
+    pub round_trip_efficiency_percent: Option<f64>,
+    /// Defaults to the site's rebound protection floor
+    pub min_soc_percent: Option<f64>,
+    /// Defaults to 100
+    pub max_soc_percent: Option<f64>,
+    /// Time of day to start at, in seconds after midnight; defaults to 0
+    pub start_offset_seconds: Option<i32>,
+    /// Hours to simulate; defaults to 24, at most a week
+    pub hours: Option<u32>,
+    /// Seconds between points on the curve; defaults to 300
+    pub step_seconds: Option<i32>,
+}
+
+/// A site's grid carbon intensity by hour of day.
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CarbonProfileResponse {
+    pub site_id: i32,
+    #[ts(type = "string")]
+    pub from: NaiveDateTime,
+    #[ts(type = "string")]
+    pub to: NaiveDateTime,
+    /// The most recent reading; absent when the site has none in the window
+    pub latest: Option<CarbonSample>,
+    /// Every hour of the day, UTC
+    pub hours: Vec<HourlyIntensity>,
+    /// The `charge_hours` hours with the lowest mean intensity, cleanest
+    /// first
+    pub cleanest_hours: Vec<u32>,
+}
+
+/// The scheduler's state for a site.
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SchedulerStatusResponse {
+    pub site_id: i32,
+    /// The last persisted tick; absent until the site's active command is
+    /// first evaluated
+    pub tick: Option<SchedulerTick>,
+}
+
+/// The site, if `user` can view its schedule; 404 or 403 otherwise.
+fn viewable_site(
+    conn: &mut diesel::SqliteConnection,
+    user: &AuthenticatedUser,
+    site_id: i32,
+) -> Result<Site, SchedulerError> {
+    let site = get_site_by_id(conn, site_id)
+        .map_err(|e| database_error("getting site", e))?
+        .ok_or_else(|| error(Status::NotFound, "Site not found"))?;
+
+    // newtown-admin and newtown-staff can view any schedule; other users
+    // their own company's
+    if user.has_any_role(&["newtown-admin", "newtown-staff"])
+        || site.company_id == user.user.company_id
+    {
+        Ok(site)
+    } else {
+        Err(error(Status::Forbidden, "Forbidden: insufficient permissions"))
+    }
+}
+
+/// The library item `item_id`, if it's one of `site_id`'s.
+fn site_library_item(
+    conn: &mut diesel::SqliteConnection,
+    site_id: i32,
+    item_id: i32,
+) -> Result<ScheduleLibraryItem, SchedulerError> {
+    match get_library_item(conn, item_id) {
+        Ok(item) if item.site_id == site_id => Ok(item),
+        Ok(_) => Err(error(Status::BadRequest, "The schedule belongs to another site")),
+        Err(diesel::result::Error::NotFound) => {
+            Err(error(Status::NotFound, "Library item not found"))
+        }
+        Err(e) => Err(database_error("getting library item", e)),
+    }
+}
+
+/// The conflicts in `site`'s plan for each day from `start_date` to
+/// `end_date`, with `candidate` in place of the applied schedules if given.
+fn plan_conflicts(
+    conn: &mut diesel::SqliteConnection,
+    site: &Site,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    candidate: Option<&ScheduleLibraryItem>,
+) -> Result<Vec<ScheduleConflict>, diesel::result::Error> {
+    let limits = BatteryLimits::from_site(site);
+    // Each schedule's commands are checked once, then reported on every day
+    // it applies
+    let items = get_library_items_for_site(conn, site.id)?;
+    let item_conflicts = |item_id: i32| {
+        let item = candidate.filter(|c| c.id == item_id).or(items.iter().find(|i| i.id == item_id));
+        item.map(|item| check_commands(&item.commands, limits.as_ref()))
+            .unwrap_or_default()
+    };
+    let mut cache = std::collections::HashMap::new();
+    let mut conflicts = Vec::new();
+
+    for date in start_date.iter_days().take_while(|d| *d <= end_date) {
+        let matches = match get_all_matching_schedules(conn, site.id, date) {
+            Ok(matches) => Some(matches),
+            Err(diesel::result::Error::NotFound) => None,
+            Err(e) => return Err(e),
+        };
+
+        // Overrides are the specific-date rules; the one in the highest
+        // priority layer, then the newest, wins, and a candidate would be
+        // applied as one more operator override
+        let mut overrides: Vec<(i32, String, OverridePriority)> = matches
+            .iter()
+            .flat_map(|m| std::iter::once(&m.winning_match).chain(&m.other_matches))
+            .filter(|m| m.rule_type == RuleType::SpecificDate)
+            .map(|m| (m.library_item_id, m.library_item_name.clone(), m.priority))
+            .collect();
+        if let Some(candidate) = candidate {
+            overrides.retain(|(id, _, _)| *id != candidate.id);
+            let at =
+                overrides.iter().take_while(|(_, _, p)| *p > OverridePriority::Operator).count();
+            overrides
+                .insert(at, (candidate.id, candidate.name.clone(), OverridePriority::Operator));
+        }
+        if overrides.len() > 1 {
+            let names: Vec<String> = overrides
+                .iter()
+                .map(|(_, name, priority)| match priority {
+                    OverridePriority::Operator => format!("'{}'", name),
+                    _ => format!("'{}' ({})", name, priority.as_str()),
+                })
+                .collect();
+            conflicts.push(ScheduleConflict {
+                date,
+                kind: ConflictKind::OverlappingOverrides,
+                library_item_ids: overrides.iter().map(|(id, _, _)| *id).collect(),
+                command_ids: Vec::new(),
+                message: format!(
+                    "{} overrides apply: {}; {} wins",
+                    overrides.len(),
+                    names.join(", "),
+                    names[0]
+                ),
+            });
+        }
+
+        // A candidate is checked on the days a higher layer doesn't hold
+        let item_id = match (candidate, &matches) {
+            (Some(_), _)
+                if overrides.first().is_some_and(|(_, _, p)| *p > OverridePriority::Operator) =>
+            {
+                overrides[0].0
+            }
+            (Some(candidate), _) => candidate.id,
+            (None, Some(matches)) => matches.winning_match.library_item_id,
+            (None, None) => continue,
+        };
+        let day_conflicts = cache.entry(item_id).or_insert_with(|| item_conflicts(item_id));
+        conflicts.extend(day_conflicts.iter().map(|c| c.on(date, item_id)));
+    }
+
+    Ok(conflicts)
+}
+
+/// Scheduler Check endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/scheduler/check`
+/// - **Method:** `POST`
+/// - **Purpose:** Reports contradictions in the site's plan for each day from
+///   `start_date` to `end_date` (at most a year): commands that charge and
+///   discharge at once, timed discharges the battery can't deliver above its
+///   SoC floor, and days more than one override applies to. With a
+///   `library_item_id`, that schedule is checked as though it were applied as
+///   an override to every day of the range. Nothing is changed.
+/// - **Authentication:** Required; the user must be able to view the site's
+///   schedules
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "start_date": "2026-07-20",
+///   "end_date": "2026-07-26",
+///   "library_item_id": 42
+/// }
+/// ```
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** the `conflicts`, each with its `date`, `kind`
+/// (`simultaneous_charge_discharge`, `soc_infeasible` or
+/// `overlapping_overrides`), the schedules and commands involved and a
+/// `message`; an empty list when the plan is consistent
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Bad dates, or the schedule isn't the site's
+/// - **403 Forbidden**: User can't view the site's schedules
+/// - **404 Not Found**: No such site or schedule
+#[post("/1/Sites/<site_id>/scheduler/check", data = "<request>")]
+pub async fn check_scheduler(
+    db: DbConn,
+    site_id: i32,
+    request: LoggedJson<SchedulerCheckRequest>,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<SchedulerCheckResponse>, SchedulerError> {
+    db.run(move |conn| {
+        let site = viewable_site(conn, &auth_user, site_id)?;
+
+        let req = request.into_inner();
+        if req.start_date > req.end_date {
+            return Err(error(Status::BadRequest, "start_date must be on or before end_date"));
+        }
+        if (req.end_date - req.start_date).num_days() >= MAX_CHECK_DAYS {
+            return Err(error(Status::BadRequest, "The date range is longer than a year"));
+        }
+
+        let candidate = match req.library_item_id {
+            Some(item_id) => Some(site_library_item(conn, site_id, item_id)?),
+            None => None,
+        };
+
+        let conflicts =
+            plan_conflicts(conn, &site, req.start_date, req.end_date, candidate.as_ref())
+                .map_err(|e| database_error("checking schedule conflicts", e))?;
+
+        Ok(Json(SchedulerCheckResponse {
+            site_id,
+            start_date: req.start_date,
+            end_date: req.end_date,
+            soc_checked: BatteryLimits::from_site(&site).is_some(),
+            conflicts,
+        }))
+    })
+    .await
+}
+
+/// The simulation's parameters from the request and the site's settings.
+fn simulation_params(
+    site: &Site,
+    req: &SocSimulationRequest,
+) -> Result<SimulationParams, SchedulerError> {
+    let bad_request = |message: &str| Err(error(Status::BadRequest, message));
+
+    let mut site = site.clone();
+    site.capacity_kwh = req.capacity_kwh.or(site.capacity_kwh);
+    let Some(mut limits) = BatteryLimits::from_site(&site) else {
+        return bad_request("The site has no capacity or power set");
+    };
+    if limits.capacity_kwh.is_nan() || limits.capacity_kwh <= 0.0 {
+        return bad_request("capacity_kwh must be positive");
+    }
+    limits.soc_floor_percent = req.min_soc_percent.unwrap_or(limits.soc_floor_percent);
+    let max_soc_percent = req.max_soc_percent.unwrap_or(100.0);
+    let efficiency = req
+        .round_trip_efficiency_percent
+        .unwrap_or(DEFAULT_ROUND_TRIP_EFFICIENCY_PERCENT);
+    let percents = [req.initial_soc_percent, limits.soc_floor_percent, max_soc_percent];
+    if percents.iter().any(|p| !(0.0..=100.0).contains(p))
+        || limits.soc_floor_percent > max_soc_percent
+    {
+        return bad_request("SoC percentages must be 0 to 100, with the minimum below the maximum");
+    }
+    if efficiency.is_nan() || efficiency <= 0.0 || efficiency > 100.0 {
+        return bad_request("round_trip_efficiency_percent must be above 0 and at most 100");
+    }
+
+    let hours = req.hours.unwrap_or(24);
+    let start_offset_seconds = req.start_offset_seconds.unwrap_or(0);
+    let step_seconds = req.step_seconds.unwrap_or(300);
+    if !(1..=MAX_SIMULATION_HOURS).contains(&hours) {
+        return bad_request("hours must be 1 to 168");
+    }
+    if !(0..24 * 3600).contains(&start_offset_seconds) {
+        return bad_request("start_offset_seconds must be within a day");
+    }
+    if !(60..=3600).contains(&step_seconds) {
+        return bad_request("step_seconds must be 60 to 3600");
+    }
+
+    Ok(SimulationParams {
+        limits,
+        max_soc_percent,
+        round_trip_efficiency: efficiency / 100.0,
+        initial_soc_percent: req.initial_soc_percent,
+        start_offset_seconds,
+        duration_seconds: hours as i32 * 3600,
+        step_seconds,
+    })
+}
+
+/// Simulate SoC endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/scheduler/simulate`
+/// - **Method:** `POST`
+/// - **Purpose:** Projects a battery's SoC through a schedule, repeated daily,
+///   from `initial_soc_percent`: the curve, a point every `step_seconds`, and
+///   where it would rise above the maximum, fall below the minimum or run
+///   empty. The battery's capacity, efficiency and limits default to the
+///   site's. Nothing is changed.
+/// - **Authentication:** Required; the user must be able to view the site's
+///   schedules
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "initial_soc_percent": 45,
+///   "library_item_id": 42,
+///   "device_id": 12,
+///   "capacity_kwh": 2000,
+///   "round_trip_efficiency_percent": 88,
+///   "hours": 48
+/// }
+/// ```
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** the curve's `points` (`offset_seconds` after
+/// the first day's midnight and `soc_percent`), the `violations`, each with
+/// its `kind` (`above_max`, `below_min` or `empty`) and the index of the
+/// command running, and the `final_soc_percent`
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Neither or both of `library_item_id` and `commands`,
+///   out-of-range values, or the site has no capacity or power
+/// - **403 Forbidden**: User can't view the site's schedules
+/// - **404 Not Found**: No such site or schedule
+#[post("/1/Sites/<site_id>/scheduler/simulate", data = "<request>")]
+pub async fn simulate_scheduler_soc(
+    db: DbConn,
+    site_id: i32,
+    request: LoggedJson<SocSimulationRequest>,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<SocSimulation>, SchedulerError> {
+    db.run(move |conn| {
+        let site = viewable_site(conn, &auth_user, site_id)?;
+        let req = request.into_inner();
+        let params = simulation_params(&site, &req)?;
+
+        let commands: Vec<ScheduleCommandDto> = match (req.library_item_id, req.commands) {
+            (Some(item_id), None) => site_library_item(conn, site_id, item_id)?.commands,
+            (None, Some(commands)) => commands
+                .into_iter()
+                .map(|cmd| ScheduleCommandDto {
+                    id: 0,
+                    execution_offset_seconds: cmd.execution_offset_seconds,
+                    command_type: cmd.command_type,
+                    duration_seconds: cmd.duration_seconds,
+                    target_soc_percent: cmd.target_soc_percent,
+                    power_kw: cmd.power_kw,
+                    device_id: cmd.device_id,
+                    bindings: cmd.bindings,
+                })
+                .collect(),
+            _ => {
+                return Err(error(Status::BadRequest, "Give either library_item_id or commands"));
+            }
+        };
+        // Violations refer to commands by their index in the request's or
+        // schedule's list
+        let (indices, commands): (Vec<usize>, Vec<ScheduleCommandDto>) = commands
+            .into_iter()
+            .enumerate()
+            .filter(|(_, c)| c.device_id.is_none() || c.device_id == req.device_id)
+            .unzip();
+        let mut simulation = simulate_soc(&commands, &params);
+        for violation in &mut simulation.violations {
+            violation.command_index = indices[violation.command_index];
+        }
+
+        Ok(Json(simulation))
+    })
+    .await
+}
+
+/// Carbon Profile endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/scheduler/carbon?days=<days>&
+///   charge_hours=<hours>`
+/// - **Method:** `GET`
+/// - **Purpose:** Averages the carbon intensity readings of the site's
+///   `carbon_intensity` sources over the last `days` (default 7, at most 90) by
+///   hour of day, UTC, and names the `charge_hours` (default 4) cleanest hours,
+///   to charge in when the grid is clean
+/// - **Authentication:** Required; the user must be able to view the site's
+///   schedules
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** the window (`from`, `to`), the `latest`
+/// reading, the 24 `hours` (`hour`, `mean_gco2_per_kwh` and the number of
+/// `samples`) and the `cleanest_hours`. Hours without readings have no mean
+/// and are never among the cleanest.
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: `days` or `charge_hours` out of range
+/// - **403 Forbidden**: User can't view the site's schedules
+/// - **404 Not Found**: No such site
+#[get("/1/Sites/<site_id>/scheduler/carbon?<days>&<charge_hours>")]
+pub async fn scheduler_carbon_profile(
+    db: DbConn,
+    site_db: SiteDbConn,
+    site_id: i32,
+    days: Option<u32>,
+    charge_hours: Option<usize>,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<CarbonProfileResponse>, SchedulerError> {
+    let days = days.unwrap_or(DEFAULT_CARBON_DAYS);
+    if !(1..=MAX_CARBON_DAYS).contains(&days) {
+        return Err(error(
+            Status::BadRequest,
+            format!("days must be between 1 and {}", MAX_CARBON_DAYS),
+        ));
+    }
+    let charge_hours = charge_hours.unwrap_or(DEFAULT_CHARGE_HOURS);
+    if charge_hours > 24 {
+        return Err(error(Status::BadRequest, "charge_hours must be at most 24"));
+    }
+    db.run(move |conn| viewable_site(conn, &auth_user, site_id)).await?;
+
+    let to = Utc::now().naive_utc();
+    let from = to - Duration::days(days as i64);
+    let readings = site_db
+        .run(move |conn| {
+            use diesel::prelude::*;
+            use neems_data::schema::{readings, sources};
+
+            let source_ids: Vec<i32> = sources::table
+                .filter(sources::site_id.eq(site_id))
+                .filter(sources::test_type.eq(TestType::CarbonIntensity.as_str()))
+                .select(sources::id.assume_not_null())
+                .load(conn)?;
+            readings::table
+                .filter(readings::source_id.eq_any(&source_ids))
+                .filter(readings::timestamp.ge(from))
+                .order(readings::timestamp.asc())
+                .select((readings::timestamp, readings::data))
+                .load::<(NaiveDateTime, String)>(conn)
+        })
+        .await
+        .map_err(|e| database_error("loading carbon intensity readings", e))?;
+
+    let samples: Vec<CarbonSample> = readings
+        .iter()
+        .filter_map(|(timestamp, data)| CarbonSample::from_reading(*timestamp, data))
+        .collect();
+    let hours = hourly_profile(&samples);
+    Ok(Json(CarbonProfileResponse {
+        site_id,
+        from,
+        to,
+        latest: samples.last().cloned(),
+        cleanest_hours: cleanest_hours(&hours, charge_hours),
+        hours,
+    }))
+}
+
+/// Scheduler Status endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/scheduler/status`
+/// - **Method:** `GET`
+/// - **Purpose:** Shows when the site's active command was last evaluated, and
+///   whether the scheduler caught up on command starts it missed while the
+///   service was down
+/// - **Authentication:** Required; the user must be able to view the site's
+///   schedules
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// {
+///   "site_id": 1,
+///   "tick": {
+///     "site_id": 1,
+///     "evaluated_at": "2026-10-17T14:00:02",
+///     "command_id": 12,
+///     "caught_up_at": "2026-10-17T14:00:02",
+///     "missed_commands": 2
+///   }
+/// }
+/// ```
+///
+/// Ticks are persisted when the command changes and at least every minute
+/// otherwise, so `evaluated_at` can be up to a minute behind the last poll.
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't view the site's schedules
+/// - **404 Not Found**: No such site
+#[get("/1/Sites/<site_id>/scheduler/status")]
+pub async fn scheduler_status(
+    db: DbConn,
+    site_id: i32,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<SchedulerStatusResponse>, SchedulerError> {
+    db.run(move |conn| {
+        viewable_site(conn, &auth_user, site_id)?;
+        let tick = get_scheduler_tick(conn, site_id)
+            .map_err(|e| database_error("getting scheduler tick", e))?;
+        Ok(Json(SchedulerStatusResponse { site_id, tick }))
+    })
+    .await
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        check_scheduler,
+        simulate_scheduler_soc,
+        scheduler_status,
+        scheduler_carbon_profile
+    ]
+}
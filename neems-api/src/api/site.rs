@@ -10,9 +10,9 @@
 //! - Regular users cannot perform CRUD operations
 
 use rocket::{
-    Route,
+    Route, State,
     http::Status,
-    response::{self, status},
+    response::{self, content::RawJson, status},
     serde::json::Json,
 };
 use serde::{Deserialize, Serialize};
@@ -20,6 +20,7 @@ use ts_rs::TS;
 
 use crate::{
     logged_json::LoggedJson,
+    merge_patch::Patch,
     models::Site,
     orm::{
         DbConn,
@@ -29,9 +30,15 @@ use crate::{
             get_sites_by_company, insert_site, update_site,
         },
     },
+    response_cache::{CachedEndpoint, ResponseCache},
     session_guards::AuthenticatedUser,
 };
 
+/// Cached responses that include sites, invalidated by site writes
+/// (`/api/1/Companies` can expand its sites)
+pub(crate) const SITE_RESPONSES: [CachedEndpoint; 2] =
+    [CachedEndpoint::Sites, CachedEndpoint::Companies];
+
 /// Error response structure for site API failures.
 #[derive(Serialize, TS)]
 #[ts(export)]
@@ -62,8 +69,8 @@ pub struct CreateSiteRequest {
 /// Doubles as the demo-defaults patch: power_kw, capacity_kwh, the off-peak
 /// and peak-revenue window bounds, interconnection cap, rebound-protection
 /// floor, closed-loop toggle, and site variant are all settable here. None
-/// means "leave alone" — there is no way to explicitly clear a nullable
-/// field through this endpoint.
+/// means "leave alone"; to clear a nullable field, send a [`SitePatch`]
+/// instead.
 #[derive(Deserialize, Serialize, TS)]
 #[ts(export)]
 pub struct UpdateSiteRequest {
@@ -88,8 +95,134 @@ pub struct UpdateSiteRequest {
     pub trickle_charge_power_kw: Option<f64>,
 }
 
+impl From<UpdateSiteRequest> for SiteUpdate {
+    fn from(request: UpdateSiteRequest) -> Self {
+        SiteUpdate {
+            name: request.name,
+            address: request.address,
+            latitude: request.latitude,
+            longitude: request.longitude,
+            company_id: request.company_id,
+            ramp_duration_seconds: request.ramp_duration_seconds,
+            power_kw: request.power_kw.map(Some),
+            capacity_kwh: request.capacity_kwh.map(Some),
+            closed_loop_enabled: request.closed_loop_enabled,
+            off_peak_start_minutes: request.off_peak_start_minutes.map(Some),
+            off_peak_end_minutes: request.off_peak_end_minutes.map(Some),
+            peak_revenue_start_minutes: request.peak_revenue_start_minutes.map(Some),
+            peak_revenue_end_minutes: request.peak_revenue_end_minutes.map(Some),
+            interconnection_max_output_kw: request.interconnection_max_output_kw.map(Some),
+            rebound_protection_soc_floor_percent: request.rebound_protection_soc_floor_percent,
+            site_variant: request.site_variant,
+            charge_rate_percent: request.charge_rate_percent,
+            discharge_rate_percent: request.discharge_rate_percent,
+            trickle_charge_power_kw: request.trickle_charge_power_kw.map(Some),
+        }
+    }
+}
+
+/// JSON Merge Patch for a site (see [`crate::merge_patch`]): leave a field
+/// out to keep it, send `null` to clear it. Only the nullable fields (the
+/// power, capacity and interconnection figures, the off-peak and
+/// peak-revenue window bounds, and the trickle charge power) can be cleared.
+#[derive(Default, Deserialize, Serialize, TS)]
+#[ts(export)]
+pub struct SitePatch {
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<String>", optional)]
+    pub name: Patch<String>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<String>", optional)]
+    pub address: Patch<String>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<f64>", optional)]
+    pub latitude: Patch<f64>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<f64>", optional)]
+    pub longitude: Patch<f64>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<i32>", optional)]
+    pub company_id: Patch<i32>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<i32>", optional)]
+    pub ramp_duration_seconds: Patch<i32>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<f64>", optional = nullable)]
+    pub power_kw: Patch<f64>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<f64>", optional = nullable)]
+    pub capacity_kwh: Patch<f64>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<bool>", optional)]
+    pub closed_loop_enabled: Patch<bool>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<i32>", optional = nullable)]
+    pub off_peak_start_minutes: Patch<i32>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<i32>", optional = nullable)]
+    pub off_peak_end_minutes: Patch<i32>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<i32>", optional = nullable)]
+    pub peak_revenue_start_minutes: Patch<i32>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<i32>", optional = nullable)]
+    pub peak_revenue_end_minutes: Patch<i32>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<f64>", optional = nullable)]
+    pub interconnection_max_output_kw: Patch<f64>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<f64>", optional)]
+    pub rebound_protection_soc_floor_percent: Patch<f64>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<String>", optional)]
+    pub site_variant: Patch<String>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<f64>", optional)]
+    pub charge_rate_percent: Patch<f64>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<f64>", optional)]
+    pub discharge_rate_percent: Patch<f64>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<f64>", optional = nullable)]
+    pub trickle_charge_power_kw: Patch<f64>,
+}
+
+impl SitePatch {
+    /// The update this patch makes, or an error if it clears a field that
+    /// can't be null
+    fn into_update(self) -> Result<SiteUpdate, String> {
+        Ok(SiteUpdate {
+            name: self.name.into_required("name")?,
+            address: self.address.into_required("address")?,
+            latitude: self.latitude.into_required("latitude")?,
+            longitude: self.longitude.into_required("longitude")?,
+            company_id: self.company_id.into_required("company_id")?,
+            ramp_duration_seconds: self
+                .ramp_duration_seconds
+                .into_required("ramp_duration_seconds")?,
+            power_kw: self.power_kw.into_nullable(),
+            capacity_kwh: self.capacity_kwh.into_nullable(),
+            closed_loop_enabled: self.closed_loop_enabled.into_required("closed_loop_enabled")?,
+            off_peak_start_minutes: self.off_peak_start_minutes.into_nullable(),
+            off_peak_end_minutes: self.off_peak_end_minutes.into_nullable(),
+            peak_revenue_start_minutes: self.peak_revenue_start_minutes.into_nullable(),
+            peak_revenue_end_minutes: self.peak_revenue_end_minutes.into_nullable(),
+            interconnection_max_output_kw: self.interconnection_max_output_kw.into_nullable(),
+            rebound_protection_soc_floor_percent: self
+                .rebound_protection_soc_floor_percent
+                .into_required("rebound_protection_soc_floor_percent")?,
+            site_variant: self.site_variant.into_required("site_variant")?,
+            charge_rate_percent: self.charge_rate_percent.into_required("charge_rate_percent")?,
+            discharge_rate_percent: self
+                .discharge_rate_percent
+                .into_required("discharge_rate_percent")?,
+            trickle_charge_power_kw: self.trickle_charge_power_kw.into_nullable(),
+        })
+    }
+}
+
 /// Helper function to check if user can perform CRUD operations on a site
-fn can_crud_site(user: &AuthenticatedUser, site_company_id: i32) -> bool {
+pub(crate) fn can_crud_site(user: &AuthenticatedUser, site_company_id: i32) -> bool {
     // newtown-admin and newtown-staff can CRUD any site
     if user.has_any_role(&["newtown-admin", "newtown-staff"]) {
         return true;
@@ -103,6 +236,10 @@ fn can_crud_site(user: &AuthenticatedUser, site_company_id: i32) -> bool {
     false
 }
 
+/// Why changes to an archived site are refused. Archived sites are read-only;
+/// see [`crate::site_archive`].
+pub(crate) const ARCHIVED_SITE: &str = "The site is archived and read-only";
+
 /// Create Site endpoint.
 ///
 /// - **URL:** `/api/1/sites`
@@ -142,6 +279,7 @@ fn can_crud_site(user: &AuthenticatedUser, site_company_id: i32) -> bool {
 #[post("/1/Sites", data = "<new_site>")]
 pub async fn create_site(
     db: DbConn,
+    cache: &State<ResponseCache>,
     new_site: LoggedJson<CreateSiteRequest>,
     auth_user: AuthenticatedUser,
 ) -> Result<status::Created<Json<Site>>, response::status::Custom<Json<ErrorResponse>>> {
@@ -233,6 +371,7 @@ pub async fn create_site(
         }
     })
     .await
+    .inspect(|_| cache.invalidate(&SITE_RESPONSES))
 }
 
 /// Get Site endpoint.
@@ -278,37 +417,44 @@ pub async fn get_site(
 /// - **Authorization:** Returns sites based on user's access level
 ///   - newtown-admin/newtown-staff: all sites
 ///   - Company admin: sites from their company only
+///
+/// Responses are cached; see [`crate::response_cache`].
 #[get("/1/Sites")]
 pub async fn list_sites(
     db: DbConn,
+    cache: &State<ResponseCache>,
     auth_user: AuthenticatedUser,
-) -> Result<Json<serde_json::Value>, Status> {
-    db.run(move |conn| {
-        let sites = if auth_user.has_any_role(&["newtown-admin", "newtown-staff"]) {
-            // Newtown roles can see all sites
-            match get_all_sites(conn) {
-                Ok(sites) => sites,
-                Err(_) => return Err(Status::InternalServerError),
-            }
-        } else if auth_user.has_role("admin") {
-            // Company admin can see sites from their company
-            match get_sites_by_company(conn, auth_user.user.company_id) {
-                Ok(sites) => sites,
-                Err(_) => return Err(Status::InternalServerError),
-            }
-        } else {
-            // Regular users cannot list sites
-            return Err(Status::Forbidden);
-        };
+) -> Result<RawJson<String>, Status> {
+    let company_id = if auth_user.has_any_role(&["newtown-admin", "newtown-staff"]) {
+        // Newtown roles can see all sites
+        None
+    } else if auth_user.has_role("admin") {
+        // Company admin can see sites from their company
+        Some(auth_user.user.company_id)
+    } else {
+        // Regular users cannot list sites
+        return Err(Status::Forbidden);
+    };
+
+    // The response only depends on which sites the caller can see
+    let key = company_id.map_or_else(|| "all".to_string(), |id| format!("company:{}", id));
+    let load = async move {
+        let sites = db
+            .run(move |conn| match company_id {
+                None => get_all_sites(conn),
+                Some(company_id) => get_sites_by_company(conn, company_id),
+            })
+            .await
+            .map_err(|_| Status::InternalServerError)?;
 
         let response = serde_json::json!({
             "@odata.context": "http://localhost/api/1/$metadata#Sites",
             "value": sites
         });
+        serde_json::to_string(&response).map_err(|_| Status::InternalServerError)
+    };
 
-        Ok(Json(response))
-    })
-    .await
+    cache.get_or_load(CachedEndpoint::Sites, key, load).await.map(RawJson)
 }
 
 /// Update Site endpoint.
@@ -334,9 +480,52 @@ pub async fn list_sites(
 #[put("/1/Sites/<site_id>", data = "<update_data>")]
 pub async fn update_site_endpoint(
     db: DbConn,
+    cache: &State<ResponseCache>,
     site_id: i32,
     update_data: LoggedJson<UpdateSiteRequest>,
     auth_user: AuthenticatedUser,
+) -> Result<Json<Site>, response::status::Custom<Json<ErrorResponse>>> {
+    apply_site_update(db, cache, site_id, update_data.into_inner().into(), auth_user).await
+}
+
+/// Patch Site endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>`
+/// - **Method:** `PATCH`
+/// - **Purpose:** Updates a site with a JSON Merge Patch (RFC 7396)
+/// - **Authentication:** Required
+/// - **Authorization:** As for `PUT`
+///
+/// Takes a [`SitePatch`]: fields left out are kept, fields set to `null` are
+/// cleared, and the rest are set, with the same checks as `PUT`.
+///
+/// ```json
+/// { "name": "Updated Office Name", "power_kw": null }
+/// ```
+///
+/// **Error Responses:** as for `PUT`, plus **422 Unprocessable Entity** if
+/// the patch sets a field that can't be null to `null`.
+#[patch("/1/Sites/<site_id>", data = "<patch>")]
+pub async fn patch_site_endpoint(
+    db: DbConn,
+    cache: &State<ResponseCache>,
+    site_id: i32,
+    patch: LoggedJson<SitePatch>,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<Site>, response::status::Custom<Json<ErrorResponse>>> {
+    let update = patch.into_inner().into_update().map_err(|error| {
+        response::status::Custom(Status::UnprocessableEntity, Json(ErrorResponse { error }))
+    })?;
+    apply_site_update(db, cache, site_id, update, auth_user).await
+}
+
+/// Checks and applies a site update for the `PUT` and `PATCH` endpoints.
+async fn apply_site_update(
+    db: DbConn,
+    cache: &State<ResponseCache>,
+    site_id: i32,
+    update_data: SiteUpdate,
+    auth_user: AuthenticatedUser,
 ) -> Result<Json<Site>, response::status::Custom<Json<ErrorResponse>>> {
     // Reject out-of-range numeric fields before touching the DB so the
     // caller gets a clear 400 instead of a quiet bad-data write. These
@@ -360,10 +549,13 @@ pub async fn update_site_endpoint(
     }
 
     for (field, value) in [
-        ("power_kw", update_data.power_kw),
-        ("capacity_kwh", update_data.capacity_kwh),
-        ("interconnection_max_output_kw", update_data.interconnection_max_output_kw),
-        ("trickle_charge_power_kw", update_data.trickle_charge_power_kw),
+        ("power_kw", update_data.power_kw.flatten()),
+        ("capacity_kwh", update_data.capacity_kwh.flatten()),
+        (
+            "interconnection_max_output_kw",
+            update_data.interconnection_max_output_kw.flatten(),
+        ),
+        ("trickle_charge_power_kw", update_data.trickle_charge_power_kw.flatten()),
     ] {
         if let Some(v) = value {
             if !v.is_finite() || v < 0.0 {
@@ -395,6 +587,10 @@ pub async fn update_site_endpoint(
                     });
                     return Err(response::status::Custom(Status::Forbidden, err));
                 }
+                if site.archived_at.is_some() {
+                    let err = Json(ErrorResponse { error: ARCHIVED_SITE.to_string() });
+                    return Err(response::status::Custom(Status::Conflict, err));
+                }
 
                 // If changing company, validate new company exists and check authorization
                 if let Some(new_company_id) = update_data.company_id {
@@ -430,28 +626,7 @@ pub async fn update_site_endpoint(
                 update_site(
                     conn,
                     site_id,
-                    SiteUpdate {
-                        name: update_data.name.clone(),
-                        address: update_data.address.clone(),
-                        latitude: update_data.latitude,
-                        longitude: update_data.longitude,
-                        company_id: update_data.company_id,
-                        ramp_duration_seconds: update_data.ramp_duration_seconds,
-                        power_kw: update_data.power_kw,
-                        capacity_kwh: update_data.capacity_kwh,
-                        closed_loop_enabled: update_data.closed_loop_enabled,
-                        off_peak_start_minutes: update_data.off_peak_start_minutes,
-                        off_peak_end_minutes: update_data.off_peak_end_minutes,
-                        peak_revenue_start_minutes: update_data.peak_revenue_start_minutes,
-                        peak_revenue_end_minutes: update_data.peak_revenue_end_minutes,
-                        interconnection_max_output_kw: update_data.interconnection_max_output_kw,
-                        rebound_protection_soc_floor_percent: update_data
-                            .rebound_protection_soc_floor_percent,
-                        site_variant: update_data.site_variant.clone(),
-                        charge_rate_percent: update_data.charge_rate_percent,
-                        discharge_rate_percent: update_data.discharge_rate_percent,
-                        trickle_charge_power_kw: update_data.trickle_charge_power_kw,
-                    },
+                    update_data,
                     Some(auth_user.user.id),
                 )
                 .map(Json)
@@ -478,6 +653,7 @@ pub async fn update_site_endpoint(
             }
         }
     }).await
+    .inspect(|_| cache.invalidate(&SITE_RESPONSES))
 }
 
 /// Delete Site endpoint.
@@ -491,6 +667,7 @@ pub async fn update_site_endpoint(
 #[delete("/1/Sites/<site_id>")]
 pub async fn delete_site_endpoint(
     db: DbConn,
+    cache: &State<ResponseCache>,
     site_id: i32,
     auth_user: AuthenticatedUser,
 ) -> Result<Status, Status> {
@@ -526,6 +703,7 @@ pub async fn delete_site_endpoint(
         }
     })
     .await
+    .inspect(|_| cache.invalidate(&SITE_RESPONSES))
 }
 
 /// Returns a vector of all routes defined in this module.
@@ -536,5 +714,12 @@ pub async fn delete_site_endpoint(
 /// # Returns
 /// A vector containing all route handlers for site endpoints
 pub fn routes() -> Vec<Route> {
-    routes![create_site, get_site, list_sites, update_site_endpoint, delete_site_endpoint]
+    routes![
+        create_site,
+        get_site,
+        list_sites,
+        update_site_endpoint,
+        patch_site_endpoint,
+        delete_site_endpoint
+    ]
 }
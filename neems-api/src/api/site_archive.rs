@@ -0,0 +1,244 @@
+//! API endpoints for decommissioning a site into an archive.
+//!
+//! Archiving exports the site's full history to a SQLite file in blob
+//! storage, marks the site archived (read-only) and deletes its readings from
+//! the site database (see [`crate::site_archive`]). There is no way back
+//! through the API: an archived site's history lives only in its archive.
+//!
+//! # Authorization Rules
+//! - newtown-admin and newtown-staff can archive any site
+//! - Company admins can archive their own company's sites
+//! - Anyone who can see a site can download its archive
+
+use std::path::PathBuf;
+
+use chrono::{NaiveDateTime, Utc};
+use neems_data::{
+    DataResult,
+    archive::{ArchivedTable, archive_site_history, purge_site_history},
+};
+use rocket::{Route, State, http::Status, response::status, serde::json::Json};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{
+    DbConn, SiteDbConn,
+    api::site::{SITE_RESPONSES, can_crud_site},
+    models::Site,
+    orm::site::{get_site_by_id, mark_site_archived},
+    response_cache::ResponseCache,
+    session_guards::AuthenticatedUser,
+    site_archive::{ARCHIVE_CONTENT_TYPE, archive_key, archive_site_records},
+    storage::{BlobLink, Storage},
+};
+
+/// Error response structure for site archive API failures.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+type ArchiveError = status::Custom<Json<ErrorResponse>>;
+
+fn error(status: Status, error: impl Into<String>) -> ArchiveError {
+    status::Custom(status, Json(ErrorResponse { error: error.into() }))
+}
+
+fn internal_error(action: &str, e: impl std::fmt::Display) -> ArchiveError {
+    eprintln!("Error {} site archive: {}", action, e);
+    error(Status::InternalServerError, format!("Error while {} site archive", action))
+}
+
+/// A site's archive, as made by [`archive_site`].
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SiteArchive {
+    pub site_id: i32,
+    #[ts(type = "string")]
+    pub archived_at: NaiveDateTime,
+    /// The tables in the archive, and their rows
+    pub tables: Vec<ArchivedTable>,
+    /// Readings removed from the live site database
+    pub readings_deleted: usize,
+    /// Bytes the site database returned to the filesystem
+    #[ts(type = "number")]
+    pub reclaimed_bytes: i64,
+    pub download: BlobLink,
+}
+
+/// The site, or 404; 403 unless `auth_user` may see it (or, with `manage`,
+/// manage it).
+async fn authorized_site(
+    db: &DbConn,
+    auth_user: &AuthenticatedUser,
+    site_id: i32,
+    manage: bool,
+) -> Result<Site, ArchiveError> {
+    let site = db
+        .run(move |conn| get_site_by_id(conn, site_id))
+        .await
+        .map_err(|e| internal_error("loading site for", e))?
+        .ok_or_else(|| error(Status::NotFound, "Site not found"))?;
+    let allowed = if manage {
+        can_crud_site(auth_user, site.company_id)
+    } else {
+        auth_user.has_any_role(&["newtown-admin", "newtown-staff"])
+            || site.company_id == auth_user.user.company_id
+    };
+    if !allowed {
+        return Err(error(Status::Forbidden, "Forbidden: insufficient permissions"));
+    }
+    Ok(site)
+}
+
+/// Write `site`'s archive to `path` and return its tables and contents.
+async fn write_archive(
+    db: &DbConn,
+    site_db: &SiteDbConn,
+    site: Site,
+    archived_at: NaiveDateTime,
+    archived_by: String,
+    path: PathBuf,
+) -> DataResult<(Vec<ArchivedTable>, Vec<u8>)> {
+    let site_id = site.id;
+    let records_path = path.clone();
+    let mut tables = db
+        .run(move |conn| {
+            archive_site_records(conn, &site, archived_at, &archived_by, &records_path)
+        })
+        .await?;
+    let history_path = path.clone();
+    tables.extend(
+        site_db
+            .run(move |conn| archive_site_history(conn, site_id, &history_path))
+            .await?,
+    );
+    Ok((tables, rocket::tokio::fs::read(&path).await?))
+}
+
+/// Archive Site endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/Archive`
+/// - **Method:** `POST`
+/// - **Purpose:** Decommissions a site: exports its full history to a SQLite
+///   archive in blob storage, marks it archived and read-only, and deletes its
+///   readings and power quality events from the site database
+/// - **Authentication:** Required; newtown-admin, newtown-staff, or an admin of
+///   the site's company
+///
+/// The archive holds the site's records (devices, device groups, schedules,
+/// application rules, tariff, KPIs, activity log) and history (sources,
+/// readings, power quality events, maintenance windows), each table under its
+/// live name, plus an `archive_info` table describing it. Afterwards changes
+/// to the site, its devices and its schedules are refused with 409 Conflict.
+///
+/// # Response
+///
+/// **Success (HTTP 201 Created):**
+/// ```json
+/// {
+///   "site_id": 3,
+///   "archived_at": "2026-10-18T09:30:00",
+///   "tables": [{ "table": "sites", "rows": 1 }, { "table": "readings", "rows": 483120 }],
+///   "readings_deleted": 483120,
+///   "reclaimed_bytes": 0,
+///   "download": {
+///     "key": "archives/site-3/site-3-20261018T093000Z.sqlite",
+///     "url": "/api/1/Blobs/archives/site-3/site-3-20261018T093000Z.sqlite?expires=...&signature=...",
+///     "expires_at": "2026-10-18T10:30:00"
+///   }
+/// }
+/// ```
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't manage the site
+/// - **404 Not Found**: No such site
+/// - **409 Conflict**: The site is already archived
+#[post("/1/Sites/<site_id>/Archive")]
+pub async fn archive_site(
+    db: DbConn,
+    site_db: SiteDbConn,
+    cache: &State<ResponseCache>,
+    storage: &State<Storage>,
+    site_id: i32,
+    auth_user: AuthenticatedUser,
+) -> Result<status::Created<Json<SiteArchive>>, ArchiveError> {
+    let site = authorized_site(&db, &auth_user, site_id, true).await?;
+    if site.archived_at.is_some() {
+        return Err(error(Status::Conflict, "The site is already archived"));
+    }
+
+    let archived_at = Utc::now().naive_utc();
+    let path =
+        std::env::temp_dir().join(format!("neems-site-archive-{}.sqlite", uuid::Uuid::new_v4()));
+    let written =
+        write_archive(&db, &site_db, site, archived_at, auth_user.user.email.clone(), path.clone())
+            .await;
+    let _ = rocket::tokio::fs::remove_file(&path).await;
+    let (tables, bytes) = written.map_err(|e| internal_error("writing", e))?;
+
+    // Only once the archive is safely stored is anything removed
+    let key = archive_key(site_id, archived_at);
+    storage
+        .store()
+        .put(&key, bytes, ARCHIVE_CONTENT_TYPE)
+        .await
+        .map_err(|e| internal_error("storing", e))?;
+    let (stored_key, user_id) = (key.clone(), auth_user.user.id);
+    db.run(move |conn| mark_site_archived(conn, site_id, archived_at, &stored_key, Some(user_id)))
+        .await
+        .map_err(|e| internal_error("recording", e))?;
+    cache.invalidate(&SITE_RESPONSES);
+    let purged = site_db
+        .run(move |conn| purge_site_history(conn, site_id))
+        .await
+        .map_err(|e| internal_error("clearing history after", e))?;
+
+    let download = storage.link(&key, Utc::now()).map_err(|e| internal_error("signing", e))?;
+    let archive = SiteArchive {
+        site_id,
+        archived_at,
+        tables,
+        readings_deleted: purged.readings_deleted,
+        reclaimed_bytes: purged.reclaimed_bytes,
+        download,
+    };
+    Ok(status::Created::new(format!("/api/1/Sites/{}/Archive", site_id)).body(Json(archive)))
+}
+
+/// Get Site Archive endpoint.
+///
+/// - **URL:** `/api/1/Sites/<site_id>/Archive`
+/// - **Method:** `GET`
+/// - **Purpose:** Returns a fresh download link for an archived site's archive
+/// - **Authentication:** Required; the user must be able to view the site
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** a [`BlobLink`]
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User can't view the site
+/// - **404 Not Found**: No such site, or it isn't archived
+#[get("/1/Sites/<site_id>/Archive")]
+pub async fn get_site_archive(
+    db: DbConn,
+    storage: &State<Storage>,
+    site_id: i32,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<BlobLink>, ArchiveError> {
+    let site = authorized_site(&db, &auth_user, site_id, false).await?;
+    let key = site
+        .archive_key
+        .ok_or_else(|| error(Status::NotFound, "The site isn't archived"))?;
+    storage
+        .link(&key, Utc::now())
+        .map(Json)
+        .map_err(|e| internal_error("signing", e))
+}
+
+/// Returns all site archive routes.
+pub fn routes() -> Vec<Route> {
+    routes![archive_site, get_site_archive]
+}
@@ -0,0 +1,448 @@
+//! API endpoints for configuring data sources.
+//!
+//! Sources live in the site database and are read by neems-data's
+//! collectors. Each source's `test_type` picks the collector, and its
+//! `arguments` are checked against that collector's argument schema (see
+//! `neems_data::collectors::arguments`) when the source is created or edited,
+//! exactly as `neems-data add`/`edit` do. The schemas are served by
+//! `GET /api/1/CollectorTypes` so the UI can render a form per type.
+//! `POST /api/1/Sources/<id>/test` runs a source's collector once, so its
+//! configuration can be checked before it's activated.
+//!
+//! # Authorization Rules
+//! - Any authenticated user can read the collector types
+//! - Only newtown-admin and newtown-staff can create, edit or test sources
+
+use std::collections::HashMap;
+
+use neems_data::{
+    NewSource, UpdateSource,
+    collectors::{self, SelfTestResult, TestType},
+    models::Source,
+    schema::sources,
+    secrets::SecretStore,
+};
+use rocket::{Route, http::Status, response::status, serde::json::Json};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use ts_rs::TS;
+
+use crate::{orm::neems_data::db::SiteDbConn, session_guards::AuthenticatedUser};
+
+/// Error response structure for source API failures.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+/// A collector type and the JSON Schema of its arguments.
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CollectorType {
+    pub test_type: String,
+    pub description: String,
+    #[ts(type = "Record<string, unknown>")]
+    pub schema: JsonValue,
+}
+
+/// Request payload for creating a source.
+///
+/// Argument values may be strings, numbers or booleans; they are stored as
+/// strings.
+#[derive(Deserialize, Serialize, TS)]
+#[ts(export)]
+pub struct CreateSourceRequest {
+    pub name: String,
+    pub description: Option<String>,
+    pub active: Option<bool>,
+    pub interval_seconds: Option<i32>,
+    pub test_type: String,
+    #[serde(default)]
+    #[ts(type = "Record<string, string | number | boolean>")]
+    pub arguments: HashMap<String, JsonValue>,
+    pub site_id: Option<i32>,
+    pub company_id: Option<i32>,
+    pub priority: Option<i32>,
+}
+
+/// Request payload for updating a source (all fields optional). `arguments`
+/// replaces the source's arguments as a whole.
+#[derive(Deserialize, Serialize, TS)]
+#[ts(export)]
+pub struct UpdateSourceRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub active: Option<bool>,
+    pub interval_seconds: Option<i32>,
+    pub test_type: Option<String>,
+    #[ts(type = "Record<string, string | number | boolean> | null")]
+    pub arguments: Option<HashMap<String, JsonValue>>,
+    pub site_id: Option<i32>,
+    pub company_id: Option<i32>,
+    pub priority: Option<i32>,
+}
+
+type SourceError = status::Custom<Json<ErrorResponse>>;
+
+fn error(status: Status, error: impl Into<String>) -> SourceError {
+    status::Custom(status, Json(ErrorResponse { error: error.into() }))
+}
+
+fn require_newtown_role(auth_user: &AuthenticatedUser) -> Result<(), SourceError> {
+    if auth_user.has_any_role(&["newtown-admin", "newtown-staff"]) {
+        Ok(())
+    } else {
+        Err(error(Status::Forbidden, "Only Newtown staff can configure data sources"))
+    }
+}
+
+fn parse_test_type(test_type: &str) -> Result<TestType, SourceError> {
+    test_type.parse().map_err(|e: String| error(Status::BadRequest, e))
+}
+
+/// Convert request arguments to the stored string map.
+fn string_arguments(
+    arguments: HashMap<String, JsonValue>,
+) -> Result<HashMap<String, String>, SourceError> {
+    arguments
+        .into_iter()
+        .map(|(key, value)| match value {
+            JsonValue::String(s) => Ok((key, s)),
+            JsonValue::Number(n) => Ok((key, n.to_string())),
+            JsonValue::Bool(b) => Ok((key, b.to_string())),
+            _ => Err(error(
+                Status::BadRequest,
+                format!("Argument '{}' must be a string, number or boolean", key),
+            )),
+        })
+        .collect()
+}
+
+fn validate_arguments(
+    test_type: &TestType,
+    arguments: &HashMap<String, String>,
+) -> Result<(), SourceError> {
+    test_type.validate_arguments(arguments).map_err(|e| {
+        error(
+            Status::BadRequest,
+            format!("Invalid arguments for {}: {}", test_type.as_str(), e),
+        )
+    })
+}
+
+fn name_taken(
+    conn: &mut diesel::SqliteConnection,
+    name: &str,
+    except_id: Option<i32>,
+) -> Result<bool, SourceError> {
+    use diesel::prelude::*;
+
+    let existing: Option<Option<i32>> = sources::table
+        .filter(sources::name.eq(name))
+        .select(sources::id)
+        .first(conn)
+        .optional()
+        .map_err(|e| {
+            eprintln!("Error checking source name: {:?}", e);
+            error(Status::InternalServerError, "Database error while checking source name")
+        })?;
+    Ok(existing.is_some_and(|id| id != except_id))
+}
+
+/// List Collector Types endpoint.
+///
+/// - **URL:** `/api/1/CollectorTypes`
+/// - **Method:** `GET`
+/// - **Purpose:** Returns every collector type with a JSON Schema (draft
+///   2020-12) describing its `arguments`
+/// - **Authentication:** Required
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// [
+///   {
+///     "test_type": "ping",
+///     "description": "Ping a host and report round-trip statistics",
+///     "schema": {
+///       "$schema": "https://json-schema.org/draft/2020-12/schema",
+///       "title": "ping",
+///       "description": "Ping a host and report round-trip statistics",
+///       "type": "object",
+///       "properties": {
+///         "target": {
+///           "type": "string",
+///           "description": "Host name or IP address to ping",
+///           "default": "127.0.0.1"
+///         }
+///       },
+///       "required": [],
+///       "additionalProperties": false
+///     }
+///   }
+/// ]
+/// ```
+#[get("/1/CollectorTypes")]
+pub fn list_collector_types(_auth_user: AuthenticatedUser) -> Json<Vec<CollectorType>> {
+    Json(
+        TestType::ALL
+            .iter()
+            .map(|test_type| CollectorType {
+                test_type: test_type.as_str().to_string(),
+                description: test_type.arguments().description.to_string(),
+                schema: test_type.argument_json_schema(),
+            })
+            .collect(),
+    )
+}
+
+/// Create Data Source endpoint.
+///
+/// - **URL:** `/api/1/DataSources`
+/// - **Method:** `POST`
+/// - **Purpose:** Creates a source for neems-data to poll
+/// - **Authentication:** Required
+/// - **Authorization:** newtown-admin or newtown-staff
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "name": "roof-weather",
+///   "test_type": "weather",
+///   "arguments": { "latitude": 38.9, "longitude": -77.0 },
+///   "interval_seconds": 300,
+///   "site_id": 5,
+///   "company_id": 1
+/// }
+/// ```
+///
+/// # Response
+///
+/// **Success (HTTP 201 Created):**
+/// Returns the created source, with credential-looking arguments masked
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Unknown test type, or arguments that don't match its
+///   schema (every problem is listed in `error`)
+/// - **403 Forbidden**: User lacks a Newtown role
+/// - **409 Conflict**: A source with this name already exists
+#[post("/1/DataSources", data = "<request>")]
+pub async fn create_data_source(
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    request: Json<CreateSourceRequest>,
+) -> Result<status::Created<Json<Source>>, SourceError> {
+    require_newtown_role(&auth_user)?;
+    let request = request.into_inner();
+    let test_type = parse_test_type(&request.test_type)?;
+    let arguments = string_arguments(request.arguments)?;
+    validate_arguments(&test_type, &arguments)?;
+
+    site_db
+        .run(move |conn| {
+            if name_taken(conn, &request.name, None)? {
+                return Err(error(
+                    Status::Conflict,
+                    format!("Source with name '{}' already exists", request.name),
+                ));
+            }
+
+            let new_source = NewSource {
+                name: request.name,
+                description: request.description,
+                active: request.active,
+                interval_seconds: request.interval_seconds,
+                test_type: Some(request.test_type),
+                arguments: Some(serde_json::to_string(&arguments).unwrap_or_default()),
+                site_id: request.site_id,
+                company_id: request.company_id,
+                priority: request.priority,
+                point_fields: None,
+            };
+            neems_data::create_source(conn, new_source)
+                .map(|source| {
+                    let location = format!("/api/1/DataSources/{}", source.id.unwrap_or_default());
+                    status::Created::new(location).body(Json(source.redacted()))
+                })
+                .map_err(|e| {
+                    eprintln!("Error creating source: {:?}", e);
+                    error(Status::InternalServerError, "Database error while creating source")
+                })
+        })
+        .await
+}
+
+/// Update Data Source endpoint.
+///
+/// - **URL:** `/api/1/DataSources/{id}`
+/// - **Method:** `PUT`
+/// - **Purpose:** Updates a source; omitted fields are left as they are
+/// - **Authentication:** Required
+/// - **Authorization:** newtown-admin or newtown-staff
+///
+/// When `test_type` or `arguments` change, the resulting arguments are
+/// checked against the resulting test type. Sources created before test
+/// types existed (`test_type` null) are only checked once given one.
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// Returns the updated source, with credential-looking arguments masked
+///
+/// **Error Responses:**
+/// - **400 Bad Request**: Unknown test type or invalid arguments
+/// - **403 Forbidden**: User lacks a Newtown role
+/// - **404 Not Found**: Source not found
+/// - **409 Conflict**: Another source already has the new name
+#[put("/1/DataSources/<source_id>", data = "<request>")]
+pub async fn update_data_source(
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    source_id: i32,
+    request: Json<UpdateSourceRequest>,
+) -> Result<Json<Source>, SourceError> {
+    require_newtown_role(&auth_user)?;
+    let request = request.into_inner();
+    let new_test_type = request.test_type.as_deref().map(parse_test_type).transpose()?;
+    let new_arguments = request.arguments.map(string_arguments).transpose()?;
+
+    site_db
+        .run(move |conn| {
+            use diesel::prelude::*;
+
+            let existing: Source = sources::table
+                .filter(sources::id.eq(source_id))
+                .select(Source::as_select())
+                .first(conn)
+                .optional()
+                .map_err(|e| {
+                    eprintln!("Error loading source: {:?}", e);
+                    error(Status::InternalServerError, "Database error while loading source")
+                })?
+                .ok_or_else(|| error(Status::NotFound, "Source not found"))?;
+
+            if new_test_type.is_some() || new_arguments.is_some() {
+                let test_type = match new_test_type {
+                    Some(test_type) => Some(test_type),
+                    None => existing.test_type.as_deref().map(parse_test_type).transpose()?,
+                };
+                if let Some(test_type) = test_type {
+                    let current_arguments;
+                    let arguments = match &new_arguments {
+                        Some(arguments) => arguments,
+                        None => {
+                            current_arguments = existing.get_arguments().unwrap_or_default();
+                            &current_arguments
+                        }
+                    };
+                    validate_arguments(&test_type, arguments)?;
+                }
+            }
+
+            if let Some(name) = &request.name
+                && name_taken(conn, name, existing.id)?
+            {
+                return Err(error(
+                    Status::Conflict,
+                    format!("Source with name '{}' already exists", name),
+                ));
+            }
+
+            let updates = UpdateSource {
+                name: request.name,
+                description: request.description.map(Some),
+                active: request.active,
+                interval_seconds: request.interval_seconds,
+                last_run: None,
+                test_type: request.test_type,
+                arguments: new_arguments.map(|a| serde_json::to_string(&a).unwrap_or_default()),
+                site_id: request.site_id.map(Some),
+                company_id: request.company_id.map(Some),
+                priority: request.priority,
+                point_fields: None,
+            };
+            neems_data::update_source(conn, source_id, updates)
+                .map(|source| Json(source.redacted()))
+                .map_err(|e| {
+                    eprintln!("Error updating source: {:?}", e);
+                    error(Status::InternalServerError, "Database error while updating source")
+                })
+        })
+        .await
+}
+
+/// Test Source endpoint.
+///
+/// - **URL:** `/api/1/Sources/{id}/test`
+/// - **Method:** `POST`
+/// - **Purpose:** Runs the source's collector once and returns what it
+///   produced, without storing a reading or updating `last_run`
+/// - **Authentication:** Required
+/// - **Authorization:** newtown-admin or newtown-staff
+///
+/// Inactive sources can be tested. The collector runs on the API server, so
+/// it must be able to reach the source the way neems-data does, and is given
+/// up on after 15 seconds. A collector that fails still answers 200, with
+/// `ok` false and the (redacted) error.
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// {
+///   "source_id": 3,
+///   "source_name": "roof-weather",
+///   "test_type": "weather",
+///   "ok": false,
+///   "data": null,
+///   "error": "weather request to https://api.weather.gov/points/38.9000,-77.0000 failed with HTTP 503 Service Unavailable",
+///   "duration_ms": 412
+/// }
+/// ```
+///
+/// **Error Responses:**
+/// - **403 Forbidden**: User lacks a Newtown role
+/// - **404 Not Found**: Source not found
+#[post("/1/Sources/<source_id>/test")]
+pub async fn test_source(
+    site_db: SiteDbConn,
+    auth_user: AuthenticatedUser,
+    source_id: i32,
+) -> Result<Json<SelfTestResult>, SourceError> {
+    require_newtown_role(&auth_user)?;
+
+    let (source, secrets) = site_db
+        .run(move |conn| {
+            use diesel::prelude::*;
+
+            let source: Source = sources::table
+                .filter(sources::id.eq(source_id))
+                .select(Source::as_select())
+                .first(conn)
+                .optional()
+                .map_err(|e| {
+                    eprintln!("Error loading source: {:?}", e);
+                    error(Status::InternalServerError, "Database error while loading source")
+                })?
+                .ok_or_else(|| error(Status::NotFound, "Source not found"))?;
+            let secrets =
+                SecretStore::for_sources(conn, std::slice::from_ref(&source)).map_err(|e| {
+                    eprintln!("Error loading secrets: {:?}", e);
+                    error(Status::InternalServerError, "Database error while loading secrets")
+                })?;
+            Ok((source, secrets))
+        })
+        .await?;
+
+    Ok(Json(
+        collectors::self_test(&source, &secrets, collectors::SELF_TEST_TIMEOUT).await,
+    ))
+}
+
+/// Returns a vector of all routes defined in this module.
+pub fn routes() -> Vec<Route> {
+    routes![list_collector_types, create_data_source, update_data_source, test_source]
+}
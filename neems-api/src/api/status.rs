@@ -3,10 +3,16 @@
 //! This module provides health check and status endpoints for monitoring
 //! the application's operational state and availability.
 
-use rocket::{Route, serde::json::Json};
+use rocket::{Route, State, http::Status, serde::json::Json};
 use serde::Serialize;
 use ts_rs::TS;
 
+use crate::{
+    api_version::{API_VERSIONS, ApiVersion, DEPRECATED_ROUTES, RouteDeprecation},
+    response_cache::{CacheStats, ResponseCache},
+    session_guards::AuthenticatedUser,
+};
+
 pub mod built_info {
     include!(concat!(env!("OUT_DIR"), "/built.rs"));
 }
@@ -55,6 +61,79 @@ pub fn health_status() -> Json<HealthStatus> {
     })
 }
 
+/// The API's versions and deprecated endpoints.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ApiVersions {
+    versions: &'static [ApiVersion],
+    deprecated_routes: &'static [RouteDeprecation],
+}
+
+/// API Versions endpoint.
+///
+/// - **URL:** `/api/1/Versions`
+/// - **Method:** `GET`
+/// - **Purpose:** Lists the API versions served and the endpoints deprecated in
+///   favour of ones in a later version (see [`crate::api_version`])
+/// - **Authentication:** None required
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// {
+///   "versions": [
+///     { "version": 1, "status": "current", "deprecated": null, "sunset": null },
+///     { "version": 2, "status": "preview", "deprecated": null, "sunset": null }
+///   ],
+///   "deprecated_routes": [
+///     {
+///       "method": "POST",
+///       "path": "/api/1/Users",
+///       "deprecated": "2026-10-17",
+///       "sunset": null,
+///       "successor": "/api/2/Users"
+///     }
+///   ]
+/// }
+/// ```
+#[rocket::get("/1/Versions")]
+pub fn api_versions() -> Json<ApiVersions> {
+    Json(ApiVersions {
+        versions: API_VERSIONS,
+        deprecated_routes: DEPRECATED_ROUTES,
+    })
+}
+
+/// Cache Stats endpoint.
+///
+/// - **URL:** `/api/1/CacheStats`
+/// - **Method:** `GET`
+/// - **Purpose:** Returns hit counts for the response cache (see
+///   [`crate::response_cache`]) since startup
+/// - **Authentication:** Required (newtown-admin or newtown-staff)
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// [
+///   { "endpoint": "Sites", "hits": 412, "misses": 9, "invalidations": 2, "entries": 3 },
+///   { "endpoint": "Companies", "hits": 130, "misses": 4, "invalidations": 1, "entries": 2 },
+///   { "endpoint": "$metadata", "hits": 7, "misses": 1, "invalidations": 0, "entries": 1 }
+/// ]
+/// ```
+#[rocket::get("/1/CacheStats")]
+pub fn cache_stats(
+    cache: &State<ResponseCache>,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<Vec<CacheStats>>, Status> {
+    if !auth_user.has_any_role(&["newtown-admin", "newtown-staff"]) {
+        return Err(Status::Forbidden);
+    }
+    Ok(Json(cache.stats()))
+}
+
 /// Returns a vector of all routes defined in this module.
 ///
 /// This function collects all the route handlers defined in this module
@@ -63,5 +142,5 @@ pub fn health_status() -> Json<HealthStatus> {
 /// # Returns
 /// A vector containing all route handlers for status endpoints
 pub fn routes() -> Vec<Route> {
-    routes![health_status]
+    routes![health_status, api_versions, cache_stats]
 }
@@ -0,0 +1,273 @@
+//! Store-and-forward sync endpoints.
+//!
+//! Edge neems-data instances upload the readings they collected to the central
+//! server through these endpoints whenever their uplink is available. The
+//! ingest logic lives in [`neems_data::sync`] alongside the uploader so both
+//! ends agree on the batch format; this module only adds routing and access
+//! control. The same goes for the integrity checks in
+//! [`neems_data::reconcile`].
+
+use chrono::NaiveDate;
+use neems_data::{
+    reconcile::{Discrepancy, ReconcileReport, ReconcileRequest, discrepancies, reconcile},
+    sync::{SyncAck, SyncBatch, SyncCursor, ingest_batch, origin_cursor},
+};
+use rocket::{Route, http::Status, serde::json::Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{orm::neems_data::db::SiteDbConn, session_guards::AuthenticatedUser};
+
+/// Roles allowed to upload readings on behalf of a site.
+const SYNC_ROLES: &[&str] = &["newtown-admin", "newtown-staff"];
+
+/// Upper bound on readings per batch, so one request can't hold the site
+/// database's write lock for long.
+const MAX_BATCH_READINGS: usize = 5000;
+
+/// Longest range one integrity check may cover.
+const MAX_RECONCILE_DAYS: i64 = 92;
+
+fn forbid_unless_sync_role(user: &AuthenticatedUser) -> Result<(), Status> {
+    if user.has_any_role(SYNC_ROLES) {
+        Ok(())
+    } else {
+        Err(Status::Forbidden)
+    }
+}
+
+/// Upload Readings endpoint.
+///
+/// - **URL:** `/api/1/Sync/Readings`
+/// - **Method:** `POST`
+/// - **Purpose:** Appends a batch of readings uploaded by an edge instance
+/// - **Authentication:** Required; `newtown-admin` or `newtown-staff`
+///
+/// Readings are stored under a source named `<origin>/<source_name>`, which
+/// is created (inactive) the first time it is seen. Each `(origin,
+/// origin_reading_id)` is appended at most once, so resending a batch is
+/// safe.
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "origin": "site-a-gateway",
+///   "readings": [
+///     {
+///       "origin_reading_id": 1041,
+///       "source_name": "charging_state",
+///       "test_type": "charging_state",
+///       "site_id": 1,
+///       "company_id": 1,
+///       "timestamp": "2024-01-01T12:00:00",
+///       "device_timestamp": null,
+///       "data": "{\"level\": 42.5}",
+///       "quality_flags": 0
+///     }
+///   ]
+/// }
+/// ```
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// { "origin": "site-a-gateway", "accepted": 1, "duplicates": 0, "cursor": 1041 }
+/// ```
+///
+/// **Error (HTTP 400 Bad Request):** Invalid origin
+/// **Error (HTTP 413 Payload Too Large):** More than 5000 readings
+#[post("/1/Sync/Readings", data = "<batch>")]
+pub async fn upload_readings(
+    user: AuthenticatedUser,
+    site_db: SiteDbConn,
+    batch: Json<SyncBatch>,
+) -> Result<Json<SyncAck>, Status> {
+    forbid_unless_sync_role(&user)?;
+
+    let batch = batch.into_inner();
+    if batch.origin.trim().is_empty() || batch.origin.contains('/') {
+        return Err(Status::BadRequest);
+    }
+    if batch.readings.len() > MAX_BATCH_READINGS {
+        return Err(Status::PayloadTooLarge);
+    }
+
+    site_db
+        .run(move |conn| {
+            ingest_batch(conn, &batch).map(Json).map_err(|e| {
+                eprintln!("Sync: failed to ingest batch from {}: {}", batch.origin, e);
+                Status::InternalServerError
+            })
+        })
+        .await
+}
+
+/// Sync Cursor endpoint.
+///
+/// - **URL:** `/api/1/Sync/Origins/<origin>/Cursor`
+/// - **Method:** `GET`
+/// - **Purpose:** Returns the highest origin reading id received from an edge
+///   instance, so it can resume uploading after that
+/// - **Authentication:** Required; `newtown-admin` or `newtown-staff`
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// { "origin": "site-a-gateway", "cursor": 1041 }
+/// ```
+#[get("/1/Sync/Origins/<origin>/Cursor")]
+pub async fn get_sync_cursor(
+    origin: String,
+    user: AuthenticatedUser,
+    site_db: SiteDbConn,
+) -> Result<Json<SyncCursor>, Status> {
+    forbid_unless_sync_role(&user)?;
+
+    site_db
+        .run(move |conn| match origin_cursor(conn, &origin) {
+            Ok(cursor) => Ok(Json(SyncCursor { origin, cursor })),
+            Err(e) => {
+                eprintln!("Sync: failed to load cursor for {}: {}", origin, e);
+                Err(Status::InternalServerError)
+            }
+        })
+        .await
+}
+
+/// Reconcile endpoint.
+///
+/// - **URL:** `/api/1/Sync/Reconcile`
+/// - **Method:** `POST`
+/// - **Purpose:** Compares an edge instance's per-source, per-day row counts
+///   and checksums with the readings received from it, and records the outcome
+///   for the discrepancy report
+/// - **Authentication:** Required; `newtown-admin` or `newtown-staff`
+///
+/// Source-days present on only one side are reported with a count of 0 and
+/// no checksum on the other.
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "origin": "site-a-gateway",
+///   "from": "2024-01-01",
+///   "to": "2024-01-07",
+///   "digests": [
+///     { "source_name": "charging_state", "day": "2024-01-01", "row_count": 1440, "checksum": "9f86d0..." }
+///   ]
+/// }
+/// ```
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// {
+///   "origin": "site-a-gateway",
+///   "from": "2024-01-01",
+///   "to": "2024-01-07",
+///   "checked": 7,
+///   "discrepancies": []
+/// }
+/// ```
+///
+/// **Error (HTTP 400 Bad Request):** Invalid origin or date range (at most 92
+/// days)
+#[post("/1/Sync/Reconcile", data = "<request>")]
+pub async fn reconcile_origin(
+    user: AuthenticatedUser,
+    site_db: SiteDbConn,
+    request: Json<ReconcileRequest>,
+) -> Result<Json<ReconcileReport>, Status> {
+    forbid_unless_sync_role(&user)?;
+
+    let request = request.into_inner();
+    if request.origin.trim().is_empty() || request.origin.contains('/') {
+        return Err(Status::BadRequest);
+    }
+    let days = (request.to - request.from).num_days();
+    if !(0..MAX_RECONCILE_DAYS).contains(&days) {
+        return Err(Status::BadRequest);
+    }
+
+    site_db
+        .run(move |conn| {
+            reconcile(conn, &request).map(Json).map_err(|e| {
+                eprintln!("Sync: failed to reconcile {}: {}", request.origin, e);
+                Status::InternalServerError
+            })
+        })
+        .await
+}
+
+/// Response of `GET /api/1/Sync/Discrepancies`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiscrepancyReport {
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+/// Discrepancy Report endpoint.
+///
+/// - **URL:** `/api/1/Sync/Discrepancies?origin=...&since=...`
+/// - **Method:** `GET`
+/// - **Purpose:** Lists source-days whose latest integrity check found the
+///   central copy differing from the edge, newest day first
+/// - **Authentication:** Required; `newtown-admin` or `newtown-staff`
+///
+/// # Query Parameters
+///
+/// - `origin` (optional): only this edge instance
+/// - `since` (optional): only days on or after this date (`YYYY-MM-DD`)
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// {
+///   "discrepancies": [
+///     {
+///       "origin": "site-a-gateway",
+///       "source_name": "charging_state",
+///       "day": "2024-01-03",
+///       "edge_count": 1440,
+///       "edge_checksum": "9f86d0...",
+///       "central_count": 1438,
+///       "central_checksum": "60303a...",
+///       "checked_at": "2024-01-08T00:00:12"
+///     }
+///   ]
+/// }
+/// ```
+///
+/// **Error (HTTP 400 Bad Request):** Invalid `since` date
+#[get("/1/Sync/Discrepancies?<origin>&<since>")]
+pub async fn get_discrepancies(
+    origin: Option<String>,
+    since: Option<String>,
+    user: AuthenticatedUser,
+    site_db: SiteDbConn,
+) -> Result<Json<DiscrepancyReport>, Status> {
+    forbid_unless_sync_role(&user)?;
+
+    let since = since
+        .map(|s| NaiveDate::parse_from_str(&s, "%Y-%m-%d"))
+        .transpose()
+        .map_err(|_| Status::BadRequest)?;
+
+    site_db
+        .run(move |conn| match discrepancies(conn, origin.as_deref(), since) {
+            Ok(discrepancies) => Ok(Json(DiscrepancyReport { discrepancies })),
+            Err(e) => {
+                eprintln!("Sync: failed to load discrepancies: {}", e);
+                Err(Status::InternalServerError)
+            }
+        })
+        .await
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![upload_readings, get_sync_cursor, reconcile_origin, get_discrepancies]
+}
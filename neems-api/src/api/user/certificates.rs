@@ -0,0 +1,352 @@
+//! Client certificate endpoints.
+//!
+//! Registered certificates authenticate as their user when the API
+//! terminates TLS with client certificate verification (see [`crate::tls`]).
+
+use diesel::Connection;
+use rocket::{http::Status, response::status, serde::json::Json};
+use ts_rs::TS;
+
+use super::ErrorResponse;
+use crate::{
+    allowlist,
+    models::{AllowedNetworks, ClientCertificate, NewClientCertificate, User},
+    orm::{
+        DbConn,
+        allowed_network::{NetworkOwner, get_allowed_networks, set_allowed_networks},
+        client_certificate::{
+            delete_client_certificate, get_user_client_certificate, get_user_client_certificates,
+            insert_client_certificate,
+        },
+        user::get_user,
+    },
+    session_guards::AuthenticatedUser,
+    tls,
+};
+
+/// Request structure for registering a client certificate (user_id comes from
+/// URL path). Give either the PEM certificate or its SHA-256 fingerprint.
+#[derive(serde::Deserialize, TS)]
+#[ts(export)]
+pub struct AddClientCertificateRequest {
+    pub certificate_pem: Option<String>,
+    pub fingerprint: Option<String>,
+    pub description: Option<String>,
+    /// Networks the certificate may be used from (see
+    /// [`crate::allowlist`]); unrestricted when absent or empty
+    pub allowed_networks: Option<Vec<String>>,
+}
+
+/// Whether `auth_user` may manage `target_user`'s certificates: newtown-admin
+/// for anyone, admin within their own company.
+fn can_manage(auth_user: &AuthenticatedUser, target_user: &User) -> bool {
+    auth_user.has_role("newtown-admin")
+        || (auth_user.has_role("admin") && auth_user.user.company_id == target_user.company_id)
+}
+
+async fn target_user(db: &DbConn, user_id: i32) -> Result<User, Status> {
+    db.run(move |conn| get_user(conn, user_id))
+        .await
+        .map_err(|e| {
+            eprintln!("Error getting target user: {:?}", e);
+            Status::InternalServerError
+        })?
+        .ok_or(Status::NotFound)
+}
+
+/// List Client Certificates endpoint.
+///
+/// - **URL:** `/api/1/Users/<user_id>/ClientCertificates`
+/// - **Method:** `GET`
+/// - **Purpose:** Lists the client certificates that authenticate as a user
+/// - **Authentication:** Required (users can view their own certificates;
+///   newtown-admin, newtown-staff and admin can view any user's)
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// [
+///   {
+///     "id": 1,
+///     "user_id": 5,
+///     "fingerprint": "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08",
+///     "subject": "CN=rtac-01",
+///     "description": "RTAC at site 3",
+///     "created_at": "2026-10-16T18:00:00"
+///   }
+/// ]
+/// ```
+#[get("/1/Users/<user_id>/ClientCertificates")]
+pub async fn list_client_certificates(
+    db: DbConn,
+    user_id: i32,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<Vec<ClientCertificate>>, Status> {
+    if auth_user.user.id != user_id
+        && !auth_user.has_any_role(&["newtown-admin", "newtown-staff", "admin"])
+    {
+        return Err(Status::Forbidden);
+    }
+
+    db.run(move |conn| {
+        get_user_client_certificates(conn, user_id).map(Json).map_err(|e| {
+            eprintln!("Error getting client certificates: {:?}", e);
+            Status::InternalServerError
+        })
+    })
+    .await
+}
+
+/// Add Client Certificate endpoint.
+///
+/// - **URL:** `/api/1/Users/<user_id>/ClientCertificates`
+/// - **Method:** `POST`
+/// - **Purpose:** Registers a client certificate that authenticates as the user
+/// - **Authentication:** Required (newtown-admin for any user, admin for users
+///   at the same company)
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "certificate_pem": "-----BEGIN CERTIFICATE-----\n...",
+///   "description": "RTAC at site 3",
+///   "allowed_networks": ["10.20.0.0/16"]
+/// }
+/// ```
+///
+/// Instead of `certificate_pem`, a `fingerprint` (SHA-256, hex with or
+/// without colons) may be given, e.g. from
+/// `openssl x509 -noout -fingerprint -sha256`.
+///
+/// # Response
+///
+/// **Success (HTTP 201 Created):** the registered certificate
+///
+/// **Failure (HTTP 400 Bad Request):** Invalid certificate or fingerprint
+///
+/// **Failure (HTTP 409 Conflict):** Certificate already registered
+#[post("/1/Users/<user_id>/ClientCertificates", data = "<request>")]
+pub async fn add_client_certificate(
+    db: DbConn,
+    user_id: i32,
+    request: Json<AddClientCertificateRequest>,
+    auth_user: AuthenticatedUser,
+) -> Result<status::Created<Json<ClientCertificate>>, status::Custom<Json<ErrorResponse>>> {
+    let error = |status, error: String| status::Custom(status, Json(ErrorResponse { error }));
+
+    let target_user = target_user(&db, user_id).await.map_err(|s| error(s, s.to_string()))?;
+    if !can_manage(&auth_user, &target_user) {
+        return Err(error(Status::Forbidden, "Forbidden".to_string()));
+    }
+
+    let request = request.into_inner();
+    let (fingerprint, subject) = match (&request.certificate_pem, &request.fingerprint) {
+        (Some(pem), None) => tls::parse_pem_certificate(pem)
+            .map(|(fingerprint, subject)| (fingerprint, Some(subject)))
+            .map_err(|e| error(Status::BadRequest, e))?,
+        (None, Some(fingerprint)) => tls::normalize_fingerprint(fingerprint)
+            .map(|fingerprint| (fingerprint, None))
+            .ok_or_else(|| {
+                error(Status::BadRequest, "Fingerprint must be 64 hex digits".to_string())
+            })?,
+        _ => {
+            return Err(error(
+                Status::BadRequest,
+                "Give exactly one of certificate_pem or fingerprint".to_string(),
+            ));
+        }
+    };
+
+    let allowed_networks =
+        allowlist::normalize_networks(request.allowed_networks.as_deref().unwrap_or_default())
+            .map_err(|e| error(Status::BadRequest, e))?;
+
+    let new_certificate = NewClientCertificate {
+        user_id,
+        fingerprint,
+        subject,
+        description: request.description,
+    };
+    let certificate = db
+        .run(move |conn| {
+            conn.transaction(|conn| {
+                let certificate = insert_client_certificate(conn, new_certificate)?;
+                set_allowed_networks(
+                    conn,
+                    NetworkOwner::ClientCertificate(certificate.id),
+                    &allowed_networks,
+                )?;
+                Ok(certificate)
+            })
+        })
+        .await
+        .map_err(|e| match e {
+            diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            ) => error(Status::Conflict, "Certificate is already registered".to_string()),
+            e => {
+                eprintln!("Error registering client certificate: {:?}", e);
+                error(Status::InternalServerError, "Database error".to_string())
+            }
+        })?;
+
+    let location = format!("/api/1/Users/{}/ClientCertificates/{}", user_id, certificate.id);
+    Ok(status::Created::new(location).body(Json(certificate)))
+}
+
+/// Delete Client Certificate endpoint.
+///
+/// - **URL:** `/api/1/Users/<user_id>/ClientCertificates/<certificate_id>`
+/// - **Method:** `DELETE`
+/// - **Purpose:** Revokes a client certificate; it no longer authenticates
+/// - **Authentication:** Required (newtown-admin for any user, admin for users
+///   at the same company)
+///
+/// # Response
+///
+/// **Success (HTTP 204 No Content)**
+///
+/// **Failure (HTTP 404 Not Found):** No such certificate for this user
+#[delete("/1/Users/<user_id>/ClientCertificates/<certificate_id>")]
+pub async fn delete_client_certificate_endpoint(
+    db: DbConn,
+    user_id: i32,
+    certificate_id: i32,
+    auth_user: AuthenticatedUser,
+) -> Result<Status, Status> {
+    let target_user = target_user(&db, user_id).await?;
+    if !can_manage(&auth_user, &target_user) {
+        return Err(Status::Forbidden);
+    }
+
+    let deleted = db
+        .run(move |conn| delete_client_certificate(conn, user_id, certificate_id))
+        .await
+        .map_err(|e| {
+            eprintln!("Error deleting client certificate: {:?}", e);
+            Status::InternalServerError
+        })?;
+
+    if deleted {
+        Ok(Status::NoContent)
+    } else {
+        Err(Status::NotFound)
+    }
+}
+
+/// Get Client Certificate Allowed Networks endpoint.
+///
+/// - **URL:** `/api/1/Users/<user_id>/ClientCertificates/<certificate_id>/
+///   AllowedNetworks`
+/// - **Method:** `GET`
+/// - **Purpose:** Retrieves the networks a client certificate may be used from
+/// - **Authentication:** Required (same rules as listing certificates)
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// {
+///   "networks": ["10.20.0.0/16"]
+/// }
+/// ```
+#[get("/1/Users/<user_id>/ClientCertificates/<certificate_id>/AllowedNetworks")]
+pub async fn get_client_certificate_allowed_networks(
+    db: DbConn,
+    user_id: i32,
+    certificate_id: i32,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<AllowedNetworks>, Status> {
+    if auth_user.user.id != user_id
+        && !auth_user.has_any_role(&["newtown-admin", "newtown-staff", "admin"])
+    {
+        return Err(Status::Forbidden);
+    }
+
+    db.run(move |conn| {
+        let result =
+            get_user_client_certificate(conn, user_id, certificate_id).and_then(|certificate| {
+                match certificate {
+                    Some(_) => {
+                        get_allowed_networks(conn, NetworkOwner::ClientCertificate(certificate_id))
+                            .map(Some)
+                    }
+                    None => Ok(None),
+                }
+            });
+        match result {
+            Ok(Some(networks)) => Ok(Json(AllowedNetworks { networks })),
+            Ok(None) => Err(Status::NotFound),
+            Err(e) => {
+                eprintln!("Error getting allowed networks: {:?}", e);
+                Err(Status::InternalServerError)
+            }
+        }
+    })
+    .await
+}
+
+/// Set Client Certificate Allowed Networks endpoint.
+///
+/// - **URL:** `/api/1/Users/<user_id>/ClientCertificates/<certificate_id>/
+///   AllowedNetworks`
+/// - **Method:** `PUT`
+/// - **Purpose:** Replaces the networks a client certificate may be used from
+/// - **Authentication:** Required (same rules as adding certificates)
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "networks": ["10.20.0.0/16"]
+/// }
+/// ```
+///
+/// An empty list removes the restriction.
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** the stored list, normalized to CIDR form
+///
+/// **Failure (HTTP 400 Bad Request):** A network didn't parse
+#[put(
+    "/1/Users/<user_id>/ClientCertificates/<certificate_id>/AllowedNetworks",
+    data = "<request>"
+)]
+pub async fn set_client_certificate_allowed_networks(
+    db: DbConn,
+    user_id: i32,
+    certificate_id: i32,
+    request: Json<AllowedNetworks>,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<AllowedNetworks>, status::Custom<Json<ErrorResponse>>> {
+    let error = |status, error: String| status::Custom(status, Json(ErrorResponse { error }));
+
+    let target_user = target_user(&db, user_id).await.map_err(|s| error(s, s.to_string()))?;
+    if !can_manage(&auth_user, &target_user) {
+        return Err(error(Status::Forbidden, "Forbidden".to_string()));
+    }
+    let networks = allowlist::normalize_networks(&request.networks)
+        .map_err(|e| error(Status::BadRequest, e))?;
+
+    db.run(move |conn| {
+        match get_user_client_certificate(conn, user_id, certificate_id) {
+            Ok(Some(_)) => {}
+            Ok(None) => return Err(error(Status::NotFound, "Certificate not found".to_string())),
+            Err(e) => {
+                eprintln!("Error getting client certificate: {:?}", e);
+                return Err(error(Status::InternalServerError, "Database error".to_string()));
+            }
+        }
+        set_allowed_networks(conn, NetworkOwner::ClientCertificate(certificate_id), &networks)
+            .map(|_| Json(AllowedNetworks { networks }))
+            .map_err(|e| {
+                eprintln!("Error setting allowed networks: {:?}", e);
+                error(Status::InternalServerError, "Database error".to_string())
+            })
+    })
+    .await
+}
@@ -1,12 +1,15 @@
 //! List users endpoint with OData filtering, sort, and expand.
 
-use rocket::{http::Status, serde::json::Json};
+use rocket::{
+    http::{Status, uri::Origin},
+    serde::json::Json,
+};
 
 use crate::{
     models::UserWithRoles,
     odata_query::{
         ODataCollectionResponse, ODataField, ODataQuery, apply_query, apply_select,
-        build_context_url,
+        build_context_url, next_link,
     },
     orm::{
         DbConn,
@@ -61,6 +64,7 @@ use crate::{
 #[get("/1/Users?<query..>")]
 pub async fn list_users(
     db: DbConn,
+    uri: &Origin<'_>,
     auth_user: AuthenticatedUser,
     query: ODataQuery,
 ) -> Result<Json<serde_json::Value>, Status> {
@@ -197,6 +201,9 @@ pub async fn list_users(
     if query.count.unwrap_or(false) {
         response = response.with_count(total_count);
     }
+    if let Some(link) = next_link(uri, &query, total_count) {
+        response = response.with_next_link(link);
+    }
 
     Ok(Json(serde_json::to_value(response).map_err(|_| Status::InternalServerError)?))
 }
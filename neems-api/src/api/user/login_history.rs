@@ -0,0 +1,71 @@
+//! Login history endpoint.
+//!
+//! Serves a user's slice of the login audit log (see
+//! [`crate::orm::login_event`]).
+
+use rocket::{http::Status, serde::json::Json};
+
+use crate::{
+    models::LoginEvent,
+    orm::{DbConn, login_event::get_user_login_events, user::get_user},
+    session_guards::AuthenticatedUser,
+};
+
+/// Get User Login History endpoint.
+///
+/// - **URL:** `/api/1/Users/<user_id>/LoginHistory?<limit>`
+/// - **Method:** `GET`
+/// - **Purpose:** Lists a user's login attempts, successful or not, newest
+///   first
+/// - **Authentication:** Required (users can view their own history;
+///   newtown-admin and newtown-staff can view anyone's, admin their own
+///   company's users')
+///
+/// Default limit is 50; max is 500.
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// [
+///   {
+///     "id": 812,
+///     "user_id": 5,
+///     "email": "user@example.com",
+///     "success": false,
+///     "ip_address": "10.20.1.5",
+///     "user_agent": "Mozilla/5.0 ...",
+///     "country": null,
+///     "anomaly": null,
+///     "created_at": "2026-10-16T20:00:00"
+///   }
+/// ]
+/// ```
+#[get("/1/Users/<user_id>/LoginHistory?<limit>")]
+pub async fn get_user_login_history(
+    db: DbConn,
+    user_id: i32,
+    limit: Option<i64>,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<Vec<LoginEvent>>, Status> {
+    let limit = limit.unwrap_or(50).clamp(1, 500);
+
+    db.run(move |conn| {
+        let target_user = get_user(conn, user_id)
+            .map_err(|_| Status::InternalServerError)?
+            .ok_or(Status::NotFound)?;
+
+        let can_view = auth_user.user.id == user_id
+            || auth_user.has_any_role(&["newtown-admin", "newtown-staff"])
+            || (auth_user.has_role("admin") && auth_user.user.company_id == target_user.company_id);
+        if !can_view {
+            return Err(Status::Forbidden);
+        }
+
+        get_user_login_events(conn, user_id, limit).map(Json).map_err(|e| {
+            eprintln!("Error getting login history: {:?}", e);
+            Status::InternalServerError
+        })
+    })
+    .await
+}
@@ -4,9 +4,15 @@
 //! along with utility functions for generating test data and helper functions
 //! for API testing.
 
+pub mod certificates;
 pub mod list;
+pub mod login_history;
+pub mod password;
 pub mod roles;
+pub mod service_accounts;
 
+pub use certificates::AddClientCertificateRequest;
+pub use password::ChangePasswordRequest;
 use rand::{prelude::IndexedRandom, rng};
 use rocket::{
     Route,
@@ -19,10 +25,12 @@ use rocket::{
     },
 };
 pub use roles::{AddUserRoleRequest, RemoveUserRoleRequest};
+pub use service_accounts::{ApiKeySecret, CreateApiKeyRequest, CreateServiceAccountRequest};
 use ts_rs::TS;
 
 use crate::{
     logged_json::LoggedJson,
+    merge_patch::Patch,
     models::{CompanyInput, UserInput, UserWithRoles},
     orm::{
         DbConn,
@@ -30,7 +38,7 @@ use crate::{
         role::get_role_by_name,
         user::{
             delete_user_with_cleanup, get_user, get_user_by_email, get_user_with_roles,
-            insert_user, update_user,
+            insert_user, set_must_change_password, update_user,
         },
         user_role::assign_user_role_by_name,
     },
@@ -363,6 +371,9 @@ pub async fn create_user_with_roles_by_api(
 /// the specified roles in a single operation. At least one role must be
 /// provided.
 ///
+/// Deprecated: `POST /api/2/Users` (see [`crate::api::v2::user`]) takes the
+/// password itself rather than its hash, and doesn't return the hash.
+///
 /// # Request Format
 ///
 /// ```json
@@ -442,15 +453,111 @@ pub async fn create_user(
     new_user: LoggedJson<CreateUserWithRolesRequest>,
     auth_user: AuthenticatedUser,
 ) -> Result<status::Created<Json<UserWithRoles>>, response::status::Custom<Json<ErrorResponse>>> {
-    // Check authorization: can create users for target company?
-    let target_company_id = new_user.company_id;
+    check_can_create(&auth_user, new_user.company_id, &new_user.role_names)?;
+
+    db.run(move |conn| {
+        insert_user_with_roles(conn, &auth_user, new_user.into_inner())
+            .map(|user| status::Created::new("/").body(Json(user)))
+    })
+    .await
+}
+
+/// Creates a user and assigns its roles, once [`check_can_create`] has
+/// passed; shared by every version of the Create User endpoint.
+pub(crate) fn insert_user_with_roles(
+    conn: &mut diesel::SqliteConnection,
+    auth_user: &AuthenticatedUser,
+    user_request: CreateUserWithRolesRequest,
+) -> Result<UserWithRoles, response::status::Custom<Json<ErrorResponse>>> {
+    // FIRST: Validate all roles exist and user can assign them
+    check_assignable_roles(conn, auth_user, user_request.company_id, &user_request.role_names)?;
+
+    // SECOND: Check if user with this email already exists
+    match get_user_by_email(conn, &user_request.email) {
+        Ok(Some(_existing_user)) => {
+            // User with this email already exists
+            let err = Json(ErrorResponse {
+                error: "User with this email already exists".to_string(),
+            });
+            return Err(response::status::Custom(Status::Conflict, err));
+        }
+        Ok(None) => {
+            // User doesn't exist, we can proceed
+        }
+        Err(e) => {
+            eprintln!("Error checking for existing user: {:?}", e);
+            let err = Json(ErrorResponse {
+                error: "Database error while checking for existing user".to_string(),
+            });
+            return Err(response::status::Custom(Status::InternalServerError, err));
+        }
+    }
+
+    // THIRD: Create the user (now that all roles are validated and email is unique)
+    let user_no_time = UserInput {
+        email: user_request.email,
+        password_hash: user_request.password_hash,
+        company_id: user_request.company_id,
+        totp_secret: user_request.totp_secret,
+    };
+
+    let created_user = match insert_user(conn, user_no_time, Some(auth_user.user.id)) {
+        Ok(user) => user,
+        Err(e) => {
+            eprintln!("Error creating user: {:?}", e);
+            let err = Json(ErrorResponse {
+                error: "Database error while creating user".to_string(),
+            });
+            return Err(response::status::Custom(Status::InternalServerError, err));
+        }
+    };
+
+    // FOURTH: Assign roles to the user (roles already validated above)
+    for role_name in &user_request.role_names {
+        // Assign the role (we already validated everything above)
+        if let Err(e) = assign_user_role_by_name(conn, created_user.id, role_name) {
+            eprintln!("Error assigning role {}: {:?}", role_name, e);
+            let err = Json(ErrorResponse {
+                error: format!("Failed to assign role '{}' to user", role_name),
+            });
+            return Err(response::status::Custom(Status::InternalServerError, err));
+        }
+    }
 
+    // Get the user with roles after creation and role assignment
+    match get_user_with_roles(conn, created_user.id) {
+        Ok(Some(user_with_roles)) => Ok(user_with_roles),
+        Ok(None) => {
+            let err = Json(ErrorResponse {
+                error: "User created but not found when retrieving with roles".to_string(),
+            });
+            Err(response::status::Custom(Status::InternalServerError, err))
+        }
+        Err(e) => {
+            eprintln!("Error getting created user with roles: {:?}", e);
+            let err = Json(ErrorResponse {
+                error: "User created but failed to retrieve with roles".to_string(),
+            });
+            Err(response::status::Custom(Status::InternalServerError, err))
+        }
+    }
+}
+
+/// Checks that `auth_user` may create users at `company_id` (newtown-admin and
+/// newtown-staff anywhere, admin in their own company), and that at least one
+/// role is given.
+pub(crate) fn check_can_create(
+    auth_user: &AuthenticatedUser,
+    company_id: i32,
+    role_names: &[String],
+) -> Result<(), response::status::Custom<Json<ErrorResponse>>> {
+    // Check authorization: can create users for target company?
     let can_create = if auth_user.has_any_role(&["newtown-admin", "newtown-staff"]) {
         // newtown-admin and newtown-staff can create users for any company
         true
     } else if auth_user.has_role("admin") {
         // admin can only create users for their own company
-        auth_user.user.company_id == target_company_id
+        auth_user.user.company_id == company_id
     } else {
         false
     };
@@ -463,163 +570,97 @@ pub async fn create_user(
     }
 
     // Validate that at least one role is provided
-    if new_user.role_names.is_empty() {
+    if role_names.is_empty() {
         let err = Json(ErrorResponse {
             error: "At least one role must be provided".to_string(),
         });
         return Err(response::status::Custom(Status::BadRequest, err));
     }
 
-    db.run(move |conn| {
-        let user_request = new_user.into_inner();
-
-        // FIRST: Validate all roles exist and user can assign them
-        for role_name in &user_request.role_names {
-            // Check if role exists
-            match get_role_by_name(conn, role_name) {
-                Ok(Some(_role)) => {
-                    // Role exists, continue with authorization check
-                }
-                Ok(None) => {
-                    let err = Json(ErrorResponse {
-                        error: format!("Role '{}' does not exist", role_name),
-                    });
-                    return Err(response::status::Custom(Status::BadRequest, err));
-                }
-                Err(e) => {
-                    eprintln!("Error checking role existence: {:?}", e);
-                    let err = Json(ErrorResponse {
-                        error: "Database error while validating roles".to_string(),
-                    });
-                    return Err(response::status::Custom(Status::InternalServerError, err));
-                }
-            }
-
-            // Check if user can assign this role (same logic as add_user_role)
-            let can_assign = if auth_user.has_role("newtown-admin") {
-                // newtown-admin can assign any role
-                true
-            } else if auth_user.has_role("newtown-staff") {
-                // newtown-staff can assign any role except newtown-admin
-                role_name != "newtown-admin"
-            } else if auth_user.has_role("admin") {
-                // admin can assign any role to users in same company
-                auth_user.user.company_id == user_request.company_id
-            } else {
-                false
-            };
-
-            if !can_assign {
-                let err = Json(ErrorResponse {
-                    error: format!("Insufficient permissions to assign role '{}'", role_name),
-                });
-                return Err(response::status::Custom(Status::Forbidden, err));
-            }
-
-            // Check if role is newtown-staff or newtown-admin (company restriction)
-            if role_name == "newtown-staff" || role_name == "newtown-admin" {
-                let newtown_company_search = CompanyInput { name: "Newtown Energy".to_string() };
-                let newtown_company = match get_company_by_name(conn, &newtown_company_search) {
-                    Ok(Some(company)) => company,
-                    Ok(None) => {
-                        eprintln!("Newtown Energy company not found");
-                        let err = Json(ErrorResponse {
-                            error: "Newtown Energy company not found".to_string(),
-                        });
-                        return Err(response::status::Custom(Status::InternalServerError, err));
-                    }
-                    Err(e) => {
-                        eprintln!("Error getting Newtown Energy company: {:?}", e);
-                        let err = Json(ErrorResponse {
-                            error: "Database error while validating company".to_string(),
-                        });
-                        return Err(response::status::Custom(Status::InternalServerError, err));
-                    }
-                };
+    Ok(())
+}
 
-                if user_request.company_id != newtown_company.id {
-                    let err = Json(ErrorResponse {
-                        error: format!(
-                            "Role '{}' is restricted to Newtown Energy company",
-                            role_name
-                        ),
-                    });
-                    return Err(response::status::Custom(Status::Forbidden, err));
-                }
+/// Checks that every role in `role_names` exists and `auth_user` may assign it
+/// to a user at `company_id`.
+fn check_assignable_roles(
+    conn: &mut diesel::SqliteConnection,
+    auth_user: &AuthenticatedUser,
+    company_id: i32,
+    role_names: &[String],
+) -> Result<(), response::status::Custom<Json<ErrorResponse>>> {
+    for role_name in role_names {
+        // Check if role exists
+        match get_role_by_name(conn, role_name) {
+            Ok(Some(_role)) => {
+                // Role exists, continue with authorization check
             }
-        }
-
-        // SECOND: Check if user with this email already exists
-        match get_user_by_email(conn, &user_request.email) {
-            Ok(Some(_existing_user)) => {
-                // User with this email already exists
+            Ok(None) => {
                 let err = Json(ErrorResponse {
-                    error: "User with this email already exists".to_string(),
+                    error: format!("Role '{}' does not exist", role_name),
                 });
-                return Err(response::status::Custom(Status::Conflict, err));
-            }
-            Ok(None) => {
-                // User doesn't exist, we can proceed
+                return Err(response::status::Custom(Status::BadRequest, err));
             }
             Err(e) => {
-                eprintln!("Error checking for existing user: {:?}", e);
+                eprintln!("Error checking role existence: {:?}", e);
                 let err = Json(ErrorResponse {
-                    error: "Database error while checking for existing user".to_string(),
+                    error: "Database error while validating roles".to_string(),
                 });
                 return Err(response::status::Custom(Status::InternalServerError, err));
             }
         }
 
-        // THIRD: Create the user (now that all roles are validated and email is unique)
-        let user_no_time = UserInput {
-            email: user_request.email,
-            password_hash: user_request.password_hash,
-            company_id: user_request.company_id,
-            totp_secret: user_request.totp_secret,
-        };
-
-        let created_user = match insert_user(conn, user_no_time, Some(auth_user.user.id)) {
-            Ok(user) => user,
-            Err(e) => {
-                eprintln!("Error creating user: {:?}", e);
-                let err = Json(ErrorResponse {
-                    error: "Database error while creating user".to_string(),
-                });
-                return Err(response::status::Custom(Status::InternalServerError, err));
-            }
+        // Check if user can assign this role (same logic as add_user_role)
+        let can_assign = if auth_user.has_role("newtown-admin") {
+            // newtown-admin can assign any role
+            true
+        } else if auth_user.has_role("newtown-staff") {
+            // newtown-staff can assign any role except newtown-admin
+            role_name != "newtown-admin"
+        } else if auth_user.has_role("admin") {
+            // admin can assign any role to users in same company
+            auth_user.user.company_id == company_id
+        } else {
+            false
         };
 
-        // FOURTH: Assign roles to the user (roles already validated above)
-        for role_name in &user_request.role_names {
-            // Assign the role (we already validated everything above)
-            if let Err(e) = assign_user_role_by_name(conn, created_user.id, role_name) {
-                eprintln!("Error assigning role {}: {:?}", role_name, e);
-                let err = Json(ErrorResponse {
-                    error: format!("Failed to assign role '{}' to user", role_name),
-                });
-                return Err(response::status::Custom(Status::InternalServerError, err));
-            }
+        if !can_assign {
+            let err = Json(ErrorResponse {
+                error: format!("Insufficient permissions to assign role '{}'", role_name),
+            });
+            return Err(response::status::Custom(Status::Forbidden, err));
         }
 
-        // Get the user with roles after creation and role assignment
-        match get_user_with_roles(conn, created_user.id) {
-            Ok(Some(user_with_roles)) => Ok(status::Created::new("/").body(Json(user_with_roles))),
-            Ok(None) => {
-                let err = Json(ErrorResponse {
-                    error: "User created but not found when retrieving with roles".to_string(),
-                });
-                Err(response::status::Custom(Status::InternalServerError, err))
-            }
-            Err(e) => {
-                eprintln!("Error getting created user with roles: {:?}", e);
+        // Check if role is newtown-staff or newtown-admin (company restriction)
+        if role_name == "newtown-staff" || role_name == "newtown-admin" {
+            let newtown_company_search = CompanyInput { name: "Newtown Energy".to_string() };
+            let newtown_company = match get_company_by_name(conn, &newtown_company_search) {
+                Ok(Some(company)) => company,
+                Ok(None) => {
+                    eprintln!("Newtown Energy company not found");
+                    let err = Json(ErrorResponse {
+                        error: "Newtown Energy company not found".to_string(),
+                    });
+                    return Err(response::status::Custom(Status::InternalServerError, err));
+                }
+                Err(e) => {
+                    eprintln!("Error getting Newtown Energy company: {:?}", e);
+                    let err = Json(ErrorResponse {
+                        error: "Database error while validating company".to_string(),
+                    });
+                    return Err(response::status::Custom(Status::InternalServerError, err));
+                }
+            };
+
+            if company_id != newtown_company.id {
                 let err = Json(ErrorResponse {
-                    error: "User created but failed to retrieve with roles".to_string(),
+                    error: format!("Role '{}' is restricted to Newtown Energy company", role_name),
                 });
-                Err(response::status::Custom(Status::InternalServerError, err))
+                return Err(response::status::Custom(Status::Forbidden, err));
             }
         }
-    })
-    .await
+    }
+
+    Ok(())
 }
 
 #[derive(serde::Deserialize)]
@@ -640,13 +681,50 @@ pub struct CreateUserWithRolesRequest {
 }
 
 /// Request structure for updating a user (all fields optional).
-#[derive(serde::Deserialize, TS)]
+#[derive(serde::Deserialize, serde::Serialize, TS)]
 #[ts(export)]
 pub struct UpdateUserRequest {
     pub email: Option<String>,
     pub password_hash: Option<String>,
     pub company_id: Option<i32>,
     pub totp_secret: Option<String>,
+    /// Force (or cancel) a password rotation; see
+    /// [`password::change_password_endpoint`]
+    pub must_change_password: Option<bool>,
+}
+
+/// JSON Merge Patch for a user (see [`crate::merge_patch`]): leave a field out
+/// to keep it, send `null` to clear it. Only `totp_secret` can be cleared.
+#[derive(Default, serde::Deserialize, serde::Serialize, TS)]
+#[ts(export)]
+pub struct UserPatch {
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<String>", optional)]
+    pub email: Patch<String>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<String>", optional)]
+    pub password_hash: Patch<String>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<i32>", optional)]
+    pub company_id: Patch<i32>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<String>", optional = nullable)]
+    pub totp_secret: Patch<String>,
+    #[serde(default, skip_serializing_if = "Patch::is_absent")]
+    #[ts(as = "Option<bool>", optional)]
+    pub must_change_password: Patch<bool>,
+}
+
+impl From<UpdateUserRequest> for UserPatch {
+    fn from(request: UpdateUserRequest) -> Self {
+        UserPatch {
+            email: request.email.into(),
+            password_hash: request.password_hash.into(),
+            company_id: request.company_id.into(),
+            totp_secret: request.totp_secret.into(),
+            must_change_password: request.must_change_password.into(),
+        }
+    }
 }
 
 /// Get User endpoint.
@@ -773,10 +851,14 @@ pub async fn get_user_endpoint(
 ///   "email": "newemail@example.com",
 ///   "password_hash": "new_hashed_password",
 ///   "company_id": 2,
-///   "totp_secret": "new_totp_secret"
+///   "totp_secret": "new_totp_secret",
+///   "must_change_password": true
 /// }
 /// ```
 ///
+/// Setting `must_change_password` restricts the user's sessions to changing
+/// their password until they do.
+///
 /// # Response
 ///
 /// **Success (HTTP 200 OK):**
@@ -787,6 +869,7 @@ pub async fn get_user_endpoint(
 ///   "password_hash": "new_hashed_password",
 ///   "company_id": 2,
 ///   "totp_secret": "new_totp_secret",
+///   "must_change_password": true,
 ///   "created_at": "2023-01-01T00:00:00Z",
 ///   "updated_at": "2023-01-01T12:30:00Z"
 /// }
@@ -814,64 +897,113 @@ pub async fn update_user_endpoint(
     request: Json<UpdateUserRequest>,
     auth_user: AuthenticatedUser,
 ) -> Result<Json<UserWithRoles>, Status> {
-    db.run(move |conn| {
-        // First, get the target user to check authorization
-        let target_user = match get_user(conn, user_id) {
-            Ok(Some(user)) => user,
-            Ok(None) => return Err(Status::NotFound),
-            Err(diesel::result::Error::NotFound) => return Err(Status::NotFound),
-            Err(e) => {
-                eprintln!("Error getting user for update: {:?}", e);
-                return Err(Status::InternalServerError);
-            }
-        };
+    let patch = UserPatch::from(request.into_inner());
+    db.run(move |conn| apply_user_patch(conn, &auth_user, user_id, patch)).await
+}
 
-        // Authorization: who can update this user?
-        let can_update = if auth_user.user.id == user_id {
-            // Users can always update their own profile
-            true
-        } else if auth_user.has_any_role(&["newtown-admin", "newtown-staff"]) {
-            // newtown-admin and newtown-staff can update any user
-            true
-        } else if auth_user.has_role("admin") {
-            // Company admins can only update users from their own company
-            auth_user.user.company_id == target_user.company_id
-        } else {
-            false
-        };
+/// Patch User endpoint.
+///
+/// - **URL:** `/api/1/Users/<user_id>`
+/// - **Method:** `PATCH`
+/// - **Purpose:** Updates a user with a JSON Merge Patch (RFC 7396)
+/// - **Authentication:** Required
+/// - **Authorization:** As for `PUT`
+///
+/// Takes a [`UserPatch`]: fields left out are kept, fields set to `null` are
+/// cleared, and the rest are set. `{"totp_secret": null}` turns off a user's
+/// TOTP, which `PUT` can't do.
+///
+/// **Error Responses:** as for `PUT`, plus **422 Unprocessable Entity** if
+/// the patch sets a field other than `totp_secret` to `null`.
+#[patch("/1/Users/<user_id>", data = "<patch>")]
+pub async fn patch_user_endpoint(
+    db: DbConn,
+    user_id: i32,
+    patch: Json<UserPatch>,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<UserWithRoles>, Status> {
+    let patch = patch.into_inner();
+    db.run(move |conn| apply_user_patch(conn, &auth_user, user_id, patch)).await
+}
 
-        if !can_update {
-            return Err(Status::Forbidden);
+/// Checks and applies a user update for the `PUT` and `PATCH` endpoints.
+fn apply_user_patch(
+    conn: &mut diesel::SqliteConnection,
+    auth_user: &AuthenticatedUser,
+    user_id: i32,
+    patch: UserPatch,
+) -> Result<Json<UserWithRoles>, Status> {
+    // First, get the target user to check authorization
+    let target_user = match get_user(conn, user_id) {
+        Ok(Some(user)) => user,
+        Ok(None) => return Err(Status::NotFound),
+        Err(diesel::result::Error::NotFound) => return Err(Status::NotFound),
+        Err(e) => {
+            eprintln!("Error getting user for update: {:?}", e);
+            return Err(Status::InternalServerError);
         }
+    };
+
+    // Authorization: who can update this user?
+    let can_update = if auth_user.user.id == user_id {
+        // Users can always update their own profile
+        true
+    } else if auth_user.has_any_role(&["newtown-admin", "newtown-staff"]) {
+        // newtown-admin and newtown-staff can update any user
+        true
+    } else if auth_user.has_role("admin") {
+        // Company admins can only update users from their own company
+        auth_user.user.company_id == target_user.company_id
+    } else {
+        false
+    };
+
+    if !can_update {
+        return Err(Status::Forbidden);
+    }
 
-        match update_user(
-            conn,
-            user_id,
-            request.email.clone(),
-            request.password_hash.clone(),
-            request.company_id,
-            request.totp_secret.clone(),
-            Some(auth_user.user.id),
-        ) {
-            Ok(_user) => {
-                // Get the updated user with roles
-                match get_user_with_roles(conn, user_id) {
-                    Ok(Some(user_with_roles)) => Ok(Json(user_with_roles)),
-                    Ok(None) => Err(Status::NotFound),
-                    Err(e) => {
-                        eprintln!("Error getting updated user with roles: {:?}", e);
-                        Err(Status::InternalServerError)
-                    }
+    let required = |e: String| {
+        eprintln!("Rejected user patch: {}", e);
+        Status::UnprocessableEntity
+    };
+    let email = patch.email.into_required("email").map_err(required)?;
+    let password_hash = patch.password_hash.into_required("password_hash").map_err(required)?;
+    let company_id = patch.company_id.into_required("company_id").map_err(required)?;
+    let must_change_password = patch
+        .must_change_password
+        .into_required("must_change_password")
+        .map_err(required)?;
+
+    match update_user(
+        conn,
+        user_id,
+        email,
+        password_hash,
+        company_id,
+        patch.totp_secret.into_nullable(),
+        Some(auth_user.user.id),
+    )
+    .and_then(|user| match must_change_password {
+        Some(value) => set_must_change_password(conn, user_id, value, Some(auth_user.user.id)),
+        None => Ok(user),
+    }) {
+        Ok(_user) => {
+            // Get the updated user with roles
+            match get_user_with_roles(conn, user_id) {
+                Ok(Some(user_with_roles)) => Ok(Json(user_with_roles)),
+                Ok(None) => Err(Status::NotFound),
+                Err(e) => {
+                    eprintln!("Error getting updated user with roles: {:?}", e);
+                    Err(Status::InternalServerError)
                 }
             }
-            Err(diesel::result::Error::NotFound) => Err(Status::NotFound),
-            Err(e) => {
-                eprintln!("Error updating user: {:?}", e);
-                Err(Status::InternalServerError)
-            }
         }
-    })
-    .await
+        Err(diesel::result::Error::NotFound) => Err(Status::NotFound),
+        Err(e) => {
+            eprintln!("Error updating user: {:?}", e);
+            Err(Status::InternalServerError)
+        }
+    }
 }
 
 /// Delete User endpoint.
@@ -1038,10 +1170,23 @@ pub fn routes() -> Vec<Route> {
         list::list_users,
         get_user_endpoint,
         update_user_endpoint,
+        patch_user_endpoint,
         delete_user_endpoint,
         roles::get_user_roles_endpoint,
         roles::add_user_role,
         roles::remove_user_role,
+        certificates::list_client_certificates,
+        certificates::add_client_certificate,
+        certificates::delete_client_certificate_endpoint,
+        certificates::get_client_certificate_allowed_networks,
+        certificates::set_client_certificate_allowed_networks,
+        login_history::get_user_login_history,
+        password::change_password_endpoint,
+        service_accounts::create_service_account,
+        service_accounts::list_api_keys,
+        service_accounts::create_api_key_endpoint,
+        service_accounts::rotate_api_key_endpoint,
+        service_accounts::delete_api_key_endpoint,
         get_user_company
     ]
 }
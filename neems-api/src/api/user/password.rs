@@ -0,0 +1,98 @@
+//! Password change endpoint.
+//!
+//! This is the one endpoint open to users flagged with
+//! `must_change_password` (see [`crate::session_guards::PasswordChangeUser`]),
+//! and changing the password here clears the flag.
+
+use rocket::{
+    http::{CookieJar, Status},
+    response::status,
+    serde::json::Json,
+};
+use ts_rs::TS;
+
+use super::ErrorResponse;
+use crate::{
+    orm::{
+        DbConn,
+        login::{hash_password, verify_password},
+        user::change_password,
+    },
+    session_guards::PasswordChangeUser,
+};
+
+/// Request structure for changing one's own password.
+#[derive(serde::Deserialize, TS)]
+#[ts(export)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+/// Change Password endpoint.
+///
+/// - **URL:** `/api/1/Users/<user_id>/Password`
+/// - **Method:** `PUT`
+/// - **Purpose:** Changes the authenticated user's own password
+/// - **Authentication:** Required (the user themselves; allowed even when the
+///   user must change their password)
+///
+/// Clears `must_change_password` and revokes the user's other sessions.
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "current_password": "old password",
+///   "new_password": "new password"
+/// }
+/// ```
+///
+/// # Response
+///
+/// **Success (HTTP 204 No Content)**
+///
+/// **Failure (HTTP 400 Bad Request):** The new password is empty or unchanged,
+/// or the user is a service account
+///
+/// **Failure (HTTP 403 Forbidden):** Not the user's own password, or the
+/// current password is wrong
+#[put("/1/Users/<user_id>/Password", data = "<request>")]
+pub async fn change_password_endpoint(
+    db: DbConn,
+    cookies: &CookieJar<'_>,
+    user_id: i32,
+    request: Json<ChangePasswordRequest>,
+    auth_user: PasswordChangeUser,
+) -> Result<Status, status::Custom<Json<ErrorResponse>>> {
+    let error = |status, error: &str| {
+        status::Custom(status, Json(ErrorResponse { error: error.to_string() }))
+    };
+    let user = auth_user.0.user;
+
+    if user.id != user_id {
+        return Err(error(Status::Forbidden, "Users can only change their own password"));
+    }
+    if user.service_account {
+        return Err(error(Status::BadRequest, "Service accounts have no password"));
+    }
+    if !verify_password(&request.current_password, &user.password_hash) {
+        return Err(error(Status::Forbidden, "Current password is incorrect"));
+    }
+    if request.new_password.trim().is_empty() {
+        return Err(error(Status::BadRequest, "New password must not be empty"));
+    }
+    if request.new_password == request.current_password {
+        return Err(error(Status::BadRequest, "New password must differ from the current one"));
+    }
+
+    let new_hash = hash_password(&request.new_password);
+    let keep_session = cookies.get("session").map(|cookie| cookie.value().to_string());
+    db.run(move |conn| change_password(conn, user_id, new_hash, keep_session))
+        .await
+        .map(|_| Status::NoContent)
+        .map_err(|e| {
+            eprintln!("Error changing password: {:?}", e);
+            error(Status::InternalServerError, "Database error")
+        })
+}
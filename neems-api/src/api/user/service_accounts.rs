@@ -0,0 +1,328 @@
+//! Service account and API key endpoints.
+//!
+//! Service accounts are users for machine clients such as the RTAC bridge and
+//! report jobs. They can't log in with a password; instead they send one of
+//! their API keys as `Authorization: Bearer <key>` (see
+//! [`crate::session_guards`]). Keys don't expire but can be rotated.
+
+use diesel::Connection;
+use rocket::{
+    http::Status,
+    response::status,
+    serde::{Serialize, json::Json},
+};
+use ts_rs::TS;
+
+use super::{ErrorResponse, check_assignable_roles, check_can_create};
+use crate::{
+    logged_json::LoggedJson,
+    models::{ApiKey, User, UserWithRoles},
+    orm::{
+        DbConn,
+        api_key::{create_api_key, delete_api_key, get_user_api_keys, rotate_api_key},
+        user::{get_user, get_user_by_email, get_user_with_roles, insert_service_account},
+        user_role::assign_user_role_by_name,
+    },
+    session_guards::AuthenticatedUser,
+};
+
+/// Request structure for creating a service account.
+#[derive(serde::Deserialize, serde::Serialize, TS)]
+#[ts(export)]
+pub struct CreateServiceAccountRequest {
+    /// Account name, used in place of an email address
+    pub name: String,
+    pub company_id: i32,
+    pub role_names: Vec<String>,
+}
+
+/// Request structure for creating an API key (user_id comes from URL path).
+#[derive(serde::Deserialize, TS)]
+#[ts(export)]
+pub struct CreateApiKeyRequest {
+    pub description: Option<String>,
+}
+
+/// A newly created or rotated API key. `key` is shown only this once.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct ApiKeySecret {
+    pub api_key: ApiKey,
+    pub key: String,
+}
+
+/// Whether `name` can name a service account: non-empty, with no whitespace,
+/// and no `@` so it can't be mistaken for a person's email address.
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains('@') && !name.chars().any(char::is_whitespace)
+}
+
+/// Whether `auth_user` may manage `target_user`'s API keys: newtown-admin for
+/// anyone, admin within their own company.
+fn can_manage(auth_user: &AuthenticatedUser, target_user: &User) -> bool {
+    auth_user.has_role("newtown-admin")
+        || (auth_user.has_role("admin") && auth_user.user.company_id == target_user.company_id)
+}
+
+/// The service account `user_id`, or 400 if it's a person's account.
+async fn target_service_account(db: &DbConn, user_id: i32) -> Result<User, Status> {
+    let user = db
+        .run(move |conn| get_user(conn, user_id))
+        .await
+        .map_err(|e| {
+            eprintln!("Error getting target user: {:?}", e);
+            Status::InternalServerError
+        })?
+        .ok_or(Status::NotFound)?;
+    if user.service_account {
+        Ok(user)
+    } else {
+        Err(Status::BadRequest)
+    }
+}
+
+/// Create Service Account endpoint.
+///
+/// - **URL:** `/api/1/ServiceAccounts`
+/// - **Method:** `POST`
+/// - **Purpose:** Creates a service account with roles
+/// - **Authentication:** Required (same rules as creating a user)
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "name": "rtac-bridge",
+///   "company_id": 1,
+///   "role_names": ["staff"]
+/// }
+/// ```
+///
+/// # Response
+///
+/// **Success (HTTP 201 Created):** the account, as returned by
+/// `/api/1/Users/<user_id>`, with `email` holding the name
+///
+/// **Failure (HTTP 400 Bad Request):** Invalid name, no roles, or an unknown
+/// role
+///
+/// **Failure (HTTP 409 Conflict):** A user with this name already exists
+#[post("/1/ServiceAccounts", data = "<request>")]
+pub async fn create_service_account(
+    db: DbConn,
+    request: LoggedJson<CreateServiceAccountRequest>,
+    auth_user: AuthenticatedUser,
+) -> Result<status::Created<Json<UserWithRoles>>, status::Custom<Json<ErrorResponse>>> {
+    let error = |status, error: &str| {
+        status::Custom(status, Json(ErrorResponse { error: error.to_string() }))
+    };
+
+    check_can_create(&auth_user, request.company_id, &request.role_names)?;
+    if !valid_name(&request.name) {
+        return Err(error(
+            Status::BadRequest,
+            "Service account names must be non-empty, without whitespace or '@'",
+        ));
+    }
+
+    let request = request.into_inner();
+    let acting_user_id = auth_user.user.id;
+    let account = db
+        .run(move |conn| {
+            check_assignable_roles(conn, &auth_user, request.company_id, &request.role_names)?;
+
+            let database_error = |e| {
+                eprintln!("Error creating service account: {:?}", e);
+                error(Status::InternalServerError, "Database error while creating service account")
+            };
+            if get_user_by_email(conn, &request.name).map_err(database_error)?.is_some() {
+                return Err(error(Status::Conflict, "User with this name already exists"));
+            }
+
+            conn.transaction(|conn| {
+                let account = insert_service_account(
+                    conn,
+                    &request.name,
+                    request.company_id,
+                    Some(acting_user_id),
+                )?;
+                for role_name in &request.role_names {
+                    assign_user_role_by_name(conn, account.id, role_name)?;
+                }
+                get_user_with_roles(conn, account.id)
+            })
+            .map_err(database_error)?
+            .ok_or_else(|| error(Status::InternalServerError, "Service account not found"))
+        })
+        .await?;
+
+    let location = format!("/api/1/Users/{}", account.id);
+    Ok(status::Created::new(location).body(Json(account)))
+}
+
+/// List API Keys endpoint.
+///
+/// - **URL:** `/api/1/Users/<user_id>/ApiKeys`
+/// - **Method:** `GET`
+/// - **Purpose:** Lists a service account's API keys (without the keys)
+/// - **Authentication:** Required (the account itself; newtown-admin,
+///   newtown-staff and admin can view any account's)
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):**
+/// ```json
+/// [
+///   {
+///     "id": 1,
+///     "user_id": 9,
+///     "prefix": "neems_3f9a0c1d",
+///     "description": "RTAC bridge at site 3",
+///     "created_at": "2026-10-16T22:00:00",
+///     "rotated_at": null
+///   }
+/// ]
+/// ```
+#[get("/1/Users/<user_id>/ApiKeys")]
+pub async fn list_api_keys(
+    db: DbConn,
+    user_id: i32,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<Vec<ApiKey>>, Status> {
+    if auth_user.user.id != user_id
+        && !auth_user.has_any_role(&["newtown-admin", "newtown-staff", "admin"])
+    {
+        return Err(Status::Forbidden);
+    }
+
+    db.run(move |conn| {
+        get_user_api_keys(conn, user_id).map(Json).map_err(|e| {
+            eprintln!("Error getting API keys: {:?}", e);
+            Status::InternalServerError
+        })
+    })
+    .await
+}
+
+/// Create API Key endpoint.
+///
+/// - **URL:** `/api/1/Users/<user_id>/ApiKeys`
+/// - **Method:** `POST`
+/// - **Purpose:** Issues a new API key for a service account
+/// - **Authentication:** Required (newtown-admin for any account, admin for
+///   accounts at the same company)
+///
+/// # Request Format
+///
+/// ```json
+/// { "description": "RTAC bridge at site 3" }
+/// ```
+///
+/// # Response
+///
+/// **Success (HTTP 201 Created):**
+/// ```json
+/// {
+///   "api_key": { "id": 1, "user_id": 9, "prefix": "neems_3f9a0c1d", ... },
+///   "key": "neems_3f9a0c1d..."
+/// }
+/// ```
+///
+/// The key is only ever returned here; store it right away.
+///
+/// **Failure (HTTP 400 Bad Request):** Not a service account
+#[post("/1/Users/<user_id>/ApiKeys", data = "<request>")]
+pub async fn create_api_key_endpoint(
+    db: DbConn,
+    user_id: i32,
+    request: Json<CreateApiKeyRequest>,
+    auth_user: AuthenticatedUser,
+) -> Result<status::Created<Json<ApiKeySecret>>, Status> {
+    let account = target_service_account(&db, user_id).await?;
+    if !can_manage(&auth_user, &account) {
+        return Err(Status::Forbidden);
+    }
+
+    let description = request.into_inner().description;
+    let (api_key, key) = db
+        .run(move |conn| create_api_key(conn, user_id, description))
+        .await
+        .map_err(|e| {
+            eprintln!("Error creating API key: {:?}", e);
+            Status::InternalServerError
+        })?;
+
+    let location = format!("/api/1/Users/{}/ApiKeys/{}", user_id, api_key.id);
+    Ok(status::Created::new(location).body(Json(ApiKeySecret { api_key, key })))
+}
+
+/// Rotate API Key endpoint.
+///
+/// - **URL:** `/api/1/Users/<user_id>/ApiKeys/<key_id>/Rotate`
+/// - **Method:** `POST`
+/// - **Purpose:** Replaces an API key with a new one; the old key stops working
+///   at once
+/// - **Authentication:** Required (the account itself, or as for creating keys)
+///
+/// # Response
+///
+/// **Success (HTTP 200 OK):** as for creating a key
+///
+/// **Failure (HTTP 404 Not Found):** No such key for this account
+#[post("/1/Users/<user_id>/ApiKeys/<key_id>/Rotate")]
+pub async fn rotate_api_key_endpoint(
+    db: DbConn,
+    user_id: i32,
+    key_id: i32,
+    auth_user: AuthenticatedUser,
+) -> Result<Json<ApiKeySecret>, Status> {
+    let account = target_service_account(&db, user_id).await?;
+    if auth_user.user.id != user_id && !can_manage(&auth_user, &account) {
+        return Err(Status::Forbidden);
+    }
+
+    db.run(move |conn| rotate_api_key(conn, user_id, key_id))
+        .await
+        .map_err(|e| {
+            eprintln!("Error rotating API key: {:?}", e);
+            Status::InternalServerError
+        })?
+        .map(|(api_key, key)| Json(ApiKeySecret { api_key, key }))
+        .ok_or(Status::NotFound)
+}
+
+/// Delete API Key endpoint.
+///
+/// - **URL:** `/api/1/Users/<user_id>/ApiKeys/<key_id>`
+/// - **Method:** `DELETE`
+/// - **Purpose:** Revokes an API key
+/// - **Authentication:** Required (as for creating keys)
+///
+/// # Response
+///
+/// **Success (HTTP 204 No Content)**
+///
+/// **Failure (HTTP 404 Not Found):** No such key for this account
+#[delete("/1/Users/<user_id>/ApiKeys/<key_id>")]
+pub async fn delete_api_key_endpoint(
+    db: DbConn,
+    user_id: i32,
+    key_id: i32,
+    auth_user: AuthenticatedUser,
+) -> Result<Status, Status> {
+    let account = target_service_account(&db, user_id).await?;
+    if !can_manage(&auth_user, &account) {
+        return Err(Status::Forbidden);
+    }
+
+    let deleted = db.run(move |conn| delete_api_key(conn, user_id, key_id)).await.map_err(|e| {
+        eprintln!("Error deleting API key: {:?}", e);
+        Status::InternalServerError
+    })?;
+
+    if deleted {
+        Ok(Status::NoContent)
+    } else {
+        Err(Status::NotFound)
+    }
+}
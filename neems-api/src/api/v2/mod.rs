@@ -0,0 +1,14 @@
+//! API version 2.
+//!
+//! Only the endpoints that changed since version 1 are here; the rest of
+//! `/api/2` is version 1's, served by the same handlers (see
+//! [`crate::api_version`]).
+
+pub mod user;
+
+use rocket::Route;
+
+/// The endpoints version 2 replaces.
+pub fn routes() -> Vec<Route> {
+    user::routes()
+}
@@ -0,0 +1,129 @@
+//! Version 2 user endpoints.
+//!
+//! Version 1 creates users from a password hash the client computed, and
+//! returns users with their hash and TOTP secret. Here the server hashes the
+//! password, and neither is returned.
+
+use rocket::{
+    Route,
+    http::Status,
+    response::{self, status},
+    serde::json::Json,
+};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{
+    api::user::{
+        CreateUserWithRolesRequest, ErrorResponse, check_can_create, insert_user_with_roles,
+    },
+    models::{Role, UserWithRoles},
+    orm::{DbConn, login::hash_password},
+    session_guards::AuthenticatedUser,
+};
+
+/// Request structure for creating a user with roles. Taken as plain
+/// [`Json`], as the password mustn't be logged.
+#[derive(Deserialize, Serialize, TS)]
+#[ts(export, rename = "CreateUserRequestV2")]
+pub struct CreateUserRequest {
+    pub email: String,
+    pub password: String,
+    pub company_id: i32,
+    pub totp_secret: Option<String>,
+    pub role_names: Vec<String>,
+}
+
+/// A user and their roles, without their credentials.
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[ts(export, rename = "UserV2")]
+pub struct User {
+    pub id: i32,
+    pub email: String,
+    pub company_id: i32,
+    pub must_change_password: bool,
+    pub service_account: bool,
+    pub roles: Vec<Role>,
+}
+
+impl From<UserWithRoles> for User {
+    fn from(user: UserWithRoles) -> Self {
+        User {
+            id: user.id,
+            email: user.email,
+            company_id: user.company_id,
+            must_change_password: user.must_change_password,
+            service_account: user.service_account,
+            roles: user.roles,
+        }
+    }
+}
+
+/// Create User endpoint, version 2.
+///
+/// - **URL:** `/api/2/Users`
+/// - **Method:** `POST`
+/// - **Purpose:** Creates a new user with assigned roles
+/// - **Authentication:** Required; the same permissions as version 1
+///
+/// # Request Format
+///
+/// ```json
+/// {
+///   "email": "newuser@example.com",
+///   "password": "correct horse battery staple",
+///   "company_id": 1,
+///   "totp_secret": null,
+///   "role_names": ["staff"]
+/// }
+/// ```
+///
+/// # Response
+///
+/// **Success (HTTP 201 Created):**
+/// ```json
+/// {
+///   "id": 123,
+///   "email": "newuser@example.com",
+///   "company_id": 1,
+///   "must_change_password": false,
+///   "service_account": false,
+///   "roles": [{ "id": 4, "name": "staff", "description": "Staff member" }]
+/// }
+/// ```
+///
+/// **Error Responses:** as version 1, and
+/// - **400 Bad Request**: The password is empty
+#[post("/2/Users", data = "<new_user>")]
+pub async fn create_user(
+    db: DbConn,
+    new_user: Json<CreateUserRequest>,
+    auth_user: AuthenticatedUser,
+) -> Result<status::Created<Json<User>>, response::status::Custom<Json<ErrorResponse>>> {
+    check_can_create(&auth_user, new_user.company_id, &new_user.role_names)?;
+    let new_user = new_user.into_inner();
+    if new_user.password.trim().is_empty() {
+        let err = Json(ErrorResponse {
+            error: "Password must not be empty".to_string(),
+        });
+        return Err(response::status::Custom(Status::BadRequest, err));
+    }
+
+    let request = CreateUserWithRolesRequest {
+        email: new_user.email,
+        password_hash: hash_password(&new_user.password),
+        company_id: new_user.company_id,
+        totp_secret: new_user.totp_secret,
+        role_names: new_user.role_names,
+    };
+    db.run(move |conn| insert_user_with_roles(conn, &auth_user, request))
+        .await
+        .map(|user| {
+            let location = format!("/api/1/Users/{}", user.id);
+            status::Created::new(location).body(Json(User::from(user)))
+        })
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![create_user]
+}
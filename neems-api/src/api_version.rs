@@ -0,0 +1,292 @@
+//! API versions, and the deprecation of old ones.
+//!
+//! Every route lives under `/api/<version>/`. A new version starts out as a
+//! copy of the one before it (see [`inherit_routes`]): only endpoints whose
+//! requests or responses change get a new handler, in [`crate::api::v2`] and
+//! so on, and the rest are served under both prefixes by the same handler.
+//! Clients can then move to the new version one endpoint at a time.
+//!
+//! [`API_VERSIONS`] records each version's status and [`DEPRECATED_ROUTES`]
+//! the old endpoints that have a replacement. Responses from a deprecated
+//! version or route carry a `Deprecation` header (RFC 9745), a `Sunset` header
+//! (RFC 8594) once a removal date is set, and a `Link` to the successor, so
+//! clients can find what they have to move off before it goes. The
+//! [`ApiVersioning`] fairing adds them; `GET /api/1/Versions` lists both
+//! registries.
+
+use chrono::{NaiveDate, NaiveTime};
+use rocket::{
+    Data, Request, Response, Route,
+    fairing::{Fairing, Info, Kind},
+    http::Header,
+    route::{self, Handler},
+};
+use serde::Serialize;
+use ts_rs::TS;
+
+/// Where a version is in its life.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum VersionStatus {
+    /// Served, but still open to breaking changes
+    Preview,
+    Current,
+    /// Served until its sunset date, if it has one
+    Deprecated,
+}
+
+/// A version of the API, served under `/api/<version>/`.
+#[derive(Clone, Debug, PartialEq, Serialize, TS)]
+#[ts(export)]
+pub struct ApiVersion {
+    pub version: u32,
+    pub status: VersionStatus,
+    /// When it was deprecated, as `YYYY-MM-DD`
+    #[ts(type = "string | null")]
+    pub deprecated: Option<&'static str>,
+    /// When it will be removed, as `YYYY-MM-DD`
+    #[ts(type = "string | null")]
+    pub sunset: Option<&'static str>,
+}
+
+/// An endpoint superseded by one in a later version.
+#[derive(Clone, Debug, PartialEq, Serialize, TS)]
+#[ts(export)]
+pub struct RouteDeprecation {
+    #[ts(type = "string")]
+    pub method: &'static str,
+    /// The route as declared, e.g. `/api/1/Users/<user_id>`
+    #[ts(type = "string")]
+    pub path: &'static str,
+    /// When it was deprecated, as `YYYY-MM-DD`
+    #[ts(type = "string")]
+    pub deprecated: &'static str,
+    /// When it will be removed, as `YYYY-MM-DD`
+    #[ts(type = "string | null")]
+    pub sunset: Option<&'static str>,
+    /// The endpoint to use instead
+    #[ts(type = "string")]
+    pub successor: &'static str,
+}
+
+/// The versions served, oldest first.
+pub const API_VERSIONS: &[ApiVersion] = &[
+    ApiVersion {
+        version: 1,
+        status: VersionStatus::Current,
+        deprecated: None,
+        sunset: None,
+    },
+    ApiVersion {
+        version: 2,
+        status: VersionStatus::Preview,
+        deprecated: None,
+        sunset: None,
+    },
+];
+
+/// Endpoints with a replacement in a later version.
+pub const DEPRECATED_ROUTES: &[RouteDeprecation] = &[RouteDeprecation {
+    method: "POST",
+    path: "/api/1/Users",
+    deprecated: "2026-10-17",
+    sunset: None,
+    successor: "/api/2/Users",
+}];
+
+/// The version a request path is under, if any.
+pub fn path_version(path: &str) -> Option<u32> {
+    path.strip_prefix("/api/")?.split('/').next()?.parse().ok()
+}
+
+fn parse_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+}
+
+/// A `Deprecation` header value: `@` and the Unix time (RFC 9745).
+fn deprecation_value(date: &str) -> Option<String> {
+    parse_date(date).map(|date| format!("@{}", date.and_time(NaiveTime::MIN).and_utc().timestamp()))
+}
+
+/// A `Sunset` header value: an HTTP date (RFC 8594).
+fn sunset_value(date: &str) -> Option<String> {
+    parse_date(date).map(|date| date.format("%a, %d %b %Y 00:00:00 GMT").to_string())
+}
+
+/// Adds deprecation headers to responses from deprecated versions and routes.
+pub struct ApiVersioning {
+    versions: Vec<ApiVersion>,
+    routes: Vec<RouteDeprecation>,
+}
+
+impl Default for ApiVersioning {
+    fn default() -> Self {
+        ApiVersioning::new(API_VERSIONS.to_vec(), DEPRECATED_ROUTES.to_vec())
+    }
+}
+
+impl ApiVersioning {
+    pub fn new(versions: Vec<ApiVersion>, routes: Vec<RouteDeprecation>) -> Self {
+        ApiVersioning { versions, routes }
+    }
+
+    /// The deprecation, sunset and successor for a request, as header values;
+    /// a deprecated route's own dates win over its version's.
+    fn headers(&self, request: &Request<'_>) -> Option<(String, Option<String>, Option<String>)> {
+        let route = request.route()?;
+        if let Some(deprecation) = self.routes.iter().find(|deprecation| {
+            deprecation.method == route.method.as_str() && deprecation.path == route.uri.path()
+        }) {
+            return Some((
+                deprecation_value(deprecation.deprecated)?,
+                deprecation.sunset.and_then(sunset_value),
+                Some(deprecation.successor.to_string()),
+            ));
+        }
+
+        let version = path_version(route.uri.path())?;
+        let api_version = self.versions.iter().find(|v| v.version == version)?;
+        if api_version.status != VersionStatus::Deprecated {
+            return None;
+        }
+        let successor = self
+            .versions
+            .iter()
+            .find(|v| v.version > version && v.status == VersionStatus::Current)
+            .map(|v| format!("/api/{}", v.version));
+        Some((
+            deprecation_value(api_version.deprecated?)?,
+            api_version.sunset.and_then(sunset_value),
+            successor,
+        ))
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for ApiVersioning {
+    fn info(&self) -> Info {
+        Info {
+            name: "API Deprecation Headers",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some((deprecation, sunset, successor)) = self.headers(request) else {
+            return;
+        };
+        response.set_header(Header::new("Deprecation", deprecation));
+        if let Some(sunset) = sunset {
+            response.set_header(Header::new("Sunset", sunset));
+        }
+        if let Some(successor) = successor {
+            response.adjoin_header(Header::new(
+                "Link",
+                format!("<{}>; rel=\"successor-version\"", successor),
+            ));
+        }
+    }
+}
+
+/// A route's handler, served again under another version.
+#[derive(Clone)]
+struct Inherited(Box<dyn Handler>);
+
+#[rocket::async_trait]
+impl Handler for Inherited {
+    async fn handle<'r>(&self, request: &'r Request<'_>, data: Data<'r>) -> route::Outcome<'r> {
+        self.0.handle(request, data).await
+    }
+}
+
+/// Version `to`'s routes: `overrides`, declared under `/<to>/`, and the rest
+/// of version `from`'s `routes` moved from `/<from>/` to `/<to>/`.
+///
+/// A route of `routes` is replaced by an override with the same method and
+/// path, ignoring the parameters' names. Routes outside `/<from>/` aren't
+/// inherited.
+pub fn inherit_routes(routes: &[Route], from: u32, to: u32, overrides: Vec<Route>) -> Vec<Route> {
+    let from_prefix = format!("/{}/", from);
+    let to_prefix = format!("/{}/", to);
+    let inherited: Vec<Route> = routes
+        .iter()
+        .filter_map(|route| {
+            let uri = route.uri.unmounted_origin.to_string();
+            let uri = format!("{}{}", to_prefix, uri.strip_prefix(&from_prefix)?);
+            let mut moved =
+                Route::ranked(route.rank, route.method, &uri, Inherited(route.handler.clone()));
+            moved.name = route.name.clone();
+            moved.format = route.format.clone();
+            let replaced = overrides.iter().any(|o| {
+                o.method == moved.method
+                    && path_shape(o.uri.unmounted_origin.path().as_str())
+                        == path_shape(moved.uri.unmounted_origin.path().as_str())
+            });
+            (!replaced).then_some(moved)
+        })
+        .collect();
+    overrides.into_iter().chain(inherited).collect()
+}
+
+/// A route path with its parameters' names blanked, e.g. `/1/Users/<>`.
+fn path_shape(path: &str) -> String {
+    path.split('/')
+        .map(|segment| match segment.starts_with('<') {
+            true if segment.ends_with("..>") => "<..>",
+            true => "<>",
+            false => segment,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::Method;
+
+    use super::*;
+
+    #[test]
+    fn test_registries_are_well_formed() {
+        for version in API_VERSIONS {
+            assert!(version.deprecated.is_none_or(|d| parse_date(d).is_some()));
+            assert!(version.sunset.is_none_or(|d| parse_date(d).is_some()));
+        }
+        for route in DEPRECATED_ROUTES {
+            assert!(parse_date(route.deprecated).is_some(), "{:?}", route);
+            assert!(route.sunset.is_none_or(|d| parse_date(d).is_some()), "{:?}", route);
+            assert!(path_version(route.successor).is_some(), "{:?}", route);
+        }
+    }
+
+    #[test]
+    fn test_header_values() {
+        assert_eq!(deprecation_value("2026-10-17").as_deref(), Some("@1792195200"));
+        assert_eq!(sunset_value("2027-06-30").as_deref(), Some("Wed, 30 Jun 2027 00:00:00 GMT"));
+        assert_eq!(deprecation_value("soon"), None);
+        assert_eq!(path_version("/api/2/Users"), Some(2));
+        assert_eq!(path_version("/api/Users"), None);
+        assert_eq!(path_version("/admin/1/status"), None);
+    }
+
+    #[test]
+    fn test_inherit_routes() {
+        let handler = route::dummy_handler;
+        let v1 = vec![
+            Route::new(Method::Get, "/1/Users/<user_id>", handler),
+            Route::new(Method::Put, "/1/Users/<user_id>", handler),
+            Route::new(Method::Get, "/1/Blobs/<key..>?<expires>", handler),
+            Route::new(Method::Get, "/Unversioned", handler),
+        ];
+        let v2 = vec![Route::new(Method::Put, "/2/Users/<id>", handler)];
+        let routes: Vec<_> = inherit_routes(&v1, 1, 2, v2)
+            .iter()
+            .map(|route| format!("{} {}", route.method, route.uri.as_str()))
+            .collect();
+        assert_eq!(
+            routes,
+            ["PUT /2/Users/<id>", "GET /2/Users/<user_id>", "GET /2/Blobs/<key..>?<expires>"]
+        );
+    }
+}
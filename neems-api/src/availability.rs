@@ -0,0 +1,430 @@
+//! Availability of sites and their devices, for SLA tracking.
+//!
+//! Time is counted in one-minute slots. At each slot a source is:
+//!
+//! - **offline** when its latest reading is older than twice its polling
+//!   interval, and at least [`MIN_OFFLINE_AFTER_SECONDS`] (capped at
+//!   [`MAX_OFFLINE_AFTER_SECONDS`]), or when it has no reading yet
+//! - **in fault** when its latest reading reports a level 1 or 2 (emergency or
+//!   high) alarm in its `alarm_registers`
+//! - **available** otherwise
+//!
+//! A device is counted through the source its device group membership names
+//! as reporting it (see [`crate::models::DeviceGroupMember`]); devices without
+//! one have no figures. The site is available when every one of its
+//! equipment sources is: its active sources other than carbon intensity
+//! feeds. Slots inside a maintenance window are excluded: a site-wide window
+//! excludes the site and all its devices, a device's window that device, and
+//! its source from the site's check. Availability is the available time over
+//! the time not excluded. Slots after `now` aren't counted.
+
+use std::collections::BTreeMap;
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use neems_data::{maintenance_windows::MaintenanceWindow, rtac::AlarmFlags};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::economics::EconomicsPeriod;
+
+/// Length of a counted slot
+pub const SLOT_SECONDS: i64 = 60;
+
+/// Least time without a reading before a source counts as offline
+pub const MIN_OFFLINE_AFTER_SECONDS: i64 = 5 * 60;
+
+/// Most time without a reading before a source counts as offline, and so how
+/// far before a report's start readings are needed
+pub const MAX_OFFLINE_AFTER_SECONDS: i64 = 2 * 60 * 60;
+
+/// A source's state in a slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlotState {
+    Available,
+    Fault,
+    Offline,
+}
+
+/// The readings of one equipment source, as `(timestamp, in fault)` in time
+/// order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceTimeline {
+    pub source_id: i32,
+    pub offline_after_seconds: i64,
+    pub samples: Vec<(NaiveDateTime, bool)>,
+}
+
+impl SourceTimeline {
+    /// The timeline of source `source_id`, polled every `interval_seconds`,
+    /// from a site's readings as `(source_id, timestamp, data)` in time order.
+    pub fn from_readings(
+        source_id: i32,
+        interval_seconds: i32,
+        readings: &[(i32, NaiveDateTime, String)],
+    ) -> Self {
+        let samples = readings
+            .iter()
+            .filter(|(id, _, _)| *id == source_id)
+            .map(|(_, timestamp, data)| {
+                let fault = serde_json::from_str(data)
+                    .ok()
+                    .and_then(|data| AlarmFlags::from_reading(&data))
+                    .is_some_and(|flags| flags.has_critical_alarm());
+                (*timestamp, fault)
+            })
+            .collect();
+        SourceTimeline {
+            source_id,
+            offline_after_seconds: (2 * interval_seconds as i64)
+                .clamp(MIN_OFFLINE_AFTER_SECONDS, MAX_OFFLINE_AFTER_SECONDS),
+            samples,
+        }
+    }
+}
+
+/// Walks a timeline forward through increasing slot times.
+struct Cursor<'a> {
+    timeline: &'a SourceTimeline,
+    next: usize,
+}
+
+impl Cursor<'_> {
+    fn state_at(&mut self, at: NaiveDateTime) -> SlotState {
+        let samples = &self.timeline.samples;
+        while self.next < samples.len() && samples[self.next].0 <= at {
+            self.next += 1;
+        }
+        match self.next.checked_sub(1).map(|i| samples[i]) {
+            Some((timestamp, _))
+                if (at - timestamp).num_seconds() > self.timeline.offline_after_seconds =>
+            {
+                SlotState::Offline
+            }
+            Some((_, true)) => SlotState::Fault,
+            Some((_, false)) => SlotState::Available,
+            None => SlotState::Offline,
+        }
+    }
+}
+
+/// Time counted for a site or device over a period.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct AvailabilityFigures {
+    /// Available time over the time not excluded; absent when all of it was
+    /// excluded
+    pub availability_percent: Option<f64>,
+    pub available_minutes: i64,
+    pub fault_minutes: i64,
+    pub offline_minutes: i64,
+    /// Time in maintenance windows
+    pub excluded_minutes: i64,
+}
+
+impl AvailabilityFigures {
+    fn count(&mut self, state: Option<SlotState>) {
+        match state {
+            Some(SlotState::Available) => self.available_minutes += 1,
+            Some(SlotState::Fault) => self.fault_minutes += 1,
+            Some(SlotState::Offline) => self.offline_minutes += 1,
+            None => self.excluded_minutes += 1,
+        }
+    }
+
+    fn finish(mut self) -> Self {
+        let counted = self.available_minutes + self.fault_minutes + self.offline_minutes;
+        self.availability_percent =
+            (counted > 0).then(|| 100.0 * self.available_minutes as f64 / counted as f64);
+        self
+    }
+}
+
+/// A device of the site and the source reporting it, if any.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceSource {
+    pub device_id: i32,
+    pub device_name: String,
+    pub source_id: Option<i32>,
+}
+
+/// A device's availability over a period.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DeviceAvailability {
+    pub device_id: i32,
+    pub device_name: String,
+    /// The source reporting the device; absent when no device group names one
+    pub source_id: Option<i32>,
+    /// Absent without a source
+    pub figures: Option<AvailabilityFigures>,
+}
+
+/// A site's and its devices' availability for a day or month.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct AvailabilityRow {
+    /// First day of the period
+    #[ts(type = "string")]
+    pub period_start: NaiveDate,
+    /// Absent when the site has no equipment sources
+    pub site: Option<AvailabilityFigures>,
+    pub devices: Vec<DeviceAvailability>,
+}
+
+/// Availability of a site's `sources` and `devices` from `start` until `end`
+/// (or `now`, if earlier) by `period`, leaving out `windows`.
+pub fn availability(
+    sources: &[SourceTimeline],
+    devices: &[DeviceSource],
+    windows: &[MaintenanceWindow],
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    now: NaiveDateTime,
+    period: EconomicsPeriod,
+) -> Vec<AvailabilityRow> {
+    let end = end.min(now);
+    let mut cursors: Vec<Cursor> =
+        sources.iter().map(|timeline| Cursor { timeline, next: 0 }).collect();
+    let source_index =
+        |source_id: Option<i32>| sources.iter().position(|s| Some(s.source_id) == source_id);
+    // A device's windows also take its source out of the site's check
+    let source_devices: Vec<Vec<i32>> = sources
+        .iter()
+        .map(|s| {
+            devices
+                .iter()
+                .filter(|d| d.source_id == Some(s.source_id))
+                .map(|d| d.device_id)
+                .collect()
+        })
+        .collect();
+    let device_sources: Vec<Option<usize>> =
+        devices.iter().map(|d| source_index(d.source_id)).collect();
+
+    let mut periods: BTreeMap<NaiveDate, (AvailabilityFigures, Vec<AvailabilityFigures>)> =
+        BTreeMap::new();
+    let mut at = start;
+    while at < end {
+        let open: Vec<&MaintenanceWindow> = windows.iter().filter(|w| w.covers(at)).collect();
+        let site_excluded = open.iter().any(|w| w.device_id.is_none());
+        let states: Vec<SlotState> = cursors.iter_mut().map(|c| c.state_at(at)).collect();
+        let (site, device_figures) = periods.entry(period.start(at)).or_insert_with(|| {
+            (AvailabilityFigures::default(), vec![Default::default(); devices.len()])
+        });
+
+        if !sources.is_empty() {
+            let checked = states.iter().zip(&source_devices).filter(|(_, device_ids)| {
+                !device_ids.iter().any(|id| open.iter().any(|w| w.device_id == Some(*id)))
+            });
+            let site_state = checked.fold(None, |worst, (state, _)| match (worst, *state) {
+                (Some(SlotState::Fault), _) | (_, SlotState::Fault) => Some(SlotState::Fault),
+                (Some(SlotState::Offline), _) | (_, SlotState::Offline) => Some(SlotState::Offline),
+                _ => Some(SlotState::Available),
+            });
+            site.count(if site_excluded { None } else { site_state });
+        }
+        for ((device, source), figures) in devices.iter().zip(&device_sources).zip(device_figures) {
+            if let Some(source) = source {
+                let excluded =
+                    site_excluded || open.iter().any(|w| w.device_id == Some(device.device_id));
+                figures.count((!excluded).then_some(states[*source]));
+            }
+        }
+        at += Duration::seconds(SLOT_SECONDS);
+    }
+
+    periods
+        .into_iter()
+        .map(|(period_start, (site, device_figures))| AvailabilityRow {
+            period_start,
+            site: (!sources.is_empty()).then(|| site.finish()),
+            devices: devices
+                .iter()
+                .zip(&device_sources)
+                .zip(device_figures)
+                .map(|((device, source), figures)| DeviceAvailability {
+                    device_id: device.device_id,
+                    device_name: device.device_name.clone(),
+                    source_id: device.source_id,
+                    figures: source.map(|_| figures.finish()),
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 7, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    /// A reading of `source_id`, with relay 86 M1 (a level 2 alarm) set when
+    /// `fault` is
+    fn reading(
+        source_id: i32,
+        timestamp: NaiveDateTime,
+        fault: bool,
+    ) -> (i32, NaiveDateTime, String) {
+        let mut registers = vec![0u16; 22];
+        if fault {
+            registers[1] = 1 << 2;
+        }
+        let data = serde_json::json!({ "alarm_registers": registers }).to_string();
+        (source_id, timestamp, data)
+    }
+
+    /// Readings of `source_id` every minute from `from` until `until`
+    fn every_minute(
+        source_id: i32,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+        fault: bool,
+    ) -> Vec<(i32, NaiveDateTime, String)> {
+        let mut readings = Vec::new();
+        let mut timestamp = from;
+        while timestamp < until {
+            readings.push(reading(source_id, timestamp, fault));
+            timestamp += Duration::minutes(1);
+        }
+        readings
+    }
+
+    fn window(
+        device_id: Option<i32>,
+        starts_at: NaiveDateTime,
+        ends_at: NaiveDateTime,
+    ) -> MaintenanceWindow {
+        MaintenanceWindow {
+            id: 1,
+            site_id: 1,
+            device_id,
+            starts_at,
+            ends_at,
+            reason: "Inspection".to_string(),
+            created_by: "tech@example.com".to_string(),
+            created_at: starts_at,
+        }
+    }
+
+    fn device(device_id: i32, source_id: Option<i32>) -> DeviceSource {
+        DeviceSource {
+            device_id,
+            device_name: format!("Device {}", device_id),
+            source_id,
+        }
+    }
+
+    #[test]
+    fn counts_gaps_as_offline_and_critical_alarms_as_faults() {
+        // Readings for the first two hours, then a 30-minute fault, then none
+        let mut readings = every_minute(1, at(1, 0, 0), at(1, 2, 0), false);
+        readings.extend(every_minute(1, at(1, 2, 0), at(1, 2, 30), true));
+        let sources = [SourceTimeline::from_readings(1, 60, &readings)];
+        let rows = availability(
+            &sources,
+            &[device(10, Some(1)), device(11, None)],
+            &[],
+            at(1, 0, 0),
+            at(1, 4, 0),
+            at(2, 0, 0),
+            EconomicsPeriod::Day,
+        );
+        assert_eq!(rows.len(), 1);
+        let site = rows[0].site.as_ref().unwrap();
+        assert_eq!(site.available_minutes, 120);
+        // The last faulted reading holds for five minutes
+        assert_eq!((site.fault_minutes, site.offline_minutes), (35, 85));
+        assert_eq!(site.availability_percent, Some(50.0));
+        assert_eq!(rows[0].devices[0].figures.as_ref(), Some(site));
+        assert_eq!(rows[0].devices[1].figures, None);
+    }
+
+    #[test]
+    fn maintenance_windows_are_excluded() {
+        let readings = every_minute(1, at(1, 0, 0), at(1, 1, 0), false);
+        let sources = [
+            SourceTimeline::from_readings(1, 60, &readings),
+            SourceTimeline::from_readings(2, 60, &every_minute(2, at(1, 0, 0), at(1, 2, 0), false)),
+        ];
+        let devices = [device(10, Some(1)), device(20, Some(2))];
+        // Device 10's source stops reporting during its window
+        let windows = [window(Some(10), at(1, 1, 0), at(1, 2, 0))];
+        let rows = availability(
+            &sources,
+            &devices,
+            &windows,
+            at(1, 0, 0),
+            at(1, 2, 0),
+            at(2, 0, 0),
+            EconomicsPeriod::Day,
+        );
+        let site = rows[0].site.as_ref().unwrap();
+        assert_eq!((site.available_minutes, site.excluded_minutes), (120, 0));
+        let first = rows[0].devices[0].figures.as_ref().unwrap();
+        assert_eq!((first.available_minutes, first.excluded_minutes), (60, 60));
+        assert_eq!(first.availability_percent, Some(100.0));
+
+        // A site-wide window excludes everything
+        let windows = [window(None, at(1, 0, 0), at(1, 2, 0))];
+        let rows = availability(
+            &sources,
+            &devices,
+            &windows,
+            at(1, 0, 0),
+            at(1, 2, 0),
+            at(2, 0, 0),
+            EconomicsPeriod::Day,
+        );
+        let site = rows[0].site.as_ref().unwrap();
+        assert_eq!((site.excluded_minutes, site.availability_percent), (120, None));
+    }
+
+    #[test]
+    fn groups_by_period_up_to_now() {
+        let readings = every_minute(1, at(1, 0, 0), at(3, 0, 0), false);
+        let sources = [SourceTimeline::from_readings(1, 60, &readings)];
+        let days = availability(
+            &sources,
+            &[],
+            &[],
+            at(1, 0, 0),
+            at(4, 0, 0),
+            at(2, 12, 0),
+            EconomicsPeriod::Day,
+        );
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].period_start, at(1, 0, 0).date());
+        assert_eq!(days[1].site.as_ref().unwrap().available_minutes, 12 * 60);
+
+        let months = availability(
+            &sources,
+            &[],
+            &[],
+            at(1, 0, 0),
+            at(4, 0, 0),
+            at(2, 12, 0),
+            EconomicsPeriod::Month,
+        );
+        assert_eq!(months.len(), 1);
+        assert_eq!(months[0].site.as_ref().unwrap().available_minutes, 36 * 60);
+        assert!(
+            availability(
+                &[],
+                &[],
+                &[],
+                at(1, 0, 0),
+                at(2, 0, 0),
+                at(3, 0, 0),
+                EconomicsPeriod::Day
+            )[0]
+            .site
+            .is_none()
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! Grid carbon intensity for sites.
+//!
+//! neems-data's `carbon_intensity` sources record the carbon intensity of a
+//! site's grid as `carbon_intensity_gco2_per_kwh` (see
+//! `neems_data::collectors::carbon_intensity`). From those readings:
+//!
+//! - [`CarbonSeries::gco2_per_kwh_at`] gives the intensity in force at any
+//!   time, for the economics report's emissions
+//! - [`hourly_profile`] averages them by hour of day (UTC, as reading
+//!   timestamps are), and [`cleanest_hours`] picks the hours a "charge when the
+//!   grid is clean" schedule should charge in
+
+use chrono::{Duration, NaiveDateTime, Timelike};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// Longest a carbon intensity reading stands for when no later one follows
+pub const MAX_CARBON_AGE_MINUTES: i64 = 2 * 60;
+
+/// The grid's carbon intensity from one reading.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CarbonSample {
+    #[ts(type = "string")]
+    pub timestamp: NaiveDateTime,
+    pub gco2_per_kwh: f64,
+}
+
+impl CarbonSample {
+    /// The sample in a reading's JSON `data`, if it reports carbon intensity.
+    pub fn from_reading(timestamp: NaiveDateTime, data_json: &str) -> Option<Self> {
+        let data: serde_json::Value = serde_json::from_str(data_json).ok()?;
+        let gco2_per_kwh = data
+            .get("carbon_intensity_gco2_per_kwh")?
+            .as_f64()
+            .filter(|n| n.is_finite() && *n >= 0.0)?;
+        Some(CarbonSample { timestamp, gco2_per_kwh })
+    }
+}
+
+/// A site's carbon intensity samples, in time order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CarbonSeries {
+    samples: Vec<CarbonSample>,
+}
+
+impl CarbonSeries {
+    pub fn new(mut samples: Vec<CarbonSample>) -> Self {
+        samples.sort_by_key(|s| s.timestamp);
+        CarbonSeries { samples }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// The intensity in force at `timestamp`: the latest sample at or before
+    /// it, unless that is more than [`MAX_CARBON_AGE_MINUTES`] old.
+    pub fn gco2_per_kwh_at(&self, timestamp: NaiveDateTime) -> Option<f64> {
+        let after = self.samples.partition_point(|s| s.timestamp <= timestamp);
+        let sample = self.samples.get(after.checked_sub(1)?)?;
+        (timestamp - sample.timestamp <= Duration::minutes(MAX_CARBON_AGE_MINUTES))
+            .then_some(sample.gco2_per_kwh)
+    }
+}
+
+/// The mean carbon intensity in one hour of the day.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct HourlyIntensity {
+    /// Hour of the day, UTC
+    pub hour: u32,
+    /// Absent when no samples fell in the hour
+    pub mean_gco2_per_kwh: Option<f64>,
+    pub samples: usize,
+}
+
+/// The mean intensity of `samples` in each of the 24 hours of the day.
+pub fn hourly_profile(samples: &[CarbonSample]) -> Vec<HourlyIntensity> {
+    let mut totals = [(0.0, 0usize); 24];
+    for sample in samples {
+        let (sum, count) = &mut totals[sample.timestamp.hour() as usize];
+        *sum += sample.gco2_per_kwh;
+        *count += 1;
+    }
+    totals
+        .iter()
+        .enumerate()
+        .map(|(hour, &(sum, count))| HourlyIntensity {
+            hour: hour as u32,
+            mean_gco2_per_kwh: (count > 0).then(|| sum / count as f64),
+            samples: count,
+        })
+        .collect()
+}
+
+/// Up to `count` hours of `profile` with the lowest mean intensity, cleanest
+/// first. Hours without samples are left out.
+pub fn cleanest_hours(profile: &[HourlyIntensity], count: usize) -> Vec<u32> {
+    let mut hours: Vec<(u32, f64)> =
+        profile.iter().filter_map(|h| Some((h.hour, h.mean_gco2_per_kwh?))).collect();
+    hours.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+    hours.into_iter().take(count).map(|(hour, _)| hour).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 7, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn sample(timestamp: NaiveDateTime, gco2_per_kwh: f64) -> CarbonSample {
+        CarbonSample { timestamp, gco2_per_kwh }
+    }
+
+    #[test]
+    fn test_reads_carbon_readings() {
+        let reading = r#"{"provider": "watttime", "carbon_intensity_gco2_per_kwh": 410.5}"#;
+        let sample = CarbonSample::from_reading(at(1, 0, 0), reading).unwrap();
+        assert_eq!(sample.gco2_per_kwh, 410.5);
+        assert!(CarbonSample::from_reading(at(1, 0, 0), r#"{"power_kw": 100}"#).is_none());
+        assert!(
+            CarbonSample::from_reading(at(1, 0, 0), r#"{"carbon_intensity_gco2_per_kwh": -1}"#)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_intensity_at() {
+        let series =
+            CarbonSeries::new(vec![sample(at(1, 12, 0), 300.0), sample(at(1, 1, 0), 100.0)]);
+        assert_eq!(series.gco2_per_kwh_at(at(1, 0, 59)), None);
+        assert_eq!(series.gco2_per_kwh_at(at(1, 1, 0)), Some(100.0));
+        assert_eq!(series.gco2_per_kwh_at(at(1, 3, 0)), Some(100.0));
+        // Too long after the last sample
+        assert_eq!(series.gco2_per_kwh_at(at(1, 3, 1)), None);
+        assert_eq!(series.gco2_per_kwh_at(at(1, 13, 30)), Some(300.0));
+        assert_eq!(CarbonSeries::default().gco2_per_kwh_at(at(1, 1, 0)), None);
+    }
+
+    #[test]
+    fn test_profile_and_cleanest_hours() {
+        let samples = vec![
+            sample(at(1, 2, 0), 200.0),
+            sample(at(2, 2, 30), 100.0),
+            sample(at(1, 13, 0), 120.0),
+            sample(at(1, 18, 0), 450.0),
+            sample(at(1, 11, 0), 150.0),
+        ];
+        let profile = hourly_profile(&samples);
+        assert_eq!(profile.len(), 24);
+        assert_eq!(profile[2].mean_gco2_per_kwh, Some(150.0));
+        assert_eq!(profile[2].samples, 2);
+        assert_eq!(profile[3].mean_gco2_per_kwh, None);
+
+        assert_eq!(cleanest_hours(&profile, 3), vec![13, 2, 11]);
+        assert_eq!(cleanest_hours(&profile, 10), vec![13, 2, 11, 18]);
+    }
+}
@@ -0,0 +1,247 @@
+//! Response compression fairing.
+//!
+//! Readings exports and list responses are large and compress well, so
+//! responses are compressed with Brotli or gzip, whichever the client's
+//! `Accept-Encoding` prefers (Brotli on a tie). Only bodies of a known size
+//! are compressed, which leaves streamed responses streaming.
+//!
+//! Configured in Rocket.toml or with `ROCKET_` variables:
+//!
+//! - `compression_min_size`: smallest body to compress, in bytes (default
+//!   [`DEFAULT_MIN_SIZE`])
+//! - `compression_types`: media types to compress, where `text/*` matches a
+//!   whole top-level type (default [`DEFAULT_TYPES`]); empty disables
+//!   compression
+
+use std::io::{Cursor, Write};
+
+use flate2::write::GzEncoder;
+use rocket::{
+    Request, Response,
+    fairing::{Fairing, Info, Kind},
+    figment::Figment,
+    http::{Header, Method, Status},
+};
+
+pub const DEFAULT_MIN_SIZE: usize = 1024;
+
+pub const DEFAULT_TYPES: &[&str] =
+    &["application/json", "application/javascript", "image/svg+xml", "text/*"];
+
+/// Brotli quality; the maximum (11) is far too slow to run per response.
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW: u32 = 22;
+
+/// A content coding the API can produce.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    pub fn compress(self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Encoding::Brotli => {
+                let mut output = Vec::new();
+                let mut writer =
+                    brotli::CompressorWriter::new(&mut output, 4096, BROTLI_QUALITY, BROTLI_WINDOW);
+                writer.write_all(data)?;
+                drop(writer);
+                Ok(output)
+            }
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// The encoding to use for a request's `Accept-Encoding` header, if any.
+///
+/// Follows RFC 9110 quality values: `q=0` refuses an encoding, and `*`
+/// covers encodings not listed by name.
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let mut brotli = None;
+    let mut gzip = None;
+    let mut wildcard = None;
+
+    for item in accept_encoding.split(',') {
+        let mut parts = item.split(';');
+        let coding = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let quality = parts
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        match coding.as_str() {
+            "br" => brotli = Some(quality),
+            "gzip" | "x-gzip" => gzip = Some(quality),
+            "*" => wildcard = Some(quality),
+            _ => {}
+        }
+    }
+
+    let brotli = brotli.or(wildcard).unwrap_or(0.0);
+    let gzip = gzip.or(wildcard).unwrap_or(0.0);
+    if brotli > 0.0 && brotli >= gzip {
+        Some(Encoding::Brotli)
+    } else if gzip > 0.0 {
+        Some(Encoding::Gzip)
+    } else {
+        None
+    }
+}
+
+/// Compresses responses the client can decode.
+pub struct Compression {
+    min_size: usize,
+    types: Vec<String>,
+}
+
+impl Compression {
+    pub fn new(min_size: usize, types: Vec<String>) -> Self {
+        Compression { min_size, types }
+    }
+
+    /// The fairing as configured by `compression_min_size` and
+    /// `compression_types`.
+    pub fn from_figment(figment: &Figment) -> Self {
+        let min_size = figment
+            .extract_inner::<usize>("compression_min_size")
+            .unwrap_or(DEFAULT_MIN_SIZE);
+        let types = figment
+            .extract_inner::<Vec<String>>("compression_types")
+            .unwrap_or_else(|_| DEFAULT_TYPES.iter().map(|t| t.to_string()).collect());
+        Compression::new(min_size, types)
+    }
+
+    fn compresses_type(&self, top: &str, sub: &str) -> bool {
+        self.types.iter().any(|pattern| match pattern.split_once('/') {
+            Some((pattern_top, "*")) => pattern_top.eq_ignore_ascii_case(top),
+            Some((pattern_top, pattern_sub)) => {
+                pattern_top.eq_ignore_ascii_case(top) && pattern_sub.eq_ignore_ascii_case(sub)
+            }
+            None => false,
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Compression {
+    fn info(&self) -> Info {
+        Info {
+            name: "Response Compression",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if request.method() == Method::Head
+            || [Status::NoContent, Status::NotModified].contains(&response.status())
+            || response.headers().contains("Content-Encoding")
+        {
+            return;
+        }
+        let Some(content_type) = response.content_type() else {
+            return;
+        };
+        if !self.compresses_type(content_type.top().as_str(), content_type.sub().as_str()) {
+            return;
+        }
+        match response.body().preset_size() {
+            Some(size) if size >= self.min_size => {}
+            _ => return,
+        }
+
+        // Whether this response is compressed depends on Accept-Encoding
+        response.adjoin_header(Header::new("Vary", "Accept-Encoding"));
+        let Some(encoding) = request.headers().get_one("Accept-Encoding").and_then(negotiate)
+        else {
+            return;
+        };
+
+        let body = match response.body_mut().to_bytes().await {
+            Ok(body) => body,
+            Err(e) => {
+                error!("Error reading response body to compress: {}", e);
+                return;
+            }
+        };
+        // Compressing megabytes of readings shouldn't stall the async workers
+        let compressed = {
+            let body = body.clone();
+            rocket::tokio::task::spawn_blocking(move || encoding.compress(&body)).await
+        };
+        match compressed {
+            Ok(Ok(compressed)) if compressed.len() < body.len() => {
+                response.set_header(Header::new("Content-Encoding", encoding.name()));
+                response.set_sized_body(compressed.len(), Cursor::new(compressed));
+            }
+            Ok(Ok(_)) => response.set_sized_body(body.len(), Cursor::new(body)),
+            Ok(Err(e)) => {
+                error!("Error compressing response: {}", e);
+                response.set_sized_body(body.len(), Cursor::new(body));
+            }
+            Err(e) => {
+                error!("Response compression task failed: {}", e);
+                response.set_sized_body(body.len(), Cursor::new(body));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate("gzip, deflate, br"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br;q=0.5, gzip;q=0.8"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br;q=0, gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("*"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("*;q=0.5, gzip;q=0"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("deflate, identity"), None);
+        assert_eq!(negotiate(""), None);
+    }
+
+    #[test]
+    fn test_type_filter() {
+        let compression = Compression::new(0, vec!["text/*".into(), "application/json".into()]);
+        assert!(compression.compresses_type("text", "csv"));
+        assert!(compression.compresses_type("application", "JSON"));
+        assert!(!compression.compresses_type("application", "octet-stream"));
+        assert!(!compression.compresses_type("image", "png"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        use std::io::Read;
+
+        let data = "{\"readings\": []}".repeat(100);
+        let gzip = Encoding::Gzip.compress(data.as_bytes()).unwrap();
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(gzip.as_slice())
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        let brotli = Encoding::Brotli.compress(data.as_bytes()).unwrap();
+        let mut decoded = String::new();
+        brotli::Decompressor::new(brotli.as_slice(), 4096)
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+        assert!(brotli.len() < data.len());
+    }
+}
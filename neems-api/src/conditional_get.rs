@@ -0,0 +1,132 @@
+//! Conditional GET support (`If-Modified-Since` / `Last-Modified`).
+//!
+//! Readings only ever change by new rows arriving, so the newest reading
+//! timestamp behind a response is a cheap stand-in for its modification time.
+//! Endpoints work that out before building the response, and if the client's
+//! copy is still current they answer 304 Not Modified with no body, which
+//! matters to dashboards polling over slow site links.
+//!
+//! HTTP dates have one-second resolution, so timestamps are compared to the
+//! second.
+
+use chrono::{DateTime, NaiveDateTime, SubsecRound};
+use rocket::{
+    Request, Response,
+    http::{Header, Status},
+    request::{FromRequest, Outcome},
+    response::{self, Responder},
+};
+
+/// HTTP-date format (RFC 9110 IMF-fixdate).
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// The request's `If-Modified-Since` time, if it sent a valid one.
+///
+/// An invalid date is ignored, as RFC 9110 requires, so this guard never
+/// fails.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IfModifiedSince(pub Option<NaiveDateTime>);
+
+impl IfModifiedSince {
+    /// Whether the client's copy is current, given the response's
+    /// modification time. Always false when there's nothing to date the
+    /// response by.
+    pub fn is_current(&self, last_modified: Option<NaiveDateTime>) -> bool {
+        match (self.0, last_modified) {
+            (Some(since), Some(modified)) => modified.trunc_subsecs(0) <= since,
+            _ => false,
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfModifiedSince {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let since = request
+            .headers()
+            .get_one("If-Modified-Since")
+            .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+            .map(|date| date.naive_utc());
+        Outcome::Success(IfModifiedSince(since))
+    }
+}
+
+/// A response to a conditional GET, carrying `Last-Modified` when known.
+pub enum Conditional<R> {
+    Modified(R, Option<NaiveDateTime>),
+    NotModified(NaiveDateTime),
+}
+
+impl<R> Conditional<R> {
+    /// 304 if the client's copy is current, otherwise the response `load`
+    /// builds. `load` only runs when needed.
+    pub fn respond<E>(
+        if_modified_since: IfModifiedSince,
+        last_modified: Option<NaiveDateTime>,
+        load: impl FnOnce() -> Result<R, E>,
+    ) -> Result<Self, E> {
+        match last_modified {
+            Some(modified) if if_modified_since.is_current(last_modified) => {
+                Ok(Conditional::NotModified(modified))
+            }
+            _ => Ok(Conditional::Modified(load()?, last_modified)),
+        }
+    }
+}
+
+fn last_modified_header(last_modified: NaiveDateTime) -> Header<'static> {
+    Header::new("Last-Modified", last_modified.and_utc().format(HTTP_DATE_FORMAT).to_string())
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Conditional<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        match self {
+            Conditional::Modified(body, last_modified) => {
+                let mut response = body.respond_to(request)?;
+                if let Some(last_modified) = last_modified {
+                    response.set_header(last_modified_header(last_modified));
+                }
+                Ok(response)
+            }
+            Conditional::NotModified(last_modified) => Response::build()
+                .status(Status::NotModified)
+                .header(last_modified_header(last_modified))
+                .ok(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn at(h: u32, m: u32, s: u32, ms: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .and_hms_milli_opt(h, m, s, ms)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_is_current() {
+        let since = IfModifiedSince(Some(at(12, 0, 0, 0)));
+        assert!(since.is_current(Some(at(11, 59, 59, 0))));
+        assert!(since.is_current(Some(at(12, 0, 0, 0))));
+        assert!(since.is_current(Some(at(12, 0, 0, 500))), "compared to the second");
+        assert!(!since.is_current(Some(at(12, 0, 1, 0))));
+        assert!(!since.is_current(None));
+        assert!(!IfModifiedSince(None).is_current(Some(at(12, 0, 0, 0))));
+    }
+
+    #[test]
+    fn test_http_date_round_trip() {
+        let header = last_modified_header(at(8, 49, 37, 250));
+        assert_eq!(header.value(), "Mon, 01 Jan 2024 08:49:37 GMT");
+        let parsed = DateTime::parse_from_rfc2822(header.value()).unwrap().naive_utc();
+        assert_eq!(parsed, at(8, 49, 37, 0));
+    }
+}
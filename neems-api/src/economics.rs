@@ -0,0 +1,478 @@
+//! Energy and revenue accounting for sites.
+//!
+//! Integrates battery power readings into the energy charged and discharged
+//! per day or month, and prices it with the site's tariff:
+//!
+//! - **Round-trip efficiency**: energy discharged over energy charged
+//! - **Arbitrage revenue**: the value of the energy discharged less the cost of
+//!   the energy charged, at the price in force when each reading was taken
+//! - **Demand-charge savings**: how far the battery lowered the period's peak
+//!   demand (site load less battery output) below the peak load alone, at the
+//!   demand charge. Demand charges are billed monthly, so a day's figure is
+//!   what its peak shaving would save were it the month's peak.
+//! - **Avoided emissions**: the grid emissions the energy discharged displaced
+//!   less those of the energy charged, at the carbon intensity in force when
+//!   each reading was taken (see [`crate::carbon`]). Energy moved while the
+//!   intensity isn't known is left out of both.
+//! - **Availability**: the share of the period the site was available, which
+//!   [`account`] leaves for the caller to fill in from [`crate::availability`]
+//!
+//! Battery power is read from a reading's `battery_power_kw` (the simulator)
+//! or `power_kw` (RTAC), positive when discharging, and site load from
+//! `load_kw`. Each reading stands for the time until its source's next one,
+//! up to [`MAX_SAMPLE_SECONDS`]; a source's last reading, and readings before
+//! a longer gap, count for that long at most. Times of day are UTC, as
+//! reading timestamps are.
+
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike};
+use neems_data::i18n::{Locale, translate};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{
+    carbon::{CarbonSample, CarbonSeries},
+    models::{Site, SiteTariff},
+};
+
+/// Longest a single reading counts for
+pub const MAX_SAMPLE_SECONDS: i64 = 15 * 60;
+
+/// How economics are grouped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "snake_case")]
+pub enum EconomicsPeriod {
+    Day,
+    Month,
+}
+
+impl EconomicsPeriod {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "day" => Ok(EconomicsPeriod::Day),
+            "month" => Ok(EconomicsPeriod::Month),
+            _ => Err(format!("Unknown period: {}", s)),
+        }
+    }
+
+    /// The first day of the period `timestamp` falls in.
+    pub fn start(&self, timestamp: NaiveDateTime) -> NaiveDate {
+        let date = timestamp.date();
+        match self {
+            EconomicsPeriod::Day => date,
+            EconomicsPeriod::Month => date.with_day(1).unwrap_or(date),
+        }
+    }
+}
+
+/// A battery's power, and the site's load, from one reading.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PowerSample {
+    pub timestamp: NaiveDateTime,
+    /// Positive when discharging, negative when charging
+    pub battery_power_kw: f64,
+    pub load_kw: Option<f64>,
+}
+
+impl PowerSample {
+    /// The sample in a reading's JSON `data`, if it reports battery power.
+    pub fn from_reading(timestamp: NaiveDateTime, data_json: &str) -> Option<Self> {
+        let data: serde_json::Value = serde_json::from_str(data_json).ok()?;
+        let number = |field: &str| data.get(field)?.as_f64().filter(|n| n.is_finite());
+        let battery_power_kw = number("battery_power_kw").or_else(|| number("power_kw"))?;
+        Some(PowerSample {
+            timestamp,
+            battery_power_kw,
+            load_kw: number("load_kw"),
+        })
+    }
+}
+
+/// The price of energy at any time, from a site's tariff and windows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pricing {
+    pub tariff: SiteTariff,
+    /// Minutes after midnight, start inclusive and end exclusive
+    pub off_peak_minutes: Option<(i32, i32)>,
+    pub peak_minutes: Option<(i32, i32)>,
+}
+
+impl Pricing {
+    pub fn new(site: &Site, tariff: SiteTariff) -> Self {
+        let window = |start: Option<i32>, end: Option<i32>| Some((start?, end?));
+        Pricing {
+            tariff,
+            off_peak_minutes: window(site.off_peak_start_minutes, site.off_peak_end_minutes),
+            peak_minutes: window(site.peak_revenue_start_minutes, site.peak_revenue_end_minutes),
+        }
+    }
+
+    /// Price per kWh at `timestamp`.
+    pub fn price_per_kwh(&self, timestamp: NaiveDateTime) -> f64 {
+        let minute = (timestamp.hour() * 60 + timestamp.minute()) as i32;
+        let within = |window: Option<(i32, i32)>| match window {
+            Some((start, end)) if start <= end => start <= minute && minute < end,
+            // Windows across midnight
+            Some((start, end)) => minute >= start || minute < end,
+            None => false,
+        };
+        if within(self.off_peak_minutes) {
+            self.tariff.off_peak_price_per_kwh
+        } else if within(self.peak_minutes) {
+            self.tariff.peak_price_per_kwh
+        } else {
+            self.tariff.standard_price_per_kwh
+        }
+    }
+}
+
+/// A site's energy and revenue for a day or month. Money is in the tariff's
+/// currency, and absent without a tariff; emissions are absent without carbon
+/// intensity readings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct EconomicsRow {
+    /// First day of the period
+    #[ts(type = "string")]
+    pub period_start: NaiveDate,
+    pub charged_kwh: f64,
+    pub discharged_kwh: f64,
+    /// Absent when nothing was charged
+    pub round_trip_efficiency_percent: Option<f64>,
+    pub charge_cost: Option<f64>,
+    pub discharge_value: Option<f64>,
+    /// Discharge value less charge cost
+    pub arbitrage_revenue: Option<f64>,
+    /// Peak site load; absent when no readings report the load
+    pub peak_demand_kw: Option<f64>,
+    /// Peak of the site load less battery output
+    pub peak_net_demand_kw: Option<f64>,
+    pub demand_charge_savings: Option<f64>,
+    /// Grid emissions of the energy charged, in kg CO2
+    pub charge_emissions_kg: Option<f64>,
+    /// Emissions displaced by the energy discharged less the charge emissions
+    pub avoided_emissions_kg: Option<f64>,
+    /// Share of the time outside maintenance windows the site was available
+    /// (see [`crate::availability`]); absent when it has no equipment sources
+    pub availability_percent: Option<f64>,
+}
+
+#[derive(Default)]
+struct Totals {
+    charged_kwh: f64,
+    discharged_kwh: f64,
+    charge_cost: f64,
+    discharge_value: f64,
+    charge_emissions_kg: f64,
+    displaced_emissions_kg: f64,
+    peak_demand_kw: Option<f64>,
+    peak_net_demand_kw: Option<f64>,
+}
+
+fn raise(peak: &mut Option<f64>, kw: f64) {
+    *peak = Some(peak.map_or(kw, |p| p.max(kw)));
+}
+
+/// Each source's power samples from `start`, and the carbon intensity, from
+/// a site's readings as `(source_id, timestamp, data)` ordered by source and
+/// time. Carbon intensity readings before `start` are kept, as the intensity
+/// in force at the start may have been read earlier.
+pub fn split_readings(
+    readings: &[(i32, NaiveDateTime, String)],
+    start: NaiveDateTime,
+) -> (Vec<Vec<PowerSample>>, CarbonSeries) {
+    let mut sources: Vec<Vec<PowerSample>> = Vec::new();
+    let mut carbon_samples = Vec::new();
+    let mut current_source = None;
+    for (source_id, timestamp, data) in readings {
+        if let Some(sample) = CarbonSample::from_reading(*timestamp, data) {
+            carbon_samples.push(sample);
+            continue;
+        }
+        if *timestamp < start {
+            continue;
+        }
+        let Some(sample) = PowerSample::from_reading(*timestamp, data) else {
+            continue;
+        };
+        if current_source != Some(*source_id) {
+            current_source = Some(*source_id);
+            sources.push(Vec::new());
+        }
+        if let Some(samples) = sources.last_mut() {
+            samples.push(sample);
+        }
+    }
+    (sources, CarbonSeries::new(carbon_samples))
+}
+
+/// Account for each source's samples, in time order, by `period`.
+pub fn account(
+    sources: &[Vec<PowerSample>],
+    period: EconomicsPeriod,
+    pricing: Option<&Pricing>,
+    carbon: Option<&CarbonSeries>,
+) -> Vec<EconomicsRow> {
+    let mut totals: BTreeMap<NaiveDate, Totals> = BTreeMap::new();
+    for samples in sources {
+        for (i, sample) in samples.iter().enumerate() {
+            let seconds = samples
+                .get(i + 1)
+                .map_or(MAX_SAMPLE_SECONDS, |next| {
+                    (next.timestamp - sample.timestamp).num_seconds()
+                })
+                .clamp(0, MAX_SAMPLE_SECONDS);
+            let energy_kwh = sample.battery_power_kw * seconds as f64 / 3600.0;
+            let price = pricing.map_or(0.0, |p| p.price_per_kwh(sample.timestamp));
+            let kg_per_kwh = carbon
+                .and_then(|c| c.gco2_per_kwh_at(sample.timestamp))
+                .map_or(0.0, |g| g / 1000.0);
+
+            let period_totals = totals.entry(period.start(sample.timestamp)).or_default();
+            if energy_kwh < 0.0 {
+                period_totals.charged_kwh -= energy_kwh;
+                period_totals.charge_cost -= energy_kwh * price;
+                period_totals.charge_emissions_kg -= energy_kwh * kg_per_kwh;
+            } else {
+                period_totals.discharged_kwh += energy_kwh;
+                period_totals.discharge_value += energy_kwh * price;
+                period_totals.displaced_emissions_kg += energy_kwh * kg_per_kwh;
+            }
+            if let Some(load_kw) = sample.load_kw {
+                raise(&mut period_totals.peak_demand_kw, load_kw);
+                raise(&mut period_totals.peak_net_demand_kw, load_kw - sample.battery_power_kw);
+            }
+        }
+    }
+
+    totals
+        .into_iter()
+        .map(|(period_start, t)| {
+            let money = |amount: f64| pricing.map(|_| amount);
+            let emissions = |kg: f64| carbon.map(|_| kg);
+            let demand_charge_savings = match (pricing, t.peak_demand_kw, t.peak_net_demand_kw) {
+                (Some(p), Some(peak), Some(net)) => {
+                    Some((peak - net) * p.tariff.demand_charge_per_kw)
+                }
+                _ => None,
+            };
+            EconomicsRow {
+                period_start,
+                charged_kwh: t.charged_kwh,
+                discharged_kwh: t.discharged_kwh,
+                round_trip_efficiency_percent: (t.charged_kwh > 0.0)
+                    .then(|| t.discharged_kwh / t.charged_kwh * 100.0),
+                charge_cost: money(t.charge_cost),
+                discharge_value: money(t.discharge_value),
+                arbitrage_revenue: money(t.discharge_value - t.charge_cost),
+                peak_demand_kw: t.peak_demand_kw,
+                peak_net_demand_kw: t.peak_net_demand_kw,
+                demand_charge_savings,
+                charge_emissions_kg: emissions(t.charge_emissions_kg),
+                avoided_emissions_kg: emissions(t.displaced_emissions_kg - t.charge_emissions_kg),
+                availability_percent: None,
+            }
+        })
+        .collect()
+}
+
+/// The rows as CSV, with a header line. In English the columns are named
+/// as the JSON report's fields; other locales get translated labels.
+pub fn to_csv(rows: &[EconomicsRow], locale: Locale) -> Result<String, csv::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    let bytes = writer.into_inner().map_err(|e| e.into_error())?;
+    let csv = String::from_utf8_lossy(&bytes).into_owned();
+    let Some((header, body)) = csv.split_once('\n').filter(|_| locale != Locale::En) else {
+        return Ok(csv);
+    };
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(header.split(',').map(|name| translate(locale, name).unwrap_or(name)))?;
+    let mut bytes = writer.into_inner().map_err(|e| e.into_error())?;
+    bytes.extend_from_slice(body.as_bytes());
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 7, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn sample(
+        timestamp: NaiveDateTime,
+        battery_power_kw: f64,
+        load_kw: Option<f64>,
+    ) -> PowerSample {
+        PowerSample { timestamp, battery_power_kw, load_kw }
+    }
+
+    fn pricing() -> Pricing {
+        Pricing {
+            tariff: SiteTariff {
+                site_id: 1,
+                currency: "USD".to_string(),
+                standard_price_per_kwh: 0.15,
+                off_peak_price_per_kwh: 0.10,
+                peak_price_per_kwh: 0.30,
+                demand_charge_per_kw: 12.0,
+                updated_at: at(1, 0, 0),
+            },
+            off_peak_minutes: Some((0, 6 * 60)),
+            peak_minutes: Some((16 * 60, 21 * 60)),
+        }
+    }
+
+    /// Readings every 15 minutes for an hour at `power_kw`, from `start`.
+    fn hour_at(start: NaiveDateTime, power_kw: f64) -> Vec<PowerSample> {
+        (0..4)
+            .map(|i| sample(start + chrono::Duration::minutes(15 * i), power_kw, None))
+            .collect()
+    }
+
+    #[test]
+    fn test_reads_simulator_and_rtac_readings() {
+        let simulator = PowerSample::from_reading(
+            at(1, 0, 0),
+            r#"{"battery_power_kw": -250.0, "load_kw": 900.0, "commanded_power_kw": -300}"#,
+        )
+        .unwrap();
+        assert_eq!(simulator.battery_power_kw, -250.0);
+        assert_eq!(simulator.load_kw, Some(900.0));
+
+        let rtac = PowerSample::from_reading(at(1, 0, 0), r#"{"power_kw": 100, "level": 50}"#);
+        assert_eq!(rtac.unwrap().battery_power_kw, 100.0);
+        assert!(PowerSample::from_reading(at(1, 0, 0), r#"{"level": 50}"#).is_none());
+    }
+
+    #[test]
+    fn test_prices_by_window() {
+        let pricing = pricing();
+        assert_eq!(pricing.price_per_kwh(at(1, 2, 0)), 0.10);
+        assert_eq!(pricing.price_per_kwh(at(1, 6, 0)), 0.15);
+        assert_eq!(pricing.price_per_kwh(at(1, 17, 30)), 0.30);
+
+        let overnight = Pricing {
+            off_peak_minutes: Some((22 * 60, 6 * 60)),
+            ..pricing
+        };
+        assert_eq!(overnight.price_per_kwh(at(1, 23, 0)), 0.10);
+        assert_eq!(overnight.price_per_kwh(at(1, 5, 59)), 0.10);
+        assert_eq!(overnight.price_per_kwh(at(1, 12, 0)), 0.15);
+    }
+
+    #[test]
+    fn test_daily_arbitrage() {
+        // Charge 400 kWh off-peak, discharge 300 kWh at peak
+        let mut samples = hour_at(at(1, 1, 0), -400.0);
+        samples.extend(hour_at(at(1, 17, 0), 300.0));
+        let rows = account(&[samples], EconomicsPeriod::Day, Some(&pricing()), None);
+
+        assert_eq!(rows.len(), 1);
+        let row = &rows[0];
+        assert_eq!(row.period_start, NaiveDate::from_ymd_opt(2026, 7, 1).unwrap());
+        assert_eq!((row.charged_kwh, row.discharged_kwh), (400.0, 300.0));
+        assert_eq!(row.round_trip_efficiency_percent, Some(75.0));
+        assert!((row.charge_cost.unwrap() - 40.0).abs() < 1e-9);
+        assert!((row.discharge_value.unwrap() - 90.0).abs() < 1e-9);
+        assert!((row.arbitrage_revenue.unwrap() - 50.0).abs() < 1e-9);
+        assert_eq!(row.demand_charge_savings, None);
+    }
+
+    #[test]
+    fn test_monthly_totals_and_demand_savings() {
+        let samples = vec![
+            sample(at(1, 17, 0), 200.0, Some(1000.0)),
+            sample(at(1, 17, 15), 0.0, Some(700.0)),
+            sample(at(2, 17, 0), 300.0, Some(1100.0)),
+        ];
+        let rows =
+            account(std::slice::from_ref(&samples), EconomicsPeriod::Month, Some(&pricing()), None);
+        assert_eq!(rows.len(), 1);
+        let row = &rows[0];
+        assert_eq!(row.peak_demand_kw, Some(1100.0));
+        assert_eq!(row.peak_net_demand_kw, Some(800.0));
+        assert_eq!(row.demand_charge_savings, Some(300.0 * 12.0));
+        // 15 minutes at 200 kW, and the last reading counts for 15 minutes
+        assert_eq!(row.discharged_kwh, 50.0 + 75.0);
+        assert_eq!(row.round_trip_efficiency_percent, None);
+
+        let days = account(&[samples], EconomicsPeriod::Day, None, None);
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].discharged_kwh, 50.0);
+        assert_eq!(days[0].arbitrage_revenue, None);
+        assert_eq!(days[0].demand_charge_savings, None);
+    }
+
+    #[test]
+    fn test_gaps_and_sources_counted_separately() {
+        // A reading before a six-hour gap counts for 15 minutes only
+        let first = vec![sample(at(1, 0, 0), -100.0, None), sample(at(1, 6, 0), -100.0, None)];
+        let second = hour_at(at(1, 0, 0), -100.0);
+        let rows = account(&[first, second], EconomicsPeriod::Day, None, None);
+        assert_eq!(rows[0].charged_kwh, 25.0 + 25.0 + 100.0);
+    }
+
+    #[test]
+    fn test_avoided_emissions() {
+        // Charge 400 kWh on a clean grid, discharge 300 kWh on a dirty one
+        let mut samples = hour_at(at(1, 1, 0), -400.0);
+        samples.extend(hour_at(at(1, 17, 0), 300.0));
+        let carbon = CarbonSeries::new(vec![
+            CarbonSample {
+                timestamp: at(1, 0, 30),
+                gco2_per_kwh: 100.0,
+            },
+            CarbonSample {
+                timestamp: at(1, 16, 30),
+                gco2_per_kwh: 500.0,
+            },
+        ]);
+        let rows = account(&[samples], EconomicsPeriod::Day, None, Some(&carbon));
+        let row = &rows[0];
+        assert!((row.charge_emissions_kg.unwrap() - 40.0).abs() < 1e-9);
+        assert!((row.avoided_emissions_kg.unwrap() - 110.0).abs() < 1e-9);
+        assert_eq!(row.arbitrage_revenue, None);
+
+        // Energy moved without a recent intensity doesn't count
+        let stale = CarbonSeries::new(vec![CarbonSample {
+            timestamp: at(1, 0, 30),
+            gco2_per_kwh: 100.0,
+        }]);
+        let rows =
+            account(&[hour_at(at(1, 17, 0), 300.0)], EconomicsPeriod::Day, None, Some(&stale));
+        assert_eq!(rows[0].avoided_emissions_kg, Some(0.0));
+    }
+
+    #[test]
+    fn test_csv_export() {
+        let rows = account(&[hour_at(at(1, 1, 0), -400.0)], EconomicsPeriod::Day, None, None);
+        let csv = to_csv(&rows, Locale::En).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some(
+                "period_start,charged_kwh,discharged_kwh,round_trip_efficiency_percent,\
+                 charge_cost,discharge_value,arbitrage_revenue,peak_demand_kw,\
+                 peak_net_demand_kw,demand_charge_savings,charge_emissions_kg,\
+                 avoided_emissions_kg,availability_percent"
+            )
+        );
+        assert_eq!(lines.next(), Some("2026-07-01,400.0,0.0,0.0,,,,,,,,,"));
+
+        let csv = to_csv(&rows, Locale::Fr).unwrap();
+        let mut lines = csv.lines();
+        assert!(lines.next().unwrap().starts_with("Début de la période,Énergie chargée (kWh),"));
+        assert_eq!(lines.next(), Some("2026-07-01,400.0,0.0,0.0,,,,,,,,,"));
+    }
+}
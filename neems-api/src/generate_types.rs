@@ -1,14 +1,513 @@
 //! TypeScript type generation module.
 //!
-//! This module exports TypeScript type definitions for all the structs
-//! annotated with `#[ts(export)]`. When this file is compiled (typically
-//! during testing), it generates .ts files in the specified output directory.
+//! [`export_types`] writes TypeScript definitions for the types the API sends
+//! and receives, including those from `neems-data`, for the frontend to build
+//! against. `neems-api export-types --out DIR` runs it, as does the
+//! `generate_typescript_types` test.
+//!
+//! The output only depends on the Rust types: each type goes to
+//! `<Name>.ts`, the first type registered under a name wins, files whose
+//! contents haven't changed aren't rewritten, and `.ts` files left over from
+//! removed or renamed types are deleted.
+
+use std::{
+    any::TypeId,
+    collections::{BTreeMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use ts_rs::{ExportError, TS, TypeVisitor};
+
+/// The bindings to write, keyed by file name.
+#[derive(Default)]
+struct Bindings {
+    files: BTreeMap<PathBuf, String>,
+    seen: HashSet<TypeId>,
+    error: Option<ExportError>,
+}
+
+impl Bindings {
+    /// Adds `T` and, recursively, the types it refers to.
+    fn add<T: TS + ?Sized + 'static>(&mut self) {
+        if self.error.is_some() || !self.seen.insert(TypeId::of::<T>()) {
+            return;
+        }
+        let Some(path) = T::output_path() else {
+            return;
+        };
+        if !self.files.contains_key(path) {
+            match T::export_to_string() {
+                Ok(contents) => {
+                    self.files.insert(path.to_path_buf(), contents);
+                }
+                Err(e) => {
+                    self.error = Some(e);
+                    return;
+                }
+            }
+        }
+        T::visit_dependencies(self);
+    }
+}
+
+impl TypeVisitor for Bindings {
+    fn visit<T: TS + 'static + ?Sized>(&mut self) {
+        self.add::<T>();
+    }
+}
+
+/// Writes every binding to `out_dir`, creating it if needed, and returns
+/// the number of files exported.
+pub fn export_types(out_dir: &Path) -> Result<usize, ExportError> {
+    let mut bindings = Bindings::default();
+    register(&mut bindings);
+    if let Some(e) = bindings.error {
+        return Err(e);
+    }
+
+    fs::create_dir_all(out_dir)?;
+    for entry in fs::read_dir(out_dir)? {
+        let path = entry?.path();
+        let stale = path.extension().is_some_and(|ext| ext == "ts")
+            && path
+                .file_name()
+                .is_none_or(|name| !bindings.files.contains_key(Path::new(name)));
+        if stale {
+            fs::remove_file(&path)?;
+        }
+    }
+    for (name, contents) in &bindings.files {
+        let path = out_dir.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match fs::read_to_string(&path) {
+            Ok(existing) if existing == *contents => continue,
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        fs::write(&path, contents)?;
+    }
+    Ok(bindings.files.len())
+}
+
+/// Registers every exported type, grouped by the API it belongs to.
+fn register(bindings: &mut Bindings) {
+    use crate::{
+        api::{
+            application_rule::{
+                ErrorResponse as ApplicationRuleErrorResponse, SeasonFillRequest,
+                SeasonFillResponse,
+            },
+            company::ErrorResponse as CompanyErrorResponse,
+            login::{ErrorResponse as LoginErrorResponse, LoginSuccessResponse},
+            schedule_library::{
+                CreateFromSiteDefaultsRequest, ErrorResponse as ScheduleLibraryErrorResponse,
+                ImportErrorResponse,
+            },
+            site::{
+                CreateSiteRequest, ErrorResponse as SiteErrorResponse, SitePatch, UpdateSiteRequest,
+            },
+            user::{
+                AddClientCertificateRequest, AddUserRoleRequest, ApiKeySecret,
+                ChangePasswordRequest, CreateApiKeyRequest, CreateServiceAccountRequest,
+                CreateUserWithRolesRequest, ErrorResponse as UserErrorResponse,
+                RemoveUserRoleRequest, UpdateUserRequest, UserPatch,
+            },
+        },
+        models::*,
+    };
+
+    bindings.add::<User>();
+    bindings.add::<UserInput>();
+    bindings.add::<UserWithRoles>();
+    bindings.add::<UserWithTimestamps>();
+    bindings.add::<UserWithRolesAndTimestamps>();
+
+    bindings.add::<Company>();
+    bindings.add::<CompanyInput>();
+    bindings.add::<CompanyWithTimestamps>();
+
+    bindings.add::<Site>();
+    bindings.add::<SiteVariant>();
+    bindings.add::<SiteInput>();
+    bindings.add::<SiteWithTimestamps>();
+
+    bindings.add::<Device>();
+    bindings.add::<DeviceInput>();
+    bindings.add::<DeviceWithTimestamps>();
+
+    bindings.add::<Role>();
+    bindings.add::<NewRole>();
+
+    // User API types
+    bindings.add::<UserErrorResponse>();
+    bindings.add::<CreateUserWithRolesRequest>();
+    bindings.add::<AddUserRoleRequest>();
+    bindings.add::<RemoveUserRoleRequest>();
+    bindings.add::<UpdateUserRequest>();
+    bindings.add::<UserPatch>();
+    bindings.add::<ChangePasswordRequest>();
+    bindings.add::<ClientCertificate>();
+    bindings.add::<AllowedNetworks>();
+    bindings.add::<AddClientCertificateRequest>();
+    bindings.add::<LoginEvent>();
+    bindings.add::<CreateServiceAccountRequest>();
+    bindings.add::<ApiKey>();
+    bindings.add::<CreateApiKeyRequest>();
+    bindings.add::<ApiKeySecret>();
+
+    // Company API types
+    bindings.add::<CompanyErrorResponse>();
+
+    // Site API types
+    bindings.add::<SiteErrorResponse>();
+    bindings.add::<CreateSiteRequest>();
+    bindings.add::<UpdateSiteRequest>();
+    bindings.add::<SitePatch>();
+
+    // Device API types
+    use crate::api::device::{CreateDeviceRequest, DevicePatch, UpdateDeviceRequest};
+    bindings.add::<CreateDeviceRequest>();
+    bindings.add::<UpdateDeviceRequest>();
+    bindings.add::<DevicePatch>();
+
+    // Login API types
+    bindings.add::<LoginErrorResponse>();
+    bindings.add::<LoginSuccessResponse>();
+    bindings.add::<crate::api::login::LoginRequest>();
+
+    // Status API types
+    use crate::api::status::HealthStatus;
+    bindings.add::<HealthStatus>();
+    use crate::response_cache::CacheStats;
+    bindings.add::<CacheStats>();
+    use crate::api::status::ApiVersions;
+    bindings.add::<ApiVersions>();
+    bindings.add::<crate::api_version::VersionStatus>();
+    bindings.add::<crate::api_version::ApiVersion>();
+    bindings.add::<crate::api_version::RouteDeprecation>();
+
+    // OData service document types
+    bindings.add::<crate::api::odata::ServiceDocument>();
+    bindings.add::<crate::api::odata::EntitySet>();
+    bindings.add::<crate::api::odata::CapabilityScope>();
+    bindings.add::<crate::api::odata::EntitySetCapabilities>();
+    bindings.add::<crate::api::odata::Capabilities>();
+
+    // Version 2 API types
+    use crate::api::v2::user::{CreateUserRequest as CreateUserRequestV2, User as UserV2};
+    bindings.add::<CreateUserRequestV2>();
+    bindings.add::<UserV2>();
+
+    // FixPhrase API types
+    #[cfg(feature = "fixphrase")]
+    use crate::api::fixphrase::FixPhraseResponse;
+    #[cfg(feature = "fixphrase")]
+    bindings.add::<FixPhraseResponse>();
+
+    // Role API types
+    use crate::api::role::UpdateRoleRequest;
+    bindings.add::<UpdateRoleRequest>();
+
+    // Alarm API types
+    use crate::api::alarm::{
+        ActiveAlarmDto, ActiveAlarmsResponse, AlarmAcknowledgeRequest, AlarmCommentRequest,
+        AlarmDefinitionDto, AlarmDefinitionsResponse, AlarmHistoryEntry, AlarmHistoryQuery,
+        AlarmHistoryResponse, AlarmSeverityDto, AlarmShelveRequest, AlarmZoneDto,
+        ForcedAlarmsRequest, ForcedAlarmsResponse,
+    };
+    bindings.add::<AlarmSeverityDto>();
+    bindings.add::<AlarmZoneDto>();
+    bindings.add::<AlarmDefinitionDto>();
+    bindings.add::<ActiveAlarmDto>();
+    bindings.add::<ActiveAlarmsResponse>();
+    bindings.add::<AlarmDefinitionsResponse>();
+    bindings.add::<AlarmHistoryEntry>();
+    bindings.add::<AlarmHistoryResponse>();
+    bindings.add::<AlarmHistoryQuery>();
+    bindings.add::<ForcedAlarmsRequest>();
+    bindings.add::<ForcedAlarmsResponse>();
+    bindings.add::<AlarmAcknowledgeRequest>();
+    bindings.add::<AlarmShelveRequest>();
+    bindings.add::<AlarmCommentRequest>();
+    bindings.add::<crate::models::AlarmState>();
+    bindings.add::<crate::models::AlarmAction>();
+    bindings.add::<crate::models::AlarmEscalation>();
+
+    // Demo API types
+    use crate::api::demo::{InjectHistoryRequest, InjectHistoryResponse, SeedSummary};
+    bindings.add::<InjectHistoryRequest>();
+    bindings.add::<SeedSummary>();
+    bindings.add::<InjectHistoryResponse>();
+
+    // Data API types
+    use crate::api::data::{
+        AggregateResponse, ChargeDischargeBucket, ChargeDischargeSummary, DataSourcesResponse,
+        ReadingsQuery, ReadingsResponse, SocHistoryPoint, SocHistoryResponse,
+    };
+    bindings.add::<DataSourcesResponse>();
+    bindings.add::<ReadingsResponse>();
+    bindings.add::<ReadingsQuery>();
+    bindings.add::<SocHistoryPoint>();
+    bindings.add::<SocHistoryResponse>();
+    bindings.add::<ChargeDischargeBucket>();
+    bindings.add::<ChargeDischargeSummary>();
+    bindings.add::<AggregateResponse>();
+
+    // Source API types
+    use crate::api::source::{
+        CollectorType, CreateSourceRequest, ErrorResponse as SourceErrorResponse,
+        UpdateSourceRequest,
+    };
+    bindings.add::<SourceErrorResponse>();
+    bindings.add::<CollectorType>();
+    bindings.add::<CreateSourceRequest>();
+    bindings.add::<UpdateSourceRequest>();
+
+    // Data runtime API types
+    use crate::api::data_runtime::ErrorResponse as DataRuntimeErrorResponse;
+    bindings.add::<DataRuntimeErrorResponse>();
+
+    // Forwarder API types
+    use crate::api::forwarder::ErrorResponse as ForwarderErrorResponse;
+    bindings.add::<ForwarderErrorResponse>();
+
+    // Edge log API types
+    use crate::api::edge_logs::ErrorResponse as EdgeLogsErrorResponse;
+    bindings.add::<EdgeLogsErrorResponse>();
+
+    // Power-quality API types
+    use crate::api::power_quality::ErrorResponse as PowerQualityErrorResponse;
+    bindings.add::<PowerQualityErrorResponse>();
+
+    // Control latency API types
+    use crate::api::control_latency::ErrorResponse as ControlLatencyErrorResponse;
+    bindings.add::<ControlLatencyErrorResponse>();
+
+    // Maintenance window API types
+    use crate::api::maintenance_window::ErrorResponse as MaintenanceWindowErrorResponse;
+    bindings.add::<MaintenanceWindowErrorResponse>();
+    bindings.add::<crate::models::CommandSource>();
+
+    // Device group API types
+    use crate::api::device_group::ErrorResponse as DeviceGroupErrorResponse;
+    bindings.add::<DeviceGroupErrorResponse>();
+    bindings.add::<crate::models::AllocationStrategy>();
+    bindings.add::<crate::models::DeviceGroup>();
+    bindings.add::<crate::models::DeviceGroupMember>();
+    bindings.add::<crate::models::DeviceGroupRequest>();
+    bindings.add::<crate::models::DeviceGroupMemberRequest>();
+    bindings.add::<crate::models::DeviceGroupTelemetry>();
+    bindings.add::<crate::models::MemberTelemetry>();
+    bindings.add::<crate::models::DispatchRequest>();
+    bindings.add::<crate::models::GroupDispatch>();
+    bindings.add::<crate::models::MemberSetpoint>();
+
+    // KPI API types
+    use crate::api::kpi::ErrorResponse as KpiErrorResponse;
+    bindings.add::<KpiErrorResponse>();
+    bindings.add::<crate::models::KpiDefinition>();
+    bindings.add::<crate::models::KpiDefinitionRequest>();
+    bindings.add::<crate::models::KpiResult>();
+
+    // Notification channel API types
+    use crate::api::notification_channel::ErrorResponse as NotificationChannelErrorResponse;
+    bindings.add::<NotificationChannelErrorResponse>();
+
+    // Escalation policy API types
+    use crate::api::escalation_policy::ErrorResponse as EscalationPolicyErrorResponse;
+    bindings.add::<EscalationPolicyErrorResponse>();
+    bindings.add::<crate::models::EscalationTier>();
+    bindings.add::<crate::models::EscalationPolicy>();
+    bindings.add::<crate::models::EscalationTierRequest>();
+    bindings.add::<crate::models::EscalationPolicyRequest>();
+
+    // On-call API types
+    use crate::api::oncall::ErrorResponse as OnCallErrorResponse;
+    bindings.add::<OnCallErrorResponse>();
+    bindings.add::<crate::models::OnCallMember>();
+    bindings.add::<crate::models::OnCallOverride>();
+    bindings.add::<crate::models::OnCallRotation>();
+    bindings.add::<crate::models::OnCallRotationRequest>();
+    bindings.add::<crate::models::OnCallOverrideRequest>();
+    bindings.add::<crate::models::OnCallShift>();
+
+    // Locale API types
+    use crate::api::locale::ErrorResponse as LocaleErrorResponse;
+    bindings.add::<LocaleErrorResponse>();
+    bindings.add::<neems_data::i18n::Locale>();
+    bindings.add::<crate::models::LocaleInfo>();
+    bindings.add::<crate::models::LocaleRequest>();
+    bindings.add::<crate::models::LocaleSetting>();
+
+    // Blob storage API types
+    use crate::api::blob::ErrorResponse as BlobErrorResponse;
+    bindings.add::<BlobErrorResponse>();
+    bindings.add::<crate::storage::BlobLink>();
+    bindings.add::<crate::api::site_archive::SiteArchive>();
+    use crate::api::site_archive::ErrorResponse as SiteArchiveErrorResponse;
+    bindings.add::<SiteArchiveErrorResponse>();
+    bindings.add::<neems_data::archive::ArchivedTable>();
+
+    // Fleet API types
+    use crate::api::fleet::{
+        Connectivity, FleetLatestReading, FleetLatestReadingsResponse, FleetSiteStatus,
+        FleetStatusResponse,
+    };
+    bindings.add::<Connectivity>();
+    bindings.add::<FleetSiteStatus>();
+    bindings.add::<FleetStatusResponse>();
+    bindings.add::<FleetLatestReading>();
+    bindings.add::<FleetLatestReadingsResponse>();
+
+    // Neems-data model types
+    bindings.add::<neems_data::models::Source>();
+    bindings.add::<neems_data::models::Reading>();
+    bindings.add::<neems_data::points::AggregateBucket>();
+    bindings.add::<neems_data::collectors::SelfTestResult>();
+    bindings.add::<neems_data::points::AggregateBackend>();
+    bindings.add::<neems_data::runtime::RuntimeOverrides>();
+    bindings.add::<neems_data::forward::ForwarderKind>();
+    bindings.add::<neems_data::forward::ForwarderSettings>();
+    bindings.add::<neems_data::forward::ForwarderInfo>();
+    bindings.add::<neems_data::logs::EdgeEndpointSettings>();
+    bindings.add::<neems_data::logs::EdgeEndpointInfo>();
+    bindings.add::<neems_data::logs::LogEntry>();
+    bindings.add::<neems_data::logs::LogPage>();
+    bindings.add::<neems_data::rtac::power_quality::PowerQualityEventKind>();
+    bindings.add::<neems_data::rtac::power_quality::PowerQualitySample>();
+    bindings.add::<neems_data::rtac::power_quality::PowerQualityEvent>();
+    bindings.add::<neems_data::rtac::power_quality::PowerQualityWaveform>();
+    bindings.add::<neems_data::rtac::latency::ControlStage>();
+    bindings.add::<neems_data::rtac::latency::LatencyBucket>();
+    bindings.add::<neems_data::rtac::latency::StageLatency>();
+    bindings.add::<neems_data::rtac::latency::LatencyReport>();
+    bindings.add::<neems_data::notifications::ChannelKind>();
+    bindings.add::<neems_data::notifications::NotificationSeverity>();
+    bindings.add::<neems_data::notifications::ChannelSettings>();
+    bindings.add::<neems_data::notifications::ChannelInfo>();
+
+    bindings.add::<neems_data::maintenance_windows::MaintenanceWindow>();
+    bindings.add::<neems_data::maintenance_windows::NewMaintenanceWindow>();
+
+    // Schedule Library types
+    bindings.add::<CommandType>();
+    bindings.add::<ScheduleCommandDto>();
+    bindings.add::<ScheduleLibraryItem>();
+    bindings.add::<CreateLibraryItemRequest>();
+    bindings.add::<CreateCommandRequest>();
+    bindings.add::<UpdateLibraryItemRequest>();
+    bindings.add::<CloneLibraryItemRequest>();
+    bindings.add::<ScheduleLibraryErrorResponse>();
+    bindings.add::<CreateFromSiteDefaultsRequest>();
+    bindings.add::<ImportErrorResponse>();
+    bindings.add::<crate::schedule_import::ImportLineError>();
+
+    bindings.add::<crate::models::TemplateParameter>();
+    bindings.add::<crate::models::CommandParameterBindings>();
+    bindings.add::<crate::models::InstantiateTemplateRequest>();
+    bindings.add::<crate::models::InstantiateTemplateResponse>();
+    bindings.add::<ActiveScheduleCommand>();
+    bindings.add::<ActiveCommandResponse>();
+
+    // Schedule feed types
+    use crate::api::schedule_feed::{CreateFeedTokenRequest, FeedTokenSecret};
+    bindings.add::<crate::models::ScheduleFeedToken>();
+    bindings.add::<CreateFeedTokenRequest>();
+    bindings.add::<FeedTokenSecret>();
+
+    // Scheduler check and SoC simulation types
+    use crate::api::scheduler::{
+        ErrorResponse as SchedulerErrorResponse, SchedulerCheckRequest, SchedulerCheckResponse,
+        SocSimulationRequest,
+    };
+    bindings.add::<SchedulerCheckRequest>();
+    bindings.add::<SchedulerCheckResponse>();
+    bindings.add::<SchedulerErrorResponse>();
+    bindings.add::<crate::schedule_check::ConflictKind>();
+    bindings.add::<crate::schedule_check::ScheduleConflict>();
+    bindings.add::<SocSimulationRequest>();
+    bindings.add::<crate::soc_simulation::SocSimulation>();
+    bindings.add::<crate::soc_simulation::SocPoint>();
+    bindings.add::<crate::soc_simulation::SocViolation>();
+    bindings.add::<crate::soc_simulation::SocViolationKind>();
+
+    // Economics and tariff types
+    use crate::api::economics::{EconomicsResponse, ErrorResponse as EconomicsErrorResponse};
+    bindings.add::<crate::models::SiteTariff>();
+    bindings.add::<crate::models::SetSiteTariffRequest>();
+    bindings.add::<EconomicsResponse>();
+    bindings.add::<EconomicsErrorResponse>();
+    bindings.add::<crate::economics::EconomicsRow>();
+    bindings.add::<crate::economics::EconomicsPeriod>();
+
+    // Availability API types
+    use crate::api::availability::{
+        AvailabilityResponse, ErrorResponse as AvailabilityErrorResponse,
+    };
+    bindings.add::<AvailabilityResponse>();
+    bindings.add::<AvailabilityErrorResponse>();
+    bindings.add::<crate::availability::AvailabilityRow>();
+    bindings.add::<crate::availability::AvailabilityFigures>();
+    bindings.add::<crate::availability::DeviceAvailability>();
+
+    // Green Button import types
+    bindings.add::<crate::api::green_button::GreenButtonImportResponse>();
+
+    // Reading batch types
+    use crate::api::reading_batch::{
+        BatchReading, BatchReadingError, ErrorResponse as ReadingBatchErrorResponse, ReadingBatch,
+        ReadingBatchResponse,
+    };
+    bindings.add::<BatchReading>();
+    bindings.add::<ReadingBatch>();
+    bindings.add::<BatchReadingError>();
+    bindings.add::<ReadingBatchResponse>();
+    bindings.add::<ReadingBatchErrorResponse>();
+
+    // Scheduler status types
+    bindings.add::<crate::api::scheduler::SchedulerStatusResponse>();
+    bindings.add::<crate::models::SchedulerTick>();
+
+    // Carbon intensity types
+    bindings.add::<crate::api::scheduler::CarbonProfileResponse>();
+    bindings.add::<crate::carbon::CarbonSample>();
+    bindings.add::<crate::carbon::HourlyIntensity>();
+
+    // Entity Activity API types (audit log surface)
+    use crate::api::entity_activity::{
+        EntityActivityWithUser, ErrorResponse as EntityActivityErrorResponse,
+        RecentScheduleActivityEntry, RecentScheduleActivityResponse,
+    };
+    bindings.add::<EntityActivityWithUser>();
+    bindings.add::<EntityActivityErrorResponse>();
+    bindings.add::<EntityActivity>();
+    bindings.add::<ActivityLogEntry>();
+    bindings.add::<RecentScheduleActivityEntry>();
+    bindings.add::<RecentScheduleActivityResponse>();
+
+    // Application Rule types
+    bindings.add::<RuleType>();
+    bindings.add::<crate::models::OverridePriority>();
+    bindings.add::<ApplicationRule>();
+    bindings.add::<CreateApplicationRuleRequest>();
+    bindings.add::<EffectiveScheduleResponse>();
+    bindings.add::<CalendarDaySchedule>();
+    bindings.add::<CalendarScheduleMatch>();
+    bindings.add::<CalendarDayScheduleMatches>();
+    bindings.add::<ApplicationRuleErrorResponse>();
+    bindings.add::<SeasonFillRequest>();
+    bindings.add::<SeasonFillResponse>();
+}
 
 #[cfg(test)]
 mod tests {
     use std::{env, path::Path};
 
-    use ts_rs::TS;
+    use super::*;
 
     #[test]
     fn generate_typescript_types() {
@@ -34,191 +533,36 @@ mod tests {
         };
 
         let output_dir = Path::new(&output_dir_str);
+        let count = export_types(output_dir).expect("Failed to export TypeScript types");
+        println!("{} TypeScript types generated successfully in {:?}", count, output_dir);
+    }
 
-        // Create the output directory if it doesn't exist
-        if !output_dir.exists() {
-            std::fs::create_dir_all(output_dir).expect("Failed to create output directory");
-        }
-
-        // Clean up old TypeScript files to prevent orphaned definitions
-        // This ensures that if a Rust type is removed or renamed, its .ts file is also
-        // removed
-        if output_dir.exists() {
-            println!("Cleaning old TypeScript files from {:?}", output_dir);
-            for entry in std::fs::read_dir(output_dir).expect("Failed to read output directory") {
-                let entry = entry.expect("Failed to read directory entry");
-                let path = entry.path();
-                if path.extension().and_then(|s| s.to_str()) == Some("ts") {
-                    std::fs::remove_file(&path).expect(&format!("Failed to remove {:?}", path));
-                }
-            }
-        }
-
-        // Set the TS_RS_EXPORT_DIR environment variable
-        unsafe {
-            env::set_var("TS_RS_EXPORT_DIR", output_dir);
-        }
+    #[test]
+    fn test_export_is_deterministic() {
+        let dir = env::temp_dir().join(format!("neems-ts-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("Removed.ts"), "export type Removed = string;\n").unwrap();
+        fs::write(dir.join("notes.txt"), "kept").unwrap();
 
-        // Import all the types to trigger their generation
-        use crate::{
-            api::{
-                application_rule::{
-                    ErrorResponse as ApplicationRuleErrorResponse, SeasonFillRequest,
-                    SeasonFillResponse,
-                },
-                company::ErrorResponse as CompanyErrorResponse,
-                login::{ErrorResponse as LoginErrorResponse, LoginSuccessResponse},
-                schedule_library::{
-                    CreateFromSiteDefaultsRequest, ErrorResponse as ScheduleLibraryErrorResponse,
-                },
-                site::{CreateSiteRequest, ErrorResponse as SiteErrorResponse, UpdateSiteRequest},
-                user::{
-                    AddUserRoleRequest, CreateUserWithRolesRequest,
-                    ErrorResponse as UserErrorResponse, RemoveUserRoleRequest, UpdateUserRequest,
-                },
-            },
-            models::*,
+        let count = export_types(&dir).unwrap();
+        let read = |dir: &Path| -> BTreeMap<PathBuf, String> {
+            fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .map(|path| (path.clone(), fs::read_to_string(path).unwrap()))
+                .collect()
         };
+        let first = read(&dir);
+        assert_eq!(first.len(), count + 1);
+        assert!(!dir.join("Removed.ts").exists());
+        assert!(dir.join("notes.txt").exists());
+        for name in ["ScheduleLibraryItem.ts", "ActiveAlarmDto.ts", "CreateSourceRequest.ts"] {
+            assert!(dir.join(name).exists(), "{} missing", name);
+        }
 
-        // Export all the types
-        User::export().expect("Failed to export User type");
-        UserInput::export().expect("Failed to export UserInput type");
-        UserWithRoles::export().expect("Failed to export UserWithRoles type");
-        UserWithTimestamps::export().expect("Failed to export UserWithTimestamps type");
-        UserWithRolesAndTimestamps::export()
-            .expect("Failed to export UserWithRolesAndTimestamps type");
-
-        Company::export().expect("Failed to export Company type");
-        CompanyInput::export().expect("Failed to export CompanyInput type");
-        CompanyWithTimestamps::export().expect("Failed to export CompanyWithTimestamps type");
-
-        Site::export().expect("Failed to export Site type");
-        SiteVariant::export().expect("Failed to export SiteVariant type");
-
-        Role::export().expect("Failed to export Role type");
-        NewRole::export().expect("Failed to export NewRole type");
-
-        // User API types
-        UserErrorResponse::export().expect("Failed to export user::ErrorResponse type");
-        CreateUserWithRolesRequest::export()
-            .expect("Failed to export CreateUserWithRolesRequest type");
-        AddUserRoleRequest::export().expect("Failed to export AddUserRoleRequest type");
-        RemoveUserRoleRequest::export().expect("Failed to export RemoveUserRoleRequest type");
-        UpdateUserRequest::export().expect("Failed to export UpdateUserRequest type");
-
-        // Company API types
-        CompanyErrorResponse::export().expect("Failed to export company::ErrorResponse type");
-
-        // Site API types
-        SiteErrorResponse::export().expect("Failed to export site::ErrorResponse type");
-        CreateSiteRequest::export().expect("Failed to export CreateSiteRequest type");
-        UpdateSiteRequest::export().expect("Failed to export UpdateSiteRequest type");
-
-        // Login API types
-        LoginErrorResponse::export().expect("Failed to export login::ErrorResponse type");
-        LoginSuccessResponse::export().expect("Failed to export LoginSuccessResponse type");
-
-        // Status API types
-        use crate::api::status::HealthStatus;
-        HealthStatus::export().expect("Failed to export HealthStatus type");
-
-        // FixPhrase API types
-        #[cfg(feature = "fixphrase")]
-        use crate::api::fixphrase::FixPhraseResponse;
-        #[cfg(feature = "fixphrase")]
-        FixPhraseResponse::export().expect("Failed to export FixPhraseResponse type");
-
-        // Role API types
-        use crate::api::role::UpdateRoleRequest;
-        UpdateRoleRequest::export().expect("Failed to export UpdateRoleRequest type");
-
-        // Alarm API types
-        use crate::api::alarm::{
-            ActiveAlarmDto, ActiveAlarmsResponse, AlarmDefinitionDto, AlarmDefinitionsResponse,
-            AlarmHistoryEntry, AlarmHistoryQuery, AlarmHistoryResponse, AlarmSeverityDto,
-            AlarmZoneDto, ForcedAlarmsRequest, ForcedAlarmsResponse,
-        };
-        AlarmSeverityDto::export().expect("Failed to export AlarmSeverityDto type");
-        AlarmZoneDto::export().expect("Failed to export AlarmZoneDto type");
-        AlarmDefinitionDto::export().expect("Failed to export AlarmDefinitionDto type");
-        ActiveAlarmDto::export().expect("Failed to export ActiveAlarmDto type");
-        ActiveAlarmsResponse::export().expect("Failed to export ActiveAlarmsResponse type");
-        AlarmDefinitionsResponse::export().expect("Failed to export AlarmDefinitionsResponse type");
-        AlarmHistoryEntry::export().expect("Failed to export AlarmHistoryEntry type");
-        AlarmHistoryResponse::export().expect("Failed to export AlarmHistoryResponse type");
-        AlarmHistoryQuery::export().expect("Failed to export AlarmHistoryQuery type");
-        ForcedAlarmsRequest::export().expect("Failed to export ForcedAlarmsRequest type");
-        ForcedAlarmsResponse::export().expect("Failed to export ForcedAlarmsResponse type");
-
-        // Demo API types
-        use crate::api::demo::{InjectHistoryRequest, InjectHistoryResponse, SeedSummary};
-        InjectHistoryRequest::export().expect("Failed to export InjectHistoryRequest type");
-        SeedSummary::export().expect("Failed to export SeedSummary type");
-        InjectHistoryResponse::export().expect("Failed to export InjectHistoryResponse type");
-
-        // Data API types
-        use crate::api::data::{
-            ChargeDischargeBucket, ChargeDischargeSummary, DataSourcesResponse, ReadingsQuery,
-            ReadingsResponse, SocHistoryPoint, SocHistoryResponse,
-        };
-        DataSourcesResponse::export().expect("Failed to export DataSourcesResponse type");
-        ReadingsResponse::export().expect("Failed to export ReadingsResponse type");
-        ReadingsQuery::export().expect("Failed to export ReadingsQuery type");
-        SocHistoryPoint::export().expect("Failed to export SocHistoryPoint type");
-        SocHistoryResponse::export().expect("Failed to export SocHistoryResponse type");
-        ChargeDischargeBucket::export().expect("Failed to export ChargeDischargeBucket type");
-        ChargeDischargeSummary::export().expect("Failed to export ChargeDischargeSummary type");
-
-        // Neems-data model types
-        neems_data::models::Source::export()
-            .expect("Failed to export neems_data::models::Source type");
-        neems_data::models::Reading::export()
-            .expect("Failed to export neems_data::models::Reading type");
-
-        // Schedule Library types
-        CommandType::export().expect("Failed to export CommandType type");
-        ScheduleCommandDto::export().expect("Failed to export ScheduleCommandDto type");
-        ScheduleLibraryItem::export().expect("Failed to export ScheduleLibraryItem type");
-        CreateLibraryItemRequest::export().expect("Failed to export CreateLibraryItemRequest type");
-        CreateCommandRequest::export().expect("Failed to export CreateCommandRequest type");
-        UpdateLibraryItemRequest::export().expect("Failed to export UpdateLibraryItemRequest type");
-        CloneLibraryItemRequest::export().expect("Failed to export CloneLibraryItemRequest type");
-        ScheduleLibraryErrorResponse::export()
-            .expect("Failed to export schedule_library::ErrorResponse type");
-        CreateFromSiteDefaultsRequest::export()
-            .expect("Failed to export CreateFromSiteDefaultsRequest type");
-
-        // Entity Activity API types (audit log surface)
-        use crate::api::entity_activity::{
-            EntityActivityWithUser, ErrorResponse as EntityActivityErrorResponse,
-            RecentScheduleActivityEntry, RecentScheduleActivityResponse,
-        };
-        EntityActivityWithUser::export().expect("Failed to export EntityActivityWithUser type");
-        EntityActivityErrorResponse::export()
-            .expect("Failed to export entity_activity::ErrorResponse type");
-        EntityActivity::export().expect("Failed to export EntityActivity type");
-        ActivityLogEntry::export().expect("Failed to export ActivityLogEntry type");
-        RecentScheduleActivityEntry::export()
-            .expect("Failed to export RecentScheduleActivityEntry type");
-        RecentScheduleActivityResponse::export()
-            .expect("Failed to export RecentScheduleActivityResponse type");
-
-        // Application Rule types
-        RuleType::export().expect("Failed to export RuleType type");
-        ApplicationRule::export().expect("Failed to export ApplicationRule type");
-        CreateApplicationRuleRequest::export()
-            .expect("Failed to export CreateApplicationRuleRequest type");
-        EffectiveScheduleResponse::export()
-            .expect("Failed to export EffectiveScheduleResponse type");
-        CalendarDaySchedule::export().expect("Failed to export CalendarDaySchedule type");
-        CalendarScheduleMatch::export().expect("Failed to export CalendarScheduleMatch type");
-        CalendarDayScheduleMatches::export()
-            .expect("Failed to export CalendarDayScheduleMatches type");
-        ApplicationRuleErrorResponse::export()
-            .expect("Failed to export application_rule::ErrorResponse type");
-        SeasonFillRequest::export().expect("Failed to export SeasonFillRequest type");
-        SeasonFillResponse::export().expect("Failed to export SeasonFillResponse type");
-
-        println!("TypeScript types generated successfully in {:?}", output_dir);
+        assert_eq!(export_types(&dir).unwrap(), count);
+        assert_eq!(read(&dir), first);
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
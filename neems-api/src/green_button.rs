@@ -0,0 +1,563 @@
+//! Utility meter data import in the Green Button formats.
+//!
+//! Utilities publish customers' interval meter data as Green Button
+//! downloads, either ESPI XML or CSV, and importing them gives baseline and
+//! savings analysis official consumption figures. The format is detected from
+//! the content.
+//!
+//! **XML** (ESPI): every `IntervalReading` is an interval, its `timePeriod`'s
+//! `start` (seconds since the Unix epoch) and `duration`, and its `value` in
+//! the `ReadingType`'s unit (`uom` 72, watt-hours) times ten to its
+//! `powerOfTenMultiplier`. The reading type given before an `IntervalBlock`
+//! applies to it, or the document's first one. `flowDirection` 1 is energy
+//! delivered to the customer and 19 energy received from them; `cost` is in
+//! hundred-thousandths of the currency.
+//!
+//! **CSV**: rows before the header, the first to name `DATE` and `START
+//! TIME` columns, are the utility's account details, and skipped. Then:
+//!
+//! ```text
+//! TYPE,DATE,START TIME,END TIME,USAGE (kWh),COST,NOTES
+//! Electric usage,2026-07-01,00:00,00:14,0.52,$0.07,
+//! ```
+//!
+//! - `DATE` is `YYYY-MM-DD` or `MM/DD/YYYY`, and the times `HH:MM`, `HH:MM:SS`
+//!   or with AM/PM; inclusive end times like `00:14` are read as the end of
+//!   that minute
+//! - `USAGE` or `IMPORT` is the energy delivered, and `EXPORT` the energy
+//!   received; a negative usage was received. Quantities are in kWh unless the
+//!   header (`USAGE (Wh)`) or a `UNITS` column says Wh.
+//! - `COST` is optional, with or without a currency symbol
+//!
+//! CSV times are the utility's local time, given as an offset from UTC;
+//! every row is checked before anything is imported, and the problems
+//! reported with their line.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use quick_xml::{Reader, events::Event};
+
+use crate::schedule_import::{ImportError, ImportLineError, csv_lines};
+
+/// ESPI unit of measure for watt-hours
+const UOM_WATT_HOURS: u32 = 72;
+
+/// ESPI flow directions
+const FLOW_FORWARD: u32 = 1;
+const FLOW_REVERSE: u32 = 19;
+
+/// ESPI costs are in hundred-thousandths of the currency
+const COST_SCALE: f64 = 100_000.0;
+
+/// One metering interval: the energy delivered to and received from the
+/// site over it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeterInterval {
+    /// Start of the interval, in UTC
+    pub start: NaiveDateTime,
+    pub duration_seconds: i64,
+    pub delivered_kwh: f64,
+    pub received_kwh: f64,
+    pub cost: Option<f64>,
+}
+
+impl MeterInterval {
+    /// End of the interval, in UTC
+    pub fn end(&self) -> NaiveDateTime {
+        self.start + Duration::seconds(self.duration_seconds)
+    }
+}
+
+/// Parse a Green Button XML or CSV file into its intervals, in time order.
+/// `utc_offset_minutes` is the utility's offset from UTC, for CSV times.
+pub fn parse_green_button(
+    bytes: &[u8],
+    utc_offset_minutes: i32,
+) -> Result<Vec<MeterInterval>, ImportError> {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    let intervals = if bytes.trim_ascii_start().starts_with(b"<") {
+        xml_intervals(bytes)?
+    } else {
+        csv_intervals(bytes, utc_offset_minutes)?
+    };
+    if intervals.is_empty() {
+        return Err(ImportError::Unreadable("The file has no interval readings".to_string()));
+    }
+    Ok(merge(intervals))
+}
+
+/// Combine the intervals reported more than once, like delivered and
+/// received energy in separate blocks.
+fn merge(intervals: Vec<MeterInterval>) -> Vec<MeterInterval> {
+    let mut merged: BTreeMap<(NaiveDateTime, i64), MeterInterval> = BTreeMap::new();
+    for interval in intervals {
+        match merged.get_mut(&(interval.start, interval.duration_seconds)) {
+            Some(existing) => {
+                existing.delivered_kwh += interval.delivered_kwh;
+                existing.received_kwh += interval.received_kwh;
+                existing.cost = match (existing.cost, interval.cost) {
+                    (None, None) => None,
+                    (a, b) => Some(a.unwrap_or(0.0) + b.unwrap_or(0.0)),
+                };
+            }
+            None => {
+                merged.insert((interval.start, interval.duration_seconds), interval);
+            }
+        }
+    }
+    merged.into_values().collect()
+}
+
+/// An ESPI `ReadingType`: the unit and direction of interval values.
+#[derive(Debug, Clone, Copy)]
+struct ReadingType {
+    power_of_ten_multiplier: i32,
+    uom: u32,
+    flow_direction: u32,
+}
+
+impl Default for ReadingType {
+    fn default() -> Self {
+        ReadingType {
+            power_of_ten_multiplier: 0,
+            uom: UOM_WATT_HOURS,
+            flow_direction: FLOW_FORWARD,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct RawReading {
+    start: Option<i64>,
+    duration: Option<i64>,
+    value: Option<f64>,
+    cost: Option<f64>,
+}
+
+/// An `IntervalBlock`'s readings, and the reading type given before it.
+struct Block {
+    reading_type: Option<usize>,
+    readings: Vec<RawReading>,
+}
+
+fn xml_intervals(bytes: &[u8]) -> Result<Vec<MeterInterval>, ImportError> {
+    let unreadable =
+        |e: &dyn std::fmt::Display| ImportError::Unreadable(format!("Invalid XML: {}", e));
+    let text = std::str::from_utf8(bytes).map_err(|e| unreadable(&e))?;
+    let mut reader = Reader::from_str(text);
+    reader.config_mut().trim_text(true);
+
+    let mut path: Vec<String> = Vec::new();
+    let mut reading_types: Vec<ReadingType> = Vec::new();
+    let mut reading_type = ReadingType::default();
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut reading = RawReading::default();
+
+    loop {
+        match reader.read_event().map_err(|e| unreadable(&e))? {
+            Event::Start(e) => {
+                let name = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+                match name.as_str() {
+                    "ReadingType" => reading_type = ReadingType::default(),
+                    "IntervalBlock" => blocks.push(Block {
+                        reading_type: reading_types.len().checked_sub(1),
+                        readings: Vec::new(),
+                    }),
+                    "IntervalReading" => reading = RawReading::default(),
+                    _ => {}
+                }
+                path.push(name);
+            }
+            Event::End(_) => match path.pop().as_deref() {
+                Some("ReadingType") => reading_types.push(reading_type),
+                Some("IntervalReading") => match blocks.last_mut() {
+                    Some(block) => block.readings.push(std::mem::take(&mut reading)),
+                    None => {
+                        return Err(ImportError::Unreadable(
+                            "An IntervalReading is outside an IntervalBlock".to_string(),
+                        ));
+                    }
+                },
+                _ => {}
+            },
+            Event::Text(e) => {
+                let value = e.decode().map_err(|e| unreadable(&e))?;
+                let value = value.trim();
+                let within = |parent: &str, field: &str| {
+                    path.len() >= 2
+                        && path[path.len() - 2] == parent
+                        && path[path.len() - 1] == field
+                };
+                let in_reading = path.iter().any(|p| p == "IntervalReading");
+                let number = |what: &str| {
+                    value.parse::<f64>().map_err(|_| {
+                        ImportError::Unreadable(format!("{} '{}' isn't a number", what, value))
+                    })
+                };
+                if within("ReadingType", "powerOfTenMultiplier") {
+                    reading_type.power_of_ten_multiplier = number("powerOfTenMultiplier")? as i32;
+                } else if within("ReadingType", "uom") {
+                    reading_type.uom = number("uom")? as u32;
+                } else if within("ReadingType", "flowDirection") {
+                    reading_type.flow_direction = number("flowDirection")? as u32;
+                } else if in_reading && within("timePeriod", "start") {
+                    reading.start = Some(number("start")? as i64);
+                } else if in_reading && within("timePeriod", "duration") {
+                    reading.duration = Some(number("duration")? as i64);
+                } else if within("IntervalReading", "value") {
+                    reading.value = Some(number("value")?);
+                } else if within("IntervalReading", "cost") {
+                    reading.cost = Some(number("cost")?);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    let mut intervals = Vec::new();
+    for block in blocks {
+        let reading_type = block
+            .reading_type
+            .or((!reading_types.is_empty()).then_some(0))
+            .map_or_else(ReadingType::default, |i| reading_types[i]);
+        if reading_type.uom != UOM_WATT_HOURS {
+            return Err(ImportError::Unreadable(format!(
+                "Readings in unit {} aren't energy (watt-hours, unit 72)",
+                reading_type.uom
+            )));
+        }
+        let scale = 10f64.powi(reading_type.power_of_ten_multiplier) / 1000.0;
+        for raw in block.readings {
+            let (Some(start), Some(duration), Some(value)) = (raw.start, raw.duration, raw.value)
+            else {
+                return Err(ImportError::Unreadable(
+                    "An IntervalReading is missing its start, duration or value".to_string(),
+                ));
+            };
+            let start = DateTime::from_timestamp(start, 0)
+                .ok_or_else(|| ImportError::Unreadable(format!("Invalid start {}", start)))?
+                .naive_utc();
+            let kwh = value * scale;
+            let (delivered_kwh, received_kwh) = match reading_type.flow_direction {
+                FLOW_FORWARD => (kwh, 0.0),
+                FLOW_REVERSE => (0.0, kwh),
+                other => {
+                    return Err(ImportError::Unreadable(format!(
+                        "Flow direction {} isn't delivered (1) or received (19)",
+                        other
+                    )));
+                }
+            };
+            intervals.push(MeterInterval {
+                start,
+                duration_seconds: duration,
+                delivered_kwh,
+                received_kwh,
+                cost: raw.cost.map(|c| c / COST_SCALE),
+            });
+        }
+    }
+    Ok(intervals)
+}
+
+/// A CSV header's name, and its unit in parentheses, if any.
+fn column(header: &str) -> (String, Option<String>) {
+    let lower = header.to_lowercase();
+    match lower.split_once('(') {
+        Some((name, unit)) => {
+            (name.trim().to_string(), Some(unit.trim_end_matches(')').trim().to_string()))
+        }
+        None => (lower.trim().to_string(), None),
+    }
+}
+
+/// kWh per unit, for the units meter data comes in.
+fn kwh_per_unit(unit: &str) -> Option<f64> {
+    match unit.to_lowercase().as_str() {
+        "kwh" => Some(1.0),
+        "wh" => Some(0.001),
+        _ => None,
+    }
+}
+
+fn parse_date(text: &str) -> Option<NaiveDate> {
+    ["%Y-%m-%d", "%m/%d/%Y"]
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(text, format).ok())
+}
+
+fn parse_time(text: &str) -> Option<NaiveTime> {
+    ["%H:%M", "%H:%M:%S", "%I:%M %p", "%I:%M:%S %p"]
+        .iter()
+        .find_map(|format| NaiveTime::parse_from_str(text, format).ok())
+}
+
+/// A number, with any currency symbol and thousands separators.
+fn parse_quantity(text: &str) -> Option<f64> {
+    let (sign, text) = match text.strip_prefix('-') {
+        Some(rest) => (-1.0, rest),
+        None => (1.0, text),
+    };
+    let text = text.trim_start_matches(['$', '€', '£']).replace(',', "");
+    text.trim().parse::<f64>().ok().map(|n| sign * n)
+}
+
+fn csv_intervals(bytes: &[u8], utc_offset_minutes: i32) -> Result<Vec<MeterInterval>, ImportError> {
+    let lines = csv_lines(bytes)?;
+    let position = |columns: &[(String, Option<String>)], names: &[&str]| {
+        columns.iter().position(|(n, _)| names.contains(&n.as_str()))
+    };
+    let header = lines.iter().enumerate().find_map(|(i, (_, fields))| {
+        let columns: Vec<(String, Option<String>)> = fields.iter().map(|f| column(f)).collect();
+        let date_col = position(&columns, &["date"])?;
+        let start_col = position(&columns, &["start time"])?;
+        Some((i, columns, date_col, start_col))
+    });
+    let Some((header_index, columns, date_col, start_col)) = header else {
+        return Err(ImportError::Unreadable(
+            "The file has no header row naming DATE and START TIME columns".to_string(),
+        ));
+    };
+
+    let find = |names: &[&str]| position(&columns, names);
+    let end_col = find(&["end time"]);
+    let delivered_col = find(&["usage", "import"]);
+    let received_col = find(&["export"]);
+    let units_col = find(&["units"]);
+    let cost_col = find(&["cost"]);
+    if delivered_col.is_none() && received_col.is_none() {
+        return Err(ImportError::Unreadable(
+            "The header names no USAGE, IMPORT or EXPORT column".to_string(),
+        ));
+    }
+    let Some(end_col) = end_col else {
+        return Err(ImportError::Unreadable("The header names no END TIME column".to_string()));
+    };
+
+    let offset = Duration::minutes(utc_offset_minutes as i64);
+    let mut intervals = Vec::new();
+    let mut errors = Vec::new();
+    for (line, fields) in &lines[header_index + 1..] {
+        if fields.iter().all(|f| f.is_empty()) {
+            continue;
+        }
+        let cell = |col: usize| fields.get(col).map(String::as_str).unwrap_or("");
+        let mut line_error = |error: String| errors.push(ImportLineError { line: *line, error });
+
+        let Some(date) = parse_date(cell(date_col)) else {
+            line_error(format!("date '{}' isn't YYYY-MM-DD or MM/DD/YYYY", cell(date_col)));
+            continue;
+        };
+        let (Some(start), Some(end)) = (parse_time(cell(start_col)), parse_time(cell(end_col)))
+        else {
+            line_error(format!("times '{}' to '{}' aren't HH:MM", cell(start_col), cell(end_col)));
+            continue;
+        };
+        let mut duration_seconds = (end - start).num_seconds();
+        if duration_seconds <= 0 {
+            duration_seconds += 24 * 3600;
+        }
+        // Utilities often give inclusive end times, like 00:00 to 00:14
+        if duration_seconds % 300 == 240 {
+            duration_seconds += 60;
+        }
+
+        let quantity = |col: Option<usize>| -> Result<f64, String> {
+            let Some(col) = col else {
+                return Ok(0.0);
+            };
+            let unit = units_col
+                .map(|u| cell(u).to_string())
+                .filter(|u| !u.is_empty())
+                .or_else(|| columns[col].1.clone())
+                .unwrap_or_else(|| "kwh".to_string());
+            let per_unit =
+                kwh_per_unit(&unit).ok_or_else(|| format!("units '{}' aren't kWh or Wh", unit))?;
+            match cell(col) {
+                "" => Ok(0.0),
+                text => parse_quantity(text)
+                    .map(|q| q * per_unit)
+                    .ok_or_else(|| format!("'{}' isn't a quantity", text)),
+            }
+        };
+        let (delivered, received) = match (quantity(delivered_col), quantity(received_col)) {
+            (Ok(d), Ok(r)) => (d, r),
+            (Err(e), _) | (_, Err(e)) => {
+                line_error(e);
+                continue;
+            }
+        };
+        // Net usage below zero was received
+        let (delivered_kwh, received_kwh) = if delivered < 0.0 {
+            (0.0, received - delivered)
+        } else {
+            (delivered, received)
+        };
+
+        intervals.push(MeterInterval {
+            start: date.and_time(start) - offset,
+            duration_seconds,
+            delivered_kwh,
+            received_kwh,
+            cost: cost_col.map(cell).and_then(parse_quantity),
+        });
+    }
+
+    if errors.is_empty() {
+        Ok(intervals)
+    } else {
+        Err(ImportError::Lines(errors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 7, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    const ESPI: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xmlns:espi="http://naesb.org/espi">
+  <entry>
+    <content>
+      <espi:ReadingType>
+        <espi:flowDirection>1</espi:flowDirection>
+        <espi:powerOfTenMultiplier>0</espi:powerOfTenMultiplier>
+        <espi:uom>72</espi:uom>
+      </espi:ReadingType>
+    </content>
+  </entry>
+  <entry>
+    <content>
+      <espi:IntervalBlock>
+        <espi:interval><espi:duration>7200</espi:duration><espi:start>1782864000</espi:start></espi:interval>
+        <espi:IntervalReading>
+          <espi:cost>12500</espi:cost>
+          <espi:timePeriod><espi:duration>3600</espi:duration><espi:start>1782864000</espi:start></espi:timePeriod>
+          <espi:value>974</espi:value>
+        </espi:IntervalReading>
+        <espi:IntervalReading>
+          <espi:timePeriod><espi:duration>3600</espi:duration><espi:start>1782867600</espi:start></espi:timePeriod>
+          <espi:value>1200</espi:value>
+        </espi:IntervalReading>
+      </espi:IntervalBlock>
+    </content>
+  </entry>
+  <entry>
+    <content>
+      <espi:ReadingType>
+        <espi:flowDirection>19</espi:flowDirection>
+        <espi:powerOfTenMultiplier>3</espi:powerOfTenMultiplier>
+        <espi:uom>72</espi:uom>
+      </espi:ReadingType>
+    </content>
+  </entry>
+  <entry>
+    <content>
+      <espi:IntervalBlock>
+        <espi:IntervalReading>
+          <espi:timePeriod><espi:duration>3600</espi:duration><espi:start>1782867600</espi:start></espi:timePeriod>
+          <espi:value>2</espi:value>
+        </espi:IntervalReading>
+      </espi:IntervalBlock>
+    </content>
+  </entry>
+</feed>"#;
+
+    fn line_errors(csv: &str) -> Vec<(usize, String)> {
+        match parse_green_button(csv.as_bytes(), 0) {
+            Err(ImportError::Lines(errors)) => {
+                errors.into_iter().map(|e| (e.line, e.error)).collect()
+            }
+            other => panic!("expected line errors, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_espi_xml() {
+        let intervals = parse_green_button(ESPI.as_bytes(), 0).unwrap();
+        assert_eq!(intervals.len(), 2);
+        // 2026-07-01 00:00 UTC
+        assert_eq!(intervals[0].start, at(1, 0, 0));
+        assert_eq!(intervals[0].duration_seconds, 3600);
+        assert_eq!(intervals[0].delivered_kwh, 0.974);
+        assert_eq!(intervals[0].cost, Some(0.125));
+        // Delivered in one block and received, in kWh, in another
+        assert_eq!(intervals[1].start, at(1, 1, 0));
+        assert_eq!(intervals[1].delivered_kwh, 1.2);
+        assert_eq!(intervals[1].received_kwh, 2.0);
+        assert_eq!(intervals[1].end(), at(1, 2, 0));
+    }
+
+    #[test]
+    fn test_xml_rejects_power_readings() {
+        let watts = ESPI.replace("<espi:uom>72</espi:uom>", "<espi:uom>38</espi:uom>");
+        let Err(ImportError::Unreadable(e)) = parse_green_button(watts.as_bytes(), 0) else {
+            panic!("expected an error");
+        };
+        assert!(e.contains("unit 38"), "{}", e);
+        assert!(matches!(
+            parse_green_button(b"<feed><entry>", 0),
+            Err(ImportError::Unreadable(_))
+        ));
+    }
+
+    #[test]
+    fn test_utility_csv() {
+        let csv = "Name,Jane Doe\n\
+                   Address,\"1 Main St, Springfield\"\n\
+                   \n\
+                   TYPE,DATE,START TIME,END TIME,USAGE (kWh),COST,NOTES\n\
+                   Electric usage,2026-07-01,00:00,00:14,0.52,$0.07,\n\
+                   Electric usage,2026-07-01,00:15,00:29,-0.25,$0.00,\n\
+                   Electric usage,07/01/2026,11:45 PM,11:59 PM,1.5,,\n";
+        let intervals = parse_green_button(csv.as_bytes(), -420).unwrap();
+        assert_eq!(intervals.len(), 3);
+        // Local 00:00 at UTC-7 is 07:00 UTC
+        assert_eq!(intervals[0].start, at(1, 7, 0));
+        assert_eq!(intervals[0].duration_seconds, 900);
+        assert_eq!((intervals[0].delivered_kwh, intervals[0].cost), (0.52, Some(0.07)));
+        assert_eq!((intervals[1].delivered_kwh, intervals[1].received_kwh), (0.0, 0.25));
+        assert_eq!(intervals[2].start, at(2, 6, 45));
+    }
+
+    #[test]
+    fn test_import_export_csv_in_watt_hours() {
+        let csv = "DATE,START TIME,END TIME,IMPORT,EXPORT,UNITS\n\
+                   2026-07-01,00:00,01:00,1500,250,Wh\n\
+                   2026-07-01,23:00,00:00,1000,0,Wh\n";
+        let intervals = parse_green_button(csv.as_bytes(), 0).unwrap();
+        assert_eq!((intervals[0].delivered_kwh, intervals[0].received_kwh), (1.5, 0.25));
+        // Ending at midnight
+        assert_eq!(intervals[1].duration_seconds, 3600);
+    }
+
+    #[test]
+    fn test_csv_line_errors() {
+        let csv = "TYPE,DATE,START TIME,END TIME,USAGE,UNITS\n\
+                   Electric usage,2026-13-01,00:00,00:14,0.52,kWh\n\
+                   Gas usage,2026-07-01,00:00,23:59,2,therms\n\
+                   Electric usage,2026-07-01,noon,00:14,0.52,kWh\n\
+                   Electric usage,2026-07-01,00:00,00:14,lots,kWh\n";
+        assert_eq!(
+            line_errors(csv),
+            vec![
+                (2, "date '2026-13-01' isn't YYYY-MM-DD or MM/DD/YYYY".to_string()),
+                (3, "units 'therms' aren't kWh or Wh".to_string()),
+                (4, "times 'noon' to '00:14' aren't HH:MM".to_string()),
+                (5, "'lots' isn't a quantity".to_string()),
+            ]
+        );
+        assert!(matches!(
+            parse_green_button(b"date,time,usage\n2026-07-01,00:00,1\n", 0),
+            Err(ImportError::Unreadable(_))
+        ));
+    }
+}
@@ -0,0 +1,123 @@
+//! Localized error messages and reports.
+//!
+//! The catalog of translations is neems-data's [`neems_data::i18n`]. A
+//! request's locale is the signed-in user's own preference, else their
+//! company's, else the best match for its `Accept-Language` header, else
+//! English; see [`RequestLocale`]. Handlers producing localized text (e.g.
+//! the Economics CSV headers) take it as a guard.
+//!
+//! Error messages are translated after the fact by the [`I18n`] fairing:
+//! a JSON error response whose top-level `error` string is in the catalog
+//! has it replaced with the translation, so endpoints and catchers keep
+//! writing English. Messages the catalog lacks, such as validation problems
+//! naming fields, stay in English.
+
+use std::io::Cursor;
+
+use neems_data::i18n::{Locale, translate};
+use rocket::{
+    Request, Response,
+    fairing::{Fairing, Info, Kind},
+    http::{ContentType, Header},
+    outcome::Outcome,
+    request::{self, FromRequest},
+};
+use serde_json::Value;
+
+use crate::{DbConn, orm::locale::get_preferred_locale, session_guards::PasswordChangeUser};
+
+/// The locale to respond in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestLocale(pub Locale);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestLocale {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let preferred = match request.guard::<PasswordChangeUser>().await {
+            Outcome::Success(PasswordChangeUser(auth_user)) => {
+                match request.guard::<DbConn>().await {
+                    Outcome::Success(db) => {
+                        let user_id = auth_user.user.id;
+                        db.run(move |conn| get_preferred_locale(conn, user_id))
+                            .await
+                            .unwrap_or_else(|e| {
+                                error!("Database error finding locale: {:?}", e);
+                                None
+                            })
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        let locale = preferred
+            .or_else(|| request.headers().get_one("Accept-Language").and_then(Locale::negotiate))
+            .unwrap_or_default();
+        Outcome::Success(RequestLocale(locale))
+    }
+}
+
+/// Translates JSON error messages into the request's locale.
+pub struct I18n;
+
+#[rocket::async_trait]
+impl Fairing for I18n {
+    fn info(&self) -> Info {
+        Info {
+            name: "Error Message Translation",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if response.status().code < 400
+            || response.content_type() != Some(ContentType::JSON)
+            || response.body().preset_size().is_none()
+        {
+            return;
+        }
+        let body = match response.body_mut().to_bytes().await {
+            Ok(body) => body,
+            Err(e) => {
+                error!("Error reading response body to translate: {}", e);
+                return;
+            }
+        };
+        let mut json: Value = match serde_json::from_slice(&body) {
+            Ok(json) => json,
+            Err(_) => {
+                response.set_sized_body(body.len(), Cursor::new(body));
+                return;
+            }
+        };
+        let in_catalog = json
+            .get("error")
+            .and_then(Value::as_str)
+            .is_some_and(|message| translate(Locale::En, message).is_some());
+        if !in_catalog {
+            response.set_sized_body(body.len(), Cursor::new(body));
+            return;
+        }
+
+        // The message now depends on the user and Accept-Language
+        response.adjoin_header(Header::new("Vary", "Accept-Language"));
+        let RequestLocale(locale) = match request.guard::<RequestLocale>().await {
+            Outcome::Success(locale) => locale,
+            _ => RequestLocale(Locale::En),
+        };
+        response.set_header(Header::new("Content-Language", locale.as_str()));
+        if locale == Locale::En {
+            response.set_sized_body(body.len(), Cursor::new(body));
+            return;
+        }
+        if let Some(message) = json.get("error").and_then(Value::as_str)
+            && let Some(translated) = translate(locale, message)
+        {
+            json["error"] = Value::String(translated.to_string());
+        }
+        let body = json.to_string();
+        response.set_sized_body(body.len(), Cursor::new(body));
+    }
+}
@@ -0,0 +1,193 @@
+//! Minimal iCalendar (RFC 5545) writer for schedule feeds.
+//!
+//! Only what the feeds need: a calendar of events with UTC or all-day
+//! times, a summary, an optional description and categories. Text is
+//! escaped and long lines folded at 75 octets as the RFC requires, so
+//! calendar apps accept site and schedule names with commas, semicolons
+//! or accents.
+
+use chrono::{NaiveDate, NaiveDateTime};
+
+/// Octets a content line may have before it's folded
+const MAX_LINE_OCTETS: usize = 75;
+
+/// When an event starts or ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventTime {
+    /// A UTC date and time
+    Utc(NaiveDateTime),
+    /// A whole day; an all-day event ends on the day after its last
+    Date(NaiveDate),
+}
+
+impl EventTime {
+    fn property(&self, name: &str) -> String {
+        match self {
+            EventTime::Utc(at) => format!("{}:{}", name, at.format("%Y%m%dT%H%M%SZ")),
+            EventTime::Date(day) => format!("{};VALUE=DATE:{}", name, day.format("%Y%m%d")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// Identifies the event across refreshes, so apps update rather than
+    /// duplicate it
+    pub uid: String,
+    pub start: EventTime,
+    pub end: EventTime,
+    pub summary: String,
+    pub description: Option<String>,
+    pub categories: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Calendar {
+    /// Shown by apps as the subscription's name
+    pub name: String,
+    pub events: Vec<Event>,
+}
+
+impl Calendar {
+    /// The calendar as an `.ics` document, with `stamp` (UTC) as every
+    /// event's DTSTAMP.
+    pub fn render(&self, stamp: NaiveDateTime) -> String {
+        let stamp = EventTime::Utc(stamp).property("DTSTAMP");
+        let mut lines = vec![
+            "BEGIN:VCALENDAR".to_string(),
+            "VERSION:2.0".to_string(),
+            "PRODID:-//Newtown Energy//NEEMS//EN".to_string(),
+            "CALSCALE:GREGORIAN".to_string(),
+            "METHOD:PUBLISH".to_string(),
+            format!("X-WR-CALNAME:{}", escape_text(&self.name)),
+        ];
+        for event in &self.events {
+            lines.push("BEGIN:VEVENT".to_string());
+            lines.push(format!("UID:{}", escape_text(&event.uid)));
+            lines.push(stamp.clone());
+            lines.push(event.start.property("DTSTART"));
+            lines.push(event.end.property("DTEND"));
+            lines.push(format!("SUMMARY:{}", escape_text(&event.summary)));
+            if let Some(description) = &event.description {
+                lines.push(format!("DESCRIPTION:{}", escape_text(description)));
+            }
+            if !event.categories.is_empty() {
+                let categories: Vec<String> =
+                    event.categories.iter().map(|c| escape_text(c)).collect();
+                lines.push(format!("CATEGORIES:{}", categories.join(",")));
+            }
+            lines.push("END:VEVENT".to_string());
+        }
+        lines.push("END:VCALENDAR".to_string());
+
+        let mut document = String::new();
+        for line in lines {
+            fold_line(&mut document, &line);
+        }
+        document
+    }
+}
+
+/// Escape a TEXT value: backslashes, commas, semicolons and newlines.
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ',' => escaped.push_str("\\,"),
+            ';' => escaped.push_str("\\;"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Append `line` to `document` with CRLF, folding it onto continuation
+/// lines (starting with a space) without splitting a UTF-8 character.
+fn fold_line(document: &mut String, line: &str) {
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            document.push_str("\r\n ");
+            // The leading space counts towards the continuation line
+            octets = 1;
+        }
+        document.push(c);
+        octets += c.len_utf8();
+    }
+    document.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 10, 17)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_render_events() {
+        let day = NaiveDate::from_ymd_opt(2026, 10, 17).unwrap();
+        let calendar = Calendar {
+            name: "Site 1 schedule".to_string(),
+            events: vec![
+                Event {
+                    uid: "1-20261017-5@neems".to_string(),
+                    start: EventTime::Utc(at(6, 0)),
+                    end: EventTime::Utc(at(10, 30)),
+                    summary: "Charge at 250 kW".to_string(),
+                    description: None,
+                    categories: vec!["Charge".to_string()],
+                },
+                Event {
+                    uid: "1-20261017-override@neems".to_string(),
+                    start: EventTime::Date(day),
+                    end: EventTime::Date(day.succ_opt().unwrap()),
+                    summary: "Override: Holiday".to_string(),
+                    description: Some("Plant closed; no peak".to_string()),
+                    categories: vec!["Override".to_string()],
+                },
+            ],
+        };
+
+        let document = calendar.render(at(12, 0));
+        assert!(document.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(document.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(document.matches("BEGIN:VEVENT").count(), 2);
+        assert!(document.contains("\r\nDTSTAMP:20261017T120000Z\r\n"));
+        assert!(document.contains("\r\nDTSTART:20261017T060000Z\r\nDTEND:20261017T103000Z\r\n"));
+        assert!(document.contains("\r\nDTSTART;VALUE=DATE:20261017\r\n"));
+        assert!(document.contains("\r\nDTEND;VALUE=DATE:20261018\r\n"));
+        assert!(document.contains("\r\nDESCRIPTION:Plant closed\\; no peak\r\n"));
+        assert!(document.contains("\r\nCATEGORIES:Override\r\n"));
+    }
+
+    #[test]
+    fn test_escape_text() {
+        assert_eq!(escape_text("a,b;c\\d\r\ne"), "a\\,b\\;c\\\\d\\ne");
+    }
+
+    #[test]
+    fn test_long_lines_are_folded() {
+        let mut document = String::new();
+        let line = format!("SUMMARY:{}", "é".repeat(60));
+        fold_line(&mut document, &line);
+
+        let lines: Vec<&str> = document.trim_end_matches("\r\n").split("\r\n").collect();
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|l| l.len() <= MAX_LINE_OCTETS));
+        assert!(lines[1..].iter().all(|l| l.starts_with(' ')));
+        let unfolded: String = lines
+            .iter()
+            .enumerate()
+            .map(|(i, l)| if i == 0 { *l } else { &l[1..] })
+            .collect();
+        assert_eq!(unfolded, line);
+    }
+}
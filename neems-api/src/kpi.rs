@@ -0,0 +1,482 @@
+//! Customer-defined KPIs: arithmetic over a site's day.
+//!
+//! A KPI's expression combines numbers with `+`, `-`, `*`, `/` and
+//! parentheses, and two kinds of input from one UTC day:
+//!
+//! - **Economics**: the day's [`EconomicsRow`] fields by name, such as
+//!   `discharged_kwh`, `round_trip_efficiency_percent` or `arbitrage_revenue`
+//!   (see [`ECONOMICS_VARIABLES`]), plus `readings`, the number of readings,
+//!   and `covered_hours`, the number of hours with at least one reading
+//! - **Reading aggregates**: `avg(field)`, `min(field)`, `max(field)`,
+//!   `sum(field)` and `count(field)` over the day's readings of all the site's
+//!   sources that report `field` in their data. A dotted field reaches into
+//!   nested objects, and `true`/`false` count as 1/0, so `100 * avg(online)` is
+//!   the share of readings that were online
+//!
+//! `100 * covered_hours / 24` is the site's data availability as a percentage.
+//! An expression can't be evaluated when an input it uses is absent (no
+//! readings of a field, a money field without a tariff) or it divides by
+//! zero; the reason is recorded in place of a value.
+
+use std::collections::BTreeSet;
+
+use chrono::{NaiveDateTime, Timelike};
+
+use crate::economics::EconomicsRow;
+
+/// Longest expression accepted
+pub const MAX_EXPRESSION_LENGTH: usize = 500;
+
+/// The day's economics, by name.
+pub const ECONOMICS_VARIABLES: &[&str] = &[
+    "charged_kwh",
+    "discharged_kwh",
+    "round_trip_efficiency_percent",
+    "charge_cost",
+    "discharge_value",
+    "arbitrage_revenue",
+    "peak_demand_kw",
+    "peak_net_demand_kw",
+    "demand_charge_savings",
+    "charge_emissions_kg",
+    "avoided_emissions_kg",
+];
+
+/// Variables that come from the readings themselves.
+const READING_VARIABLES: &[&str] = &["readings", "covered_hours"];
+
+/// How a field's values over the day's readings are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    Avg,
+    Min,
+    Max,
+    Sum,
+    Count,
+}
+
+impl Aggregate {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "avg" => Some(Aggregate::Avg),
+            "min" => Some(Aggregate::Min),
+            "max" => Some(Aggregate::Max),
+            "sum" => Some(Aggregate::Sum),
+            "count" => Some(Aggregate::Count),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+/// A parsed KPI expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Variable(String),
+    Aggregate(Aggregate, String),
+    Negate(Box<Expr>),
+    Binary(Operator, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Operator(Operator),
+    Open,
+    Close,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Number(n) => format!("number {}", n),
+            Token::Name(name) => format!("'{}'", name),
+            Token::Operator(_) => "operator".to_string(),
+            Token::Open => "'('".to_string(),
+            Token::Close => "')'".to_string(),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(at, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = at;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_digit() || c == '.') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let text = &source[at..end];
+            let number = text.parse().map_err(|_| format!("Invalid number '{}'", text))?;
+            tokens.push(Token::Number(number));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut end = at;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_' || c == '.') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token::Name(source[at..end].to_string()));
+        } else {
+            tokens.push(match c {
+                '+' => Token::Operator(Operator::Add),
+                '-' => Token::Operator(Operator::Subtract),
+                '*' => Token::Operator(Operator::Multiply),
+                '/' => Token::Operator(Operator::Divide),
+                '(' => Token::Open,
+                ')' => Token::Close,
+                _ => return Err(format!("Unexpected '{}' at position {}", c, at + 1)),
+            });
+            chars.next();
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens: sums of products of unary terms.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expression(&mut self) -> Result<Expr, String> {
+        let mut left = self.term()?;
+        while let Some(Token::Operator(op @ (Operator::Add | Operator::Subtract))) = self.peek() {
+            let op = *op;
+            self.next();
+            left = Expr::Binary(op, Box::new(left), Box::new(self.term()?));
+        }
+        Ok(left)
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while let Some(Token::Operator(op @ (Operator::Multiply | Operator::Divide))) = self.peek()
+        {
+            let op = *op;
+            self.next();
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if let Some(Token::Operator(Operator::Subtract)) = self.peek() {
+            self.next();
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Open) => {
+                let inner = self.expression()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(inner),
+                    _ => Err("Missing ')'".to_string()),
+                }
+            }
+            Some(Token::Name(name)) if self.peek() == Some(&Token::Open) => {
+                self.next();
+                let aggregate = Aggregate::from_name(&name).ok_or_else(|| {
+                    format!("Unknown function '{}'; expected avg, min, max, sum or count", name)
+                })?;
+                let field = match self.next() {
+                    Some(Token::Name(field)) => field,
+                    _ => return Err(format!("{}() takes a reading field name", name)),
+                };
+                match self.next() {
+                    Some(Token::Close) => Ok(Expr::Aggregate(aggregate, field)),
+                    _ => Err(format!("Missing ')' after {}({}", name, field)),
+                }
+            }
+            Some(Token::Name(name)) => {
+                if ECONOMICS_VARIABLES.contains(&name.as_str())
+                    || READING_VARIABLES.contains(&name.as_str())
+                {
+                    Ok(Expr::Variable(name))
+                } else {
+                    Err(format!("Unknown variable '{}'", name))
+                }
+            }
+            Some(Token::Close) => Err("Unexpected ')'".to_string()),
+            Some(Token::Operator(_)) => Err("Expected a number, variable or '('".to_string()),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+}
+
+impl Expr {
+    /// Parse and check an expression.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        if source.len() > MAX_EXPRESSION_LENGTH {
+            return Err(format!("Expressions are at most {} characters", MAX_EXPRESSION_LENGTH));
+        }
+        let mut parser = Parser { tokens: tokenize(source)?, position: 0 };
+        if parser.tokens.is_empty() {
+            return Err("Empty expression".to_string());
+        }
+        let expr = parser.expression()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(format!("Unexpected {} after the expression", token.describe())),
+        }
+    }
+
+    /// The expression's value over `day`.
+    pub fn evaluate(&self, day: &DayInputs) -> Result<f64, String> {
+        let value = match self {
+            Expr::Number(n) => *n,
+            Expr::Variable(name) => day.variable(name)?,
+            Expr::Aggregate(aggregate, field) => day.aggregate(*aggregate, field)?,
+            Expr::Negate(inner) => -inner.evaluate(day)?,
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (left.evaluate(day)?, right.evaluate(day)?);
+                match op {
+                    Operator::Add => left + right,
+                    Operator::Subtract => left - right,
+                    Operator::Multiply => left * right,
+                    Operator::Divide if right == 0.0 => {
+                        return Err("Division by zero".to_string());
+                    }
+                    Operator::Divide => left / right,
+                }
+            }
+        };
+        if value.is_finite() {
+            Ok(value)
+        } else {
+            Err("The result is not a finite number".to_string())
+        }
+    }
+}
+
+/// What a site's KPIs are evaluated over for one day.
+#[derive(Debug, Clone, Default)]
+pub struct DayInputs {
+    /// The day's economics; absent without battery power readings
+    pub economics: Option<EconomicsRow>,
+    /// Each reading's data
+    pub readings: Vec<serde_json::Value>,
+    pub covered_hours: usize,
+}
+
+impl DayInputs {
+    /// Inputs from the day's economics and its readings, as `(timestamp,
+    /// data)`.
+    pub fn new(economics: Option<EconomicsRow>, readings: &[(NaiveDateTime, String)]) -> Self {
+        let hours: BTreeSet<_> = readings.iter().map(|(t, _)| (t.date(), t.hour())).collect();
+        DayInputs {
+            economics,
+            readings: readings
+                .iter()
+                .filter_map(|(_, data)| serde_json::from_str(data).ok())
+                .collect(),
+            covered_hours: hours.len(),
+        }
+    }
+
+    fn variable(&self, name: &str) -> Result<f64, String> {
+        match name {
+            "readings" => return Ok(self.readings.len() as f64),
+            "covered_hours" => return Ok(self.covered_hours as f64),
+            _ => {}
+        }
+        let row = self
+            .economics
+            .as_ref()
+            .ok_or_else(|| format!("{} is not available: no battery power readings", name))?;
+        let value = match name {
+            "charged_kwh" => Some(row.charged_kwh),
+            "discharged_kwh" => Some(row.discharged_kwh),
+            "round_trip_efficiency_percent" => row.round_trip_efficiency_percent,
+            "charge_cost" => row.charge_cost,
+            "discharge_value" => row.discharge_value,
+            "arbitrage_revenue" => row.arbitrage_revenue,
+            "peak_demand_kw" => row.peak_demand_kw,
+            "peak_net_demand_kw" => row.peak_net_demand_kw,
+            "demand_charge_savings" => row.demand_charge_savings,
+            "charge_emissions_kg" => row.charge_emissions_kg,
+            "avoided_emissions_kg" => row.avoided_emissions_kg,
+            _ => return Err(format!("Unknown variable '{}'", name)),
+        };
+        value.ok_or_else(|| format!("{} is not available for the day", name))
+    }
+
+    fn aggregate(&self, aggregate: Aggregate, field: &str) -> Result<f64, String> {
+        let values: Vec<f64> = self
+            .readings
+            .iter()
+            .filter_map(|data| {
+                let value = field.split('.').try_fold(data, |value, key| value.get(key))?;
+                match value {
+                    serde_json::Value::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+                    value => value.as_f64().filter(|n| n.is_finite()),
+                }
+            })
+            .collect();
+        if aggregate == Aggregate::Count {
+            return Ok(values.len() as f64);
+        }
+        if values.is_empty() {
+            return Err(format!("No readings report {}", field));
+        }
+        let sum: f64 = values.iter().sum();
+        Ok(match aggregate {
+            Aggregate::Avg => sum / values.len() as f64,
+            Aggregate::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+            Aggregate::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            Aggregate::Sum | Aggregate::Count => sum,
+        })
+    }
+}
+
+/// Whether `value` meets `target`.
+pub fn meets_target(value: f64, target: f64, higher_is_better: bool) -> bool {
+    if higher_is_better {
+        value >= target
+    } else {
+        value <= target
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use serde_json::json;
+
+    use super::*;
+
+    fn at(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 7, 1)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn day(economics: Option<EconomicsRow>) -> DayInputs {
+        let readings = [
+            (
+                at(0, 0),
+                json!({ "soc_percent": 40.0, "online": true, "rtac": { "temp_c": 30 } }),
+            ),
+            (at(0, 30), json!({ "soc_percent": 60.0, "online": false })),
+            (at(6, 0), json!({ "soc_percent": 80.0, "online": true })),
+            (at(6, 10), json!({ "load_kw": 12.5 })),
+        ]
+        .map(|(t, data)| (t, data.to_string()));
+        DayInputs::new(economics, &readings)
+    }
+
+    fn row() -> EconomicsRow {
+        EconomicsRow {
+            period_start: NaiveDate::from_ymd_opt(2026, 7, 1).unwrap(),
+            charged_kwh: 100.0,
+            discharged_kwh: 85.0,
+            round_trip_efficiency_percent: Some(85.0),
+            charge_cost: None,
+            discharge_value: None,
+            arbitrage_revenue: None,
+            peak_demand_kw: Some(250.0),
+            peak_net_demand_kw: Some(200.0),
+            demand_charge_savings: None,
+            charge_emissions_kg: None,
+            avoided_emissions_kg: None,
+            availability_percent: None,
+        }
+    }
+
+    fn eval(source: &str, inputs: &DayInputs) -> Result<f64, String> {
+        Expr::parse(source)?.evaluate(inputs)
+    }
+
+    #[test]
+    fn test_precedence_and_parentheses() {
+        let inputs = DayInputs::default();
+        assert_eq!(eval("1 + 2 * 3", &inputs), Ok(7.0));
+        assert_eq!(eval("(1 + 2) * 3", &inputs), Ok(9.0));
+        assert_eq!(eval("10 - 4 - 3", &inputs), Ok(3.0));
+        assert_eq!(eval("-2 * -3 / 4", &inputs), Ok(1.5));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Expr::parse("").is_err());
+        assert!(Expr::parse("1 +").is_err());
+        assert!(Expr::parse("(1 + 2").is_err());
+        assert!(Expr::parse("1 2").is_err());
+        assert!(Expr::parse("2 ^ 3").is_err());
+        assert!(Expr::parse("uptime").unwrap_err().contains("Unknown variable"));
+        assert!(Expr::parse("median(soc_percent)").unwrap_err().contains("Unknown function"));
+        assert!(Expr::parse("avg(1)").is_err());
+        assert!(Expr::parse(&"1+".repeat(300)).is_err());
+    }
+
+    #[test]
+    fn test_reading_aggregates() {
+        let inputs = day(None);
+        assert_eq!(eval("avg(soc_percent)", &inputs), Ok(60.0));
+        assert_eq!(eval("max(soc_percent) - min(soc_percent)", &inputs), Ok(40.0));
+        assert_eq!(eval("sum(rtac.temp_c)", &inputs), Ok(30.0));
+        assert_eq!(eval("count(online)", &inputs), Ok(3.0));
+        assert_eq!(eval("count(missing)", &inputs), Ok(0.0));
+        assert_eq!(eval("readings", &inputs), Ok(4.0));
+        assert_eq!(eval("100 * covered_hours / 24", &inputs), Ok(100.0 * 2.0 / 24.0));
+        let online = eval("100 * avg(online)", &inputs).unwrap();
+        assert!((online - 200.0 / 3.0).abs() < 1e-9);
+        assert!(eval("avg(missing)", &inputs).unwrap_err().contains("No readings"));
+    }
+
+    #[test]
+    fn test_economics_variables() {
+        let inputs = day(Some(row()));
+        assert_eq!(eval("discharged_kwh / charged_kwh * 100", &inputs), Ok(85.0));
+        assert_eq!(eval("peak_demand_kw - peak_net_demand_kw", &inputs), Ok(50.0));
+        assert!(eval("arbitrage_revenue", &inputs).unwrap_err().contains("not available"));
+        assert!(eval("charged_kwh", &day(None)).unwrap_err().contains("no battery power"));
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        let inputs = day(None);
+        assert_eq!(eval("1 / count(missing)", &inputs), Err("Division by zero".to_string()));
+    }
+
+    #[test]
+    fn test_meets_target() {
+        assert!(meets_target(99.5, 99.0, true));
+        assert!(!meets_target(98.0, 99.0, true));
+        assert!(meets_target(5.0, 10.0, false));
+    }
+}
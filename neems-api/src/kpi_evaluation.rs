@@ -0,0 +1,177 @@
+//! Daily evaluation of site KPIs.
+//!
+//! Once a day, shortly after midnight UTC, each site's KPIs are evaluated over
+//! the day before (see [`crate::kpi`]) and their results recorded (see
+//! [`crate::orm::kpi`]). Days in the last [`KPI_CATCH_UP_DAYS`] on which a KPI
+//! has no result are evaluated too, so a KPI added during the week, or a
+//! service down across midnight, leaves no gap. A day can be evaluated again
+//! on demand, e.g. after late readings arrive, from the KPI API.
+
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use rocket::fairing::AdHoc;
+
+use crate::{
+    carbon::MAX_CARBON_AGE_MINUTES,
+    economics::{EconomicsPeriod, Pricing, account, split_readings},
+    kpi::{DayInputs, Expr, meets_target},
+    models::{KpiDefinition, KpiResult},
+    orm::{
+        DbConn, SiteDbConn,
+        kpi::{
+            list_kpi_definitions, list_sites_with_kpis, list_unevaluated_days, save_kpi_results,
+        },
+        neems_data::load_site_readings,
+        site::get_site_by_id,
+        site_tariff::get_site_tariff,
+    },
+};
+
+/// Furthest back days without results are evaluated
+pub const KPI_CATCH_UP_DAYS: i64 = 7;
+
+/// How long after midnight UTC the day before is evaluated, to let the last
+/// readings arrive
+const EVALUATION_DELAY_MINUTES: i64 = 15;
+
+/// Each KPI's result for `day`, evaluated over `inputs` at `now`.
+pub fn evaluate_kpis(
+    definitions: &[KpiDefinition],
+    inputs: &DayInputs,
+    day: NaiveDate,
+    now: NaiveDateTime,
+) -> Vec<KpiResult> {
+    definitions
+        .iter()
+        .map(|kpi| {
+            let value = Expr::parse(&kpi.expression).and_then(|expr| expr.evaluate(inputs));
+            let (value, error) = match value {
+                Ok(value) => (Some(value), None),
+                Err(e) => (None, Some(e)),
+            };
+            KpiResult {
+                kpi_id: kpi.id,
+                day,
+                value,
+                met: value.zip(kpi.target).map(|(v, t)| meets_target(v, t, kpi.higher_is_better)),
+                error,
+                evaluated_at: now,
+            }
+        })
+        .collect()
+}
+
+/// Evaluate a site's KPIs over `day` and record the results.
+pub async fn evaluate_site_day(
+    db: &DbConn,
+    site_db: &SiteDbConn,
+    site_id: i32,
+    day: NaiveDate,
+) -> Result<Vec<KpiResult>, diesel::result::Error> {
+    let (site, tariff, definitions) = db
+        .run(move |conn| {
+            let site = get_site_by_id(conn, site_id)?.ok_or(diesel::result::Error::NotFound)?;
+            let tariff = get_site_tariff(conn, site_id)?;
+            let definitions = list_kpi_definitions(conn, site_id)?;
+            Ok::<_, diesel::result::Error>((site, tariff, definitions))
+        })
+        .await?;
+    if definitions.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let start = day.and_hms_opt(0, 0, 0).unwrap_or_default();
+    let end = start + Duration::days(1);
+    // The carbon intensity in force at the start may have been read earlier
+    let carbon_start = start - Duration::minutes(MAX_CARBON_AGE_MINUTES);
+    let readings = site_db
+        .run(move |conn| load_site_readings(conn, site_id, carbon_start, end))
+        .await?;
+
+    let (sources, carbon) = split_readings(&readings, start);
+    let pricing = tariff.map(|tariff| Pricing::new(&site, tariff));
+    let economics = account(
+        &sources,
+        EconomicsPeriod::Day,
+        pricing.as_ref(),
+        (!carbon.is_empty()).then_some(&carbon),
+    )
+    .into_iter()
+    .find(|row| row.period_start == day);
+    let day_readings: Vec<(NaiveDateTime, String)> = readings
+        .into_iter()
+        .filter(|(_, timestamp, _)| *timestamp >= start)
+        .map(|(_, timestamp, data)| (timestamp, data))
+        .collect();
+    let inputs = DayInputs::new(economics, &day_readings);
+
+    let results = evaluate_kpis(&definitions, &inputs, day, Utc::now().naive_utc());
+    let saved = results.clone();
+    db.run(move |conn| save_kpi_results(conn, &saved)).await?;
+    Ok(results)
+}
+
+/// Evaluate every site's KPIs over the days before `today`, back to
+/// [`KPI_CATCH_UP_DAYS`], that lack results.
+pub async fn evaluate_missing_days(db: &DbConn, site_db: &SiteDbConn, today: NaiveDate) {
+    let from = today - Duration::days(KPI_CATCH_UP_DAYS);
+    let to = today - Duration::days(1);
+    let sites = match db.run(list_sites_with_kpis).await {
+        Ok(sites) => sites,
+        Err(e) => {
+            error!("[kpi] Failed to list sites with KPIs: {}", e);
+            return;
+        }
+    };
+    for site_id in sites {
+        let days = match db.run(move |c| list_unevaluated_days(c, site_id, from, to)).await {
+            Ok(days) => days,
+            Err(e) => {
+                error!("[kpi] Site {}: failed to find days to evaluate: {}", site_id, e);
+                continue;
+            }
+        };
+        for day in days {
+            match evaluate_site_day(db, site_db, site_id, day).await {
+                Ok(results) => {
+                    info!("[kpi] Site {}: evaluated {} KPI(s) for {}", site_id, results.len(), day)
+                }
+                Err(e) => {
+                    error!("[kpi] Site {}: failed to evaluate KPIs for {}: {}", site_id, day, e)
+                }
+            }
+        }
+    }
+}
+
+/// Evaluate KPIs at liftoff, catching up on missed days, and then daily.
+pub fn kpi_evaluation_fairing() -> AdHoc {
+    AdHoc::on_liftoff("KPI Evaluation", |rocket| {
+        Box::pin(async move {
+            let (Some(db_pool), Some(site_pool)) =
+                (DbConn::pool(rocket).cloned(), SiteDbConn::pool(rocket).cloned())
+            else {
+                error!("[kpi] No database pools; KPIs won't be evaluated");
+                return;
+            };
+            rocket::tokio::spawn(async move {
+                loop {
+                    let now = Utc::now().naive_utc();
+                    let connections = (
+                        DbConn::from_pool(&db_pool).await,
+                        SiteDbConn::from_pool(&site_pool).await,
+                    );
+                    match connections {
+                        (Some(db), Some(site_db)) => {
+                            evaluate_missing_days(&db, &site_db, now.date()).await
+                        }
+                        _ => error!("[kpi] Could not get DB connections to evaluate KPIs"),
+                    }
+                    let next = (now.date() + Duration::days(1)).and_hms_opt(0, 0, 0).unwrap_or(now)
+                        + Duration::minutes(EVALUATION_DELAY_MINUTES);
+                    let wait = (next - Utc::now().naive_utc()).to_std().unwrap_or_default();
+                    rocket::tokio::time::sleep(wait).await;
+                }
+            });
+        })
+    })
+}
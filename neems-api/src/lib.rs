@@ -2,6 +2,7 @@
 extern crate rocket;
 
 use diesel_migrations::{EmbeddedMigrations, embed_migrations};
+use neems_data::encryption;
 use rocket::{
     Build, Rocket,
     figment::{
@@ -15,18 +16,46 @@ use rocket::{
 };
 
 pub mod admin_init_fairing;
+pub mod admin_status;
+pub mod alarm_escalation;
+pub mod allowlist;
 pub mod api;
+pub mod api_version;
+pub mod availability;
+pub mod carbon;
 pub mod company;
+pub mod compression;
+pub mod conditional_get;
+pub mod economics;
+pub mod generate_types;
+pub mod green_button;
+pub mod i18n;
+pub mod ical;
+pub mod kpi;
+pub mod kpi_evaluation;
 pub mod logged_json;
+pub mod merge_patch;
+#[cfg(feature = "test-staging")]
+pub mod mock;
 pub mod models;
+pub mod ndjson;
 pub mod odata_query;
+pub mod oncall;
 pub mod orm;
+pub mod power_allocation;
 pub use orm::{DbConn, SiteDbConn};
+pub mod response_cache;
+pub mod schedule_check;
+pub mod schedule_import;
+pub mod scheduler_catch_up;
 pub mod schema;
 pub mod session_guards;
-
-#[cfg(test)]
-pub mod generate_types;
+pub mod site_archive;
+pub mod soc_simulation;
+pub mod storage;
+#[cfg(feature = "systemd")]
+pub mod systemd;
+pub mod tls;
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
 
@@ -84,10 +113,31 @@ fn default_catcher(status: rocket::http::Status, req: &Request) -> Json<Value> {
     }))
 }
 
+/// The catchers rendering errors as JSON.
+pub(crate) fn error_catchers() -> Vec<rocket::Catcher> {
+    catchers![
+        unauthorized,
+        forbidden,
+        not_found,
+        unprocessable_entity,
+        internal_server_error,
+        default_catcher
+    ]
+}
+
 pub fn mount_api_routes(rocket: Rocket<Build>) -> Rocket<Build> {
+    let response_cache = response_cache::ResponseCache::from_figment(rocket.figment());
+    let compression = compression::Compression::from_figment(rocket.figment());
     rocket
+        // Translate error messages before they are compressed
+        .attach(i18n::I18n)
+        .attach(compression)
+        .attach(api_version::ApiVersioning::default())
+        .attach(storage::Storage::fairing())
         .manage(api::alarm::DemoForcedAlarms::default())
+        .manage(response_cache)
         .mount("/api", api::routes())
+        .mount("/admin", admin_status::routes())
 }
 
 fn log_rocket_info(rocket: &Rocket<Build>) {
@@ -103,11 +153,20 @@ fn log_rocket_info(rocket: &Rocket<Build>) {
         info!("Rocket is listening on port: {}", port);
     }
 
+    if figment.contains("tls.certs") {
+        let mutual = if figment.contains("tls.mutual.ca_certs") {
+            " with client certificate verification"
+        } else {
+            ""
+        };
+        info!("TLS enabled{}", mutual);
+    }
+
     // Log the database URL with better error handling
     match figment.extract_inner::<Map<String, Value>>("databases.sqlite_db") {
         Ok(db_config) => {
             if let Some(Value::String(url)) = db_config.get("url") {
-                info!("Database URL: {}", url);
+                info!("Database URL: {}", encryption::redact_url(url));
             } else {
                 warn!("Database URL not found in configuration");
             }
@@ -126,30 +185,46 @@ pub fn rocket() -> Rocket<Build> {
     let site_database_url =
         std::env::var("SITE_DATABASE_URL").expect("SITE_DATABASE_URL must be set");
 
+    // Keyed URLs when the databases are encrypted at rest (NEEMS_DB_KEY)
+    let database_url = encryption::connection_url(&database_url).expect("database key");
+    let site_database_url = encryption::connection_url(&site_database_url).expect("database key");
+
     let figment = Figment::from(rocket::Config::default())
         .merge(Toml::file("Rocket.toml").nested())
         .merge(Env::prefixed("ROCKET_").global())
         .merge(("databases.sqlite_db.url", database_url))
         .merge(("databases.site_db.url", site_database_url));
 
+    // Native TLS and client certificates (NEEMS_TLS_*)
+    let figment = match tls::TlsSettings::from_env().expect("TLS configuration") {
+        Some(settings) => settings.merge_into(figment),
+        None => figment,
+    };
+
+    // A socket passed in by systemd, forwarded to Rocket on a loopback port
+    #[cfg(feature = "systemd")]
+    let (figment, socket_activation) =
+        systemd::socket_activation(figment).expect("systemd socket activation");
+
+    // Timing and query plans for slow queries (NEEMS_SLOW_QUERY_MS), set up
+    // before the pools open their connections
+    orm::slow_query::install_from_env();
+
     let rocket = rocket::custom(figment)
         .attach(DbConn::fairing())
         .attach(SiteDbConn::fairing())
         .attach(orm::set_foreign_keys_fairing())
         .attach(orm::neems_data::set_foreign_keys_fairing())
+        .attach(orm::neems_data::site_read_pool_fairing())
         .attach(orm::run_migrations_fairing())
         .attach(admin_init_fairing::admin_init_fairing())
-        .register(
-            "/",
-            catchers![
-                unauthorized,
-                forbidden,
-                not_found,
-                unprocessable_entity,
-                internal_server_error,
-                default_catcher
-            ],
-        );
+        .attach(scheduler_catch_up::scheduler_catch_up_fairing())
+        .attach(kpi_evaluation::kpi_evaluation_fairing())
+        .attach(alarm_escalation::alarm_escalation_fairing())
+        .register("/", error_catchers());
+
+    #[cfg(feature = "systemd")]
+    let rocket = systemd::attach(rocket, socket_activation);
 
     log_rocket_info(&rocket);
 
@@ -1,8 +1,8 @@
 // neems-api/src/main.rs
 
-use std::env;
+use std::{env, path::PathBuf, process};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use rocket::{error, info};
 
 pub mod built_info {
@@ -17,6 +17,29 @@ struct Cli {
     /// Show extended version information
     #[arg(long, action = clap::ArgAction::SetTrue)]
     version_info: bool,
+
+    /// Serve the API from seeded in-memory databases, for frontend
+    /// development (needs the test-staging feature)
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    mock: bool,
+
+    /// Fixture user that requests without credentials act as in mock mode
+    #[arg(long, value_name = "EMAIL", requires = "mock")]
+    mock_user: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Export the TypeScript bindings for the API's types, then exit
+    ExportTypes {
+        /// Directory to write the .ts files to; stale .ts files in it are
+        /// removed
+        #[arg(long, value_name = "DIR")]
+        out: PathBuf,
+    },
 }
 
 #[rocket::main]
@@ -33,6 +56,35 @@ async fn main() {
         return;
     }
 
+    if let Some(Command::ExportTypes { out }) = cli.command {
+        match neems_api::generate_types::export_types(&out) {
+            Ok(count) => println!("Exported {} TypeScript types to {}", count, out.display()),
+            Err(e) => {
+                eprintln!("Failed to export TypeScript types to {}: {}", out.display(), e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if cli.mock {
+        #[cfg(feature = "test-staging")]
+        {
+            let user = cli.mock_user.as_deref().unwrap_or(neems_api::mock::DEFAULT_MOCK_USER);
+            info!("NEEMS API v{} starting in mock mode", built_info::PKG_VERSION);
+            neems_api::mock::mock_rocket(user)
+                .launch()
+                .await
+                .expect("Rocket server failed to launch");
+            return;
+        }
+        #[cfg(not(feature = "test-staging"))]
+        {
+            eprintln!("--mock needs neems-api built with --features test-staging");
+            process::exit(2);
+        }
+    }
+
     match env::current_dir() {
         Ok(path) => info!("Current directory: {}", path.display()),
         Err(e) => error!("Error getting current directory: {}", e),
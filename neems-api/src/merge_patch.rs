@@ -0,0 +1,124 @@
+//! JSON Merge Patch (RFC 7396) request bodies.
+//!
+//! In a merge patch a member that's left out leaves the field alone, `null`
+//! clears it and any other value replaces it. `Option<T>` can only say two of
+//! those three things, which is why the PUT endpoints can't clear a nullable
+//! field. Patch bodies use [`Patch`] for every field instead, with
+//! `#[serde(default, skip_serializing_if = "Patch::is_absent")]` so an absent
+//! member stays absent both ways.
+//!
+//! ts-rs can't see through `Patch`, so each field says what TypeScript should
+//! make of it with `#[ts(as = "Option<T>", optional = nullable)]` (`t?: T |
+//! null`) for a nullable column, or `#[ts(as = "Option<T>", optional)]` (`t?:
+//! T`) for one that can't be null.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// One member of a merge patch.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Patch<T> {
+    /// Left out: keep the current value
+    #[default]
+    Absent,
+    /// `null`: clear the field
+    Null,
+    /// Replace the field
+    Value(T),
+}
+
+impl<T> Patch<T> {
+    pub fn is_absent(&self) -> bool {
+        matches!(self, Patch::Absent)
+    }
+
+    /// The new value, if the patch gives one.
+    pub fn value(&self) -> Option<&T> {
+        match self {
+            Patch::Value(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The change to a nullable column, in the double-`Option` form the ORM
+    /// update functions take: `None` leaves it, `Some(None)` clears it.
+    pub fn into_nullable(self) -> Option<Option<T>> {
+        match self {
+            Patch::Absent => None,
+            Patch::Null => Some(None),
+            Patch::Value(value) => Some(Some(value)),
+        }
+    }
+
+    /// The change to a column that can't be null, or an error naming `field`
+    /// if the patch tries to clear it.
+    pub fn into_required(self, field: &str) -> Result<Option<T>, String> {
+        match self {
+            Patch::Absent => Ok(None),
+            Patch::Null => Err(format!("{} can't be null", field)),
+            Patch::Value(value) => Ok(Some(value)),
+        }
+    }
+}
+
+/// A PUT body's `None` means "leave it", so it becomes [`Patch::Absent`].
+impl<T> From<Option<T>> for Patch<T> {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Patch::Absent, Patch::Value)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Patch<T> {
+    /// Only called for members that are present; `#[serde(default)]` covers
+    /// the rest.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Option::<T>::deserialize(deserializer)?.map_or(Patch::Null, Patch::Value))
+    }
+}
+
+impl<T: Serialize> Serialize for Patch<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Patch::Value(value) => value.serialize(serializer),
+            Patch::Absent | Patch::Null => serializer.serialize_none(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[derive(Debug, Default, Deserialize, Serialize)]
+    struct Body {
+        #[serde(default, skip_serializing_if = "Patch::is_absent")]
+        name: Patch<String>,
+        #[serde(default, skip_serializing_if = "Patch::is_absent")]
+        serial: Patch<String>,
+    }
+
+    #[test]
+    fn absent_null_and_value_are_told_apart() {
+        let body: Body = serde_json::from_str(r#"{"serial": null}"#).unwrap();
+        assert_eq!(body.name, Patch::Absent);
+        assert_eq!(body.serial, Patch::Null);
+        assert_eq!(body.serial.clone().into_nullable(), Some(None));
+        assert!(body.serial.into_required("serial").is_err());
+
+        let body: Body = serde_json::from_str(r#"{"name": "Meter"}"#).unwrap();
+        assert_eq!(body.name.value().map(String::as_str), Some("Meter"));
+        assert_eq!(body.name.into_required("name"), Ok(Some("Meter".to_string())));
+        assert_eq!(body.serial.into_nullable(), None);
+    }
+
+    #[test]
+    fn serializes_back_to_the_same_patch() {
+        let body = Body {
+            name: Patch::Value("Meter".to_string()),
+            serial: Patch::Null,
+        };
+        assert_eq!(serde_json::to_string(&body).unwrap(), r#"{"name":"Meter","serial":null}"#);
+        assert_eq!(serde_json::to_string(&Body::default()).unwrap(), "{}");
+    }
+}
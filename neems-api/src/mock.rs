@@ -0,0 +1,116 @@
+//! Mock API server for frontend development.
+//!
+//! `neems-api --mock` (built with the `test-staging` feature) serves every
+//! route from in-memory databases, so it needs no `DATABASE_URL`, SQLite
+//! files or admin setup. At startup they're migrated and seeded with the
+//! golden test fixtures ([`FixtureBuilder::golden`]), plus
+//! [`HISTORY_DAYS`] days of SoC and alarm history for every site. The
+//! fixtures are inserted in the same order every time, so their ids don't
+//! change between runs, and each history reading is a function of its
+//! timestamp alone, on a grid aligned to the reading interval (see
+//! [`neems_data::seed_soc_history`]). Nothing is kept after exit.
+//!
+//! Requests without a session cookie, API key or client certificate act as
+//! the mock user (`superadmin@example.com` unless another fixture user is
+//! given), so the frontend works without logging in. Logging in as another
+//! fixture user still works, for checking what other roles see.
+
+use neems_data::{seed_alarm_history, seed_soc_history};
+use rocket::{
+    Build, Rocket,
+    fairing::AdHoc,
+    figment::{
+        Figment,
+        providers::{Env, Format, Toml},
+    },
+};
+use uuid::Uuid;
+
+use crate::orm::{
+    DbConn, SiteDbConn, neems_data::db::run_site_migrations_fairing, run_migrations_fairing,
+    set_foreign_keys_fairing, testing::FixtureBuilder, user::get_user_by_email,
+};
+
+/// The user requests without credentials act as, unless another is given.
+pub const DEFAULT_MOCK_USER: &str = "superadmin@example.com";
+
+/// Days of SoC and alarm history seeded for each site.
+pub const HISTORY_DAYS: u32 = 14;
+
+/// Minutes between seeded readings, as from the RTAC collector.
+const HISTORY_INTERVAL_MINUTES: u32 = 6;
+
+/// The user unauthenticated requests act as in mock mode.
+pub struct MockUser {
+    pub user_id: i32,
+}
+
+/// A Rocket serving the API from seeded in-memory databases, with
+/// unauthenticated requests acting as `mock_user`.
+///
+/// Address, port and the other non-database settings come from
+/// `Rocket.toml` and `ROCKET_*` as usual.
+pub fn mock_rocket(mock_user: &str) -> Rocket<Build> {
+    let memory_url =
+        |name: &str| format!("file:{}_{}?mode=memory&cache=shared", name, Uuid::new_v4());
+    let figment = Figment::from(rocket::Config::default())
+        .merge(Toml::file("Rocket.toml").nested())
+        .merge(Env::prefixed("ROCKET_").global())
+        .merge(("databases.sqlite_db.url", memory_url("neems_mock")))
+        .merge(("databases.site_db.url", memory_url("neems_mock_site")))
+        .merge(("storage_path", std::env::temp_dir().join("neems_mock_blobs")));
+
+    let rocket = rocket::custom(figment)
+        .attach(DbConn::fairing())
+        .attach(SiteDbConn::fairing())
+        .attach(set_foreign_keys_fairing())
+        .attach(crate::orm::neems_data::set_foreign_keys_fairing())
+        .attach(run_migrations_fairing())
+        .attach(run_site_migrations_fairing())
+        .attach(seed_fairing(mock_user.to_string()))
+        .register("/", crate::error_catchers());
+    crate::mount_api_routes(rocket)
+}
+
+/// Seed the fixtures and history, and manage the [`MockUser`].
+fn seed_fairing(mock_user: String) -> AdHoc {
+    AdHoc::try_on_ignite("Mock Data", |rocket| async move {
+        let conn = DbConn::get_one(&rocket).await.expect("database connection for mock data");
+        let seeded = conn
+            .run(move |c| {
+                let fixtures = FixtureBuilder::golden().build(c)?;
+                let user = get_user_by_email(c, &mock_user)?
+                    .ok_or_else(|| format!("no fixture user '{}'", mock_user))?;
+                Ok::<_, Box<dyn std::error::Error + Send + Sync>>((fixtures, user.id))
+            })
+            .await;
+        let (fixtures, user_id) = match seeded {
+            Ok(seeded) => seeded,
+            Err(e) => {
+                error!("Mock data: {}", e);
+                return Err(rocket);
+            }
+        };
+
+        let site_conn = SiteDbConn::get_one(&rocket)
+            .await
+            .expect("site database connection for mock data");
+        let site_ids = fixtures.site_ids();
+        let history = site_conn
+            .run(move |c| {
+                for site_id in site_ids {
+                    seed_soc_history(c, site_id, HISTORY_DAYS, HISTORY_INTERVAL_MINUTES)?;
+                    seed_alarm_history(c, site_id, HISTORY_DAYS, HISTORY_INTERVAL_MINUTES)?;
+                }
+                Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+            })
+            .await;
+        if let Err(e) = history {
+            error!("Mock data: seeding history failed: {}", e);
+            return Err(rocket);
+        }
+
+        info!("Mock mode: unauthenticated requests act as user {}", user_id);
+        Ok(rocket.manage(MockUser { user_id }))
+    })
+}
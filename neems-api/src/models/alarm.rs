@@ -0,0 +1,89 @@
+use chrono::NaiveDateTime;
+use diesel::{Identifiable, Insertable, Queryable, Selectable};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::schema::{alarm_actions, alarm_states};
+
+/// What an operator did to an alarm, as stored in `alarm_actions.action`.
+pub mod alarm_action {
+    pub const ACKNOWLEDGE: &str = "acknowledge";
+    pub const SHELVE: &str = "shelve";
+    pub const UNSHELVE: &str = "unshelve";
+    pub const COMMENT: &str = "comment";
+}
+
+/// An alarm's acknowledgement and shelving.
+#[derive(
+    Queryable,
+    Selectable,
+    Identifiable,
+    Insertable,
+    Debug,
+    Clone,
+    Default,
+    PartialEq,
+    Serialize,
+    Deserialize,
+    TS,
+)]
+#[diesel(table_name = alarm_states)]
+#[diesel(primary_key(alarm_num))]
+#[ts(export)]
+pub struct AlarmState {
+    pub alarm_num: i32,
+    #[ts(type = "string | null")]
+    pub acknowledged_at: Option<NaiveDateTime>,
+    /// Email of the user who acknowledged the alarm
+    pub acknowledged_by: Option<String>,
+    /// The alarm is shelved until then; earlier times have expired
+    #[ts(type = "string | null")]
+    pub shelved_until: Option<NaiveDateTime>,
+    /// Email of the user who shelved the alarm
+    pub shelved_by: Option<String>,
+    pub shelve_reason: Option<String>,
+    /// When the alarm was first seen active; null while it is clear
+    #[ts(type = "string | null")]
+    pub raised_at: Option<NaiveDateTime>,
+}
+
+impl AlarmState {
+    /// Whether the alarm is shelved at `now`.
+    pub fn is_shelved(&self, now: NaiveDateTime) -> bool {
+        self.shelved_until.is_some_and(|until| until > now)
+    }
+}
+
+/// An acknowledgement, shelving, unshelving or comment on an alarm.
+#[derive(
+    Queryable, Selectable, Identifiable, Debug, Clone, PartialEq, Serialize, Deserialize, TS,
+)]
+#[diesel(table_name = alarm_actions)]
+#[ts(export)]
+pub struct AlarmAction {
+    pub id: i32,
+    pub alarm_num: i32,
+    /// `acknowledge`, `shelve`, `unshelve` or `comment`
+    pub action: String,
+    /// Absent once the user is deleted
+    pub user_id: Option<i32>,
+    pub user_email: String,
+    pub comment: Option<String>,
+    /// For `shelve`, when the shelving expires
+    #[ts(type = "string | null")]
+    pub shelved_until: Option<NaiveDateTime>,
+    #[ts(type = "string")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = alarm_actions)]
+pub struct NewAlarmAction {
+    pub alarm_num: i32,
+    pub action: String,
+    pub user_id: Option<i32>,
+    pub user_email: String,
+    pub comment: Option<String>,
+    pub shelved_until: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
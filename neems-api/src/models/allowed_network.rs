@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// The networks a company's users, or a client certificate, may connect from
+/// (see [`crate::allowlist`]). An empty list places no restriction.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct AllowedNetworks {
+    /// CIDR networks such as `10.20.0.0/16`; bare addresses are single hosts
+    pub networks: Vec<String>,
+}
@@ -0,0 +1,32 @@
+use chrono::NaiveDateTime;
+use diesel::{Identifiable, Insertable, Queryable, Selectable};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::schema::api_keys;
+
+/// An API key that authenticates as a service account. The key itself is
+/// only shown when created or rotated; its hash is never returned.
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone, Serialize, Deserialize, TS)]
+#[diesel(table_name = api_keys)]
+#[ts(export)]
+pub struct ApiKey {
+    pub id: i32,
+    pub user_id: i32,
+    /// The start of the key, to tell keys apart
+    pub prefix: String,
+    pub description: Option<String>,
+    #[ts(type = "string")]
+    pub created_at: NaiveDateTime,
+    #[ts(type = "string | null")]
+    pub rotated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = api_keys)]
+pub struct NewApiKey {
+    pub user_id: i32,
+    pub key_hash: String,
+    pub prefix: String,
+    pub description: Option<String>,
+}
@@ -16,6 +16,26 @@ pub enum RuleType {
     SpecificDate,
 }
 
+/// Priority layer of an application rule.
+///
+/// Layers are resolved before specificity: a rule in a higher layer wins over
+/// every rule in a lower one, and within a layer the most specific (then the
+/// newest) rule wins. Ordered lowest first.
+#[derive(
+    Debug, Clone, Copy, Default, Serialize, Deserialize, TS, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[ts(export)]
+#[serde(rename_all = "snake_case")]
+pub enum OverridePriority {
+    /// Day-to-day scheduling by the site's operators
+    #[default]
+    Operator,
+    /// Utility demand-response events
+    UtilityDr,
+    /// Safety holds, which nothing preempts
+    Safety,
+}
+
 /// Database model for application rules
 #[derive(
     Queryable,
@@ -39,6 +59,7 @@ pub struct ApplicationRuleDb {
     pub specific_dates: Option<String>,
     pub override_reason: Option<String>,
     pub created_at: chrono::NaiveDateTime,
+    pub priority: String,
 }
 
 /// Insertable struct for creating new application rules
@@ -50,6 +71,7 @@ pub struct NewApplicationRule {
     pub days_of_week: Option<String>,
     pub specific_dates: Option<String>,
     pub override_reason: Option<String>,
+    pub priority: String,
 }
 
 // ============================================================================
@@ -66,6 +88,7 @@ pub struct ApplicationRule {
     pub days_of_week: Option<Vec<i32>>, // 0=Sunday, 6=Saturday
     pub specific_dates: Option<Vec<String>>, // ISO date strings
     pub override_reason: Option<String>,
+    pub priority: OverridePriority,
     #[ts(type = "string")]
     pub created_at: chrono::NaiveDateTime,
 }
@@ -84,6 +107,9 @@ pub struct CreateApplicationRuleRequest {
     /// schedule*) — `change_reason` explains *why this rule is being
     /// created or removed* and lands on entity_activity.
     pub change_reason: Option<String>,
+    /// Priority layer; defaults to `operator`. Only specific-date rules can be
+    /// in a higher layer.
+    pub priority: Option<OverridePriority>,
 }
 
 /// Response with effective schedule for a date
@@ -92,6 +118,8 @@ pub struct CreateApplicationRuleRequest {
 pub struct EffectiveScheduleResponse {
     pub library_item: super::schedule_library::ScheduleLibraryItem,
     pub specificity: i32, // 0=default, 1=day_of_week, 2=specific_date
+    /// The priority layer that won; resolved before specificity
+    pub priority: OverridePriority,
     pub rule: ApplicationRule,
 }
 
@@ -102,6 +130,7 @@ pub struct CalendarDaySchedule {
     pub library_item_id: i32,
     pub library_item_name: String,
     pub specificity: i32,
+    pub priority: OverridePriority,
     pub rule_id: i32,
 }
 
@@ -115,6 +144,7 @@ pub struct CalendarScheduleMatch {
     pub rule_id: i32,
     pub rule_type: RuleType,
     pub override_reason: Option<String>,
+    pub priority: OverridePriority,
 }
 
 /// All matching schedules for a calendar day
@@ -149,6 +179,29 @@ impl FromStr for RuleType {
     }
 }
 
+impl OverridePriority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OverridePriority::Operator => "operator",
+            OverridePriority::UtilityDr => "utility_dr",
+            OverridePriority::Safety => "safety",
+        }
+    }
+}
+
+impl FromStr for OverridePriority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "operator" => Ok(OverridePriority::Operator),
+            "utility_dr" => Ok(OverridePriority::UtilityDr),
+            "safety" => Ok(OverridePriority::Safety),
+            _ => Err(format!("Unknown override priority: {}", s)),
+        }
+    }
+}
+
 // Conversion from database model to API model
 impl ApplicationRuleDb {
     pub fn to_api_model(&self) -> Result<ApplicationRule, String> {
@@ -173,6 +226,7 @@ impl ApplicationRuleDb {
             days_of_week,
             specific_dates,
             override_reason: self.override_reason.clone(),
+            priority: OverridePriority::from_str(&self.priority)?,
             created_at: self.created_at,
         })
     }
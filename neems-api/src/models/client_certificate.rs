@@ -0,0 +1,30 @@
+use chrono::NaiveDateTime;
+use diesel::{Identifiable, Insertable, Queryable, Selectable};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::schema::client_certificates;
+
+/// A client certificate that authenticates as a user (see [`crate::tls`]).
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone, Serialize, Deserialize, TS)]
+#[diesel(table_name = client_certificates)]
+#[ts(export)]
+pub struct ClientCertificate {
+    pub id: i32,
+    pub user_id: i32,
+    /// SHA-256 of the certificate's DER encoding, lowercase hex
+    pub fingerprint: String,
+    pub subject: Option<String>,
+    pub description: Option<String>,
+    #[ts(type = "string")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = client_certificates)]
+pub struct NewClientCertificate {
+    pub user_id: i32,
+    pub fingerprint: String,
+    pub subject: Option<String>,
+    pub description: Option<String>,
+}
@@ -0,0 +1,223 @@
+use std::str::FromStr;
+
+use chrono::NaiveDateTime;
+use diesel::{Identifiable, Insertable, Queryable, Selectable};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::schema::{device_group_members, device_groups};
+
+/// How a group's total power setpoint is split across its members.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "snake_case")]
+pub enum AllocationStrategy {
+    /// The same share for every member
+    #[default]
+    Equal,
+    /// Shares in proportion to the energy each member has to give: SoC when
+    /// discharging, the room left (100 - SoC) when charging
+    SocBalancing,
+}
+
+impl AllocationStrategy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AllocationStrategy::Equal => "equal",
+            AllocationStrategy::SocBalancing => "soc_balancing",
+        }
+    }
+}
+
+impl FromStr for AllocationStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "equal" => Ok(AllocationStrategy::Equal),
+            "soc_balancing" => Ok(AllocationStrategy::SocBalancing),
+            _ => Err(format!("Unknown allocation strategy: {}", s)),
+        }
+    }
+}
+
+/// Database model for device groups
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone)]
+#[diesel(table_name = device_groups)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct DeviceGroupDb {
+    pub id: i32,
+    pub site_id: i32,
+    pub name: String,
+    pub allocation: String,
+    pub schedule_dispatch: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = device_groups)]
+pub struct NewDeviceGroup {
+    pub site_id: i32,
+    pub name: String,
+    pub allocation: String,
+    pub schedule_dispatch: bool,
+}
+
+/// Database model for a device's membership of a group
+#[derive(Queryable, Selectable, Insertable, Debug, Clone)]
+#[diesel(table_name = device_group_members)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct DeviceGroupMemberDb {
+    pub group_id: i32,
+    pub device_id: i32,
+    pub rated_power_kw: Option<f64>,
+    pub source_id: Option<i32>,
+}
+
+/// A device in a group.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DeviceGroupMember {
+    pub device_id: i32,
+    pub device_name: String,
+    /// Most power the device takes or gives, capping its share of a setpoint
+    pub rated_power_kw: Option<f64>,
+    /// Site database source reporting the device's SoC and power
+    pub source_id: Option<i32>,
+}
+
+/// A group of a site's devices controlled together.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DeviceGroup {
+    pub id: i32,
+    pub site_id: i32,
+    pub name: String,
+    pub allocation: AllocationStrategy,
+    /// Whether the scheduler's site-wide power commands are split across
+    /// this group
+    pub schedule_dispatch: bool,
+    pub members: Vec<DeviceGroupMember>,
+    #[ts(type = "string")]
+    pub created_at: NaiveDateTime,
+    #[ts(type = "string")]
+    pub updated_at: NaiveDateTime,
+}
+
+/// A member in a request to create or replace a group.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DeviceGroupMemberRequest {
+    pub device_id: i32,
+    pub rated_power_kw: Option<f64>,
+    pub source_id: Option<i32>,
+}
+
+/// Request to create a group, or replace one.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DeviceGroupRequest {
+    pub name: String,
+    /// Defaults to `equal`
+    #[serde(default)]
+    pub allocation: AllocationStrategy,
+    /// Defaults to false
+    #[serde(default)]
+    pub schedule_dispatch: bool,
+    pub members: Vec<DeviceGroupMemberRequest>,
+}
+
+impl DeviceGroupRequest {
+    /// Every problem with the request, if any.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if self.name.trim().is_empty() {
+            problems.push("name: must not be empty".to_string());
+        }
+        if self.members.is_empty() {
+            problems.push("members: a group needs at least one device".to_string());
+        }
+        let mut seen = std::collections::HashSet::new();
+        for member in &self.members {
+            if !seen.insert(member.device_id) {
+                problems.push(format!("members: device {} is listed twice", member.device_id));
+            }
+            if let Some(rated) = member.rated_power_kw {
+                if !rated.is_finite() || rated <= 0.0 {
+                    problems.push(format!(
+                        "members: device {} rated_power_kw must be positive",
+                        member.device_id
+                    ));
+                }
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+/// The latest telemetry of one member.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct MemberTelemetry {
+    pub device_id: i32,
+    pub device_name: String,
+    pub soc_percent: Option<f64>,
+    /// Positive when discharging
+    pub power_kw: Option<f64>,
+    #[ts(type = "string | null")]
+    pub timestamp: Option<NaiveDateTime>,
+}
+
+/// A group's telemetry, rolled up from its members' latest readings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DeviceGroupTelemetry {
+    pub group_id: i32,
+    pub members: Vec<MemberTelemetry>,
+    /// Members with a reading
+    pub reporting_count: usize,
+    /// Sum of the reporting members' power, positive when discharging
+    pub total_power_kw: Option<f64>,
+    /// Mean SoC of the reporting members, weighted by rated power when every
+    /// one of them has it
+    pub soc_percent: Option<f64>,
+    /// Sum of the members' rated power, when every member has it
+    pub rated_power_kw: Option<f64>,
+    #[ts(type = "string | null")]
+    pub last_reading_at: Option<NaiveDateTime>,
+}
+
+/// Request to dispatch a total power setpoint to a group.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DispatchRequest {
+    /// Total power, positive to discharge and negative to charge
+    pub power_kw: f64,
+}
+
+/// One member's share of a dispatched setpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct MemberSetpoint {
+    pub device_id: i32,
+    /// Positive to discharge and negative to charge
+    pub power_kw: f64,
+}
+
+/// A total power setpoint split across a group.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct GroupDispatch {
+    pub group_id: i32,
+    pub allocation: AllocationStrategy,
+    pub requested_power_kw: f64,
+    /// What the members can take; less than requested when it exceeds their
+    /// rated power
+    pub allocated_power_kw: f64,
+    pub setpoints: Vec<MemberSetpoint>,
+}
@@ -0,0 +1,241 @@
+use chrono::NaiveDateTime;
+use diesel::{Identifiable, Insertable, Queryable, Selectable};
+use neems_data::notifications::NotificationSeverity;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::schema::{alarm_escalations, escalation_policies, escalation_tiers};
+
+/// Most tiers a policy can have
+pub const MAX_ESCALATION_TIERS: usize = 5;
+
+/// Longest a tier can wait for an acknowledgement
+pub const MAX_ESCALATION_DELAY_MINUTES: i32 = 7 * 24 * 60;
+
+/// Most contacts one tier can name
+const MAX_TIER_CONTACTS: usize = 20;
+
+/// Database model for escalation policies
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone)]
+#[diesel(table_name = escalation_policies)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct EscalationPolicyDb {
+    pub id: i32,
+    pub company_id: i32,
+    pub name: String,
+    pub min_severity: String,
+    pub enabled: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = escalation_policies)]
+pub struct NewEscalationPolicy {
+    pub company_id: i32,
+    pub name: String,
+    pub min_severity: String,
+    pub enabled: bool,
+}
+
+/// Database model for a policy's tier
+#[derive(Queryable, Selectable, Insertable, Debug, Clone)]
+#[diesel(table_name = escalation_tiers)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct EscalationTierDb {
+    pub policy_id: i32,
+    pub tier: i32,
+    pub delay_minutes: i32,
+    pub channel_id: i32,
+    /// JSON array of contacts
+    pub contacts: String,
+    pub rotation_id: Option<i32>,
+}
+
+/// A step of an escalation policy.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct EscalationTier {
+    /// 1 for the first tier, and so on
+    pub tier: i32,
+    /// Minutes after the alarm is raised that the tier is notified, if it is
+    /// still unacknowledged
+    pub delay_minutes: i32,
+    /// Notification channel the tier is notified through
+    pub channel_id: i32,
+    /// The people the tier reaches, named in the notification
+    pub contacts: Vec<String>,
+    /// On-call rotation whose member on call when the tier is notified is
+    /// named first
+    pub rotation_id: Option<i32>,
+}
+
+/// A company's rule for escalating alarms nobody acknowledges.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct EscalationPolicy {
+    pub id: i32,
+    pub company_id: i32,
+    pub name: String,
+    /// Alarms of this severity or above are escalated
+    pub min_severity: NotificationSeverity,
+    pub enabled: bool,
+    /// In order of delay
+    pub tiers: Vec<EscalationTier>,
+    #[ts(type = "string")]
+    pub created_at: NaiveDateTime,
+    #[ts(type = "string")]
+    pub updated_at: NaiveDateTime,
+}
+
+/// A tier in a request to create or replace a policy.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct EscalationTierRequest {
+    pub delay_minutes: i32,
+    pub channel_id: i32,
+    #[serde(default)]
+    pub contacts: Vec<String>,
+    #[serde(default)]
+    pub rotation_id: Option<i32>,
+}
+
+fn default_min_severity() -> NotificationSeverity {
+    NotificationSeverity::Critical
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Request to create a policy, or replace one.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct EscalationPolicyRequest {
+    pub name: String,
+    /// Defaults to `critical`
+    #[serde(default = "default_min_severity")]
+    pub min_severity: NotificationSeverity,
+    /// Defaults to true
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// In order of delay
+    pub tiers: Vec<EscalationTierRequest>,
+}
+
+impl EscalationPolicyRequest {
+    /// Every problem with the request, if any.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if self.name.trim().is_empty() {
+            problems.push("name: must not be empty".to_string());
+        }
+        if self.tiers.is_empty() || self.tiers.len() > MAX_ESCALATION_TIERS {
+            problems.push(format!("tiers: a policy needs 1 to {} tiers", MAX_ESCALATION_TIERS));
+        }
+        let mut previous = 0;
+        for (i, tier) in self.tiers.iter().enumerate() {
+            if tier.delay_minutes <= previous {
+                problems.push(format!(
+                    "tiers: tier {} delay_minutes must be positive and longer than the tier \
+                     before's",
+                    i + 1
+                ));
+            } else if tier.delay_minutes > MAX_ESCALATION_DELAY_MINUTES {
+                problems.push(format!(
+                    "tiers: tier {} delay_minutes must be at most {}",
+                    i + 1,
+                    MAX_ESCALATION_DELAY_MINUTES
+                ));
+            }
+            previous = previous.max(tier.delay_minutes);
+            if tier.contacts.len() > MAX_TIER_CONTACTS
+                || tier.contacts.iter().any(|contact| contact.trim().is_empty())
+            {
+                problems.push(format!(
+                    "tiers: tier {} needs at most {} contacts, none empty",
+                    i + 1,
+                    MAX_TIER_CONTACTS
+                ));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+/// Database model for a sent escalation
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone)]
+#[diesel(table_name = alarm_escalations)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct AlarmEscalationDb {
+    pub id: i32,
+    pub alarm_num: i32,
+    pub raised_at: NaiveDateTime,
+    pub policy_id: i32,
+    pub policy_name: String,
+    pub tier: i32,
+    pub channel_id: i32,
+    pub contacts: String,
+    pub error: Option<String>,
+    pub escalated_at: NaiveDateTime,
+    pub on_call_email: Option<String>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = alarm_escalations)]
+pub struct NewAlarmEscalation {
+    pub alarm_num: i32,
+    pub raised_at: NaiveDateTime,
+    pub policy_id: i32,
+    pub policy_name: String,
+    pub tier: i32,
+    pub channel_id: i32,
+    pub contacts: String,
+    pub error: Option<String>,
+    pub escalated_at: NaiveDateTime,
+    pub on_call_email: Option<String>,
+}
+
+/// A tier notified of an alarm nobody acknowledged.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct AlarmEscalation {
+    pub id: i32,
+    pub alarm_num: i32,
+    /// When the alarm was raised, identifying the occurrence escalated
+    #[ts(type = "string")]
+    pub raised_at: NaiveDateTime,
+    pub policy_id: i32,
+    pub policy_name: String,
+    pub tier: i32,
+    pub channel_id: i32,
+    pub contacts: Vec<String>,
+    /// Why the notification could not be sent; null if it was
+    pub error: Option<String>,
+    #[ts(type = "string")]
+    pub escalated_at: NaiveDateTime,
+    /// Who was on call on the tier's rotation
+    pub on_call_email: Option<String>,
+}
+
+impl From<AlarmEscalationDb> for AlarmEscalation {
+    fn from(escalation: AlarmEscalationDb) -> Self {
+        Self {
+            id: escalation.id,
+            alarm_num: escalation.alarm_num,
+            raised_at: escalation.raised_at,
+            policy_id: escalation.policy_id,
+            policy_name: escalation.policy_name,
+            tier: escalation.tier,
+            channel_id: escalation.channel_id,
+            contacts: serde_json::from_str(&escalation.contacts).unwrap_or_default(),
+            error: escalation.error,
+            escalated_at: escalation.escalated_at,
+            on_call_email: escalation.on_call_email,
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::{AsChangeset, Identifiable, Insertable, Queryable, Selectable};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{
+    kpi::Expr,
+    schema::{kpi_definitions, kpi_results},
+};
+
+/// A site's custom metric, evaluated once a day (see [`crate::kpi`]).
+#[derive(
+    Queryable, Selectable, Identifiable, Debug, Clone, PartialEq, Serialize, Deserialize, TS,
+)]
+#[diesel(table_name = kpi_definitions)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[ts(export)]
+pub struct KpiDefinition {
+    pub id: i32,
+    pub site_id: i32,
+    pub name: String,
+    /// Arithmetic over the day's economics and reading aggregates, e.g.
+    /// `100 * covered_hours / 24`
+    pub expression: String,
+    pub target: Option<f64>,
+    /// Whether the target is a minimum (true) or a maximum (false)
+    pub higher_is_better: bool,
+    pub unit: Option<String>,
+    #[ts(type = "string")]
+    pub created_at: NaiveDateTime,
+    #[ts(type = "string")]
+    pub updated_at: NaiveDateTime,
+}
+
+/// Request to create a KPI, or replace one.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct KpiDefinitionRequest {
+    pub name: String,
+    pub expression: String,
+    pub target: Option<f64>,
+    /// Defaults to true
+    #[serde(default = "default_higher_is_better")]
+    pub higher_is_better: bool,
+    pub unit: Option<String>,
+}
+
+fn default_higher_is_better() -> bool {
+    true
+}
+
+impl KpiDefinitionRequest {
+    /// Every problem with the request, if any.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if self.name.trim().is_empty() {
+            problems.push("name: must not be empty".to_string());
+        }
+        if let Err(e) = Expr::parse(&self.expression) {
+            problems.push(format!("expression: {}", e));
+        }
+        if self.target.is_some_and(|t| !t.is_finite()) {
+            problems.push("target: must be a number".to_string());
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+#[derive(Insertable, AsChangeset, Debug)]
+#[diesel(table_name = kpi_definitions)]
+#[diesel(treat_none_as_null = true)]
+pub struct KpiDefinitionChanges {
+    pub name: String,
+    pub expression: String,
+    pub target: Option<f64>,
+    pub higher_is_better: bool,
+    pub unit: Option<String>,
+}
+
+impl From<KpiDefinitionRequest> for KpiDefinitionChanges {
+    fn from(request: KpiDefinitionRequest) -> Self {
+        KpiDefinitionChanges {
+            name: request.name.trim().to_string(),
+            expression: request.expression.trim().to_string(),
+            target: request.target,
+            higher_is_better: request.higher_is_better,
+            unit: request.unit.filter(|u| !u.trim().is_empty()),
+        }
+    }
+}
+
+/// A KPI's value for a UTC day.
+#[derive(
+    Queryable, Selectable, Insertable, Debug, Clone, PartialEq, Serialize, Deserialize, TS,
+)]
+#[diesel(table_name = kpi_results)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[ts(export)]
+pub struct KpiResult {
+    pub kpi_id: i32,
+    #[ts(type = "string")]
+    pub day: NaiveDate,
+    /// Absent when the expression couldn't be evaluated
+    pub value: Option<f64>,
+    /// Whether the value meets the KPI's target; absent without either
+    pub met: Option<bool>,
+    /// Why the expression couldn't be evaluated
+    pub error: Option<String>,
+    #[ts(type = "string")]
+    pub evaluated_at: NaiveDateTime,
+}
@@ -0,0 +1,32 @@
+use neems_data::i18n::Locale;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// Request to set or clear a company's or user's locale
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LocaleRequest {
+    /// `null` clears the preference
+    pub locale: Option<Locale>,
+}
+
+/// A company's or user's stored locale, and the one in effect.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LocaleSetting {
+    /// The stored preference, if any
+    pub locale: Option<Locale>,
+    /// The locale used where there's no request to negotiate with, e.g. for
+    /// notification channels created without one: the stored preference,
+    /// falling back to the company's and then English
+    pub effective: Locale,
+}
+
+/// A supported locale.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LocaleInfo {
+    pub locale: Locale,
+    /// The language's own name for itself, e.g. `Español`
+    pub name: String,
+}
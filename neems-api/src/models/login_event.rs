@@ -0,0 +1,39 @@
+use chrono::NaiveDateTime;
+use diesel::{Identifiable, Insertable, Queryable, Selectable};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::schema::login_events;
+
+/// A recorded login attempt (see [`crate::orm::login_event`]).
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone, Serialize, Deserialize, TS)]
+#[diesel(table_name = login_events)]
+#[ts(export)]
+pub struct LoginEvent {
+    pub id: i32,
+    /// The user the email belongs to; absent for unknown emails and deleted
+    /// users
+    pub user_id: Option<i32>,
+    /// The email the attempt was made with
+    pub email: String,
+    pub success: bool,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    /// Country code reported by the reverse proxy, when configured
+    pub country: Option<String>,
+    /// Comma-separated anomaly flags: `new_country`, `failures_then_success`
+    pub anomaly: Option<String>,
+    #[ts(type = "string")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = login_events)]
+pub struct NewLoginEvent {
+    pub user_id: Option<i32>,
+    pub email: String,
+    pub success: bool,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub country: Option<String>,
+}
@@ -1,26 +1,52 @@
+pub mod alarm;
+pub mod allowed_network;
+pub mod api_key;
 pub mod application_rule;
+pub mod client_certificate;
 pub mod company;
 pub mod deleted_company;
 pub mod deleted_user;
 pub mod device;
+pub mod device_group;
 pub mod entity_activity;
+pub mod escalation;
+pub mod kpi;
+pub mod locale;
+pub mod login_event;
+pub mod oncall;
 pub mod role;
+pub mod schedule_feed_token;
 pub mod schedule_library;
+pub mod scheduler_tick;
 pub mod session;
 pub mod site;
+pub mod site_tariff;
 pub mod user;
 pub mod user_role;
 
 // Re-export models for easier access
+pub use alarm::*;
+pub use allowed_network::*;
+pub use api_key::*;
 pub use application_rule::*;
+pub use client_certificate::*;
 pub use company::*;
 pub use deleted_company::*;
 pub use deleted_user::*;
 pub use device::*;
+pub use device_group::*;
 pub use entity_activity::*;
+pub use escalation::*;
+pub use kpi::*;
+pub use locale::*;
+pub use login_event::*;
+pub use oncall::*;
 pub use role::*;
+pub use schedule_feed_token::*;
 pub use schedule_library::*;
+pub use scheduler_tick::*;
 pub use session::*;
 pub use site::*;
+pub use site_tariff::*;
 pub use user::*;
 pub use user_role::*;
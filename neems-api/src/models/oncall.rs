@@ -0,0 +1,206 @@
+use chrono::NaiveDateTime;
+use diesel::{Identifiable, Insertable, Queryable, Selectable};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::schema::{oncall_members, oncall_overrides, oncall_rotations};
+
+/// Most members one rotation can have
+pub const MAX_ROTATION_MEMBERS: usize = 50;
+
+/// Longest shift, four weeks
+pub const MAX_SHIFT_HOURS: i32 = 28 * 24;
+
+/// Longest override, 90 days
+pub const MAX_OVERRIDE_DAYS: i64 = 90;
+
+/// Database model for on-call rotations
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone)]
+#[diesel(table_name = oncall_rotations)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct OnCallRotationDb {
+    pub id: i32,
+    pub company_id: i32,
+    pub name: String,
+    pub handoff_at: NaiveDateTime,
+    pub shift_hours: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = oncall_rotations)]
+pub struct NewOnCallRotation {
+    pub company_id: i32,
+    pub name: String,
+    pub handoff_at: NaiveDateTime,
+    pub shift_hours: i32,
+}
+
+/// Database model for a rotation's member
+#[derive(Queryable, Selectable, Insertable, Debug, Clone)]
+#[diesel(table_name = oncall_members)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct OnCallMemberDb {
+    pub rotation_id: i32,
+    pub position: i32,
+    pub user_id: i32,
+}
+
+/// A user taking shifts on a rotation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OnCallMember {
+    pub user_id: i32,
+    pub email: String,
+}
+
+/// Database model for a rotation's override
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone)]
+#[diesel(table_name = oncall_overrides)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct OnCallOverrideDb {
+    pub id: i32,
+    pub rotation_id: i32,
+    pub user_id: i32,
+    pub starts_at: NaiveDateTime,
+    pub ends_at: NaiveDateTime,
+    pub reason: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// A time window in which a user covers a rotation instead of its member
+/// on shift.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OnCallOverride {
+    pub id: i32,
+    pub rotation_id: i32,
+    pub user_id: i32,
+    pub email: String,
+    #[ts(type = "string")]
+    pub starts_at: NaiveDateTime,
+    #[ts(type = "string")]
+    pub ends_at: NaiveDateTime,
+    pub reason: Option<String>,
+    #[ts(type = "string")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = oncall_overrides)]
+pub struct NewOnCallOverride {
+    pub rotation_id: i32,
+    pub user_id: i32,
+    pub starts_at: NaiveDateTime,
+    pub ends_at: NaiveDateTime,
+    pub reason: Option<String>,
+}
+
+/// A company's on-call rotation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OnCallRotation {
+    pub id: i32,
+    pub company_id: i32,
+    pub name: String,
+    /// When the first member's first shift starts
+    #[ts(type = "string")]
+    pub handoff_at: NaiveDateTime,
+    pub shift_hours: i32,
+    /// In shift order
+    pub members: Vec<OnCallMember>,
+    /// Overrides that haven't ended, by start
+    pub overrides: Vec<OnCallOverride>,
+    #[ts(type = "string")]
+    pub created_at: NaiveDateTime,
+    #[ts(type = "string")]
+    pub updated_at: NaiveDateTime,
+}
+
+/// Request to create a rotation, or replace one.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OnCallRotationRequest {
+    pub name: String,
+    #[ts(type = "string")]
+    pub handoff_at: NaiveDateTime,
+    /// Defaults to a week
+    #[serde(default = "default_shift_hours")]
+    pub shift_hours: i32,
+    /// User ids in shift order; a user can take more than one turn
+    pub members: Vec<i32>,
+}
+
+fn default_shift_hours() -> i32 {
+    7 * 24
+}
+
+impl OnCallRotationRequest {
+    /// Every problem with the request, if any.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if self.name.trim().is_empty() {
+            problems.push("name: must not be empty".to_string());
+        }
+        if self.shift_hours < 1 || self.shift_hours > MAX_SHIFT_HOURS {
+            problems.push(format!("shift_hours: must be from 1 to {}", MAX_SHIFT_HOURS));
+        }
+        if self.members.is_empty() || self.members.len() > MAX_ROTATION_MEMBERS {
+            problems
+                .push(format!("members: a rotation needs 1 to {} members", MAX_ROTATION_MEMBERS));
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+/// Request to add an override to a rotation.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OnCallOverrideRequest {
+    pub user_id: i32,
+    #[ts(type = "string")]
+    pub starts_at: NaiveDateTime,
+    #[ts(type = "string")]
+    pub ends_at: NaiveDateTime,
+    pub reason: Option<String>,
+}
+
+impl OnCallOverrideRequest {
+    /// Every problem with the request, if any.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if self.ends_at <= self.starts_at {
+            problems.push("ends_at: must be after starts_at".to_string());
+        } else if self.ends_at - self.starts_at > chrono::Duration::days(MAX_OVERRIDE_DAYS) {
+            problems
+                .push(format!("ends_at: an override can last at most {} days", MAX_OVERRIDE_DAYS));
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+/// Who is on call on a rotation at a time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OnCallShift {
+    pub rotation_id: i32,
+    pub user_id: i32,
+    pub email: String,
+    /// Covering through an override rather than on shift
+    pub overridden: bool,
+    /// When the shift or override started
+    #[ts(type = "string")]
+    pub from: NaiveDateTime,
+    /// When the shift or override ends
+    #[ts(type = "string")]
+    pub until: NaiveDateTime,
+}
@@ -0,0 +1,32 @@
+use chrono::NaiveDateTime;
+use diesel::{Identifiable, Insertable, Queryable, Selectable};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::schema::schedule_feed_tokens;
+
+/// A token a calendar app uses to subscribe to a site's schedule feed. The
+/// token itself is only shown when created; its hash is never returned.
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone, Serialize, Deserialize, TS)]
+#[diesel(table_name = schedule_feed_tokens)]
+#[ts(export)]
+pub struct ScheduleFeedToken {
+    pub id: i32,
+    pub site_id: i32,
+    pub user_id: i32,
+    /// The start of the token, to tell tokens apart
+    pub prefix: String,
+    pub description: Option<String>,
+    #[ts(type = "string")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = schedule_feed_tokens)]
+pub struct NewScheduleFeedToken {
+    pub site_id: i32,
+    pub user_id: i32,
+    pub token_hash: String,
+    pub prefix: String,
+    pub description: Option<String>,
+}
@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr};
 
 use diesel::{Associations, Identifiable, Insertable, Queryable, QueryableByName, Selectable};
 use serde::{Deserialize, Serialize};
@@ -77,6 +77,7 @@ pub struct ScheduleTemplate {
     pub is_active: bool,
     pub is_default: bool,
     pub created_at: chrono::NaiveDateTime,
+    pub parameters: Option<String>,
 }
 
 /// Insertable struct for creating new schedule templates
@@ -88,6 +89,7 @@ pub struct NewScheduleTemplate {
     pub description: Option<String>,
     pub is_active: bool,
     pub is_default: bool,
+    pub parameters: Option<String>,
 }
 
 /// Database model for schedule template entries
@@ -128,6 +130,45 @@ pub struct NewScheduleTemplateEntry {
 // API Models (exported to TypeScript)
 // ============================================================================
 
+/// A value a template leaves open for its commands, filled in when the
+/// template is instantiated
+#[derive(Debug, Clone, Serialize, Deserialize, TS, PartialEq)]
+#[ts(export)]
+pub struct TemplateParameter {
+    /// Referenced by command bindings; letters, digits and underscores
+    pub name: String,
+    pub description: Option<String>,
+    /// Used when an instantiation doesn't give a value
+    pub default_value: Option<f64>,
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+}
+
+/// The names of the template parameters that set a command's settings.
+/// The command's own values stand in for them until it's instantiated.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS, PartialEq)]
+#[ts(export)]
+pub struct CommandParameterBindings {
+    /// Sets `power_kw`
+    pub power_kw: Option<String>,
+    /// Sets `target_soc_percent`; the value must be a whole number
+    pub target_soc_percent: Option<String>,
+    /// Sets `execution_offset_seconds` from an hour of the day, e.g. 17.5
+    /// for 17:30
+    pub start_hour: Option<String>,
+    /// Sets `duration_seconds` from a number of hours
+    pub duration_hours: Option<String>,
+}
+
+impl CommandParameterBindings {
+    /// The parameter names bound, in field order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        [&self.power_kw, &self.target_soc_percent, &self.start_hour, &self.duration_hours]
+            .into_iter()
+            .filter_map(|name| name.as_deref())
+    }
+}
+
 /// A single command within a schedule (API model)
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export)]
@@ -137,6 +178,12 @@ pub struct ScheduleCommandDto {
     pub command_type: CommandType,
     pub duration_seconds: Option<i32>,
     pub target_soc_percent: Option<i32>,
+    /// Charge or discharge power in kW, when the command sets one
+    pub power_kw: Option<f64>,
+    /// Device the command is for, when it isn't for the whole site
+    pub device_id: Option<i32>,
+    /// Template parameters that set this command's values
+    pub bindings: Option<CommandParameterBindings>,
 }
 
 /// A schedule library item (template with embedded commands)
@@ -148,6 +195,9 @@ pub struct ScheduleLibraryItem {
     pub name: String,
     pub description: Option<String>,
     pub commands: Vec<ScheduleCommandDto>,
+    /// Parameters the commands' bindings refer to; empty for a concrete
+    /// schedule
+    pub parameters: Vec<TemplateParameter>,
     #[ts(type = "string")]
     pub created_at: chrono::NaiveDateTime,
 }
@@ -159,6 +209,8 @@ pub struct CreateLibraryItemRequest {
     pub name: String,
     pub description: Option<String>,
     pub commands: Vec<CreateCommandRequest>,
+    #[serde(default)]
+    pub parameters: Vec<TemplateParameter>,
     /// Free-form reason for creating this schedule, surfaced in the
     /// Change History pane. Optional on the wire so older/trigger-only
     /// callers stay NULL, but the UI requires it on the create form.
@@ -173,6 +225,12 @@ pub struct CreateCommandRequest {
     pub command_type: CommandType,
     pub duration_seconds: Option<i32>,
     pub target_soc_percent: Option<i32>,
+    /// Charge or discharge power in kW
+    pub power_kw: Option<f64>,
+    /// Device the command is for; omitted for the whole site
+    pub device_id: Option<i32>,
+    /// Template parameters that set this command's values
+    pub bindings: Option<CommandParameterBindings>,
 }
 
 /// Request to update a library item
@@ -182,6 +240,7 @@ pub struct UpdateLibraryItemRequest {
     pub name: Option<String>,
     pub description: Option<String>,
     pub commands: Option<Vec<CreateCommandRequest>>,
+    pub parameters: Option<Vec<TemplateParameter>>,
     /// Free-form reason for this change, surfaced in the per-day
     /// Change History pane. Optional — older callers and trigger-only
     /// changes stay NULL.
@@ -196,6 +255,37 @@ pub struct CloneLibraryItemRequest {
     pub description: Option<String>,
 }
 
+/// Request to instantiate a template for a date range
+#[derive(Debug, Deserialize, Serialize, TS)]
+#[ts(export)]
+pub struct InstantiateTemplateRequest {
+    /// Name of the schedule created
+    pub name: String,
+    pub description: Option<String>,
+    /// Site to schedule; defaults to the device's site, or the template's
+    pub site_id: Option<i32>,
+    /// Device all the commands are for; omitted to keep the template's
+    pub device_id: Option<i32>,
+    #[ts(type = "string")]
+    pub start_date: chrono::NaiveDate,
+    #[ts(type = "string")]
+    pub end_date: chrono::NaiveDate,
+    /// Parameter values by name; parameters left out take their defaults
+    #[serde(default)]
+    pub values: HashMap<String, f64>,
+    pub override_reason: Option<String>,
+}
+
+/// The schedule an instantiation created and the rule applying it
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct InstantiateTemplateResponse {
+    pub library_item: ScheduleLibraryItem,
+    pub rule: super::application_rule::ApplicationRule,
+    #[ts(type = "string[]")]
+    pub applied_dates: Vec<chrono::NaiveDate>,
+}
+
 /// The schedule command that is active for a site at a given moment.
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export)]
@@ -205,18 +295,62 @@ pub struct ActiveScheduleCommand {
     pub target_soc_percent: Option<i32>,
     pub duration_seconds: Option<i32>,
     pub ramp_duration_seconds: i32,
+    /// Charge or discharge power in kW, when the command sets one
+    pub power_kw: Option<f64>,
+    /// Device the command is for, when it isn't for the whole site
+    pub device_id: Option<i32>,
     /// When this command became active (UTC, naive).
     #[ts(type = "string")]
     pub starts_at: chrono::NaiveDateTime,
 }
 
+/// What decided the active command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum CommandSource {
+    /// The site's effective schedule
+    Schedule,
+    /// A maintenance window, which holds the site (or device) in standby
+    Maintenance,
+}
+
 /// Response for the active-command endpoint. `command` is `None` when the site
-/// has no effective schedule (the battery should fall back to standby).
+/// has no effective schedule, or is under maintenance (the battery should fall
+/// back to standby).
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export)]
 pub struct ActiveCommandResponse {
     pub site_id: i32,
     pub command: Option<ActiveScheduleCommand>,
+    pub source: CommandSource,
+    /// The priority layer of the schedule rule that won, when `source` is
+    /// `schedule` and one applies
+    pub priority: Option<super::application_rule::OverridePriority>,
+    /// The open maintenance window, when `source` is `maintenance`
+    pub maintenance: Option<neems_data::maintenance_windows::MaintenanceWindow>,
+    /// The command's power split across the site's dispatch device group,
+    /// when the command sets a site-wide power and the site has such a group
+    pub dispatch: Option<super::device_group::GroupDispatch>,
+}
+
+impl ActiveCommandResponse {
+    /// A response from the schedule, decided by a rule in the `priority`
+    /// layer.
+    pub fn scheduled(
+        site_id: i32,
+        command: Option<ActiveScheduleCommand>,
+        priority: Option<super::application_rule::OverridePriority>,
+    ) -> Self {
+        ActiveCommandResponse {
+            site_id,
+            command,
+            source: CommandSource::Schedule,
+            priority,
+            maintenance: None,
+            dispatch: None,
+        }
+    }
 }
 
 // Helper function to convert CommandType to string for database
@@ -0,0 +1,37 @@
+use chrono::NaiveDateTime;
+use diesel::{Identifiable, Insertable, Queryable, Selectable};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::schema::scheduler_ticks;
+
+/// The scheduler's last evaluation of a site's active command, and its last
+/// catch-up after a restart.
+#[derive(
+    Queryable, Selectable, Identifiable, Debug, Clone, PartialEq, Serialize, Deserialize, TS,
+)]
+#[diesel(table_name = scheduler_ticks)]
+#[diesel(primary_key(site_id))]
+#[ts(export)]
+pub struct SchedulerTick {
+    pub site_id: i32,
+    #[ts(type = "string")]
+    pub evaluated_at: NaiveDateTime,
+    /// The schedule command active then, if any
+    pub command_id: Option<i32>,
+    /// When the scheduler last caught up on command starts it missed
+    #[ts(type = "string | null")]
+    pub caught_up_at: Option<NaiveDateTime>,
+    /// How many command starts that catch-up found missed
+    pub missed_commands: i32,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = scheduler_ticks)]
+pub struct NewSchedulerTick {
+    pub site_id: i32,
+    pub evaluated_at: NaiveDateTime,
+    pub command_id: Option<i32>,
+    pub caught_up_at: Option<NaiveDateTime>,
+    pub missed_commands: i32,
+}
@@ -1,3 +1,4 @@
+use chrono::NaiveDateTime;
 use diesel::{Associations, Identifiable, Insertable, Queryable, QueryableByName, Selectable};
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
@@ -80,6 +81,12 @@ pub struct Site {
     /// Nullable so existing rows are interpreted as "unset" and the
     /// consumer falls back to a default.
     pub trickle_charge_power_kw: Option<f64>,
+    /// When the site was decommissioned and its history archived; an
+    /// archived site is read-only.
+    #[ts(type = "string | null")]
+    pub archived_at: Option<NaiveDateTime>,
+    /// Blob storage key of the site's archive
+    pub archive_key: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -140,6 +147,8 @@ pub struct SiteWithTimestamps {
     pub charge_rate_percent: f64,
     pub discharge_rate_percent: f64,
     pub trickle_charge_power_kw: Option<f64>,
+    #[ts(type = "string | null")]
+    pub archived_at: Option<NaiveDateTime>,
     #[ts(type = "string")]
     pub created_at: chrono::NaiveDateTime,
     #[ts(type = "string")]
@@ -0,0 +1,57 @@
+use chrono::NaiveDateTime;
+use diesel::{Identifiable, Insertable, Queryable, Selectable};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::schema::site_tariffs;
+
+/// A site's electricity prices. Which price applies when comes from the
+/// site's off-peak and peak revenue windows.
+#[derive(
+    Queryable, Selectable, Identifiable, Debug, Clone, PartialEq, Serialize, Deserialize, TS,
+)]
+#[diesel(table_name = site_tariffs)]
+#[diesel(primary_key(site_id))]
+#[ts(export)]
+pub struct SiteTariff {
+    pub site_id: i32,
+    pub currency: String,
+    /// Price of energy outside the off-peak and peak windows
+    pub standard_price_per_kwh: f64,
+    pub off_peak_price_per_kwh: f64,
+    pub peak_price_per_kwh: f64,
+    /// Charge per kW of a month's peak demand
+    pub demand_charge_per_kw: f64,
+    #[ts(type = "string")]
+    pub updated_at: NaiveDateTime,
+}
+
+/// Request to set a site's tariff, replacing any it has.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SetSiteTariffRequest {
+    /// Defaults to USD
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub standard_price_per_kwh: f64,
+    pub off_peak_price_per_kwh: f64,
+    pub peak_price_per_kwh: f64,
+    /// Defaults to 0
+    #[serde(default)]
+    pub demand_charge_per_kw: f64,
+}
+
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = site_tariffs)]
+pub struct NewSiteTariff {
+    pub site_id: i32,
+    pub currency: String,
+    pub standard_price_per_kwh: f64,
+    pub off_peak_price_per_kwh: f64,
+    pub peak_price_per_kwh: f64,
+    pub demand_charge_per_kw: f64,
+}
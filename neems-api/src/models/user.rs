@@ -13,6 +13,14 @@ pub struct User {
     pub password_hash: String,
     pub company_id: i32,
     pub totp_secret: Option<String>,
+    /// Set to force a password rotation; until the password is changed the
+    /// user can do nothing else (see [`crate::session_guards`])
+    #[serde(default)]
+    pub must_change_password: bool,
+    /// A machine client that authenticates with API keys rather than a
+    /// password; `email` holds its account name
+    #[serde(default)]
+    pub service_account: bool,
 }
 
 #[derive(Insertable, Deserialize)]
@@ -43,6 +51,8 @@ pub struct UserWithRoles {
     pub password_hash: String,
     pub company_id: i32,
     pub totp_secret: Option<String>,
+    pub must_change_password: bool,
+    pub service_account: bool,
     pub roles: Vec<Role>,
 }
 
@@ -55,6 +65,8 @@ pub struct UserWithTimestamps {
     pub password_hash: String,
     pub company_id: i32,
     pub totp_secret: Option<String>,
+    pub must_change_password: bool,
+    pub service_account: bool,
     #[ts(type = "string")]
     pub created_at: chrono::NaiveDateTime,
     #[ts(type = "string")]
@@ -70,6 +82,8 @@ pub struct UserWithRolesAndTimestamps {
     pub password_hash: String,
     pub company_id: i32,
     pub totp_secret: Option<String>,
+    pub must_change_password: bool,
+    pub service_account: bool,
     #[ts(type = "string")]
     pub created_at: chrono::NaiveDateTime,
     #[ts(type = "string")]
@@ -0,0 +1,93 @@
+//! Streaming NDJSON (newline-delimited JSON) responses.
+//!
+//! Endpoints that can return very large collections, like readings exports,
+//! stream one JSON row per line when the client sends
+//! `Accept: application/x-ndjson`, instead of building the whole response in
+//! memory. Rows are produced on a database thread and handed to the response
+//! through a small bounded channel, so a slow client slows the query down
+//! rather than letting rows pile up.
+//!
+//! Errors after the response has started can't change its status, so they
+//! end the stream with a final `{"error": "..."}` line.
+
+use std::io::Cursor;
+
+use rocket::{
+    Request, Response,
+    futures::stream,
+    http::ContentType,
+    request::{FromRequest, Outcome},
+    response::{self, Responder, stream::ReaderStream},
+    tokio::sync::mpsc,
+};
+use serde::Serialize;
+
+/// Lines buffered between the database thread and the response.
+const CHANNEL_CAPACITY: usize = 64;
+
+pub fn content_type() -> ContentType {
+    ContentType::new("application", "x-ndjson")
+}
+
+/// Whether the client prefers NDJSON over a single JSON document.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WantsNdjson(pub bool);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for WantsNdjson {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let ndjson = content_type();
+        let wants = request
+            .accept()
+            .is_some_and(|accept| accept.preferred().media_type() == ndjson.media_type());
+        Outcome::Success(WantsNdjson(wants))
+    }
+}
+
+/// Creates the sending and responding ends of an NDJSON stream.
+pub fn channel() -> (NdjsonSender, NdjsonStream) {
+    let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    (NdjsonSender(sender), NdjsonStream(receiver))
+}
+
+/// Sends rows from a database thread. Sends block while the channel is full,
+/// so only use this off the async workers (e.g. inside `DbConn::run`).
+pub struct NdjsonSender(mpsc::Sender<String>);
+
+impl NdjsonSender {
+    /// Sends one row, returning false once the client has gone away and
+    /// there's no point producing more.
+    pub fn send<T: Serialize>(&self, row: &T) -> bool {
+        match serde_json::to_string(row) {
+            Ok(line) => self.0.blocking_send(line + "\n").is_ok(),
+            Err(e) => {
+                self.send_error(&format!("Error serializing row: {}", e));
+                false
+            }
+        }
+    }
+
+    /// Ends the stream with an error line.
+    pub fn send_error(&self, error: &str) {
+        let line = serde_json::json!({ "error": error }).to_string();
+        let _ = self.0.blocking_send(line + "\n");
+    }
+}
+
+/// A streamed `application/x-ndjson` response body.
+pub struct NdjsonStream(mpsc::Receiver<String>);
+
+impl<'r, 'o: 'r> Responder<'r, 'o> for NdjsonStream {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'o> {
+        let lines = stream::unfold(self.0, |mut receiver| async move {
+            let line = receiver.recv().await?;
+            Some((Cursor::new(line.into_bytes()), receiver))
+        });
+        Response::build()
+            .header(content_type())
+            .streamed_body(ReaderStream::from(lines))
+            .ok()
+    }
+}
@@ -3,7 +3,7 @@
 //! This module provides parsing and handling for OData system query options
 //! including $select, $filter, $orderby, $top, $skip, $count, and $expand.
 
-use rocket::form::FromForm;
+use rocket::{form::FromForm, http::uri::Origin};
 use serde::Serialize;
 
 /// OData system query options
@@ -371,6 +371,32 @@ impl<T> ODataEntityResponse<T> {
     }
 }
 
+/// The `@odata.nextLink` of a page of results: the request `uri` again with
+/// `$skip` moved past this page. `None` unless `$top` left matching entities
+/// out; `total_count` is the count [`apply_query`] returns.
+///
+/// The link is relative to the server, and keeps the request's other query
+/// options as they were sent.
+pub fn next_link(uri: &Origin<'_>, query: &ODataQuery, total_count: i64) -> Option<String> {
+    let top = query.top.filter(|&top| top > 0)?;
+    let skip = query.skip.unwrap_or(0).max(0) + top;
+    if skip >= total_count {
+        return None;
+    }
+
+    let is_skip = |param: &str| {
+        let key = param.split('=').next().unwrap_or_default();
+        key.eq_ignore_ascii_case("$skip") || key.eq_ignore_ascii_case("%24skip")
+    };
+    let skip_param = format!("$skip={}", skip);
+    let mut params: Vec<&str> = uri
+        .query()
+        .map(|q| q.as_str().split('&').filter(|p| !p.is_empty() && !is_skip(p)).collect())
+        .unwrap_or_default();
+    params.push(&skip_param);
+    Some(format!("{}?{}", uri.path(), params.join("&")))
+}
+
 /// Helper function to build context URL
 pub fn build_context_url(base_url: &str, entity_set: &str, select: Option<&[String]>) -> String {
     let mut context = format!("{base_url}/$metadata#{entity_set}");
@@ -412,3 +438,38 @@ pub fn apply_select<T: Serialize>(
         serde_json::to_value(entity)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(top: Option<i64>, skip: Option<i64>) -> ODataQuery {
+        ODataQuery { top, skip, ..Default::default() }
+    }
+
+    #[test]
+    fn test_next_link_advances_skip() {
+        let uri = Origin::parse("/api/1/Roles?$filter=id%20gt%201&$top=2&$skip=2").unwrap();
+        assert_eq!(
+            next_link(&uri, &query(Some(2), Some(2)), 5).as_deref(),
+            Some("/api/1/Roles?$filter=id%20gt%201&$top=2&$skip=4")
+        );
+
+        let uri = Origin::parse("/api/1/Roles?%24top=2").unwrap();
+        assert_eq!(
+            next_link(&uri, &query(Some(2), None), 3).as_deref(),
+            Some("/api/1/Roles?%24top=2&$skip=2")
+        );
+    }
+
+    #[test]
+    fn test_no_next_link_on_the_last_page() {
+        let uri = Origin::parse("/api/1/Roles?$top=2&$skip=2").unwrap();
+        assert_eq!(next_link(&uri, &query(Some(2), Some(2)), 4), None);
+        // Nothing was left out without $top
+        let uri = Origin::parse("/api/1/Roles?$skip=2").unwrap();
+        assert_eq!(next_link(&uri, &query(None, Some(2)), 10), None);
+        let uri = Origin::parse("/api/1/Roles?$top=0").unwrap();
+        assert_eq!(next_link(&uri, &query(Some(0), None), 10), None);
+    }
+}
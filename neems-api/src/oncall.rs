@@ -0,0 +1,147 @@
+//! Who is on call on a rotation.
+//!
+//! A rotation's members take shifts of `shift_hours` in order, the first
+//! starting at `handoff_at`, and the pattern repeats, both forwards and
+//! backwards from it. An override puts a user on call for its time window
+//! instead; where overrides overlap, the latest added wins.
+
+use chrono::{Duration, NaiveDateTime};
+
+use crate::models::{OnCallRotation, OnCallShift};
+
+/// Who is on call on `rotation` at `at`, from the overrides it was loaded
+/// with; None if it has no members.
+pub fn on_call_at(rotation: &OnCallRotation, at: NaiveDateTime) -> Option<OnCallShift> {
+    let overriding = rotation
+        .overrides
+        .iter()
+        .filter(|o| o.starts_at <= at && at < o.ends_at)
+        .max_by_key(|o| (o.created_at, o.id));
+    if let Some(o) = overriding {
+        // Until it ends, or a later override takes over
+        let until = rotation
+            .overrides
+            .iter()
+            .filter(|later| (later.created_at, later.id) > (o.created_at, o.id))
+            .map(|later| later.starts_at)
+            .filter(|start| *start > at && *start < o.ends_at)
+            .min()
+            .unwrap_or(o.ends_at);
+        return Some(OnCallShift {
+            rotation_id: rotation.id,
+            user_id: o.user_id,
+            email: o.email.clone(),
+            overridden: true,
+            from: o.starts_at,
+            until,
+        });
+    }
+
+    let shift = Duration::hours(rotation.shift_hours.max(1) as i64);
+    let shifts = (at - rotation.handoff_at).num_seconds().div_euclid(shift.num_seconds());
+    let member = rotation
+        .members
+        .get(shifts.rem_euclid(rotation.members.len().max(1) as i64) as usize)?;
+    let shift_start = rotation.handoff_at + Duration::seconds(shift.num_seconds() * shifts);
+    let shift_end = shift_start + shift;
+    // An override ending or starting during the shift cuts it short
+    let from = rotation
+        .overrides
+        .iter()
+        .map(|o| o.ends_at)
+        .filter(|end| *end > shift_start && *end <= at)
+        .max()
+        .unwrap_or(shift_start);
+    let until = rotation
+        .overrides
+        .iter()
+        .map(|o| o.starts_at)
+        .filter(|start| *start > at && *start < shift_end)
+        .min()
+        .unwrap_or(shift_end);
+    Some(OnCallShift {
+        rotation_id: rotation.id,
+        user_id: member.user_id,
+        email: member.email.clone(),
+        overridden: false,
+        from,
+        until,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::models::{OnCallMember, OnCallOverride};
+
+    fn at(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 10, day).unwrap().and_hms_opt(hour, 0, 0).unwrap()
+    }
+
+    fn rotation(overrides: Vec<OnCallOverride>) -> OnCallRotation {
+        let member = |user_id: i32| OnCallMember {
+            user_id,
+            email: format!("{}@example.com", user_id),
+        };
+        OnCallRotation {
+            id: 1,
+            company_id: 1,
+            name: "operations".to_string(),
+            handoff_at: at(5, 9),
+            shift_hours: 24,
+            members: vec![member(1), member(2), member(3)],
+            overrides,
+            created_at: at(1, 0),
+            updated_at: at(1, 0),
+        }
+    }
+
+    fn cover(
+        id: i32,
+        user_id: i32,
+        starts_at: NaiveDateTime,
+        ends_at: NaiveDateTime,
+    ) -> OnCallOverride {
+        OnCallOverride {
+            id,
+            rotation_id: 1,
+            user_id,
+            email: format!("{}@example.com", user_id),
+            starts_at,
+            ends_at,
+            reason: None,
+            created_at: at(1, id as u32),
+        }
+    }
+
+    #[test]
+    fn members_take_shifts_in_turn() {
+        let rotation = rotation(vec![]);
+        let on_call = |t| on_call_at(&rotation, t).unwrap();
+        assert_eq!(on_call(at(5, 9)).user_id, 1);
+        assert_eq!(on_call(at(6, 8)).user_id, 1);
+        assert_eq!(on_call(at(6, 9)).user_id, 2);
+        assert_eq!(on_call(at(8, 12)).user_id, 1);
+        // And before the first handoff
+        let shift = on_call(at(4, 12));
+        assert_eq!((shift.user_id, shift.from, shift.until), (3, at(4, 9), at(5, 9)));
+    }
+
+    #[test]
+    fn overrides_cover_their_window() {
+        let rotation = rotation(vec![
+            cover(1, 7, at(5, 12), at(5, 18)),
+            // Added later, so it wins where they overlap
+            cover(2, 8, at(5, 16), at(5, 20)),
+        ]);
+        let shift = on_call_at(&rotation, at(5, 10)).unwrap();
+        assert_eq!((shift.user_id, shift.until), (1, at(5, 12)));
+        let shift = on_call_at(&rotation, at(5, 13)).unwrap();
+        assert_eq!((shift.user_id, shift.overridden, shift.until), (7, true, at(5, 16)));
+        assert_eq!(on_call_at(&rotation, at(5, 17)).unwrap().user_id, 8);
+        let shift = on_call_at(&rotation, at(5, 21)).unwrap();
+        assert_eq!((shift.user_id, shift.from, shift.until), (1, at(5, 20), at(6, 9)));
+    }
+}
@@ -0,0 +1,181 @@
+//! Database operations for alarm acknowledgement, shelving and comments.
+
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+use crate::models::{AlarmAction, AlarmState, NewAlarmAction, User, alarm_action};
+
+/// Every alarm's state, by alarm number
+pub fn list_alarm_states(
+    conn: &mut SqliteConnection,
+) -> Result<Vec<AlarmState>, diesel::result::Error> {
+    use crate::schema::alarm_states::dsl::*;
+
+    alarm_states.order(alarm_num.asc()).select(AlarmState::as_select()).load(conn)
+}
+
+/// An alarm's state; the default if no one has acted on it
+pub fn get_alarm_state(
+    conn: &mut SqliteConnection,
+    num: i32,
+) -> Result<AlarmState, diesel::result::Error> {
+    use crate::schema::alarm_states::dsl::*;
+
+    Ok(alarm_states
+        .find(num)
+        .select(AlarmState::as_select())
+        .first(conn)
+        .optional()?
+        .unwrap_or(AlarmState { alarm_num: num, ..Default::default() }))
+}
+
+/// Applies `change` to an alarm's state and logs `action`, in one transaction
+fn record(
+    conn: &mut SqliteConnection,
+    action: NewAlarmAction,
+    change: impl FnOnce(&mut AlarmState),
+) -> Result<AlarmState, diesel::result::Error> {
+    use crate::schema::{alarm_actions, alarm_states};
+
+    conn.transaction(|conn| {
+        let mut state = get_alarm_state(conn, action.alarm_num)?;
+        change(&mut state);
+        diesel::replace_into(alarm_states::table).values(&state).execute(conn)?;
+        diesel::insert_into(alarm_actions::table).values(&action).execute(conn)?;
+        Ok(state)
+    })
+}
+
+fn new_action(
+    num: i32,
+    action: &str,
+    user: &User,
+    comment: Option<String>,
+    shelved_until: Option<NaiveDateTime>,
+    now: NaiveDateTime,
+) -> NewAlarmAction {
+    NewAlarmAction {
+        alarm_num: num,
+        action: action.to_string(),
+        user_id: Some(user.id),
+        user_email: user.email.clone(),
+        comment,
+        shelved_until,
+        created_at: now,
+    }
+}
+
+/// Acknowledges an alarm as `user`
+pub fn acknowledge_alarm(
+    conn: &mut SqliteConnection,
+    num: i32,
+    user: &User,
+    comment: Option<String>,
+    now: NaiveDateTime,
+) -> Result<AlarmState, diesel::result::Error> {
+    let action = new_action(num, alarm_action::ACKNOWLEDGE, user, comment, None, now);
+    record(conn, action, |state| {
+        state.acknowledged_at = Some(now);
+        state.acknowledged_by = Some(user.email.clone());
+    })
+}
+
+/// Shelves an alarm until `until` as `user`, for `reason`
+pub fn shelve_alarm(
+    conn: &mut SqliteConnection,
+    num: i32,
+    user: &User,
+    until: NaiveDateTime,
+    reason: String,
+    now: NaiveDateTime,
+) -> Result<AlarmState, diesel::result::Error> {
+    let action =
+        new_action(num, alarm_action::SHELVE, user, Some(reason.clone()), Some(until), now);
+    record(conn, action, |state| {
+        state.shelved_until = Some(until);
+        state.shelved_by = Some(user.email.clone());
+        state.shelve_reason = Some(reason);
+    })
+}
+
+/// Returns a shelved alarm to active views as `user`
+pub fn unshelve_alarm(
+    conn: &mut SqliteConnection,
+    num: i32,
+    user: &User,
+    now: NaiveDateTime,
+) -> Result<AlarmState, diesel::result::Error> {
+    let action = new_action(num, alarm_action::UNSHELVE, user, None, None, now);
+    record(conn, action, |state| {
+        state.shelved_until = None;
+        state.shelved_by = None;
+        state.shelve_reason = None;
+    })
+}
+
+/// Comments on an alarm as `user`
+pub fn comment_on_alarm(
+    conn: &mut SqliteConnection,
+    num: i32,
+    user: &User,
+    comment: String,
+    now: NaiveDateTime,
+) -> Result<AlarmAction, diesel::result::Error> {
+    use crate::schema::alarm_actions;
+
+    let action = new_action(num, alarm_action::COMMENT, user, Some(comment), None, now);
+    conn.transaction(|conn| {
+        diesel::insert_into(alarm_actions::table).values(&action).execute(conn)?;
+        alarm_actions::table
+            .order(alarm_actions::id.desc())
+            .select(AlarmAction::as_select())
+            .first(conn)
+    })
+}
+
+/// An alarm's actions, newest first
+pub fn list_alarm_actions(
+    conn: &mut SqliteConnection,
+    num: i32,
+) -> Result<Vec<AlarmAction>, diesel::result::Error> {
+    use crate::schema::alarm_actions::dsl::*;
+
+    alarm_actions
+        .filter(alarm_num.eq(num))
+        .order((created_at.desc(), id.desc()))
+        .select(AlarmAction::as_select())
+        .load(conn)
+}
+
+/// Brings alarm states up to date with the `active` alarms at `now`: an alarm
+/// newly active is marked raised, and every other alarm has its
+/// acknowledgement and raise dropped, so it needs acknowledging, and is
+/// escalated, afresh when it recurs.
+pub fn sync_active_alarms(
+    conn: &mut SqliteConnection,
+    active: &[i32],
+    now: NaiveDateTime,
+) -> Result<(), diesel::result::Error> {
+    use crate::schema::alarm_states::dsl::*;
+
+    conn.transaction(|conn| {
+        diesel::update(
+            alarm_states
+                .filter(acknowledged_at.is_not_null().or(raised_at.is_not_null()))
+                .filter(alarm_num.ne_all(active)),
+        )
+        .set((
+            acknowledged_at.eq(None::<NaiveDateTime>),
+            acknowledged_by.eq(None::<String>),
+            raised_at.eq(None::<NaiveDateTime>),
+        ))
+        .execute(conn)?;
+        let new: Vec<_> =
+            active.iter().map(|num| (alarm_num.eq(*num), raised_at.eq(now))).collect();
+        diesel::insert_or_ignore_into(alarm_states).values(&new).execute(conn)?;
+        diesel::update(alarm_states.filter(alarm_num.eq_any(active)).filter(raised_at.is_null()))
+            .set(raised_at.eq(now))
+            .execute(conn)?;
+        Ok(())
+    })
+}
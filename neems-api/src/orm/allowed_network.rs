@@ -0,0 +1,59 @@
+use diesel::prelude::*;
+
+/// What an allowlist belongs to
+#[derive(Debug, Clone, Copy)]
+pub enum NetworkOwner {
+    Company(i32),
+    ClientCertificate(i32),
+}
+
+/// Gets the networks on an allowlist, in the order they were set
+pub fn get_allowed_networks(
+    conn: &mut SqliteConnection,
+    owner: NetworkOwner,
+) -> Result<Vec<String>, diesel::result::Error> {
+    use crate::schema::allowed_networks::dsl::*;
+
+    let query = allowed_networks.select(network).order(id.asc()).into_boxed();
+    match owner {
+        NetworkOwner::Company(owner_id) => query.filter(company_id.eq(owner_id)).load(conn),
+        NetworkOwner::ClientCertificate(owner_id) => {
+            query.filter(client_certificate_id.eq(owner_id)).load(conn)
+        }
+    }
+}
+
+/// Replaces the networks on an allowlist
+pub fn set_allowed_networks(
+    conn: &mut SqliteConnection,
+    owner: NetworkOwner,
+    networks: &[String],
+) -> Result<(), diesel::result::Error> {
+    use crate::schema::allowed_networks::dsl::*;
+
+    let (company, certificate) = match owner {
+        NetworkOwner::Company(owner_id) => (Some(owner_id), None),
+        NetworkOwner::ClientCertificate(owner_id) => (None, Some(owner_id)),
+    };
+    conn.transaction(|conn| {
+        match owner {
+            NetworkOwner::Company(owner_id) => {
+                diesel::delete(allowed_networks.filter(company_id.eq(owner_id))).execute(conn)?
+            }
+            NetworkOwner::ClientCertificate(owner_id) => {
+                diesel::delete(allowed_networks.filter(client_certificate_id.eq(owner_id)))
+                    .execute(conn)?
+            }
+        };
+        for entry in networks {
+            diesel::insert_into(allowed_networks)
+                .values((
+                    company_id.eq(company),
+                    client_certificate_id.eq(certificate),
+                    network.eq(entry),
+                ))
+                .execute(conn)?;
+        }
+        Ok(())
+    })
+}
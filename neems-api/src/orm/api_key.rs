@@ -0,0 +1,154 @@
+//! Database operations for service account API keys.
+//!
+//! Keys are 32 random bytes, hex-encoded behind a `neems_` prefix. Only
+//! their SHA-256 is stored: unlike passwords they carry enough entropy that
+//! a slow hash buys nothing, and a fast one keeps per-request lookups cheap.
+
+use chrono::Utc;
+use diesel::prelude::*;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::models::{ApiKey, NewApiKey};
+
+const KEY_PREFIX: &str = "neems_";
+
+/// Characters of a key kept as its displayed prefix
+const DISPLAYED_PREFIX_LEN: usize = KEY_PREFIX.len() + 8;
+
+fn generate_api_key() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rng().fill_bytes(&mut bytes);
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}{}", KEY_PREFIX, hex)
+}
+
+fn hash_api_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Creates an API key for a user, returning it along with the key itself,
+/// which isn't stored and can't be retrieved later
+pub fn create_api_key(
+    conn: &mut SqliteConnection,
+    user_id_param: i32,
+    description_param: Option<String>,
+) -> Result<(ApiKey, String), diesel::result::Error> {
+    use crate::schema::api_keys::dsl::*;
+
+    let key = generate_api_key();
+    let new_key = NewApiKey {
+        user_id: user_id_param,
+        key_hash: hash_api_key(&key),
+        prefix: key[..DISPLAYED_PREFIX_LEN].to_string(),
+        description: description_param,
+    };
+    diesel::insert_into(api_keys).values(&new_key).execute(conn)?;
+
+    let api_key = api_keys
+        .filter(key_hash.eq(&new_key.key_hash))
+        .select(ApiKey::as_select())
+        .first(conn)?;
+    Ok((api_key, key))
+}
+
+/// Replaces one of a user's API keys with a new one, returning `None` if
+/// there's no such key. The old key stops working at once.
+pub fn rotate_api_key(
+    conn: &mut SqliteConnection,
+    user_id_param: i32,
+    key_id: i32,
+) -> Result<Option<(ApiKey, String)>, diesel::result::Error> {
+    use crate::schema::api_keys::dsl::*;
+
+    let key = generate_api_key();
+    let updated = diesel::update(api_keys.filter(id.eq(key_id)).filter(user_id.eq(user_id_param)))
+        .set((
+            key_hash.eq(hash_api_key(&key)),
+            prefix.eq(&key[..DISPLAYED_PREFIX_LEN]),
+            rotated_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(conn)?;
+    if updated == 0 {
+        return Ok(None);
+    }
+
+    let api_key = api_keys.filter(id.eq(key_id)).select(ApiKey::as_select()).first(conn)?;
+    Ok(Some((api_key, key)))
+}
+
+/// Gets all of a user's API keys
+pub fn get_user_api_keys(
+    conn: &mut SqliteConnection,
+    user_id_param: i32,
+) -> Result<Vec<ApiKey>, diesel::result::Error> {
+    use crate::schema::api_keys::dsl::*;
+
+    api_keys
+        .filter(user_id.eq(user_id_param))
+        .order(id.asc())
+        .select(ApiKey::as_select())
+        .load(conn)
+}
+
+/// Removes one of a user's API keys, returning whether it existed
+pub fn delete_api_key(
+    conn: &mut SqliteConnection,
+    user_id_param: i32,
+    key_id: i32,
+) -> Result<bool, diesel::result::Error> {
+    use crate::schema::api_keys::dsl::*;
+
+    let deleted = diesel::delete(api_keys.filter(id.eq(key_id)).filter(user_id.eq(user_id_param)))
+        .execute(conn)?;
+    Ok(deleted > 0)
+}
+
+/// Finds the API key a client presented
+pub fn get_api_key_by_key(
+    conn: &mut SqliteConnection,
+    key: &str,
+) -> Result<Option<ApiKey>, diesel::result::Error> {
+    use crate::schema::api_keys::dsl::*;
+
+    api_keys
+        .filter(key_hash.eq(hash_api_key(key)))
+        .select(ApiKey::as_select())
+        .first(conn)
+        .optional()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orm::{testing::setup_test_db, user::insert_service_account};
+
+    #[test]
+    fn test_api_key_lifecycle() {
+        let mut conn = setup_test_db();
+        let company = crate::company::insert_company(&mut conn, "Key Co".to_string(), None)
+            .expect("insert company");
+        let user = insert_service_account(&mut conn, "report-job", company.id, None)
+            .expect("insert service account");
+        assert!(user.service_account);
+
+        let (api_key, key) =
+            create_api_key(&mut conn, user.id, Some("reports".to_string())).unwrap();
+        assert!(key.starts_with(&api_key.prefix));
+        assert_eq!(key.len(), KEY_PREFIX.len() + 64);
+        assert_eq!(get_api_key_by_key(&mut conn, &key).unwrap().unwrap().id, api_key.id);
+        assert!(get_api_key_by_key(&mut conn, &generate_api_key()).unwrap().is_none());
+
+        assert!(rotate_api_key(&mut conn, user.id + 1, api_key.id).unwrap().is_none());
+        let (rotated, new_key) = rotate_api_key(&mut conn, user.id, api_key.id).unwrap().unwrap();
+        assert_eq!(rotated.id, api_key.id);
+        assert!(rotated.rotated_at.is_some());
+        assert!(get_api_key_by_key(&mut conn, &key).unwrap().is_none(), "old key revoked");
+        assert_eq!(get_api_key_by_key(&mut conn, &new_key).unwrap().unwrap().id, api_key.id);
+
+        assert_eq!(get_user_api_keys(&mut conn, user.id).unwrap().len(), 1);
+        assert!(delete_api_key(&mut conn, user.id, api_key.id).unwrap());
+        assert!(!delete_api_key(&mut conn, user.id, api_key.id).unwrap());
+        assert!(get_api_key_by_key(&mut conn, &new_key).unwrap().is_none());
+    }
+}
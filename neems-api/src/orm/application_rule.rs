@@ -3,8 +3,9 @@ use std::collections::HashMap;
 use diesel::{prelude::*, sql_types::BigInt};
 
 use crate::models::{
-    ApplicationRule, ApplicationRuleDb, CalendarDaySchedule, CalendarDayScheduleMatches,
-    CreateApplicationRuleRequest, EffectiveScheduleResponse, NewApplicationRule, RuleType,
+    ActiveCommandResponse, ActiveScheduleCommand, ApplicationRule, ApplicationRuleDb,
+    CalendarDaySchedule, CalendarDayScheduleMatches, CreateApplicationRuleRequest,
+    EffectiveScheduleResponse, NewApplicationRule, OverridePriority, RuleType,
 };
 
 #[derive(QueryableByName)]
@@ -58,6 +59,7 @@ pub fn create_application_rule(
             days_of_week: days_of_week_json,
             specific_dates: specific_dates_json,
             override_reason: request.override_reason.clone(),
+            priority: request.priority.unwrap_or_default().as_str().to_string(),
         };
 
         diesel::insert_into(application_rules::table).values(&new_rule).execute(conn)?;
@@ -191,6 +193,31 @@ pub fn get_application_rules_for_site(
         .collect()
 }
 
+/// The site's specific-date rules in a higher priority layer than `priority`
+/// that cover any of `dates`, each with the dates it shares with them. An
+/// override on those dates would never take effect.
+pub fn preempting_overrides(
+    conn: &mut SqliteConnection,
+    site_id: i32,
+    dates: &[String],
+    priority: OverridePriority,
+) -> Result<Vec<(ApplicationRule, Vec<String>)>, diesel::result::Error> {
+    Ok(get_application_rules_for_site(conn, site_id)?
+        .into_iter()
+        .filter(|rule| rule.rule_type == RuleType::SpecificDate && rule.priority > priority)
+        .filter_map(|rule| {
+            let shared: Vec<String> = rule
+                .specific_dates
+                .iter()
+                .flatten()
+                .filter(|date| dates.contains(date))
+                .cloned()
+                .collect();
+            (!shared.is_empty()).then_some((rule, shared))
+        })
+        .collect())
+}
+
 /// Compute the list of dates that satisfy a season-fill request without
 /// touching the database. Exposed for the API layer so it can preview the
 /// fill, and for tests.
@@ -258,6 +285,7 @@ pub fn season_fill_application_rule(
         specific_dates: Some(dates.iter().map(|d| d.to_string()).collect()),
         override_reason,
         change_reason: None,
+        priority: None,
     };
 
     let rule = create_application_rule(conn, template_id, request, acting_user_id)?;
@@ -297,7 +325,9 @@ pub fn delete_application_rule(
 }
 
 /// Gets the effective schedule for a specific date
-/// Applies precedence rules: specific_date > day_of_week > default
+/// Applies precedence rules: the highest priority layer first (safety, then
+/// utility_dr, then operator), then within it specific_date > day_of_week >
+/// default, then the newest rule
 pub fn get_effective_schedule(
     conn: &mut SqliteConnection,
     site_id: i32,
@@ -358,9 +388,13 @@ pub fn get_effective_schedule(
         })
         .collect();
 
-    // 5. Sort by specificity DESC, then created_at DESC
+    // 5. Sort by priority DESC, then specificity DESC, then created_at DESC
     matching_rules.sort_by(|(rule_a, spec_a), (rule_b, spec_b)| {
-        spec_b.cmp(spec_a).then_with(|| rule_b.created_at.cmp(&rule_a.created_at))
+        rule_b
+            .priority
+            .cmp(&rule_a.priority)
+            .then_with(|| spec_b.cmp(spec_a))
+            .then_with(|| rule_b.created_at.cmp(&rule_a.created_at))
     });
 
     // 6. Get winning rule
@@ -376,10 +410,107 @@ pub fn get_effective_schedule(
     Ok(EffectiveScheduleResponse {
         library_item,
         specificity: *specificity,
+        priority: winning_rule.priority,
         rule: winning_rule.clone(),
     })
 }
 
+/// The schedule command active for a site at `now` (UTC).
+///
+/// The command with the greatest `execution_offset_seconds` not after the
+/// time of day, or — before the first command of the day — the day's last
+/// command, which carries over from the previous day since schedules are
+/// daily-cyclic. `command` is `None` when the site has no effective schedule.
+pub fn get_active_command(
+    conn: &mut SqliteConnection,
+    site_id: i32,
+    now: chrono::NaiveDateTime,
+) -> Result<ActiveCommandResponse, diesel::result::Error> {
+    let today = now.date();
+
+    let effective = match get_effective_schedule(conn, site_id, today) {
+        Ok(schedule) => schedule,
+        // No schedule configured for today: no active command.
+        Err(diesel::result::Error::NotFound) => {
+            return Ok(ActiveCommandResponse::scheduled(site_id, None, None));
+        }
+        Err(e) => return Err(e),
+    };
+
+    let priority = Some(effective.priority);
+    let mut commands = effective.library_item.commands;
+    commands.sort_by_key(|c| c.execution_offset_seconds);
+    let now_secs = chrono::Timelike::num_seconds_from_midnight(&now.time()) as i32;
+
+    // The active command is the latest one whose offset is at or before the
+    // current time of day. Before the day's first command, the previous
+    // day's last command carries over.
+    let (active, carried_over) =
+        match commands.iter().rev().find(|c| c.execution_offset_seconds <= now_secs) {
+            Some(c) => (c.clone(), false),
+            None => match commands.last() {
+                Some(c) => (c.clone(), true),
+                None => return Ok(ActiveCommandResponse::scheduled(site_id, None, priority)),
+            },
+        };
+
+    let ramp_duration_seconds = crate::orm::site::get_site_by_id(conn, site_id)
+        .ok()
+        .flatten()
+        .map(|s| s.ramp_duration_seconds)
+        .unwrap_or(120);
+
+    let start_day = if carried_over {
+        today.pred_opt().unwrap_or(today)
+    } else {
+        today
+    };
+    let starts_at = start_day.and_hms_opt(0, 0, 0).unwrap_or_default()
+        + chrono::Duration::seconds(active.execution_offset_seconds as i64);
+
+    Ok(ActiveCommandResponse::scheduled(
+        site_id,
+        Some(ActiveScheduleCommand {
+            command_id: active.id,
+            command_type: active.command_type,
+            target_soc_percent: active.target_soc_percent,
+            duration_seconds: active.duration_seconds,
+            ramp_duration_seconds,
+            power_kw: active.power_kw,
+            device_id: active.device_id,
+            starts_at,
+        }),
+        priority,
+    ))
+}
+
+/// How many of a site's schedule commands were due to start after `from`, up
+/// to and including `to`, going by each day's effective schedule.
+pub fn count_command_starts(
+    conn: &mut SqliteConnection,
+    site_id: i32,
+    from: chrono::NaiveDateTime,
+    to: chrono::NaiveDateTime,
+) -> Result<usize, diesel::result::Error> {
+    let mut count = 0;
+    for date in from.date().iter_days().take_while(|d| *d <= to.date()) {
+        let effective = match get_effective_schedule(conn, site_id, date) {
+            Ok(schedule) => schedule,
+            Err(diesel::result::Error::NotFound) => continue,
+            Err(e) => return Err(e),
+        };
+        let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+        count += effective
+            .library_item
+            .commands
+            .iter()
+            .map(|c| midnight + chrono::Duration::seconds(c.execution_offset_seconds as i64))
+            .filter(|starts_at| from < *starts_at && *starts_at <= to)
+            .count();
+    }
+    Ok(count)
+}
+
 /// Gets ALL matching schedules for a specific date (not just the winning one)
 /// Returns the winning match and all other matches with lower priority
 pub fn get_all_matching_schedules(
@@ -450,9 +581,13 @@ pub fn get_all_matching_schedules(
         return Err(diesel::result::Error::NotFound);
     }
 
-    // 5. Sort by specificity DESC, then created_at DESC
+    // 5. Sort by priority DESC, then specificity DESC, then created_at DESC
     matching_rules.sort_by(|(rule_a, spec_a), (rule_b, spec_b)| {
-        spec_b.cmp(spec_a).then_with(|| rule_b.created_at.cmp(&rule_a.created_at))
+        rule_b
+            .priority
+            .cmp(&rule_a.priority)
+            .then_with(|| spec_b.cmp(spec_a))
+            .then_with(|| rule_b.created_at.cmp(&rule_a.created_at))
     });
 
     // 6. Convert all matches to CalendarScheduleMatch format
@@ -478,6 +613,7 @@ pub fn get_all_matching_schedules(
                     rule_id: rule.id,
                     rule_type: rule.rule_type.clone(),
                     override_reason: rule.override_reason.clone(),
+                    priority: rule.priority,
                 }
             })
         })
@@ -554,6 +690,7 @@ pub fn get_calendar_schedules(
                         library_item_id: schedule.library_item.id,
                         library_item_name: schedule.library_item.name,
                         specificity: schedule.specificity,
+                        priority: schedule.priority,
                         rule_id: schedule.rule.id,
                     },
                 );
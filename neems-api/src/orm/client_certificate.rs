@@ -0,0 +1,81 @@
+use diesel::prelude::*;
+
+use crate::models::{ClientCertificate, NewClientCertificate};
+
+/// Registers a client certificate for a user
+pub fn insert_client_certificate(
+    conn: &mut SqliteConnection,
+    new_certificate: NewClientCertificate,
+) -> Result<ClientCertificate, diesel::result::Error> {
+    use crate::schema::client_certificates::dsl::*;
+
+    diesel::insert_into(client_certificates)
+        .values(&new_certificate)
+        .execute(conn)?;
+
+    client_certificates
+        .filter(fingerprint.eq(&new_certificate.fingerprint))
+        .select(ClientCertificate::as_select())
+        .first(conn)
+}
+
+/// Gets all client certificates registered for a user
+pub fn get_user_client_certificates(
+    conn: &mut SqliteConnection,
+    user_id_param: i32,
+) -> Result<Vec<ClientCertificate>, diesel::result::Error> {
+    use crate::schema::client_certificates::dsl::*;
+
+    client_certificates
+        .filter(user_id.eq(user_id_param))
+        .order(id.asc())
+        .select(ClientCertificate::as_select())
+        .load(conn)
+}
+
+/// Removes one of a user's client certificates, returning whether it existed
+pub fn delete_client_certificate(
+    conn: &mut SqliteConnection,
+    user_id_param: i32,
+    certificate_id: i32,
+) -> Result<bool, diesel::result::Error> {
+    use crate::schema::client_certificates::dsl::*;
+
+    let deleted = diesel::delete(
+        client_certificates
+            .filter(id.eq(certificate_id))
+            .filter(user_id.eq(user_id_param)),
+    )
+    .execute(conn)?;
+    Ok(deleted > 0)
+}
+
+/// Finds the certificate with a fingerprint
+pub fn get_client_certificate_by_fingerprint(
+    conn: &mut SqliteConnection,
+    fingerprint_param: &str,
+) -> Result<Option<ClientCertificate>, diesel::result::Error> {
+    use crate::schema::client_certificates::dsl::*;
+
+    client_certificates
+        .filter(fingerprint.eq(fingerprint_param))
+        .select(ClientCertificate::as_select())
+        .first(conn)
+        .optional()
+}
+
+/// Gets one of a user's client certificates
+pub fn get_user_client_certificate(
+    conn: &mut SqliteConnection,
+    user_id_param: i32,
+    certificate_id: i32,
+) -> Result<Option<ClientCertificate>, diesel::result::Error> {
+    use crate::schema::client_certificates::dsl::*;
+
+    client_certificates
+        .filter(id.eq(certificate_id))
+        .filter(user_id.eq(user_id_param))
+        .select(ClientCertificate::as_select())
+        .first(conn)
+        .optional()
+}
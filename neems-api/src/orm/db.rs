@@ -1,13 +1,21 @@
 use diesel::connection::SimpleConnection;
 use diesel_migrations::{EmbeddedMigrations, MigrationHarness, embed_migrations};
 use rocket::fairing::AdHoc;
-use rocket_sync_db_pools::{database, diesel};
+use rocket_sync_db_pools::{ConnectionPool, database, diesel};
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
 
 #[database("sqlite_db")]
 pub struct DbConn(diesel::SqliteConnection);
 
+impl DbConn {
+    /// A connection from `pool`, for background tasks that shouldn't hold one
+    /// between runs.
+    pub async fn from_pool(pool: &ConnectionPool<Self, diesel::SqliteConnection>) -> Option<Self> {
+        pool.get().await.map(DbConn)
+    }
+}
+
 /// Enables foreign key support for SQLite connections.
 ///
 /// This executes the `PRAGMA foreign_keys = ON` command on the provided
@@ -55,15 +63,19 @@ pub fn run_pending_migrations(conn: &mut diesel::SqliteConnection) {
 /// Creates a Rocket fairing that runs database migrations on ignition.
 ///
 /// This fairing ensures all pending Diesel migrations are run when the
-/// Rocket application starts up.
+/// Rocket application starts up. When any of them is destructive, they are
+/// first checked against a backed-up copy of the database (see
+/// [`super::migration_preflight`]), and launch fails if that goes wrong.
 pub fn run_migrations_fairing() -> AdHoc {
-    AdHoc::on_ignite("Diesel Migrations", |rocket| async {
+    AdHoc::try_on_ignite("Diesel Migrations", |rocket| async {
         // Get a database connection from Rocket's pool
         let conn = DbConn::get_one(&rocket).await.expect("database connection for migration");
-        conn.run(|c| {
-            run_pending_migrations(c);
-        })
-        .await;
-        rocket
+        match conn.run(super::migration_preflight::run_migrations_safely).await {
+            Ok(_) => Ok(rocket),
+            Err(e) => {
+                error!("Database migration aborted: {}", e);
+                Err(rocket)
+            }
+        }
     })
 }
@@ -0,0 +1,260 @@
+//! Database operations for device groups.
+
+use std::str::FromStr;
+
+use diesel::prelude::*;
+
+use crate::{
+    availability::DeviceSource,
+    models::{
+        AllocationStrategy, DeviceGroup, DeviceGroupDb, DeviceGroupMember, DeviceGroupMemberDb,
+        DeviceGroupRequest, NewDeviceGroup,
+    },
+};
+
+/// Loads a group's members and builds the API model
+fn to_device_group(
+    conn: &mut SqliteConnection,
+    group: DeviceGroupDb,
+) -> Result<DeviceGroup, diesel::result::Error> {
+    use crate::schema::{device_group_members, devices};
+
+    let allocation = AllocationStrategy::from_str(&group.allocation)
+        .map_err(|e| diesel::result::Error::DeserializationError(e.into()))?;
+    let members = device_group_members::table
+        .inner_join(devices::table)
+        .filter(device_group_members::group_id.eq(group.id))
+        .order(device_group_members::device_id.asc())
+        .select((DeviceGroupMemberDb::as_select(), devices::name))
+        .load::<(DeviceGroupMemberDb, String)>(conn)?
+        .into_iter()
+        .map(|(member, device_name)| DeviceGroupMember {
+            device_id: member.device_id,
+            device_name,
+            rated_power_kw: member.rated_power_kw,
+            source_id: member.source_id,
+        })
+        .collect();
+    Ok(DeviceGroup {
+        id: group.id,
+        site_id: group.site_id,
+        name: group.name,
+        allocation,
+        schedule_dispatch: group.schedule_dispatch,
+        members,
+        created_at: group.created_at,
+        updated_at: group.updated_at,
+    })
+}
+
+/// Lists a site's device groups by name
+pub fn list_device_groups(
+    conn: &mut SqliteConnection,
+    site_id_param: i32,
+) -> Result<Vec<DeviceGroup>, diesel::result::Error> {
+    use crate::schema::device_groups::dsl::*;
+
+    device_groups
+        .filter(site_id.eq(site_id_param))
+        .order(name.asc())
+        .select(DeviceGroupDb::as_select())
+        .load(conn)?
+        .into_iter()
+        .map(|group| to_device_group(conn, group))
+        .collect()
+}
+
+/// A site's devices by id, each with the source a device group names as
+/// reporting it, if any
+pub fn list_device_sources(
+    conn: &mut SqliteConnection,
+    site_id_param: i32,
+) -> Result<Vec<DeviceSource>, diesel::result::Error> {
+    use crate::schema::{device_group_members, devices};
+
+    let site_devices: Vec<(i32, String)> = devices::table
+        .filter(devices::site_id.eq(site_id_param))
+        .order(devices::id.asc())
+        .select((devices::id, devices::name))
+        .load(conn)?;
+    let device_ids: Vec<i32> = site_devices.iter().map(|(id, _)| *id).collect();
+    let member_sources: Vec<(i32, i32)> = device_group_members::table
+        .filter(device_group_members::device_id.eq_any(&device_ids))
+        .filter(device_group_members::source_id.is_not_null())
+        .order((device_group_members::device_id.asc(), device_group_members::group_id.asc()))
+        .select((
+            device_group_members::device_id,
+            device_group_members::source_id.assume_not_null(),
+        ))
+        .load(conn)?;
+    Ok(site_devices
+        .into_iter()
+        .map(|(device_id, device_name)| DeviceSource {
+            device_id,
+            device_name,
+            source_id: member_sources.iter().find(|(id, _)| *id == device_id).map(|(_, s)| *s),
+        })
+        .collect())
+}
+
+/// The source a device group names as reporting a device, if any, taking the
+/// lowest group id when more than one does (as [`list_device_sources`] does)
+pub fn get_device_source_id(
+    conn: &mut SqliteConnection,
+    device_id_param: i32,
+) -> Result<Option<i32>, diesel::result::Error> {
+    use crate::schema::device_group_members::dsl::*;
+
+    device_group_members
+        .filter(device_id.eq(device_id_param))
+        .filter(source_id.is_not_null())
+        .order(group_id.asc())
+        .select(source_id.assume_not_null())
+        .first(conn)
+        .optional()
+}
+
+/// Gets a device group by id
+pub fn get_device_group(
+    conn: &mut SqliteConnection,
+    group_id: i32,
+) -> Result<Option<DeviceGroup>, diesel::result::Error> {
+    use crate::schema::device_groups::dsl::*;
+
+    let group = device_groups
+        .find(group_id)
+        .select(DeviceGroupDb::as_select())
+        .first(conn)
+        .optional()?;
+    group.map(|group| to_device_group(conn, group)).transpose()
+}
+
+/// Gets the group a site's scheduled power commands are split across, if it
+/// has one
+pub fn get_schedule_dispatch_group(
+    conn: &mut SqliteConnection,
+    site_id_param: i32,
+) -> Result<Option<DeviceGroup>, diesel::result::Error> {
+    use crate::schema::device_groups::dsl::*;
+
+    let group = device_groups
+        .filter(site_id.eq(site_id_param))
+        .filter(schedule_dispatch.eq(true))
+        .select(DeviceGroupDb::as_select())
+        .first(conn)
+        .optional()?;
+    group.map(|group| to_device_group(conn, group)).transpose()
+}
+
+/// Sets a group's members, replacing any it has
+fn set_members(
+    conn: &mut SqliteConnection,
+    group_id_param: i32,
+    request: &DeviceGroupRequest,
+) -> Result<(), diesel::result::Error> {
+    use crate::schema::device_group_members::dsl::*;
+
+    diesel::delete(device_group_members.filter(group_id.eq(group_id_param))).execute(conn)?;
+    let members: Vec<DeviceGroupMemberDb> = request
+        .members
+        .iter()
+        .map(|member| DeviceGroupMemberDb {
+            group_id: group_id_param,
+            device_id: member.device_id,
+            rated_power_kw: member.rated_power_kw,
+            source_id: member.source_id,
+        })
+        .collect();
+    diesel::insert_into(device_group_members).values(&members).execute(conn)?;
+    Ok(())
+}
+
+/// Takes the scheduler's dispatch away from a site's other groups
+fn clear_schedule_dispatch(
+    conn: &mut SqliteConnection,
+    site_id_param: i32,
+) -> Result<(), diesel::result::Error> {
+    use crate::schema::device_groups::dsl::*;
+
+    diesel::update(device_groups.filter(site_id.eq(site_id_param)))
+        .set(schedule_dispatch.eq(false))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Creates a device group on a site. A group taking the scheduler's dispatch
+/// takes it from any other group on the site.
+pub fn create_device_group(
+    conn: &mut SqliteConnection,
+    site_id_param: i32,
+    request: DeviceGroupRequest,
+) -> Result<DeviceGroup, diesel::result::Error> {
+    use crate::schema::device_groups::dsl::*;
+
+    conn.transaction(|conn| {
+        if request.schedule_dispatch {
+            clear_schedule_dispatch(conn, site_id_param)?;
+        }
+        let group_name = request.name.trim().to_string();
+        diesel::insert_into(device_groups)
+            .values(&NewDeviceGroup {
+                site_id: site_id_param,
+                name: group_name.clone(),
+                allocation: request.allocation.as_str().to_string(),
+                schedule_dispatch: request.schedule_dispatch,
+            })
+            .execute(conn)?;
+        let group = device_groups
+            .filter(site_id.eq(site_id_param))
+            .filter(name.eq(group_name))
+            .select(DeviceGroupDb::as_select())
+            .first(conn)?;
+        set_members(conn, group.id, &request)?;
+        to_device_group(conn, group)
+    })
+}
+
+/// Replaces a device group's settings and members, returning None if there
+/// is no such group
+pub fn update_device_group(
+    conn: &mut SqliteConnection,
+    group_id: i32,
+    request: DeviceGroupRequest,
+) -> Result<Option<DeviceGroup>, diesel::result::Error> {
+    use crate::schema::device_groups::dsl::*;
+
+    conn.transaction(|conn| {
+        let Some(existing) = device_groups
+            .find(group_id)
+            .select(DeviceGroupDb::as_select())
+            .first(conn)
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        if request.schedule_dispatch {
+            clear_schedule_dispatch(conn, existing.site_id)?;
+        }
+        diesel::update(device_groups.find(group_id))
+            .set((
+                name.eq(request.name.trim()),
+                allocation.eq(request.allocation.as_str()),
+                schedule_dispatch.eq(request.schedule_dispatch),
+                updated_at.eq(chrono::Utc::now().naive_utc()),
+            ))
+            .execute(conn)?;
+        set_members(conn, group_id, &request)?;
+        get_device_group(conn, group_id)
+    })
+}
+
+/// Deletes a device group, returning whether it existed
+pub fn delete_device_group(
+    conn: &mut SqliteConnection,
+    group_id: i32,
+) -> Result<bool, diesel::result::Error> {
+    use crate::schema::device_groups::dsl::*;
+
+    let deleted = diesel::delete(device_groups.find(group_id)).execute(conn)?;
+    Ok(deleted > 0)
+}
@@ -1,5 +1,7 @@
+use std::collections::HashMap;
+
 use chrono::NaiveDateTime;
-use diesel::prelude::*;
+use diesel::{prelude::*, sqlite::Sqlite};
 
 use crate::models::{EntityActivity, NewEntityActivity};
 
@@ -61,19 +63,48 @@ pub fn get_updated_at(
         .first::<NaiveDateTime>(conn)
 }
 
-/// Get full activity history for an entity
-pub fn get_activity_history(
-    conn: &mut SqliteConnection,
+/// Query for the full activity history of an entity, oldest first
+pub fn activity_history_query(
     table_name_val: &str,
     entity_id_val: i32,
-) -> Result<Vec<EntityActivity>, diesel::result::Error> {
+) -> crate::schema::entity_activity::BoxedQuery<'_, Sqlite> {
     use crate::schema::entity_activity::dsl::*;
 
     entity_activity
         .filter(table_name.eq(table_name_val))
         .filter(entity_id.eq(entity_id_val))
         .order(timestamp.asc())
-        .load::<EntityActivity>(conn)
+        .into_boxed()
+}
+
+/// Get full activity history for an entity
+pub fn get_activity_history(
+    conn: &mut SqliteConnection,
+    table_name_val: &str,
+    entity_id_val: i32,
+) -> Result<Vec<EntityActivity>, diesel::result::Error> {
+    activity_history_query(table_name_val, entity_id_val).load::<EntityActivity>(conn)
+}
+
+/// Get the emails of the users acting in an entity's activity history, by
+/// user id
+pub fn get_activity_user_emails(
+    conn: &mut SqliteConnection,
+    table_name_val: &str,
+    entity_id_val: i32,
+) -> Result<HashMap<i32, String>, diesel::result::Error> {
+    use crate::schema::{entity_activity, users};
+
+    let acting_users = entity_activity::table
+        .filter(entity_activity::table_name.eq(table_name_val))
+        .filter(entity_activity::entity_id.eq(entity_id_val))
+        .filter(entity_activity::user_id.is_not_null())
+        .select(entity_activity::user_id.assume_not_null());
+    let emails = users::table
+        .filter(users::id.eq_any(acting_users))
+        .select((users::id, users::email))
+        .load::<(i32, String)>(conn)?;
+    Ok(emails.into_iter().collect())
 }
 
 /// Get all activities of a specific type
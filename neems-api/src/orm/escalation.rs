@@ -0,0 +1,212 @@
+//! Database operations for alarm escalation policies and their history.
+
+use diesel::prelude::*;
+use neems_data::notifications::NotificationSeverity;
+
+use crate::models::{
+    AlarmEscalation, AlarmEscalationDb, EscalationPolicy, EscalationPolicyDb,
+    EscalationPolicyRequest, EscalationTier, EscalationTierDb, NewAlarmEscalation,
+    NewEscalationPolicy,
+};
+
+fn deserialization_error(
+    e: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+) -> diesel::result::Error {
+    diesel::result::Error::DeserializationError(e.into())
+}
+
+/// Loads a policy's tiers and builds the API model
+fn to_escalation_policy(
+    conn: &mut SqliteConnection,
+    policy: EscalationPolicyDb,
+) -> Result<EscalationPolicy, diesel::result::Error> {
+    use crate::schema::escalation_tiers::dsl::*;
+
+    let min_severity: NotificationSeverity =
+        serde_json::from_value(serde_json::Value::String(policy.min_severity))
+            .map_err(deserialization_error)?;
+    let tiers = escalation_tiers
+        .filter(policy_id.eq(policy.id))
+        .order(tier.asc())
+        .select(EscalationTierDb::as_select())
+        .load(conn)?
+        .into_iter()
+        .map(|row| {
+            Ok(EscalationTier {
+                tier: row.tier,
+                delay_minutes: row.delay_minutes,
+                channel_id: row.channel_id,
+                contacts: serde_json::from_str(&row.contacts).map_err(deserialization_error)?,
+                rotation_id: row.rotation_id,
+            })
+        })
+        .collect::<Result<_, diesel::result::Error>>()?;
+    Ok(EscalationPolicy {
+        id: policy.id,
+        company_id: policy.company_id,
+        name: policy.name,
+        min_severity,
+        enabled: policy.enabled,
+        tiers,
+        created_at: policy.created_at,
+        updated_at: policy.updated_at,
+    })
+}
+
+/// Lists a company's escalation policies by name
+pub fn list_escalation_policies(
+    conn: &mut SqliteConnection,
+    company_id_param: i32,
+) -> Result<Vec<EscalationPolicy>, diesel::result::Error> {
+    use crate::schema::escalation_policies::dsl::*;
+
+    escalation_policies
+        .filter(company_id.eq(company_id_param))
+        .order(name.asc())
+        .select(EscalationPolicyDb::as_select())
+        .load(conn)?
+        .into_iter()
+        .map(|policy| to_escalation_policy(conn, policy))
+        .collect()
+}
+
+/// Gets an escalation policy by id
+pub fn get_escalation_policy(
+    conn: &mut SqliteConnection,
+    policy_id: i32,
+) -> Result<Option<EscalationPolicy>, diesel::result::Error> {
+    use crate::schema::escalation_policies::dsl::*;
+
+    let policy = escalation_policies
+        .find(policy_id)
+        .select(EscalationPolicyDb::as_select())
+        .first(conn)
+        .optional()?;
+    policy.map(|policy| to_escalation_policy(conn, policy)).transpose()
+}
+
+/// Sets a policy's tiers, numbered in order, replacing any it has
+fn set_tiers(
+    conn: &mut SqliteConnection,
+    policy_id_param: i32,
+    request: &EscalationPolicyRequest,
+) -> Result<(), diesel::result::Error> {
+    use crate::schema::escalation_tiers::dsl::*;
+
+    diesel::delete(escalation_tiers.filter(policy_id.eq(policy_id_param))).execute(conn)?;
+    let tiers = request
+        .tiers
+        .iter()
+        .zip(1..)
+        .map(|(request, number)| {
+            let trimmed: Vec<&str> = request.contacts.iter().map(|c| c.trim()).collect();
+            Ok(EscalationTierDb {
+                policy_id: policy_id_param,
+                tier: number,
+                delay_minutes: request.delay_minutes,
+                channel_id: request.channel_id,
+                contacts: serde_json::to_string(&trimmed)
+                    .map_err(|e| diesel::result::Error::SerializationError(e.into()))?,
+                rotation_id: request.rotation_id,
+            })
+        })
+        .collect::<Result<Vec<_>, diesel::result::Error>>()?;
+    diesel::insert_into(escalation_tiers).values(&tiers).execute(conn)?;
+    Ok(())
+}
+
+/// Creates an escalation policy for a company
+pub fn create_escalation_policy(
+    conn: &mut SqliteConnection,
+    company_id_param: i32,
+    request: EscalationPolicyRequest,
+) -> Result<EscalationPolicy, diesel::result::Error> {
+    use crate::schema::escalation_policies::dsl::*;
+
+    conn.transaction(|conn| {
+        let policy_name = request.name.trim().to_string();
+        diesel::insert_into(escalation_policies)
+            .values(&NewEscalationPolicy {
+                company_id: company_id_param,
+                name: policy_name.clone(),
+                min_severity: request.min_severity.as_str().to_string(),
+                enabled: request.enabled,
+            })
+            .execute(conn)?;
+        let policy = escalation_policies
+            .filter(company_id.eq(company_id_param))
+            .filter(name.eq(policy_name))
+            .select(EscalationPolicyDb::as_select())
+            .first(conn)?;
+        set_tiers(conn, policy.id, &request)?;
+        to_escalation_policy(conn, policy)
+    })
+}
+
+/// Replaces an escalation policy's settings and tiers, returning None if
+/// there is no such policy
+pub fn update_escalation_policy(
+    conn: &mut SqliteConnection,
+    policy_id: i32,
+    request: EscalationPolicyRequest,
+) -> Result<Option<EscalationPolicy>, diesel::result::Error> {
+    use crate::schema::escalation_policies::dsl::*;
+
+    conn.transaction(|conn| {
+        let updated = diesel::update(escalation_policies.find(policy_id))
+            .set((
+                name.eq(request.name.trim()),
+                min_severity.eq(request.min_severity.as_str()),
+                enabled.eq(request.enabled),
+                updated_at.eq(chrono::Utc::now().naive_utc()),
+            ))
+            .execute(conn)?;
+        if updated == 0 {
+            return Ok(None);
+        }
+        set_tiers(conn, policy_id, &request)?;
+        get_escalation_policy(conn, policy_id)
+    })
+}
+
+/// Deletes an escalation policy, returning whether it existed. Its
+/// escalations stay in the alarms' history.
+pub fn delete_escalation_policy(
+    conn: &mut SqliteConnection,
+    policy_id: i32,
+) -> Result<bool, diesel::result::Error> {
+    use crate::schema::escalation_policies::dsl::*;
+
+    let deleted = diesel::delete(escalation_policies.find(policy_id)).execute(conn)?;
+    Ok(deleted > 0)
+}
+
+/// Records an escalation sent, or attempted
+pub fn record_alarm_escalation(
+    conn: &mut SqliteConnection,
+    escalation: &NewAlarmEscalation,
+) -> Result<(), diesel::result::Error> {
+    use crate::schema::alarm_escalations::dsl::*;
+
+    diesel::insert_or_ignore_into(alarm_escalations)
+        .values(escalation)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// The escalations of the given alarms, newest first
+pub fn list_alarm_escalations(
+    conn: &mut SqliteConnection,
+    nums: &[i32],
+) -> Result<Vec<AlarmEscalation>, diesel::result::Error> {
+    use crate::schema::alarm_escalations::dsl::*;
+
+    Ok(alarm_escalations
+        .filter(alarm_num.eq_any(nums))
+        .order((escalated_at.desc(), id.desc()))
+        .select(AlarmEscalationDb::as_select())
+        .load(conn)?
+        .into_iter()
+        .map(AlarmEscalation::from)
+        .collect())
+}
@@ -0,0 +1,162 @@
+//! Database operations for site KPIs and their daily results.
+
+use std::collections::HashSet;
+
+use chrono::NaiveDate;
+use diesel::prelude::*;
+
+use crate::models::{KpiDefinition, KpiDefinitionChanges, KpiDefinitionRequest, KpiResult};
+
+/// Lists a site's KPIs by name
+pub fn list_kpi_definitions(
+    conn: &mut SqliteConnection,
+    site_id_param: i32,
+) -> Result<Vec<KpiDefinition>, diesel::result::Error> {
+    use crate::schema::kpi_definitions::dsl::*;
+
+    kpi_definitions
+        .filter(site_id.eq(site_id_param))
+        .order(name.asc())
+        .select(KpiDefinition::as_select())
+        .load(conn)
+}
+
+/// Gets a KPI by id
+pub fn get_kpi_definition(
+    conn: &mut SqliteConnection,
+    kpi_id: i32,
+) -> Result<Option<KpiDefinition>, diesel::result::Error> {
+    use crate::schema::kpi_definitions::dsl::*;
+
+    kpi_definitions
+        .find(kpi_id)
+        .select(KpiDefinition::as_select())
+        .first(conn)
+        .optional()
+}
+
+/// Creates a KPI on a site
+pub fn create_kpi_definition(
+    conn: &mut SqliteConnection,
+    site_id_param: i32,
+    request: KpiDefinitionRequest,
+) -> Result<KpiDefinition, diesel::result::Error> {
+    use crate::schema::kpi_definitions::dsl::*;
+
+    let changes = KpiDefinitionChanges::from(request);
+    let kpi_name = changes.name.clone();
+    conn.transaction(|conn| {
+        diesel::insert_into(kpi_definitions)
+            .values((site_id.eq(site_id_param), &changes))
+            .execute(conn)?;
+        kpi_definitions
+            .filter(site_id.eq(site_id_param))
+            .filter(name.eq(kpi_name))
+            .select(KpiDefinition::as_select())
+            .first(conn)
+    })
+}
+
+/// Replaces a KPI's definition, returning None if there is no such KPI.
+/// Results already recorded are kept.
+pub fn update_kpi_definition(
+    conn: &mut SqliteConnection,
+    kpi_id: i32,
+    request: KpiDefinitionRequest,
+) -> Result<Option<KpiDefinition>, diesel::result::Error> {
+    use crate::schema::kpi_definitions::dsl::*;
+
+    let changes = KpiDefinitionChanges::from(request);
+    let updated = diesel::update(kpi_definitions.find(kpi_id))
+        .set((&changes, updated_at.eq(chrono::Utc::now().naive_utc())))
+        .execute(conn)?;
+    if updated == 0 {
+        return Ok(None);
+    }
+    get_kpi_definition(conn, kpi_id)
+}
+
+/// Deletes a KPI and its results, returning whether it existed
+pub fn delete_kpi_definition(
+    conn: &mut SqliteConnection,
+    kpi_id: i32,
+) -> Result<bool, diesel::result::Error> {
+    use crate::schema::{kpi_definitions, kpi_results};
+
+    conn.transaction(|conn| {
+        diesel::delete(kpi_results::table.filter(kpi_results::kpi_id.eq(kpi_id))).execute(conn)?;
+        let deleted = diesel::delete(kpi_definitions::table.find(kpi_id)).execute(conn)?;
+        Ok(deleted > 0)
+    })
+}
+
+/// Ids of the sites with at least one KPI
+pub fn list_sites_with_kpis(
+    conn: &mut SqliteConnection,
+) -> Result<Vec<i32>, diesel::result::Error> {
+    use crate::schema::kpi_definitions::dsl::*;
+
+    kpi_definitions.select(site_id).distinct().order(site_id.asc()).load(conn)
+}
+
+/// Records results, replacing any for the same KPIs and days
+pub fn save_kpi_results(
+    conn: &mut SqliteConnection,
+    results: &[KpiResult],
+) -> Result<(), diesel::result::Error> {
+    use crate::schema::kpi_results::dsl::*;
+
+    conn.transaction(|conn| {
+        for result in results {
+            diesel::replace_into(kpi_results).values(result).execute(conn)?;
+        }
+        Ok(())
+    })
+}
+
+/// A site's KPI results from `from` to `to` inclusive, by day and KPI,
+/// optionally for one KPI
+pub fn list_kpi_results(
+    conn: &mut SqliteConnection,
+    site_id_param: i32,
+    from: NaiveDate,
+    to: NaiveDate,
+    kpi_id_param: Option<i32>,
+) -> Result<Vec<KpiResult>, diesel::result::Error> {
+    use crate::schema::{kpi_definitions, kpi_results};
+
+    let mut query = kpi_results::table
+        .inner_join(kpi_definitions::table)
+        .filter(kpi_definitions::site_id.eq(site_id_param))
+        .filter(kpi_results::day.between(from, to))
+        .select(KpiResult::as_select())
+        .into_boxed();
+    if let Some(kpi_id_param) = kpi_id_param {
+        query = query.filter(kpi_results::kpi_id.eq(kpi_id_param));
+    }
+    query.order((kpi_results::day.asc(), kpi_results::kpi_id.asc())).load(conn)
+}
+
+/// The days from `from` to `to` inclusive on which any of a site's KPIs has
+/// no result
+pub fn list_unevaluated_days(
+    conn: &mut SqliteConnection,
+    site_id_param: i32,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<NaiveDate>, diesel::result::Error> {
+    let kpi_ids: Vec<i32> = list_kpi_definitions(conn, site_id_param)?
+        .into_iter()
+        .map(|kpi| kpi.id)
+        .collect();
+    let evaluated: HashSet<(i32, NaiveDate)> =
+        list_kpi_results(conn, site_id_param, from, to, None)?
+            .into_iter()
+            .map(|result| (result.kpi_id, result.day))
+            .collect();
+    Ok(from
+        .iter_days()
+        .take_while(|day| *day <= to)
+        .filter(|day| kpi_ids.iter().any(|id| !evaluated.contains(&(*id, *day))))
+        .collect())
+}
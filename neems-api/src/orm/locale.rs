@@ -0,0 +1,118 @@
+//! Database operations for company and user locale preferences.
+
+use chrono::Utc;
+use diesel::prelude::*;
+use neems_data::i18n::Locale;
+
+fn parse(locale: Option<String>) -> Option<Locale> {
+    locale.and_then(|locale| locale.parse().ok())
+}
+
+/// A company's locale, if it has set one
+pub fn get_company_locale(
+    conn: &mut SqliteConnection,
+    company_id_param: i32,
+) -> Result<Option<Locale>, diesel::result::Error> {
+    use crate::schema::company_locales::dsl::*;
+
+    let stored = company_locales
+        .find(company_id_param)
+        .select(locale)
+        .first::<String>(conn)
+        .optional()?;
+    Ok(parse(stored))
+}
+
+/// Sets a company's locale, or clears it with `None`
+pub fn set_company_locale(
+    conn: &mut SqliteConnection,
+    company_id_param: i32,
+    locale_param: Option<Locale>,
+) -> Result<(), diesel::result::Error> {
+    use crate::schema::company_locales::dsl::*;
+
+    match locale_param {
+        Some(new_locale) => {
+            let now = Utc::now().naive_utc();
+            diesel::insert_into(company_locales)
+                .values((
+                    company_id.eq(company_id_param),
+                    locale.eq(new_locale.as_str()),
+                    updated_at.eq(now),
+                ))
+                .on_conflict(company_id)
+                .do_update()
+                .set((locale.eq(new_locale.as_str()), updated_at.eq(now)))
+                .execute(conn)?;
+        }
+        None => {
+            diesel::delete(company_locales.find(company_id_param)).execute(conn)?;
+        }
+    }
+    Ok(())
+}
+
+/// A user's own locale, if they have set one
+pub fn get_user_locale(
+    conn: &mut SqliteConnection,
+    user_id_param: i32,
+) -> Result<Option<Locale>, diesel::result::Error> {
+    use crate::schema::user_locales::dsl::*;
+
+    let stored = user_locales
+        .find(user_id_param)
+        .select(locale)
+        .first::<String>(conn)
+        .optional()?;
+    Ok(parse(stored))
+}
+
+/// Sets a user's locale, or clears it with `None`
+pub fn set_user_locale(
+    conn: &mut SqliteConnection,
+    user_id_param: i32,
+    locale_param: Option<Locale>,
+) -> Result<(), diesel::result::Error> {
+    use crate::schema::user_locales::dsl::*;
+
+    match locale_param {
+        Some(new_locale) => {
+            let now = Utc::now().naive_utc();
+            diesel::insert_into(user_locales)
+                .values((
+                    user_id.eq(user_id_param),
+                    locale.eq(new_locale.as_str()),
+                    updated_at.eq(now),
+                ))
+                .on_conflict(user_id)
+                .do_update()
+                .set((locale.eq(new_locale.as_str()), updated_at.eq(now)))
+                .execute(conn)?;
+        }
+        None => {
+            diesel::delete(user_locales.find(user_id_param)).execute(conn)?;
+        }
+    }
+    Ok(())
+}
+
+/// The user's locale, or else their company's, if either is set
+pub fn get_preferred_locale(
+    conn: &mut SqliteConnection,
+    user_id_param: i32,
+) -> Result<Option<Locale>, diesel::result::Error> {
+    use crate::schema::users;
+
+    if let Some(locale) = get_user_locale(conn, user_id_param)? {
+        return Ok(Some(locale));
+    }
+    let company_id = users::table
+        .find(user_id_param)
+        .select(users::company_id)
+        .first::<i32>(conn)
+        .optional()?;
+    match company_id {
+        Some(company_id) => get_company_locale(conn, company_id),
+        None => Ok(None),
+    }
+}
@@ -106,7 +106,7 @@ pub async fn find_user_by_email<D: DbRunner>(db: &D, email: &str) -> Result<Opti
 /// # Returns
 /// * `true` - Password matches the stored hash
 /// * `false` - Password doesn't match or hash format is invalid
-fn verify_password(password: &str, stored_hash: &str) -> bool {
+pub fn verify_password(password: &str, stored_hash: &str) -> bool {
     let parsed_hash = PasswordHash::new(stored_hash).expect("Invalid hash format");
     Argon2::default().verify_password(password.as_bytes(), &parsed_hash).is_ok()
 }
@@ -204,7 +204,8 @@ pub async fn process_login<D: DbRunner>(
         None => return Err(Status::Unauthorized),
     };
 
-    if !verify_password(&login.password, &user.password_hash) {
+    // Service accounts have no password; they use API keys
+    if user.service_account || !verify_password(&login.password, &user.password_hash) {
         return Err(Status::Unauthorized);
     }
 
@@ -262,6 +263,8 @@ mod tests {
             password_hash: hash,
             company_id: 1,
             totp_secret: Some("dummysecret".to_string()),
+            must_change_password: false,
+            service_account: false,
         };
 
         // Correct password should verify
@@ -0,0 +1,195 @@
+//! Database operations for the login audit log.
+//!
+//! Every login attempt is recorded, and successful ones are checked for
+//! anomalies against the user's earlier attempts:
+//!
+//! - `new_country`: the user has logged in before from known countries, but
+//!   never from this one
+//! - `failures_then_success`: at least [`FAILURES_BEFORE_SUCCESS_THRESHOLD`]
+//!   failed attempts since the user's last successful login
+
+use diesel::prelude::*;
+
+use crate::models::{LoginEvent, NewLoginEvent};
+
+pub const NEW_COUNTRY: &str = "new_country";
+pub const FAILURES_THEN_SUCCESS: &str = "failures_then_success";
+
+/// Failed attempts since the last success that make a success anomalous
+pub const FAILURES_BEFORE_SUCCESS_THRESHOLD: i64 = 5;
+
+/// Anomaly flags for a successful login by `user_id_param` from
+/// `country_param`, judged against the attempts recorded before it
+fn detect_anomalies(
+    conn: &mut SqliteConnection,
+    user_id_param: i32,
+    country_param: Option<&str>,
+) -> Result<Vec<&'static str>, diesel::result::Error> {
+    use crate::schema::login_events::dsl::*;
+
+    let mut anomalies = Vec::new();
+
+    if let Some(country_param) = country_param {
+        let known_countries: Vec<String> = login_events
+            .filter(user_id.eq(user_id_param))
+            .filter(success.eq(true))
+            .filter(country.is_not_null())
+            .select(country.assume_not_null())
+            .distinct()
+            .load(conn)?;
+        if !known_countries.is_empty() && !known_countries.iter().any(|c| c == country_param) {
+            anomalies.push(NEW_COUNTRY);
+        }
+    }
+
+    let last_success: Option<i32> = login_events
+        .filter(user_id.eq(user_id_param))
+        .filter(success.eq(true))
+        .select(diesel::dsl::max(id))
+        .first(conn)?;
+    let failures: i64 = login_events
+        .filter(user_id.eq(user_id_param))
+        .filter(success.eq(false))
+        .filter(id.gt(last_success.unwrap_or(0)))
+        .count()
+        .get_result(conn)?;
+    if failures >= FAILURES_BEFORE_SUCCESS_THRESHOLD {
+        anomalies.push(FAILURES_THEN_SUCCESS);
+    }
+
+    Ok(anomalies)
+}
+
+/// Records a login attempt, flagging anomalies on successful ones
+pub fn record_login_event(
+    conn: &mut SqliteConnection,
+    event: NewLoginEvent,
+) -> Result<LoginEvent, diesel::result::Error> {
+    use crate::schema::login_events::dsl::*;
+
+    conn.transaction(|conn| {
+        let flags = match (event.success, event.user_id) {
+            (true, Some(user)) => detect_anomalies(conn, user, event.country.as_deref())?,
+            _ => Vec::new(),
+        };
+        let flags = (!flags.is_empty()).then(|| flags.join(","));
+
+        diesel::insert_into(login_events)
+            .values((&event, anomaly.eq(flags)))
+            .execute(conn)?;
+
+        login_events.order(id.desc()).select(LoginEvent::as_select()).first(conn)
+    })
+}
+
+/// Gets a user's login attempts, newest first
+pub fn get_user_login_events(
+    conn: &mut SqliteConnection,
+    user_id_param: i32,
+    limit: i64,
+) -> Result<Vec<LoginEvent>, diesel::result::Error> {
+    use crate::schema::login_events::dsl::*;
+
+    login_events
+        .filter(user_id.eq(user_id_param))
+        .order(id.desc())
+        .limit(limit)
+        .select(LoginEvent::as_select())
+        .load(conn)
+}
+
+/// Gets login attempts flagged as anomalous, newest first, optionally only
+/// those of one company's users
+pub fn get_login_anomalies(
+    conn: &mut SqliteConnection,
+    company_id_param: Option<i32>,
+    limit: i64,
+) -> Result<Vec<LoginEvent>, diesel::result::Error> {
+    use crate::schema::{login_events, users};
+
+    let query = login_events::table
+        .filter(login_events::anomaly.is_not_null())
+        .order(login_events::id.desc())
+        .limit(limit)
+        .select(LoginEvent::as_select())
+        .into_boxed();
+    match company_id_param {
+        Some(company) => query
+            .filter(login_events::user_id.eq_any(
+                users::table.filter(users::company_id.eq(company)).select(users::id.nullable()),
+            ))
+            .load(conn),
+        None => query.load(conn),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::UserInput,
+        orm::{login::hash_password, testing::setup_test_db, user::insert_user},
+    };
+
+    fn attempt(user: i32, ok: bool, from: Option<&str>) -> NewLoginEvent {
+        NewLoginEvent {
+            user_id: Some(user),
+            email: "login-audit@example.com".to_string(),
+            success: ok,
+            ip_address: Some("10.0.0.1".to_string()),
+            user_agent: None,
+            country: from.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_login_anomalies() {
+        let mut conn = setup_test_db();
+        let company = crate::company::insert_company(&mut conn, "Audit Co".to_string(), None)
+            .expect("insert company");
+        let user = insert_user(
+            &mut conn,
+            UserInput {
+                email: "login-audit@example.com".to_string(),
+                password_hash: hash_password("password"),
+                company_id: company.id,
+                totp_secret: None,
+            },
+            None,
+        )
+        .expect("insert user");
+
+        // The first login, and ones without a country, set no baseline
+        let event = record_login_event(&mut conn, attempt(user.id, true, None)).unwrap();
+        assert_eq!(event.anomaly, None);
+        let event = record_login_event(&mut conn, attempt(user.id, true, Some("US"))).unwrap();
+        assert_eq!(event.anomaly, None);
+        let event = record_login_event(&mut conn, attempt(user.id, true, Some("US"))).unwrap();
+        assert_eq!(event.anomaly, None);
+
+        let event = record_login_event(&mut conn, attempt(user.id, true, Some("RO"))).unwrap();
+        assert_eq!(event.anomaly.as_deref(), Some(NEW_COUNTRY));
+
+        for _ in 0..FAILURES_BEFORE_SUCCESS_THRESHOLD {
+            let event = record_login_event(&mut conn, attempt(user.id, false, Some("KP"))).unwrap();
+            assert_eq!(event.anomaly, None, "failures themselves aren't flagged");
+        }
+        let event = record_login_event(&mut conn, attempt(user.id, true, Some("KP"))).unwrap();
+        assert_eq!(event.anomaly.as_deref(), Some("new_country,failures_then_success"));
+
+        // The failure count restarts after a success
+        let event = record_login_event(&mut conn, attempt(user.id, false, Some("US"))).unwrap();
+        assert_eq!(event.anomaly, None);
+        let event = record_login_event(&mut conn, attempt(user.id, true, Some("US"))).unwrap();
+        assert_eq!(event.anomaly, None);
+
+        let history = get_user_login_events(&mut conn, user.id, 3).unwrap();
+        assert_eq!(history.len(), 3);
+        assert!(history[0].id > history[1].id, "newest first");
+
+        let anomalies = get_login_anomalies(&mut conn, Some(company.id), 50).unwrap();
+        assert_eq!(anomalies.len(), 2);
+        assert!(get_login_anomalies(&mut conn, Some(company.id + 1), 50).unwrap().is_empty());
+        assert_eq!(get_login_anomalies(&mut conn, None, 50).unwrap().len(), 2);
+    }
+}
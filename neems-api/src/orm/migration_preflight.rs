@@ -0,0 +1,353 @@
+//! Safety checks before destructive migrations.
+//!
+//! Most migrations only add tables and columns, but SQLite can't alter a
+//! column in place, so changing one means copying its table, dropping the
+//! original and renaming the copy. A mistake there loses data for good. So
+//! before running a pending migration that drops a table or column or
+//! deletes rows (see [`is_destructive`]), [`run_migrations_safely`] runs a
+//! pre-flight:
+//!
+//! 1. The live database is backed up with `VACUUM INTO`, next to it as
+//!    `<file>.<timestamp>.pre-migration.bak`.
+//! 2. The pending migrations are applied to a scratch copy of the backup.
+//! 3. The copy must pass `PRAGMA integrity_check` and `PRAGMA
+//!    foreign_key_check`, every table must still be readable, and the core
+//!    tables must load into their models.
+//!
+//! Only then is the live database migrated. If the pre-flight fails the live
+//! database is left alone and the error says why; the backup is kept either
+//! way. Tables that disappear or lose rows in the copy are reported as
+//! warnings, since destructive migrations may mean to do that.
+//!
+//! Migrations with nothing destructive, and those of new or in-memory
+//! databases, are simply run. `neems-admin system migrate --check` runs the
+//! pre-flight alone.
+
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use diesel::{
+    OptionalExtension, QueryableByName, RunQueryDsl,
+    connection::SimpleConnection,
+    sql_types::{BigInt, Text},
+    sqlite::SqliteConnection,
+};
+use diesel_migrations::MigrationHarness;
+
+use super::db::MIGRATIONS;
+use crate::{
+    models::{Company, Role, Site, User},
+    schema::{companies, roles, sites, users},
+};
+
+mod embedded {
+    include!(concat!(env!("OUT_DIR"), "/migration_sql.rs"));
+}
+
+/// Statements that can lose data, after comments are stripped and whitespace
+/// collapsed.
+const DESTRUCTIVE_STATEMENTS: &[&str] = &["DROP TABLE", "DROP COLUMN", "DELETE FROM"];
+
+/// A migration not yet applied to the database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingMigration {
+    /// The migration's directory name, e.g. `2026-10-18-030000_add_locales`
+    pub name: String,
+    pub destructive: bool,
+}
+
+/// What a pre-flight found.
+#[derive(Debug, Default)]
+pub struct PreflightReport {
+    /// The backup of the database as it was before migrating
+    pub backup: PathBuf,
+    /// The migrations applied to the copy
+    pub migrations: Vec<String>,
+    /// Tables the migrations dropped or took rows from
+    pub warnings: Vec<String>,
+}
+
+/// Whether `sql` drops a table or column or deletes rows.
+pub fn is_destructive(sql: &str) -> bool {
+    let statements = sql
+        .lines()
+        .map(|line| line.split("--").next().unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join(" ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_uppercase();
+    DESTRUCTIVE_STATEMENTS.iter().any(|statement| statements.contains(statement))
+}
+
+/// The migrations not yet applied, oldest first.
+pub fn pending_migrations(conn: &mut SqliteConnection) -> Result<Vec<PendingMigration>, String> {
+    let mut pending: Vec<PendingMigration> = conn
+        .pending_migrations(MIGRATIONS)
+        .map_err(|e| format!("Failed to list pending migrations: {}", e))?
+        .iter()
+        .map(|migration| {
+            let name = migration.name().to_string();
+            let destructive = embedded::MIGRATION_SQL
+                .iter()
+                .find(|(embedded, _)| *embedded == name)
+                .is_none_or(|(_, sql)| is_destructive(sql));
+            PendingMigration { name, destructive }
+        })
+        .collect();
+    pending.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(pending)
+}
+
+#[derive(QueryableByName)]
+struct DatabaseFile {
+    #[diesel(sql_type = Text)]
+    name: String,
+    #[diesel(sql_type = Text)]
+    file: String,
+}
+
+/// The file `conn`'s main database is in; `None` when it's in memory.
+pub fn database_file(conn: &mut SqliteConnection) -> Result<Option<PathBuf>, String> {
+    let databases = diesel::sql_query("PRAGMA database_list")
+        .load::<DatabaseFile>(conn)
+        .map_err(|e| format!("Failed to find the database file: {}", e))?;
+    Ok(databases
+        .into_iter()
+        .find(|database| database.name == "main" && !database.file.is_empty())
+        .map(|database| PathBuf::from(database.file)))
+}
+
+#[derive(QueryableByName)]
+struct TableName {
+    #[diesel(sql_type = Text)]
+    name: String,
+}
+
+#[derive(QueryableByName)]
+struct RowCount {
+    #[diesel(sql_type = BigInt)]
+    count: i64,
+}
+
+#[derive(QueryableByName)]
+struct IntegrityCheck {
+    #[diesel(sql_type = Text)]
+    integrity_check: String,
+}
+
+/// Every table's row count, by name; failing if any can't be read.
+fn row_counts(conn: &mut SqliteConnection) -> Result<Vec<(String, i64)>, String> {
+    let tables = diesel::sql_query(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' \
+         ORDER BY name",
+    )
+    .load::<TableName>(conn)
+    .map_err(|e| format!("Failed to list tables: {}", e))?;
+    tables
+        .into_iter()
+        .map(|table| {
+            let query = format!("SELECT COUNT(*) AS count FROM \"{}\"", table.name);
+            let count = diesel::sql_query(query)
+                .get_result::<RowCount>(conn)
+                .map_err(|e| format!("Failed to read table {}: {}", table.name, e))?;
+            Ok((table.name, count.count))
+        })
+        .collect()
+}
+
+/// Integrity checks and smoke queries on a migrated database.
+fn check_database(conn: &mut SqliteConnection) -> Result<(), String> {
+    let integrity = diesel::sql_query("PRAGMA integrity_check")
+        .load::<IntegrityCheck>(conn)
+        .map_err(|e| format!("Failed to check integrity: {}", e))?;
+    let problems: Vec<_> = integrity
+        .into_iter()
+        .map(|row| row.integrity_check)
+        .filter(|message| message != "ok")
+        .collect();
+    if !problems.is_empty() {
+        return Err(format!("Integrity check failed: {}", problems.join("; ")));
+    }
+
+    let violations = diesel::sql_query(
+        "SELECT \"table\" AS name FROM pragma_foreign_key_check GROUP BY \"table\"",
+    )
+    .load::<TableName>(conn)
+    .map_err(|e| format!("Failed to check foreign keys: {}", e))?;
+    if !violations.is_empty() {
+        let tables: Vec<_> = violations.into_iter().map(|table| table.name).collect();
+        return Err(format!("Foreign key violations in {}", tables.join(", ")));
+    }
+
+    // The models must still match the tables
+    let smoke = |e: diesel::result::Error| format!("Smoke query failed: {}", e);
+    users::table.first::<User>(conn).optional().map_err(smoke)?;
+    companies::table.first::<Company>(conn).optional().map_err(smoke)?;
+    sites::table.first::<Site>(conn).optional().map_err(smoke)?;
+    roles::table.first::<Role>(conn).optional().map_err(smoke)?;
+    Ok(())
+}
+
+/// Backs up `database` and checks the pending migrations on a copy of it,
+/// leaving `conn`'s database unmigrated.
+pub fn preflight(conn: &mut SqliteConnection, database: &Path) -> Result<PreflightReport, String> {
+    let migrations: Vec<String> =
+        pending_migrations(conn)?.into_iter().map(|migration| migration.name).collect();
+    let before = row_counts(conn)?;
+
+    let stamp = Utc::now().format("%Y%m%dT%H%M%S%6f");
+    let backup = PathBuf::from(format!("{}.{}.pre-migration.bak", database.display(), stamp));
+    conn.batch_execute(&format!(
+        "VACUUM INTO '{}'",
+        backup.display().to_string().replace('\'', "''")
+    ))
+    .map_err(|e| format!("Failed to back up {}: {}", database.display(), e))?;
+
+    let scratch = PathBuf::from(format!("{}.{}.preflight", database.display(), stamp));
+    std::fs::copy(&backup, &scratch)
+        .map_err(|e| format!("Failed to copy {}: {}", backup.display(), e))?;
+    let result = migrate_copy(&scratch, &before);
+    if let Err(e) = std::fs::remove_file(&scratch) {
+        warn!("Failed to remove {}: {}", scratch.display(), e);
+    }
+
+    result.map(|warnings| PreflightReport { backup, migrations, warnings })
+}
+
+/// Migrates the database at `path` and checks it, returning warnings about
+/// tables that lost rows compared to `before`.
+fn migrate_copy(path: &Path, before: &[(String, i64)]) -> Result<Vec<String>, String> {
+    let mut copy = neems_data::encryption::establish(&path.to_string_lossy())
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    copy.batch_execute("PRAGMA foreign_keys = ON")
+        .map_err(|e| format!("Failed to enable foreign keys: {}", e))?;
+    copy.run_pending_migrations(MIGRATIONS)
+        .map_err(|e| format!("Migrating a copy of the database failed: {}", e))?;
+    check_database(&mut copy)?;
+
+    let after = row_counts(&mut copy)?;
+    Ok(before
+        .iter()
+        .filter_map(|(table, count)| match after.iter().find(|(name, _)| name == table) {
+            None => Some(format!("table {} ({} rows) is dropped", table, count)),
+            Some((_, after)) if after < count => {
+                Some(format!("table {} goes from {} to {} rows", table, count, after))
+            }
+            Some(_) => None,
+        })
+        .collect())
+}
+
+/// Runs the pending migrations, with a pre-flight first if any is
+/// destructive and the database is a file that has been migrated before.
+/// Returns the pre-flight's report when there was one.
+pub fn run_migrations_safely(
+    conn: &mut SqliteConnection,
+) -> Result<Option<PreflightReport>, String> {
+    let pending = pending_migrations(conn)?;
+    if pending.is_empty() {
+        return Ok(None);
+    }
+    let destructive: Vec<_> =
+        pending.iter().filter(|m| m.destructive).map(|m| m.name.as_str()).collect();
+    // A new database has nothing to lose
+    let new = conn
+        .applied_migrations()
+        .map_err(|e| format!("Failed to list applied migrations: {}", e))?
+        .is_empty();
+
+    let report = match database_file(conn)? {
+        Some(database) if !destructive.is_empty() && !new => {
+            info!(
+                "Destructive migrations pending ({}); running pre-flight checks",
+                destructive.join(", ")
+            );
+            let report = preflight(conn, &database)?;
+            info!("Pre-flight passed; backup at {}", report.backup.display());
+            for warning in &report.warnings {
+                warn!("Migration pre-flight: {}", warning);
+            }
+            Some(report)
+        }
+        _ => None,
+    };
+
+    conn.run_pending_migrations(MIGRATIONS)
+        .map_err(|e| format!("Failed to run pending migrations: {}", e))?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::Connection;
+
+    use super::*;
+
+    #[test]
+    fn test_is_destructive() {
+        assert!(is_destructive("ALTER TABLE users\n  drop   column totp_secret;"));
+        assert!(is_destructive("DROP TABLE IF EXISTS old_sites;"));
+        assert!(is_destructive("delete from roles where name = 'x';"));
+        assert!(!is_destructive("CREATE TABLE kpis (id INTEGER PRIMARY KEY);"));
+        assert!(!is_destructive("-- DROP TABLE kpis;\nALTER TABLE sites ADD COLUMN x TEXT;"));
+        assert!(
+            embedded::MIGRATION_SQL
+                .iter()
+                .any(|(name, sql)| name.ends_with("_remove_user_timestamps") && is_destructive(sql))
+        );
+    }
+
+    #[test]
+    fn test_preflight_backs_up_and_leaves_database_unmigrated() {
+        let dir = std::env::temp_dir().join(format!("neems_preflight_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let database = dir.join("neems.db");
+        let mut conn = SqliteConnection::establish(&database.to_string_lossy()).unwrap();
+
+        assert_eq!(database_file(&mut conn).unwrap(), Some(database.clone()));
+        let pending = pending_migrations(&mut conn).unwrap();
+        assert_eq!(pending.len(), embedded::MIGRATION_SQL.len());
+        assert!(pending.iter().any(|m| m.destructive));
+
+        let report = preflight(&mut conn, &database).unwrap();
+        assert!(report.backup.exists());
+        assert_eq!(report.migrations.len(), pending.len());
+        assert_eq!(pending_migrations(&mut conn).unwrap().len(), pending.len());
+        let leftovers = std::fs::read_dir(&dir)
+            .unwrap()
+            .filter(|entry| {
+                entry.as_ref().unwrap().path().to_string_lossy().ends_with(".preflight")
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+
+        conn.run_next_migration(MIGRATIONS).unwrap();
+        let report = run_migrations_safely(&mut conn).unwrap();
+        assert!(report.is_some());
+        assert!(pending_migrations(&mut conn).unwrap().is_empty());
+        assert!(run_migrations_safely(&mut conn).unwrap().is_none());
+
+        let mut memory = SqliteConnection::establish(":memory:").unwrap();
+        assert_eq!(database_file(&mut memory).unwrap(), None);
+        assert!(run_migrations_safely(&mut memory).unwrap().is_none());
+        assert!(pending_migrations(&mut memory).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_new_database_skips_preflight() {
+        let dir = std::env::temp_dir().join(format!("neems_preflight_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut conn =
+            SqliteConnection::establish(&dir.join("neems.db").to_string_lossy()).unwrap();
+
+        assert!(run_migrations_safely(&mut conn).unwrap().is_none());
+        assert!(pending_migrations(&mut conn).unwrap().is_empty());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,19 +1,34 @@
+pub mod alarm;
+pub mod allowed_network;
+pub mod api_key;
 pub mod application_rule;
+pub mod client_certificate;
 pub mod company;
 mod db;
 pub mod device;
+pub mod device_group;
 pub mod entity_activity;
+pub mod escalation;
 pub mod holidays;
+pub mod kpi;
+pub mod locale;
 pub mod login;
+pub mod login_event;
 pub mod logout;
+pub mod migration_preflight;
 pub mod neems_data;
+pub mod oncall;
 pub mod role;
+pub mod schedule_feed_token;
 pub mod schedule_library;
+pub mod scheduler_tick;
 pub mod site;
+pub mod site_tariff;
+pub mod slow_query;
 #[cfg(feature = "test-staging")]
 pub mod testing;
 pub mod user;
 pub mod user_role;
 
 pub use db::*;
-pub use neems_data::SiteDbConn;
+pub use neems_data::{SiteDbConn, SiteReadConn};
@@ -1,13 +1,21 @@
 use diesel::connection::SimpleConnection;
 use diesel_migrations::{EmbeddedMigrations, MigrationHarness, embed_migrations};
 use rocket::fairing::AdHoc;
-use rocket_sync_db_pools::{database, diesel};
+use rocket_sync_db_pools::{ConnectionPool, database, diesel};
 
 pub const SITE_MIGRATIONS: EmbeddedMigrations = embed_migrations!("../neems-data/migrations");
 
 #[database("site_db")]
 pub struct SiteDbConn(diesel::SqliteConnection);
 
+impl SiteDbConn {
+    /// A connection from `pool`, for background tasks that shouldn't hold one
+    /// between runs.
+    pub async fn from_pool(pool: &ConnectionPool<Self, diesel::SqliteConnection>) -> Option<Self> {
+        pool.get().await.map(SiteDbConn)
+    }
+}
+
 pub fn set_foreign_keys(conn: &mut diesel::SqliteConnection) {
     conn.batch_execute("PRAGMA foreign_keys = ON")
         .expect("Failed to enable foreign keys");
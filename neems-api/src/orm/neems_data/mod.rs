@@ -1,3 +1,7 @@
 pub mod db;
+pub mod read_pool;
+pub mod readings;
 
 pub use db::{SiteDbConn, set_foreign_keys_fairing};
+pub use read_pool::{SiteReadConn, site_read_pool_fairing};
+pub use readings::{load_equipment_sources, load_site_readings};
@@ -0,0 +1,279 @@
+//! Read-only site database connections for the API's heavy reads.
+//!
+//! On an edge box neems-data's collectors share the CPU and the site database
+//! with neems-api, and a long report query can hold up the control loop. With
+//! `SITE_READ_DATABASE_URL` set, the endpoints that read the most (readings,
+//! aggregates, reports, the fleet overview) take a [`SiteReadConn`] that runs
+//! their queries on a few dedicated worker threads instead of Rocket's blocking
+//! pool:
+//!
+//! - each worker has its own connection, opened with `PRAGMA query_only`, to
+//!   `SITE_READ_DATABASE_URL`: the site database itself, or a replica of it
+//!   kept up to date by other means (e.g. Litestream), which takes the reads
+//!   off the live file altogether
+//! - there are `NEEMS_SITE_READ_WORKERS` of them (default 2), so at most that
+//!   many such queries run at once and the rest queue
+//! - on Linux they run at nice `NEEMS_SITE_READ_NICE` (default 10), so the
+//!   scheduler favours the collectors and the rest of the API over them
+//!
+//! Without it, [`SiteReadConn`] is a plain [`SiteDbConn`].
+
+use std::{
+    sync::{Arc, Mutex, mpsc},
+    thread,
+};
+
+use diesel::{SqliteConnection, connection::SimpleConnection};
+use neems_data::{DataResult, encryption};
+use rocket::{
+    Request,
+    fairing::AdHoc,
+    request::{FromRequest, Outcome},
+    tokio::sync::oneshot,
+};
+
+use super::db::SiteDbConn;
+
+/// Environment variable naming the database read-only connections open.
+pub const READ_DATABASE_URL_ENV: &str = "SITE_READ_DATABASE_URL";
+
+/// Environment variable holding the number of read workers.
+pub const READ_WORKERS_ENV: &str = "NEEMS_SITE_READ_WORKERS";
+
+/// Environment variable holding the read workers' nice value.
+pub const READ_NICE_ENV: &str = "NEEMS_SITE_READ_NICE";
+
+const DEFAULT_WORKERS: usize = 2;
+const DEFAULT_NICE: i32 = 10;
+
+type Job = Box<dyn FnOnce(&mut SqliteConnection) + Send>;
+
+/// How the read workers are set up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadPoolConfig {
+    pub database_url: String,
+    pub workers: usize,
+    /// Nice value of the worker threads, from -20 (highest priority) to 19
+    pub nice: i32,
+}
+
+impl ReadPoolConfig {
+    /// The configuration from the environment, or `None` when
+    /// `SITE_READ_DATABASE_URL` isn't set.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let Some(database_url) =
+            std::env::var(READ_DATABASE_URL_ENV).ok().filter(|url| !url.trim().is_empty())
+        else {
+            return Ok(None);
+        };
+        let workers = match std::env::var(READ_WORKERS_ENV) {
+            Ok(value) => value
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| format!("{} must be a positive number", READ_WORKERS_ENV))?,
+            Err(_) => DEFAULT_WORKERS,
+        };
+        let nice = match std::env::var(READ_NICE_ENV) {
+            Ok(value) => value
+                .trim()
+                .parse::<i32>()
+                .ok()
+                .filter(|n| (-20..=19).contains(n))
+                .ok_or_else(|| format!("{} must be between -20 and 19", READ_NICE_ENV))?,
+            Err(_) => DEFAULT_NICE,
+        };
+        Ok(Some(Self { database_url, workers, nice }))
+    }
+}
+
+/// The read workers, as Rocket state.
+#[derive(Clone)]
+pub struct SiteReadPool {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl SiteReadPool {
+    /// Open `config.workers` read-only connections and start a worker thread
+    /// for each. The workers stop once the pool and its clones are dropped.
+    pub fn start(config: &ReadPoolConfig) -> DataResult<Self> {
+        let connections = (0..config.workers)
+            .map(|_| read_only_connection(&config.database_url))
+            .collect::<DataResult<Vec<_>>>()?;
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let queue = Arc::new(Mutex::new(queue));
+        for (i, mut conn) in connections.into_iter().enumerate() {
+            let queue = Arc::clone(&queue);
+            let nice = config.nice;
+            thread::Builder::new().name(format!("site-read-{}", i)).spawn(move || {
+                lower_thread_priority(nice);
+                loop {
+                    let job = queue.lock().unwrap_or_else(|e| e.into_inner()).recv();
+                    match job {
+                        Ok(job) => job(&mut conn),
+                        Err(_) => break,
+                    }
+                }
+            })?;
+        }
+        Ok(Self { jobs })
+    }
+
+    /// Run `f` on one of the workers' connections once one is free.
+    pub async fn run<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut SqliteConnection) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (done, result) = oneshot::channel();
+        let job: Job = Box::new(move |conn| {
+            let _ = done.send(f(conn));
+        });
+        self.jobs.send(job).expect("site read workers stopped");
+        // A query that panics takes its worker down with it and drops `done`
+        result.await.expect("site read query panicked")
+    }
+}
+
+/// A connection to `database_url` that refuses to write.
+fn read_only_connection(database_url: &str) -> DataResult<SqliteConnection> {
+    let mut conn = encryption::establish(database_url)?;
+    conn.batch_execute("PRAGMA query_only = ON;")?;
+    Ok(conn)
+}
+
+/// Set the calling thread's nice value; Linux applies it per thread.
+fn lower_thread_priority(nice: i32) {
+    #[cfg(target_os = "linux")]
+    {
+        // SAFETY: setpriority only reads its arguments; with PRIO_PROCESS and
+        // 0 it changes the calling thread alone.
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } != 0 {
+            warn!(
+                "Could not set site read worker priority to nice {}: {}",
+                nice,
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = nice;
+}
+
+/// Start the read workers when `SITE_READ_DATABASE_URL` is set.
+pub fn site_read_pool_fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Site DB Read Pool", |rocket| async {
+        let config = match ReadPoolConfig::from_env() {
+            Ok(Some(config)) => config,
+            Ok(None) => return Ok(rocket),
+            Err(e) => {
+                eprintln!("Invalid site read pool configuration: {}", e);
+                return Err(rocket);
+            }
+        };
+        match SiteReadPool::start(&config) {
+            Ok(pool) => {
+                info!(
+                    "Heavy site database reads use {} worker(s) at nice {} on {}",
+                    config.workers,
+                    config.nice,
+                    encryption::redact_url(&config.database_url)
+                );
+                Ok(rocket.manage(pool))
+            }
+            Err(e) => {
+                eprintln!("Failed to open site read connections: {}", e);
+                Err(rocket)
+            }
+        }
+    })
+}
+
+/// A site database connection for reads that may be slow: the read workers
+/// when they're configured, otherwise the shared pool.
+pub enum SiteReadConn {
+    Isolated(SiteReadPool),
+    Shared(SiteDbConn),
+}
+
+impl SiteReadConn {
+    pub async fn run<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut SqliteConnection) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        match self {
+            SiteReadConn::Isolated(pool) => pool.run(f).await,
+            SiteReadConn::Shared(conn) => conn.run(f).await,
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SiteReadConn {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+        if let Some(pool) = request.rocket().state::<SiteReadPool>() {
+            return Outcome::Success(SiteReadConn::Isolated(pool.clone()));
+        }
+        request.guard::<SiteDbConn>().await.map(SiteReadConn::Shared)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::{prelude::*, sql_types::Integer};
+
+    use super::*;
+
+    #[derive(QueryableByName)]
+    struct Count {
+        #[diesel(sql_type = Integer)]
+        n: i32,
+    }
+
+    #[rocket::async_test]
+    async fn test_read_workers_read_but_never_write() {
+        let path = std::env::temp_dir().join(format!("site-read-{}.db", uuid::Uuid::new_v4()));
+        let url = path.to_str().unwrap().to_string();
+        let mut conn = SqliteConnection::establish(&url).unwrap();
+        conn.batch_execute(
+            "CREATE TABLE probe (id INTEGER PRIMARY KEY); INSERT INTO probe VALUES (1);",
+        )
+        .unwrap();
+
+        let config = ReadPoolConfig {
+            database_url: url,
+            workers: 2,
+            nice: DEFAULT_NICE,
+        };
+        let pool = SiteReadPool::start(&config).unwrap();
+        let count = pool
+            .run(|conn| {
+                diesel::sql_query("SELECT COUNT(*) AS n FROM probe").get_result::<Count>(conn)
+            })
+            .await
+            .unwrap();
+        assert_eq!(count.n, 1);
+        let written = pool.run(|conn| conn.batch_execute("INSERT INTO probe VALUES (2);")).await;
+        assert!(written.is_err(), "read connections must refuse writes");
+
+        // The pool serves more queries than it has workers
+        let counts: Vec<i32> = rocket::futures::future::join_all((0..5).map(|_| {
+            pool.run(|conn| {
+                diesel::sql_query("SELECT COUNT(*) AS n FROM probe").get_result::<Count>(conn)
+            })
+        }))
+        .await
+        .into_iter()
+        .map(|count| count.unwrap().n)
+        .collect();
+        assert_eq!(counts, vec![1; 5]);
+
+        drop(pool);
+        drop(conn);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
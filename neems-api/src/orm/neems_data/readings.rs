@@ -0,0 +1,48 @@
+//! Queries over a site's readings in the site database.
+
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use neems_data::{
+    collectors::TestType,
+    schema::{readings, sources},
+};
+
+/// The readings of `site_id`'s sources from `start` until `end`, as
+/// `(source_id, timestamp, data)` ordered by source and time.
+pub fn load_site_readings(
+    conn: &mut SqliteConnection,
+    site_id: i32,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+) -> QueryResult<Vec<(i32, NaiveDateTime, String)>> {
+    let source_ids: Vec<i32> = sources::table
+        .filter(sources::site_id.eq(site_id))
+        .select(sources::id.assume_not_null())
+        .load(conn)?;
+    readings::table
+        .filter(readings::source_id.eq_any(&source_ids))
+        .filter(readings::timestamp.ge(start))
+        .filter(readings::timestamp.lt(end))
+        .order((readings::source_id.asc(), readings::timestamp.asc()))
+        .select((readings::source_id, readings::timestamp, readings::data))
+        .load(conn)
+}
+
+/// The `(id, interval_seconds)` of `site_id`'s active equipment sources: those
+/// other than carbon intensity feeds, by id.
+pub fn load_equipment_sources(
+    conn: &mut SqliteConnection,
+    site_id: i32,
+) -> QueryResult<Vec<(i32, i32)>> {
+    sources::table
+        .filter(sources::site_id.eq(site_id))
+        .filter(sources::active.eq(true))
+        .filter(
+            sources::test_type
+                .is_null()
+                .or(sources::test_type.ne(TestType::CarbonIntensity.as_str())),
+        )
+        .order(sources::id.asc())
+        .select((sources::id.assume_not_null(), sources::interval_seconds))
+        .load(conn)
+}
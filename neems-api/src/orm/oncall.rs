@@ -0,0 +1,242 @@
+//! Database operations for on-call rotations and their overrides.
+
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+use crate::models::{
+    NewOnCallOverride, NewOnCallRotation, OnCallMember, OnCallMemberDb, OnCallOverride,
+    OnCallOverrideDb, OnCallRotation, OnCallRotationDb, OnCallRotationRequest,
+};
+
+/// Loads a rotation's members, and its overrides ending after
+/// `overrides_after`, and builds the API model
+fn to_oncall_rotation(
+    conn: &mut SqliteConnection,
+    rotation: OnCallRotationDb,
+    overrides_after: NaiveDateTime,
+) -> Result<OnCallRotation, diesel::result::Error> {
+    use crate::schema::{oncall_members, oncall_overrides, users};
+
+    let members = oncall_members::table
+        .inner_join(users::table)
+        .filter(oncall_members::rotation_id.eq(rotation.id))
+        .order(oncall_members::position.asc())
+        .select((oncall_members::user_id, users::email))
+        .load::<(i32, String)>(conn)?
+        .into_iter()
+        .map(|(user_id, email)| OnCallMember { user_id, email })
+        .collect();
+    let overrides = oncall_overrides::table
+        .inner_join(users::table)
+        .filter(oncall_overrides::rotation_id.eq(rotation.id))
+        .filter(oncall_overrides::ends_at.gt(overrides_after))
+        .order((oncall_overrides::starts_at.asc(), oncall_overrides::id.asc()))
+        .select((OnCallOverrideDb::as_select(), users::email))
+        .load::<(OnCallOverrideDb, String)>(conn)?
+        .into_iter()
+        .map(|(o, email)| to_oncall_override(o, email))
+        .collect();
+    Ok(OnCallRotation {
+        id: rotation.id,
+        company_id: rotation.company_id,
+        name: rotation.name,
+        handoff_at: rotation.handoff_at,
+        shift_hours: rotation.shift_hours,
+        members,
+        overrides,
+        created_at: rotation.created_at,
+        updated_at: rotation.updated_at,
+    })
+}
+
+fn to_oncall_override(o: OnCallOverrideDb, email: String) -> OnCallOverride {
+    OnCallOverride {
+        id: o.id,
+        rotation_id: o.rotation_id,
+        user_id: o.user_id,
+        email,
+        starts_at: o.starts_at,
+        ends_at: o.ends_at,
+        reason: o.reason,
+        created_at: o.created_at,
+    }
+}
+
+/// Lists a company's rotations by name, with the overrides ending after
+/// `overrides_after`
+pub fn list_oncall_rotations(
+    conn: &mut SqliteConnection,
+    company_id_param: i32,
+    overrides_after: NaiveDateTime,
+) -> Result<Vec<OnCallRotation>, diesel::result::Error> {
+    use crate::schema::oncall_rotations::dsl::*;
+
+    oncall_rotations
+        .filter(company_id.eq(company_id_param))
+        .order(name.asc())
+        .select(OnCallRotationDb::as_select())
+        .load(conn)?
+        .into_iter()
+        .map(|rotation| to_oncall_rotation(conn, rotation, overrides_after))
+        .collect()
+}
+
+/// Gets a rotation by id, with the overrides ending after `overrides_after`
+pub fn get_oncall_rotation(
+    conn: &mut SqliteConnection,
+    rotation_id: i32,
+    overrides_after: NaiveDateTime,
+) -> Result<Option<OnCallRotation>, diesel::result::Error> {
+    use crate::schema::oncall_rotations::dsl::*;
+
+    let rotation = oncall_rotations
+        .find(rotation_id)
+        .select(OnCallRotationDb::as_select())
+        .first(conn)
+        .optional()?;
+    rotation
+        .map(|rotation| to_oncall_rotation(conn, rotation, overrides_after))
+        .transpose()
+}
+
+/// Sets a rotation's members, in order, replacing any it has
+fn set_members(
+    conn: &mut SqliteConnection,
+    rotation_id_param: i32,
+    request: &OnCallRotationRequest,
+) -> Result<(), diesel::result::Error> {
+    use crate::schema::oncall_members::dsl::*;
+
+    diesel::delete(oncall_members.filter(rotation_id.eq(rotation_id_param))).execute(conn)?;
+    let members: Vec<OnCallMemberDb> = request
+        .members
+        .iter()
+        .zip(0..)
+        .map(|(member, index)| OnCallMemberDb {
+            rotation_id: rotation_id_param,
+            position: index,
+            user_id: *member,
+        })
+        .collect();
+    diesel::insert_into(oncall_members).values(&members).execute(conn)?;
+    Ok(())
+}
+
+/// Creates an on-call rotation for a company
+pub fn create_oncall_rotation(
+    conn: &mut SqliteConnection,
+    company_id_param: i32,
+    request: OnCallRotationRequest,
+    now: NaiveDateTime,
+) -> Result<OnCallRotation, diesel::result::Error> {
+    use crate::schema::oncall_rotations::dsl::*;
+
+    conn.transaction(|conn| {
+        let rotation_name = request.name.trim().to_string();
+        diesel::insert_into(oncall_rotations)
+            .values(&NewOnCallRotation {
+                company_id: company_id_param,
+                name: rotation_name.clone(),
+                handoff_at: request.handoff_at,
+                shift_hours: request.shift_hours,
+            })
+            .execute(conn)?;
+        let rotation = oncall_rotations
+            .filter(company_id.eq(company_id_param))
+            .filter(name.eq(rotation_name))
+            .select(OnCallRotationDb::as_select())
+            .first(conn)?;
+        set_members(conn, rotation.id, &request)?;
+        to_oncall_rotation(conn, rotation, now)
+    })
+}
+
+/// Replaces a rotation's settings and members, keeping its overrides;
+/// returns None if there is no such rotation
+pub fn update_oncall_rotation(
+    conn: &mut SqliteConnection,
+    rotation_id: i32,
+    request: OnCallRotationRequest,
+    now: NaiveDateTime,
+) -> Result<Option<OnCallRotation>, diesel::result::Error> {
+    use crate::schema::oncall_rotations::dsl::*;
+
+    conn.transaction(|conn| {
+        let updated = diesel::update(oncall_rotations.find(rotation_id))
+            .set((
+                name.eq(request.name.trim()),
+                handoff_at.eq(request.handoff_at),
+                shift_hours.eq(request.shift_hours),
+                updated_at.eq(now),
+            ))
+            .execute(conn)?;
+        if updated == 0 {
+            return Ok(None);
+        }
+        set_members(conn, rotation_id, &request)?;
+        get_oncall_rotation(conn, rotation_id, now)
+    })
+}
+
+/// Names of the escalation policies with a tier on the rotation
+pub fn policies_using_rotation(
+    conn: &mut SqliteConnection,
+    rotation_id_param: i32,
+) -> Result<Vec<String>, diesel::result::Error> {
+    use crate::schema::{escalation_policies, escalation_tiers};
+
+    escalation_tiers::table
+        .inner_join(escalation_policies::table)
+        .filter(escalation_tiers::rotation_id.eq(rotation_id_param))
+        .select(escalation_policies::name)
+        .distinct()
+        .order(escalation_policies::name.asc())
+        .load(conn)
+}
+
+/// Deletes a rotation with its members and overrides, returning whether it
+/// existed
+pub fn delete_oncall_rotation(
+    conn: &mut SqliteConnection,
+    rotation_id: i32,
+) -> Result<bool, diesel::result::Error> {
+    use crate::schema::oncall_rotations::dsl::*;
+
+    let deleted = diesel::delete(oncall_rotations.find(rotation_id)).execute(conn)?;
+    Ok(deleted > 0)
+}
+
+/// Adds an override to a rotation
+pub fn add_oncall_override(
+    conn: &mut SqliteConnection,
+    new: NewOnCallOverride,
+) -> Result<OnCallOverride, diesel::result::Error> {
+    use crate::schema::{oncall_overrides, users};
+
+    conn.transaction(|conn| {
+        diesel::insert_into(oncall_overrides::table).values(&new).execute(conn)?;
+        let (o, email) = oncall_overrides::table
+            .inner_join(users::table)
+            .order(oncall_overrides::id.desc())
+            .select((OnCallOverrideDb::as_select(), users::email))
+            .first::<(OnCallOverrideDb, String)>(conn)?;
+        Ok(to_oncall_override(o, email))
+    })
+}
+
+/// Deletes one of a rotation's overrides, returning whether it existed
+pub fn delete_oncall_override(
+    conn: &mut SqliteConnection,
+    rotation_id_param: i32,
+    override_id: i32,
+) -> Result<bool, diesel::result::Error> {
+    use crate::schema::oncall_overrides::dsl::*;
+
+    let deleted = diesel::delete(
+        oncall_overrides
+            .filter(id.eq(override_id))
+            .filter(rotation_id.eq(rotation_id_param)),
+    )
+    .execute(conn)?;
+    Ok(deleted > 0)
+}
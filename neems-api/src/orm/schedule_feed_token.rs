@@ -0,0 +1,141 @@
+//! Database operations for schedule feed tokens.
+//!
+//! Tokens are 32 random bytes, hex-encoded behind an `ical_` prefix, and like
+//! API keys only their SHA-256 is stored. They travel in calendar
+//! subscription URLs, so each is limited to reading one site's schedule.
+
+use diesel::prelude::*;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::models::{NewScheduleFeedToken, ScheduleFeedToken};
+
+const TOKEN_PREFIX: &str = "ical_";
+
+/// Characters of a token kept as its displayed prefix
+const DISPLAYED_PREFIX_LEN: usize = TOKEN_PREFIX.len() + 8;
+
+fn generate_feed_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rng().fill_bytes(&mut bytes);
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}{}", TOKEN_PREFIX, hex)
+}
+
+fn hash_feed_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Creates a feed token for a user to read a site's schedule, returning it
+/// along with the token itself, which isn't stored and can't be retrieved
+/// later
+pub fn create_feed_token(
+    conn: &mut SqliteConnection,
+    site_id_param: i32,
+    user_id_param: i32,
+    description_param: Option<String>,
+) -> Result<(ScheduleFeedToken, String), diesel::result::Error> {
+    use crate::schema::schedule_feed_tokens::dsl::*;
+
+    let token = generate_feed_token();
+    let new_token = NewScheduleFeedToken {
+        site_id: site_id_param,
+        user_id: user_id_param,
+        token_hash: hash_feed_token(&token),
+        prefix: token[..DISPLAYED_PREFIX_LEN].to_string(),
+        description: description_param,
+    };
+    diesel::insert_into(schedule_feed_tokens).values(&new_token).execute(conn)?;
+
+    let feed_token = schedule_feed_tokens
+        .filter(token_hash.eq(&new_token.token_hash))
+        .select(ScheduleFeedToken::as_select())
+        .first(conn)?;
+    Ok((feed_token, token))
+}
+
+/// Gets a user's feed tokens for a site
+pub fn get_user_feed_tokens(
+    conn: &mut SqliteConnection,
+    site_id_param: i32,
+    user_id_param: i32,
+) -> Result<Vec<ScheduleFeedToken>, diesel::result::Error> {
+    use crate::schema::schedule_feed_tokens::dsl::*;
+
+    schedule_feed_tokens
+        .filter(site_id.eq(site_id_param))
+        .filter(user_id.eq(user_id_param))
+        .order(id.asc())
+        .select(ScheduleFeedToken::as_select())
+        .load(conn)
+}
+
+/// Removes one of a user's feed tokens, returning whether it existed
+pub fn delete_feed_token(
+    conn: &mut SqliteConnection,
+    user_id_param: i32,
+    token_id: i32,
+) -> Result<bool, diesel::result::Error> {
+    use crate::schema::schedule_feed_tokens::dsl::*;
+
+    let deleted = diesel::delete(
+        schedule_feed_tokens.filter(id.eq(token_id)).filter(user_id.eq(user_id_param)),
+    )
+    .execute(conn)?;
+    Ok(deleted > 0)
+}
+
+/// Finds the feed token a calendar app presented
+pub fn get_feed_token_by_token(
+    conn: &mut SqliteConnection,
+    token: &str,
+) -> Result<Option<ScheduleFeedToken>, diesel::result::Error> {
+    use crate::schema::schedule_feed_tokens::dsl::*;
+
+    schedule_feed_tokens
+        .filter(token_hash.eq(hash_feed_token(token)))
+        .select(ScheduleFeedToken::as_select())
+        .first(conn)
+        .optional()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orm::{site::insert_site, testing::setup_test_db, user::insert_service_account};
+
+    #[test]
+    fn test_feed_token_lifecycle() {
+        let mut conn = setup_test_db();
+        let company = crate::company::insert_company(&mut conn, "Feed Co".to_string(), None)
+            .expect("insert company");
+        let site = insert_site(
+            &mut conn,
+            "Feed Site".to_string(),
+            "1 Calendar Rd".to_string(),
+            40.0,
+            -74.0,
+            company.id,
+            120,
+            None,
+        )
+        .expect("insert site");
+        let user = insert_service_account(&mut conn, "calendar", company.id, None)
+            .expect("insert service account");
+
+        let (feed_token, token) =
+            create_feed_token(&mut conn, site.id, user.id, Some("ops calendar".to_string()))
+                .unwrap();
+        assert!(token.starts_with(&feed_token.prefix));
+        assert_eq!(token.len(), TOKEN_PREFIX.len() + 64);
+        let found = get_feed_token_by_token(&mut conn, &token).unwrap().unwrap();
+        assert_eq!((found.id, found.site_id), (feed_token.id, site.id));
+        assert!(get_feed_token_by_token(&mut conn, &generate_feed_token()).unwrap().is_none());
+
+        assert_eq!(get_user_feed_tokens(&mut conn, site.id, user.id).unwrap().len(), 1);
+        assert!(get_user_feed_tokens(&mut conn, site.id + 1, user.id).unwrap().is_empty());
+        assert!(!delete_feed_token(&mut conn, user.id + 1, feed_token.id).unwrap());
+        assert!(delete_feed_token(&mut conn, user.id, feed_token.id).unwrap());
+        assert!(get_feed_token_by_token(&mut conn, &token).unwrap().is_none());
+    }
+}
@@ -1,11 +1,13 @@
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr};
 
 use diesel::{prelude::*, sql_types::BigInt};
+use serde::{Deserialize, Serialize};
 
 use crate::models::{
-    CommandType, CreateCommandRequest, CreateLibraryItemRequest, NewScheduleCommand,
-    NewScheduleTemplate, NewScheduleTemplateEntry, ScheduleCommandDto, ScheduleLibraryItem,
-    ScheduleTemplate, ScheduleTemplateEntry, UpdateLibraryItemRequest,
+    ApplicationRule, CommandParameterBindings, CommandType, CreateCommandRequest,
+    CreateLibraryItemRequest, InstantiateTemplateRequest, NewScheduleCommand, NewScheduleTemplate,
+    NewScheduleTemplateEntry, ScheduleCommandDto, ScheduleLibraryItem, ScheduleTemplate,
+    ScheduleTemplateEntry, TemplateParameter, UpdateLibraryItemRequest,
 };
 
 #[derive(QueryableByName)]
@@ -14,6 +16,58 @@ struct LastInsertRowId {
     last_insert_rowid: i64,
 }
 
+/// The optional command settings kept as JSON in
+/// `schedule_commands.parameters`.
+#[derive(Default, Serialize, Deserialize)]
+struct CommandParameters {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    power_kw: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device_id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bindings: Option<CommandParameterBindings>,
+}
+
+impl CommandParameters {
+    fn parse(parameters: Option<&str>) -> Result<Self, String> {
+        match parameters {
+            Some(json) => serde_json::from_str(json)
+                .map_err(|e| format!("Invalid schedule command parameters: {}", e)),
+            None => Ok(Self::default()),
+        }
+    }
+}
+
+/// The `parameters` column for `cmd`, or NULL when it has none.
+fn command_parameters(cmd: &CreateCommandRequest) -> Option<String> {
+    if cmd.power_kw.is_none() && cmd.device_id.is_none() && cmd.bindings.is_none() {
+        return None;
+    }
+    let parameters = CommandParameters {
+        power_kw: cmd.power_kw,
+        device_id: cmd.device_id,
+        bindings: cmd.bindings.clone(),
+    };
+    serde_json::to_string(&parameters).ok()
+}
+
+/// The `schedule_templates.parameters` column for a template's parameters,
+/// or NULL when it has none.
+fn template_parameters_column(parameters: &[TemplateParameter]) -> Option<String> {
+    if parameters.is_empty() {
+        return None;
+    }
+    serde_json::to_string(parameters).ok()
+}
+
+fn parse_template_parameters(parameters: Option<&str>) -> Result<Vec<TemplateParameter>, String> {
+    match parameters {
+        Some(json) => serde_json::from_str(json)
+            .map_err(|e| format!("Invalid schedule template parameters: {}", e)),
+        None => Ok(Vec::new()),
+    }
+}
+
 /// Creates a new library item with commands in a transaction
 pub fn create_library_item(
     conn: &mut SqliteConnection,
@@ -27,8 +81,13 @@ pub fn create_library_item(
         // 1. Validate execution offsets
         validate_execution_offsets(&request.commands)?;
 
-        // 2. Validate unique name
+        // 2. Validate unique name and the commands' devices
         validate_library_item_name(conn, site_id, &request.name, None)?;
+        validate_command_devices(conn, site_id, &request.commands)?;
+        validate_template_parameters(
+            &request.parameters,
+            request.commands.iter().filter_map(|c| c.bindings.as_ref()),
+        )?;
 
         // 3. Insert template
         let new_template = NewScheduleTemplate {
@@ -37,6 +96,7 @@ pub fn create_library_item(
             description: request.description.clone(),
             is_active: true,
             is_default: false, // Normal schedules are not default
+            parameters: template_parameters_column(&request.parameters),
         };
 
         diesel::insert_into(schedule_templates::table)
@@ -80,7 +140,7 @@ pub fn create_library_item(
             let new_cmd = NewScheduleCommand {
                 site_id,
                 type_: cmd_req.command_type.as_str().to_string(),
-                parameters: None,
+                parameters: command_parameters(cmd_req),
                 duration_seconds: cmd_req.duration_seconds,
                 target_soc_percent: cmd_req.target_soc_percent,
                 is_active: true,
@@ -110,6 +170,9 @@ pub fn create_library_item(
                 command_type: cmd_req.command_type.clone(),
                 duration_seconds: cmd_req.duration_seconds,
                 target_soc_percent: cmd_req.target_soc_percent,
+                power_kw: cmd_req.power_kw,
+                device_id: cmd_req.device_id,
+                bindings: cmd_req.bindings.clone(),
             });
         }
 
@@ -123,6 +186,7 @@ pub fn create_library_item(
             name: template.name,
             description: template.description,
             commands: created_commands,
+            parameters: request.parameters,
             created_at: template.created_at,
         })
     })
@@ -168,12 +232,18 @@ fn commands_from_site_defaults(
             command_type: CommandType::Charge,
             duration_seconds: Some((off_peak_end - off_peak_start) * 60),
             target_soc_percent: Some(end_of_charge_soc_percent),
+            power_kw: None,
+            device_id: None,
+            bindings: None,
         },
         CreateCommandRequest {
             execution_offset_seconds: peak_start * 60,
             command_type: CommandType::Discharge,
             duration_seconds: Some((peak_end - peak_start) * 60),
             target_soc_percent: None,
+            power_kw: None,
+            device_id: None,
+            bindings: None,
         },
     ])
 }
@@ -211,6 +281,7 @@ pub fn create_library_item_from_site_defaults(
             name,
             description,
             commands,
+            parameters: Vec::new(),
             change_reason: Some("Created from site defaults".to_string()),
         },
         acting_user_id,
@@ -228,40 +299,49 @@ pub fn get_library_item(
     let template = schedule_templates::table.find(item_id).first::<ScheduleTemplate>(conn)?;
 
     // Get entries with commands (JOIN)
-    let entries_with_commands: Vec<(ScheduleTemplateEntry, String, Option<i32>, Option<i32>)> =
-        schedule_template_entries::table
-            .inner_join(schedule_commands::table)
-            .filter(schedule_template_entries::template_id.eq(item_id))
-            .filter(schedule_template_entries::is_active.eq(true))
-            .order_by(schedule_template_entries::execution_offset_seconds.asc())
-            .select((
-                ScheduleTemplateEntry::as_select(),
-                schedule_commands::type_,
-                schedule_commands::duration_seconds,
-                schedule_commands::target_soc_percent,
-            ))
-            .load(conn)?;
+    type EntryWithCommand =
+        (ScheduleTemplateEntry, String, Option<i32>, Option<i32>, Option<String>);
+    let entries_with_commands: Vec<EntryWithCommand> = schedule_template_entries::table
+        .inner_join(schedule_commands::table)
+        .filter(schedule_template_entries::template_id.eq(item_id))
+        .filter(schedule_template_entries::is_active.eq(true))
+        .order_by(schedule_template_entries::execution_offset_seconds.asc())
+        .select((
+            ScheduleTemplateEntry::as_select(),
+            schedule_commands::type_,
+            schedule_commands::duration_seconds,
+            schedule_commands::target_soc_percent,
+            schedule_commands::parameters,
+        ))
+        .load(conn)?;
 
     // Map to ScheduleCommandDto
     let commands: Result<Vec<ScheduleCommandDto>, String> = entries_with_commands
         .into_iter()
-        .map(|(entry, type_str, duration_seconds, target_soc_percent)| {
+        .map(|(entry, type_str, duration_seconds, target_soc_percent, parameters)| {
+            let parameters = CommandParameters::parse(parameters.as_deref())?;
             Ok(ScheduleCommandDto {
                 id: entry.id,
                 execution_offset_seconds: entry.execution_offset_seconds,
                 command_type: CommandType::from_str(&type_str)?,
                 duration_seconds,
                 target_soc_percent,
+                power_kw: parameters.power_kw,
+                device_id: parameters.device_id,
+                bindings: parameters.bindings,
             })
         })
         .collect();
 
-    let commands = commands.map_err(|e| {
+    let invalid_data = |e: String| {
         diesel::result::Error::DeserializationError(Box::new(std::io::Error::new(
             std::io::ErrorKind::InvalidData,
             e,
         )))
-    })?;
+    };
+    let commands = commands.map_err(invalid_data)?;
+    let parameters =
+        parse_template_parameters(template.parameters.as_deref()).map_err(invalid_data)?;
 
     Ok(ScheduleLibraryItem {
         id: template.id,
@@ -269,6 +349,7 @@ pub fn get_library_item(
         name: template.name,
         description: template.description,
         commands,
+        parameters,
         created_at: template.created_at,
     })
 }
@@ -317,6 +398,7 @@ pub fn update_library_item(
         let name_changed = request.name.is_some();
         let description_changed = request.description.is_some();
         let commands_changed = request.commands.is_some();
+        let parameters_changed = request.parameters.is_some();
 
         if let Some(name_val) = request.name {
             diesel::update(schedule_templates::table.filter(schedule_templates::id.eq(item_id)))
@@ -330,6 +412,12 @@ pub fn update_library_item(
                 .execute(conn)?;
         }
 
+        if let Some(parameters_val) = request.parameters {
+            diesel::update(schedule_templates::table.filter(schedule_templates::id.eq(item_id)))
+                .set(schedule_templates::parameters.eq(template_parameters_column(&parameters_val)))
+                .execute(conn)?;
+        }
+
         // Replace commands if provided
         if let Some(commands) = request.commands {
             validate_execution_offsets(&commands)?;
@@ -338,6 +426,7 @@ pub fn update_library_item(
             for cmd_req in commands.iter() {
                 validate_command(cmd_req)?;
             }
+            validate_command_devices(conn, current.site_id, &commands)?;
 
             // Get existing entries
             let existing_entries: Vec<ScheduleTemplateEntry> = schedule_template_entries::table
@@ -364,7 +453,7 @@ pub fn update_library_item(
                 let new_cmd = NewScheduleCommand {
                     site_id: current.site_id,
                     type_: cmd_req.command_type.as_str().to_string(),
-                    parameters: None,
+                    parameters: command_parameters(cmd_req),
                     duration_seconds: cmd_req.duration_seconds,
                     target_soc_percent: cmd_req.target_soc_percent,
                     is_active: true,
@@ -396,7 +485,7 @@ pub fn update_library_item(
         // Resulting Schedule pane's provenance stuck at the original
         // create event — the leaf-table audit rows live under a
         // different table_name so the pane never sees them.
-        let parent_row_touched = name_changed || description_changed;
+        let parent_row_touched = name_changed || description_changed || parameters_changed;
         if commands_changed && !parent_row_touched {
             let current_name = schedule_templates::table
                 .find(item_id)
@@ -411,7 +500,8 @@ pub fn update_library_item(
         // call this once at the end so the row we backfill is the most
         // recent trigger-emitted one — covers the name/description path,
         // the commands-only no-op path, and the all-of-the-above path.
-        let any_change = name_changed || description_changed || commands_changed;
+        let any_change =
+            name_changed || description_changed || commands_changed || parameters_changed;
         if any_change {
             use crate::orm::entity_activity::{
                 update_latest_activity_reason, update_latest_activity_user,
@@ -434,8 +524,15 @@ pub fn update_library_item(
             );
         }
 
-        // Return updated item
-        get_library_item(conn, item_id)
+        // The bindings must still refer to the parameters, whichever of the
+        // two changed
+        let item = get_library_item(conn, item_id)?;
+        validate_template_parameters(
+            &item.parameters,
+            item.commands.iter().filter_map(|c| c.bindings.as_ref()),
+        )?;
+
+        Ok(item)
     })
 }
 
@@ -496,14 +593,150 @@ pub fn clone_library_item(
                 command_type: cmd.command_type,
                 duration_seconds: cmd.duration_seconds,
                 target_soc_percent: cmd.target_soc_percent,
+                power_kw: cmd.power_kw,
+                device_id: cmd.device_id,
+                bindings: cmd.bindings,
             })
             .collect(),
+        parameters: original.parameters,
         change_reason: Some(format!("Cloned from '{}'", original.name)),
     };
 
     create_library_item(conn, original.site_id, create_request, acting_user_id)
 }
 
+// ============================================================================
+// Template instantiation
+// ============================================================================
+
+/// The concrete commands of a template with its parameters set to `values`,
+/// or those not given to their defaults, all for `device_id` if given.
+pub fn resolve_template_commands(
+    item: &ScheduleLibraryItem,
+    values: &HashMap<String, f64>,
+    device_id: Option<i32>,
+) -> Result<Vec<CreateCommandRequest>, String> {
+    let mut unknown: Vec<&str> = values
+        .keys()
+        .filter(|name| !item.parameters.iter().any(|p| &p.name == *name))
+        .map(String::as_str)
+        .collect();
+    if !unknown.is_empty() {
+        unknown.sort_unstable();
+        return Err(format!("Unknown parameters: {}", unknown.join(", ")));
+    }
+
+    let mut resolved = HashMap::new();
+    for parameter in &item.parameters {
+        let value = values
+            .get(&parameter.name)
+            .copied()
+            .or(parameter.default_value)
+            .ok_or_else(|| format!("No value for parameter '{}'", parameter.name))?;
+        let below = parameter.min_value.is_some_and(|min| value < min);
+        let above = parameter.max_value.is_some_and(|max| value > max);
+        if !value.is_finite() || below || above {
+            return Err(format!(
+                "Value {} is out of range for parameter '{}'",
+                value, parameter.name
+            ));
+        }
+        resolved.insert(parameter.name.as_str(), value);
+    }
+
+    item.commands
+        .iter()
+        .map(|cmd| {
+            let mut command = CreateCommandRequest {
+                execution_offset_seconds: cmd.execution_offset_seconds,
+                command_type: cmd.command_type.clone(),
+                duration_seconds: cmd.duration_seconds,
+                target_soc_percent: cmd.target_soc_percent,
+                power_kw: cmd.power_kw,
+                device_id: device_id.or(cmd.device_id),
+                bindings: None,
+            };
+            let Some(bindings) = &cmd.bindings else {
+                return Ok(command);
+            };
+            // Bindings were checked against the parameters when stored
+            let value = |name: &String| {
+                resolved
+                    .get(name.as_str())
+                    .copied()
+                    .ok_or_else(|| format!("No value for parameter '{}'", name))
+            };
+            if let Some(name) = &bindings.power_kw {
+                command.power_kw = Some(value(name)?);
+            }
+            if let Some(name) = &bindings.target_soc_percent {
+                let soc = value(name)?;
+                if soc.fract() != 0.0 {
+                    return Err(format!("Parameter '{}' must be a whole number", name));
+                }
+                command.target_soc_percent = Some(soc as i32);
+            }
+            if let Some(name) = &bindings.start_hour {
+                command.execution_offset_seconds = (value(name)? * 3600.0).round() as i32;
+            }
+            if let Some(name) = &bindings.duration_hours {
+                command.duration_seconds = Some((value(name)? * 3600.0).round() as i32);
+            }
+            Ok(command)
+        })
+        .collect()
+}
+
+/// Instantiates a template: creates a schedule on `site_id` from its
+/// commands with the request's parameter values, applied by a specific-date
+/// rule to every day of the request's date range. Returns the schedule, the
+/// rule and the dates.
+pub fn instantiate_template(
+    conn: &mut SqliteConnection,
+    template_id: i32,
+    site_id: i32,
+    request: InstantiateTemplateRequest,
+    acting_user_id: Option<i32>,
+) -> Result<(ScheduleLibraryItem, ApplicationRule, Vec<chrono::NaiveDate>), diesel::result::Error> {
+    use crate::orm::application_rule::season_fill_application_rule;
+
+    conn.transaction(|conn| {
+        let template = get_library_item(conn, template_id)?;
+        let commands = resolve_template_commands(&template, &request.values, request.device_id)
+            .map_err(|e| {
+                diesel::result::Error::DeserializationError(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    e,
+                )))
+            })?;
+
+        let item = create_library_item(
+            conn,
+            site_id,
+            CreateLibraryItemRequest {
+                name: request.name,
+                description: request.description,
+                commands,
+                parameters: Vec::new(),
+                change_reason: Some(format!("Instantiated from '{}'", template.name)),
+            },
+            acting_user_id,
+        )?;
+        let (rule, dates) = season_fill_application_rule(
+            conn,
+            item.id,
+            request.start_date,
+            request.end_date,
+            false,
+            false,
+            &[],
+            request.override_reason,
+            acting_user_id,
+        )?;
+        Ok((item, rule, dates))
+    })
+}
+
 // ============================================================================
 // Default schedule helpers
 // ============================================================================
@@ -539,6 +772,7 @@ pub fn ensure_default_schedule_exists(
             description: Some("Default schedule".to_string()),
             is_active: true,
             is_default: true, // Mark as default
+            parameters: None,
         };
 
         diesel::insert_into(schedule_templates::table)
@@ -573,6 +807,7 @@ pub fn ensure_default_schedule_exists(
             specific_dates: None,
             override_reason: None,
             change_reason: None,
+            priority: None,
         };
 
         // Create the default rule - ignore errors since rule creation is best-effort
@@ -675,5 +910,167 @@ fn validate_command(cmd: &CreateCommandRequest) -> Result<(), diesel::result::Er
         }
     }
 
+    if cmd.power_kw.is_some_and(|power| !(power.is_finite() && power > 0.0)) {
+        return Err(diesel::result::Error::DeserializationError(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "power_kw must be positive",
+        ))));
+    }
+
+    Ok(())
+}
+
+/// Validates a template's parameters, and that its commands' bindings refer
+/// to them
+fn validate_template_parameters<'a>(
+    parameters: &[TemplateParameter],
+    mut bindings: impl Iterator<Item = &'a CommandParameterBindings>,
+) -> Result<(), diesel::result::Error> {
+    let invalid = |message: String| {
+        diesel::result::Error::DeserializationError(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            message,
+        )))
+    };
+
+    for (i, parameter) in parameters.iter().enumerate() {
+        let name = &parameter.name;
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(invalid(format!(
+                "Parameter name '{}' must be letters, digits and underscores",
+                name
+            )));
+        }
+        if parameters[..i].iter().any(|p| &p.name == name) {
+            return Err(invalid(format!("Parameter '{}' is declared twice", name)));
+        }
+        if parameter.min_value.zip(parameter.max_value).is_some_and(|(min, max)| min > max) {
+            return Err(invalid(format!("Parameter '{}' has min_value above max_value", name)));
+        }
+        if let Some(default) = parameter.default_value {
+            let below = parameter.min_value.is_some_and(|min| default < min);
+            let above = parameter.max_value.is_some_and(|max| default > max);
+            if below || above {
+                return Err(invalid(format!(
+                    "Parameter '{}' has a default_value out of its range",
+                    name
+                )));
+            }
+        }
+    }
+
+    match bindings.find_map(|b| b.names().find(|n| !parameters.iter().any(|p| p.name == *n))) {
+        Some(name) => Err(invalid(format!("Command binds undeclared parameter '{}'", name))),
+        None => Ok(()),
+    }
+}
+
+/// Validates the commands' devices are installed at the site
+fn validate_command_devices(
+    conn: &mut SqliteConnection,
+    site_id: i32,
+    commands: &[CreateCommandRequest],
+) -> Result<(), diesel::result::Error> {
+    use crate::schema::devices;
+
+    for device_id in commands.iter().filter_map(|c| c.device_id) {
+        let at_site: i64 = devices::table
+            .filter(devices::id.eq(device_id))
+            .filter(devices::site_id.eq(site_id))
+            .count()
+            .get_result(conn)?;
+        if at_site == 0 {
+            return Err(diesel::result::Error::DeserializationError(Box::new(
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Device {} is not at this site", device_id),
+                ),
+            )));
+        }
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parameter(name: &str, default_value: Option<f64>) -> TemplateParameter {
+        TemplateParameter {
+            name: name.to_string(),
+            description: None,
+            default_value,
+            min_value: Some(0.0),
+            max_value: Some(1000.0),
+        }
+    }
+
+    fn template() -> ScheduleLibraryItem {
+        ScheduleLibraryItem {
+            id: 1,
+            site_id: 1,
+            name: "Peak shave".to_string(),
+            description: None,
+            commands: vec![ScheduleCommandDto {
+                id: 1,
+                execution_offset_seconds: 17 * 3600,
+                command_type: CommandType::Discharge,
+                duration_seconds: Some(3600),
+                target_soc_percent: None,
+                power_kw: Some(100.0),
+                device_id: None,
+                bindings: Some(CommandParameterBindings {
+                    power_kw: Some("discharge_kw".to_string()),
+                    start_hour: Some("start_hour".to_string()),
+                    ..Default::default()
+                }),
+            }],
+            parameters: vec![parameter("discharge_kw", None), parameter("start_hour", Some(16.0))],
+            created_at: chrono::NaiveDateTime::default(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_template_commands() {
+        let values = HashMap::from([("discharge_kw".to_string(), 250.0)]);
+        let commands = resolve_template_commands(&template(), &values, Some(7)).unwrap();
+
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].power_kw, Some(250.0));
+        assert_eq!(commands[0].execution_offset_seconds, 16 * 3600);
+        assert_eq!(commands[0].duration_seconds, Some(3600));
+        assert_eq!(commands[0].device_id, Some(7));
+        assert!(commands[0].bindings.is_none());
+    }
+
+    #[test]
+    fn test_resolve_template_commands_rejects_bad_values() {
+        let item = template();
+        let missing = resolve_template_commands(&item, &HashMap::new(), None).unwrap_err();
+        assert_eq!(missing, "No value for parameter 'discharge_kw'");
+
+        let values = HashMap::from([("discharge_kw".to_string(), 5000.0)]);
+        let out_of_range = resolve_template_commands(&item, &values, None).unwrap_err();
+        assert_eq!(out_of_range, "Value 5000 is out of range for parameter 'discharge_kw'");
+
+        let values = HashMap::from([("discharge_kw".to_string(), 1.0), ("kw".to_string(), 1.0)]);
+        let unknown = resolve_template_commands(&item, &values, None).unwrap_err();
+        assert_eq!(unknown, "Unknown parameters: kw");
+    }
+
+    #[test]
+    fn test_validate_template_parameters() {
+        let item = template();
+        let bindings = || item.commands.iter().filter_map(|c| c.bindings.as_ref());
+        assert!(validate_template_parameters(&item.parameters, bindings()).is_ok());
+        assert!(validate_template_parameters(&item.parameters[..1], bindings()).is_err());
+        assert!(validate_template_parameters(&[parameter("bad name", None)], bindings()).is_err());
+        let twice = [parameter("x", None), parameter("x", None)];
+        assert!(validate_template_parameters(&twice, std::iter::empty()).is_err());
+        assert!(
+            validate_template_parameters(&[parameter("x", Some(-1.0))], std::iter::empty())
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,94 @@
+//! Database operations for scheduler ticks.
+//!
+//! Every evaluation of a site's active command is a tick. Ticks are persisted
+//! when the command changes, and otherwise at most every
+//! [`TICK_PERSIST_SECONDS`], so the last persisted tick is never later than a
+//! command start the scheduler has seen.
+
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+use crate::models::{NewSchedulerTick, SchedulerTick};
+
+/// How often a tick that doesn't change the command is persisted
+pub const TICK_PERSIST_SECONDS: i64 = 60;
+
+/// Gets a site's last persisted tick, if it has one
+pub fn get_scheduler_tick(
+    conn: &mut SqliteConnection,
+    site_id_param: i32,
+) -> Result<Option<SchedulerTick>, diesel::result::Error> {
+    use crate::schema::scheduler_ticks::dsl::*;
+
+    scheduler_ticks
+        .filter(site_id.eq(site_id_param))
+        .select(SchedulerTick::as_select())
+        .first(conn)
+        .optional()
+}
+
+/// Gets every site's last persisted tick
+pub fn list_scheduler_ticks(
+    conn: &mut SqliteConnection,
+) -> Result<Vec<SchedulerTick>, diesel::result::Error> {
+    use crate::schema::scheduler_ticks::dsl::*;
+
+    scheduler_ticks.order(site_id).select(SchedulerTick::as_select()).load(conn)
+}
+
+/// Records an evaluation at `at` that found `command_id_param` active,
+/// returning whether it was persisted
+pub fn record_scheduler_tick(
+    conn: &mut SqliteConnection,
+    site_id_param: i32,
+    at: NaiveDateTime,
+    command_id_param: Option<i32>,
+) -> Result<bool, diesel::result::Error> {
+    use crate::schema::scheduler_ticks::dsl::*;
+
+    if let Some(last) = get_scheduler_tick(conn, site_id_param)? {
+        let recent = at - last.evaluated_at < chrono::Duration::seconds(TICK_PERSIST_SECONDS);
+        if last.command_id == command_id_param && recent {
+            return Ok(false);
+        }
+    }
+    diesel::insert_into(scheduler_ticks)
+        .values(&NewSchedulerTick {
+            site_id: site_id_param,
+            evaluated_at: at,
+            command_id: command_id_param,
+            caught_up_at: None,
+            missed_commands: 0,
+        })
+        .on_conflict(site_id)
+        .do_update()
+        .set((evaluated_at.eq(at), command_id.eq(command_id_param)))
+        .execute(conn)?;
+    Ok(true)
+}
+
+/// Records a catch-up at `at` that found `missed` command starts and
+/// `command_id_param` active
+pub fn record_catch_up(
+    conn: &mut SqliteConnection,
+    site_id_param: i32,
+    at: NaiveDateTime,
+    command_id_param: Option<i32>,
+    missed: i32,
+) -> Result<SchedulerTick, diesel::result::Error> {
+    use crate::schema::scheduler_ticks::dsl::*;
+
+    diesel::replace_into(scheduler_ticks)
+        .values(&NewSchedulerTick {
+            site_id: site_id_param,
+            evaluated_at: at,
+            command_id: command_id_param,
+            caught_up_at: Some(at),
+            missed_commands: missed,
+        })
+        .execute(conn)?;
+    scheduler_ticks
+        .filter(site_id.eq(site_id_param))
+        .select(SchedulerTick::as_select())
+        .first(conn)
+}
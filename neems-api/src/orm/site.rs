@@ -1,3 +1,4 @@
+use chrono::NaiveDateTime;
 use diesel::prelude::*;
 
 use crate::models::{NewSite, Site, SiteWithTimestamps};
@@ -16,8 +17,8 @@ pub const DEFAULT_PEAK_REVENUE_END_MINUTES: i32 = 1200; // 20:00
 pub const DEFAULT_INTERCONNECTION_MAX_OUTPUT_KW: f64 = 5000.0;
 
 /// Partial update payload for [`update_site`]. Any field left `None` is
-/// preserved at its current value; nullable demo fields cannot be cleared
-/// through this struct (a future API can grow a double-`Option` if needed).
+/// preserved at its current value; nullable fields take a double `Option`, so
+/// `Some(None)` clears them.
 #[derive(Default, Debug, Clone)]
 pub struct SiteUpdate {
     pub name: Option<String>,
@@ -26,19 +27,19 @@ pub struct SiteUpdate {
     pub longitude: Option<f64>,
     pub company_id: Option<i32>,
     pub ramp_duration_seconds: Option<i32>,
-    pub power_kw: Option<f64>,
-    pub capacity_kwh: Option<f64>,
+    pub power_kw: Option<Option<f64>>,
+    pub capacity_kwh: Option<Option<f64>>,
     pub closed_loop_enabled: Option<bool>,
-    pub off_peak_start_minutes: Option<i32>,
-    pub off_peak_end_minutes: Option<i32>,
-    pub peak_revenue_start_minutes: Option<i32>,
-    pub peak_revenue_end_minutes: Option<i32>,
-    pub interconnection_max_output_kw: Option<f64>,
+    pub off_peak_start_minutes: Option<Option<i32>>,
+    pub off_peak_end_minutes: Option<Option<i32>>,
+    pub peak_revenue_start_minutes: Option<Option<i32>>,
+    pub peak_revenue_end_minutes: Option<Option<i32>>,
+    pub interconnection_max_output_kw: Option<Option<f64>>,
     pub rebound_protection_soc_floor_percent: Option<f64>,
     pub site_variant: Option<String>,
     pub charge_rate_percent: Option<f64>,
     pub discharge_rate_percent: Option<f64>,
-    pub trickle_charge_power_kw: Option<f64>,
+    pub trickle_charge_power_kw: Option<Option<f64>>,
 }
 
 /// Gets all sites for a specific company ID.
@@ -120,7 +121,7 @@ pub fn get_site_by_company_and_name(
          power_kw, capacity_kwh, closed_loop_enabled, off_peak_start_minutes, \
          off_peak_end_minutes, peak_revenue_start_minutes, peak_revenue_end_minutes, \
          interconnection_max_output_kw, rebound_protection_soc_floor_percent, site_variant, \
-         charge_rate_percent, discharge_rate_percent, trickle_charge_power_kw \
+         charge_rate_percent, discharge_rate_percent, trickle_charge_power_kw, archived_at, archive_key \
          FROM sites WHERE company_id = ? AND LOWER(name) = LOWER(?)",
     )
     .bind::<diesel::sql_types::Integer, _>(site_company_id)
@@ -157,21 +158,23 @@ pub fn update_site(
             company_id.eq(update.company_id.unwrap_or(current_site.company_id)),
             ramp_duration_seconds
                 .eq(update.ramp_duration_seconds.unwrap_or(current_site.ramp_duration_seconds)),
-            power_kw.eq(update.power_kw.or(current_site.power_kw)),
-            capacity_kwh.eq(update.capacity_kwh.or(current_site.capacity_kwh)),
+            power_kw.eq(update.power_kw.unwrap_or(current_site.power_kw)),
+            capacity_kwh.eq(update.capacity_kwh.unwrap_or(current_site.capacity_kwh)),
             closed_loop_enabled
                 .eq(update.closed_loop_enabled.unwrap_or(current_site.closed_loop_enabled)),
             off_peak_start_minutes
-                .eq(update.off_peak_start_minutes.or(current_site.off_peak_start_minutes)),
+                .eq(update.off_peak_start_minutes.unwrap_or(current_site.off_peak_start_minutes)),
             off_peak_end_minutes
-                .eq(update.off_peak_end_minutes.or(current_site.off_peak_end_minutes)),
-            peak_revenue_start_minutes
-                .eq(update.peak_revenue_start_minutes.or(current_site.peak_revenue_start_minutes)),
-            peak_revenue_end_minutes
-                .eq(update.peak_revenue_end_minutes.or(current_site.peak_revenue_end_minutes)),
+                .eq(update.off_peak_end_minutes.unwrap_or(current_site.off_peak_end_minutes)),
+            peak_revenue_start_minutes.eq(update
+                .peak_revenue_start_minutes
+                .unwrap_or(current_site.peak_revenue_start_minutes)),
+            peak_revenue_end_minutes.eq(update
+                .peak_revenue_end_minutes
+                .unwrap_or(current_site.peak_revenue_end_minutes)),
             interconnection_max_output_kw.eq(update
                 .interconnection_max_output_kw
-                .or(current_site.interconnection_max_output_kw)),
+                .unwrap_or(current_site.interconnection_max_output_kw)),
             rebound_protection_soc_floor_percent.eq(update
                 .rebound_protection_soc_floor_percent
                 .unwrap_or(current_site.rebound_protection_soc_floor_percent)),
@@ -181,7 +184,7 @@ pub fn update_site(
             discharge_rate_percent
                 .eq(update.discharge_rate_percent.unwrap_or(current_site.discharge_rate_percent)),
             trickle_charge_power_kw
-                .eq(update.trickle_charge_power_kw.or(current_site.trickle_charge_power_kw)),
+                .eq(update.trickle_charge_power_kw.unwrap_or(current_site.trickle_charge_power_kw)),
         ))
         .execute(conn)?;
 
@@ -215,6 +218,29 @@ pub fn delete_site(
     Ok(result)
 }
 
+/// Mark a site archived at `at`, its history stored under `key`.
+pub fn mark_site_archived(
+    conn: &mut SqliteConnection,
+    site_id: i32,
+    at: NaiveDateTime,
+    key: &str,
+    acting_user_id: Option<i32>,
+) -> Result<Site, diesel::result::Error> {
+    use crate::schema::sites::dsl::*;
+
+    diesel::update(sites.filter(id.eq(site_id)))
+        .set((archived_at.eq(Some(at)), archive_key.eq(Some(key))))
+        .execute(conn)?;
+    let site = sites.filter(id.eq(site_id)).select(Site::as_select()).first(conn)?;
+
+    if let Some(user_id) = acting_user_id {
+        use crate::orm::entity_activity::update_latest_activity_user;
+        let _ = update_latest_activity_user(conn, "sites", site_id, "update", user_id);
+    }
+
+    Ok(site)
+}
+
 /// Get a site with computed timestamps from activity log
 pub fn get_site_with_timestamps(
     conn: &mut SqliteConnection,
@@ -253,6 +279,7 @@ pub fn get_site_with_timestamps(
         charge_rate_percent: site.charge_rate_percent,
         discharge_rate_percent: site.discharge_rate_percent,
         trickle_charge_power_kw: site.trickle_charge_power_kw,
+        archived_at: site.archived_at,
         created_at,
         updated_at,
     }))
@@ -508,14 +535,14 @@ mod tests {
             &mut conn,
             created_site.id,
             SiteUpdate {
-                power_kw: Some(5000.0),
-                capacity_kwh: Some(23500.0),
+                power_kw: Some(Some(5000.0)),
+                capacity_kwh: Some(Some(23500.0)),
                 closed_loop_enabled: Some(false),
-                off_peak_start_minutes: Some(0),
-                off_peak_end_minutes: Some(8 * 60),
-                peak_revenue_start_minutes: Some(16 * 60),
-                peak_revenue_end_minutes: Some(20 * 60),
-                interconnection_max_output_kw: Some(5000.0),
+                off_peak_start_minutes: Some(Some(0)),
+                off_peak_end_minutes: Some(Some(8 * 60)),
+                peak_revenue_start_minutes: Some(Some(16 * 60)),
+                peak_revenue_end_minutes: Some(Some(20 * 60)),
+                interconnection_max_output_kw: Some(Some(5000.0)),
                 rebound_protection_soc_floor_percent: Some(2.5),
                 site_variant: Some("no_grid_charge".to_string()),
                 ..Default::default()
@@ -548,6 +575,20 @@ mod tests {
         .expect("Failed to update name");
         assert_eq!(after_name_change.power_kw, Some(5000.0));
         assert_eq!(after_name_change.site_variant, "no_grid_charge");
+
+        // Some(None) clears a nullable field
+        let cleared = update_site(
+            &mut conn,
+            created_site.id,
+            SiteUpdate {
+                power_kw: Some(None),
+                ..Default::default()
+            },
+            None,
+        )
+        .expect("Failed to clear power_kw");
+        assert_eq!(cleared.power_kw, None);
+        assert_eq!(cleared.capacity_kwh, Some(23500.0));
     }
 
     #[test]
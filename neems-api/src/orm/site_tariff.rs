@@ -0,0 +1,53 @@
+//! Database operations for site tariffs.
+
+use diesel::prelude::*;
+
+use crate::models::{NewSiteTariff, SetSiteTariffRequest, SiteTariff};
+
+/// Gets a site's tariff, if it has one
+pub fn get_site_tariff(
+    conn: &mut SqliteConnection,
+    site_id_param: i32,
+) -> Result<Option<SiteTariff>, diesel::result::Error> {
+    use crate::schema::site_tariffs::dsl::*;
+
+    site_tariffs
+        .filter(site_id.eq(site_id_param))
+        .select(SiteTariff::as_select())
+        .first(conn)
+        .optional()
+}
+
+/// Sets a site's tariff, replacing any it has
+pub fn set_site_tariff(
+    conn: &mut SqliteConnection,
+    site_id_param: i32,
+    request: SetSiteTariffRequest,
+) -> Result<SiteTariff, diesel::result::Error> {
+    use crate::schema::site_tariffs::dsl::*;
+
+    let new_tariff = NewSiteTariff {
+        site_id: site_id_param,
+        currency: request.currency,
+        standard_price_per_kwh: request.standard_price_per_kwh,
+        off_peak_price_per_kwh: request.off_peak_price_per_kwh,
+        peak_price_per_kwh: request.peak_price_per_kwh,
+        demand_charge_per_kw: request.demand_charge_per_kw,
+    };
+    diesel::replace_into(site_tariffs).values(&new_tariff).execute(conn)?;
+    site_tariffs
+        .filter(site_id.eq(site_id_param))
+        .select(SiteTariff::as_select())
+        .first(conn)
+}
+
+/// Removes a site's tariff, returning whether it had one
+pub fn delete_site_tariff(
+    conn: &mut SqliteConnection,
+    site_id_param: i32,
+) -> Result<bool, diesel::result::Error> {
+    use crate::schema::site_tariffs::dsl::*;
+
+    let deleted = diesel::delete(site_tariffs.filter(site_id.eq(site_id_param))).execute(conn)?;
+    Ok(deleted > 0)
+}
@@ -0,0 +1,202 @@
+//! Opt-in slow query log.
+//!
+//! With `NEEMS_SLOW_QUERY_MS` set, every database connection neems-api opens
+//! times its queries, and any query taking at least that many milliseconds is
+//! logged as a warning with its SQL and SQLite's `EXPLAIN QUERY PLAN` for it,
+//! which shows at a glance whether it used an index or scanned a table. The
+//! latest [`RECENT_LIMIT`] are also kept for [`recent_slow_queries`].
+//!
+//! The plan is taken on a second connection to the same database, with the
+//! query's parameters unbound; SQLite plans the same either way. Bound
+//! values are never logged, as they may hold personal data. In-memory
+//! databases can't be reopened, so their slow queries are logged without a
+//! plan.
+
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use diesel::{
+    connection::{Instrumentation, InstrumentationEvent, set_default_instrumentation},
+    prelude::*,
+    sql_types::Text,
+};
+
+/// Environment variable holding the threshold, in milliseconds.
+pub const SLOW_QUERY_ENV: &str = "NEEMS_SLOW_QUERY_MS";
+
+/// Slow queries kept for [`recent_slow_queries`].
+pub const RECENT_LIMIT: usize = 100;
+
+static THRESHOLD: OnceLock<Duration> = OnceLock::new();
+
+static RECENT: Mutex<VecDeque<SlowQuery>> = Mutex::new(VecDeque::new());
+
+/// One query that took longer than the threshold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlowQuery {
+    pub sql: String,
+    pub elapsed: Duration,
+    /// `EXPLAIN QUERY PLAN` rows, or why there is no plan
+    pub plan: Vec<String>,
+}
+
+/// Times the queries of one connection.
+pub struct SlowQueryLog {
+    threshold: Duration,
+    url: Option<String>,
+    running: Option<(Instant, String)>,
+}
+
+impl SlowQueryLog {
+    pub fn new(threshold: Duration) -> Self {
+        Self { threshold, url: None, running: None }
+    }
+
+    /// As [`SlowQueryLog::new`], for a connection opened from `url` before
+    /// the log was attached to it.
+    pub fn for_url(threshold: Duration, url: &str) -> Self {
+        Self {
+            url: Some(url.to_string()),
+            ..Self::new(threshold)
+        }
+    }
+
+    fn explain(&self, sql: &str) -> Vec<String> {
+        let Some(url) = self.url.as_deref().filter(|url| !url.contains(":memory:")) else {
+            return vec!["(no plan: in-memory database)".to_string()];
+        };
+        let mut conn = match SqliteConnection::establish(url) {
+            Ok(conn) => conn,
+            Err(e) => return vec![format!("(no plan: {})", e)],
+        };
+        // The plan's own query mustn't be timed in turn
+        conn.set_instrumentation(|_: InstrumentationEvent<'_>| {});
+        match diesel::sql_query(format!("EXPLAIN QUERY PLAN {}", sql)).load::<PlanRow>(&mut conn) {
+            Ok(rows) => rows.into_iter().map(|row| row.detail).collect(),
+            Err(e) => vec![format!("(no plan: {})", e)],
+        }
+    }
+}
+
+#[derive(QueryableByName)]
+struct PlanRow {
+    #[diesel(sql_type = Text)]
+    detail: String,
+}
+
+/// The SQL of a query as diesel displays it, without its bound values.
+fn query_sql(query: &str) -> &str {
+    query.rsplit_once(" -- binds: ").map_or(query, |(sql, _)| sql).trim()
+}
+
+impl Instrumentation for SlowQueryLog {
+    fn on_connection_event(&mut self, event: InstrumentationEvent<'_>) {
+        match event {
+            InstrumentationEvent::StartEstablishConnection { url, .. } => {
+                self.url = Some(url.to_string());
+            }
+            InstrumentationEvent::StartQuery { query, .. } => {
+                self.running = Some((Instant::now(), query.to_string()));
+            }
+            InstrumentationEvent::FinishQuery { .. } => {
+                let Some((started, query)) = self.running.take() else {
+                    return;
+                };
+                let elapsed = started.elapsed();
+                if elapsed < self.threshold {
+                    return;
+                }
+                let sql = query_sql(&query).to_string();
+                let plan = self.explain(&sql);
+                warn!(
+                    "Slow query ({} ms): {}\n  plan: {}",
+                    elapsed.as_millis(),
+                    sql,
+                    plan.join("\n        ")
+                );
+                let mut recent = RECENT.lock().unwrap_or_else(|e| e.into_inner());
+                if recent.len() == RECENT_LIMIT {
+                    recent.pop_front();
+                }
+                recent.push_back(SlowQuery { sql, elapsed, plan });
+            }
+            _ => {}
+        }
+    }
+}
+
+fn default_log() -> Option<Box<dyn Instrumentation>> {
+    THRESHOLD
+        .get()
+        .map(|&threshold| Box::new(SlowQueryLog::new(threshold)) as Box<dyn Instrumentation>)
+}
+
+/// Log slow queries on every connection opened from now on, if
+/// `NEEMS_SLOW_QUERY_MS` is set. Call before the connection pools start.
+pub fn install_from_env() {
+    let Some(ms) = std::env::var(SLOW_QUERY_ENV).ok().and_then(|v| v.trim().parse::<u64>().ok())
+    else {
+        return;
+    };
+    if THRESHOLD.set(Duration::from_millis(ms)).is_ok() {
+        if let Err(e) = set_default_instrumentation(default_log) {
+            warn!("Slow query log not enabled: {}", e);
+            return;
+        }
+        info!("Logging queries slower than {} ms", ms);
+    }
+}
+
+/// The latest slow queries, oldest first.
+pub fn recent_slow_queries() -> Vec<SlowQuery> {
+    RECENT.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::connection::SimpleConnection;
+
+    use super::*;
+
+    #[test]
+    fn test_query_sql_drops_binds() {
+        assert_eq!(
+            query_sql("SELECT * FROM users WHERE email = ? -- binds: [\"a@example.com\"]"),
+            "SELECT * FROM users WHERE email = ?"
+        );
+        assert_eq!(query_sql("BEGIN"), "BEGIN");
+    }
+
+    #[test]
+    fn test_slow_queries_are_logged_with_plan() {
+        let path = std::env::temp_dir().join(format!("slow-query-{}.db", uuid::Uuid::new_v4()));
+        let url = path.to_str().unwrap().to_string();
+        let mut conn = SqliteConnection::establish(&url).unwrap();
+        conn.batch_execute(
+            "CREATE TABLE slow_query_probe (id INTEGER PRIMARY KEY, owner INTEGER NOT NULL);",
+        )
+        .unwrap();
+        conn.set_instrumentation(SlowQueryLog::for_url(Duration::ZERO, &url));
+
+        diesel::sql_query("SELECT id FROM slow_query_probe WHERE owner = ?")
+            .bind::<diesel::sql_types::Integer, _>(7)
+            .execute(&mut conn)
+            .unwrap();
+        let logged = recent_slow_queries()
+            .into_iter()
+            .find(|q| q.sql.contains("slow_query_probe WHERE owner"))
+            .expect("query logged");
+        assert_eq!(logged.sql, "SELECT id FROM slow_query_probe WHERE owner = ?");
+        assert!(
+            logged.plan.iter().any(|row| row.contains("SCAN slow_query_probe")),
+            "{:?}",
+            logged.plan
+        );
+
+        drop(conn);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
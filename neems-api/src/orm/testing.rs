@@ -14,6 +14,10 @@ use rocket_sync_db_pools::diesel;
 use super::db::{DbConn, run_pending_migrations, set_foreign_keys};
 use crate::admin_init_fairing::admin_init_fairing;
 
+mod fixtures;
+
+pub use fixtures::{FixtureBuilder, Fixtures, create_golden_db, golden_fixtures};
+
 /// Creates a golden database template with all test data pre-populated.
 /// This is created once and then copied for each test that needs it.
 /// The golden database is identified by timestamp and located automatically.
@@ -134,8 +138,11 @@ pub fn test_rocket() -> Rocket<Build> {
         databases.insert("site_db", site_db_config);
     }
 
-    // Merge DB config into Rocket's figment
-    let figment = rocket::Config::figment().merge(("databases", databases));
+    // Merge DB config into Rocket's figment, with exports stored apart from
+    // other tests'
+    let figment = rocket::Config::figment()
+        .merge(("databases", databases))
+        .merge(("storage_path", test_storage_path()));
 
     // Build the Rocket instance with the DB fairing attached
     let mut rocket = rocket::custom(figment)
@@ -225,8 +232,11 @@ pub fn fast_test_rocket() -> Rocket<Build> {
         "site_db" => site_db_config
     ];
 
-    // Merge DB config into Rocket's figment
-    let figment = rocket::Config::figment().merge(("databases", databases));
+    // Merge DB config into Rocket's figment, with exports stored apart from
+    // other tests'
+    let figment = rocket::Config::figment()
+        .merge(("databases", databases))
+        .merge(("storage_path", test_storage_path()));
 
     // Build the Rocket instance with minimal fairings (no initialization fairings
     // needed!) The golden database is already fully set up, so we only need
@@ -241,6 +251,14 @@ pub fn fast_test_rocket() -> Rocket<Build> {
     crate::mount_api_routes(rocket)
 }
 
+/// A fresh directory for a test rocket's blob storage.
+fn test_storage_path() -> String {
+    std::env::temp_dir()
+        .join(format!("neems_test_blobs_{}", uuid::Uuid::new_v4()))
+        .to_string_lossy()
+        .to_string()
+}
+
 /// Creates a synchronous in-memory SQLite database connection for unit tests.
 ///
 /// This function returns a `diesel::SqliteConnection` connected to an in-memory
@@ -0,0 +1,436 @@
+//! Declarative test fixtures.
+//!
+//! A [`FixtureBuilder`] describes roles, companies, sites, users and devices
+//! by name and inserts them through the ORM. The resulting [`Fixtures`] map
+//! those names back to whatever ids the database handed out, so tests can ask
+//! for "Test Site 1" rather than assuming it is site 1.
+//!
+//! [`FixtureBuilder::golden`] is the data set behind the golden database used
+//! by `fast_test_rocket()`; [`create_golden_db`] writes it to a new file and
+//! [`golden_fixtures`] reads the names and ids back out of the current one.
+
+use std::{collections::HashMap, path::Path};
+
+use diesel::{Connection, sqlite::SqliteConnection};
+
+use super::get_golden_db_path;
+use crate::{
+    models::{CompanyInput, DeviceInput, NewRole, UserInput},
+    orm::{
+        company::{get_all_companies, get_company_by_name, insert_company},
+        db::{run_pending_migrations, set_foreign_keys},
+        device::{get_all_devices, insert_device},
+        login::hash_password,
+        role::{get_role_by_name, insert_role},
+        site::{get_all_sites, insert_site},
+        user::{insert_user, list_all_users},
+        user_role::assign_user_role_by_name,
+    },
+};
+
+/// Ramp duration given to fixture sites, matching `neems-admin site add`.
+const SITE_RAMP_DURATION_SECONDS: i32 = 120;
+
+#[derive(Debug, Clone)]
+struct SiteFixture {
+    name: String,
+    company: String,
+    address: String,
+    latitude: f64,
+    longitude: f64,
+}
+
+#[derive(Debug, Clone)]
+struct UserFixture {
+    email: String,
+    company: String,
+    password: String,
+    roles: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct DeviceFixture {
+    name: String,
+    type_: String,
+    model: String,
+    serial: Option<String>,
+    site: String,
+}
+
+/// A set of fixtures to insert, referring to each other by name.
+///
+/// Everything is inserted in declaration order, roles and companies first,
+/// then sites, users and devices, so a builder produces the same ids every
+/// time it runs against a freshly migrated database. Roles and companies
+/// that already exist (such as those seeded by the migrations) are reused.
+///
+/// Names must be unique within their kind; a reference to a name that hasn't
+/// been declared panics when the fixtures are built.
+#[derive(Debug, Clone, Default)]
+pub struct FixtureBuilder {
+    roles: Vec<(String, String)>,
+    companies: Vec<String>,
+    sites: Vec<SiteFixture>,
+    users: Vec<UserFixture>,
+    devices: Vec<DeviceFixture>,
+}
+
+impl FixtureBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a role, unless one with this name already exists.
+    pub fn role(mut self, name: &str, description: &str) -> Self {
+        self.roles.push((name.to_string(), description.to_string()));
+        self
+    }
+
+    /// Add a company, unless one with this name already exists.
+    pub fn company(mut self, name: &str) -> Self {
+        self.companies.push(name.to_string());
+        self
+    }
+
+    /// Add a site belonging to the named company.
+    pub fn site(
+        mut self,
+        name: &str,
+        company: &str,
+        address: &str,
+        latitude: f64,
+        longitude: f64,
+    ) -> Self {
+        self.sites.push(SiteFixture {
+            name: name.to_string(),
+            company: company.to_string(),
+            address: address.to_string(),
+            latitude,
+            longitude,
+        });
+        self
+    }
+
+    /// Add a user of the named company, holding the named roles.
+    pub fn user(mut self, email: &str, company: &str, password: &str, roles: &[&str]) -> Self {
+        self.users.push(UserFixture {
+            email: email.to_string(),
+            company: company.to_string(),
+            password: password.to_string(),
+            roles: roles.iter().map(|r| r.to_string()).collect(),
+        });
+        self
+    }
+
+    /// Add a device installed at the named site, owned by the site's company.
+    pub fn device(
+        mut self,
+        name: &str,
+        type_: &str,
+        model: &str,
+        serial: Option<&str>,
+        site: &str,
+    ) -> Self {
+        self.devices.push(DeviceFixture {
+            name: name.to_string(),
+            type_: type_.to_string(),
+            model: model.to_string(),
+            serial: serial.map(str::to_string),
+            site: site.to_string(),
+        });
+        self
+    }
+
+    /// The golden database's data set.
+    ///
+    /// Test Company 1 and 2 each have one site and the general-purpose test
+    /// users; Device Test Company A and B have a site each with the devices
+    /// the device API tests use; Removable LLC is left empty for deletion
+    /// tests.
+    pub fn golden() -> Self {
+        let builder = Self::new()
+            .role("newtown-admin", "Administrator for Newtown")
+            .role("newtown-staff", "Staff member for Newtown")
+            .role("admin", "Administrator for Site Owner")
+            .role("staff", "User")
+            .company("Newtown Energy")
+            .company("Test Company 1")
+            .company("Test Company 2")
+            .company("Removable LLC")
+            .company("Device Test Company A")
+            .company("Device Test Company B")
+            .site("Test Site 1", "Test Company 1", "111 Test Ave", 35.0, -80.0)
+            .site("Test Site 2", "Test Company 2", "222 Test Blvd", 36.0, -81.0)
+            .site("Device API Site A", "Device Test Company A", "123 Device St", 40.0, -74.0)
+            .site("Device API Site B", "Device Test Company B", "456 Device Ave", 41.0, -75.0)
+            // Stands in for the operator account neems-admin records its
+            // changes against.
+            .user("admin@localhost", "Newtown Energy", "unused-password", &["newtown-staff"])
+            .user("superadmin@example.com", "Newtown Energy", "admin", &["newtown-admin"]);
+
+        let builder = [
+            ("user@testcompany.com", "Test Company 1", "admin"),
+            ("user@company1.com", "Test Company 1", "admin"),
+            ("user@company2.com", "Test Company 2", "admin"),
+            ("user@empty.com", "Test Company 1", "admin"),
+            ("admin@company1.com", "Test Company 1", "admin"),
+            ("admin@company2.com", "Test Company 2", "admin"),
+            ("staff@testcompany.com", "Test Company 1", "staff"),
+            ("newtownadmin@newtown.com", "Newtown Energy", "newtown-admin"),
+            ("newtownstaff@newtown.com", "Newtown Energy", "newtown-staff"),
+            ("admin@devicetesta.com", "Device Test Company A", "admin"),
+            ("admin@devicetestb.com", "Device Test Company B", "admin"),
+            ("testuser@example.com", "Test Company 1", "staff"),
+        ]
+        .into_iter()
+        .fold(builder, |builder, (email, company, role)| {
+            builder.user(email, company, "admin", &[role])
+        });
+
+        builder
+            .user("test_superadmin@example.com", "Newtown Energy", "adminpass", &["admin"])
+            .user("staff@example.com", "Test Company 1", "staffpass", &["staff"])
+            .user(
+                "newtown_superadmin@example.com",
+                "Newtown Energy",
+                "newtownpass",
+                &["newtown-admin"],
+            )
+            .user(
+                "newtown_staff@example.com",
+                "Newtown Energy",
+                "newtownstaffpass",
+                &["newtown-staff"],
+            )
+            .user("regular@example.com", "Test Company 1", "regularpass", &["staff"])
+            .user("admin_staff@example.com", "Test Company 1", "adminstaff", &["admin", "staff"])
+            .device("SEL-451", "Protection", "SEL-451", None, "Device API Site A")
+            .device("SEL-735", "Meter", "SEL-735", Some("TEST001"), "Device API Site A")
+            .device("SEL-451B", "Protection", "SEL-451", None, "Device API Site B")
+            .device("SEL-735A", "Meter", "SEL-735", Some("TEST002"), "Device API Site B")
+            .device("SEL-735B", "Meter", "SEL-735", Some("TEST003"), "Device API Site B")
+    }
+
+    /// Insert the fixtures and return the ids of everything in the database.
+    pub fn build(&self, conn: &mut SqliteConnection) -> Result<Fixtures, diesel::result::Error> {
+        conn.transaction(|conn| {
+            for (name, description) in &self.roles {
+                if get_role_by_name(conn, name)?.is_none() {
+                    insert_role(
+                        conn,
+                        NewRole {
+                            name: name.clone(),
+                            description: Some(description.clone()),
+                        },
+                    )?;
+                }
+            }
+
+            let mut fixtures = Fixtures::load(conn)?;
+            for name in &self.companies {
+                let existing = get_company_by_name(conn, &CompanyInput { name: name.clone() })?;
+                if existing.is_none() {
+                    let company = insert_company(conn, name.clone(), None)?;
+                    fixtures.companies.insert(company.name, company.id);
+                }
+            }
+
+            for site in &self.sites {
+                let created = insert_site(
+                    conn,
+                    site.name.clone(),
+                    site.address.clone(),
+                    site.latitude,
+                    site.longitude,
+                    fixtures.company_id(&site.company),
+                    SITE_RAMP_DURATION_SECONDS,
+                    None,
+                )?;
+                fixtures.site_companies.insert(created.name.clone(), created.company_id);
+                fixtures.sites.insert(created.name, created.id);
+            }
+
+            for user in &self.users {
+                let created = insert_user(
+                    conn,
+                    UserInput {
+                        email: user.email.clone(),
+                        password_hash: hash_password(&user.password),
+                        company_id: fixtures.company_id(&user.company),
+                        totp_secret: None,
+                    },
+                    None,
+                )?;
+                for role in &user.roles {
+                    assign_user_role_by_name(conn, created.id, role)?;
+                }
+                fixtures.users.insert(created.email, created.id);
+            }
+
+            for device in &self.devices {
+                let site_id = fixtures.site_id(&device.site);
+                let created = insert_device(
+                    conn,
+                    DeviceInput {
+                        name: Some(device.name.clone()),
+                        description: None,
+                        type_: device.type_.clone(),
+                        model: device.model.clone(),
+                        serial: device.serial.clone(),
+                        ip_address: None,
+                        install_date: None,
+                        company_id: fixtures.site_companies[&device.site],
+                        site_id,
+                    },
+                    None,
+                )?;
+                fixtures.devices.insert(created.name, created.id);
+            }
+
+            Ok(fixtures)
+        })
+    }
+}
+
+/// Ids of fixture entities, by company name, site name, user email and
+/// device name.
+#[derive(Debug, Clone, Default)]
+pub struct Fixtures {
+    companies: HashMap<String, i32>,
+    sites: HashMap<String, i32>,
+    site_companies: HashMap<String, i32>,
+    users: HashMap<String, i32>,
+    devices: HashMap<String, i32>,
+}
+
+fn lookup(map: &HashMap<String, i32>, kind: &str, name: &str) -> i32 {
+    *map.get(name)
+        .unwrap_or_else(|| panic!("no {} named '{}' in the fixtures", kind, name))
+}
+
+impl Fixtures {
+    /// Read the names and ids of every company, site, user and device in the
+    /// database.
+    pub fn load(conn: &mut SqliteConnection) -> Result<Self, diesel::result::Error> {
+        let sites = get_all_sites(conn)?;
+        Ok(Self {
+            companies: get_all_companies(conn)?.into_iter().map(|c| (c.name, c.id)).collect(),
+            site_companies: sites.iter().map(|s| (s.name.clone(), s.company_id)).collect(),
+            sites: sites.into_iter().map(|s| (s.name, s.id)).collect(),
+            users: list_all_users(conn)?.into_iter().map(|u| (u.email, u.id)).collect(),
+            devices: get_all_devices(conn)?.into_iter().map(|d| (d.name, d.id)).collect(),
+        })
+    }
+
+    /// The company's id; panics if there's no such company.
+    pub fn company_id(&self, name: &str) -> i32 {
+        lookup(&self.companies, "company", name)
+    }
+
+    /// The site's id; panics if there's no such site.
+    pub fn site_id(&self, name: &str) -> i32 {
+        lookup(&self.sites, "site", name)
+    }
+
+    /// Every site's id, in ascending order.
+    pub fn site_ids(&self) -> Vec<i32> {
+        let mut ids: Vec<i32> = self.sites.values().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// The user's id; panics if there's no such user.
+    pub fn user_id(&self, email: &str) -> i32 {
+        lookup(&self.users, "user", email)
+    }
+
+    /// The device's id; panics if there's no such device.
+    pub fn device_id(&self, name: &str) -> i32 {
+        lookup(&self.devices, "device", name)
+    }
+}
+
+/// Create a golden database at `path`: run the migrations and insert
+/// [`FixtureBuilder::golden`]. Fails if `path` already exists.
+pub fn create_golden_db(path: &Path) -> Result<Fixtures, Box<dyn std::error::Error>> {
+    if path.exists() {
+        return Err(format!("{} already exists", path.display()).into());
+    }
+    let mut conn = SqliteConnection::establish(&path.to_string_lossy())?;
+    set_foreign_keys(&mut conn);
+    run_pending_migrations(&mut conn);
+    Ok(FixtureBuilder::golden().build(&mut conn)?)
+}
+
+/// Names and ids in the golden database `fast_test_rocket()` copies.
+///
+/// # Panics
+/// Panics if the golden database doesn't exist or can't be read.
+pub fn golden_fixtures() -> Fixtures {
+    let path = get_golden_db_path();
+    let mut conn = SqliteConnection::establish(&path.to_string_lossy())
+        .unwrap_or_else(|e| panic!("Failed to open golden database {:?}: {}", path, e));
+    Fixtures::load(&mut conn)
+        .unwrap_or_else(|e| panic!("Failed to read golden database {:?}: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orm::{testing::setup_test_db, user_role::get_user_roles};
+
+    #[test]
+    fn test_builder_resolves_names_to_ids() {
+        let mut conn = setup_test_db();
+        let fixtures = FixtureBuilder::new()
+            .company("Newtown Energy")
+            .company("Acme Storage")
+            .site("Acme North", "Acme Storage", "1 North Rd", 40.0, -75.0)
+            .user("ops@acme.example", "Acme Storage", "secret", &["admin", "staff"])
+            .device("Meter 1", "Meter", "SEL-735", Some("M1"), "Acme North")
+            .build(&mut conn)
+            .expect("fixtures");
+
+        // The migrations seed Newtown Energy; it's reused, not duplicated.
+        assert_eq!(fixtures.company_id("Newtown Energy"), 1);
+        assert_eq!(get_all_companies(&mut conn).unwrap().len(), 2);
+
+        let acme = fixtures.company_id("Acme Storage");
+        let site = crate::orm::site::get_site_by_id(&mut conn, fixtures.site_id("Acme North"))
+            .unwrap()
+            .expect("site");
+        assert_eq!(site.company_id, acme);
+
+        let device = get_all_devices(&mut conn).unwrap().pop().expect("device");
+        assert_eq!(device.id, fixtures.device_id("Meter 1"));
+        assert_eq!((device.company_id, device.site_id), (acme, site.id));
+
+        let roles = get_user_roles(&mut conn, fixtures.user_id("ops@acme.example")).unwrap();
+        let mut names: Vec<_> = roles.into_iter().map(|r| r.name).collect();
+        names.sort();
+        assert_eq!(names, ["admin", "staff"]);
+    }
+
+    #[test]
+    fn test_builder_is_repeatable() {
+        let builder = FixtureBuilder::new()
+            .role("auditor", "Read-only auditor")
+            .company("First")
+            .company("Second")
+            .site("Second Site", "Second", "2 Second St", 1.0, 2.0)
+            .site("First Site", "First", "1 First St", 1.0, 2.0);
+
+        let first = builder.build(&mut setup_test_db()).expect("fixtures");
+        let second = builder.build(&mut setup_test_db()).expect("fixtures");
+        assert_eq!(first.site_id("First Site"), second.site_id("First Site"));
+        assert!(first.site_id("Second Site") < first.site_id("First Site"));
+    }
+
+    #[test]
+    #[should_panic(expected = "no company named 'Missing'")]
+    fn test_builder_panics_on_unknown_reference() {
+        let _ = FixtureBuilder::new()
+            .site("Orphan", "Missing", "Nowhere", 0.0, 0.0)
+            .build(&mut setup_test_db());
+    }
+}
@@ -42,6 +42,32 @@ pub fn insert_user(
     Ok(user)
 }
 
+/// Inserts a service account: a user named `name` with no password, which
+/// authenticates with API keys (see [`crate::orm::api_key`])
+pub fn insert_service_account(
+    conn: &mut SqliteConnection,
+    name: &str,
+    company_id: i32,
+    acting_user_id: Option<i32>,
+) -> Result<User, diesel::result::Error> {
+    use crate::schema::users;
+
+    conn.transaction(|conn| {
+        let new_user = UserInput {
+            email: name.to_string(),
+            password_hash: String::new(),
+            company_id,
+            totp_secret: None,
+        };
+        let user = insert_user(conn, new_user, acting_user_id)?;
+
+        diesel::update(users::table.filter(users::id.eq(user.id)))
+            .set(users::service_account.eq(true))
+            .execute(conn)?;
+        users::table.filter(users::id.eq(user.id)).first::<User>(conn)
+    })
+}
+
 /// Get a user with computed timestamps from activity log
 pub fn get_user_with_timestamps(
     conn: &mut SqliteConnection,
@@ -65,6 +91,8 @@ pub fn get_user_with_timestamps(
         password_hash: user.password_hash,
         company_id: user.company_id,
         totp_secret: user.totp_secret,
+        must_change_password: user.must_change_password,
+        service_account: user.service_account,
         created_at,
         updated_at,
     }))
@@ -93,6 +121,8 @@ pub fn get_user_with_roles_and_timestamps(
         password_hash: user_with_roles.password_hash,
         company_id: user_with_roles.company_id,
         totp_secret: user_with_roles.totp_secret,
+        must_change_password: user_with_roles.must_change_password,
+        service_account: user_with_roles.service_account,
         created_at,
         updated_at,
         roles: user_with_roles.roles,
@@ -164,7 +194,7 @@ pub fn get_user_by_email(
 /// * `new_email` - Optional new email address
 /// * `new_password_hash` - Optional new password hash
 /// * `new_company_id` - Optional new company ID
-/// * `new_totp_secret` - Optional new TOTP secret
+/// * `new_totp_secret` - Optional new TOTP secret; `Some(None)` clears it
 ///
 /// # Returns
 /// * `Ok(User)` - Updated user object
@@ -175,7 +205,7 @@ pub fn update_user(
     new_email: Option<String>,
     new_password_hash: Option<String>,
     new_company_id: Option<i32>,
-    new_totp_secret: Option<String>,
+    new_totp_secret: Option<Option<String>>,
     acting_user_id: Option<i32>,
 ) -> Result<User, diesel::result::Error> {
     use crate::schema::users::dsl::*;
@@ -217,6 +247,77 @@ pub fn update_user(
     Ok(user)
 }
 
+/// Sets or clears a user's `must_change_password` flag.
+///
+/// While set, the user's sessions can only change the password (see
+/// [`crate::session_guards::PasswordChangeUser`]).
+///
+/// # Returns
+/// * `Ok(User)` - Updated user object
+/// * `Err(diesel::result::Error::NotFound)` - No such user
+pub fn set_must_change_password(
+    conn: &mut SqliteConnection,
+    user_id: i32,
+    value: bool,
+    acting_user_id: Option<i32>,
+) -> Result<User, diesel::result::Error> {
+    use crate::schema::users::dsl::*;
+
+    let updated = diesel::update(users.filter(id.eq(user_id)))
+        .set(must_change_password.eq(value))
+        .execute(conn)?;
+    if updated == 0 {
+        return Err(diesel::result::Error::NotFound);
+    }
+
+    if let Some(actor_id) = acting_user_id {
+        use crate::orm::entity_activity::update_latest_activity_user;
+        let _ = update_latest_activity_user(conn, "users", user_id, "update", actor_id);
+    }
+
+    users.filter(id.eq(user_id)).first::<User>(conn)
+}
+
+/// Replaces a user's password hash, clearing `must_change_password` and
+/// revoking all of the user's sessions except `keep_session`.
+///
+/// Revoking the other sessions matters when rotation was forced: a session
+/// held by whoever compromised the account would otherwise regain full
+/// access once the flag is cleared.
+pub fn change_password(
+    conn: &mut SqliteConnection,
+    user_id: i32,
+    new_password_hash: String,
+    keep_session: Option<String>,
+) -> Result<User, diesel::result::Error> {
+    use crate::schema::{sessions, users};
+
+    conn.transaction(|conn| {
+        let updated = diesel::update(users::table.filter(users::id.eq(user_id)))
+            .set((
+                users::password_hash.eq(new_password_hash),
+                users::must_change_password.eq(false),
+            ))
+            .execute(conn)?;
+        if updated == 0 {
+            return Err(diesel::result::Error::NotFound);
+        }
+
+        let user_sessions = sessions::table.filter(sessions::user_id.eq(user_id));
+        match keep_session {
+            Some(keep) => diesel::update(user_sessions.filter(sessions::id.ne(keep)))
+                .set(sessions::revoked.eq(true))
+                .execute(conn)?,
+            None => diesel::update(user_sessions).set(sessions::revoked.eq(true)).execute(conn)?,
+        };
+
+        use crate::orm::entity_activity::update_latest_activity_user;
+        let _ = update_latest_activity_user(conn, "users", user_id, "update", user_id);
+
+        users::table.filter(users::id.eq(user_id)).first::<User>(conn)
+    })
+}
+
 /// Deletes a user by ID.
 ///
 /// This function permanently removes a user from the database. This is a hard
@@ -390,6 +491,8 @@ pub fn get_user_with_roles(
         password_hash: user.password_hash,
         company_id: user.company_id,
         totp_secret: user.totp_secret,
+        must_change_password: user.must_change_password,
+        service_account: user.service_account,
         roles: user_roles,
     }))
 }
@@ -421,6 +524,8 @@ pub fn list_all_users_with_roles(
             password_hash: user.password_hash,
             company_id: user.company_id,
             totp_secret: user.totp_secret,
+            must_change_password: user.must_change_password,
+            service_account: user.service_account,
             roles: user_roles,
         });
     }
@@ -461,6 +566,8 @@ pub fn get_users_by_company_with_roles(
             password_hash: user.password_hash,
             company_id: user.company_id,
             totp_secret: user.totp_secret,
+            must_change_password: user.must_change_password,
+            service_account: user.service_account,
             roles: user_roles,
         });
     }
@@ -0,0 +1,165 @@
+//! Splitting a total power setpoint across a device group.
+//!
+//! Each member gets a share by weight: the same for every member with
+//! `equal`, and with `soc_balancing` the energy it has to give, its SoC when
+//! discharging and the room it has left (100 - SoC) when charging, so the
+//! members' SoCs converge. A member without a known SoC is weighted as the
+//! mean of those with one (50% if none has one).
+//!
+//! Shares are capped at each member's rated power. What a capped member can't
+//! take is shared among the others by the same weights until everything is
+//! allocated or every member is at its rating, in which case less than the
+//! setpoint is allocated.
+
+use crate::models::{AllocationStrategy, MemberSetpoint};
+
+/// A group member as the allocation sees it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AllocationMember {
+    pub device_id: i32,
+    pub rated_power_kw: Option<f64>,
+    pub soc_percent: Option<f64>,
+}
+
+/// Split `power_kw` (positive to discharge, negative to charge) across
+/// `members`, returning each member's setpoint in the same order.
+pub fn allocate(
+    members: &[AllocationMember],
+    power_kw: f64,
+    strategy: AllocationStrategy,
+) -> Vec<MemberSetpoint> {
+    let weights = weights(members, power_kw, strategy);
+    let mut shares = vec![0.0; members.len()];
+    let mut open: Vec<usize> = (0..members.len()).filter(|&i| weights[i] > 0.0).collect();
+    let mut remaining = power_kw.abs();
+
+    while remaining > 1e-9 && !open.is_empty() {
+        let total_weight: f64 = open.iter().map(|&i| weights[i]).sum();
+        let capped: Vec<usize> = open
+            .iter()
+            .copied()
+            .filter(|&i| {
+                let headroom = members[i].rated_power_kw.map_or(f64::INFINITY, |r| r - shares[i]);
+                remaining * weights[i] / total_weight >= headroom
+            })
+            .collect();
+        if capped.is_empty() {
+            for &i in &open {
+                shares[i] += remaining * weights[i] / total_weight;
+            }
+            break;
+        }
+        // Fill the members that hit their rating and share the rest again
+        for &i in &capped {
+            let rated = members[i].rated_power_kw.unwrap_or_default();
+            remaining -= rated - shares[i];
+            shares[i] = rated;
+        }
+        open.retain(|i| !capped.contains(i));
+    }
+
+    members
+        .iter()
+        .zip(shares)
+        .map(|(member, share)| MemberSetpoint {
+            device_id: member.device_id,
+            power_kw: if power_kw < 0.0 && share > 0.0 {
+                -share
+            } else {
+                share
+            },
+        })
+        .collect()
+}
+
+fn weights(members: &[AllocationMember], power_kw: f64, strategy: AllocationStrategy) -> Vec<f64> {
+    match strategy {
+        AllocationStrategy::Equal => vec![1.0; members.len()],
+        AllocationStrategy::SocBalancing => {
+            let known: Vec<f64> = members
+                .iter()
+                .filter_map(|m| m.soc_percent)
+                .map(|soc| soc.clamp(0.0, 100.0))
+                .collect();
+            let fallback = if known.is_empty() {
+                50.0
+            } else {
+                known.iter().sum::<f64>() / known.len() as f64
+            };
+            members
+                .iter()
+                .map(|m| {
+                    let soc = m.soc_percent.map_or(fallback, |soc| soc.clamp(0.0, 100.0));
+                    if power_kw >= 0.0 { soc } else { 100.0 - soc }
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(device_id: i32, rated_power_kw: Option<f64>, soc: Option<f64>) -> AllocationMember {
+        AllocationMember {
+            device_id,
+            rated_power_kw,
+            soc_percent: soc,
+        }
+    }
+
+    fn powers(setpoints: &[MemberSetpoint]) -> Vec<f64> {
+        setpoints.iter().map(|s| (s.power_kw * 1000.0).round() / 1000.0).collect()
+    }
+
+    #[test]
+    fn test_equal_split() {
+        let members = [member(1, None, None), member(2, None, None), member(3, None, None)];
+        let setpoints = allocate(&members, 300.0, AllocationStrategy::Equal);
+        assert_eq!(powers(&setpoints), vec![100.0, 100.0, 100.0]);
+        assert_eq!(setpoints[2].device_id, 3);
+
+        let setpoints = allocate(&members, -90.0, AllocationStrategy::Equal);
+        assert_eq!(powers(&setpoints), vec![-30.0, -30.0, -30.0]);
+    }
+
+    #[test]
+    fn test_soc_balancing_favours_fuller_members_when_discharging() {
+        let members = [member(1, None, Some(80.0)), member(2, None, Some(20.0))];
+        let setpoints = allocate(&members, 100.0, AllocationStrategy::SocBalancing);
+        assert_eq!(powers(&setpoints), vec![80.0, 20.0]);
+
+        // And emptier ones when charging
+        let setpoints = allocate(&members, -100.0, AllocationStrategy::SocBalancing);
+        assert_eq!(powers(&setpoints), vec![-20.0, -80.0]);
+    }
+
+    #[test]
+    fn test_soc_balancing_missing_soc_uses_mean() {
+        let members =
+            [member(1, None, Some(60.0)), member(2, None, Some(20.0)), member(3, None, None)];
+        let setpoints = allocate(&members, 120.0, AllocationStrategy::SocBalancing);
+        assert_eq!(powers(&setpoints), vec![60.0, 20.0, 40.0]);
+    }
+
+    #[test]
+    fn test_rated_power_caps_and_redistributes() {
+        let members = [member(1, Some(50.0), None), member(2, None, None), member(3, None, None)];
+        let setpoints = allocate(&members, 300.0, AllocationStrategy::Equal);
+        assert_eq!(powers(&setpoints), vec![50.0, 125.0, 125.0]);
+
+        // More than the group can take
+        let members = [member(1, Some(50.0), None), member(2, Some(100.0), None)];
+        let setpoints = allocate(&members, -400.0, AllocationStrategy::Equal);
+        assert_eq!(powers(&setpoints), vec![-50.0, -100.0]);
+    }
+
+    #[test]
+    fn test_empty_members_give_nothing() {
+        let members = [member(1, None, Some(0.0)), member(2, None, Some(0.0))];
+        let setpoints = allocate(&members, 100.0, AllocationStrategy::SocBalancing);
+        assert_eq!(powers(&setpoints), vec![0.0, 0.0]);
+        assert!(allocate(&[], 100.0, AllocationStrategy::Equal).is_empty());
+    }
+}
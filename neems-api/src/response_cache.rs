@@ -0,0 +1,257 @@
+//! In-process cache for hot read endpoints.
+//!
+//! Dashboards poll `/api/1/Sites`, `/api/1/Companies` and `/api/1/$metadata`
+//! far more often than the data behind them changes, and every poll competes
+//! for SQLite. These endpoints keep their serialized response bodies here,
+//! keyed by whatever else the body depends on (the caller's scope, query
+//! options).
+//!
+//! Entries live for `response_cache_ttl` seconds (`ROCKET_RESPONSE_CACHE_TTL`,
+//! default [`DEFAULT_TTL_SECONDS`]; 0 disables caching). Endpoints that write
+//! the underlying tables invalidate the affected endpoints at once, so the TTL
+//! only bounds how stale a response can be after writes made outside the API,
+//! e.g. with neems-admin.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use rocket::figment::Figment;
+use serde::Serialize;
+use ts_rs::TS;
+
+pub const DEFAULT_TTL_SECONDS: u64 = 30;
+
+/// Most entries kept per endpoint; expired entries are dropped first, and if
+/// that isn't enough the endpoint's entries are cleared.
+const MAX_ENTRIES_PER_ENDPOINT: usize = 256;
+
+/// The endpoints whose responses are cached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CachedEndpoint {
+    Sites,
+    Companies,
+    Metadata,
+}
+
+impl CachedEndpoint {
+    pub const ALL: [CachedEndpoint; 3] =
+        [CachedEndpoint::Sites, CachedEndpoint::Companies, CachedEndpoint::Metadata];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CachedEndpoint::Sites => "Sites",
+            CachedEndpoint::Companies => "Companies",
+            CachedEndpoint::Metadata => "$metadata",
+        }
+    }
+}
+
+/// Hit counts for one cached endpoint, since startup.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, TS)]
+#[ts(export)]
+pub struct CacheStats {
+    pub endpoint: String,
+    #[ts(type = "number")]
+    pub hits: u64,
+    #[ts(type = "number")]
+    pub misses: u64,
+    #[ts(type = "number")]
+    pub invalidations: u64,
+    /// Entries currently held, including expired ones not yet dropped
+    pub entries: usize,
+}
+
+struct Entry {
+    body: String,
+    stored_at: Instant,
+}
+
+#[derive(Default)]
+struct Partition {
+    entries: HashMap<String, Entry>,
+    /// Bumped on every invalidation, so a response computed from data read
+    /// before the invalidation isn't stored after it
+    generation: u64,
+    hits: u64,
+    misses: u64,
+    invalidations: u64,
+}
+
+/// Cached response bodies, managed as Rocket state.
+pub struct ResponseCache {
+    ttl: Duration,
+    partitions: Mutex<HashMap<CachedEndpoint, Partition>>,
+}
+
+impl ResponseCache {
+    pub fn new(ttl: Duration) -> Self {
+        ResponseCache {
+            ttl,
+            partitions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// A cache with the TTL configured as `response_cache_ttl`.
+    pub fn from_figment(figment: &Figment) -> Self {
+        let seconds = figment
+            .extract_inner::<u64>("response_cache_ttl")
+            .unwrap_or(DEFAULT_TTL_SECONDS);
+        ResponseCache::new(Duration::from_secs(seconds))
+    }
+
+    /// The cached body for `key`, or else the body `load` produces, which is
+    /// cached if it succeeds. `load` only runs on a miss.
+    pub async fn get_or_load<E>(
+        &self,
+        endpoint: CachedEndpoint,
+        key: String,
+        load: impl Future<Output = Result<String, E>>,
+    ) -> Result<String, E> {
+        let generation = {
+            let mut partitions = self.partitions.lock().unwrap();
+            let partition = partitions.entry(endpoint).or_default();
+            match partition.entries.get(&key) {
+                Some(entry) if entry.stored_at.elapsed() < self.ttl => {
+                    partition.hits += 1;
+                    return Ok(entry.body.clone());
+                }
+                _ => {
+                    partition.misses += 1;
+                    partition.generation
+                }
+            }
+        };
+
+        let body = load.await?;
+
+        if !self.ttl.is_zero() {
+            let mut partitions = self.partitions.lock().unwrap();
+            let partition = partitions.entry(endpoint).or_default();
+            if partition.generation == generation {
+                if partition.entries.len() >= MAX_ENTRIES_PER_ENDPOINT {
+                    let ttl = self.ttl;
+                    partition.entries.retain(|_, entry| entry.stored_at.elapsed() < ttl);
+                    if partition.entries.len() >= MAX_ENTRIES_PER_ENDPOINT {
+                        partition.entries.clear();
+                    }
+                }
+                let entry = Entry {
+                    body: body.clone(),
+                    stored_at: Instant::now(),
+                };
+                partition.entries.insert(key, entry);
+            }
+        }
+
+        Ok(body)
+    }
+
+    /// Drops every cached response of the given endpoints, after a write to
+    /// the data behind them.
+    pub fn invalidate(&self, endpoints: &[CachedEndpoint]) {
+        let mut partitions = self.partitions.lock().unwrap();
+        for endpoint in endpoints {
+            let partition = partitions.entry(*endpoint).or_default();
+            partition.entries.clear();
+            partition.generation += 1;
+            partition.invalidations += 1;
+        }
+    }
+
+    /// Hit counts for each cached endpoint.
+    pub fn stats(&self) -> Vec<CacheStats> {
+        let partitions = self.partitions.lock().unwrap();
+        CachedEndpoint::ALL
+            .iter()
+            .map(|endpoint| {
+                let partition = partitions.get(endpoint);
+                CacheStats {
+                    endpoint: endpoint.name().to_string(),
+                    hits: partition.map_or(0, |p| p.hits),
+                    misses: partition.map_or(0, |p| p.misses),
+                    invalidations: partition.map_or(0, |p| p.invalidations),
+                    entries: partition.map_or(0, |p| p.entries.len()),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+
+    async fn load(cache: &ResponseCache, key: &str, body: &str) -> String {
+        let body = body.to_string();
+        cache
+            .get_or_load(CachedEndpoint::Sites, key.to_string(), async move {
+                Ok::<_, Infallible>(body)
+            })
+            .await
+            .unwrap()
+    }
+
+    fn sites_stats(cache: &ResponseCache) -> CacheStats {
+        cache.stats().into_iter().find(|s| s.endpoint == "Sites").unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_hits_misses_and_invalidation() {
+        let cache = ResponseCache::new(Duration::from_secs(60));
+        assert_eq!(load(&cache, "all", "first").await, "first");
+        assert_eq!(load(&cache, "all", "second").await, "first");
+        assert_eq!(load(&cache, "company:2", "other").await, "other");
+
+        let stats = sites_stats(&cache);
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 2, 2));
+
+        cache.invalidate(&[CachedEndpoint::Sites]);
+        assert_eq!(load(&cache, "all", "third").await, "third");
+        let stats = sites_stats(&cache);
+        assert_eq!((stats.invalidations, stats.entries), (1, 1));
+
+        // Other endpoints are untouched
+        let companies = cache.stats().into_iter().find(|s| s.endpoint == "Companies").unwrap();
+        assert_eq!(
+            companies,
+            CacheStats {
+                endpoint: "Companies".to_string(),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ttl() {
+        let cache = ResponseCache::new(Duration::from_millis(20));
+        load(&cache, "all", "first").await;
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(load(&cache, "all", "second").await, "second");
+
+        let disabled = ResponseCache::new(Duration::ZERO);
+        load(&disabled, "all", "first").await;
+        assert_eq!(load(&disabled, "all", "second").await, "second");
+        assert_eq!(sites_stats(&disabled).entries, 0);
+    }
+
+    #[tokio::test]
+    async fn test_invalidation_during_load_isnt_cached() {
+        let cache = ResponseCache::new(Duration::from_secs(60));
+        let body = cache
+            .get_or_load(CachedEndpoint::Sites, "all".to_string(), async {
+                // A write lands while the stale body is being built
+                cache.invalidate(&[CachedEndpoint::Sites]);
+                Ok::<_, Infallible>("stale".to_string())
+            })
+            .await
+            .unwrap();
+        assert_eq!(body, "stale");
+        assert_eq!(load(&cache, "all", "fresh").await, "fresh");
+    }
+}
@@ -0,0 +1,367 @@
+//! Conflict checks for site schedules.
+//!
+//! Before a schedule or override goes live, operators want to know whether
+//! the plan contradicts itself. The checks here look at one day's commands
+//! at a time:
+//!
+//! - **Simultaneous charge and discharge**: a charging and a discharging
+//!   command whose windows overlap, for the same device or with one of them for
+//!   the whole site
+//! - **SoC-infeasible plans**: site-wide discharges with a duration that need
+//!   more energy than the battery can hold above its SoC floor, alone or
+//!   together with everything the day's charging could add
+//!
+//! A command runs for its duration or until the next command for the same
+//! device (or the whole site) starts, and the day's last command until
+//! midnight, as the schedule feed shows them. Overlapping overrides need the
+//! site's application rules, so `api::scheduler` checks those.
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::models::{CommandType, ScheduleCommandDto, Site};
+
+const SECONDS_PER_DAY: i32 = 24 * 60 * 60;
+
+/// What kind of contradiction a conflict is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictKind {
+    SimultaneousChargeDischarge,
+    SocInfeasible,
+    OverlappingOverrides,
+}
+
+/// A contradiction found in a site's plan for a day.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ScheduleConflict {
+    #[ts(type = "string")]
+    pub date: NaiveDate,
+    pub kind: ConflictKind,
+    /// Schedules involved
+    pub library_item_ids: Vec<i32>,
+    /// Commands involved, if the conflict is between commands
+    pub command_ids: Vec<i32>,
+    pub message: String,
+}
+
+/// The battery limits a plan must respect, from the site's settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatteryLimits {
+    pub capacity_kwh: f64,
+    /// Power of commands that don't set `power_kw`
+    pub charge_kw: f64,
+    pub discharge_kw: f64,
+    pub trickle_charge_kw: f64,
+    pub soc_floor_percent: f64,
+}
+
+impl BatteryLimits {
+    /// The site's limits, if its capacity and power are set.
+    pub fn from_site(site: &Site) -> Option<Self> {
+        let capacity_kwh = site.capacity_kwh?;
+        let power_kw = site.power_kw?;
+        Some(BatteryLimits {
+            capacity_kwh,
+            charge_kw: power_kw * site.charge_rate_percent / 100.0,
+            discharge_kw: power_kw * site.discharge_rate_percent / 100.0,
+            trickle_charge_kw: site.trickle_charge_power_kw.unwrap_or(0.0),
+            soc_floor_percent: site.rebound_protection_soc_floor_percent,
+        })
+    }
+
+    /// Energy that can be discharged from full before reaching the floor.
+    fn usable_kwh(&self) -> f64 {
+        self.capacity_kwh * (100.0 - self.soc_floor_percent) / 100.0
+    }
+
+    /// Power `command` runs at: its own, else the site's for its type.
+    pub(crate) fn power_kw(&self, command: &ScheduleCommandDto) -> f64 {
+        command.power_kw.unwrap_or(match command.command_type {
+            CommandType::Charge => self.charge_kw,
+            CommandType::Discharge => self.discharge_kw,
+            CommandType::TrickleCharge => self.trickle_charge_kw,
+        })
+    }
+}
+
+/// A conflict within one schedule's commands, before it's placed on a date.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandConflict {
+    pub kind: ConflictKind,
+    pub command_ids: Vec<i32>,
+    pub message: String,
+}
+
+impl CommandConflict {
+    /// This conflict on `date`, in the schedule `library_item_id`.
+    pub fn on(&self, date: NaiveDate, library_item_id: i32) -> ScheduleConflict {
+        ScheduleConflict {
+            date,
+            kind: self.kind.clone(),
+            library_item_ids: vec![library_item_id],
+            command_ids: self.command_ids.clone(),
+            message: self.message.clone(),
+        }
+    }
+}
+
+/// When a command runs, in seconds from midnight.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Window {
+    start: i32,
+    end: i32,
+}
+
+impl Window {
+    fn overlaps(&self, other: &Window) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+
+    fn hours(&self) -> f64 {
+        (self.end - self.start) as f64 / 3600.0
+    }
+}
+
+fn is_charging(command_type: &CommandType) -> bool {
+    matches!(command_type, CommandType::Charge | CommandType::TrickleCharge)
+}
+
+fn format_offset(seconds: i32) -> String {
+    format!("{:02}:{:02}", seconds / 3600, seconds % 3600 / 60)
+}
+
+/// Each command's window: its duration, cut short by the next command for
+/// the same device or the whole site, else until midnight.
+fn command_windows(commands: &[ScheduleCommandDto]) -> Vec<Window> {
+    commands
+        .iter()
+        .map(|command| {
+            let start = command.execution_offset_seconds;
+            let next_start = commands
+                .iter()
+                .filter(|c| c.device_id == command.device_id && c.execution_offset_seconds > start)
+                .map(|c| c.execution_offset_seconds)
+                .min()
+                .unwrap_or(SECONDS_PER_DAY);
+            let end = match command.duration_seconds {
+                Some(duration) => (start + duration).min(next_start),
+                None => next_start,
+            };
+            Window { start, end }
+        })
+        .collect()
+}
+
+/// Pairs of commands that charge and discharge at the same time.
+pub fn simultaneous_charge_discharge(commands: &[ScheduleCommandDto]) -> Vec<CommandConflict> {
+    let windows = command_windows(commands);
+    let mut conflicts = Vec::new();
+
+    for (i, a) in commands.iter().enumerate() {
+        for (j, b) in commands.iter().enumerate().skip(i + 1) {
+            let same_target =
+                a.device_id.is_none() || b.device_id.is_none() || a.device_id == b.device_id;
+            if !same_target
+                || is_charging(&a.command_type) == is_charging(&b.command_type)
+                || !windows[i].overlaps(&windows[j])
+            {
+                continue;
+            }
+            let (charge, discharge) = if is_charging(&a.command_type) {
+                (i, j)
+            } else {
+                (j, i)
+            };
+            conflicts.push(CommandConflict {
+                kind: ConflictKind::SimultaneousChargeDischarge,
+                command_ids: vec![commands[charge].id, commands[discharge].id],
+                message: format!(
+                    "The charge at {} overlaps the discharge at {}",
+                    format_offset(windows[charge].start),
+                    format_offset(windows[discharge].start)
+                ),
+            });
+        }
+    }
+
+    conflicts
+}
+
+/// Site-wide discharges with a duration that the battery can't deliver.
+pub fn soc_infeasible(
+    commands: &[ScheduleCommandDto],
+    limits: &BatteryLimits,
+) -> Vec<CommandConflict> {
+    let windows = command_windows(commands);
+    let usable_kwh = limits.usable_kwh();
+    let mut conflicts = Vec::new();
+    let mut timed_discharges = Vec::new();
+    let mut discharge_kwh = 0.0;
+    let mut charge_kwh = 0.0;
+
+    for (command, window) in commands.iter().zip(&windows) {
+        if command.device_id.is_some() {
+            continue;
+        }
+        let energy_kwh = limits.power_kw(command) * window.hours();
+        if is_charging(&command.command_type) {
+            charge_kwh += energy_kwh;
+            continue;
+        }
+        // A discharge without a duration stops at the floor, so only timed
+        // ones promise an amount of energy
+        if command.duration_seconds.is_none() {
+            continue;
+        }
+        if energy_kwh > usable_kwh {
+            conflicts.push(CommandConflict {
+                kind: ConflictKind::SocInfeasible,
+                command_ids: vec![command.id],
+                message: format!(
+                    "The discharge at {} needs {:.1} kWh, more than the {:.1} kWh usable above \
+                     the {}% SoC floor",
+                    format_offset(window.start),
+                    energy_kwh,
+                    usable_kwh,
+                    limits.soc_floor_percent
+                ),
+            });
+        }
+        timed_discharges.push(command.id);
+        discharge_kwh += energy_kwh;
+    }
+
+    // Even starting full, the day can't discharge more than the battery holds
+    // plus what its charging adds
+    if conflicts.is_empty() && discharge_kwh > usable_kwh + charge_kwh {
+        conflicts.push(CommandConflict {
+            kind: ConflictKind::SocInfeasible,
+            command_ids: timed_discharges,
+            message: format!(
+                "The day's discharges need {:.1} kWh, more than the {:.1} kWh usable plus \
+                 {:.1} kWh its charging can add",
+                discharge_kwh, usable_kwh, charge_kwh
+            ),
+        });
+    }
+
+    conflicts
+}
+
+/// All the conflicts among a schedule's commands.
+pub fn check_commands(
+    commands: &[ScheduleCommandDto],
+    limits: Option<&BatteryLimits>,
+) -> Vec<CommandConflict> {
+    let mut conflicts = simultaneous_charge_discharge(commands);
+    if let Some(limits) = limits {
+        conflicts.extend(soc_infeasible(commands, limits));
+    }
+    conflicts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(
+        id: i32,
+        hour: i32,
+        command_type: CommandType,
+        duration_hours: Option<i32>,
+        device_id: Option<i32>,
+    ) -> ScheduleCommandDto {
+        ScheduleCommandDto {
+            id,
+            execution_offset_seconds: hour * 3600,
+            command_type,
+            duration_seconds: duration_hours.map(|h| h * 3600),
+            target_soc_percent: None,
+            power_kw: None,
+            device_id,
+            bindings: None,
+        }
+    }
+
+    fn limits() -> BatteryLimits {
+        BatteryLimits {
+            capacity_kwh: 1000.0,
+            charge_kw: 250.0,
+            discharge_kw: 250.0,
+            trickle_charge_kw: 10.0,
+            soc_floor_percent: 20.0,
+        }
+    }
+
+    #[test]
+    fn test_sequential_commands_do_not_conflict() {
+        let commands = [
+            command(1, 6, CommandType::Charge, None, None),
+            command(2, 17, CommandType::Discharge, None, None),
+        ];
+        assert!(check_commands(&commands, Some(&limits())).is_empty());
+    }
+
+    #[test]
+    fn test_device_and_site_commands_overlapping() {
+        let commands = [
+            command(1, 6, CommandType::Charge, Some(4), None),
+            command(2, 8, CommandType::Discharge, Some(1), Some(7)),
+            // Another device may discharge while this one charges
+            command(3, 8, CommandType::Charge, None, Some(8)),
+            command(4, 9, CommandType::Discharge, None, Some(9)),
+        ];
+        let conflicts = simultaneous_charge_discharge(&commands);
+
+        let pairs: Vec<Vec<i32>> = conflicts.iter().map(|c| c.command_ids.clone()).collect();
+        assert_eq!(pairs, vec![vec![1, 2], vec![1, 4]]);
+        assert_eq!(conflicts[0].message, "The charge at 06:00 overlaps the discharge at 08:00");
+    }
+
+    #[test]
+    fn test_same_time_opposite_commands_conflict() {
+        let commands = [
+            command(1, 12, CommandType::Discharge, None, Some(7)),
+            command(2, 12, CommandType::TrickleCharge, None, Some(7)),
+        ];
+        let conflicts = simultaneous_charge_discharge(&commands);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].command_ids, vec![2, 1]);
+    }
+
+    #[test]
+    fn test_discharge_longer_than_usable_capacity() {
+        // 250 kW for 4 hours is 1000 kWh; 800 kWh are above the floor
+        let commands = [command(1, 17, CommandType::Discharge, Some(4), None)];
+        let conflicts = soc_infeasible(&commands, &limits());
+
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].kind, ConflictKind::SocInfeasible);
+        assert_eq!(conflicts[0].command_ids, vec![1]);
+    }
+
+    #[test]
+    fn test_day_discharges_more_than_charging_adds() {
+        let mut commands = vec![
+            command(1, 7, CommandType::Discharge, Some(3), None),
+            command(2, 11, CommandType::Charge, Some(1), None),
+            command(3, 17, CommandType::Discharge, Some(3), None),
+        ];
+        // 1500 kWh out against 800 usable plus 250 charged
+        let conflicts = soc_infeasible(&commands, &limits());
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].command_ids, vec![1, 3]);
+
+        // Charging for 3 hours instead adds enough
+        commands[1].duration_seconds = Some(3 * 3600);
+        assert!(soc_infeasible(&commands, &limits()).is_empty());
+
+        // Open-ended discharges stop at the floor
+        let open = [command(1, 12, CommandType::Discharge, None, None)];
+        assert!(soc_infeasible(&open, &limits()).is_empty());
+    }
+}
@@ -0,0 +1,429 @@
+//! Schedule command import from CSV and Excel files.
+//!
+//! Customers draft dispatch plans in spreadsheets, so a device's commands can
+//! be uploaded as a CSV or XLSX file (the first worksheet) instead of built
+//! up one at a time. The file's first non-empty row is a header naming the
+//! `time`, `action`, `type` and `power` columns, in any order and case;
+//! other columns, like notes, are ignored:
+//!
+//! ```text
+//! time,action,type,power
+//! 06:00,start,charge,250
+//! 10:30,stop,,
+//! 17:00,start,discharge,500
+//! 21:00,stop,,
+//! ```
+//!
+//! - `time` is the time of day, `HH:MM` or `HH:MM:SS` (or an Excel time cell),
+//!   in ascending order
+//! - `action` is `start`, which begins a command, or `stop`, which ends the
+//!   running one; a command that isn't stopped runs until the next starts
+//! - `type` is `charge`, `discharge` or `trickle_charge`; a `stop` may leave it
+//!   blank
+//! - `power` is the command's power in kW, required on `start` rows
+//!
+//! Every row is checked before anything is saved, and all the problems are
+//! reported with their line (the spreadsheet row for XLSX files), so a plan
+//! can be fixed in one pass.
+
+use std::{io::Cursor, str::FromStr};
+
+use calamine::{Data, Reader, Xlsx};
+use chrono::{NaiveTime, Timelike};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::models::{CommandType, CreateCommandRequest};
+
+/// Columns the header row must name.
+const COLUMNS: [&str; 4] = ["time", "action", "type", "power"];
+
+/// XLSX files are zip archives.
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// A problem with one line of an imported file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ImportLineError {
+    /// 1-based line of a CSV file, or row of a worksheet
+    pub line: usize,
+    pub error: String,
+}
+
+/// Why a file couldn't be imported.
+#[derive(Debug, PartialEq)]
+pub enum ImportError {
+    /// Not a file we can read at all
+    Unreadable(String),
+    /// Readable, but some lines are invalid
+    Lines(Vec<ImportLineError>),
+}
+
+/// A cell's value, as the spreadsheet typed it.
+#[derive(Debug, Clone, PartialEq)]
+enum Cell {
+    Text(String),
+    Number(f64),
+    /// An Excel date/time, as days since its epoch
+    DateTime(f64),
+}
+
+impl Cell {
+    fn is_blank(&self) -> bool {
+        matches!(self, Cell::Text(text) if text.is_empty())
+    }
+
+    fn text(&self) -> String {
+        match self {
+            Cell::Text(text) => text.clone(),
+            Cell::Number(n) | Cell::DateTime(n) => n.to_string(),
+        }
+    }
+}
+
+/// A line of the file and its cells.
+type Row = (usize, Vec<Cell>);
+
+/// Parse a CSV or XLSX schedule into commands for `device_id`, detecting the
+/// format from the content.
+pub fn parse_schedule_file(
+    bytes: &[u8],
+    device_id: i32,
+) -> Result<Vec<CreateCommandRequest>, ImportError> {
+    let rows = if bytes.starts_with(ZIP_MAGIC) {
+        xlsx_rows(bytes)?
+    } else {
+        csv_rows(bytes)?
+    };
+    commands_from_rows(rows, device_id)
+}
+
+fn csv_rows(bytes: &[u8]) -> Result<Vec<Row>, ImportError> {
+    Ok(csv_lines(bytes)?
+        .into_iter()
+        .map(|(line, fields)| (line, fields.into_iter().map(Cell::Text).collect()))
+        .collect())
+}
+
+/// The records of a CSV file, trimmed, with their lines.
+pub(crate) fn csv_lines(bytes: &[u8]) -> Result<Vec<(usize, Vec<String>)>, ImportError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(bytes);
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| ImportError::Unreadable(format!("Invalid CSV: {}", e)))?;
+        // The record's position is where the reader started, before any
+        // blank lines it skipped
+        let start = record.position().map_or(0, |p| p.byte() as usize);
+        let skipped = bytes[start..].iter().take_while(|b| matches!(b, b'\r' | b'\n')).count();
+        let line = bytes[..start + skipped].iter().filter(|b| **b == b'\n').count() + 1;
+        rows.push((line, record.iter().map(str::to_string).collect()));
+    }
+    Ok(rows)
+}
+
+fn xlsx_rows(bytes: &[u8]) -> Result<Vec<Row>, ImportError> {
+    let mut workbook = Xlsx::new(Cursor::new(bytes))
+        .map_err(|e| ImportError::Unreadable(format!("Invalid XLSX file: {}", e)))?;
+    let range = workbook
+        .worksheet_range_at(0)
+        .ok_or_else(|| ImportError::Unreadable("The XLSX file has no worksheets".to_string()))?
+        .map_err(|e| ImportError::Unreadable(format!("Invalid XLSX file: {}", e)))?;
+    let (first_row, first_column) = range.start().unwrap_or_default();
+
+    Ok(range
+        .rows()
+        .enumerate()
+        .map(|(i, cells)| {
+            // The range starts at the first used cell; keep column positions
+            // as the header sees them
+            let mut row = vec![Cell::Text(String::new()); first_column as usize];
+            row.extend(cells.iter().map(|cell| match cell {
+                Data::Int(n) => Cell::Number(*n as f64),
+                Data::Float(n) => Cell::Number(*n),
+                Data::DateTime(dt) => Cell::DateTime(dt.as_f64()),
+                Data::Empty => Cell::Text(String::new()),
+                other => Cell::Text(other.to_string().trim().to_string()),
+            }));
+            (first_row as usize + i + 1, row)
+        })
+        .collect())
+}
+
+/// Seconds into the day `cell` names.
+fn parse_time(cell: &Cell) -> Result<i32, String> {
+    let seconds = match cell {
+        // A date and time keeps only the time
+        Cell::DateTime(days) => (days.fract() * 86_400.0).round() as i32,
+        // An untyped cell holding a time is a fraction of a day
+        Cell::Number(days) if (0.0..1.0).contains(days) => (days * 86_400.0).round() as i32,
+        Cell::Text(text) => NaiveTime::parse_from_str(text, "%H:%M:%S")
+            .or_else(|_| NaiveTime::parse_from_str(text, "%H:%M"))
+            .map(|time| time.num_seconds_from_midnight() as i32)
+            .map_err(|_| format!("time '{}' isn't a time of day (HH:MM or HH:MM:SS)", text))?,
+        other => return Err(format!("time '{}' isn't a time of day", other.text())),
+    };
+    // A time rounding up to midnight is the end of the day, not the start
+    if seconds >= 86_400 {
+        return Err("time must be before 24:00".to_string());
+    }
+    Ok(seconds)
+}
+
+fn parse_power(cell: &Cell) -> Result<f64, String> {
+    let power = match cell {
+        Cell::Number(n) => *n,
+        Cell::Text(text) if text.is_empty() => {
+            return Err("power is required to start a command".to_string());
+        }
+        other => other
+            .text()
+            .parse::<f64>()
+            .map_err(|_| format!("power '{}' isn't a number of kW", other.text()))?,
+    };
+    if !(power.is_finite() && power > 0.0) {
+        return Err(format!("power must be a positive number of kW, not {}", power));
+    }
+    Ok(power)
+}
+
+fn format_time(seconds: i32) -> String {
+    format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+fn commands_from_rows(
+    rows: Vec<Row>,
+    device_id: i32,
+) -> Result<Vec<CreateCommandRequest>, ImportError> {
+    let mut rows = rows.into_iter().filter(|(_, cells)| !cells.iter().all(Cell::is_blank));
+    let Some((header_line, header)) = rows.next() else {
+        return Err(ImportError::Unreadable("The file has no rows".to_string()));
+    };
+
+    // Column positions, in COLUMNS order
+    let names: Vec<String> = header.iter().map(|cell| cell.text().to_lowercase()).collect();
+    let mut columns = [0; COLUMNS.len()];
+    let mut missing = Vec::new();
+    for (column, name) in columns.iter_mut().zip(COLUMNS) {
+        match names.iter().position(|n| n == name) {
+            Some(i) => *column = i,
+            None => missing.push(name),
+        }
+    }
+    if !missing.is_empty() {
+        return Err(ImportError::Lines(vec![ImportLineError {
+            line: header_line,
+            error: format!("The header row is missing the {} column(s)", missing.join(", ")),
+        }]));
+    }
+    let [time_col, action_col, type_col, power_col] = columns;
+    let blank = Cell::Text(String::new());
+
+    let mut commands: Vec<CreateCommandRequest> = Vec::new();
+    let mut errors = Vec::new();
+    // The command a `stop` would end, by index into `commands`
+    let mut running: Option<usize> = None;
+    let mut last_time: Option<i32> = None;
+
+    for (line, cells) in rows {
+        let cell = |i: usize| cells.get(i).unwrap_or(&blank);
+        let result = (|| {
+            let time = parse_time(cell(time_col))?;
+            if let Some(last) = last_time.filter(|last| time < *last) {
+                return Err(format!(
+                    "time {} is before the previous row's {}",
+                    format_time(time),
+                    format_time(last)
+                ));
+            }
+            let command_type = match cell(type_col).text().to_lowercase().as_str() {
+                "" => None,
+                name => Some(CommandType::from_str(name).map_err(|_| {
+                    format!("type '{}' isn't charge, discharge or trickle_charge", name)
+                })?),
+            };
+
+            match cell(action_col).text().to_lowercase().as_str() {
+                "start" => {
+                    let command_type = command_type
+                        .ok_or_else(|| "type is required to start a command".to_string())?;
+                    let power_kw = parse_power(cell(power_col))?;
+                    if commands.iter().any(|c| c.execution_offset_seconds == time) {
+                        return Err(format!(
+                            "another command already starts at {}",
+                            format_time(time)
+                        ));
+                    }
+                    commands.push(CreateCommandRequest {
+                        execution_offset_seconds: time,
+                        command_type,
+                        duration_seconds: None,
+                        target_soc_percent: None,
+                        power_kw: Some(power_kw),
+                        device_id: Some(device_id),
+                        bindings: None,
+                    });
+                    running = Some(commands.len() - 1);
+                }
+                "stop" => {
+                    let command = running
+                        .take()
+                        .map(|i| &mut commands[i])
+                        .ok_or_else(|| "stop without a running command to end".to_string())?;
+                    if command_type.is_some_and(|t| t != command.command_type) {
+                        return Err(format!(
+                            "stop names {} but the running command is {}",
+                            cell(type_col).text(),
+                            command.command_type.as_str()
+                        ));
+                    }
+                    if time == command.execution_offset_seconds {
+                        return Err("stop at the same time the command starts".to_string());
+                    }
+                    command.duration_seconds = Some(time - command.execution_offset_seconds);
+                }
+                "" => return Err("action is required (start or stop)".to_string()),
+                other => return Err(format!("action '{}' isn't start or stop", other)),
+            }
+            last_time = Some(time);
+            Ok(())
+        })();
+        if let Err(error) = result {
+            errors.push(ImportLineError { line, error });
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(ImportError::Lines(errors));
+    }
+    if commands.is_empty() {
+        return Err(ImportError::Lines(vec![ImportLineError {
+            line: header_line,
+            error: "The file has no start rows".to_string(),
+        }]));
+    }
+    Ok(commands)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(csv: &str) -> Result<Vec<CreateCommandRequest>, ImportError> {
+        parse_schedule_file(csv.as_bytes(), 7)
+    }
+
+    fn line_errors(csv: &str) -> Vec<(usize, String)> {
+        match parse(csv) {
+            Err(ImportError::Lines(errors)) => {
+                errors.into_iter().map(|e| (e.line, e.error)).collect()
+            }
+            other => panic!("expected line errors, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_start_and_stop_rows() {
+        let commands = parse(
+            "Time,Action,Type,Power,Notes\n\
+             06:00,start,charge,250,overnight\n\
+             10:30,stop,charge,,\n\
+             \n\
+             17:00:30,START,discharge,500.5,\n\
+             21:00,start,trickle_charge,10,\n",
+        )
+        .unwrap();
+
+        assert_eq!(commands.len(), 3);
+        assert_eq!(commands[0].execution_offset_seconds, 6 * 3600);
+        assert_eq!(commands[0].command_type, CommandType::Charge);
+        assert_eq!(commands[0].duration_seconds, Some(4 * 3600 + 1800));
+        assert_eq!(commands[0].power_kw, Some(250.0));
+        assert_eq!(commands[0].device_id, Some(7));
+        // Not stopped: runs until the next command
+        assert_eq!(commands[1].execution_offset_seconds, 17 * 3600 + 30);
+        assert_eq!(commands[1].duration_seconds, None);
+        assert_eq!(commands[1].power_kw, Some(500.5));
+        assert_eq!(commands[2].command_type, CommandType::TrickleCharge);
+    }
+
+    #[test]
+    fn test_columns_in_any_order() {
+        let commands = parse("power,type,time,action\n100,discharge,18:00,start\n").unwrap();
+        assert_eq!(commands[0].execution_offset_seconds, 18 * 3600);
+        assert_eq!(commands[0].command_type, CommandType::Discharge);
+    }
+
+    #[test]
+    fn test_errors_are_reported_per_line() {
+        let errors = line_errors(
+            "time,action,type,power\n\
+             25:00,start,charge,100\n\
+             06:00,start,boost,100\n\
+             07:00,start,charge,-5\n\
+             08:00,start,charge,\n\
+             09:00,pause,,\n\
+             10:00,stop,,\n\
+             11:00,start,charge,100\n\
+             10:00,stop,,\n\
+             12:00,stop,discharge,\n",
+        );
+
+        let lines: Vec<usize> = errors.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, vec![2, 3, 4, 5, 6, 7, 9, 10]);
+        assert!(errors[0].1.contains("isn't a time of day"));
+        assert!(errors[1].1.contains("type 'boost'"));
+        assert!(errors[2].1.contains("positive"));
+        assert!(errors[3].1.contains("power is required"));
+        assert!(errors[4].1.contains("action 'pause'"));
+        assert!(errors[5].1.contains("without a running command"));
+        assert!(errors[6].1.contains("before the previous row's 11:00:00"));
+        assert!(errors[7].1.contains("running command is charge"));
+    }
+
+    #[test]
+    fn test_duplicate_start_times() {
+        let errors = line_errors(
+            "time,action,type,power\n06:00,start,charge,100\n06:00,start,discharge,100\n",
+        );
+        assert_eq!(errors, vec![(3, "another command already starts at 06:00:00".to_string())]);
+    }
+
+    #[test]
+    fn test_header_must_name_every_column() {
+        let errors = line_errors("\ntime,kind,power\n06:00,charge,100\n");
+        assert_eq!(
+            errors,
+            vec![(2, "The header row is missing the action, type column(s)".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_empty_files() {
+        assert_eq!(
+            parse("").unwrap_err(),
+            ImportError::Unreadable("The file has no rows".to_string())
+        );
+        assert_eq!(
+            line_errors("time,action,type,power\n"),
+            vec![(1, "The file has no start rows".to_string())]
+        );
+        assert!(matches!(
+            parse_schedule_file(b"PK\x03\x04not really a zip", 1),
+            Err(ImportError::Unreadable(_))
+        ));
+    }
+
+    #[test]
+    fn test_spreadsheet_time_cells() {
+        assert_eq!(parse_time(&Cell::DateTime(0.25)), Ok(6 * 3600));
+        // A date and time keeps only the time
+        assert_eq!(parse_time(&Cell::DateTime(45_000.75)), Ok(18 * 3600));
+        assert_eq!(parse_time(&Cell::Number(0.5)), Ok(12 * 3600));
+        assert!(parse_time(&Cell::DateTime(0.999_999_9)).is_err());
+        assert_eq!(parse_power(&Cell::Number(42.0)), Ok(42.0));
+    }
+}
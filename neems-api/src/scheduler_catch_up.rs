@@ -0,0 +1,91 @@
+//! Catching up on scheduler ticks missed while neems-api was down.
+//!
+//! The RTAC follows a site's schedule by polling `ActiveCommand`, and each
+//! evaluation is a scheduler tick, persisted per site (see
+//! [`crate::orm::scheduler_tick`]). When the service restarts, or is down
+//! across a command start, the RTAC keeps running the command it last got and
+//! the start would otherwise pass unnoticed. On liftoff, this fairing compares
+//! each site's last tick with its schedule and, where commands were due to
+//! start since, re-evaluates the active command, records the catch-up on the
+//! tick and logs it. The next poll then gets the command that is due.
+//!
+//! Catching up is idempotent: a second start finds the caught-up tick and
+//! nothing missed after it. Maintenance windows aren't considered; they are
+//! checked on every poll.
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::SqliteConnection;
+use rocket::fairing::AdHoc;
+
+use crate::orm::{
+    DbConn,
+    application_rule::{count_command_starts, get_active_command},
+    scheduler_tick::{list_scheduler_ticks, record_catch_up},
+};
+
+/// Furthest back missed command starts are looked for
+pub const MAX_CATCH_UP_DAYS: i64 = 31;
+
+/// A site the scheduler caught up on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CatchUp {
+    pub site_id: i32,
+    /// The last tick before the catch-up
+    pub last_evaluated_at: NaiveDateTime,
+    pub missed_commands: usize,
+    /// The command active after the catch-up, if any
+    pub command_id: Option<i32>,
+}
+
+/// Re-evaluate, at `now`, every site whose schedule had commands due to start
+/// since its last tick, recording each catch-up.
+pub fn catch_up(
+    conn: &mut SqliteConnection,
+    now: NaiveDateTime,
+) -> Result<Vec<CatchUp>, diesel::result::Error> {
+    let mut caught_up = Vec::new();
+    for tick in list_scheduler_ticks(conn)? {
+        let from = tick.evaluated_at.max(now - Duration::days(MAX_CATCH_UP_DAYS));
+        let missed = count_command_starts(conn, tick.site_id, from, now)?;
+        if missed == 0 {
+            continue;
+        }
+        let active = get_active_command(conn, tick.site_id, now)?;
+        let command_id = active.command.map(|c| c.command_id);
+        record_catch_up(conn, tick.site_id, now, command_id, missed as i32)?;
+        caught_up.push(CatchUp {
+            site_id: tick.site_id,
+            last_evaluated_at: tick.evaluated_at,
+            missed_commands: missed,
+            command_id,
+        });
+    }
+    Ok(caught_up)
+}
+
+/// Catch up on missed scheduler ticks once the server is up.
+pub fn scheduler_catch_up_fairing() -> AdHoc {
+    AdHoc::on_liftoff("Scheduler Catch-up", |rocket| {
+        Box::pin(async move {
+            let Some(conn) = DbConn::get_one(rocket).await else {
+                error!("[scheduler] Could not get DB connection to catch up on missed ticks");
+                return;
+            };
+            let now = Utc::now().naive_utc();
+            match conn.run(move |c| catch_up(c, now)).await {
+                Ok(caught_up) => {
+                    for c in caught_up {
+                        warn!(
+                            "[scheduler] Site {}: {} command start(s) missed since {}; active command is now {}",
+                            c.site_id,
+                            c.missed_commands,
+                            c.last_evaluated_at,
+                            c.command_id.map_or("none".to_string(), |id| id.to_string())
+                        );
+                    }
+                }
+                Err(e) => error!("[scheduler] Failed to catch up on missed ticks: {}", e),
+            }
+        })
+    })
+}
@@ -1,5 +1,68 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    alarm_actions (id) {
+        id -> Integer,
+        alarm_num -> Integer,
+        action -> Text,
+        user_id -> Nullable<Integer>,
+        user_email -> Text,
+        comment -> Nullable<Text>,
+        shelved_until -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    alarm_escalations (id) {
+        id -> Integer,
+        alarm_num -> Integer,
+        raised_at -> Timestamp,
+        policy_id -> Integer,
+        policy_name -> Text,
+        tier -> Integer,
+        channel_id -> Integer,
+        contacts -> Text,
+        error -> Nullable<Text>,
+        escalated_at -> Timestamp,
+        on_call_email -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    alarm_states (alarm_num) {
+        alarm_num -> Integer,
+        acknowledged_at -> Nullable<Timestamp>,
+        acknowledged_by -> Nullable<Text>,
+        shelved_until -> Nullable<Timestamp>,
+        shelved_by -> Nullable<Text>,
+        shelve_reason -> Nullable<Text>,
+        raised_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    allowed_networks (id) {
+        id -> Integer,
+        company_id -> Nullable<Integer>,
+        client_certificate_id -> Nullable<Integer>,
+        network -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    api_keys (id) {
+        id -> Integer,
+        user_id -> Integer,
+        key_hash -> Text,
+        prefix -> Text,
+        description -> Nullable<Text>,
+        created_at -> Timestamp,
+        rotated_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     application_rules (id) {
         id -> Integer,
@@ -9,6 +72,18 @@ diesel::table! {
         specific_dates -> Nullable<Text>,
         override_reason -> Nullable<Text>,
         created_at -> Timestamp,
+        priority -> Text,
+    }
+}
+
+diesel::table! {
+    client_certificates (id) {
+        id -> Integer,
+        user_id -> Integer,
+        fingerprint -> Text,
+        subject -> Nullable<Text>,
+        description -> Nullable<Text>,
+        created_at -> Timestamp,
     }
 }
 
@@ -19,6 +94,14 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    company_locales (company_id) {
+        company_id -> Integer,
+        locale -> Text,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     deleted_companies (id) {
         id -> Integer,
@@ -40,6 +123,27 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    device_group_members (group_id, device_id) {
+        group_id -> Integer,
+        device_id -> Integer,
+        rated_power_kw -> Nullable<Double>,
+        source_id -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    device_groups (id) {
+        id -> Integer,
+        site_id -> Integer,
+        name -> Text,
+        allocation -> Text,
+        schedule_dispatch -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     devices (id) {
         id -> Integer,
@@ -68,6 +172,100 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    escalation_policies (id) {
+        id -> Integer,
+        company_id -> Integer,
+        name -> Text,
+        min_severity -> Text,
+        enabled -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    escalation_tiers (policy_id, tier) {
+        policy_id -> Integer,
+        tier -> Integer,
+        delay_minutes -> Integer,
+        channel_id -> Integer,
+        contacts -> Text,
+        rotation_id -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    kpi_definitions (id) {
+        id -> Integer,
+        site_id -> Integer,
+        name -> Text,
+        expression -> Text,
+        target -> Nullable<Double>,
+        higher_is_better -> Bool,
+        unit -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    kpi_results (kpi_id, day) {
+        kpi_id -> Integer,
+        day -> Date,
+        value -> Nullable<Double>,
+        met -> Nullable<Bool>,
+        error -> Nullable<Text>,
+        evaluated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    login_events (id) {
+        id -> Integer,
+        user_id -> Nullable<Integer>,
+        email -> Text,
+        success -> Bool,
+        ip_address -> Nullable<Text>,
+        user_agent -> Nullable<Text>,
+        country -> Nullable<Text>,
+        anomaly -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    oncall_members (rotation_id, position) {
+        rotation_id -> Integer,
+        position -> Integer,
+        user_id -> Integer,
+    }
+}
+
+diesel::table! {
+    oncall_overrides (id) {
+        id -> Integer,
+        rotation_id -> Integer,
+        user_id -> Integer,
+        starts_at -> Timestamp,
+        ends_at -> Timestamp,
+        reason -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    oncall_rotations (id) {
+        id -> Integer,
+        company_id -> Integer,
+        name -> Text,
+        handoff_at -> Timestamp,
+        shift_hours -> Integer,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     roles (id) {
         id -> Integer,
@@ -89,6 +287,18 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    schedule_feed_tokens (id) {
+        id -> Integer,
+        site_id -> Integer,
+        user_id -> Integer,
+        token_hash -> Text,
+        prefix -> Text,
+        description -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     schedule_template_entries (id) {
         id -> Integer,
@@ -108,6 +318,17 @@ diesel::table! {
         is_active -> Bool,
         is_default -> Bool,
         created_at -> Timestamp,
+        parameters -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    scheduler_ticks (site_id) {
+        site_id -> Integer,
+        evaluated_at -> Timestamp,
+        command_id -> Nullable<Integer>,
+        caught_up_at -> Nullable<Timestamp>,
+        missed_commands -> Integer,
     }
 }
 
@@ -121,6 +342,18 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    site_tariffs (site_id) {
+        site_id -> Integer,
+        currency -> Text,
+        standard_price_per_kwh -> Double,
+        off_peak_price_per_kwh -> Double,
+        peak_price_per_kwh -> Double,
+        demand_charge_per_kw -> Double,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     sites (id) {
         id -> Integer,
@@ -143,6 +376,16 @@ diesel::table! {
         charge_rate_percent -> Double,
         discharge_rate_percent -> Double,
         trickle_charge_power_kw -> Nullable<Double>,
+        archived_at -> Nullable<Timestamp>,
+        archive_key -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    user_locales (user_id) {
+        user_id -> Integer,
+        locale -> Text,
+        updated_at -> Timestamp,
     }
 }
 
@@ -160,35 +403,82 @@ diesel::table! {
         password_hash -> Text,
         company_id -> Integer,
         totp_secret -> Nullable<Text>,
+        must_change_password -> Bool,
+        service_account -> Bool,
     }
 }
 
+diesel::joinable!(alarm_actions -> users (user_id));
+diesel::joinable!(allowed_networks -> client_certificates (client_certificate_id));
+diesel::joinable!(allowed_networks -> companies (company_id));
+diesel::joinable!(api_keys -> users (user_id));
 diesel::joinable!(application_rules -> schedule_templates (template_id));
+diesel::joinable!(client_certificates -> users (user_id));
+diesel::joinable!(company_locales -> companies (company_id));
+diesel::joinable!(device_group_members -> device_groups (group_id));
+diesel::joinable!(device_group_members -> devices (device_id));
+diesel::joinable!(device_groups -> sites (site_id));
 diesel::joinable!(devices -> companies (company_id));
 diesel::joinable!(devices -> sites (site_id));
+diesel::joinable!(escalation_policies -> companies (company_id));
+diesel::joinable!(escalation_tiers -> escalation_policies (policy_id));
+diesel::joinable!(kpi_definitions -> sites (site_id));
+diesel::joinable!(kpi_results -> kpi_definitions (kpi_id));
+diesel::joinable!(login_events -> users (user_id));
+diesel::joinable!(oncall_members -> oncall_rotations (rotation_id));
+diesel::joinable!(oncall_members -> users (user_id));
+diesel::joinable!(oncall_overrides -> oncall_rotations (rotation_id));
+diesel::joinable!(oncall_overrides -> users (user_id));
+diesel::joinable!(oncall_rotations -> companies (company_id));
 diesel::joinable!(schedule_commands -> sites (site_id));
+diesel::joinable!(schedule_feed_tokens -> sites (site_id));
+diesel::joinable!(schedule_feed_tokens -> users (user_id));
 diesel::joinable!(schedule_template_entries -> schedule_commands (schedule_command_id));
 diesel::joinable!(schedule_template_entries -> schedule_templates (template_id));
 diesel::joinable!(schedule_templates -> sites (site_id));
+diesel::joinable!(scheduler_ticks -> sites (site_id));
 diesel::joinable!(sessions -> users (user_id));
+diesel::joinable!(site_tariffs -> sites (site_id));
 diesel::joinable!(sites -> companies (company_id));
+diesel::joinable!(user_locales -> users (user_id));
 diesel::joinable!(user_roles -> roles (role_id));
 diesel::joinable!(user_roles -> users (user_id));
 diesel::joinable!(users -> companies (company_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    alarm_actions,
+    alarm_escalations,
+    alarm_states,
+    allowed_networks,
+    api_keys,
     application_rules,
+    client_certificates,
     companies,
+    company_locales,
     deleted_companies,
     deleted_users,
+    device_group_members,
+    device_groups,
     devices,
     entity_activity,
+    escalation_policies,
+    escalation_tiers,
+    kpi_definitions,
+    kpi_results,
+    login_events,
+    oncall_members,
+    oncall_overrides,
+    oncall_rotations,
     roles,
     schedule_commands,
+    schedule_feed_tokens,
     schedule_template_entries,
     schedule_templates,
+    scheduler_ticks,
     sessions,
+    site_tariffs,
     sites,
+    user_locales,
     user_roles,
     users,
 );
@@ -74,15 +74,22 @@ use chrono::Utc;
 use diesel::prelude::*;
 use rocket::{
     http::Status,
+    mtls::Certificate,
     outcome::Outcome,
     request::{self, FromRequest, Request},
 };
 
 use crate::{
-    DbConn,
-    models::{Role, Session, User},
-    orm::user_role::get_user_roles,
+    DbConn, allowlist,
+    models::{ApiKey, ClientCertificate, Role, Session, User},
+    orm::{
+        allowed_network::{NetworkOwner, get_allowed_networks},
+        api_key::get_api_key_by_key,
+        client_certificate::get_client_certificate_by_fingerprint,
+        user_role::get_user_roles,
+    },
     schema::{sessions, users},
+    tls,
 };
 
 /// A request guard for routes that require an authenticated user.
@@ -91,19 +98,26 @@ use crate::{
 /// is authenticated before allowing access to protected routes. It performs
 /// the following checks:
 ///
-/// 1. Extracts the session cookie from the request
+/// 1. Extracts the session cookie from the request (or, without one, a service
+///    account's API key given as `Authorization: Bearer <key>`, or a verified
+///    client certificate registered to a user; see [`crate::tls`])
 /// 2. Validates the session exists in the database
 /// 3. Checks that the session is not revoked
 /// 4. Verifies the session has not expired
 /// 5. Retrieves the associated user from the database
 /// 6. Loads all roles assigned to the user
 /// 7. Ensures the user has at least one role (database constraint)
+/// 8. Checks the client address against the company's and the client
+///    certificate's network allowlists, if set (see [`crate::allowlist`])
+/// 9. Refuses users who must change their password (see [`PasswordChangeUser`])
 ///
 /// # Returns
 ///
 /// - `Outcome::Success(AuthenticatedUser)` if authentication succeeds
 /// - `Outcome::Error(Status::Unauthorized)` if authentication fails or user has
 ///   no roles
+/// - `Outcome::Error(Status::Forbidden)` if the client address is outside an
+///   allowlist, or the user must change their password
 /// - `Outcome::Error(Status::InternalServerError)` if database connection fails
 ///
 /// # Usage
@@ -145,19 +159,8 @@ pub struct AuthenticatedUser {
 impl<'r> FromRequest<'r> for AuthenticatedUser {
     type Error = ();
 
-    /// Extracts and validates an authenticated user from the request.
-    ///
-    /// This method implements the core authentication logic by:
-    /// 1. Extracting the database connection from the request
-    /// 2. Reading the "session" cookie from the request
-    /// 3. Querying the sessions table to find a valid, non-revoked, non-expired
-    ///    session
-    /// 4. Retrieving the associated user from the users table
-    ///
-    /// # Arguments
-    ///
-    /// * `request` - The incoming HTTP request containing cookies and database
-    ///   connection
+    /// Extracts and validates an authenticated user from the request (see
+    /// [`authenticate`]), refusing users who must change their password.
     ///
     /// # Returns
     ///
@@ -165,86 +168,263 @@ impl<'r> FromRequest<'r> for AuthenticatedUser {
     ///   authenticated user
     /// * `Outcome::Error(Status::Unauthorized)` - Invalid/missing session or
     ///   user
+    /// * `Outcome::Error(Status::Forbidden)` - The user must change their
+    ///   password first, or is outside an allowlist
     /// * `Outcome::Error(Status::InternalServerError)` - Database connection
     ///   failure
     async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
-        let cookies = request.cookies();
-        let db = match request.guard::<DbConn>().await {
-            Outcome::Success(db) => db,
-            _ => return Outcome::Error((Status::InternalServerError, ())),
-        };
+        match authenticate(request).await {
+            Outcome::Success(auth_user) if auth_user.user.must_change_password => {
+                warn!(
+                    "Refused {} for user {} pending password change",
+                    request.uri(),
+                    auth_user.user.id
+                );
+                Outcome::Error((Status::Forbidden, ()))
+            }
+            outcome => outcome,
+        }
+    }
+}
 
-        // Get session cookie
-        let session_cookie = match cookies.get("session") {
-            Some(cookie) => cookie,
-            None => return Outcome::Error((Status::Unauthorized, ())),
-        };
+/// A request guard like [`AuthenticatedUser`] that also admits users whose
+/// password must be changed. Only the password change endpoint uses it, so a
+/// flagged session can do nothing else.
+#[derive(Debug)]
+pub struct PasswordChangeUser(pub AuthenticatedUser);
 
-        let session_id = session_cookie.value().to_string();
-
-        // Query the sessions table for a valid session
-        let session_result = db
-            .run(move |conn| {
-                sessions::table
-                    .filter(sessions::id.eq(&session_id))
-                    .filter(sessions::revoked.eq(false))
-                    .filter(
-                        sessions::expires_at
-                            .is_null()
-                            .or(sessions::expires_at.gt(Utc::now().naive_utc())),
-                    )
-                    .first::<Session>(conn)
-                    .optional()
-            })
-            .await;
-
-        let session = match session_result {
-            Ok(Some(sess)) => sess,
-            Ok(None) => return Outcome::Error((Status::Unauthorized, ())),
-            Err(e) => {
-                error!("Database error finding session: {:?}", e);
-                return Outcome::Error((Status::Unauthorized, ()));
-            }
-        };
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for PasswordChangeUser {
+    type Error = ();
 
-        // Query the users table for the user associated with the session
-        let user_result = db
-            .run(move |conn| {
-                users::table
-                    .filter(users::id.eq(session.user_id))
-                    .first::<User>(conn)
-                    .optional()
-            })
-            .await;
-
-        let user = match user_result {
-            Ok(Some(u)) => u,
-            Ok(None) => return Outcome::Error((Status::Unauthorized, ())),
-            Err(e) => {
-                error!("Database error finding user: {:?}", e);
-                return Outcome::Error((Status::Unauthorized, ()));
-            }
-        };
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        authenticate(request).await.map(PasswordChangeUser)
+    }
+}
 
-        // Query all roles for the user
-        let user_id = user.id;
-        let roles_result = db.run(move |conn| get_user_roles(conn, user_id)).await;
+/// Extracts and validates an authenticated user from the request, whether or
+/// not they must change their password.
+///
+/// This function implements the core authentication logic by:
+/// 1. Extracting the database connection from the request
+/// 2. Reading the "session" cookie from the request
+/// 3. Querying the sessions table to find a valid, non-revoked, non-expired
+///    session
+/// 4. Retrieving the associated user from the users table
+///
+/// # Arguments
+///
+/// * `request` - The incoming HTTP request containing cookies and database
+///   connection
+///
+/// # Returns
+///
+/// * `Outcome::Success(AuthenticatedUser)` - Valid session with authenticated
+///   user
+/// * `Outcome::Error(Status::Unauthorized)` - Invalid/missing session or user
+/// * `Outcome::Error(Status::InternalServerError)` - Database connection
+///   failure
+async fn authenticate(request: &Request<'_>) -> request::Outcome<AuthenticatedUser, ()> {
+    let cookies = request.cookies();
+    let db = match request.guard::<DbConn>().await {
+        Outcome::Success(db) => db,
+        _ => return Outcome::Error((Status::InternalServerError, ())),
+    };
 
-        let roles = match roles_result {
-            Ok(r) => {
-                if r.is_empty() {
-                    return Outcome::Error((Status::Unauthorized, ()));
+    // Get session cookie; without one, an API key or a registered client
+    // certificate can authenticate instead
+    let session_cookie = match cookies.get("session") {
+        Some(cookie) => cookie,
+        None => {
+            if let Some(key) = bearer_token(request) {
+                return match request_api_key(&db, key).await {
+                    Some(api_key) => authorize(request, &db, api_key.user_id, None).await,
+                    None => Outcome::Error((Status::Unauthorized, ())),
+                };
+            }
+            return match request_certificate(request, &db).await {
+                Some(certificate) => {
+                    authorize(request, &db, certificate.user_id, Some(certificate.id)).await
                 }
-                r
+                None => unauthenticated(request, &db).await,
+            };
+        }
+    };
+
+    let session_id = session_cookie.value().to_string();
+
+    // Query the sessions table for a valid session
+    let session_result = db
+        .run(move |conn| {
+            sessions::table
+                .filter(sessions::id.eq(&session_id))
+                .filter(sessions::revoked.eq(false))
+                .filter(
+                    sessions::expires_at
+                        .is_null()
+                        .or(sessions::expires_at.gt(Utc::now().naive_utc())),
+                )
+                .first::<Session>(conn)
+                .optional()
+        })
+        .await;
+
+    let session = match session_result {
+        Ok(Some(sess)) => sess,
+        Ok(None) => return Outcome::Error((Status::Unauthorized, ())),
+        Err(e) => {
+            error!("Database error finding session: {:?}", e);
+            return Outcome::Error((Status::Unauthorized, ()));
+        }
+    };
+
+    authorize(request, &db, session.user_id, None).await
+}
+
+/// A request with no credentials: refused, except in mock mode (see
+/// [`crate::mock`]), where it acts as the mock user.
+async fn unauthenticated(
+    request: &Request<'_>,
+    db: &DbConn,
+) -> request::Outcome<AuthenticatedUser, ()> {
+    #[cfg(feature = "test-staging")]
+    if let Some(mock) = request.rocket().state::<crate::mock::MockUser>() {
+        return authorize(request, db, mock.user_id, None).await;
+    }
+    #[cfg(not(feature = "test-staging"))]
+    let _ = (request, db);
+    Outcome::Error((Status::Unauthorized, ()))
+}
+
+/// The token in the request's `Authorization: Bearer` header, if any.
+fn bearer_token<'r>(request: &'r Request<'_>) -> Option<&'r str> {
+    let value = request.headers().get_one("Authorization")?;
+    let (scheme, token) = value.trim().split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+/// The API key the request presented, if it's a valid one.
+async fn request_api_key(db: &DbConn, key: &str) -> Option<ApiKey> {
+    let key = key.to_string();
+    match db.run(move |conn| get_api_key_by_key(conn, &key)).await {
+        Ok(api_key) => api_key,
+        Err(e) => {
+            error!("Database error finding API key: {:?}", e);
+            None
+        }
+    }
+}
+
+/// The registered client certificate the request presented, if any.
+async fn request_certificate(request: &Request<'_>, db: &DbConn) -> Option<ClientCertificate> {
+    let certificate = match request.guard::<Certificate<'_>>().await {
+        Outcome::Success(certificate) => certificate,
+        _ => return None,
+    };
+    let fingerprint = tls::fingerprint(certificate.as_bytes());
+    match db
+        .run(move |conn| get_client_certificate_by_fingerprint(conn, &fingerprint))
+        .await
+    {
+        Ok(certificate) => certificate,
+        Err(e) => {
+            error!("Database error finding client certificate: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Authenticate a request as `user_id` on the strength of a credential the
+/// guards don't know about, such as a schedule feed token: load the user
+/// and check the client address against their company's allowlist.
+pub(crate) async fn authorize_user(
+    request: &Request<'_>,
+    user_id: i32,
+) -> request::Outcome<AuthenticatedUser, ()> {
+    match request.guard::<DbConn>().await {
+        Outcome::Success(db) => authorize(request, &db, user_id, None).await,
+        _ => Outcome::Error((Status::InternalServerError, ())),
+    }
+}
+
+/// Load the user, then check the client address against their company's
+/// allowlist and, when they authenticated with a client certificate, the
+/// certificate's.
+async fn authorize(
+    request: &Request<'_>,
+    db: &DbConn,
+    user_id: i32,
+    certificate_id: Option<i32>,
+) -> request::Outcome<AuthenticatedUser, ()> {
+    let auth_user = match load_user(db, user_id).await {
+        Outcome::Success(auth_user) => auth_user,
+        outcome => return outcome,
+    };
+
+    let company_id = auth_user.user.company_id;
+    let networks = db
+        .run(move |conn| {
+            let mut lists = vec![get_allowed_networks(conn, NetworkOwner::Company(company_id))?];
+            if let Some(certificate_id) = certificate_id {
+                lists.push(get_allowed_networks(
+                    conn,
+                    NetworkOwner::ClientCertificate(certificate_id),
+                )?);
             }
-            Err(e) => {
-                error!("Database error finding user roles: {:?}", e);
+            Ok::<_, diesel::result::Error>(lists)
+        })
+        .await;
+
+    let ip = request.client_ip();
+    match networks {
+        Ok(lists) if lists.iter().all(|networks| allowlist::stored_allows(networks, ip)) => {
+            Outcome::Success(auth_user)
+        }
+        Ok(_) => {
+            warn!("Refused {} for user {} from outside allowed networks", request.uri(), user_id);
+            Outcome::Error((Status::Forbidden, ()))
+        }
+        Err(e) => {
+            error!("Database error finding allowed networks: {:?}", e);
+            Outcome::Error((Status::InternalServerError, ()))
+        }
+    }
+}
+
+/// Load an authenticated user and their roles.
+async fn load_user(db: &DbConn, user_id: i32) -> request::Outcome<AuthenticatedUser, ()> {
+    // Query the users table for the user associated with the session
+    let user_result = db
+        .run(move |conn| users::table.filter(users::id.eq(user_id)).first::<User>(conn).optional())
+        .await;
+
+    let user = match user_result {
+        Ok(Some(u)) => u,
+        Ok(None) => return Outcome::Error((Status::Unauthorized, ())),
+        Err(e) => {
+            error!("Database error finding user: {:?}", e);
+            return Outcome::Error((Status::Unauthorized, ()));
+        }
+    };
+
+    // Query all roles for the user
+    let user_id = user.id;
+    let roles_result = db.run(move |conn| get_user_roles(conn, user_id)).await;
+
+    let roles = match roles_result {
+        Ok(r) => {
+            if r.is_empty() {
                 return Outcome::Error((Status::Unauthorized, ()));
             }
-        };
+            r
+        }
+        Err(e) => {
+            error!("Database error finding user roles: {:?}", e);
+            return Outcome::Error((Status::Unauthorized, ()));
+        }
+    };
 
-        Outcome::Success(AuthenticatedUser { user, roles })
-    }
+    Outcome::Success(AuthenticatedUser { user, roles })
 }
 
 impl AuthenticatedUser {
@@ -358,6 +538,8 @@ impl RoleGuard {
                 password_hash: String::new(),
                 company_id: 0,
                 totp_secret: None,
+                must_change_password: false,
+                service_account: false,
             },
             roles: Vec::new(),
             required_roles,
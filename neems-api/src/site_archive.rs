@@ -0,0 +1,105 @@
+//! Decommissioning sites into archives.
+//!
+//! Archiving a site copies everything recorded about it into one SQLite file
+//! and stores that in blob storage (see [`crate::storage`]):
+//!
+//! - from neems-api's database: the site itself, its devices and device groups,
+//!   schedules and the rules applying them, tariff, KPIs and their results, and
+//!   the site's activity log ([`SITE_RECORD_TABLES`])
+//! - from the site database: its sources, readings, power quality events and
+//!   maintenance windows ([`neems_data::archive::SITE_HISTORY_TABLES`])
+//!
+//! Each table keeps its live name and columns, and an `archive_info` table
+//! says what the file is. The site is then marked archived, which makes it
+//! read-only through the API, and its readings and power quality events are
+//! deleted from the site database. Its other records stay, so the site still
+//! shows up, with its archive to download.
+
+use std::path::Path;
+
+use chrono::NaiveDateTime;
+use diesel::{prelude::*, sql_types::Text};
+use neems_data::{
+    DataResult,
+    archive::{ARCHIVE_SCHEMA, ArchivedTable, copy_site_rows, with_archive},
+};
+
+use crate::models::Site;
+
+/// Version of the archive layout, recorded in `archive_info`.
+pub const ARCHIVE_FORMAT_VERSION: i32 = 1;
+
+/// Content type archives are stored with.
+pub const ARCHIVE_CONTENT_TYPE: &str = "application/vnd.sqlite3";
+
+/// Rows of a site's schedule templates; `?1` is the site id.
+const SITE_TEMPLATES: &str =
+    "template_id IN (SELECT id FROM main.schedule_templates WHERE site_id = ?1)";
+
+/// neems-api's tables archived with a site, and which rows belong to it;
+/// `?1` is the site id.
+pub const SITE_RECORD_TABLES: &[(&str, &str)] = &[
+    ("sites", "id = ?1"),
+    ("devices", "site_id = ?1"),
+    ("device_groups", "site_id = ?1"),
+    (
+        "device_group_members",
+        "group_id IN (SELECT id FROM main.device_groups WHERE site_id = ?1)",
+    ),
+    ("schedule_commands", "site_id = ?1"),
+    ("schedule_templates", "site_id = ?1"),
+    ("schedule_template_entries", SITE_TEMPLATES),
+    ("application_rules", SITE_TEMPLATES),
+    ("site_tariffs", "site_id = ?1"),
+    ("kpi_definitions", "site_id = ?1"),
+    (
+        "kpi_results",
+        "kpi_id IN (SELECT id FROM main.kpi_definitions WHERE site_id = ?1)",
+    ),
+    ("entity_activity", "table_name = 'sites' AND entity_id = ?1"),
+];
+
+/// The blob storage key for `site_id`'s archive made at `at`.
+pub fn archive_key(site_id: i32, at: NaiveDateTime) -> String {
+    format!(
+        "archives/site-{}/site-{}-{}.sqlite",
+        site_id,
+        site_id,
+        at.format("%Y%m%dT%H%M%SZ")
+    )
+}
+
+/// Write `site`'s records from neems-api's database, and an `archive_info`
+/// table describing the archive, to the archive file at `path`.
+pub fn archive_site_records(
+    conn: &mut SqliteConnection,
+    site: &Site,
+    archived_at: NaiveDateTime,
+    archived_by: &str,
+    path: &Path,
+) -> DataResult<Vec<ArchivedTable>> {
+    with_archive(conn, path, |conn| {
+        diesel::sql_query(format!(
+            "CREATE TABLE {ARCHIVE_SCHEMA}.archive_info (name TEXT PRIMARY KEY NOT NULL, value TEXT NOT NULL)"
+        ))
+        .execute(conn)?;
+        let info = [
+            ("format_version", ARCHIVE_FORMAT_VERSION.to_string()),
+            ("site_id", site.id.to_string()),
+            ("site_name", site.name.clone()),
+            ("company_id", site.company_id.to_string()),
+            ("archived_at", archived_at.format("%Y-%m-%dT%H:%M:%SZ").to_string()),
+            ("archived_by", archived_by.to_string()),
+            ("neems_api_version", env!("CARGO_PKG_VERSION").to_string()),
+        ];
+        for (name, value) in info {
+            diesel::sql_query(format!(
+                "INSERT INTO {ARCHIVE_SCHEMA}.archive_info (name, value) VALUES (?, ?)"
+            ))
+            .bind::<Text, _>(name)
+            .bind::<Text, _>(value)
+            .execute(conn)?;
+        }
+        copy_site_rows(conn, site.id, SITE_RECORD_TABLES)
+    })
+}
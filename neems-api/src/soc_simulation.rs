@@ -0,0 +1,296 @@
+//! State-of-charge simulation for schedules.
+//!
+//! Walks a battery through a proposed command sequence from a starting SoC
+//! and returns the projected SoC curve, flagging where it would rise above
+//! the maximum, fall below the minimum, or run empty.
+//!
+//! The schedule repeats daily. At any moment the command that started last
+//! runs, for its duration or until the next one starts, and before the
+//! day's first command the previous day's last one carries on, as the
+//! active-command endpoint serves them. Charging commands charge until their
+//! `target_soc_percent`, or full; discharging ones discharge until theirs,
+//! or empty. Losses are split evenly between charging and discharging: with
+//! a round-trip efficiency of 81%, charging stores 90% of the energy drawn
+//! and discharging drains 1/0.9 of the energy delivered.
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{
+    models::{CommandType, ScheduleCommandDto},
+    schedule_check::BatteryLimits,
+};
+
+const SECONDS_PER_DAY: i32 = 24 * 60 * 60;
+
+/// The battery simulated and where it starts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationParams {
+    /// Capacity, command powers and the minimum SoC (the floor)
+    pub limits: BatteryLimits,
+    pub max_soc_percent: f64,
+    /// Round-trip efficiency, 0 to 1
+    pub round_trip_efficiency: f64,
+    pub initial_soc_percent: f64,
+    /// Seconds after midnight the simulation starts
+    pub start_offset_seconds: i32,
+    pub duration_seconds: i32,
+    pub step_seconds: i32,
+}
+
+/// A point on the projected SoC curve.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SocPoint {
+    /// Seconds after the first simulated day's midnight
+    pub offset_seconds: i32,
+    pub soc_percent: f64,
+}
+
+/// How a projected SoC breaks the battery's limits.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "snake_case")]
+pub enum SocViolationKind {
+    AboveMax,
+    BelowMin,
+    Empty,
+}
+
+/// Where the projected SoC first breaks a limit, and the command running.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SocViolation {
+    pub offset_seconds: i32,
+    pub kind: SocViolationKind,
+    pub soc_percent: f64,
+    /// Index of the command running in the commands simulated
+    pub command_index: usize,
+}
+
+/// The projected SoC curve and its violations.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SocSimulation {
+    pub points: Vec<SocPoint>,
+    pub violations: Vec<SocViolation>,
+    pub final_soc_percent: f64,
+}
+
+/// The index of the command running `time_of_day` seconds after midnight.
+fn active_command(commands: &[ScheduleCommandDto], time_of_day: i32) -> Option<usize> {
+    let latest = |before: i32| {
+        (0..commands.len())
+            .filter(|i| commands[*i].execution_offset_seconds <= before)
+            .max_by_key(|i| (commands[*i].execution_offset_seconds, *i))
+    };
+    let (index, elapsed) = match latest(time_of_day) {
+        Some(i) => (i, time_of_day - commands[i].execution_offset_seconds),
+        // Before the first command the previous day's last one carries on
+        None => {
+            let i = latest(SECONDS_PER_DAY)?;
+            (i, time_of_day + SECONDS_PER_DAY - commands[i].execution_offset_seconds)
+        }
+    };
+    match commands[index].duration_seconds {
+        Some(duration) if elapsed >= duration => None,
+        _ => Some(index),
+    }
+}
+
+/// Change in SoC, in percentage points, from running `command` for
+/// `seconds`, before limits.
+fn soc_change(command: &ScheduleCommandDto, params: &SimulationParams, seconds: i32) -> f64 {
+    let limits = &params.limits;
+    if limits.capacity_kwh <= 0.0 {
+        return 0.0;
+    }
+    let one_way = params.round_trip_efficiency.sqrt();
+    let energy_kwh = limits.power_kw(command) * seconds as f64 / 3600.0;
+    let stored_kwh = match command.command_type {
+        CommandType::Charge | CommandType::TrickleCharge => energy_kwh * one_way,
+        CommandType::Discharge if one_way > 0.0 => -energy_kwh / one_way,
+        CommandType::Discharge => 0.0,
+    };
+    stored_kwh / limits.capacity_kwh * 100.0
+}
+
+/// The SoC after running `command` for `seconds` from `soc`: towards its
+/// target, or full or empty, and no further.
+fn step_soc(
+    command: &ScheduleCommandDto,
+    params: &SimulationParams,
+    soc: f64,
+    seconds: i32,
+) -> f64 {
+    let change = soc_change(command, params, seconds);
+    let target = command.target_soc_percent.map(f64::from);
+    if change >= 0.0 {
+        let stop = target.unwrap_or(100.0).min(100.0);
+        if soc >= stop {
+            soc
+        } else {
+            (soc + change).min(stop)
+        }
+    } else {
+        let stop = target.unwrap_or(0.0).max(0.0);
+        if soc <= stop {
+            soc
+        } else {
+            (soc + change).max(stop)
+        }
+    }
+}
+
+/// Simulate the battery running `commands` (a daily schedule).
+pub fn simulate_soc(commands: &[ScheduleCommandDto], params: &SimulationParams) -> SocSimulation {
+    let step = params.step_seconds.max(1);
+    let min = params.limits.soc_floor_percent;
+    let max = params.max_soc_percent;
+    let mut soc = params.initial_soc_percent.clamp(0.0, 100.0);
+    let mut offset = params.start_offset_seconds;
+    let end = params.start_offset_seconds + params.duration_seconds;
+    let mut points = vec![SocPoint { offset_seconds: offset, soc_percent: soc }];
+    let mut violations = Vec::new();
+
+    while offset < end {
+        let running = active_command(commands, offset.rem_euclid(SECONDS_PER_DAY));
+        let before = soc;
+        let seconds = step.min(end - offset);
+        if let Some(index) = running {
+            soc = step_soc(&commands[index], params, soc, seconds);
+        }
+        offset += seconds;
+        points.push(SocPoint { offset_seconds: offset, soc_percent: soc });
+
+        // Flag each limit as the curve crosses it
+        let Some(command_index) = running else {
+            continue;
+        };
+        let crossed = [
+            (before <= max && soc > max, SocViolationKind::AboveMax),
+            (before >= min && soc < min, SocViolationKind::BelowMin),
+            (before > 0.0 && soc <= 0.0, SocViolationKind::Empty),
+        ];
+        for (crossed, kind) in crossed {
+            if crossed {
+                violations.push(SocViolation {
+                    offset_seconds: offset,
+                    kind,
+                    soc_percent: soc,
+                    command_index,
+                });
+            }
+        }
+    }
+
+    SocSimulation {
+        points,
+        violations,
+        final_soc_percent: soc,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(
+        hour: i32,
+        command_type: CommandType,
+        duration_hours: Option<i32>,
+        target_soc_percent: Option<i32>,
+    ) -> ScheduleCommandDto {
+        ScheduleCommandDto {
+            id: 0,
+            execution_offset_seconds: hour * 3600,
+            command_type,
+            duration_seconds: duration_hours.map(|h| h * 3600),
+            target_soc_percent,
+            power_kw: None,
+            device_id: None,
+            bindings: None,
+        }
+    }
+
+    fn params(initial_soc_percent: f64) -> SimulationParams {
+        SimulationParams {
+            limits: BatteryLimits {
+                capacity_kwh: 1000.0,
+                charge_kw: 250.0,
+                discharge_kw: 250.0,
+                trickle_charge_kw: 10.0,
+                soc_floor_percent: 20.0,
+            },
+            max_soc_percent: 90.0,
+            round_trip_efficiency: 1.0,
+            initial_soc_percent,
+            start_offset_seconds: 0,
+            duration_seconds: SECONDS_PER_DAY,
+            step_seconds: 3600,
+        }
+    }
+
+    #[test]
+    fn test_charge_to_target_then_discharge() {
+        let commands = [
+            command(2, CommandType::Charge, None, Some(80)),
+            command(17, CommandType::Discharge, Some(2), None),
+        ];
+        let result = simulate_soc(&commands, &params(30.0));
+
+        assert_eq!(result.points.len(), 25);
+        // 25% an hour from 02:00, stopping at 80%
+        assert_eq!(result.points[3].soc_percent, 55.0);
+        assert_eq!(result.points[4].soc_percent, 80.0);
+        assert_eq!(result.points[17].soc_percent, 80.0);
+        assert_eq!(result.points[19].soc_percent, 30.0);
+        assert_eq!(result.final_soc_percent, 30.0);
+        assert!(result.violations.is_empty());
+    }
+
+    #[test]
+    fn test_flags_limits_and_empty() {
+        // Charges past the 90% maximum, then discharges until empty
+        let commands = [
+            command(0, CommandType::Charge, Some(2), None),
+            command(12, CommandType::Discharge, None, None),
+        ];
+        let result = simulate_soc(&commands, &params(50.0));
+
+        let kinds: Vec<_> = result
+            .violations
+            .iter()
+            .map(|v| (v.offset_seconds / 3600, v.kind.clone()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (2, SocViolationKind::AboveMax),
+                (16, SocViolationKind::BelowMin),
+                (16, SocViolationKind::Empty),
+            ]
+        );
+        assert_eq!(result.violations[0].command_index, 0);
+        assert_eq!(result.violations[2].command_index, 1);
+        assert_eq!(result.final_soc_percent, 0.0);
+    }
+
+    #[test]
+    fn test_last_command_carries_over_midnight() {
+        let commands = [command(22, CommandType::Charge, None, None)];
+        let result = simulate_soc(&commands, &params(50.0));
+        // Charging from midnight to 02:00 fills the battery
+        assert_eq!(result.points[2].soc_percent, 100.0);
+    }
+
+    #[test]
+    fn test_efficiency_losses() {
+        let commands = [command(0, CommandType::Discharge, Some(1), None)];
+        let mut lossy = params(50.0);
+        lossy.round_trip_efficiency = 0.64;
+        let result = simulate_soc(&commands, &lossy);
+        // 250 kWh delivered drains 312.5 kWh at 80% one-way
+        assert!((result.final_soc_percent - 18.75).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,140 @@
+//! Blob storage for exported reports and waveform captures.
+//!
+//! Exports are written to a [`BlobStore`] (see [`neems_data::storage`]) and
+//! the caller gets a time-limited download URL for them rather than the file
+//! itself, so large exports can be fetched later, shared, or downloaded
+//! straight from S3. With S3 the URL is presigned; with local storage it
+//! points at `/api/1/Blobs/<key>`, signed with the storage URL key (see
+//! [`crate::api::blob`]).
+//!
+//! Configured in Rocket.toml or with `ROCKET_` variables:
+//!
+//! - `storage_backend`: `local` (the default) or `s3`
+//! - `storage_path`: the local directory (default `storage`)
+//! - `storage_s3_bucket`, `storage_s3_region` (default `us-east-1`),
+//!   `storage_s3_access_key_id`, `storage_s3_secret_access_key`: the bucket and
+//!   its credentials
+//! - `storage_s3_endpoint`: an S3-compatible service's URL, e.g. `http://minio:9000`
+//!   (default AWS's endpoint for the region), and `storage_s3_path_style` to
+//!   address buckets in the path
+//! - `storage_url_ttl`: how long download URLs last, in seconds (default
+//!   [`DEFAULT_URL_TTL_SECONDS`])
+//! - `storage_url_key`: the secret signing local download URLs; without one a
+//!   random key is made at startup, so URLs stop working on restart
+
+use std::{path::PathBuf, time::Duration};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use neems_data::storage::{BlobStore, S3Config, StorageConfig, UrlSigner};
+use rand::RngCore;
+use rocket::{fairing::AdHoc, figment::Figment};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+pub const DEFAULT_URL_TTL_SECONDS: u64 = 3600;
+
+/// A stored export and where to download it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct BlobLink {
+    pub key: String,
+    /// Where anyone with the link can download it until `expires_at`
+    pub url: String,
+    #[ts(type = "string")]
+    pub expires_at: NaiveDateTime,
+}
+
+/// The configured blob store, and the signing of its download URLs.
+pub struct Storage {
+    store: BlobStore,
+    signer: UrlSigner,
+    url_ttl: Duration,
+}
+
+impl Storage {
+    pub fn new(store: BlobStore, signer: UrlSigner, url_ttl: Duration) -> Self {
+        Storage { store, signer, url_ttl }
+    }
+
+    /// The storage as configured by the `storage_` settings.
+    pub fn from_figment(figment: &Figment) -> Result<Self, String> {
+        let setting = |name: &str| figment.extract_inner::<String>(name).ok();
+        let config = match setting("storage_backend").as_deref().unwrap_or("local") {
+            "local" => StorageConfig::Local {
+                path: setting("storage_path")
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| "storage".into()),
+            },
+            "s3" => StorageConfig::S3(S3Config {
+                endpoint: setting("storage_s3_endpoint"),
+                bucket: setting("storage_s3_bucket").unwrap_or_default(),
+                region: setting("storage_s3_region").unwrap_or_else(|| "us-east-1".to_string()),
+                access_key_id: setting("storage_s3_access_key_id").unwrap_or_default(),
+                secret_access_key: setting("storage_s3_secret_access_key").unwrap_or_default(),
+                path_style: figment.extract_inner::<bool>("storage_s3_path_style").unwrap_or(false),
+            }),
+            other => return Err(format!("Unknown storage_backend: {}", other)),
+        };
+        let store = BlobStore::new(config).map_err(|e| e.to_string())?;
+        let signer = match setting("storage_url_key") {
+            Some(key) => UrlSigner::new(key.as_bytes()),
+            None => {
+                let mut key = [0u8; 32];
+                rand::rng().fill_bytes(&mut key);
+                UrlSigner::new(&key)
+            }
+        };
+        let url_ttl = figment
+            .extract_inner::<u64>("storage_url_ttl")
+            .unwrap_or(DEFAULT_URL_TTL_SECONDS);
+        Ok(Storage::new(store, signer, Duration::from_secs(url_ttl)))
+    }
+
+    /// Manages the configured storage, failing launch if it's misconfigured.
+    pub fn fairing() -> AdHoc {
+        AdHoc::try_on_ignite("Blob Storage", |rocket| async {
+            match Storage::from_figment(rocket.figment()) {
+                Ok(storage) => Ok(rocket.manage(storage)),
+                Err(e) => {
+                    error!("Blob storage is misconfigured: {}", e);
+                    Err(rocket)
+                }
+            }
+        })
+    }
+
+    pub fn store(&self) -> &BlobStore {
+        &self.store
+    }
+
+    /// A link to download `key` from, lasting the configured time from
+    /// `now`.
+    pub fn link(&self, key: &str, now: DateTime<Utc>) -> Result<BlobLink, String> {
+        let expires_at = now + self.url_ttl;
+        let url = match self.store.presigned_url(key, self.url_ttl, now) {
+            Ok(Some(url)) => url,
+            Ok(None) => {
+                let expires = expires_at.timestamp();
+                format!(
+                    "/api/1/Blobs/{}?expires={}&signature={}",
+                    key,
+                    expires,
+                    self.signer.sign(key, expires)
+                )
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+        Ok(BlobLink {
+            key: key.to_string(),
+            url,
+            expires_at: expires_at.naive_utc(),
+        })
+    }
+
+    /// Whether a local download URL's `expires` and `signature` are good for
+    /// `key` at `now`.
+    pub fn verify(&self, key: &str, expires: i64, signature: &str, now: DateTime<Utc>) -> bool {
+        matches!(self.store, BlobStore::Local(_))
+            && self.signer.verify(key, expires, signature, now)
+    }
+}
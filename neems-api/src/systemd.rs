@@ -0,0 +1,212 @@
+//! systemd integration (the `systemd` feature).
+//!
+//! - `READY=1` once Rocket is serving and `STOPPING=1` at shutdown, for
+//!   `Type=notify` units.
+//! - `WATCHDOG=1` keep-alives when the unit sets `WatchdogSec=`, sent only
+//!   while the API can still get a database connection and run a query, so a
+//!   wedged pool gets the service restarted.
+//! - Socket activation: with a `.socket` unit, systemd hands the API its
+//!   listening socket. Rocket 0.5 only serves listeners it binds itself, so
+//!   Rocket then listens on an ephemeral loopback port and connections on the
+//!   activated socket are forwarded to it. The forwarder passes the client
+//!   address in Rocket's `ip_header` (`X-Real-IP` unless configured otherwise),
+//!   so [`client_ip`](rocket::Request::client_ip) still sees the real client
+//!   for login history and network allowlists. When `ip_header` is disabled, a
+//!   client-supplied header is replaced rather than trusted.
+//!
+//! Socket activation can't be combined with native TLS (see [`crate::tls`]);
+//! terminate TLS in front of the activated socket instead.
+
+use std::{
+    convert::Infallible,
+    net::{SocketAddr, TcpListener},
+    os::fd::FromRawFd,
+    time::Duration,
+};
+
+use diesel::{RunQueryDsl, sql_query};
+use hyper::{
+    Body, Client, Request, Response, Server, StatusCode,
+    header::{HeaderName, HeaderValue},
+    server::conn::AddrStream,
+    service::{make_service_fn, service_fn},
+};
+use neems_data::systemd::{self as notify, Liveness};
+use rocket::{
+    Build, Orbit, Rocket,
+    fairing::AdHoc,
+    figment::{Figment, value::Value},
+};
+
+use crate::DbConn;
+
+const DEFAULT_IP_HEADER: &str = "X-Real-IP";
+
+/// Headers that describe the client's connection to the forwarder rather
+/// than the request.
+const HOP_BY_HOP_HEADERS: [&str; 3] = ["connection", "keep-alive", "proxy-connection"];
+
+/// The listening socket systemd passed in, and how to tell Rocket who
+/// connected to it.
+pub struct SocketActivation {
+    listener: TcpListener,
+    ip_header: HeaderName,
+    replace_ip_header: bool,
+}
+
+/// Take the socket systemd passed in, if any, and bind Rocket to a loopback
+/// port for it to be forwarded to.
+pub fn socket_activation(figment: Figment) -> Result<(Figment, Option<SocketActivation>), String> {
+    let fds: Vec<_> = sd_notify::listen_fds()
+        .map_err(|e| format!("Invalid socket activation environment: {}", e))?
+        .collect();
+    let Some(&fd) = fds.first() else {
+        return Ok((figment, None));
+    };
+    if fds.len() > 1 {
+        warn!("systemd passed {} sockets; only the first is served", fds.len());
+    }
+    if figment.contains("tls.certs") {
+        return Err("Socket activation can't be combined with native TLS".to_string());
+    }
+
+    // SAFETY: systemd passes listening sockets starting at fd 3, owned by
+    // this process and used nowhere else.
+    let listener = unsafe { TcpListener::from_raw_fd(fd) };
+    listener.set_nonblocking(true).map_err(|e| format!("Activated socket: {}", e))?;
+
+    let (ip_header, replace_ip_header) = match figment.find_value("ip_header") {
+        Ok(Value::String(_, name)) => (name, false),
+        _ => (DEFAULT_IP_HEADER.to_string(), true),
+    };
+    let ip_header = HeaderName::try_from(ip_header.as_str())
+        .map_err(|e| format!("Invalid ip_header '{}': {}", ip_header, e))?;
+
+    let figment = figment
+        .merge(("address", "127.0.0.1"))
+        .merge(("port", 0))
+        .merge(("ip_header", ip_header.as_str()));
+    Ok((figment, Some(SocketActivation { listener, ip_header, replace_ip_header })))
+}
+
+/// Notify systemd of readiness and shutdown, send watchdog keep-alives and
+/// serve the activated socket, if any.
+pub fn attach(rocket: Rocket<Build>, activation: Option<SocketActivation>) -> Rocket<Build> {
+    rocket
+        .attach(AdHoc::on_liftoff("systemd Ready", |rocket| {
+            Box::pin(async move {
+                if let Some(activation) = activation {
+                    if let Err(e) = serve_activated_socket(rocket, activation) {
+                        error!("Failed to serve the activated socket: {}", e);
+                        rocket.shutdown().notify();
+                        return;
+                    }
+                }
+                if let Some(timeout) = notify::watchdog_timeout() {
+                    start_watchdog(rocket, timeout);
+                }
+                notify::notify_ready();
+            })
+        }))
+        .attach(AdHoc::on_shutdown("systemd Stopping", |_| {
+            Box::pin(async move { notify::notify_stopping() })
+        }))
+}
+
+fn start_watchdog(rocket: &Rocket<Orbit>, timeout: Duration) {
+    let Some(pool) = DbConn::pool(rocket).cloned() else {
+        warn!("No database pool; systemd watchdog keep-alives disabled");
+        return;
+    };
+    info!("systemd watchdog enabled ({}s)", timeout.as_secs());
+
+    let liveness = Liveness::new();
+    let heartbeat = liveness.register("database");
+    rocket::tokio::spawn(async move {
+        loop {
+            if let Some(conn) = pool.get().await {
+                if conn.run(|c| sql_query("SELECT 1").execute(c)).await.is_ok() {
+                    heartbeat.beat();
+                }
+            }
+            rocket::tokio::time::sleep(timeout / 4).await;
+        }
+    });
+    rocket::tokio::spawn(notify::run_watchdog(liveness, timeout));
+}
+
+fn serve_activated_socket(
+    rocket: &Rocket<Orbit>,
+    activation: SocketActivation,
+) -> Result<(), hyper::Error> {
+    let upstream = SocketAddr::new(rocket.config().address, rocket.config().port);
+    let SocketActivation { listener, ip_header, replace_ip_header } = activation;
+    if let Ok(addr) = listener.local_addr() {
+        info!("Serving systemd socket {} (forwarded to {})", addr, upstream);
+    }
+
+    let client = Client::new();
+    let make_service = make_service_fn(move |conn: &AddrStream| {
+        let peer = conn.remote_addr();
+        let client = client.clone();
+        let ip_header = ip_header.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                forward(client.clone(), upstream, peer, ip_header.clone(), replace_ip_header, req)
+            }))
+        }
+    });
+    let server = Server::from_tcp(listener)?
+        .http1_only(true)
+        .serve(make_service)
+        .with_graceful_shutdown(rocket.shutdown());
+    rocket::tokio::spawn(async move {
+        if let Err(e) = server.await {
+            error!("Activated socket stopped serving: {}", e);
+        }
+    });
+    Ok(())
+}
+
+async fn forward(
+    client: Client<hyper::client::HttpConnector>,
+    upstream: SocketAddr,
+    peer: SocketAddr,
+    ip_header: HeaderName,
+    replace_ip_header: bool,
+    mut req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let path = req.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    *req.uri_mut() = match format!("http://{}{}", upstream, path).parse() {
+        Ok(uri) => uri,
+        Err(_) => return Ok(status_response(StatusCode::BAD_REQUEST)),
+    };
+
+    let headers = req.headers_mut();
+    for name in HOP_BY_HOP_HEADERS {
+        headers.remove(name);
+    }
+    if replace_ip_header || !headers.contains_key(&ip_header) {
+        let peer_ip = peer.ip().to_canonical().to_string();
+        headers.insert(ip_header, HeaderValue::from_str(&peer_ip).expect("IP address header"));
+    }
+
+    match client.request(req).await {
+        Ok(mut response) => {
+            for name in HOP_BY_HOP_HEADERS {
+                response.headers_mut().remove(name);
+            }
+            Ok(response)
+        }
+        Err(e) => {
+            error!("Forwarding to {} failed: {}", upstream, e);
+            Ok(status_response(StatusCode::BAD_GATEWAY))
+        }
+    }
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
@@ -0,0 +1,162 @@
+//! Native TLS and client certificate authentication.
+//!
+//! Edge deployments often have no reverse proxy in front of the API, so the
+//! API can terminate TLS itself. It is configured from the environment:
+//!
+//! - `NEEMS_TLS_CERT`: PEM certificate chain to serve
+//! - `NEEMS_TLS_KEY`: PEM private key for that chain
+//! - `NEEMS_TLS_CLIENT_CA`: PEM CA bundle; when set, clients may present a
+//!   certificate signed by one of these CAs
+//! - `NEEMS_TLS_CLIENT_CERT_REQUIRED`: `true` to refuse connections without a
+//!   valid client certificate (default `false`)
+//!
+//! These map onto Rocket's `tls` settings, which can also be given in
+//! `Rocket.toml` or as `ROCKET_TLS`.
+//!
+//! A verified client certificate authenticates as the user it was registered
+//! to (see `/api/1/Users/<id>/ClientCertificates`) when the request carries no
+//! session cookie. Certificates are identified by [`fingerprint`].
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use rocket::{
+    figment::Figment,
+    mtls::x509::{FromDer, X509Certificate},
+};
+use sha2::{Digest, Sha256};
+
+pub const CERT_ENV: &str = "NEEMS_TLS_CERT";
+pub const KEY_ENV: &str = "NEEMS_TLS_KEY";
+pub const CLIENT_CA_ENV: &str = "NEEMS_TLS_CLIENT_CA";
+pub const CLIENT_CERT_REQUIRED_ENV: &str = "NEEMS_TLS_CLIENT_CERT_REQUIRED";
+
+/// TLS settings read from the environment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsSettings {
+    pub cert: String,
+    pub key: String,
+    pub client_ca: Option<String>,
+    pub client_cert_required: bool,
+}
+
+impl TlsSettings {
+    /// The settings from the environment, or `None` when TLS isn't
+    /// configured there.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let var = |name| std::env::var(name).ok().filter(|v: &String| !v.trim().is_empty());
+        Self::from_values(
+            var(CERT_ENV),
+            var(KEY_ENV),
+            var(CLIENT_CA_ENV),
+            var(CLIENT_CERT_REQUIRED_ENV).as_deref(),
+        )
+    }
+
+    fn from_values(
+        cert: Option<String>,
+        key: Option<String>,
+        client_ca: Option<String>,
+        client_cert_required: Option<&str>,
+    ) -> Result<Option<Self>, String> {
+        let client_cert_required = match client_cert_required.map(str::trim) {
+            None => false,
+            Some(v) if v.eq_ignore_ascii_case("true") || v == "1" => true,
+            Some(v) if v.eq_ignore_ascii_case("false") || v == "0" => false,
+            Some(v) => return Err(format!("Invalid {}: {:?}", CLIENT_CERT_REQUIRED_ENV, v)),
+        };
+        let (cert, key) = match (cert, key) {
+            (Some(cert), Some(key)) => (cert, key),
+            (None, None) if client_ca.is_none() => return Ok(None),
+            _ => {
+                return Err(format!("{} and {} must both be set to enable TLS", CERT_ENV, KEY_ENV));
+            }
+        };
+        if client_cert_required && client_ca.is_none() {
+            return Err(format!("{} requires {}", CLIENT_CERT_REQUIRED_ENV, CLIENT_CA_ENV));
+        }
+        Ok(Some(Self {
+            cert,
+            key,
+            client_ca,
+            client_cert_required,
+        }))
+    }
+
+    /// `figment` with these settings as its `tls` configuration.
+    pub fn merge_into(&self, figment: Figment) -> Figment {
+        let figment = figment.merge(("tls.certs", &self.cert)).merge(("tls.key", &self.key));
+        match &self.client_ca {
+            Some(ca) => figment
+                .merge(("tls.mutual.ca_certs", ca))
+                .merge(("tls.mutual.mandatory", self.client_cert_required)),
+            None => figment,
+        }
+    }
+}
+
+/// SHA-256 fingerprint of a DER-encoded certificate, as lowercase hex.
+pub fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Normalize a fingerprint as commonly printed (`AB:CD:...`, any case) to
+/// the form returned by [`fingerprint`].
+pub fn normalize_fingerprint(s: &str) -> Option<String> {
+    let hex: String = s.chars().filter(|c| !matches!(c, ':' | ' ')).collect();
+    (hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .then(|| hex.to_ascii_lowercase())
+}
+
+/// Decode the first certificate of a PEM bundle, returning its fingerprint
+/// and subject.
+pub fn parse_pem_certificate(pem: &str) -> Result<(String, String), String> {
+    let body: String = pem
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| *line != "-----BEGIN CERTIFICATE-----")
+        .skip(1)
+        .take_while(|line| *line != "-----END CERTIFICATE-----")
+        .collect();
+    if body.is_empty() {
+        return Err("No PEM certificate found".to_string());
+    }
+    let der = STANDARD.decode(body).map_err(|e| format!("Invalid PEM certificate: {}", e))?;
+    let (_, certificate) =
+        X509Certificate::from_der(&der).map_err(|e| format!("Invalid X.509 certificate: {}", e))?;
+    Ok((fingerprint(&der), certificate.subject().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_require_cert_and_key() {
+        let s = |v: &str| Some(v.to_string());
+        assert_eq!(TlsSettings::from_values(None, None, None, None), Ok(None));
+        assert!(TlsSettings::from_values(s("cert.pem"), None, None, None).is_err());
+        assert!(TlsSettings::from_values(None, None, s("ca.pem"), None).is_err());
+        assert!(
+            TlsSettings::from_values(s("cert.pem"), s("key.pem"), None, Some("true")).is_err(),
+            "required client certificates need a CA"
+        );
+
+        let settings =
+            TlsSettings::from_values(s("cert.pem"), s("key.pem"), s("ca.pem"), Some("1"))
+                .unwrap()
+                .unwrap();
+        assert!(settings.client_cert_required);
+        let figment = settings.merge_into(Figment::new());
+        assert_eq!(figment.extract_inner::<String>("tls.mutual.ca_certs").unwrap(), "ca.pem");
+        assert!(figment.extract_inner::<bool>("tls.mutual.mandatory").unwrap());
+    }
+
+    #[test]
+    fn test_normalize_fingerprint() {
+        let hex = "ab".repeat(32);
+        let colons = vec!["AB"; 32].join(":");
+        assert_eq!(normalize_fingerprint(&colons).as_deref(), Some(hex.as_str()));
+        assert_eq!(normalize_fingerprint(&hex).as_deref(), Some(hex.as_str()));
+        assert_eq!(normalize_fingerprint("abcd"), None);
+        assert_eq!(normalize_fingerprint(&"zz".repeat(32)), None);
+    }
+}
@@ -0,0 +1,51 @@
+//! Integration tests for the server-rendered admin status page.
+
+use neems_api::{SiteDbConn, orm::testing::fast_test_rocket};
+use neems_data::status::{StatusBoard, save_status};
+use rocket::{
+    http::{ContentType, Status},
+    local::asynchronous::Client,
+    tokio,
+};
+use serde_json::json;
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+#[tokio::test]
+async fn status_page_shows_databases_and_monitor() {
+    let rocket = fast_test_rocket().ignite().await.expect("ignite");
+    let site_db = SiteDbConn::get_one(&rocket).await.expect("site database");
+    site_db
+        .run(|conn| save_status(conn, &StatusBoard::new().snapshot()))
+        .await
+        .unwrap();
+    let client = Client::tracked(rocket).await.unwrap();
+    let admin = login_as(&client, "newtown_superadmin@example.com", "newtownpass").await;
+
+    let resp = client.get("/admin/status").cookie(admin).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    assert_eq!(resp.content_type(), Some(ContentType::HTML));
+    let page = resp.into_string().await.expect("page");
+    assert!(page.contains("<h2>Databases</h2>"), "{}", page);
+    assert!(page.contains("Site data"), "{}", page);
+    // Published but never ticked: the scheduler hasn't come round
+    assert!(page.contains("stalled: the scheduler isn't ticking"), "{}", page);
+    assert!(page.contains("signed in as newtown_superadmin@example.com"), "{}", page);
+}
+
+#[tokio::test]
+async fn status_page_requires_newtown_admin() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+
+    let resp = client.get("/admin/status").dispatch().await;
+    assert_eq!(resp.status(), Status::Unauthorized);
+
+    let staff = login_as(&client, "newtown_staff@example.com", "newtownstaffpass").await;
+    let resp = client.get("/admin/status").cookie(staff).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+}
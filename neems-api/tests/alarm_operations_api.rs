@@ -0,0 +1,188 @@
+//! Integration tests for acknowledging, shelving and commenting on alarms.
+
+use chrono::{Duration, Utc};
+use neems_api::{
+    SiteDbConn,
+    models::{AlarmAction, AlarmState},
+    orm::testing::fast_test_rocket,
+};
+use neems_data::models::{NewReading, NewSource};
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::{Value, json};
+
+/// Relay 86 M1 set, a level 2 alarm
+const RELAY_ALARM: u64 = 103;
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+/// Records an RTAC reading `seconds_ago`, with the relay alarm set or not.
+async fn record_reading(site_db: &SiteDbConn, source_id: i32, seconds_ago: i64, alarm: bool) {
+    site_db
+        .run(move |conn| {
+            let mut registers = vec![0u16; 22];
+            if alarm {
+                registers[1] = 1 << 2;
+            }
+            let reading = NewReading {
+                source_id,
+                timestamp: Some(Utc::now().naive_utc() - Duration::seconds(seconds_ago)),
+                data: json!({ "alarm_registers": registers }).to_string(),
+                quality_flags: None,
+                device_timestamp: None,
+            };
+            neems_data::insert_readings_batch(conn, vec![reading]).expect("insert reading");
+        })
+        .await;
+}
+
+async fn active_alarms(
+    client: &Client,
+    session: &rocket::http::Cookie<'static>,
+    url: &str,
+) -> Value {
+    let resp = client.get(url).cookie(session.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    resp.into_json().await.expect("json")
+}
+
+fn alarm_nums(active: &Value) -> Vec<u64> {
+    active["alarms"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|a| a["alarm_num"].as_u64().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn operators_acknowledge_shelve_and_comment_on_alarms() {
+    let rocket = fast_test_rocket().ignite().await.expect("ignite");
+    let site_db = SiteDbConn::get_one(&rocket).await.expect("site database");
+    let source_id = site_db
+        .run(|conn| {
+            let source = neems_data::create_source(
+                conn,
+                NewSource {
+                    name: "RTAC".to_string(),
+                    description: None,
+                    active: Some(true),
+                    interval_seconds: Some(1),
+                    test_type: None,
+                    arguments: None,
+                    site_id: None,
+                    company_id: None,
+                    priority: None,
+                    point_fields: None,
+                },
+            )
+            .expect("create source");
+            source.id.unwrap()
+        })
+        .await;
+    record_reading(&site_db, source_id, 30, true).await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let staff = login_as(&client, "staff@example.com", "staffpass").await;
+    let admin = login_as(&client, "newtown_superadmin@example.com", "newtownpass").await;
+
+    let active = active_alarms(&client, &staff, "/api/1/Alarms/Active").await;
+    assert_eq!(alarm_nums(&active), vec![RELAY_ALARM]);
+    assert_eq!(active["unacknowledged_count"], json!(1));
+    assert_eq!(active["has_critical"], json!(true));
+
+    // Only active, defined alarms can be acknowledged
+    let ack = |num: u64| format!("/api/1/Alarms/{}/Acknowledge", num);
+    let resp = client.post(ack(104)).cookie(staff.clone()).json(&json!({})).dispatch().await;
+    assert_eq!(resp.status(), Status::Conflict);
+    let resp = client.post(ack(9999)).cookie(staff.clone()).json(&json!({})).dispatch().await;
+    assert_eq!(resp.status(), Status::NotFound);
+    let resp = client
+        .post(ack(RELAY_ALARM))
+        .cookie(staff.clone())
+        .json(&json!({ "comment": "Crew dispatched" }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let state: AlarmState = resp.into_json().await.expect("json");
+    assert_eq!(state.acknowledged_by.as_deref(), Some("staff@example.com"));
+    let active = active_alarms(&client, &staff, "/api/1/Alarms/Active").await;
+    assert_eq!(active["alarms"][0]["acknowledged_by"], json!("staff@example.com"));
+    assert_eq!(active["unacknowledged_count"], json!(0));
+
+    // Shelving is for admins, for a limited time
+    let shelve = format!("/api/1/Alarms/{}/Shelve", RELAY_ALARM);
+    let body = json!({ "minutes": 60, "reason": "Relay under test" });
+    let resp = client.post(shelve.clone()).cookie(staff.clone()).json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+    let resp = client
+        .post(shelve.clone())
+        .cookie(admin.clone())
+        .json(&json!({ "minutes": 100000, "reason": "Forever" }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+    let resp = client.post(shelve.clone()).cookie(admin.clone()).json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let state: AlarmState = resp.into_json().await.expect("json");
+    assert!(state.shelved_until.is_some());
+
+    let active = active_alarms(&client, &staff, "/api/1/Alarms/Active").await;
+    assert!(alarm_nums(&active).is_empty());
+    assert_eq!(active["shelved_count"], json!(1));
+    assert_eq!(active["has_critical"], json!(false));
+    let active = active_alarms(&client, &staff, "/api/1/Alarms/Active?include_shelved=true").await;
+    assert_eq!(alarm_nums(&active), vec![RELAY_ALARM]);
+    assert!(active["alarms"][0]["shelved_until"].is_string());
+    let resp = client.get("/api/1/Alarms/Shelved").cookie(staff.clone()).dispatch().await;
+    let shelved: Vec<AlarmState> = resp.into_json().await.expect("json");
+    assert_eq!(shelved.len(), 1);
+
+    // Anyone can comment; the log has every action, newest first
+    let comments = format!("/api/1/Alarms/{}/Comments", RELAY_ALARM);
+    let resp = client
+        .post(comments.clone())
+        .cookie(staff.clone())
+        .json(&json!({ "comment": "  " }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+    let resp = client
+        .post(comments)
+        .cookie(staff.clone())
+        .json(&json!({ "comment": "Relay tested OK" }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let resp = client
+        .get(format!("/api/1/Alarms/{}/Actions", RELAY_ALARM))
+        .cookie(staff.clone())
+        .dispatch()
+        .await;
+    let actions: Vec<AlarmAction> = resp.into_json().await.expect("json");
+    assert_eq!(
+        actions.iter().map(|a| a.action.as_str()).collect::<Vec<_>>(),
+        vec!["comment", "shelve", "acknowledge"]
+    );
+    assert_eq!(actions[1].user_email, "newtown_superadmin@example.com");
+    assert_eq!(actions[2].comment.as_deref(), Some("Crew dispatched"));
+
+    let resp = client.delete(shelve.clone()).cookie(admin.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let resp = client.delete(shelve).cookie(admin).dispatch().await;
+    assert_eq!(resp.status(), Status::Conflict);
+
+    // Once the alarm clears, a recurrence needs acknowledging again
+    record_reading(&SiteDbConn::get_one(client.rocket()).await.unwrap(), source_id, 20, false)
+        .await;
+    let active = active_alarms(&client, &staff, "/api/1/Alarms/Active").await;
+    assert!(alarm_nums(&active).is_empty());
+    record_reading(&SiteDbConn::get_one(client.rocket()).await.unwrap(), source_id, 10, true).await;
+    let active = active_alarms(&client, &staff, "/api/1/Alarms/Active").await;
+    assert_eq!(alarm_nums(&active), vec![RELAY_ALARM]);
+    assert_eq!(active["alarms"][0]["acknowledged_at"], Value::Null);
+    assert_eq!(active["unacknowledged_count"], json!(1));
+}
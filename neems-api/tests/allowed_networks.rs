@@ -0,0 +1,169 @@
+//! Network allowlist tests
+//!
+//! Covers /api/1/Companies/{id}/AllowedNetworks and
+//! /api/1/Users/{id}/ClientCertificates/{id}/AllowedNetworks, and their
+//! enforcement in the authentication guard. Requests set their remote address
+//! explicitly; the client is untracked so cookies are only sent when given.
+
+use std::net::SocketAddr;
+
+use neems_api::orm::testing::fast_test_rocket;
+use rocket::{
+    http::{ContentType, Cookie, Status},
+    local::asynchronous::Client,
+};
+use serde_json::{Value, json};
+
+const CLIENT_CERT: &str = include_str!("fixtures/rtac-client.pem");
+
+const SITE_ADDRESS: &str = "10.20.1.5:40000";
+const OUTSIDE_ADDRESS: &str = "203.0.113.9:40000";
+
+/// Log in as a golden DB user, returning the session cookie and user id
+async fn login(client: &Client, email: &str, password: &str) -> (Cookie<'static>, i32) {
+    let response = client
+        .post("/api/1/login")
+        .header(ContentType::JSON)
+        .body(json!({ "email": email, "password": password }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let cookie = response.cookies().get("session").expect("session cookie").clone().into_owned();
+    let body: Value = response.into_json().await.expect("login response");
+    (cookie, body["user_id"].as_i64().expect("user_id") as i32)
+}
+
+fn address(s: &str) -> SocketAddr {
+    s.parse().unwrap()
+}
+
+#[tokio::test]
+async fn test_company_allowlist_restricts_its_users() {
+    let client = Client::untracked(fast_test_rocket()).await.expect("valid rocket instance");
+    let (admin_cookie, _) = login(&client, "superadmin@example.com", "admin").await;
+    let (user_cookie, user_id) = login(&client, "testuser@example.com", "admin").await;
+    let roles_url = format!("/api/1/Users/{}/Roles", user_id);
+
+    let response = client.get(format!("/api/1/Users/{}", user_id)).cookie(admin_cookie.clone());
+    let user: Value = response.dispatch().await.into_json().await.expect("user JSON");
+    let networks_url = format!("/api/1/Companies/{}/AllowedNetworks", user["company_id"]);
+
+    // Unrestricted until a list is set
+    let response = client.get(&networks_url).cookie(user_cookie.clone()).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_json::<Value>().await.unwrap(), json!({ "networks": [] }));
+
+    // Staff can't change the list
+    let response = client
+        .put(&networks_url)
+        .cookie(user_cookie.clone())
+        .header(ContentType::JSON)
+        .body(json!({ "networks": ["10.20.0.0/16"] }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let response = client
+        .put(&networks_url)
+        .cookie(admin_cookie.clone())
+        .header(ContentType::JSON)
+        .body(json!({ "networks": ["not-a-network"] }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let response = client
+        .put(&networks_url)
+        .cookie(admin_cookie.clone())
+        .header(ContentType::JSON)
+        .body(json!({ "networks": ["10.20.0.0/16", "192.168.50.7"] }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.into_json::<Value>().await.unwrap(),
+        json!({ "networks": ["10.20.0.0/16", "192.168.50.7/32"] })
+    );
+
+    let request = |remote: Option<&str>| {
+        let request = client.get(&roles_url).cookie(user_cookie.clone());
+        match remote {
+            Some(remote) => request.remote(address(remote)),
+            None => request,
+        }
+    };
+    assert_eq!(request(Some(SITE_ADDRESS)).dispatch().await.status(), Status::Ok);
+    assert_eq!(request(Some(OUTSIDE_ADDRESS)).dispatch().await.status(), Status::Forbidden);
+    assert_eq!(request(None).dispatch().await.status(), Status::Forbidden);
+
+    // Users of other companies are unaffected
+    let response = client
+        .get(&roles_url)
+        .cookie(admin_cookie.clone())
+        .remote(address(OUTSIDE_ADDRESS))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .put(&networks_url)
+        .cookie(admin_cookie)
+        .header(ContentType::JSON)
+        .body(json!({ "networks": [] }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(request(Some(OUTSIDE_ADDRESS)).dispatch().await.status(), Status::Ok);
+}
+
+#[tokio::test]
+async fn test_client_certificate_allowlist() {
+    let client = Client::untracked(fast_test_rocket()).await.expect("valid rocket instance");
+    let (admin_cookie, _) = login(&client, "superadmin@example.com", "admin").await;
+    let (_, user_id) = login(&client, "testuser@example.com", "admin").await;
+    let roles_url = format!("/api/1/Users/{}/Roles", user_id);
+    let certs_url = format!("/api/1/Users/{}/ClientCertificates", user_id);
+
+    let response = client
+        .post(&certs_url)
+        .cookie(admin_cookie.clone())
+        .header(ContentType::JSON)
+        .body(
+            json!({ "certificate_pem": CLIENT_CERT, "allowed_networks": ["10.20.0.0/16"] })
+                .to_string(),
+        )
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+    let certificate: Value = response.into_json().await.expect("certificate JSON");
+    let networks_url = format!("{}/{}/AllowedNetworks", certs_url, certificate["id"]);
+
+    let request = |remote: &str| {
+        client.get(&roles_url).identity(CLIENT_CERT.as_bytes()).remote(address(remote))
+    };
+    assert_eq!(request(SITE_ADDRESS).dispatch().await.status(), Status::Ok);
+    assert_eq!(request(OUTSIDE_ADDRESS).dispatch().await.status(), Status::Forbidden);
+
+    let response = client.get(&networks_url).cookie(admin_cookie.clone()).dispatch().await;
+    assert_eq!(
+        response.into_json::<Value>().await.unwrap(),
+        json!({ "networks": ["10.20.0.0/16"] })
+    );
+
+    let response = client
+        .put(&networks_url)
+        .cookie(admin_cookie.clone())
+        .header(ContentType::JSON)
+        .body(json!({ "networks": [] }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(request(OUTSIDE_ADDRESS).dispatch().await.status(), Status::Ok);
+
+    let response = client
+        .get(format!("{}/999999/AllowedNetworks", certs_url))
+        .cookie(admin_cookie)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+}
@@ -0,0 +1,141 @@
+//! Integration tests for API versions: version 2 inheriting version 1's
+//! routes, and deprecation headers on deprecated versions and routes.
+
+use neems_api::{
+    api::v2::user::User as UserV2,
+    api_version::{ApiVersion, ApiVersioning, VersionStatus},
+    orm::testing::{fast_test_rocket, golden_fixtures},
+};
+use rocket::{
+    http::Status,
+    local::asynchronous::{Client, LocalResponse},
+    tokio,
+};
+use serde_json::{Value, json};
+
+async fn login_as(
+    client: &Client,
+    email: &str,
+    password: &str,
+) -> Option<rocket::http::Cookie<'static>> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    if resp.status() != Status::Ok {
+        return None;
+    }
+    resp.cookies().get("session").map(|cookie| cookie.clone().into_owned())
+}
+
+fn header<'a>(resp: &'a LocalResponse<'_>, name: &str) -> Option<&'a str> {
+    resp.headers().get_one(name)
+}
+
+#[tokio::test]
+async fn version_2_creates_users_from_passwords() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let company_id = golden_fixtures().company_id("Test Company 1");
+    let admin = login_as(&client, "superadmin@example.com", "admin").await.expect("login");
+
+    let resp = client
+        .post("/api/2/Users")
+        .cookie(admin.clone())
+        .json(&json!({
+            "email": "v2user@test.com",
+            "password": "v2 password",
+            "company_id": company_id,
+            "totp_secret": null,
+            "role_names": ["staff"]
+        }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    assert_eq!(header(&resp, "Deprecation"), None);
+    let body: Value = resp.into_json().await.expect("json");
+    assert!(body.get("password_hash").is_none());
+    assert!(body.get("totp_secret").is_none());
+    let user: UserV2 = serde_json::from_value(body).expect("UserV2");
+    assert_eq!(user.email, "v2user@test.com");
+    assert_eq!(user.roles.len(), 1);
+
+    // The server hashed the password
+    assert!(login_as(&client, "v2user@test.com", "v2 password").await.is_some());
+
+    // Empty passwords are refused
+    let resp = client
+        .post("/api/2/Users")
+        .cookie(admin.clone())
+        .json(&json!({
+            "email": "v2empty@test.com",
+            "password": " ",
+            "company_id": company_id,
+            "totp_secret": null,
+            "role_names": ["staff"]
+        }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+
+    // Version 1's endpoint still works, flagged as deprecated
+    let resp = client
+        .post("/api/1/Users")
+        .cookie(admin.clone())
+        .json(&json!({
+            "email": "v1user@test.com",
+            "password_hash": "hashed_pw",
+            "company_id": company_id,
+            "totp_secret": null,
+            "role_names": ["staff"]
+        }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    assert_eq!(header(&resp, "Deprecation"), Some("@1792195200"));
+    assert_eq!(header(&resp, "Link"), Some("</api/2/Users>; rel=\"successor-version\""));
+    assert_eq!(header(&resp, "Sunset"), None);
+
+    // The rest of version 1 is served under version 2 too
+    let resp = client.get(format!("/api/2/Users/{}", user.id)).cookie(admin).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    assert_eq!(header(&resp, "Deprecation"), None);
+    let body: Value = resp.into_json().await.expect("json");
+    assert_eq!(body["email"], "v2user@test.com");
+
+    let resp = client.get("/api/2/Versions").dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let body: Value = resp.into_json().await.expect("json");
+    assert_eq!(body["versions"][0]["version"], 1);
+    assert_eq!(body["deprecated_routes"][0]["successor"], "/api/2/Users");
+}
+
+#[tokio::test]
+async fn deprecated_versions_carry_sunset_headers() {
+    let versions = vec![
+        ApiVersion {
+            version: 1,
+            status: VersionStatus::Deprecated,
+            deprecated: Some("2026-10-17"),
+            sunset: Some("2027-06-30"),
+        },
+        ApiVersion {
+            version: 2,
+            status: VersionStatus::Current,
+            deprecated: None,
+            sunset: None,
+        },
+    ];
+    let client =
+        Client::tracked(fast_test_rocket().attach(ApiVersioning::new(versions, Vec::new())))
+            .await
+            .unwrap();
+
+    let resp = client.get("/api/1/status").dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    assert_eq!(header(&resp, "Deprecation"), Some("@1792195200"));
+    assert_eq!(header(&resp, "Sunset"), Some("Wed, 30 Jun 2027 00:00:00 GMT"));
+    assert_eq!(header(&resp, "Link"), Some("</api/2>; rel=\"successor-version\""));
+
+    let resp = client.get("/api/2/status").dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    assert_eq!(header(&resp, "Deprecation"), None);
+    assert_eq!(header(&resp, "Sunset"), None);
+}
@@ -2,8 +2,8 @@ use std::collections::HashMap;
 
 use neems_api::{
     models::{
-        ApplicationRule, CalendarDaySchedule, EffectiveScheduleResponse, RuleType,
-        ScheduleLibraryItem,
+        ApplicationRule, CalendarDaySchedule, EffectiveScheduleResponse, OverridePriority,
+        RuleType, ScheduleLibraryItem,
     },
     orm::testing::fast_test_rocket,
 };
@@ -409,6 +409,104 @@ async fn test_effective_schedule_precedence() {
     assert_eq!(effective.specificity, 2); // Specific date
 }
 
+#[rocket::async_test]
+async fn test_higher_priority_overrides_preempt_lower_ones() {
+    let client = Client::tracked(fast_test_rocket()).await.expect("valid rocket instance");
+    let admin_cookie = login_admin(&client).await;
+
+    let planned = create_library_item(&client, &admin_cookie, "Planned Outage").await;
+    let hold = create_library_item(&client, &admin_cookie, "Safety Hold").await;
+    let late = create_library_item(&client, &admin_cookie, "Late Change").await;
+    let override_rule = |priority: &str| {
+        json!({
+            "rule_type": "specific_date",
+            "specific_dates": ["2025-01-13"],
+            "priority": priority
+        })
+    };
+
+    let url = format!("/api/1/ScheduleLibraryItems/{}/ApplicationRules", planned.id);
+    let response = client
+        .post(&url)
+        .cookie(admin_cookie.clone())
+        .json(&override_rule("operator"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+
+    // Only specific-date rules go above the operator layer
+    let url = format!("/api/1/ScheduleLibraryItems/{}/ApplicationRules", hold.id);
+    let response = client
+        .post(&url)
+        .cookie(admin_cookie.clone())
+        .json(&json!({ "rule_type": "default", "priority": "safety" }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::BadRequest);
+
+    // Company admins can't open a higher layer
+    let login = json!({ "email": "admin@company1.com", "password": "admin" });
+    let response = client.post("/api/1/login").json(&login).dispatch().await;
+    let company_admin = response.cookies().get("session").expect("session").clone().into_owned();
+    let response = client
+        .post(&url)
+        .cookie(company_admin.clone())
+        .json(&override_rule("safety"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let response = client
+        .post(&url)
+        .cookie(admin_cookie.clone())
+        .json(&override_rule("safety"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+    let safety: ApplicationRule = response.into_json().await.expect("valid JSON");
+    assert_eq!(safety.priority, OverridePriority::Safety);
+
+    let response = client
+        .get("/api/1/Sites/1/EffectiveSchedule?date=2025-01-13")
+        .cookie(admin_cookie.clone())
+        .dispatch()
+        .await;
+    let effective: EffectiveScheduleResponse = response.into_json().await.expect("valid JSON");
+    assert_eq!(effective.library_item.id, hold.id);
+    assert_eq!(effective.priority, OverridePriority::Safety);
+
+    // A newer operator override can't take the held date, whoever asks
+    let url = format!("/api/1/ScheduleLibraryItems/{}/ApplicationRules", late.id);
+    for cookie in [&admin_cookie, &company_admin] {
+        let response = client
+            .post(&url)
+            .cookie(cookie.clone())
+            .json(&override_rule("operator"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Conflict);
+        let body: serde_json::Value = response.into_json().await.expect("valid JSON");
+        let error = body["error"].as_str().unwrap();
+        assert!(error.contains("2025-01-13") && error.contains("safety"), "{}", error);
+    }
+
+    // Only Newtown staff can lift the hold, which lets the operator layer back
+    let url = format!("/api/1/ApplicationRules/{}", safety.id);
+    let response = client.delete(&url).cookie(company_admin).dispatch().await;
+    assert_eq!(response.status(), Status::Forbidden);
+    let response = client.delete(&url).cookie(admin_cookie.clone()).dispatch().await;
+    assert_eq!(response.status(), Status::NoContent);
+
+    let response = client
+        .get("/api/1/Sites/1/EffectiveSchedule?date=2025-01-13")
+        .cookie(admin_cookie.clone())
+        .dispatch()
+        .await;
+    let effective: EffectiveScheduleResponse = response.into_json().await.expect("valid JSON");
+    assert_eq!(effective.library_item.id, planned.id);
+    assert_eq!(effective.priority, OverridePriority::Operator);
+}
+
 #[rocket::async_test]
 async fn test_calendar_schedules() {
     let client = Client::tracked(fast_test_rocket()).await.expect("valid rocket instance");
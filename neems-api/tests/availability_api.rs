@@ -0,0 +1,164 @@
+//! Integration tests for site and device availability.
+
+use chrono::{Duration, NaiveDate, Utc};
+use diesel::prelude::*;
+use neems_api::{
+    SiteDbConn,
+    api::{availability::AvailabilityResponse, economics::EconomicsResponse},
+    orm::testing::{fast_test_rocket, golden_fixtures},
+};
+use neems_data::{
+    models::{NewReading, NewSource},
+    schema::maintenance_windows,
+};
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::json;
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+/// A client whose site data has two batteries on `site_id` reporting every ten
+/// minutes of `day`: the first all day, with a relay alarm from 12:00 to
+/// 14:00, and the second only until 12:00, when a maintenance window on
+/// `device_id` opens. Returns the sources' ids.
+async fn client_with_readings(site_id: i32, device_id: i32, day: NaiveDate) -> (Client, i32, i32) {
+    let rocket = fast_test_rocket().ignite().await.expect("ignite");
+    let site_db = SiteDbConn::get_one(&rocket).await.expect("site database");
+    let (first, second) = site_db
+        .run(move |conn| {
+            let start = day.and_hms_opt(0, 0, 0).unwrap();
+            let mut source = |name: &str, minutes: i64, fault_from: i64, fault_until: i64| {
+                let source = neems_data::create_source(
+                    conn,
+                    NewSource {
+                        name: name.to_string(),
+                        description: None,
+                        active: Some(true),
+                        interval_seconds: Some(600),
+                        test_type: Some("simulator".to_string()),
+                        arguments: None,
+                        site_id: Some(site_id),
+                        company_id: None,
+                        priority: None,
+                        point_fields: None,
+                    },
+                )
+                .expect("create source");
+                let source_id = source.id.unwrap();
+                let readings = (0..minutes / 10)
+                    .map(|i| {
+                        let mut registers = vec![0u16; 22];
+                        if (fault_from..fault_until).contains(&(i * 10)) {
+                            // Relay 86 M1, a level 2 alarm
+                            registers[1] = 1 << 2;
+                        }
+                        NewReading {
+                            source_id,
+                            timestamp: Some(start + Duration::minutes(i * 10)),
+                            data: json!({ "power_kw": 10.0, "alarm_registers": registers })
+                                .to_string(),
+                            quality_flags: None,
+                            device_timestamp: None,
+                        }
+                    })
+                    .collect();
+                neems_data::insert_readings_batch(conn, readings).expect("insert readings");
+                source_id
+            };
+            let first = source("BESS 1", 24 * 60, 12 * 60, 14 * 60);
+            let second = source("BESS 2", 12 * 60, 0, 0);
+            diesel::insert_into(maintenance_windows::table)
+                .values((
+                    maintenance_windows::site_id.eq(site_id),
+                    maintenance_windows::device_id.eq(Some(device_id)),
+                    maintenance_windows::starts_at.eq(start + Duration::hours(12)),
+                    maintenance_windows::ends_at.eq(start + Duration::hours(24)),
+                    maintenance_windows::reason.eq("Module swap"),
+                    maintenance_windows::created_by.eq("admin@devicetesta.com"),
+                    maintenance_windows::created_at.eq(start),
+                ))
+                .execute(conn)
+                .expect("insert window");
+            (first, second)
+        })
+        .await;
+    (Client::tracked(rocket).await.unwrap(), first, second)
+}
+
+#[tokio::test]
+async fn availability_leaves_out_maintenance_windows() {
+    let fixtures = golden_fixtures();
+    let site_id = fixtures.site_id("Device API Site A");
+    let day = Utc::now().date_naive() - Duration::days(1);
+    let (first_device, second_device) =
+        (fixtures.device_id("SEL-451"), fixtures.device_id("SEL-735"));
+    let (client, first_source, second_source) =
+        client_with_readings(site_id, second_device, day).await;
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+
+    let body = json!({
+        "name": "BESS containers",
+        "members": [
+            { "device_id": first_device, "source_id": first_source },
+            { "device_id": second_device, "source_id": second_source }
+        ]
+    });
+    let resp = client
+        .post(format!("/api/1/Sites/{}/DeviceGroups", site_id))
+        .cookie(admin.clone())
+        .json(&body)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+
+    let url = format!("/api/1/Sites/{}/Availability?from={}&to={}", site_id, day, day);
+    let resp = client.get(url.clone()).cookie(admin.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let report: AvailabilityResponse = resp.into_json().await.expect("json");
+    assert_eq!(report.rows.len(), 1);
+    let row = &report.rows[0];
+    assert_eq!(row.period_start, day);
+
+    // The site is down while the first battery is faulted; the second's
+    // silence is covered by its window
+    let site = row.site.as_ref().unwrap();
+    assert_eq!(
+        (site.available_minutes, site.fault_minutes, site.offline_minutes),
+        (1320, 120, 0)
+    );
+    let device = |id: i32| {
+        let device = row.devices.iter().find(|d| d.device_id == id).unwrap();
+        device.figures.clone().unwrap()
+    };
+    assert_eq!(device(first_device).fault_minutes, 120);
+    let second = device(second_device);
+    assert_eq!((second.available_minutes, second.excluded_minutes), (720, 720));
+    assert_eq!(second.availability_percent, Some(100.0));
+
+    // The monthly economics report carries the site's figure
+    let resp = client
+        .get(format!(
+            "/api/1/Sites/{}/Economics?from={}&to={}&period=month",
+            site_id, day, day
+        ))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    let economics: EconomicsResponse = resp.into_json().await.expect("json");
+    assert_eq!(economics.rows[0].availability_percent, site.availability_percent);
+
+    let resp = client
+        .get(format!("/api/1/Sites/{}/Availability?period=year", site_id))
+        .cookie(admin)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+
+    let other = login_as(&client, "admin@company1.com", "admin").await;
+    let resp = client.get(url).cookie(other).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+}
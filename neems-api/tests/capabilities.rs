@@ -0,0 +1,62 @@
+//! Integration tests for the per-caller capabilities endpoint.
+
+use neems_api::orm::testing::fast_test_rocket;
+use rocket::{
+    http::{ContentType, Status},
+    local::asynchronous::Client,
+};
+use serde_json::{Value, json};
+
+/// Log in and return the caller's capabilities, keyed by entity set.
+async fn capabilities_of(client: &Client, email: &str, password: &str) -> Value {
+    let response = client
+        .post("/api/1/login")
+        .header(ContentType::JSON)
+        .body(json!({ "email": email, "password": password }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.get("/api/1/capabilities").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let body: Value = response.into_json().await.expect("json");
+    let by_name = body["value"]
+        .as_array()
+        .expect("value")
+        .iter()
+        .map(|set| (set["name"].as_str().unwrap().to_string(), set.clone()))
+        .collect::<serde_json::Map<_, _>>();
+    Value::Object(by_name)
+}
+
+#[rocket::async_test]
+async fn test_capabilities_requires_authentication() {
+    let client = Client::tracked(fast_test_rocket()).await.expect("valid rocket instance");
+    let response = client.get("/api/1/capabilities").dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn test_capabilities_follow_roles() {
+    let client = Client::tracked(fast_test_rocket()).await.expect("valid rocket instance");
+
+    let caps = capabilities_of(&client, "superadmin@example.com", "admin").await;
+    assert_eq!(caps["Sites"]["insertable"], "any");
+    assert_eq!(caps["Roles"]["deletable"], "any");
+    assert_eq!(caps["DataSources"]["updatable"], "any");
+    assert_eq!(caps["Readings"]["insertable"], Value::Null);
+
+    let caps = capabilities_of(&client, "newtown_staff@example.com", "newtownstaffpass").await;
+    assert_eq!(caps["Devices"]["deletable"], "any");
+    assert_eq!(caps["Roles"]["insertable"], Value::Null);
+
+    let caps = capabilities_of(&client, "admin_staff@example.com", "adminstaff").await;
+    assert_eq!(caps["Sites"]["updatable"], "company");
+    assert_eq!(caps["Users"]["insertable"], "company");
+    assert_eq!(caps["DataSources"]["insertable"], Value::Null);
+
+    let caps = capabilities_of(&client, "staff@example.com", "staffpass").await;
+    assert_eq!(caps["Sites"]["insertable"], Value::Null);
+    assert_eq!(caps["Users"]["updatable"], "own");
+    assert_eq!(caps["Users"]["deletable"], Value::Null);
+}
@@ -0,0 +1,153 @@
+//! Integration tests for grid carbon intensity in the scheduler and economics.
+
+use chrono::{Duration, NaiveDateTime, Timelike, Utc};
+use neems_api::{
+    SiteDbConn,
+    api::{economics::EconomicsResponse, scheduler::CarbonProfileResponse},
+    orm::testing::{fast_test_rocket, golden_fixtures},
+};
+use neems_data::models::{NewReading, NewSource};
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::json;
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+fn new_source(name: &str, test_type: &str, site_id: i32) -> NewSource {
+    NewSource {
+        name: name.to_string(),
+        description: None,
+        active: Some(true),
+        interval_seconds: Some(900),
+        test_type: Some(test_type.to_string()),
+        arguments: None,
+        site_id: Some(site_id),
+        company_id: None,
+        priority: None,
+        point_fields: None,
+    }
+}
+
+/// A client whose site data has, on `site_id`, a day of hourly carbon
+/// intensity readings ending at the start of the current hour, cleanest two
+/// and three hours ago, and a battery that charged an hour at 100 kW three
+/// hours ago and discharged an hour at 80 kW just now.
+async fn client_with_readings(site_id: i32) -> (Client, NaiveDateTime) {
+    let rocket = fast_test_rocket().ignite().await.expect("ignite");
+    let site_db = SiteDbConn::get_one(&rocket).await.expect("site database");
+    let now = Utc::now().naive_utc();
+    let hour = now.date().and_hms_opt(now.hour(), 0, 0).unwrap();
+    site_db
+        .run(move |conn| {
+            let grid = neems_data::create_source(
+                conn,
+                new_source("Grid carbon", "carbon_intensity", site_id),
+            )
+            .expect("create source");
+            let battery = neems_data::create_source(conn, new_source("Battery", "simulator", site_id))
+                .expect("create source");
+            let reading = |source_id: i32, timestamp: NaiveDateTime, data: serde_json::Value| {
+                NewReading {
+                    source_id,
+                    timestamp: Some(timestamp),
+                    data: data.to_string(),
+                    quality_flags: None,
+                    device_timestamp: None,
+                }
+            };
+
+            let mut readings: Vec<NewReading> = (0..24)
+                .map(|i| {
+                    let intensity = match i {
+                        2 => 90.0,
+                        3 => 100.0,
+                        _ => 400.0 + i as f64,
+                    };
+                    reading(
+                        grid.id.unwrap(),
+                        hour - Duration::hours(i),
+                        json!({ "provider": "electricitymaps", "carbon_intensity_gco2_per_kwh": intensity }),
+                    )
+                })
+                .collect();
+            let battery_id = battery.id.unwrap();
+            readings.extend((0..4).map(|i| {
+                reading(
+                    battery_id,
+                    hour - Duration::hours(3) + Duration::minutes(15 * i),
+                    json!({ "battery_power_kw": -100.0 }),
+                )
+            }));
+            readings.extend((0..4).map(|i| {
+                reading(
+                    battery_id,
+                    hour + Duration::minutes(15 * i),
+                    json!({ "battery_power_kw": 80.0 }),
+                )
+            }));
+            neems_data::insert_readings_batch(conn, readings).expect("insert readings");
+        })
+        .await;
+    (Client::tracked(rocket).await.unwrap(), hour)
+}
+
+#[tokio::test]
+async fn carbon_profile_and_avoided_emissions() {
+    let site_id = golden_fixtures().site_id("Device API Site A");
+    let (client, hour) = client_with_readings(site_id).await;
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+
+    let resp = client
+        .get(format!("/api/1/Sites/{}/scheduler/carbon?days=2&charge_hours=2", site_id))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let profile: CarbonProfileResponse = resp.into_json().await.expect("json");
+    assert_eq!(profile.hours.len(), 24);
+    assert!(profile.hours.iter().all(|h| h.samples == 1));
+    let hour_of = |ago: i64| (hour - Duration::hours(ago)).hour();
+    assert_eq!(profile.cleanest_hours, vec![hour_of(2), hour_of(3)]);
+    let latest = profile.latest.expect("latest reading");
+    assert_eq!((latest.timestamp, latest.gco2_per_kwh), (hour, 400.0));
+
+    // The economics report credits discharging on the dirty grid
+    let date = hour.date().format("%Y-%m-%d");
+    let from = (hour - Duration::hours(3)).date().format("%Y-%m-%d");
+    let resp = client
+        .get(format!(
+            "/api/1/Sites/{}/Economics?from={}&to={}&period=month",
+            site_id, from, date
+        ))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let report: EconomicsResponse = resp.into_json().await.expect("json");
+    let charge_kg: f64 = report.rows.iter().filter_map(|r| r.charge_emissions_kg).sum();
+    let avoided_kg: f64 = report.rows.iter().filter_map(|r| r.avoided_emissions_kg).sum();
+    assert!((charge_kg - 10.0).abs() < 1e-9, "{:?}", report.rows);
+    assert!((avoided_kg - (32.0 - 10.0)).abs() < 1e-9, "{:?}", report.rows);
+
+    for query in ["days=0", "days=91", "charge_hours=25"] {
+        let resp = client
+            .get(format!("/api/1/Sites/{}/scheduler/carbon?{}", site_id, query))
+            .cookie(admin.clone())
+            .dispatch()
+            .await;
+        assert_eq!(resp.status(), Status::BadRequest, "{}", query);
+    }
+
+    // Another company's users can't see it
+    let other = login_as(&client, "admin@company1.com", "admin").await;
+    let resp = client
+        .get(format!("/api/1/Sites/{}/scheduler/carbon", site_id))
+        .cookie(other)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Forbidden);
+}
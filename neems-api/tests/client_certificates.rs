@@ -0,0 +1,128 @@
+//! Client certificate authentication tests
+//!
+//! Covers registering certificates through
+//! /api/1/Users/{id}/ClientCertificates and authenticating with a registered
+//! certificate instead of a session cookie. The local client injects the
+//! certificate as if TLS had verified it; it is untracked so no session cookie
+//! from logging in rides along.
+
+use neems_api::orm::testing::fast_test_rocket;
+use rocket::{
+    http::{ContentType, Cookie, Status},
+    local::asynchronous::Client,
+};
+use serde_json::{Value, json};
+
+const CLIENT_CERT: &str = include_str!("fixtures/rtac-client.pem");
+const CLIENT_CERT_FINGERPRINT: &str =
+    "69623c384e236d18a938f17028e7f4cb05b511ea6c74be556b1a405d686fcf90";
+
+/// Log in as a golden DB user, returning the session cookie and user id
+async fn login(client: &Client, email: &str, password: &str) -> (Cookie<'static>, i32) {
+    let response = client
+        .post("/api/1/login")
+        .header(ContentType::JSON)
+        .body(json!({ "email": email, "password": password }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let cookie = response.cookies().get("session").expect("session cookie").clone().into_owned();
+    let body: Value = response.into_json().await.expect("login response");
+    (cookie, body["user_id"].as_i64().expect("user_id") as i32)
+}
+
+#[tokio::test]
+async fn test_registered_client_certificate_authenticates() {
+    let client = Client::untracked(fast_test_rocket()).await.expect("valid rocket instance");
+    let (admin_cookie, _) = login(&client, "superadmin@example.com", "admin").await;
+    let (user_cookie, user_id) = login(&client, "testuser@example.com", "admin").await;
+    let roles_url = format!("/api/1/Users/{}/Roles", user_id);
+    let certs_url = format!("/api/1/Users/{}/ClientCertificates", user_id);
+
+    // An unregistered certificate doesn't authenticate
+    let response = client.get(&roles_url).identity(CLIENT_CERT.as_bytes()).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    // Staff can't register certificates, even for themselves
+    let response = client
+        .post(&certs_url)
+        .cookie(user_cookie)
+        .header(ContentType::JSON)
+        .body(json!({ "certificate_pem": CLIENT_CERT }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let response = client
+        .post(&certs_url)
+        .cookie(admin_cookie.clone())
+        .header(ContentType::JSON)
+        .body(json!({ "certificate_pem": CLIENT_CERT, "description": "RTAC" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+    let certificate: Value = response.into_json().await.expect("certificate JSON");
+    assert_eq!(certificate["fingerprint"], CLIENT_CERT_FINGERPRINT);
+    assert_eq!(certificate["subject"], "CN=rtac-test");
+
+    // The same certificate given by its printed fingerprint is a duplicate
+    let printed = CLIENT_CERT_FINGERPRINT.to_uppercase();
+    let response = client
+        .post(&certs_url)
+        .cookie(admin_cookie.clone())
+        .header(ContentType::JSON)
+        .body(json!({ "fingerprint": printed }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Conflict);
+
+    // The certificate now authenticates as the user, without a session
+    let response = client.get(&certs_url).identity(CLIENT_CERT.as_bytes()).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let listed: Vec<Value> = response.into_json().await.expect("certificate list");
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0]["description"], "RTAC");
+
+    // Once revoked, it no longer does
+    let response = client
+        .delete(format!("{}/{}", certs_url, certificate["id"]))
+        .cookie(admin_cookie)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NoContent);
+    let response = client.get(&roles_url).identity(CLIENT_CERT.as_bytes()).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[tokio::test]
+async fn test_invalid_certificate_requests_are_rejected() {
+    let client = Client::untracked(fast_test_rocket()).await.expect("valid rocket instance");
+    let (admin_cookie, _) = login(&client, "superadmin@example.com", "admin").await;
+    let (_, user_id) = login(&client, "testuser@example.com", "admin").await;
+    let certs_url = format!("/api/1/Users/{}/ClientCertificates", user_id);
+
+    for body in [
+        json!({}),
+        json!({ "fingerprint": "abcd" }),
+        json!({ "certificate_pem": "not a certificate" }),
+        json!({ "certificate_pem": CLIENT_CERT, "fingerprint": CLIENT_CERT_FINGERPRINT }),
+    ] {
+        let response = client
+            .post(&certs_url)
+            .cookie(admin_cookie.clone())
+            .header(ContentType::JSON)
+            .body(body.to_string())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest, "body: {}", body);
+    }
+
+    let response = client
+        .post("/api/1/Users/999999/ClientCertificates")
+        .cookie(admin_cookie)
+        .header(ContentType::JSON)
+        .body(json!({ "fingerprint": CLIENT_CERT_FINGERPRINT }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+}
@@ -102,6 +102,37 @@ async fn test_list_companies() {
     assert!(list.iter().any(|c| c.name == "Newtown Energy"), "Should contain Newtown Energy");
 }
 
+#[rocket::async_test]
+async fn test_list_companies_next_link() {
+    let client = Client::tracked(fast_test_rocket()).await.expect("valid rocket instance");
+    let session_cookie = login_and_get_session(&client).await;
+
+    let response = client
+        .get("/api/1/Companies?$orderby=id&$top=2&$count=true")
+        .cookie(session_cookie.clone())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let first: serde_json::Value = response.into_json().await.expect("valid JSON response");
+    let total = first["@odata.count"].as_i64().unwrap();
+    assert!(total > 2, "test data has more than two companies");
+    let next = first["@odata.nextLink"].as_str().expect("a next page");
+    assert_eq!(next, "/api/1/Companies?$orderby=id&$top=2&$count=true&$skip=2");
+
+    let response = client.get(next.to_string()).cookie(session_cookie.clone()).dispatch().await;
+    let second: serde_json::Value = response.into_json().await.expect("valid JSON response");
+    assert!(first["value"][1]["id"].as_i64() < second["value"][0]["id"].as_i64());
+
+    // The last page has no link
+    let response = client
+        .get(format!("/api/1/Companies?$orderby=id&$top=2&$skip={}", total - 2))
+        .cookie(session_cookie)
+        .dispatch()
+        .await;
+    let last: serde_json::Value = response.into_json().await.expect("valid JSON response");
+    assert!(last.get("@odata.nextLink").is_none());
+}
+
 #[rocket::async_test]
 async fn test_delete_company() {
     let client = Client::tracked(fast_test_rocket()).await.expect("valid rocket instance");
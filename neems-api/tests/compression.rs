@@ -0,0 +1,65 @@
+//! Response compression tests
+//!
+//! Uses /api/1/$metadata, which needs no login and is well over the default
+//! minimum size, and /api/1/status, which is under it.
+
+use std::io::Read;
+
+use neems_api::orm::testing::fast_test_rocket;
+use rocket::{
+    http::{Header, Status},
+    local::asynchronous::{Client, LocalResponse},
+};
+
+async fn get_metadata<'c>(client: &'c Client, accept_encoding: Option<&str>) -> LocalResponse<'c> {
+    let mut request = client.get("/api/1/$metadata");
+    if let Some(accept_encoding) = accept_encoding {
+        request = request.header(Header::new("Accept-Encoding", accept_encoding.to_string()));
+    }
+    let response = request.dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    response
+}
+
+#[tokio::test]
+async fn test_compression_negotiation() {
+    let client = Client::untracked(fast_test_rocket()).await.expect("valid rocket instance");
+
+    let response = get_metadata(&client, None).await;
+    assert!(response.headers().get_one("Content-Encoding").is_none());
+    assert_eq!(response.headers().get_one("Vary"), Some("Accept-Encoding"));
+    let plain = response.into_string().await.unwrap();
+    assert!(plain.starts_with("<?xml"));
+
+    let response = get_metadata(&client, Some("gzip, deflate")).await;
+    assert_eq!(response.headers().get_one("Content-Encoding"), Some("gzip"));
+    let gzip = response.into_bytes().await.unwrap();
+    assert!(gzip.len() < plain.len());
+    let mut decoded = String::new();
+    flate2::read::GzDecoder::new(gzip.as_slice())
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, plain);
+
+    let response = get_metadata(&client, Some("gzip, br")).await;
+    assert_eq!(response.headers().get_one("Content-Encoding"), Some("br"));
+    let brotli = response.into_bytes().await.unwrap();
+    let mut decoded = String::new();
+    brotli::Decompressor::new(brotli.as_slice(), 4096)
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, plain);
+
+    let response = get_metadata(&client, Some("identity")).await;
+    assert!(response.headers().get_one("Content-Encoding").is_none());
+
+    // Below the minimum size
+    let response = client
+        .get("/api/1/status")
+        .header(Header::new("Accept-Encoding", "gzip"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.headers().get_one("Content-Encoding").is_none());
+    assert!(response.into_json::<serde_json::Value>().await.is_some());
+}
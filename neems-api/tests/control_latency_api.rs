@@ -0,0 +1,82 @@
+//! Integration tests for reading a site's control-path latency.
+
+use std::time::{Duration, Instant};
+
+use neems_api::{
+    SiteDbConn,
+    orm::testing::{fast_test_rocket, golden_fixtures},
+};
+use neems_data::rtac::{
+    latency::{ControlLatency, ControlStage, LatencyReport, save_report},
+    protocol::{CommandType, OperatingMode},
+};
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::json;
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+#[tokio::test]
+async fn staff_read_the_published_latency_report() {
+    let fixtures = golden_fixtures();
+    let site_id = fixtures.site_id("Device API Site A");
+    let other_site_id = fixtures.site_id("Test Site 1");
+
+    // A command acknowledged 700ms after it was issued, against a 500ms budget
+    let latency = ControlLatency::new(Duration::from_millis(500));
+    let issued = Instant::now();
+    latency.command_issued(CommandType::Charge, issued);
+    latency.command_written(
+        CommandType::Charge,
+        issued + Duration::from_millis(100),
+        issued + Duration::from_millis(150),
+    );
+    latency.status_read(OperatingMode::Charging, issued + Duration::from_millis(700));
+    let report = latency.report();
+
+    let rocket = fast_test_rocket().ignite().await.expect("ignite");
+    let site_db = SiteDbConn::get_one(&rocket).await.expect("site database");
+    let saved = report.clone();
+    site_db
+        .run(move |conn| save_report(conn, site_id, &saved))
+        .await
+        .expect("save report");
+    let client = Client::tracked(rocket).await.expect("client");
+    let staff = login_as(&client, "newtown_staff@example.com", "newtownstaffpass").await;
+
+    let resp = client
+        .get(format!("/api/1/Sites/{}/ControlLatency", site_id))
+        .cookie(staff.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let served: LatencyReport = resp.into_json().await.expect("json");
+    assert_eq!(served, report);
+    assert_eq!((served.budget_ms, served.over_budget), (500, 1));
+    assert!(served.alarm_active);
+    let end_to_end = served.stages.iter().find(|s| s.stage == ControlStage::EndToEnd).unwrap();
+    assert_eq!(end_to_end.max_ms, Some(700.0));
+
+    // Nothing published for another site, and no such site at all
+    let resp = client
+        .get(format!("/api/1/Sites/{}/ControlLatency", other_site_id))
+        .cookie(staff.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::NotFound);
+    let resp = client.get("/api/1/Sites/999999/ControlLatency").cookie(staff).dispatch().await;
+    assert_eq!(resp.status(), Status::NotFound);
+
+    // Customers can't read it, even for their own site
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let resp = client
+        .get(format!("/api/1/Sites/{}/ControlLatency", site_id))
+        .cookie(admin)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Forbidden);
+}
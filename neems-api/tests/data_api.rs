@@ -10,20 +10,21 @@
 //! the `test-staging` feature.
 
 use neems_api::{
-    api::data::{DataSourcesResponse, ReadingsResponse},
+    api::data::{AggregateResponse, DataSourcesResponse, ReadingsResponse},
     models::{CompanyInput, NewRole, UserInput},
     orm::{
         DbConn,
         company::{get_company_by_name, insert_company},
         login::hash_password,
         role::insert_role,
-        testing::fast_test_rocket,
+        testing::{fast_test_rocket, golden_fixtures},
         user::insert_user,
         user_role::assign_user_role_by_name,
     },
 };
+use neems_data::{models::Reading, points::AggregateBackend};
 use rocket::{
-    http::{ContentType, Status},
+    http::{Accept, ContentType, MediaType, QMediaType, Status},
     local::asynchronous::Client,
 };
 use serde_json::json;
@@ -712,3 +713,161 @@ async fn test_newtown_staff_access() {
 
     assert_eq!(readings_response.source_id, Some(test_source_id));
 }
+
+/// Test that declared point fields are aggregated from reading_points and
+/// other numeric fields from the JSON data, with the same results.
+#[tokio::test]
+async fn test_source_aggregate_uses_points_for_declared_fields() {
+    let client = Client::tracked(fast_test_rocket()).await.expect("valid rocket instance");
+    let session_cookie =
+        login_as_user(&client, "newtown_staff@example.com", "newtownstaffpass").await;
+
+    let fixtures = golden_fixtures();
+    let (site_id, company_id) =
+        (fixtures.site_id("Test Site 1"), fixtures.company_id("Test Company 1"));
+    // charging_state sources declare `level` by default
+    let readings: Vec<_> = [(0, 40.0), (30, 50.0), (3600, 70.0)]
+        .iter()
+        .enumerate()
+        .map(|(i, (offset, level))| {
+            json!({
+                "origin_reading_id": i + 1,
+                "source_name": "charging_state",
+                "test_type": "charging_state",
+                "site_id": site_id,
+                "company_id": company_id,
+                "timestamp": (chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()
+                    .and_hms_opt(0, 0, 0).unwrap() + chrono::Duration::seconds(*offset))
+                    .format("%Y-%m-%dT%H:%M:%S").to_string(),
+                "device_timestamp": null,
+                "data": json!({ "level": level, "voltage": level * 10.0 }).to_string(),
+                "quality_flags": 0
+            })
+        })
+        .collect();
+    let response = client
+        .post("/api/1/Sync/Readings")
+        .cookie(session_cookie.clone())
+        .json(&json!({ "origin": "aggregate-test", "readings": readings }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let sources: DataSourcesResponse =
+        client.get("/api/1/DataSources").dispatch().await.into_json().await.unwrap();
+    let source_id = sources
+        .sources
+        .iter()
+        .find(|s| s.name == "aggregate-test/charging_state")
+        .and_then(|s| s.id)
+        .expect("synced source");
+
+    let window = "from=2024-01-01T00:00:00Z&to=2024-01-02T00:00:00Z&bucket_seconds=3600";
+    let url = format!("/api/1/DataSources/{}/Aggregate?field=level&{}", source_id, window);
+    let response = client.get(&url).cookie(session_cookie.clone()).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let level: AggregateResponse = response.into_json().await.expect("valid AggregateResponse");
+    // Whole hours come from the hourly rollups the writer keeps
+    assert_eq!(level.backend, AggregateBackend::HourlyRollup);
+    assert_eq!(level.buckets.len(), 2);
+    assert_eq!((level.buckets[0].count, level.buckets[0].avg), (2, 45.0));
+    assert_eq!((level.buckets[0].min, level.buckets[0].max), (40.0, 50.0));
+
+    let url = format!(
+        "/api/1/DataSources/{}/Aggregate?field=level&{}",
+        source_id,
+        window.replace("bucket_seconds=3600", "bucket_seconds=1800")
+    );
+    let response = client.get(&url).cookie(session_cookie.clone()).dispatch().await;
+    let half_hours: AggregateResponse =
+        response.into_json().await.expect("valid AggregateResponse");
+    assert_eq!(half_hours.backend, AggregateBackend::Points);
+    assert_eq!(half_hours.buckets[0].avg, 45.0);
+
+    let url = format!("/api/1/DataSources/{}/Aggregate?field=voltage&{}", source_id, window);
+    let response = client.get(&url).cookie(session_cookie.clone()).dispatch().await;
+    let voltage: AggregateResponse = response.into_json().await.expect("valid AggregateResponse");
+    assert_eq!(voltage.backend, AggregateBackend::Json);
+    assert_eq!(voltage.buckets.len(), 2);
+    assert_eq!(voltage.buckets[1].avg, 700.0);
+
+    let url = format!("/api/1/DataSources/{}/Aggregate?field=$.level&{}", source_id, window);
+    let response = client.get(&url).cookie(session_cookie).dispatch().await;
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+/// Test that readings stream as NDJSON, in the same order as the JSON
+/// response, when the client asks for them.
+#[tokio::test]
+async fn test_readings_stream_as_ndjson() {
+    let client = Client::tracked(fast_test_rocket()).await.expect("valid rocket instance");
+    let session_cookie =
+        login_as_user(&client, "newtown_staff@example.com", "newtownstaffpass").await;
+
+    let fixtures = golden_fixtures();
+    let (site_id, company_id) =
+        (fixtures.site_id("Test Site 1"), fixtures.company_id("Test Company 1"));
+    let readings: Vec<_> = (0..5)
+        .map(|i| {
+            json!({
+                "origin_reading_id": i + 1,
+                "source_name": "charging_state",
+                "test_type": "charging_state",
+                "site_id": site_id,
+                "company_id": company_id,
+                "timestamp": format!("2024-01-01T00:0{}:00", i),
+                "device_timestamp": null,
+                "data": json!({ "level": 40 + i }).to_string(),
+                "quality_flags": 0
+            })
+        })
+        .collect();
+    let response = client
+        .post("/api/1/Sync/Readings")
+        .cookie(session_cookie.clone())
+        .json(&json!({ "origin": "ndjson-test", "readings": readings }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let sources: DataSourcesResponse =
+        client.get("/api/1/DataSources").dispatch().await.into_json().await.unwrap();
+    let source_id = sources
+        .sources
+        .iter()
+        .find(|s| s.name == "ndjson-test/charging_state")
+        .and_then(|s| s.id)
+        .expect("synced source");
+
+    let ndjson = Accept::new([QMediaType(MediaType::new("application", "x-ndjson"), None)]);
+    for query in ["", "&to_time=2024-01-01T00:03:00Z&count=2", "&to_time=2024-01-01T00:03:00Z"] {
+        let url = format!("/api/1/Readings?source_ids={}{}", source_id, query);
+        let response = client.get(&url).cookie(session_cookie.clone()).dispatch().await;
+        let expected: ReadingsResponse = response.into_json().await.expect("valid JSON");
+
+        let response = client
+            .get(&url)
+            .cookie(session_cookie.clone())
+            .header(ndjson.clone())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::new("application", "x-ndjson")));
+        let body = response.into_string().await.expect("body");
+        let streamed: Vec<Reading> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("reading line"))
+            .collect();
+        let ids = |readings: &[Reading]| readings.iter().map(|r| r.id).collect::<Vec<_>>();
+        assert_eq!(ids(&streamed), ids(&expected.readings), "{}", url);
+    }
+
+    // Access is checked before streaming starts
+    let response = client
+        .get(format!("/api/1/DataSources/{}/Readings", i32::MAX))
+        .cookie(session_cookie)
+        .header(ndjson)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+}
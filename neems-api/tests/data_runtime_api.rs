@@ -0,0 +1,93 @@
+//! Integration tests for the neems-data runtime settings endpoints.
+
+use neems_api::orm::testing::fast_test_rocket;
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::{Value, json};
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+#[tokio::test]
+async fn newtown_admin_can_set_and_clear_runtime_overrides() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "newtown_superadmin@example.com", "newtownpass").await;
+
+    let resp = client.get("/api/1/DataRuntime").cookie(admin.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let current: Value = resp.into_json().await.expect("json");
+    assert_eq!(current["batch_size"], Value::Null);
+    assert_eq!(current["updated_at"], Value::Null);
+
+    let resp = client
+        .put("/api/1/DataRuntime")
+        .cookie(admin.clone())
+        .json(&json!({
+            "log_level": "info,neems_data=debug",
+            "batch_size": 200,
+            "test_type_concurrency": { "ping": 8 }
+        }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let saved: Value = resp.into_json().await.expect("json");
+    assert_eq!(saved["batch_size"], json!(200));
+    assert_eq!(saved["test_type_concurrency"], json!({ "ping": 8 }));
+    assert_eq!(saved["updated_by"], json!("newtown_superadmin@example.com"));
+
+    // Staff can read what the admin set
+    let staff = login_as(&client, "newtown_staff@example.com", "newtownstaffpass").await;
+    let resp = client.get("/api/1/DataRuntime").cookie(staff).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let current: Value = resp.into_json().await.expect("json");
+    assert_eq!(current["log_level"], json!("info,neems_data=debug"));
+
+    // An empty request clears every override
+    let resp = client.put("/api/1/DataRuntime").cookie(admin).json(&json!({})).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let cleared: Value = resp.into_json().await.expect("json");
+    assert_eq!(cleared["batch_size"], Value::Null);
+    assert_eq!(cleared["test_type_concurrency"], Value::Null);
+}
+
+#[tokio::test]
+async fn invalid_runtime_overrides_are_rejected() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "newtown_superadmin@example.com", "newtownpass").await;
+
+    let resp = client
+        .put("/api/1/DataRuntime")
+        .cookie(admin)
+        .json(&json!({ "batch_size": 0, "test_type_concurrency": { "pnig": 2 } }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+    let body: Value = resp.into_json().await.expect("json");
+    let error = body["error"].as_str().unwrap();
+    assert!(error.contains("batch_size: must be at least 1"), "{}", error);
+    assert!(error.contains("Unknown test type: pnig"), "{}", error);
+}
+
+#[tokio::test]
+async fn only_newtown_admins_can_change_runtime_overrides() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+
+    let resp = client.get("/api/1/DataRuntime").dispatch().await;
+    assert_eq!(resp.status(), Status::Unauthorized);
+
+    let staff = login_as(&client, "newtown_staff@example.com", "newtownstaffpass").await;
+    let resp = client
+        .put("/api/1/DataRuntime")
+        .cookie(staff)
+        .json(&json!({ "batch_size": 10 }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Forbidden);
+
+    let customer = login_as(&client, "admin@company1.com", "admin").await;
+    let resp = client.get("/api/1/DataRuntime").cookie(customer).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+}
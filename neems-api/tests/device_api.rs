@@ -1,6 +1,6 @@
 use neems_api::{
     models::{Company, Device, Site},
-    orm::testing::fast_test_rocket,
+    orm::testing::{fast_test_rocket, golden_fixtures},
 };
 use rocket::{
     http::{ContentType, Status},
@@ -398,6 +398,72 @@ async fn test_update_device_success() {
     assert_eq!(updated_device.type_, "Meter"); // Should remain unchanged
 }
 
+#[rocket::async_test]
+async fn test_patch_device_merges_and_clears_fields() {
+    let client = Client::tracked(fast_test_rocket()).await.expect("valid rocket instance");
+
+    let admin_cookie = login_admin(&client).await;
+    let company = get_company_by_name(&client, &admin_cookie, "Device Test Company A").await;
+    let site = get_site_by_name(&client, &admin_cookie, "Device API Site A").await;
+
+    let new_device = json!({
+        "name": "Device to Patch",
+        "description": "Rooftop meter",
+        "type_": "Meter",
+        "model": "M-100",
+        "serial": "SN-1",
+        "ip_address": "10.0.0.5",
+        "company_id": company.id,
+        "site_id": site.id
+    });
+    let create_response = client
+        .post("/api/1/Devices")
+        .cookie(admin_cookie.clone())
+        .json(&new_device)
+        .dispatch()
+        .await;
+    assert_eq!(create_response.status(), Status::Created);
+    let created_device: Device = create_response.into_json().await.expect("valid device JSON");
+    let url = format!("/api/1/Devices/{}", created_device.id);
+
+    // Absent fields are kept, null clears, values replace
+    let patch = json!({ "model": "M-200", "serial": null, "description": null });
+    let response = client.patch(&url).cookie(admin_cookie.clone()).json(&patch).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let patched: Device = response.into_json().await.expect("valid device JSON");
+    assert_eq!(patched.model, "M-200");
+    assert_eq!(patched.serial, None);
+    assert_eq!(patched.description, None);
+    assert_eq!(patched.ip_address, Some("10.0.0.5".to_string()));
+    assert_eq!(patched.name, "Device to Patch");
+
+    // A field that can't be null
+    let response = client
+        .patch(&url)
+        .cookie(admin_cookie.clone())
+        .json(&json!({ "name": null }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    // The same checks as PUT
+    let response = client
+        .patch("/api/1/Devices/99999")
+        .cookie(admin_cookie)
+        .json(&json!({ "model": "M-300" }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+    let staff_cookie = login_user(&client, "staff@testcompany.com", "admin").await;
+    let response = client
+        .patch(&url)
+        .cookie(staff_cookie)
+        .json(&json!({ "model": "M-300" }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+}
+
 #[rocket::async_test]
 async fn test_delete_device_success() {
     let client = Client::tracked(fast_test_rocket()).await.expect("valid rocket instance");
@@ -584,7 +650,8 @@ async fn test_device_rbac_regular_staff() {
 
     // Staff user from Test Company 1 should see no devices (devices moved to Device
     // Test companies)
-    let staff_company_id = 2; // Test Company 1 has ID 2 in test data
+    let fixtures = golden_fixtures();
+    let staff_company_id = fixtures.company_id("Test Company 1");
     assert_eq!(devices.len(), 0); // No devices should exist in Test Company 1 anymore
     for device in &devices {
         assert_eq!(device.company_id, staff_company_id);
@@ -596,7 +663,7 @@ async fn test_device_rbac_regular_staff() {
         "type_": "Sensor",
         "model": "SENS-100",
         "company_id": staff_company_id,
-        "site_id": 1
+        "site_id": fixtures.site_id("Test Site 1")
     });
 
     let response = client
@@ -0,0 +1,182 @@
+//! Integration tests for device groups, their telemetry and dispatch.
+
+use neems_api::{
+    SiteDbConn,
+    models::{ActiveCommandResponse, DeviceGroup, DeviceGroupTelemetry, GroupDispatch},
+    orm::testing::{fast_test_rocket, golden_fixtures},
+};
+use neems_data::models::{NewReading, NewSource};
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::json;
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+/// A client whose site data has two battery sources on `site_id`, one at 80%
+/// SoC discharging 50 kW and one at 20% charging 10 kW, returning their ids.
+async fn client_with_sources(site_id: i32) -> (Client, i32, i32) {
+    let rocket = fast_test_rocket().ignite().await.expect("ignite");
+    let site_db = SiteDbConn::get_one(&rocket).await.expect("site database");
+    let (first, second) = site_db
+        .run(move |conn| {
+            let mut source = |name: &str, data: serde_json::Value| {
+                let source = neems_data::create_source(
+                    conn,
+                    NewSource {
+                        name: name.to_string(),
+                        description: None,
+                        active: Some(true),
+                        interval_seconds: Some(60),
+                        test_type: Some("simulator".to_string()),
+                        arguments: None,
+                        site_id: Some(site_id),
+                        company_id: None,
+                        priority: None,
+                        point_fields: None,
+                    },
+                )
+                .expect("create source");
+                let source_id = source.id.unwrap();
+                let reading = NewReading {
+                    source_id,
+                    timestamp: Some(chrono::Utc::now().naive_utc()),
+                    data: data.to_string(),
+                    quality_flags: None,
+                    device_timestamp: None,
+                };
+                neems_data::insert_readings_batch(conn, vec![reading]).expect("insert readings");
+                source_id
+            };
+            let first = source("BESS 1", json!({ "soc_percent": 80.0, "power_kw": 50.0 }));
+            let second = source("BESS 2", json!({ "level": 20, "battery_power_kw": -10.0 }));
+            (first, second)
+        })
+        .await;
+    (Client::tracked(rocket).await.unwrap(), first, second)
+}
+
+#[tokio::test]
+async fn group_telemetry_dispatch_and_scheduled_split() {
+    let fixtures = golden_fixtures();
+    let site_id = fixtures.site_id("Device API Site A");
+    let (client, first_source, second_source) = client_with_sources(site_id).await;
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let groups_url = format!("/api/1/Sites/{}/DeviceGroups", site_id);
+
+    let body = json!({
+        "name": "BESS containers",
+        "allocation": "soc_balancing",
+        "schedule_dispatch": true,
+        "members": [
+            { "device_id": fixtures.device_id("SEL-451"), "rated_power_kw": 300.0, "source_id": first_source },
+            { "device_id": fixtures.device_id("SEL-735"), "rated_power_kw": 300.0, "source_id": second_source }
+        ]
+    });
+    let resp = client
+        .post(groups_url.clone())
+        .cookie(admin.clone())
+        .json(&body)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let group: DeviceGroup = resp.into_json().await.expect("json");
+    assert_eq!(group.members.len(), 2);
+    assert!(group.schedule_dispatch);
+    let group_url = format!("{}/{}", groups_url, group.id);
+
+    // The name is taken now, and devices must be on the site
+    let resp = client
+        .post(groups_url.clone())
+        .cookie(admin.clone())
+        .json(&body)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+    let bad = json!({ "name": "Elsewhere", "members": [{ "device_id": 999999 }] });
+    let resp = client
+        .post(groups_url.clone())
+        .cookie(admin.clone())
+        .json(&bad)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+
+    // Telemetry rolls up the members' latest readings
+    let resp = client
+        .get(format!("{}/Telemetry", group_url))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let telemetry: DeviceGroupTelemetry = resp.into_json().await.expect("json");
+    assert_eq!(telemetry.reporting_count, 2);
+    assert_eq!(telemetry.total_power_kw, Some(40.0));
+    assert_eq!(telemetry.soc_percent, Some(50.0));
+    assert_eq!(telemetry.rated_power_kw, Some(600.0));
+
+    // Discharging favours the fuller member, and ratings cap the split
+    let dispatch_url = format!("{}/Dispatch", group_url);
+    let resp = client
+        .post(dispatch_url.clone())
+        .cookie(admin.clone())
+        .json(&json!({ "power_kw": 200.0 }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let dispatch: GroupDispatch = resp.into_json().await.expect("json");
+    let powers: Vec<f64> = dispatch.setpoints.iter().map(|s| s.power_kw).collect();
+    assert_eq!(powers, vec![160.0, 40.0]);
+    let resp = client
+        .post(dispatch_url.clone())
+        .cookie(admin.clone())
+        .json(&json!({ "power_kw": -1000.0 }))
+        .dispatch()
+        .await;
+    let dispatch: GroupDispatch = resp.into_json().await.expect("json");
+    assert_eq!(dispatch.allocated_power_kw, -600.0);
+
+    // The scheduler splits a site-wide power command across the group
+    let item = json!({
+        "name": "Discharge 100 kW",
+        "commands": [{ "execution_offset_seconds": 0, "command_type": "discharge", "power_kw": 100.0 }]
+    });
+    let resp = client
+        .post(format!("/api/1/Sites/{}/ScheduleLibraryItems", site_id))
+        .cookie(admin.clone())
+        .json(&item)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let item: serde_json::Value = resp.into_json().await.expect("json");
+    let resp = client
+        .post(format!("/api/1/ScheduleLibraryItems/{}/ApplicationRules", item["id"]))
+        .cookie(admin.clone())
+        .json(&json!({ "rule_type": "default" }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let resp = client
+        .get(format!("/api/1/Sites/{}/ActiveCommand", site_id))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    let active: ActiveCommandResponse = resp.into_json().await.expect("json");
+    let dispatch = active.dispatch.expect("dispatch");
+    assert_eq!(dispatch.group_id, group.id);
+    let powers: Vec<f64> = dispatch.setpoints.iter().map(|s| s.power_kw).collect();
+    assert_eq!(powers, vec![80.0, 20.0]);
+
+    // Other companies can't see the group
+    let other = login_as(&client, "admin@company1.com", "admin").await;
+    let resp = client.get(group_url.clone()).cookie(other.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+
+    let resp = client.delete(group_url.clone()).cookie(admin.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::NoContent);
+    let resp = client.get(group_url).cookie(admin).dispatch().await;
+    assert_eq!(resp.status(), Status::NotFound);
+}
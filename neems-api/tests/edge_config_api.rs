@@ -0,0 +1,133 @@
+//! Integration tests for pushing configuration bundles to edge instances.
+
+use neems_api::orm::testing::fast_test_rocket;
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::{Value, json};
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+fn bundle(target: &str) -> Value {
+    json!({
+        "description": format!("Ping {}", target),
+        "sources": [{ "name": "gateway_ping", "test_type": "ping", "arguments": { "target": target } }]
+    })
+}
+
+#[tokio::test]
+async fn bundles_roll_out_in_stages_and_roll_back() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let staff = login_as(&client, "newtown_staff@example.com", "newtownstaffpass").await;
+
+    let mut versions = Vec::new();
+    for target in ["10.0.0.1", "10.0.0.2"] {
+        let resp = client
+            .post("/api/1/EdgeConfig/Bundles")
+            .cookie(staff.clone())
+            .json(&bundle(target))
+            .dispatch()
+            .await;
+        assert_eq!(resp.status(), Status::Created);
+        let summary: Value = resp.into_json().await.expect("json");
+        versions.push(summary["version"].as_i64().unwrap());
+    }
+
+    // Unknown collectors and secrets the bundle doesn't carry are refused
+    let bad = json!({
+        "sources": [{ "name": "x", "test_type": "ping", "arguments": { "target": "secret://missing" } }]
+    });
+    let resp = client
+        .post("/api/1/EdgeConfig/Bundles")
+        .cookie(staff.clone())
+        .json(&bad)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+
+    // Nothing is targeted at an origin until a rollout reaches it
+    let desired = |origin: &str| format!("/api/1/EdgeConfig/Agents/{}/Desired", origin);
+    let resp = client.get(desired("site-b")).cookie(staff.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::NoContent);
+
+    let rollout = |version: i64| json!({ "version": version, "stages": [["site-a"], ["site-b"]] });
+    let resp = client
+        .post("/api/1/EdgeConfig/Rollouts")
+        .cookie(staff.clone())
+        .json(&rollout(versions[0]))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let first: Value = resp.into_json().await.expect("json");
+    let advance = format!("/api/1/EdgeConfig/Rollouts/{}/Advance", first["id"]);
+
+    let resp = client.get(desired("site-a")).cookie(staff.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let pushed: Value = resp.into_json().await.expect("json");
+    assert_eq!(pushed["version"], json!(versions[0]));
+    assert_eq!(pushed["sources"][0]["arguments"]["target"], json!("10.0.0.1"));
+
+    // The next stage waits for the canary
+    let resp = client.post(advance.clone()).cookie(staff.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Conflict);
+    let report = |origin: &str| format!("/api/1/EdgeConfig/Agents/{}/Status", origin);
+    let resp = client
+        .post(report("site-a"))
+        .cookie(staff.clone())
+        .json(&json!({ "version": versions[0], "error": null }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let resp = client.post(advance.clone()).cookie(staff.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    client
+        .post(report("site-b"))
+        .cookie(staff.clone())
+        .json(&json!({ "version": versions[0], "error": null }))
+        .dispatch()
+        .await;
+    let resp = client
+        .get(format!("/api/1/EdgeConfig/Rollouts/{}", first["id"]))
+        .cookie(staff.clone())
+        .dispatch()
+        .await;
+    let first: Value = resp.into_json().await.expect("json");
+    assert_eq!(first["status"], json!("completed"));
+
+    // A failed canary halts the next rollout, and rolling back restores the
+    // bundle it had
+    let resp = client
+        .post("/api/1/EdgeConfig/Rollouts")
+        .cookie(staff.clone())
+        .json(&rollout(versions[1]))
+        .dispatch()
+        .await;
+    let second: Value = resp.into_json().await.expect("json");
+    client
+        .post(report("site-a"))
+        .cookie(staff.clone())
+        .json(&json!({ "version": versions[1], "error": "ping target unreachable" }))
+        .dispatch()
+        .await;
+    let resp = client
+        .post(format!("/api/1/EdgeConfig/Rollouts/{}/Rollback", second["id"]))
+        .cookie(staff.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let second: Value = resp.into_json().await.expect("json");
+    assert_eq!(second["status"], json!("rolled_back"));
+    let resp = client.get(desired("site-a")).cookie(staff.clone()).dispatch().await;
+    let pushed: Value = resp.into_json().await.expect("json");
+    assert_eq!(pushed["version"], json!(versions[0]));
+
+    // Company users can't see or fetch configuration
+    let other = login_as(&client, "admin@company1.com", "admin").await;
+    let resp = client.get("/api/1/EdgeConfig/Bundles").cookie(other.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+    let resp = client.get(desired("site-a")).cookie(other).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+}
@@ -0,0 +1,106 @@
+//! Integration tests for pulling a site's recent logs through the central API.
+
+use neems_api::orm::testing::{fast_test_rocket, golden_fixtures};
+use neems_data::logs::{LogBuffer, LogEntry, LogPage, serve_logs};
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::{Value, json};
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+fn log(level: &str, message: &str) -> LogEntry {
+    LogEntry {
+        timestamp: chrono::Utc::now(),
+        level: level.to_string(),
+        target: "neems_data::rtac::worker".to_string(),
+        message: message.to_string(),
+        fields: [("host".to_string(), json!("10.0.0.5"))].into(),
+    }
+}
+
+#[tokio::test]
+async fn staff_read_edge_logs_through_the_proxy() {
+    // An edge log listener on a free local port
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let edge_url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(serve_logs(listener, "edge-token".to_string()));
+    LogBuffer::global().push(log("info", "Polling RTAC"));
+    LogBuffer::global().push(log("warn", "RTAC connection lost"));
+
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let staff = login_as(&client, "newtown_staff@example.com", "newtownstaffpass").await;
+    let site_id = golden_fixtures().site_id("Device API Site A");
+    let endpoint_url = format!("/api/1/Sites/{}/EdgeEndpoint", site_id);
+    let logs_url = format!("/api/1/Sites/{}/EdgeLogs", site_id);
+
+    // Nothing to proxy to yet, and a new endpoint needs a token
+    let resp = client.get(logs_url.clone()).cookie(staff.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::NotFound);
+    let resp = client
+        .put(endpoint_url.clone())
+        .cookie(staff.clone())
+        .json(&json!({ "url": edge_url }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+
+    let resp = client
+        .put(endpoint_url.clone())
+        .cookie(staff.clone())
+        .json(&json!({ "url": format!("{}/", edge_url), "token": "edge-token" }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let info: Value = resp.into_json().await.expect("json");
+    assert_eq!(info["url"], json!(edge_url));
+    assert_eq!(info["token"], json!("***"));
+
+    let resp = client
+        .get(format!("{}?level=warn", logs_url))
+        .cookie(staff.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let page: LogPage = resp.into_json().await.expect("json");
+    let entry = page
+        .entries
+        .iter()
+        .find(|e| e.message == "RTAC connection lost")
+        .expect("warning is returned");
+    assert_eq!(entry.fields["host"], json!("10.0.0.5"));
+    assert!(page.entries.iter().all(|e| e.level != "info"));
+
+    let resp = client
+        .get(format!("{}?level=loud", logs_url))
+        .cookie(staff.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+
+    // A wrong token is refused by the edge; keeping the URL, replace the token
+    let resp = client
+        .put(endpoint_url.clone())
+        .cookie(staff.clone())
+        .json(&json!({ "url": edge_url, "token": "stale-token" }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let resp = client.get(logs_url.clone()).cookie(staff.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::BadGateway);
+
+    // Company admins can't read edge logs, even for their own sites
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let resp = client.get(logs_url.clone()).cookie(admin.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+    let resp = client.get(endpoint_url.clone()).cookie(admin).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+
+    let resp = client.delete(endpoint_url.clone()).cookie(staff.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::NoContent);
+    let resp = client.get(endpoint_url).cookie(staff).dispatch().await;
+    assert_eq!(resp.status(), Status::NotFound);
+}
@@ -6,14 +6,16 @@
 //!   2. activity rows surface for an entity created during the test,
 //!   3. the acting user's email is resolved server-side,
 //!   4. row ordering is oldest-first,
-//!   5. unknown entities return an empty list (no 404).
+//!   5. unknown entities return an empty list (no 404),
+//!   6. the same rows stream as NDJSON on request.
 
 use neems_api::{
-    api::entity_activity::EntityActivityWithUser, models::ScheduleLibraryItem,
-    orm::testing::fast_test_rocket,
+    api::entity_activity::EntityActivityWithUser,
+    models::ScheduleLibraryItem,
+    orm::testing::{fast_test_rocket, golden_fixtures},
 };
 use rocket::{
-    http::{ContentType, Status},
+    http::{Accept, ContentType, MediaType, QMediaType, Status},
     local::asynchronous::Client,
 };
 use serde_json::json;
@@ -59,7 +61,10 @@ async fn entity_activity_returns_history_with_user_email() {
         "commands": []
     });
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(format!(
+            "/api/1/Sites/{}/ScheduleLibraryItems",
+            golden_fixtures().site_id("Test Site 1")
+        ))
         .cookie(admin.clone())
         .json(&new_item)
         .dispatch()
@@ -116,6 +121,22 @@ async fn entity_activity_returns_history_with_user_email() {
             pair[1].timestamp
         );
     }
+
+    // The same rows, streamed one per line
+    let response = client
+        .get(&url)
+        .cookie(admin.clone())
+        .header(Accept::new([QMediaType(MediaType::new("application", "x-ndjson"), None)]))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::new("application", "x-ndjson")));
+    let body = response.into_string().await.expect("body");
+    let streamed: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("JSON line"))
+        .collect();
+    assert_eq!(serde_json::to_value(&rows).unwrap(), serde_json::Value::from(streamed));
 }
 
 #[rocket::async_test]
@@ -135,7 +156,10 @@ async fn entity_activity_records_commands_only_update() {
         ]
     });
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(format!(
+            "/api/1/Sites/{}/ScheduleLibraryItems",
+            golden_fixtures().site_id("Test Site 1")
+        ))
         .cookie(admin.clone())
         .json(&new_item)
         .dispatch()
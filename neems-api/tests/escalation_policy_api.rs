@@ -0,0 +1,172 @@
+//! Integration tests for alarm escalation policies and escalation history.
+
+use std::collections::HashSet;
+
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use neems_api::{
+    DbConn, SiteDbConn,
+    alarm_escalation::escalate_alarms,
+    models::{AlarmEscalation, EscalationPolicy},
+    orm::testing::{fast_test_rocket, golden_fixtures},
+    schema::alarm_states,
+};
+use neems_data::models::{NewReading, NewSource};
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::{Value, json};
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+#[tokio::test]
+async fn unacknowledged_alarms_escalate_through_policy_tiers() {
+    let fixtures = golden_fixtures();
+    let company_id = fixtures.company_id("Device Test Company A");
+    let site_id = fixtures.site_id("Device API Site A");
+    let rocket = fast_test_rocket().ignite().await.expect("ignite");
+    let site_db = SiteDbConn::get_one(&rocket).await.expect("site database");
+    site_db
+        .run(move |conn| {
+            // An RTAC at the company's site with relay 86 M1, a critical
+            // alarm, set
+            let source = neems_data::create_source(
+                conn,
+                NewSource {
+                    name: "RTAC".to_string(),
+                    description: None,
+                    active: Some(true),
+                    interval_seconds: Some(1),
+                    test_type: None,
+                    arguments: None,
+                    site_id: Some(site_id),
+                    company_id: None,
+                    priority: None,
+                    point_fields: None,
+                },
+            )
+            .expect("create source");
+            let mut registers = vec![0u16; 22];
+            registers[1] = 1 << 2;
+            let reading = NewReading {
+                source_id: source.id.unwrap(),
+                timestamp: Some(Utc::now().naive_utc()),
+                data: json!({ "alarm_registers": registers }).to_string(),
+                quality_flags: None,
+                device_timestamp: None,
+            };
+            neems_data::insert_readings_batch(conn, vec![reading]).expect("insert reading");
+        })
+        .await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+
+    let channels = format!("/api/1/Companies/{}/NotificationChannels", company_id);
+    let resp = client
+        .post(channels)
+        .cookie(admin.clone())
+        .json(&json!({
+            "name": "on-call",
+            "kind": "pagerduty",
+            "destination": "secret://missing-routing-key",
+            "severities": ["critical"]
+        }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let channel: Value = resp.into_json().await.expect("json");
+    let channel_id = channel["id"].as_i64().unwrap();
+
+    let policies = format!("/api/1/Companies/{}/EscalationPolicies", company_id);
+    let tiers = json!([
+        { "delay_minutes": 15, "channel_id": channel_id, "contacts": ["Site lead"] },
+        { "delay_minutes": 60, "channel_id": channel_id, "contacts": ["Operations manager"] }
+    ]);
+    let invalid = [
+        json!({ "name": "bad", "tiers": [] }),
+        json!({ "name": "bad", "tiers": [{ "delay_minutes": 15, "channel_id": 9999 }] }),
+        json!({ "name": "bad", "tiers": [
+            { "delay_minutes": 30, "channel_id": channel_id },
+            { "delay_minutes": 30, "channel_id": channel_id }
+        ] }),
+    ];
+    for body in invalid {
+        let resp = client.post(policies.clone()).cookie(admin.clone()).json(&body).dispatch().await;
+        assert_eq!(resp.status(), Status::BadRequest, "{}", body);
+    }
+    let body = json!({ "name": "critical alarms", "tiers": tiers });
+    let resp = client.post(policies.clone()).cookie(admin.clone()).json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Created);
+    let policy: EscalationPolicy = resp.into_json().await.expect("json");
+    assert_eq!(policy.tiers.iter().map(|t| t.tier).collect::<Vec<_>>(), vec![1, 2]);
+    assert_eq!(policy.tiers[0].contacts, vec!["Site lead"]);
+    let resp = client.post(policies.clone()).cookie(admin.clone()).json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Conflict);
+
+    let other = login_as(&client, "admin@company1.com", "admin").await;
+    let resp = client.get(policies.clone()).cookie(other).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+
+    // Nothing is due as the alarm is first seen
+    let db = DbConn::get_one(client.rocket()).await.unwrap();
+    let site_db = SiteDbConn::get_one(client.rocket()).await.unwrap();
+    let none = HashSet::new();
+    assert_eq!(escalate_alarms(&db, &site_db, &none).await.expect("escalate"), 0);
+
+    // Twenty minutes on, the first tier is notified, once; its channel's
+    // routing key can't be found, which the history records
+    db.run(|conn| {
+        diesel::update(alarm_states::table.find(103))
+            .set(alarm_states::raised_at.eq(Utc::now().naive_utc() - Duration::minutes(20)))
+            .execute(conn)
+    })
+    .await
+    .expect("backdate raise");
+    assert_eq!(escalate_alarms(&db, &site_db, &none).await.expect("escalate"), 1);
+    assert_eq!(escalate_alarms(&db, &site_db, &none).await.expect("escalate"), 0);
+    let resp = client
+        .get("/api/1/Alarms/103/Escalations")
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let escalations: Vec<AlarmEscalation> = resp.into_json().await.expect("json");
+    assert_eq!(escalations.len(), 1);
+    assert_eq!((escalations[0].policy_id, escalations[0].tier), (policy.id, 1));
+    assert_eq!(escalations[0].contacts, vec!["Site lead"]);
+    assert!(escalations[0].error.as_deref().unwrap().contains("missing-routing-key"));
+
+    // Once acknowledged, the second tier is never notified
+    let resp = client
+        .post("/api/1/Alarms/103/Acknowledge")
+        .cookie(admin.clone())
+        .json(&json!({}))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    db.run(|conn| {
+        diesel::update(alarm_states::table.find(103))
+            .set(alarm_states::raised_at.eq(Utc::now().naive_utc() - Duration::minutes(90)))
+            .execute(conn)
+    })
+    .await
+    .expect("backdate raise");
+    assert_eq!(escalate_alarms(&db, &site_db, &none).await.expect("escalate"), 0);
+
+    let url = format!("{}/{}", policies, policy.id);
+    let body = json!({ "name": "critical alarms", "enabled": false, "tiers": tiers });
+    let resp = client.put(url.clone()).cookie(admin.clone()).json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let updated: EscalationPolicy = resp.into_json().await.expect("json");
+    assert!(!updated.enabled);
+    let resp = client.delete(url.clone()).cookie(admin.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::NoContent);
+    let resp = client.delete(url).cookie(admin.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::NotFound);
+    let resp = client.get("/api/1/Alarms/103/Escalations").cookie(admin).dispatch().await;
+    let escalations: Vec<AlarmEscalation> = resp.into_json().await.expect("json");
+    assert_eq!(escalations.len(), 1);
+}
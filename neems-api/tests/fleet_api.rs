@@ -0,0 +1,168 @@
+//! Integration tests for the fleet endpoints.
+
+use chrono::Utc;
+use neems_api::orm::testing::fast_test_rocket;
+use rocket::{
+    http::{Header, Status},
+    local::asynchronous::Client,
+    tokio,
+};
+use serde_json::{Value, json};
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+/// Store a fresh `charging_state` reading for `site_id` through the sync
+/// endpoint.
+async fn seed_soc(client: &Client, origin: &str, site_id: i32, company_id: i32, level: f64) {
+    let session = login_as(client, "newtown_staff@example.com", "newtownstaffpass").await;
+    let now = Utc::now().naive_utc().format("%Y-%m-%dT%H:%M:%S").to_string();
+    let batch = json!({
+        "origin": origin,
+        "readings": [{
+            "origin_reading_id": 1,
+            "source_name": "charging_state",
+            "test_type": "charging_state",
+            "site_id": site_id,
+            "company_id": company_id,
+            "timestamp": now,
+            "device_timestamp": null,
+            "data": json!({ "level": level, "state": "charging" }).to_string(),
+            "quality_flags": 0
+        }]
+    });
+    let resp = client
+        .post("/api/1/Sync/Readings")
+        .cookie(session)
+        .json(&batch)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+}
+
+fn site(status: &Value, site_id: i32) -> Option<&Value> {
+    status["sites"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|s| s["site_id"] == json!(site_id))
+}
+
+#[tokio::test]
+async fn fleet_requires_authentication() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+
+    let resp = client.get("/api/1/Fleet/Status").dispatch().await;
+    assert_eq!(resp.status(), Status::Unauthorized);
+    let resp = client.get("/api/1/Fleet/Readings/latest").dispatch().await;
+    assert_eq!(resp.status(), Status::Unauthorized);
+}
+
+#[tokio::test]
+async fn fleet_status_aggregates_every_site_for_newtown() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    seed_soc(&client, "gateway-1", 1, 2, 62.5).await;
+
+    let session = login_as(&client, "newtown_superadmin@example.com", "newtownpass").await;
+    let resp = client.get("/api/1/Fleet/Status").cookie(session.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let status: Value = resp.into_json().await.expect("json");
+
+    let site_one = site(&status, 1).expect("site 1 listed");
+    assert_eq!(site_one["soc_percent"], json!(62.5));
+    assert_eq!(site_one["battery_state"], json!("charging"));
+    assert_eq!(site_one["connectivity"], json!("online"));
+    assert!(site(&status, 2).is_some(), "newtown sees other companies' sites");
+
+    let resp = client
+        .get("/api/1/Fleet/Readings/latest?test_type=charging_state")
+        .cookie(session)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let latest: Value = resp.into_json().await.expect("json");
+    assert!(latest["readings"].as_array().unwrap().iter().any(|r| {
+        r["site_id"] == json!(1) && r["source_name"] == json!("gateway-1/charging_state")
+    }));
+}
+
+#[tokio::test]
+async fn fleet_is_scoped_to_company_for_other_users() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    seed_soc(&client, "gateway-2", 2, 3, 40.0).await;
+
+    // staff@example.com belongs to company 2, which owns site 1 only
+    let session = login_as(&client, "staff@example.com", "staffpass").await;
+    let resp = client.get("/api/1/Fleet/Status").cookie(session.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let status: Value = resp.into_json().await.expect("json");
+    assert!(site(&status, 1).is_some());
+    assert!(site(&status, 2).is_none());
+
+    let resp = client.get("/api/1/Fleet/Readings/latest").cookie(session).dispatch().await;
+    let latest: Value = resp.into_json().await.expect("json");
+    assert!(latest["readings"].as_array().unwrap().iter().all(|r| r["site_id"] != json!(2)));
+}
+
+#[tokio::test]
+async fn fleet_and_readings_answer_conditional_gets() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    seed_soc(&client, "gateway-3", 1, 2, 55.0).await;
+    let session = login_as(&client, "newtown_superadmin@example.com", "newtownpass").await;
+
+    let resp = client.get("/api/1/Fleet/Status").cookie(session.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let last_modified = resp.headers().get_one("Last-Modified").expect("Last-Modified").to_string();
+
+    let resp = client
+        .get("/api/1/Fleet/Readings/latest?test_type=charging_state")
+        .cookie(session.clone())
+        .dispatch()
+        .await;
+    let latest: Value = resp.into_json().await.expect("json");
+    let source_id = latest["readings"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|r| r["source_name"] == json!("gateway-3/charging_state"))
+        .expect("seeded source")["source_id"]
+        .clone();
+
+    for url in [
+        "/api/1/Fleet/Status".to_string(),
+        "/api/1/Fleet/Readings/latest".to_string(),
+        format!("/api/1/DataSources/{}/Readings?latest=5", source_id),
+        format!("/api/1/Readings?source_ids={}", source_id),
+    ] {
+        let resp = client
+            .get(url.clone())
+            .cookie(session.clone())
+            .header(Header::new("If-Modified-Since", last_modified.clone()))
+            .dispatch()
+            .await;
+        assert_eq!(resp.status(), Status::NotModified, "{}", url);
+        assert!(resp.into_string().await.is_none_or(|body| body.is_empty()));
+
+        let resp = client
+            .get(url.clone())
+            .cookie(session.clone())
+            .header(Header::new("If-Modified-Since", "Mon, 01 Jan 2024 00:00:00 GMT"))
+            .dispatch()
+            .await;
+        assert_eq!(resp.status(), Status::Ok, "{}", url);
+        assert!(resp.headers().get_one("Last-Modified").is_some());
+    }
+
+    // An unparseable date is ignored
+    let resp = client
+        .get("/api/1/Fleet/Status")
+        .cookie(session)
+        .header(Header::new("If-Modified-Since", "yesterday"))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+}
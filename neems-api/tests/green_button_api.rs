@@ -0,0 +1,91 @@
+//! Integration tests for Green Button meter data import.
+
+use neems_api::{
+    api::{data::AggregateResponse, green_button::GreenButtonImportResponse},
+    orm::testing::{fast_test_rocket, golden_fixtures},
+};
+use neems_data::points::AggregateBackend;
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::{Value, json};
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+const DOWNLOAD: &str = "Name,Device Test Company A\n\
+                        \n\
+                        TYPE,DATE,START TIME,END TIME,USAGE (kWh),COST,NOTES\n\
+                        Electric usage,2026-07-01,00:00,00:59,120,$12.00,\n\
+                        Electric usage,2026-07-01,01:00,01:59,80,$8.00,\n\
+                        Electric usage,2026-07-01,02:00,02:59,-20,$0.00,\n";
+
+#[tokio::test]
+async fn import_green_button_csv() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let site_id = golden_fixtures().site_id("Device API Site A");
+    let url = format!("/api/1/Sites/{}/GreenButton?utc_offset_minutes=-420", site_id);
+
+    let resp = client.post(url.clone()).cookie(admin.clone()).body(DOWNLOAD).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let import: GreenButtonImportResponse = resp.into_json().await.expect("json");
+    assert_eq!(import.intervals, 3);
+    assert_eq!(import.start.to_string(), "2026-07-01 07:00:00");
+    assert_eq!(import.end.to_string(), "2026-07-01 10:00:00");
+    assert_eq!((import.delivered_kwh, import.received_kwh), (200.0, 20.0));
+
+    // Importing the same data again replaces it
+    let resp = client.post(url).cookie(admin.clone()).body(DOWNLOAD).dispatch().await;
+    let again: GreenButtonImportResponse = resp.into_json().await.expect("json");
+    assert_eq!(again.source_id, import.source_id);
+
+    let resp = client
+        .get(format!(
+            "/api/1/DataSources/{}/Aggregate?field=average_kw&from=2026-07-01T00:00:00Z&to=2026-07-02T00:00:00Z&bucket_seconds=86400",
+            import.source_id
+        ))
+        .cookie(admin)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let aggregate: AggregateResponse = resp.into_json().await.expect("json");
+    assert!(matches!(aggregate.backend, AggregateBackend::DailyRollup));
+    let bucket = &aggregate.buckets[0];
+    assert_eq!((bucket.count, bucket.min, bucket.max), (3, -20.0, 120.0));
+}
+
+#[tokio::test]
+async fn import_green_button_rejects_bad_files() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let fixtures = golden_fixtures();
+    let url = format!("/api/1/Sites/{}/GreenButton", fixtures.site_id("Device API Site A"));
+
+    let invalid = "DATE,START TIME,END TIME,USAGE\n2026-07-01,00:00,00:59,lots\n";
+    let resp = client.post(url.clone()).cookie(admin.clone()).body(invalid).dispatch().await;
+    assert_eq!(resp.status(), Status::UnprocessableEntity);
+    let body: Value = resp.into_json().await.expect("json");
+    assert_eq!(body["line_errors"][0]["line"], 2);
+
+    let resp = client.post(url.clone()).cookie(admin.clone()).body("<feed>").dispatch().await;
+    assert_eq!(resp.status(), Status::BadRequest);
+    let resp = client
+        .post(url)
+        .cookie(admin.clone())
+        .body("just,some\nwords,here\n")
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+
+    // Staff can't import, nor other companies' admins
+    let site_id = fixtures.site_id("Test Site 1");
+    let url = format!("/api/1/Sites/{}/GreenButton", site_id);
+    let resp = client.post(url.clone()).cookie(admin).body(DOWNLOAD).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+    let staff = login_as(&client, "staff@testcompany.com", "admin").await;
+    let resp = client.post(url).cookie(staff).body(DOWNLOAD).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+}
@@ -0,0 +1,179 @@
+//! Integration tests for site KPIs, their daily evaluation and results.
+
+use chrono::{Duration, NaiveDate, Utc};
+use neems_api::{
+    SiteDbConn,
+    models::{KpiDefinition, KpiResult},
+    orm::testing::{fast_test_rocket, golden_fixtures},
+};
+use neems_data::models::{NewReading, NewSource};
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::json;
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+/// A client whose site data has a battery on `site_id` reporting every half
+/// hour for the first twelve hours of `day`, at 40% SoC then 60%.
+async fn client_with_readings(site_id: i32, day: NaiveDate) -> Client {
+    let rocket = fast_test_rocket().ignite().await.expect("ignite");
+    let site_db = SiteDbConn::get_one(&rocket).await.expect("site database");
+    site_db
+        .run(move |conn| {
+            let source = neems_data::create_source(
+                conn,
+                NewSource {
+                    name: "BESS".to_string(),
+                    description: None,
+                    active: Some(true),
+                    interval_seconds: Some(1800),
+                    test_type: Some("simulator".to_string()),
+                    arguments: None,
+                    site_id: Some(site_id),
+                    company_id: None,
+                    priority: None,
+                    point_fields: None,
+                },
+            )
+            .expect("create source");
+            let start = day.and_hms_opt(0, 0, 0).unwrap();
+            let readings = (0..24)
+                .map(|i| NewReading {
+                    source_id: source.id.unwrap(),
+                    timestamp: Some(start + Duration::minutes(30 * i)),
+                    data:
+                        json!({ "soc_percent": if i < 12 { 40.0 } else { 60.0 }, "power_kw": 10.0 })
+                            .to_string(),
+                    quality_flags: None,
+                    device_timestamp: None,
+                })
+                .collect();
+            neems_data::insert_readings_batch(conn, readings).expect("insert readings");
+        })
+        .await;
+    Client::tracked(rocket).await.unwrap()
+}
+
+#[tokio::test]
+async fn site_admins_define_kpis_and_read_daily_results() {
+    let site_id = golden_fixtures().site_id("Device API Site A");
+    let yesterday = Utc::now().date_naive() - Duration::days(1);
+    let client = client_with_readings(site_id, yesterday).await;
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let kpis_url = format!("/api/1/Sites/{}/Kpis", site_id);
+
+    let mut kpis = Vec::new();
+    for body in [
+        json!({ "name": "Availability", "expression": "100 * covered_hours / 24", "target": 99.0, "unit": "%" }),
+        json!({ "name": "Mean SoC", "expression": "avg(soc_percent)", "target": 60.0, "higher_is_better": false }),
+        json!({ "name": "Revenue", "expression": "arbitrage_revenue" }),
+    ] {
+        let resp = client.post(kpis_url.clone()).cookie(admin.clone()).json(&body).dispatch().await;
+        assert_eq!(resp.status(), Status::Created);
+        kpis.push(resp.into_json::<KpiDefinition>().await.expect("json"));
+    }
+    assert!(kpis[0].higher_is_better);
+    assert_eq!(kpis[0].unit.as_deref(), Some("%"));
+
+    let resp = client
+        .post(kpis_url.clone())
+        .cookie(admin.clone())
+        .json(&json!({ "name": "", "expression": "uptime * 2" }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+    let body: serde_json::Value = resp.into_json().await.expect("json");
+    let message = body["error"].as_str().unwrap();
+    assert!(message.contains("name:") && message.contains("Unknown variable 'uptime'"));
+
+    let resp = client
+        .post(kpis_url.clone())
+        .cookie(admin.clone())
+        .json(&json!({ "name": "Availability", "expression": "1" }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Conflict);
+
+    let resp = client
+        .post(format!("{}/Evaluate?day={}", kpis_url, yesterday))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let results: Vec<KpiResult> = resp.into_json().await.expect("json");
+    assert_eq!(results.len(), 3);
+    let result = |kpi: &KpiDefinition| results.iter().find(|r| r.kpi_id == kpi.id).unwrap();
+    assert_eq!(result(&kpis[0]).value, Some(50.0));
+    assert_eq!(result(&kpis[0]).met, Some(false));
+    assert_eq!(result(&kpis[1]).value, Some(50.0));
+    assert_eq!(result(&kpis[1]).met, Some(true));
+    // No tariff, so no revenue
+    assert_eq!(result(&kpis[2]).value, None);
+    assert!(result(&kpis[2]).error.as_deref().unwrap().contains("arbitrage_revenue"));
+
+    // Other users of the company read the results; other companies can't
+    let results_url = format!("/api/1/Sites/{}/KpiResults", site_id);
+    let resp = client
+        .get(format!("{}?kpi_id={}", results_url, kpis[0].id))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    let results: Vec<KpiResult> = resp.into_json().await.expect("json");
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].day, yesterday);
+
+    let other = login_as(&client, "admin@company1.com", "admin").await;
+    let resp = client.get(results_url.clone()).cookie(other.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+    let resp = client
+        .delete(format!("{}/{}", kpis_url, kpis[0].id))
+        .cookie(other)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Forbidden);
+
+    let tomorrow = yesterday + Duration::days(2);
+    let resp = client
+        .post(format!("{}/Evaluate?day={}", kpis_url, tomorrow))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+
+    // Redefine a KPI and evaluate the day again
+    let resp = client
+        .put(format!("{}/{}", kpis_url, kpis[1].id))
+        .cookie(admin.clone())
+        .json(&json!({ "name": "Peak SoC", "expression": "max(soc_percent)", "target": 80.0 }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let resp = client
+        .post(format!("{}/Evaluate", kpis_url))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    let results: Vec<KpiResult> = resp.into_json().await.expect("json");
+    let peak = results.iter().find(|r| r.kpi_id == kpis[1].id).unwrap();
+    assert_eq!((peak.value, peak.met), (Some(60.0), Some(false)));
+
+    let resp = client
+        .delete(format!("{}/{}", kpis_url, kpis[0].id))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::NoContent);
+    let resp = client.get(results_url).cookie(admin.clone()).dispatch().await;
+    let results: Vec<KpiResult> = resp.into_json().await.expect("json");
+    assert!(results.iter().all(|r| r.kpi_id != kpis[0].id));
+    let resp = client.get(kpis_url).cookie(admin).dispatch().await;
+    let kpis: Vec<KpiDefinition> = resp.into_json().await.expect("json");
+    assert_eq!(
+        kpis.iter().map(|k| k.name.as_str()).collect::<Vec<_>>(),
+        vec!["Peak SoC", "Revenue"]
+    );
+}
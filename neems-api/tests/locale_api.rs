@@ -0,0 +1,189 @@
+//! Integration tests for locale preferences and translated responses.
+
+use neems_api::{
+    models::{LocaleInfo, LocaleSetting},
+    orm::testing::{fast_test_rocket, golden_fixtures},
+};
+use neems_data::i18n::Locale;
+use rocket::{
+    http::{Header, Status},
+    local::asynchronous::Client,
+    tokio,
+};
+use serde_json::{Value, json};
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+#[tokio::test]
+async fn errors_follow_accept_language() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let session = login_as(&client, "staff@testcompany.com", "admin").await;
+
+    let resp = client
+        .get("/api/1/Companies/99999/Locale")
+        .cookie(session.clone())
+        .header(Header::new("Accept-Language", "de, es-MX;q=0.9, en;q=0.5"))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::NotFound);
+    assert_eq!(resp.headers().get_one("Content-Language"), Some("es"));
+    assert!(resp.headers().get("Vary").any(|vary| vary == "Accept-Language"));
+    let body: Value = resp.into_json().await.expect("json");
+    assert_eq!(body["error"], json!("Empresa no encontrada"));
+
+    let resp = client
+        .get("/api/1/Companies/99999/Locale")
+        .cookie(session.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.headers().get_one("Content-Language"), Some("en"));
+    let body: Value = resp.into_json().await.expect("json");
+    assert_eq!(body["error"], json!("Company not found"));
+
+    let resp = client.get("/api/1/Locales").cookie(session).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    assert_eq!(resp.headers().get_one("Content-Language"), None);
+    let locales: Vec<LocaleInfo> = resp.into_json().await.expect("json");
+    assert_eq!(
+        locales.iter().map(|l| l.locale).collect::<Vec<_>>(),
+        vec![Locale::En, Locale::Es, Locale::Fr]
+    );
+}
+
+#[tokio::test]
+async fn user_and_company_locales() {
+    let fixtures = golden_fixtures();
+    let company_id = fixtures.company_id("Test Company 1");
+    let user_id = fixtures.user_id("staff@testcompany.com");
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@company1.com", "admin").await;
+    let user = login_as(&client, "staff@testcompany.com", "admin").await;
+    let french = Header::new("Accept-Language", "fr");
+
+    // Only the company's admins (and Newtown staff) set its locale
+    let company_locale = format!("/api/1/Companies/{}/Locale", company_id);
+    let resp = client
+        .put(company_locale.clone())
+        .cookie(user.clone())
+        .json(&json!({ "locale": "es" }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Forbidden);
+    let resp = client
+        .put(company_locale.clone())
+        .cookie(admin.clone())
+        .json(&json!({ "locale": "de" }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::UnprocessableEntity);
+    let resp = client
+        .put(company_locale.clone())
+        .cookie(admin.clone())
+        .json(&json!({ "locale": "es" }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let resp = client.get(company_locale.clone()).cookie(user.clone()).dispatch().await;
+    let setting: LocaleSetting = resp.into_json().await.expect("json");
+    assert_eq!(
+        setting,
+        LocaleSetting {
+            locale: Some(Locale::Es),
+            effective: Locale::Es
+        }
+    );
+
+    // The company's locale beats Accept-Language
+    let resp = client
+        .get("/api/1/Companies/99999/Locale")
+        .cookie(user.clone())
+        .header(french.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::NotFound);
+    assert_eq!(resp.headers().get_one("Content-Language"), Some("es"));
+    let body: Value = resp.into_json().await.expect("json");
+    assert_eq!(body["error"], json!("Empresa no encontrada"));
+
+    // ... and the user's own beats the company's
+    let user_locale = format!("/api/1/Users/{}/Locale", user_id);
+    let resp = client
+        .put(user_locale.clone())
+        .cookie(user.clone())
+        .json(&json!({ "locale": "fr" }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let setting: LocaleSetting = resp.into_json().await.expect("json");
+    assert_eq!(
+        setting,
+        LocaleSetting {
+            locale: Some(Locale::Fr),
+            effective: Locale::Fr
+        }
+    );
+    let resp = client
+        .put(company_locale.clone())
+        .cookie(user.clone())
+        .json(&json!({ "locale": null }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Forbidden);
+    let body: Value = resp.into_json().await.expect("json");
+    assert_eq!(body["error"], json!("Interdit : autorisations insuffisantes"));
+
+    let resp = client
+        .put(user_locale.clone())
+        .cookie(user.clone())
+        .json(&json!({ "locale": "pt" }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::UnprocessableEntity);
+
+    // Messages outside the catalog stay in English
+    let resp = client
+        .get(format!("/api/1/Companies/{}/NotificationChannels", company_id))
+        .cookie(user.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Forbidden);
+    assert_eq!(resp.headers().get_one("Content-Language"), None);
+    let body: Value = resp.into_json().await.expect("json");
+    assert!(body["error"].as_str().unwrap().starts_with("Only Newtown staff"));
+
+    // Other companies' users can't see or change it
+    let other = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let resp = client.get(user_locale.clone()).cookie(other).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+
+    // Cleared, the company's applies again
+    let resp = client
+        .put(user_locale.clone())
+        .cookie(admin.clone())
+        .json(&json!({ "locale": null }))
+        .dispatch()
+        .await;
+    let setting: LocaleSetting = resp.into_json().await.expect("json");
+    assert_eq!(setting, LocaleSetting { locale: None, effective: Locale::Es });
+
+    // New notification channels take the company's locale
+    let resp = client
+        .post(format!("/api/1/Companies/{}/NotificationChannels", company_id))
+        .cookie(admin)
+        .json(&json!({
+            "name": "operaciones",
+            "kind": "slack",
+            "destination": "https://hooks.slack.com/services/T/B/x",
+            "severities": ["warning"]
+        }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let channel: Value = resp.into_json().await.expect("json");
+    assert_eq!(channel["locale"], json!("es"));
+}
@@ -0,0 +1,108 @@
+//! Login audit tests
+//!
+//! Covers recording login attempts, /api/1/Users/{id}/LoginHistory and
+//! /api/1/LoginAnomalies. The rocket reads client countries from an
+//! `X-Country` header, as if a geolocating proxy set it.
+
+use neems_api::orm::testing::fast_test_rocket;
+use rocket::{
+    http::{ContentType, Cookie, Header, Status},
+    local::asynchronous::Client,
+};
+use serde_json::{Value, json};
+
+async fn client() -> Client {
+    let rocket = fast_test_rocket();
+    let figment = rocket.figment().clone().merge(("country_header", "X-Country"));
+    Client::untracked(rocket.configure(figment))
+        .await
+        .expect("valid rocket instance")
+}
+
+/// Attempt a login from a country, returning the session cookie if it worked
+async fn login(
+    client: &Client,
+    email: &str,
+    password: &str,
+    country: &str,
+) -> Option<Cookie<'static>> {
+    let response = client
+        .post("/api/1/login")
+        .header(ContentType::JSON)
+        .header(Header::new("X-Country", country.to_string()))
+        .header(Header::new("User-Agent", "login-history-test"))
+        .body(json!({ "email": email, "password": password }).to_string())
+        .dispatch()
+        .await;
+    response.cookies().get("session").map(|cookie| cookie.clone().into_owned())
+}
+
+async fn get_json(client: &Client, url: &str, cookie: &Cookie<'static>) -> (Status, Value) {
+    let response = client.get(url).cookie(cookie.clone()).dispatch().await;
+    let status = response.status();
+    (status, response.into_json().await.unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn test_login_history_records_attempts() {
+    let client = client().await;
+    let admin_cookie = login(&client, "superadmin@example.com", "admin", "us").await.unwrap();
+    assert!(login(&client, "testuser@example.com", "wrong", "us").await.is_none());
+    assert!(login(&client, "nobody@example.com", "admin", "us").await.is_none());
+    let user_cookie = login(&client, "testuser@example.com", "admin", "us").await.unwrap();
+
+    let (_, me) = get_json(&client, "/api/1/hello", &user_cookie).await;
+    let user_id = me["user_id"].as_i64().unwrap();
+    let history_url = format!("/api/1/Users/{}/LoginHistory", user_id);
+
+    let (status, history) = get_json(&client, &history_url, &user_cookie).await;
+    assert_eq!(status, Status::Ok);
+    let history = history.as_array().unwrap();
+    assert_eq!(history.len(), 2, "unknown emails aren't in anyone's history");
+    assert_eq!(history[0]["success"], true);
+    assert_eq!(history[1]["success"], false);
+    assert_eq!(history[0]["country"], "US");
+    assert_eq!(history[0]["user_agent"], "login-history-test");
+    assert_eq!(history[0]["anomaly"], Value::Null);
+
+    let (status, history) =
+        get_json(&client, &format!("{}?limit=1", history_url), &admin_cookie).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(history.as_array().unwrap().len(), 1);
+
+    // Staff can't see other users' history, or the anomaly feed
+    let (_, admin) = get_json(&client, "/api/1/hello", &admin_cookie).await;
+    let admin_history_url = format!("/api/1/Users/{}/LoginHistory", admin["user_id"]);
+    let (status, _) = get_json(&client, &admin_history_url, &user_cookie).await;
+    assert_eq!(status, Status::Forbidden);
+    let (status, _) = get_json(&client, "/api/1/LoginAnomalies", &user_cookie).await;
+    assert_eq!(status, Status::Forbidden);
+
+    let (status, _) = get_json(&client, "/api/1/Users/999999/LoginHistory", &admin_cookie).await;
+    assert_eq!(status, Status::NotFound);
+}
+
+#[tokio::test]
+async fn test_login_anomalies_feed() {
+    let client = client().await;
+    let admin_cookie = login(&client, "superadmin@example.com", "admin", "US").await.unwrap();
+    login(&client, "testuser@example.com", "admin", "US").await.unwrap();
+
+    // A new country
+    login(&client, "testuser@example.com", "admin", "RO").await.unwrap();
+
+    // Repeated failures, then a success
+    for _ in 0..5 {
+        assert!(login(&client, "testuser@example.com", "guess", "US").await.is_none());
+    }
+    login(&client, "testuser@example.com", "admin", "US").await.unwrap();
+
+    let (status, anomalies) = get_json(&client, "/api/1/LoginAnomalies", &admin_cookie).await;
+    assert_eq!(status, Status::Ok);
+    let anomalies = anomalies.as_array().unwrap();
+    assert_eq!(anomalies.len(), 2);
+    assert_eq!(anomalies[0]["email"], "testuser@example.com");
+    assert_eq!(anomalies[0]["anomaly"], "failures_then_success");
+    assert_eq!(anomalies[1]["anomaly"], "new_country");
+    assert_eq!(anomalies[1]["country"], "RO");
+}
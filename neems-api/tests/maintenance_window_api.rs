@@ -0,0 +1,174 @@
+//! Integration tests for site maintenance windows.
+
+use chrono::{Duration, Utc};
+use neems_api::{
+    models::{ActiveCommandResponse, CommandSource},
+    orm::testing::{fast_test_rocket, golden_fixtures},
+};
+use neems_data::maintenance_windows::MaintenanceWindow;
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::json;
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+async fn active_command(
+    client: &Client,
+    cookie: &rocket::http::Cookie<'static>,
+    site_id: i32,
+    query: &str,
+) -> ActiveCommandResponse {
+    let resp = client
+        .get(format!("/api/1/Sites/{}/ActiveCommand{}", site_id, query))
+        .cookie(cookie.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    resp.into_json().await.expect("json")
+}
+
+#[tokio::test]
+async fn maintenance_windows_hold_the_site_in_standby() {
+    let fixtures = golden_fixtures();
+    let site_id = fixtures.site_id("Device API Site A");
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+
+    let device_id = fixtures.device_id("SEL-451");
+
+    let before = active_command(&client, &admin, site_id, "").await;
+    assert_eq!(before.source, CommandSource::Schedule);
+    assert!(before.maintenance.is_none());
+
+    // A device's window only stops that device
+    let ends_at = (Utc::now() + Duration::hours(2)).naive_utc();
+    let resp = client
+        .post(format!("/api/1/Sites/{}/MaintenanceWindows", site_id))
+        .cookie(admin.clone())
+        .json(&json!({ "device_id": device_id, "ends_at": ends_at, "reason": "Inverter swap" }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let device_window: MaintenanceWindow = resp.into_json().await.expect("json");
+    assert_eq!(device_window.created_by, "admin@devicetesta.com");
+    assert_eq!(device_window.device_id, Some(device_id));
+
+    let site = active_command(&client, &admin, site_id, "").await;
+    assert_eq!(site.source, CommandSource::Schedule);
+    let device =
+        active_command(&client, &admin, site_id, &format!("?device_id={}", device_id)).await;
+    assert_eq!(device.source, CommandSource::Maintenance);
+    assert!(device.command.is_none());
+    assert_eq!(device.maintenance.map(|w| w.id), Some(device_window.id));
+
+    // A site window stops everything
+    let resp = client
+        .post(format!("/api/1/Sites/{}/MaintenanceWindows", site_id))
+        .cookie(admin.clone())
+        .json(&json!({ "ends_at": ends_at, "reason": "Switchgear inspection" }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let site_window: MaintenanceWindow = resp.into_json().await.expect("json");
+    let site = active_command(&client, &admin, site_id, "").await;
+    assert_eq!(site.source, CommandSource::Maintenance);
+    assert_eq!(site.maintenance.map(|w| w.id), Some(site_window.id));
+
+    let resp = client
+        .get(format!("/api/1/Sites/{}/MaintenanceWindows?current=true", site_id))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let windows: Vec<MaintenanceWindow> = resp.into_json().await.expect("json");
+    assert_eq!(windows.len(), 2);
+
+    // Ending both lets the schedule back in, and keeps them for the record
+    for window in [&site_window, &device_window] {
+        let resp = client
+            .delete(format!("/api/1/Sites/{}/MaintenanceWindows/{}", site_id, window.id))
+            .cookie(admin.clone())
+            .dispatch()
+            .await;
+        assert_eq!(resp.status(), Status::Ok);
+        let ended: MaintenanceWindow = resp.into_json().await.expect("json");
+        assert!(ended.ends_at <= Utc::now().naive_utc());
+    }
+    let device =
+        active_command(&client, &admin, site_id, &format!("?device_id={}", device_id)).await;
+    assert_eq!(device.source, CommandSource::Schedule);
+    let resp = client
+        .get(format!("/api/1/Sites/{}/MaintenanceWindows?current=true", site_id))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    let windows: Vec<MaintenanceWindow> = resp.into_json().await.expect("json");
+    assert!(windows.is_empty());
+    let resp = client
+        .get(format!("/api/1/Sites/{}/MaintenanceWindows", site_id))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    let windows: Vec<MaintenanceWindow> = resp.into_json().await.expect("json");
+    assert_eq!(windows.len(), 2);
+}
+
+#[tokio::test]
+async fn maintenance_window_validation_and_permissions() {
+    let fixtures = golden_fixtures();
+    let site_id = fixtures.site_id("Device API Site A");
+    let other_site_id = fixtures.site_id("Test Site 1");
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+
+    let now = Utc::now().naive_utc();
+    for body in [
+        json!({ "ends_at": now - Duration::hours(1), "reason": "Inverter swap" }),
+        json!({ "ends_at": now + Duration::days(40), "reason": "Inverter swap" }),
+        json!({ "ends_at": now + Duration::hours(1), "reason": " " }),
+        json!({ "device_id": 999_999, "ends_at": now + Duration::hours(1), "reason": "Inverter swap" }),
+    ] {
+        let resp = client
+            .post(format!("/api/1/Sites/{}/MaintenanceWindows", site_id))
+            .cookie(admin.clone())
+            .json(&body)
+            .dispatch()
+            .await;
+        assert_eq!(resp.status(), Status::BadRequest, "{}", body);
+    }
+
+    // Another company's admin can't see or open windows on the site
+    let other = login_as(&client, "admin@company1.com", "admin").await;
+    let resp = client
+        .get(format!("/api/1/Sites/{}/MaintenanceWindows", site_id))
+        .cookie(other.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Forbidden);
+    let resp = client
+        .post(format!("/api/1/Sites/{}/MaintenanceWindows", site_id))
+        .cookie(other.clone())
+        .json(&json!({ "ends_at": now + Duration::hours(1), "reason": "Inverter swap" }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Forbidden);
+
+    // A window can only be ended through its own site
+    let resp = client
+        .post(format!("/api/1/Sites/{}/MaintenanceWindows", site_id))
+        .cookie(admin.clone())
+        .json(&json!({ "ends_at": now + Duration::hours(1), "reason": "Inverter swap" }))
+        .dispatch()
+        .await;
+    let window: MaintenanceWindow = resp.into_json().await.expect("json");
+    let resp = client
+        .delete(format!("/api/1/Sites/{}/MaintenanceWindows/{}", other_site_id, window.id))
+        .cookie(other)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::NotFound);
+}
@@ -0,0 +1,70 @@
+//! Integration tests for mock mode: seeded in-memory data, and requests
+//! without credentials acting as the mock user.
+
+use neems_api::mock::{DEFAULT_MOCK_USER, mock_rocket};
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::{Value, json};
+
+#[tokio::test]
+async fn mock_mode_serves_seeded_data_without_login() {
+    let client = Client::tracked(mock_rocket(DEFAULT_MOCK_USER)).await.expect("mock rocket");
+
+    let resp = client.get("/api/1/hello").dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let me: Value = resp.into_json().await.expect("json");
+    assert_eq!(me["email"], DEFAULT_MOCK_USER);
+
+    let resp = client.get("/api/1/Sites").dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let sites: Value = resp.into_json().await.expect("json");
+    let sites = sites["value"].as_array().expect("sites");
+    let names: Vec<&str> = sites.iter().filter_map(|s| s["name"].as_str()).collect();
+    assert!(names.contains(&"Test Site 1"), "{:?}", names);
+
+    // Every site has history to chart
+    let site_id = sites[0]["id"].as_i64().expect("site id");
+    let resp = client.get(format!("/api/1/Sites/{}/SocHistory", site_id)).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let history: Value = resp.into_json().await.expect("json");
+    assert!(!history["points"].as_array().expect("points").is_empty());
+
+    // Logging in as someone else still works, and their session wins
+    let resp = client
+        .post("/api/1/login")
+        .json(&json!({ "email": "staff@testcompany.com", "password": "admin" }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let resp = client.get("/api/1/hello").dispatch().await;
+    let me: Value = resp.into_json().await.expect("json");
+    assert_eq!(me["email"], "staff@testcompany.com");
+}
+
+#[tokio::test]
+async fn mock_mode_ids_are_deterministic() {
+    let ids = |sites: Value| -> Vec<(i64, String)> {
+        let mut ids: Vec<_> = sites["value"]
+            .as_array()
+            .expect("sites")
+            .iter()
+            .map(|s| (s["id"].as_i64().unwrap(), s["name"].as_str().unwrap().to_string()))
+            .collect();
+        ids.sort();
+        ids
+    };
+    let mut runs = Vec::new();
+    for _ in 0..2 {
+        let client = Client::tracked(mock_rocket(DEFAULT_MOCK_USER)).await.expect("mock rocket");
+        let resp = client.get("/api/1/Sites").dispatch().await;
+        runs.push(ids(resp.into_json().await.expect("json")));
+    }
+    assert_eq!(runs[0], runs[1]);
+}
+
+#[tokio::test]
+async fn mock_mode_refuses_unknown_mock_user() {
+    match Client::tracked(mock_rocket("nobody@example.com")).await {
+        Err(e) => assert!(matches!(e.kind(), rocket::error::ErrorKind::FailedFairings(_))),
+        Ok(_) => panic!("mock mode started with an unknown mock user"),
+    }
+}
@@ -0,0 +1,158 @@
+//! Integration tests for the company notification channel endpoints.
+
+use neems_api::orm::testing::{fast_test_rocket, golden_fixtures};
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::{Value, json};
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+fn channels_path(company: &str) -> String {
+    format!(
+        "/api/1/Companies/{}/NotificationChannels",
+        golden_fixtures().company_id(company)
+    )
+}
+
+fn pagerduty_settings(name: &str) -> Value {
+    json!({
+        "name": name,
+        "kind": "pagerduty",
+        "destination": "0123456789abcdef0123456789abcdef",
+        "severities": ["critical", "emergency"]
+    })
+}
+
+#[tokio::test]
+async fn company_admin_manages_own_channels() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@company1.com", "admin").await;
+    let path = channels_path("Test Company 1");
+
+    let resp = client
+        .post(&path)
+        .cookie(admin.clone())
+        .json(&pagerduty_settings("on-call"))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let created: Value = resp.into_json().await.expect("json");
+    let id = created["id"].as_i64().expect("id");
+    assert_eq!(created["kind"], json!("pagerduty"));
+    assert_eq!(created["destination"], json!("***"));
+    assert_eq!(created["severities"], json!(["critical", "emergency"]));
+    assert_eq!(created["send_cleared"], json!(true));
+    assert_eq!(created["enabled"], json!(true));
+
+    let resp = client
+        .post(&path)
+        .cookie(admin.clone())
+        .json(&pagerduty_settings("on-call"))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Conflict);
+
+    // Replacing without a destination keeps the stored one...
+    let mut settings = pagerduty_settings("on-call");
+    settings["destination"] = Value::Null;
+    settings["severities"] = json!(["emergency"]);
+    let resp = client
+        .put(format!("{}/{}", path, id))
+        .cookie(admin.clone())
+        .json(&settings)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let updated: Value = resp.into_json().await.expect("json");
+    assert_eq!(updated["severities"], json!(["emergency"]));
+    assert_eq!(updated["destination"], json!("***"));
+
+    // ...but a routing key isn't a Slack webhook
+    settings["kind"] = json!("slack");
+    let resp = client
+        .put(format!("{}/{}", path, id))
+        .cookie(admin.clone())
+        .json(&settings)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+
+    let resp = client.get(&path).cookie(admin.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let listed: Vec<Value> = resp.into_json().await.expect("json");
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0]["name"], json!("on-call"));
+    assert_eq!(listed[0]["kind"], json!("pagerduty"));
+
+    let resp = client.delete(format!("{}/{}", path, id)).cookie(admin.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::NoContent);
+    let resp = client.delete(format!("{}/{}", path, id)).cookie(admin).dispatch().await;
+    assert_eq!(resp.status(), Status::NotFound);
+}
+
+#[tokio::test]
+async fn invalid_channel_settings_are_rejected() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let staff = login_as(&client, "newtown_staff@example.com", "newtownstaffpass").await;
+
+    let resp = client
+        .post(channels_path("Test Company 2"))
+        .cookie(staff.clone())
+        .json(&json!({
+            "name": "chat",
+            "kind": "teams",
+            "destination": "http://example.com/webhook",
+            "severities": []
+        }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+    let body: Value = resp.into_json().await.expect("json");
+    let message = body["error"].as_str().expect("error message");
+    assert!(message.contains("must use https"), "{}", message);
+    assert!(message.contains("severities:"), "{}", message);
+
+    let mut settings = pagerduty_settings("on-call");
+    settings["destination"] = Value::Null;
+    let resp = client
+        .post(channels_path("Test Company 2"))
+        .cookie(staff)
+        .json(&settings)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+    let body: Value = resp.into_json().await.expect("json");
+    assert_eq!(body["error"], json!("destination: required"));
+}
+
+#[tokio::test]
+async fn channels_require_company_access() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let path = channels_path("Test Company 2");
+
+    let resp = client.get(&path).dispatch().await;
+    assert_eq!(resp.status(), Status::Unauthorized);
+
+    // Company 1's admin can't manage Company 2's channels
+    let admin = login_as(&client, "admin@company1.com", "admin").await;
+    let resp = client.get(&path).cookie(admin.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+    let resp = client
+        .post(&path)
+        .cookie(admin.clone())
+        .json(&pagerduty_settings("on-call"))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Forbidden);
+
+    let resp = client
+        .get("/api/1/Companies/999999/NotificationChannels")
+        .cookie(admin)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::NotFound);
+}
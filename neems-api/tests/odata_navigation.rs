@@ -4,9 +4,11 @@
 //! relationships between entities through dedicated endpoints.
 
 use neems_api::{
-    models::{Company, Role, Site, UserWithRoles},
-    orm::testing::fast_test_rocket,
+    SiteDbConn,
+    models::{Company, Device, Role, Site, UserWithRoles},
+    orm::testing::{fast_test_rocket, golden_fixtures},
 };
+use neems_data::models::{NewSource, Source};
 use rocket::{
     http::{ContentType, Status},
     local::asynchronous::Client,
@@ -176,6 +178,105 @@ async fn test_company_sites_navigation() {
     }
 }
 
+#[rocket::async_test]
+async fn test_site_devices_and_device_source_navigation() {
+    let fixtures = golden_fixtures();
+    let site_id = fixtures.site_id("Device API Site A");
+    let meter_id = fixtures.device_id("SEL-735");
+    let relay_id = fixtures.device_id("SEL-451");
+
+    // A source on the site, which a device group names as reporting the meter
+    let rocket = fast_test_rocket().ignite().await.expect("ignite");
+    let site_db = SiteDbConn::get_one(&rocket).await.expect("site database");
+    let source_id = site_db
+        .run(move |conn| {
+            neems_data::create_source(
+                conn,
+                NewSource {
+                    name: "Revenue Meter".to_string(),
+                    description: None,
+                    active: Some(true),
+                    interval_seconds: Some(60),
+                    test_type: Some("simulator".to_string()),
+                    arguments: None,
+                    site_id: Some(site_id),
+                    company_id: None,
+                    priority: None,
+                    point_fields: None,
+                },
+            )
+            .expect("create source")
+            .id
+            .unwrap()
+        })
+        .await;
+    let client = Client::tracked(rocket).await.expect("valid rocket instance");
+    let device_admin = login_user(&client, "admin@devicetesta.com", "admin").await;
+    let body = json!({
+        "name": "Metering",
+        "members": [{ "device_id": meter_id, "source_id": source_id }, { "device_id": relay_id }]
+    });
+    let response = client
+        .post(format!("/api/1/Sites/{}/DeviceGroups", site_id))
+        .cookie(device_admin.clone())
+        .json(&body)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+
+    // Site -> Devices
+    let response = client
+        .get(format!("/api/1/Sites/{}/Devices", site_id))
+        .cookie(device_admin.clone())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let devices: Vec<Device> = response.into_json().await.expect("valid devices JSON");
+    assert!(devices.iter().all(|d| d.site_id == site_id));
+    assert!(devices.iter().any(|d| d.id == meter_id));
+    assert!(devices.iter().any(|d| d.id == relay_id));
+
+    // Device -> Source, and a device no group names a source for
+    let response = client
+        .get(format!("/api/1/Devices/{}/Source", meter_id))
+        .cookie(device_admin.clone())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let source: Source = response.into_json().await.expect("valid source JSON");
+    assert_eq!(source.id, Some(source_id));
+    assert_eq!(source.name, "Revenue Meter");
+    let response = client
+        .get(format!("/api/1/Devices/{}/Source", relay_id))
+        .cookie(device_admin)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    // Another company's user can't navigate from this site or its devices
+    let other = login_user(&client, "admin@company1.com", "admin").await;
+    for url in [
+        format!("/api/1/Sites/{}/Devices", site_id),
+        format!("/api/1/Devices/{}/Source", meter_id),
+    ] {
+        let response = client.get(url.clone()).cookie(other.clone()).dispatch().await;
+        assert_eq!(response.status(), Status::Forbidden, "{}", url);
+    }
+
+    // Newtown staff can
+    let staff = login_user(&client, "newtownstaff@newtown.com", "admin").await;
+    let response = client
+        .get(format!("/api/1/Devices/{}/Source", meter_id))
+        .cookie(staff.clone())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    // A site that doesn't exist
+    let response = client.get("/api/1/Sites/99999/Devices").cookie(staff).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
 #[rocket::async_test]
 async fn test_navigation_authorization() {
     let client = Client::tracked(fast_test_rocket()).await.expect("valid rocket instance");
@@ -236,6 +337,8 @@ async fn test_navigation_requires_authentication() {
         "/api/1/Users/1/Roles",
         "/api/1/Companies/1/Users",
         "/api/1/Companies/1/Sites",
+        "/api/1/Sites/1/Devices",
+        "/api/1/Devices/1/Source",
     ];
 
     for endpoint in endpoints {
@@ -0,0 +1,283 @@
+//! Integration tests for on-call rotations and their use by alarm escalation.
+
+use std::collections::HashSet;
+
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use neems_api::{
+    DbConn, SiteDbConn,
+    alarm_escalation::escalate_alarms,
+    models::{AlarmEscalation, OnCallOverride, OnCallRotation, OnCallShift},
+    orm::testing::{fast_test_rocket, golden_fixtures},
+    schema::alarm_states,
+};
+use neems_data::models::{NewReading, NewSource};
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::{Value, json};
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+#[tokio::test]
+async fn rotations_name_who_is_on_call() {
+    let fixtures = golden_fixtures();
+    let company_id = fixtures.company_id("Test Company 1");
+    let alex = fixtures.user_id("user@company1.com");
+    let sam = fixtures.user_id("staff@example.com");
+    let outsider = fixtures.user_id("admin@devicetesta.com");
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@company1.com", "admin").await;
+
+    let rotations = format!("/api/1/Companies/{}/OnCallRotations", company_id);
+    let handoff = (Utc::now() - Duration::hours(1)).naive_utc();
+    let body = json!({
+        "name": "operations",
+        "handoff_at": handoff,
+        "shift_hours": 24,
+        "members": [alex, sam]
+    });
+    let invalid = [
+        json!({ "name": "bad", "handoff_at": handoff, "members": [] }),
+        json!({ "name": "bad", "handoff_at": handoff, "shift_hours": 0, "members": [alex] }),
+        json!({ "name": "bad", "handoff_at": handoff, "members": [outsider] }),
+    ];
+    for invalid in invalid {
+        let resp = client
+            .post(rotations.clone())
+            .cookie(admin.clone())
+            .json(&invalid)
+            .dispatch()
+            .await;
+        assert_eq!(resp.status(), Status::BadRequest, "{}", invalid);
+    }
+    let resp = client
+        .post(rotations.clone())
+        .cookie(admin.clone())
+        .json(&body)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let rotation: OnCallRotation = resp.into_json().await.expect("json");
+    assert_eq!(rotation.members.iter().map(|m| m.user_id).collect::<Vec<_>>(), vec![alex, sam]);
+    let resp = client
+        .post(rotations.clone())
+        .cookie(admin.clone())
+        .json(&body)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Conflict);
+
+    // Company users can see who is on call but not change the rotation
+    let staff = login_as(&client, "staff@example.com", "staffpass").await;
+    let url = format!("{}/{}", rotations, rotation.id);
+    let resp = client.get(format!("{}/OnCall", url)).cookie(staff.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let shift: OnCallShift = resp.into_json().await.expect("json");
+    assert_eq!((shift.user_id, shift.overridden), (alex, false));
+    assert_eq!(shift.until, handoff + Duration::hours(24));
+    let resp = client.delete(url.clone()).cookie(staff.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+    let other = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let resp = client.get(rotations.clone()).cookie(other).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+
+    // A day on, it is the second member's shift
+    let tomorrow = (Utc::now() + Duration::hours(24)).to_rfc3339();
+    let resp = client
+        .get(format!("{}/OnCall?at={}", url, tomorrow.replace('+', "%2B")))
+        .cookie(staff.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let shift: OnCallShift = resp.into_json().await.expect("json");
+    assert_eq!(shift.user_id, sam);
+    let resp = client
+        .get(format!("{}/OnCall?at=soon", url))
+        .cookie(staff.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+
+    // An override puts the second member on call now
+    let overrides = format!("{}/Overrides", url);
+    let cover = json!({
+        "user_id": sam,
+        "starts_at": handoff,
+        "ends_at": handoff + Duration::hours(4),
+        "reason": "Swap"
+    });
+    let resp = client
+        .post(overrides.clone())
+        .cookie(admin.clone())
+        .json(&cover)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let created: OnCallOverride = resp.into_json().await.expect("json");
+    assert_eq!(created.email, "staff@example.com");
+    let resp = client.get(format!("{}/OnCall", url)).cookie(staff.clone()).dispatch().await;
+    let shift: OnCallShift = resp.into_json().await.expect("json");
+    assert_eq!((shift.user_id, shift.overridden), (sam, true));
+    let backwards = json!({ "user_id": sam, "starts_at": handoff, "ends_at": handoff });
+    let resp = client
+        .post(overrides.clone())
+        .cookie(admin.clone())
+        .json(&backwards)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+
+    let resp = client
+        .delete(format!("{}/{}", overrides, created.id))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::NoContent);
+    let resp = client.get(format!("{}/OnCall", url)).cookie(staff).dispatch().await;
+    let shift: OnCallShift = resp.into_json().await.expect("json");
+    assert_eq!(shift.user_id, alex);
+
+    // Replacing the members keeps the rotation
+    let body = json!({ "name": "operations", "handoff_at": handoff, "members": [sam] });
+    let resp = client.put(url.clone()).cookie(admin.clone()).json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let updated: OnCallRotation = resp.into_json().await.expect("json");
+    assert_eq!((updated.shift_hours, updated.members.len()), (168, 1));
+
+    let resp = client.delete(url.clone()).cookie(admin.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::NoContent);
+    let resp = client.get(format!("{}/OnCall", url)).cookie(admin).dispatch().await;
+    assert_eq!(resp.status(), Status::NotFound);
+}
+
+#[tokio::test]
+async fn escalation_reaches_whoever_is_on_call() {
+    let fixtures = golden_fixtures();
+    let company_id = fixtures.company_id("Test Company 1");
+    let site_id = fixtures.site_id("Test Site 1");
+    let alex = fixtures.user_id("user@company1.com");
+    let rocket = fast_test_rocket().ignite().await.expect("ignite");
+    let site_db = SiteDbConn::get_one(&rocket).await.expect("site database");
+    site_db
+        .run(move |conn| {
+            // An RTAC at the company's site with a critical alarm set
+            let source = neems_data::create_source(
+                conn,
+                NewSource {
+                    name: "RTAC".to_string(),
+                    description: None,
+                    active: Some(true),
+                    interval_seconds: Some(1),
+                    test_type: None,
+                    arguments: None,
+                    site_id: Some(site_id),
+                    company_id: None,
+                    priority: None,
+                    point_fields: None,
+                },
+            )
+            .expect("create source");
+            let mut registers = vec![0u16; 22];
+            registers[1] = 1 << 2;
+            let reading = NewReading {
+                source_id: source.id.unwrap(),
+                timestamp: Some(Utc::now().naive_utc()),
+                data: json!({ "alarm_registers": registers }).to_string(),
+                quality_flags: None,
+                device_timestamp: None,
+            };
+            neems_data::insert_readings_batch(conn, vec![reading]).expect("insert reading");
+        })
+        .await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let admin = login_as(&client, "admin@company1.com", "admin").await;
+
+    let resp = client
+        .post(format!("/api/1/Companies/{}/NotificationChannels", company_id))
+        .cookie(admin.clone())
+        .json(&json!({
+            "name": "on-call",
+            "kind": "pagerduty",
+            "destination": "secret://missing-routing-key",
+            "severities": ["critical"]
+        }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let channel: Value = resp.into_json().await.expect("json");
+    let channel_id = channel["id"].as_i64().unwrap();
+
+    let rotations = format!("/api/1/Companies/{}/OnCallRotations", company_id);
+    let body = json!({
+        "name": "operations",
+        "handoff_at": Utc::now().naive_utc() - Duration::hours(1),
+        "members": [alex]
+    });
+    let resp = client
+        .post(rotations.clone())
+        .cookie(admin.clone())
+        .json(&body)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let rotation: OnCallRotation = resp.into_json().await.expect("json");
+
+    // Tiers can only use the company's own rotations
+    let policies = format!("/api/1/Companies/{}/EscalationPolicies", company_id);
+    let tier = |rotation_id: i32| {
+        json!({ "name": "critical alarms", "tiers": [{
+            "delay_minutes": 15,
+            "channel_id": channel_id,
+            "contacts": ["Site lead"],
+            "rotation_id": rotation_id
+        }] })
+    };
+    let resp = client
+        .post(policies.clone())
+        .cookie(admin.clone())
+        .json(&tier(9999))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+    let resp = client
+        .post(policies.clone())
+        .cookie(admin.clone())
+        .json(&tier(rotation.id))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+
+    let db = DbConn::get_one(client.rocket()).await.unwrap();
+    let site_db = SiteDbConn::get_one(client.rocket()).await.unwrap();
+    let none = HashSet::new();
+    assert_eq!(escalate_alarms(&db, &site_db, &none).await.expect("escalate"), 0);
+    db.run(|conn| {
+        diesel::update(alarm_states::table.find(103))
+            .set(alarm_states::raised_at.eq(Utc::now().naive_utc() - Duration::minutes(20)))
+            .execute(conn)
+    })
+    .await
+    .expect("backdate raise");
+    assert_eq!(escalate_alarms(&db, &site_db, &none).await.expect("escalate"), 1);
+    let resp = client
+        .get("/api/1/Alarms/103/Escalations")
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    let escalations: Vec<AlarmEscalation> = resp.into_json().await.expect("json");
+    assert_eq!(escalations.len(), 1);
+    assert_eq!(escalations[0].on_call_email.as_deref(), Some("user@company1.com"));
+    assert_eq!(escalations[0].contacts, vec!["Site lead"]);
+
+    // The rotation can't be deleted while the policy uses it
+    let resp = client
+        .delete(format!("{}/{}", rotations, rotation.id))
+        .cookie(admin)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Conflict);
+}
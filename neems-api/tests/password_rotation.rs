@@ -0,0 +1,108 @@
+//! Forced password rotation tests
+//!
+//! Covers setting must_change_password through PUT /api/1/Users/{id}, the
+//! guard restricting flagged users to PUT /api/1/Users/{id}/Password, and the
+//! password change itself. The client is untracked so each request carries
+//! only the session cookie it is given.
+
+use neems_api::orm::testing::fast_test_rocket;
+use rocket::{
+    http::{ContentType, Cookie, Status},
+    local::asynchronous::Client,
+};
+use serde_json::{Value, json};
+
+/// Log in as a golden DB user, returning the session cookie and the response
+async fn login(client: &Client, email: &str, password: &str) -> Option<(Cookie<'static>, Value)> {
+    let response = client
+        .post("/api/1/login")
+        .header(ContentType::JSON)
+        .body(json!({ "email": email, "password": password }).to_string())
+        .dispatch()
+        .await;
+    if response.status() != Status::Ok {
+        return None;
+    }
+    let cookie = response.cookies().get("session").expect("session cookie").clone().into_owned();
+    Some((cookie, response.into_json().await.expect("login response")))
+}
+
+async fn change_password(
+    client: &Client,
+    cookie: &Cookie<'static>,
+    user_id: i64,
+    current: &str,
+    new: &str,
+) -> Status {
+    client
+        .put(format!("/api/1/Users/{}/Password", user_id))
+        .cookie(cookie.clone())
+        .header(ContentType::JSON)
+        .body(json!({ "current_password": current, "new_password": new }).to_string())
+        .dispatch()
+        .await
+        .status()
+}
+
+#[tokio::test]
+async fn test_forced_password_rotation() {
+    let client = Client::untracked(fast_test_rocket()).await.expect("valid rocket instance");
+    let (admin_cookie, admin) = login(&client, "superadmin@example.com", "admin").await.unwrap();
+    let (stolen_cookie, user) = login(&client, "testuser@example.com", "admin").await.unwrap();
+    assert_eq!(user["must_change_password"], false);
+    let user_id = user["user_id"].as_i64().unwrap();
+    let roles_url = format!("/api/1/Users/{}/Roles", user_id);
+
+    let response = client
+        .put(format!("/api/1/Users/{}", user_id))
+        .cookie(admin_cookie.clone())
+        .header(ContentType::JSON)
+        .body(json!({ "must_change_password": true }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let updated: Value = response.into_json().await.unwrap();
+    assert_eq!(updated["must_change_password"], true);
+
+    // Existing sessions are restricted at once
+    let response = client.get(&roles_url).cookie(stolen_cookie.clone()).dispatch().await;
+    assert_eq!(response.status(), Status::Forbidden);
+    let response = client.get("/api/1/hello").cookie(stolen_cookie.clone()).dispatch().await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let (cookie, user) = login(&client, "testuser@example.com", "admin").await.unwrap();
+    assert_eq!(user["must_change_password"], true);
+
+    let admin_id = admin["user_id"].as_i64().unwrap();
+    assert_eq!(
+        change_password(&client, &cookie, admin_id, "admin", "rotated").await,
+        Status::Forbidden,
+        "only one's own password"
+    );
+    assert_eq!(
+        change_password(&client, &cookie, user_id, "wrong", "rotated").await,
+        Status::Forbidden
+    );
+    assert_eq!(
+        change_password(&client, &cookie, user_id, "admin", "admin").await,
+        Status::BadRequest
+    );
+    assert_eq!(
+        change_password(&client, &cookie, user_id, "admin", " ").await,
+        Status::BadRequest
+    );
+    assert_eq!(
+        change_password(&client, &cookie, user_id, "admin", "rotated").await,
+        Status::NoContent
+    );
+
+    // The session that changed the password carries on; the others are revoked
+    let response = client.get(&roles_url).cookie(cookie).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let response = client.get(&roles_url).cookie(stolen_cookie).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    assert!(login(&client, "testuser@example.com", "admin").await.is_none());
+    let (_, user) = login(&client, "testuser@example.com", "rotated").await.unwrap();
+    assert_eq!(user["must_change_password"], false);
+}
@@ -0,0 +1,203 @@
+//! Integration tests for listing power-quality events and downloading their
+//! waveforms.
+
+use chrono::{Duration, SecondsFormat, Utc};
+use neems_api::{
+    SiteDbConn,
+    orm::testing::{fast_test_rocket, golden_fixtures},
+    storage::BlobLink,
+};
+use neems_data::{
+    models::NewSource,
+    rtac::power_quality::{
+        CapturedEvent, PowerQualityEvent, PowerQualityEventKind, PowerQualitySample,
+        PowerQualityWaveform, insert_event,
+    },
+};
+use rocket::{
+    http::{ContentType, Status},
+    local::asynchronous::Client,
+    tokio,
+};
+use serde_json::json;
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+/// A captured event of `kind`: ten samples 100 ms apart, the sixth the trigger.
+fn captured(kind: PowerQualityEventKind, minutes_ago: i64) -> CapturedEvent {
+    let start = Utc::now() - Duration::minutes(minutes_ago);
+    let samples: Vec<_> = (0..10)
+        .map(|i| PowerQualitySample {
+            timestamp: start + Duration::milliseconds(100 * i),
+            voltage_v: if i == 5 { 400.0 } else { 480.0 },
+            current_a: 120.0,
+            grid_frequency_hz: 60.0,
+            power_kw: 100.0,
+        })
+        .collect();
+    CapturedEvent {
+        kind,
+        triggered_at: samples[5].timestamp,
+        nominal_value: 480.0,
+        threshold: 432.0,
+        extreme_value: 400.0,
+        samples,
+    }
+}
+
+/// A client whose site data has a sag and, an hour earlier, a frequency
+/// excursion at `site_id`, returning the sag's id.
+async fn client_with_events(site_id: i32) -> (Client, i32) {
+    let rocket = fast_test_rocket().ignite().await.expect("ignite");
+    let site_db = SiteDbConn::get_one(&rocket).await.expect("site database");
+    let sag_id = site_db
+        .run(move |conn| {
+            let source = neems_data::create_source(
+                conn,
+                NewSource {
+                    name: "RTAC".to_string(),
+                    description: None,
+                    active: Some(true),
+                    interval_seconds: Some(1),
+                    test_type: Some("rtac".to_string()),
+                    arguments: None,
+                    site_id: Some(site_id),
+                    company_id: None,
+                    priority: None,
+                    point_fields: None,
+                },
+            )
+            .expect("create source");
+            let source_id = source.id.unwrap();
+            insert_event(conn, source_id, &captured(PowerQualityEventKind::FrequencyExcursion, 60))
+                .expect("insert excursion");
+            insert_event(conn, source_id, &captured(PowerQualityEventKind::VoltageSag, 1))
+                .expect("insert sag")
+        })
+        .await;
+    (Client::tracked(rocket).await.unwrap(), sag_id)
+}
+
+#[tokio::test]
+async fn site_users_list_events_and_download_waveforms() {
+    let site_id = golden_fixtures().site_id("Device API Site A");
+    let (client, sag_id) = client_with_events(site_id).await;
+    let user = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let events_url = format!("/api/1/Sites/{}/PowerQualityEvents", site_id);
+
+    let resp = client.get(events_url.clone()).cookie(user.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let events: Vec<PowerQualityEvent> = resp.into_json().await.expect("json");
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].id, sag_id, "most recent first");
+    assert_eq!(events[0].kind, PowerQualityEventKind::VoltageSag);
+    assert_eq!(events[0].sample_count, 10);
+
+    let resp = client
+        .get(format!("{}?kind=frequency_excursion", events_url))
+        .cookie(user.clone())
+        .dispatch()
+        .await;
+    let events: Vec<PowerQualityEvent> = resp.into_json().await.expect("json");
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].kind, PowerQualityEventKind::FrequencyExcursion);
+
+    let since = (Utc::now() - Duration::minutes(30)).to_rfc3339_opts(SecondsFormat::Secs, true);
+    let resp = client
+        .get(format!("{}?since={}", events_url, since))
+        .cookie(user.clone())
+        .dispatch()
+        .await;
+    let events: Vec<PowerQualityEvent> = resp.into_json().await.expect("json");
+    assert_eq!(events.iter().map(|e| e.id).collect::<Vec<_>>(), vec![sag_id]);
+
+    for bad in ["kind=swell", "since=yesterday", "limit=0"] {
+        let resp = client
+            .get(format!("{}?{}", events_url, bad))
+            .cookie(user.clone())
+            .dispatch()
+            .await;
+        assert_eq!(resp.status(), Status::BadRequest, "{}", bad);
+    }
+
+    let resp = client
+        .get(format!("{}/{}", events_url, sag_id))
+        .cookie(user.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let waveform: PowerQualityWaveform = resp.into_json().await.expect("json");
+    assert_eq!(waveform.event.id, sag_id);
+    assert_eq!(waveform.samples.len(), 10);
+    assert_eq!(waveform.samples[5].voltage_v, 400.0);
+
+    let resp = client
+        .get(format!("{}/{}/Waveform.csv", events_url, sag_id))
+        .cookie(user.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    assert_eq!(resp.content_type(), Some(ContentType::CSV));
+    let csv = resp.into_string().await.expect("body");
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("timestamp,voltage_v,current_a,grid_frequency_hz,power_kw"));
+    assert_eq!(lines.count(), 10);
+
+    let resp = client
+        .get(format!("{}/{}", events_url, sag_id + 1000))
+        .cookie(user)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::NotFound);
+
+    // Other companies can't see the site's events
+    let other = login_as(&client, "admin@company1.com", "admin").await;
+    let resp = client.get(events_url.clone()).cookie(other.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+    let resp = client
+        .get(format!("{}/{}/Waveform.csv", events_url, sag_id))
+        .cookie(other)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Forbidden);
+}
+
+#[tokio::test]
+async fn waveform_exports_are_saved_once() {
+    let site_id = golden_fixtures().site_id("Device API Site A");
+    let (client, sag_id) = client_with_events(site_id).await;
+    let user = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let exports_url =
+        format!("/api/1/Sites/{}/PowerQualityEvents/{}/Waveform/Exports", site_id, sag_id);
+
+    let resp = client.post(exports_url.clone()).cookie(user.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Created);
+    let link: BlobLink = resp.into_json().await.expect("json");
+    assert_eq!(link.key, format!("waveforms/site-{}/event-{}.csv", site_id, sag_id));
+
+    let resp = client.get(link.url).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    assert_eq!(resp.content_type(), Some(ContentType::CSV));
+    let csv = resp.into_string().await.expect("body");
+    assert_eq!(csv.lines().count(), 11);
+
+    let resp = client.post(exports_url).cookie(user.clone()).dispatch().await;
+    let again: BlobLink = resp.into_json().await.expect("json");
+    assert_eq!(again.key, link.key);
+
+    let resp = client
+        .post(format!(
+            "/api/1/Sites/{}/PowerQualityEvents/{}/Waveform/Exports",
+            site_id,
+            sag_id + 1000
+        ))
+        .cookie(user)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::NotFound);
+}
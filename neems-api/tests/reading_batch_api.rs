@@ -0,0 +1,114 @@
+//! Integration tests for uploading readings in bulk.
+
+use neems_api::{
+    SiteDbConn,
+    api::reading_batch::ReadingBatchResponse,
+    orm::testing::{fast_test_rocket, golden_fixtures},
+};
+use neems_data::models::NewSource;
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::json;
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+fn new_source(name: &str, site_id: i32, company_id: i32) -> NewSource {
+    NewSource {
+        name: name.to_string(),
+        description: None,
+        active: Some(false),
+        interval_seconds: Some(60),
+        test_type: Some("gateway".to_string()),
+        arguments: None,
+        site_id: Some(site_id),
+        company_id: Some(company_id),
+        priority: None,
+        point_fields: None,
+    }
+}
+
+#[tokio::test]
+async fn batch_upserts_readings_and_reports_each_refusal() {
+    let fixtures = golden_fixtures();
+    let site_id = fixtures.site_id("Device API Site A");
+    let company_id = fixtures.company_id("Device Test Company A");
+    let other_site_id = fixtures.site_id("Test Site 1");
+    let other_company_id = fixtures.company_id("Test Company 1");
+    let rocket = fast_test_rocket().ignite().await.expect("ignite");
+    let site_db = SiteDbConn::get_one(&rocket).await.expect("site database");
+    let (own, other) = site_db
+        .run(move |conn| {
+            let own = neems_data::create_source(
+                conn,
+                new_source("Buffered gateway", site_id, company_id),
+            )
+            .unwrap();
+            let other = neems_data::create_source(
+                conn,
+                new_source("Someone else's gateway", other_site_id, other_company_id),
+            )
+            .unwrap();
+            (own.id.unwrap(), other.id.unwrap())
+        })
+        .await;
+    let client = Client::tracked(rocket).await.expect("client");
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+
+    let body = json!({
+        "readings": [
+            { "source_id": own, "timestamp": "2024-01-01T12:00:00", "data": { "level": 40 } },
+            { "source_id": own, "timestamp": "2024-01-01T12:01:00", "data": { "level": 41 }, "quality_flags": 2 },
+            { "source_id": other, "timestamp": "2024-01-01T12:00:00", "data": { "level": 1 } },
+            { "source_id": 999999, "timestamp": "2024-01-01T12:00:00", "data": { "level": 1 } },
+            { "source_id": own, "timestamp": "2024-01-01T12:02:00", "data": 42 }
+        ]
+    });
+    let resp = client
+        .post("/api/1/Readings/batch")
+        .cookie(admin.clone())
+        .json(&body)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let outcome: ReadingBatchResponse = resp.into_json().await.expect("json");
+    assert_eq!(outcome.stored, 2);
+    let refused: Vec<(usize, i32)> =
+        outcome.errors.iter().map(|e| (e.index, e.source_id)).collect();
+    assert_eq!(refused, vec![(2, other), (3, 999999), (4, own)]);
+    assert_eq!(outcome.errors[1].error, "Source not found");
+
+    // Resending a reading replaces it
+    let body = json!({
+        "readings": [
+            { "source_id": own, "timestamp": "2024-01-01T12:00:00", "data": { "level": 45 } }
+        ]
+    });
+    let resp = client
+        .post("/api/1/Readings/batch")
+        .cookie(admin.clone())
+        .json(&body)
+        .dispatch()
+        .await;
+    let outcome: ReadingBatchResponse = resp.into_json().await.expect("json");
+    assert_eq!((outcome.stored, outcome.errors.len()), (1, 0));
+    let site_db = SiteDbConn::get_one(client.rocket()).await.expect("site database");
+    let readings = site_db
+        .run(move |conn| neems_data::get_recent_readings(conn, own, 10))
+        .await
+        .unwrap();
+    assert_eq!(readings.len(), 2);
+    let first = readings.iter().find(|r| r.data.contains("45")).expect("replaced reading");
+    assert_eq!(first.timestamp.to_string(), "2024-01-01 12:00:00");
+    let second = readings.iter().find(|r| r.data.contains("41")).expect("second reading");
+    assert_eq!(second.quality_flags, 2);
+
+    // Batches are bounded
+    let reading = json!({ "source_id": own, "timestamp": "2024-01-01T12:00:00", "data": {} });
+    let body = json!({ "readings": vec![reading; 5001] });
+    let resp = client.post("/api/1/Readings/batch").cookie(admin).json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::PayloadTooLarge);
+}
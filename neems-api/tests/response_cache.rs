@@ -0,0 +1,129 @@
+//! Response cache tests
+//!
+//! Covers caching of /api/1/Sites and /api/1/Companies, invalidation when
+//! sites and companies are written through the API, and /api/1/CacheStats.
+
+use neems_api::orm::testing::fast_test_rocket;
+use rocket::{
+    http::{ContentType, Cookie, Status},
+    local::asynchronous::Client,
+};
+use serde_json::{Value, json};
+
+async fn login(client: &Client, email: &str, password: &str) -> Cookie<'static> {
+    let response = client
+        .post("/api/1/login")
+        .header(ContentType::JSON)
+        .body(json!({ "email": email, "password": password }).to_string())
+        .dispatch()
+        .await;
+    response.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+async fn get_json(client: &Client, url: &str, cookie: &Cookie<'static>) -> Value {
+    let response = client.get(url.to_string()).cookie(cookie.clone()).dispatch().await;
+    assert_eq!(response.status(), Status::Ok, "GET {}", url);
+    response.into_json().await.unwrap()
+}
+
+async fn endpoint_stats(client: &Client, cookie: &Cookie<'static>, endpoint: &str) -> Value {
+    let stats = get_json(client, "/api/1/CacheStats", cookie).await;
+    stats
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|s| s["endpoint"] == endpoint)
+        .unwrap()
+        .clone()
+}
+
+#[tokio::test]
+async fn test_sites_cache_invalidated_by_writes() {
+    let client = Client::untracked(fast_test_rocket()).await.expect("valid rocket instance");
+    let admin_cookie = login(&client, "superadmin@example.com", "admin").await;
+
+    let before = get_json(&client, "/api/1/Sites", &admin_cookie).await;
+    let again = get_json(&client, "/api/1/Sites", &admin_cookie).await;
+    assert_eq!(before, again);
+    let stats = endpoint_stats(&client, &admin_cookie, "Sites").await;
+    assert_eq!((stats["hits"].as_u64(), stats["misses"].as_u64()), (Some(1), Some(1)));
+
+    let company = client
+        .post("/api/1/Companies")
+        .cookie(admin_cookie.clone())
+        .header(ContentType::JSON)
+        .body(json!({ "name": "Cache Test Co" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(company.status(), Status::Created);
+    let company: Value = company.into_json().await.unwrap();
+
+    let site = json!({
+        "name": "Cached Site",
+        "address": "1 Cache Way",
+        "latitude": 40.0,
+        "longitude": -74.0,
+        "company_id": company["id"],
+    });
+    let response = client
+        .post("/api/1/Sites")
+        .cookie(admin_cookie.clone())
+        .header(ContentType::JSON)
+        .body(site.to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+
+    let after = get_json(&client, "/api/1/Sites", &admin_cookie).await;
+    let count = |sites: &Value| sites["value"].as_array().unwrap().len();
+    assert_eq!(count(&after), count(&before) + 1);
+    assert!(after["value"].as_array().unwrap().iter().any(|s| s["name"] == "Cached Site"));
+    let stats = endpoint_stats(&client, &admin_cookie, "Sites").await;
+    assert_eq!(stats["invalidations"].as_u64(), Some(2), "company and site creation");
+}
+
+#[tokio::test]
+async fn test_companies_cache_per_query() {
+    let client = Client::untracked(fast_test_rocket()).await.expect("valid rocket instance");
+    let admin_cookie = login(&client, "superadmin@example.com", "admin").await;
+
+    get_json(&client, "/api/1/Companies", &admin_cookie).await;
+    get_json(&client, "/api/1/Companies", &admin_cookie).await;
+    let top = get_json(&client, "/api/1/Companies?$top=1", &admin_cookie).await;
+    assert_eq!(top["value"].as_array().unwrap().len(), 1);
+    // Responses expanding Users skip the cache
+    get_json(&client, "/api/1/Companies?$expand=Users", &admin_cookie).await;
+
+    let stats = endpoint_stats(&client, &admin_cookie, "Companies").await;
+    assert_eq!(stats["hits"].as_u64(), Some(1));
+    assert_eq!(stats["misses"].as_u64(), Some(2));
+    assert_eq!(stats["entries"].as_u64(), Some(2));
+
+    let response = client
+        .post("/api/1/Companies")
+        .cookie(admin_cookie.clone())
+        .header(ContentType::JSON)
+        .body(json!({ "name": "Another Cache Co" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+    let companies = get_json(&client, "/api/1/Companies", &admin_cookie).await;
+    let names: Vec<&str> = companies["value"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|c| c["name"].as_str())
+        .collect();
+    assert!(names.contains(&"Another Cache Co"));
+}
+
+#[tokio::test]
+async fn test_cache_stats_requires_newtown_role() {
+    let client = Client::untracked(fast_test_rocket()).await.expect("valid rocket instance");
+    let staff_cookie = login(&client, "testuser@example.com", "admin").await;
+
+    let response = client.get("/api/1/CacheStats").cookie(staff_cookie).dispatch().await;
+    assert_eq!(response.status(), Status::Forbidden);
+    let response = client.get("/api/1/CacheStats").dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
@@ -9,9 +9,9 @@
 use neems_api::{models::Role, orm::testing::fast_test_rocket};
 use rocket::{
     http::{ContentType, Status},
-    local::asynchronous::Client,
+    local::asynchronous::{Client, LocalResponse},
 };
-use serde_json::json;
+use serde_json::{Value, json};
 
 /// Unified helper to login with specific credentials and get session cookie
 async fn login_user(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
@@ -36,6 +36,12 @@ async fn login_user(client: &Client, email: &str, password: &str) -> rocket::htt
         .into_owned()
 }
 
+/// The roles in a `/api/1/Roles` response's OData envelope
+async fn role_list(response: LocalResponse<'_>) -> Vec<Role> {
+    let body: Value = response.into_json().await.expect("valid roles JSON");
+    serde_json::from_value(body["value"].clone()).expect("roles in value")
+}
+
 /// Helper to create a test role
 async fn create_test_role(
     client: &Client,
@@ -115,7 +121,7 @@ async fn test_role_crud_operations_and_authentication() {
     // Test authenticated LIST roles succeeds
     let response = client.get("/api/1/Roles").cookie(admin_cookie.clone()).dispatch().await;
     assert_eq!(response.status(), Status::Ok);
-    let list: Vec<Role> = response.into_json().await.expect("valid JSON response");
+    let list = role_list(response).await;
     assert!(!list.is_empty()); // Should have at least the default roles + created role
 
     // Test GET single role
@@ -430,7 +436,7 @@ async fn test_all_authenticated_users_can_read_roles() {
     // Use existing golden database roles instead of creating new ones
     let response = client.get("/api/1/Roles").cookie(admin_cookie).dispatch().await;
     assert_eq!(response.status(), Status::Ok);
-    let all_roles: Vec<Role> = response.into_json().await.expect("valid roles JSON");
+    let all_roles = role_list(response).await;
 
     // Find an existing role from golden database (we know "admin" role exists)
     let existing_role = all_roles
@@ -444,7 +450,7 @@ async fn test_all_authenticated_users_can_read_roles() {
     // Should be able to list roles
     let response = client.get("/api/1/Roles").cookie(admin_session.clone()).dispatch().await;
     assert_eq!(response.status(), Status::Ok);
-    let roles: Vec<Role> = response.into_json().await.expect("valid roles JSON");
+    let roles = role_list(response).await;
     assert!(roles.len() >= 4); // At least the 4 default roles from golden DB
 
     // Should be able to get individual role using existing role
@@ -460,7 +466,7 @@ async fn test_all_authenticated_users_can_read_roles() {
     // Should be able to list roles
     let response = client.get("/api/1/Roles").cookie(staff_session.clone()).dispatch().await;
     assert_eq!(response.status(), Status::Ok);
-    let roles: Vec<Role> = response.into_json().await.expect("valid roles JSON");
+    let roles = role_list(response).await;
     assert!(roles.len() >= 4);
 
     // Should be able to get individual role
@@ -473,10 +479,56 @@ async fn test_all_authenticated_users_can_read_roles() {
     // Should be able to list roles
     let response = client.get("/api/1/Roles").cookie(user_session.clone()).dispatch().await;
     assert_eq!(response.status(), Status::Ok);
-    let roles: Vec<Role> = response.into_json().await.expect("valid roles JSON");
+    let roles = role_list(response).await;
     assert!(roles.len() >= 4);
 
     // Should be able to get individual role
     let response = client.get(&url).cookie(user_session).dispatch().await;
     assert_eq!(response.status(), Status::Ok);
 }
+
+#[rocket::async_test]
+async fn test_list_roles_query_options() {
+    let client = Client::tracked(fast_test_rocket()).await.expect("valid rocket instance");
+    let session = login_user(&client, "staff@testcompany.com", "admin").await;
+
+    // $filter, $select and $count
+    let response = client
+        .get("/api/1/Roles?$filter=name%20eq%20'admin'&$select=id,name&$count=true")
+        .cookie(session.clone())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let body: Value = response.into_json().await.expect("valid JSON");
+    assert_eq!(body["@odata.context"], "http://localhost/api/1/$metadata#Roles(id,name)");
+    assert_eq!(body["@odata.count"], 1);
+    let admin = &body["value"][0];
+    assert_eq!(admin["name"], "admin");
+    assert!(admin.get("description").is_none());
+    assert!(body.get("@odata.nextLink").is_none());
+
+    // Paging with $top follows @odata.nextLink to the last page
+    let response = client.get("/api/1/Roles?$count=true").cookie(session.clone()).dispatch().await;
+    let total = response.into_json::<Value>().await.expect("valid JSON")["@odata.count"]
+        .as_u64()
+        .unwrap();
+    let mut names = Vec::new();
+    let mut next = Some("/api/1/Roles?$orderby=name&$top=2".to_string());
+    while let Some(url) = next {
+        let response = client.get(url).cookie(session.clone()).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let body: Value = response.into_json().await.expect("valid JSON");
+        let page = body["value"].as_array().unwrap();
+        assert!(page.len() <= 2);
+        names.extend(page.iter().map(|r| r["name"].as_str().unwrap().to_string()));
+        next = body["@odata.nextLink"].as_str().map(str::to_string);
+    }
+    assert_eq!(names.len() as u64, total);
+    let mut sorted = names.clone();
+    sorted.sort();
+    assert_eq!(names, sorted);
+
+    // Invalid options are rejected
+    let response = client.get("/api/1/Roles?$top=-1").cookie(session).dispatch().await;
+    assert_eq!(response.status(), Status::BadRequest);
+}
@@ -0,0 +1,181 @@
+//! Integration tests for the iCalendar schedule feed and its feed tokens.
+
+use chrono::{Duration, Utc};
+use neems_api::{
+    api::schedule_feed::FeedTokenSecret,
+    models::{ScheduleFeedToken, ScheduleLibraryItem},
+    orm::testing::{fast_test_rocket, golden_fixtures},
+};
+use rocket::{
+    http::{ContentType, Status},
+    local::asynchronous::Client,
+    tokio,
+};
+use serde_json::json;
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+/// Schedules a two-command override on the site for tomorrow, returning the
+/// date as it appears in the feed
+async fn schedule_override(
+    client: &Client,
+    cookie: &rocket::http::Cookie<'static>,
+    site_id: i32,
+) -> String {
+    let item = json!({
+        "name": "Heatwave, peak shaving",
+        "commands": [
+            { "execution_offset_seconds": 6 * 3600, "command_type": "charge", "duration_seconds": 3600, "power_kw": 250.0 },
+            { "execution_offset_seconds": 17 * 3600, "command_type": "discharge" }
+        ]
+    });
+    let resp = client
+        .post(format!("/api/1/Sites/{}/ScheduleLibraryItems", site_id))
+        .cookie(cookie.clone())
+        .json(&item)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let item: ScheduleLibraryItem = resp.into_json().await.expect("json");
+
+    let tomorrow = Utc::now().date_naive() + Duration::days(1);
+    let rule = json!({
+        "rule_type": "specific_date",
+        "days_of_week": null,
+        "specific_dates": [tomorrow.format("%Y-%m-%d").to_string()],
+        "override_reason": "Grid emergency"
+    });
+    let resp = client
+        .post(format!("/api/1/ScheduleLibraryItems/{}/ApplicationRules", item.id))
+        .cookie(cookie.clone())
+        .json(&rule)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    tomorrow.format("%Y%m%d").to_string()
+}
+
+#[tokio::test]
+async fn feed_lists_override_and_its_windows() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let site_id = golden_fixtures().site_id("Device API Site A");
+    let day = schedule_override(&client, &admin, site_id).await;
+
+    let resp = client
+        .get(format!("/api/1/Sites/{}/Schedule.ics?days=3", site_id))
+        .cookie(admin)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    assert_eq!(resp.content_type(), Some(ContentType::Calendar));
+    let body = resp.into_string().await.expect("body");
+    assert!(body.starts_with("BEGIN:VCALENDAR\r\n"));
+    assert!(body.contains(&format!("DTSTART;VALUE=DATE:{}\r\n", day)));
+    assert!(body.contains("SUMMARY:Override: Heatwave\\, peak shaving\r\n"));
+    assert!(body.contains("DESCRIPTION:Grid emergency\r\n"));
+    assert!(body.contains(&format!(
+        "DTSTART:{}T060000Z\r\nDTEND:{}T070000Z\r\nSUMMARY:Charge at 250 kW\r\n",
+        day, day
+    )));
+    assert!(body.contains("CATEGORIES:Discharge,Override\r\n"));
+}
+
+#[tokio::test]
+async fn feed_requires_auth_and_bounded_days() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let site_id = golden_fixtures().site_id("Device API Site A");
+
+    let resp = client.get(format!("/api/1/Sites/{}/Schedule.ics", site_id)).dispatch().await;
+    assert_eq!(resp.status(), Status::Unauthorized);
+    let resp = client
+        .get(format!("/api/1/Sites/{}/Schedule.ics?token=ical_nope", site_id))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Unauthorized);
+
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let resp = client
+        .get(format!("/api/1/Sites/{}/Schedule.ics?days=400", site_id))
+        .cookie(admin)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+}
+
+#[tokio::test]
+async fn feed_token_reads_only_its_site() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let fixtures = golden_fixtures();
+    let site_id = fixtures.site_id("Device API Site A");
+
+    let resp = client
+        .post(format!("/api/1/Sites/{}/ScheduleFeedTokens", site_id))
+        .cookie(admin.clone())
+        .json(&json!({ "description": "Ops calendar" }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let secret: FeedTokenSecret = resp.into_json().await.expect("json");
+    assert!(secret.token.starts_with(&secret.feed_token.prefix));
+    assert_eq!(
+        secret.url,
+        format!("/api/1/Sites/{}/Schedule.ics?token={}", site_id, secret.token)
+    );
+
+    // A calendar app subscribes with just the URL
+    let resp = client.get(secret.url.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+
+    let resp = client
+        .get(format!("/api/1/Sites/{}/Schedule.ics?token={}", site_id + 1, secret.token))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Forbidden);
+
+    let resp = client
+        .get(format!("/api/1/Sites/{}/ScheduleFeedTokens", site_id))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let tokens: Vec<ScheduleFeedToken> = resp.into_json().await.expect("json");
+    assert!(tokens.iter().any(|t| t.id == secret.feed_token.id));
+
+    // Revoked tokens stop working
+    let resp = client
+        .delete(format!("/api/1/ScheduleFeedTokens/{}", secret.feed_token.id))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::NoContent);
+    let resp = client.get(secret.url).dispatch().await;
+    assert_eq!(resp.status(), Status::Unauthorized);
+    let resp = client
+        .delete(format!("/api/1/ScheduleFeedTokens/{}", secret.feed_token.id))
+        .cookie(admin)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::NotFound);
+}
+
+#[tokio::test]
+async fn other_companies_cannot_create_feed_tokens() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let site_id = golden_fixtures().site_id("Device API Site B");
+
+    let resp = client
+        .post(format!("/api/1/Sites/{}/ScheduleFeedTokens", site_id))
+        .cookie(admin)
+        .json(&json!({}))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Forbidden);
+}
@@ -0,0 +1,186 @@
+//! Integration tests for importing schedule commands from CSV and XLSX files.
+
+use neems_api::{
+    models::{CommandType, ScheduleLibraryItem},
+    orm::testing::{fast_test_rocket, golden_fixtures},
+};
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use rust_xlsxwriter::{ExcelDateTime, Format, Workbook};
+use serde_json::{Value, json};
+
+const PLAN: &str = "time,action,type,power,notes\n\
+                    06:00,start,charge,250,off-peak\n\
+                    10:30,stop,,,\n\
+                    17:00,start,discharge,500,peak\n";
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+fn import_path(device: &str, name: &str) -> String {
+    format!(
+        "/api/1/Devices/{}/ScheduleCommands/import?name={}",
+        golden_fixtures().device_id(device),
+        name
+    )
+}
+
+#[tokio::test]
+async fn company_admin_imports_csv_for_own_device() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let fixtures = golden_fixtures();
+    let device_id = fixtures.device_id("SEL-451");
+
+    let resp = client
+        .post(import_path("SEL-451", "Weekday%20plan"))
+        .cookie(admin.clone())
+        .body(PLAN)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let item: ScheduleLibraryItem = resp.into_json().await.expect("json");
+    assert_eq!(item.name, "Weekday plan");
+    assert_eq!(item.site_id, fixtures.site_id("Device API Site A"));
+    assert_eq!(item.commands.len(), 2);
+    assert_eq!(item.commands[0].execution_offset_seconds, 6 * 3600);
+    assert_eq!(item.commands[0].command_type, CommandType::Charge);
+    assert_eq!(item.commands[0].duration_seconds, Some(4 * 3600 + 1800));
+    assert_eq!(item.commands[0].power_kw, Some(250.0));
+    assert_eq!(item.commands[0].device_id, Some(device_id));
+    assert_eq!(item.commands[1].command_type, CommandType::Discharge);
+    assert_eq!(item.commands[1].duration_seconds, None);
+
+    // The power and device are kept with the stored item
+    let resp = client
+        .get(format!("/api/1/ScheduleLibraryItems/{}", item.id))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let stored: ScheduleLibraryItem = resp.into_json().await.expect("json");
+    assert_eq!(stored.commands[1].power_kw, Some(500.0));
+    assert_eq!(stored.commands[1].device_id, Some(device_id));
+
+    // The name must still be unique on the site
+    let resp = client
+        .post(import_path("SEL-451", "Weekday%20plan"))
+        .cookie(admin)
+        .body(PLAN)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+}
+
+#[tokio::test]
+async fn invalid_rows_are_reported_per_line() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+
+    let resp = client
+        .post(import_path("SEL-735", "Broken"))
+        .cookie(admin.clone())
+        .body(
+            "time,action,type,power\n\
+             06:00,start,charge,250\n\
+             07:00,start,boost,100\n\
+             08:00,start,discharge,\n",
+        )
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::UnprocessableEntity);
+    let body: Value = resp.into_json().await.expect("json");
+    assert_eq!(body["error"], json!("2 lines of the file are invalid"));
+    let lines: Vec<i64> = body["line_errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["line"].as_i64().unwrap())
+        .collect();
+    assert_eq!(lines, vec![3, 4]);
+
+    // Nothing was saved
+    let resp = client
+        .get(format!(
+            "/api/1/Sites/{}/ScheduleLibraryItems",
+            golden_fixtures().site_id("Device API Site A")
+        ))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    let items: Vec<ScheduleLibraryItem> = resp.into_json().await.expect("json");
+    assert!(items.iter().all(|item| item.name != "Broken"));
+
+    let resp = client
+        .post(import_path("SEL-735", "Empty"))
+        .cookie(admin)
+        .body("")
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+}
+
+#[tokio::test]
+async fn xlsx_files_are_imported() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let staff = login_as(&client, "newtownstaff@newtown.com", "admin").await;
+
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    let time_format = Format::new().set_num_format("hh:mm");
+    for (col, header) in ["Time", "Action", "Type", "Power"].into_iter().enumerate() {
+        sheet.write_string(0, col as u16, header).unwrap();
+    }
+    sheet
+        .write_datetime_with_format(1, 0, ExcelDateTime::from_hms(6, 0, 0).unwrap(), &time_format)
+        .unwrap();
+    sheet.write_string(1, 1, "start").unwrap();
+    sheet.write_string(1, 2, "charge").unwrap();
+    sheet.write_number(1, 3, 125.0).unwrap();
+    // Times typed as text work too
+    sheet.write_string(2, 0, "09:15").unwrap();
+    sheet.write_string(2, 1, "stop").unwrap();
+    let file = workbook.save_to_buffer().unwrap();
+
+    let resp = client
+        .post(import_path("SEL-451B", "From%20Excel"))
+        .cookie(staff)
+        .body(file)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let item: ScheduleLibraryItem = resp.into_json().await.expect("json");
+    assert_eq!(item.commands.len(), 1);
+    assert_eq!(item.commands[0].execution_offset_seconds, 6 * 3600);
+    assert_eq!(item.commands[0].duration_seconds, Some(3 * 3600 + 900));
+    assert_eq!(item.commands[0].power_kw, Some(125.0));
+}
+
+#[tokio::test]
+async fn imports_require_schedule_access() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+
+    let resp = client.post(import_path("SEL-451", "Plan")).body(PLAN).dispatch().await;
+    assert_eq!(resp.status(), Status::Unauthorized);
+
+    // Another company's admin
+    let other = login_as(&client, "admin@devicetestb.com", "admin").await;
+    let resp = client
+        .post(import_path("SEL-451", "Plan"))
+        .cookie(other.clone())
+        .body(PLAN)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Forbidden);
+
+    let resp = client
+        .post("/api/1/Devices/999999/ScheduleCommands/import?name=Plan")
+        .cookie(other)
+        .body(PLAN)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::NotFound);
+}
@@ -1,6 +1,6 @@
 use neems_api::{
     models::{CommandType, ScheduleLibraryItem},
-    orm::testing::fast_test_rocket,
+    orm::testing::{fast_test_rocket, golden_fixtures},
 };
 use rocket::{
     http::{ContentType, Status},
@@ -31,12 +31,17 @@ async fn login_admin(client: &Client) -> rocket::http::Cookie<'static> {
         .into_owned()
 }
 
+/// Path to `suffix` under the named golden-database site.
+fn site_path(site: &str, suffix: &str) -> String {
+    format!("/api/1/Sites/{}{}", golden_fixtures().site_id(site), suffix)
+}
+
 #[rocket::async_test]
 async fn test_schedule_library_endpoints_require_authentication() {
     let client = Client::tracked(fast_test_rocket()).await.expect("valid rocket instance");
 
     // Test all endpoints require authentication
-    let response = client.get("/api/1/Sites/1/ScheduleLibraryItems").dispatch().await;
+    let response = client.get(site_path("Test Site 1", "/ScheduleLibraryItems")).dispatch().await;
     assert_eq!(response.status(), Status::Unauthorized);
 
     let response = client.get("/api/1/ScheduleLibraryItems/1").dispatch().await;
@@ -48,7 +53,7 @@ async fn test_schedule_library_endpoints_require_authentication() {
     });
 
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .json(&new_item)
         .dispatch()
         .await;
@@ -62,7 +67,7 @@ async fn test_default_schedule_auto_created() {
 
     // List schedules for a site - should auto-create default
     let response = client
-        .get("/api/1/Sites/1/ScheduleLibraryItems")
+        .get(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .dispatch()
         .await;
@@ -99,7 +104,7 @@ async fn test_create_library_item() {
     });
 
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .json(&new_item)
         .dispatch()
@@ -127,7 +132,7 @@ async fn test_create_library_item_duplicate_name() {
 
     // Create first item
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .json(&new_item)
         .dispatch()
@@ -136,7 +141,7 @@ async fn test_create_library_item_duplicate_name() {
 
     // Try to create second item with same name
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .json(&new_item)
         .dispatch()
@@ -161,7 +166,7 @@ async fn test_create_library_item_invalid_execution_time() {
     });
 
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .json(&new_item)
         .dispatch()
@@ -189,7 +194,7 @@ async fn test_create_library_item_duplicate_execution_times() {
     });
 
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .json(&new_item)
         .dispatch()
@@ -209,7 +214,7 @@ async fn test_get_library_item() {
     });
 
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .json(&new_item)
         .dispatch()
@@ -238,7 +243,7 @@ async fn test_update_library_item() {
     });
 
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .json(&new_item)
         .dispatch()
@@ -274,7 +279,7 @@ async fn test_update_default_schedule_allowed() {
 
     // Get the default schedule
     let response = client
-        .get("/api/1/Sites/1/ScheduleLibraryItems")
+        .get(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .dispatch()
         .await;
@@ -313,7 +318,7 @@ async fn test_delete_library_item() {
     });
 
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .json(&new_item)
         .dispatch()
@@ -338,7 +343,7 @@ async fn test_cannot_delete_default_schedule() {
 
     // Get the default schedule
     let response = client
-        .get("/api/1/Sites/1/ScheduleLibraryItems")
+        .get(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .dispatch()
         .await;
@@ -370,7 +375,7 @@ async fn test_clone_library_item() {
     });
 
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .json(&new_item)
         .dispatch()
@@ -414,7 +419,7 @@ async fn test_list_library_items() {
         });
 
         client
-            .post("/api/1/Sites/1/ScheduleLibraryItems")
+            .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
             .cookie(admin_cookie.clone())
             .json(&new_item)
             .dispatch()
@@ -423,7 +428,7 @@ async fn test_list_library_items() {
 
     // List all items
     let response = client
-        .get("/api/1/Sites/1/ScheduleLibraryItems")
+        .get(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .dispatch()
         .await;
@@ -457,7 +462,7 @@ async fn test_create_command_with_duration_and_target_soc() {
     });
 
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .json(&new_item)
         .dispatch()
@@ -488,7 +493,7 @@ async fn test_create_command_with_only_duration() {
     });
 
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .json(&new_item)
         .dispatch()
@@ -518,7 +523,7 @@ async fn test_create_command_with_only_target_soc() {
     });
 
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .json(&new_item)
         .dispatch()
@@ -548,7 +553,7 @@ async fn test_create_command_invalid_duration_zero() {
     });
 
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .json(&new_item)
         .dispatch()
@@ -574,7 +579,7 @@ async fn test_create_command_invalid_duration_negative() {
     });
 
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .json(&new_item)
         .dispatch()
@@ -600,7 +605,7 @@ async fn test_create_command_invalid_soc_over_100() {
     });
 
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .json(&new_item)
         .dispatch()
@@ -626,7 +631,7 @@ async fn test_create_command_invalid_soc_negative() {
     });
 
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .json(&new_item)
         .dispatch()
@@ -653,7 +658,7 @@ async fn test_create_command_soc_boundary_values() {
     });
 
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .json(&new_item)
         .dispatch()
@@ -676,7 +681,7 @@ async fn test_create_command_soc_boundary_values() {
     });
 
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .json(&new_item)
         .dispatch()
@@ -706,7 +711,7 @@ async fn test_clone_preserves_duration_and_soc() {
     });
 
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .json(&new_item)
         .dispatch()
@@ -747,7 +752,7 @@ async fn test_update_preserves_duration_and_soc() {
     });
 
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems"))
         .cookie(admin_cookie.clone())
         .json(&new_item)
         .dispatch()
@@ -794,7 +799,7 @@ async fn test_from_site_defaults_creates_charge_and_discharge_commands() {
         "interconnection_max_output_kw": 5000.0
     });
     let response = client
-        .put("/api/1/Sites/1")
+        .put(site_path("Test Site 1", ""))
         .cookie(admin_cookie.clone())
         .json(&site_patch)
         .dispatch()
@@ -807,7 +812,7 @@ async fn test_from_site_defaults_creates_charge_and_discharge_commands() {
         "end_of_charge_soc_percent": 100
     });
     let response = client
-        .post("/api/1/Sites/1/ScheduleLibraryItems/FromSiteDefaults")
+        .post(site_path("Test Site 1", "/ScheduleLibraryItems/FromSiteDefaults"))
         .cookie(admin_cookie.clone())
         .json(&body)
         .dispatch()
@@ -849,7 +854,7 @@ async fn test_from_site_defaults_rejects_invalid_windows() {
         "peak_revenue_end_minutes": 960
     });
     let response = client
-        .put("/api/1/Sites/2")
+        .put(site_path("Test Site 2", ""))
         .cookie(admin_cookie.clone())
         .json(&site_patch)
         .dispatch()
@@ -858,7 +863,7 @@ async fn test_from_site_defaults_rejects_invalid_windows() {
 
     let body = json!({ "name": "Should Fail" });
     let response = client
-        .post("/api/1/Sites/2/ScheduleLibraryItems/FromSiteDefaults")
+        .post(site_path("Test Site 2", "/ScheduleLibraryItems/FromSiteDefaults"))
         .cookie(admin_cookie)
         .json(&body)
         .dispatch()
@@ -0,0 +1,170 @@
+//! Integration tests for parameterized schedule templates and instantiating
+//! them.
+
+use neems_api::{
+    models::{InstantiateTemplateResponse, RuleType, ScheduleLibraryItem},
+    orm::testing::{fast_test_rocket, golden_fixtures},
+};
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::{Value, json};
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+fn peak_shave_template(name: &str) -> Value {
+    json!({
+        "name": name,
+        "parameters": [
+            { "name": "discharge_kw", "description": "Discharge power", "default_value": null, "min_value": 0, "max_value": 1000 },
+            { "name": "start_hour", "description": null, "default_value": 17, "min_value": 0, "max_value": 23 }
+        ],
+        "commands": [
+            { "execution_offset_seconds": 6 * 3600, "command_type": "charge", "target_soc_percent": 90 },
+            {
+                "execution_offset_seconds": 17 * 3600,
+                "command_type": "discharge",
+                "duration_seconds": 7200,
+                "power_kw": 100.0,
+                "bindings": { "power_kw": "discharge_kw", "start_hour": "start_hour" }
+            }
+        ]
+    })
+}
+
+async fn create_template(
+    client: &Client,
+    cookie: &rocket::http::Cookie<'static>,
+    site_id: i32,
+    template: &Value,
+) -> ScheduleLibraryItem {
+    let resp = client
+        .post(format!("/api/1/Sites/{}/ScheduleLibraryItems", site_id))
+        .cookie(cookie.clone())
+        .json(template)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    resp.into_json().await.expect("json")
+}
+
+#[tokio::test]
+async fn instantiate_template_for_device_and_dates() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let fixtures = golden_fixtures();
+    let site_id = fixtures.site_id("Device API Site A");
+    let device_id = fixtures.device_id("SEL-451");
+
+    let template =
+        create_template(&client, &admin, site_id, &peak_shave_template("Peak shave")).await;
+    assert_eq!(template.parameters.len(), 2);
+
+    // Parameters and bindings are kept with the stored template
+    let resp = client
+        .get(format!("/api/1/ScheduleLibraryItems/{}", template.id))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    let stored: ScheduleLibraryItem = resp.into_json().await.expect("json");
+    assert_eq!(stored.parameters, template.parameters);
+    let bindings = stored.commands[1].bindings.clone().expect("bindings");
+    assert_eq!(bindings.power_kw.as_deref(), Some("discharge_kw"));
+
+    let body = json!({
+        "name": "Heatwave week",
+        "device_id": device_id,
+        "start_date": "2026-07-20",
+        "end_date": "2026-07-22",
+        "values": { "discharge_kw": 400, "start_hour": 16.5 },
+        "override_reason": "Heatwave"
+    });
+    let resp = client
+        .post(format!("/api/1/ScheduleLibraryItems/{}/Instantiate", template.id))
+        .cookie(admin.clone())
+        .json(&body)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let created: InstantiateTemplateResponse = resp.into_json().await.expect("json");
+    let item = created.library_item;
+    assert_eq!(item.site_id, site_id);
+    assert!(item.parameters.is_empty());
+    assert_eq!(item.commands[0].target_soc_percent, Some(90));
+    assert_eq!(item.commands[1].execution_offset_seconds, 16 * 3600 + 1800);
+    assert_eq!(item.commands[1].power_kw, Some(400.0));
+    assert_eq!(item.commands[1].duration_seconds, Some(7200));
+    assert!(
+        item.commands
+            .iter()
+            .all(|c| c.device_id == Some(device_id) && c.bindings.is_none())
+    );
+    assert_eq!(created.rule.rule_type, RuleType::SpecificDate);
+    assert_eq!(created.rule.library_item_id, item.id);
+    assert_eq!(created.rule.override_reason.as_deref(), Some("Heatwave"));
+    assert_eq!(created.applied_dates.len(), 3);
+}
+
+#[tokio::test]
+async fn instantiate_rejects_bad_values_and_dates() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let site_id = golden_fixtures().site_id("Device API Site A");
+    let template =
+        create_template(&client, &admin, site_id, &peak_shave_template("Peak shave 2")).await;
+    let url = format!("/api/1/ScheduleLibraryItems/{}/Instantiate", template.id);
+
+    let cases = [
+        // discharge_kw has no default
+        json!({ "name": "a", "start_date": "2026-07-20", "end_date": "2026-07-20" }),
+        json!({ "name": "b", "start_date": "2026-07-20", "end_date": "2026-07-20",
+                "values": { "discharge_kw": 5000 } }),
+        json!({ "name": "c", "start_date": "2026-07-20", "end_date": "2026-07-20",
+                "values": { "discharge_kw": 100, "soc": 50 } }),
+        json!({ "name": "d", "start_date": "2026-07-21", "end_date": "2026-07-20",
+                "values": { "discharge_kw": 100 } }),
+        json!({ "name": "e", "start_date": "2026-01-01", "end_date": "2027-06-01",
+                "values": { "discharge_kw": 100 } }),
+    ];
+    for body in cases {
+        let resp = client.post(url.clone()).cookie(admin.clone()).json(&body).dispatch().await;
+        assert_eq!(resp.status(), Status::BadRequest, "{}", body);
+    }
+
+    // A device at another company's site
+    let other_device = golden_fixtures().device_id("SEL-451B");
+    let body = json!({ "name": "f", "device_id": other_device, "start_date": "2026-07-20",
+                       "end_date": "2026-07-20", "values": { "discharge_kw": 100 } });
+    let resp = client.post(url).cookie(admin).json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+}
+
+#[tokio::test]
+async fn bindings_must_name_declared_parameters() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let site_id = golden_fixtures().site_id("Device API Site A");
+
+    let mut template = peak_shave_template("Undeclared");
+    template["parameters"] = json!([]);
+    let resp = client
+        .post(format!("/api/1/Sites/{}/ScheduleLibraryItems", site_id))
+        .cookie(admin.clone())
+        .json(&template)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::InternalServerError);
+
+    // Nor can a parameter be removed while a command binds it
+    let created = create_template(&client, &admin, site_id, &peak_shave_template("Declared")).await;
+    let resp = client
+        .put(format!("/api/1/ScheduleLibraryItems/{}", created.id))
+        .cookie(admin)
+        .json(&json!({ "parameters": [] }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::InternalServerError);
+}
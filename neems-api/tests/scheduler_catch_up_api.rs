@@ -0,0 +1,121 @@
+//! Integration tests for scheduler ticks and the catch-up on missed ticks.
+
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use neems_api::{
+    DbConn,
+    api::scheduler::SchedulerStatusResponse,
+    models::{ActiveCommandResponse, ScheduleLibraryItem},
+    orm::testing::{fast_test_rocket, golden_fixtures},
+    scheduler_catch_up::catch_up,
+};
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::json;
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+async fn status(
+    client: &Client,
+    cookie: &rocket::http::Cookie<'static>,
+    site_id: i32,
+) -> SchedulerStatusResponse {
+    let resp = client
+        .get(format!("/api/1/Sites/{}/scheduler/status", site_id))
+        .cookie(cookie.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    resp.into_json().await.expect("json")
+}
+
+#[tokio::test]
+async fn restart_catches_up_on_missed_command_starts() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let site_id = golden_fixtures().site_id("Device API Site A");
+
+    // A command every six hours, every day
+    let body = json!({
+        "name": "Six-hourly",
+        "commands": (0..4).map(|i| json!({
+            "execution_offset_seconds": i * 6 * 3600,
+            "command_type": if i % 2 == 0 { "charge" } else { "discharge" }
+        })).collect::<Vec<_>>()
+    });
+    let resp = client
+        .post(format!("/api/1/Sites/{}/ScheduleLibraryItems", site_id))
+        .cookie(admin.clone())
+        .json(&body)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let item: ScheduleLibraryItem = resp.into_json().await.expect("json");
+    let resp = client
+        .post(format!("/api/1/ScheduleLibraryItems/{}/ApplicationRules", item.id))
+        .cookie(admin.clone())
+        .json(&json!({ "rule_type": "default" }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+
+    assert!(status(&client, &admin, site_id).await.tick.is_none());
+
+    // Evaluating the active command records a tick
+    let resp = client
+        .get(format!("/api/1/Sites/{}/ActiveCommand", site_id))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    let active: ActiveCommandResponse = resp.into_json().await.expect("json");
+    let command_id = active.command.expect("active command").command_id;
+    let tick = status(&client, &admin, site_id).await.tick.expect("tick");
+    assert_eq!(tick.command_id, Some(command_id));
+    assert_eq!((tick.caught_up_at, tick.missed_commands), (None, 0));
+
+    // Nothing was missed since
+    let conn = DbConn::get_one(client.rocket()).await.expect("database");
+    let now = Utc::now().naive_utc();
+    assert!(conn.run(move |c| catch_up(c, now)).await.expect("catch up").is_empty());
+
+    // As though the service had been down for twelve hours
+    conn.run(move |c| {
+        use neems_api::schema::scheduler_ticks::dsl::*;
+        diesel::update(scheduler_ticks.find(site_id))
+            .set(evaluated_at.eq(now - Duration::hours(12)))
+            .execute(c)
+    })
+    .await
+    .expect("backdate tick");
+    let caught_up = conn.run(move |c| catch_up(c, now)).await.expect("catch up");
+    assert_eq!(caught_up.len(), 1);
+    assert_eq!((caught_up[0].site_id, caught_up[0].missed_commands), (site_id, 2));
+    assert_eq!(caught_up[0].command_id, Some(command_id));
+
+    let tick = status(&client, &admin, site_id).await.tick.expect("tick");
+    assert_eq!((tick.evaluated_at, tick.caught_up_at), (now, Some(now)));
+    assert_eq!(tick.missed_commands, 2);
+
+    // Catching up again finds nothing, and a poll straight after doesn't
+    // rewrite the tick
+    assert!(conn.run(move |c| catch_up(c, now)).await.expect("catch up").is_empty());
+    client
+        .get(format!("/api/1/Sites/{}/ActiveCommand", site_id))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    assert_eq!(status(&client, &admin, site_id).await.tick.expect("tick").evaluated_at, now);
+
+    // Another company's users can't see it
+    let other = login_as(&client, "admin@company1.com", "admin").await;
+    let resp = client
+        .get(format!("/api/1/Sites/{}/scheduler/status", site_id))
+        .cookie(other)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Forbidden);
+}
@@ -0,0 +1,159 @@
+//! Integration tests for the scheduler conflict check.
+
+use neems_api::{
+    api::scheduler::SchedulerCheckResponse,
+    models::ScheduleLibraryItem,
+    orm::testing::{fast_test_rocket, golden_fixtures},
+    schedule_check::ConflictKind,
+};
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::{Value, json};
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+async fn create_schedule(
+    client: &Client,
+    cookie: &rocket::http::Cookie<'static>,
+    site_id: i32,
+    body: &Value,
+) -> ScheduleLibraryItem {
+    let resp = client
+        .post(format!("/api/1/Sites/{}/ScheduleLibraryItems", site_id))
+        .cookie(cookie.clone())
+        .json(body)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    resp.into_json().await.expect("json")
+}
+
+async fn check(
+    client: &Client,
+    cookie: &rocket::http::Cookie<'static>,
+    site_id: i32,
+    body: &Value,
+) -> SchedulerCheckResponse {
+    let resp = client
+        .post(format!("/api/1/Sites/{}/scheduler/check", site_id))
+        .cookie(cookie.clone())
+        .json(body)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    resp.into_json().await.expect("json")
+}
+
+#[tokio::test]
+async fn check_candidate_schedule_commands() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let fixtures = golden_fixtures();
+    let site_id = fixtures.site_id("Device API Site A");
+    let device_id = fixtures.device_id("SEL-451");
+
+    let body = json!({
+        "name": "Contradictory",
+        "commands": [
+            { "execution_offset_seconds": 6 * 3600, "command_type": "charge", "duration_seconds": 4 * 3600 },
+            { "execution_offset_seconds": 8 * 3600, "command_type": "discharge", "device_id": device_id },
+            { "execution_offset_seconds": 17 * 3600, "command_type": "discharge",
+              "duration_seconds": 5 * 3600, "power_kw": 5000.0 }
+        ]
+    });
+    let item = create_schedule(&client, &admin, site_id, &body).await;
+
+    let body =
+        json!({ "start_date": "2026-07-20", "end_date": "2026-07-21", "library_item_id": item.id });
+    let result = check(&client, &admin, site_id, &body).await;
+
+    assert!(result.soc_checked);
+    let kinds: Vec<_> =
+        result.conflicts.iter().map(|c| (c.date.to_string(), c.kind.clone())).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            ("2026-07-20".to_string(), ConflictKind::SimultaneousChargeDischarge),
+            ("2026-07-20".to_string(), ConflictKind::SocInfeasible),
+            ("2026-07-21".to_string(), ConflictKind::SimultaneousChargeDischarge),
+            ("2026-07-21".to_string(), ConflictKind::SocInfeasible),
+        ]
+    );
+    let overlap = &result.conflicts[0];
+    assert_eq!(overlap.library_item_ids, vec![item.id]);
+    assert_eq!(overlap.command_ids, vec![item.commands[0].id, item.commands[1].id]);
+}
+
+#[tokio::test]
+async fn check_reports_overlapping_overrides() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let site_id = golden_fixtures().site_id("Device API Site A");
+
+    let mut ids = Vec::new();
+    for name in ["Heatwave", "Maintenance"] {
+        let body = json!({
+            "name": name,
+            "commands": [{ "execution_offset_seconds": 0, "command_type": "charge" }]
+        });
+        let item = create_schedule(&client, &admin, site_id, &body).await;
+        let rule = json!({ "rule_type": "specific_date", "specific_dates": ["2026-08-01"] });
+        let resp = client
+            .post(format!("/api/1/ScheduleLibraryItems/{}/ApplicationRules", item.id))
+            .cookie(admin.clone())
+            .json(&rule)
+            .dispatch()
+            .await;
+        assert_eq!(resp.status(), Status::Created);
+        ids.push(item.id);
+    }
+
+    let body = json!({ "start_date": "2026-08-01", "end_date": "2026-08-02" });
+    let result = check(&client, &admin, site_id, &body).await;
+    assert_eq!(result.conflicts.len(), 1);
+    let conflict = &result.conflicts[0];
+    assert_eq!(conflict.kind, ConflictKind::OverlappingOverrides);
+    assert_eq!(conflict.date.to_string(), "2026-08-01");
+    let mut involved = conflict.library_item_ids.clone();
+    involved.sort();
+    assert_eq!(involved, ids);
+
+    // Checked as the candidate, one still clashes with the other, and wins
+    let body =
+        json!({ "start_date": "2026-08-01", "end_date": "2026-08-01", "library_item_id": ids[0] });
+    let result = check(&client, &admin, site_id, &body).await;
+    assert_eq!(result.conflicts[0].library_item_ids[0], ids[0]);
+    assert_eq!(result.conflicts[0].library_item_ids.len(), 2);
+}
+
+#[tokio::test]
+async fn check_rejects_bad_requests() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let fixtures = golden_fixtures();
+    let site_id = fixtures.site_id("Device API Site A");
+    let url = format!("/api/1/Sites/{}/scheduler/check", site_id);
+
+    let backwards = json!({ "start_date": "2026-07-21", "end_date": "2026-07-20" });
+    let resp = client.post(url.clone()).cookie(admin.clone()).json(&backwards).dispatch().await;
+    assert_eq!(resp.status(), Status::BadRequest);
+
+    let missing =
+        json!({ "start_date": "2026-07-20", "end_date": "2026-07-20", "library_item_id": 999999 });
+    let resp = client.post(url).cookie(admin.clone()).json(&missing).dispatch().await;
+    assert_eq!(resp.status(), Status::NotFound);
+
+    let other_site = fixtures.site_id("Test Site 1");
+    let body = json!({ "start_date": "2026-07-20", "end_date": "2026-07-20" });
+    let resp = client
+        .post(format!("/api/1/Sites/{}/scheduler/check", other_site))
+        .cookie(admin)
+        .json(&body)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Forbidden);
+}
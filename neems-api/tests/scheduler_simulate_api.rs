@@ -0,0 +1,144 @@
+//! Integration tests for the scheduler SoC simulation.
+
+use neems_api::{
+    models::ScheduleLibraryItem,
+    orm::testing::{fast_test_rocket, golden_fixtures},
+    soc_simulation::{SocSimulation, SocViolationKind},
+};
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::json;
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+#[tokio::test]
+async fn simulate_proposed_commands() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let site_id = golden_fixtures().site_id("Device API Site A");
+
+    // 250 kW into 1000 kWh is 25% an hour
+    let body = json!({
+        "initial_soc_percent": 50,
+        "capacity_kwh": 1000,
+        "round_trip_efficiency_percent": 100,
+        "min_soc_percent": 10,
+        "max_soc_percent": 90,
+        "step_seconds": 3600,
+        "commands": [
+            { "execution_offset_seconds": 0, "command_type": "charge", "duration_seconds": 7200, "power_kw": 250 },
+            { "execution_offset_seconds": 12 * 3600, "command_type": "discharge", "power_kw": 250 }
+        ]
+    });
+    let resp = client
+        .post(format!("/api/1/Sites/{}/scheduler/simulate", site_id))
+        .cookie(admin.clone())
+        .json(&body)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let result: SocSimulation = resp.into_json().await.expect("json");
+
+    assert_eq!(result.points.len(), 25);
+    assert_eq!(result.points[2].soc_percent, 100.0);
+    assert_eq!(result.final_soc_percent, 0.0);
+    let kinds: Vec<_> = result
+        .violations
+        .iter()
+        .map(|v| (v.offset_seconds / 3600, v.kind.clone(), v.command_index))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (2, SocViolationKind::AboveMax, 0),
+            (16, SocViolationKind::BelowMin, 1),
+            (16, SocViolationKind::Empty, 1),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn simulate_saved_schedule_for_device() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let fixtures = golden_fixtures();
+    let site_id = fixtures.site_id("Device API Site A");
+    let device_id = fixtures.device_id("SEL-451");
+    let other_device_id = fixtures.device_id("SEL-735");
+
+    let body = json!({
+        "name": "Two devices",
+        "commands": [
+            { "execution_offset_seconds": 0, "command_type": "discharge", "power_kw": 100, "device_id": other_device_id },
+            { "execution_offset_seconds": 3600, "command_type": "charge", "power_kw": 100,
+              "target_soc_percent": 80, "device_id": device_id }
+        ]
+    });
+    let resp = client
+        .post(format!("/api/1/Sites/{}/ScheduleLibraryItems", site_id))
+        .cookie(admin.clone())
+        .json(&body)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let item: ScheduleLibraryItem = resp.into_json().await.expect("json");
+
+    let body = json!({
+        "initial_soc_percent": 50,
+        "library_item_id": item.id,
+        "device_id": device_id,
+        "capacity_kwh": 1000,
+        "round_trip_efficiency_percent": 100,
+        "hours": 6,
+        "step_seconds": 3600
+    });
+    let resp = client
+        .post(format!("/api/1/Sites/{}/scheduler/simulate", site_id))
+        .cookie(admin.clone())
+        .json(&body)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let result: SocSimulation = resp.into_json().await.expect("json");
+
+    // The other device's discharge isn't simulated; this one's charge carries
+    // over from the day before, to 80%
+    let socs: Vec<f64> = result.points.iter().map(|p| p.soc_percent).collect();
+    assert_eq!(socs, vec![50.0, 60.0, 70.0, 80.0, 80.0, 80.0, 80.0]);
+    assert!(result.violations.is_empty());
+}
+
+#[tokio::test]
+async fn simulate_rejects_bad_requests() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let fixtures = golden_fixtures();
+    let url = format!("/api/1/Sites/{}/scheduler/simulate", fixtures.site_id("Device API Site A"));
+    let commands = json!([{ "execution_offset_seconds": 0, "command_type": "charge" }]);
+
+    let cases = [
+        json!({ "initial_soc_percent": 50 }),
+        json!({ "initial_soc_percent": 50, "library_item_id": 1, "commands": commands }),
+        json!({ "initial_soc_percent": 150, "commands": commands }),
+        json!({ "initial_soc_percent": 50, "commands": commands, "round_trip_efficiency_percent": 0 }),
+        json!({ "initial_soc_percent": 50, "commands": commands, "hours": 1000 }),
+        json!({ "initial_soc_percent": 50, "commands": commands, "min_soc_percent": 60, "max_soc_percent": 40 }),
+    ];
+    for body in cases {
+        let resp = client.post(url.clone()).cookie(admin.clone()).json(&body).dispatch().await;
+        assert_eq!(resp.status(), Status::BadRequest, "{}", body);
+    }
+
+    let body = json!({ "initial_soc_percent": 50, "commands": commands });
+    let resp = client
+        .post(format!("/api/1/Sites/{}/scheduler/simulate", fixtures.site_id("Test Site 1")))
+        .cookie(admin)
+        .json(&body)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Forbidden);
+}
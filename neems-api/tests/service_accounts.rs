@@ -0,0 +1,135 @@
+//! Service account tests
+//!
+//! Covers creating service accounts through /api/1/ServiceAccounts, managing
+//! their API keys under /api/1/Users/{id}/ApiKeys, and authenticating with
+//! `Authorization: Bearer` instead of a session.
+
+use neems_api::orm::testing::fast_test_rocket;
+use rocket::{
+    http::{ContentType, Cookie, Header, Status},
+    local::asynchronous::{Client, LocalResponse},
+};
+use serde_json::{Value, json};
+
+async fn login(client: &Client, email: &str, password: &str) -> Option<Cookie<'static>> {
+    let response = client
+        .post("/api/1/login")
+        .header(ContentType::JSON)
+        .body(json!({ "email": email, "password": password }).to_string())
+        .dispatch()
+        .await;
+    response.cookies().get("session").map(|cookie| cookie.clone().into_owned())
+}
+
+async fn post_json<'c>(
+    client: &'c Client,
+    url: &str,
+    cookie: &Cookie<'static>,
+    body: Value,
+) -> LocalResponse<'c> {
+    client
+        .post(url.to_string())
+        .cookie(cookie.clone())
+        .header(ContentType::JSON)
+        .body(body.to_string())
+        .dispatch()
+        .await
+}
+
+/// GET /api/1/hello with an API key, returning the status and who it was
+async fn hello_with_key(client: &Client, key: &str) -> (Status, Value) {
+    let response = client
+        .get("/api/1/hello")
+        .header(Header::new("Authorization", format!("Bearer {}", key)))
+        .dispatch()
+        .await;
+    let status = response.status();
+    (status, response.into_json().await.unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn test_service_account_api_keys() {
+    let client = Client::untracked(fast_test_rocket()).await.expect("valid rocket instance");
+    let admin_cookie = login(&client, "superadmin@example.com", "admin").await.unwrap();
+    let staff_cookie = login(&client, "testuser@example.com", "admin").await.unwrap();
+
+    let response = client.get("/api/1/hello").cookie(admin_cookie.clone()).dispatch().await;
+    let admin: Value = response.into_json().await.unwrap();
+    let response = client
+        .get(format!("/api/1/Users/{}", admin["user_id"]))
+        .cookie(admin_cookie.clone())
+        .dispatch()
+        .await;
+    let company_id = response.into_json::<Value>().await.unwrap()["company_id"].clone();
+
+    let account =
+        json!({ "name": "rtac-bridge", "company_id": company_id, "role_names": ["staff"] });
+    let response =
+        post_json(&client, "/api/1/ServiceAccounts", &admin_cookie, account.clone()).await;
+    assert_eq!(response.status(), Status::Created);
+    let created: Value = response.into_json().await.unwrap();
+    assert_eq!(created["email"], "rtac-bridge");
+    assert_eq!(created["service_account"], true);
+    assert_eq!(created["roles"][0]["name"], "staff");
+    let account_id = created["id"].as_i64().unwrap();
+
+    let response = post_json(&client, "/api/1/ServiceAccounts", &admin_cookie, account).await;
+    assert_eq!(response.status(), Status::Conflict);
+    let bad_name =
+        json!({ "name": "bot@example.com", "company_id": company_id, "role_names": ["staff"] });
+    let response = post_json(&client, "/api/1/ServiceAccounts", &admin_cookie, bad_name).await;
+    assert_eq!(response.status(), Status::BadRequest);
+
+    // No password login, whatever the password
+    assert!(login(&client, "rtac-bridge", "").await.is_none());
+    assert!(login(&client, "rtac-bridge", "anything").await.is_none());
+
+    let keys_url = format!("/api/1/Users/{}/ApiKeys", account_id);
+    let response = post_json(&client, &keys_url, &staff_cookie, json!({})).await;
+    assert_eq!(response.status(), Status::Forbidden);
+    let response =
+        post_json(&client, &keys_url, &admin_cookie, json!({ "description": "site 3" })).await;
+    assert_eq!(response.status(), Status::Created);
+    let secret: Value = response.into_json().await.unwrap();
+    let key = secret["key"].as_str().unwrap().to_string();
+    let key_id = secret["api_key"]["id"].as_i64().unwrap();
+    assert!(key.starts_with(secret["api_key"]["prefix"].as_str().unwrap()));
+
+    let (status, hello) = hello_with_key(&client, &key).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(hello["email"], "rtac-bridge");
+    let (status, _) = hello_with_key(&client, "neems_not-a-key").await;
+    assert_eq!(status, Status::Unauthorized);
+
+    let response = client.get(keys_url.as_str()).cookie(admin_cookie.clone()).dispatch().await;
+    let listed: Value = response.into_json().await.unwrap();
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+    assert!(listed[0].get("key_hash").is_none());
+
+    // The account rotates its own key
+    let response = client
+        .post(format!("{}/{}/Rotate", keys_url, key_id))
+        .header(Header::new("Authorization", format!("Bearer {}", key)))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let rotated: Value = response.into_json().await.unwrap();
+    let new_key = rotated["key"].as_str().unwrap().to_string();
+    assert_eq!(hello_with_key(&client, &key).await.0, Status::Unauthorized);
+    assert_eq!(hello_with_key(&client, &new_key).await.0, Status::Ok);
+
+    // People don't get API keys
+    let response = client.get("/api/1/hello").cookie(staff_cookie.clone()).dispatch().await;
+    let staff: Value = response.into_json().await.unwrap();
+    let staff_keys_url = format!("/api/1/Users/{}/ApiKeys", staff["user_id"]);
+    let response = post_json(&client, &staff_keys_url, &admin_cookie, json!({})).await;
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let response = client
+        .delete(format!("{}/{}", keys_url, key_id))
+        .cookie(admin_cookie.clone())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(hello_with_key(&client, &new_key).await.0, Status::Unauthorized);
+}
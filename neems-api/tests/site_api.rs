@@ -1,6 +1,6 @@
 use neems_api::{
     models::{Company, Site},
-    orm::testing::fast_test_rocket,
+    orm::testing::{fast_test_rocket, golden_fixtures},
 };
 use rocket::{
     http::{ContentType, Status},
@@ -384,3 +384,54 @@ async fn test_update_site_persists_demo_defaults() {
     assert_eq!(after.site_variant, "no_grid_charge");
     assert!(!after.closed_loop_enabled);
 }
+
+#[rocket::async_test]
+async fn test_patch_site_merges_and_clears_fields() {
+    let client = Client::tracked(fast_test_rocket()).await.expect("valid rocket instance");
+    let admin_cookie = login_admin(&client).await;
+    let site_id = golden_fixtures().site_id("Test Site 1");
+    let url = format!("/api/1/Sites/{}", site_id);
+
+    let response = client
+        .put(&url)
+        .cookie(admin_cookie.clone())
+        .json(&json!({ "power_kw": 5000.0, "capacity_kwh": 23500.0 }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let before: Site = response.into_json().await.expect("valid site JSON");
+
+    // Absent fields are kept, null clears, values replace
+    let patch = json!({ "power_kw": null, "charge_rate_percent": 50.0 });
+    let response = client.patch(&url).cookie(admin_cookie.clone()).json(&patch).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let patched: Site = response.into_json().await.expect("valid site JSON");
+    assert_eq!(patched.power_kw, None);
+    assert_eq!(patched.capacity_kwh, Some(23500.0));
+    assert!((patched.charge_rate_percent - 50.0).abs() < 1e-6);
+    assert_eq!(patched.name, before.name);
+
+    // Fields that can't be null, and the same range checks as PUT
+    for patch in [json!({ "name": null }), json!({ "site_variant": null })] {
+        let response =
+            client.patch(&url).cookie(admin_cookie.clone()).json(&patch).dispatch().await;
+        assert_eq!(response.status(), Status::UnprocessableEntity, "{}", patch);
+    }
+    let response = client
+        .patch(&url)
+        .cookie(admin_cookie)
+        .json(&json!({ "charge_rate_percent": 150.0 }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::BadRequest);
+
+    // The same authorization as PUT
+    let user_cookie = login_user(&client, "staff@testcompany.com", "admin").await;
+    let response = client
+        .patch(&url)
+        .cookie(user_cookie)
+        .json(&json!({ "name": "Not Mine" }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+}
@@ -0,0 +1,112 @@
+//! Integration tests for archiving a decommissioned site.
+
+use diesel::{prelude::*, sql_types::Text};
+use neems_api::{
+    SiteDbConn,
+    api::site_archive::SiteArchive,
+    orm::testing::{fast_test_rocket, golden_fixtures},
+    storage::BlobLink,
+};
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::json;
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+#[derive(QueryableByName)]
+struct InfoValue {
+    #[diesel(sql_type = Text)]
+    value: String,
+}
+
+#[tokio::test]
+async fn archiving_exports_history_and_makes_site_read_only() {
+    let site_id = golden_fixtures().site_id("Device API Site A");
+    let rocket = fast_test_rocket().ignite().await.expect("ignite");
+    let site_db = SiteDbConn::get_one(&rocket).await.expect("site database");
+    let seeded = site_db
+        .run(move |conn| neems_data::seed_soc_history(conn, site_id, 1, 60))
+        .await
+        .expect("seed history");
+    let client = Client::tracked(rocket).await.expect("client");
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let archive_url = format!("/api/1/Sites/{}/Archive", site_id);
+
+    // Only those who can manage the site may archive it
+    let other = login_as(&client, "admin@devicetestb.com", "admin").await;
+    let resp = client.post(archive_url.clone()).cookie(other).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+    let resp = client.get(archive_url.clone()).cookie(admin.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::NotFound);
+
+    let resp = client.post(archive_url.clone()).cookie(admin.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Created);
+    let archive: SiteArchive = resp.into_json().await.expect("json");
+    let rows = |table: &str| archive.tables.iter().find(|t| t.table == table).unwrap().rows;
+    assert_eq!(rows("sites"), 1);
+    assert_eq!(rows("devices"), 2);
+    assert_eq!(rows("readings"), seeded.written);
+    assert_eq!(archive.readings_deleted, seeded.written);
+
+    // The download is a SQLite database standing on its own
+    let resp = client.get(archive.download.url.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let bytes = resp.into_bytes().await.expect("archive");
+    let path = std::env::temp_dir().join(format!("site-archive-{}.sqlite", uuid::Uuid::new_v4()));
+    std::fs::write(&path, bytes).unwrap();
+    let mut conn = SqliteConnection::establish(path.to_str().unwrap()).unwrap();
+    let info = diesel::sql_query("SELECT value FROM archive_info WHERE name = 'site_id'")
+        .get_result::<InfoValue>(&mut conn)
+        .unwrap();
+    assert_eq!(info.value, site_id.to_string());
+    let names = diesel::sql_query("SELECT name AS value FROM sites")
+        .load::<InfoValue>(&mut conn)
+        .unwrap();
+    assert_eq!(names[0].value, "Device API Site A");
+    drop(conn);
+    std::fs::remove_file(&path).unwrap();
+
+    // A fresh link to it can be had later
+    let resp = client.get(archive_url.clone()).cookie(admin.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let link: BlobLink = resp.into_json().await.expect("json");
+    assert_eq!(link.key, archive.download.key);
+
+    // The site is marked archived and can't be changed
+    let resp = client
+        .get(format!("/api/1/Sites/{}", site_id))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    let site: serde_json::Value = resp.into_json().await.expect("json");
+    assert!(site["archived_at"].is_string());
+    let resp = client
+        .put(format!("/api/1/Sites/{}", site_id))
+        .cookie(admin.clone())
+        .json(&json!({ "name": "Renamed" }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Conflict);
+    let resp = client.post(archive_url).cookie(admin.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Conflict);
+    let device_id = golden_fixtures().device_id("SEL-451");
+    let resp = client
+        .delete(format!("/api/1/Devices/{}", device_id))
+        .cookie(admin)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Conflict);
+
+    // Its readings are gone from the live database
+    let site_db = SiteDbConn::get_one(client.rocket()).await.expect("site database");
+    let source_id = seeded.source_id;
+    let remaining = site_db
+        .run(move |conn| neems_data::get_recent_readings(conn, source_id, 10))
+        .await
+        .unwrap();
+    assert!(remaining.is_empty());
+}
@@ -0,0 +1,205 @@
+//! Integration tests for site tariffs and the economics report.
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use neems_api::{
+    SiteDbConn,
+    api::economics::EconomicsResponse,
+    economics::EconomicsPeriod,
+    models::SiteTariff,
+    orm::testing::{fast_test_rocket, golden_fixtures},
+};
+use neems_data::models::{NewReading, NewSource};
+use rocket::{
+    http::{ContentType, Status},
+    local::asynchronous::Client,
+    tokio,
+};
+use serde_json::json;
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+fn at(day: u32, hour: u32, minute: i64) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2026, 7, day).unwrap().and_hms_opt(hour, 0, 0).unwrap()
+        + Duration::minutes(minute)
+}
+
+/// A client whose site data has a battery on `site_id` that charges 400 kWh
+/// off-peak and discharges 300 kWh at peak on July 1st, and discharges 25 kWh
+/// at midday on the 2nd, shaving 100 kW off the site's load.
+async fn client_with_readings(site_id: i32) -> Client {
+    let rocket = fast_test_rocket().ignite().await.expect("ignite");
+    let site_db = SiteDbConn::get_one(&rocket).await.expect("site database");
+    site_db
+        .run(move |conn| {
+            let source = neems_data::create_source(
+                conn,
+                NewSource {
+                    name: "Economics battery".to_string(),
+                    description: None,
+                    active: Some(true),
+                    interval_seconds: Some(900),
+                    test_type: Some("simulator".to_string()),
+                    arguments: None,
+                    site_id: Some(site_id),
+                    company_id: None,
+                    priority: None,
+                    point_fields: None,
+                },
+            )
+            .expect("create source");
+            let reading = |timestamp: NaiveDateTime, data: serde_json::Value| NewReading {
+                source_id: source.id.unwrap(),
+                timestamp: Some(timestamp),
+                data: data.to_string(),
+                quality_flags: None,
+                device_timestamp: None,
+            };
+            let mut readings: Vec<NewReading> = (0..4)
+                .map(|i| reading(at(1, 1, 15 * i), json!({ "power_kw": -400.0 })))
+                .collect();
+            readings
+                .extend((0..4).map(|i| reading(at(1, 17, 15 * i), json!({ "power_kw": 300.0 }))));
+            readings.push(reading(at(1, 12, 0), json!({ "level": 50 })));
+            readings.push(reading(
+                at(2, 12, 0),
+                json!({ "battery_power_kw": 100.0, "load_kw": 800.0 }),
+            ));
+            neems_data::insert_readings_batch(conn, readings).expect("insert readings");
+        })
+        .await;
+    Client::tracked(rocket).await.unwrap()
+}
+
+#[tokio::test]
+async fn economics_from_readings_and_tariff() {
+    let site_id = golden_fixtures().site_id("Device API Site A");
+    let client = client_with_readings(site_id).await;
+    let admin = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let url = format!("/api/1/Sites/{}/Economics?from=2026-07-01&to=2026-07-31", site_id);
+
+    // Without a tariff, only the energy
+    let resp = client.get(url.clone()).cookie(admin.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let report: EconomicsResponse = resp.into_json().await.expect("json");
+    assert_eq!(report.currency, None);
+    assert_eq!(report.rows.len(), 2);
+    assert_eq!((report.rows[0].charged_kwh, report.rows[0].discharged_kwh), (400.0, 300.0));
+    assert_eq!(report.rows[0].round_trip_efficiency_percent, Some(75.0));
+    assert_eq!(report.rows[0].arbitrage_revenue, None);
+
+    // Off-peak is midnight to 08:00 and peak 16:00 to 20:00 on this site
+    let tariff = json!({
+        "standard_price_per_kwh": 0.15,
+        "off_peak_price_per_kwh": 0.10,
+        "peak_price_per_kwh": 0.30,
+        "demand_charge_per_kw": 12.0
+    });
+    let resp = client
+        .put(format!("/api/1/Sites/{}/Tariff", site_id))
+        .cookie(admin.clone())
+        .json(&tariff)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let saved: SiteTariff = resp.into_json().await.expect("json");
+    assert_eq!(saved.currency, "USD");
+
+    let resp = client.get(url.clone()).cookie(admin.clone()).dispatch().await;
+    let report: EconomicsResponse = resp.into_json().await.expect("json");
+    assert_eq!(report.currency.as_deref(), Some("USD"));
+    let first = &report.rows[0];
+    assert!((first.charge_cost.unwrap() - 40.0).abs() < 1e-9);
+    assert!((first.arbitrage_revenue.unwrap() - 50.0).abs() < 1e-9);
+    let second = &report.rows[1];
+    assert!((second.discharge_value.unwrap() - 3.75).abs() < 1e-9);
+    assert_eq!(second.peak_net_demand_kw, Some(700.0));
+    assert_eq!(second.demand_charge_savings, Some(1200.0));
+
+    let resp = client
+        .get(format!("{}&period=month", url))
+        .cookie(admin.clone())
+        .dispatch()
+        .await;
+    let report: EconomicsResponse = resp.into_json().await.expect("json");
+    assert_eq!(report.period, EconomicsPeriod::Month);
+    assert_eq!(report.rows.len(), 1);
+    assert_eq!(report.rows[0].discharged_kwh, 325.0);
+
+    let resp = client
+        .get(format!("/api/1/Sites/{}/Economics.csv?from=2026-07-01&to=2026-07-01", site_id))
+        .cookie(admin)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    assert_eq!(resp.content_type(), Some(ContentType::CSV));
+    let csv = resp.into_string().await.expect("csv");
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("period_start,charged_kwh,discharged_kwh,"), "{}", csv);
+    assert!(lines[1].starts_with("2026-07-01,400.0,300.0,75.0,40."), "{}", csv);
+}
+
+#[tokio::test]
+async fn tariff_permissions_and_validation() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let fixtures = golden_fixtures();
+    let site_id = fixtures.site_id("Test Site 1");
+    let url = format!("/api/1/Sites/{}/Tariff", site_id);
+    let tariff = json!({
+        "currency": "EUR",
+        "standard_price_per_kwh": 0.2,
+        "off_peak_price_per_kwh": -0.01,
+        "peak_price_per_kwh": 0.4
+    });
+
+    // Staff can view the site's economics but not set its tariff
+    let staff = login_as(&client, "staff@testcompany.com", "admin").await;
+    let resp = client.put(url.clone()).cookie(staff.clone()).json(&tariff).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+    let resp = client
+        .get(format!("/api/1/Sites/{}/Economics", site_id))
+        .cookie(staff.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+
+    // Another company's admin can't see it at all
+    let other = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let resp = client.get(url.clone()).cookie(other).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+
+    let admin = login_as(&client, "admin@company1.com", "admin").await;
+    for bad in [
+        json!({ "currency": "euro", "standard_price_per_kwh": 0.2, "off_peak_price_per_kwh": 0.1, "peak_price_per_kwh": 0.4 }),
+        json!({ "standard_price_per_kwh": 0.2, "off_peak_price_per_kwh": 0.1, "peak_price_per_kwh": 0.4, "demand_charge_per_kw": -1 }),
+    ] {
+        let resp = client.put(url.clone()).cookie(admin.clone()).json(&bad).dispatch().await;
+        assert_eq!(resp.status(), Status::BadRequest, "{}", bad);
+    }
+
+    let resp = client.get(url.clone()).cookie(admin.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::NotFound);
+    let resp = client.put(url.clone()).cookie(admin.clone()).json(&tariff).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let resp = client.get(url.clone()).cookie(staff).dispatch().await;
+    let saved: SiteTariff = resp.into_json().await.expect("json");
+    assert_eq!((saved.currency.as_str(), saved.off_peak_price_per_kwh), ("EUR", -0.01));
+    let resp = client.delete(url.clone()).cookie(admin.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::NoContent);
+    let resp = client.delete(url).cookie(admin.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::NotFound);
+
+    for query in ["from=2026-07-02&to=2026-07-01", "from=2025-01-01&to=2026-07-01", "period=week"] {
+        let resp = client
+            .get(format!("/api/1/Sites/{}/Economics?{}", site_id, query))
+            .cookie(admin.clone())
+            .dispatch()
+            .await;
+        assert_eq!(resp.status(), Status::BadRequest, "{}", query);
+    }
+}
@@ -0,0 +1,186 @@
+//! Integration tests for configuring data sources and listing collector types.
+
+use neems_api::orm::testing::fast_test_rocket;
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::{Value, json};
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+#[tokio::test]
+async fn collector_types_expose_argument_schemas() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+
+    let resp = client.get("/api/1/CollectorTypes").dispatch().await;
+    assert_eq!(resp.status(), Status::Unauthorized);
+
+    let session = login_as(&client, "testuser@example.com", "admin").await;
+    let resp = client.get("/api/1/CollectorTypes").cookie(session).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    let types: Vec<Value> = resp.into_json().await.unwrap();
+    let weather = types.iter().find(|t| t["test_type"] == "weather").expect("weather type");
+    let schema = &weather["schema"];
+    assert_eq!(schema["required"], json!(["latitude", "longitude"]));
+    assert_eq!(schema["properties"]["latitude"]["type"], "number");
+    assert_eq!(schema["properties"]["provider"]["default"], "nws");
+    let exec = types.iter().find(|t| t["test_type"] == "exec").expect("exec type");
+    assert_eq!(exec["schema"]["additionalProperties"], true);
+}
+
+#[tokio::test]
+async fn source_arguments_are_validated_on_create_and_update() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+
+    let source = json!({
+        "name": "roof-weather",
+        "test_type": "weather",
+        "arguments": { "latitude": 95, "provder": "nws" }
+    });
+
+    let staff = login_as(&client, "testuser@example.com", "admin").await;
+    let resp = client.post("/api/1/DataSources").cookie(staff).json(&source).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+
+    let session = login_as(&client, "newtown_staff@example.com", "newtownstaffpass").await;
+    let resp = client
+        .post("/api/1/DataSources")
+        .cookie(session.clone())
+        .json(&source)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+    let body: Value = resp.into_json().await.unwrap();
+    let error = body["error"].as_str().unwrap();
+    assert!(error.contains("'latitude' must be at most 90"), "{}", error);
+    assert!(error.contains("'longitude' is required"), "{}", error);
+    assert!(error.contains("unknown argument 'provder'"), "{}", error);
+
+    let source = json!({
+        "name": "roof-weather",
+        "test_type": "weather",
+        "arguments": { "latitude": 38.9, "longitude": -77.0 },
+        "interval_seconds": 300
+    });
+    let resp = client
+        .post("/api/1/DataSources")
+        .cookie(session.clone())
+        .json(&source)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Created);
+    let created: Value = resp.into_json().await.unwrap();
+    let id = created["id"].as_i64().unwrap();
+    let arguments: Value = serde_json::from_str(created["arguments"].as_str().unwrap()).unwrap();
+    assert_eq!(arguments, json!({ "latitude": "38.9", "longitude": "-77.0" }));
+
+    let resp = client
+        .post("/api/1/DataSources")
+        .cookie(session.clone())
+        .json(&source)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Conflict);
+
+    // Changing the type checks the existing arguments against it
+    let url = format!("/api/1/DataSources/{}", id);
+    let resp = client
+        .put(url.clone())
+        .cookie(session.clone())
+        .json(&json!({ "test_type": "exec" }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+
+    let resp = client
+        .put(url.clone())
+        .cookie(session.clone())
+        .json(&json!({
+            "test_type": "exec",
+            "arguments": { "command": "/opt/poll", "timeout_seconds": 5, "site": "roof" }
+        }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let updated: Value = resp.into_json().await.unwrap();
+    assert_eq!(updated["test_type"], "exec");
+    assert_eq!(updated["interval_seconds"], 300);
+
+    let resp = client
+        .put("/api/1/DataSources/999999")
+        .cookie(session)
+        .json(&json!({ "active": false }))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::NotFound);
+}
+
+#[tokio::test]
+async fn source_self_test_runs_collector_without_storing() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let session = login_as(&client, "newtown_staff@example.com", "newtownstaffpass").await;
+
+    let mut ids = Vec::new();
+    for source in [
+        json!({ "name": "self-test-memory", "test_type": "memory", "active": false }),
+        json!({
+            "name": "self-test-exec",
+            "test_type": "exec",
+            "arguments": { "command": "/bin/false" }
+        }),
+    ] {
+        let resp = client
+            .post("/api/1/DataSources")
+            .cookie(session.clone())
+            .json(&source)
+            .dispatch()
+            .await;
+        assert_eq!(resp.status(), Status::Created);
+        let created: Value = resp.into_json().await.unwrap();
+        ids.push(created["id"].as_i64().unwrap());
+    }
+
+    let resp = client
+        .post(format!("/api/1/Sources/{}/test", ids[0]))
+        .cookie(session.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let result: Value = resp.into_json().await.unwrap();
+    assert_eq!(result["ok"], true);
+    assert_eq!(result["source_name"], "self-test-memory");
+    assert!(result["data"]["total_bytes"].is_u64());
+
+    let resp = client
+        .get(format!("/api/1/DataSources/{}/Readings?latest=10", ids[0]))
+        .cookie(session.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let readings: Value = resp.into_json().await.unwrap();
+    assert_eq!(readings["readings"], json!([]), "self-test must not store a reading");
+
+    let resp = client
+        .post(format!("/api/1/Sources/{}/test", ids[1]))
+        .cookie(session.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let result: Value = resp.into_json().await.unwrap();
+    assert_eq!(result["ok"], false);
+    assert!(result["error"].as_str().unwrap().contains("/bin/false"));
+
+    let resp = client.post("/api/1/Sources/999999/test").cookie(session).dispatch().await;
+    assert_eq!(resp.status(), Status::NotFound);
+
+    let staff = login_as(&client, "testuser@example.com", "admin").await;
+    let resp = client
+        .post(format!("/api/1/Sources/{}/test", ids[0]))
+        .cookie(staff)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Forbidden);
+}
@@ -0,0 +1,77 @@
+//! Integration tests for exporting reports to blob storage and downloading
+//! them from signed links.
+
+use neems_api::{
+    orm::testing::{fast_test_rocket, golden_fixtures},
+    storage::BlobLink,
+};
+use rocket::{
+    http::{ContentType, Status},
+    local::asynchronous::Client,
+    tokio,
+};
+use serde_json::json;
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+#[tokio::test]
+async fn economics_exports_download_from_signed_links() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let site_id = golden_fixtures().site_id("Device API Site A");
+    let user = login_as(&client, "admin@devicetesta.com", "admin").await;
+    let exports_url = format!(
+        "/api/1/Sites/{}/Economics/Exports?from=2026-09-01&to=2026-09-30&period=month",
+        site_id
+    );
+
+    let resp = client
+        .get(format!(
+            "/api/1/Sites/{}/Economics.csv?from=2026-09-01&to=2026-09-30&period=month",
+            site_id
+        ))
+        .cookie(user.clone())
+        .dispatch()
+        .await;
+    let report = resp.into_string().await.expect("body");
+
+    let resp = client.post(exports_url.clone()).cookie(user).dispatch().await;
+    assert_eq!(resp.status(), Status::Created);
+    let link: BlobLink = resp.into_json().await.expect("json");
+    assert!(
+        link.key.starts_with(&format!(
+            "reports/site-{}/economics-2026-09-01-2026-09-30-month-",
+            site_id
+        ))
+    );
+    assert!(link.url.starts_with(&format!("/api/1/Blobs/{}?expires=", link.key)));
+
+    // The link needs no session
+    let resp = client.get(link.url.clone()).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok);
+    assert_eq!(resp.content_type(), Some(ContentType::CSV));
+    assert_eq!(resp.into_string().await.expect("body"), report);
+
+    // Nor does it work for another key, or once tampered with
+    let (path, query) = link.url.split_once('?').unwrap();
+    let other = format!("{}x?{}", path.trim_end_matches(".csv"), query);
+    let resp = client.get(other).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+    let tampered = format!("{}0", link.url);
+    let resp = client.get(tampered).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+    let signature = query.split_once("&signature=").unwrap().1;
+    let expires = link.expires_at.and_utc().timestamp();
+    let extended = format!("{}?expires={}&signature={}", path, expires + 60, signature);
+    let resp = client.get(extended).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+
+    // Exporting needs the site's usual permissions
+    let other = login_as(&client, "admin@company1.com", "admin").await;
+    let resp = client.post(exports_url).cookie(other).dispatch().await;
+    assert_eq!(resp.status(), Status::Forbidden);
+}
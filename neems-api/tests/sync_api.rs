@@ -0,0 +1,171 @@
+//! Integration tests for the store-and-forward sync endpoints.
+
+use neems_api::orm::testing::fast_test_rocket;
+use rocket::{http::Status, local::asynchronous::Client, tokio};
+use serde_json::{Value, json};
+
+async fn login_as(client: &Client, email: &str, password: &str) -> rocket::http::Cookie<'static> {
+    let body = json!({ "email": email, "password": password });
+    let resp = client.post("/api/1/login").json(&body).dispatch().await;
+    assert_eq!(resp.status(), Status::Ok, "login failed for {}", email);
+    resp.cookies().get("session").expect("session cookie").clone().into_owned()
+}
+
+fn batch(origin: &str, ids: &[i32]) -> Value {
+    let readings: Vec<Value> = ids
+        .iter()
+        .map(|id| {
+            json!({
+                "origin_reading_id": id,
+                "source_name": "charging_state",
+                "test_type": "charging_state",
+                "site_id": null,
+                "company_id": null,
+                "timestamp": format!("2024-01-01T12:00:{:02}", id),
+                "device_timestamp": null,
+                "data": format!("{{\"level\": {}}}", id),
+                "quality_flags": 0
+            })
+        })
+        .collect();
+    json!({ "origin": origin, "readings": readings })
+}
+
+#[tokio::test]
+async fn sync_upload_is_idempotent_and_advances_cursor() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let session = login_as(&client, "newtown_staff@example.com", "newtownstaffpass").await;
+
+    let cursor = client
+        .get("/api/1/Sync/Origins/gateway-1/Cursor")
+        .cookie(session.clone())
+        .dispatch()
+        .await;
+    assert_eq!(cursor.status(), Status::Ok);
+    let cursor: Value = cursor.into_json().await.expect("json");
+    assert_eq!(cursor["cursor"], json!(0));
+
+    let resp = client
+        .post("/api/1/Sync/Readings")
+        .cookie(session.clone())
+        .json(&batch("gateway-1", &[1, 2, 3]))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let ack: Value = resp.into_json().await.expect("json");
+    assert_eq!(ack["accepted"], json!(3));
+    assert_eq!(ack["cursor"], json!(3));
+
+    // Resending overlapping readings only appends the new one
+    let resp = client
+        .post("/api/1/Sync/Readings")
+        .cookie(session.clone())
+        .json(&batch("gateway-1", &[3, 4]))
+        .dispatch()
+        .await;
+    let ack: Value = resp.into_json().await.expect("json");
+    assert_eq!(ack["accepted"], json!(1));
+    assert_eq!(ack["duplicates"], json!(1));
+    assert_eq!(ack["cursor"], json!(4));
+
+    let sources = client.get("/api/1/DataSources").dispatch().await;
+    let sources: Value = sources.into_json().await.expect("json");
+    assert!(
+        sources["sources"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|s| s["name"] == "gateway-1/charging_state" && s["active"] == json!(false))
+    );
+}
+
+#[tokio::test]
+async fn sync_rejects_invalid_origin() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let session = login_as(&client, "newtown_superadmin@example.com", "newtownpass").await;
+
+    let resp = client
+        .post("/api/1/Sync/Readings")
+        .cookie(session)
+        .json(&batch("a/b", &[1]))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+}
+
+#[tokio::test]
+async fn sync_requires_newtown_role() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+
+    let resp = client
+        .post("/api/1/Sync/Readings")
+        .json(&batch("gateway-1", &[1]))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Unauthorized);
+
+    let session = login_as(&client, "staff@example.com", "staffpass").await;
+    let resp = client
+        .post("/api/1/Sync/Readings")
+        .cookie(session)
+        .json(&batch("gateway-1", &[1]))
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Forbidden);
+}
+
+#[tokio::test]
+async fn reconcile_records_discrepancies() {
+    let client = Client::tracked(fast_test_rocket()).await.unwrap();
+    let session = login_as(&client, "newtown_staff@example.com", "newtownstaffpass").await;
+
+    client
+        .post("/api/1/Sync/Readings")
+        .cookie(session.clone())
+        .json(&batch("gateway-3", &[1, 2]))
+        .dispatch()
+        .await;
+
+    // The edge claims three readings that day; the server holds two
+    let request = json!({
+        "origin": "gateway-3",
+        "from": "2024-01-01",
+        "to": "2024-01-01",
+        "digests": [{
+            "source_name": "charging_state",
+            "day": "2024-01-01",
+            "row_count": 3,
+            "checksum": "0000"
+        }]
+    });
+    let resp = client
+        .post("/api/1/Sync/Reconcile")
+        .cookie(session.clone())
+        .json(&request)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let report: Value = resp.into_json().await.expect("json");
+    assert_eq!(report["checked"], json!(1));
+    assert_eq!(report["discrepancies"][0]["central_count"], json!(2));
+
+    let resp = client
+        .get("/api/1/Sync/Discrepancies?origin=gateway-3&since=2024-01-01")
+        .cookie(session.clone())
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::Ok);
+    let listed: Value = resp.into_json().await.expect("json");
+    assert_eq!(listed["discrepancies"].as_array().unwrap().len(), 1);
+    assert_eq!(listed["discrepancies"][0]["edge_count"], json!(3));
+
+    let backwards =
+        json!({ "origin": "gateway-3", "from": "2024-01-02", "to": "2024-01-01", "digests": [] });
+    let resp = client
+        .post("/api/1/Sync/Reconcile")
+        .cookie(session)
+        .json(&backwards)
+        .dispatch()
+        .await;
+    assert_eq!(resp.status(), Status::BadRequest);
+}
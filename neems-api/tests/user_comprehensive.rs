@@ -479,6 +479,65 @@ async fn test_user_profile_access_permissions() {
     assert_eq!(response.status(), rocket::http::Status::Forbidden);
 }
 
+#[rocket::async_test]
+async fn test_patch_user_merges_and_clears_totp_secret() {
+    let client = Client::tracked(fast_test_rocket()).await.expect("valid rocket instance");
+    let admin_cookie = login_user(&client, "superadmin@example.com", "admin").await;
+
+    let users_response = client.get("/api/1/Users").cookie(admin_cookie.clone()).dispatch().await;
+    let odata_response: serde_json::Value =
+        users_response.into_json().await.expect("valid OData JSON");
+    let users: Vec<UserWithRoles> =
+        serde_json::from_value(odata_response["value"].clone()).expect("valid users array");
+    let test_user = users
+        .iter()
+        .find(|u| u.email == "staff@testcompany.com")
+        .expect("staff@testcompany.com should exist in golden DB");
+    let url = format!("/api/1/Users/{}", test_user.id);
+
+    // PUT can set a TOTP secret but not clear it
+    let response = client
+        .put(&url)
+        .cookie(admin_cookie.clone())
+        .json(&json!({ "totp_secret": "JBSWY3DPEHPK3PXP" }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), rocket::http::Status::Ok);
+
+    // PATCH keeps absent fields and clears null ones
+    let response = client
+        .patch(&url)
+        .cookie(admin_cookie.clone())
+        .json(&json!({ "totp_secret": null, "must_change_password": true }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), rocket::http::Status::Ok);
+    let patched: UserWithRoles = response.into_json().await.expect("valid user JSON");
+    assert_eq!(patched.totp_secret, None);
+    assert!(patched.must_change_password);
+    assert_eq!(patched.email, "staff@testcompany.com");
+    assert_eq!(patched.company_id, test_user.company_id);
+
+    // Fields that can't be null
+    let response = client
+        .patch(&url)
+        .cookie(admin_cookie)
+        .json(&json!({ "email": null }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), rocket::http::Status::UnprocessableEntity);
+
+    // The same authorization as PUT
+    let other_session = login_user(&client, "user@company2.com", "admin").await;
+    let response = client
+        .patch(&url)
+        .cookie(other_session)
+        .json(&json!({ "email": "taken@testcompany.com" }))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), rocket::http::Status::Forbidden);
+}
+
 #[rocket::async_test]
 async fn test_user_deletion_permissions() {
     let client = Client::tracked(fast_test_rocket()).await.expect("valid rocket instance");
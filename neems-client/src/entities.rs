@@ -0,0 +1,136 @@
+//! Companies, users, sites and devices.
+
+use neems_api::{
+    api::{
+        device::{CreateDeviceRequest, DevicePatch, UpdateDeviceRequest},
+        site::{CreateSiteRequest, SitePatch, UpdateSiteRequest},
+        site_archive::SiteArchive,
+        user::{UpdateUserRequest, UserPatch},
+        v2::user::{CreateUserRequest, User},
+    },
+    models::{Company, CompanyInput, Device, Site, UserWithRoles},
+    storage::BlobLink,
+};
+use reqwest::Method;
+
+use crate::{Client, ClientResult};
+
+impl Client {
+    pub async fn list_companies(&self) -> ClientResult<Vec<Company>> {
+        self.list("1/Companies").await
+    }
+
+    pub async fn create_company(&self, company: &CompanyInput) -> ClientResult<Company> {
+        self.send_json(Method::POST, "1/Companies", company).await
+    }
+
+    pub async fn delete_company(&self, company_id: i32) -> ClientResult<()> {
+        self.delete(&format!("1/Companies/{}", company_id)).await
+    }
+
+    /// The users visible to the caller.
+    pub async fn list_users(&self) -> ClientResult<Vec<UserWithRoles>> {
+        self.list("1/Users").await
+    }
+
+    pub async fn get_user(&self, user_id: i32) -> ClientResult<UserWithRoles> {
+        self.get(&format!("1/Users/{}", user_id)).await
+    }
+
+    /// Create a user with the given password, which the server hashes.
+    pub async fn create_user(&self, user: &CreateUserRequest) -> ClientResult<User> {
+        self.send_json(Method::POST, "2/Users", user).await
+    }
+
+    pub async fn update_user(
+        &self,
+        user_id: i32,
+        update: &UpdateUserRequest,
+    ) -> ClientResult<UserWithRoles> {
+        self.send_json(Method::PUT, &format!("1/Users/{}", user_id), update).await
+    }
+
+    /// Change only the fields the patch gives, clearing those set to
+    /// `Patch::Null`.
+    pub async fn patch_user(&self, user_id: i32, patch: &UserPatch) -> ClientResult<UserWithRoles> {
+        self.send_json(Method::PATCH, &format!("1/Users/{}", user_id), patch).await
+    }
+
+    pub async fn delete_user(&self, user_id: i32) -> ClientResult<()> {
+        self.delete(&format!("1/Users/{}", user_id)).await
+    }
+
+    /// The sites visible to the caller.
+    pub async fn list_sites(&self) -> ClientResult<Vec<Site>> {
+        self.list("1/Sites").await
+    }
+
+    pub async fn get_site(&self, site_id: i32) -> ClientResult<Site> {
+        self.get(&format!("1/Sites/{}", site_id)).await
+    }
+
+    pub async fn create_site(&self, site: &CreateSiteRequest) -> ClientResult<Site> {
+        self.send_json(Method::POST, "1/Sites", site).await
+    }
+
+    pub async fn update_site(
+        &self,
+        site_id: i32,
+        update: &UpdateSiteRequest,
+    ) -> ClientResult<Site> {
+        self.send_json(Method::PUT, &format!("1/Sites/{}", site_id), update).await
+    }
+
+    /// Change only the fields the patch gives, clearing those set to
+    /// `Patch::Null`.
+    pub async fn patch_site(&self, site_id: i32, patch: &SitePatch) -> ClientResult<Site> {
+        self.send_json(Method::PATCH, &format!("1/Sites/{}", site_id), patch).await
+    }
+
+    pub async fn delete_site(&self, site_id: i32) -> ClientResult<()> {
+        self.delete(&format!("1/Sites/{}", site_id)).await
+    }
+
+    /// Archive a decommissioned site, making it read-only and deleting its
+    /// readings once they are safely in the archive.
+    pub async fn archive_site(&self, site_id: i32) -> ClientResult<SiteArchive> {
+        let path = format!("1/Sites/{}/Archive", site_id);
+        Ok(Self::send(self.request(Method::POST, &path)).await?.json().await?)
+    }
+
+    /// A fresh download link for an archived site's archive.
+    pub async fn get_site_archive(&self, site_id: i32) -> ClientResult<BlobLink> {
+        self.get(&format!("1/Sites/{}/Archive", site_id)).await
+    }
+
+    /// The devices visible to the caller.
+    pub async fn list_devices(&self) -> ClientResult<Vec<Device>> {
+        self.list("1/Devices").await
+    }
+
+    pub async fn get_device(&self, device_id: i32) -> ClientResult<Device> {
+        self.get(&format!("1/Devices/{}", device_id)).await
+    }
+
+    pub async fn create_device(&self, device: &CreateDeviceRequest) -> ClientResult<Device> {
+        self.send_json(Method::POST, "1/Devices", device).await
+    }
+
+    pub async fn update_device(
+        &self,
+        device_id: i32,
+        update: &UpdateDeviceRequest,
+    ) -> ClientResult<Device> {
+        self.send_json(Method::PUT, &format!("1/Devices/{}", device_id), update).await
+    }
+
+    /// Change only the fields the patch gives, clearing those set to
+    /// `Patch::Null`.
+    pub async fn patch_device(&self, device_id: i32, patch: &DevicePatch) -> ClientResult<Device> {
+        self.send_json(Method::PATCH, &format!("1/Devices/{}", device_id), patch).await
+    }
+
+    pub async fn delete_device(&self, device_id: i32) -> ClientResult<()> {
+        self.delete(&format!("1/Devices/{}", device_id)).await
+    }
+}
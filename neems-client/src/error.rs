@@ -0,0 +1,63 @@
+use std::fmt;
+
+use reqwest::StatusCode;
+
+/// Why a request failed.
+#[derive(Debug)]
+pub enum ClientError {
+    /// The request couldn't be sent, or its response couldn't be read
+    Http(reqwest::Error),
+    /// The server refused the request
+    Status {
+        status: StatusCode,
+        /// The `error` of the server's error response, or its body
+        message: String,
+    },
+}
+
+pub type ClientResult<T> = Result<T, ClientError>;
+
+impl ClientError {
+    pub(crate) fn from_response(status: StatusCode, body: &str) -> Self {
+        let message = serde_json::from_str::<serde_json::Value>(body)
+            .ok()
+            .and_then(|body| body["error"].as_str().map(str::to_string))
+            .unwrap_or_else(|| body.trim().to_string());
+        ClientError::Status { status, message }
+    }
+
+    /// The HTTP status, if the server answered.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            ClientError::Http(e) => e.status(),
+            ClientError::Status { status, .. } => Some(*status),
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Http(e) => write!(f, "{}", e),
+            ClientError::Status { status, message } if message.is_empty() => {
+                write!(f, "HTTP {}", status)
+            }
+            ClientError::Status { status, message } => write!(f, "HTTP {}: {}", status, message),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Http(e) => Some(e),
+            ClientError::Status { .. } => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Http(e)
+    }
+}
@@ -0,0 +1,203 @@
+//! Typed async client for the NEEMS API.
+//!
+//! [`Client`] wraps the HTTP calls our tools make against neems-api: logging
+//! in, CRUD on companies, users, sites and devices, readings queries, the
+//! scheduler, and the edge sync endpoints. Requests and responses use the
+//! same types the server does, from `neems-api` and `neems-data`, so a change
+//! to them breaks the client's build rather than its parsing at run time.
+//!
+//! ```no_run
+//! # async fn example() -> neems_client::ClientResult<()> {
+//! let mut client = neems_client::Client::new("https://neems.example.com")?;
+//! client.login("admin@example.com", "password").await?;
+//! for site in client.list_sites().await? {
+//!     println!("{}: {}", site.id, site.name);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! neems-data's own sync loop ([`neems_data::sync::SyncClient`]) can't use
+//! this crate, as it would depend on itself through neems-api; agents outside
+//! neems-data should use [`Client::push_readings`] and friends.
+
+mod entities;
+mod error;
+mod readings;
+mod scheduler;
+
+use std::time::Duration;
+
+use reqwest::{Method, RequestBuilder, Response, StatusCode, header};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+pub use crate::error::{ClientError, ClientResult};
+
+/// How long a request may take before it fails.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How the client authenticates its requests.
+#[derive(Debug, Clone)]
+enum Auth {
+    /// The `session` cookie from [`Client::login`]
+    Session(String),
+    /// A service account's API key, sent as `Authorization: Bearer`
+    ApiKey(String),
+}
+
+/// An OData collection, as returned by the list endpoints.
+#[derive(Deserialize)]
+struct Collection<T> {
+    value: Vec<T>,
+}
+
+/// A client for one neems-api server.
+#[derive(Debug, Clone)]
+pub struct Client {
+    base_url: String,
+    http: reqwest::Client,
+    auth: Option<Auth>,
+}
+
+impl Client {
+    /// A client for the server at `base_url`, e.g. `https://neems.example.com`,
+    /// not yet authenticated.
+    pub fn new(base_url: impl Into<String>) -> ClientResult<Self> {
+        let http = reqwest::Client::builder().timeout(DEFAULT_TIMEOUT).build()?;
+        Ok(Self::with_http_client(base_url, http))
+    }
+
+    /// As [`Client::new`], with a preconfigured HTTP client (for timeouts,
+    /// proxies or TLS roots).
+    pub fn with_http_client(base_url: impl Into<String>, http: reqwest::Client) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            http,
+            auth: None,
+        }
+    }
+
+    /// Authenticate as a service account with its API key instead of logging
+    /// in.
+    pub fn with_api_key(mut self, key: impl Into<String>) -> Self {
+        self.auth = Some(Auth::ApiKey(key.into()));
+        self
+    }
+
+    /// Reuse a session token from an earlier [`Client::login`].
+    pub fn with_session(mut self, token: impl Into<String>) -> Self {
+        self.auth = Some(Auth::Session(token.into()));
+        self
+    }
+
+    /// The server's base URL.
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// The session token, once logged in.
+    pub fn session(&self) -> Option<&str> {
+        match &self.auth {
+            Some(Auth::Session(token)) => Some(token),
+            _ => None,
+        }
+    }
+
+    /// A request to `path` under the server's `/api/`, e.g. `1/Sites`.
+    ///
+    /// The session cookie is flagged `Secure`, so it is sent by hand rather
+    /// than left to a cookie store, which would drop it over plain HTTP.
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self.http.request(method, format!("{}/api/{}", self.base_url, path));
+        match &self.auth {
+            Some(Auth::Session(token)) => {
+                request.header(header::COOKIE, format!("session={}", token))
+            }
+            Some(Auth::ApiKey(key)) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+
+    /// Send `request`, turning error statuses into [`ClientError::Status`].
+    async fn send(request: RequestBuilder) -> ClientResult<Response> {
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.text().await.unwrap_or_default();
+        Err(ClientError::from_response(status, &body))
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> ClientResult<T> {
+        Ok(Self::send(self.request(Method::GET, path)).await?.json().await?)
+    }
+
+    async fn get_query<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &(impl Serialize + ?Sized),
+    ) -> ClientResult<T> {
+        Ok(Self::send(self.request(Method::GET, path).query(query)).await?.json().await?)
+    }
+
+    async fn list<T: DeserializeOwned>(&self, path: &str) -> ClientResult<Vec<T>> {
+        Ok(self.get::<Collection<T>>(path).await?.value)
+    }
+
+    async fn send_json<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: &(impl Serialize + ?Sized),
+    ) -> ClientResult<T> {
+        Ok(Self::send(self.request(method, path).json(body)).await?.json().await?)
+    }
+
+    async fn delete(&self, path: &str) -> ClientResult<()> {
+        Self::send(self.request(Method::DELETE, path)).await?;
+        Ok(())
+    }
+
+    /// Log in, keeping the session for later requests, and return who the
+    /// server says we are.
+    pub async fn login(
+        &mut self,
+        email: &str,
+        password: &str,
+    ) -> ClientResult<neems_api::api::login::LoginSuccessResponse> {
+        let request = neems_api::api::login::LoginRequest {
+            email: email.to_string(),
+            password: password.to_string(),
+        };
+        let response = Self::send(self.request(Method::POST, "1/login").json(&request)).await?;
+        let session = response
+            .cookies()
+            .find(|cookie| cookie.name() == "session")
+            .map(|cookie| cookie.value().to_string())
+            .ok_or_else(|| ClientError::Status {
+                status: StatusCode::UNAUTHORIZED,
+                message: "login response had no session cookie".to_string(),
+            })?;
+        let user = response.json().await?;
+        self.auth = Some(Auth::Session(session));
+        Ok(user)
+    }
+
+    /// Who the current session or API key belongs to.
+    pub async fn hello(&self) -> ClientResult<neems_api::api::login::LoginSuccessResponse> {
+        self.get("1/hello").await
+    }
+
+    /// What the caller may insert, update and delete in each entity set.
+    pub async fn capabilities(&self) -> ClientResult<neems_api::api::odata::Capabilities> {
+        self.get("1/capabilities").await
+    }
+
+    /// End the session.
+    pub async fn logout(&mut self) -> ClientResult<()> {
+        Self::send(self.request(Method::POST, "1/logout")).await?;
+        self.auth = None;
+        Ok(())
+    }
+}
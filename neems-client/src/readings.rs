@@ -0,0 +1,92 @@
+//! Data sources, readings, and the edge sync endpoints.
+
+use chrono::NaiveDateTime;
+use neems_api::api::{
+    data::{
+        AggregateResponse, DataSourcesResponse, ReadingsQuery, ReadingsResponse, SocHistoryResponse,
+    },
+    reading_batch::{ReadingBatch, ReadingBatchResponse},
+};
+use neems_data::{
+    models::Source,
+    sync::{SyncAck, SyncBatch, SyncCursor},
+};
+use reqwest::Method;
+
+use crate::{Client, ClientResult};
+
+/// Timestamps as the readings endpoints take them.
+fn timestamp(time: NaiveDateTime) -> String {
+    time.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+impl Client {
+    pub async fn data_sources(&self) -> ClientResult<Vec<Source>> {
+        Ok(self.get::<DataSourcesResponse>("1/DataSources").await?.sources)
+    }
+
+    /// One source's readings matching `query`.
+    pub async fn source_readings(
+        &self,
+        source_id: i32,
+        query: &ReadingsQuery,
+    ) -> ClientResult<ReadingsResponse> {
+        self.get_query(&format!("1/DataSources/{}/Readings", source_id), query).await
+    }
+
+    /// Readings from the sources in `query.source_ids`.
+    pub async fn readings(&self, query: &ReadingsQuery) -> ClientResult<ReadingsResponse> {
+        self.get_query("1/Readings", query).await
+    }
+
+    /// Store a batch of readings, replacing any with the same source and
+    /// timestamp; readings that can't be stored are listed in the response.
+    pub async fn upload_readings(
+        &self,
+        batch: &ReadingBatch,
+    ) -> ClientResult<ReadingBatchResponse> {
+        self.send_json(Method::POST, "1/Readings/batch", batch).await
+    }
+
+    /// A numeric field of a source's readings, bucketed over `[from, to)`;
+    /// the server picks the window and bucket size when they're `None`.
+    pub async fn aggregate(
+        &self,
+        source_id: i32,
+        field: &str,
+        from: Option<NaiveDateTime>,
+        to: Option<NaiveDateTime>,
+        bucket_seconds: Option<i64>,
+    ) -> ClientResult<AggregateResponse> {
+        let mut query = vec![("field", field.to_string())];
+        query.extend(from.map(|from| ("from", timestamp(from))));
+        query.extend(to.map(|to| ("to", timestamp(to))));
+        query.extend(bucket_seconds.map(|seconds| ("bucket_seconds", seconds.to_string())));
+        self.get_query(&format!("1/DataSources/{}/Aggregate", source_id), &query).await
+    }
+
+    /// A site's battery state of charge over `[from, to]`.
+    pub async fn soc_history(
+        &self,
+        site_id: i32,
+        from: Option<NaiveDateTime>,
+        to: Option<NaiveDateTime>,
+    ) -> ClientResult<SocHistoryResponse> {
+        let mut query = Vec::new();
+        query.extend(from.map(|from| ("from", timestamp(from))));
+        query.extend(to.map(|to| ("to", timestamp(to))));
+        self.get_query(&format!("1/Sites/{}/SocHistory", site_id), &query).await
+    }
+
+    /// The highest reading id the server holds from the edge `origin`.
+    pub async fn sync_cursor(&self, origin: &str) -> ClientResult<i32> {
+        let cursor: SyncCursor = self.get(&format!("1/Sync/Origins/{}/Cursor", origin)).await?;
+        Ok(cursor.cursor)
+    }
+
+    /// Upload a batch of an edge's readings; readings the server already
+    /// holds are skipped.
+    pub async fn push_readings(&self, batch: &SyncBatch) -> ClientResult<SyncAck> {
+        self.send_json(Method::POST, "1/Sync/Readings", batch).await
+    }
+}
@@ -0,0 +1,132 @@
+//! Schedule library items, application rules, and the scheduler's checks and
+//! status.
+
+use chrono::NaiveDate;
+use neems_api::{
+    api::scheduler::{
+        SchedulerCheckRequest, SchedulerCheckResponse, SchedulerStatusResponse,
+        SocSimulationRequest,
+    },
+    models::{
+        ApplicationRule, CloneLibraryItemRequest, CreateApplicationRuleRequest,
+        CreateLibraryItemRequest, EffectiveScheduleResponse, ScheduleLibraryItem,
+        UpdateLibraryItemRequest,
+    },
+    soc_simulation::SocSimulation,
+};
+use reqwest::Method;
+
+use crate::{Client, ClientResult};
+
+impl Client {
+    /// A site's schedule library.
+    pub async fn schedule_library_items(
+        &self,
+        site_id: i32,
+    ) -> ClientResult<Vec<ScheduleLibraryItem>> {
+        self.get(&format!("1/Sites/{}/ScheduleLibraryItems", site_id)).await
+    }
+
+    pub async fn get_schedule_library_item(&self, id: i32) -> ClientResult<ScheduleLibraryItem> {
+        self.get(&format!("1/ScheduleLibraryItems/{}", id)).await
+    }
+
+    pub async fn create_schedule_library_item(
+        &self,
+        site_id: i32,
+        item: &CreateLibraryItemRequest,
+    ) -> ClientResult<ScheduleLibraryItem> {
+        self.send_json(Method::POST, &format!("1/Sites/{}/ScheduleLibraryItems", site_id), item)
+            .await
+    }
+
+    pub async fn update_schedule_library_item(
+        &self,
+        id: i32,
+        update: &UpdateLibraryItemRequest,
+    ) -> ClientResult<ScheduleLibraryItem> {
+        self.send_json(Method::PUT, &format!("1/ScheduleLibraryItems/{}", id), update)
+            .await
+    }
+
+    pub async fn clone_schedule_library_item(
+        &self,
+        id: i32,
+        request: &CloneLibraryItemRequest,
+    ) -> ClientResult<ScheduleLibraryItem> {
+        self.send_json(Method::POST, &format!("1/ScheduleLibraryItems/{}/Clone", id), request)
+            .await
+    }
+
+    pub async fn delete_schedule_library_item(&self, id: i32) -> ClientResult<()> {
+        self.delete(&format!("1/ScheduleLibraryItems/{}", id)).await
+    }
+
+    /// The rules applying a site's schedules to days.
+    pub async fn application_rules(&self, site_id: i32) -> ClientResult<Vec<ApplicationRule>> {
+        self.get(&format!("1/Sites/{}/ApplicationRules", site_id)).await
+    }
+
+    /// Apply library item `item_id` to the days `rule` picks.
+    pub async fn create_application_rule(
+        &self,
+        item_id: i32,
+        rule: &CreateApplicationRuleRequest,
+    ) -> ClientResult<ApplicationRule> {
+        self.send_json(
+            Method::POST,
+            &format!("1/ScheduleLibraryItems/{}/ApplicationRules", item_id),
+            rule,
+        )
+        .await
+    }
+
+    /// Remove a rule, recording `change_reason` in the site's activity log.
+    pub async fn delete_application_rule(
+        &self,
+        id: i32,
+        change_reason: Option<&str>,
+    ) -> ClientResult<()> {
+        let request = self.request(Method::DELETE, &format!("1/ApplicationRules/{}", id));
+        let query: Vec<_> =
+            change_reason.map(|reason| ("change_reason", reason)).into_iter().collect();
+        Self::send(request.query(&query)).await?;
+        Ok(())
+    }
+
+    /// The schedule that runs at a site on `date`, and the rule picking it.
+    pub async fn effective_schedule(
+        &self,
+        site_id: i32,
+        date: NaiveDate,
+    ) -> ClientResult<EffectiveScheduleResponse> {
+        let date = date.format("%Y-%m-%d").to_string();
+        self.get_query(&format!("1/Sites/{}/EffectiveSchedule", site_id), &[("date", date)])
+            .await
+    }
+
+    /// Conflicts in a site's plan over a range of days.
+    pub async fn check_schedule(
+        &self,
+        site_id: i32,
+        request: &SchedulerCheckRequest,
+    ) -> ClientResult<SchedulerCheckResponse> {
+        self.send_json(Method::POST, &format!("1/Sites/{}/scheduler/check", site_id), request)
+            .await
+    }
+
+    /// The state of charge a day's schedule would lead to.
+    pub async fn simulate_soc(
+        &self,
+        site_id: i32,
+        request: &SocSimulationRequest,
+    ) -> ClientResult<SocSimulation> {
+        self.send_json(Method::POST, &format!("1/Sites/{}/scheduler/simulate", site_id), request)
+            .await
+    }
+
+    /// The scheduler's last evaluation of a site.
+    pub async fn scheduler_status(&self, site_id: i32) -> ClientResult<SchedulerStatusResponse> {
+        self.get(&format!("1/Sites/{}/scheduler/status", site_id)).await
+    }
+}
@@ -0,0 +1,217 @@
+//! The client against a real neems-api, served over HTTP on a local port.
+
+use chrono::Utc;
+use neems_api::{
+    api::{
+        data::ReadingsQuery,
+        scheduler::SchedulerCheckRequest,
+        site::{CreateSiteRequest, SitePatch, UpdateSiteRequest},
+        v2::user::CreateUserRequest,
+    },
+    merge_patch::Patch,
+    models::{
+        CommandType, CreateApplicationRuleRequest, CreateCommandRequest, CreateLibraryItemRequest,
+        RuleType,
+    },
+    orm::testing::{fast_test_rocket, golden_fixtures},
+};
+use neems_client::{Client, ClientError};
+use reqwest::StatusCode;
+
+/// Serve a fresh neems-api and return its base URL.
+async fn serve() -> (String, rocket::Shutdown) {
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let rocket = fast_test_rocket();
+    let figment = rocket
+        .figment()
+        .clone()
+        .merge(("address", "127.0.0.1"))
+        .merge(("port", port))
+        .merge(("log_level", "off"))
+        .merge(("shutdown.ctrlc", false));
+    let rocket = rocket.configure(figment).ignite().await.expect("ignite");
+    let shutdown = rocket.shutdown();
+    tokio::spawn(rocket.launch());
+
+    let base_url = format!("http://127.0.0.1:{}", port);
+    for _ in 0..100 {
+        if reqwest::get(format!("{}/api/1/status", base_url)).await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    (base_url, shutdown)
+}
+
+#[tokio::test]
+async fn test_login_and_entity_crud() {
+    let (base_url, shutdown) = serve().await;
+    let company_id = golden_fixtures().company_id("Test Company 1");
+    let mut client = Client::new(format!("{}/", base_url)).unwrap();
+
+    // Nothing without logging in
+    let err = client.list_sites().await.unwrap_err();
+    assert_eq!(err.status(), Some(StatusCode::UNAUTHORIZED));
+
+    let me = client.login("superadmin@example.com", "admin").await.unwrap();
+    assert_eq!(me.email, "superadmin@example.com");
+    assert_eq!(client.hello().await.unwrap().user_id, me.user_id);
+
+    // A session can be handed to another client
+    let other = Client::new(&base_url).unwrap().with_session(client.session().unwrap());
+    assert!(other.list_companies().await.unwrap().iter().any(|c| c.id == company_id));
+
+    let site = client
+        .create_site(&CreateSiteRequest {
+            name: "Client Site".to_string(),
+            address: "1 Client Way".to_string(),
+            latitude: 40.0,
+            longitude: -75.0,
+            company_id,
+            ramp_duration_seconds: 0,
+        })
+        .await
+        .unwrap();
+    assert_eq!(client.get_site(site.id).await.unwrap().name, "Client Site");
+    assert!(client.list_sites().await.unwrap().iter().any(|s| s.id == site.id));
+
+    let update = UpdateSiteRequest {
+        name: Some("Renamed Client Site".to_string()),
+        ..serde_json::from_value(serde_json::json!({})).unwrap()
+    };
+    let updated = client.update_site(site.id, &update).await.unwrap();
+    assert_eq!(updated.name, "Renamed Client Site");
+    assert_eq!(updated.address, "1 Client Way");
+
+    // A patch can clear what PUT can only set
+    let update = UpdateSiteRequest {
+        power_kw: Some(500.0),
+        ..serde_json::from_value(serde_json::json!({})).unwrap()
+    };
+    assert_eq!(client.update_site(site.id, &update).await.unwrap().power_kw, Some(500.0));
+    let patch = SitePatch {
+        power_kw: Patch::Null,
+        ..Default::default()
+    };
+    let patched = client.patch_site(site.id, &patch).await.unwrap();
+    assert_eq!(patched.power_kw, None);
+    assert_eq!(patched.name, "Renamed Client Site");
+
+    let user = client
+        .create_user(&CreateUserRequest {
+            email: "client@test.com".to_string(),
+            password: "client password".to_string(),
+            company_id,
+            totp_secret: None,
+            role_names: vec!["staff".to_string()],
+        })
+        .await
+        .unwrap();
+    assert_eq!(client.get_user(user.id).await.unwrap().email, "client@test.com");
+    client.delete_user(user.id).await.unwrap();
+
+    client.delete_site(site.id).await.unwrap();
+    match client.get_site(site.id).await {
+        Err(ClientError::Status { status, .. }) => assert_eq!(status, StatusCode::NOT_FOUND),
+        other => panic!("expected 404, got {:?}", other.map(|s| s.id)),
+    }
+
+    assert!(!client.list_devices().await.unwrap().is_empty());
+
+    client.logout().await.unwrap();
+    assert!(client.session().is_none());
+    shutdown.notify();
+}
+
+#[tokio::test]
+async fn test_readings_and_scheduler() {
+    let (base_url, shutdown) = serve().await;
+    let site_id = golden_fixtures().site_id("Test Site 1");
+    let mut client = Client::new(&base_url).unwrap();
+    client.login("superadmin@example.com", "admin").await.unwrap();
+
+    let sources = client.data_sources().await.unwrap();
+    if let Some(source) = sources.first() {
+        let query = ReadingsQuery {
+            since: None,
+            until: None,
+            from_time: None,
+            to_time: None,
+            count: None,
+            latest: Some(5),
+            source_ids: None,
+        };
+        let readings = client.source_readings(source.id.unwrap(), &query).await.unwrap();
+        assert!(readings.readings.len() <= 5);
+    }
+    assert_eq!(client.soc_history(site_id, None, None).await.unwrap().site_id, site_id);
+
+    let item = client
+        .create_schedule_library_item(
+            site_id,
+            &CreateLibraryItemRequest {
+                name: "Client schedule".to_string(),
+                description: None,
+                commands: vec![CreateCommandRequest {
+                    execution_offset_seconds: 3600,
+                    command_type: CommandType::Charge,
+                    duration_seconds: None,
+                    target_soc_percent: Some(90),
+                    power_kw: None,
+                    device_id: None,
+                    bindings: None,
+                }],
+                parameters: Vec::new(),
+                change_reason: None,
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(item.commands.len(), 1);
+    assert!(
+        client
+            .schedule_library_items(site_id)
+            .await
+            .unwrap()
+            .iter()
+            .any(|i| i.id == item.id)
+    );
+
+    let rule = client
+        .create_application_rule(
+            item.id,
+            &CreateApplicationRuleRequest {
+                rule_type: RuleType::Default,
+                days_of_week: None,
+                specific_dates: None,
+                override_reason: None,
+                change_reason: None,
+                priority: None,
+            },
+        )
+        .await
+        .unwrap();
+    let today = Utc::now().date_naive();
+    let effective = client.effective_schedule(site_id, today).await.unwrap();
+    assert_eq!(effective.library_item.id, item.id);
+    assert_eq!(effective.rule.id, rule.id);
+
+    let check = client
+        .check_schedule(
+            site_id,
+            &SchedulerCheckRequest {
+                start_date: today,
+                end_date: today,
+                library_item_id: None,
+            },
+        )
+        .await
+        .unwrap();
+    assert!(check.conflicts.is_empty());
+    assert_eq!(client.scheduler_status(site_id).await.unwrap().site_id, site_id);
+
+    client.delete_application_rule(rule.id, Some("done")).await.unwrap();
+    assert!(client.application_rules(site_id).await.unwrap().is_empty());
+    client.delete_schedule_library_item(item.id).await.unwrap();
+    shutdown.notify();
+}
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Where an aggregation read its values from.
+ */
+export type AggregateBackend = "points" | "json" | "hourly_rollup" | "daily_rollup";
@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Summary of one field over one time bucket.
+ */
+export type AggregateBucket = { 
+/**
+ * Start of the bucket (UTC)
+ */
+start: string, count: bigint, min: number, max: number, avg: number, };
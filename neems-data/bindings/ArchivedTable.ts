@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * One table in an archive, and how many rows it holds.
+ */
+export type ArchivedTable = { table: string, rows: number, };
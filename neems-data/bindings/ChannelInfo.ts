@@ -0,0 +1,22 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ChannelKind } from "./ChannelKind";
+import type { Locale } from "./Locale";
+import type { NotificationSeverity } from "./NotificationSeverity";
+
+/**
+ * A channel as shown by the API: its settings with the destination
+ * redacted, and how far it has got.
+ */
+export type ChannelInfo = { id: number, company_id: number, name: string, kind: ChannelKind, 
+/**
+ * `***`, or the `secret://` reference
+ */
+destination: string, severities: Array<NotificationSeverity>, send_cleared: boolean, enabled: boolean, locale: Locale, created_at: string, updated_at: string, 
+/**
+ * Highest reading id checked for alarms
+ */
+last_reading_id: number, last_checked_at: string | null, 
+/**
+ * Why the last attempt failed, until one succeeds
+ */
+last_error: string | null, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Where a channel sends notifications.
+ */
+export type ChannelKind = "slack" | "teams" | "pagerduty";
@@ -0,0 +1,29 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ChannelKind } from "./ChannelKind";
+import type { Locale } from "./Locale";
+import type { NotificationSeverity } from "./NotificationSeverity";
+
+/**
+ * A channel's settings, as created or replaced through the API.
+ */
+export type ChannelSettings = { name: string, kind: ChannelKind, 
+/**
+ * The Slack or Teams webhook URL, or the PagerDuty integration's routing
+ * key, or a `secret://<name>` reference. Required when creating a
+ * channel; when replacing one, `null` or `""` keeps the stored one.
+ */
+destination: string | null, 
+/**
+ * Alarm severities sent to the channel
+ */
+severities: Array<NotificationSeverity>, 
+/**
+ * Whether cleared alarms are sent too (resolving PagerDuty incidents)
+ */
+send_cleared: boolean, enabled: boolean, 
+/**
+ * The language notifications are written in. When creating a channel,
+ * `null` means English, or the company's locale if neems-api has one;
+ * when replacing one, `null` keeps the stored one.
+ */
+locale: Locale | null, };
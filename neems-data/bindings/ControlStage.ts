@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A stage of the control path.
+ */
+export type ControlStage = "schedule_eval" | "interlock_check" | "dispatch_wait" | "modbus_write" | "ack" | "end_to_end";
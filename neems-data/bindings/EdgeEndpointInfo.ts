@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A site's edge endpoint as shown by the API, with the token redacted.
+ */
+export type EdgeEndpointInfo = { site_id: number, url: string, 
+/**
+ * `***` for a stored token, or the `secret://` reference
+ */
+token: string, updated_at: string, };
@@ -0,0 +1,15 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A site's edge endpoint, as set through the API.
+ */
+export type EdgeEndpointSettings = { 
+/**
+ * Base URL of the site's log listener, e.g. `https://site-12.example.com:9180`
+ */
+url: string, 
+/**
+ * The listener's `NEEMS_LOGS_TOKEN`, or a `secret://<name>` reference.
+ * When replacing an endpoint, `null` keeps the stored token.
+ */
+token: string | null, };
@@ -0,0 +1,24 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ForwarderKind } from "./ForwarderKind";
+
+/**
+ * A forwarder as shown by the API: its settings with the token redacted,
+ * and how far it has got.
+ */
+export type ForwarderInfo = { id: number, site_id: number, name: string, kind: ForwarderKind, url: string, organization: string | null, destination: string, 
+/**
+ * `***` for a stored token, or the `secret://` reference
+ */
+token: string | null, enabled: boolean, batch_size: number, created_at: string, updated_at: string, 
+/**
+ * Highest reading id delivered
+ */
+last_reading_id: number, last_forwarded_at: string | null, 
+/**
+ * Why the last attempt failed, until one succeeds
+ */
+last_error: string | null, 
+/**
+ * The site's readings still to be delivered
+ */
+pending_readings: bigint, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Where a forwarder sends readings.
+ */
+export type ForwarderKind = "influxdb" | "timescale";
@@ -0,0 +1,31 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ForwarderKind } from "./ForwarderKind";
+
+/**
+ * A forwarder's settings, as created or replaced through the API.
+ */
+export type ForwarderSettings = { name: string, kind: ForwarderKind, 
+/**
+ * InfluxDB base URL (`https://influx.example.com:8086`) or PostgreSQL
+ * connection URL without a password
+ * (`postgres://neems@historian.example.com/metrics?sslmode=require`)
+ */
+url: string, 
+/**
+ * InfluxDB organization
+ */
+organization: string | null, 
+/**
+ * InfluxDB bucket, or Timescale table (optionally `schema.table`)
+ */
+destination: string, 
+/**
+ * InfluxDB API token or Timescale password, or a `secret://<name>`
+ * reference. When replacing a forwarder, `null` keeps the stored token
+ * and `""` removes it.
+ */
+token: string | null, enabled: boolean, 
+/**
+ * Readings per write (default 500)
+ */
+batch_size: number | null, };
@@ -0,0 +1,14 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * One histogram bucket of a [`StageLatency`].
+ */
+export type LatencyBucket = { 
+/**
+ * Upper bound in milliseconds; `None` for the last, unbounded bucket
+ */
+le_ms: number | null, 
+/**
+ * Samples in this bucket (not cumulative)
+ */
+count: number, };
@@ -0,0 +1,24 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { StageLatency } from "./StageLatency";
+
+/**
+ * The control path's latency, as the collector last published it.
+ */
+export type LatencyReport = { budget_ms: number, 
+/**
+ * Commands that took longer than the budget to be acknowledged, or
+ * weren't acknowledged within it
+ */
+over_budget: number, 
+/**
+ * When a command last went over budget
+ */
+last_over_budget_at: string | null, 
+/**
+ * Whether the budget alarm is raised
+ */
+alarm_active: boolean, stages: Array<StageLatency>, 
+/**
+ * When the report was published
+ */
+updated_at: string, };
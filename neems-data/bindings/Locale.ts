@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A language the API and notifications can be read in.
+ */
+export type Locale = "en" | "es" | "fr";
@@ -0,0 +1,18 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * One logged event.
+ */
+export type LogEntry = { timestamp: string, 
+/**
+ * `error`, `warn`, `info`, `debug` or `trace`
+ */
+level: string, 
+/**
+ * Module that logged the event
+ */
+target: string, message: string, 
+/**
+ * The event's structured fields other than the message
+ */
+fields: Record<string, unknown>, };
@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { LogEntry } from "./LogEntry";
+
+/**
+ * Entries returned for a query, oldest first.
+ */
+export type LogPage = { entries: Array<LogEntry>, 
+/**
+ * Whether older matching entries were left out by the limit
+ */
+truncated: boolean, };
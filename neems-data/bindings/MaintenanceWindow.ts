@@ -0,0 +1,14 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A stored maintenance window.
+ */
+export type MaintenanceWindow = { id: number, site_id: number, 
+/**
+ * The device under maintenance; absent for the whole site
+ */
+device_id: number | null, starts_at: string, ends_at: string, reason: string, 
+/**
+ * Email of the user who opened the window
+ */
+created_by: string, created_at: string, };
@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A window to open, as requested through the API.
+ */
+export type NewMaintenanceWindow = { device_id: number | null, 
+/**
+ * Defaults to now
+ */
+starts_at: string | null, ends_at: string, reason: string, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * An alarm severity a channel can route.
+ */
+export type NotificationSeverity = "info" | "warning" | "critical" | "emergency";
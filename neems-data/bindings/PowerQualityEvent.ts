@@ -0,0 +1,19 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PowerQualityEventKind } from "./PowerQualityEventKind";
+
+/**
+ * A stored event, without its samples.
+ */
+export type PowerQualityEvent = { id: number, source_id: number, kind: PowerQualityEventKind, triggered_at: string, window_start: string, window_end: string, 
+/**
+ * Nominal voltage or frequency
+ */
+nominal_value: number, 
+/**
+ * Sag voltage, or allowed frequency deviation
+ */
+threshold: number, 
+/**
+ * Lowest voltage, or the frequency furthest from nominal, in the window
+ */
+extreme_value: number, sample_count: number, };
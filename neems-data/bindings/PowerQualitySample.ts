@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * One full-rate reading in an event's window.
+ */
+export type PowerQualitySample = { timestamp: string, voltage_v: number, current_a: number, grid_frequency_hz: number, power_kw: number, };
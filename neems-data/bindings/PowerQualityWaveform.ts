@@ -0,0 +1,20 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PowerQualityEventKind } from "./PowerQualityEventKind";
+import type { PowerQualitySample } from "./PowerQualitySample";
+
+/**
+ * A stored event with its samples.
+ */
+export type PowerQualityWaveform = { samples: Array<PowerQualitySample>, id: number, source_id: number, kind: PowerQualityEventKind, triggered_at: string, window_start: string, window_end: string, 
+/**
+ * Nominal voltage or frequency
+ */
+nominal_value: number, 
+/**
+ * Sag voltage, or allowed frequency deviation
+ */
+threshold: number, 
+/**
+ * Lowest voltage, or the frequency furthest from nominal, in the window
+ */
+extreme_value: number, sample_count: number, };
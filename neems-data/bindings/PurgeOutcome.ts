@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * What [`purge_site_history`] removed from the site database.
+ */
+export type PurgeOutcome = { readings_deleted: number, 
+/**
+ * Bytes returned to the filesystem; zero unless the database uses
+ * incremental auto-vacuum, though freed pages are reused either way
+ */
+reclaimed_bytes: number, };
@@ -1,3 +1,8 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export type Reading = { id: number | null, source_id: number, timestamp: string, data: string, quality_flags: number, };
+export type Reading = { id: number | null, source_id: number, timestamp: string, data: string, quality_flags: number, 
+/**
+ * Timestamp reported by the device, when known. Together with
+ * `source_id` this is the reading's idempotency key.
+ */
+device_timestamp: string | null, };
@@ -0,0 +1,23 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Tunables set at runtime; `None` leaves the configured value in place.
+ */
+export type RuntimeOverrides = { 
+/**
+ * `tracing` filter directives, e.g. `info,neems_data=debug`
+ */
+log_level: string | null, flush_interval_ms: number | null, batch_size: number | null, max_concurrent_collectors: number | null, 
+/**
+ * Replaces the configured per-test-type limits as a whole; an empty map
+ * removes them
+ */
+test_type_concurrency: { [key in string]?: number } | null, 
+/**
+ * When the overrides were last saved (ignored on input)
+ */
+updated_at: string | null, 
+/**
+ * Who last saved them (ignored on input)
+ */
+updated_by: string | null, };
@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * The outcome of running a source's collector once.
+ */
+export type SelfTestResult = { source_id: number, source_name: string, test_type: string | null, ok: boolean, 
+/**
+ * What the collector returned, exactly as it would be stored
+ */
+data: unknown, error: string | null, duration_ms: number, };
@@ -1,3 +1,13 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export type Source = { id: number | null, name: string, description: string | null, active: boolean, created_at: string, updated_at: string, interval_seconds: number, last_run: string | null, test_type: string | null, arguments: string | null, site_id: number | null, company_id: number | null, };
+export type Source = { id: number | null, name: string, description: string | null, active: boolean, created_at: string, updated_at: string, interval_seconds: number, last_run: string | null, test_type: string | null, arguments: string | null, site_id: number | null, company_id: number | null, 
+/**
+ * Scheduling priority; higher values are dispatched first when the
+ * reader loop is saturated (default 0)
+ */
+priority: number, 
+/**
+ * JSON array of numeric reading fields copied into `reading_points`;
+ * `None` uses the test type's defaults
+ */
+point_fields: string | null, };
@@ -0,0 +1,9 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ControlStage } from "./ControlStage";
+import type { LatencyBucket } from "./LatencyBucket";
+
+/**
+ * Latency of one stage since the collector started. The quantiles are
+ * bucket bounds, so they're accurate to a bucket.
+ */
+export type StageLatency = { stage: ControlStage, count: number, mean_ms: number | null, p50_ms: number | null, p95_ms: number | null, p99_ms: number | null, max_ms: number | null, buckets: Array<LatencyBucket>, };
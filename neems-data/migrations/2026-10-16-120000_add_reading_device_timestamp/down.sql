@@ -0,0 +1,2 @@
+DROP INDEX idx_readings_source_device_timestamp;
+ALTER TABLE readings DROP COLUMN device_timestamp;
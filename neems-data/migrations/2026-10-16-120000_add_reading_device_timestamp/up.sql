@@ -0,0 +1,9 @@
+-- Device-reported timestamp used as an idempotency key for ingestion.
+-- Gateways that retry a push after a network blip resend the same
+-- (source_id, device_timestamp) pair, which the unique index turns into an
+-- upsert instead of a duplicate row. NULLs are distinct in SQLite unique
+-- indexes, so readings without a device timestamp are never deduplicated.
+ALTER TABLE readings ADD COLUMN device_timestamp TIMESTAMP;
+
+CREATE UNIQUE INDEX idx_readings_source_device_timestamp
+    ON readings (source_id, device_timestamp);
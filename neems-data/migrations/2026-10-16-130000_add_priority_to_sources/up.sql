@@ -0,0 +1,3 @@
+-- Scheduling priority for the reader loop. Higher values are dispatched first
+-- when more sources are due than the loop can start in one pass.
+ALTER TABLE sources ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
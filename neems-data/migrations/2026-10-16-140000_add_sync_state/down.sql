@@ -0,0 +1,2 @@
+DROP TABLE sync_receipts;
+DROP TABLE sync_cursors;
@@ -0,0 +1,17 @@
+-- Edge side: how far each sync target has acknowledged this site's readings
+CREATE TABLE sync_cursors (
+    target TEXT PRIMARY KEY NOT NULL,
+    last_reading_id INTEGER NOT NULL DEFAULT 0,
+    last_synced_at TIMESTAMP,
+    last_error TEXT,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Central side: one row per reading received from an edge instance, so a
+-- resent batch is appended at most once
+CREATE TABLE sync_receipts (
+    origin TEXT NOT NULL,
+    origin_reading_id INTEGER NOT NULL,
+    received_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (origin, origin_reading_id)
+);
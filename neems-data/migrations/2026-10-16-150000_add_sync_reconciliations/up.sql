@@ -0,0 +1,16 @@
+-- Central side: latest comparison of an edge source's readings for one day
+-- against what the central server holds
+CREATE TABLE sync_reconciliations (
+    origin TEXT NOT NULL,
+    source_name TEXT NOT NULL,
+    day DATE NOT NULL,
+    edge_count INTEGER NOT NULL,
+    edge_checksum TEXT,
+    central_count INTEGER NOT NULL,
+    central_checksum TEXT,
+    matched BOOLEAN NOT NULL,
+    checked_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (origin, source_name, day)
+);
+
+CREATE INDEX idx_sync_reconciliations_unmatched ON sync_reconciliations (matched, day);
@@ -0,0 +1,2 @@
+DROP TABLE reading_points;
+ALTER TABLE sources DROP COLUMN point_fields;
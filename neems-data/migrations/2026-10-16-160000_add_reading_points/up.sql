@@ -0,0 +1,25 @@
+-- Numeric fields of a source's readings to copy into reading_points, as a JSON
+-- array of top-level field names. NULL uses the defaults for the test type.
+ALTER TABLE sources ADD COLUMN point_fields TEXT;
+
+-- Normalized numeric values extracted from readings, so aggregation queries
+-- can use an index instead of parsing every JSON blob
+CREATE TABLE reading_points (
+    reading_id INTEGER NOT NULL REFERENCES readings(id) ON DELETE CASCADE,
+    source_id INTEGER NOT NULL REFERENCES sources(id),
+    field TEXT NOT NULL,
+    ts TIMESTAMP NOT NULL,
+    value REAL NOT NULL,
+    PRIMARY KEY (reading_id, field)
+);
+
+CREATE INDEX idx_reading_points_series ON reading_points (source_id, field, ts);
+
+-- charging_state declares `level` by default; backfill existing readings
+INSERT INTO reading_points (reading_id, source_id, field, ts, value)
+SELECT r.id, r.source_id, 'level', r.timestamp, json_extract(r.data, '$.level')
+FROM readings r
+JOIN sources s ON s.id = r.source_id
+WHERE s.test_type = 'charging_state'
+  AND json_valid(r.data)
+  AND json_type(r.data, '$.level') IN ('integer', 'real');
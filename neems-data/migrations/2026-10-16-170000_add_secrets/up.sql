@@ -0,0 +1,10 @@
+-- Credentials referenced from source arguments as `secret://<name>`. Values
+-- are sealed with AES-256-GCM under the master key (NEEMS_SECRETS_KEY), with
+-- the name as associated data so a value can't be moved to another name.
+CREATE TABLE secrets (
+    name TEXT PRIMARY KEY NOT NULL,
+    nonce BLOB NOT NULL,
+    ciphertext BLOB NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
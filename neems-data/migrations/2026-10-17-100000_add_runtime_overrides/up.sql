@@ -0,0 +1,14 @@
+-- Runtime settings changed through the admin API while neems-data monitor is
+-- running. A single row; NULL columns leave the configured value in place.
+-- `test_type_concurrency` uses the NEEMS_DATA_TEST_TYPE_CONCURRENCY format
+-- (`ping=8,exec=2`).
+CREATE TABLE runtime_overrides (
+    id INTEGER PRIMARY KEY NOT NULL CHECK (id = 1),
+    log_level TEXT,
+    flush_interval_ms INTEGER,
+    batch_size INTEGER,
+    max_concurrent_collectors INTEGER,
+    test_type_concurrency TEXT,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_by TEXT
+);
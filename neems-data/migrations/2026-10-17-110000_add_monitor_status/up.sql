@@ -0,0 +1,17 @@
+-- What the running neems-data monitor reports about itself, for diagnostics
+-- in other processes (neems-api's /admin/status). A single row, replaced
+-- every few seconds while the monitor runs.
+CREATE TABLE monitor_status (
+    id INTEGER PRIMARY KEY NOT NULL CHECK (id = 1),
+    pid INTEGER NOT NULL,
+    version TEXT NOT NULL,
+    started_at TIMESTAMP NOT NULL,
+    last_tick_at TIMESTAMP,
+    active_sources INTEGER NOT NULL DEFAULT 0,
+    collectors_running INTEGER NOT NULL DEFAULT 0,
+    collectors_queued INTEGER NOT NULL DEFAULT 0,
+    writer_queue_depth INTEGER NOT NULL DEFAULT 0,
+    writer_high_water_mark INTEGER NOT NULL DEFAULT 0,
+    readings_dropped BIGINT NOT NULL DEFAULT 0,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
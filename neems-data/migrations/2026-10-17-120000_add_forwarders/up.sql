@@ -0,0 +1,20 @@
+-- Forwarders mirroring a site's readings to a customer's historian, managed
+-- through neems-api. `destination` is the InfluxDB bucket or the Timescale
+-- table; `token` is the InfluxDB API token or the Timescale password, or a
+-- `secret://<name>` reference. Each forwarder's position is kept in
+-- sync_cursors under the target `forwarder:<id>`.
+CREATE TABLE forwarders (
+    id INTEGER PRIMARY KEY NOT NULL,
+    site_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('influxdb', 'timescale')),
+    url TEXT NOT NULL,
+    organization TEXT,
+    destination TEXT NOT NULL,
+    token TEXT,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    batch_size INTEGER NOT NULL DEFAULT 500,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (site_id, name)
+);
@@ -0,0 +1,19 @@
+-- Channels notifying a company of its sources' alarms in Slack, Microsoft
+-- Teams or PagerDuty, managed through neems-api. `destination` is the Slack
+-- or Teams webhook URL or the PagerDuty routing key, or a `secret://<name>`
+-- reference; `severities` is a JSON array of the alarm severities routed to
+-- the channel. Each channel's position is kept in sync_cursors under the
+-- target `notification:<id>`.
+CREATE TABLE notification_channels (
+    id INTEGER PRIMARY KEY NOT NULL,
+    company_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('slack', 'teams', 'pagerduty')),
+    destination TEXT NOT NULL,
+    severities TEXT NOT NULL DEFAULT '[]',
+    send_cleared BOOLEAN NOT NULL DEFAULT TRUE,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (company_id, name)
+);
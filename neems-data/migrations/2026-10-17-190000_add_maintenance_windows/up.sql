@@ -0,0 +1,19 @@
+-- Periods when a site, or one of its devices, is under maintenance, managed
+-- through neems-api. While a site-wide window is open, alarm notifications
+-- for the site's sources are not sent; while any window is open, automated
+-- dispatch of the site (or that device) is blocked. `device_id` is a
+-- neems-api device, NULL for the whole site; `created_by` is the email of the
+-- user who opened the window.
+CREATE TABLE maintenance_windows (
+    id INTEGER PRIMARY KEY NOT NULL,
+    site_id INTEGER NOT NULL,
+    device_id INTEGER,
+    starts_at TIMESTAMP NOT NULL,
+    ends_at TIMESTAMP NOT NULL,
+    reason TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CHECK (ends_at > starts_at)
+);
+
+CREATE INDEX idx_maintenance_windows_site_ends ON maintenance_windows (site_id, ends_at);
@@ -0,0 +1,4 @@
+DROP TABLE config_applied;
+DROP TABLE config_agents;
+DROP TABLE config_rollouts;
+DROP TABLE config_bundles;
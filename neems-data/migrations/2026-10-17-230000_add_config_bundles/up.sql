@@ -0,0 +1,45 @@
+-- Central side: versioned configuration bundles for edge instances. Secret
+-- values in `contents` are sealed under the central master key.
+CREATE TABLE config_bundles (
+    version INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    description TEXT,
+    contents TEXT NOT NULL,
+    checksum TEXT NOT NULL,
+    created_by TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Central side: a bundle rolled out to edge instances stage by stage
+CREATE TABLE config_rollouts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    version INTEGER NOT NULL REFERENCES config_bundles(version),
+    stages TEXT NOT NULL,
+    current_stage INTEGER NOT NULL DEFAULT 0,
+    status TEXT NOT NULL DEFAULT 'in_progress',
+    created_by TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Central side: the bundle each edge instance should run and the one it
+-- last reported applying
+CREATE TABLE config_agents (
+    origin TEXT PRIMARY KEY NOT NULL,
+    target_version INTEGER REFERENCES config_bundles(version),
+    previous_version INTEGER REFERENCES config_bundles(version),
+    rollout_id INTEGER REFERENCES config_rollouts(id),
+    applied_version INTEGER,
+    last_error TEXT,
+    last_seen_at TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Edge side: every bundle applied here, latest last
+CREATE TABLE config_applied (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    version INTEGER NOT NULL,
+    checksum TEXT NOT NULL,
+    source_names TEXT NOT NULL,
+    secret_names TEXT NOT NULL,
+    applied_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,10 @@
+-- Where neems-api reaches each site's log listener (NEEMS_LOGS_LISTEN) to
+-- proxy recent logs to support staff. `token` is the listener's
+-- NEEMS_LOGS_TOKEN, or a `secret://<name>` reference.
+CREATE TABLE edge_endpoints (
+    site_id INTEGER PRIMARY KEY NOT NULL,
+    url TEXT NOT NULL,
+    token TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
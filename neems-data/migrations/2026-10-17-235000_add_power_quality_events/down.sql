@@ -0,0 +1 @@
+DROP TABLE power_quality_events;
@@ -0,0 +1,22 @@
+-- Power-quality events captured by the RTAC collector: when a trigger fires
+-- (voltage sag, frequency excursion), the full-rate readings from shortly
+-- before to shortly after it are kept here as one event. `samples` is the
+-- JSON array of those readings; `extreme_value` is the lowest voltage or the
+-- frequency furthest from nominal seen in the window.
+CREATE TABLE power_quality_events (
+    id INTEGER PRIMARY KEY NOT NULL,
+    source_id INTEGER NOT NULL REFERENCES sources(id),
+    kind TEXT NOT NULL CHECK (kind IN ('voltage_sag', 'frequency_excursion')),
+    triggered_at TIMESTAMP NOT NULL,
+    window_start TIMESTAMP NOT NULL,
+    window_end TIMESTAMP NOT NULL,
+    nominal_value DOUBLE NOT NULL,
+    threshold DOUBLE NOT NULL,
+    extreme_value DOUBLE NOT NULL,
+    sample_count INTEGER NOT NULL,
+    samples TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_power_quality_events_source_triggered
+    ON power_quality_events (source_id, triggered_at);
@@ -0,0 +1,2 @@
+-- The language a channel's notifications are written in
+ALTER TABLE notification_channels ADD COLUMN locale TEXT NOT NULL DEFAULT 'en';
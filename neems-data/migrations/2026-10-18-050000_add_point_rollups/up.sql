@@ -0,0 +1,23 @@
+-- Hourly and daily summaries of reading_points, kept up to date as points are
+-- written, so long-range aggregates don't have to scan every point
+CREATE TABLE point_rollups (
+    source_id INTEGER NOT NULL REFERENCES sources(id),
+    field TEXT NOT NULL,
+    -- Bucket width in seconds: 3600 or 86400
+    resolution INTEGER NOT NULL,
+    -- Start of the bucket (UTC), aligned to the Unix epoch
+    bucket TIMESTAMP NOT NULL,
+    count INTEGER NOT NULL,
+    sum REAL NOT NULL,
+    min REAL NOT NULL,
+    max REAL NOT NULL,
+    PRIMARY KEY (source_id, field, resolution, bucket)
+);
+
+-- Summarize the points already stored
+INSERT INTO point_rollups (source_id, field, resolution, bucket, count, sum, min, max)
+SELECT source_id, field, r.resolution,
+       datetime(CAST(strftime('%s', ts) AS INTEGER) / r.resolution * r.resolution, 'unixepoch'),
+       COUNT(*), SUM(value), MIN(value), MAX(value)
+FROM reading_points, (SELECT 3600 AS resolution UNION ALL SELECT 86400) r
+GROUP BY source_id, field, r.resolution, 4;
@@ -0,0 +1,3 @@
+DROP INDEX idx_sources_company;
+DROP INDEX idx_sources_site;
+CREATE INDEX idx_readings_source_recent ON readings (source_id, timestamp DESC);
@@ -0,0 +1,13 @@
+-- The readings queries rely on these. The first migration created them, but
+-- databases restored from old backups or built by hand may lack them
+CREATE INDEX IF NOT EXISTS idx_readings_source_time ON readings (source_id, timestamp);
+CREATE INDEX IF NOT EXISTS idx_readings_timestamp ON readings (timestamp);
+
+-- Same columns as idx_readings_source_time, which SQLite scans in either
+-- direction, so this one only slowed down every write
+DROP INDEX IF EXISTS idx_readings_source_recent;
+
+-- Listing, archiving and syncing a site's or company's sources scanned the
+-- whole table
+CREATE INDEX IF NOT EXISTS idx_sources_site ON sources (site_id);
+CREATE INDEX IF NOT EXISTS idx_sources_company ON sources (company_id);
@@ -0,0 +1,9 @@
+-- Latency of each site's RTAC control path, as its collector last published
+-- it: per-stage histograms from the evaluation that issues a command to the
+-- RTAC acknowledging it, and how often that went over budget. `report` is
+-- the JSON of neems_data::rtac::latency::LatencyReport.
+CREATE TABLE control_latency (
+    site_id INTEGER PRIMARY KEY NOT NULL,
+    report TEXT NOT NULL,
+    updated_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,144 @@
+//! Site archives: a decommissioned site's history, copied out to a standalone
+//! SQLite file.
+//!
+//! The archive file is attached to a connection as the `archive` schema and
+//! each table's rows for the site are copied into a table of the same name,
+//! so the archive can be opened with any SQLite client and queried like the
+//! live databases. neems-api copies the site's records from its own database
+//! into the same file (see [`copy_site_rows`]), then uploads it.
+//!
+//! Archives are never encrypted, even with SQLCipher: they are meant to be
+//! portable, and cold storage brings its own encryption.
+
+use std::path::Path;
+
+use diesel::{
+    connection::SimpleConnection,
+    prelude::*,
+    sql_types::{Integer, Text},
+    sqlite::SqliteConnection,
+};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{DataResult, encryption, maintenance::run_maintenance};
+
+/// The schema name the archive file is attached as.
+pub const ARCHIVE_SCHEMA: &str = "archive";
+
+/// Rows of a site's sources; `?1` is the site id.
+const SITE_SOURCES: &str = "source_id IN (SELECT id FROM main.sources WHERE site_id = ?1)";
+
+/// The site database's tables archived with a site, and which rows belong to
+/// it; `?1` is the site id.
+pub const SITE_HISTORY_TABLES: &[(&str, &str)] = &[
+    ("sources", "site_id = ?1"),
+    ("readings", SITE_SOURCES),
+    ("reading_points", SITE_SOURCES),
+    ("point_rollups", SITE_SOURCES),
+    ("power_quality_events", SITE_SOURCES),
+    ("maintenance_windows", "site_id = ?1"),
+];
+
+/// One table in an archive, and how many rows it holds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ArchivedTable {
+    pub table: String,
+    pub rows: usize,
+}
+
+/// What [`purge_site_history`] removed from the site database.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PurgeOutcome {
+    pub readings_deleted: usize,
+    /// Bytes returned to the filesystem; zero unless the database uses
+    /// incremental auto-vacuum, though freed pages are reused either way
+    #[ts(type = "number")]
+    pub reclaimed_bytes: i64,
+}
+
+/// Attach the archive file at `path` (created if missing), run `f`, and
+/// detach it again whether or not `f` succeeded.
+pub fn with_archive<T>(
+    conn: &mut SqliteConnection,
+    path: &Path,
+    f: impl FnOnce(&mut SqliteConnection) -> DataResult<T>,
+) -> DataResult<T> {
+    // SQLCipher would otherwise encrypt the archive with the main key
+    let attach = if encryption::ENABLED {
+        "ATTACH DATABASE ? AS archive KEY ''"
+    } else {
+        "ATTACH DATABASE ? AS archive"
+    };
+    diesel::sql_query(attach)
+        .bind::<Text, _>(path.to_string_lossy().as_ref())
+        .execute(conn)?;
+    let result = f(conn);
+    conn.batch_execute("DETACH DATABASE archive")?;
+    result
+}
+
+/// Copy each table's rows for `site_id` into a new table of the same name in
+/// the attached archive. Each entry is a table and a `WHERE` condition in
+/// which `?1` is the site id.
+pub fn copy_site_rows(
+    conn: &mut SqliteConnection,
+    site_id: i32,
+    tables: &[(&str, &str)],
+) -> DataResult<Vec<ArchivedTable>> {
+    let mut archived = Vec::with_capacity(tables.len());
+    for (table, condition) in tables {
+        conn.batch_execute(&format!(
+            "CREATE TABLE {ARCHIVE_SCHEMA}.{table} AS SELECT * FROM main.{table} WHERE 0"
+        ))?;
+        let rows = diesel::sql_query(format!(
+            "INSERT INTO {ARCHIVE_SCHEMA}.{table} SELECT * FROM main.{table} WHERE {condition}"
+        ))
+        .bind::<Integer, _>(site_id)
+        .execute(conn)?;
+        archived.push(ArchivedTable { table: table.to_string(), rows });
+    }
+    Ok(archived)
+}
+
+/// Copy the site's sources, readings, rollups, power quality events and
+/// maintenance windows into the archive file at `path`.
+pub fn archive_site_history(
+    conn: &mut SqliteConnection,
+    site_id: i32,
+    path: &Path,
+) -> DataResult<Vec<ArchivedTable>> {
+    with_archive(conn, path, |conn| copy_site_rows(conn, site_id, SITE_HISTORY_TABLES))
+}
+
+/// Delete the site's readings (with their points and rollups) and power
+/// quality events and deactivate its sources, so nothing is collected for it
+/// again, then return what space SQLite can to the filesystem. Only call this
+/// once the history is safely archived.
+///
+/// Sources and maintenance windows are kept, as they are small and the site's
+/// pages still show them.
+pub fn purge_site_history(conn: &mut SqliteConnection, site_id: i32) -> DataResult<PurgeOutcome> {
+    let readings_deleted = conn.transaction(|conn| {
+        for table in ["reading_points", "point_rollups", "power_quality_events"] {
+            diesel::sql_query(format!("DELETE FROM {table} WHERE {SITE_SOURCES}"))
+                .bind::<Integer, _>(site_id)
+                .execute(conn)?;
+        }
+        let readings_deleted =
+            diesel::sql_query(format!("DELETE FROM readings WHERE {SITE_SOURCES}"))
+                .bind::<Integer, _>(site_id)
+                .execute(conn)?;
+        diesel::sql_query("UPDATE sources SET active = 0 WHERE site_id = ?1")
+            .bind::<Integer, _>(site_id)
+            .execute(conn)?;
+        Ok::<_, diesel::result::Error>(readings_deleted)
+    })?;
+    let report = run_maintenance(conn, false)?;
+    Ok(PurgeOutcome {
+        readings_deleted,
+        reclaimed_bytes: report.reclaimed_bytes(),
+    })
+}
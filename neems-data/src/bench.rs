@@ -0,0 +1,362 @@
+//! Load test for the readings pipeline (`neems-data bench`).
+//!
+//! Synthetic sources push readings through the same bounded queue and batched
+//! SQLite writes that `monitor` uses, against a scratch database, and the run
+//! reports what the pipeline sustained: write throughput, how long each batch
+//! took to commit, and how deep the queue got. Raise the source count or rate
+//! until readings are dropped (or, with critical readings, until sources are
+//! held up by backpressure) to find a device's ceiling before deploying it.
+//!
+//! Sources are created as `simulator` sources, so each reading also writes the
+//! simulator's three default point fields, as real simulator readings would.
+
+use std::{
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use serde_json::{Map, Value as JsonValue};
+use tokio::task;
+
+use crate::{
+    DataAggregator, DataResult, NewReading, NewSource, PendingReading, create_source, encryption,
+    insert_readings_batch,
+    writer::{WriterConfig, reading_queue},
+};
+
+/// Field names given to each reading; any beyond these are `field_<n>`.
+const NAMED_FIELDS: [&str; 3] = ["soc_percent", "battery_power_kw", "load_kw"];
+
+/// How often the queue depth is sampled.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What to run.
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    /// Number of synthetic sources
+    pub sources: usize,
+    /// Readings per second from each source
+    pub rate_hz: f64,
+    /// How long the sources produce readings; queued readings are still
+    /// written afterwards
+    pub duration: Duration,
+    /// Numeric fields in each reading
+    pub fields: usize,
+    /// Mark readings critical, so a full queue holds sources up instead of
+    /// dropping readings
+    pub critical: bool,
+    pub writer: WriterConfig,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            sources: 50,
+            rate_hz: 1.0,
+            duration: Duration::from_secs(30),
+            fields: 8,
+            critical: false,
+            writer: WriterConfig::default(),
+        }
+    }
+}
+
+/// Distribution of batch write times.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl LatencySummary {
+    pub fn from_samples(mut samples: Vec<Duration>) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort();
+        let percentile = |p: f64| {
+            let rank = ((p / 100.0) * samples.len() as f64).ceil() as usize;
+            samples[rank.clamp(1, samples.len()) - 1]
+        };
+        Self {
+            count: samples.len(),
+            mean: samples.iter().sum::<Duration>() / samples.len() as u32,
+            p50: percentile(50.0),
+            p95: percentile(95.0),
+            p99: percentile(99.0),
+            max: samples[samples.len() - 1],
+        }
+    }
+}
+
+/// What the pipeline sustained.
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    /// Readings the sources produced
+    pub offered: u64,
+    /// Readings committed to the database
+    pub written: u64,
+    /// Readings dropped because the queue was full
+    pub dropped: u64,
+    /// Readings in batches that failed to write
+    pub failed: u64,
+    /// Time the sources ran for
+    pub duration: Duration,
+    /// Time from the first reading until the queue was drained
+    pub elapsed: Duration,
+    /// Time to open a connection and commit each batch
+    pub batch_latency: LatencySummary,
+    pub mean_batch_size: f64,
+    /// Mean queue depth while the sources were running
+    pub mean_queue_depth: f64,
+    pub queue_high_water_mark: usize,
+    pub queue_capacity: usize,
+    /// Times a critical reading had to wait for space
+    pub backpressure_waits: u64,
+}
+
+impl BenchReport {
+    /// Readings per second the sources produced.
+    pub fn offered_rate(&self) -> f64 {
+        self.offered as f64 / self.duration.as_secs_f64()
+    }
+
+    /// Readings per second written, including draining the queue at the end.
+    pub fn throughput(&self) -> f64 {
+        self.written as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// A synthetic reading: the sequence number shifted per field, so values vary
+/// between readings and fields.
+fn synthetic_data(fields: usize, sequence: u64) -> JsonValue {
+    let mut data = Map::new();
+    for i in 0..fields {
+        let name = match NAMED_FIELDS.get(i) {
+            Some(name) => name.to_string(),
+            None => format!("field_{}", i),
+        };
+        data.insert(name, JsonValue::from(((sequence + i as u64 * 7) % 1000) as f64 / 10.0));
+    }
+    JsonValue::Object(data)
+}
+
+/// Create the bench sources in a new database at `database_path`.
+fn create_bench_sources(database_path: &Path, count: usize) -> DataResult<Vec<(i32, String)>> {
+    let aggregator = DataAggregator::new(Some(&database_path.to_string_lossy()));
+    let mut connection = aggregator.establish_connection()?;
+    (0..count)
+        .map(|i| {
+            let name = format!("bench_{:04}", i);
+            let source = create_source(
+                &mut connection,
+                NewSource {
+                    name: name.clone(),
+                    description: Some("neems-data bench".to_string()),
+                    active: Some(false),
+                    interval_seconds: Some(1),
+                    test_type: Some("simulator".to_string()),
+                    arguments: None,
+                    site_id: None,
+                    company_id: None,
+                    priority: None,
+                    point_fields: None,
+                },
+            )?;
+            Ok((source.id.ok_or("created source has no id")?, name))
+        })
+        .collect()
+}
+
+/// Run the load test against a new database at `database_path`.
+pub async fn run_bench(database_path: &Path, config: &BenchConfig) -> DataResult<BenchReport> {
+    if database_path.exists() {
+        return Err(format!("{} already exists", database_path.display()).into());
+    }
+    if config.rate_hz <= 0.0 || !config.rate_hz.is_finite() {
+        return Err("rate must be a positive number".into());
+    }
+    let sources = create_bench_sources(database_path, config.sources)?;
+    let database_url = format!("sqlite://{}", database_path.display());
+
+    let (tx, rx) = reading_queue(config.writer.queue_capacity);
+    let offered = Arc::new(AtomicU64::new(0));
+    let started = Instant::now();
+    let deadline = tokio::time::Instant::from_std(started + config.duration);
+    let period = Duration::from_secs_f64(1.0 / config.rate_hz);
+
+    let mut producers = Vec::with_capacity(sources.len());
+    for (source_id, source_name) in sources {
+        let tx = tx.clone();
+        let offered = offered.clone();
+        let (fields, critical) = (config.fields, config.critical);
+        producers.push(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            let mut sequence = 0;
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = tokio::time::sleep_until(deadline) => break,
+                }
+                let data = synthetic_data(fields, sequence);
+                let reading = PendingReading {
+                    reading: NewReading::with_json_data(source_id, &data)
+                        .expect("synthetic data serializes"),
+                    source_name: source_name.clone(),
+                    critical,
+                };
+                sequence += 1;
+                offered.fetch_add(1, Ordering::Relaxed);
+                if tx.send(reading).await.is_err() {
+                    break;
+                }
+            }
+        }));
+    }
+
+    // Samples queue depth until the sources stop; holds the last sender.
+    let sampler = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+        let mut samples = Vec::new();
+        while tokio::time::Instant::now() < deadline {
+            interval.tick().await;
+            samples.push(tx.metrics().queue_depth);
+        }
+        samples
+    });
+
+    // The writer loop from `monitor`, timing each batch.
+    let writer = config.writer.clone();
+    let mut flush = tokio::time::interval(writer.flush_interval);
+    let mut latencies = Vec::new();
+    let (mut written, mut failed) = (0u64, 0u64);
+    loop {
+        tokio::select! {
+            _ = flush.tick() => {}
+            _ = rx.wait_for_batch(writer.batch_size) => {}
+        }
+        let batch = rx.drain(writer.batch_size);
+        if batch.is_empty() {
+            if rx.is_finished() {
+                break;
+            }
+            continue;
+        }
+
+        let count = batch.len() as u64;
+        let readings: Vec<NewReading> = batch.into_iter().map(|pr| pr.reading).collect();
+        let database_url = database_url.clone();
+        let result = task::spawn_blocking(move || -> DataResult<Duration> {
+            let start = Instant::now();
+            let mut connection = encryption::establish(&database_url)?;
+            insert_readings_batch(&mut connection, readings)?;
+            Ok(start.elapsed())
+        })
+        .await?;
+        match result {
+            Ok(latency) => {
+                latencies.push(latency);
+                written += count;
+            }
+            Err(e) => {
+                eprintln!("Error writing batch: {}", e);
+                failed += count;
+            }
+        }
+    }
+    let elapsed = started.elapsed();
+
+    for producer in producers {
+        producer.await?;
+    }
+    let depth_samples = sampler.await?;
+    let metrics = rx.metrics();
+    let batches = latencies.len();
+
+    Ok(BenchReport {
+        offered: offered.load(Ordering::Relaxed),
+        written,
+        dropped: metrics.dropped,
+        failed,
+        duration: config.duration,
+        elapsed,
+        batch_latency: LatencySummary::from_samples(latencies),
+        mean_batch_size: if batches == 0 {
+            0.0
+        } else {
+            written as f64 / batches as f64
+        },
+        mean_queue_depth: if depth_samples.is_empty() {
+            0.0
+        } else {
+            depth_samples.iter().sum::<usize>() as f64 / depth_samples.len() as f64
+        },
+        queue_high_water_mark: metrics.high_water_mark,
+        queue_capacity: config.writer.queue_capacity,
+        backpressure_waits: metrics.backpressure_waits,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::prelude::*;
+
+    use super::*;
+    use crate::schema::{reading_points, readings};
+
+    #[test]
+    fn test_latency_summary_percentiles() {
+        let samples = (1..=100).rev().map(Duration::from_millis).collect();
+        let summary = LatencySummary::from_samples(samples);
+        assert_eq!(summary.count, 100);
+        assert_eq!(summary.p50, Duration::from_millis(50));
+        assert_eq!(summary.p95, Duration::from_millis(95));
+        assert_eq!(summary.p99, Duration::from_millis(99));
+        assert_eq!(summary.max, Duration::from_millis(100));
+        assert_eq!(summary.mean, Duration::from_micros(50_500));
+
+        assert_eq!(LatencySummary::from_samples(Vec::new()), LatencySummary::default());
+    }
+
+    #[tokio::test]
+    async fn test_bench_accounts_for_every_reading() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bench.sqlite");
+        let config = BenchConfig {
+            sources: 4,
+            rate_hz: 50.0,
+            duration: Duration::from_millis(500),
+            fields: 5,
+            critical: false,
+            writer: WriterConfig {
+                queue_capacity: 1000,
+                batch_size: 20,
+                flush_interval: Duration::from_millis(50),
+            },
+        };
+
+        let report = run_bench(&path, &config).await.unwrap();
+        assert!(report.offered > 0);
+        assert_eq!(report.written + report.dropped + report.failed, report.offered);
+        assert_eq!(report.dropped, 0);
+        assert!(report.batch_latency.count > 0);
+        assert!(report.queue_high_water_mark <= 1000);
+
+        let mut conn = encryption::establish(&format!("sqlite://{}", path.display())).unwrap();
+        let stored: i64 = readings::table.count().get_result(&mut conn).unwrap();
+        assert_eq!(stored as u64, report.written);
+        // The three simulator point fields are stored for every reading.
+        let points: i64 = reading_points::table.count().get_result(&mut conn).unwrap();
+        assert_eq!(points as u64, report.written * 3);
+
+        assert!(run_bench(&path, &config).await.is_err(), "refuses an existing database");
+    }
+}
@@ -0,0 +1,151 @@
+//! Clock skew detection.
+//!
+//! The reader loop schedules on the monotonic clock, so NTP corrections don't
+//! cause burst polling or stalls. Wall-clock time is still what gets recorded,
+//! which makes two kinds of skew worth reporting:
+//!
+//! - **Device skew**: a collector returned a timestamp from the device it
+//!   polled (`device_timestamp`, or `timestamp_utc` for collectors that pass
+//!   through the device's own time) that differs from the host clock by more
+//!   than the threshold. The reader stores a warning reading flagged with
+//!   [`QUALITY_CLOCK_SKEW`] alongside the data.
+//! - **Host clock jumps**: the host's wall clock moved differently from the
+//!   monotonic clock between two checks, i.e. it was stepped. See
+//!   [`WallClockMonitor`].
+
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde_json::{Value as JsonValue, json};
+
+/// Quality flag set on readings that report clock skew.
+pub const QUALITY_CLOCK_SKEW: i32 = 1;
+
+/// A device clock that disagrees with the host clock.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClockSkew {
+    pub device_time: DateTime<Utc>,
+    pub host_time: DateTime<Utc>,
+}
+
+impl ClockSkew {
+    /// Device time minus host time; positive when the device is ahead.
+    pub fn skew_seconds(&self) -> f64 {
+        (self.device_time - self.host_time).num_milliseconds() as f64 / 1000.0
+    }
+
+    /// Render the warning stored as a reading for the skewed source.
+    pub fn to_warning_json(&self, source_id: i32) -> JsonValue {
+        json!({
+            "source_id": source_id,
+            "warning": "clock_skew",
+            "skew_seconds": self.skew_seconds(),
+            "device_time": self.device_time.to_rfc3339(),
+            "host_time": self.host_time.to_rfc3339(),
+            "timestamp_utc": self.host_time.to_rfc3339()
+        })
+    }
+}
+
+/// The device-reported time in a collector's output, if any.
+pub fn reported_time(data: &JsonValue) -> Option<DateTime<Utc>> {
+    ["device_timestamp", "timestamp_utc"]
+        .iter()
+        .filter_map(|key| data.get(*key)?.as_str())
+        .find_map(|raw| DateTime::parse_from_rfc3339(raw).ok())
+        .map(|time| time.with_timezone(&Utc))
+}
+
+/// Compare the device time in `data` with `host_time`, returning the skew if
+/// it exceeds `threshold`.
+pub fn detect_skew(
+    data: &JsonValue,
+    host_time: DateTime<Utc>,
+    threshold: Duration,
+) -> Option<ClockSkew> {
+    let device_time = reported_time(data)?;
+    let skew = (device_time - host_time).abs().to_std().ok()?;
+    (skew > threshold).then_some(ClockSkew { device_time, host_time })
+}
+
+/// Detects steps in the host's wall clock by comparing it with the monotonic
+/// clock.
+pub struct WallClockMonitor {
+    threshold: Duration,
+    last: (Instant, DateTime<Utc>),
+}
+
+impl WallClockMonitor {
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            last: (Instant::now(), Utc::now()),
+        }
+    }
+
+    /// Record a check at the given times and return how far the wall clock
+    /// jumped (in seconds, negative for backwards) since the previous check,
+    /// if that exceeds the threshold.
+    pub fn check_at(&mut self, monotonic: Instant, wall: DateTime<Utc>) -> Option<f64> {
+        let (last_monotonic, last_wall) = std::mem::replace(&mut self.last, (monotonic, wall));
+        let monotonic_elapsed = monotonic.saturating_duration_since(last_monotonic);
+        let wall_elapsed_ms = (wall - last_wall).num_milliseconds();
+        let jump_ms = wall_elapsed_ms - monotonic_elapsed.as_millis() as i64;
+
+        (jump_ms.unsigned_abs() > self.threshold.as_millis() as u64)
+            .then_some(jump_ms as f64 / 1000.0)
+    }
+
+    /// [`check_at`](Self::check_at) with the current time.
+    pub fn check(&mut self) -> Option<f64> {
+        self.check_at(Instant::now(), Utc::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host_time() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2025-08-04T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_detect_skew() {
+        let threshold = Duration::from_secs(30);
+
+        let ahead = json!({"timestamp_utc": "2025-08-04T12:02:00+00:00"});
+        let skew = detect_skew(&ahead, host_time(), threshold).unwrap();
+        assert_eq!(skew.skew_seconds(), 120.0);
+        assert_eq!(skew.to_warning_json(7)["warning"], "clock_skew");
+
+        // device_timestamp takes precedence over timestamp_utc
+        let behind = json!({
+            "device_timestamp": "2025-08-04T11:59:00Z",
+            "timestamp_utc": "2025-08-04T12:00:00Z"
+        });
+        assert_eq!(detect_skew(&behind, host_time(), threshold).unwrap().skew_seconds(), -60.0);
+
+        let close = json!({"timestamp_utc": "2025-08-04T12:00:10Z"});
+        assert!(detect_skew(&close, host_time(), threshold).is_none());
+        assert!(detect_skew(&json!({"value": 1}), host_time(), threshold).is_none());
+    }
+
+    #[test]
+    fn test_wall_clock_jump() {
+        let mut monitor = WallClockMonitor::new(Duration::from_secs(5));
+        let start = Instant::now();
+        monitor.last = (start, host_time());
+
+        // Both clocks advanced 10s: no jump
+        let t1 = start + Duration::from_secs(10);
+        assert_eq!(monitor.check_at(t1, host_time() + chrono::Duration::seconds(10)), None);
+
+        // Wall clock stepped back an hour while 1s passed
+        let t2 = t1 + Duration::from_secs(1);
+        let jump = monitor.check_at(t2, host_time() - chrono::Duration::seconds(3589));
+        assert_eq!(jump, Some(-3600.0));
+    }
+}
@@ -1,10 +1,51 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 
 use chrono::{DateTime, Datelike, Timelike, Utc, Weekday};
+use serde::{Deserialize, Serialize};
 use serde_json::{Value as JsonValue, json};
+use ts_rs::TS;
+
+use self::arguments::{ArgumentErrors, ArgumentSchema};
+use crate::{
+    models::Source,
+    secrets::{Redactor, SecretStore},
+};
+
+pub mod arguments;
+pub mod carbon_intensity;
+pub mod exec;
+pub mod replay;
+pub mod simulator;
+pub mod weather;
 
 pub mod data_sources {
     use super::*;
+    use crate::collectors::arguments::{Argument, ArgumentSchema};
+
+    pub const PING_ARGUMENTS: ArgumentSchema = ArgumentSchema::new(
+        "Ping a host and report round-trip statistics",
+        &[Argument::string("target", "Host name or IP address to ping").default("127.0.0.1")],
+    );
+
+    pub const CHARGING_STATE_ARGUMENTS: ArgumentSchema = ArgumentSchema::new(
+        "Simulated battery charging state and level",
+        &[Argument::string("battery_id", "Battery to report on").default("default")],
+    );
+
+    pub const DISK_SPACE_ARGUMENTS: ArgumentSchema =
+        ArgumentSchema::new("Space on the root and /dev mounted drives", &[]);
+
+    pub const CPU_LOAD_ARGUMENTS: ArgumentSchema =
+        ArgumentSchema::new("Host CPU utilisation and load averages", &[]);
+
+    pub const MEMORY_ARGUMENTS: ArgumentSchema =
+        ArgumentSchema::new("Host RAM and swap usage", &[]);
+
+    pub const THERMAL_ARGUMENTS: ArgumentSchema =
+        ArgumentSchema::new("Host thermal zone and sensor temperatures", &[]);
 
     /// Ping localhost several times and get statistics using ping's built-in
     /// capabilities
@@ -176,6 +217,100 @@ pub mod data_sources {
         }))
     }
 
+    /// Report CPU utilisation and load averages for the host.
+    ///
+    /// CPU usage is computed by sysinfo as the delta between two samples, so
+    /// this sleeps for the minimum update interval between refreshes.
+    pub async fn cpu_load(
+        source_id: i32,
+    ) -> Result<JsonValue, Box<dyn std::error::Error + Send + Sync>> {
+        let mut system = sysinfo::System::new();
+        system.refresh_cpu_usage();
+        tokio::time::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL).await;
+        system.refresh_cpu_usage();
+
+        let per_cpu: Vec<f32> = system.cpus().iter().map(|cpu| cpu.cpu_usage()).collect();
+        let load = sysinfo::System::load_average();
+
+        Ok(json!({
+            "source_id": source_id,
+            "cpu_count": per_cpu.len(),
+            "usage_percent": system.global_cpu_usage(),
+            "per_cpu_usage_percent": per_cpu,
+            "load_average_1m": load.one,
+            "load_average_5m": load.five,
+            "load_average_15m": load.fifteen,
+            "timestamp_utc": Utc::now().to_rfc3339()
+        }))
+    }
+
+    /// Report RAM and swap usage for the host.
+    pub async fn memory(
+        source_id: i32,
+    ) -> Result<JsonValue, Box<dyn std::error::Error + Send + Sync>> {
+        let mut system = sysinfo::System::new();
+        system.refresh_memory();
+
+        let total_bytes = system.total_memory();
+        let used_bytes = system.used_memory();
+        let swap_total_bytes = system.total_swap();
+        let swap_used_bytes = system.used_swap();
+        let percent = |used: u64, total: u64| {
+            if total > 0 {
+                (used as f64 / total as f64) * 100.0
+            } else {
+                0.0
+            }
+        };
+
+        Ok(json!({
+            "source_id": source_id,
+            "total_bytes": total_bytes,
+            "used_bytes": used_bytes,
+            "available_bytes": system.available_memory(),
+            "used_percent": percent(used_bytes, total_bytes),
+            "swap_total_bytes": swap_total_bytes,
+            "swap_used_bytes": swap_used_bytes,
+            "swap_used_percent": percent(swap_used_bytes, swap_total_bytes),
+            "timestamp_utc": Utc::now().to_rfc3339()
+        }))
+    }
+
+    /// Report temperatures from the host's thermal zones and hardware
+    /// sensors. Hosts without sensors (e.g. most VMs) report an empty list
+    /// rather than an error so the source doesn't flap.
+    pub async fn thermal(
+        source_id: i32,
+    ) -> Result<JsonValue, Box<dyn std::error::Error + Send + Sync>> {
+        let components = sysinfo::Components::new_with_refreshed_list();
+
+        let sensors: Vec<JsonValue> = components
+            .list()
+            .iter()
+            .map(|component| {
+                json!({
+                    "label": component.label(),
+                    "temperature_c": component.temperature(),
+                    "max_c": component.max(),
+                    "critical_c": component.critical()
+                })
+            })
+            .collect();
+        let max_temperature_c = components
+            .list()
+            .iter()
+            .filter_map(|component| component.temperature())
+            .filter(|t| t.is_finite())
+            .reduce(f32::max);
+
+        Ok(json!({
+            "source_id": source_id,
+            "sensors": sensors,
+            "max_temperature_c": max_temperature_c,
+            "timestamp_utc": Utc::now().to_rfc3339()
+        }))
+    }
+
     /// Enhanced function that returns both state and battery level percentage
     pub fn charging_state_with_level(now: DateTime<Utc>, _battery_id: &str) -> (&'static str, f64) {
         let weekday = now.weekday();
@@ -225,6 +360,14 @@ pub enum TestType {
     Ping,
     ChargingState,
     DiskSpace,
+    CpuLoad,
+    Memory,
+    Thermal,
+    Weather,
+    CarbonIntensity,
+    Exec,
+    Simulator,
+    Replay,
 }
 
 impl std::str::FromStr for TestType {
@@ -235,19 +378,87 @@ impl std::str::FromStr for TestType {
             "ping" => Ok(TestType::Ping),
             "charging_state" => Ok(TestType::ChargingState),
             "disk_space" => Ok(TestType::DiskSpace),
+            "cpu_load" => Ok(TestType::CpuLoad),
+            "memory" => Ok(TestType::Memory),
+            "thermal" => Ok(TestType::Thermal),
+            "weather" => Ok(TestType::Weather),
+            "carbon_intensity" => Ok(TestType::CarbonIntensity),
+            "exec" => Ok(TestType::Exec),
+            "simulator" => Ok(TestType::Simulator),
+            "replay" => Ok(TestType::Replay),
             _ => Err(format!("Unknown test type: {}", s)),
         }
     }
 }
 
 impl TestType {
+    /// Every test type, in the order they're listed to users.
+    pub const ALL: &[TestType] = &[
+        TestType::Ping,
+        TestType::ChargingState,
+        TestType::DiskSpace,
+        TestType::CpuLoad,
+        TestType::Memory,
+        TestType::Thermal,
+        TestType::Weather,
+        TestType::CarbonIntensity,
+        TestType::Exec,
+        TestType::Simulator,
+        TestType::Replay,
+    ];
+
     pub fn as_str(&self) -> &'static str {
         match self {
             TestType::Ping => "ping",
             TestType::ChargingState => "charging_state",
             TestType::DiskSpace => "disk_space",
+            TestType::CpuLoad => "cpu_load",
+            TestType::Memory => "memory",
+            TestType::Thermal => "thermal",
+            TestType::Weather => "weather",
+            TestType::CarbonIntensity => "carbon_intensity",
+            TestType::Exec => "exec",
+            TestType::Simulator => "simulator",
+            TestType::Replay => "replay",
         }
     }
+
+    /// Whether readings of this type are operational telemetry that must not
+    /// be dropped when the writer queue backs up. Host health and weather
+    /// readings are sampled often enough that losing a few is harmless.
+    pub fn is_critical(&self) -> bool {
+        matches!(self, TestType::ChargingState | TestType::Exec)
+    }
+
+    /// The arguments sources of this type accept.
+    pub fn arguments(&self) -> &'static ArgumentSchema {
+        match self {
+            TestType::Ping => &data_sources::PING_ARGUMENTS,
+            TestType::ChargingState => &data_sources::CHARGING_STATE_ARGUMENTS,
+            TestType::DiskSpace => &data_sources::DISK_SPACE_ARGUMENTS,
+            TestType::CpuLoad => &data_sources::CPU_LOAD_ARGUMENTS,
+            TestType::Memory => &data_sources::MEMORY_ARGUMENTS,
+            TestType::Thermal => &data_sources::THERMAL_ARGUMENTS,
+            TestType::Weather => &weather::ARGUMENTS,
+            TestType::CarbonIntensity => &carbon_intensity::ARGUMENTS,
+            TestType::Exec => &exec::ARGUMENTS,
+            TestType::Simulator => &simulator::ARGUMENTS,
+            TestType::Replay => &replay::ARGUMENTS,
+        }
+    }
+
+    /// Check a source's arguments against this type's schema.
+    pub fn validate_arguments(
+        &self,
+        arguments: &HashMap<String, String>,
+    ) -> Result<(), ArgumentErrors> {
+        self.arguments().validate(arguments)
+    }
+
+    /// This type's arguments as JSON Schema.
+    pub fn argument_json_schema(&self) -> JsonValue {
+        self.arguments().to_json_schema(self.as_str())
+    }
 }
 
 /// Data collector that manages async polling of various data sources
@@ -255,6 +466,8 @@ pub struct DataCollector {
     pub test_type: TestType,
     pub source_id: i32,
     pub arguments: HashMap<String, String>,
+    /// Masks the resolved secret values in whatever the collector returns
+    pub redactor: Redactor,
 }
 
 impl DataCollector {
@@ -265,14 +478,24 @@ impl DataCollector {
         source_id: i32,
         arguments: HashMap<String, String>,
     ) -> Self {
-        Self { test_type, source_id, arguments }
+        Self {
+            test_type,
+            source_id,
+            arguments,
+            redactor: Redactor::default(),
+        }
     }
 
     /// Backward-compatible constructor that parses old-style names
     /// For unknown names, creates a collector that will fail at collect time
     pub fn new(name: String, source_id: i32) -> Self {
         if let Some((test_type, arguments)) = Self::parse_legacy_name(&name) {
-            Self { test_type, source_id, arguments }
+            Self {
+                test_type,
+                source_id,
+                arguments,
+                redactor: Redactor::default(),
+            }
         } else {
             // Create a placeholder that will fail at collect time for unknown names
             Self {
@@ -283,7 +506,29 @@ impl DataCollector {
                     args.insert("__unknown_name".to_string(), name);
                     args
                 },
+                redactor: Redactor::default(),
+            }
+        }
+    }
+
+    /// Build the collector for a configured source. Sources with a
+    /// `test_type` use it together with their stored arguments, with
+    /// `secret://` references resolved from `secrets`; older sources without
+    /// one fall back to the legacy name parsing.
+    pub fn from_source(source: &Source, secrets: &SecretStore) -> Result<Self, String> {
+        let source_id = source.id.ok_or("Source has no id")?;
+        match &source.test_type {
+            Some(test_type) => {
+                let test_type = test_type.parse::<TestType>()?;
+                let arguments = source
+                    .get_arguments()
+                    .map_err(|e| format!("Invalid arguments for '{}': {}", source.name, e))?;
+                let (arguments, secret_values) = secrets.resolve(&arguments)?;
+                let mut collector = Self::new_with_test_type(test_type, source_id, arguments);
+                collector.redactor = Redactor::new(secret_values);
+                Ok(collector)
             }
+            None => Ok(Self::new(source.name.clone(), source_id)),
         }
     }
 
@@ -300,6 +545,9 @@ impl DataCollector {
             }
             "charging_state" => Some((TestType::ChargingState, arguments)),
             "disk_space" => Some((TestType::DiskSpace, arguments)),
+            "cpu_load" => Some((TestType::CpuLoad, arguments)),
+            "memory" => Some((TestType::Memory, arguments)),
+            "thermal" => Some((TestType::Thermal, arguments)),
             name if name.starts_with("charging_state_") => {
                 let battery_id = name.strip_prefix("charging_state_").unwrap_or("default");
                 arguments.insert("battery_id".to_string(), battery_id.to_string());
@@ -314,8 +562,24 @@ impl DataCollector {
         }
     }
 
-    /// Collect data based on the collector type
+    /// Collect data based on the collector type. Resolved secrets are
+    /// masked in the returned data and error messages.
     pub async fn collect(&self) -> Result<JsonValue, Box<dyn std::error::Error + Send + Sync>> {
+        match self.collect_unredacted().await {
+            Ok(mut data) => {
+                self.redactor.redact_json(&mut data);
+                Ok(data)
+            }
+            Err(e) if !self.redactor.is_empty() => {
+                Err(self.redactor.redact_str(&e.to_string()).into())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn collect_unredacted(
+        &self,
+    ) -> Result<JsonValue, Box<dyn std::error::Error + Send + Sync>> {
         // Check if this was an unknown collector name
         if let Some(unknown_name) = self.arguments.get("__unknown_name") {
             return Err(format!("Unknown collector type: {}", unknown_name).into());
@@ -333,6 +597,16 @@ impl DataCollector {
                 data_sources::charging_state_for_battery(self.source_id, battery_id).await
             }
             TestType::DiskSpace => data_sources::disk_space(self.source_id).await,
+            TestType::CpuLoad => data_sources::cpu_load(self.source_id).await,
+            TestType::Memory => data_sources::memory(self.source_id).await,
+            TestType::Thermal => data_sources::thermal(self.source_id).await,
+            TestType::Weather => weather::collect(self.source_id, &self.arguments).await,
+            TestType::CarbonIntensity => {
+                carbon_intensity::collect(self.source_id, &self.arguments).await
+            }
+            TestType::Exec => exec::collect(self.source_id, &self.arguments).await,
+            TestType::Simulator => simulator::collect(self.source_id, &self.arguments).await,
+            TestType::Replay => replay::collect(self.source_id, &self.arguments).await,
         }
     }
 
@@ -358,6 +632,59 @@ impl DataCollector {
         Self::new_with_test_type(TestType::DiskSpace, source_id, HashMap::new())
     }
 
+    /// Helper method to create a CPU load collector
+    pub fn new_cpu_load(source_id: i32) -> Self {
+        Self::new_with_test_type(TestType::CpuLoad, source_id, HashMap::new())
+    }
+
+    /// Helper method to create a memory usage collector
+    pub fn new_memory(source_id: i32) -> Self {
+        Self::new_with_test_type(TestType::Memory, source_id, HashMap::new())
+    }
+
+    /// Helper method to create a thermal sensor collector
+    pub fn new_thermal(source_id: i32) -> Self {
+        Self::new_with_test_type(TestType::Thermal, source_id, HashMap::new())
+    }
+
+    /// Helper method to create a weather collector for a site's coordinates
+    /// using the default (NWS) provider
+    pub fn new_weather(source_id: i32, latitude: f64, longitude: f64) -> Self {
+        let mut arguments = HashMap::new();
+        arguments.insert("latitude".to_string(), latitude.to_string());
+        arguments.insert("longitude".to_string(), longitude.to_string());
+        Self::new_with_test_type(TestType::Weather, source_id, arguments)
+    }
+
+    /// Helper method to create a carbon intensity collector for an
+    /// Electricity Maps zone
+    pub fn new_carbon_intensity(source_id: i32, zone: &str, api_key: &str) -> Self {
+        let mut arguments = HashMap::new();
+        arguments.insert("zone".to_string(), zone.to_string());
+        arguments.insert("api_key".to_string(), api_key.to_string());
+        Self::new_with_test_type(TestType::CarbonIntensity, source_id, arguments)
+    }
+
+    /// Helper method to create a collector that runs an external program
+    pub fn new_exec(source_id: i32, command: &str) -> Self {
+        let mut arguments = HashMap::new();
+        arguments.insert("command".to_string(), command.to_string());
+        Self::new_with_test_type(TestType::Exec, source_id, arguments)
+    }
+
+    /// Helper method to create a simulated site collector with default
+    /// parameters
+    pub fn new_simulator(source_id: i32) -> Self {
+        Self::new_with_test_type(TestType::Simulator, source_id, HashMap::new())
+    }
+
+    /// Helper method to create a collector that replays a CSV recording
+    pub fn new_replay(source_id: i32, path: &str) -> Self {
+        let mut arguments = HashMap::new();
+        arguments.insert("path".to_string(), path.to_string());
+        Self::new_with_test_type(TestType::Replay, source_id, arguments)
+    }
+
     /// Get the test type as a string
     pub fn test_type_str(&self) -> &'static str {
         self.test_type.as_str()
@@ -368,3 +695,56 @@ impl DataCollector {
         self.arguments.get(key)
     }
 }
+
+/// How long a self-test waits for the collector by default.
+pub const SELF_TEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The outcome of running a source's collector once.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SelfTestResult {
+    pub source_id: i32,
+    pub source_name: String,
+    pub test_type: Option<String>,
+    pub ok: bool,
+    /// What the collector returned, exactly as it would be stored
+    #[ts(type = "unknown")]
+    pub data: Option<JsonValue>,
+    pub error: Option<String>,
+    #[ts(type = "number")]
+    pub duration_ms: u64,
+}
+
+/// Run `source`'s collector once and report what it returns, without
+/// storing a reading or touching `last_run`, so a source can be checked
+/// before it's activated. `secrets` must hold the secrets the source refers
+/// to (see [`SecretStore::for_sources`]). The collector is abandoned after
+/// `timeout`.
+pub async fn self_test(
+    source: &Source,
+    secrets: &SecretStore,
+    timeout: Duration,
+) -> SelfTestResult {
+    let started = Instant::now();
+    let outcome = match DataCollector::from_source(source, secrets) {
+        Ok(collector) => match tokio::time::timeout(timeout, collector.collect()).await {
+            Ok(Ok(data)) => Ok(data),
+            Ok(Err(e)) => Err(e.to_string()),
+            Err(_) => Err(format!("Timed out after {}s", timeout.as_secs_f64())),
+        },
+        Err(e) => Err(format!("Failed to configure collector: {}", e)),
+    };
+    let (data, error) = match outcome {
+        Ok(data) => (Some(data), None),
+        Err(e) => (None, Some(e)),
+    };
+    SelfTestResult {
+        source_id: source.id.unwrap_or_default(),
+        source_name: source.name.clone(),
+        test_type: source.test_type.clone(),
+        ok: error.is_none(),
+        data,
+        error,
+        duration_ms: started.elapsed().as_millis() as u64,
+    }
+}
@@ -0,0 +1,406 @@
+//! Argument schemas for collector types.
+//!
+//! A source's `arguments` are a flat map of strings, and each collector
+//! reads the keys it needs. Every collector declares those keys next to its
+//! implementation as an [`ArgumentSchema`] (name, type, whether it's
+//! required, its default), which is used to:
+//!
+//! - validate arguments when a source is created or edited, from the CLI or the
+//!   API, so a typo fails then rather than on every poll
+//! - render the schema as JSON Schema (draft 2020-12) for the
+//!   `/api/1/CollectorTypes` endpoint, so the UI can build the source form
+//!
+//! Values are stored as strings, so a type says what the string must parse
+//! as. `secret://` references are only resolved when the collector runs, so
+//! they are accepted for any argument without checking the value.
+
+use std::{collections::HashMap, fmt};
+
+use serde_json::{Map, Value as JsonValue, json};
+
+use crate::secrets::REFERENCE_PREFIX;
+
+const JSON_SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// What an argument's string value must parse as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgumentType {
+    String,
+    Number,
+    Integer,
+    Boolean,
+}
+
+impl ArgumentType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ArgumentType::String => "string",
+            ArgumentType::Number => "number",
+            ArgumentType::Integer => "integer",
+            ArgumentType::Boolean => "boolean",
+        }
+    }
+
+    /// The value as JSON of this type, if it parses.
+    fn parse(&self, value: &str) -> Option<JsonValue> {
+        let trimmed = value.trim();
+        match self {
+            ArgumentType::String => Some(json!(value)),
+            ArgumentType::Number => {
+                trimmed.parse::<f64>().ok().filter(|n| n.is_finite()).map(|n| json!(n))
+            }
+            ArgumentType::Integer => trimmed.parse::<i64>().ok().map(|n| json!(n)),
+            ArgumentType::Boolean => trimmed.parse::<bool>().ok().map(|b| json!(b)),
+        }
+    }
+}
+
+/// One argument a collector reads.
+#[derive(Debug, Clone, Copy)]
+pub struct Argument {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub kind: ArgumentType,
+    pub required: bool,
+    /// What the collector uses when the argument is missing
+    pub default: Option<&'static str>,
+    /// The only accepted values, if limited
+    pub allowed: &'static [&'static str],
+    pub minimum: Option<f64>,
+    pub maximum: Option<f64>,
+}
+
+impl Argument {
+    const fn new(name: &'static str, kind: ArgumentType, description: &'static str) -> Self {
+        Argument {
+            name,
+            description,
+            kind,
+            required: false,
+            default: None,
+            allowed: &[],
+            minimum: None,
+            maximum: None,
+        }
+    }
+
+    pub const fn string(name: &'static str, description: &'static str) -> Self {
+        Self::new(name, ArgumentType::String, description)
+    }
+
+    pub const fn number(name: &'static str, description: &'static str) -> Self {
+        Self::new(name, ArgumentType::Number, description)
+    }
+
+    pub const fn integer(name: &'static str, description: &'static str) -> Self {
+        Self::new(name, ArgumentType::Integer, description)
+    }
+
+    pub const fn boolean(name: &'static str, description: &'static str) -> Self {
+        Self::new(name, ArgumentType::Boolean, description)
+    }
+
+    pub const fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    pub const fn default(mut self, default: &'static str) -> Self {
+        self.default = Some(default);
+        self
+    }
+
+    pub const fn one_of(mut self, allowed: &'static [&'static str]) -> Self {
+        self.allowed = allowed;
+        self
+    }
+
+    pub const fn minimum(mut self, minimum: f64) -> Self {
+        self.minimum = Some(minimum);
+        self
+    }
+
+    pub const fn range(mut self, minimum: f64, maximum: f64) -> Self {
+        self.minimum = Some(minimum);
+        self.maximum = Some(maximum);
+        self
+    }
+
+    /// Check one value, returning what's wrong with it.
+    fn check(&self, value: &str) -> Option<String> {
+        if value.starts_with(REFERENCE_PREFIX) {
+            return None;
+        }
+        if self.required && value.trim().is_empty() {
+            return Some(format!("'{}' must not be empty", self.name));
+        }
+        let Some(parsed) = self.kind.parse(value) else {
+            return Some(format!("'{}' must be {}: {}", self.name, an(self.kind.as_str()), value));
+        };
+        if !self.allowed.is_empty() && !self.allowed.contains(&value) {
+            return Some(format!(
+                "'{}' must be one of {}: {}",
+                self.name,
+                self.allowed.join(", "),
+                value
+            ));
+        }
+        if let Some(n) = parsed.as_f64() {
+            if self.minimum.is_some_and(|minimum| n < minimum) {
+                return Some(format!(
+                    "'{}' must be at least {}: {}",
+                    self.name,
+                    self.minimum.unwrap_or_default(),
+                    value
+                ));
+            }
+            if self.maximum.is_some_and(|maximum| n > maximum) {
+                return Some(format!(
+                    "'{}' must be at most {}: {}",
+                    self.name,
+                    self.maximum.unwrap_or_default(),
+                    value
+                ));
+            }
+        }
+        None
+    }
+
+    fn to_json_schema(self) -> JsonValue {
+        let mut property = Map::new();
+        property.insert("type".into(), json!(self.kind.as_str()));
+        property.insert("description".into(), json!(self.description));
+        if let Some(default) = self.default.and_then(|d| self.kind.parse(d)) {
+            property.insert("default".into(), default);
+        }
+        if !self.allowed.is_empty() {
+            property.insert("enum".into(), json!(self.allowed));
+        }
+        if let Some(minimum) = self.minimum {
+            property.insert("minimum".into(), json!(minimum));
+        }
+        if let Some(maximum) = self.maximum {
+            property.insert("maximum".into(), json!(maximum));
+        }
+        JsonValue::Object(property)
+    }
+}
+
+fn an(kind: &str) -> String {
+    match kind.chars().next() {
+        Some('a' | 'e' | 'i' | 'o' | 'u') => format!("an {}", kind),
+        _ => format!("a {}", kind),
+    }
+}
+
+/// An argument that's only required when another argument has a given
+/// value, e.g. `api_key` when `provider` is `openweather`.
+#[derive(Debug, Clone, Copy)]
+pub struct RequiredWhen {
+    pub argument: &'static str,
+    pub when: &'static str,
+    pub equals: &'static str,
+}
+
+/// The arguments a collector type accepts.
+#[derive(Debug, Clone, Copy)]
+pub struct ArgumentSchema {
+    pub description: &'static str,
+    pub arguments: &'static [Argument],
+    pub required_when: &'static [RequiredWhen],
+    /// Whether arguments not listed are accepted, for collectors that pass
+    /// everything through (exec)
+    pub additional_arguments: bool,
+}
+
+impl ArgumentSchema {
+    pub const fn new(description: &'static str, arguments: &'static [Argument]) -> Self {
+        ArgumentSchema {
+            description,
+            arguments,
+            required_when: &[],
+            additional_arguments: false,
+        }
+    }
+
+    pub const fn required_when(mut self, required_when: &'static [RequiredWhen]) -> Self {
+        self.required_when = required_when;
+        self
+    }
+
+    pub const fn allow_additional_arguments(mut self) -> Self {
+        self.additional_arguments = true;
+        self
+    }
+
+    /// Check `arguments` against the schema, collecting every problem.
+    pub fn validate(&self, arguments: &HashMap<String, String>) -> Result<(), ArgumentErrors> {
+        let mut errors = Vec::new();
+
+        for argument in self.arguments {
+            match arguments.get(argument.name) {
+                Some(value) => errors.extend(argument.check(value)),
+                None if argument.required => {
+                    errors.push(format!("'{}' is required", argument.name))
+                }
+                None => {}
+            }
+        }
+
+        for rule in self.required_when {
+            if arguments.get(rule.when).map(String::as_str) == Some(rule.equals)
+                && !arguments.contains_key(rule.argument)
+            {
+                errors.push(format!(
+                    "'{}' is required when '{}' is {}",
+                    rule.argument, rule.when, rule.equals
+                ));
+            }
+        }
+
+        if !self.additional_arguments {
+            let mut unknown: Vec<&str> = arguments
+                .keys()
+                .map(String::as_str)
+                .filter(|key| !self.arguments.iter().any(|a| a.name == *key))
+                .collect();
+            unknown.sort_unstable();
+            for key in unknown {
+                errors.push(if self.arguments.is_empty() {
+                    format!("unknown argument '{}' (this type takes no arguments)", key)
+                } else {
+                    format!(
+                        "unknown argument '{}' (expected {})",
+                        key,
+                        self.arguments.iter().map(|a| a.name).collect::<Vec<_>>().join(", ")
+                    )
+                });
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ArgumentErrors(errors))
+        }
+    }
+
+    /// The schema as a JSON Schema object describing the arguments map.
+    pub fn to_json_schema(&self, title: &str) -> JsonValue {
+        let properties: Map<String, JsonValue> = self
+            .arguments
+            .iter()
+            .map(|argument| (argument.name.to_string(), argument.to_json_schema()))
+            .collect();
+        let required: Vec<&str> =
+            self.arguments.iter().filter(|a| a.required).map(|a| a.name).collect();
+
+        let mut schema = json!({
+            "$schema": JSON_SCHEMA_DIALECT,
+            "title": title,
+            "description": self.description,
+            "type": "object",
+            "properties": properties,
+            "required": required,
+            "additionalProperties": self.additional_arguments,
+        });
+        if !self.required_when.is_empty() {
+            let conditions: Vec<JsonValue> = self
+                .required_when
+                .iter()
+                .map(|rule| {
+                    json!({
+                        "if": {
+                            "properties": { rule.when: { "const": rule.equals } },
+                            "required": [rule.when]
+                        },
+                        "then": { "required": [rule.argument] }
+                    })
+                })
+                .collect();
+            schema["allOf"] = json!(conditions);
+        }
+        schema
+    }
+}
+
+/// Everything wrong with a source's arguments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArgumentErrors(pub Vec<String>);
+
+impl fmt::Display for ArgumentErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.join("; "))
+    }
+}
+
+impl std::error::Error for ArgumentErrors {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: ArgumentSchema = ArgumentSchema::new(
+        "Test collector",
+        &[
+            Argument::string("host", "Host to poll").required(),
+            Argument::integer("port", "TCP port").default("502").range(1.0, 65535.0),
+            Argument::string("mode", "Polling mode").one_of(&["fast", "slow"]),
+            Argument::string("password", "Password"),
+        ],
+    )
+    .required_when(&[RequiredWhen {
+        argument: "password",
+        when: "mode",
+        equals: "slow",
+    }]);
+
+    fn args(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(SCHEMA.validate(&args(&[("host", "10.0.0.5")])).is_ok());
+        assert!(SCHEMA.validate(&args(&[("host", "10.0.0.5"), ("port", "1502")])).is_ok());
+
+        let errors = SCHEMA
+            .validate(&args(&[("port", "70000"), ("mode", "slow"), ("hots", "x")]))
+            .unwrap_err();
+        assert_eq!(
+            errors.0,
+            vec![
+                "'host' is required",
+                "'port' must be at most 65535: 70000",
+                "'password' is required when 'mode' is slow",
+                "unknown argument 'hots' (expected host, port, mode, password)",
+            ]
+        );
+
+        let errors = SCHEMA
+            .validate(&args(&[("host", "a"), ("port", "x"), ("mode", "medium")]))
+            .unwrap_err();
+        assert_eq!(
+            errors.0,
+            vec!["'port' must be an integer: x", "'mode' must be one of fast, slow: medium"]
+        );
+    }
+
+    #[test]
+    fn test_secret_references_skip_value_checks() {
+        let arguments = args(&[("host", "secret://rtac-host"), ("port", "secret://rtac-port")]);
+        assert!(SCHEMA.validate(&arguments).is_ok());
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let schema = SCHEMA.to_json_schema("test");
+        assert_eq!(schema["title"], "test");
+        assert_eq!(schema["required"], json!(["host"]));
+        assert_eq!(schema["additionalProperties"], false);
+        assert_eq!(schema["properties"]["port"]["type"], "integer");
+        assert_eq!(schema["properties"]["port"]["default"], 502);
+        assert_eq!(schema["properties"]["mode"]["enum"], json!(["fast", "slow"]));
+        assert_eq!(schema["allOf"][0]["then"]["required"], json!(["password"]));
+    }
+}
@@ -0,0 +1,239 @@
+//! Grid carbon intensity collector.
+//!
+//! Fetches the current carbon intensity of the grid a site draws from, so
+//! batteries can charge when the grid is clean and reports can credit the
+//! emissions they avoid. Readings carry `carbon_intensity_gco2_per_kwh`
+//! whichever provider they come from.
+//!
+//! Two providers are supported:
+//! - `electricitymaps` (default): the Electricity Maps latest carbon intensity
+//!   of a `zone` (e.g. `US-CAL-CISO`), an average over the zone's generation
+//!   mix. Requires an `api_key` argument.
+//! - `watttime`: WattTime's marginal operating emissions rate (MOER) for a
+//!   `region` (e.g. `CAISO_NORTH`), the emissions of the generator that would
+//!   answer more demand. Requires `username` and `password` arguments, which
+//!   are exchanged for a token on each poll.
+//!
+//! Source arguments: `provider`, `zone`, `region`, `api_key`, `username`,
+//! `password`.
+
+use std::{collections::HashMap, time::Duration};
+
+use chrono::Utc;
+use serde_json::{Value as JsonValue, json};
+
+use super::arguments::{Argument, ArgumentSchema, RequiredWhen};
+
+type CollectResult = Result<JsonValue, Box<dyn std::error::Error + Send + Sync>>;
+
+const ELECTRICITYMAPS_URL: &str = "https://api.electricitymap.org/v3/carbon-intensity/latest";
+const WATTTIME_LOGIN_URL: &str = "https://api.watttime.org/login";
+const WATTTIME_FORECAST_URL: &str = "https://api.watttime.org/v3/forecast";
+
+const USER_AGENT: &str = concat!("neems-data/", env!("CARGO_PKG_VERSION"));
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Grams in a pound, for WattTime's lbs/MWh
+const GRAMS_PER_POUND: f64 = 453.592_37;
+
+/// Source arguments.
+pub const ARGUMENTS: ArgumentSchema = ArgumentSchema::new(
+    "Current carbon intensity of a site's grid",
+    &[
+        Argument::string("provider", "Carbon intensity data provider")
+            .default("electricitymaps")
+            .one_of(&["electricitymaps", "watttime"]),
+        Argument::string("zone", "Electricity Maps zone, e.g. US-CAL-CISO"),
+        Argument::string("region", "WattTime region, e.g. CAISO_NORTH"),
+        Argument::string("api_key", "Electricity Maps API key"),
+        Argument::string("username", "WattTime username"),
+        Argument::string("password", "WattTime password"),
+    ],
+)
+.required_when(&[
+    RequiredWhen {
+        argument: "zone",
+        when: "provider",
+        equals: "electricitymaps",
+    },
+    RequiredWhen {
+        argument: "api_key",
+        when: "provider",
+        equals: "electricitymaps",
+    },
+    RequiredWhen {
+        argument: "region",
+        when: "provider",
+        equals: "watttime",
+    },
+    RequiredWhen {
+        argument: "username",
+        when: "provider",
+        equals: "watttime",
+    },
+    RequiredWhen {
+        argument: "password",
+        when: "provider",
+        equals: "watttime",
+    },
+]);
+
+/// Carbon intensity provider selected by the `provider` source argument.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CarbonProvider {
+    ElectricityMaps,
+    WattTime,
+}
+
+impl std::str::FromStr for CarbonProvider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "electricitymaps" => Ok(CarbonProvider::ElectricityMaps),
+            "watttime" => Ok(CarbonProvider::WattTime),
+            _ => Err(format!("Unknown carbon intensity provider: {}", s)),
+        }
+    }
+}
+
+impl CarbonProvider {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CarbonProvider::ElectricityMaps => "electricitymaps",
+            CarbonProvider::WattTime => "watttime",
+        }
+    }
+
+    /// Which emissions the provider's figure measures.
+    pub fn signal(&self) -> &'static str {
+        match self {
+            CarbonProvider::ElectricityMaps => "average",
+            CarbonProvider::WattTime => "marginal",
+        }
+    }
+}
+
+/// A provider-independent carbon intensity, in grams of CO2 (equivalent) per
+/// kWh.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CarbonIntensity {
+    pub zone: Option<String>,
+    pub observed_at: Option<String>,
+    pub gco2_per_kwh: f64,
+    /// Whether the provider modelled the figure rather than measuring it
+    pub estimated: Option<bool>,
+}
+
+impl CarbonIntensity {
+    /// Render the intensity as the JSON blob stored in `readings.data`.
+    pub fn to_json(&self, source_id: i32, provider: CarbonProvider) -> JsonValue {
+        json!({
+            "source_id": source_id,
+            "provider": provider.as_str(),
+            "signal": provider.signal(),
+            "zone": self.zone,
+            "observed_at": self.observed_at,
+            "carbon_intensity_gco2_per_kwh": self.gco2_per_kwh,
+            "estimated": self.estimated,
+            "timestamp_utc": Utc::now().to_rfc3339()
+        })
+    }
+}
+
+/// Parse an Electricity Maps `carbon-intensity/latest` response.
+pub fn parse_electricitymaps(body: &JsonValue) -> Option<CarbonIntensity> {
+    Some(CarbonIntensity {
+        zone: body.get("zone").and_then(|z| z.as_str()).map(str::to_string),
+        observed_at: body.get("datetime").and_then(|d| d.as_str()).map(str::to_string),
+        gco2_per_kwh: body.get("carbonIntensity")?.as_f64()?,
+        estimated: body.get("isEstimated").and_then(|e| e.as_bool()),
+    })
+}
+
+/// Parse a WattTime v3 `forecast` response for `co2_moer`, taking its first
+/// (current) point and converting lbs/MWh to g/kWh.
+pub fn parse_watttime(body: &JsonValue) -> Option<CarbonIntensity> {
+    let point = body.get("data")?.as_array()?.first()?;
+    let value = point.get("value")?.as_f64()?;
+    let meta = body.get("meta");
+    let gco2_per_kwh = match meta.and_then(|m| m.get("units")).and_then(|u| u.as_str()) {
+        Some("lbs_co2_per_mwh") | None => value * GRAMS_PER_POUND / 1000.0,
+        Some("g_co2_per_kwh") => value,
+        Some(_) => return None,
+    };
+    Some(CarbonIntensity {
+        zone: meta.and_then(|m| m.get("region")).and_then(|r| r.as_str()).map(str::to_string),
+        observed_at: point.get("point_time").and_then(|t| t.as_str()).map(str::to_string),
+        gco2_per_kwh,
+        estimated: Some(true),
+    })
+}
+
+fn required<'a>(arguments: &'a HashMap<String, String>, key: &str) -> Result<&'a str, String> {
+    arguments
+        .get(key)
+        .map(|v| v.as_str())
+        .filter(|v| !v.trim().is_empty())
+        .ok_or_else(|| format!("carbon_intensity collector requires a '{}' argument", key))
+}
+
+async fn get_json(request: reqwest::RequestBuilder, what: &str) -> CollectResult {
+    let response = request.send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("{} request failed with HTTP {}", what, status).into());
+    }
+    Ok(response.json().await?)
+}
+
+/// Collect the current carbon intensity for the zone or region in
+/// `arguments`.
+pub async fn collect(source_id: i32, arguments: &HashMap<String, String>) -> CollectResult {
+    let provider = match arguments.get("provider") {
+        Some(p) => p.parse::<CarbonProvider>()?,
+        None => CarbonProvider::ElectricityMaps,
+    };
+    let client = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()?;
+
+    let intensity = match provider {
+        CarbonProvider::ElectricityMaps => {
+            let zone = required(arguments, "zone")?;
+            let api_key = required(arguments, "api_key")?;
+            let request = client
+                .get(ELECTRICITYMAPS_URL)
+                .query(&[("zone", zone)])
+                .header("auth-token", api_key);
+            let body = get_json(request, "Electricity Maps").await?;
+            parse_electricitymaps(&body)
+                .ok_or("Electricity Maps carbon intensity response is malformed")?
+        }
+        CarbonProvider::WattTime => {
+            let region = required(arguments, "region")?;
+            let username = required(arguments, "username")?;
+            let password = required(arguments, "password")?;
+            let login = client.get(WATTTIME_LOGIN_URL).basic_auth(username, Some(password));
+            let token = get_json(login, "WattTime login").await?;
+            let token = token
+                .get("token")
+                .and_then(|t| t.as_str())
+                .ok_or("WattTime login response has no token")?;
+            let request = client.get(WATTTIME_FORECAST_URL).bearer_auth(token).query(&[
+                ("region", region),
+                ("signal_type", "co2_moer"),
+                ("horizon_hours", "0"),
+            ]);
+            let body = get_json(request, "WattTime forecast").await?;
+            let mut intensity =
+                parse_watttime(&body).ok_or("WattTime forecast response is malformed")?;
+            intensity.zone.get_or_insert_with(|| region.to_string());
+            intensity
+        }
+    };
+
+    Ok(intensity.to_json(source_id, provider))
+}
@@ -0,0 +1,158 @@
+//! External program collector.
+//!
+//! Runs a site-specific program (typically a Python script) as a one-shot
+//! collector. The program receives a JSON request on stdin and must print a
+//! single JSON object on stdout before exiting successfully:
+//!
+//! ```json
+//! {"source_id": 12, "arguments": {"command": "...", "host": "..."}, "timestamp_utc": "..."}
+//! ```
+//!
+//! The returned object becomes the reading's data. If it has no `source_id`
+//! or `timestamp_utc`, they are filled in so exec readings look like the
+//! built-in ones.
+//!
+//! Source arguments:
+//! - `command` (required): path to the executable.
+//! - `args`: whitespace-separated arguments passed to the program.
+//! - `timeout_seconds`: wall-clock limit, after which the process is killed
+//!   (default 10).
+//! - `max_output_bytes`: stdout size limit (default 1 MiB).
+//!
+//! Every argument, including ones the collector doesn't use itself, is passed
+//! through in the request so scripts can take their own configuration.
+
+use std::{collections::HashMap, process::Stdio, time::Duration};
+
+use chrono::Utc;
+use serde_json::{Value as JsonValue, json};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use super::arguments::{Argument, ArgumentSchema};
+
+type CollectResult = Result<JsonValue, Box<dyn std::error::Error + Send + Sync>>;
+
+pub const DEFAULT_TIMEOUT_SECONDS: u64 = 10;
+pub const DEFAULT_MAX_OUTPUT_BYTES: u64 = 1024 * 1024;
+
+/// Source arguments; any others are passed through to the program.
+pub const ARGUMENTS: ArgumentSchema = ArgumentSchema::new(
+    "Run an external program that prints a JSON reading",
+    &[
+        Argument::string("command", "Path to the executable").required(),
+        Argument::string("args", "Whitespace-separated arguments passed to the program"),
+        Argument::integer("timeout_seconds", "Seconds before the program is killed")
+            .default("10")
+            .minimum(1.0),
+        Argument::integer("max_output_bytes", "Largest accepted stdout, in bytes")
+            .default("1048576")
+            .minimum(1.0),
+    ],
+)
+.allow_additional_arguments();
+
+/// Cap on the stderr captured for error messages.
+const MAX_STDERR_BYTES: u64 = 4096;
+
+fn parse_limit(
+    arguments: &HashMap<String, String>,
+    key: &str,
+    default: u64,
+) -> Result<u64, String> {
+    match arguments.get(key) {
+        Some(raw) => match raw.trim().parse::<u64>() {
+            Ok(value) if value > 0 => Ok(value),
+            _ => Err(format!("exec collector '{}' must be a positive integer: {}", key, raw)),
+        },
+        None => Ok(default),
+    }
+}
+
+/// Run the configured program and return its JSON output.
+pub async fn collect(source_id: i32, arguments: &HashMap<String, String>) -> CollectResult {
+    let command = arguments.get("command").ok_or("exec collector requires a 'command' argument")?;
+    let timeout =
+        Duration::from_secs(parse_limit(arguments, "timeout_seconds", DEFAULT_TIMEOUT_SECONDS)?);
+    let max_output_bytes = parse_limit(arguments, "max_output_bytes", DEFAULT_MAX_OUTPUT_BYTES)?;
+
+    let request = json!({
+        "source_id": source_id,
+        "arguments": arguments,
+        "timestamp_utc": Utc::now().to_rfc3339()
+    });
+
+    let mut child = tokio::process::Command::new(command)
+        .args(
+            arguments
+                .get("args")
+                .map(|a| a.split_whitespace().collect::<Vec<_>>())
+                .unwrap_or_default(),
+        )
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to start '{}': {}", command, e))?;
+
+    let mut stdin = child.stdin.take().ok_or("Failed to open stdin")?;
+    let stdout = child.stdout.take().ok_or("Failed to open stdout")?;
+    let stderr = child.stderr.take().ok_or("Failed to open stderr")?;
+
+    let run = async {
+        // A program that never reads stdin will close it; that's not an error.
+        let payload = serde_json::to_vec(&request)?;
+        let _ = stdin.write_all(&payload).await;
+        drop(stdin);
+
+        // Read one byte past the limit so oversized output can be detected
+        // without buffering all of it.
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        let mut stdout = stdout.take(max_output_bytes + 1);
+        // Keep draining stderr past the cap so a chatty program can't block on
+        // a full pipe.
+        let read_stderr = async {
+            let mut stderr = stderr.take(MAX_STDERR_BYTES);
+            stderr.read_to_end(&mut errors).await?;
+            tokio::io::copy(&mut stderr.into_inner(), &mut tokio::io::sink()).await
+        };
+        let (read_out, read_err) = tokio::join!(stdout.read_to_end(&mut output), read_stderr);
+        read_out?;
+        read_err?;
+
+        if output.len() as u64 > max_output_bytes {
+            return Err(format!(
+                "'{}' produced more than {} bytes of output",
+                command, max_output_bytes
+            )
+            .into());
+        }
+
+        let status = child.wait().await?;
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>((status, output, errors))
+    };
+
+    let (status, output, errors) = match tokio::time::timeout(timeout, run).await {
+        Ok(result) => result?,
+        Err(_) => {
+            // kill_on_drop reaps the process when `child` goes out of scope.
+            return Err(format!("'{}' timed out after {}s", command, timeout.as_secs()).into());
+        }
+    };
+
+    if !status.success() {
+        let stderr = String::from_utf8_lossy(&errors);
+        return Err(format!("'{}' exited with {}: {}", command, status, stderr.trim()).into());
+    }
+
+    let mut data: JsonValue = serde_json::from_slice(&output)
+        .map_err(|e| format!("'{}' did not print valid JSON: {}", command, e))?;
+    let object = data
+        .as_object_mut()
+        .ok_or_else(|| format!("'{}' must print a JSON object", command))?;
+    object.entry("source_id").or_insert(json!(source_id));
+    object.entry("timestamp_utc").or_insert(json!(Utc::now().to_rfc3339()));
+
+    Ok(data)
+}
@@ -0,0 +1,275 @@
+//! Recorded telemetry replay collector.
+//!
+//! Plays back a CSV recording (for example, an export of a real site's
+//! readings) so the scheduler and UI can be exercised against known data.
+//! The first line names the columns; one column holds each row's timestamp,
+//! as RFC 3339, `YYYY-MM-DD HH:MM:SS` (UTC), or Unix seconds. Fields are
+//! split on commas without quoting, so values can't contain commas.
+//!
+//! Playback starts at the first row the first time the source is polled and
+//! follows the recording's own timing, scaled by `speed` (2 plays twice as
+//! fast). Each poll reports the latest row played so far: numeric fields as
+//! numbers, empty fields as `null`, anything else as a string, plus the row's
+//! `recorded_at` time. At the end the recording starts over, unless `loop` is
+//! false, in which case the last row is repeated with `"finished": true`.
+//! Changes to the file are picked up without restarting playback.
+//!
+//! Source arguments: `path` (required), `speed`, `timestamp_column`, `loop`.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, Mutex},
+    time::SystemTime,
+};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde_json::{Map, Value as JsonValue, json};
+
+use super::arguments::{Argument, ArgumentSchema};
+
+type CollectResult = Result<JsonValue, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Source arguments.
+pub const ARGUMENTS: ArgumentSchema = ArgumentSchema::new(
+    "Replay recorded telemetry from a CSV file",
+    &[
+        Argument::string("path", "Path to the CSV recording").required(),
+        Argument::number("speed", "Playback speed relative to the recording")
+            .default("1")
+            .minimum(0.001),
+        Argument::string("timestamp_column", "Column holding each row's timestamp")
+            .default("timestamp"),
+        Argument::boolean("loop", "Start over at the end of the recording").default("true"),
+    ],
+);
+
+/// A parsed CSV recording, in time order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    /// Column names, without the timestamp column
+    pub columns: Vec<String>,
+    pub rows: Vec<RecordedRow>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedRow {
+    pub recorded_at: DateTime<Utc>,
+    pub values: Vec<JsonValue>,
+}
+
+fn parse_timestamp(raw: &str) -> Option<DateTime<Utc>> {
+    if let Ok(ts) = DateTime::parse_from_rfc3339(raw) {
+        return Some(ts.with_timezone(&Utc));
+    }
+    if let Ok(ts) = NaiveDateTime::parse_from_str(raw, "%Y-%m-%d %H:%M:%S%.f") {
+        return Some(ts.and_utc());
+    }
+    let seconds = raw.parse::<f64>().ok().filter(|s| s.is_finite())?;
+    DateTime::from_timestamp_millis((seconds * 1000.0).round() as i64)
+}
+
+fn parse_value(raw: &str) -> JsonValue {
+    if raw.is_empty() {
+        return JsonValue::Null;
+    }
+    if let Ok(n) = raw.parse::<i64>() {
+        return json!(n);
+    }
+    match raw.parse::<f64>() {
+        Ok(n) if n.is_finite() => json!(n),
+        _ => json!(raw),
+    }
+}
+
+impl Recording {
+    /// Parse a CSV recording whose timestamps are in `timestamp_column`.
+    pub fn parse(csv: &str, timestamp_column: &str) -> Result<Self, String> {
+        let mut lines = csv.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        let (_, header) = lines.next().ok_or("recording is empty")?;
+        let header: Vec<&str> = header.split(',').map(str::trim).collect();
+        let timestamp_index = header
+            .iter()
+            .position(|column| *column == timestamp_column)
+            .ok_or_else(|| format!("recording has no '{}' column", timestamp_column))?;
+
+        let mut rows: Vec<RecordedRow> = Vec::new();
+        for (index, line) in lines {
+            let line_number = index + 1;
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            if fields.len() != header.len() {
+                return Err(format!(
+                    "line {} has {} fields, expected {}",
+                    line_number,
+                    fields.len(),
+                    header.len()
+                ));
+            }
+            let recorded_at = parse_timestamp(fields[timestamp_index]).ok_or_else(|| {
+                format!(
+                    "line {} has an invalid timestamp: {}",
+                    line_number, fields[timestamp_index]
+                )
+            })?;
+            if rows.last().is_some_and(|last| recorded_at < last.recorded_at) {
+                return Err(format!("line {} is earlier than the line before it", line_number));
+            }
+            let values = fields
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != timestamp_index)
+                .map(|(_, field)| parse_value(field))
+                .collect();
+            rows.push(RecordedRow { recorded_at, values });
+        }
+        if rows.is_empty() {
+            return Err("recording has no rows".to_string());
+        }
+
+        let columns = header
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != timestamp_index)
+            .map(|(_, column)| column.to_string())
+            .collect();
+        Ok(Self { columns, rows })
+    }
+
+    /// The row being played `elapsed` recording-time after playback started,
+    /// and whether a non-looping recording has finished.
+    ///
+    /// When looping, the last row is held for the recording's average row
+    /// interval before the first row comes round again.
+    pub fn row_at(&self, elapsed: chrono::Duration, looping: bool) -> (usize, bool) {
+        let start = self.rows[0].recorded_at;
+        let last = self.rows.len() - 1;
+        let span = self.rows[last].recorded_at - start;
+        let mut elapsed = elapsed.max(chrono::Duration::zero());
+
+        if last == 0 {
+            return (0, !looping);
+        }
+        if elapsed >= span && !looping {
+            return (last, true);
+        }
+        if looping {
+            let period = span + span / last as i32;
+            let period_ms = period.num_milliseconds().max(1);
+            elapsed = chrono::Duration::milliseconds(elapsed.num_milliseconds() % period_ms);
+        }
+
+        let at = start + elapsed;
+        let index = self.rows.partition_point(|row| row.recorded_at <= at).saturating_sub(1);
+        (index, false)
+    }
+
+    /// The row's values keyed by column.
+    pub fn row_data(&self, index: usize) -> Map<String, JsonValue> {
+        self.columns
+            .iter()
+            .cloned()
+            .zip(self.rows[index].values.iter().cloned())
+            .collect()
+    }
+}
+
+/// One source's playback.
+#[derive(Debug)]
+struct Playback {
+    path: String,
+    timestamp_column: String,
+    modified: Option<SystemTime>,
+    recording: Arc<Recording>,
+    started_at: DateTime<Utc>,
+}
+
+/// Playback by source id, kept for as long as neems-data runs.
+static PLAYBACKS: LazyLock<Mutex<HashMap<i32, Playback>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+async fn load(path: &str, timestamp_column: &str) -> Result<Recording, String> {
+    let csv = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| format!("Failed to read recording '{}': {}", path, e))?;
+    Recording::parse(&csv, timestamp_column)
+        .map_err(|e| format!("Invalid recording '{}': {}", path, e))
+}
+
+/// The source's recording and when its playback started, (re)loading the
+/// file when the source is new, its path or timestamp column changed, or
+/// the file was modified.
+async fn playback(
+    source_id: i32,
+    path: &str,
+    timestamp_column: &str,
+    now: DateTime<Utc>,
+) -> Result<(Arc<Recording>, DateTime<Utc>), String> {
+    let modified = tokio::fs::metadata(path).await.and_then(|m| m.modified()).ok();
+    {
+        let playbacks = PLAYBACKS.lock().map_err(|_| "replay state is poisoned")?;
+        if let Some(playback) = playbacks.get(&source_id)
+            && playback.path == path
+            && playback.timestamp_column == timestamp_column
+            && playback.modified == modified
+        {
+            return Ok((playback.recording.clone(), playback.started_at));
+        }
+    }
+
+    let recording = Arc::new(load(path, timestamp_column).await?);
+    let mut playbacks = PLAYBACKS.lock().map_err(|_| "replay state is poisoned")?;
+    let started_at = match playbacks.get(&source_id) {
+        Some(playback) if playback.path == path => playback.started_at,
+        _ => now,
+    };
+    playbacks.insert(
+        source_id,
+        Playback {
+            path: path.to_string(),
+            timestamp_column: timestamp_column.to_string(),
+            modified,
+            recording: recording.clone(),
+            started_at,
+        },
+    );
+    Ok((recording, started_at))
+}
+
+/// Report the recording's row for the current point in playback.
+pub async fn collect(source_id: i32, arguments: &HashMap<String, String>) -> CollectResult {
+    let path = arguments.get("path").ok_or("replay collector requires a 'path' argument")?;
+    let timestamp_column =
+        arguments.get("timestamp_column").map(|s| s.as_str()).unwrap_or("timestamp");
+    let speed = match arguments.get("speed") {
+        Some(raw) => raw
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|s| s.is_finite() && *s > 0.0)
+            .ok_or_else(|| {
+                format!("replay collector 'speed' must be a positive number: {}", raw)
+            })?,
+        None => 1.0,
+    };
+    let looping = match arguments.get("loop") {
+        Some(raw) => raw
+            .trim()
+            .parse::<bool>()
+            .map_err(|_| format!("replay collector 'loop' must be true or false: {}", raw))?,
+        None => true,
+    };
+
+    let now = Utc::now();
+    let (recording, started_at) = playback(source_id, path, timestamp_column, now).await?;
+    let elapsed_ms = ((now - started_at).num_milliseconds() as f64 * speed) as i64;
+    let (index, finished) = recording.row_at(chrono::Duration::milliseconds(elapsed_ms), looping);
+
+    let mut data = recording.row_data(index);
+    data.insert("source_id".to_string(), json!(source_id));
+    data.insert("recorded_at".to_string(), json!(recording.rows[index].recorded_at.to_rfc3339()));
+    data.insert("row".to_string(), json!(index + 1));
+    if !looping {
+        data.insert("finished".to_string(), json!(finished));
+    }
+    data.insert("timestamp_utc".to_string(), json!(now.to_rfc3339()));
+    Ok(JsonValue::Object(data))
+}
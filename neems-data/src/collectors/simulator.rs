@@ -0,0 +1,234 @@
+//! Simulated battery site collector.
+//!
+//! Generates plausible site telemetry so the scheduler and UI can be
+//! developed without hardware:
+//!
+//! - site load follows a daily sine wave, lowest at 06:00 UTC and highest at
+//!   18:00 UTC
+//! - the battery is commanded to charge at `power_kw` from 00:00 to 08:00 UTC
+//!   and discharge at `power_kw` from 16:00 to 20:00 UTC, and holds otherwise
+//! - state of charge is integrated from the power the battery actually
+//!   delivers, which drops to zero once it's full or empty
+//! - reported load and battery power carry `noise_percent` of random noise
+//!
+//! Each source keeps its state of charge in memory between polls, starting
+//! from `initial_soc_percent` when neems-data starts. Power is signed like the
+//! RTAC's: negative is charging.
+//!
+//! Source arguments: `capacity_kwh`, `power_kw`, `initial_soc_percent`,
+//! `base_load_kw`, `load_amplitude_kw`, `noise_percent` (all optional).
+
+use std::{
+    collections::HashMap,
+    f64::consts::PI,
+    hash::{BuildHasher, RandomState},
+    sync::{LazyLock, Mutex},
+};
+
+use chrono::{DateTime, Duration, Timelike, Utc};
+use serde_json::{Value as JsonValue, json};
+
+use super::arguments::{Argument, ArgumentSchema};
+
+type CollectResult = Result<JsonValue, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Source arguments.
+pub const ARGUMENTS: ArgumentSchema = ArgumentSchema::new(
+    "Synthetic battery site telemetry for development",
+    &[
+        Argument::number("capacity_kwh", "Battery energy capacity in kWh")
+            .default("1000")
+            .minimum(1.0),
+        Argument::number("power_kw", "Charge and discharge power the schedule commands, in kW")
+            .default("250")
+            .minimum(0.0),
+        Argument::number("initial_soc_percent", "State of charge when the simulation starts")
+            .default("50")
+            .range(0.0, 100.0),
+        Argument::number("base_load_kw", "Average site load in kW")
+            .default("400")
+            .minimum(0.0),
+        Argument::number(
+            "load_amplitude_kw",
+            "Swing of the daily load curve either side of the base, in kW",
+        )
+        .default("150")
+        .minimum(0.0),
+        Argument::number("noise_percent", "Random noise added to reported power, as a percentage")
+            .default("2")
+            .range(0.0, 100.0),
+    ],
+);
+
+/// Share of energy kept over a full charge/discharge cycle; losses are split
+/// evenly between charging and discharging.
+pub const ROUND_TRIP_EFFICIENCY: f64 = 0.9;
+
+/// Longest step used when integrating state of charge, so a charge window
+/// that starts or ends between polls is accounted for.
+const MAX_STEP_SECONDS: i64 = 60;
+
+/// Longest gap integrated after a pause in polling; anything older is
+/// forgotten rather than replayed minute by minute.
+const MAX_GAP_SECONDS: i64 = 24 * 60 * 60;
+
+/// Simulation parameters, read from the source arguments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulatorConfig {
+    pub capacity_kwh: f64,
+    pub power_kw: f64,
+    pub initial_soc_percent: f64,
+    pub base_load_kw: f64,
+    pub load_amplitude_kw: f64,
+    pub noise_percent: f64,
+}
+
+impl Default for SimulatorConfig {
+    fn default() -> Self {
+        Self {
+            capacity_kwh: 1000.0,
+            power_kw: 250.0,
+            initial_soc_percent: 50.0,
+            base_load_kw: 400.0,
+            load_amplitude_kw: 150.0,
+            noise_percent: 2.0,
+        }
+    }
+}
+
+impl SimulatorConfig {
+    pub fn from_arguments(arguments: &HashMap<String, String>) -> Result<Self, String> {
+        let defaults = Self::default();
+        let number = |key: &str, default: f64| match arguments.get(key) {
+            Some(raw) => {
+                raw.trim().parse::<f64>().ok().filter(|n| n.is_finite()).ok_or_else(|| {
+                    format!("simulator collector '{}' is not a number: {}", key, raw)
+                })
+            }
+            None => Ok(default),
+        };
+        Ok(Self {
+            capacity_kwh: number("capacity_kwh", defaults.capacity_kwh)?.max(1.0),
+            power_kw: number("power_kw", defaults.power_kw)?.abs(),
+            initial_soc_percent: number("initial_soc_percent", defaults.initial_soc_percent)?
+                .clamp(0.0, 100.0),
+            base_load_kw: number("base_load_kw", defaults.base_load_kw)?,
+            load_amplitude_kw: number("load_amplitude_kw", defaults.load_amplitude_kw)?,
+            noise_percent: number("noise_percent", defaults.noise_percent)?.clamp(0.0, 100.0),
+        })
+    }
+
+    /// Battery power the daily schedule asks for at `now` (negative is
+    /// charging).
+    pub fn commanded_power_kw(&self, now: DateTime<Utc>) -> f64 {
+        match now.hour() {
+            0..8 => -self.power_kw,
+            16..20 => self.power_kw,
+            _ => 0.0,
+        }
+    }
+
+    /// Site load at `now`, before noise.
+    pub fn load_kw(&self, now: DateTime<Utc>) -> f64 {
+        let hours = now.num_seconds_from_midnight() as f64 / 3600.0;
+        (self.base_load_kw + self.load_amplitude_kw * (2.0 * PI * (hours - 12.0) / 24.0).sin())
+            .max(0.0)
+    }
+
+    /// What the battery delivers when commanded `commanded_kw` at
+    /// `soc_percent`: nothing once it's full or empty.
+    pub fn battery_power_kw(&self, commanded_kw: f64, soc_percent: f64) -> f64 {
+        if (commanded_kw < 0.0 && soc_percent >= 100.0)
+            || (commanded_kw > 0.0 && soc_percent <= 0.0)
+        {
+            0.0
+        } else {
+            commanded_kw
+        }
+    }
+}
+
+/// One source's simulated battery.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Simulation {
+    pub soc_percent: f64,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Simulation {
+    pub fn new(config: &SimulatorConfig, now: DateTime<Utc>) -> Self {
+        Self {
+            soc_percent: config.initial_soc_percent,
+            updated_at: now,
+        }
+    }
+
+    /// Integrate state of charge up to `now`, in steps of at most a minute.
+    pub fn advance(&mut self, config: &SimulatorConfig, now: DateTime<Utc>) {
+        let mut at = self.updated_at.max(now - Duration::seconds(MAX_GAP_SECONDS));
+        let one_way_efficiency = ROUND_TRIP_EFFICIENCY.sqrt();
+        while at < now {
+            let step = (now - at).min(Duration::seconds(MAX_STEP_SECONDS));
+            let hours = step.num_milliseconds() as f64 / 3_600_000.0;
+            let power_kw = config.battery_power_kw(config.commanded_power_kw(at), self.soc_percent);
+            let energy_kwh = if power_kw < 0.0 {
+                power_kw * hours * one_way_efficiency
+            } else {
+                power_kw * hours / one_way_efficiency
+            };
+            self.soc_percent =
+                (self.soc_percent - energy_kwh / config.capacity_kwh * 100.0).clamp(0.0, 100.0);
+            at += step;
+        }
+        self.updated_at = self.updated_at.max(now);
+    }
+}
+
+/// Simulations by source id, kept for as long as neems-data runs.
+static SIMULATIONS: LazyLock<Mutex<HashMap<i32, Simulation>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// A uniformly distributed value in `[-1, 1]`.
+fn noise() -> f64 {
+    let bits = RandomState::new().hash_one(Utc::now().timestamp_nanos_opt());
+    (bits >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+}
+
+/// Advance the source's simulation to now and report it.
+pub async fn collect(source_id: i32, arguments: &HashMap<String, String>) -> CollectResult {
+    let config = SimulatorConfig::from_arguments(arguments)?;
+    let now = Utc::now();
+
+    let soc_percent = {
+        let mut simulations = SIMULATIONS.lock().map_err(|_| "simulator state is poisoned")?;
+        let simulation =
+            simulations.entry(source_id).or_insert_with(|| Simulation::new(&config, now));
+        simulation.advance(&config, now);
+        simulation.soc_percent
+    };
+
+    let commanded_power_kw = config.commanded_power_kw(now);
+    let jitter = |value: f64| value * (1.0 + config.noise_percent / 100.0 * noise());
+    let battery_power_kw = jitter(config.battery_power_kw(commanded_power_kw, soc_percent));
+    let load_kw = jitter(config.load_kw(now));
+    let state = if battery_power_kw < 0.0 {
+        "charging"
+    } else if battery_power_kw > 0.0 {
+        "discharging"
+    } else {
+        "hold"
+    };
+
+    Ok(json!({
+        "source_id": source_id,
+        "simulated": true,
+        "state": state,
+        "soc_percent": soc_percent,
+        "commanded_power_kw": commanded_power_kw,
+        "battery_power_kw": battery_power_kw,
+        "load_kw": load_kw,
+        "grid_power_kw": load_kw - battery_power_kw,
+        "capacity_kwh": config.capacity_kwh,
+        "timestamp_utc": now.to_rfc3339()
+    }))
+}
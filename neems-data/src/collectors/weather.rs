@@ -0,0 +1,323 @@
+//! Weather observation collector.
+//!
+//! Fetches the latest surface observation near a site's coordinates and
+//! normalizes it into a provider-independent reading. Ambient temperature
+//! drives battery derating, so these readings sit alongside the site's other
+//! sources and surface in reports.
+//!
+//! Two providers are supported:
+//! - `nws` (default): the US National Weather Service API. No key required, but
+//!   coverage is limited to the US. The nearest observation station is looked
+//!   up from the coordinates unless a `station` argument is given.
+//! - `openweather`: OpenWeather's current-weather endpoint. Requires an
+//!   `api_key` argument.
+//!
+//! Source arguments: `latitude`, `longitude` (required), `provider`,
+//! `station`, `api_key`.
+
+use std::{collections::HashMap, time::Duration};
+
+use chrono::Utc;
+use serde_json::{Value as JsonValue, json};
+
+use super::arguments::{Argument, ArgumentSchema, RequiredWhen};
+
+type CollectResult = Result<JsonValue, Box<dyn std::error::Error + Send + Sync>>;
+
+const NWS_BASE_URL: &str = "https://api.weather.gov";
+const OPENWEATHER_URL: &str = "https://api.openweathermap.org/data/2.5/weather";
+
+/// NWS rejects requests without a User-Agent identifying the caller.
+const USER_AGENT: &str = concat!("neems-data/", env!("CARGO_PKG_VERSION"));
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Source arguments.
+pub const ARGUMENTS: ArgumentSchema = ArgumentSchema::new(
+    "Latest weather observation near a site",
+    &[
+        Argument::number("latitude", "Site latitude in degrees")
+            .required()
+            .range(-90.0, 90.0),
+        Argument::number("longitude", "Site longitude in degrees")
+            .required()
+            .range(-180.0, 180.0),
+        Argument::string("provider", "Weather data provider")
+            .default("nws")
+            .one_of(&["nws", "openweather"]),
+        Argument::string("station", "NWS observation station; the nearest is used if unset"),
+        Argument::string("api_key", "OpenWeather API key"),
+    ],
+)
+.required_when(&[RequiredWhen {
+    argument: "api_key",
+    when: "provider",
+    equals: "openweather",
+}]);
+
+/// Weather data provider selected by the `provider` source argument.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WeatherProvider {
+    Nws,
+    OpenWeather,
+}
+
+impl std::str::FromStr for WeatherProvider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nws" => Ok(WeatherProvider::Nws),
+            "openweather" => Ok(WeatherProvider::OpenWeather),
+            _ => Err(format!("Unknown weather provider: {}", s)),
+        }
+    }
+}
+
+impl WeatherProvider {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WeatherProvider::Nws => "nws",
+            WeatherProvider::OpenWeather => "openweather",
+        }
+    }
+}
+
+/// A provider-independent weather observation. Every measurement is optional
+/// because stations routinely omit fields (NWS reports `null` for sensors that
+/// are down).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WeatherObservation {
+    pub station: Option<String>,
+    pub observed_at: Option<String>,
+    pub temperature_c: Option<f64>,
+    pub relative_humidity_percent: Option<f64>,
+    pub wind_speed_mps: Option<f64>,
+    pub wind_gust_mps: Option<f64>,
+    pub wind_direction_deg: Option<f64>,
+    pub cloud_cover_percent: Option<f64>,
+    pub conditions: Option<String>,
+}
+
+impl WeatherObservation {
+    /// Irradiance proxy in `[0, 1]`: the clear-sky fraction implied by cloud
+    /// cover (1 = clear, 0 = overcast). Neither provider reports irradiance
+    /// directly, so this is what reports use to explain PV-adjacent load.
+    pub fn irradiance_proxy(&self) -> Option<f64> {
+        self.cloud_cover_percent.map(|c| (1.0 - c / 100.0).clamp(0.0, 1.0))
+    }
+
+    /// Render the observation as the JSON blob stored in `readings.data`.
+    pub fn to_json(
+        &self,
+        source_id: i32,
+        provider: WeatherProvider,
+        latitude: f64,
+        longitude: f64,
+    ) -> JsonValue {
+        json!({
+            "source_id": source_id,
+            "provider": provider.as_str(),
+            "latitude": latitude,
+            "longitude": longitude,
+            "station": self.station,
+            "observed_at": self.observed_at,
+            "temperature_c": self.temperature_c,
+            "relative_humidity_percent": self.relative_humidity_percent,
+            "wind_speed_mps": self.wind_speed_mps,
+            "wind_gust_mps": self.wind_gust_mps,
+            "wind_direction_deg": self.wind_direction_deg,
+            "cloud_cover_percent": self.cloud_cover_percent,
+            "irradiance_proxy": self.irradiance_proxy(),
+            "conditions": self.conditions,
+            "timestamp_utc": Utc::now().to_rfc3339()
+        })
+    }
+}
+
+/// Read an NWS quantitative value (`{"unitCode": ..., "value": ...}`),
+/// converting km/h to m/s so wind speeds match the OpenWeather units.
+fn nws_quantity(properties: &JsonValue, key: &str) -> Option<f64> {
+    let quantity = properties.get(key)?;
+    let value = quantity.get("value")?.as_f64()?;
+    match quantity.get("unitCode").and_then(|u| u.as_str()) {
+        Some("wmoUnit:km_h-1") => Some(value / 3.6),
+        _ => Some(value),
+    }
+}
+
+/// Map an NWS/METAR cloud amount code to an approximate coverage percentage
+/// (midpoint of the okta range each code represents).
+fn cloud_amount_percent(code: &str) -> Option<f64> {
+    match code {
+        "SKC" | "CLR" => Some(0.0),
+        "FEW" => Some(18.75),
+        "SCT" => Some(43.75),
+        "BKN" => Some(75.0),
+        "OVC" | "VV" => Some(100.0),
+        _ => None,
+    }
+}
+
+/// Parse the `properties` of an NWS `observations/latest` response.
+///
+/// Cloud cover is the densest reported layer, since that is the one that
+/// limits sunlight.
+pub fn parse_nws_observation(body: &JsonValue) -> Option<WeatherObservation> {
+    let properties = body.get("properties")?;
+
+    let cloud_cover_percent = properties
+        .get("cloudLayers")
+        .and_then(|layers| layers.as_array())
+        .and_then(|layers| {
+            layers
+                .iter()
+                .filter_map(|layer| layer.get("amount")?.as_str())
+                .filter_map(cloud_amount_percent)
+                .reduce(f64::max)
+        });
+
+    Some(WeatherObservation {
+        station: properties
+            .get("stationId")
+            .or_else(|| properties.get("station"))
+            .and_then(|s| s.as_str())
+            .map(|s| s.rsplit('/').next().unwrap_or(s).to_string()),
+        observed_at: properties.get("timestamp").and_then(|t| t.as_str()).map(str::to_string),
+        temperature_c: nws_quantity(properties, "temperature"),
+        relative_humidity_percent: nws_quantity(properties, "relativeHumidity"),
+        wind_speed_mps: nws_quantity(properties, "windSpeed"),
+        wind_gust_mps: nws_quantity(properties, "windGust"),
+        wind_direction_deg: nws_quantity(properties, "windDirection"),
+        cloud_cover_percent,
+        conditions: properties
+            .get("textDescription")
+            .and_then(|t| t.as_str())
+            .filter(|t| !t.is_empty())
+            .map(str::to_string),
+    })
+}
+
+/// Parse an OpenWeather current-weather response requested with
+/// `units=metric`.
+pub fn parse_openweather_observation(body: &JsonValue) -> Option<WeatherObservation> {
+    let main = body.get("main")?;
+    let wind = body.get("wind");
+
+    Some(WeatherObservation {
+        station: body.get("name").and_then(|n| n.as_str()).map(str::to_string),
+        observed_at: body
+            .get("dt")
+            .and_then(|dt| dt.as_i64())
+            .and_then(|dt| chrono::DateTime::from_timestamp(dt, 0))
+            .map(|dt| dt.to_rfc3339()),
+        temperature_c: main.get("temp").and_then(|t| t.as_f64()),
+        relative_humidity_percent: main.get("humidity").and_then(|h| h.as_f64()),
+        wind_speed_mps: wind.and_then(|w| w.get("speed")).and_then(|s| s.as_f64()),
+        wind_gust_mps: wind.and_then(|w| w.get("gust")).and_then(|g| g.as_f64()),
+        wind_direction_deg: wind.and_then(|w| w.get("deg")).and_then(|d| d.as_f64()),
+        cloud_cover_percent: body.get("clouds").and_then(|c| c.get("all")).and_then(|a| a.as_f64()),
+        conditions: body
+            .get("weather")
+            .and_then(|w| w.as_array())
+            .and_then(|w| w.first())
+            .and_then(|w| w.get("description"))
+            .and_then(|d| d.as_str())
+            .map(str::to_string),
+    })
+}
+
+fn parse_coordinate(arguments: &HashMap<String, String>, key: &str) -> Result<f64, String> {
+    let raw = arguments
+        .get(key)
+        .ok_or_else(|| format!("weather collector requires a '{}' argument", key))?;
+    raw.trim()
+        .parse::<f64>()
+        .map_err(|_| format!("weather collector '{}' is not a number: {}", key, raw))
+}
+
+fn http_client() -> Result<reqwest::Client, reqwest::Error> {
+    reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+}
+
+async fn get_json(client: &reqwest::Client, url: &str) -> CollectResult {
+    let response = client
+        .get(url)
+        .header("Accept", "application/geo+json, application/json")
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("weather request to {} failed with HTTP {}", url, status).into());
+    }
+    Ok(response.json().await?)
+}
+
+/// Resolve the nearest NWS observation station for a coordinate pair.
+async fn nws_nearest_station(
+    client: &reqwest::Client,
+    latitude: f64,
+    longitude: f64,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let point =
+        get_json(client, &format!("{}/points/{:.4},{:.4}", NWS_BASE_URL, latitude, longitude))
+            .await?;
+    let stations_url = point
+        .pointer("/properties/observationStations")
+        .and_then(|u| u.as_str())
+        .ok_or("NWS points response has no observationStations")?;
+
+    let stations = get_json(client, stations_url).await?;
+    stations
+        .pointer("/features/0/properties/stationIdentifier")
+        .and_then(|s| s.as_str())
+        .map(str::to_string)
+        .ok_or_else(|| "NWS returned no observation stations for this location".into())
+}
+
+/// Collect the latest weather observation for the coordinates in `arguments`.
+pub async fn collect(source_id: i32, arguments: &HashMap<String, String>) -> CollectResult {
+    let latitude = parse_coordinate(arguments, "latitude")?;
+    let longitude = parse_coordinate(arguments, "longitude")?;
+    let provider = match arguments.get("provider") {
+        Some(p) => p.parse::<WeatherProvider>()?,
+        None => WeatherProvider::Nws,
+    };
+
+    let client = http_client()?;
+
+    let observation = match provider {
+        WeatherProvider::Nws => {
+            let station = match arguments.get("station") {
+                Some(station) => station.clone(),
+                None => nws_nearest_station(&client, latitude, longitude).await?,
+            };
+            let body = get_json(
+                &client,
+                &format!("{}/stations/{}/observations/latest", NWS_BASE_URL, station),
+            )
+            .await?;
+            let mut observation =
+                parse_nws_observation(&body).ok_or("NWS observation response is malformed")?;
+            observation.station.get_or_insert(station);
+            observation
+        }
+        WeatherProvider::OpenWeather => {
+            let api_key = arguments
+                .get("api_key")
+                .ok_or("openweather provider requires an 'api_key' argument")?;
+            let url = format!(
+                "{}?lat={}&lon={}&units=metric&appid={}",
+                OPENWEATHER_URL, latitude, longitude, api_key
+            );
+            let body = get_json(&client, &url).await?;
+            parse_openweather_observation(&body)
+                .ok_or("OpenWeather observation response is malformed")?
+        }
+    };
+
+    Ok(observation.to_json(source_id, provider, latitude, longitude))
+}
@@ -0,0 +1,323 @@
+//! Concurrency limits for collector tasks.
+//!
+//! Every collector task holds a permit from a global semaphore, plus one from
+//! its test type's semaphore when that type has a limit, for as long as it is
+//! collecting. This keeps bursts (hundreds of pings due at once) from
+//! exhausting file descriptors or processes on the edge device. Tasks waiting
+//! for a permit are counted as queued in [`CollectorMetrics`].
+
+use std::{
+    collections::HashMap,
+    env,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::collectors::TestType;
+
+/// Concurrency limits for collector tasks.
+///
+/// Read from the environment by [`ConcurrencyConfig::from_env`]:
+/// - `NEEMS_DATA_MAX_CONCURRENT_COLLECTORS`: collectors running at once
+/// - `NEEMS_DATA_TEST_TYPE_CONCURRENCY`: per-test-type limits as `type=limit`
+///   pairs, e.g. `ping=8,exec=2`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConcurrencyConfig {
+    pub max_concurrent: usize,
+    pub per_test_type: HashMap<String, usize>,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 32,
+            per_test_type: HashMap::new(),
+        }
+    }
+}
+
+impl ConcurrencyConfig {
+    /// Build a config from the environment, falling back to [`Default`] for
+    /// anything missing or invalid.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+
+        Self {
+            max_concurrent: env::var("NEEMS_DATA_MAX_CONCURRENT_COLLECTORS")
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok())
+                .filter(|&v| v > 0)
+                .unwrap_or(defaults.max_concurrent),
+            per_test_type: env::var("NEEMS_DATA_TEST_TYPE_CONCURRENCY")
+                .map(|v| Self::parse_per_test_type(&v))
+                .unwrap_or(defaults.per_test_type),
+        }
+    }
+
+    /// Parse `type=limit` pairs separated by commas. Unknown test types and
+    /// malformed or zero limits are ignored.
+    pub fn parse_per_test_type(spec: &str) -> HashMap<String, usize> {
+        spec.split(',')
+            .filter_map(|pair| {
+                let (test_type, limit) = pair.split_once('=')?;
+                let test_type = test_type.trim().parse::<TestType>().ok()?;
+                let limit = limit.trim().parse::<usize>().ok().filter(|&l| l > 0)?;
+                Some((test_type.as_str().to_string(), limit))
+            })
+            .collect()
+    }
+}
+
+/// Snapshot of collector concurrency.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CollectorMetrics {
+    /// Collectors currently running
+    pub running: usize,
+    /// Collector tasks waiting for a permit
+    pub queued: usize,
+    /// Waiting tasks by test type
+    pub queued_by_test_type: HashMap<String, usize>,
+}
+
+/// Semaphores enforcing [`ConcurrencyConfig`].
+pub struct CollectorLimits {
+    global: Arc<Semaphore>,
+    per_test_type: Mutex<HashMap<String, Arc<Semaphore>>>,
+    /// The limits the semaphores are currently sized for
+    config: Mutex<ConcurrencyConfig>,
+    running: Arc<AtomicUsize>,
+    queued: Mutex<HashMap<String, usize>>,
+}
+
+/// Held by a running collector; releases its permits when dropped.
+pub struct CollectorPermit {
+    _global: OwnedSemaphorePermit,
+    _test_type: Option<OwnedSemaphorePermit>,
+    running: Arc<AtomicUsize>,
+}
+
+impl Drop for CollectorPermit {
+    fn drop(&mut self) {
+        self.running.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl CollectorLimits {
+    pub fn new(config: &ConcurrencyConfig) -> Self {
+        Self {
+            global: Arc::new(Semaphore::new(config.max_concurrent.max(1))),
+            per_test_type: Mutex::new(
+                config
+                    .per_test_type
+                    .iter()
+                    .map(|(test_type, &limit)| (test_type.clone(), Arc::new(Semaphore::new(limit))))
+                    .collect(),
+            ),
+            config: Mutex::new(config.clone()),
+            running: Arc::new(AtomicUsize::new(0)),
+            queued: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Resize the limits to `config` without disturbing running collectors.
+    ///
+    /// Raised limits take effect at once. Lowered ones retire permits as
+    /// running collectors release them, so no more than the new limit run
+    /// once those finish. Types that gain a limit get a fresh semaphore, so
+    /// collectors of that type already running don't count against it.
+    pub fn reconfigure(&self, config: &ConcurrencyConfig) {
+        let mut current = self.config.lock().unwrap();
+        resize(&self.global, current.max_concurrent.max(1), config.max_concurrent.max(1));
+
+        let mut semaphores = self.per_test_type.lock().unwrap();
+        semaphores.retain(|test_type, _| config.per_test_type.contains_key(test_type));
+        for (test_type, &limit) in &config.per_test_type {
+            match (semaphores.get(test_type), current.per_test_type.get(test_type)) {
+                (Some(semaphore), Some(&old)) => resize(semaphore, old, limit),
+                _ => {
+                    semaphores.insert(test_type.clone(), Arc::new(Semaphore::new(limit)));
+                }
+            }
+        }
+        *current = config.clone();
+    }
+
+    /// Global permits not currently held. The reader loop dispatches at most
+    /// this many sources per pass so that, when saturated, the next free slot
+    /// goes to whichever source ranks highest at that moment.
+    pub fn available(&self) -> usize {
+        self.global.available_permits()
+    }
+
+    /// Wait for permission to run a collector of `test_type`.
+    ///
+    /// The test-type permit is taken before the global one so a saturated
+    /// type waits without holding a global slot other types could use.
+    pub async fn acquire(&self, test_type: &TestType) -> CollectorPermit {
+        let key = test_type.as_str();
+        *self.queued.lock().unwrap().entry(key.to_string()).or_default() += 1;
+
+        let test_type_semaphore = self.per_test_type.lock().unwrap().get(key).cloned();
+        let test_type_permit = match test_type_semaphore {
+            Some(semaphore) => {
+                Some(semaphore.acquire_owned().await.expect("semaphore is never closed"))
+            }
+            None => None,
+        };
+        let global_permit =
+            self.global.clone().acquire_owned().await.expect("semaphore is never closed");
+
+        {
+            let mut queued = self.queued.lock().unwrap();
+            if let Some(count) = queued.get_mut(key) {
+                *count -= 1;
+                if *count == 0 {
+                    queued.remove(key);
+                }
+            }
+        }
+        self.running.fetch_add(1, Ordering::Relaxed);
+
+        CollectorPermit {
+            _global: global_permit,
+            _test_type: test_type_permit,
+            running: self.running.clone(),
+        }
+    }
+
+    /// Current running/queued counts.
+    pub fn metrics(&self) -> CollectorMetrics {
+        let queued_by_test_type = self.queued.lock().unwrap().clone();
+        CollectorMetrics {
+            running: self.running.load(Ordering::Relaxed),
+            queued: queued_by_test_type.values().sum(),
+            queued_by_test_type,
+        }
+    }
+}
+
+/// Change a semaphore sized for `from` permits to `to`.
+fn resize(semaphore: &Arc<Semaphore>, from: usize, to: usize) {
+    if to > from {
+        semaphore.add_permits(to - from);
+    } else if to < from {
+        let excess = from - to;
+        let held = excess - semaphore.forget_permits(excess);
+        if held > 0 {
+            // Queued ahead of new acquirers, so the held permits are retired
+            // as they are released
+            let semaphore = semaphore.clone();
+            tokio::spawn(async move {
+                if let Ok(permits) = semaphore.acquire_many_owned(held as u32).await {
+                    permits.forget();
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_per_test_type() {
+        let limits =
+            ConcurrencyConfig::parse_per_test_type("ping=8, exec=2,bogus=3,disk_space=0,x");
+        assert_eq!(limits, HashMap::from([("ping".to_string(), 8), ("exec".to_string(), 2)]));
+    }
+
+    #[tokio::test]
+    async fn test_per_test_type_limit_queues_excess() {
+        let config = ConcurrencyConfig {
+            max_concurrent: 10,
+            per_test_type: HashMap::from([("ping".to_string(), 2)]),
+        };
+        let limits = Arc::new(CollectorLimits::new(&config));
+
+        let first = limits.acquire(&TestType::Ping).await;
+        let _second = limits.acquire(&TestType::Ping).await;
+
+        let waiting = tokio::spawn({
+            let limits = limits.clone();
+            async move {
+                let _permit = limits.acquire(&TestType::Ping).await;
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        // Other test types are unaffected by the ping limit
+        let _disk = limits.acquire(&TestType::DiskSpace).await;
+
+        let metrics = limits.metrics();
+        assert_eq!(metrics.running, 3);
+        assert_eq!(metrics.queued, 1);
+        assert_eq!(metrics.queued_by_test_type.get("ping"), Some(&1));
+        assert_eq!(limits.available(), 7);
+
+        drop(first);
+        waiting.await.unwrap();
+        assert_eq!(limits.metrics().queued, 0);
+        assert_eq!(limits.metrics().running, 2);
+    }
+
+    #[tokio::test]
+    async fn test_global_limit() {
+        let config = ConcurrencyConfig {
+            max_concurrent: 1,
+            per_test_type: HashMap::new(),
+        };
+        let limits = CollectorLimits::new(&config);
+
+        let permit = limits.acquire(&TestType::Memory).await;
+        assert_eq!(limits.available(), 0);
+        let blocked =
+            tokio::time::timeout(Duration::from_millis(20), limits.acquire(&TestType::CpuLoad))
+                .await;
+        assert!(blocked.is_err(), "second collector should wait for the global permit");
+
+        drop(permit);
+        assert_eq!(limits.available(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reconfigure_resizes_limits() {
+        let limits = CollectorLimits::new(&ConcurrencyConfig {
+            max_concurrent: 3,
+            per_test_type: HashMap::new(),
+        });
+        let first = limits.acquire(&TestType::Memory).await;
+        let second = limits.acquire(&TestType::Memory).await;
+
+        limits.reconfigure(&ConcurrencyConfig {
+            max_concurrent: 5,
+            per_test_type: HashMap::new(),
+        });
+        assert_eq!(limits.available(), 3);
+
+        // Lowering below what's running retires permits as they're released
+        limits.reconfigure(&ConcurrencyConfig {
+            max_concurrent: 1,
+            per_test_type: HashMap::from([("ping".to_string(), 1)]),
+        });
+        assert_eq!(limits.available(), 0);
+        drop(first);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(limits.available(), 0);
+        drop(second);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(limits.available(), 1);
+
+        // The new per-type limit applies
+        let _ping = limits.acquire(&TestType::Ping).await;
+        let blocked =
+            tokio::time::timeout(Duration::from_millis(20), limits.acquire(&TestType::Ping)).await;
+        assert!(blocked.is_err(), "second ping should wait for the new per-type limit");
+    }
+}
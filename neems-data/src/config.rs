@@ -0,0 +1,1030 @@
+//! Settings for the neems-data service.
+//!
+//! Settings come from an optional TOML file (`--config` or
+//! `NEEMS_DATA_CONFIG`), overridden by the environment variables each module
+//! already reads, over the built-in defaults. Unlike the `from_env`
+//! constructors, which skip values they can't use, [`Settings::load`] rejects
+//! unknown keys and bad values, listing every problem with the file key or
+//! variable it came from, so a typo fails at startup instead of silently
+//! falling back to a default.
+//!
+//! ```toml
+//! database_path = "/var/lib/neems/site-data.sqlite"  # SITE_DATABASE_URL
+//! log_level = "info,neems_data=debug"                 # RUST_LOG
+//!
+//! [writer]
+//! queue_capacity = 1024        # NEEMS_DATA_QUEUE_CAPACITY
+//! batch_size = 500             # NEEMS_DATA_BATCH_SIZE
+//! flush_interval_ms = 1000     # NEEMS_DATA_FLUSH_INTERVAL_MS
+//!
+//! [reader]
+//! max_dispatch_per_tick = 64       # NEEMS_DATA_MAX_DISPATCH_PER_TICK
+//! priority_aging_secs = 30         # NEEMS_DATA_PRIORITY_AGING_SECS
+//! clock_skew_threshold_secs = 30   # NEEMS_DATA_CLOCK_SKEW_THRESHOLD_SECS
+//!
+//! [concurrency]
+//! max_concurrent_collectors = 32   # NEEMS_DATA_MAX_CONCURRENT_COLLECTORS
+//! per_test_type = { ping = 8 }     # NEEMS_DATA_TEST_TYPE_CONCURRENCY
+//!
+//! [sync]
+//! url = "https://central.example.com"   # NEEMS_SYNC_URL
+//! origin = "site-12"                    # NEEMS_SYNC_ORIGIN
+//! email = "sync@example.com"            # NEEMS_SYNC_EMAIL
+//! password = "..."                      # NEEMS_SYNC_PASSWORD
+//! batch_size = 500                      # NEEMS_SYNC_BATCH_SIZE
+//! interval_secs = 60                    # NEEMS_SYNC_INTERVAL_SECS
+//! reconcile_days = 7                    # NEEMS_SYNC_RECONCILE_DAYS
+//! config_interval_secs = 300            # NEEMS_SYNC_CONFIG_INTERVAL_SECS (0 disables)
+//!
+//! [prometheus]
+//! url = "http://prometheus:9090/api/v1/write"   # NEEMS_PROMETHEUS_URL
+//! username = "neems"                            # NEEMS_PROMETHEUS_USERNAME
+//! password = "..."                              # NEEMS_PROMETHEUS_PASSWORD
+//! bearer_token = "..."                          # NEEMS_PROMETHEUS_BEARER_TOKEN
+//! prefix = "neems"                              # NEEMS_PROMETHEUS_PREFIX
+//! labels = { site = "north" }                   # NEEMS_PROMETHEUS_LABELS
+//! batch_size = 500                              # NEEMS_PROMETHEUS_BATCH_SIZE
+//! interval_secs = 15                            # NEEMS_PROMETHEUS_INTERVAL_SECS
+//!
+//! [mqtt]
+//! url = "mqtts://broker.local:8883"           # NEEMS_MQTT_URL
+//! client_id = "neems-site-12"                 # NEEMS_MQTT_CLIENT_ID
+//! username = "neems"                          # NEEMS_MQTT_USERNAME
+//! password = "..."                            # NEEMS_MQTT_PASSWORD
+//! ca_file = "/etc/neems/broker-ca.pem"        # NEEMS_MQTT_CA_FILE
+//! topic = "neems/{site_id}/{source}/{event}"  # NEEMS_MQTT_TOPIC
+//! qos = 1                                     # NEEMS_MQTT_QOS
+//! retain = true                               # NEEMS_MQTT_RETAIN
+//! readings = ["rtac", "simulator"]            # NEEMS_MQTT_READINGS
+//! batch_size = 500                            # NEEMS_MQTT_BATCH_SIZE
+//! interval_secs = 1                           # NEEMS_MQTT_INTERVAL_SECS
+//!
+//! [snmp]
+//! targets = ["noc.example.com:162"]   # NEEMS_SNMP_TARGETS
+//! version = "3"                       # NEEMS_SNMP_VERSION, 2c or 3
+//! community = "public"                # NEEMS_SNMP_COMMUNITY
+//! user = "neems"                      # NEEMS_SNMP_USER
+//! auth_protocol = "sha256"            # NEEMS_SNMP_AUTH_PROTOCOL
+//! auth_password = "..."               # NEEMS_SNMP_AUTH_PASSWORD
+//! priv_password = "..."               # NEEMS_SNMP_PRIV_PASSWORD
+//! engine_id = "80001f880473697465"    # NEEMS_SNMP_ENGINE_ID
+//! batch_size = 500                    # NEEMS_SNMP_BATCH_SIZE
+//! interval_secs = 1                   # NEEMS_SNMP_INTERVAL_SECS
+//!
+//! [maintenance]
+//! window = "02:00-04:00"   # NEEMS_DATA_MAINTENANCE_WINDOW, or "off"
+//! interval_hours = 24      # NEEMS_DATA_MAINTENANCE_INTERVAL_HOURS
+//!
+//! [logs]
+//! listen = "0.0.0.0:9180"                  # NEEMS_LOGS_LISTEN
+//! token = "..."                            # NEEMS_LOGS_TOKEN
+//! token_file = "/etc/neems/logs-token"     # NEEMS_LOGS_TOKEN_FILE
+//! buffer_size = 5000                       # NEEMS_LOGS_BUFFER_SIZE
+//! ```
+
+use std::{collections::HashMap, env, fs, path::Path, str::FromStr, time::Duration};
+
+use serde::Deserialize;
+
+use crate::{
+    DataResult,
+    collectors::TestType,
+    concurrency::ConcurrencyConfig,
+    encryption,
+    logs::{self, LogsConfig},
+    maintenance::{MaintenanceConfig, MaintenanceWindow},
+    mqtt::{self, MqttConfig},
+    prometheus::{self, PrometheusConfig},
+    scheduler::ReaderConfig,
+    snmp::{self, SnmpConfig, pdu},
+    sync::SyncConfig,
+    writer::WriterConfig,
+};
+
+/// Environment variable naming the config file when `--config` isn't given.
+pub const CONFIG_ENV: &str = "NEEMS_DATA_CONFIG";
+
+const DEFAULT_DATABASE_PATH: &str = "site-data.sqlite";
+const DEFAULT_LOG_LEVEL: &str = "info";
+
+/// The config file as written; every key is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileConfig {
+    pub database_path: Option<String>,
+    pub log_level: Option<String>,
+    #[serde(default)]
+    pub writer: WriterSection,
+    #[serde(default)]
+    pub reader: ReaderSection,
+    #[serde(default)]
+    pub concurrency: ConcurrencySection,
+    #[serde(default)]
+    pub sync: SyncSection,
+    #[serde(default)]
+    pub prometheus: PrometheusSection,
+    #[serde(default)]
+    pub mqtt: MqttSection,
+    #[serde(default)]
+    pub snmp: SnmpSection,
+    #[serde(default)]
+    pub maintenance: MaintenanceSection,
+    #[serde(default)]
+    pub logs: LogsSection,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WriterSection {
+    pub queue_capacity: Option<u64>,
+    pub batch_size: Option<u64>,
+    pub flush_interval_ms: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReaderSection {
+    pub max_dispatch_per_tick: Option<u64>,
+    pub priority_aging_secs: Option<u64>,
+    pub clock_skew_threshold_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConcurrencySection {
+    pub max_concurrent_collectors: Option<u64>,
+    pub per_test_type: Option<HashMap<String, u64>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SyncSection {
+    pub url: Option<String>,
+    pub origin: Option<String>,
+    pub email: Option<String>,
+    pub password: Option<String>,
+    pub batch_size: Option<u64>,
+    pub interval_secs: Option<u64>,
+    pub reconcile_days: Option<u32>,
+    pub config_interval_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PrometheusSection {
+    pub url: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub bearer_token: Option<String>,
+    pub prefix: Option<String>,
+    pub labels: Option<HashMap<String, String>>,
+    pub batch_size: Option<u64>,
+    pub interval_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttSection {
+    pub url: Option<String>,
+    pub client_id: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub ca_file: Option<String>,
+    pub topic: Option<String>,
+    pub qos: Option<u8>,
+    pub retain: Option<bool>,
+    pub readings: Option<Vec<String>>,
+    pub batch_size: Option<u64>,
+    pub interval_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SnmpSection {
+    pub targets: Option<Vec<String>>,
+    pub version: Option<String>,
+    pub community: Option<String>,
+    pub user: Option<String>,
+    pub auth_protocol: Option<String>,
+    pub auth_password: Option<String>,
+    pub priv_password: Option<String>,
+    pub engine_id: Option<String>,
+    pub batch_size: Option<u64>,
+    pub interval_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaintenanceSection {
+    pub window: Option<String>,
+    pub interval_hours: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogsSection {
+    pub listen: Option<String>,
+    pub token: Option<String>,
+    pub token_file: Option<String>,
+    pub buffer_size: Option<u64>,
+}
+
+/// Resolved settings for the service.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// SQLite database file, without a `sqlite://` scheme
+    pub database_path: String,
+    /// `tracing` filter directives, e.g. `info,neems_data=debug`
+    pub log_level: String,
+    pub writer: WriterConfig,
+    pub reader: ReaderConfig,
+    /// `None` when no sync URL is configured
+    pub sync: Option<SyncConfig>,
+    /// `None` when no remote-write URL is configured
+    pub prometheus: Option<PrometheusConfig>,
+    /// `None` when no broker URL is configured
+    pub mqtt: Option<MqttConfig>,
+    /// `None` when no trap receivers are configured
+    pub snmp: Option<SnmpConfig>,
+    pub maintenance: MaintenanceConfig,
+    /// `None` when no log listener address is configured
+    pub logs: Option<LogsConfig>,
+}
+
+impl Settings {
+    /// Load the config file at `path`, if any, and apply environment
+    /// overrides.
+    pub fn load(path: Option<&Path>) -> DataResult<Self> {
+        let file = match path {
+            Some(path) => {
+                let text = fs::read_to_string(path)
+                    .map_err(|e| format!("Cannot read config file {}: {}", path.display(), e))?;
+                Some((path.display().to_string(), text))
+            }
+            None => None,
+        };
+        Self::resolve(file.as_ref().map(|(name, text)| (name.as_str(), text.as_str())), |key| {
+            env::var(key).ok()
+        })
+    }
+
+    /// Resolve settings from a config file's `(name, contents)` and an
+    /// environment lookup.
+    pub fn resolve(
+        file: Option<(&str, &str)>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> DataResult<Self> {
+        let (file_name, config) = match file {
+            Some((name, text)) => {
+                let config: FileConfig = toml::from_str(text)
+                    .map_err(|e| format!("Invalid config file {}: {}", name, e))?;
+                (name, config)
+            }
+            None => ("the config file", FileConfig::default()),
+        };
+
+        let mut r = Resolver {
+            file_name,
+            env: &env,
+            problems: Vec::new(),
+        };
+        let settings = r.settings(config);
+        if r.problems.is_empty() {
+            Ok(settings)
+        } else {
+            Err(format!("Invalid configuration:\n  - {}", r.problems.join("\n  - ")).into())
+        }
+    }
+}
+
+/// Merges file values with environment overrides, collecting problems.
+struct Resolver<'a, E> {
+    file_name: &'a str,
+    env: &'a E,
+    problems: Vec<String>,
+}
+
+impl<E: Fn(&str) -> Option<String>> Resolver<'_, E> {
+    fn settings(&mut self, config: FileConfig) -> Settings {
+        let database_path = self
+            .string("SITE_DATABASE_URL", config.database_path)
+            .map(|path| path.strip_prefix("sqlite://").map(str::to_string).unwrap_or(path))
+            .unwrap_or_else(|| DEFAULT_DATABASE_PATH.to_string());
+        if database_path.trim().is_empty() {
+            self.problem("database_path", "SITE_DATABASE_URL", "must not be empty");
+        }
+
+        let log_level = self
+            .string("RUST_LOG", config.log_level)
+            .unwrap_or_else(|| DEFAULT_LOG_LEVEL.to_string());
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&log_level) {
+            self.problem(
+                "log_level",
+                "RUST_LOG",
+                &format!("invalid filter '{}': {}", log_level, e),
+            );
+        }
+
+        Settings {
+            database_path,
+            log_level,
+            writer: self.writer(config.writer),
+            reader: self.reader(config.reader, config.concurrency),
+            sync: self.sync(config.sync),
+            prometheus: self.prometheus(config.prometheus),
+            mqtt: self.mqtt(config.mqtt),
+            snmp: self.snmp(config.snmp),
+            maintenance: self.maintenance(config.maintenance),
+            logs: self.logs(config.logs),
+        }
+    }
+
+    fn writer(&mut self, section: WriterSection) -> WriterConfig {
+        let defaults = WriterConfig::default();
+        WriterConfig {
+            queue_capacity: self
+                .positive(
+                    "writer.queue_capacity",
+                    "NEEMS_DATA_QUEUE_CAPACITY",
+                    section.queue_capacity,
+                )
+                .map_or(defaults.queue_capacity, |v| v as usize),
+            batch_size: self
+                .positive("writer.batch_size", "NEEMS_DATA_BATCH_SIZE", section.batch_size)
+                .map_or(defaults.batch_size, |v| v as usize),
+            flush_interval: self
+                .positive(
+                    "writer.flush_interval_ms",
+                    "NEEMS_DATA_FLUSH_INTERVAL_MS",
+                    section.flush_interval_ms,
+                )
+                .map_or(defaults.flush_interval, Duration::from_millis),
+        }
+    }
+
+    fn reader(&mut self, section: ReaderSection, concurrency: ConcurrencySection) -> ReaderConfig {
+        let defaults = ReaderConfig::default();
+        ReaderConfig {
+            max_dispatch_per_tick: self
+                .positive(
+                    "reader.max_dispatch_per_tick",
+                    "NEEMS_DATA_MAX_DISPATCH_PER_TICK",
+                    section.max_dispatch_per_tick,
+                )
+                .map_or(defaults.max_dispatch_per_tick, |v| v as usize),
+            aging_interval: self
+                .positive(
+                    "reader.priority_aging_secs",
+                    "NEEMS_DATA_PRIORITY_AGING_SECS",
+                    section.priority_aging_secs,
+                )
+                .map_or(defaults.aging_interval, Duration::from_secs),
+            clock_skew_threshold: self
+                .positive(
+                    "reader.clock_skew_threshold_secs",
+                    "NEEMS_DATA_CLOCK_SKEW_THRESHOLD_SECS",
+                    section.clock_skew_threshold_secs,
+                )
+                .map_or(defaults.clock_skew_threshold, Duration::from_secs),
+            concurrency: self.concurrency(concurrency),
+        }
+    }
+
+    fn concurrency(&mut self, section: ConcurrencySection) -> ConcurrencyConfig {
+        let defaults = ConcurrencyConfig::default();
+        let max_concurrent = self
+            .positive(
+                "concurrency.max_concurrent_collectors",
+                "NEEMS_DATA_MAX_CONCURRENT_COLLECTORS",
+                section.max_concurrent_collectors,
+            )
+            .map_or(defaults.max_concurrent, |v| v as usize);
+
+        const KEY: &str = "concurrency.per_test_type";
+        const VAR: &str = "NEEMS_DATA_TEST_TYPE_CONCURRENCY";
+        let pairs: Vec<(String, String)> = match self.env_value(VAR) {
+            Some(spec) => spec
+                .split(',')
+                .filter(|pair| !pair.trim().is_empty())
+                .filter_map(|pair| match pair.split_once('=') {
+                    Some((test_type, limit)) => {
+                        Some((test_type.trim().to_string(), limit.trim().to_string()))
+                    }
+                    None => {
+                        self.problems.push(format!(
+                            "{}: '{}' is not a type=limit pair",
+                            VAR,
+                            pair.trim()
+                        ));
+                        None
+                    }
+                })
+                .collect(),
+            None => section
+                .per_test_type
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(test_type, limit)| (test_type, limit.to_string()))
+                .collect(),
+        };
+        let mut per_test_type = HashMap::new();
+        for (test_type, limit) in pairs {
+            let test_type = match test_type.parse::<TestType>() {
+                Ok(test_type) => test_type,
+                Err(e) => {
+                    self.problem(KEY, VAR, &e);
+                    continue;
+                }
+            };
+            match limit.parse::<usize>() {
+                Ok(limit) if limit > 0 => {
+                    per_test_type.insert(test_type.as_str().to_string(), limit);
+                }
+                _ => self.problem(
+                    KEY,
+                    VAR,
+                    &format!(
+                        "limit for {} must be at least 1, got '{}'",
+                        test_type.as_str(),
+                        limit
+                    ),
+                ),
+            }
+        }
+
+        ConcurrencyConfig { max_concurrent, per_test_type }
+    }
+
+    fn sync(&mut self, section: SyncSection) -> Option<SyncConfig> {
+        let url = self.string("NEEMS_SYNC_URL", section.url);
+        let origin = self.string("NEEMS_SYNC_ORIGIN", section.origin);
+        let email = self.string("NEEMS_SYNC_EMAIL", section.email);
+        let password = self.string("NEEMS_SYNC_PASSWORD", section.password);
+        let batch_size =
+            self.positive("sync.batch_size", "NEEMS_SYNC_BATCH_SIZE", section.batch_size);
+        let interval =
+            self.positive("sync.interval_secs", "NEEMS_SYNC_INTERVAL_SECS", section.interval_secs);
+        let reconcile_days =
+            self.parsed("sync.reconcile_days", "NEEMS_SYNC_RECONCILE_DAYS", section.reconcile_days);
+        let config_interval = self.parsed(
+            "sync.config_interval_secs",
+            "NEEMS_SYNC_CONFIG_INTERVAL_SECS",
+            section.config_interval_secs,
+        );
+
+        let url = url?;
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            self.problem(
+                "sync.url",
+                "NEEMS_SYNC_URL",
+                &format!("'{}' is not an http:// or https:// URL", url),
+            );
+        }
+        if email.is_some() != password.is_some() {
+            self.problems.push(
+                "sync.email and sync.password (NEEMS_SYNC_EMAIL / NEEMS_SYNC_PASSWORD) must be set together"
+                    .to_string(),
+            );
+        }
+
+        let origin =
+            origin.or_else(sysinfo::System::host_name).unwrap_or_else(|| "edge".to_string());
+        let mut config = SyncConfig::new(url, origin);
+        config.email = email;
+        config.password = password;
+        if let Some(batch_size) = batch_size {
+            config.batch_size = batch_size as usize;
+        }
+        if let Some(interval) = interval {
+            config.interval = Duration::from_secs(interval);
+        }
+        if let Some(days) = reconcile_days {
+            config.reconcile_days = days;
+        }
+        if let Some(secs) = config_interval {
+            config.config_interval = (secs > 0).then(|| Duration::from_secs(secs));
+        }
+        Some(config)
+    }
+
+    fn prometheus(&mut self, section: PrometheusSection) -> Option<PrometheusConfig> {
+        let url = self.string("NEEMS_PROMETHEUS_URL", section.url);
+        let username = self.string("NEEMS_PROMETHEUS_USERNAME", section.username);
+        let password = self.string("NEEMS_PROMETHEUS_PASSWORD", section.password);
+        let bearer_token = self.string("NEEMS_PROMETHEUS_BEARER_TOKEN", section.bearer_token);
+        let prefix = self.string("NEEMS_PROMETHEUS_PREFIX", section.prefix);
+        let batch_size = self.positive(
+            "prometheus.batch_size",
+            "NEEMS_PROMETHEUS_BATCH_SIZE",
+            section.batch_size,
+        );
+        let interval = self.positive(
+            "prometheus.interval_secs",
+            "NEEMS_PROMETHEUS_INTERVAL_SECS",
+            section.interval_secs,
+        );
+
+        const KEY: &str = "prometheus.labels";
+        const VAR: &str = "NEEMS_PROMETHEUS_LABELS";
+        let labels = match self.env_value(VAR) {
+            Some(spec) => prometheus::parse_labels(&spec),
+            None => {
+                let mut labels: Vec<_> = section.labels.unwrap_or_default().into_iter().collect();
+                labels.sort();
+                let spec: Vec<String> =
+                    labels.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+                prometheus::parse_labels(&spec.join(",")).map(|_| labels)
+            }
+        };
+        let labels = labels.unwrap_or_else(|e| {
+            self.problem(KEY, VAR, &e);
+            Vec::new()
+        });
+
+        let url = url?;
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            self.problem(
+                "prometheus.url",
+                "NEEMS_PROMETHEUS_URL",
+                &format!("'{}' is not an http:// or https:// URL", url),
+            );
+        }
+        if bearer_token.is_some() && (username.is_some() || password.is_some()) {
+            self.problems.push(
+                "prometheus.bearer_token (NEEMS_PROMETHEUS_BEARER_TOKEN) can't be combined with a username and password"
+                    .to_string(),
+            );
+        }
+        if password.is_some() && username.is_none() {
+            self.problem(
+                "prometheus.password",
+                "NEEMS_PROMETHEUS_PASSWORD",
+                "needs a username (NEEMS_PROMETHEUS_USERNAME)",
+            );
+        }
+
+        let mut config = PrometheusConfig::new(url);
+        config.username = username;
+        config.password = password;
+        config.bearer_token = bearer_token;
+        config.labels = labels;
+        if let Some(prefix) = prefix {
+            if prefix.is_empty() || prometheus::is_metric_name(&prefix) {
+                config.prefix = prefix;
+            } else {
+                self.problem(
+                    "prometheus.prefix",
+                    "NEEMS_PROMETHEUS_PREFIX",
+                    &format!("'{}' is not a valid metric name", prefix),
+                );
+            }
+        }
+        if let Some(batch_size) = batch_size {
+            config.batch_size = batch_size as usize;
+        }
+        if let Some(interval) = interval {
+            config.interval = Duration::from_secs(interval);
+        }
+        Some(config)
+    }
+
+    fn mqtt(&mut self, section: MqttSection) -> Option<MqttConfig> {
+        let url = self.string("NEEMS_MQTT_URL", section.url);
+        let client_id = self.string("NEEMS_MQTT_CLIENT_ID", section.client_id);
+        let username = self.string("NEEMS_MQTT_USERNAME", section.username);
+        let password = self.string("NEEMS_MQTT_PASSWORD", section.password);
+        let ca_file = self.string("NEEMS_MQTT_CA_FILE", section.ca_file);
+        let topic = self.string("NEEMS_MQTT_TOPIC", section.topic);
+        let batch_size =
+            self.positive("mqtt.batch_size", "NEEMS_MQTT_BATCH_SIZE", section.batch_size);
+        let interval =
+            self.positive("mqtt.interval_secs", "NEEMS_MQTT_INTERVAL_SECS", section.interval_secs);
+        let readings = match self.env_value("NEEMS_MQTT_READINGS") {
+            Some(spec) => Some(mqtt::parse_list(&spec)),
+            None => section.readings,
+        };
+
+        const QOS_KEY: &str = "mqtt.qos";
+        const QOS_VAR: &str = "NEEMS_MQTT_QOS";
+        let qos = match self.env_value(QOS_VAR).or(section.qos.map(|qos| qos.to_string())) {
+            Some(qos) => mqtt::parse_qos(&qos).map(Some).unwrap_or_else(|e| {
+                self.problem(QOS_KEY, QOS_VAR, &e);
+                None
+            }),
+            None => None,
+        };
+        const RETAIN_KEY: &str = "mqtt.retain";
+        const RETAIN_VAR: &str = "NEEMS_MQTT_RETAIN";
+        let retain = match self.env_value(RETAIN_VAR) {
+            Some(value) => mqtt::parse_bool(&value).or_else(|| {
+                self.problem(
+                    RETAIN_KEY,
+                    RETAIN_VAR,
+                    &format!("expected true or false, got '{}'", value),
+                );
+                None
+            }),
+            None => section.retain,
+        };
+
+        let url = url?;
+        if !["mqtt://", "mqtts://", "tcp://", "ssl://"]
+            .iter()
+            .any(|scheme| url.starts_with(scheme))
+        {
+            self.problem(
+                "mqtt.url",
+                "NEEMS_MQTT_URL",
+                &format!("'{}' is not an mqtt:// or mqtts:// URL", url),
+            );
+        }
+        if password.is_some() && username.is_none() {
+            self.problem(
+                "mqtt.password",
+                "NEEMS_MQTT_PASSWORD",
+                "needs a username (NEEMS_MQTT_USERNAME)",
+            );
+        }
+
+        let mut config = MqttConfig::new(url);
+        if let Some(client_id) = client_id {
+            config.client_id = client_id;
+        }
+        config.username = username;
+        config.password = password;
+        config.ca_file = ca_file.map(Into::into);
+        if let Some(topic) = topic {
+            match mqtt::check_topic(&topic) {
+                Ok(()) => config.topic = topic,
+                Err(e) => self.problem("mqtt.topic", "NEEMS_MQTT_TOPIC", &e),
+            }
+        }
+        if let Some(qos) = qos {
+            config.qos = qos;
+        }
+        if let Some(retain) = retain {
+            config.retain = retain;
+        }
+        if let Some(readings) = readings {
+            config.readings = readings;
+        }
+        if let Some(batch_size) = batch_size {
+            config.batch_size = batch_size as usize;
+        }
+        if let Some(interval) = interval {
+            config.interval = Duration::from_secs(interval);
+        }
+        Some(config)
+    }
+
+    fn snmp(&mut self, section: SnmpSection) -> Option<SnmpConfig> {
+        let community = self.string("NEEMS_SNMP_COMMUNITY", section.community);
+        let user = self.string("NEEMS_SNMP_USER", section.user);
+        let auth_password = self.string("NEEMS_SNMP_AUTH_PASSWORD", section.auth_password);
+        let priv_password = self.string("NEEMS_SNMP_PRIV_PASSWORD", section.priv_password);
+        let batch_size =
+            self.positive("snmp.batch_size", "NEEMS_SNMP_BATCH_SIZE", section.batch_size);
+        let interval =
+            self.positive("snmp.interval_secs", "NEEMS_SNMP_INTERVAL_SECS", section.interval_secs);
+
+        let version = self.string("NEEMS_SNMP_VERSION", section.version).and_then(|version| {
+            snmp::parse_version(&version)
+                .map_err(|e| self.problem("snmp.version", "NEEMS_SNMP_VERSION", &e))
+                .ok()
+        });
+        let auth_protocol = self
+            .string("NEEMS_SNMP_AUTH_PROTOCOL", section.auth_protocol)
+            .and_then(|protocol| {
+                protocol
+                    .parse::<pdu::AuthProtocol>()
+                    .map_err(|e| self.problem("snmp.auth_protocol", "NEEMS_SNMP_AUTH_PROTOCOL", &e))
+                    .ok()
+            });
+        const ENGINE_ID_KEY: &str = "snmp.engine_id";
+        const ENGINE_ID_VAR: &str = "NEEMS_SNMP_ENGINE_ID";
+        let engine_id = self.string(ENGINE_ID_VAR, section.engine_id).and_then(|engine_id| {
+            pdu::parse_hex(&engine_id)
+                .and_then(|engine_id| snmp::check_engine_id(&engine_id).map(|_| engine_id))
+                .map_err(|e| self.problem(ENGINE_ID_KEY, ENGINE_ID_VAR, &e))
+                .ok()
+        });
+
+        const TARGETS_KEY: &str = "snmp.targets";
+        const TARGETS_VAR: &str = "NEEMS_SNMP_TARGETS";
+        let spec = match self.env_value(TARGETS_VAR) {
+            Some(spec) => spec,
+            None => section.targets?.join(","),
+        };
+        let targets = snmp::parse_targets(&spec).unwrap_or_else(|e| {
+            self.problem(TARGETS_KEY, TARGETS_VAR, &e);
+            Vec::new()
+        });
+
+        let mut config = SnmpConfig::new(targets);
+        if let Some(version) = version {
+            config.version = version;
+        }
+        if let Some(community) = community {
+            config.community = community;
+        }
+        config.user = user;
+        if let Some(auth_protocol) = auth_protocol {
+            config.auth_protocol = auth_protocol;
+        }
+        config.auth_password = auth_password;
+        config.priv_password = priv_password;
+        if let Some(engine_id) = engine_id {
+            config.engine_id = engine_id;
+        }
+        for problem in config.check_v3() {
+            self.problems.push(format!("snmp: {}", problem));
+        }
+        if let Some(batch_size) = batch_size {
+            config.batch_size = batch_size as usize;
+        }
+        if let Some(interval) = interval {
+            config.interval = Duration::from_secs(interval);
+        }
+        Some(config)
+    }
+
+    fn maintenance(&mut self, section: MaintenanceSection) -> MaintenanceConfig {
+        let mut config = MaintenanceConfig::default();
+        const KEY: &str = "maintenance.window";
+        const VAR: &str = "NEEMS_DATA_MAINTENANCE_WINDOW";
+        if let Some(window) = self.string(VAR, section.window) {
+            if window.trim().eq_ignore_ascii_case("off") {
+                config.window = None;
+            } else {
+                match MaintenanceWindow::parse(&window) {
+                    Some(window) => config.window = Some(window),
+                    None => {
+                        self.problem(KEY, VAR, &format!("'{}' is not HH:MM-HH:MM or 'off'", window))
+                    }
+                }
+            }
+        }
+        if let Some(hours) = self.positive(
+            "maintenance.interval_hours",
+            "NEEMS_DATA_MAINTENANCE_INTERVAL_HOURS",
+            section.interval_hours,
+        ) {
+            config.interval = Duration::from_secs(hours * 60 * 60);
+        }
+        config
+    }
+
+    fn logs(&mut self, section: LogsSection) -> Option<LogsConfig> {
+        let buffer_size =
+            self.positive("logs.buffer_size", "NEEMS_LOGS_BUFFER_SIZE", section.buffer_size);
+        const LISTEN_KEY: &str = "logs.listen";
+        const LISTEN_VAR: &str = "NEEMS_LOGS_LISTEN";
+        let listen = self.string(LISTEN_VAR, section.listen)?;
+        let listen = listen
+            .trim()
+            .parse()
+            .map_err(|_| {
+                self.problem(LISTEN_KEY, LISTEN_VAR, &format!("'{}' is not host:port", listen))
+            })
+            .ok();
+
+        const TOKEN_FILE_VAR: &str = "NEEMS_LOGS_TOKEN_FILE";
+        let token = self.string("NEEMS_LOGS_TOKEN", section.token);
+        let token_file = self.string(TOKEN_FILE_VAR, section.token_file);
+        let token = match encryption::read_key(token, token_file.as_deref(), TOKEN_FILE_VAR) {
+            Ok(Some(token)) => Some(token),
+            Ok(None) => {
+                self.problem(
+                    LISTEN_KEY,
+                    LISTEN_VAR,
+                    "needs a token (NEEMS_LOGS_TOKEN or NEEMS_LOGS_TOKEN_FILE)",
+                );
+                None
+            }
+            Err(e) => {
+                self.problem("logs.token_file", TOKEN_FILE_VAR, &e.to_string());
+                None
+            }
+        };
+
+        Some(LogsConfig {
+            listen: listen?,
+            token: token?,
+            buffer_size: buffer_size.map_or(logs::DEFAULT_BUFFER_SIZE, |v| v as usize),
+        })
+    }
+
+    /// The variable's value, treating an empty one as unset.
+    fn env_value(&self, var: &str) -> Option<String> {
+        (self.env)(var).filter(|v| !v.trim().is_empty())
+    }
+
+    /// Record a problem with a setting that may have come from either place.
+    fn problem(&mut self, key: &str, var: &str, message: &str) {
+        let source = if self.env_value(var).is_some() {
+            var.to_string()
+        } else {
+            format!("{} in {}", key, self.file_name)
+        };
+        self.problems.push(format!("{}: {}", source, message));
+    }
+
+    fn string(&self, var: &str, file: Option<String>) -> Option<String> {
+        self.env_value(var).or(file)
+    }
+
+    /// A value from the environment, parsed, or else from the file.
+    fn parsed<T: FromStr>(&mut self, key: &str, var: &str, file: Option<T>) -> Option<T> {
+        match self.env_value(var) {
+            Some(value) => match value.trim().parse() {
+                Ok(value) => Some(value),
+                Err(_) => {
+                    self.problem(key, var, &format!("expected a whole number, got '{}'", value));
+                    None
+                }
+            },
+            None => file,
+        }
+    }
+
+    /// Like [`Self::parsed`], but zero is rejected.
+    fn positive(&mut self, key: &str, var: &str, file: Option<u64>) -> Option<u64> {
+        let value = self.parsed(key, var, file)?;
+        if value == 0 {
+            self.problem(key, var, "must be at least 1");
+            return None;
+        }
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(file: &str, env: &[(&str, &str)]) -> DataResult<Settings> {
+        let env: HashMap<String, String> =
+            env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Settings::resolve(Some(("neems-data.toml", file)), |key| env.get(key).cloned())
+    }
+
+    #[test]
+    fn test_defaults_without_file_or_env() {
+        let settings = Settings::resolve(None, |_| None).unwrap();
+        assert_eq!(settings.database_path, "site-data.sqlite");
+        assert_eq!(settings.log_level, "info");
+        assert_eq!(settings.writer.batch_size, WriterConfig::default().batch_size);
+        assert_eq!(settings.reader.concurrency.max_concurrent, 32);
+        assert!(settings.sync.is_none());
+        assert!(settings.prometheus.is_none());
+        assert!(settings.mqtt.is_none());
+        assert!(settings.snmp.is_none());
+        assert_eq!(settings.maintenance.window, MaintenanceConfig::default().window);
+        assert!(settings.logs.is_none());
+    }
+
+    #[test]
+    fn test_file_values_and_env_overrides() {
+        let file = r#"
+            database_path = "sqlite:///var/lib/neems/site.sqlite"
+            log_level = "debug"
+
+            [writer]
+            batch_size = 200
+            flush_interval_ms = 250
+
+            [concurrency]
+            per_test_type = { ping = 8, exec = 2 }
+
+            [sync]
+            url = "https://central.example.com/"
+            origin = "site-12"
+            interval_secs = 30
+
+            [prometheus]
+            url = "http://prometheus:9090/api/v1/write"
+            labels = { site = "north" }
+
+            [mqtt]
+            url = "mqtts://broker.local:8883"
+            topic = "scada/{site_id}/{event}/{source}"
+            readings = ["rtac"]
+
+            [snmp]
+            targets = ["noc.example.com", "10.0.0.5:1162"]
+            version = "3"
+            user = "neems"
+            auth_password = "authpass1"
+
+            [maintenance]
+            window = "off"
+
+            [logs]
+            listen = "0.0.0.0:9180"
+            token = "logs-token"
+        "#;
+        let settings = resolve(
+            file,
+            &[
+                ("NEEMS_DATA_BATCH_SIZE", "100"),
+                ("NEEMS_SYNC_ORIGIN", "site-13"),
+                ("NEEMS_MQTT_QOS", "2"),
+                ("NEEMS_SNMP_AUTH_PROTOCOL", "sha256"),
+                ("NEEMS_LOGS_BUFFER_SIZE", "1000"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(settings.database_path, "/var/lib/neems/site.sqlite");
+        assert_eq!(settings.log_level, "debug");
+        assert_eq!(settings.writer.batch_size, 100, "env overrides the file");
+        assert_eq!(settings.writer.flush_interval, Duration::from_millis(250));
+        assert_eq!(settings.reader.concurrency.per_test_type.get("ping"), Some(&8));
+        assert_eq!(settings.reader.concurrency.per_test_type.get("exec"), Some(&2));
+        let sync = settings.sync.unwrap();
+        assert_eq!(sync.base_url, "https://central.example.com");
+        assert_eq!(sync.origin, "site-13");
+        assert_eq!(sync.interval, Duration::from_secs(30));
+        let prometheus = settings.prometheus.unwrap();
+        assert_eq!(prometheus.prefix, "neems");
+        assert_eq!(prometheus.labels, vec![("site".to_string(), "north".to_string())]);
+        let mqtt = settings.mqtt.unwrap();
+        assert_eq!(mqtt.topic, "scada/{site_id}/{event}/{source}");
+        assert_eq!(mqtt.qos, 2);
+        assert!(mqtt.retain);
+        assert_eq!(mqtt.readings, vec!["rtac".to_string()]);
+        let snmp = settings.snmp.unwrap();
+        assert_eq!(snmp.targets, vec!["noc.example.com", "10.0.0.5:1162"]);
+        assert_eq!(snmp.version, snmp::SnmpVersion::V3);
+        assert_eq!(snmp.auth_protocol, pdu::AuthProtocol::Sha256);
+        assert_eq!(snmp.priv_password, None);
+        assert!(settings.maintenance.window.is_none());
+        let logs = settings.logs.unwrap();
+        assert_eq!(logs.listen, "0.0.0.0:9180".parse().unwrap());
+        assert_eq!(logs.token, "logs-token");
+        assert_eq!(logs.buffer_size, 1000);
+    }
+
+    #[test]
+    fn test_problems_are_all_reported_with_their_source() {
+        let file = r#"
+            [writer]
+            batch_size = 0
+
+            [concurrency]
+            per_test_type = { pnig = 4 }
+
+            [sync]
+            url = "central.example.com"
+            email = "sync@example.com"
+
+            [prometheus]
+            url = "http://prometheus:9090/api/v1/write"
+            labels = { source = "x" }
+
+            [mqtt]
+            url = "broker.local"
+            topic = "neems/{site}/{event}"
+            qos = 3
+
+            [snmp]
+            targets = ["noc.example.com:trap"]
+            version = "3"
+            engine_id = "8000"
+
+            [maintenance]
+            window = "2am-4am"
+
+            [logs]
+            listen = "0.0.0.0"
+        "#;
+        let err = resolve(file, &[("NEEMS_DATA_QUEUE_CAPACITY", "lots")]).unwrap_err().to_string();
+
+        for expected in [
+            "NEEMS_DATA_QUEUE_CAPACITY: expected a whole number, got 'lots'",
+            "writer.batch_size in neems-data.toml: must be at least 1",
+            "concurrency.per_test_type in neems-data.toml: Unknown test type: pnig",
+            "sync.url in neems-data.toml: 'central.example.com' is not an http:// or https:// URL",
+            "must be set together",
+            "prometheus.labels in neems-data.toml: 'source' can't be used as a label name",
+            "mqtt.url in neems-data.toml: 'broker.local' is not an mqtt:// or mqtts:// URL",
+            "mqtt.topic in neems-data.toml: unknown placeholder {site}",
+            "mqtt.qos in neems-data.toml: QoS must be 0, 1 or 2, got '3'",
+            "snmp.targets in neems-data.toml: 'noc.example.com:trap' is not host or host:port",
+            "snmp.engine_id in neems-data.toml: engine id must be 5 to 32 bytes, got 2",
+            "snmp: SNMPv3 needs a user",
+            "maintenance.window in neems-data.toml: '2am-4am' is not HH:MM-HH:MM or 'off'",
+            "logs.listen in neems-data.toml: '0.0.0.0' is not host:port",
+            "logs.listen in neems-data.toml: needs a token",
+        ] {
+            assert!(err.contains(expected), "missing '{}' in:\n{}", expected, err);
+        }
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        let err = resolve("[writer]\nbatchsize = 10\n", &[]).unwrap_err().to_string();
+        assert!(err.contains("neems-data.toml"), "{}", err);
+        assert!(err.contains("unknown field `batchsize`"), "{}", err);
+    }
+}
@@ -0,0 +1,1231 @@
+//! Configuration bundles pushed from a central server to edge instances.
+//!
+//! A fleet of edge instances shouldn't need an SSH session each for a config
+//! change, so the central neems-api keeps versioned bundles of sources and
+//! the secrets they refer to, and the edges pull them:
+//!
+//! - **Bundles** (`config_bundles`) are immutable and numbered in order of
+//!   creation. Secret values are sealed under the central master key at rest
+//!   and only opened when a bundle is handed to an edge. Each bundle has a
+//!   SHA-256 checksum of its contents, which the edge checks before applying.
+//! - **Staged rollout** (`config_rollouts`): a rollout targets a bundle at a
+//!   list of stages, each a list of origins (the `NEEMS_SYNC_ORIGIN` of an
+//!   edge). Only the first stage is targeted at first; [`advance_rollout`]
+//!   moves on once every origin in the current stage has applied the bundle. An
+//!   origin reporting a failure halts the rollout. Only one rollout may be open
+//!   at a time.
+//! - **Rollback**: [`rollback_rollout`] points every origin the rollout reached
+//!   back at the bundle it was targeted at before. Origins that had none keep
+//!   what they are running.
+//! - **Edge agent**: when sync is configured, [`run_config_loop`] asks the
+//!   server for its origin's target bundle (`GET
+//!   /api/1/EdgeConfig/Agents/<origin>/Desired`), applies it if it differs from
+//!   the last one applied, reloads the sources and reports the outcome (`POST
+//!   /api/1/EdgeConfig/Agents/<origin>/Status`). A bundle is applied in one
+//!   transaction, so a failed apply leaves the previous configuration in place.
+//!
+//! Applying a bundle creates or updates its sources by name and deactivates
+//! sources an earlier bundle created that the new one drops; sources created
+//! locally are left alone. Secrets are stored under the edge's own master
+//! key. Secrets a bundle drops are kept, since local sources may use them.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+    time::Duration,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chrono::{Local, NaiveDateTime, Utc};
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+
+use crate::{
+    DataResult, NewSource, UpdateSource,
+    collectors::TestType,
+    create_source, encryption, get_source_by_name, schema,
+    secrets::{self, MasterKey},
+    sync::{SyncClient, SyncConfig},
+    update_source,
+};
+
+/// A source as configured by a bundle.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigSource {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default = "default_active")]
+    pub active: bool,
+    pub interval_seconds: Option<i32>,
+    pub test_type: String,
+    /// Values may refer to a secret in the bundle as `secret://<name>`
+    #[serde(default)]
+    pub arguments: BTreeMap<String, String>,
+    pub site_id: Option<i32>,
+    pub company_id: Option<i32>,
+    pub priority: Option<i32>,
+    pub point_fields: Option<Vec<String>>,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+impl ConfigSource {
+    fn to_new_source(&self) -> DataResult<NewSource> {
+        Ok(NewSource {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            active: Some(self.active),
+            interval_seconds: self.interval_seconds,
+            test_type: Some(self.test_type.clone()),
+            arguments: Some(serde_json::to_string(&self.arguments)?),
+            site_id: self.site_id,
+            company_id: self.company_id,
+            priority: self.priority,
+            point_fields: self.point_fields.as_ref().map(serde_json::to_string).transpose()?,
+        })
+    }
+}
+
+/// Body of `POST /api/1/EdgeConfig/Bundles`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigBundleRequest {
+    pub description: Option<String>,
+    pub sources: Vec<ConfigSource>,
+    /// Secret values by name
+    #[serde(default)]
+    pub secrets: BTreeMap<String, String>,
+}
+
+impl ConfigBundleRequest {
+    /// Every problem with the request, if any.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        let mut names = BTreeSet::new();
+        for source in &self.sources {
+            if source.name.trim().is_empty() {
+                problems.push("sources: every source needs a name".to_string());
+                continue;
+            }
+            if !names.insert(source.name.as_str()) {
+                problems.push(format!("sources: '{}' is listed twice", source.name));
+            }
+            let arguments: HashMap<String, String> =
+                source.arguments.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+            match source.test_type.parse::<TestType>() {
+                Ok(test_type) => {
+                    if let Err(e) = test_type.validate_arguments(&arguments) {
+                        problems.push(format!("sources: '{}': {}", source.name, e));
+                    }
+                }
+                Err(e) => problems.push(format!("sources: '{}': {}", source.name, e)),
+            }
+            for value in source.arguments.values() {
+                if let Some(name) = secrets::reference_name(value) {
+                    if !self.secrets.contains_key(name) {
+                        problems.push(format!(
+                            "sources: '{}' refers to secret '{}', which the bundle doesn't include",
+                            source.name, name
+                        ));
+                    }
+                }
+            }
+        }
+        for name in self.secrets.keys() {
+            if !secrets::is_valid_name(name) {
+                problems.push(format!("secrets: invalid name {:?}", name));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+/// A bundle as handed to an edge, with its secrets in the clear.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigBundle {
+    pub version: i32,
+    pub checksum: String,
+    pub description: Option<String>,
+    pub sources: Vec<ConfigSource>,
+    pub secrets: BTreeMap<String, String>,
+}
+
+impl fmt::Debug for ConfigBundle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConfigBundle")
+            .field("version", &self.version)
+            .field("checksum", &self.checksum)
+            .field("sources", &self.sources)
+            .field("secrets", &self.secrets.keys())
+            .finish()
+    }
+}
+
+/// A bundle as listed to operators: source arguments that look like
+/// credentials are masked and secrets are named only.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigBundleSummary {
+    pub version: i32,
+    pub description: Option<String>,
+    pub checksum: String,
+    pub sources: Vec<ConfigSource>,
+    pub secret_names: Vec<String>,
+    pub created_by: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// How a bundle is stored, with each secret sealed under its name.
+#[derive(Serialize, Deserialize)]
+struct StoredContents {
+    sources: Vec<ConfigSource>,
+    secrets: Vec<SealedSecret>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SealedSecret {
+    name: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Where a rollout stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RolloutStatus {
+    InProgress,
+    /// An origin failed to apply the bundle; advance once it has, or roll back
+    Halted,
+    Completed,
+    RolledBack,
+}
+
+impl RolloutStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RolloutStatus::InProgress => "in_progress",
+            RolloutStatus::Halted => "halted",
+            RolloutStatus::Completed => "completed",
+            RolloutStatus::RolledBack => "rolled_back",
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        matches!(self, RolloutStatus::InProgress | RolloutStatus::Halted)
+    }
+}
+
+impl std::str::FromStr for RolloutStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "in_progress" => Ok(RolloutStatus::InProgress),
+            "halted" => Ok(RolloutStatus::Halted),
+            "completed" => Ok(RolloutStatus::Completed),
+            "rolled_back" => Ok(RolloutStatus::RolledBack),
+            _ => Err(format!("Unknown rollout status: {}", s)),
+        }
+    }
+}
+
+/// Body of `POST /api/1/EdgeConfig/Rollouts`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RolloutRequest {
+    pub version: i32,
+    /// Origins in the order they are targeted, e.g. a canary stage first
+    pub stages: Vec<Vec<String>>,
+}
+
+/// Where one edge instance stands with its configuration.
+#[derive(Debug, Clone, PartialEq, Queryable, Serialize, Deserialize)]
+pub struct ConfigAgent {
+    pub origin: String,
+    /// Bundle the edge should run
+    pub target_version: Option<i32>,
+    /// Target before the latest rollout reached it, restored on rollback
+    pub previous_version: Option<i32>,
+    pub rollout_id: Option<i32>,
+    /// Bundle the edge last reported applying
+    pub applied_version: Option<i32>,
+    /// Why the last apply failed, until one succeeds
+    pub last_error: Option<String>,
+    pub last_seen_at: Option<NaiveDateTime>,
+    pub updated_at: NaiveDateTime,
+}
+
+/// A bundle rolled out stage by stage, with the origins it has reached.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigRollout {
+    pub id: i32,
+    pub version: i32,
+    pub stages: Vec<Vec<String>>,
+    /// Index of the latest stage targeted
+    pub current_stage: usize,
+    pub status: RolloutStatus,
+    pub created_by: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub agents: Vec<ConfigAgent>,
+}
+
+/// Body of `POST /api/1/EdgeConfig/Agents/<origin>/Status`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigReport {
+    pub version: i32,
+    /// Why the bundle couldn't be applied; absent on success
+    pub error: Option<String>,
+}
+
+/// Why a bundle or rollout operation was refused.
+#[derive(Debug)]
+pub enum ConfigError {
+    NotFound(String),
+    Invalid(String),
+    /// The operation doesn't fit the rollout's state
+    Conflict(String),
+    Data(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::NotFound(e) | ConfigError::Invalid(e) | ConfigError::Conflict(e) => {
+                f.write_str(e)
+            }
+            ConfigError::Data(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<diesel::result::Error> for ConfigError {
+    fn from(e: diesel::result::Error) -> Self {
+        ConfigError::Data(e.into())
+    }
+}
+
+impl From<Box<dyn std::error::Error + Send + Sync>> for ConfigError {
+    fn from(e: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        ConfigError::Data(e)
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(e: serde_json::Error) -> Self {
+        ConfigError::Data(e.into())
+    }
+}
+
+/// Hex SHA-256 of a bundle's sources and secrets.
+pub fn bundle_checksum(
+    sources: &[ConfigSource],
+    secrets: &BTreeMap<String, String>,
+) -> DataResult<String> {
+    let bytes = serde_json::to_vec(&(sources, secrets))?;
+    Ok(Sha256::digest(&bytes).iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Store a new bundle, sealing its secrets with `key`. Returns its summary.
+pub fn create_bundle(
+    connection: &mut SqliteConnection,
+    key: Option<&MasterKey>,
+    request: &ConfigBundleRequest,
+    created_by: Option<&str>,
+) -> Result<ConfigBundleSummary, ConfigError> {
+    use schema::config_bundles::dsl;
+
+    request
+        .validate()
+        .map_err(|problems| ConfigError::Invalid(problems.join("; ")))?;
+    let sealed = match key {
+        Some(key) => request
+            .secrets
+            .iter()
+            .map(|(name, value)| {
+                let (nonce, ciphertext) = key.seal(name, value)?;
+                Ok(SealedSecret {
+                    name: name.clone(),
+                    nonce: BASE64.encode(nonce),
+                    ciphertext: BASE64.encode(ciphertext),
+                })
+            })
+            .collect::<DataResult<Vec<_>>>()?,
+        None if request.secrets.is_empty() => Vec::new(),
+        None => {
+            return Err(ConfigError::Invalid(format!(
+                "secrets: can't store secrets because {} is not set",
+                secrets::KEY_ENV
+            )));
+        }
+    };
+    let contents = StoredContents {
+        sources: request.sources.clone(),
+        secrets: sealed,
+    };
+    let checksum = bundle_checksum(&request.sources, &request.secrets)?;
+
+    let version = connection.transaction(|connection| {
+        diesel::insert_into(dsl::config_bundles)
+            .values((
+                dsl::description.eq(&request.description),
+                dsl::contents.eq(serde_json::to_string(&contents)?),
+                dsl::checksum.eq(&checksum),
+                dsl::created_by.eq(created_by),
+                dsl::created_at.eq(Utc::now().naive_utc()),
+            ))
+            .execute(connection)?;
+        dsl::config_bundles
+            .select(dsl::version)
+            .order(dsl::version.desc())
+            .first::<i32>(connection)
+            .map_err(ConfigError::from)
+    })?;
+    get_bundle(connection, version)?
+        .ok_or_else(|| ConfigError::NotFound(format!("Bundle {} not found", version)))
+}
+
+type BundleRow = (i32, Option<String>, String, String, Option<String>, NaiveDateTime);
+
+fn bundle_row(connection: &mut SqliteConnection, version: i32) -> DataResult<Option<BundleRow>> {
+    use schema::config_bundles::dsl;
+
+    Ok(dsl::config_bundles
+        .find(version)
+        .select((
+            dsl::version,
+            dsl::description,
+            dsl::contents,
+            dsl::checksum,
+            dsl::created_by,
+            dsl::created_at,
+        ))
+        .first(connection)
+        .optional()?)
+}
+
+fn summary(row: BundleRow) -> DataResult<ConfigBundleSummary> {
+    let (version, description, contents, checksum, created_by, created_at) = row;
+    let contents: StoredContents = serde_json::from_str(&contents)?;
+    let sources = contents
+        .sources
+        .into_iter()
+        .map(|mut source| {
+            for (key, value) in source.arguments.iter_mut() {
+                if secrets::is_sensitive_key(key) && secrets::reference_name(value).is_none() {
+                    *value = secrets::REDACTED.to_string();
+                }
+            }
+            source
+        })
+        .collect();
+    Ok(ConfigBundleSummary {
+        version,
+        description,
+        checksum,
+        sources,
+        secret_names: contents.secrets.into_iter().map(|s| s.name).collect(),
+        created_by,
+        created_at,
+    })
+}
+
+/// One bundle's summary.
+pub fn get_bundle(
+    connection: &mut SqliteConnection,
+    version: i32,
+) -> DataResult<Option<ConfigBundleSummary>> {
+    bundle_row(connection, version)?.map(summary).transpose()
+}
+
+/// Every bundle's summary, newest first.
+pub fn list_bundles(connection: &mut SqliteConnection) -> DataResult<Vec<ConfigBundleSummary>> {
+    use schema::config_bundles::dsl;
+
+    dsl::config_bundles
+        .select((
+            dsl::version,
+            dsl::description,
+            dsl::contents,
+            dsl::checksum,
+            dsl::created_by,
+            dsl::created_at,
+        ))
+        .order(dsl::version.desc())
+        .load::<BundleRow>(connection)?
+        .into_iter()
+        .map(summary)
+        .collect()
+}
+
+/// A bundle with its secrets opened by `key`, as handed to an edge.
+pub fn load_bundle(
+    connection: &mut SqliteConnection,
+    key: Option<&MasterKey>,
+    version: i32,
+) -> DataResult<Option<ConfigBundle>> {
+    let Some((version, description, contents, checksum, _, _)) = bundle_row(connection, version)?
+    else {
+        return Ok(None);
+    };
+    let contents: StoredContents = serde_json::from_str(&contents)?;
+    let mut opened = BTreeMap::new();
+    for sealed in contents.secrets {
+        let key = key.ok_or_else(|| {
+            format!("bundle {} has secrets but {} is not set", version, secrets::KEY_ENV)
+        })?;
+        let nonce = BASE64.decode(&sealed.nonce)?;
+        let ciphertext = BASE64.decode(&sealed.ciphertext)?;
+        let value = key.open(&sealed.name, &nonce, &ciphertext)?;
+        opened.insert(sealed.name, value);
+    }
+    Ok(Some(ConfigBundle {
+        version,
+        checksum,
+        description,
+        sources: contents.sources,
+        secrets: opened,
+    }))
+}
+
+/// Every edge instance that has checked in or been targeted, by origin.
+pub fn list_agents(connection: &mut SqliteConnection) -> DataResult<Vec<ConfigAgent>> {
+    use schema::config_agents::dsl;
+
+    Ok(dsl::config_agents.order(dsl::origin.asc()).load(connection)?)
+}
+
+fn get_agent(connection: &mut SqliteConnection, origin: &str) -> DataResult<Option<ConfigAgent>> {
+    use schema::config_agents::dsl;
+
+    Ok(dsl::config_agents.find(origin).first(connection).optional()?)
+}
+
+/// Record that `origin` checked in, creating its row the first time.
+fn touch_agent(connection: &mut SqliteConnection, origin: &str) -> DataResult<()> {
+    use schema::config_agents::dsl;
+
+    let now = Utc::now().naive_utc();
+    diesel::insert_into(dsl::config_agents)
+        .values((dsl::origin.eq(origin), dsl::last_seen_at.eq(now), dsl::updated_at.eq(now)))
+        .on_conflict(dsl::origin)
+        .do_update()
+        .set(dsl::last_seen_at.eq(now))
+        .execute(connection)?;
+    Ok(())
+}
+
+/// The bundle `origin` should run, if it has been targeted by one.
+pub fn desired_bundle(
+    connection: &mut SqliteConnection,
+    key: Option<&MasterKey>,
+    origin: &str,
+) -> DataResult<Option<ConfigBundle>> {
+    touch_agent(connection, origin)?;
+    match get_agent(connection, origin)?.and_then(|agent| agent.target_version) {
+        Some(version) => load_bundle(connection, key, version),
+        None => Ok(None),
+    }
+}
+
+/// Record the outcome of `origin` applying a bundle. A failure halts the
+/// rollout that targeted it; the last origin succeeding completes it.
+pub fn record_report(
+    connection: &mut SqliteConnection,
+    origin: &str,
+    report: &ConfigReport,
+) -> Result<ConfigAgent, ConfigError> {
+    use schema::config_agents::dsl;
+
+    connection.transaction(|connection| {
+        touch_agent(connection, origin)?;
+        let now = Utc::now().naive_utc();
+        match &report.error {
+            None => diesel::update(dsl::config_agents.find(origin))
+                .set((
+                    dsl::applied_version.eq(report.version),
+                    dsl::last_error.eq(None::<String>),
+                    dsl::updated_at.eq(now),
+                ))
+                .execute(connection)?,
+            Some(error) => diesel::update(dsl::config_agents.find(origin))
+                .set((dsl::last_error.eq(error), dsl::updated_at.eq(now)))
+                .execute(connection)?,
+        };
+
+        let agent = get_agent(connection, origin)?
+            .ok_or_else(|| ConfigError::NotFound(format!("Origin {} not found", origin)))?;
+        if let Some(rollout) =
+            agent.rollout_id.map(|id| load_rollout(connection, id)).transpose()?
+        {
+            let rollout = rollout.ok_or_else(|| {
+                ConfigError::NotFound(format!("Rollout {:?} not found", agent.rollout_id))
+            })?;
+            if rollout.status.is_open() && rollout.version == report.version {
+                if report.error.is_some() {
+                    set_rollout_status(connection, rollout.id, RolloutStatus::Halted)?;
+                } else if rollout.status == RolloutStatus::InProgress
+                    && rollout.current_stage + 1 == rollout.stages.len()
+                    && pending_origins(&rollout).is_empty()
+                {
+                    set_rollout_status(connection, rollout.id, RolloutStatus::Completed)?;
+                }
+            }
+        }
+        Ok(agent)
+    })
+}
+
+type RolloutRow = (i32, i32, String, i32, String, Option<String>, NaiveDateTime, NaiveDateTime);
+
+fn load_rollout(
+    connection: &mut SqliteConnection,
+    rollout_id: i32,
+) -> Result<Option<ConfigRollout>, ConfigError> {
+    use schema::{config_agents, config_rollouts::dsl};
+
+    let Some((id, version, stages, current_stage, status, created_by, created_at, updated_at)) =
+        dsl::config_rollouts
+            .find(rollout_id)
+            .select((
+                dsl::id,
+                dsl::version,
+                dsl::stages,
+                dsl::current_stage,
+                dsl::status,
+                dsl::created_by,
+                dsl::created_at,
+                dsl::updated_at,
+            ))
+            .first::<RolloutRow>(connection)
+            .optional()?
+    else {
+        return Ok(None);
+    };
+    let agents = config_agents::table
+        .filter(config_agents::rollout_id.eq(id))
+        .order(config_agents::origin.asc())
+        .load(connection)?;
+    Ok(Some(ConfigRollout {
+        id,
+        version,
+        stages: serde_json::from_str(&stages)?,
+        current_stage: current_stage as usize,
+        status: status.parse().map_err(|e: String| ConfigError::Data(e.into()))?,
+        created_by,
+        created_at,
+        updated_at,
+        agents,
+    }))
+}
+
+/// A rollout and the origins it has reached.
+pub fn get_rollout(
+    connection: &mut SqliteConnection,
+    rollout_id: i32,
+) -> Result<Option<ConfigRollout>, ConfigError> {
+    load_rollout(connection, rollout_id)
+}
+
+/// Every rollout, newest first.
+pub fn list_rollouts(connection: &mut SqliteConnection) -> Result<Vec<ConfigRollout>, ConfigError> {
+    use schema::config_rollouts::dsl;
+
+    let ids: Vec<i32> =
+        dsl::config_rollouts.select(dsl::id).order(dsl::id.desc()).load(connection)?;
+    let mut rollouts = Vec::with_capacity(ids.len());
+    for id in ids {
+        rollouts.extend(load_rollout(connection, id)?);
+    }
+    Ok(rollouts)
+}
+
+fn set_rollout_status(
+    connection: &mut SqliteConnection,
+    rollout_id: i32,
+    status: RolloutStatus,
+) -> DataResult<()> {
+    use schema::config_rollouts::dsl;
+
+    diesel::update(dsl::config_rollouts.find(rollout_id))
+        .set((dsl::status.eq(status.as_str()), dsl::updated_at.eq(Utc::now().naive_utc())))
+        .execute(connection)?;
+    Ok(())
+}
+
+/// Origins targeted so far that haven't applied the rollout's bundle.
+fn pending_origins(rollout: &ConfigRollout) -> Vec<String> {
+    rollout.stages[..=rollout.current_stage]
+        .iter()
+        .flatten()
+        .filter(|origin| {
+            !rollout
+                .agents
+                .iter()
+                .any(|a| &a.origin == *origin && a.applied_version == Some(rollout.version))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Point a stage's origins at the rollout's bundle, remembering what they
+/// were targeted at before.
+fn target_stage(
+    connection: &mut SqliteConnection,
+    rollout_id: i32,
+    version: i32,
+    origins: &[String],
+) -> DataResult<()> {
+    use schema::config_agents::dsl;
+
+    let now = Utc::now().naive_utc();
+    for origin in origins {
+        let previous = get_agent(connection, origin)?.and_then(|agent| agent.target_version);
+        diesel::insert_into(dsl::config_agents)
+            .values((
+                dsl::origin.eq(origin),
+                dsl::target_version.eq(version),
+                dsl::previous_version.eq(previous),
+                dsl::rollout_id.eq(rollout_id),
+                dsl::updated_at.eq(now),
+            ))
+            .on_conflict(dsl::origin)
+            .do_update()
+            .set((
+                dsl::target_version.eq(version),
+                dsl::previous_version.eq(previous),
+                dsl::rollout_id.eq(rollout_id),
+                dsl::last_error.eq(None::<String>),
+                dsl::updated_at.eq(now),
+            ))
+            .execute(connection)?;
+    }
+    Ok(())
+}
+
+/// Start rolling a bundle out, targeting the first stage.
+pub fn start_rollout(
+    connection: &mut SqliteConnection,
+    request: &RolloutRequest,
+    created_by: Option<&str>,
+) -> Result<ConfigRollout, ConfigError> {
+    use schema::config_rollouts::dsl;
+
+    if request.stages.is_empty() || request.stages.iter().any(Vec::is_empty) {
+        return Err(ConfigError::Invalid(
+            "stages: at least one stage is needed, and no stage may be empty".to_string(),
+        ));
+    }
+    let mut seen = BTreeSet::new();
+    for origin in request.stages.iter().flatten() {
+        if origin.trim().is_empty() || origin.contains('/') {
+            return Err(ConfigError::Invalid(format!("stages: invalid origin {:?}", origin)));
+        }
+        if !seen.insert(origin) {
+            return Err(ConfigError::Invalid(format!("stages: '{}' is listed twice", origin)));
+        }
+    }
+
+    connection.transaction(|connection| {
+        if bundle_row(connection, request.version)?.is_none() {
+            return Err(ConfigError::NotFound(format!("Bundle {} not found", request.version)));
+        }
+        let open: Option<i32> = dsl::config_rollouts
+            .filter(
+                dsl::status
+                    .eq_any([RolloutStatus::InProgress.as_str(), RolloutStatus::Halted.as_str()]),
+            )
+            .select(dsl::id)
+            .first(connection)
+            .optional()?;
+        if let Some(open) = open {
+            return Err(ConfigError::Conflict(format!(
+                "Rollout {} is still open; complete or roll it back first",
+                open
+            )));
+        }
+
+        let now = Utc::now().naive_utc();
+        diesel::insert_into(dsl::config_rollouts)
+            .values((
+                dsl::version.eq(request.version),
+                dsl::stages.eq(serde_json::to_string(&request.stages)?),
+                dsl::current_stage.eq(0),
+                dsl::status.eq(RolloutStatus::InProgress.as_str()),
+                dsl::created_by.eq(created_by),
+                dsl::created_at.eq(now),
+                dsl::updated_at.eq(now),
+            ))
+            .execute(connection)?;
+        let id: i32 =
+            dsl::config_rollouts.select(dsl::id).order(dsl::id.desc()).first(connection)?;
+        target_stage(connection, id, request.version, &request.stages[0])?;
+        load_rollout(connection, id)?
+            .ok_or_else(|| ConfigError::NotFound(format!("Rollout {} not found", id)))
+    })
+}
+
+/// Target the next stage of a rollout once every origin targeted so far has
+/// applied its bundle, completing it after the last stage.
+pub fn advance_rollout(
+    connection: &mut SqliteConnection,
+    rollout_id: i32,
+) -> Result<ConfigRollout, ConfigError> {
+    use schema::config_rollouts::dsl;
+
+    connection.transaction(|connection| {
+        let rollout = load_rollout(connection, rollout_id)?
+            .ok_or_else(|| ConfigError::NotFound(format!("Rollout {} not found", rollout_id)))?;
+        if !rollout.status.is_open() {
+            return Err(ConfigError::Conflict(format!(
+                "Rollout {} is {}",
+                rollout_id,
+                rollout.status.as_str()
+            )));
+        }
+        let pending = pending_origins(&rollout);
+        if !pending.is_empty() {
+            return Err(ConfigError::Conflict(format!(
+                "Waiting for {} to apply bundle {}",
+                pending.join(", "),
+                rollout.version
+            )));
+        }
+
+        let next = rollout.current_stage + 1;
+        if next == rollout.stages.len() {
+            set_rollout_status(connection, rollout_id, RolloutStatus::Completed)?;
+        } else {
+            target_stage(connection, rollout_id, rollout.version, &rollout.stages[next])?;
+            diesel::update(dsl::config_rollouts.find(rollout_id))
+                .set((
+                    dsl::current_stage.eq(next as i32),
+                    dsl::status.eq(RolloutStatus::InProgress.as_str()),
+                    dsl::updated_at.eq(Utc::now().naive_utc()),
+                ))
+                .execute(connection)?;
+        }
+        load_rollout(connection, rollout_id)?
+            .ok_or_else(|| ConfigError::NotFound(format!("Rollout {} not found", rollout_id)))
+    })
+}
+
+/// Point every origin a rollout reached back at its earlier bundle. Only
+/// the latest rollout can be rolled back.
+pub fn rollback_rollout(
+    connection: &mut SqliteConnection,
+    rollout_id: i32,
+) -> Result<ConfigRollout, ConfigError> {
+    use schema::{config_agents, config_rollouts::dsl};
+
+    connection.transaction(|connection| {
+        let rollout = load_rollout(connection, rollout_id)?
+            .ok_or_else(|| ConfigError::NotFound(format!("Rollout {} not found", rollout_id)))?;
+        if rollout.status == RolloutStatus::RolledBack {
+            return Err(ConfigError::Conflict(format!(
+                "Rollout {} is already rolled back",
+                rollout_id
+            )));
+        }
+        let latest: i32 =
+            dsl::config_rollouts.select(dsl::id).order(dsl::id.desc()).first(connection)?;
+        if latest != rollout_id {
+            return Err(ConfigError::Conflict(format!(
+                "Rollout {} has been superseded by rollout {}",
+                rollout_id, latest
+            )));
+        }
+
+        diesel::update(config_agents::table.filter(config_agents::rollout_id.eq(rollout_id)))
+            .set((
+                config_agents::target_version.eq(config_agents::previous_version),
+                config_agents::last_error.eq(None::<String>),
+                config_agents::updated_at.eq(Utc::now().naive_utc()),
+            ))
+            .execute(connection)?;
+        set_rollout_status(connection, rollout_id, RolloutStatus::RolledBack)?;
+        load_rollout(connection, rollout_id)?
+            .ok_or_else(|| ConfigError::NotFound(format!("Rollout {} not found", rollout_id)))
+    })
+}
+
+/// Version and checksum of the bundle last applied on this edge.
+pub fn applied_bundle(connection: &mut SqliteConnection) -> DataResult<Option<(i32, String)>> {
+    use schema::config_applied::dsl;
+
+    Ok(dsl::config_applied
+        .select((dsl::version, dsl::checksum))
+        .order(dsl::id.desc())
+        .first(connection)
+        .optional()?)
+}
+
+/// Apply a bundle on this edge in one transaction, storing its secrets
+/// under `key`.
+pub fn apply_bundle(
+    connection: &mut SqliteConnection,
+    key: Option<&MasterKey>,
+    bundle: &ConfigBundle,
+) -> DataResult<()> {
+    use schema::config_applied::dsl;
+
+    let checksum = bundle_checksum(&bundle.sources, &bundle.secrets)?;
+    if checksum != bundle.checksum {
+        return Err(format!("bundle {} failed its checksum", bundle.version).into());
+    }
+    if !bundle.secrets.is_empty() && key.is_none() {
+        return Err(format!(
+            "bundle {} has secrets but {} is not set",
+            bundle.version,
+            secrets::KEY_ENV
+        )
+        .into());
+    }
+
+    connection.transaction(|connection| {
+        if let Some(key) = key {
+            for (name, value) in &bundle.secrets {
+                secrets::set_secret(connection, key, name, value)?;
+            }
+        }
+
+        for source in &bundle.sources {
+            let new_source = source.to_new_source()?;
+            match get_source_by_name(connection, &source.name)? {
+                Some(existing) => {
+                    let updates = UpdateSource {
+                        name: None,
+                        description: Some(new_source.description),
+                        active: new_source.active,
+                        interval_seconds: new_source.interval_seconds,
+                        last_run: None,
+                        test_type: new_source.test_type,
+                        arguments: new_source.arguments,
+                        site_id: Some(new_source.site_id),
+                        company_id: Some(new_source.company_id),
+                        priority: new_source.priority,
+                        point_fields: Some(new_source.point_fields),
+                    };
+                    update_source(connection, existing.id.unwrap_or_default(), updates)?;
+                }
+                None => {
+                    create_source(connection, new_source)?;
+                }
+            }
+        }
+
+        // Deactivate what the previous bundle managed and this one drops
+        let previous: Option<String> = dsl::config_applied
+            .select(dsl::source_names)
+            .order(dsl::id.desc())
+            .first(connection)
+            .optional()?;
+        let managed: BTreeSet<&str> = bundle.sources.iter().map(|s| s.name.as_str()).collect();
+        let dropped: Vec<String> = previous
+            .map(|names| serde_json::from_str::<Vec<String>>(&names))
+            .transpose()?
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|name| !managed.contains(name.as_str()))
+            .collect();
+        diesel::update(schema::sources::table.filter(schema::sources::name.eq_any(&dropped)))
+            .set(schema::sources::active.eq(false))
+            .execute(connection)?;
+
+        diesel::insert_into(dsl::config_applied)
+            .values((
+                dsl::version.eq(bundle.version),
+                dsl::checksum.eq(&bundle.checksum),
+                dsl::source_names.eq(serde_json::to_string(&managed)?),
+                dsl::secret_names
+                    .eq(serde_json::to_string(&bundle.secrets.keys().collect::<Vec<_>>())?),
+                dsl::applied_at.eq(Utc::now().naive_utc()),
+            ))
+            .execute(connection)?;
+        Ok(())
+    })
+}
+
+impl SyncClient {
+    /// The bundle the server wants this origin to run, if any.
+    pub async fn desired_config(&mut self) -> DataResult<Option<ConfigBundle>> {
+        let url = self.url(&format!("EdgeConfig/Agents/{}/Desired", self.origin()));
+        let response = self.send(|http| http.get(&url)).await?;
+        if response.status() == reqwest::StatusCode::NO_CONTENT {
+            return Ok(None);
+        }
+        Ok(Some(response.json::<ConfigBundle>().await?))
+    }
+
+    /// Tell the server how applying a bundle went.
+    pub async fn report_config(&mut self, report: &ConfigReport) -> DataResult<()> {
+        let url = self.url(&format!("EdgeConfig/Agents/{}/Status", self.origin()));
+        self.send(|http| http.post(&url).json(report)).await?;
+        Ok(())
+    }
+}
+
+/// Poll the server for this origin's bundle forever, applying each new one
+/// and asking the reader to reload its sources.
+pub async fn run_config_loop(
+    database_url: String,
+    config: SyncConfig,
+    interval: Duration,
+    reload_tx: mpsc::Sender<()>,
+) {
+    let target = config.base_url.clone();
+    let mut client = match SyncClient::new(config) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Config updates disabled: {}", e);
+            return;
+        }
+    };
+    // Reported this run, so a restart reports the current bundle once more
+    let mut reported: Option<(i32, String)> = None;
+
+    loop {
+        let result: DataResult<()> = async {
+            let Some(bundle) = client.desired_config().await? else {
+                return Ok(());
+            };
+            let current = (bundle.version, bundle.checksum.clone());
+            if reported.as_ref() == Some(&current) {
+                return Ok(());
+            }
+
+            let applied = {
+                let database_url = database_url.clone();
+                let bundle = bundle.clone();
+                tokio::task::spawn_blocking(move || -> DataResult<bool> {
+                    let mut connection = encryption::establish(&database_url)?;
+                    if applied_bundle(&mut connection)?.as_ref()
+                        == Some(&(bundle.version, bundle.checksum.clone()))
+                    {
+                        return Ok(false);
+                    }
+                    apply_bundle(&mut connection, MasterKey::from_env()?, &bundle)?;
+                    Ok(true)
+                })
+                .await?
+            };
+            let error = match applied {
+                Ok(true) => {
+                    println!(
+                        "{} - Applied configuration bundle {} from {}",
+                        Local::now().to_rfc3339(),
+                        bundle.version,
+                        target
+                    );
+                    let _ = reload_tx.try_send(());
+                    None
+                }
+                Ok(false) => None,
+                Err(e) => {
+                    eprintln!(
+                        "{} - Failed to apply configuration bundle {}: {}",
+                        Local::now().to_rfc3339(),
+                        bundle.version,
+                        e
+                    );
+                    Some(e.to_string())
+                }
+            };
+            client.report_config(&ConfigReport { version: bundle.version, error }).await?;
+            reported = Some(current);
+            Ok(())
+        }
+        .await;
+
+        if let Err(e) = result {
+            eprintln!(
+                "{} - Configuration check with {} failed: {}",
+                Local::now().to_rfc3339(),
+                target,
+                e
+            );
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel_migrations::MigrationHarness;
+
+    use super::*;
+    use crate::MIGRATIONS;
+
+    fn connection() -> SqliteConnection {
+        let mut connection = SqliteConnection::establish(":memory:").unwrap();
+        connection.run_pending_migrations(MIGRATIONS).unwrap();
+        connection
+    }
+
+    fn source(name: &str, arguments: &[(&str, &str)]) -> ConfigSource {
+        ConfigSource {
+            name: name.to_string(),
+            description: None,
+            active: true,
+            interval_seconds: Some(60),
+            test_type: "ping".to_string(),
+            arguments: arguments.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            site_id: None,
+            company_id: None,
+            priority: None,
+            point_fields: None,
+        }
+    }
+
+    fn key() -> MasterKey {
+        MasterKey::parse(&secrets::generate_key().unwrap()).unwrap()
+    }
+
+    fn request(sources: Vec<ConfigSource>, secrets: &[(&str, &str)]) -> ConfigBundleRequest {
+        ConfigBundleRequest {
+            description: None,
+            sources,
+            secrets: secrets.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn test_validate_requires_bundled_secrets() {
+        let bundle = request(vec![source("gw", &[("target", "secret://gw-host")])], &[]);
+        let problems = bundle.validate().unwrap_err();
+        assert!(problems[0].contains("gw-host"), "{:?}", problems);
+
+        let bundle = request(vec![source("gw", &[]), source("gw", &[])], &[]);
+        assert!(bundle.validate().is_err());
+    }
+
+    #[test]
+    fn test_bundle_round_trip_seals_secrets() {
+        let mut central = connection();
+        let key = key();
+        let bundle = request(
+            vec![source("gw", &[("target", "secret://gw-host")])],
+            &[("gw-host", "10.0.0.1")],
+        );
+        let summary = create_bundle(&mut central, Some(&key), &bundle, Some("ops")).unwrap();
+        assert_eq!(summary.version, 1);
+        assert_eq!(summary.secret_names, vec!["gw-host"]);
+
+        let stored: String = schema::config_bundles::table
+            .select(schema::config_bundles::contents)
+            .first(&mut central)
+            .unwrap();
+        assert!(!stored.contains("10.0.0.1"));
+
+        let loaded = load_bundle(&mut central, Some(&key), 1).unwrap().unwrap();
+        assert_eq!(loaded.secrets["gw-host"], "10.0.0.1");
+        assert_eq!(loaded.checksum, summary.checksum);
+
+        // Secrets need a master key to be stored
+        assert!(matches!(
+            create_bundle(&mut central, None, &bundle, None),
+            Err(ConfigError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_apply_bundle_upserts_and_deactivates_dropped_sources() {
+        let mut central = connection();
+        let mut edge = connection();
+        let key = key();
+
+        let first = request(
+            vec![source("gw", &[("target", "secret://gw-host")]), source("old", &[])],
+            &[("gw-host", "10.0.0.1")],
+        );
+        create_bundle(&mut central, Some(&key), &first, None).unwrap();
+        let bundle = load_bundle(&mut central, Some(&key), 1).unwrap().unwrap();
+        apply_bundle(&mut edge, Some(&key), &bundle).unwrap();
+        assert_eq!(applied_bundle(&mut edge).unwrap(), Some((1, bundle.checksum.clone())));
+        assert_eq!(
+            secrets::get_secret(&mut edge, &key, "gw-host").unwrap().as_deref(),
+            Some("10.0.0.1")
+        );
+
+        let second = request(vec![source("gw", &[("target", "10.0.0.2")])], &[]);
+        create_bundle(&mut central, Some(&key), &second, None).unwrap();
+        let bundle = load_bundle(&mut central, Some(&key), 2).unwrap().unwrap();
+        apply_bundle(&mut edge, Some(&key), &bundle).unwrap();
+
+        let gw = get_source_by_name(&mut edge, "gw").unwrap().unwrap();
+        assert_eq!(gw.get_arguments().unwrap()["target"], "10.0.0.2");
+        assert!(gw.active);
+        let old = get_source_by_name(&mut edge, "old").unwrap().unwrap();
+        assert!(!old.active);
+
+        // A tampered bundle is refused and changes nothing
+        let mut tampered = bundle.clone();
+        tampered.version = 3;
+        tampered.sources[0]
+            .arguments
+            .insert("target".to_string(), "10.6.6.6".to_string());
+        assert!(apply_bundle(&mut edge, Some(&key), &tampered).is_err());
+        assert_eq!(applied_bundle(&mut edge).unwrap().unwrap().0, 2);
+    }
+
+    #[test]
+    fn test_staged_rollout_halts_advances_and_rolls_back() {
+        let mut central = connection();
+        for name in ["a", "b"] {
+            create_bundle(&mut central, None, &request(vec![source(name, &[])], &[]), None)
+                .unwrap();
+        }
+        let stages = vec![vec!["canary".to_string()], vec!["edge-1".to_string()]];
+        let first =
+            start_rollout(&mut central, &RolloutRequest { version: 1, stages }, None).unwrap();
+        assert_eq!(first.agents.len(), 1);
+        assert!(matches!(advance_rollout(&mut central, first.id), Err(ConfigError::Conflict(_))));
+
+        let ok = ConfigReport { version: 1, error: None };
+        record_report(&mut central, "canary", &ok).unwrap();
+        let first = advance_rollout(&mut central, first.id).unwrap();
+        assert_eq!(first.current_stage, 1);
+        record_report(&mut central, "edge-1", &ok).unwrap();
+        let first = get_rollout(&mut central, first.id).unwrap().unwrap();
+        assert_eq!(first.status, RolloutStatus::Completed);
+
+        // The next bundle fails on the canary, halting its rollout
+        let stages = vec![vec!["canary".to_string()], vec!["edge-1".to_string()]];
+        let second =
+            start_rollout(&mut central, &RolloutRequest { version: 2, stages }, None).unwrap();
+        let failed = ConfigReport {
+            version: 2,
+            error: Some("boom".to_string()),
+        };
+        record_report(&mut central, "canary", &failed).unwrap();
+        let second = get_rollout(&mut central, second.id).unwrap().unwrap();
+        assert_eq!(second.status, RolloutStatus::Halted);
+        assert!(
+            start_rollout(
+                &mut central,
+                &RolloutRequest {
+                    version: 1,
+                    stages: vec![vec!["x".to_string()]]
+                },
+                None
+            )
+            .is_err()
+        );
+
+        let second = rollback_rollout(&mut central, second.id).unwrap();
+        assert_eq!(second.status, RolloutStatus::RolledBack);
+        let canary = desired_bundle(&mut central, None, "canary").unwrap().unwrap();
+        assert_eq!(canary.version, 1);
+        assert!(matches!(
+            rollback_rollout(&mut central, first.id),
+            Err(ConfigError::Conflict(_))
+        ));
+    }
+}
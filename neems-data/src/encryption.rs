@@ -0,0 +1,276 @@
+//! Encryption at rest with SQLCipher.
+//!
+//! Some edge hardware has no full-disk encryption, so the databases can be
+//! encrypted by SQLCipher instead. Build with the `sqlcipher` feature and
+//! provide the key in one of two ways:
+//!
+//! - `NEEMS_DB_KEY`: the key itself
+//! - `NEEMS_DB_KEY_FILE`: a file holding the key, e.g. written at boot by a KMS
+//!   or TPM unseal step. It must not be readable by group or others.
+//!
+//! A key is either a passphrase or a raw 256-bit key written as
+//! `x'<64 hex digits>'`. Every connection is opened through [`establish`],
+//! which passes the key as the `key` parameter of a `file:` URI so it is set
+//! before SQLite reads the file; the same URI works for connection pools
+//! (see [`connection_url`]).
+//!
+//! A build without the `sqlcipher` feature refuses to open a database when a
+//! key is configured, rather than silently writing plaintext.
+
+use std::{env, fs, path::Path, sync::OnceLock};
+
+use diesel::{
+    ConnectionError, ConnectionResult, connection::SimpleConnection, prelude::*,
+    sqlite::SqliteConnection,
+};
+
+use crate::DataResult;
+
+pub const KEY_ENV: &str = "NEEMS_DB_KEY";
+pub const KEY_FILE_ENV: &str = "NEEMS_DB_KEY_FILE";
+
+/// Whether this build links SQLCipher.
+pub const ENABLED: bool = cfg!(feature = "sqlcipher");
+
+/// The configured database key, read once per process.
+pub fn database_key() -> DataResult<Option<String>> {
+    static KEY: OnceLock<Result<Option<String>, String>> = OnceLock::new();
+    KEY.get_or_init(|| {
+        read_key(env::var(KEY_ENV).ok(), env::var(KEY_FILE_ENV).ok().as_deref(), KEY_FILE_ENV)
+            .map_err(|e| e.to_string())
+    })
+    .clone()
+    .map_err(Into::into)
+}
+
+/// A key given directly, or else read from `key_file` (named by the
+/// `file_env` variable), which must not be readable by other users.
+pub(crate) fn read_key(
+    key: Option<String>,
+    key_file: Option<&str>,
+    file_env: &str,
+) -> DataResult<Option<String>> {
+    if let Some(key) = key.filter(|k| !k.is_empty()) {
+        return Ok(Some(key));
+    }
+    let Some(path) = key_file.filter(|p| !p.is_empty()) else {
+        return Ok(None);
+    };
+    check_key_file_permissions(Path::new(path), file_env)?;
+    let key = fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read {} {}: {}", file_env, path, e))?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(format!("Key file {} is empty", path).into());
+    }
+    Ok(Some(key.to_string()))
+}
+
+#[cfg(unix)]
+fn check_key_file_permissions(path: &Path, file_env: &str) -> DataResult<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path)
+        .map_err(|e| format!("Cannot read {} {}: {}", file_env, path.display(), e))?
+        .permissions()
+        .mode();
+    if mode & 0o077 != 0 {
+        return Err(format!(
+            "Key file {} is accessible by other users (mode {:o}); chmod 600 it",
+            path.display(),
+            mode & 0o777
+        )
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_key_file_permissions(_path: &Path, _file_env: &str) -> DataResult<()> {
+    Ok(())
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters, plus `/`
+/// and `:` when `keep_path` is set.
+fn percent_encode(s: &str, keep_path: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        let keep = byte.is_ascii_alphanumeric()
+            || matches!(byte, b'-' | b'.' | b'_' | b'~')
+            || (keep_path && matches!(byte, b'/' | b':'));
+        if keep {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    out
+}
+
+/// `database_url` (a path, `sqlite://` URL or `file:` URI) as a `file:` URI
+/// carrying `key`.
+pub fn keyed_url(database_url: &str, key: &str) -> String {
+    let key = percent_encode(key, false);
+    if database_url.starts_with("file:") {
+        let separator = if database_url.contains('?') { '&' } else { '?' };
+        format!("{}{}key={}", database_url, separator, key)
+    } else {
+        let path = database_url.strip_prefix("sqlite://").unwrap_or(database_url);
+        format!("file:{}?key={}", percent_encode(path, true), key)
+    }
+}
+
+/// `database_url` with any key replaced by `***`, for logging.
+pub fn redact_url(database_url: &str) -> String {
+    let Some((base, query)) = database_url.split_once('?') else {
+        return database_url.to_string();
+    };
+    let query: Vec<String> = query
+        .split('&')
+        .map(|param| match param.split_once('=') {
+            Some((name @ ("key" | "hexkey"), _)) => format!("{}=***", name),
+            _ => param.to_string(),
+        })
+        .collect();
+    format!("{}?{}", base, query.join("&"))
+}
+
+fn url_for_key(database_url: &str, key: Option<&str>) -> Result<String, String> {
+    match key {
+        None => Ok(database_url.to_string()),
+        Some(_) if !ENABLED => Err(format!(
+            "{} or {} is set, but this build lacks SQLCipher support (build with the \
+             `sqlcipher` feature)",
+            KEY_ENV, KEY_FILE_ENV
+        )),
+        Some(key) => Ok(keyed_url(database_url, key)),
+    }
+}
+
+/// The URL to open `database_url` with: keyed when a database key is
+/// configured. For handing to connection pools; prefer [`establish`] for
+/// single connections, which also checks the key.
+pub fn connection_url(database_url: &str) -> DataResult<String> {
+    Ok(url_for_key(database_url, database_key()?.as_deref())?)
+}
+
+/// How long a connection waits for another one's lock before failing with
+/// "database is locked".
+const BUSY_TIMEOUT_MS: u32 = 5_000;
+
+/// Open `database_url`, keyed when a database key is configured.
+///
+/// The monitor's loops each open their own connections, so these wait up to
+/// [`BUSY_TIMEOUT_MS`] for a lock held by another rather than failing at
+/// once. With a key, this also checks that the linked SQLite is SQLCipher and
+/// that the key opens the file, so a wrong key fails here instead of on the
+/// first query.
+pub fn establish(database_url: &str) -> ConnectionResult<SqliteConnection> {
+    let key = database_key().map_err(|e| ConnectionError::BadConnection(e.to_string()))?;
+    establish_with_key(database_url, key.as_deref())
+}
+
+fn establish_with_key(database_url: &str, key: Option<&str>) -> ConnectionResult<SqliteConnection> {
+    let url = url_for_key(database_url, key).map_err(ConnectionError::BadConnection)?;
+    let mut connection = SqliteConnection::establish(&url)?;
+    if key.is_some() {
+        verify_key(&mut connection)
+            .map_err(|e| ConnectionError::BadConnection(format!("{}: {}", redact_url(&url), e)))?;
+    }
+    connection
+        .batch_execute(&format!("PRAGMA busy_timeout = {};", BUSY_TIMEOUT_MS))
+        .map_err(|e| ConnectionError::BadConnection(e.to_string()))?;
+    Ok(connection)
+}
+
+#[derive(QueryableByName)]
+struct CipherVersion {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    cipher_version: String,
+}
+
+fn verify_key(connection: &mut SqliteConnection) -> Result<(), String> {
+    let version = diesel::sql_query("PRAGMA cipher_version")
+        .load::<CipherVersion>(connection)
+        .map_err(|e| e.to_string())?;
+    if version.first().is_none_or(|v| v.cipher_version.is_empty()) {
+        return Err("the linked SQLite library is not SQLCipher".to_string());
+    }
+    connection
+        .batch_execute("SELECT count(*) FROM sqlite_master;")
+        .map_err(|e| format!("wrong database key or unencrypted database ({})", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyed_url_forms() {
+        assert_eq!(keyed_url("sqlite://data/site.sqlite", "pw"), "file:data/site.sqlite?key=pw");
+        assert_eq!(keyed_url("/var/lib/neems.db", "a b&c"), "file:/var/lib/neems.db?key=a%20b%26c");
+        assert_eq!(keyed_url("file:x.db?mode=rwc", "pw"), "file:x.db?mode=rwc&key=pw");
+        assert_eq!(keyed_url("odd?name.db", "pw"), "file:odd%3Fname.db?key=pw");
+    }
+
+    #[test]
+    fn test_redact_url_hides_key() {
+        assert_eq!(redact_url("file:x.db?mode=rwc&key=secret"), "file:x.db?mode=rwc&key=***");
+        assert_eq!(redact_url("sqlite://x.db"), "sqlite://x.db");
+    }
+
+    #[test]
+    fn test_key_file_is_read_and_checked() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(file.path(), "x'00112233'\n").unwrap();
+        let path = file.path().to_str().unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(file.path(), fs::Permissions::from_mode(0o644)).unwrap();
+            assert!(
+                read_key(None, Some(path), KEY_FILE_ENV).is_err(),
+                "world-readable key file is refused"
+            );
+            fs::set_permissions(file.path(), fs::Permissions::from_mode(0o600)).unwrap();
+        }
+
+        assert_eq!(
+            read_key(None, Some(path), KEY_FILE_ENV).unwrap().as_deref(),
+            Some("x'00112233'")
+        );
+        assert_eq!(
+            read_key(Some("env".into()), Some(path), KEY_FILE_ENV).unwrap().as_deref(),
+            Some("env")
+        );
+        assert_eq!(read_key(None, None, KEY_FILE_ENV).unwrap(), None);
+    }
+
+    #[cfg(not(feature = "sqlcipher"))]
+    #[test]
+    fn test_key_without_sqlcipher_is_refused() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let url = file.path().to_str().unwrap();
+        assert!(establish_with_key(url, None).is_ok());
+        assert!(establish_with_key(url, Some("secret")).is_err());
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[test]
+    fn test_encrypted_database_needs_its_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("encrypted.db");
+        let url = path.to_str().unwrap();
+
+        let mut conn = establish_with_key(url, Some("right")).unwrap();
+        conn.batch_execute("CREATE TABLE t (x INTEGER); INSERT INTO t VALUES (1);")
+            .unwrap();
+        drop(conn);
+
+        assert!(establish_with_key(url, Some("wrong")).is_err());
+        assert!(establish_with_key(url, Some("right")).is_ok());
+        let header = fs::read(&path).unwrap();
+        assert!(!header.starts_with(b"SQLite format 3"), "file is encrypted");
+    }
+}
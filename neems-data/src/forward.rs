@@ -0,0 +1,1044 @@
+//! Forwarding readings to a customer's historian.
+//!
+//! Sites that treat NEEMS as an edge collector can have their readings
+//! mirrored to InfluxDB (line protocol over the v2 write API, which InfluxDB
+//! 1.8+, 2.x and 3.x all accept) or to a Timescale (or plain PostgreSQL)
+//! table. Forwarders are stored per site in the `forwarders` table and
+//! managed through neems-api; `monitor` checks the table every
+//! [`FORWARDERS_POLL_INTERVAL`] and starts, restarts or stops a task per
+//! enabled forwarder as they change.
+//!
+//! The site database is the buffer: like [`crate::sync`], each forwarder
+//! keeps the last reading id it delivered in `sync_cursors` (under the target
+//! `forwarder:<id>`), sends the site's readings after it in batches, and backs
+//! off while the historian is unreachable, so an outage only delays delivery.
+//! A new forwarder starts from the site's first reading. Resending a batch is
+//! harmless: InfluxDB overwrites identical points and Timescale rows are
+//! inserted with `ON CONFLICT DO NOTHING`.
+
+use std::{collections::HashMap, str::FromStr, time::Duration};
+
+use chrono::{Local, NaiveDateTime, Utc};
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use ts_rs::TS;
+
+use crate::{
+    DataResult, encryption,
+    prometheus::Delivery,
+    schema,
+    secrets::{self, MasterKey},
+    sync::{SyncReading, load_cursor, save_cursor},
+};
+
+/// How often `monitor` checks the `forwarders` table for changes.
+pub const FORWARDERS_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+const DEFAULT_BATCH_SIZE: u32 = 500;
+const MAX_BATCH_SIZE: u32 = 10_000;
+const MAX_BACKOFF: Duration = Duration::from_secs(15 * 60);
+
+/// Where a forwarder sends readings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum ForwarderKind {
+    /// InfluxDB line protocol, one measurement per test type
+    Influxdb,
+    /// Rows in a Timescale hypertable (or a PostgreSQL table)
+    Timescale,
+}
+
+impl ForwarderKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Influxdb => "influxdb",
+            Self::Timescale => "timescale",
+        }
+    }
+}
+
+impl FromStr for ForwarderKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "influxdb" => Ok(Self::Influxdb),
+            "timescale" => Ok(Self::Timescale),
+            _ => Err(format!("Unknown forwarder kind: {}", s)),
+        }
+    }
+}
+
+/// A stored forwarder.
+#[derive(Debug, Clone, PartialEq, Queryable, Selectable)]
+#[diesel(table_name = schema::forwarders)]
+pub struct Forwarder {
+    pub id: i32,
+    pub site_id: i32,
+    pub name: String,
+    pub kind: String,
+    pub url: String,
+    pub organization: Option<String>,
+    pub destination: String,
+    pub token: Option<String>,
+    pub enabled: bool,
+    pub batch_size: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl Forwarder {
+    /// The forwarder's key in `sync_cursors`.
+    pub fn target(&self) -> String {
+        format!("forwarder:{}", self.id)
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// A forwarder's settings, as created or replaced through the API.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ForwarderSettings {
+    pub name: String,
+    pub kind: ForwarderKind,
+    /// InfluxDB base URL (`https://influx.example.com:8086`) or PostgreSQL
+    /// connection URL without a password
+    /// (`postgres://neems@historian.example.com/metrics?sslmode=require`)
+    pub url: String,
+    /// InfluxDB organization
+    #[serde(default)]
+    pub organization: Option<String>,
+    /// InfluxDB bucket, or Timescale table (optionally `schema.table`)
+    pub destination: String,
+    /// InfluxDB API token or Timescale password, or a `secret://<name>`
+    /// reference. When replacing a forwarder, `null` keeps the stored token
+    /// and `""` removes it.
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Readings per write (default 500)
+    #[serde(default)]
+    pub batch_size: Option<u32>,
+}
+
+impl ForwarderSettings {
+    /// Every problem with these settings, or `Ok` if there are none.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if self.name.trim().is_empty() {
+            problems.push("name: must not be empty".to_string());
+        }
+        match self.kind {
+            ForwarderKind::Influxdb => {
+                if !(self.url.starts_with("http://") || self.url.starts_with("https://")) {
+                    problems.push(format!("url: '{}' is not an http:// or https:// URL", self.url));
+                }
+                if self.destination.trim().is_empty() {
+                    problems.push("destination: the InfluxDB bucket must not be empty".to_string());
+                }
+            }
+            ForwarderKind::Timescale => {
+                if !(self.url.starts_with("postgres://") || self.url.starts_with("postgresql://")) {
+                    problems.push(format!("url: '{}' is not a postgres:// URL", self.url));
+                } else {
+                    match tokio_postgres::Config::from_str(&self.url) {
+                        Ok(config) if config.get_password().is_some() => {
+                            problems.push("url: put the password in token, not the URL".to_string())
+                        }
+                        Ok(_) => {}
+                        Err(e) => problems.push(format!("url: {}", e)),
+                    }
+                }
+                if !is_table_name(&self.destination) {
+                    problems.push(format!(
+                        "destination: '{}' is not a table name (letters, digits and _, \
+                         optionally schema.table)",
+                        self.destination
+                    ));
+                }
+            }
+        }
+        if let Some(name) = self.token.as_deref().and_then(secrets::reference_name) {
+            if !secrets::is_valid_name(name) {
+                problems.push(format!("token: invalid secret name: {:?}", name));
+            }
+        }
+        if let Some(batch_size) = self.batch_size {
+            if !(1..=MAX_BATCH_SIZE).contains(&batch_size) {
+                problems.push(format!("batch_size: must be between 1 and {}", MAX_BATCH_SIZE));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+/// Whether `name` is a plain SQL identifier, optionally schema-qualified.
+fn is_table_name(name: &str) -> bool {
+    let parts: Vec<&str> = name.split('.').collect();
+    parts.len() <= 2
+        && parts.iter().all(|part| {
+            let mut chars = part.chars();
+            chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+}
+
+/// A forwarder as shown by the API: its settings with the token redacted,
+/// and how far it has got.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ForwarderInfo {
+    pub id: i32,
+    pub site_id: i32,
+    pub name: String,
+    pub kind: ForwarderKind,
+    pub url: String,
+    pub organization: Option<String>,
+    pub destination: String,
+    /// `***` for a stored token, or the `secret://` reference
+    pub token: Option<String>,
+    pub enabled: bool,
+    pub batch_size: u32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    /// Highest reading id delivered
+    pub last_reading_id: i32,
+    pub last_forwarded_at: Option<NaiveDateTime>,
+    /// Why the last attempt failed, until one succeeds
+    pub last_error: Option<String>,
+    /// The site's readings still to be delivered
+    pub pending_readings: i64,
+}
+
+/// Forwarders of `site_id`, or of every site.
+pub fn list_forwarders(
+    connection: &mut SqliteConnection,
+    site_id: Option<i32>,
+) -> DataResult<Vec<Forwarder>> {
+    use schema::forwarders::dsl;
+
+    let mut query = dsl::forwarders.select(Forwarder::as_select()).into_boxed();
+    if let Some(site_id) = site_id {
+        query = query.filter(dsl::site_id.eq(site_id));
+    }
+    Ok(query.order((dsl::site_id.asc(), dsl::name.asc())).load(connection)?)
+}
+
+pub fn get_forwarder(connection: &mut SqliteConnection, id: i32) -> DataResult<Option<Forwarder>> {
+    use schema::forwarders::dsl;
+
+    Ok(dsl::forwarders
+        .find(id)
+        .select(Forwarder::as_select())
+        .first(connection)
+        .optional()?)
+}
+
+fn invalid(problems: Vec<String>) -> Box<dyn std::error::Error + Send + Sync> {
+    problems.join("; ").into()
+}
+
+/// Validate and store a new forwarder for `site_id`.
+pub fn create_forwarder(
+    connection: &mut SqliteConnection,
+    site_id: i32,
+    settings: &ForwarderSettings,
+) -> DataResult<Forwarder> {
+    use schema::forwarders::dsl;
+
+    settings.validate().map_err(invalid)?;
+    let now = Utc::now().naive_utc();
+    let token = settings.token.as_deref().filter(|token| !token.is_empty());
+    diesel::insert_into(dsl::forwarders)
+        .values((
+            dsl::site_id.eq(site_id),
+            dsl::name.eq(settings.name.trim()),
+            dsl::kind.eq(settings.kind.as_str()),
+            dsl::url.eq(&settings.url),
+            dsl::organization.eq(&settings.organization),
+            dsl::destination.eq(&settings.destination),
+            dsl::token.eq(token),
+            dsl::enabled.eq(settings.enabled),
+            dsl::batch_size.eq(settings.batch_size.unwrap_or(DEFAULT_BATCH_SIZE) as i32),
+            dsl::created_at.eq(now),
+            dsl::updated_at.eq(now),
+        ))
+        .execute(connection)?;
+    Ok(dsl::forwarders
+        .filter(dsl::site_id.eq(site_id))
+        .filter(dsl::name.eq(settings.name.trim()))
+        .select(Forwarder::as_select())
+        .first(connection)?)
+}
+
+/// Validate and replace a forwarder's settings. A `None` token keeps the
+/// stored one. Returns `None` if there's no such forwarder.
+pub fn update_forwarder(
+    connection: &mut SqliteConnection,
+    id: i32,
+    settings: &ForwarderSettings,
+) -> DataResult<Option<Forwarder>> {
+    use schema::forwarders::dsl;
+
+    settings.validate().map_err(invalid)?;
+    let Some(existing) = get_forwarder(connection, id)? else {
+        return Ok(None);
+    };
+    let token = match &settings.token {
+        Some(token) if token.is_empty() => None,
+        Some(token) => Some(token.clone()),
+        None => existing.token,
+    };
+    diesel::update(dsl::forwarders.find(id))
+        .set((
+            dsl::name.eq(settings.name.trim()),
+            dsl::kind.eq(settings.kind.as_str()),
+            dsl::url.eq(&settings.url),
+            dsl::organization.eq(&settings.organization),
+            dsl::destination.eq(&settings.destination),
+            dsl::token.eq(token),
+            dsl::enabled.eq(settings.enabled),
+            dsl::batch_size.eq(settings.batch_size.unwrap_or(DEFAULT_BATCH_SIZE) as i32),
+            dsl::updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(connection)?;
+    get_forwarder(connection, id)
+}
+
+/// Delete a forwarder and its cursor. Returns whether it existed.
+pub fn delete_forwarder(connection: &mut SqliteConnection, id: i32) -> DataResult<bool> {
+    use schema::{forwarders, sync_cursors};
+
+    let target = format!("forwarder:{}", id);
+    let deleted = connection.transaction::<_, diesel::result::Error, _>(|conn| {
+        diesel::delete(sync_cursors::table.filter(sync_cursors::target.eq(&target)))
+            .execute(conn)?;
+        diesel::delete(forwarders::table.find(id)).execute(conn)
+    })?;
+    Ok(deleted > 0)
+}
+
+/// `forwarder` as shown by the API.
+pub fn forwarder_info(
+    connection: &mut SqliteConnection,
+    forwarder: &Forwarder,
+) -> DataResult<ForwarderInfo> {
+    use schema::{readings, sources, sync_cursors};
+
+    let cursor = sync_cursors::table
+        .filter(sync_cursors::target.eq(forwarder.target()))
+        .select((
+            sync_cursors::last_reading_id,
+            sync_cursors::last_synced_at,
+            sync_cursors::last_error,
+        ))
+        .first::<(i32, Option<NaiveDateTime>, Option<String>)>(connection)
+        .optional()?;
+    let (last_reading_id, last_forwarded_at, last_error) = cursor.unwrap_or((0, None, None));
+    let pending_readings = readings::table
+        .inner_join(sources::table)
+        .filter(sources::site_id.eq(forwarder.site_id))
+        .filter(readings::id.gt(last_reading_id))
+        .count()
+        .get_result(connection)?;
+
+    let token = forwarder.token.as_ref().map(|token| match secrets::reference_name(token) {
+        Some(_) => token.clone(),
+        None => secrets::REDACTED.to_string(),
+    });
+    Ok(ForwarderInfo {
+        id: forwarder.id,
+        site_id: forwarder.site_id,
+        name: forwarder.name.clone(),
+        kind: forwarder.kind.parse()?,
+        url: forwarder.url.clone(),
+        organization: forwarder.organization.clone(),
+        destination: forwarder.destination.clone(),
+        token,
+        enabled: forwarder.enabled,
+        batch_size: forwarder.batch_size.max(1) as u32,
+        created_at: forwarder.created_at,
+        updated_at: forwarder.updated_at,
+        last_reading_id,
+        last_forwarded_at,
+        last_error,
+        pending_readings,
+    })
+}
+
+/// The next `limit` readings of `site_id`'s sources after `after_id`, oldest
+/// first.
+pub fn pending_site_readings(
+    connection: &mut SqliteConnection,
+    site_id: i32,
+    after_id: i32,
+    limit: usize,
+) -> DataResult<Vec<SyncReading>> {
+    use schema::{readings, sources};
+
+    let rows = readings::table
+        .inner_join(sources::table)
+        .filter(sources::site_id.eq(site_id))
+        .filter(readings::id.gt(after_id))
+        .order(readings::id.asc())
+        .limit(limit as i64)
+        .select((
+            readings::id,
+            sources::name,
+            sources::test_type,
+            sources::site_id,
+            sources::company_id,
+            readings::timestamp,
+            readings::device_timestamp,
+            readings::data,
+            readings::quality_flags,
+        ))
+        .load::<(
+            Option<i32>,
+            String,
+            Option<String>,
+            Option<i32>,
+            Option<i32>,
+            NaiveDateTime,
+            Option<NaiveDateTime>,
+            String,
+            i32,
+        )>(connection)?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(
+            |(
+                id,
+                source_name,
+                test_type,
+                site_id,
+                company_id,
+                timestamp,
+                device_ts,
+                data,
+                flags,
+            )| {
+                Some(SyncReading {
+                    origin_reading_id: id?,
+                    source_name,
+                    test_type,
+                    site_id,
+                    company_id,
+                    timestamp,
+                    device_timestamp: device_ts,
+                    data,
+                    quality_flags: flags,
+                })
+            },
+        )
+        .collect())
+}
+
+/// When a reading was measured: the device's clock if it reported one.
+fn measured_at(reading: &SyncReading) -> NaiveDateTime {
+    reading.device_timestamp.unwrap_or(reading.timestamp)
+}
+
+/// InfluxDB line protocol for `readings`, one line each.
+///
+/// The measurement is the source's test type (`reading` if it has none),
+/// tagged with `source`, `site_id` and `company_id`. Top-level numbers,
+/// booleans and strings in the reading's data become fields (numbers always
+/// as floats, so a field never changes type), along with the integer
+/// `quality_flags`; nested values and nulls are left out.
+pub fn line_protocol(readings: &[SyncReading]) -> String {
+    let mut lines = String::new();
+    for reading in readings {
+        lines.push_str(&escape(reading.test_type.as_deref().unwrap_or("reading"), ", "));
+        if let Some(company_id) = reading.company_id {
+            lines.push_str(&format!(",company_id={}", company_id));
+        }
+        if let Some(site_id) = reading.site_id {
+            lines.push_str(&format!(",site_id={}", site_id));
+        }
+        lines.push_str(",source=");
+        lines.push_str(&escape(&reading.source_name, ",= "));
+
+        let mut fields = Vec::new();
+        if let Ok(JsonValue::Object(data)) = serde_json::from_str::<JsonValue>(&reading.data) {
+            for (key, value) in data {
+                let value = match value {
+                    JsonValue::Number(n) => match n.as_f64().filter(|n| n.is_finite()) {
+                        Some(n) => format!("{:?}", n),
+                        None => continue,
+                    },
+                    JsonValue::Bool(b) => b.to_string(),
+                    JsonValue::String(s) => {
+                        format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+                    }
+                    _ => continue,
+                };
+                if key != "quality_flags" && !key.is_empty() {
+                    fields.push(format!("{}={}", escape(&key, ",= "), value));
+                }
+            }
+        }
+        fields.push(format!("quality_flags={}i", reading.quality_flags));
+        lines.push(' ');
+        lines.push_str(&fields.join(","));
+
+        let nanos = measured_at(reading).and_utc().timestamp_nanos_opt().unwrap_or_default();
+        lines.push_str(&format!(" {}\n", nanos));
+    }
+    lines
+}
+
+/// Backslash-escape `special` characters (and newlines, which can't be
+/// escaped, are replaced) for line protocol.
+fn escape(text: &str, special: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\n' | '\r' => escaped.push(' '),
+            c if special.contains(c) || c == '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes to an InfluxDB bucket.
+struct InfluxSink {
+    http: reqwest::Client,
+    url: String,
+    organization: Option<String>,
+    bucket: String,
+    token: Option<String>,
+}
+
+impl InfluxSink {
+    /// Network errors, 5xx and other refusals (e.g. a bad token) are errors,
+    /// to be retried; 400 and 422 (unparseable or conflicting points) are
+    /// [`Delivery::Rejected`].
+    async fn send(&self, readings: &[SyncReading]) -> DataResult<Delivery> {
+        let mut query = vec![("bucket", self.bucket.as_str()), ("precision", "ns")];
+        if let Some(organization) = &self.organization {
+            query.push(("org", organization));
+        }
+        let mut request = self
+            .http
+            .post(format!("{}/api/v2/write", self.url.trim_end_matches('/')))
+            .query(&query)
+            .header(reqwest::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body(line_protocol(readings));
+        if let Some(token) = &self.token {
+            request = request.header(reqwest::header::AUTHORIZATION, format!("Token {}", token));
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(Delivery::Accepted);
+        }
+        let message = response.text().await.unwrap_or_default();
+        let message = format!("HTTP {}: {}", status, message.trim());
+        match status.as_u16() {
+            400 | 422 => Ok(Delivery::Rejected(message)),
+            _ => Err(format!("InfluxDB write failed with {}", message).into()),
+        }
+    }
+}
+
+/// Inserts into a Timescale table, creating it on first use.
+struct TimescaleSink {
+    config: tokio_postgres::Config,
+    table: String,
+    origin: String,
+    client: Option<tokio_postgres::Client>,
+}
+
+impl TimescaleSink {
+    async fn connect(&self) -> DataResult<tokio_postgres::Client> {
+        let tls = postgres_native_tls::MakeTlsConnector::new(native_tls::TlsConnector::new()?);
+        let (client, connection) = self.config.connect(tls).await?;
+        let table = self.table.clone();
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                eprintln!("Timescale connection for {} closed: {}", table, e);
+            }
+        });
+
+        let exists: Option<String> =
+            client.query_one("SELECT to_regclass($1)::text", &[&self.table]).await?.get(0);
+        if exists.is_none() {
+            client
+                .batch_execute(&format!(
+                    "CREATE TABLE {} (
+                        time TIMESTAMPTZ NOT NULL,
+                        origin TEXT NOT NULL,
+                        reading_id INTEGER NOT NULL,
+                        source TEXT NOT NULL,
+                        test_type TEXT,
+                        site_id INTEGER,
+                        company_id INTEGER,
+                        quality_flags INTEGER NOT NULL,
+                        data JSONB NOT NULL,
+                        UNIQUE (origin, reading_id, time)
+                    )",
+                    self.table
+                ))
+                .await?;
+            let timescale = client
+                .query_opt("SELECT 1 FROM pg_extension WHERE extname = 'timescaledb'", &[])
+                .await?
+                .is_some();
+            if timescale {
+                client
+                    .execute("SELECT create_hypertable($1::text::regclass, 'time')", &[&self.table])
+                    .await?;
+            } else {
+                println!(
+                    "Created {} as a plain table: the timescaledb extension isn't installed",
+                    self.table
+                );
+            }
+        }
+        Ok(client)
+    }
+
+    /// Any failure is an error, to be retried on a new connection.
+    async fn send(&mut self, readings: &[SyncReading]) -> DataResult<Delivery> {
+        if self.client.as_ref().is_none_or(|client| client.is_closed()) {
+            self.client = Some(self.connect().await?);
+        }
+        let client = self.client.as_ref().expect("connected above");
+
+        let mut times = Vec::with_capacity(readings.len());
+        let mut ids = Vec::with_capacity(readings.len());
+        let mut sources = Vec::with_capacity(readings.len());
+        let mut test_types = Vec::with_capacity(readings.len());
+        let mut site_ids = Vec::with_capacity(readings.len());
+        let mut company_ids = Vec::with_capacity(readings.len());
+        let mut flags = Vec::with_capacity(readings.len());
+        let mut data = Vec::with_capacity(readings.len());
+        for reading in readings {
+            times.push(measured_at(reading).and_utc());
+            ids.push(reading.origin_reading_id);
+            sources.push(reading.source_name.clone());
+            test_types.push(reading.test_type.clone());
+            site_ids.push(reading.site_id);
+            company_ids.push(reading.company_id);
+            flags.push(reading.quality_flags);
+            data.push(
+                serde_json::from_str::<JsonValue>(&reading.data)
+                    .unwrap_or_else(|_| JsonValue::String(reading.data.clone())),
+            );
+        }
+
+        let statement = format!(
+            "INSERT INTO {} (time, origin, reading_id, source, test_type, site_id, company_id, \
+             quality_flags, data) \
+             SELECT t.time, $1, t.reading_id, t.source, t.test_type, t.site_id, t.company_id, \
+             t.quality_flags, t.data \
+             FROM UNNEST($2::timestamptz[], $3::int4[], $4::text[], $5::text[], $6::int4[], \
+             $7::int4[], $8::int4[], $9::jsonb[]) \
+             AS t(time, reading_id, source, test_type, site_id, company_id, quality_flags, data) \
+             ON CONFLICT DO NOTHING",
+            self.table
+        );
+        let result = client
+            .execute(
+                &statement,
+                &[
+                    &self.origin,
+                    &times,
+                    &ids,
+                    &sources,
+                    &test_types,
+                    &site_ids,
+                    &company_ids,
+                    &flags,
+                    &data,
+                ],
+            )
+            .await;
+        if let Err(e) = result {
+            self.client = None;
+            return Err(format!("Timescale insert into {} failed: {}", self.table, e).into());
+        }
+        Ok(Delivery::Accepted)
+    }
+}
+
+enum Sink {
+    Influx(InfluxSink),
+    Timescale(Box<TimescaleSink>),
+}
+
+impl Sink {
+    fn new(forwarder: &Forwarder, token: Option<String>) -> DataResult<Self> {
+        Ok(match forwarder.kind.parse::<ForwarderKind>()? {
+            ForwarderKind::Influxdb => Self::Influx(InfluxSink {
+                http: reqwest::Client::builder().timeout(Duration::from_secs(60)).build()?,
+                url: forwarder.url.clone(),
+                organization: forwarder.organization.clone(),
+                bucket: forwarder.destination.clone(),
+                token,
+            }),
+            ForwarderKind::Timescale => {
+                let mut config = tokio_postgres::Config::from_str(&forwarder.url)?;
+                config.connect_timeout(Duration::from_secs(30));
+                if let Some(password) = token {
+                    config.password(password);
+                }
+                Self::Timescale(Box::new(TimescaleSink {
+                    config,
+                    table: forwarder.destination.clone(),
+                    origin: sysinfo::System::host_name().unwrap_or_else(|| "edge".to_string()),
+                    client: None,
+                }))
+            }
+        })
+    }
+
+    async fn send(&mut self, readings: &[SyncReading]) -> DataResult<Delivery> {
+        match self {
+            Self::Influx(sink) => sink.send(readings).await,
+            Self::Timescale(sink) => sink.send(readings).await,
+        }
+    }
+}
+
+/// The forwarder's token, with a `secret://` reference resolved.
+fn resolve_token(
+    connection: &mut SqliteConnection,
+    forwarder: &Forwarder,
+) -> DataResult<Option<String>> {
+    let Some(token) = &forwarder.token else {
+        return Ok(None);
+    };
+    let Some(name) = secrets::reference_name(token) else {
+        return Ok(Some(token.clone()));
+    };
+    let key = MasterKey::from_env()?.ok_or_else(|| {
+        format!("token refers to secret '{}' but {} is not set", name, secrets::KEY_ENV)
+    })?;
+    let value = secrets::get_secret(connection, key, name)?
+        .ok_or_else(|| format!("token refers to secret '{}', which does not exist", name))?;
+    Ok(Some(value))
+}
+
+/// Deliver readings from `cursor` until caught up. Returns the new cursor and
+/// the number of readings delivered.
+async fn forward_from(
+    database_url: &str,
+    forwarder: &Forwarder,
+    sink: &mut Sink,
+    mut cursor: i32,
+) -> DataResult<(i32, usize)> {
+    let target = forwarder.target();
+    let mut delivered = 0;
+    loop {
+        let readings = {
+            let database_url = database_url.to_string();
+            let site_id = forwarder.site_id;
+            let batch_size = forwarder.batch_size.max(1) as usize;
+            tokio::task::spawn_blocking(move || {
+                let mut connection = encryption::establish(&database_url)?;
+                pending_site_readings(&mut connection, site_id, cursor, batch_size)
+            })
+            .await??
+        };
+        let Some(last_id) = readings.last().map(|reading| reading.origin_reading_id) else {
+            return Ok((cursor, delivered));
+        };
+
+        let error = match sink.send(&readings).await? {
+            Delivery::Accepted => {
+                delivered += readings.len();
+                None
+            }
+            Delivery::Rejected(message) => {
+                eprintln!(
+                    "{} - Forwarder '{}' skipped readings {}-{}, which {} rejected: {}",
+                    Local::now().to_rfc3339(),
+                    forwarder.name,
+                    cursor + 1,
+                    last_id,
+                    forwarder.url,
+                    message
+                );
+                Some(message)
+            }
+        };
+        cursor = last_id;
+
+        let database_url = database_url.to_string();
+        let target = target.clone();
+        tokio::task::spawn_blocking(move || -> DataResult<()> {
+            let mut connection = encryption::establish(&database_url)?;
+            save_cursor(&mut connection, &target, cursor, error.as_deref())
+        })
+        .await??;
+    }
+}
+
+/// Forward one forwarder's readings forever, backing off exponentially while
+/// its historian is unreachable.
+pub async fn run_forwarder(database_url: String, forwarder: Forwarder) {
+    let target = forwarder.target();
+    let mut sink: Option<Sink> = None;
+    let mut cursor: Option<i32> = None;
+    let mut backoff = FORWARDERS_POLL_INTERVAL;
+    loop {
+        let result = async {
+            if sink.is_none() || cursor.is_none() {
+                let database_url = database_url.clone();
+                let stored = forwarder.clone();
+                let (token, start) = tokio::task::spawn_blocking(move || -> DataResult<_> {
+                    let mut connection = encryption::establish(&database_url)?;
+                    let token = resolve_token(&mut connection, &stored)?;
+                    Ok((token, load_cursor(&mut connection, &stored.target())?))
+                })
+                .await??;
+                if sink.is_none() {
+                    sink = Some(Sink::new(&forwarder, token)?);
+                }
+                cursor = Some(start);
+            }
+            let sink = sink.as_mut().expect("created above");
+            forward_from(&database_url, &forwarder, sink, cursor.unwrap_or_default()).await
+        }
+        .await;
+
+        let wait = match result {
+            Ok((new_cursor, delivered)) => {
+                if delivered > 0 {
+                    println!(
+                        "{} - Forwarded {} readings to {} '{}' (cursor {})",
+                        Local::now().to_rfc3339(),
+                        delivered,
+                        forwarder.kind,
+                        forwarder.name,
+                        new_cursor
+                    );
+                }
+                cursor = Some(new_cursor);
+                backoff = FORWARDERS_POLL_INTERVAL;
+                FORWARDERS_POLL_INTERVAL
+            }
+            Err(e) => {
+                eprintln!(
+                    "{} - Forwarder '{}' failed: {}",
+                    Local::now().to_rfc3339(),
+                    forwarder.name,
+                    e
+                );
+                let database_url = database_url.clone();
+                let target = target.clone();
+                let error = e.to_string();
+                let _ = tokio::task::spawn_blocking(move || -> DataResult<()> {
+                    let mut connection = encryption::establish(&database_url)?;
+                    let last = load_cursor(&mut connection, &target)?;
+                    save_cursor(&mut connection, &target, last, Some(&error))
+                })
+                .await;
+                cursor = None;
+                let wait = backoff;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                wait
+            }
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Keep a [`run_forwarder`] task running for every enabled forwarder,
+/// restarting one when its settings change and stopping it when it's
+/// disabled or deleted. Runs until the process exits.
+pub async fn run_forwarders(database_url: String) {
+    let mut running: HashMap<i32, (Forwarder, tokio::task::JoinHandle<()>)> = HashMap::new();
+    let mut interval = tokio::time::interval(FORWARDERS_POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        let loaded = {
+            let database_url = database_url.clone();
+            tokio::task::spawn_blocking(move || {
+                let mut connection = encryption::establish(&database_url)?;
+                list_forwarders(&mut connection, None)
+            })
+            .await
+        };
+        let forwarders = match loaded {
+            Ok(Ok(forwarders)) => forwarders,
+            Ok(Err(e)) => {
+                eprintln!("Error loading forwarders: {}", e);
+                continue;
+            }
+            Err(e) => {
+                eprintln!("Forwarder loading task failed: {}", e);
+                continue;
+            }
+        };
+
+        let mut wanted: HashMap<i32, Forwarder> = forwarders
+            .into_iter()
+            .filter(|forwarder| forwarder.enabled)
+            .map(|forwarder| (forwarder.id, forwarder))
+            .collect();
+        running.retain(|id, (forwarder, handle)| {
+            if wanted.get(id) == Some(&*forwarder) {
+                return true;
+            }
+            handle.abort();
+            println!("Stopped forwarder '{}'", forwarder.name);
+            false
+        });
+        for (id, forwarder) in wanted.drain() {
+            if running.contains_key(&id) {
+                continue;
+            }
+            println!(
+                "Forwarding site {} readings to {} '{}' at {}",
+                forwarder.site_id, forwarder.kind, forwarder.name, forwarder.url
+            );
+            let handle = tokio::spawn(run_forwarder(database_url.clone(), forwarder.clone()));
+            running.insert(id, (forwarder, handle));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(kind: ForwarderKind, url: &str, destination: &str) -> ForwarderSettings {
+        ForwarderSettings {
+            name: "historian".to_string(),
+            kind,
+            url: url.to_string(),
+            organization: None,
+            destination: destination.to_string(),
+            token: None,
+            enabled: true,
+            batch_size: None,
+        }
+    }
+
+    #[test]
+    fn test_settings_validation() {
+        assert!(
+            settings(ForwarderKind::Influxdb, "https://influx:8086", "neems")
+                .validate()
+                .is_ok()
+        );
+        assert!(
+            settings(ForwarderKind::Timescale, "postgres://neems@db/metrics", "public.readings")
+                .validate()
+                .is_ok()
+        );
+
+        let mut bad = settings(ForwarderKind::Timescale, "postgres://neems:pw@db/metrics", "x;y");
+        bad.batch_size = Some(0);
+        let problems = bad.validate().unwrap_err().join("\n");
+        assert!(problems.contains("put the password in token"), "{}", problems);
+        assert!(problems.contains("'x;y' is not a table name"), "{}", problems);
+        assert!(problems.contains("batch_size"), "{}", problems);
+        assert!(settings(ForwarderKind::Influxdb, "influx:8086", "").validate().is_err());
+    }
+
+    #[test]
+    fn test_line_protocol() {
+        let ts = chrono::DateTime::from_timestamp(1_700_000_000, 5).unwrap().naive_utc();
+        let reading = |data: &str| SyncReading {
+            origin_reading_id: 1,
+            source_name: "bess 1,a".to_string(),
+            test_type: Some("simulator".to_string()),
+            site_id: Some(3),
+            company_id: None,
+            timestamp: ts,
+            device_timestamp: None,
+            data: data.to_string(),
+            quality_flags: 2,
+        };
+        let lines = line_protocol(&[
+            reading(r#"{"soc_percent": 50, "mode": "say \"hi\"", "ok": true, "nested": {"a": 1}}"#),
+            reading("not json"),
+        ]);
+        assert_eq!(
+            lines,
+            "simulator,site_id=3,source=bess\\ 1\\,a \
+             mode=\"say \\\"hi\\\"\",ok=true,soc_percent=50.0,quality_flags=2i 1700000000000000005\n\
+             simulator,site_id=3,source=bess\\ 1\\,a quality_flags=2i 1700000000000000005\n"
+        );
+    }
+
+    #[test]
+    fn test_forwarder_crud_and_pending_readings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("site.sqlite");
+        let mut conn = crate::DataAggregator::new(Some(&path.to_string_lossy()))
+            .establish_connection()
+            .unwrap();
+
+        let mut new = settings(ForwarderKind::Influxdb, "http://influx:8086", "neems");
+        new.token = Some("t0ken".to_string());
+        let forwarder = create_forwarder(&mut conn, 3, &new).unwrap();
+        assert_eq!(forwarder.batch_size, 500);
+
+        // A null token keeps the stored one
+        new.token = None;
+        new.batch_size = Some(50);
+        let forwarder = update_forwarder(&mut conn, forwarder.id, &new).unwrap().unwrap();
+        assert_eq!(forwarder.token.as_deref(), Some("t0ken"));
+        assert_eq!(forwarder.batch_size, 50);
+
+        let source = crate::create_source(
+            &mut conn,
+            crate::NewSource {
+                name: "bess-1".to_string(),
+                description: None,
+                active: Some(true),
+                interval_seconds: None,
+                test_type: Some("simulator".to_string()),
+                arguments: None,
+                site_id: Some(3),
+                company_id: None,
+                priority: None,
+                point_fields: None,
+            },
+        )
+        .unwrap();
+        for level in [1, 2, 3] {
+            diesel::insert_into(schema::readings::table)
+                .values((
+                    schema::readings::source_id.eq(source.id.unwrap()),
+                    schema::readings::data.eq(format!(r#"{{"level": {}}}"#, level)),
+                ))
+                .execute(&mut conn)
+                .unwrap();
+        }
+        let readings = pending_site_readings(&mut conn, 3, 0, 2).unwrap();
+        assert_eq!(readings.len(), 2);
+        assert!(pending_site_readings(&mut conn, 4, 0, 10).unwrap().is_empty());
+
+        save_cursor(&mut conn, &forwarder.target(), readings[1].origin_reading_id, None).unwrap();
+        let info = forwarder_info(&mut conn, &forwarder).unwrap();
+        assert_eq!(info.token.as_deref(), Some("***"));
+        assert_eq!(info.pending_readings, 1);
+        assert!(info.last_forwarded_at.is_some());
+
+        assert!(delete_forwarder(&mut conn, forwarder.id).unwrap());
+        assert!(list_forwarders(&mut conn, Some(3)).unwrap().is_empty());
+        assert_eq!(load_cursor(&mut conn, &forwarder.target()).unwrap(), 0);
+    }
+}
@@ -0,0 +1,294 @@
+//! Translations of user-facing text.
+//!
+//! Messages are written in English throughout, and the English text is the
+//! key: [`translate`] looks it up in a fixed catalog of Spanish and French
+//! translations, and text the catalog lacks stays in English. Templates name
+//! their values in braces, e.g. `{source}`, filled in by [`fill`], so a
+//! translation can put them in its own order.
+//!
+//! neems-api uses the catalog for its error messages and reports, in the
+//! user's or company's locale, and [`crate::notifications`] for alarm
+//! notifications, in the channel's.
+
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// A language the API and notifications can be read in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum Locale {
+    #[default]
+    En,
+    Es,
+    Fr,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 3] = [Locale::En, Locale::Es, Locale::Fr];
+
+    /// The ISO 639-1 code, e.g. `es`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::En => "en",
+            Self::Es => "es",
+            Self::Fr => "fr",
+        }
+    }
+
+    /// The language's own name for itself.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::En => "English",
+            Self::Es => "Español",
+            Self::Fr => "Français",
+        }
+    }
+
+    /// The locale a request's `Accept-Language` header prefers, if any is
+    /// one of ours.
+    ///
+    /// Follows RFC 9110 quality values, matching on the primary language
+    /// subtag, so `es-MX` is Spanish; `*` is English.
+    pub fn negotiate(accept_language: &str) -> Option<Locale> {
+        let mut best: Option<(Locale, f32)> = None;
+        for item in accept_language.split(',') {
+            let mut parts = item.split(';');
+            let tag = parts.next().unwrap_or("").trim();
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let locale = match tag {
+                "*" => Some(Locale::En),
+                tag => tag.parse().ok(),
+            };
+            if let Some(locale) = locale
+                && quality > 0.0
+                && best.is_none_or(|(_, best)| quality > best)
+            {
+                best = Some((locale, quality));
+            }
+        }
+        best.map(|(locale, _)| locale)
+    }
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    /// Parses a language tag, e.g. `fr` or `fr-CA`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let language = s.split(['-', '_']).next().unwrap_or("");
+        Locale::ALL
+            .into_iter()
+            .find(|locale| locale.as_str().eq_ignore_ascii_case(language))
+            .ok_or_else(|| format!("Unsupported locale: {}", s))
+    }
+}
+
+/// English, Spanish and French, in that order.
+const CATALOG: &[(&str, &str, &str)] = &[
+    // API errors
+    ("Unauthorized", "No autorizado", "Non autorisé"),
+    ("Forbidden", "Prohibido", "Interdit"),
+    ("Not Found", "No encontrado", "Introuvable"),
+    ("Unprocessable Entity", "Entidad no procesable", "Entité non traitable"),
+    (
+        "Internal Server Error",
+        "Error interno del servidor",
+        "Erreur interne du serveur",
+    ),
+    (
+        "Internal server error",
+        "Error interno del servidor",
+        "Erreur interne du serveur",
+    ),
+    ("Database error", "Error de base de datos", "Erreur de base de données"),
+    (
+        "Forbidden: insufficient permissions",
+        "Prohibido: permisos insuficientes",
+        "Interdit : autorisations insuffisantes",
+    ),
+    ("Invalid credentials", "Credenciales no válidas", "Identifiants invalides"),
+    (
+        "Users can only change their own password",
+        "Los usuarios solo pueden cambiar su propia contraseña",
+        "Les utilisateurs ne peuvent modifier que leur propre mot de passe",
+    ),
+    (
+        "Insufficient permissions to create users",
+        "Permisos insuficientes para crear usuarios",
+        "Autorisations insuffisantes pour créer des utilisateurs",
+    ),
+    (
+        "At least one role must be provided",
+        "Debe indicarse al menos un rol",
+        "Au moins un rôle doit être fourni",
+    ),
+    (
+        "User with this email already exists",
+        "Ya existe un usuario con este correo electrónico",
+        "Un utilisateur avec cette adresse e-mail existe déjà",
+    ),
+    ("Company not found", "Empresa no encontrada", "Entreprise introuvable"),
+    ("Site not found", "Sitio no encontrado", "Site introuvable"),
+    ("User not found", "Usuario no encontrado", "Utilisateur introuvable"),
+    ("Device not found", "Dispositivo no encontrado", "Appareil introuvable"),
+    (
+        "Device group not found",
+        "Grupo de dispositivos no encontrado",
+        "Groupe d'appareils introuvable",
+    ),
+    ("Source not found", "Fuente no encontrada", "Source introuvable"),
+    ("KPI not found", "KPI no encontrado", "KPI introuvable"),
+    (
+        "Notification channel not found",
+        "Canal de notificación no encontrado",
+        "Canal de notification introuvable",
+    ),
+    (
+        "Maintenance window not found",
+        "Ventana de mantenimiento no encontrada",
+        "Fenêtre de maintenance introuvable",
+    ),
+    (
+        "Escalation policy not found",
+        "Política de escalado no encontrada",
+        "Politique d'escalade introuvable",
+    ),
+    (
+        "On-call rotation not found",
+        "Rotación de guardia no encontrada",
+        "Rotation d'astreinte introuvable",
+    ),
+    ("Nobody is on call", "No hay nadie de guardia", "Personne n'est d'astreinte"),
+    (
+        "Site does not belong to the specified company",
+        "El sitio no pertenece a la empresa indicada",
+        "Le site n'appartient pas à l'entreprise indiquée",
+    ),
+    ("The site has no tariff", "El sitio no tiene tarifa", "Le site n'a pas de tarif"),
+    (
+        "The date range is longer than a year",
+        "El intervalo de fechas supera un año",
+        "La période dépasse un an",
+    ),
+    (
+        "The file is too large",
+        "El archivo es demasiado grande",
+        "Le fichier est trop volumineux",
+    ),
+    // Alarm notifications
+    ("Info", "informativa", "d'information"),
+    ("Warning", "de advertencia", "d'avertissement"),
+    ("Critical", "crítica", "critique"),
+    ("Emergency", "de emergencia", "d'urgence"),
+    (
+        "{severity} alarm raised: {name} ({zone}) on {source}",
+        "Alarma {severity} activada: {name} ({zone}) en {source}",
+        "Alarme {severity} déclenchée : {name} ({zone}) sur {source}",
+    ),
+    (
+        "{severity} alarm cleared: {name} ({zone}) on {source}",
+        "Alarma {severity} despejada: {name} ({zone}) en {source}",
+        "Alarme {severity} levée : {name} ({zone}) sur {source}",
+    ),
+    (" at site {site}", " en el sitio {site}", " au site {site}"),
+    (
+        "Alarm {alarm} at {time}",
+        "Alarma {alarm} a las {time}",
+        "Alarme {alarm} à {time}",
+    ),
+    (
+        "Unacknowledged for {minutes} minutes; escalated to tier {tier} of '{policy}'",
+        "Sin reconocer durante {minutes} minutos; escalada al nivel {tier} de '{policy}'",
+        "Non acquittée depuis {minutes} minutes ; escaladée au niveau {tier} de « {policy} »",
+    ),
+    ("Site", "Sitio", "Site"),
+    ("Source", "Fuente", "Source"),
+    ("Alarm", "Alarma", "Alarme"),
+    ("Zone", "Zona", "Zone"),
+    ("Time", "Hora", "Heure"),
+    // Economics report columns
+    ("period_start", "Inicio del periodo", "Début de la période"),
+    ("charged_kwh", "Energía cargada (kWh)", "Énergie chargée (kWh)"),
+    ("discharged_kwh", "Energía descargada (kWh)", "Énergie déchargée (kWh)"),
+    (
+        "round_trip_efficiency_percent",
+        "Eficiencia de ida y vuelta (%)",
+        "Rendement aller-retour (%)",
+    ),
+    ("charge_cost", "Coste de carga", "Coût de charge"),
+    ("discharge_value", "Valor de descarga", "Valeur de décharge"),
+    ("arbitrage_revenue", "Ingresos por arbitraje", "Revenus d'arbitrage"),
+    ("peak_demand_kw", "Demanda máxima (kW)", "Demande de pointe (kW)"),
+    ("peak_net_demand_kw", "Demanda neta máxima (kW)", "Demande nette de pointe (kW)"),
+    (
+        "demand_charge_savings",
+        "Ahorro en cargo por demanda",
+        "Économies sur la prime de puissance",
+    ),
+    ("charge_emissions_kg", "Emisiones de carga (kg)", "Émissions de charge (kg)"),
+    ("avoided_emissions_kg", "Emisiones evitadas (kg)", "Émissions évitées (kg)"),
+    ("availability_percent", "Disponibilidad (%)", "Disponibilité (%)"),
+];
+
+/// `english` in `locale`, if the catalog has it.
+pub fn translate(locale: Locale, english: &str) -> Option<&'static str> {
+    let (en, es, fr) = CATALOG.iter().find(|(en, _, _)| *en == english)?;
+    Some(match locale {
+        Locale::En => en,
+        Locale::Es => es,
+        Locale::Fr => fr,
+    })
+}
+
+/// `english` in `locale`, or in English where the catalog lacks it.
+pub fn tr(locale: Locale, english: &'static str) -> &'static str {
+    translate(locale, english).unwrap_or(english)
+}
+
+/// `template` with each `{name}` replaced by its value.
+pub fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    values.iter().fold(template.to_string(), |text, (name, value)| {
+        text.replace(&format!("{{{}}}", name), value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(Locale::negotiate("es-MX,es;q=0.9,en;q=0.8"), Some(Locale::Es));
+        assert_eq!(Locale::negotiate("de, fr-CA;q=0.7, en;q=0.5"), Some(Locale::Fr));
+        assert_eq!(Locale::negotiate("fr;q=0, *;q=0.1"), Some(Locale::En));
+        assert_eq!(Locale::negotiate("de, ja"), None);
+        assert_eq!(Locale::negotiate(""), None);
+        assert_eq!("FR_ca".parse(), Ok(Locale::Fr));
+        assert!("pt".parse::<Locale>().is_err());
+    }
+
+    #[test]
+    fn translations_keep_placeholders() {
+        let placeholders = |text: &str| {
+            let mut names: Vec<String> = text
+                .split('{')
+                .skip(1)
+                .filter_map(|part| part.split_once('}').map(|(name, _)| name.to_string()))
+                .collect();
+            names.sort();
+            names
+        };
+        for (en, es, fr) in CATALOG {
+            assert_eq!(placeholders(en), placeholders(es), "{}", en);
+            assert_eq!(placeholders(en), placeholders(fr), "{}", en);
+        }
+        assert_eq!(fill(tr(Locale::Es, " at site {site}"), &[("site", "3")]), " en el sitio 3");
+        assert_eq!(tr(Locale::Fr, "Not in the catalog"), "Not in the catalog");
+    }
+}
@@ -1,25 +1,64 @@
-use std::{collections::HashSet, env, error::Error, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    error::Error,
+    path::PathBuf,
+    sync::Arc,
+};
 
 use chrono::Local;
 use collectors::DataCollector;
+use concurrency::CollectorLimits;
 use diesel::{prelude::*, sqlite::SqliteConnection};
 use diesel_migrations::{EmbeddedMigrations, MigrationHarness, embed_migrations};
 use futures_util::stream::StreamExt;
+use secrets::SecretStore;
 use signal_hook::consts::SIGHUP;
 use signal_hook_tokio::Signals;
 use tokio::{
-    sync::{Mutex, mpsc},
+    sync::{Mutex, mpsc, watch},
     task,
 };
 
+pub mod archive;
+pub mod bench;
+pub mod clock;
 pub mod collectors;
+pub mod concurrency;
+pub mod config;
+pub mod edge_config;
+pub mod encryption;
+pub mod forward;
+pub mod i18n;
+pub mod logs;
+pub mod maintenance;
+pub mod maintenance_windows;
 pub mod models;
+pub mod mqtt;
+pub mod notifications;
+pub mod points;
+pub mod prometheus;
+pub mod reconcile;
 pub mod rtac;
+pub mod runtime;
+pub mod scheduler;
 pub mod schema;
+pub mod secrets;
 pub mod seed;
-
+pub mod snmp;
+pub mod status;
+pub mod storage;
+pub mod support;
+pub mod sync;
+pub mod systemd;
+pub mod writer;
+
+pub use concurrency::{CollectorMetrics, ConcurrencyConfig};
 pub use models::*;
+pub use scheduler::ReaderConfig;
 pub use seed::{SeedOutcome, seed_alarm_history, seed_soc_history, seeded_alarm_flags};
+use writer::{ReadingReceiver, ReadingSender};
+pub use writer::{WriterConfig, WriterMetrics};
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
 
@@ -30,12 +69,26 @@ pub type DataResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
 
 pub struct DataAggregator {
     database_url: String,
+    writer_config: WriterConfig,
+    reader_config: ReaderConfig,
+    sync_config: Option<sync::SyncConfig>,
+    prometheus_config: Option<prometheus::PrometheusConfig>,
+    mqtt_config: Option<mqtt::MqttConfig>,
+    snmp_config: Option<snmp::SnmpConfig>,
+    logs_config: Option<logs::LogsConfig>,
+    maintenance_config: maintenance::MaintenanceConfig,
+    log_level: String,
+    config_path: Option<PathBuf>,
+    log_control: Option<runtime::LogControl>,
 }
 
 #[derive(Debug, Clone)]
 pub struct PendingReading {
     pub reading: NewReading,
     pub source_name: String,
+    /// Critical readings are never dropped when the writer queue is full;
+    /// the reader waits for space instead.
+    pub critical: bool,
 }
 
 impl DataAggregator {
@@ -48,11 +101,69 @@ impl DataAggregator {
         };
         let database_url = format!("sqlite://{}", database_path);
 
-        Self { database_url }
+        Self {
+            database_url,
+            writer_config: WriterConfig::from_env(),
+            reader_config: ReaderConfig::from_env(),
+            sync_config: sync::SyncConfig::from_env(),
+            prometheus_config: prometheus::PrometheusConfig::from_env(),
+            mqtt_config: mqtt::MqttConfig::from_env(),
+            snmp_config: snmp::SnmpConfig::from_env(),
+            logs_config: logs::LogsConfig::from_env(),
+            maintenance_config: maintenance::MaintenanceConfig::from_env(),
+            log_level: env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string()),
+            config_path: None,
+            log_control: None,
+        }
+    }
+
+    /// Build an aggregator from resolved [`config::Settings`].
+    pub fn from_settings(settings: &config::Settings) -> Self {
+        Self {
+            database_url: format!("sqlite://{}", settings.database_path),
+            writer_config: settings.writer.clone(),
+            reader_config: settings.reader.clone(),
+            sync_config: settings.sync.clone(),
+            prometheus_config: settings.prometheus.clone(),
+            mqtt_config: settings.mqtt.clone(),
+            snmp_config: settings.snmp.clone(),
+            logs_config: settings.logs.clone(),
+            maintenance_config: settings.maintenance.clone(),
+            log_level: settings.log_level.clone(),
+            config_path: None,
+            log_control: None,
+        }
+    }
+
+    /// The config file to re-read on SIGHUP (see [`runtime`]).
+    pub fn with_config_path(mut self, config_path: Option<PathBuf>) -> Self {
+        self.config_path = config_path;
+        self
+    }
+
+    /// Lets runtime setting changes reach the `tracing` filter (see
+    /// [`runtime::init_tracing`]).
+    pub fn with_log_control(mut self, log_control: Option<runtime::LogControl>) -> Self {
+        self.log_control = log_control;
+        self
+    }
+
+    /// Override the writer queue and batching settings (by default read from
+    /// the environment, see [`WriterConfig::from_env`]).
+    pub fn with_writer_config(mut self, writer_config: WriterConfig) -> Self {
+        self.writer_config = writer_config;
+        self
+    }
+
+    /// Override the reader loop scheduling settings (by default read from the
+    /// environment, see [`ReaderConfig::from_env`]).
+    pub fn with_reader_config(mut self, reader_config: ReaderConfig) -> Self {
+        self.reader_config = reader_config;
+        self
     }
 
     pub fn establish_connection(&self) -> Result<SqliteConnection, Box<dyn Error + Send + Sync>> {
-        let mut connection = SqliteConnection::establish(&self.database_url)?;
+        let mut connection = encryption::establish(&self.database_url)?;
         connection
             .run_pending_migrations(MIGRATIONS)
             .map_err(|e| format!("Error running migrations: {}", e))?;
@@ -91,19 +202,131 @@ impl DataAggregator {
             })?;
         }
 
-        // Create a channel for collecting readings
-        let (tx, rx) = mpsc::unbounded_channel::<PendingReading>();
+        // Upload readings to the central server when one is configured. The
+        // sync task outlives outages on its own, so it isn't joined below.
+        if let Some(sync_config) = self.sync_config.clone() {
+            println!(
+                "Syncing readings to {} as origin '{}'",
+                sync_config.base_url, sync_config.origin
+            );
+            tokio::spawn(sync::run_sync_loop(self.database_url.clone(), sync_config));
+        }
+
+        // Likewise forward reading points to a Prometheus remote-write endpoint
+        if let Some(prometheus_config) = self.prometheus_config.clone() {
+            println!("Exporting reading points to {}", prometheus_config.url);
+            tokio::spawn(prometheus::run_prometheus_loop(
+                self.database_url.clone(),
+                prometheus_config,
+            ));
+        }
+
+        // Publish state changes, alarms and selected readings to an MQTT broker
+        if let Some(mqtt_config) = self.mqtt_config.clone() {
+            println!("Publishing state and alarms to {} as {}", mqtt_config.url, mqtt_config.topic);
+            tokio::spawn(mqtt::run_mqtt_loop(self.database_url.clone(), mqtt_config));
+        }
+
+        // Send alarm and device-offline traps to the NOC's receivers
+        if let Some(snmp_config) = self.snmp_config.clone() {
+            println!("Sending SNMP traps to {}", snmp_config.targets.join(", "));
+            tokio::spawn(snmp::run_snmp_loop(self.database_url.clone(), snmp_config));
+        }
+
+        // Serve recent logs to support staff through the central proxy
+        if let Some(logs_config) = self.logs_config.clone() {
+            println!("Serving recent logs on {}", logs_config.listen);
+            tokio::spawn(logs::run_logs_server(logs_config));
+        }
+
+        // Mirror readings to the historians configured per site, as the
+        // forwarders table changes
+        tokio::spawn(forward::run_forwarders(self.database_url.clone()));
+
+        // Notify each company's Slack, Teams and PagerDuty channels of alarms,
+        // as the notification_channels table changes
+        tokio::spawn(notifications::run_channels(self.database_url.clone()));
+
+        // Vacuum, ANALYZE and checkpoint during the configured quiet window
+        let maintenance_config = self.maintenance_config.clone();
+        if let Some(window) = maintenance_config.window {
+            if verbose {
+                println!(
+                    "Database maintenance window: {}-{}",
+                    window.start.format("%H:%M"),
+                    window.end.format("%H:%M")
+                );
+            }
+            tokio::spawn(maintenance::run_maintenance_loop(
+                self.database_url.clone(),
+                maintenance_config,
+                verbose,
+            ));
+        }
+
+        // Create a bounded queue for collected readings
+        let writer_config = self.writer_config.clone();
+        let (tx, rx) = writer::reading_queue(writer_config.queue_capacity);
 
         // Shared state to track sources with pending writes
         let pending_sources = Arc::new(Mutex::new(HashSet::<i32>::new()));
 
-        // Start the writer task that batches writes every second
-        let writer_handle =
-            Self::start_writer_task(database_url.clone(), rx, pending_sources.clone(), verbose);
+        // Log level, batching and concurrency limits can change while running
+        let (tunables_tx, tunables_rx) = watch::channel(runtime::Tunables::new(
+            &self.log_level,
+            &writer_config,
+            &self.reader_config.concurrency,
+        ));
+        let (config_reload_tx, config_reload_rx) = mpsc::channel(1);
+        tokio::spawn(runtime::run_tunables_loop(
+            database_url.clone(),
+            self.config_path.clone(),
+            self.log_control.clone(),
+            tunables_tx,
+            config_reload_rx,
+        ));
+
+        // Under systemd, keep-alives only go out while both loops turn over
+        let liveness = systemd::Liveness::new();
+        let writer_heartbeat = liveness.register("writer");
+        let reader_heartbeat = liveness.register("reader");
+        if let Some(timeout) = systemd::watchdog_timeout() {
+            if verbose {
+                println!("systemd watchdog enabled ({}s)", timeout.as_secs());
+            }
+            tokio::spawn(systemd::run_watchdog(liveness, timeout));
+        }
+
+        // Publish the scheduler and queue status for neems-api's status page
+        let status_board = status::StatusBoard::new();
+        tokio::spawn(status::run_status_loop(database_url.clone(), status_board.clone()));
+
+        // Start the writer task that batches writes
+        let writer_handle = Self::start_writer_task(
+            database_url.clone(),
+            rx,
+            tunables_rx.clone(),
+            pending_sources.clone(),
+            writer_heartbeat,
+            verbose,
+        );
 
         // Create a channel to notify reader tasks of source reloads
         let (reload_tx, reload_rx) = mpsc::channel(1);
 
+        // Apply configuration bundles the central server targets at this
+        // origin, reloading the sources after each
+        if let Some(sync_config) = self.sync_config.clone() {
+            if let Some(interval) = sync_config.config_interval {
+                tokio::spawn(edge_config::run_config_loop(
+                    self.database_url.clone(),
+                    sync_config,
+                    interval,
+                    reload_tx.clone(),
+                ));
+            }
+        }
+
         // Set up the SIGHUP signal handler
         let signals = Signals::new([SIGHUP])?;
         let handle = signals.handle();
@@ -111,7 +334,9 @@ impl DataAggregator {
             let mut signals = signals.fuse();
             while let Some(signal) = signals.next().await {
                 if signal == SIGHUP {
-                    println!("SIGHUP received, triggering source reload...");
+                    println!("SIGHUP received, triggering source and config reload...");
+                    // A reload already pending covers this one too
+                    let _ = config_reload_tx.try_send(());
                     if reload_tx.send(()).await.is_err() {
                         eprintln!("Failed to send reload signal to reader task");
                         break;
@@ -121,11 +346,24 @@ impl DataAggregator {
         });
 
         // Start the reader tasks
-        let reader_handle =
-            Self::start_reader_tasks(database_url, tx, pending_sources, reload_rx, verbose);
+        let reader_handle = Self::start_reader_tasks(
+            database_url,
+            tx,
+            pending_sources,
+            reload_rx,
+            self.reader_config.clone(),
+            tunables_rx,
+            reader_heartbeat,
+            status_board,
+            verbose,
+        );
+
+        systemd::notify_ready();
 
         // Wait for both tasks
-        tokio::try_join!(writer_handle, reader_handle)?;
+        let result = tokio::try_join!(writer_handle, reader_handle);
+        systemd::notify_stopping();
+        result?;
 
         handle.close();
         signals_task.await?;
@@ -135,84 +373,105 @@ impl DataAggregator {
 
     async fn start_writer_task(
         database_url: String,
-        mut rx: mpsc::UnboundedReceiver<PendingReading>,
+        rx: ReadingReceiver,
+        mut tunables: watch::Receiver<runtime::Tunables>,
         pending_sources: Arc<Mutex<HashSet<i32>>>,
+        heartbeat: systemd::Heartbeat,
         verbose: bool,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(1));
-        let mut batch: Vec<PendingReading> = Vec::new();
+        let mut config = tunables.borrow_and_update().clone();
+        let mut interval = tokio::time::interval(config.flush_interval);
 
         loop {
+            heartbeat.beat();
+
+            // Flush on the interval, or as soon as a full batch is queued
             tokio::select! {
-                _ = interval.tick() => {
-                    if !batch.is_empty() {
-                        if verbose {
-                            println!("Writing batch of {} readings to database", batch.len());
-                        }
+                _ = interval.tick() => {}
+                // Beat while idle even if the flush interval is long
+                _ = tokio::time::sleep(tokio::time::Duration::from_secs(1)) => continue,
+                _ = rx.wait_for_batch(config.batch_size) => {}
+                Ok(()) = tunables.changed() => {
+                    let updated = tunables.borrow_and_update().clone();
+                    if updated.flush_interval != config.flush_interval {
+                        interval = tokio::time::interval(updated.flush_interval);
+                    }
+                    config = updated;
+                    continue;
+                }
+            }
 
-                        let current_batch = std::mem::take(&mut batch);
-
-                        // Extract readings and source IDs for cleanup
-                        let readings: Vec<NewReading> = current_batch.iter().map(|pr| pr.reading.clone()).collect();
-                        let source_ids: HashSet<i32> = current_batch.iter().map(|pr| pr.reading.source_id).collect();
-
-                        // Clone what's needed for the spawned task
-                        let database_url_clone = database_url.clone();
-                        let pending_sources_clone = pending_sources.clone();
-
-                        // Write batch to database in a spawned task
-                        tokio::spawn(async move {
-                            let write_result = task::spawn_blocking(move || -> Result<(), Box<dyn Error + Send + Sync>> {
-                                let mut connection = SqliteConnection::establish(&database_url_clone)?;
-                                insert_readings_batch(&mut connection, readings)?;
-                                Ok(())
-                            }).await;
-
-                            match write_result {
-                                Ok(Ok(_)) => {
-                                    println!("{} - Successfully wrote batch of {} readings", Local::now().to_rfc3339(), current_batch.len());
-                                    // Remove source IDs from pending set
-                                    let mut pending = pending_sources_clone.lock().await;
-                                    for source_id in source_ids {
-                                        pending.remove(&source_id);
-                                    }
-                                }
-                                Ok(Err(e)) => {
-                                    eprintln!("Error writing batch: {}", e);
-                                    // Keep the source IDs in pending set so they won't be read again immediately
-                                }
-                                Err(e) => {
-                                    eprintln!("Write task failed to execute: {}", e);
-                                    // The write didn't happen, so unlock the sources
-                                    let mut pending = pending_sources_clone.lock().await;
-                                    for source_id in source_ids {
-                                        pending.remove(&source_id);
-                                    }
-                                }
-                            }
-                        });
+            let dropped = rx.take_unreported_drops();
+            if !dropped.is_empty() {
+                let total: u64 = dropped.values().sum();
+                eprintln!(
+                    "{} - Reading queue full, dropped {} readings (by source id: {:?})",
+                    Local::now().to_rfc3339(),
+                    total,
+                    dropped
+                );
+            }
+
+            let batch = rx.drain(config.batch_size);
+            if batch.is_empty() {
+                if rx.is_finished() {
+                    break;
+                }
+                continue;
+            }
+
+            if verbose {
+                let metrics = rx.metrics();
+                println!(
+                    "Writing batch of {} readings to database (queued: {}, high water: {}, \
+                     backpressure waits: {})",
+                    batch.len(),
+                    metrics.queue_depth,
+                    metrics.high_water_mark,
+                    metrics.backpressure_waits
+                );
+            }
+
+            let count = batch.len();
+            let readings: Vec<NewReading> = batch.into_iter().map(|pr| pr.reading).collect();
+            let source_ids: HashSet<i32> = readings.iter().map(|r| r.source_id).collect();
+
+            // The write is awaited rather than spawned so that a stalled
+            // database fills the bounded queue instead of piling up batches in
+            // memory.
+            let database_url = database_url.clone();
+            let write_result =
+                task::spawn_blocking(move || -> Result<(), Box<dyn Error + Send + Sync>> {
+                    let mut connection = encryption::establish(&database_url)?;
+                    insert_readings_batch(&mut connection, readings)?;
+                    Ok(())
+                })
+                .await;
+
+            match write_result {
+                Ok(Ok(_)) => {
+                    println!(
+                        "{} - Successfully wrote batch of {} readings",
+                        Local::now().to_rfc3339(),
+                        count
+                    );
+                    // Remove source IDs from pending set
+                    let mut pending = pending_sources.lock().await;
+                    for source_id in source_ids {
+                        pending.remove(&source_id);
                     }
                 }
-                reading = rx.recv() => {
-                    match reading {
-                        Some(pending_reading) => {
-                            if verbose {
-                                println!("Received reading from source: {}", pending_reading.source_name);
-                            }
-                            batch.push(pending_reading);
-                        }
-                        None => {
-                            // Channel closed, write final batch and exit
-                            if !batch.is_empty() {
-                                let readings: Vec<NewReading> = batch.iter().map(|pr| pr.reading.clone()).collect();
-                                let _ = task::spawn_blocking(move || -> Result<(), Box<dyn Error + Send + Sync>> {
-                                    let mut connection = SqliteConnection::establish(&database_url)?;
-                                    insert_readings_batch(&mut connection, readings)?;
-                                    Ok(())
-                                }).await;
-                            }
-                            break;
-                        }
+                Ok(Err(e)) => {
+                    eprintln!("Error writing batch: {}", e);
+                    // Keep the source IDs in pending set so they won't be read
+                    // again immediately
+                }
+                Err(e) => {
+                    eprintln!("Write task failed to execute: {}", e);
+                    // The write didn't happen, so unlock the sources
+                    let mut pending = pending_sources.lock().await;
+                    for source_id in source_ids {
+                        pending.remove(&source_id);
                     }
                 }
             }
@@ -221,25 +480,24 @@ impl DataAggregator {
         Ok(())
     }
 
+    /// Load the active sources and the secrets their arguments refer to.
     async fn reload_sources(
         database_url: &str,
         verbose: bool,
-    ) -> Result<Vec<Source>, Box<dyn Error + Send + Sync>> {
+    ) -> Result<(Vec<Source>, SecretStore), Box<dyn Error + Send + Sync>> {
         let database_url = database_url.to_string();
-        let (active_sources, _db_path) = task::spawn_blocking({
-            move || -> Result<(Vec<Source>, String), Box<dyn Error + Send + Sync>> {
-                let mut connection = SqliteConnection::establish(&database_url)?;
+        let (active_sources, secret_store) = task::spawn_blocking({
+            move || -> Result<(Vec<Source>, SecretStore), Box<dyn Error + Send + Sync>> {
+                let mut connection = encryption::establish(&database_url)?;
 
                 use schema::sources::dsl::*;
                 let active_sources: Vec<Source> = sources
                     .filter(active.eq(true))
                     .select(Source::as_select())
                     .load(&mut connection)?;
+                let secret_store = SecretStore::for_sources(&mut connection, &active_sources)?;
 
-                let db_path =
-                    database_url.strip_prefix("sqlite://").unwrap_or(&database_url).to_string();
-
-                Ok((active_sources, db_path))
+                Ok((active_sources, secret_store))
             }
         })
         .await??;
@@ -248,21 +506,34 @@ impl DataAggregator {
             println!("Found {} active data sources to poll", active_sources.len());
         }
 
-        Ok(active_sources)
+        Ok((active_sources, secret_store))
     }
 
     async fn start_reader_tasks(
         database_url: String,
-        tx: mpsc::UnboundedSender<PendingReading>,
+        tx: ReadingSender,
         pending_sources: Arc<Mutex<HashSet<i32>>>,
         mut reload_rx: mpsc::Receiver<()>,
+        reader_config: ReaderConfig,
+        mut tunables: watch::Receiver<runtime::Tunables>,
+        heartbeat: systemd::Heartbeat,
+        status_board: status::StatusBoard,
         verbose: bool,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        let active_sources =
-            Arc::new(Mutex::new(Self::reload_sources(&database_url, verbose).await?));
+        let (loaded_sources, mut secret_store) =
+            Self::reload_sources(&database_url, verbose).await?;
+        let active_sources = Arc::new(Mutex::new(loaded_sources));
+        // Monotonic last dispatch time per source; the loaded `last_run` is
+        // only the starting point
+        let mut last_dispatch: HashMap<i32, std::time::Instant> = HashMap::new();
+        let mut wall_clock = clock::WallClockMonitor::new(reader_config.clock_skew_threshold);
         let db_path = database_url.strip_prefix("sqlite://").unwrap_or(&database_url).to_string();
+        let limits = Arc::new(CollectorLimits::new(&reader_config.concurrency));
+        let mut last_metrics_log = tokio::time::Instant::now();
 
         loop {
+            heartbeat.beat();
+
             tokio::select! {
                 _ = tokio::time::sleep(tokio::time::Duration::from_millis(100)) => {
                     // This branch executes periodically
@@ -270,9 +541,10 @@ impl DataAggregator {
                 Some(_) = reload_rx.recv() => {
                     println!("Reloading sources...");
                     match Self::reload_sources(&database_url, verbose).await {
-                        Ok(new_sources) => {
+                        Ok((new_sources, new_secrets)) => {
                             let mut sources_guard = active_sources.lock().await;
                             *sources_guard = new_sources;
+                            secret_store = new_secrets;
                         }
                         Err(e) => {
                             eprintln!("Error reloading sources: {}", e);
@@ -282,46 +554,64 @@ impl DataAggregator {
                 }
             }
 
-            let now = chrono::Utc::now().naive_utc();
-            let sources_guard = active_sources.lock().await;
-
-            // Spawn a task for each source that is ready to run
-            for source in &*sources_guard {
-                if let Some(source_id) = source.id {
-                    let mut pending = pending_sources.lock().await;
-
-                    // Skip if already running/pending write
-                    if pending.contains(&source_id) {
-                        if verbose {
-                            // println!("Skipping source '{}' (ID: {}) - write
-                            // pending", source.name, source_id);
-                        }
-                        continue;
-                    }
+            if tunables.has_changed().unwrap_or(false) {
+                limits.reconfigure(&tunables.borrow_and_update().concurrency);
+            }
 
-                    // Check if enough time has passed since last run
-                    let should_run = match source.last_run {
-                        Some(last_run) => {
-                            let seconds_since_last_run = (now - last_run).num_seconds();
-                            seconds_since_last_run >= source.interval_seconds as i64
-                        }
-                        None => true, // Never run before, so run now
-                    };
+            let metrics = limits.metrics();
+            if (verbose || metrics.queued > 0)
+                && last_metrics_log.elapsed() >= tokio::time::Duration::from_secs(10)
+            {
+                println!(
+                    "Collectors: {} running, {} queued (by test type: {:?})",
+                    metrics.running, metrics.queued, metrics.queued_by_test_type
+                );
+                last_metrics_log = tokio::time::Instant::now();
+            }
 
-                    if !should_run {
-                        continue;
-                    }
+            if let Some(jump) = wall_clock.check() {
+                eprintln!(
+                    "{} - Warning: system clock jumped {:+.1}s; scheduling is unaffected but \
+                     recorded timestamps may be discontinuous",
+                    Local::now().to_rfc3339(),
+                    jump
+                );
+            }
 
+            // Schedule on the monotonic clock; the wall clock is only recorded
+            let monotonic_now = std::time::Instant::now();
+            let now = chrono::Utc::now().naive_utc();
+            let sources_guard = active_sources.lock().await;
+            status_board.tick(sources_guard.len(), &metrics, &tx.metrics());
+
+            // Pick the due sources, in priority order, to start this pass. No
+            // more are started than there are free collector slots, so when
+            // saturated the next slot goes to whatever ranks highest then.
+            let mut due = {
+                let pending = pending_sources.lock().await;
+                scheduler::select_due_sources(
+                    &sources_guard,
+                    &last_dispatch,
+                    &pending,
+                    monotonic_now,
+                    now,
+                    &reader_config,
+                )
+            };
+            due.truncate(limits.available());
+
+            for source in due {
+                if let Some(source_id) = source.id {
                     // Mark source as having a pending write *before* spawning the task
-                    pending.insert(source_id);
-                    drop(pending);
+                    pending_sources.lock().await.insert(source_id);
+                    last_dispatch.insert(source_id, monotonic_now);
 
                     // Update last_run timestamp immediately (when test starts, not completes)
                     let database_url_clone = database_url.clone();
                     let update_result = task::spawn_blocking({
                         let database_url = database_url_clone.clone();
                         move || -> Result<(), String> {
-                            let mut connection = SqliteConnection::establish(&database_url)
+                            let mut connection = encryption::establish(&database_url)
                                 .map_err(|e| format!("Failed to connect: {}", e))?;
                             update_last_run(&mut connection, source_id, now)
                                 .map_err(|e| format!("Failed to update last_run: {}", e))?;
@@ -343,8 +633,27 @@ impl DataAggregator {
                     let _db_path_clone = db_path.clone();
                     let source_name = source.name.clone();
                     let interval_seconds = source.interval_seconds;
+                    let collector = match DataCollector::from_source(source, &secret_store) {
+                        Ok(collector) => collector,
+                        Err(e) => {
+                            eprintln!(
+                                "  → Failed to configure collector for {}: {}",
+                                source_name, e
+                            );
+                            let mut pending = pending_sources.lock().await;
+                            pending.remove(&source_id);
+                            continue;
+                        }
+                    };
+                    let critical = collector.test_type.is_critical();
+                    let limits = limits.clone();
+                    let skew_threshold = reader_config.clock_skew_threshold;
 
                     task::spawn(async move {
+                        // Held until the reading is queued; waiting here is
+                        // what shows up as queued in the collector metrics
+                        let _permit = limits.acquire(&collector.test_type).await;
+
                         if verbose {
                             println!(
                                 "Polling data source: {} (ID: {}) [interval: {}s]",
@@ -352,8 +661,6 @@ impl DataAggregator {
                             );
                         }
 
-                        let collector = DataCollector::new(source_name.clone(), source_id);
-
                         match collector.collect().await {
                             Ok(data) => {
                                 if verbose {
@@ -365,21 +672,64 @@ impl DataAggregator {
                                     );
                                 }
 
+                                if let Some(skew) =
+                                    clock::detect_skew(&data, chrono::Utc::now(), skew_threshold)
+                                {
+                                    eprintln!(
+                                        "  → Clock skew of {:+.1}s detected for {}",
+                                        skew.skew_seconds(),
+                                        source_name
+                                    );
+                                    let warning = NewReading::with_quality(
+                                        source_id,
+                                        &skew.to_warning_json(source_id),
+                                        clock::QUALITY_CLOCK_SKEW,
+                                    );
+                                    if let Ok(reading) = warning {
+                                        let warning = PendingReading {
+                                            reading,
+                                            source_name: source_name.clone(),
+                                            critical: false,
+                                        };
+                                        if let Ok(outcome) = tx_clone.send(warning).await {
+                                            let mut pending = pending_sources_clone.lock().await;
+                                            for dropped in &outcome.dropped {
+                                                pending.remove(&dropped.reading.source_id);
+                                            }
+                                        }
+                                    }
+                                }
+
                                 match NewReading::with_json_data(source_id, &data) {
                                     Ok(new_reading) => {
                                         let pending_reading = PendingReading {
                                             reading: new_reading,
                                             source_name: source_name.clone(),
+                                            critical,
                                         };
 
-                                        if let Err(e) = tx_clone.send(pending_reading) {
-                                            eprintln!(
-                                                "Failed to send reading for {}: {}",
-                                                source_name, e
-                                            );
-                                            // Remove from pending set if send failed
-                                            let mut pending = pending_sources_clone.lock().await;
-                                            pending.remove(&source_id);
+                                        match tx_clone.send(pending_reading).await {
+                                            Ok(outcome) => {
+                                                // Dropped readings will never be written, so
+                                                // their sources can be polled again
+                                                if !outcome.dropped.is_empty() {
+                                                    let mut pending =
+                                                        pending_sources_clone.lock().await;
+                                                    for dropped in &outcome.dropped {
+                                                        pending.remove(&dropped.reading.source_id);
+                                                    }
+                                                }
+                                            }
+                                            Err(e) => {
+                                                eprintln!(
+                                                    "Failed to send reading for {}: {}",
+                                                    source_name, e
+                                                );
+                                                // Remove from pending set if send failed
+                                                let mut pending =
+                                                    pending_sources_clone.lock().await;
+                                                pending.remove(&source_id);
+                                            }
                                         }
                                     }
                                     Err(e) => {
@@ -416,7 +766,14 @@ pub fn insert_reading(
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
     use schema::readings;
 
-    diesel::insert_into(readings::table).values(&reading).execute(connection)?;
+    connection.transaction(|conn| {
+        diesel::insert_into(readings::table).values(&reading).execute(conn)?;
+        let fields = points::fields_by_source(conn, &[reading.source_id])?;
+        if let Some(fields) = fields.get(&reading.source_id) {
+            points::record_points(conn, reading.source_id, reading.device_timestamp, fields)?;
+        }
+        Ok::<_, diesel::result::Error>(())
+    })?;
 
     Ok(())
 }
@@ -426,10 +783,66 @@ pub fn insert_readings_batch(
     connection: &mut SqliteConnection,
     readings: Vec<NewReading>,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
-    use schema::readings;
+    // Readings carrying a device timestamp are upserted on (source_id,
+    // device_timestamp), so a retried push replaces the earlier row rather
+    // than duplicating it. Readings without one never conflict. SQLite can't
+    // combine multi-row VALUES with ON CONFLICT in diesel, so the rows are
+    // inserted one at a time inside a single transaction. Declared numeric
+    // fields are copied into reading_points in the same transaction.
+    connection.transaction(|conn| {
+        let source_ids: Vec<i32> = readings.iter().map(|r| r.source_id).collect();
+        let point_fields = points::fields_by_source(conn, &source_ids)?;
+        for reading in &readings {
+            upsert_reading(conn, reading, &point_fields)?;
+        }
+        Ok::<_, diesel::result::Error>(())
+    })?;
+
+    Ok(())
+}
 
-    diesel::insert_into(readings::table).values(&readings).execute(connection)?;
+/// Insert multiple readings as [`insert_readings_batch`] does, but each in
+/// its own savepoint, so one that fails is left out rather than failing the
+/// batch. Returns each reading's outcome, in order.
+pub fn insert_readings_each(
+    connection: &mut SqliteConnection,
+    readings: &[NewReading],
+) -> Result<Vec<QueryResult<()>>, Box<dyn Error + Send + Sync>> {
+    let outcomes = connection.transaction(|conn| {
+        let source_ids: Vec<i32> = readings.iter().map(|r| r.source_id).collect();
+        let point_fields = points::fields_by_source(conn, &source_ids)?;
+        let outcomes = readings
+            .iter()
+            .map(|reading| conn.transaction(|conn| upsert_reading(conn, reading, &point_fields)))
+            .collect();
+        Ok::<_, diesel::result::Error>(outcomes)
+    })?;
+
+    Ok(outcomes)
+}
 
+/// Upsert one reading on (source_id, device_timestamp) and record its points.
+fn upsert_reading(
+    conn: &mut SqliteConnection,
+    reading: &NewReading,
+    point_fields: &HashMap<i32, Vec<String>>,
+) -> QueryResult<()> {
+    use diesel::upsert::excluded;
+    use schema::readings;
+
+    diesel::insert_into(readings::table)
+        .values(reading)
+        .on_conflict((readings::source_id, readings::device_timestamp))
+        .do_update()
+        .set((
+            readings::timestamp.eq(excluded(readings::timestamp)),
+            readings::data.eq(excluded(readings::data)),
+            readings::quality_flags.eq(excluded(readings::quality_flags)),
+        ))
+        .execute(conn)?;
+    if let Some(fields) = point_fields.get(&reading.source_id) {
+        points::record_points(conn, reading.source_id, reading.device_timestamp, fields)?;
+    }
     Ok(())
 }
 
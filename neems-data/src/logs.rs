@@ -0,0 +1,749 @@
+//! Recent logs kept in memory and served to support staff.
+//!
+//! Pulling logs off a site used to mean a VPN and an SSH session. Instead,
+//! every `tracing` event the monitor logs (at the configured level) is also
+//! kept in a bounded in-memory buffer ([`LogBuffer`]), and when
+//! `NEEMS_LOGS_LISTEN` is set `monitor` serves it as JSON:
+//!
+//! ```text
+//! GET /logs?since=2024-01-01T12:00:00Z&level=warn&limit=500
+//! Authorization: Bearer <NEEMS_LOGS_TOKEN>
+//! ```
+//!
+//! - `since`: only entries after this time (RFC 3339, or a naive timestamp in
+//!   UTC)
+//! - `level`: only entries at least this severe (`error`, `warn`, `info`,
+//!   `debug` or `trace`)
+//! - `limit`: the newest this many matching entries (default 500, at most 5000)
+//!
+//! The listener speaks plain HTTP/1.1 with one request per connection and
+//! refuses every request without the bearer token; put it behind a TLS
+//! terminating proxy when it is reachable from outside the site.
+//!
+//! The central neems-api reaches a site's listener through the URL and token
+//! stored for the site in `edge_endpoints` ([`set_edge_endpoint`]) and proxies
+//! `GET /api/1/Sites/<id>/EdgeLogs` to it ([`fetch_edge_logs`]). Tokens may be
+//! `secret://` references (see [`crate::secrets`]).
+//!
+//! Messages printed directly to stdout or stderr rather than logged through
+//! `tracing` aren't kept.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    env,
+    fmt::Debug,
+    net::SocketAddr,
+    str::FromStr,
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{
+    Event, Level, Subscriber,
+    field::{Field, Visit},
+};
+use tracing_subscriber::{Layer, layer::Context};
+use ts_rs::TS;
+
+use crate::{
+    DataResult, encryption, schema,
+    secrets::{self, MasterKey},
+};
+
+/// Entries kept by default.
+pub const DEFAULT_BUFFER_SIZE: usize = 5000;
+
+/// Entries returned when a request doesn't set `limit`.
+pub const DEFAULT_LIMIT: usize = 500;
+
+/// Most entries one request may return.
+pub const MAX_LIMIT: usize = 5000;
+
+/// Longest a client may take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest request head accepted.
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+
+/// One logged event.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LogEntry {
+    #[ts(type = "string")]
+    pub timestamp: DateTime<Utc>,
+    /// `error`, `warn`, `info`, `debug` or `trace`
+    pub level: String,
+    /// Module that logged the event
+    pub target: String,
+    pub message: String,
+    /// The event's structured fields other than the message
+    #[ts(type = "Record<string, unknown>")]
+    pub fields: BTreeMap<String, JsonValue>,
+}
+
+/// Entries returned for a query, oldest first.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LogPage {
+    pub entries: Vec<LogEntry>,
+    /// Whether older matching entries were left out by the limit
+    pub truncated: bool,
+}
+
+/// Which entries to return.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogQuery {
+    pub since: Option<DateTime<Utc>>,
+    /// Least severe level returned
+    pub level: Option<Level>,
+    pub limit: usize,
+}
+
+impl Default for LogQuery {
+    fn default() -> Self {
+        Self {
+            since: None,
+            level: None,
+            limit: DEFAULT_LIMIT,
+        }
+    }
+}
+
+impl LogQuery {
+    /// Parse the `since`, `level` and `limit` parameters.
+    pub fn from_params(
+        since: Option<&str>,
+        level: Option<&str>,
+        limit: Option<&str>,
+    ) -> Result<Self, String> {
+        let since = since.filter(|s| !s.is_empty()).map(parse_since).transpose()?;
+        let level = level
+            .filter(|l| !l.is_empty())
+            .map(|l| Level::from_str(l).map_err(|_| format!("level: unknown level '{}'", l)))
+            .transpose()?;
+        let limit = match limit.filter(|l| !l.is_empty()) {
+            Some(limit) => match limit.parse::<usize>() {
+                Ok(limit) if (1..=MAX_LIMIT).contains(&limit) => limit,
+                _ => return Err(format!("limit: must be between 1 and {}", MAX_LIMIT)),
+            },
+            None => DEFAULT_LIMIT,
+        };
+        Ok(Self { since, level, limit })
+    }
+
+    /// The query as URL parameters, e.g. `level=warn&limit=500`.
+    pub fn to_query_string(&self) -> String {
+        let mut params = Vec::new();
+        if let Some(since) = self.since {
+            params.push(format!("since={}", since.format("%Y-%m-%dT%H:%M:%S%.fZ")));
+        }
+        if let Some(level) = self.level {
+            params.push(format!("level={}", level.as_str().to_ascii_lowercase()));
+        }
+        params.push(format!("limit={}", self.limit));
+        params.join("&")
+    }
+}
+
+fn parse_since(since: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(since)
+        .map(|t| t.with_timezone(&Utc))
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(since, "%Y-%m-%dT%H:%M:%S%.f").map(|t| t.and_utc())
+        })
+        .map_err(|_| format!("since: '{}' is not an RFC 3339 timestamp", since))
+}
+
+/// The most recent log entries, oldest dropped first.
+pub struct LogBuffer {
+    entries: Mutex<VecDeque<(Level, LogEntry)>>,
+    capacity: AtomicUsize,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::new()),
+            capacity: AtomicUsize::new(capacity),
+        }
+    }
+
+    /// The buffer [`BufferLayer`] records into.
+    pub fn global() -> &'static Self {
+        static BUFFER: OnceLock<LogBuffer> = OnceLock::new();
+        BUFFER.get_or_init(|| LogBuffer::new(DEFAULT_BUFFER_SIZE))
+    }
+
+    /// Keep at most `capacity` entries from now on.
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        while entries.len() > capacity {
+            entries.pop_front();
+        }
+    }
+
+    pub fn push(&self, entry: LogEntry) {
+        let level = Level::from_str(&entry.level).unwrap_or(Level::TRACE);
+        let capacity = self.capacity.load(Ordering::Relaxed);
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.push_back((level, entry));
+        while entries.len() > capacity {
+            entries.pop_front();
+        }
+    }
+
+    /// The newest entries matching `query`, oldest first.
+    pub fn query(&self, query: &LogQuery) -> LogPage {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        // More verbose levels compare greater
+        let mut matching: Vec<LogEntry> = entries
+            .iter()
+            .rev()
+            .filter(|(level, entry)| {
+                query.level.is_none_or(|min| *level <= min)
+                    && query.since.is_none_or(|since| entry.timestamp > since)
+            })
+            .take(query.limit + 1)
+            .map(|(_, entry)| entry.clone())
+            .collect();
+        let truncated = matching.len() > query.limit;
+        matching.truncate(query.limit);
+        matching.reverse();
+        LogPage { entries: matching, truncated }
+    }
+}
+
+/// A `tracing` layer that records every event it sees into
+/// [`LogBuffer::global`].
+pub struct BufferLayer;
+
+impl<S: Subscriber> Layer<S> for BufferLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        LogBuffer::global().push(LogEntry {
+            timestamp: Utc::now(),
+            level: metadata.level().as_str().to_ascii_lowercase(),
+            target: metadata.target().to_string(),
+            message: visitor.message,
+            fields: visitor.fields,
+        });
+    }
+}
+
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: BTreeMap<String, JsonValue>,
+}
+
+impl FieldVisitor {
+    fn insert(&mut self, field: &Field, value: JsonValue) {
+        if field.name() == "message" {
+            self.message = match value {
+                JsonValue::String(s) => s,
+                other => other.to_string(),
+            };
+        } else {
+            self.fields.insert(field.name().to_string(), value);
+        }
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.insert(field, JsonValue::String(format!("{:?}", value)));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, JsonValue::String(value.to_string()));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+}
+
+/// Settings for serving recent logs.
+///
+/// Read from the environment by [`LogsConfig::from_env`]:
+/// - `NEEMS_LOGS_LISTEN`: address to listen on, e.g. `0.0.0.0:9180` (the
+///   listener is off when unset)
+/// - `NEEMS_LOGS_TOKEN` / `NEEMS_LOGS_TOKEN_FILE`: bearer token every request
+///   must carry, or a file holding it (required)
+/// - `NEEMS_LOGS_BUFFER_SIZE`: entries kept in memory
+#[derive(Debug, Clone)]
+pub struct LogsConfig {
+    pub listen: SocketAddr,
+    pub token: String,
+    pub buffer_size: usize,
+}
+
+impl LogsConfig {
+    /// Build a config from the environment, or `None` if `NEEMS_LOGS_LISTEN`
+    /// is not set or no token is configured.
+    pub fn from_env() -> Option<Self> {
+        let listen = env::var("NEEMS_LOGS_LISTEN").ok()?.trim().parse().ok()?;
+        let token = encryption::read_key(
+            env::var("NEEMS_LOGS_TOKEN").ok(),
+            env::var("NEEMS_LOGS_TOKEN_FILE").ok().as_deref(),
+            "NEEMS_LOGS_TOKEN_FILE",
+        )
+        .ok()??;
+        let buffer_size = env::var("NEEMS_LOGS_BUFFER_SIZE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&v| v > 0)
+            .unwrap_or(DEFAULT_BUFFER_SIZE);
+        Some(Self { listen, token, buffer_size })
+    }
+}
+
+/// Serve recent logs on `config.listen` until the process exits.
+pub async fn run_logs_server(config: LogsConfig) {
+    LogBuffer::global().set_capacity(config.buffer_size);
+    let listener = match TcpListener::bind(config.listen).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Log server disabled: cannot listen on {}: {}", config.listen, e);
+            return;
+        }
+    };
+    serve_logs(listener, config.token).await
+}
+
+/// Answer log requests on `listener` until the process exits.
+pub async fn serve_logs(listener: TcpListener, token: String) {
+    let token: Arc<str> = token.into();
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let token = token.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve(stream, &token).await {
+                        tracing::debug!(error = %e, "Log request failed");
+                    }
+                });
+            }
+            Err(e) => {
+                eprintln!("Log server: accept failed: {}", e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+async fn serve(mut stream: TcpStream, token: &str) -> std::io::Result<()> {
+    let head = match tokio::time::timeout(REQUEST_TIMEOUT, read_head(&mut stream)).await {
+        Ok(head) => head?,
+        Err(_) => return Ok(()),
+    };
+    let (status, body) = match head {
+        Some(head) => handle_request(&head, token, LogBuffer::global()),
+        None => (400, error_body("Malformed request")),
+    };
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        _ => "Method Not Allowed",
+    };
+    let challenge = if status == 401 {
+        "WWW-Authenticate: Bearer\r\n"
+    } else {
+        ""
+    };
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        challenge,
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Read up to the blank line ending the request head, or `None` if it is
+/// too long or not UTF-8.
+async fn read_head(stream: &mut TcpStream) -> std::io::Result<Option<String>> {
+    let mut head = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 || head.len() + read > MAX_REQUEST_BYTES {
+            return Ok(None);
+        }
+        head.extend_from_slice(&chunk[..read]);
+    }
+    Ok(String::from_utf8(head).ok())
+}
+
+fn error_body(error: &str) -> String {
+    serde_json::json!({ "error": error }).to_string()
+}
+
+/// Answer one request head with a status and JSON body.
+fn handle_request(head: &str, token: &str, buffer: &LogBuffer) -> (u16, String) {
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (method, target) = (request_line.next().unwrap_or_default(), request_line.next());
+    let authorized = lines
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(name, _)| name.trim().eq_ignore_ascii_case("authorization"))
+        .filter_map(|(_, value)| value.trim().strip_prefix("Bearer "))
+        .any(|presented| constant_time_eq(presented.trim().as_bytes(), token.as_bytes()));
+
+    if !authorized {
+        return (401, error_body("A valid bearer token is required"));
+    }
+    let Some(target) = target else {
+        return (400, error_body("Malformed request"));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    if path != "/logs" {
+        return (404, error_body("Not found"));
+    }
+    if method != "GET" {
+        return (405, error_body("Only GET is supported"));
+    }
+
+    let params: BTreeMap<String, String> = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(name, value)| (percent_decode(name), percent_decode(value)))
+        .collect();
+    let param = |name: &str| params.get(name).map(String::as_str);
+    match LogQuery::from_params(param("since"), param("level"), param("limit")) {
+        Ok(query) => match serde_json::to_string(&buffer.query(&query)) {
+            Ok(body) => (200, body),
+            Err(e) => (400, error_body(&e.to_string())),
+        },
+        Err(e) => (400, error_body(&e)),
+    }
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                match value.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Where the central server reaches a site's log listener.
+#[derive(Debug, Clone, PartialEq, Queryable, Selectable)]
+#[diesel(table_name = schema::edge_endpoints)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct EdgeEndpoint {
+    pub site_id: i32,
+    pub url: String,
+    pub token: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// A site's edge endpoint, as set through the API.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct EdgeEndpointSettings {
+    /// Base URL of the site's log listener, e.g. `https://site-12.example.com:9180`
+    pub url: String,
+    /// The listener's `NEEMS_LOGS_TOKEN`, or a `secret://<name>` reference.
+    /// When replacing an endpoint, `null` keeps the stored token.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+impl EdgeEndpointSettings {
+    /// Check the settings, listing every problem.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        let url = self.url.trim();
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            problems.push(format!("url: '{}' is not an http:// or https:// URL", self.url));
+        }
+        if self.token.as_deref().is_some_and(|token| token.trim().is_empty()) {
+            problems.push("token: must not be empty".to_string());
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+/// A site's edge endpoint as shown by the API, with the token redacted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct EdgeEndpointInfo {
+    pub site_id: i32,
+    pub url: String,
+    /// `***` for a stored token, or the `secret://` reference
+    pub token: String,
+    #[ts(type = "string")]
+    pub updated_at: NaiveDateTime,
+}
+
+impl From<&EdgeEndpoint> for EdgeEndpointInfo {
+    fn from(endpoint: &EdgeEndpoint) -> Self {
+        let token = match secrets::reference_name(&endpoint.token) {
+            Some(_) => endpoint.token.clone(),
+            None => secrets::REDACTED.to_string(),
+        };
+        Self {
+            site_id: endpoint.site_id,
+            url: endpoint.url.clone(),
+            token,
+            updated_at: endpoint.updated_at,
+        }
+    }
+}
+
+/// The edge endpoint stored for `site_id`, if any.
+pub fn get_edge_endpoint(
+    connection: &mut SqliteConnection,
+    site_id: i32,
+) -> DataResult<Option<EdgeEndpoint>> {
+    use schema::edge_endpoints::dsl;
+
+    Ok(dsl::edge_endpoints
+        .find(site_id)
+        .select(EdgeEndpoint::as_select())
+        .first(connection)
+        .optional()?)
+}
+
+/// Store the edge endpoint of `site_id`, replacing any. A `None` token keeps
+/// the stored one; it's an error if there is none.
+pub fn set_edge_endpoint(
+    connection: &mut SqliteConnection,
+    site_id: i32,
+    settings: &EdgeEndpointSettings,
+) -> DataResult<EdgeEndpoint> {
+    use schema::edge_endpoints::dsl;
+
+    let url = settings.url.trim().trim_end_matches('/');
+    let token = match settings.token.as_deref() {
+        Some(token) => token.trim().to_string(),
+        None => get_edge_endpoint(connection, site_id)?
+            .map(|endpoint| endpoint.token)
+            .ok_or("token: required for a new endpoint")?,
+    };
+
+    let now = Utc::now().naive_utc();
+    diesel::insert_into(dsl::edge_endpoints)
+        .values((
+            dsl::site_id.eq(site_id),
+            dsl::url.eq(url),
+            dsl::token.eq(&token),
+            dsl::created_at.eq(now),
+            dsl::updated_at.eq(now),
+        ))
+        .on_conflict(dsl::site_id)
+        .do_update()
+        .set((dsl::url.eq(url), dsl::token.eq(&token), dsl::updated_at.eq(now)))
+        .execute(connection)?;
+    get_edge_endpoint(connection, site_id)?.ok_or_else(|| "edge endpoint not stored".into())
+}
+
+/// Delete the edge endpoint of `site_id`. Returns whether there was one.
+pub fn delete_edge_endpoint(connection: &mut SqliteConnection, site_id: i32) -> DataResult<bool> {
+    use schema::edge_endpoints::dsl;
+
+    Ok(diesel::delete(dsl::edge_endpoints.find(site_id)).execute(connection)? > 0)
+}
+
+/// The endpoint's token, with a `secret://` reference resolved.
+pub fn resolve_edge_token(
+    connection: &mut SqliteConnection,
+    endpoint: &EdgeEndpoint,
+) -> DataResult<String> {
+    let Some(name) = secrets::reference_name(&endpoint.token) else {
+        return Ok(endpoint.token.clone());
+    };
+    let key = MasterKey::from_env()?.ok_or_else(|| {
+        format!("token refers to secret '{}' but {} is not set", name, secrets::KEY_ENV)
+    })?;
+    secrets::get_secret(connection, key, name)?
+        .ok_or_else(|| format!("token refers to secret '{}', which does not exist", name).into())
+}
+
+/// Fetch recent logs from a site's listener at `url`.
+pub async fn fetch_edge_logs(url: &str, token: &str, query: &LogQuery) -> DataResult<LogPage> {
+    let response = reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()?
+        .get(format!("{}/logs?{}", url, query.to_query_string()))
+        .bearer_auth(token)
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("edge log listener answered HTTP {}", status).into());
+    }
+    Ok(response.json::<LogPage>().await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(seconds: i64, level: Level, message: &str) -> LogEntry {
+        LogEntry {
+            timestamp: DateTime::from_timestamp(1_700_000_000 + seconds, 0).unwrap(),
+            level: level.as_str().to_ascii_lowercase(),
+            target: "neems_data::test".to_string(),
+            message: message.to_string(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    fn buffer() -> LogBuffer {
+        let buffer = LogBuffer::new(3);
+        for (i, level) in
+            [Level::INFO, Level::ERROR, Level::DEBUG, Level::WARN].into_iter().enumerate()
+        {
+            buffer.push(entry(i as i64, level, &format!("event {}", i)));
+        }
+        buffer
+    }
+
+    fn messages(page: &LogPage) -> Vec<&str> {
+        page.entries.iter().map(|e| e.message.as_str()).collect()
+    }
+
+    #[test]
+    fn test_buffer_drops_oldest_and_filters() {
+        let buffer = buffer();
+        let page = buffer.query(&LogQuery::default());
+        assert_eq!(messages(&page), vec!["event 1", "event 2", "event 3"]);
+        assert!(!page.truncated);
+
+        let warnings = LogQuery {
+            level: Some(Level::WARN),
+            ..Default::default()
+        };
+        assert_eq!(messages(&buffer.query(&warnings)), vec!["event 1", "event 3"]);
+
+        let since = LogQuery {
+            since: DateTime::from_timestamp(1_700_000_001, 0),
+            limit: 1,
+            ..Default::default()
+        };
+        let page = buffer.query(&since);
+        assert_eq!(messages(&page), vec!["event 3"]);
+        assert!(page.truncated);
+    }
+
+    #[test]
+    fn test_query_params() {
+        let query =
+            LogQuery::from_params(Some("2024-01-01T12:00:00Z"), Some("WARN"), Some("10")).unwrap();
+        assert_eq!(query.level, Some(Level::WARN));
+        assert_eq!(query.limit, 10);
+        assert_eq!(query.to_query_string(), "since=2024-01-01T12:00:00Z&level=warn&limit=10");
+        assert_eq!(
+            LogQuery::from_params(query.since.map(|s| s.to_rfc3339()).as_deref(), None, None)
+                .unwrap()
+                .since,
+            query.since
+        );
+
+        assert!(LogQuery::from_params(Some("yesterday"), None, None).is_err());
+        assert!(LogQuery::from_params(None, Some("loud"), None).is_err());
+        assert!(LogQuery::from_params(None, None, Some("0")).is_err());
+    }
+
+    #[test]
+    fn test_handle_request_requires_token() {
+        let buffer = buffer();
+        let request = |auth: &str, target: &str| {
+            format!("GET {} HTTP/1.1\r\nHost: edge\r\n{}\r\n\r\n", target, auth)
+        };
+
+        let (status, _) = handle_request(&request("", "/logs"), "s3cret", &buffer);
+        assert_eq!(status, 401);
+        let (status, _) =
+            handle_request(&request("Authorization: Bearer wrong", "/logs"), "s3cret", &buffer);
+        assert_eq!(status, 401);
+
+        let auth = "authorization: Bearer s3cret";
+        let (status, body) = handle_request(
+            &request(auth, "/logs?level=error&since=2023-11-14T22%3A13%3A19Z"),
+            "s3cret",
+            &buffer,
+        );
+        assert_eq!(status, 200);
+        let page: LogPage = serde_json::from_str(&body).unwrap();
+        assert_eq!(messages(&page), vec!["event 1"]);
+
+        assert_eq!(handle_request(&request(auth, "/other"), "s3cret", &buffer).0, 404);
+        assert_eq!(handle_request(&request(auth, "/logs?limit=x"), "s3cret", &buffer).0, 400);
+    }
+
+    #[test]
+    fn test_layer_records_events() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let subscriber = tracing_subscriber::registry().with(BufferLayer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!(host = "10.0.0.5", attempts = 3, "RTAC unreachable");
+        });
+        let page = LogBuffer::global().query(&LogQuery {
+            level: Some(Level::WARN),
+            ..Default::default()
+        });
+        let entry = page.entries.iter().find(|e| e.message == "RTAC unreachable").unwrap();
+        assert_eq!(entry.level, "warn");
+        assert_eq!(entry.fields["host"], "10.0.0.5");
+        assert_eq!(entry.fields["attempts"], 3);
+    }
+}
@@ -3,8 +3,8 @@ use std::{env, error::Error};
 use clap::{Args, Parser, Subcommand};
 use dotenvy::dotenv;
 use neems_data::{
-    DataAggregator, NewSource, UpdateSource, create_source, delete_source, get_source_by_name,
-    list_sources, update_source,
+    DataAggregator, NewSource, UpdateSource, config::Settings, create_source, delete_source,
+    get_source_by_name, list_sources, secrets::redact_arguments, update_source,
 };
 
 pub mod built_info {
@@ -19,6 +19,10 @@ struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
 
+    /// TOML config file; environment variables override its settings
+    #[arg(long, global = true, env = neems_data::config::CONFIG_ENV)]
+    config: Option<std::path::PathBuf>,
+
     /// Show extended version information
     #[arg(long, action = clap::ArgAction::SetTrue)]
     version_info: bool,
@@ -26,7 +30,10 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Start the data monitoring and aggregation service
+    /// Start the data monitoring and aggregation service.
+    ///
+    /// SIGHUP reloads the sources, and the log level, batching and
+    /// concurrency limits from the config file, without restarting.
     Monitor {
         #[arg(
             short,
@@ -53,6 +60,16 @@ enum Commands {
         /// Name of the source to show
         name: String,
     },
+    /// Run a source's collector once and print the result without storing
+    /// a reading. Works on inactive sources, so configuration can be
+    /// checked before activating.
+    Test {
+        /// Name of the source to test
+        name: String,
+        /// Seconds to wait for the collector (default: 15)
+        #[arg(long)]
+        timeout: Option<u64>,
+    },
     /// Seed plausible past SoC history for a site (demo data).
     ///
     /// Creates a `charging_state` source for the site if one doesn't
@@ -70,6 +87,103 @@ enum Commands {
     /// transitions. Existing timestamps are skipped, so re-running is
     /// safe.
     SeedAlarmHistory(SeedAlarmHistoryArgs),
+    /// Upload unsynced readings to the central server (sync.url or
+    /// NEEMS_SYNC_URL).
+    ///
+    /// `monitor` does this continuously when a sync URL is set; this
+    /// runs a single pass, e.g. to drain a backlog by hand.
+    Sync {
+        /// Show each sync target's cursor and last error instead of syncing
+        #[arg(long)]
+        status: bool,
+        /// After syncing, compare per-day row counts and checksums with the
+        /// server and list any mismatches
+        #[arg(long, conflicts_with = "status")]
+        reconcile: bool,
+    },
+    /// Vacuum, ANALYZE and checkpoint the database now.
+    ///
+    /// `monitor` does this daily in NEEMS_DATA_MAINTENANCE_WINDOW.
+    Maintenance {
+        /// Rewrite the whole file with VACUUM and switch it to incremental
+        /// auto-vacuum; needs free space for a copy of the database
+        #[arg(long)]
+        full: bool,
+    },
+    /// Manage encrypted credentials for source arguments.
+    ///
+    /// Refer to a secret from an argument as `secret://<name>`, e.g.
+    /// `neems-data add rtac -t exec -a password=secret://rtac-main`.
+    /// Needs NEEMS_SECRETS_KEY or NEEMS_SECRETS_KEY_FILE.
+    Secret {
+        #[command(subcommand)]
+        action: SecretAction,
+    },
+    /// Load-test the readings pipeline against a scratch database.
+    ///
+    /// Synthetic sources push readings through the same queue and batched
+    /// writes as `monitor`; reports sustained write throughput, batch write
+    /// latencies and queue depth. Queue and batch settings default to the
+    /// configured writer settings, like `monitor`.
+    Bench(BenchArgs),
+}
+
+#[derive(Subcommand)]
+enum SecretAction {
+    /// Store a secret, replacing any existing value
+    Set {
+        /// Name to refer to it by
+        name: String,
+        /// The value; read from stdin when omitted, to keep it out of shell
+        /// history
+        #[arg(long)]
+        value: Option<String>,
+    },
+    /// List stored secrets (names only)
+    #[command(alias = "ls")]
+    List,
+    /// Delete a secret
+    #[command(alias = "rm")]
+    Remove {
+        /// Name of the secret
+        name: String,
+    },
+    /// Print a new random master key for NEEMS_SECRETS_KEY
+    GenerateKey,
+}
+
+#[derive(Args)]
+struct BenchArgs {
+    /// Number of synthetic sources
+    #[arg(long, default_value = "50")]
+    sources: usize,
+    /// Readings per second from each source
+    #[arg(long, default_value = "1")]
+    rate: f64,
+    /// Seconds to produce readings for
+    #[arg(long, default_value = "30")]
+    duration: u64,
+    /// Numeric fields per reading
+    #[arg(long, default_value = "8")]
+    fields: usize,
+    /// Mark readings critical, so a full queue holds sources up instead of
+    /// dropping readings
+    #[arg(long)]
+    critical: bool,
+    /// Database file to write (must not exist); a temporary file that is
+    /// deleted afterwards by default
+    #[arg(long)]
+    database: Option<std::path::PathBuf>,
+    /// Reading queue capacity (NEEMS_DATA_QUEUE_CAPACITY)
+    #[arg(long)]
+    queue_capacity: Option<usize>,
+    /// Readings per write transaction (NEEMS_DATA_BATCH_SIZE)
+    #[arg(long)]
+    batch_size: Option<usize>,
+    /// Longest a reading waits to be written, in ms
+    /// (NEEMS_DATA_FLUSH_INTERVAL_MS)
+    #[arg(long)]
+    flush_interval_ms: Option<u64>,
 }
 
 #[derive(Args)]
@@ -102,7 +216,8 @@ struct SeedAlarmHistoryArgs {
 struct AddArgs {
     /// Name of the source
     name: String,
-    /// Test type (ping, charging_state, disk_space)
+    /// Test type (ping, charging_state, disk_space, cpu_load, memory, thermal,
+    /// weather, exec, simulator, replay)
     #[arg(short = 't', long)]
     test_type: String,
     /// Test arguments in key=value format (can be used multiple times)
@@ -123,6 +238,14 @@ struct AddArgs {
     /// Company ID that this source belongs to
     #[arg(long)]
     company_id: Option<i32>,
+    /// Scheduling priority; higher runs first when the reader is saturated
+    /// (default: 0)
+    #[arg(long)]
+    priority: Option<i32>,
+    /// Numeric reading fields to store in reading_points for fast
+    /// aggregation, comma-separated (default: per test type)
+    #[arg(long)]
+    point_fields: Option<String>,
 }
 
 /// Parse a single key=value pair
@@ -138,7 +261,8 @@ struct EditArgs {
     /// New name for the source
     #[arg(long)]
     new_name: Option<String>,
-    /// New test type (ping, charging_state, disk_space)
+    /// New test type (ping, charging_state, disk_space, cpu_load, memory,
+    /// thermal, weather, exec, simulator, replay)
     #[arg(short = 't', long)]
     test_type: Option<String>,
     /// New test arguments in key=value format (can be used multiple times)
@@ -171,37 +295,22 @@ struct EditArgs {
     /// Clear the company ID (set to null)
     #[arg(long)]
     clear_company_id: bool,
+    /// New scheduling priority
+    #[arg(long)]
+    priority: Option<i32>,
+    /// Numeric reading fields to store in reading_points, comma-separated;
+    /// existing readings are backfilled
+    #[arg(long)]
+    point_fields: Option<String>,
+    /// Go back to the test type's default point fields
+    #[arg(long, conflicts_with = "point_fields")]
+    clear_point_fields: bool,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     dotenv().ok();
 
-    // Initialize tracing so the RTAC collector / worker / control logic logs are
-    // visible. Honors RUST_LOG; defaults to info.
-    let _ = tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
-        )
-        .try_init();
-
-    let database_path =
-        env::var("SITE_DATABASE_URL").unwrap_or_else(|_| "site-data.sqlite".to_string());
-    // DataAggregator::new prepends `sqlite://`, so strip any leading
-    // scheme from the env var to avoid a doubled prefix
-    // (`sqlite://sqlite:///path/...`) on hosts that ship the URL with
-    // the scheme already attached.
-    let database_path = database_path
-        .strip_prefix("sqlite://")
-        .map(|s| s.to_string())
-        .unwrap_or(database_path);
-
-    let aggregator = DataAggregator::new(Some(&database_path));
-    let mut connection = aggregator
-        .establish_connection()
-        .map_err(|e| format!("Failed to establish database connection: {}", e))?;
-
     let cli = Cli::parse();
 
     // Handle --version-info flag
@@ -214,6 +323,27 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
         return Ok(());
     }
 
+    let settings = match Settings::load(cli.config.as_deref()) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Initialize tracing so the RTAC collector / worker / control logic logs are
+    // visible. Honors RUST_LOG (or log_level in the config file); defaults to
+    // info. `monitor` can change the level at runtime.
+    let log_control = neems_data::runtime::init_tracing(&settings.log_level);
+
+    let database_path = settings.database_path.clone();
+    let aggregator = DataAggregator::from_settings(&settings)
+        .with_config_path(cli.config.clone())
+        .with_log_control(log_control);
+    let mut connection = aggregator
+        .establish_connection()
+        .map_err(|e| format!("Failed to establish database connection: {}", e))?;
+
     match cli.command {
         Some(Commands::Monitor { verbose }) => {
             println!("Starting neems-data aggregator v{}", built_info::PKG_VERSION);
@@ -221,6 +351,9 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
             if let Some(commit) = built_info::GIT_COMMIT_HASH {
                 println!("Git commit: {}", commit);
             }
+            if let Some(config) = &cli.config {
+                println!("Config file: {}", config.display());
+            }
             println!("Database path: {}", database_path);
             if verbose {
                 println!("Verbose mode enabled - will show data source polling details");
@@ -253,8 +386,10 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                             ) {
                                 Ok(args) if args.is_empty() => "{}".to_string(),
                                 Ok(args) => {
-                                    let formatted: Vec<String> =
-                                        args.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+                                    let formatted: Vec<String> = redact_arguments(&args)
+                                        .iter()
+                                        .map(|(k, v)| format!("{}={}", k, v))
+                                        .collect();
                                     formatted.join(",")
                                 }
                                 Err(_) => "(invalid)".to_string(),
@@ -305,7 +440,7 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                                 Ok(args) if args.is_empty() => println!("  Arguments: (none)"),
                                 Ok(args) => {
                                     println!("  Arguments:");
-                                    for (key, value) in &args {
+                                    for (key, value) in &redact_arguments(&args) {
                                         println!("    {}: {}", key, value);
                                     }
                                 }
@@ -315,12 +450,15 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                         None => println!("  Arguments: (none)"),
                     }
 
+                    let point_fields = neems_data::points::source_fields(&source).join(", ");
                     println!(
                         "  Description: {}",
                         source.description.unwrap_or_else(|| "(none)".to_string())
                     );
                     println!("  Active: {}", source.active);
                     println!("  Interval: {} seconds", source.interval_seconds);
+                    println!("  Priority: {}", source.priority);
+                    println!("  Point Fields: {}", point_fields);
                     println!("  Created: {}", source.created_at.format("%Y-%m-%d %H:%M:%S"));
                     println!("  Updated: {}", source.updated_at.format("%Y-%m-%d %H:%M:%S"));
                     println!(
@@ -351,6 +489,39 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                 }
             }
         }
+        Some(Commands::Test { name, timeout }) => {
+            let source = match get_source_by_name(&mut connection, &name)? {
+                Some(source) => source,
+                None => {
+                    eprintln!("Error: Source '{}' not found.", name);
+                    std::process::exit(1);
+                }
+            };
+            let secrets = neems_data::secrets::SecretStore::for_sources(
+                &mut connection,
+                std::slice::from_ref(&source),
+            )?;
+            let timeout = timeout
+                .map(std::time::Duration::from_secs)
+                .unwrap_or(neems_data::collectors::SELF_TEST_TIMEOUT);
+
+            let result = neems_data::collectors::self_test(&source, &secrets, timeout).await;
+            match (&result.data, &result.error) {
+                (Some(data), _) => {
+                    println!("Source '{}' OK ({} ms):", name, result.duration_ms);
+                    println!("{}", serde_json::to_string_pretty(data)?);
+                }
+                (None, error) => {
+                    eprintln!(
+                        "Source '{}' failed ({} ms): {}",
+                        name,
+                        result.duration_ms,
+                        error.as_deref().unwrap_or("unknown error")
+                    );
+                    std::process::exit(1);
+                }
+            }
+        }
         Some(Commands::Add(args)) => {
             // Check if source already exists
             if get_source_by_name(&mut connection, &args.name)?.is_some() {
@@ -362,7 +533,7 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
             use std::str::FromStr;
 
             use neems_data::collectors::TestType;
-            let _test_type = TestType::from_str(&args.test_type)
+            let test_type = TestType::from_str(&args.test_type)
                 .map_err(|e| format!("Invalid test type '{}': {}", args.test_type, e))?;
 
             // Convert arguments Vec to HashMap
@@ -370,6 +541,9 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
             for (key, value) in args.arguments {
                 arguments.insert(key, value);
             }
+            test_type
+                .validate_arguments(&arguments)
+                .map_err(|e| format!("Invalid arguments for {}: {}", args.test_type, e))?;
 
             // Use environment variables for defaults if not provided
             let site_id = args
@@ -389,6 +563,12 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                 arguments: Some(serde_json::to_string(&arguments)?),
                 site_id,
                 company_id,
+                priority: args.priority,
+                point_fields: args
+                    .point_fields
+                    .as_deref()
+                    .map(neems_data::points::point_fields_json)
+                    .transpose()?,
             };
 
             let created = create_source(&mut connection, new_source)?;
@@ -396,10 +576,11 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
             println!("  Test Type: {}", test_type_str);
             if !arguments.is_empty() {
                 println!("  Arguments:");
-                for (key, value) in &arguments {
+                for (key, value) in &redact_arguments(&arguments) {
                     println!("    {}: {}", key, value);
                 }
             }
+            warn_plaintext_credentials(&arguments);
         }
         Some(Commands::Edit(args)) => {
             // Check if source exists
@@ -431,28 +612,44 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
             }
 
             // Handle arguments updates
-            let arguments = if args.clear_arguments {
-                Some("{}".to_string())
+            let current_args = match &existing.arguments {
+                Some(args_json) => {
+                    serde_json::from_str::<std::collections::HashMap<String, String>>(args_json)
+                        .unwrap_or_default()
+                }
+                None => std::collections::HashMap::new(),
+            };
+            let new_args = if args.clear_arguments {
+                Some(std::collections::HashMap::new())
             } else if !args.arguments.is_empty() {
                 // Merge with existing arguments if no clear flag
-                let mut current_args = match &existing.arguments {
-                    Some(args_json) => {
-                        serde_json::from_str::<std::collections::HashMap<String, String>>(args_json)
-                            .unwrap_or_default()
-                    }
-                    None => std::collections::HashMap::new(),
-                };
+                let mut merged = current_args.clone();
 
                 // Add/update new arguments
-                for (key, value) in args.arguments {
-                    current_args.insert(key, value);
-                }
+                let new_arguments: std::collections::HashMap<_, _> =
+                    args.arguments.into_iter().collect();
+                warn_plaintext_credentials(&new_arguments);
+                merged.extend(new_arguments);
 
-                Some(serde_json::to_string(&current_args)?)
+                Some(merged)
             } else {
                 None
             };
 
+            // Check the arguments against the (possibly new) test type
+            // whenever either changes; legacy sources have no test type
+            if let Some(test_type) = args.test_type.as_ref().or(existing.test_type.as_ref())
+                && (args.test_type.is_some() || new_args.is_some())
+            {
+                use neems_data::collectors::TestType;
+                let checked = new_args.as_ref().unwrap_or(&current_args);
+                test_type
+                    .parse::<TestType>()?
+                    .validate_arguments(checked)
+                    .map_err(|e| format!("Invalid arguments for {}: {}", test_type, e))?;
+            }
+            let arguments = new_args.map(|a| serde_json::to_string(&a)).transpose()?;
+
             // Handle site_id updates
             let site_id = if args.clear_site_id {
                 Some(None)
@@ -471,6 +668,16 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                 None
             };
 
+            let point_fields = if args.clear_point_fields {
+                Some(None)
+            } else {
+                args.point_fields
+                    .as_deref()
+                    .map(neems_data::points::point_fields_json)
+                    .transpose()?
+                    .map(Some)
+            };
+
             let updates = UpdateSource {
                 name: args.new_name,
                 description,
@@ -481,10 +688,17 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                 arguments,
                 site_id,
                 company_id,
+                priority: args.priority,
+                point_fields,
             };
+            let point_fields_changed = updates.point_fields.is_some();
 
             let updated = update_source(&mut connection, source_id, updates)?;
             println!("Updated source '{}'", updated.name);
+            if point_fields_changed {
+                let written = neems_data::points::backfill_points(&mut connection, &updated)?;
+                println!("  Backfilled {} reading points", written);
+            }
         }
         Some(Commands::Remove { name }) => {
             // Check if source exists
@@ -526,6 +740,62 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
             )?;
             report_seed("alarm", args.site_id, &outcome);
         }
+        Some(Commands::Sync { status: true, .. }) => {
+            let targets = neems_data::sync::sync_status(&mut connection)?;
+            if targets.is_empty() {
+                println!("No sync targets yet.");
+            }
+            for status in targets {
+                let synced_at = status
+                    .last_synced_at
+                    .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                    .unwrap_or_else(|| "never".to_string());
+                println!(
+                    "{}: cursor {}, last synced {}",
+                    status.target, status.last_reading_id, synced_at
+                );
+                if let Some(error) = status.last_error {
+                    println!("  last error: {}", error);
+                }
+            }
+        }
+        Some(Commands::Sync { status: false, reconcile }) => {
+            let config = settings.sync.clone().ok_or("No sync URL configured (NEEMS_SYNC_URL)")?;
+            let target = config.base_url.clone();
+            let mut client = neems_data::sync::SyncClient::new(config)?;
+            let start = client.remote_cursor().await?;
+            let database_url = format!("sqlite://{}", database_path);
+            let (cursor, accepted) = client.sync_from(&database_url, start).await?;
+            println!("Synced {} readings to {} (cursor {}).", accepted, target, cursor);
+
+            if reconcile {
+                let report = client.reconcile(&database_url, cursor).await?;
+                println!(
+                    "Checked {} source-days from {} to {}: {} mismatched.",
+                    report.checked,
+                    report.from,
+                    report.to,
+                    report.discrepancies.len()
+                );
+                for d in report.discrepancies {
+                    let note = if d.edge_count == d.central_count {
+                        " (contents differ)"
+                    } else {
+                        ""
+                    };
+                    println!(
+                        "  {} {}: edge {} readings, server {}{}",
+                        d.day, d.source_name, d.edge_count, d.central_count, note
+                    );
+                }
+            }
+        }
+        Some(Commands::Maintenance { full }) => {
+            let report = neems_data::maintenance::run_maintenance(&mut connection, full)?;
+            print_maintenance_report(&report);
+        }
+        Some(Commands::Secret { action }) => run_secret_action(&mut connection, action)?,
+        Some(Commands::Bench(args)) => run_bench(args, settings.writer.clone()).await?,
         None => {
             eprintln!("No command provided. Use --help for usage information.");
             std::process::exit(1);
@@ -535,6 +805,192 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     Ok(())
 }
 
+async fn run_bench(
+    args: BenchArgs,
+    mut writer: neems_data::WriterConfig,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    use neems_data::bench::{BenchConfig, run_bench};
+
+    writer.queue_capacity = args.queue_capacity.unwrap_or(writer.queue_capacity).max(1);
+    writer.batch_size = args.batch_size.unwrap_or(writer.batch_size).max(1);
+    if let Some(ms) = args.flush_interval_ms {
+        writer.flush_interval = std::time::Duration::from_millis(ms.max(1));
+    }
+    let config = BenchConfig {
+        sources: args.sources,
+        rate_hz: args.rate,
+        duration: std::time::Duration::from_secs(args.duration),
+        fields: args.fields,
+        critical: args.critical,
+        writer,
+    };
+
+    // Keeps the temporary database until the run is over
+    let scratch = tempfile::tempdir()?;
+    let database = args.database.unwrap_or_else(|| scratch.path().join("bench.sqlite"));
+
+    println!(
+        "Benchmarking {} sources at {}/s for {}s ({} fields, {} readings) into {}",
+        config.sources,
+        config.rate_hz,
+        args.duration,
+        config.fields,
+        if config.critical {
+            "critical"
+        } else {
+            "droppable"
+        },
+        database.display()
+    );
+    println!(
+        "Queue capacity {}, batch size {}, flush interval {} ms",
+        config.writer.queue_capacity,
+        config.writer.batch_size,
+        config.writer.flush_interval.as_millis()
+    );
+
+    let report = run_bench(&database, &config).await?;
+    let ms = |d: std::time::Duration| d.as_secs_f64() * 1000.0;
+    println!();
+    println!("Readings offered:  {} ({:.1}/s)", report.offered, report.offered_rate());
+    println!(
+        "Readings written:  {} ({:.1}/s sustained over {:.1}s)",
+        report.written,
+        report.throughput(),
+        report.elapsed.as_secs_f64()
+    );
+    println!("Readings dropped:  {}", report.dropped);
+    if report.failed > 0 {
+        println!("Readings failed:   {}", report.failed);
+    }
+    println!(
+        "Batches:           {} (mean {:.1} readings)",
+        report.batch_latency.count, report.mean_batch_size
+    );
+    println!(
+        "Batch latency:     mean {:.1} ms, p50 {:.1} ms, p95 {:.1} ms, p99 {:.1} ms, max {:.1} ms",
+        ms(report.batch_latency.mean),
+        ms(report.batch_latency.p50),
+        ms(report.batch_latency.p95),
+        ms(report.batch_latency.p99),
+        ms(report.batch_latency.max)
+    );
+    println!(
+        "Queue depth:       mean {:.1}, high water {} of {}",
+        report.mean_queue_depth, report.queue_high_water_mark, report.queue_capacity
+    );
+    println!("Backpressure waits: {}", report.backpressure_waits);
+    Ok(())
+}
+
+fn run_secret_action(
+    connection: &mut diesel::sqlite::SqliteConnection,
+    action: SecretAction,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    use neems_data::secrets::{self, MasterKey};
+
+    let master_key = || -> Result<&'static MasterKey, Box<dyn Error + Send + Sync>> {
+        MasterKey::from_env()?.ok_or_else(|| {
+            format!(
+                "Set {} or {} (generate one with `neems-data secret generate-key`)",
+                secrets::KEY_ENV,
+                secrets::KEY_FILE_ENV
+            )
+            .into()
+        })
+    };
+
+    match action {
+        SecretAction::Set { name, value } => {
+            let key = master_key()?;
+            let value = match value {
+                Some(value) => value,
+                None => {
+                    let mut line = String::new();
+                    std::io::stdin().read_line(&mut line)?;
+                    line.trim_end_matches(['\r', '\n']).to_string()
+                }
+            };
+            if value.is_empty() {
+                return Err("Refusing to store an empty secret".into());
+            }
+            secrets::set_secret(connection, key, &name, &value)?;
+            println!(
+                "Stored secret '{}'; refer to it as {}{}",
+                name,
+                secrets::REFERENCE_PREFIX,
+                name
+            );
+        }
+        SecretAction::List => {
+            let stored = secrets::list_secrets(connection)?;
+            if stored.is_empty() {
+                println!("No secrets stored.");
+            }
+            for (name, _created_at, updated_at) in stored {
+                println!("{:<32} updated {}", name, updated_at.format("%Y-%m-%d %H:%M:%S"));
+            }
+        }
+        SecretAction::Remove { name } => {
+            if secrets::delete_secret(connection, &name)? {
+                println!("Deleted secret '{}'", name);
+            } else {
+                eprintln!("Error: Secret '{}' not found.", name);
+                std::process::exit(1);
+            }
+        }
+        SecretAction::GenerateKey => println!("{}", secrets::generate_key()?),
+    }
+    Ok(())
+}
+
+/// Point out credentials given as plain argument values.
+fn warn_plaintext_credentials(arguments: &std::collections::HashMap<String, String>) {
+    for (key, value) in arguments {
+        if neems_data::secrets::is_sensitive_key(key)
+            && neems_data::secrets::reference_name(value).is_none()
+        {
+            eprintln!(
+                "Warning: argument '{}' looks like a credential and is stored in plaintext; \
+                 use `neems-data secret set <name>` and `{}=secret://<name>` instead",
+                key, key
+            );
+        }
+    }
+}
+
+/// Print the outcome of a maintenance run.
+fn print_maintenance_report(report: &neems_data::maintenance::MaintenanceReport) {
+    println!(
+        "Maintenance complete{}: {} -> {} pages ({} bytes reclaimed), {} free pages",
+        if report.full_vacuum {
+            " (full vacuum)"
+        } else {
+            ""
+        },
+        report.pages_before,
+        report.pages_after,
+        report.reclaimed_bytes(),
+        report.free_pages
+    );
+    match report.checkpoint {
+        Some((busy, log, checkpointed)) => println!(
+            "  WAL checkpoint: {} of {} frames{}",
+            checkpointed,
+            log,
+            if busy != 0 {
+                " (busy, retry later)"
+            } else {
+                ""
+            }
+        ),
+        None => println!("  WAL checkpoint: not in WAL mode"),
+    }
+    if report.auto_vacuum != 2 {
+        println!("  auto_vacuum is not incremental; run with --full once to enable it");
+    }
+}
+
 /// Print a one-line summary of a seed run.
 fn report_seed(kind: &str, site_id: i32, outcome: &neems_data::SeedOutcome) {
     if outcome.written == 0 {
@@ -0,0 +1,280 @@
+//! Routine SQLite maintenance.
+//!
+//! Edge databases take a constant stream of inserts for months without
+//! attention. Left alone, free pages from retention deletes are never
+//! returned to the filesystem, query plans go stale as tables grow, and the
+//! WAL file can keep its high-water size. [`run_maintenance`] handles all
+//! three:
+//!
+//! - **Incremental vacuum**: returns free pages to the filesystem when the
+//!   database uses `auto_vacuum = INCREMENTAL`. A full vacuum (`--full` on the
+//!   CLI) rewrites the file once and switches it to incremental mode.
+//! - **ANALYZE**: refreshes the statistics the query planner uses.
+//! - **WAL checkpoint**: `wal_checkpoint(TRUNCATE)` folds the WAL back into the
+//!   database and truncates it.
+//!
+//! The monitor runs maintenance once per interval inside a configured
+//! low-activity window (see [`MaintenanceConfig`]).
+
+use std::{env, time::Duration};
+
+use chrono::{Local, NaiveTime};
+use diesel::{
+    connection::SimpleConnection, prelude::*, sql_types::Integer, sqlite::SqliteConnection,
+};
+
+use crate::{DataResult, encryption};
+
+/// A daily local-time window, e.g. `02:00-04:00`. A window whose end is
+/// before its start wraps past midnight.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MaintenanceWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl MaintenanceWindow {
+    /// Parse `HH:MM-HH:MM`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (start, end) = s.split_once('-')?;
+        let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?;
+        let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?;
+        (start != end).then_some(Self { start, end })
+    }
+
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            time >= self.start && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+/// When the monitor runs maintenance.
+///
+/// Read from the environment by [`MaintenanceConfig::from_env`]:
+/// - `NEEMS_DATA_MAINTENANCE_WINDOW`: local-time window as `HH:MM-HH:MM`
+///   (default `02:00-04:00`), or `off` to disable scheduled maintenance
+/// - `NEEMS_DATA_MAINTENANCE_INTERVAL_HOURS`: minimum time between runs
+///   (default 24)
+#[derive(Debug, Clone)]
+pub struct MaintenanceConfig {
+    pub window: Option<MaintenanceWindow>,
+    pub interval: Duration,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            window: MaintenanceWindow::parse("02:00-04:00"),
+            interval: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+impl MaintenanceConfig {
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Ok(window) = env::var("NEEMS_DATA_MAINTENANCE_WINDOW") {
+            config.window = if window.trim().eq_ignore_ascii_case("off") {
+                None
+            } else {
+                MaintenanceWindow::parse(&window).or(config.window)
+            };
+        }
+        if let Some(hours) = env::var("NEEMS_DATA_MAINTENANCE_INTERVAL_HOURS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .filter(|&v| v > 0)
+        {
+            config.interval = Duration::from_secs(hours * 60 * 60);
+        }
+        config
+    }
+}
+
+/// Outcome of one maintenance run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaintenanceReport {
+    /// Whether the file was rewritten with a full VACUUM
+    pub full_vacuum: bool,
+    /// `auto_vacuum` mode after the run: 0 none, 1 full, 2 incremental
+    pub auto_vacuum: i32,
+    pub page_size: i32,
+    pub pages_before: i32,
+    pub pages_after: i32,
+    /// Free pages left in the file after the run
+    pub free_pages: i32,
+    /// `(busy, wal frames, checkpointed frames)` from the WAL checkpoint, or
+    /// `None` when the database isn't in WAL mode
+    pub checkpoint: Option<(i32, i32, i32)>,
+}
+
+impl MaintenanceReport {
+    /// Bytes returned to the filesystem.
+    pub fn reclaimed_bytes(&self) -> i64 {
+        i64::from(self.pages_before - self.pages_after).max(0) * i64::from(self.page_size)
+    }
+}
+
+#[derive(QueryableByName)]
+struct PragmaValue {
+    #[diesel(sql_type = Integer)]
+    value: i32,
+}
+
+#[derive(QueryableByName)]
+struct CheckpointRow {
+    #[diesel(sql_type = Integer)]
+    busy: i32,
+    #[diesel(sql_type = Integer)]
+    log: i32,
+    #[diesel(sql_type = Integer)]
+    checkpointed: i32,
+}
+
+fn pragma(connection: &mut SqliteConnection, name: &str) -> QueryResult<i32> {
+    diesel::sql_query(format!("SELECT {0} AS value FROM pragma_{0}()", name))
+        .get_result::<PragmaValue>(connection)
+        .map(|row| row.value)
+}
+
+/// Run incremental vacuum (or a full vacuum when `full_vacuum` is set),
+/// ANALYZE, and a truncating WAL checkpoint on `connection`.
+///
+/// Must not be called inside a transaction. A full vacuum needs free disk
+/// space for a copy of the database and blocks writers while it runs.
+pub fn run_maintenance(
+    connection: &mut SqliteConnection,
+    full_vacuum: bool,
+) -> DataResult<MaintenanceReport> {
+    let page_size = pragma(connection, "page_size")?;
+    let pages_before = pragma(connection, "page_count")?;
+
+    if full_vacuum {
+        // auto_vacuum can only change on an empty database or through VACUUM
+        connection.batch_execute("PRAGMA auto_vacuum = INCREMENTAL; VACUUM;")?;
+    } else if pragma(connection, "auto_vacuum")? == 2 {
+        connection.batch_execute("PRAGMA incremental_vacuum;")?;
+    }
+
+    connection.batch_execute("ANALYZE;")?;
+
+    let checkpoint = diesel::sql_query("PRAGMA wal_checkpoint(TRUNCATE)")
+        .get_result::<CheckpointRow>(connection)?;
+    // Outside WAL mode the log and checkpointed counts are -1
+    let checkpoint =
+        (checkpoint.log >= 0).then_some((checkpoint.busy, checkpoint.log, checkpoint.checkpointed));
+
+    Ok(MaintenanceReport {
+        full_vacuum,
+        auto_vacuum: pragma(connection, "auto_vacuum")?,
+        page_size,
+        pages_before,
+        pages_after: pragma(connection, "page_count")?,
+        free_pages: pragma(connection, "freelist_count")?,
+        checkpoint,
+    })
+}
+
+/// Run maintenance once per `config.interval`, inside `config.window`.
+/// Returns immediately if no window is configured.
+pub async fn run_maintenance_loop(database_url: String, config: MaintenanceConfig, verbose: bool) {
+    let Some(window) = config.window else {
+        return;
+    };
+    let mut last_run: Option<tokio::time::Instant> = None;
+
+    loop {
+        let due = last_run.is_none_or(|t| t.elapsed() >= config.interval);
+        if due && window.contains(Local::now().time()) {
+            let database_url = database_url.clone();
+            let result = tokio::task::spawn_blocking(move || -> DataResult<MaintenanceReport> {
+                let mut connection = encryption::establish(&database_url)?;
+                run_maintenance(&mut connection, false)
+            })
+            .await;
+
+            match result {
+                Ok(Ok(report)) => {
+                    if verbose || report.reclaimed_bytes() > 0 {
+                        println!(
+                            "{} - Database maintenance done: {} bytes reclaimed, {} free pages",
+                            Local::now().to_rfc3339(),
+                            report.reclaimed_bytes(),
+                            report.free_pages
+                        );
+                    }
+                    if report.auto_vacuum != 2 && report.free_pages > 0 {
+                        println!(
+                            "  auto_vacuum is not incremental; run `neems-data maintenance --full` \
+                             once to reclaim free pages"
+                        );
+                    }
+                }
+                Ok(Err(e)) => eprintln!("{} - Database maintenance failed: {}", Local::now(), e),
+                Err(e) => eprintln!("{} - Database maintenance panicked: {}", Local::now(), e),
+            }
+            // Failures aren't retried until the next interval
+            last_run = Some(tokio::time::Instant::now());
+        }
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_window_parsing_and_wraparound() {
+        let night = MaintenanceWindow::parse("02:00-04:00").unwrap();
+        assert!(night.contains(at(2, 0)));
+        assert!(night.contains(at(3, 59)));
+        assert!(!night.contains(at(4, 0)));
+
+        let overnight = MaintenanceWindow::parse("23:30 - 01:00").unwrap();
+        assert!(overnight.contains(at(23, 45)));
+        assert!(overnight.contains(at(0, 30)));
+        assert!(!overnight.contains(at(12, 0)));
+
+        assert!(MaintenanceWindow::parse("02:00").is_none());
+        assert!(MaintenanceWindow::parse("02:00-02:00").is_none());
+    }
+
+    #[test]
+    fn test_full_vacuum_switches_to_incremental() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut conn = SqliteConnection::establish(file.path().to_str().unwrap()).unwrap();
+        conn.batch_execute(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE t (x BLOB);
+             WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 200)
+             INSERT INTO t SELECT zeroblob(4096) FROM n;
+             DELETE FROM t;",
+        )
+        .unwrap();
+
+        let report = run_maintenance(&mut conn, true).unwrap();
+        assert_eq!(report.auto_vacuum, 2);
+        assert!(report.reclaimed_bytes() > 0);
+        assert_eq!(report.free_pages, 0);
+        assert!(report.checkpoint.is_some(), "WAL mode reports a checkpoint");
+
+        // Later deletes are reclaimed incrementally
+        conn.batch_execute(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 100)
+             INSERT INTO t SELECT zeroblob(4096) FROM n;
+             DELETE FROM t;",
+        )
+        .unwrap();
+        let report = run_maintenance(&mut conn, false).unwrap();
+        assert!(report.reclaimed_bytes() > 0);
+        assert_eq!(report.free_pages, 0);
+    }
+}
@@ -0,0 +1,296 @@
+//! Maintenance windows: periods when a site, or one of its devices, is being
+//! worked on.
+//!
+//! Windows are stored in the `maintenance_windows` table and managed through
+//! neems-api. While one is open:
+//!
+//! - alarm notifications for the site's sources are not sent, if it covers the
+//!   whole site (see [`crate::notifications`]); sources aren't tied to devices,
+//!   so a device's window doesn't silence them
+//! - neems-api reports no active command for the site, or for the device, so
+//!   automated dispatch falls back to standby
+//!
+//! A window is open from `starts_at` up to, not including, `ends_at` (UTC).
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{DataResult, schema};
+
+/// Longest a single window can be
+pub const MAX_WINDOW_DAYS: i64 = 31;
+
+/// A stored maintenance window.
+#[derive(Debug, Clone, PartialEq, Queryable, Selectable, Serialize, Deserialize, TS)]
+#[diesel(table_name = schema::maintenance_windows)]
+#[ts(export)]
+pub struct MaintenanceWindow {
+    pub id: i32,
+    pub site_id: i32,
+    /// The device under maintenance; absent for the whole site
+    pub device_id: Option<i32>,
+    #[ts(type = "string")]
+    pub starts_at: NaiveDateTime,
+    #[ts(type = "string")]
+    pub ends_at: NaiveDateTime,
+    pub reason: String,
+    /// Email of the user who opened the window
+    pub created_by: String,
+    #[ts(type = "string")]
+    pub created_at: NaiveDateTime,
+}
+
+impl MaintenanceWindow {
+    /// Whether the window is open at `at`.
+    pub fn covers(&self, at: NaiveDateTime) -> bool {
+        self.starts_at <= at && at < self.ends_at
+    }
+
+    /// Whether the window applies to the whole site, or to `device_id`.
+    pub fn applies_to(&self, device_id: Option<i32>) -> bool {
+        self.device_id.is_none() || self.device_id == device_id
+    }
+}
+
+/// A window to open, as requested through the API.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct NewMaintenanceWindow {
+    pub device_id: Option<i32>,
+    /// Defaults to now
+    #[ts(type = "string | null")]
+    #[serde(default)]
+    pub starts_at: Option<NaiveDateTime>,
+    #[ts(type = "string")]
+    pub ends_at: NaiveDateTime,
+    pub reason: String,
+}
+
+impl NewMaintenanceWindow {
+    /// Every problem with this window, or `Ok` if there are none.
+    pub fn validate(&self, now: NaiveDateTime) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if self.reason.trim().is_empty() {
+            problems.push("reason: must not be empty".to_string());
+        }
+        let starts_at = self.starts_at.unwrap_or(now);
+        if self.ends_at <= starts_at {
+            problems.push("ends_at: must be after starts_at".to_string());
+        } else if self.ends_at - starts_at > chrono::Duration::days(MAX_WINDOW_DAYS) {
+            problems.push(format!("ends_at: a window can last at most {} days", MAX_WINDOW_DAYS));
+        }
+        if self.ends_at <= now {
+            problems.push("ends_at: must be in the future".to_string());
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+/// `site_id`'s windows, most recent first; only those not yet over when
+/// `current` is set.
+pub fn list_windows(
+    connection: &mut SqliteConnection,
+    site_id: i32,
+    current: bool,
+) -> DataResult<Vec<MaintenanceWindow>> {
+    use schema::maintenance_windows::dsl;
+
+    let mut query = dsl::maintenance_windows
+        .filter(dsl::site_id.eq(site_id))
+        .select(MaintenanceWindow::as_select())
+        .into_boxed();
+    if current {
+        query = query.filter(dsl::ends_at.gt(Utc::now().naive_utc()));
+    }
+    Ok(query.order((dsl::starts_at.desc(), dsl::id.desc())).load(connection)?)
+}
+
+pub fn get_window(
+    connection: &mut SqliteConnection,
+    id: i32,
+) -> DataResult<Option<MaintenanceWindow>> {
+    use schema::maintenance_windows::dsl;
+
+    Ok(dsl::maintenance_windows
+        .find(id)
+        .select(MaintenanceWindow::as_select())
+        .first(connection)
+        .optional()?)
+}
+
+/// Validate and store a window for `site_id`, opened by `created_by`.
+pub fn create_window(
+    connection: &mut SqliteConnection,
+    site_id: i32,
+    window: &NewMaintenanceWindow,
+    created_by: &str,
+) -> DataResult<MaintenanceWindow> {
+    use schema::maintenance_windows::dsl;
+
+    let now = Utc::now().naive_utc();
+    window.validate(now).map_err(|problems| problems.join("; "))?;
+    let created = connection.transaction::<_, diesel::result::Error, _>(|conn| {
+        diesel::insert_into(dsl::maintenance_windows)
+            .values((
+                dsl::site_id.eq(site_id),
+                dsl::device_id.eq(window.device_id),
+                dsl::starts_at.eq(window.starts_at.unwrap_or(now)),
+                dsl::ends_at.eq(window.ends_at),
+                dsl::reason.eq(window.reason.trim()),
+                dsl::created_by.eq(created_by),
+                dsl::created_at.eq(now),
+            ))
+            .execute(conn)?;
+        dsl::maintenance_windows
+            .order(dsl::id.desc())
+            .select(MaintenanceWindow::as_select())
+            .first(conn)
+    })?;
+    Ok(created)
+}
+
+/// Close a window now, or delete it if it hasn't started. Returns the
+/// window as it stands, or `None` if there's no such window.
+pub fn end_window(
+    connection: &mut SqliteConnection,
+    id: i32,
+) -> DataResult<Option<MaintenanceWindow>> {
+    use schema::maintenance_windows::dsl;
+
+    let Some(window) = get_window(connection, id)? else {
+        return Ok(None);
+    };
+    let now = Utc::now().naive_utc();
+    if window.starts_at > now {
+        diesel::delete(dsl::maintenance_windows.find(id)).execute(connection)?;
+        return Ok(Some(window));
+    }
+    if window.ends_at > now {
+        diesel::update(dsl::maintenance_windows.find(id))
+            .set(dsl::ends_at.eq(now))
+            .execute(connection)?;
+    }
+    get_window(connection, id)
+}
+
+/// The window open on `site_id` at `at` that applies to `device_id` (or to
+/// the whole site, for `None`), if any; the one ending last when several
+/// are.
+pub fn open_window(
+    connection: &mut SqliteConnection,
+    site_id: i32,
+    device_id: Option<i32>,
+    at: NaiveDateTime,
+) -> DataResult<Option<MaintenanceWindow>> {
+    use schema::maintenance_windows::dsl;
+
+    let windows = dsl::maintenance_windows
+        .filter(dsl::site_id.eq(site_id))
+        .filter(dsl::starts_at.le(at))
+        .filter(dsl::ends_at.gt(at))
+        .order(dsl::ends_at.desc())
+        .select(MaintenanceWindow::as_select())
+        .load(connection)?;
+    Ok(windows.into_iter().find(|w| w.applies_to(device_id)))
+}
+
+/// Site-wide windows of any site that end after `after`, for checking many
+/// readings at once.
+pub fn site_windows_ending_after(
+    connection: &mut SqliteConnection,
+    after: NaiveDateTime,
+) -> DataResult<Vec<MaintenanceWindow>> {
+    use schema::maintenance_windows::dsl;
+
+    Ok(dsl::maintenance_windows
+        .filter(dsl::device_id.is_null())
+        .filter(dsl::ends_at.gt(after))
+        .select(MaintenanceWindow::as_select())
+        .load(connection)?)
+}
+
+/// Whether one of `windows` covers `site_id` as a whole at `at`.
+pub fn site_under_maintenance(
+    windows: &[MaintenanceWindow],
+    site_id: Option<i32>,
+    at: NaiveDateTime,
+) -> bool {
+    windows
+        .iter()
+        .any(|w| Some(w.site_id) == site_id && w.device_id.is_none() && w.covers(at))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate};
+
+    use super::*;
+
+    fn at(hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 7, 1).unwrap().and_hms_opt(hour, 0, 0).unwrap()
+    }
+
+    fn window(site_id: i32, device_id: Option<i32>, from: u32, to: u32) -> MaintenanceWindow {
+        MaintenanceWindow {
+            id: 1,
+            site_id,
+            device_id,
+            starts_at: at(from),
+            ends_at: at(to),
+            reason: "Inverter swap".to_string(),
+            created_by: "tech@example.com".to_string(),
+            created_at: at(0),
+        }
+    }
+
+    #[test]
+    fn test_covers_and_applies() {
+        let site = window(3, None, 8, 12);
+        assert!(!site.covers(at(7)));
+        assert!(site.covers(at(8)));
+        assert!(!site.covers(at(12)));
+        assert!(site.applies_to(None) && site.applies_to(Some(5)));
+
+        let device = window(3, Some(5), 8, 12);
+        assert!(device.applies_to(Some(5)));
+        assert!(!device.applies_to(Some(6)));
+        assert!(!device.applies_to(None));
+
+        let windows = [device, window(4, None, 8, 12)];
+        assert!(!site_under_maintenance(&windows, Some(3), at(9)));
+        assert!(site_under_maintenance(&windows, Some(4), at(9)));
+        assert!(!site_under_maintenance(&windows, None, at(9)));
+    }
+
+    #[test]
+    fn test_validation() {
+        let now = at(6);
+        let mut new = NewMaintenanceWindow {
+            device_id: None,
+            starts_at: None,
+            ends_at: at(10),
+            reason: "Inverter swap".to_string(),
+        };
+        assert!(new.validate(now).is_ok());
+
+        new.starts_at = Some(at(11));
+        new.reason = " ".to_string();
+        let problems = new.validate(now).unwrap_err().join("\n");
+        assert!(problems.contains("reason"), "{}", problems);
+        assert!(problems.contains("after starts_at"), "{}", problems);
+
+        new.starts_at = Some(at(1));
+        new.ends_at = at(1) + Duration::days(40);
+        new.reason = "Inverter swap".to_string();
+        assert!(new.validate(now).unwrap_err()[0].contains("at most 31 days"));
+
+        new.ends_at = at(5);
+        assert_eq!(new.validate(now).unwrap_err(), vec!["ends_at: must be in the future"]);
+    }
+}
@@ -16,6 +16,9 @@ pub struct Reading {
     pub timestamp: NaiveDateTime,
     pub data: String, // JSON string
     pub quality_flags: i32,
+    /// Timestamp reported by the device, when known. Together with
+    /// `source_id` this is the reading's idempotency key.
+    pub device_timestamp: Option<NaiveDateTime>,
 }
 
 #[derive(Debug, Clone, Insertable, Serialize, Deserialize)]
@@ -25,6 +28,7 @@ pub struct NewReading {
     pub timestamp: Option<NaiveDateTime>,
     pub data: String, // JSON string
     pub quality_flags: Option<i32>,
+    pub device_timestamp: Option<NaiveDateTime>,
 }
 
 impl Reading {
@@ -42,6 +46,7 @@ impl NewReading {
             timestamp: None, // Will use database default
             data: serde_json::to_string(data)?,
             quality_flags: None, // Will use database default (0)
+            device_timestamp: None,
         })
     }
 
@@ -56,6 +61,15 @@ impl NewReading {
             timestamp: None,
             data: serde_json::to_string(data)?,
             quality_flags: Some(quality_flags),
+            device_timestamp: None,
         })
     }
+
+    /// Attach the device-reported timestamp, making the reading idempotent:
+    /// inserting another reading with the same source and device timestamp
+    /// updates this one instead of adding a row.
+    pub fn with_device_timestamp(mut self, device_timestamp: NaiveDateTime) -> Self {
+        self.device_timestamp = Some(device_timestamp);
+        self
+    }
 }
@@ -24,6 +24,12 @@ pub struct Source {
     pub arguments: Option<String>, // JSON string
     pub site_id: Option<i32>,
     pub company_id: Option<i32>,
+    /// Scheduling priority; higher values are dispatched first when the
+    /// reader loop is saturated (default 0)
+    pub priority: i32,
+    /// JSON array of numeric reading fields copied into `reading_points`;
+    /// `None` uses the test type's defaults
+    pub point_fields: Option<String>,
 }
 
 impl Source {
@@ -35,6 +41,13 @@ impl Source {
         }
     }
 
+    /// This source with credential-looking argument values masked, for
+    /// showing to users (see [`crate::secrets::redact_arguments`])
+    pub fn redacted(mut self) -> Self {
+        self.arguments = self.arguments.map(|a| crate::secrets::redact_arguments_json(&a));
+        self
+    }
+
     /// Set arguments from a HashMap, serializing to JSON
     pub fn set_arguments(
         &mut self,
@@ -56,6 +69,8 @@ pub struct NewSource {
     pub arguments: Option<String>, // JSON string
     pub site_id: Option<i32>,
     pub company_id: Option<i32>,
+    pub priority: Option<i32>,
+    pub point_fields: Option<String>,
 }
 
 /// Builder-style configuration for creating a NewSource
@@ -66,6 +81,8 @@ pub struct NewSourceConfig {
     pub interval_seconds: Option<i32>,
     pub site_id: Option<i32>,
     pub company_id: Option<i32>,
+    pub priority: Option<i32>,
+    pub point_fields: Option<String>,
 }
 
 impl NewSource {
@@ -85,6 +102,8 @@ impl NewSource {
             arguments: Some(serde_json::to_string(arguments)?),
             site_id: config.site_id,
             company_id: config.company_id,
+            priority: config.priority,
+            point_fields: config.point_fields,
         })
     }
 }
@@ -101,6 +120,8 @@ pub struct UpdateSource {
     pub arguments: Option<String>, // JSON string
     pub site_id: Option<Option<i32>>,
     pub company_id: Option<Option<i32>>,
+    pub priority: Option<i32>,
+    pub point_fields: Option<Option<String>>,
 }
 
 impl UpdateSource {
@@ -0,0 +1,736 @@
+//! MQTT publisher for site state, alarms and selected readings.
+//!
+//! When `NEEMS_MQTT_URL` is set, `monitor` publishes to an MQTT broker what
+//! on-prem SCADA and Node-RED flows want to react to, as JSON:
+//!
+//! - `state`: a source's operating state (its reading's `state` or `mode`
+//!   field, e.g. the RTAC's `Charging`) whenever it changes, retained
+//! - `alarm/<alarm_num>`: an alarm being raised or cleared, decoded from the
+//!   reading's `alarm_registers` like `GET /api/1/Alarms/Active`, retained
+//! - `reading`: every reading of the sources or test types listed in
+//!   `NEEMS_MQTT_READINGS`, not retained
+//!
+//! Topics come from a template (default `neems/{site_id}/{source}/{event}`),
+//! so deployments can match an existing topic scheme. Like
+//! [`crate::prometheus`], the publisher follows the readings table with a
+//! cursor in `sync_cursors` (under the target `mqtt:<url>`), advances it only
+//! once the broker has acknowledged a batch (at QoS 1 and 2), and backs off
+//! while the broker is unreachable, so transitions during an outage are
+//! published in order when it comes back. A new target starts from the
+//! latest reading, publishing each source's current state and active alarms
+//! with its next reading.
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use chrono::Local;
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS, TlsConfiguration, Transport};
+use serde_json::{Value as JsonValue, json};
+use tokio::sync::watch;
+
+use crate::{
+    DataResult, encryption,
+    rtac::{AlarmFlags, AlarmSeverity, sld_meta_for},
+    schema,
+    sync::{SyncReading, load_cursor, pending_batch, save_cursor},
+};
+
+/// Topic template used when none is configured.
+pub const DEFAULT_TOPIC: &str = "neems/{site_id}/{source}/{event}";
+
+/// Placeholders a topic template may use.
+pub const TOPIC_PLACEHOLDERS: &[&str] =
+    &["{company_id}", "{site_id}", "{source}", "{test_type}", "{event}"];
+
+/// Reading fields holding a source's operating state, in order of preference.
+const STATE_FIELDS: &[&str] = &["state", "mode"];
+
+/// How long a batch may wait for the broker to accept and acknowledge it.
+const ACK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Settings for publishing to an MQTT broker.
+///
+/// Read from the environment by [`MqttConfig::from_env`]:
+/// - `NEEMS_MQTT_URL`: broker URL, `mqtt://host:1883` or `mqtts://host:8883`
+///   for TLS (publishing is disabled when unset)
+/// - `NEEMS_MQTT_CLIENT_ID`: client id (default `neems-data-<host name>`)
+/// - `NEEMS_MQTT_USERNAME` / `NEEMS_MQTT_PASSWORD`: credentials
+/// - `NEEMS_MQTT_CA_FILE`: PEM CA certificate for a broker with a private CA
+/// - `NEEMS_MQTT_TOPIC`: topic template (default [`DEFAULT_TOPIC`])
+/// - `NEEMS_MQTT_QOS`: 0, 1 or 2 (default 1)
+/// - `NEEMS_MQTT_RETAIN`: whether state and alarm messages are retained
+///   (default true)
+/// - `NEEMS_MQTT_READINGS`: source names or test types whose readings are
+///   published, as `name,...`, or `*` for all
+/// - `NEEMS_MQTT_BATCH_SIZE`: readings per batch
+/// - `NEEMS_MQTT_INTERVAL_SECS`: pause between passes once caught up
+#[derive(Debug, Clone)]
+pub struct MqttConfig {
+    pub url: String,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub ca_file: Option<PathBuf>,
+    pub topic: String,
+    pub qos: u8,
+    pub retain: bool,
+    /// Source names or test types whose readings are published; `*` matches
+    /// every source
+    pub readings: Vec<String>,
+    pub batch_size: usize,
+    pub interval: Duration,
+    /// Longest wait between retries after repeated failures
+    pub max_backoff: Duration,
+}
+
+impl MqttConfig {
+    pub fn new(url: impl Into<String>) -> Self {
+        let host = sysinfo::System::host_name().unwrap_or_else(|| "edge".to_string());
+        Self {
+            url: url.into(),
+            client_id: format!("neems-data-{}", host),
+            username: None,
+            password: None,
+            ca_file: None,
+            topic: DEFAULT_TOPIC.to_string(),
+            qos: 1,
+            retain: true,
+            readings: Vec::new(),
+            batch_size: 500,
+            interval: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5 * 60),
+        }
+    }
+
+    /// Build a config from the environment, or `None` if `NEEMS_MQTT_URL` is
+    /// not set.
+    pub fn from_env() -> Option<Self> {
+        let url = env::var("NEEMS_MQTT_URL").ok().filter(|url| !url.trim().is_empty())?;
+        let var =
+            |key: &str| env::var(key).ok().and_then(|v| v.parse::<u64>().ok()).filter(|&v| v > 0);
+
+        let mut config = Self::new(url);
+        if let Ok(client_id) = env::var("NEEMS_MQTT_CLIENT_ID") {
+            config.client_id = client_id;
+        }
+        config.username = env::var("NEEMS_MQTT_USERNAME").ok();
+        config.password = env::var("NEEMS_MQTT_PASSWORD").ok();
+        config.ca_file = env::var("NEEMS_MQTT_CA_FILE").ok().map(PathBuf::from);
+        let topic = env::var("NEEMS_MQTT_TOPIC").ok();
+        if let Some(topic) = topic.filter(|topic| check_topic(topic).is_ok()) {
+            config.topic = topic;
+        }
+        if let Some(qos) = env::var("NEEMS_MQTT_QOS").ok().and_then(|qos| parse_qos(&qos).ok()) {
+            config.qos = qos;
+        }
+        if let Some(retain) = env::var("NEEMS_MQTT_RETAIN").ok().and_then(|v| parse_bool(&v)) {
+            config.retain = retain;
+        }
+        if let Ok(readings) = env::var("NEEMS_MQTT_READINGS") {
+            config.readings = parse_list(&readings);
+        }
+        if let Some(batch_size) = var("NEEMS_MQTT_BATCH_SIZE") {
+            config.batch_size = batch_size as usize;
+        }
+        if let Some(interval) = var("NEEMS_MQTT_INTERVAL_SECS") {
+            config.interval = Duration::from_secs(interval);
+        }
+        Some(config)
+    }
+
+    /// The publisher's key in `sync_cursors`.
+    pub fn target(&self) -> String {
+        format!("mqtt:{}", self.url)
+    }
+
+    /// Whether `reading`'s source has its readings published.
+    fn publishes_readings(&self, reading: &SyncReading) -> bool {
+        self.readings.iter().any(|name| {
+            name == "*"
+                || *name == reading.source_name
+                || reading.test_type.as_deref() == Some(name.as_str())
+        })
+    }
+
+    fn qos(&self) -> QoS {
+        match self.qos {
+            0 => QoS::AtMostOnce,
+            2 => QoS::ExactlyOnce,
+            _ => QoS::AtLeastOnce,
+        }
+    }
+
+    /// Broker connection options.
+    fn options(&self) -> DataResult<MqttOptions> {
+        let (tls, rest) = match self.url.split_once("://") {
+            Some(("mqtt" | "tcp", rest)) => (false, rest),
+            Some(("mqtts" | "ssl", rest)) => (true, rest),
+            _ => return Err(format!("'{}' is not an mqtt:// or mqtts:// URL", self.url).into()),
+        };
+        let authority = rest.trim_end_matches('/');
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse::<u16>().map_err(|_| format!("invalid port in '{}'", self.url))?,
+            ),
+            None => (authority, if tls { 8883 } else { 1883 }),
+        };
+        if host.is_empty() {
+            return Err(format!("no host in '{}'", self.url).into());
+        }
+
+        let mut options = MqttOptions::new(&self.client_id, host, port);
+        options.set_keep_alive(Duration::from_secs(30));
+        // Keep the session, so the broker holds unacknowledged messages
+        // across reconnects
+        options.set_clean_session(false);
+        if let Some(username) = &self.username {
+            options.set_credentials(username, self.password.clone().unwrap_or_default());
+        }
+        if tls {
+            let tls = match &self.ca_file {
+                Some(path) => TlsConfiguration::SimpleNative {
+                    ca: fs::read(path)
+                        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?,
+                    client_auth: None,
+                },
+                None => TlsConfiguration::Native,
+            };
+            options.set_transport(Transport::tls_with_config(tls));
+        }
+        Ok(options)
+    }
+}
+
+/// Check a topic template: it needs `{event}` so each kind of message gets
+/// its own topic, and may only use [`TOPIC_PLACEHOLDERS`].
+pub fn check_topic(template: &str) -> Result<(), String> {
+    if !template.contains("{event}") {
+        return Err(format!("'{}' must contain {{event}}", template));
+    }
+    if template.contains(['+', '#']) || template.starts_with('$') {
+        return Err(format!("'{}' can't contain wildcards or start with $", template));
+    }
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}').map(|end| start + end + 1).unwrap_or(rest.len());
+        let placeholder = &rest[start..end];
+        if !TOPIC_PLACEHOLDERS.contains(&placeholder) {
+            return Err(format!(
+                "unknown placeholder {} (use {})",
+                placeholder,
+                TOPIC_PLACEHOLDERS.join(", ")
+            ));
+        }
+        rest = &rest[end..];
+    }
+    Ok(())
+}
+
+pub fn parse_qos(value: &str) -> Result<u8, String> {
+    match value.trim() {
+        "0" => Ok(0),
+        "1" => Ok(1),
+        "2" => Ok(2),
+        other => Err(format!("QoS must be 0, 1 or 2, got '{}'", other)),
+    }
+}
+
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Split `name,name,...`, dropping empty entries.
+pub fn parse_list(spec: &str) -> Vec<String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// `template` filled in for `reading`'s source and `event`. Values that
+/// would add topic levels or wildcards have those characters replaced by `_`;
+/// a missing site, company or test type becomes `none`.
+pub fn topic_for(template: &str, reading: &SyncReading, event: &str) -> String {
+    let level = |value: &str| value.replace(['/', '+', '#'], "_");
+    let id = |id: Option<i32>| id.map_or_else(|| "none".to_string(), |id| id.to_string());
+    template
+        .replace("{company_id}", &id(reading.company_id))
+        .replace("{site_id}", &id(reading.site_id))
+        .replace("{source}", &level(&reading.source_name))
+        .replace("{test_type}", &level(reading.test_type.as_deref().unwrap_or("none")))
+        .replace("{event}", event)
+}
+
+/// A message ready to publish.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub topic: String,
+    pub payload: JsonValue,
+    pub retain: bool,
+}
+
+/// What's been published for a source.
+#[derive(Debug, Default)]
+struct SourceState {
+    state: Option<String>,
+    /// `None` until a reading with alarm registers has been seen
+    alarms: Option<AlarmFlags>,
+}
+
+/// The state and alarms last published per source, turning readings into
+/// messages for what changed.
+#[derive(Debug, Default)]
+pub struct Tracker {
+    sources: HashMap<String, SourceState>,
+}
+
+fn reading_state(data: &JsonValue) -> Option<String> {
+    STATE_FIELDS.iter().find_map(|field| match data.get(*field)? {
+        JsonValue::String(state) => Some(state.clone()),
+        JsonValue::Number(state) => Some(state.to_string()),
+        _ => None,
+    })
+}
+
+fn severity_name(severity: AlarmSeverity) -> &'static str {
+    match severity {
+        AlarmSeverity::Info => "info",
+        AlarmSeverity::Warning => "warning",
+        AlarmSeverity::Critical => "critical",
+        AlarmSeverity::Emergency => "emergency",
+    }
+}
+
+impl Tracker {
+    /// Take `reading` as already published, e.g. the last one before the
+    /// cursor.
+    pub fn seed(&mut self, reading: &SyncReading) {
+        let data: JsonValue = serde_json::from_str(&reading.data).unwrap_or_default();
+        let source = self.sources.entry(reading.source_name.clone()).or_default();
+        source.state = reading_state(&data).or(source.state.take());
+        source.alarms = AlarmFlags::from_reading(&data).or(source.alarms.take());
+    }
+
+    /// The messages `reading` calls for under `config`.
+    pub fn messages(&mut self, config: &MqttConfig, reading: &SyncReading) -> Vec<Message> {
+        let data: JsonValue = serde_json::from_str(&reading.data).unwrap_or_default();
+        let timestamp =
+            reading.device_timestamp.unwrap_or(reading.timestamp).and_utc().to_rfc3339();
+        let header = json!({
+            "source": reading.source_name,
+            "test_type": reading.test_type,
+            "site_id": reading.site_id,
+            "company_id": reading.company_id,
+            "reading_id": reading.origin_reading_id,
+            "timestamp": timestamp,
+        });
+        let with = |fields: JsonValue| {
+            let mut payload = header.clone();
+            if let (Some(payload), JsonValue::Object(fields)) = (payload.as_object_mut(), fields) {
+                payload.extend(fields);
+            }
+            payload
+        };
+
+        let mut messages = Vec::new();
+        let source = self.sources.entry(reading.source_name.clone()).or_default();
+        if let Some(state) = reading_state(&data) {
+            if source.state.as_ref() != Some(&state) {
+                messages.push(Message {
+                    topic: topic_for(&config.topic, reading, "state"),
+                    payload: with(json!({ "state": state, "previous": source.state })),
+                    retain: config.retain,
+                });
+                source.state = Some(state);
+            }
+        }
+        if let Some(alarms) = AlarmFlags::from_reading(&data) {
+            for (def, active) in alarms.changes_since(source.alarms.as_ref()) {
+                messages.push(Message {
+                    topic: topic_for(&config.topic, reading, &format!("alarm/{}", def.alarm_num)),
+                    payload: with(json!({
+                        "alarm_num": def.alarm_num,
+                        "name": def.name,
+                        "zone": def.zone.to_string(),
+                        "severity": severity_name(AlarmSeverity::from_level(def.level)),
+                        "active": active,
+                        "message": sld_meta_for(def.alarm_num).and_then(|meta| meta.message_opt()),
+                    })),
+                    retain: config.retain,
+                });
+            }
+            source.alarms = Some(alarms);
+        }
+        if config.publishes_readings(reading) {
+            messages.push(Message {
+                topic: topic_for(&config.topic, reading, "reading"),
+                payload: with(json!({ "quality_flags": reading.quality_flags, "data": data })),
+                retain: false,
+            });
+        }
+        messages
+    }
+}
+
+/// The latest reading of each source at or before `cursor`.
+pub fn readings_at(connection: &mut SqliteConnection, cursor: i32) -> DataResult<Vec<SyncReading>> {
+    use schema::readings;
+
+    let ids: Vec<Option<i32>> = readings::table
+        .filter(readings::id.le(cursor))
+        .group_by(readings::source_id)
+        .select(diesel::dsl::max(readings::id))
+        .load(connection)?;
+    let mut latest = Vec::new();
+    for id in ids.into_iter().flatten() {
+        latest.extend(pending_batch(connection, "", id - 1, 1)?.readings);
+    }
+    Ok(latest)
+}
+
+pub(crate) fn latest_reading_id(connection: &mut SqliteConnection) -> DataResult<i32> {
+    use schema::readings;
+
+    Ok(readings::table
+        .select(diesel::dsl::max(readings::id))
+        .first::<Option<i32>>(connection)?
+        .unwrap_or(0))
+}
+
+/// A connection to the broker. The event loop runs on its own task,
+/// reconnecting as needed, and counts the broker's acknowledgements.
+pub struct MqttPublisher {
+    config: MqttConfig,
+    client: AsyncClient,
+    connected: watch::Receiver<bool>,
+    acked: Arc<AtomicU64>,
+    sent: u64,
+}
+
+impl MqttPublisher {
+    pub fn connect(config: MqttConfig) -> DataResult<Self> {
+        let (client, mut event_loop) = AsyncClient::new(config.options()?, 64);
+        let (connected_tx, connected) = watch::channel(false);
+        let acked = Arc::new(AtomicU64::new(0));
+        let counter = acked.clone();
+        let url = config.url.clone();
+        tokio::spawn(async move {
+            loop {
+                match event_loop.poll().await {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        println!(
+                            "{} - Connected to MQTT broker {}",
+                            Local::now().to_rfc3339(),
+                            url
+                        );
+                        let _ = connected_tx.send(true);
+                    }
+                    Ok(Event::Incoming(Packet::PubAck(_) | Packet::PubComp(_))) => {
+                        counter.fetch_add(1, Ordering::SeqCst);
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        if *connected_tx.borrow() {
+                            eprintln!(
+                                "{} - Lost MQTT broker {}: {}",
+                                Local::now().to_rfc3339(),
+                                url,
+                                e
+                            );
+                        }
+                        let _ = connected_tx.send(false);
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                    }
+                }
+            }
+        });
+        Ok(Self {
+            config,
+            client,
+            connected,
+            acked,
+            sent: 0,
+        })
+    }
+
+    /// Publish `messages` and wait until the broker has them: acknowledged at
+    /// QoS 1 and 2, or handed over while connected at QoS 0.
+    pub async fn publish(&mut self, messages: Vec<Message>) -> DataResult<()> {
+        let qos = self.config.qos();
+        let work = async {
+            self.connected.wait_for(|connected| *connected).await?;
+            for message in messages {
+                let payload = serde_json::to_vec(&message.payload)?;
+                self.client.publish(message.topic, qos, message.retain, payload).await?;
+                if qos != QoS::AtMostOnce {
+                    self.sent += 1;
+                }
+            }
+            while self.acked.load(Ordering::SeqCst) < self.sent {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+        };
+        match tokio::time::timeout(ACK_TIMEOUT, work).await {
+            Ok(result) => result,
+            Err(_) => {
+                // Whatever is still outstanding is resent with the batch
+                self.sent = self.acked.load(Ordering::SeqCst);
+                Err(format!("{} didn't acknowledge within {:?}", self.config.url, ACK_TIMEOUT)
+                    .into())
+            }
+        }
+    }
+
+    /// Publish batches from `cursor` until caught up. Returns the new cursor
+    /// and the number of messages published.
+    pub async fn publish_from(
+        &mut self,
+        database_url: &str,
+        tracker: &mut Tracker,
+        mut cursor: i32,
+    ) -> DataResult<(i32, usize)> {
+        let target = self.config.target();
+        let mut published = 0;
+        loop {
+            let readings = {
+                let database_url = database_url.to_string();
+                let batch_size = self.config.batch_size;
+                tokio::task::spawn_blocking(move || {
+                    let mut connection = encryption::establish(&database_url)?;
+                    pending_batch(&mut connection, "", cursor, batch_size)
+                })
+                .await??
+                .readings
+            };
+            let Some(last_id) = readings.last().map(|reading| reading.origin_reading_id) else {
+                return Ok((cursor, published));
+            };
+
+            let messages: Vec<Message> = readings
+                .iter()
+                .flat_map(|reading| tracker.messages(&self.config, reading))
+                .collect();
+            published += messages.len();
+            if !messages.is_empty() {
+                self.publish(messages).await?;
+            }
+            cursor = last_id;
+
+            let database_url = database_url.to_string();
+            let target = target.clone();
+            tokio::task::spawn_blocking(move || -> DataResult<()> {
+                let mut connection = encryption::establish(&database_url)?;
+                save_cursor(&mut connection, &target, cursor, None)
+            })
+            .await??;
+        }
+    }
+}
+
+/// The stored cursor for `target`, or the latest reading for a new target,
+/// with the tracker seeded from the readings before it.
+fn start_cursor(database_url: &str, target: &str) -> DataResult<(i32, Tracker)> {
+    use schema::sync_cursors::dsl;
+
+    let mut connection = encryption::establish(database_url)?;
+    let stored = dsl::sync_cursors
+        .filter(dsl::target.eq(target))
+        .select(dsl::last_reading_id)
+        .first::<i32>(&mut connection)
+        .optional()?;
+    let mut tracker = Tracker::default();
+    let cursor = match stored {
+        Some(cursor) => {
+            for reading in readings_at(&mut connection, cursor)? {
+                tracker.seed(&reading);
+            }
+            cursor
+        }
+        None => {
+            let latest = latest_reading_id(&mut connection)?;
+            save_cursor(&mut connection, target, latest, None)?;
+            latest
+        }
+    };
+    Ok((cursor, tracker))
+}
+
+/// Publish forever, backing off exponentially while the broker is
+/// unreachable.
+pub async fn run_mqtt_loop(database_url: String, config: MqttConfig) {
+    let interval = config.interval;
+    let max_backoff = config.max_backoff;
+    let target = config.target();
+    let mut publisher = match MqttPublisher::connect(config) {
+        Ok(publisher) => publisher,
+        Err(e) => {
+            eprintln!("MQTT publishing disabled: {}", e);
+            return;
+        }
+    };
+
+    let mut position: Option<(i32, Tracker)> = None;
+    let mut backoff = interval;
+    loop {
+        let result = async {
+            let (cursor, mut tracker) = match position.take() {
+                Some(position) => position,
+                None => {
+                    let database_url = database_url.clone();
+                    let target = target.clone();
+                    tokio::task::spawn_blocking(move || start_cursor(&database_url, &target))
+                        .await??
+                }
+            };
+            let (cursor, published) =
+                publisher.publish_from(&database_url, &mut tracker, cursor).await?;
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>((cursor, tracker, published))
+        }
+        .await;
+
+        let wait = match result {
+            Ok((cursor, tracker, published)) => {
+                if published > 0 {
+                    tracing::debug!(target = %target, published, cursor, "Published MQTT messages");
+                }
+                position = Some((cursor, tracker));
+                backoff = interval;
+                interval
+            }
+            Err(e) => {
+                eprintln!("{} - Publishing to {} failed: {}", Local::now().to_rfc3339(), target, e);
+                let database_url = database_url.clone();
+                let target = target.clone();
+                let error = e.to_string();
+                let _ = tokio::task::spawn_blocking(move || -> DataResult<()> {
+                    let mut connection = encryption::establish(&database_url)?;
+                    let last = load_cursor(&mut connection, &target)?;
+                    save_cursor(&mut connection, &target, last, Some(&error))
+                })
+                .await;
+                let wait = backoff;
+                backoff = (backoff * 2).min(max_backoff);
+                wait
+            }
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(id: i32, data: JsonValue) -> SyncReading {
+        SyncReading {
+            origin_reading_id: id,
+            source_name: "rtac/main".to_string(),
+            test_type: Some("charging_state".to_string()),
+            site_id: Some(3),
+            company_id: None,
+            timestamp: chrono::DateTime::from_timestamp(1_800_000_000 + i64::from(id), 0)
+                .unwrap()
+                .naive_utc(),
+            device_timestamp: None,
+            data: data.to_string(),
+            quality_flags: 0,
+        }
+    }
+
+    fn registers(active: &[u16]) -> JsonValue {
+        let mut flags = AlarmFlags::default();
+        for alarm_num in active {
+            flags.set_alarm_num(*alarm_num, true);
+        }
+        json!(flags.to_registers())
+    }
+
+    #[test]
+    fn test_topic_templates() {
+        assert!(check_topic(DEFAULT_TOPIC).is_ok());
+        assert!(check_topic("scada/{company_id}/{test_type}/{event}").is_ok());
+        assert!(check_topic("neems/{site_id}/{source}").unwrap_err().contains("{event}"));
+        assert!(check_topic("neems/+/{event}").is_err());
+        assert!(check_topic("neems/{site}/{event}").unwrap_err().contains("{site}"));
+
+        let reading = reading(1, json!({}));
+        assert_eq!(topic_for(DEFAULT_TOPIC, &reading, "state"), "neems/3/rtac_main/state");
+        assert_eq!(
+            topic_for("x/{company_id}/{test_type}/{event}", &reading, "alarm/1"),
+            "x/none/charging_state/alarm/1"
+        );
+    }
+
+    #[test]
+    fn test_tracker_publishes_changes() {
+        let mut config = MqttConfig::new("mqtt://broker:1883");
+        let mut tracker = Tracker::default();
+        let topics = |messages: Vec<Message>| -> Vec<(String, JsonValue)> {
+            messages.into_iter().map(|message| (message.topic, message.payload)).collect()
+        };
+
+        // A new source reports its state and only its active alarms
+        let first = topics(tracker.messages(
+            &config,
+            &reading(1, json!({ "mode": "Standby", "alarm_registers": registers(&[1]) })),
+        ));
+        assert_eq!(first.len(), 2, "{:?}", first);
+        assert_eq!(first[0].0, "neems/3/rtac_main/state");
+        assert_eq!(first[0].1["state"], json!("Standby"));
+        assert_eq!(first[0].1["previous"], JsonValue::Null);
+        assert_eq!(first[1].0, "neems/3/rtac_main/alarm/1");
+        assert_eq!(first[1].1["name"], json!("loss_fiber"));
+        assert_eq!(first[1].1["severity"], json!("warning"));
+        assert_eq!(first[1].1["active"], json!(true));
+
+        // Nothing changed
+        let same = json!({ "mode": "Standby", "alarm_registers": registers(&[1]) });
+        assert!(tracker.messages(&config, &reading(2, same)).is_empty());
+
+        // A transition, one alarm cleared and another raised
+        let changed = topics(tracker.messages(
+            &config,
+            &reading(3, json!({ "mode": "Charging", "alarm_registers": registers(&[2]) })),
+        ));
+        assert_eq!(changed.len(), 3, "{:?}", changed);
+        assert_eq!(changed[0].1["previous"], json!("Standby"));
+        assert_eq!(changed[1].0, "neems/3/rtac_main/alarm/1");
+        assert_eq!(changed[1].1["active"], json!(false));
+        assert_eq!(changed[2].0, "neems/3/rtac_main/alarm/2");
+        assert_eq!(changed[2].1["reading_id"], json!(3));
+
+        // Selected readings are published whole, without retain
+        config.readings = vec!["charging_state".to_string()];
+        let messages = tracker.messages(&config, &reading(4, json!({ "mode": "Charging" })));
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].topic, "neems/3/rtac_main/reading");
+        assert_eq!(messages[0].payload["data"]["mode"], json!("Charging"));
+        assert!(!messages[0].retain);
+
+        // A tracker seeded from the last published reading carries on from it
+        let mut seeded = Tracker::default();
+        seeded.seed(&reading(3, json!({ "mode": "Charging", "alarm_registers": registers(&[2]) })));
+        let after = seeded.messages(
+            &MqttConfig::new("mqtt://broker:1883"),
+            &reading(5, json!({ "mode": "Charging", "alarm_registers": registers(&[]) })),
+        );
+        assert_eq!(after.len(), 1);
+        assert_eq!(after[0].payload["active"], json!(false));
+    }
+}
@@ -0,0 +1,1113 @@
+//! Alarm notifications to Slack, Microsoft Teams and PagerDuty.
+//!
+//! Each company can have notification channels, stored in the
+//! `notification_channels` table and managed through neems-api. A channel
+//! receives the alarms raised and cleared on the company's sources (decoded
+//! from readings' `alarm_registers`, as for [`crate::mqtt`]) whose severity
+//! is one it routes, so e.g. critical alarms page the on-call through
+//! PagerDuty while informational ones go to a chat channel. `monitor` checks
+//! the table every [`CHANNELS_POLL_INTERVAL`] and starts, restarts or stops
+//! a task per enabled channel as they change.
+//!
+//! Like [`crate::forward`], each channel follows the readings table with a
+//! cursor in `sync_cursors` (under the target `notification:<id>`) and backs
+//! off while its service is unreachable, so alarms raised during an outage
+//! are sent in order once it comes back. A new channel starts from the
+//! latest reading, announcing each source's active alarms with its next
+//! reading. PagerDuty incidents are keyed by company, source and alarm, so
+//! a cleared alarm resolves the incident its raise opened.
+//!
+//! Alarms raised or cleared at a site while a site-wide maintenance window is
+//! open (see [`crate::maintenance_windows`]) are not sent.
+//!
+//! Each channel has a locale, and its notifications are written in that
+//! language (see [`crate::i18n`]).
+
+use std::{collections::HashMap, str::FromStr, time::Duration};
+
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value as JsonValue, json};
+use ts_rs::TS;
+
+use crate::{
+    DataResult, encryption,
+    i18n::{Locale, fill, tr},
+    maintenance_windows::{site_under_maintenance, site_windows_ending_after},
+    mqtt::{latest_reading_id, readings_at},
+    prometheus::Delivery,
+    rtac::{AlarmFlags, AlarmSeverity, sld_meta_for},
+    schema,
+    secrets::{self, MasterKey},
+    sync::{SyncReading, load_cursor, pending_batch, save_cursor},
+};
+
+/// How often `monitor` checks the `notification_channels` table for changes.
+pub const CHANNELS_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// PagerDuty Events API v2 endpoint.
+pub const PAGERDUTY_EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+
+const BATCH_SIZE: usize = 500;
+const MAX_BACKOFF: Duration = Duration::from_secs(15 * 60);
+
+/// Where a channel sends notifications.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum ChannelKind {
+    /// A Slack incoming webhook
+    Slack,
+    /// A Microsoft Teams incoming webhook or Workflows webhook
+    Teams,
+    /// A PagerDuty service, through the Events API v2
+    Pagerduty,
+}
+
+impl ChannelKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Slack => "slack",
+            Self::Teams => "teams",
+            Self::Pagerduty => "pagerduty",
+        }
+    }
+}
+
+impl FromStr for ChannelKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "slack" => Ok(Self::Slack),
+            "teams" => Ok(Self::Teams),
+            "pagerduty" => Ok(Self::Pagerduty),
+            _ => Err(format!("Unknown channel kind: {}", s)),
+        }
+    }
+}
+
+/// An alarm severity a channel can route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum NotificationSeverity {
+    Info,
+    Warning,
+    Critical,
+    Emergency,
+}
+
+impl NotificationSeverity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Critical => "critical",
+            Self::Emergency => "emergency",
+        }
+    }
+}
+
+impl From<AlarmSeverity> for NotificationSeverity {
+    fn from(severity: AlarmSeverity) -> Self {
+        match severity {
+            AlarmSeverity::Info => Self::Info,
+            AlarmSeverity::Warning => Self::Warning,
+            AlarmSeverity::Critical => Self::Critical,
+            AlarmSeverity::Emergency => Self::Emergency,
+        }
+    }
+}
+
+/// A stored notification channel.
+#[derive(Debug, Clone, PartialEq, Queryable, Selectable)]
+#[diesel(table_name = schema::notification_channels)]
+pub struct NotificationChannel {
+    pub id: i32,
+    pub company_id: i32,
+    pub name: String,
+    pub kind: String,
+    pub destination: String,
+    /// JSON array of [`NotificationSeverity`] names
+    pub severities: String,
+    pub send_cleared: bool,
+    pub enabled: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    /// The [`Locale`] notifications are written in
+    pub locale: String,
+}
+
+impl NotificationChannel {
+    /// The channel's key in `sync_cursors`.
+    pub fn target(&self) -> String {
+        format!("notification:{}", self.id)
+    }
+
+    pub fn severities(&self) -> Vec<NotificationSeverity> {
+        serde_json::from_str(&self.severities).unwrap_or_default()
+    }
+
+    pub fn locale(&self) -> Locale {
+        self.locale.parse().unwrap_or_default()
+    }
+
+    /// Whether `event` is sent to this channel.
+    pub fn routes(&self, event: &AlarmEvent) -> bool {
+        Some(self.company_id) == event.company_id
+            && (event.active || self.send_cleared)
+            && self.severities().contains(&event.severity)
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// A channel's settings, as created or replaced through the API.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ChannelSettings {
+    pub name: String,
+    pub kind: ChannelKind,
+    /// The Slack or Teams webhook URL, or the PagerDuty integration's routing
+    /// key, or a `secret://<name>` reference. Required when creating a
+    /// channel; when replacing one, `null` or `""` keeps the stored one.
+    #[serde(default)]
+    pub destination: Option<String>,
+    /// Alarm severities sent to the channel
+    pub severities: Vec<NotificationSeverity>,
+    /// Whether cleared alarms are sent too (resolving PagerDuty incidents)
+    #[serde(default = "default_true")]
+    pub send_cleared: bool,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// The language notifications are written in. When creating a channel,
+    /// `null` means English, or the company's locale if neems-api has one;
+    /// when replacing one, `null` keeps the stored one.
+    #[serde(default)]
+    pub locale: Option<Locale>,
+}
+
+impl ChannelSettings {
+    /// Every problem with these settings, or `Ok` if there are none.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if self.name.trim().is_empty() {
+            problems.push("name: must not be empty".to_string());
+        }
+        if self.severities.is_empty() {
+            problems.push("severities: route at least one severity".to_string());
+        }
+        if let Some(destination) = self.destination.as_deref().filter(|d| !d.is_empty()) {
+            match secrets::reference_name(destination) {
+                Some(name) if !secrets::is_valid_name(name) => {
+                    problems.push(format!("destination: invalid secret name: {:?}", name))
+                }
+                Some(_) => {}
+                None => {
+                    if let Err(e) = check_destination(self.kind, destination) {
+                        problems.push(format!("destination: {}", e));
+                    }
+                }
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+/// Check a webhook URL or routing key for `kind`.
+pub fn check_destination(kind: ChannelKind, destination: &str) -> Result<(), String> {
+    match kind {
+        ChannelKind::Slack | ChannelKind::Teams => {
+            let url = reqwest::Url::parse(destination)
+                .map_err(|_| "must be the webhook URL".to_string())?;
+            if url.scheme() != "https" {
+                return Err("the webhook URL must use https".to_string());
+            }
+        }
+        ChannelKind::Pagerduty => {
+            if destination.len() != 32 || !destination.chars().all(|c| c.is_ascii_alphanumeric()) {
+                return Err("must be the 32-character integration (routing) key".to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A channel as shown by the API: its settings with the destination
+/// redacted, and how far it has got.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ChannelInfo {
+    pub id: i32,
+    pub company_id: i32,
+    pub name: String,
+    pub kind: ChannelKind,
+    /// `***`, or the `secret://` reference
+    pub destination: String,
+    pub severities: Vec<NotificationSeverity>,
+    pub send_cleared: bool,
+    pub enabled: bool,
+    pub locale: Locale,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    /// Highest reading id checked for alarms
+    pub last_reading_id: i32,
+    pub last_checked_at: Option<NaiveDateTime>,
+    /// Why the last attempt failed, until one succeeds
+    pub last_error: Option<String>,
+}
+
+/// Channels of `company_id`, or of every company.
+pub fn list_channels(
+    connection: &mut SqliteConnection,
+    company_id: Option<i32>,
+) -> DataResult<Vec<NotificationChannel>> {
+    use schema::notification_channels::dsl;
+
+    let mut query =
+        dsl::notification_channels.select(NotificationChannel::as_select()).into_boxed();
+    if let Some(company_id) = company_id {
+        query = query.filter(dsl::company_id.eq(company_id));
+    }
+    Ok(query.order((dsl::company_id.asc(), dsl::name.asc())).load(connection)?)
+}
+
+pub fn get_channel(
+    connection: &mut SqliteConnection,
+    id: i32,
+) -> DataResult<Option<NotificationChannel>> {
+    use schema::notification_channels::dsl;
+
+    Ok(dsl::notification_channels
+        .find(id)
+        .select(NotificationChannel::as_select())
+        .first(connection)
+        .optional()?)
+}
+
+fn invalid(problems: Vec<String>) -> Box<dyn std::error::Error + Send + Sync> {
+    problems.join("; ").into()
+}
+
+fn severities_json(settings: &ChannelSettings) -> DataResult<String> {
+    let mut severities = settings.severities.clone();
+    severities.sort();
+    severities.dedup();
+    Ok(serde_json::to_string(&severities)?)
+}
+
+/// Validate and store a new channel for `company_id`, which needs a
+/// destination.
+pub fn create_channel(
+    connection: &mut SqliteConnection,
+    company_id: i32,
+    settings: &ChannelSettings,
+) -> DataResult<NotificationChannel> {
+    use schema::notification_channels::dsl;
+
+    settings.validate().map_err(invalid)?;
+    let destination = settings
+        .destination
+        .as_deref()
+        .filter(|destination| !destination.is_empty())
+        .ok_or_else(|| invalid(vec!["destination: required".to_string()]))?;
+    let now = Utc::now().naive_utc();
+    diesel::insert_into(dsl::notification_channels)
+        .values((
+            dsl::company_id.eq(company_id),
+            dsl::name.eq(settings.name.trim()),
+            dsl::kind.eq(settings.kind.as_str()),
+            dsl::destination.eq(destination),
+            dsl::severities.eq(severities_json(settings)?),
+            dsl::send_cleared.eq(settings.send_cleared),
+            dsl::enabled.eq(settings.enabled),
+            dsl::locale.eq(settings.locale.unwrap_or_default().as_str()),
+            dsl::created_at.eq(now),
+            dsl::updated_at.eq(now),
+        ))
+        .execute(connection)?;
+    Ok(dsl::notification_channels
+        .filter(dsl::company_id.eq(company_id))
+        .filter(dsl::name.eq(settings.name.trim()))
+        .select(NotificationChannel::as_select())
+        .first(connection)?)
+}
+
+/// Validate and replace a channel's settings. A `None` destination keeps the
+/// stored one. Returns `None` if there's no such channel.
+pub fn update_channel(
+    connection: &mut SqliteConnection,
+    id: i32,
+    settings: &ChannelSettings,
+) -> DataResult<Option<NotificationChannel>> {
+    use schema::notification_channels::dsl;
+
+    settings.validate().map_err(invalid)?;
+    let Some(existing) = get_channel(connection, id)? else {
+        return Ok(None);
+    };
+    let locale = settings.locale.unwrap_or_else(|| existing.locale());
+    let destination = match settings.destination.as_deref() {
+        Some("") | None => existing.destination,
+        Some(destination) => destination.to_string(),
+    };
+    if existing.kind != settings.kind.as_str()
+        && secrets::reference_name(&destination).is_none()
+        && settings.destination.as_deref().is_none_or(str::is_empty)
+    {
+        // The stored destination was for the old kind
+        check_destination(settings.kind, &destination)
+            .map_err(|e| invalid(vec![format!("destination: {}", e)]))?;
+    }
+    diesel::update(dsl::notification_channels.find(id))
+        .set((
+            dsl::name.eq(settings.name.trim()),
+            dsl::kind.eq(settings.kind.as_str()),
+            dsl::destination.eq(destination),
+            dsl::severities.eq(severities_json(settings)?),
+            dsl::send_cleared.eq(settings.send_cleared),
+            dsl::enabled.eq(settings.enabled),
+            dsl::locale.eq(locale.as_str()),
+            dsl::updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(connection)?;
+    get_channel(connection, id)
+}
+
+/// Delete a channel and its cursor. Returns whether it existed.
+pub fn delete_channel(connection: &mut SqliteConnection, id: i32) -> DataResult<bool> {
+    use schema::{notification_channels, sync_cursors};
+
+    let target = format!("notification:{}", id);
+    let deleted = connection.transaction::<_, diesel::result::Error, _>(|conn| {
+        diesel::delete(sync_cursors::table.filter(sync_cursors::target.eq(&target)))
+            .execute(conn)?;
+        diesel::delete(notification_channels::table.find(id)).execute(conn)
+    })?;
+    Ok(deleted > 0)
+}
+
+/// `channel` as shown by the API.
+pub fn channel_info(
+    connection: &mut SqliteConnection,
+    channel: &NotificationChannel,
+) -> DataResult<ChannelInfo> {
+    use schema::sync_cursors;
+
+    let cursor = sync_cursors::table
+        .filter(sync_cursors::target.eq(channel.target()))
+        .select((
+            sync_cursors::last_reading_id,
+            sync_cursors::last_synced_at,
+            sync_cursors::last_error,
+        ))
+        .first::<(i32, Option<NaiveDateTime>, Option<String>)>(connection)
+        .optional()?;
+    let (last_reading_id, last_checked_at, last_error) = cursor.unwrap_or((0, None, None));
+
+    let destination = match secrets::reference_name(&channel.destination) {
+        Some(_) => channel.destination.clone(),
+        None => secrets::REDACTED.to_string(),
+    };
+    Ok(ChannelInfo {
+        id: channel.id,
+        company_id: channel.company_id,
+        name: channel.name.clone(),
+        kind: channel.kind.parse()?,
+        destination,
+        severities: channel.severities(),
+        send_cleared: channel.send_cleared,
+        enabled: channel.enabled,
+        locale: channel.locale(),
+        created_at: channel.created_at,
+        updated_at: channel.updated_at,
+        last_reading_id,
+        last_checked_at,
+        last_error,
+    })
+}
+
+/// An alarm raised or cleared on a source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlarmEvent {
+    pub source: String,
+    pub site_id: Option<i32>,
+    pub company_id: Option<i32>,
+    pub alarm_num: u16,
+    pub name: &'static str,
+    pub zone: String,
+    pub severity: NotificationSeverity,
+    pub active: bool,
+    pub message: Option<String>,
+    /// The device timestamp where there is one
+    pub timestamp: DateTime<Utc>,
+}
+
+impl AlarmEvent {
+    /// PagerDuty's key for the incident, shared by the raise and the clear.
+    pub fn dedup_key(&self) -> String {
+        format!("neems:{}:{}:{}", self.company_id.unwrap_or(0), self.source, self.alarm_num)
+    }
+
+    /// A one-line description in `locale`, e.g. `Critical alarm raised:
+    /// estop (breaker_relay) on rtac at site 3`.
+    pub fn summary(&self, locale: Locale) -> String {
+        let severity = match self.severity {
+            NotificationSeverity::Info => "Info",
+            NotificationSeverity::Warning => "Warning",
+            NotificationSeverity::Critical => "Critical",
+            NotificationSeverity::Emergency => "Emergency",
+        };
+        let template = if self.active {
+            "{severity} alarm raised: {name} ({zone}) on {source}"
+        } else {
+            "{severity} alarm cleared: {name} ({zone}) on {source}"
+        };
+        let mut summary = fill(
+            tr(locale, template),
+            &[
+                ("severity", tr(locale, severity)),
+                ("name", self.name),
+                ("zone", &self.zone),
+                ("source", &self.source),
+            ],
+        );
+        if let Some(site_id) = self.site_id {
+            summary
+                .push_str(&fill(tr(locale, " at site {site}"), &[("site", &site_id.to_string())]));
+        }
+        summary
+    }
+}
+
+/// The alarms announced per source, turning readings into [`AlarmEvent`]s
+/// for what changed.
+#[derive(Debug, Default)]
+pub struct Tracker {
+    alarms: HashMap<String, AlarmFlags>,
+}
+
+impl Tracker {
+    /// Take `reading`'s alarms as already announced, e.g. the last reading
+    /// before the cursor.
+    pub fn seed(&mut self, reading: &SyncReading) {
+        let data: JsonValue = serde_json::from_str(&reading.data).unwrap_or_default();
+        if let Some(alarms) = AlarmFlags::from_reading(&data) {
+            self.alarms.insert(reading.source_name.clone(), alarms);
+        }
+    }
+
+    /// The alarms `reading` raises or clears.
+    pub fn events(&mut self, reading: &SyncReading) -> Vec<AlarmEvent> {
+        let data: JsonValue = serde_json::from_str(&reading.data).unwrap_or_default();
+        let Some(alarms) = AlarmFlags::from_reading(&data) else {
+            return Vec::new();
+        };
+        let last = self.alarms.insert(reading.source_name.clone(), alarms);
+        alarms
+            .changes_since(last.as_ref())
+            .into_iter()
+            .map(|(def, active)| AlarmEvent {
+                source: reading.source_name.clone(),
+                site_id: reading.site_id,
+                company_id: reading.company_id,
+                alarm_num: def.alarm_num,
+                name: def.name,
+                zone: def.zone.to_string(),
+                severity: AlarmSeverity::from_level(def.level).into(),
+                active,
+                message: sld_meta_for(def.alarm_num)
+                    .and_then(|meta| meta.message_opt())
+                    .map(str::to_string),
+                timestamp: reading.device_timestamp.unwrap_or(reading.timestamp).and_utc(),
+            })
+            .collect()
+    }
+}
+
+/// A Slack incoming-webhook message for `event`, in `locale`.
+pub fn slack_payload(event: &AlarmEvent, locale: Locale) -> JsonValue {
+    let icon = match (event.active, event.severity) {
+        (false, _) => ":white_check_mark:",
+        (true, NotificationSeverity::Emergency | NotificationSeverity::Critical) => {
+            ":rotating_light:"
+        }
+        (true, NotificationSeverity::Warning) => ":warning:",
+        (true, NotificationSeverity::Info) => ":information_source:",
+    };
+    let mut text = format!("{} *{}*", icon, event.summary(locale));
+    if let Some(message) = &event.message {
+        text.push_str(&format!("\n{}", message));
+    }
+    let alarm = fill(
+        tr(locale, "Alarm {alarm} at {time}"),
+        &[("alarm", &event.alarm_num.to_string()), ("time", &event.timestamp.to_rfc3339())],
+    );
+    text.push_str(&format!("\n{}", alarm));
+    json!({ "text": text })
+}
+
+/// A Microsoft Teams webhook message for `event`, as an Adaptive Card, in
+/// `locale`.
+pub fn teams_payload(event: &AlarmEvent, locale: Locale) -> JsonValue {
+    let color = match (event.active, event.severity) {
+        (false, _) => "Good",
+        (true, NotificationSeverity::Emergency | NotificationSeverity::Critical) => "Attention",
+        (true, NotificationSeverity::Warning) => "Warning",
+        (true, NotificationSeverity::Info) => "Default",
+    };
+    let mut facts = vec![
+        json!({ "title": tr(locale, "Source"), "value": event.source }),
+        json!({ "title": tr(locale, "Alarm"), "value": event.alarm_num.to_string() }),
+        json!({ "title": tr(locale, "Zone"), "value": event.zone }),
+        json!({ "title": tr(locale, "Time"), "value": event.timestamp.to_rfc3339() }),
+    ];
+    if let Some(site_id) = event.site_id {
+        facts.insert(0, json!({ "title": tr(locale, "Site"), "value": site_id.to_string() }));
+    }
+    let mut body = vec![json!({
+        "type": "TextBlock",
+        "text": event.summary(locale),
+        "weight": "Bolder",
+        "color": color,
+        "wrap": true,
+    })];
+    if let Some(message) = &event.message {
+        body.push(json!({ "type": "TextBlock", "text": message, "wrap": true }));
+    }
+    body.push(json!({ "type": "FactSet", "facts": facts }));
+    json!({
+        "type": "message",
+        "attachments": [{
+            "contentType": "application/vnd.microsoft.card.adaptive",
+            "content": {
+                "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                "type": "AdaptiveCard",
+                "version": "1.4",
+                "body": body,
+            },
+        }],
+    })
+}
+
+/// A PagerDuty Events API v2 event for `event`: a trigger for a raised
+/// alarm, a resolve for a cleared one. The summary is in `locale`.
+pub fn pagerduty_payload(routing_key: &str, event: &AlarmEvent, locale: Locale) -> JsonValue {
+    if !event.active {
+        return json!({
+            "routing_key": routing_key,
+            "event_action": "resolve",
+            "dedup_key": event.dedup_key(),
+        });
+    }
+    let severity = match event.severity {
+        NotificationSeverity::Emergency => "critical",
+        NotificationSeverity::Critical => "error",
+        NotificationSeverity::Warning => "warning",
+        NotificationSeverity::Info => "info",
+    };
+    json!({
+        "routing_key": routing_key,
+        "event_action": "trigger",
+        "dedup_key": event.dedup_key(),
+        "payload": {
+            "summary": event.summary(locale),
+            "source": event.source,
+            "severity": severity,
+            "timestamp": event.timestamp.to_rfc3339(),
+            "component": event.zone,
+            "group": event.site_id.map(|site_id| format!("site {}", site_id)),
+            "class": event.name,
+            "custom_details": {
+                "alarm_num": event.alarm_num,
+                "neems_severity": event.severity.as_str(),
+                "message": event.message,
+                "site_id": event.site_id,
+                "company_id": event.company_id,
+            },
+        },
+    })
+}
+
+/// Sends a channel's notifications.
+struct Notifier {
+    http: reqwest::Client,
+    kind: ChannelKind,
+    destination: String,
+    locale: Locale,
+}
+
+impl Notifier {
+    fn new(channel: &NotificationChannel, destination: String) -> DataResult<Self> {
+        Ok(Self {
+            http: reqwest::Client::builder().timeout(Duration::from_secs(30)).build()?,
+            kind: channel.kind.parse()?,
+            destination,
+            locale: channel.locale(),
+        })
+    }
+
+    /// Send one notification. Network errors, 5xx and 429 responses are
+    /// errors, to be retried; other 4xx responses are [`Delivery::Rejected`].
+    async fn send(&self, event: &AlarmEvent) -> DataResult<Delivery> {
+        let (url, body) = match self.kind {
+            ChannelKind::Slack => (self.destination.as_str(), slack_payload(event, self.locale)),
+            ChannelKind::Teams => (self.destination.as_str(), teams_payload(event, self.locale)),
+            ChannelKind::Pagerduty => {
+                (PAGERDUTY_EVENTS_URL, pagerduty_payload(&self.destination, event, self.locale))
+            }
+        };
+        let response = self
+            .http
+            .post(url)
+            .header(reqwest::header::USER_AGENT, concat!("neems-data/", env!("CARGO_PKG_VERSION")))
+            .json(&body)
+            .send()
+            .await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(Delivery::Accepted);
+        }
+        let message = response.text().await.unwrap_or_default();
+        let message = format!("HTTP {}: {}", status, message.trim());
+        if status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+            Ok(Delivery::Rejected(message))
+        } else {
+            Err(format!("{} notification failed with {}", self.kind.as_str(), message).into())
+        }
+    }
+}
+
+/// The channel's destination, with a `secret://` reference resolved.
+pub fn resolve_destination(
+    connection: &mut SqliteConnection,
+    channel: &NotificationChannel,
+) -> DataResult<String> {
+    let Some(name) = secrets::reference_name(&channel.destination) else {
+        return Ok(channel.destination.clone());
+    };
+    let key = MasterKey::from_env()?.ok_or_else(|| {
+        format!("destination refers to secret '{}' but {} is not set", name, secrets::KEY_ENV)
+    })?;
+    secrets::get_secret(connection, key, name)?.ok_or_else(|| {
+        format!("destination refers to secret '{}', which does not exist", name).into()
+    })
+}
+
+/// Send one `event` through `channel` to `destination` (as resolved by
+/// [`resolve_destination`]) outside the channel's own task, e.g. to escalate
+/// an alarm nobody acknowledged.
+pub async fn send_event(
+    channel: &NotificationChannel,
+    destination: String,
+    event: &AlarmEvent,
+) -> DataResult<Delivery> {
+    Notifier::new(channel, destination)?.send(event).await
+}
+
+/// The stored cursor for `channel`, or the latest reading for a new one,
+/// with the tracker seeded from the readings before it.
+fn start_cursor(
+    connection: &mut SqliteConnection,
+    channel: &NotificationChannel,
+) -> DataResult<(i32, Tracker)> {
+    use schema::sync_cursors::dsl;
+
+    let target = channel.target();
+    let stored = dsl::sync_cursors
+        .filter(dsl::target.eq(&target))
+        .select(dsl::last_reading_id)
+        .first::<i32>(connection)
+        .optional()?;
+    let mut tracker = Tracker::default();
+    let cursor = match stored {
+        Some(cursor) => {
+            for reading in readings_at(connection, cursor)? {
+                tracker.seed(&reading);
+            }
+            cursor
+        }
+        None => {
+            let latest = latest_reading_id(connection)?;
+            save_cursor(connection, &target, latest, None)?;
+            latest
+        }
+    };
+    Ok((cursor, tracker))
+}
+
+/// Send `channel`'s notifications for readings from `cursor` until caught
+/// up. Returns the new cursor and the number of notifications sent. On
+/// failure the cursor is saved after the last reading fully handled, so
+/// little is sent twice.
+async fn notify_from(
+    database_url: &str,
+    channel: &NotificationChannel,
+    notifier: &Notifier,
+    tracker: &mut Tracker,
+    cursor: &mut i32,
+) -> DataResult<usize> {
+    let target = channel.target();
+    let save = |cursor: i32, error: Option<String>| {
+        let database_url = database_url.to_string();
+        let target = target.clone();
+        tokio::task::spawn_blocking(move || -> DataResult<()> {
+            let mut connection = encryption::establish(&database_url)?;
+            save_cursor(&mut connection, &target, cursor, error.as_deref())
+        })
+    };
+
+    let mut sent = 0;
+    loop {
+        let (readings, maintenance) = {
+            let database_url = database_url.to_string();
+            let after = *cursor;
+            tokio::task::spawn_blocking(move || -> DataResult<_> {
+                let mut connection = encryption::establish(&database_url)?;
+                let readings = pending_batch(&mut connection, "", after, BATCH_SIZE)?.readings;
+                let earliest = readings.iter().map(|reading| reading.timestamp).min();
+                let maintenance = match earliest {
+                    Some(earliest) => site_windows_ending_after(&mut connection, earliest)?,
+                    None => Vec::new(),
+                };
+                Ok((readings, maintenance))
+            })
+            .await??
+        };
+        let Some(last_id) = readings.last().map(|reading| reading.origin_reading_id) else {
+            return Ok(sent);
+        };
+
+        let mut rejected = None;
+        for reading in
+            readings.iter().filter(|reading| reading.company_id == Some(channel.company_id))
+        {
+            for event in tracker.events(reading) {
+                if !channel.routes(&event)
+                    || site_under_maintenance(&maintenance, event.site_id, reading.timestamp)
+                {
+                    continue;
+                }
+                let delivery = match notifier.send(&event).await {
+                    Ok(delivery) => delivery,
+                    Err(e) => {
+                        save(*cursor, None).await??;
+                        return Err(e);
+                    }
+                };
+                match delivery {
+                    Delivery::Accepted => sent += 1,
+                    Delivery::Rejected(message) => {
+                        eprintln!(
+                            "{} - Channel '{}' skipped alarm {} on {}, which {} rejected: {}",
+                            Local::now().to_rfc3339(),
+                            channel.name,
+                            event.alarm_num,
+                            event.source,
+                            channel.kind,
+                            message
+                        );
+                        rejected = Some(message);
+                    }
+                }
+            }
+            *cursor = reading.origin_reading_id;
+        }
+        *cursor = last_id;
+        save(*cursor, rejected).await??;
+    }
+}
+
+/// Send one channel's notifications forever, backing off exponentially while
+/// its service is unreachable.
+pub async fn run_channel(database_url: String, channel: NotificationChannel) {
+    let target = channel.target();
+    let mut state: Option<(Notifier, Tracker, i32)> = None;
+    let mut backoff = CHANNELS_POLL_INTERVAL;
+    loop {
+        let result = async {
+            if state.is_none() {
+                let database_url = database_url.clone();
+                let stored = channel.clone();
+                let (destination, (cursor, tracker)) =
+                    tokio::task::spawn_blocking(move || -> DataResult<_> {
+                        let mut connection = encryption::establish(&database_url)?;
+                        let destination = resolve_destination(&mut connection, &stored)?;
+                        Ok((destination, start_cursor(&mut connection, &stored)?))
+                    })
+                    .await??;
+                state = Some((Notifier::new(&channel, destination)?, tracker, cursor));
+            }
+            let (notifier, tracker, cursor) = state.as_mut().expect("created above");
+            notify_from(&database_url, &channel, notifier, tracker, cursor).await
+        }
+        .await;
+
+        let wait = match result {
+            Ok(sent) => {
+                if sent > 0 {
+                    println!(
+                        "{} - Sent {} alarm notifications to {} '{}'",
+                        Local::now().to_rfc3339(),
+                        sent,
+                        channel.kind,
+                        channel.name
+                    );
+                }
+                backoff = CHANNELS_POLL_INTERVAL;
+                CHANNELS_POLL_INTERVAL
+            }
+            Err(e) => {
+                eprintln!(
+                    "{} - Notification channel '{}' failed: {}",
+                    Local::now().to_rfc3339(),
+                    channel.name,
+                    e
+                );
+                let database_url = database_url.clone();
+                let target = target.clone();
+                let error = e.to_string();
+                let _ = tokio::task::spawn_blocking(move || -> DataResult<()> {
+                    let mut connection = encryption::establish(&database_url)?;
+                    let last = load_cursor(&mut connection, &target)?;
+                    save_cursor(&mut connection, &target, last, Some(&error))
+                })
+                .await;
+                // Start again from the saved cursor
+                state = None;
+                let wait = backoff;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                wait
+            }
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Keep a [`run_channel`] task running for every enabled channel, restarting
+/// one when its settings change and stopping it when it's disabled or
+/// deleted. Runs until the process exits.
+pub async fn run_channels(database_url: String) {
+    let mut running: HashMap<i32, (NotificationChannel, tokio::task::JoinHandle<()>)> =
+        HashMap::new();
+    let mut interval = tokio::time::interval(CHANNELS_POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        let loaded = {
+            let database_url = database_url.clone();
+            tokio::task::spawn_blocking(move || {
+                let mut connection = encryption::establish(&database_url)?;
+                list_channels(&mut connection, None)
+            })
+            .await
+        };
+        let channels = match loaded {
+            Ok(Ok(channels)) => channels,
+            Ok(Err(e)) => {
+                eprintln!("Error loading notification channels: {}", e);
+                continue;
+            }
+            Err(e) => {
+                eprintln!("Notification channel loading task failed: {}", e);
+                continue;
+            }
+        };
+
+        let mut wanted: HashMap<i32, NotificationChannel> = channels
+            .into_iter()
+            .filter(|channel| channel.enabled)
+            .map(|channel| (channel.id, channel))
+            .collect();
+        running.retain(|id, (channel, handle)| {
+            if wanted.get(id) == Some(&*channel) {
+                return true;
+            }
+            handle.abort();
+            println!("Stopped notification channel '{}'", channel.name);
+            false
+        });
+        for (id, channel) in wanted.drain() {
+            if running.contains_key(&id) {
+                continue;
+            }
+            println!(
+                "Notifying company {} of {} alarms via {} '{}'",
+                channel.company_id,
+                channel
+                    .severities()
+                    .iter()
+                    .map(NotificationSeverity::as_str)
+                    .collect::<Vec<_>>()
+                    .join("/"),
+                channel.kind,
+                channel.name
+            );
+            let handle = tokio::spawn(run_channel(database_url.clone(), channel.clone()));
+            running.insert(id, (channel, handle));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(kind: ChannelKind, destination: &str) -> ChannelSettings {
+        ChannelSettings {
+            name: "on-call".to_string(),
+            kind,
+            destination: Some(destination.to_string()),
+            severities: vec![NotificationSeverity::Critical, NotificationSeverity::Emergency],
+            send_cleared: true,
+            enabled: true,
+            locale: None,
+        }
+    }
+
+    fn reading(id: i32, company_id: i32, active: &[u16]) -> SyncReading {
+        let mut flags = AlarmFlags::default();
+        for alarm_num in active {
+            flags.set_alarm_num(*alarm_num, true);
+        }
+        SyncReading {
+            origin_reading_id: id,
+            source_name: "rtac".to_string(),
+            test_type: Some("charging_state".to_string()),
+            site_id: Some(3),
+            company_id: Some(company_id),
+            timestamp: DateTime::from_timestamp(1_800_000_000 + i64::from(id), 0)
+                .unwrap()
+                .naive_utc(),
+            device_timestamp: None,
+            data: json!({ "alarm_registers": flags.to_registers() }).to_string(),
+            quality_flags: 0,
+        }
+    }
+
+    #[test]
+    fn test_settings_validation() {
+        assert!(
+            settings(ChannelKind::Slack, "https://hooks.slack.com/services/T/B/x")
+                .validate()
+                .is_ok()
+        );
+        assert!(settings(ChannelKind::Pagerduty, "secret://pd-key").validate().is_ok());
+        assert!(
+            settings(ChannelKind::Pagerduty, "0123456789abcdef0123456789abcdef")
+                .validate()
+                .is_ok()
+        );
+
+        let mut bad = settings(ChannelKind::Teams, "http://example.com/webhook");
+        bad.severities.clear();
+        bad.name = " ".to_string();
+        let problems = bad.validate().unwrap_err().join("\n");
+        assert!(problems.contains("name"), "{}", problems);
+        assert!(problems.contains("severities"), "{}", problems);
+        assert!(problems.contains("must use https"), "{}", problems);
+        let problems = settings(ChannelKind::Pagerduty, "key").validate().unwrap_err().join("\n");
+        assert!(problems.contains("routing) key"), "{}", problems);
+    }
+
+    #[test]
+    fn test_events_and_routing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("site.sqlite");
+        let mut conn = crate::DataAggregator::new(Some(&path.to_string_lossy()))
+            .establish_connection()
+            .unwrap();
+        let pager = create_channel(
+            &mut conn,
+            2,
+            &settings(ChannelKind::Pagerduty, "0123456789abcdef0123456789abcdef"),
+        )
+        .unwrap();
+        let mut chat = settings(ChannelKind::Slack, "https://hooks.slack.com/services/T/B/x");
+        chat.name = "chat".to_string();
+        chat.severities = vec![NotificationSeverity::Info, NotificationSeverity::Warning];
+        chat.send_cleared = false;
+        let chat = create_channel(&mut conn, 2, &chat).unwrap();
+
+        // E-stop (104) is critical, loss of fiber (1) a warning
+        let mut tracker = Tracker::default();
+        let raised = tracker.events(&reading(1, 2, &[1, 104]));
+        assert_eq!(raised.len(), 2);
+        let (fiber, estop) = (&raised[0], &raised[1]);
+        assert_eq!(estop.severity, NotificationSeverity::Critical);
+        assert!(pager.routes(estop) && !pager.routes(fiber));
+        assert!(chat.routes(fiber) && !chat.routes(estop));
+        assert_eq!(
+            estop.summary(Locale::En),
+            "Critical alarm raised: estop (breaker_relay) on rtac at site 3"
+        );
+
+        let cleared = tracker.events(&reading(2, 2, &[1]));
+        assert_eq!(cleared.len(), 1);
+        assert!(pager.routes(&cleared[0]));
+        let resolve = pagerduty_payload("key", &cleared[0], Locale::En);
+        assert_eq!(resolve["event_action"], json!("resolve"));
+        assert_eq!(resolve["dedup_key"], json!(estop.dedup_key()));
+        let trigger = pagerduty_payload("key", estop, Locale::En);
+        assert_eq!(trigger["payload"]["severity"], json!("error"));
+        assert!(
+            slack_payload(fiber, Locale::En)["text"]
+                .as_str()
+                .unwrap()
+                .starts_with(":warning: *Warning")
+        );
+        assert_eq!(
+            teams_payload(estop, Locale::En)["attachments"][0]["content"]["body"][0]["color"],
+            json!("Attention")
+        );
+        assert_eq!(
+            estop.summary(Locale::Es),
+            "Alarma crítica activada: estop (breaker_relay) en rtac en el sitio 3"
+        );
+        let card = teams_payload(estop, Locale::Fr);
+        let facts = card["attachments"][0]["content"]["body"].as_array().unwrap().last().unwrap();
+        assert_eq!(facts["facts"][1]["title"], json!("Source"));
+        assert_eq!(facts["facts"][4]["title"], json!("Heure"));
+
+        // Another company's alarms and cleared ones the channel doesn't want
+        let other = Tracker::default().events(&reading(3, 1, &[104]));
+        assert!(!pager.routes(&other[0]));
+        let fiber_cleared = tracker.events(&reading(4, 2, &[]));
+        assert!(!chat.routes(&fiber_cleared[0]));
+
+        // Destinations are write-only
+        let info = channel_info(&mut conn, &pager).unwrap();
+        assert_eq!(info.destination, "***");
+        assert_eq!(
+            info.severities,
+            vec![NotificationSeverity::Critical, NotificationSeverity::Emergency]
+        );
+
+        // A null destination keeps the stored one
+        let mut replaced = settings(ChannelKind::Pagerduty, "");
+        replaced.destination = None;
+        replaced.enabled = false;
+        replaced.locale = Some(Locale::Fr);
+        let updated = update_channel(&mut conn, pager.id, &replaced).unwrap().unwrap();
+        assert_eq!(updated.destination, "0123456789abcdef0123456789abcdef");
+        assert!(!updated.enabled);
+        assert_eq!(updated.locale(), Locale::Fr);
+        // ... as does a null locale
+        replaced.locale = None;
+        let updated = update_channel(&mut conn, pager.id, &replaced).unwrap().unwrap();
+        assert_eq!(updated.locale(), Locale::Fr);
+        // ... but not when switching to a kind it doesn't suit
+        replaced.kind = ChannelKind::Slack;
+        assert!(update_channel(&mut conn, pager.id, &replaced).is_err());
+
+        assert!(delete_channel(&mut conn, pager.id).unwrap());
+        assert_eq!(list_channels(&mut conn, Some(2)).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,556 @@
+//! Typed numeric columns for readings.
+//!
+//! Readings store their payload as a JSON blob, which SQLite can't index, so
+//! aggregating one field over a long window means parsing every row. Sources
+//! can declare numeric fields (`point_fields`, a JSON array of top-level
+//! field names); whenever a reading from such a source is written, each
+//! declared field holding a number is also copied into `reading_points` as
+//! `(source_id, field, ts, value)`, indexed for range scans.
+//!
+//! Points are also summarized into hourly and daily `point_rollups` (count,
+//! sum, min and max per bucket) as they are written, so a year of one field
+//! is a few hundred rows rather than millions.
+//!
+//! [`aggregate`] reads from the rollups when the requested buckets are whole
+//! hours or days (topping up partial buckets at either end of the range from
+//! `reading_points`), from `reading_points` when the field is declared, and
+//! falls back to extracting it from the JSON blobs otherwise, so callers get
+//! the same answer either way.
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Duration, NaiveDateTime};
+use diesel::{
+    prelude::*,
+    sql_types::{BigInt, Double, Integer, Text, Timestamp},
+    sqlite::SqliteConnection,
+};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{DataResult, models::Source, schema};
+
+/// Fields declared by default for sources of a test type that don't set
+/// `point_fields`.
+pub const DEFAULT_POINT_FIELDS: &[(&str, &[&str])] = &[
+    ("charging_state", &["level"]),
+    ("simulator", &["soc_percent", "battery_power_kw", "load_kw"]),
+];
+
+/// The numeric fields a source copies into `reading_points`.
+pub fn declared_fields(test_type: Option<&str>, point_fields: Option<&str>) -> Vec<String> {
+    if let Some(point_fields) = point_fields {
+        return serde_json::from_str(point_fields).unwrap_or_default();
+    }
+    DEFAULT_POINT_FIELDS
+        .iter()
+        .find(|(t, _)| Some(*t) == test_type)
+        .map(|(_, fields)| fields.iter().map(|f| f.to_string()).collect())
+        .unwrap_or_default()
+}
+
+/// [`declared_fields`] for a loaded source.
+pub fn source_fields(source: &Source) -> Vec<String> {
+    declared_fields(source.test_type.as_deref(), source.point_fields.as_deref())
+}
+
+/// Turn a comma-separated field list (as given on the command line) into the
+/// JSON stored in `point_fields`.
+pub fn point_fields_json(list: &str) -> DataResult<String> {
+    let fields: Vec<&str> = list.split(',').map(str::trim).filter(|f| !f.is_empty()).collect();
+    if let Some(bad) = fields.iter().find(|f| !is_valid_field(f)) {
+        return Err(format!("Invalid point field name: {:?}", bad).into());
+    }
+    Ok(serde_json::to_string(&fields)?)
+}
+
+/// Field names are limited to identifier characters so they can be used in a
+/// JSON path.
+pub fn is_valid_field(field: &str) -> bool {
+    !field.is_empty() && field.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The declared fields of `data` that hold finite numbers.
+pub fn extract_points(data: &str, fields: &[String]) -> Vec<(String, f64)> {
+    let Ok(serde_json::Value::Object(object)) = serde_json::from_str(data) else {
+        return Vec::new();
+    };
+    fields
+        .iter()
+        .filter_map(|field| {
+            let value = object.get(field)?.as_f64()?;
+            value.is_finite().then(|| (field.clone(), value))
+        })
+        .collect()
+}
+
+/// Bucket widths kept in `point_rollups`, in seconds, widest first.
+pub const ROLLUP_RESOLUTIONS: &[i64] = &[86_400, 3_600];
+
+/// Start of the `resolution`-second bucket, aligned to the Unix epoch, that
+/// holds `ts`.
+fn bucket_start(ts: NaiveDateTime, resolution: i64) -> NaiveDateTime {
+    let seconds = ts.and_utc().timestamp();
+    DateTime::from_timestamp(seconds - seconds.rem_euclid(resolution), 0)
+        .map(|start| start.naive_utc())
+        .unwrap_or(ts)
+}
+
+/// Add one new point to its source's rollups.
+fn add_to_rollups(
+    connection: &mut SqliteConnection,
+    source_id: i32,
+    field: &str,
+    ts: NaiveDateTime,
+    value: f64,
+) -> QueryResult<()> {
+    for &resolution in ROLLUP_RESOLUTIONS {
+        diesel::sql_query(
+            "INSERT INTO point_rollups (source_id, field, resolution, bucket, count, sum, min, max) \
+             VALUES (?1, ?2, ?3, ?4, 1, ?5, ?5, ?5) \
+             ON CONFLICT (source_id, field, resolution, bucket) DO UPDATE SET \
+                 count = count + 1, sum = sum + excluded.sum, \
+                 min = MIN(min, excluded.min), max = MAX(max, excluded.max)",
+        )
+        .bind::<Integer, _>(source_id)
+        .bind::<Text, _>(field)
+        .bind::<BigInt, _>(resolution)
+        .bind::<Timestamp, _>(bucket_start(ts, resolution))
+        .bind::<Double, _>(value)
+        .execute(connection)?;
+    }
+    Ok(())
+}
+
+/// Recompute the rollups of `field` covering `ts` from `reading_points`,
+/// after points in them were replaced (a minimum can't be un-applied).
+fn refresh_rollups(
+    connection: &mut SqliteConnection,
+    source_id: i32,
+    field: &str,
+    ts: NaiveDateTime,
+) -> QueryResult<()> {
+    use schema::point_rollups::dsl;
+
+    for &resolution in ROLLUP_RESOLUTIONS {
+        let start = bucket_start(ts, resolution);
+        diesel::delete(
+            dsl::point_rollups
+                .filter(dsl::source_id.eq(source_id))
+                .filter(dsl::field.eq(field))
+                .filter(dsl::resolution.eq(resolution as i32))
+                .filter(dsl::bucket.eq(start)),
+        )
+        .execute(connection)?;
+        diesel::sql_query(
+            "INSERT INTO point_rollups (source_id, field, resolution, bucket, count, sum, min, max) \
+             SELECT ?1, ?2, ?3, ?4, COUNT(*), SUM(value), MIN(value), MAX(value) \
+             FROM reading_points \
+             WHERE source_id = ?1 AND field = ?2 AND ts >= ?4 AND ts < ?5 \
+             HAVING COUNT(*) > 0",
+        )
+        .bind::<Integer, _>(source_id)
+        .bind::<Text, _>(field)
+        .bind::<BigInt, _>(resolution)
+        .bind::<Timestamp, _>(start)
+        .bind::<Timestamp, _>(start + Duration::seconds(resolution))
+        .execute(connection)?;
+    }
+    Ok(())
+}
+
+/// Replace the points of one stored reading, keeping the rollups in step.
+pub(crate) fn write_points(
+    connection: &mut SqliteConnection,
+    reading_id: i32,
+    source_id: i32,
+    ts: NaiveDateTime,
+    data: &str,
+    fields: &[String],
+) -> QueryResult<()> {
+    use schema::reading_points::dsl;
+
+    let replaced = dsl::reading_points
+        .filter(dsl::reading_id.eq(reading_id))
+        .select((dsl::field, dsl::ts))
+        .load::<(String, NaiveDateTime)>(connection)?;
+    diesel::delete(dsl::reading_points.filter(dsl::reading_id.eq(reading_id)))
+        .execute(connection)?;
+    let points = extract_points(data, fields);
+    for (field, value) in &points {
+        diesel::insert_into(dsl::reading_points)
+            .values((
+                dsl::reading_id.eq(reading_id),
+                dsl::source_id.eq(source_id),
+                dsl::field.eq(field),
+                dsl::ts.eq(ts),
+                dsl::value.eq(value),
+            ))
+            .execute(connection)?;
+    }
+
+    if replaced.is_empty() {
+        for (field, value) in &points {
+            add_to_rollups(connection, source_id, field, ts, *value)?;
+        }
+    } else {
+        let mut touched: Vec<(String, NaiveDateTime)> = replaced;
+        touched.extend(points.into_iter().map(|(field, _)| (field, ts)));
+        touched.sort();
+        touched.dedup();
+        for (field, ts) in touched {
+            refresh_rollups(connection, source_id, &field, ts)?;
+        }
+    }
+    Ok(())
+}
+
+diesel::define_sql_function!(fn last_insert_rowid() -> BigInt);
+
+/// Declared fields of each of `source_ids` that declares any.
+pub(crate) fn fields_by_source(
+    connection: &mut SqliteConnection,
+    source_ids: &[i32],
+) -> QueryResult<HashMap<i32, Vec<String>>> {
+    use schema::sources;
+
+    let rows = sources::table
+        .filter(sources::id.eq_any(source_ids))
+        .select((sources::id, sources::test_type, sources::point_fields))
+        .load::<(Option<i32>, Option<String>, Option<String>)>(connection)?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|(id, test_type, point_fields)| {
+            let fields = declared_fields(test_type.as_deref(), point_fields.as_deref());
+            Some((id?, fields)).filter(|(_, fields)| !fields.is_empty())
+        })
+        .collect())
+}
+
+/// Copy the declared fields of the reading just written for `source_id` into
+/// `reading_points`. Readings with a device timestamp are looked up by it,
+/// since an upsert that updated an existing row leaves `last_insert_rowid`
+/// unchanged.
+pub(crate) fn record_points(
+    connection: &mut SqliteConnection,
+    source_id: i32,
+    device_timestamp: Option<NaiveDateTime>,
+    fields: &[String],
+) -> QueryResult<()> {
+    use schema::readings;
+
+    if fields.is_empty() {
+        return Ok(());
+    }
+    let query = readings::table
+        .select((readings::id, readings::timestamp, readings::data))
+        .into_boxed();
+    let query = match device_timestamp {
+        Some(device_timestamp) => query
+            .filter(readings::source_id.eq(source_id))
+            .filter(readings::device_timestamp.eq(device_timestamp)),
+        None => {
+            let rowid = diesel::select(last_insert_rowid()).get_result::<i64>(connection)?;
+            query.filter(readings::id.eq(rowid as i32))
+        }
+    };
+    if let Some((Some(id), ts, data)) =
+        query.first::<(Option<i32>, NaiveDateTime, String)>(connection).optional()?
+    {
+        write_points(connection, id, source_id, ts, &data, fields)?;
+    }
+    Ok(())
+}
+
+/// Rebuild a source's points and rollups from its stored readings, e.g. after
+/// its declared fields change. Returns the number of points written.
+pub fn backfill_points(connection: &mut SqliteConnection, source: &Source) -> DataResult<usize> {
+    use schema::{point_rollups, reading_points, readings};
+
+    let source_id = source.id.ok_or("source loaded from database is missing its id")?;
+    let fields = source_fields(source);
+    let written = connection.transaction::<_, diesel::result::Error, _>(|conn| {
+        diesel::delete(reading_points::table.filter(reading_points::source_id.eq(source_id)))
+            .execute(conn)?;
+        diesel::delete(point_rollups::table.filter(point_rollups::source_id.eq(source_id)))
+            .execute(conn)?;
+        if fields.is_empty() {
+            return Ok(0);
+        }
+        let rows = readings::table
+            .filter(readings::source_id.eq(source_id))
+            .select((readings::id, readings::timestamp, readings::data))
+            .load::<(Option<i32>, NaiveDateTime, String)>(conn)?;
+        for (id, ts, data) in &rows {
+            if let Some(id) = id {
+                write_points(conn, *id, source_id, *ts, data, &fields)?;
+            }
+        }
+        reading_points::table
+            .filter(reading_points::source_id.eq(source_id))
+            .count()
+            .get_result::<i64>(conn)
+    })?;
+    Ok(written as usize)
+}
+
+/// Summary of one field over one time bucket.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct AggregateBucket {
+    /// Start of the bucket (UTC)
+    pub start: NaiveDateTime,
+    pub count: i64,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+}
+
+#[derive(QueryableByName)]
+struct SumRow {
+    #[diesel(sql_type = BigInt)]
+    bucket: i64,
+    #[diesel(sql_type = BigInt)]
+    count: i64,
+    #[diesel(sql_type = Double)]
+    sum: f64,
+    #[diesel(sql_type = Double)]
+    min: f64,
+    #[diesel(sql_type = Double)]
+    max: f64,
+}
+
+#[derive(QueryableByName)]
+struct BucketRow {
+    #[diesel(sql_type = BigInt)]
+    bucket: i64,
+    #[diesel(sql_type = BigInt)]
+    count: i64,
+    #[diesel(sql_type = Double)]
+    min: f64,
+    #[diesel(sql_type = Double)]
+    max: f64,
+    #[diesel(sql_type = Double)]
+    avg: f64,
+}
+
+/// Where an aggregation read its values from.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "snake_case")]
+pub enum AggregateBackend {
+    /// The indexed `reading_points` table
+    Points,
+    /// The readings' JSON data
+    Json,
+    /// Hourly `point_rollups`, with `reading_points` for partial hours
+    HourlyRollup,
+    /// Daily `point_rollups`, with `reading_points` for partial days
+    DailyRollup,
+}
+
+/// The rollup resolution serving `bucket_seconds`-wide buckets over
+/// `[from, to)`, and the whole-bucket span of the range it covers: the
+/// widest resolution that divides the bucket width and fits at least one of
+/// its buckets in the range.
+fn rollup_span(
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    bucket_seconds: i64,
+) -> Option<(i64, NaiveDateTime, NaiveDateTime)> {
+    ROLLUP_RESOLUTIONS.iter().find_map(|&resolution| {
+        if bucket_seconds % resolution != 0 {
+            return None;
+        }
+        let mut start = bucket_start(from, resolution);
+        if start < from {
+            start += Duration::seconds(resolution);
+        }
+        let end = bucket_start(to, resolution);
+        (start < end).then_some((resolution, start, end))
+    })
+}
+
+/// [`aggregate`] of a declared field from its rollups over `[start, end)`
+/// and its points over the rest of `[from, to)`.
+fn aggregate_rollups(
+    connection: &mut SqliteConnection,
+    source_id: i32,
+    field: &str,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    bucket_seconds: i64,
+    (resolution, start, end): (i64, NaiveDateTime, NaiveDateTime),
+) -> QueryResult<Vec<SumRow>> {
+    let mut rows = diesel::sql_query(
+        "SELECT CAST(strftime('%s', bucket) AS INTEGER) / ?1 * ?1 AS bucket, \
+                SUM(count) AS count, SUM(sum) AS sum, MIN(min) AS min, MAX(max) AS max \
+         FROM point_rollups \
+         WHERE source_id = ?2 AND field = ?3 AND resolution = ?4 AND bucket >= ?5 AND bucket < ?6 \
+         GROUP BY 1",
+    )
+    .bind::<BigInt, _>(bucket_seconds)
+    .bind::<Integer, _>(source_id)
+    .bind::<Text, _>(field)
+    .bind::<BigInt, _>(resolution)
+    .bind::<Timestamp, _>(start)
+    .bind::<Timestamp, _>(end)
+    .load::<SumRow>(connection)?;
+    for (edge_from, edge_to) in [(from, start), (end, to)] {
+        if edge_from >= edge_to {
+            continue;
+        }
+        rows.extend(
+            diesel::sql_query(
+                "SELECT CAST(strftime('%s', ts) AS INTEGER) / ?1 * ?1 AS bucket, \
+                        COUNT(*) AS count, SUM(value) AS sum, MIN(value) AS min, MAX(value) AS max \
+                 FROM reading_points \
+                 WHERE source_id = ?2 AND field = ?3 AND ts >= ?4 AND ts < ?5 \
+                 GROUP BY 1",
+            )
+            .bind::<BigInt, _>(bucket_seconds)
+            .bind::<Integer, _>(source_id)
+            .bind::<Text, _>(field)
+            .bind::<Timestamp, _>(edge_from)
+            .bind::<Timestamp, _>(edge_to)
+            .load::<SumRow>(connection)?,
+        );
+    }
+    Ok(rows)
+}
+
+/// Bucket `field` of `source`'s readings in `[from, to)` into
+/// `bucket_seconds`-wide windows aligned to the Unix epoch. Empty buckets
+/// are omitted.
+///
+/// Declared fields are read from the rollups when `bucket_seconds` is a
+/// whole number of hours and the range spans at least one whole hour.
+pub fn aggregate(
+    connection: &mut SqliteConnection,
+    source: &Source,
+    field: &str,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    bucket_seconds: i64,
+) -> DataResult<(AggregateBackend, Vec<AggregateBucket>)> {
+    if !is_valid_field(field) {
+        return Err(format!("Invalid field name: {:?}", field).into());
+    }
+    if bucket_seconds <= 0 {
+        return Err("Bucket width must be positive".into());
+    }
+    let source_id = source.id.ok_or("source loaded from database is missing its id")?;
+    let declared = source_fields(source).iter().any(|f| f == field);
+
+    if declared && let Some(span) = rollup_span(from, to, bucket_seconds) {
+        let backend = if span.0 == 86_400 {
+            AggregateBackend::DailyRollup
+        } else {
+            AggregateBackend::HourlyRollup
+        };
+        let rows = aggregate_rollups(connection, source_id, field, from, to, bucket_seconds, span)?;
+        let mut merged: BTreeMap<i64, SumRow> = BTreeMap::new();
+        for row in rows {
+            match merged.get_mut(&row.bucket) {
+                Some(total) => {
+                    total.count += row.count;
+                    total.sum += row.sum;
+                    total.min = total.min.min(row.min);
+                    total.max = total.max.max(row.max);
+                }
+                None => {
+                    merged.insert(row.bucket, row);
+                }
+            }
+        }
+        let buckets = merged
+            .into_values()
+            .filter_map(|row| {
+                Some(AggregateBucket {
+                    start: DateTime::from_timestamp(row.bucket, 0)?.naive_utc(),
+                    count: row.count,
+                    min: row.min,
+                    max: row.max,
+                    avg: row.sum / row.count as f64,
+                })
+            })
+            .collect();
+        return Ok((backend, buckets));
+    }
+
+    let (backend, query) = if declared {
+        let query = diesel::sql_query(
+            "SELECT CAST(strftime('%s', ts) AS INTEGER) / ?1 * ?1 AS bucket, \
+                    COUNT(*) AS count, MIN(value) AS min, MAX(value) AS max, AVG(value) AS avg \
+             FROM reading_points \
+             WHERE source_id = ?2 AND field = ?3 AND ts >= ?4 AND ts < ?5 \
+             GROUP BY bucket ORDER BY bucket",
+        );
+        (AggregateBackend::Points, query)
+    } else {
+        let query = diesel::sql_query(
+            "SELECT CAST(strftime('%s', timestamp) AS INTEGER) / ?1 * ?1 AS bucket, \
+                    COUNT(*) AS count, MIN(v) AS min, MAX(v) AS max, AVG(v) AS avg \
+             FROM (SELECT timestamp, json_extract(data, '$.' || ?3) AS v \
+                   FROM readings \
+                   WHERE source_id = ?2 AND timestamp >= ?4 AND timestamp < ?5 \
+                     AND json_valid(data)) \
+             WHERE typeof(v) IN ('integer', 'real') \
+             GROUP BY bucket ORDER BY bucket",
+        );
+        (AggregateBackend::Json, query)
+    };
+
+    let rows = query
+        .bind::<BigInt, _>(bucket_seconds)
+        .bind::<Integer, _>(source_id)
+        .bind::<Text, _>(field)
+        .bind::<Timestamp, _>(from)
+        .bind::<Timestamp, _>(to)
+        .load::<BucketRow>(connection)?;
+
+    let buckets = rows
+        .into_iter()
+        .filter_map(|row| {
+            Some(AggregateBucket {
+                start: DateTime::from_timestamp(row.bucket, 0)?.naive_utc(),
+                count: row.count,
+                min: row.min,
+                max: row.max,
+                avg: row.avg,
+            })
+        })
+        .collect();
+    Ok((backend, buckets))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_declared_fields_default_by_test_type() {
+        assert_eq!(declared_fields(Some("charging_state"), None), vec!["level"]);
+        assert!(declared_fields(Some("ping"), None).is_empty());
+        assert_eq!(
+            declared_fields(Some("charging_state"), Some(r#"["voltage"]"#)),
+            vec!["voltage"],
+            "an explicit list replaces the defaults"
+        );
+        assert!(declared_fields(Some("charging_state"), Some("[]")).is_empty());
+    }
+
+    #[test]
+    fn test_extract_points_skips_non_numeric() {
+        let fields = vec!["level".to_string(), "state".to_string(), "missing".to_string()];
+        let points = extract_points(r#"{"level": 42.5, "state": "charging"}"#, &fields);
+        assert_eq!(points, vec![("level".to_string(), 42.5)]);
+        assert!(extract_points("not json", &fields).is_empty());
+    }
+
+    #[test]
+    fn test_point_fields_json_validates_names() {
+        assert_eq!(point_fields_json("level, voltage").unwrap(), r#"["level","voltage"]"#);
+        assert!(point_fields_json("level,$.x").is_err());
+    }
+}
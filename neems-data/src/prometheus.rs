@@ -0,0 +1,566 @@
+//! Prometheus remote-write exporter for reading points.
+//!
+//! When `NEEMS_PROMETHEUS_URL` is set, `monitor` forwards the numeric points
+//! in `reading_points` (see [`crate::points`]) to a Prometheus remote-write
+//! endpoint (Prometheus with `--web.enable-remote-write-receiver`, Mimir,
+//! Cortex, VictoriaMetrics, Grafana Cloud), so sites with an existing Grafana
+//! stack can chart NEEMS data alongside everything else.
+//!
+//! Each point becomes a sample of the metric `<prefix>_<field>` (e.g.
+//! `neems_level`), labelled with its source's name, test type, site and
+//! company plus any configured static labels. Like [`crate::sync`], the
+//! exporter keeps a cursor of the last exported reading id in `sync_cursors`
+//! (under the target `prometheus:<url>`), sends whole readings in batches and
+//! backs off while the endpoint is unreachable. A batch the endpoint rejects
+//! outright (4xx, e.g. samples older than its ingestion window) is logged and
+//! skipped so it can't hold up everything after it.
+//!
+//! A new target starts from the latest point rather than exporting history,
+//! which Prometheus would mostly refuse as out of bounds.
+
+use std::{collections::BTreeMap, env, time::Duration};
+
+use chrono::{Local, NaiveDateTime};
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use prost::Message;
+
+use crate::{
+    DataResult, encryption, schema,
+    sync::{load_cursor, save_cursor},
+};
+
+/// Labels the exporter sets itself, which static labels may not replace.
+pub const RESERVED_LABELS: &[&str] = &["__name__", "source", "test_type", "site_id", "company_id"];
+
+/// Settings for exporting points to a Prometheus remote-write endpoint.
+///
+/// Read from the environment by [`PrometheusConfig::from_env`]:
+/// - `NEEMS_PROMETHEUS_URL`: remote-write URL, e.g. `http://prometheus:9090/api/v1/write`
+///   (export is disabled when unset)
+/// - `NEEMS_PROMETHEUS_USERNAME` / `NEEMS_PROMETHEUS_PASSWORD`: basic auth
+/// - `NEEMS_PROMETHEUS_BEARER_TOKEN`: bearer auth, instead of basic auth
+/// - `NEEMS_PROMETHEUS_PREFIX`: metric name prefix (default `neems`)
+/// - `NEEMS_PROMETHEUS_LABELS`: static labels as `name=value,...`
+/// - `NEEMS_PROMETHEUS_BATCH_SIZE`: readings per request
+/// - `NEEMS_PROMETHEUS_INTERVAL_SECS`: pause between passes once caught up
+#[derive(Debug, Clone)]
+pub struct PrometheusConfig {
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub bearer_token: Option<String>,
+    pub prefix: String,
+    /// Added to every series, e.g. `site=north`
+    pub labels: Vec<(String, String)>,
+    pub batch_size: usize,
+    pub interval: Duration,
+    /// Longest wait between retries after repeated failures
+    pub max_backoff: Duration,
+}
+
+impl PrometheusConfig {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            username: None,
+            password: None,
+            bearer_token: None,
+            prefix: "neems".to_string(),
+            labels: Vec::new(),
+            batch_size: 500,
+            interval: Duration::from_secs(15),
+            max_backoff: Duration::from_secs(5 * 60),
+        }
+    }
+
+    /// Build a config from the environment, or `None` if
+    /// `NEEMS_PROMETHEUS_URL` is not set.
+    pub fn from_env() -> Option<Self> {
+        let url = env::var("NEEMS_PROMETHEUS_URL").ok().filter(|url| !url.trim().is_empty())?;
+        let var =
+            |key: &str| env::var(key).ok().and_then(|v| v.parse::<u64>().ok()).filter(|&v| v > 0);
+
+        let mut config = Self::new(url);
+        config.username = env::var("NEEMS_PROMETHEUS_USERNAME").ok();
+        config.password = env::var("NEEMS_PROMETHEUS_PASSWORD").ok();
+        config.bearer_token = env::var("NEEMS_PROMETHEUS_BEARER_TOKEN").ok();
+        let prefix = env::var("NEEMS_PROMETHEUS_PREFIX").ok();
+        if let Some(prefix) = prefix.filter(|prefix| is_metric_name(prefix)) {
+            config.prefix = prefix;
+        }
+        if let Ok(labels) = env::var("NEEMS_PROMETHEUS_LABELS") {
+            config.labels = parse_labels(&labels).unwrap_or_default();
+        }
+        if let Some(batch_size) = var("NEEMS_PROMETHEUS_BATCH_SIZE") {
+            config.batch_size = batch_size as usize;
+        }
+        if let Some(interval) = var("NEEMS_PROMETHEUS_INTERVAL_SECS") {
+            config.interval = Duration::from_secs(interval);
+        }
+        Some(config)
+    }
+
+    /// The exporter's key in `sync_cursors`.
+    pub fn target(&self) -> String {
+        format!("prometheus:{}", self.url)
+    }
+}
+
+/// Parse static labels written as `name=value,name=value`.
+pub fn parse_labels(spec: &str) -> Result<Vec<(String, String)>, String> {
+    let mut labels = Vec::new();
+    for pair in spec.split(',').filter(|pair| !pair.trim().is_empty()) {
+        let (name, value) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("'{}' is not a name=value pair", pair.trim()))?;
+        let name = name.trim();
+        if !is_label_name(name) || RESERVED_LABELS.contains(&name) {
+            return Err(format!("'{}' can't be used as a label name", name));
+        }
+        labels.push((name.to_string(), value.trim().to_string()));
+    }
+    Ok(labels)
+}
+
+/// Whether `name` is a valid Prometheus metric name.
+pub fn is_metric_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+/// Whether `name` is a valid label name that isn't reserved for Prometheus
+/// itself (`__` prefix).
+pub fn is_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with("__")
+}
+
+/// The metric a point field is exported as: `<prefix>_<field>`, with
+/// characters Prometheus doesn't allow replaced by `_`.
+pub fn metric_name(prefix: &str, field: &str) -> String {
+    let field: String = field
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if prefix.is_empty() {
+        if field.starts_with(|c: char| c.is_ascii_digit()) {
+            format!("_{}", field)
+        } else {
+            field
+        }
+    } else {
+        format!("{}_{}", prefix, field)
+    }
+}
+
+// The remote-write 1.0 protobuf messages (prometheus/prompb/types.proto),
+// trimmed to the fields we send.
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub timeseries: Vec<TimeSeries>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TimeSeries {
+    /// Sorted by name
+    #[prost(message, repeated, tag = "1")]
+    pub labels: Vec<Label>,
+    /// Oldest first
+    #[prost(message, repeated, tag = "2")]
+    pub samples: Vec<Sample>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Label {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Sample {
+    #[prost(double, tag = "1")]
+    pub value: f64,
+    /// Milliseconds since the Unix epoch
+    #[prost(int64, tag = "2")]
+    pub timestamp: i64,
+}
+
+/// A reading point with the metadata of the source it came from.
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct ExportPoint {
+    pub reading_id: i32,
+    pub field: String,
+    pub ts: NaiveDateTime,
+    pub value: f64,
+    pub source_name: String,
+    pub test_type: Option<String>,
+    pub site_id: Option<i32>,
+    pub company_id: Option<i32>,
+}
+
+/// The points of the next `limit` readings after `after_id`, oldest first.
+/// Batches hold whole readings, so the cursor never splits one.
+pub fn pending_points(
+    connection: &mut SqliteConnection,
+    after_id: i32,
+    limit: usize,
+) -> DataResult<Vec<ExportPoint>> {
+    use schema::{reading_points, sources};
+
+    let reading_ids: Vec<i32> = reading_points::table
+        .filter(reading_points::reading_id.gt(after_id))
+        .select(reading_points::reading_id)
+        .distinct()
+        .order(reading_points::reading_id.asc())
+        .limit(limit as i64)
+        .load(connection)?;
+    let Some(&last_id) = reading_ids.last() else {
+        return Ok(Vec::new());
+    };
+
+    Ok(reading_points::table
+        .inner_join(sources::table.on(sources::id.eq(reading_points::source_id.nullable())))
+        .filter(reading_points::reading_id.gt(after_id))
+        .filter(reading_points::reading_id.le(last_id))
+        .order((reading_points::reading_id.asc(), reading_points::field.asc()))
+        .select((
+            reading_points::reading_id,
+            reading_points::field,
+            reading_points::ts,
+            reading_points::value,
+            sources::name,
+            sources::test_type,
+            sources::site_id,
+            sources::company_id,
+        ))
+        .load(connection)?)
+}
+
+/// Highest reading id with points, where a new target starts.
+pub fn latest_point_id(connection: &mut SqliteConnection) -> DataResult<i32> {
+    use schema::reading_points::dsl;
+
+    let latest = dsl::reading_points
+        .select(diesel::dsl::max(dsl::reading_id))
+        .first::<Option<i32>>(connection)?;
+    Ok(latest.unwrap_or(0))
+}
+
+/// Group points into one series per source and field. Non-finite values are
+/// dropped; Prometheus would read NaN as a staleness marker.
+pub fn build_request(config: &PrometheusConfig, points: &[ExportPoint]) -> WriteRequest {
+    let mut series: BTreeMap<Vec<(String, String)>, Vec<Sample>> = BTreeMap::new();
+    for point in points.iter().filter(|point| point.value.is_finite()) {
+        let mut labels = vec![
+            ("__name__".to_string(), metric_name(&config.prefix, &point.field)),
+            ("source".to_string(), point.source_name.clone()),
+        ];
+        if let Some(test_type) = &point.test_type {
+            labels.push(("test_type".to_string(), test_type.clone()));
+        }
+        if let Some(site_id) = point.site_id {
+            labels.push(("site_id".to_string(), site_id.to_string()));
+        }
+        if let Some(company_id) = point.company_id {
+            labels.push(("company_id".to_string(), company_id.to_string()));
+        }
+        labels.extend(config.labels.iter().cloned());
+        labels.sort();
+
+        series.entry(labels).or_default().push(Sample {
+            value: point.value,
+            timestamp: point.ts.and_utc().timestamp_millis(),
+        });
+    }
+
+    WriteRequest {
+        timeseries: series
+            .into_iter()
+            .map(|(labels, mut samples)| {
+                samples.sort_by_key(|sample| sample.timestamp);
+                TimeSeries {
+                    labels: labels.into_iter().map(|(name, value)| Label { name, value }).collect(),
+                    samples,
+                }
+            })
+            .collect(),
+    }
+}
+
+/// What the endpoint made of a request.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Delivery {
+    Accepted,
+    /// Refused in a way resending won't fix
+    Rejected(String),
+}
+
+/// HTTP client for a remote-write endpoint.
+pub struct PrometheusClient {
+    config: PrometheusConfig,
+    http: reqwest::Client,
+}
+
+impl PrometheusClient {
+    pub fn new(config: PrometheusConfig) -> DataResult<Self> {
+        let http = reqwest::Client::builder().timeout(Duration::from_secs(30)).build()?;
+        Ok(Self { config, http })
+    }
+
+    /// Send one request. Network errors, 5xx and 429 responses are errors, to
+    /// be retried; other 4xx responses are [`Delivery::Rejected`].
+    pub async fn push(&self, request: &WriteRequest) -> DataResult<Delivery> {
+        let body = snap::raw::Encoder::new().compress_vec(&request.encode_to_vec())?;
+        let mut builder = self
+            .http
+            .post(&self.config.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/x-protobuf")
+            .header(reqwest::header::CONTENT_ENCODING, "snappy")
+            .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+            .header(reqwest::header::USER_AGENT, concat!("neems-data/", env!("CARGO_PKG_VERSION")))
+            .body(body);
+        if let Some(token) = &self.config.bearer_token {
+            builder = builder.bearer_auth(token);
+        } else if let Some(username) = &self.config.username {
+            builder = builder.basic_auth(username, self.config.password.as_ref());
+        }
+
+        let response = builder.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(Delivery::Accepted);
+        }
+        let message = response.text().await.unwrap_or_default();
+        let message = format!("HTTP {}: {}", status, message.trim());
+        if status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+            Ok(Delivery::Rejected(message))
+        } else {
+            Err(format!("remote write failed with {}", message).into())
+        }
+    }
+
+    /// Export batches from `cursor` until caught up. Returns the new cursor
+    /// and the number of samples accepted.
+    pub async fn export_from(
+        &self,
+        database_url: &str,
+        mut cursor: i32,
+    ) -> DataResult<(i32, usize)> {
+        let target = self.config.target();
+        let mut exported = 0;
+        loop {
+            let points = {
+                let database_url = database_url.to_string();
+                let batch_size = self.config.batch_size;
+                tokio::task::spawn_blocking(move || {
+                    let mut connection = encryption::establish(&database_url)?;
+                    pending_points(&mut connection, cursor, batch_size)
+                })
+                .await??
+            };
+            let Some(last_id) = points.last().map(|point| point.reading_id) else {
+                return Ok((cursor, exported));
+            };
+
+            let request = build_request(&self.config, &points);
+            let samples: usize = request.timeseries.iter().map(|series| series.samples.len()).sum();
+            let error = match self.push(&request).await? {
+                Delivery::Accepted => {
+                    exported += samples;
+                    None
+                }
+                Delivery::Rejected(message) => {
+                    eprintln!(
+                        "{} - {} rejected {} samples from readings {}-{}, skipping them: {}",
+                        Local::now().to_rfc3339(),
+                        self.config.url,
+                        samples,
+                        cursor + 1,
+                        last_id,
+                        message
+                    );
+                    Some(message)
+                }
+            };
+            cursor = last_id;
+
+            let database_url = database_url.to_string();
+            let target = target.clone();
+            tokio::task::spawn_blocking(move || -> DataResult<()> {
+                let mut connection = encryption::establish(&database_url)?;
+                save_cursor(&mut connection, &target, cursor, error.as_deref())
+            })
+            .await??;
+        }
+    }
+}
+
+/// The stored cursor for `target`, or `None` for a target never exported to.
+fn stored_cursor(connection: &mut SqliteConnection, target: &str) -> DataResult<Option<i32>> {
+    use schema::sync_cursors::dsl;
+
+    Ok(dsl::sync_cursors
+        .filter(dsl::target.eq(target))
+        .select(dsl::last_reading_id)
+        .first::<i32>(connection)
+        .optional()?)
+}
+
+/// Export points forever, backing off exponentially while the endpoint is
+/// unreachable.
+pub async fn run_prometheus_loop(database_url: String, config: PrometheusConfig) {
+    let interval = config.interval;
+    let max_backoff = config.max_backoff;
+    let target = config.target();
+    let client = match PrometheusClient::new(config) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Prometheus export disabled: {}", e);
+            return;
+        }
+    };
+
+    let mut cursor: Option<i32> = None;
+    let mut backoff = interval;
+    loop {
+        let result = async {
+            let start = match cursor {
+                Some(cursor) => cursor,
+                None => {
+                    let database_url = database_url.clone();
+                    let target = target.clone();
+                    tokio::task::spawn_blocking(move || -> DataResult<i32> {
+                        let mut connection = encryption::establish(&database_url)?;
+                        match stored_cursor(&mut connection, &target)? {
+                            Some(cursor) => Ok(cursor),
+                            None => {
+                                let latest = latest_point_id(&mut connection)?;
+                                save_cursor(&mut connection, &target, latest, None)?;
+                                Ok(latest)
+                            }
+                        }
+                    })
+                    .await??
+                }
+            };
+            client.export_from(&database_url, start).await
+        }
+        .await;
+
+        let wait = match result {
+            Ok((new_cursor, exported)) => {
+                if exported > 0 {
+                    println!(
+                        "{} - Exported {} samples to {} (cursor {})",
+                        Local::now().to_rfc3339(),
+                        exported,
+                        target,
+                        new_cursor
+                    );
+                }
+                cursor = Some(new_cursor);
+                backoff = interval;
+                interval
+            }
+            Err(e) => {
+                eprintln!("{} - Export to {} failed: {}", Local::now().to_rfc3339(), target, e);
+                let database_url = database_url.clone();
+                let target = target.clone();
+                let error = e.to_string();
+                let _ = tokio::task::spawn_blocking(move || -> DataResult<()> {
+                    let mut connection = encryption::establish(&database_url)?;
+                    let last = load_cursor(&mut connection, &target)?;
+                    save_cursor(&mut connection, &target, last, Some(&error))
+                })
+                .await;
+                cursor = None;
+                let wait = backoff;
+                backoff = (backoff * 2).min(max_backoff);
+                wait
+            }
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(reading_id: i32, field: &str, secs: i64, value: f64) -> ExportPoint {
+        ExportPoint {
+            reading_id,
+            field: field.to_string(),
+            ts: chrono::DateTime::from_timestamp(secs, 0).unwrap().naive_utc(),
+            value,
+            source_name: "battery-1".to_string(),
+            test_type: Some("charging_state".to_string()),
+            site_id: Some(3),
+            company_id: None,
+        }
+    }
+
+    #[test]
+    fn test_names_and_labels() {
+        assert_eq!(metric_name("neems", "level"), "neems_level");
+        assert_eq!(metric_name("neems", "temp.c-1"), "neems_temp_c_1");
+        assert_eq!(metric_name("", "1st"), "_1st");
+        assert!(is_metric_name("neems:site"));
+        assert!(!is_metric_name("9neems"));
+
+        assert_eq!(
+            parse_labels("site = north, env=prod").unwrap(),
+            vec![
+                ("site".to_string(), "north".to_string()),
+                ("env".to_string(), "prod".to_string())
+            ]
+        );
+        assert!(parse_labels("source=x").is_err(), "reserved label");
+        assert!(parse_labels("__meta=x").is_err());
+        assert!(parse_labels("site").is_err());
+    }
+
+    #[test]
+    fn test_request_groups_series_and_round_trips() {
+        let mut config = PrometheusConfig::new("http://localhost:9090/api/v1/write");
+        config.labels = vec![("env".to_string(), "prod".to_string())];
+        let points = vec![
+            point(2, "level", 200, 51.0),
+            point(1, "level", 100, 50.0),
+            point(1, "voltage", 100, 812.5),
+            point(3, "level", 300, f64::NAN),
+        ];
+
+        let request = build_request(&config, &points);
+        assert_eq!(request.timeseries.len(), 2);
+        let level = &request.timeseries[0];
+        let names: Vec<&str> = level.labels.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["__name__", "env", "site_id", "source", "test_type"]);
+        assert_eq!(level.labels[0].value, "neems_level");
+        assert_eq!(
+            level.samples,
+            vec![
+                Sample { value: 50.0, timestamp: 100_000 },
+                Sample { value: 51.0, timestamp: 200_000 }
+            ],
+            "sorted by time, NaN dropped"
+        );
+
+        let body = snap::raw::Encoder::new().compress_vec(&request.encode_to_vec()).unwrap();
+        let decoded = snap::raw::Decoder::new().decompress_vec(&body).unwrap();
+        assert_eq!(WriteRequest::decode(decoded.as_slice()).unwrap(), request);
+    }
+}
@@ -0,0 +1,314 @@
+//! Integrity checks between edge and central copies of synced readings.
+//!
+//! Sync is append-only and idempotent, but billing-grade data needs proof
+//! that nothing was lost on the way. Both ends summarize their readings per
+//! source per UTC day as a row count plus a checksum; the edge sends its
+//! summaries to the central server, which compares them with its own copy
+//! and records the outcome in `sync_reconciliations`.
+//!
+//! The checksum is a SHA-256 over the sorted SHA-256 hashes of each reading's
+//! timestamp, device timestamp, quality flags and data, so it doesn't depend
+//! on reading ids or insertion order, which differ between the two databases.
+//! The edge only summarizes readings up to its sync cursor, so readings still
+//! waiting to be uploaded aren't reported as missing.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{Days, NaiveDate, NaiveDateTime, Utc};
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{DataResult, schema};
+
+/// Row count and checksum of one source's readings for one UTC day.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DayDigest {
+    pub source_name: String,
+    pub day: NaiveDate,
+    pub row_count: i32,
+    pub checksum: String,
+}
+
+/// Body of `POST /api/1/Sync/Reconcile`: the edge's digests for every day in
+/// `from..=to` on which it has synced readings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconcileRequest {
+    pub origin: String,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub digests: Vec<DayDigest>,
+}
+
+/// A source-day whose edge and central copies differ.
+///
+/// A missing checksum means that side holds no readings for the day.
+#[derive(Debug, Clone, PartialEq, Queryable, Serialize, Deserialize)]
+pub struct Discrepancy {
+    pub origin: String,
+    pub source_name: String,
+    pub day: NaiveDate,
+    pub edge_count: i32,
+    pub edge_checksum: Option<String>,
+    pub central_count: i32,
+    pub central_checksum: Option<String>,
+    pub checked_at: NaiveDateTime,
+}
+
+/// Outcome of one reconciliation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconcileReport {
+    pub origin: String,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    /// Source-days compared
+    pub checked: usize,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+/// The reading fields covered by the checksum.
+type DigestRow = (NaiveDateTime, Option<NaiveDateTime>, i32, String);
+
+fn row_hash((timestamp, device_timestamp, quality_flags, data): &DigestRow) -> [u8; 32] {
+    let format = "%Y-%m-%dT%H:%M:%S%.f";
+    let device_timestamp =
+        device_timestamp.map(|t| t.format(format).to_string()).unwrap_or_default();
+    let mut hasher = Sha256::new();
+    hasher.update(timestamp.format(format).to_string());
+    hasher.update([0]);
+    hasher.update(device_timestamp);
+    hasher.update([0]);
+    hasher.update(quality_flags.to_le_bytes());
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+/// Row count and hex checksum of a set of readings, independent of order.
+pub fn digest_rows(rows: &[DigestRow]) -> (i32, String) {
+    let mut hashes: Vec<[u8; 32]> = rows.iter().map(row_hash).collect();
+    hashes.sort_unstable();
+    let mut hasher = Sha256::new();
+    for hash in &hashes {
+        hasher.update(hash);
+    }
+    let checksum = hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+    (rows.len() as i32, checksum)
+}
+
+fn group_by_day(rows: Vec<(String, DigestRow)>) -> Vec<DayDigest> {
+    let mut days: BTreeMap<(String, NaiveDate), Vec<DigestRow>> = BTreeMap::new();
+    for (source_name, row) in rows {
+        days.entry((source_name, row.0.date())).or_default().push(row);
+    }
+    days.into_iter()
+        .map(|((source_name, day), rows)| {
+            let (row_count, checksum) = digest_rows(&rows);
+            DayDigest { source_name, day, row_count, checksum }
+        })
+        .collect()
+}
+
+/// Start of `from` to the start of the day after `to`.
+fn day_bounds(from: NaiveDate, to: NaiveDate) -> (NaiveDateTime, NaiveDateTime) {
+    let end = to.checked_add_days(Days::new(1)).unwrap_or(to);
+    (from.and_time(Default::default()), end.and_time(Default::default()))
+}
+
+/// The edge's digests for readings with ids up to `max_reading_id` (its
+/// sync cursor) between `from` and `to`, inclusive.
+pub fn edge_digests(
+    connection: &mut SqliteConnection,
+    max_reading_id: i32,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> DataResult<Vec<DayDigest>> {
+    use schema::{readings, sources};
+
+    let (start, end) = day_bounds(from, to);
+    let rows = readings::table
+        .inner_join(sources::table)
+        .filter(readings::id.le(max_reading_id))
+        .filter(readings::timestamp.ge(start))
+        .filter(readings::timestamp.lt(end))
+        .select((
+            sources::name,
+            (
+                readings::timestamp,
+                readings::device_timestamp,
+                readings::quality_flags,
+                readings::data,
+            ),
+        ))
+        .load::<(String, DigestRow)>(connection)?;
+    Ok(group_by_day(rows))
+}
+
+/// The central server's digests for readings synced from `origin` between
+/// `from` and `to`, inclusive, keyed by the edge's source names.
+pub fn central_digests(
+    connection: &mut SqliteConnection,
+    origin: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> DataResult<Vec<DayDigest>> {
+    use schema::{readings, sources};
+
+    let prefix = format!("{}/", origin);
+    let (start, end) = day_bounds(from, to);
+    let rows = readings::table
+        .inner_join(sources::table)
+        .filter(sources::name.like(format!("{}%", prefix)))
+        .filter(readings::timestamp.ge(start))
+        .filter(readings::timestamp.lt(end))
+        .select((
+            sources::name,
+            (
+                readings::timestamp,
+                readings::device_timestamp,
+                readings::quality_flags,
+                readings::data,
+            ),
+        ))
+        .load::<(String, DigestRow)>(connection)?;
+
+    // LIKE treats `_` and `%` in the origin as wildcards
+    let rows = rows
+        .into_iter()
+        .filter_map(|(name, row)| Some((name.strip_prefix(&prefix)?.to_string(), row)))
+        .collect();
+    Ok(group_by_day(rows))
+}
+
+/// Compare an edge's digests with the central copy, record the outcome of
+/// every source-day in `sync_reconciliations`, and report the mismatches.
+pub fn reconcile(
+    connection: &mut SqliteConnection,
+    request: &ReconcileRequest,
+) -> DataResult<ReconcileReport> {
+    use schema::sync_reconciliations::dsl;
+
+    if request.origin.trim().is_empty() || request.origin.contains('/') {
+        return Err(format!("Invalid sync origin: {:?}", request.origin).into());
+    }
+    if request.from > request.to {
+        return Err("Reconciliation range starts after it ends".into());
+    }
+
+    let central = central_digests(connection, &request.origin, request.from, request.to)?;
+    let key = |d: &DayDigest| (d.source_name.clone(), d.day);
+    let edge: BTreeMap<_, _> = request
+        .digests
+        .iter()
+        .filter(|d| d.day >= request.from && d.day <= request.to)
+        .map(|d| (key(d), d))
+        .collect();
+    let central: BTreeMap<_, _> = central.iter().map(|d| (key(d), d)).collect();
+    let keys: BTreeSet<_> = edge.keys().chain(central.keys()).cloned().collect();
+
+    let checked_at = Utc::now().naive_utc();
+    let discrepancies = connection.transaction::<_, diesel::result::Error, _>(|conn| {
+        let mut discrepancies = Vec::new();
+        for (source_name, day) in &keys {
+            let edge = edge.get(&(source_name.clone(), *day));
+            let central = central.get(&(source_name.clone(), *day));
+            let edge_count = edge.map_or(0, |d| d.row_count);
+            let central_count = central.map_or(0, |d| d.row_count);
+            let edge_checksum = edge.map(|d| d.checksum.clone());
+            let central_checksum = central.map(|d| d.checksum.clone());
+            let matched = edge_count == central_count && edge_checksum == central_checksum;
+
+            diesel::replace_into(dsl::sync_reconciliations)
+                .values((
+                    dsl::origin.eq(&request.origin),
+                    dsl::source_name.eq(source_name),
+                    dsl::day.eq(day),
+                    dsl::edge_count.eq(edge_count),
+                    dsl::edge_checksum.eq(&edge_checksum),
+                    dsl::central_count.eq(central_count),
+                    dsl::central_checksum.eq(&central_checksum),
+                    dsl::matched.eq(matched),
+                    dsl::checked_at.eq(checked_at),
+                ))
+                .execute(conn)?;
+
+            if !matched {
+                discrepancies.push(Discrepancy {
+                    origin: request.origin.clone(),
+                    source_name: source_name.clone(),
+                    day: *day,
+                    edge_count,
+                    edge_checksum,
+                    central_count,
+                    central_checksum,
+                    checked_at,
+                });
+            }
+        }
+        Ok(discrepancies)
+    })?;
+
+    Ok(ReconcileReport {
+        origin: request.origin.clone(),
+        from: request.from,
+        to: request.to,
+        checked: keys.len(),
+        discrepancies,
+    })
+}
+
+/// Source-days whose latest reconciliation found a mismatch, newest day
+/// first, optionally for one origin and from `since` on.
+pub fn discrepancies(
+    connection: &mut SqliteConnection,
+    origin: Option<&str>,
+    since: Option<NaiveDate>,
+) -> DataResult<Vec<Discrepancy>> {
+    use schema::sync_reconciliations::dsl;
+
+    let mut query = dsl::sync_reconciliations
+        .filter(dsl::matched.eq(false))
+        .select((
+            dsl::origin,
+            dsl::source_name,
+            dsl::day,
+            dsl::edge_count,
+            dsl::edge_checksum,
+            dsl::central_count,
+            dsl::central_checksum,
+            dsl::checked_at,
+        ))
+        .order((dsl::day.desc(), dsl::origin.asc(), dsl::source_name.asc()))
+        .into_boxed();
+    if let Some(origin) = origin {
+        query = query.filter(dsl::origin.eq(origin.to_string()));
+    }
+    if let Some(since) = since {
+        query = query.filter(dsl::day.ge(since));
+    }
+    Ok(query.load(connection)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(second: u32, data: &str) -> DigestRow {
+        let timestamp = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(12, 0, second);
+        (timestamp.unwrap(), None, 0, data.to_string())
+    }
+
+    #[test]
+    fn test_checksum_ignores_order_but_not_content() {
+        let a = row(0, r#"{"level": 1}"#);
+        let b = row(1, r#"{"level": 2}"#);
+
+        let forward = digest_rows(&[a.clone(), b.clone()]);
+        assert_eq!(forward, digest_rows(&[b.clone(), a.clone()]));
+        assert_eq!(forward.0, 2);
+
+        let altered = row(1, r#"{"level": 3}"#);
+        assert_ne!(forward.1, digest_rows(&[a.clone(), altered]).1);
+        // A duplicated reading changes the checksum too
+        assert_ne!(digest_rows(std::slice::from_ref(&a)).1, digest_rows(&[a.clone(), a]).1);
+    }
+}
@@ -71,6 +71,18 @@ pub const CONED_CURTAILMENT_TRIP_ALARM_NUM: u16 = 321;
 /// Alarm number for utility loss of power
 pub const UTILITY_LOP_ALARM_NUM: u16 = 322;
 
+/// Alarm number for loss of communication with the RTAC itself.
+///
+/// Raised by the Modbus worker's watchdog rather than read from the alarm
+/// registers, so it has no [`AlarmDefinition`].
+pub const RTAC_COMMS_LOSS_ALARM_NUM: u16 = 900;
+
+/// Alarm number for control commands taking longer than the latency budget
+/// to be acknowledged (see [`super::latency`]).
+///
+/// Raised by the Modbus worker, like [`RTAC_COMMS_LOSS_ALARM_NUM`].
+pub const RTAC_LATENCY_BUDGET_ALARM_NUM: u16 = 901;
+
 /// Alarm zones in the Newtown system
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum AlarmZone {
@@ -13,7 +13,15 @@ use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
 use tracing::{debug, error, info, warn};
 
-use super::alarm_definitions::{AlarmDefinition, AlarmZone};
+use super::alarm_definitions::{
+    AlarmDefinition, AlarmZone, RTAC_COMMS_LOSS_ALARM_NUM, RTAC_LATENCY_BUDGET_ALARM_NUM,
+};
+
+/// Name of the alarm raised when communication with the RTAC is lost
+pub const RTAC_COMMS_LOSS_ALARM_NAME: &str = "rtac_loss_of_comms";
+
+/// Name of the alarm raised when control commands go over the latency budget
+pub const RTAC_LATENCY_BUDGET_ALARM_NAME: &str = "rtac_control_latency_budget";
 
 /// Alarm severity levels
 ///
@@ -102,6 +110,53 @@ impl Alarm {
         }
     }
 
+    /// Create the critical alarm raised when communication with the RTAC is
+    /// lost
+    pub fn comms_lost() -> Self {
+        Self {
+            name: RTAC_COMMS_LOSS_ALARM_NAME.to_string(),
+            alarm_num: RTAC_COMMS_LOSS_ALARM_NUM,
+            zone: AlarmZone::Site,
+            state: AlarmState::Active,
+            severity: AlarmSeverity::Critical,
+            timestamp: Utc::now(),
+            message: None,
+        }
+    }
+
+    /// Create the event clearing [`comms_lost`](Self::comms_lost) once
+    /// communication recovers
+    pub fn comms_restored() -> Self {
+        Self {
+            state: AlarmState::Cleared,
+            ..Self::comms_lost()
+        }
+    }
+
+    /// Create the warning raised when a control command takes longer than
+    /// the latency budget to be acknowledged
+    pub fn latency_budget_exceeded() -> Self {
+        Self {
+            name: RTAC_LATENCY_BUDGET_ALARM_NAME.to_string(),
+            alarm_num: RTAC_LATENCY_BUDGET_ALARM_NUM,
+            zone: AlarmZone::Site,
+            state: AlarmState::Active,
+            severity: AlarmSeverity::Warning,
+            timestamp: Utc::now(),
+            message: None,
+        }
+    }
+
+    /// Create the event clearing
+    /// [`latency_budget_exceeded`](Self::latency_budget_exceeded) once a
+    /// command is acknowledged within budget
+    pub fn latency_budget_restored() -> Self {
+        Self {
+            state: AlarmState::Cleared,
+            ..Self::latency_budget_exceeded()
+        }
+    }
+
     /// Create an alarm with a custom message
     pub fn with_message(mut self, message: &str) -> Self {
         self.message = Some(message.to_string());
@@ -383,6 +438,34 @@ mod tests {
         assert!(alarm.is_critical_or_higher());
     }
 
+    #[test]
+    fn test_alarm_comms_lost() {
+        let alarm = Alarm::comms_lost();
+        assert_eq!(alarm.name, "rtac_loss_of_comms");
+        assert_eq!(alarm.alarm_num, RTAC_COMMS_LOSS_ALARM_NUM);
+        assert!(alarm.is_active());
+        assert!(alarm.is_critical_or_higher());
+        assert!(find_by_alarm_num(RTAC_COMMS_LOSS_ALARM_NUM).is_none());
+
+        let restored = Alarm::comms_restored();
+        assert_eq!(restored.alarm_num, RTAC_COMMS_LOSS_ALARM_NUM);
+        assert!(!restored.is_active());
+    }
+
+    #[test]
+    fn test_alarm_latency_budget() {
+        let alarm = Alarm::latency_budget_exceeded();
+        assert_eq!(alarm.name, "rtac_control_latency_budget");
+        assert_eq!(alarm.alarm_num, RTAC_LATENCY_BUDGET_ALARM_NUM);
+        assert_eq!(alarm.severity, AlarmSeverity::Warning);
+        assert!(alarm.is_active());
+        assert!(find_by_alarm_num(RTAC_LATENCY_BUDGET_ALARM_NUM).is_none());
+
+        let restored = Alarm::latency_budget_restored();
+        assert_eq!(restored.alarm_num, RTAC_LATENCY_BUDGET_ALARM_NUM);
+        assert!(!restored.is_active());
+    }
+
     #[test]
     fn test_alarm_history() {
         let fire_def = find_by_alarm_num(FIRE_ALARM_NUM).unwrap();
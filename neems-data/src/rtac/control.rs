@@ -6,14 +6,20 @@
 //!   execution_offset_seconds
 //! - Monitors real-time state for conditions requiring reactive adjustments
 //! - Sends commands via watch channel to the Modbus worker
+//! - Times the schedule lookup and interlock checks, and marks each command it
+//!   issues, for the control path's latency budget (see [`super::latency`])
 
-use std::{sync::Arc, time::Duration};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use chrono::{DateTime, Utc};
 use tokio::sync::{RwLock, watch};
 use tracing::{debug, error, info, warn};
 
 use super::{
+    latency::{ControlLatency, ControlStage},
     protocol::{CommandType, OperatingMode},
     state::{PendingCommand, RtacState},
 };
@@ -150,6 +156,7 @@ pub struct ControlLogicTask<S: ScheduleProvider> {
     command_tx: watch::Sender<Option<PendingCommand>>,
     last_command_id: Option<i64>,
     last_reactive_command: Option<CommandType>,
+    latency: ControlLatency,
 }
 
 impl<S: ScheduleProvider> ControlLogicTask<S> {
@@ -167,9 +174,17 @@ impl<S: ScheduleProvider> ControlLogicTask<S> {
             command_tx,
             last_command_id: None,
             last_reactive_command: None,
+            latency: ControlLatency::default(),
         }
     }
 
+    /// Record stage latencies and issued commands in `latency`, shared with
+    /// the Modbus worker.
+    pub fn with_latency(mut self, latency: ControlLatency) -> Self {
+        self.latency = latency;
+        self
+    }
+
     /// Run the control logic loop
     pub async fn run(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         info!("Starting control logic task");
@@ -187,12 +202,19 @@ impl<S: ScheduleProvider> ControlLogicTask<S> {
 
     /// Evaluate current state and schedules, send commands as needed
     async fn evaluate(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let started = Instant::now();
         let now = Utc::now();
         let current_state = self.state.read().await.clone();
 
         // Check if system is available for commands
-        if !current_state.is_available_for_commands() {
-            if current_state.alarms.is_estop_active() {
+        let interlock_started = Instant::now();
+        let available = current_state.is_available_for_commands();
+        let mut interlock = interlock_started.elapsed();
+        if !available {
+            self.latency.record(ControlStage::InterlockCheck, interlock);
+            if let Some(since) = current_state.failsafe_since {
+                warn!(%since, "RTAC communication lost, skipping command evaluation");
+            } else if current_state.alarms.is_estop_active() {
                 warn!("System in emergency stop, skipping command evaluation");
             } else if current_state.alarms.has_critical_alarm() {
                 let active: Vec<_> = current_state
@@ -209,6 +231,7 @@ impl<S: ScheduleProvider> ControlLogicTask<S> {
                 debug!("System not available for commands, clearing pending command");
                 // Clear the current command in the watch channel
                 self.command_tx.send(None)?;
+                self.latency.command_withdrawn();
                 self.last_command_id = None;
                 self.last_reactive_command = None;
             }
@@ -216,12 +239,23 @@ impl<S: ScheduleProvider> ControlLogicTask<S> {
             return Ok(());
         }
 
+        let eval_started = Instant::now();
+        let scheduled = self.schedule_provider.get_active_command(now);
+        self.latency.record(ControlStage::ScheduleEval, eval_started.elapsed());
+
         // First, check for reactive control conditions
-        if self.config.enable_reactive_control {
-            if let Some(reactive_cmd) = self.check_reactive_conditions(&current_state) {
-                self.send_reactive_command(reactive_cmd).await;
-                return Ok(());
-            }
+        let reactive = if self.config.enable_reactive_control {
+            let reactive_started = Instant::now();
+            let reactive = self.check_reactive_conditions(&current_state, scheduled.as_ref());
+            interlock += reactive_started.elapsed();
+            reactive
+        } else {
+            None
+        };
+        self.latency.record(ControlStage::InterlockCheck, interlock);
+        if let Some(reactive_cmd) = reactive {
+            self.send_reactive_command(reactive_cmd, started).await;
+            return Ok(());
         }
 
         // Clear reactive command if conditions no longer apply
@@ -231,7 +265,7 @@ impl<S: ScheduleProvider> ControlLogicTask<S> {
         }
 
         // Check scheduled commands
-        if let Some(scheduled) = self.schedule_provider.get_active_command(now) {
+        if let Some(scheduled) = scheduled {
             // Only send if this is a different command than last time
             if self.last_command_id != Some(scheduled.id) {
                 let pending =
@@ -244,7 +278,9 @@ impl<S: ScheduleProvider> ControlLogicTask<S> {
                     "Activating scheduled command"
                 );
 
+                let command_type = pending.command_type;
                 self.command_tx.send(Some(pending))?;
+                self.latency.command_issued(command_type, started);
                 self.last_command_id = Some(scheduled.id);
             }
         } else {
@@ -254,6 +290,7 @@ impl<S: ScheduleProvider> ControlLogicTask<S> {
                 // Optionally send standby command when schedule ends
                 let standby = PendingCommand::standby(self.config.default_ramp_duration_seconds);
                 self.command_tx.send(Some(standby))?;
+                self.latency.command_issued(CommandType::Standby, started);
                 self.last_command_id = None;
             }
         }
@@ -262,10 +299,22 @@ impl<S: ScheduleProvider> ControlLogicTask<S> {
     }
 
     /// Check for reactive control conditions based on current state
-    fn check_reactive_conditions(&self, state: &RtacState) -> Option<CommandType> {
+    ///
+    /// The scheduled command counts as well as the current mode, so once a
+    /// reactive standby has taken effect the schedule can't restart the
+    /// discharge (or charge) it just stopped.
+    fn check_reactive_conditions(
+        &self,
+        state: &RtacState,
+        scheduled: Option<&ScheduledCommand>,
+    ) -> Option<CommandType> {
+        let scheduled_type = scheduled.map(|cmd| cmd.command_type);
+
         // Check for low SOC - stop discharging
         if state.soc_percent <= self.config.low_soc_threshold {
-            if state.mode == OperatingMode::Discharging {
+            if state.mode == OperatingMode::Discharging
+                || scheduled_type == Some(CommandType::Discharge)
+            {
                 warn!(
                     soc = state.soc_percent,
                     threshold = self.config.low_soc_threshold,
@@ -277,7 +326,8 @@ impl<S: ScheduleProvider> ControlLogicTask<S> {
 
         // Check for high SOC - stop charging
         if state.soc_percent >= self.config.high_soc_threshold {
-            if state.mode == OperatingMode::Charging {
+            if state.mode == OperatingMode::Charging || scheduled_type == Some(CommandType::Charge)
+            {
                 warn!(
                     soc = state.soc_percent,
                     threshold = self.config.high_soc_threshold,
@@ -291,7 +341,7 @@ impl<S: ScheduleProvider> ControlLogicTask<S> {
     }
 
     /// Send a reactive command (overrides scheduled commands)
-    async fn send_reactive_command(&mut self, command_type: CommandType) {
+    async fn send_reactive_command(&mut self, command_type: CommandType, started: Instant) {
         // Only send if different from last reactive command
         if self.last_reactive_command == Some(command_type) {
             return;
@@ -308,8 +358,9 @@ impl<S: ScheduleProvider> ControlLogicTask<S> {
 
         info!(command_type = %command_type, "Sending reactive command");
 
-        if let Err(e) = self.command_tx.send(Some(pending)) {
-            error!(error = %e, "Failed to send reactive command");
+        match self.command_tx.send(Some(pending)) {
+            Ok(()) => self.latency.command_issued(command_type, started),
+            Err(e) => error!(error = %e, "Failed to send reactive command"),
         }
 
         self.last_reactive_command = Some(command_type);
@@ -329,7 +380,9 @@ impl<S: ScheduleProvider> ControlLogicTask<S> {
         command: PendingCommand,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         info!(command_type = %command.command_type, "Manual command triggered");
+        let command_type = command.command_type;
         self.command_tx.send(Some(command))?;
+        self.latency.command_issued(command_type, Instant::now());
         Ok(())
     }
 }
@@ -539,6 +592,52 @@ mod tests {
         assert_eq!(cmd.unwrap().command_type, CommandType::Discharge);
     }
 
+    #[tokio::test]
+    async fn test_low_soc_standby_holds_against_schedule() {
+        let config = ControlConfig {
+            low_soc_threshold: 10.0,
+            high_soc_threshold: 95.0,
+            enable_reactive_control: true,
+            ..Default::default()
+        };
+
+        let mut provider = InMemoryScheduleProvider::new(vec![]);
+        provider.add_command(ScheduledCommand {
+            id: 1,
+            command_type: CommandType::Discharge,
+            starts_at: Utc::now() - chrono::Duration::hours(1),
+            ends_at: Some(Utc::now() + chrono::Duration::hours(1)),
+            duration_seconds: None,
+            target_soc_percent: None,
+            ramp_duration_seconds: None,
+        });
+
+        let (mut task, command_rx, state) = create_test_task(config, provider);
+
+        // Low SOC while discharging triggers reactive standby
+        {
+            let mut s = state.write().await;
+            s.mode = OperatingMode::Discharging;
+            s.soc_percent = 9.0;
+        }
+        task.evaluate().await.unwrap();
+        assert_eq!(task.last_reactive_command, Some(CommandType::Standby));
+
+        // The RTAC has gone to standby but SOC is still low: the scheduled
+        // discharge must not be re-sent
+        {
+            let mut s = state.write().await;
+            s.mode = OperatingMode::Standby;
+        }
+        task.evaluate().await.unwrap();
+        assert_eq!(task.last_reactive_command, Some(CommandType::Standby));
+        assert_eq!(task.last_command_id, None);
+
+        let cmd = command_rx.borrow().clone();
+        assert!(cmd.is_some());
+        assert_eq!(cmd.unwrap().command_type, CommandType::Standby);
+    }
+
     #[tokio::test]
     async fn test_emergency_stop_clears_pending_command() {
         let config = ControlConfig::default();
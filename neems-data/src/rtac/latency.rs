@@ -0,0 +1,598 @@
+//! Control-path latency instrumentation.
+//!
+//! A scheduled command goes through several stages between the control logic
+//! deciding on it and the RTAC acting on it:
+//!
+//! - [`ControlStage::ScheduleEval`]: the control logic looks up the active
+//!   scheduled command
+//! - [`ControlStage::InterlockCheck`]: it checks the RTAC is available for
+//!   commands and whether a reactive override applies
+//! - [`ControlStage::DispatchWait`]: the command waits for the Modbus worker to
+//!   pick it up
+//! - [`ControlStage::ModbusWrite`]: the worker writes it to the RTAC
+//! - [`ControlStage::Ack`]: the worker waits for a status read showing the RTAC
+//!   in the commanded mode
+//!
+//! [`ControlLatency`] keeps a histogram of each stage and of the whole path
+//! ([`ControlStage::EndToEnd`], from the start of the evaluation that issued
+//! a command to its ack). When a command takes longer than the budget
+//! (`RTAC_LATENCY_BUDGET_MS`, default 500ms) to be acknowledged, the worker
+//! raises the `rtac_control_latency_budget` alarm, cleared by the next
+//! command acknowledged within budget. The collector publishes a
+//! [`LatencyReport`] to `control_latency` every [`PUBLISH_INTERVAL`], served
+//! by neems-api.
+
+use std::{
+    env,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use serde::{Deserialize, Serialize};
+use tokio::task;
+use tracing::warn;
+use ts_rs::TS;
+
+use super::protocol::{CommandType, OperatingMode};
+use crate::{DataResult, encryption, schema};
+
+/// Default end-to-end budget for a command.
+pub const DEFAULT_BUDGET: Duration = Duration::from_millis(500);
+
+/// How often the collector publishes its latency report.
+pub const PUBLISH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Upper bounds of the histogram buckets, in milliseconds. Longer samples
+/// fall in a final, unbounded bucket.
+pub const BUCKET_BOUNDS_MS: [u64; 12] = [1, 2, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000];
+
+/// A stage of the control path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum ControlStage {
+    ScheduleEval,
+    InterlockCheck,
+    DispatchWait,
+    ModbusWrite,
+    Ack,
+    EndToEnd,
+}
+
+impl ControlStage {
+    pub const ALL: [ControlStage; 6] = [
+        Self::ScheduleEval,
+        Self::InterlockCheck,
+        Self::DispatchWait,
+        Self::ModbusWrite,
+        Self::Ack,
+        Self::EndToEnd,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Whether a status read in `mode` shows the RTAC carrying out `command`.
+fn acknowledges(command: CommandType, mode: OperatingMode) -> bool {
+    match command {
+        CommandType::Standby => mode == OperatingMode::Standby,
+        CommandType::Charge => mode == OperatingMode::Charging,
+        CommandType::Discharge => mode == OperatingMode::Discharging,
+        CommandType::TrickleCharge => mode == OperatingMode::TrickleCharge,
+        CommandType::EmergencyStop => mode == OperatingMode::EmergencyStop,
+        CommandType::ClearFaults => mode != OperatingMode::Fault,
+    }
+}
+
+/// Durations of one stage, counted into fixed buckets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyHistogram {
+    /// One count per bound in [`BUCKET_BOUNDS_MS`], then the overflow bucket
+    counts: [u64; BUCKET_BOUNDS_MS.len() + 1],
+    total: Duration,
+    max: Duration,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            counts: [0; BUCKET_BOUNDS_MS.len() + 1],
+            total: Duration::ZERO,
+            max: Duration::ZERO,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, elapsed: Duration) {
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        let bucket = BUCKET_BOUNDS_MS
+            .iter()
+            .position(|&bound| ms <= bound as f64)
+            .unwrap_or(BUCKET_BOUNDS_MS.len());
+        self.counts[bucket] += 1;
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// The upper bound of the bucket holding the `q` quantile, capped at the
+    /// longest sample; `None` when nothing has been recorded.
+    pub fn quantile_ms(&self, q: f64) -> Option<f64> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((q * count as f64).ceil() as u64).clamp(1, count);
+        let max_ms = self.max.as_secs_f64() * 1000.0;
+        let mut seen = 0;
+        for (i, n) in self.counts.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                let bound = BUCKET_BOUNDS_MS.get(i).map_or(max_ms, |&b| b as f64);
+                return Some(bound.min(max_ms));
+            }
+        }
+        Some(max_ms)
+    }
+
+    fn summary(&self, stage: ControlStage) -> StageLatency {
+        let count = self.count();
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        StageLatency {
+            stage,
+            count,
+            mean_ms: (count > 0).then(|| ms(self.total) / count as f64),
+            p50_ms: self.quantile_ms(0.5),
+            p95_ms: self.quantile_ms(0.95),
+            p99_ms: self.quantile_ms(0.99),
+            max_ms: (count > 0).then(|| ms(self.max)),
+            buckets: BUCKET_BOUNDS_MS
+                .iter()
+                .map(|&bound| Some(bound))
+                .chain([None])
+                .zip(self.counts)
+                .map(|(le_ms, count)| LatencyBucket { le_ms, count })
+                .collect(),
+        }
+    }
+}
+
+/// One histogram bucket of a [`StageLatency`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LatencyBucket {
+    /// Upper bound in milliseconds; `None` for the last, unbounded bucket
+    #[ts(type = "number | null")]
+    pub le_ms: Option<u64>,
+    /// Samples in this bucket (not cumulative)
+    #[ts(type = "number")]
+    pub count: u64,
+}
+
+/// Latency of one stage since the collector started. The quantiles are
+/// bucket bounds, so they're accurate to a bucket.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct StageLatency {
+    pub stage: ControlStage,
+    #[ts(type = "number")]
+    pub count: u64,
+    pub mean_ms: Option<f64>,
+    pub p50_ms: Option<f64>,
+    pub p95_ms: Option<f64>,
+    pub p99_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+    pub buckets: Vec<LatencyBucket>,
+}
+
+/// The control path's latency, as the collector last published it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LatencyReport {
+    #[ts(type = "number")]
+    pub budget_ms: u64,
+    /// Commands that took longer than the budget to be acknowledged, or
+    /// weren't acknowledged within it
+    #[ts(type = "number")]
+    pub over_budget: u64,
+    /// When a command last went over budget
+    #[ts(type = "string | null")]
+    pub last_over_budget_at: Option<DateTime<Utc>>,
+    /// Whether the budget alarm is raised
+    pub alarm_active: bool,
+    pub stages: Vec<StageLatency>,
+    /// When the report was published
+    #[ts(type = "string")]
+    pub updated_at: NaiveDateTime,
+}
+
+/// A change of the budget alarm's state, for the worker to forward to the
+/// alarm handler.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BudgetAlarm {
+    /// A command went over budget: it took this long, or hasn't been
+    /// acknowledged after this long
+    Raised(Duration),
+    /// A command was acknowledged within budget
+    Cleared(Duration),
+}
+
+/// The command issued most recently, until it's acknowledged.
+#[derive(Debug, Clone)]
+struct InFlight {
+    command_type: CommandType,
+    issued_at: Instant,
+    written_at: Option<Instant>,
+    /// Already counted as over budget, while still unacknowledged
+    over_budget: bool,
+}
+
+#[derive(Debug)]
+struct Tracker {
+    budget: Duration,
+    histograms: [LatencyHistogram; ControlStage::ALL.len()],
+    in_flight: Option<InFlight>,
+    over_budget: u64,
+    last_over_budget_at: Option<DateTime<Utc>>,
+    alarm_active: bool,
+}
+
+impl Tracker {
+    /// Count the in-flight command as over budget, raising the alarm unless
+    /// it's raised already.
+    fn over_budget(&mut self, elapsed: Duration) -> Option<BudgetAlarm> {
+        self.over_budget += 1;
+        self.last_over_budget_at = Some(Utc::now());
+        warn!(
+            elapsed_ms = elapsed.as_millis() as u64,
+            budget_ms = self.budget.as_millis() as u64,
+            "Control command over latency budget"
+        );
+        (!std::mem::replace(&mut self.alarm_active, true)).then_some(BudgetAlarm::Raised(elapsed))
+    }
+
+    /// Count the in-flight command as over budget the first time it's been
+    /// unacknowledged for longer than the budget.
+    fn check_unacknowledged(&mut self, elapsed: Duration) -> Option<BudgetAlarm> {
+        let in_flight = self.in_flight.as_mut()?;
+        if in_flight.over_budget || elapsed <= self.budget {
+            return None;
+        }
+        in_flight.over_budget = true;
+        self.over_budget(elapsed)
+    }
+}
+
+/// Latency of the control path, shared by the control logic and the Modbus
+/// worker.
+#[derive(Debug, Clone)]
+pub struct ControlLatency(Arc<Mutex<Tracker>>);
+
+impl Default for ControlLatency {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUDGET)
+    }
+}
+
+impl ControlLatency {
+    pub fn new(budget: Duration) -> Self {
+        Self(Arc::new(Mutex::new(Tracker {
+            budget,
+            histograms: Default::default(),
+            in_flight: None,
+            over_budget: 0,
+            last_over_budget_at: None,
+            alarm_active: false,
+        })))
+    }
+
+    /// The budget from `RTAC_LATENCY_BUDGET_MS`, or [`DEFAULT_BUDGET`] when
+    /// it's unset or invalid.
+    pub fn budget_from_env() -> Duration {
+        env::var("RTAC_LATENCY_BUDGET_MS")
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .filter(|ms| *ms > 0)
+            .map_or(DEFAULT_BUDGET, Duration::from_millis)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Tracker> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn budget(&self) -> Duration {
+        self.lock().budget
+    }
+
+    /// Record a duration of `stage`.
+    pub fn record(&self, stage: ControlStage, elapsed: Duration) {
+        self.lock().histograms[stage.index()].record(elapsed);
+    }
+
+    /// The control logic handed `command_type` to the worker, in an
+    /// evaluation that started at `evaluation_started`. A command still in
+    /// flight is superseded.
+    pub fn command_issued(&self, command_type: CommandType, evaluation_started: Instant) {
+        self.lock().in_flight = Some(InFlight {
+            command_type,
+            issued_at: evaluation_started,
+            written_at: None,
+            over_budget: false,
+        });
+    }
+
+    /// The control logic withdrew its command.
+    pub fn command_withdrawn(&self) {
+        self.lock().in_flight = None;
+    }
+
+    /// The worker wrote `command_type`, starting at `started` and finishing
+    /// at `finished`. Records the write, and how long the command waited for
+    /// it if this is the in-flight command's first write.
+    pub fn command_written(&self, command_type: CommandType, started: Instant, finished: Instant) {
+        let mut tracker = self.lock();
+        tracker.histograms[ControlStage::ModbusWrite.index()].record(finished - started);
+        let Some(in_flight) = tracker.in_flight.as_mut() else {
+            return;
+        };
+        if in_flight.command_type != command_type || in_flight.written_at.is_some() {
+            return;
+        }
+        in_flight.written_at = Some(finished);
+        let waited = started.saturating_duration_since(in_flight.issued_at);
+        tracker.histograms[ControlStage::DispatchWait.index()].record(waited);
+    }
+
+    /// The worker read the RTAC in `mode` at `now`. Completes the in-flight
+    /// command if `mode` acknowledges it, and reports any change to the
+    /// budget alarm.
+    pub fn status_read(&self, mode: OperatingMode, now: Instant) -> Option<BudgetAlarm> {
+        let mut tracker = self.lock();
+        let budget = tracker.budget;
+        let in_flight = tracker.in_flight.as_mut()?;
+        let elapsed = now.saturating_duration_since(in_flight.issued_at);
+        let Some(written_at) = in_flight.written_at else {
+            return tracker.check_unacknowledged(elapsed);
+        };
+        if !acknowledges(in_flight.command_type, mode) {
+            return tracker.check_unacknowledged(elapsed);
+        }
+
+        let counted = in_flight.over_budget;
+        tracker.in_flight = None;
+        tracker.histograms[ControlStage::Ack.index()]
+            .record(now.saturating_duration_since(written_at));
+        tracker.histograms[ControlStage::EndToEnd.index()].record(elapsed);
+        if elapsed > budget {
+            if counted {
+                None
+            } else {
+                tracker.over_budget(elapsed)
+            }
+        } else if std::mem::take(&mut tracker.alarm_active) {
+            Some(BudgetAlarm::Cleared(elapsed))
+        } else {
+            None
+        }
+    }
+
+    /// The histograms and budget state, stamped with the current time.
+    pub fn report(&self) -> LatencyReport {
+        let tracker = self.lock();
+        LatencyReport {
+            budget_ms: tracker.budget.as_millis() as u64,
+            over_budget: tracker.over_budget,
+            last_over_budget_at: tracker.last_over_budget_at,
+            alarm_active: tracker.alarm_active,
+            stages: ControlStage::ALL
+                .iter()
+                .map(|&stage| tracker.histograms[stage.index()].summary(stage))
+                .collect(),
+            updated_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
+/// The report `site_id`'s collector last published, if it has.
+pub fn load_report(conn: &mut SqliteConnection, site_id: i32) -> DataResult<Option<LatencyReport>> {
+    use schema::control_latency::dsl;
+
+    let report = dsl::control_latency
+        .find(site_id)
+        .select(dsl::report)
+        .first::<String>(conn)
+        .optional()?;
+    Ok(report.map(|report| serde_json::from_str(&report)).transpose()?)
+}
+
+/// Publish `report` as `site_id`'s.
+pub fn save_report(
+    conn: &mut SqliteConnection,
+    site_id: i32,
+    report: &LatencyReport,
+) -> DataResult<()> {
+    use schema::control_latency::dsl;
+
+    let json = serde_json::to_string(report)?;
+    diesel::insert_into(dsl::control_latency)
+        .values((
+            dsl::site_id.eq(site_id),
+            dsl::report.eq(&json),
+            dsl::updated_at.eq(report.updated_at),
+        ))
+        .on_conflict(dsl::site_id)
+        .do_update()
+        .set((dsl::report.eq(&json), dsl::updated_at.eq(report.updated_at)))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Publish `latency`'s report every [`PUBLISH_INTERVAL`]. Runs until the
+/// process exits.
+pub async fn run_latency_publisher(database_url: String, site_id: i32, latency: ControlLatency) {
+    let mut interval = tokio::time::interval(PUBLISH_INTERVAL);
+    loop {
+        interval.tick().await;
+        let database_url = database_url.clone();
+        let report = latency.report();
+        let saved = task::spawn_blocking(move || {
+            save_report(&mut encryption::establish(&database_url)?, site_id, &report)
+        })
+        .await;
+        match saved {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!(error = %e, "Error publishing control latency"),
+            Err(e) => warn!(error = %e, "Control latency publisher failed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel_migrations::MigrationHarness;
+
+    use super::*;
+    use crate::MIGRATIONS;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    fn stage(report: &LatencyReport, stage: ControlStage) -> &StageLatency {
+        report.stages.iter().find(|s| s.stage == stage).unwrap()
+    }
+
+    #[test]
+    fn test_histogram_buckets_and_quantiles() {
+        let mut histogram = LatencyHistogram::default();
+        assert_eq!(histogram.quantile_ms(0.5), None);
+
+        for ms in [3, 4, 20, 40, 7000] {
+            histogram.record(MS * ms);
+        }
+        assert_eq!(histogram.count(), 5);
+        // 3 and 4 fall in the 5ms bucket, 7000 overflows
+        assert_eq!(histogram.counts[2], 2);
+        assert_eq!(histogram.counts[BUCKET_BOUNDS_MS.len()], 1);
+        assert_eq!(histogram.quantile_ms(0.4), Some(5.0));
+        assert_eq!(histogram.quantile_ms(0.6), Some(25.0));
+        // The overflow bucket reports the longest sample
+        assert_eq!(histogram.quantile_ms(0.99), Some(7000.0));
+
+        let summary = histogram.summary(ControlStage::Ack);
+        assert_eq!(summary.mean_ms, Some(1413.4));
+        assert_eq!(summary.buckets.len(), BUCKET_BOUNDS_MS.len() + 1);
+        assert_eq!(summary.buckets.last().unwrap().le_ms, None);
+    }
+
+    #[test]
+    fn test_command_path_within_budget() {
+        let latency = ControlLatency::new(MS * 500);
+        let issued = Instant::now();
+        latency.command_issued(CommandType::Charge, issued);
+        latency.command_written(CommandType::Charge, issued + MS * 100, issued + MS * 120);
+
+        // Reads before the RTAC changes mode don't acknowledge the command
+        assert_eq!(latency.status_read(OperatingMode::Standby, issued + MS * 200), None);
+        assert_eq!(latency.status_read(OperatingMode::Charging, issued + MS * 300), None);
+
+        let report = latency.report();
+        assert_eq!(report.over_budget, 0);
+        assert!(!report.alarm_active);
+        assert_eq!(stage(&report, ControlStage::DispatchWait).max_ms, Some(100.0));
+        assert_eq!(stage(&report, ControlStage::ModbusWrite).max_ms, Some(20.0));
+        assert_eq!(stage(&report, ControlStage::Ack).max_ms, Some(180.0));
+        assert_eq!(stage(&report, ControlStage::EndToEnd).max_ms, Some(300.0));
+
+        // Once acknowledged, later reads don't count it again
+        latency.status_read(OperatingMode::Charging, issued + MS * 400);
+        assert_eq!(stage(&latency.report(), ControlStage::EndToEnd).count, 1);
+    }
+
+    #[test]
+    fn test_over_budget_raises_and_clears_the_alarm() {
+        let latency = ControlLatency::new(MS * 500);
+        let issued = Instant::now();
+        latency.command_issued(CommandType::Discharge, issued);
+        latency.command_written(CommandType::Discharge, issued, issued + MS * 10);
+        assert_eq!(
+            latency.status_read(OperatingMode::Discharging, issued + MS * 700),
+            Some(BudgetAlarm::Raised(MS * 700))
+        );
+
+        // A second slow command doesn't raise it again
+        let issued = issued + MS * 1000;
+        latency.command_issued(CommandType::Standby, issued);
+        latency.command_written(CommandType::Standby, issued, issued + MS * 10);
+        assert_eq!(latency.status_read(OperatingMode::Standby, issued + MS * 600), None);
+        let report = latency.report();
+        assert_eq!(report.over_budget, 2);
+        assert!(report.alarm_active);
+        assert!(report.last_over_budget_at.is_some());
+
+        // A command acknowledged within budget clears it
+        let issued = issued + MS * 1000;
+        latency.command_issued(CommandType::Charge, issued);
+        latency.command_written(CommandType::Charge, issued, issued + MS * 10);
+        assert_eq!(
+            latency.status_read(OperatingMode::Charging, issued + MS * 50),
+            Some(BudgetAlarm::Cleared(MS * 50))
+        );
+        assert!(!latency.report().alarm_active);
+    }
+
+    #[test]
+    fn test_unacknowledged_command_goes_over_budget_once() {
+        let latency = ControlLatency::new(MS * 500);
+        let issued = Instant::now();
+        latency.command_issued(CommandType::Charge, issued);
+
+        // Never written: still over budget once the budget has passed
+        assert_eq!(latency.status_read(OperatingMode::Standby, issued + MS * 400), None);
+        assert_eq!(
+            latency.status_read(OperatingMode::Standby, issued + MS * 600),
+            Some(BudgetAlarm::Raised(MS * 600))
+        );
+        assert_eq!(latency.status_read(OperatingMode::Standby, issued + MS * 900), None);
+
+        // When it's finally acknowledged it isn't counted a second time
+        latency.command_written(CommandType::Charge, issued + MS * 950, issued + MS * 960);
+        assert_eq!(latency.status_read(OperatingMode::Charging, issued + MS * 1000), None);
+        let report = latency.report();
+        assert_eq!(report.over_budget, 1);
+        assert_eq!(stage(&report, ControlStage::EndToEnd).max_ms, Some(1000.0));
+
+        // A withdrawn command isn't waited for
+        latency.command_issued(CommandType::Discharge, issued);
+        latency.command_withdrawn();
+        assert_eq!(latency.status_read(OperatingMode::Standby, issued + MS * 5000), None);
+        assert_eq!(latency.report().over_budget, 1);
+    }
+
+    #[test]
+    fn test_save_and_load_report() {
+        let mut conn = SqliteConnection::establish(":memory:").unwrap();
+        conn.run_pending_migrations(MIGRATIONS).unwrap();
+        assert_eq!(load_report(&mut conn, 1).unwrap(), None);
+
+        let latency = ControlLatency::new(MS * 250);
+        latency.record(ControlStage::ScheduleEval, MS * 2);
+        let report = latency.report();
+        save_report(&mut conn, 1, &report).unwrap();
+        latency.record(ControlStage::ScheduleEval, MS * 3);
+        let report = latency.report();
+        save_report(&mut conn, 1, &report).unwrap();
+
+        let loaded = load_report(&mut conn, 1).unwrap().unwrap();
+        assert_eq!(loaded, report);
+        assert_eq!(loaded.budget_ms, 250);
+        assert_eq!(stage(&loaded, ControlStage::ScheduleEval).count, 2);
+        assert_eq!(load_report(&mut conn, 2).unwrap(), None);
+    }
+}
@@ -11,7 +11,9 @@ pub mod alarm_definitions;
 pub mod alarm_sld_meta;
 pub mod alarms;
 pub mod control;
+pub mod latency;
 pub mod modbus_client;
+pub mod power_quality;
 pub mod protocol;
 pub mod runner;
 pub mod schedule_http;
@@ -19,13 +21,17 @@ pub mod state;
 pub mod storage;
 pub mod worker;
 
-pub use alarm_definitions::{ALARM_REGISTER_COUNT, AlarmDefinition, AlarmZone};
+pub use alarm_definitions::{
+    ALARM_REGISTER_COUNT, AlarmDefinition, AlarmZone, RTAC_COMMS_LOSS_ALARM_NUM,
+    RTAC_LATENCY_BUDGET_ALARM_NUM,
+};
 pub use alarm_sld_meta::{AlarmSldMeta, sld_meta_for};
 pub use alarms::{Alarm, AlarmHandlerTask, AlarmSeverity};
 pub use control::ControlLogicTask;
+pub use latency::{ControlLatency, ControlStage, LatencyReport};
 pub use modbus_client::ModbusClient;
 pub use protocol::{CommandType, OperatingMode, RegisterMap};
-pub use runner::run_rtac_collector;
+pub use runner::{RtacCollector, RtacCollectorConfig, run_rtac_collector};
 pub use state::{AlarmFlags, ConnectionStatus, PendingCommand, RtacReading, RtacState};
 pub use storage::{DataSampler, StorageWriterTask};
-pub use worker::{ModbusWorker, RtacConfig, ShutdownReason};
+pub use worker::{CommsWatchdog, ModbusWorker, RtacConfig, ShutdownReason};
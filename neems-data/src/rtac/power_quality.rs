@@ -0,0 +1,621 @@
+//! Power-quality event capture.
+//!
+//! The RTAC is read at 10Hz but stored as one-second averages (see
+//! [`super::storage`]), so a voltage sag or frequency excursion lasting a few
+//! hundred milliseconds only shows up as the min/max of a single row. When a
+//! trigger fires, [`EventCapture`] keeps the full-rate readings from
+//! `pre_trigger` before it to `post_trigger` after it, and the storage task
+//! stores them as one event in `power_quality_events`, listed and downloaded
+//! through neems-api.
+//!
+//! Triggers:
+//! - [`PowerQualityEventKind::VoltageSag`]: voltage below `sag_threshold` of
+//!   `nominal_voltage_v`
+//! - [`PowerQualityEventKind::FrequencyExcursion`]: grid frequency more than
+//!   `frequency_deviation_hz` from `nominal_frequency_hz`
+//!
+//! Each trigger fires once per excursion: it re-arms when the value returns
+//! within its threshold. Only one event is captured at a time; an excursion
+//! of the other kind that starts during a capture fires once it ends, if the
+//! value is still outside its threshold.
+//!
+//! Capture is off unless `RTAC_PQ_NOMINAL_VOLTAGE_V` is set (see
+//! [`PqCaptureConfig::from_env`]).
+
+use std::{collections::VecDeque, env, str::FromStr, time::Duration};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use super::state::RtacReading;
+use crate::{DataResult, schema};
+
+/// Most events [`list_events`] returns.
+pub const MAX_LIST_LIMIT: i64 = 1000;
+
+/// What fired a power-quality event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum PowerQualityEventKind {
+    VoltageSag,
+    FrequencyExcursion,
+}
+
+impl PowerQualityEventKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::VoltageSag => "voltage_sag",
+            Self::FrequencyExcursion => "frequency_excursion",
+        }
+    }
+}
+
+impl FromStr for PowerQualityEventKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "voltage_sag" => Ok(Self::VoltageSag),
+            "frequency_excursion" => Ok(Self::FrequencyExcursion),
+            other => Err(format!(
+                "unknown event kind '{}'; expected voltage_sag or frequency_excursion",
+                other
+            )),
+        }
+    }
+}
+
+/// Trigger thresholds and capture window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PqCaptureConfig {
+    /// Nominal voltage the RTAC reports, in volts
+    pub nominal_voltage_v: f32,
+    /// Fraction of nominal voltage below which a sag fires (default 0.9)
+    pub sag_threshold: f32,
+    /// Nominal grid frequency (default 60Hz)
+    pub nominal_frequency_hz: f32,
+    /// Deviation from nominal frequency beyond which an excursion fires
+    /// (default 0.5Hz)
+    pub frequency_deviation_hz: f32,
+    /// Readings kept from before the trigger (default 2s); at most what the
+    /// storage task's full-rate buffer holds
+    pub pre_trigger: Duration,
+    /// Readings kept after the trigger (default 5s)
+    pub post_trigger: Duration,
+}
+
+impl PqCaptureConfig {
+    /// A config for `nominal_voltage_v` with the default thresholds.
+    pub fn new(nominal_voltage_v: f32) -> Self {
+        Self {
+            nominal_voltage_v,
+            sag_threshold: 0.9,
+            nominal_frequency_hz: 60.0,
+            frequency_deviation_hz: 0.5,
+            pre_trigger: Duration::from_secs(2),
+            post_trigger: Duration::from_secs(5),
+        }
+    }
+
+    /// Build a config from the environment, or `None` when
+    /// `RTAC_PQ_NOMINAL_VOLTAGE_V` is not set.
+    ///
+    /// Honored variables:
+    /// - `RTAC_PQ_NOMINAL_VOLTAGE_V`: nominal voltage; enables capture
+    /// - `RTAC_PQ_SAG_PERCENT`: sag threshold as a percentage of nominal
+    ///   (default 90)
+    /// - `RTAC_PQ_NOMINAL_FREQUENCY_HZ`: nominal frequency (default 60)
+    /// - `RTAC_PQ_FREQUENCY_DEVIATION_HZ`: excursion threshold (default 0.5)
+    /// - `RTAC_PQ_PRE_TRIGGER_SECS` / `RTAC_PQ_POST_TRIGGER_SECS`: capture
+    ///   window (default 2 and 5)
+    ///
+    /// Invalid values are ignored and the default is kept.
+    pub fn from_env() -> Option<Self> {
+        fn var<T: FromStr>(key: &str) -> Option<T> {
+            env::var(key).ok().and_then(|v| v.trim().parse().ok())
+        }
+
+        let nominal_voltage_v = var::<f32>("RTAC_PQ_NOMINAL_VOLTAGE_V").filter(|v| *v > 0.0)?;
+        let mut config = Self::new(nominal_voltage_v);
+        if let Some(percent) = var::<f32>("RTAC_PQ_SAG_PERCENT").filter(|p| *p > 0.0 && *p < 100.0)
+        {
+            config.sag_threshold = percent / 100.0;
+        }
+        if let Some(hz) = var::<f32>("RTAC_PQ_NOMINAL_FREQUENCY_HZ").filter(|hz| *hz > 0.0) {
+            config.nominal_frequency_hz = hz;
+        }
+        if let Some(hz) = var::<f32>("RTAC_PQ_FREQUENCY_DEVIATION_HZ").filter(|hz| *hz > 0.0) {
+            config.frequency_deviation_hz = hz;
+        }
+        if let Some(secs) = var::<u64>("RTAC_PQ_PRE_TRIGGER_SECS") {
+            config.pre_trigger = Duration::from_secs(secs);
+        }
+        if let Some(secs) = var::<u64>("RTAC_PQ_POST_TRIGGER_SECS") {
+            config.post_trigger = Duration::from_secs(secs);
+        }
+        Some(config)
+    }
+
+    fn sag_threshold_v(&self) -> f32 {
+        self.nominal_voltage_v * self.sag_threshold
+    }
+
+    /// Whether `reading` is outside the threshold for `kind`.
+    fn fires(&self, kind: PowerQualityEventKind, reading: &RtacReading) -> bool {
+        match kind {
+            PowerQualityEventKind::VoltageSag => reading.voltage_v < self.sag_threshold_v(),
+            PowerQualityEventKind::FrequencyExcursion => {
+                (reading.grid_frequency_hz - self.nominal_frequency_hz).abs()
+                    > self.frequency_deviation_hz
+            }
+        }
+    }
+}
+
+/// One full-rate reading in an event's window.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PowerQualitySample {
+    #[ts(type = "string")]
+    pub timestamp: DateTime<Utc>,
+    pub voltage_v: f32,
+    pub current_a: f32,
+    pub grid_frequency_hz: f32,
+    pub power_kw: f32,
+}
+
+impl From<&RtacReading> for PowerQualitySample {
+    fn from(reading: &RtacReading) -> Self {
+        Self {
+            timestamp: reading.timestamp,
+            voltage_v: reading.voltage_v,
+            current_a: reading.current_a,
+            grid_frequency_hz: reading.grid_frequency_hz,
+            power_kw: reading.power_kw,
+        }
+    }
+}
+
+/// A finished capture, ready to store.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedEvent {
+    pub kind: PowerQualityEventKind,
+    pub triggered_at: DateTime<Utc>,
+    pub nominal_value: f64,
+    pub threshold: f64,
+    /// Lowest voltage, or the frequency furthest from nominal, in the window
+    pub extreme_value: f64,
+    pub samples: Vec<PowerQualitySample>,
+}
+
+struct ActiveCapture {
+    event: CapturedEvent,
+    ends_at: DateTime<Utc>,
+}
+
+/// Watches full-rate readings for triggers and collects each event's window.
+pub struct EventCapture {
+    config: PqCaptureConfig,
+    active: Option<ActiveCapture>,
+    /// Kinds that have fired and not yet returned within their threshold
+    latched: Vec<PowerQualityEventKind>,
+}
+
+impl EventCapture {
+    pub fn new(config: PqCaptureConfig) -> Self {
+        Self {
+            config,
+            active: None,
+            latched: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &PqCaptureConfig {
+        &self.config
+    }
+
+    /// Check the next reading, given the readings before it (oldest first).
+    /// Returns an event once its post-trigger window is complete.
+    pub fn observe(
+        &mut self,
+        reading: &RtacReading,
+        history: &VecDeque<RtacReading>,
+    ) -> Option<CapturedEvent> {
+        let mut triggered = None;
+        for kind in [PowerQualityEventKind::VoltageSag, PowerQualityEventKind::FrequencyExcursion] {
+            let fires = self.config.fires(kind, reading);
+            let latched = self.latched.contains(&kind);
+            if !fires {
+                self.latched.retain(|k| *k != kind);
+            } else if !latched && self.active.is_none() && triggered.is_none() {
+                self.latched.push(kind);
+                triggered = Some(kind);
+            }
+        }
+
+        if let Some(kind) = triggered {
+            let pre_trigger = chrono::Duration::from_std(self.config.pre_trigger)
+                .unwrap_or(chrono::Duration::zero());
+            let post_trigger = chrono::Duration::from_std(self.config.post_trigger)
+                .unwrap_or(chrono::Duration::zero());
+            let (nominal_value, threshold) = match kind {
+                PowerQualityEventKind::VoltageSag => {
+                    (self.config.nominal_voltage_v, self.config.sag_threshold_v())
+                }
+                PowerQualityEventKind::FrequencyExcursion => {
+                    (self.config.nominal_frequency_hz, self.config.frequency_deviation_hz)
+                }
+            };
+            let samples = history
+                .iter()
+                .filter(|r| r.timestamp >= reading.timestamp - pre_trigger)
+                .map(PowerQualitySample::from)
+                .collect();
+            self.active = Some(ActiveCapture {
+                event: CapturedEvent {
+                    kind,
+                    triggered_at: reading.timestamp,
+                    nominal_value: nominal_value as f64,
+                    threshold: threshold as f64,
+                    extreme_value: self.extreme_of(kind, None, reading),
+                    samples,
+                },
+                ends_at: reading.timestamp + post_trigger,
+            });
+        }
+
+        let event = &self.active.as_ref()?.event;
+        let extreme = self.extreme_of(event.kind, Some(event.extreme_value), reading);
+        let active = self.active.as_mut()?;
+        active.event.samples.push(PowerQualitySample::from(reading));
+        active.event.extreme_value = extreme;
+        if reading.timestamp >= active.ends_at {
+            return self.finish();
+        }
+        None
+    }
+
+    /// End the capture in progress, if any, with the readings so far.
+    pub fn finish(&mut self) -> Option<CapturedEvent> {
+        self.active.take().map(|active| active.event)
+    }
+
+    /// The more extreme of `current` and `reading`'s value for `kind`.
+    fn extreme_of(
+        &self,
+        kind: PowerQualityEventKind,
+        current: Option<f64>,
+        reading: &RtacReading,
+    ) -> f64 {
+        match kind {
+            PowerQualityEventKind::VoltageSag => {
+                let voltage = reading.voltage_v as f64;
+                current.map_or(voltage, |v| v.min(voltage))
+            }
+            PowerQualityEventKind::FrequencyExcursion => {
+                let nominal = self.config.nominal_frequency_hz as f64;
+                let frequency = reading.grid_frequency_hz as f64;
+                match current {
+                    Some(f) if (f - nominal).abs() >= (frequency - nominal).abs() => f,
+                    _ => frequency,
+                }
+            }
+        }
+    }
+}
+
+/// A stored event, without its samples.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PowerQualityEvent {
+    pub id: i32,
+    pub source_id: i32,
+    pub kind: PowerQualityEventKind,
+    #[ts(type = "string")]
+    pub triggered_at: NaiveDateTime,
+    #[ts(type = "string")]
+    pub window_start: NaiveDateTime,
+    #[ts(type = "string")]
+    pub window_end: NaiveDateTime,
+    /// Nominal voltage or frequency
+    pub nominal_value: f64,
+    /// Sag voltage, or allowed frequency deviation
+    pub threshold: f64,
+    /// Lowest voltage, or the frequency furthest from nominal, in the window
+    pub extreme_value: f64,
+    pub sample_count: i32,
+}
+
+/// A stored event with its samples.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PowerQualityWaveform {
+    #[serde(flatten)]
+    #[ts(flatten)]
+    pub event: PowerQualityEvent,
+    pub samples: Vec<PowerQualitySample>,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = schema::power_quality_events)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+struct EventRow {
+    id: i32,
+    source_id: i32,
+    kind: String,
+    triggered_at: NaiveDateTime,
+    window_start: NaiveDateTime,
+    window_end: NaiveDateTime,
+    nominal_value: f64,
+    threshold: f64,
+    extreme_value: f64,
+    sample_count: i32,
+}
+
+impl EventRow {
+    fn into_event(self) -> DataResult<PowerQualityEvent> {
+        Ok(PowerQualityEvent {
+            id: self.id,
+            source_id: self.source_id,
+            kind: self.kind.parse()?,
+            triggered_at: self.triggered_at,
+            window_start: self.window_start,
+            window_end: self.window_end,
+            nominal_value: self.nominal_value,
+            threshold: self.threshold,
+            extreme_value: self.extreme_value,
+            sample_count: self.sample_count,
+        })
+    }
+}
+
+/// Store a captured event for `source_id`, returning its id.
+pub fn insert_event(
+    connection: &mut SqliteConnection,
+    source_id: i32,
+    event: &CapturedEvent,
+) -> DataResult<i32> {
+    use schema::power_quality_events::dsl;
+
+    let (Some(first), Some(last)) = (event.samples.first(), event.samples.last()) else {
+        return Err("power-quality event has no samples".into());
+    };
+    let samples = serde_json::to_string(&event.samples)?;
+    connection.transaction(|conn| {
+        diesel::insert_into(dsl::power_quality_events)
+            .values((
+                dsl::source_id.eq(source_id),
+                dsl::kind.eq(event.kind.as_str()),
+                dsl::triggered_at.eq(event.triggered_at.naive_utc()),
+                dsl::window_start.eq(first.timestamp.naive_utc()),
+                dsl::window_end.eq(last.timestamp.naive_utc()),
+                dsl::nominal_value.eq(event.nominal_value),
+                dsl::threshold.eq(event.threshold),
+                dsl::extreme_value.eq(event.extreme_value),
+                dsl::sample_count.eq(event.samples.len() as i32),
+                dsl::samples.eq(samples),
+            ))
+            .execute(conn)?;
+        Ok(dsl::power_quality_events.select(dsl::id).order(dsl::id.desc()).first(conn)?)
+    })
+}
+
+/// Ids of the sources belonging to `site_id`.
+fn site_source_ids(connection: &mut SqliteConnection, site_id: i32) -> DataResult<Vec<i32>> {
+    use schema::sources::dsl;
+
+    Ok(dsl::sources
+        .filter(dsl::site_id.eq(site_id))
+        .select(dsl::id)
+        .load::<Option<i32>>(connection)?
+        .into_iter()
+        .flatten()
+        .collect())
+}
+
+/// `site_id`'s events, most recent first: those triggered at or after
+/// `since` and of `kind`, where given, and at most `limit` of them.
+pub fn list_events(
+    connection: &mut SqliteConnection,
+    site_id: i32,
+    since: Option<NaiveDateTime>,
+    kind: Option<PowerQualityEventKind>,
+    limit: i64,
+) -> DataResult<Vec<PowerQualityEvent>> {
+    use schema::power_quality_events::dsl;
+
+    let source_ids = site_source_ids(connection, site_id)?;
+    let mut query = dsl::power_quality_events
+        .filter(dsl::source_id.eq_any(source_ids))
+        .select(EventRow::as_select())
+        .into_boxed();
+    if let Some(since) = since {
+        query = query.filter(dsl::triggered_at.ge(since));
+    }
+    if let Some(kind) = kind {
+        query = query.filter(dsl::kind.eq(kind.as_str()));
+    }
+    query
+        .order((dsl::triggered_at.desc(), dsl::id.desc()))
+        .limit(limit.clamp(1, MAX_LIST_LIMIT))
+        .load(connection)?
+        .into_iter()
+        .map(EventRow::into_event)
+        .collect()
+}
+
+/// Event `id` with its samples, if it belongs to one of `site_id`'s sources.
+pub fn get_waveform(
+    connection: &mut SqliteConnection,
+    site_id: i32,
+    id: i32,
+) -> DataResult<Option<PowerQualityWaveform>> {
+    use schema::power_quality_events::dsl;
+
+    let source_ids = site_source_ids(connection, site_id)?;
+    let row = dsl::power_quality_events
+        .find(id)
+        .filter(dsl::source_id.eq_any(source_ids))
+        .select((EventRow::as_select(), dsl::samples))
+        .first::<(EventRow, String)>(connection)
+        .optional()?;
+    let Some((row, samples)) = row else {
+        return Ok(None);
+    };
+    Ok(Some(PowerQualityWaveform {
+        event: row.into_event()?,
+        samples: serde_json::from_str(&samples)?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use diesel_migrations::MigrationHarness;
+
+    use super::*;
+    use crate::{MIGRATIONS, rtac::ALARM_REGISTER_COUNT};
+
+    fn connection() -> SqliteConnection {
+        let mut connection = SqliteConnection::establish(":memory:").unwrap();
+        connection.run_pending_migrations(MIGRATIONS).unwrap();
+        connection
+    }
+
+    fn reading(tenths: i64, voltage_v: f32, grid_frequency_hz: f32) -> RtacReading {
+        RtacReading {
+            timestamp: Utc.with_ymd_and_hms(2024, 6, 15, 12, 0, 0).unwrap()
+                + chrono::Duration::milliseconds(tenths * 100),
+            soc_percent: 50.0,
+            power_kw: 100.0,
+            mode: "discharging".to_string(),
+            voltage_v,
+            current_a: 200.0,
+            temperature_c: 25.0,
+            grid_frequency_hz,
+            alarm_registers: [0u16; ALARM_REGISTER_COUNT],
+            sequence: tenths as u64,
+        }
+    }
+
+    /// Feed `readings` through a capture the way the storage task does,
+    /// returning the events it finishes.
+    fn run(capture: &mut EventCapture, readings: Vec<RtacReading>) -> Vec<CapturedEvent> {
+        let mut history = VecDeque::new();
+        let mut events = Vec::new();
+        for reading in readings {
+            events.extend(capture.observe(&reading, &history));
+            history.push_back(reading);
+        }
+        events
+    }
+
+    fn config() -> PqCaptureConfig {
+        PqCaptureConfig {
+            pre_trigger: Duration::from_millis(300),
+            post_trigger: Duration::from_millis(500),
+            ..PqCaptureConfig::new(480.0)
+        }
+    }
+
+    #[test]
+    fn test_sag_captures_pre_and_post_window() {
+        let mut capture = EventCapture::new(config());
+        // 1s normal, a 300ms sag to 350V, then normal again
+        let readings = (0..30)
+            .map(|i| {
+                let voltage = match i {
+                    10 => 420.0,
+                    11 => 350.0,
+                    12 => 400.0,
+                    _ => 480.0,
+                };
+                reading(i, voltage, 60.0)
+            })
+            .collect();
+        let events = run(&mut capture, readings);
+
+        assert_eq!(events.len(), 1, "one event per excursion");
+        let event = &events[0];
+        assert_eq!(event.kind, PowerQualityEventKind::VoltageSag);
+        assert_eq!(event.triggered_at, reading(10, 0.0, 0.0).timestamp);
+        assert_eq!(event.threshold, 432.0);
+        assert_eq!(event.extreme_value, 350.0);
+        // 300ms before (7, 8, 9), the trigger (10) and 500ms after (11..=15)
+        assert_eq!(event.samples.len(), 9);
+        assert_eq!(event.samples[0].timestamp, reading(7, 0.0, 0.0).timestamp);
+        assert_eq!(event.samples.last().unwrap().timestamp, reading(15, 0.0, 0.0).timestamp);
+    }
+
+    #[test]
+    fn test_frequency_excursion_rearms_after_recovery() {
+        let mut capture = EventCapture::new(config());
+        let readings = (0..40)
+            .map(|i| {
+                let frequency = match i {
+                    5..=7 => 59.2,
+                    8 => 59.0,
+                    25..=26 => 60.7,
+                    _ => 60.0,
+                };
+                reading(i, 480.0, frequency)
+            })
+            .collect();
+        let events = run(&mut capture, readings);
+
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|e| e.kind == PowerQualityEventKind::FrequencyExcursion));
+        assert_eq!(events[0].extreme_value as f32, 59.0);
+        assert_eq!(events[1].extreme_value as f32, 60.7);
+    }
+
+    #[test]
+    fn test_finish_returns_partial_capture() {
+        let mut capture = EventCapture::new(config());
+        let events = run(&mut capture, vec![reading(0, 480.0, 60.0), reading(1, 100.0, 60.0)]);
+        assert!(events.is_empty());
+        let event = capture.finish().expect("capture in progress");
+        assert_eq!(event.samples.len(), 2);
+        assert!(capture.finish().is_none());
+    }
+
+    #[test]
+    fn test_store_and_list_events() {
+        let mut conn = connection();
+        let source = |name: &str, site_id: i32| crate::NewSource {
+            name: name.to_string(),
+            description: None,
+            active: Some(false),
+            interval_seconds: Some(1),
+            test_type: Some("charging_state".to_string()),
+            arguments: None,
+            site_id: Some(site_id),
+            company_id: Some(1),
+            priority: None,
+            point_fields: None,
+        };
+        let here = crate::create_source(&mut conn, source("rtac", 7)).unwrap().id.unwrap();
+        let elsewhere = crate::create_source(&mut conn, source("other", 8)).unwrap().id.unwrap();
+
+        let mut capture = EventCapture::new(config());
+        let mut readings: Vec<_> = (0..10).map(|i| reading(i, 480.0, 60.0)).collect();
+        readings[3].voltage_v = 300.0;
+        let event = run(&mut capture, readings).remove(0);
+        let id = insert_event(&mut conn, here, &event).unwrap();
+        insert_event(&mut conn, elsewhere, &event).unwrap();
+
+        let listed = list_events(&mut conn, 7, None, None, 100).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, id);
+        assert_eq!(listed[0].sample_count, event.samples.len() as i32);
+        let frequency = Some(PowerQualityEventKind::FrequencyExcursion);
+        assert!(list_events(&mut conn, 7, None, frequency, 100).unwrap().is_empty());
+
+        let waveform = get_waveform(&mut conn, 7, id).unwrap().unwrap();
+        assert_eq!(waveform.samples, event.samples);
+        assert!(get_waveform(&mut conn, 8, id).unwrap().is_none());
+    }
+}
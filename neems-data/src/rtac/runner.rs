@@ -14,24 +14,31 @@ use std::{
     env,
     error::Error,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
-use diesel::{Connection, sqlite::SqliteConnection};
+use tokio::sync::{RwLock, watch};
 use tracing::{error, info};
 
 use super::{
     alarms::{AlarmConfig, AlarmHandlerTask, create_alarm_channel},
     control::{ControlConfig, ControlLogicTask},
+    latency::{ControlLatency, run_latency_publisher},
+    power_quality::PqCaptureConfig,
     schedule_http::{ApiClientConfig, HttpScheduleProvider, run_active_command_poller},
-    state::PendingCommand,
+    state::{PendingCommand, RtacState},
     storage::{DatabaseStorageBackend, StorageConfig, StorageWriterTask, create_storage_channel},
-    worker::{ModbusWorker, RtacConfig, create_worker_channels},
+    worker::{ModbusWorker, RtacConfig, ShutdownReason, create_worker_channels},
 };
-use crate::{NewSource, create_source, get_source_by_name};
+use crate::{NewSource, create_source, encryption, get_source_by_name};
 
 /// Name of the source the RTAC worker writes SoC readings to.
 const RTAC_SOURCE_NAME: &str = "rtac";
 
+/// Default number of full-rate (10Hz) reads averaged into each stored
+/// reading, i.e. one row per second.
+const DEFAULT_STORAGE_DECIMATION: i32 = 10;
+
 type DynError = Box<dyn Error + Send + Sync>;
 
 /// Read an integer environment variable, falling back to `default`.
@@ -45,7 +52,7 @@ fn env_i32(key: &str, default: i32) -> i32 {
 /// poller does not also write to it — the RTAC worker is its sole writer. The
 /// SoC history endpoint serves its readings regardless of the `active` flag.
 fn ensure_rtac_source(database_url: &str, site_id: i32, company_id: i32) -> Result<i32, DynError> {
-    let mut conn = SqliteConnection::establish(database_url)?;
+    let mut conn = encryption::establish(database_url)?;
 
     if let Some(existing) = get_source_by_name(&mut conn, RTAC_SOURCE_NAME)? {
         if let Some(id) = existing.id {
@@ -62,90 +69,213 @@ fn ensure_rtac_source(database_url: &str, site_id: i32, company_id: i32) -> Resu
         arguments: None,
         site_id: Some(site_id),
         company_id: Some(company_id),
+        priority: None,
+        point_fields: None,
     };
 
     let source = create_source(&mut conn, new_source)?;
     source.id.ok_or_else(|| "created RTAC source has no id".into())
 }
 
-/// Run the RTAC collector until the worker stops.
+/// Everything the RTAC collector needs to run.
+#[derive(Debug, Clone)]
+pub struct RtacCollectorConfig {
+    /// Site database URL (e.g. `sqlite:///app/data/...`)
+    pub database_url: String,
+    /// Site the readings and schedule belong to
+    pub site_id: i32,
+    /// Company the RTAC source is created under
+    pub company_id: i32,
+    /// Number of reads averaged into each stored row
+    pub decimation: usize,
+    pub rtac: RtacConfig,
+    pub api: ApiClientConfig,
+    pub control: ControlConfig,
+    /// Power-quality event capture; off when `None`
+    pub power_quality: Option<PqCaptureConfig>,
+    /// Longest a command may take from being issued to being acknowledged
+    pub latency_budget: Duration,
+}
+
+impl RtacCollectorConfig {
+    /// Build the configuration for `database_url` from the environment.
+    ///
+    /// Honors `NEEMS_DEFAULT_SITE`, `NEEMS_DEFAULT_COMPANY` and
+    /// `RTAC_STORAGE_DECIMATION`, plus the variables read by
+    /// [`RtacConfig::from_env`], [`ApiClientConfig::from_env`],
+    /// [`PqCaptureConfig::from_env`] and [`ControlLatency::budget_from_env`].
+    pub fn from_env(database_url: String) -> Self {
+        let site_id = env_i32("NEEMS_DEFAULT_SITE", 1);
+        Self {
+            database_url,
+            site_id,
+            company_id: env_i32("NEEMS_DEFAULT_COMPANY", 1),
+            decimation: env_i32("RTAC_STORAGE_DECIMATION", DEFAULT_STORAGE_DECIMATION).max(1)
+                as usize,
+            rtac: RtacConfig::from_env(),
+            api: ApiClientConfig::from_env(site_id),
+            control: ControlConfig::default(),
+            power_quality: PqCaptureConfig::from_env(),
+            latency_budget: ControlLatency::budget_from_env(),
+        }
+    }
+}
+
+/// A started RTAC collector: the storage, alarm, poller and control tasks are
+/// running, and [`run`](Self::run) drives the Modbus worker.
 ///
-/// `database_url` is the site database URL (e.g. `sqlite:///app/data/...`).
-/// The RTAC endpoint and slave id come from the environment via
-/// [`RtacConfig::from_env`].
-pub async fn run_rtac_collector(database_url: String) -> Result<(), DynError> {
-    let site_id = env_i32("NEEMS_DEFAULT_SITE", 1);
-    let company_id = env_i32("NEEMS_DEFAULT_COMPANY", 1);
+/// The Modbus client context is not `Send`, so the worker runs on the task
+/// that calls `run` rather than being spawned.
+pub struct RtacCollector {
+    source_id: i32,
+    worker: ModbusWorker,
+    state: Arc<RwLock<RtacState>>,
+    commands: watch::Receiver<Option<PendingCommand>>,
+    // Held for the lifetime of the collector so the worker's shutdown channel
+    // is not seen as closed.
+    _shutdown_tx: watch::Sender<bool>,
+}
 
-    // Ensure the destination source exists (blocking DB work off the runtime).
-    let source_id = {
-        let database_url = database_url.clone();
-        tokio::task::spawn_blocking(move || ensure_rtac_source(&database_url, site_id, company_id))
+impl RtacCollector {
+    /// Ensure the RTAC source exists and spawn the supporting tasks onto the
+    /// current runtime.
+    pub async fn start(config: RtacCollectorConfig) -> Result<Self, DynError> {
+        let RtacCollectorConfig {
+            database_url,
+            site_id,
+            company_id,
+            decimation,
+            rtac: mut rtac_config,
+            api: api_config,
+            control: control_config,
+            power_quality,
+            latency_budget,
+        } = config;
+
+        // Ensure the destination source exists (blocking DB work off the
+        // runtime).
+        let source_id = {
+            let database_url = database_url.clone();
+            tokio::task::spawn_blocking(move || {
+                ensure_rtac_source(&database_url, site_id, company_id)
+            })
             .await??
-    };
+        };
 
-    let config = RtacConfig::from_env();
-    info!(
-        address = %config.rtac_address,
-        slave_id = config.slave_id,
-        source_id,
-        "Starting RTAC collector"
-    );
-
-    // Build the inter-task channels. `command_tx` goes to the control logic,
-    // `command_rx` to the worker; `shutdown_tx` is held for the lifetime of the
-    // collector so the worker's shutdown channel is not seen as closed.
-    let (command_tx, command_rx) = tokio::sync::watch::channel::<Option<PendingCommand>>(None);
-    let (storage_tx, storage_rx) = create_storage_channel(256);
-    let (alarm_tx, alarm_rx) = create_alarm_channel();
-    let (channels, shutdown_tx) = create_worker_channels(command_rx, storage_tx, alarm_tx);
-    // The control logic reads the same shared state the worker updates.
-    let shared_state = channels.state.clone();
-    let _shutdown_tx = shutdown_tx;
-
-    // Storage task: persist readings to the site database.
-    let backend = DatabaseStorageBackend::new(database_url, source_id);
-    let mut storage_task =
-        StorageWriterTask::new(StorageConfig::default(), backend, storage_rx, None);
-    tokio::spawn(async move {
-        if let Err(e) = storage_task.run().await {
-            error!(error = %e, "RTAC storage task stopped");
+        // `decimation` sets how many reads are averaged into each stored row
+        // (keeping per-field min/max). When decimating, the worker forwards
+        // every read to storage instead of sampling every Nth one; a value of
+        // 1 restores plain sampling.
+        let decimation = decimation.max(1);
+        if decimation > 1 {
+            rtac_config.storage_sample_rate = 1;
         }
-    });
+        info!(
+            address = %rtac_config.rtac_address,
+            slave_id = rtac_config.slave_id,
+            source_id,
+            decimation,
+            "Starting RTAC collector"
+        );
 
-    // Alarm task: log alarm transitions.
-    let mut alarm_task = AlarmHandlerTask::new(AlarmConfig::default(), alarm_rx);
-    tokio::spawn(async move {
-        if let Err(e) = alarm_task.run().await {
-            error!(error = %e, "RTAC alarm task stopped");
-        }
-    });
-
-    // Active-command poller: fetch the current schedule command from neems-api
-    // into a shared cache that the control logic reads.
-    let command_cache = Arc::new(Mutex::new(None));
-    let api_config = ApiClientConfig::from_env(site_id);
-    tokio::spawn(run_active_command_poller(api_config, command_cache.clone()));
-
-    // Control logic: turn the active command into RTAC commands (with reactive
-    // SoC/alarm safety overrides) and write them via the command channel.
-    let schedule_provider = HttpScheduleProvider::new(command_cache);
-    let mut control_task = ControlLogicTask::new(
-        ControlConfig::default(),
-        schedule_provider,
-        shared_state,
-        command_tx,
-    );
-    tokio::spawn(async move {
-        if let Err(e) = control_task.run().await {
-            error!(error = %e, "RTAC control logic task stopped");
+        // Build the inter-task channels. `command_tx` goes to the control
+        // logic, `command_rx` to the worker.
+        let (command_tx, command_rx) = watch::channel::<Option<PendingCommand>>(None);
+        let (storage_tx, storage_rx) = create_storage_channel(256);
+        let (alarm_tx, alarm_rx) = create_alarm_channel();
+        let commands = command_rx.clone();
+        let (channels, shutdown_tx) = create_worker_channels(command_rx, storage_tx, alarm_tx);
+        // The control logic reads the same shared state the worker updates.
+        let state = channels.state.clone();
+
+        // Storage task: persist readings to the site database.
+        let backend = DatabaseStorageBackend::new(database_url.clone(), source_id);
+        let mut storage_task =
+            StorageWriterTask::new(StorageConfig::default(), backend, storage_rx, Some(decimation));
+        if let Some(power_quality) = power_quality {
+            info!(
+                nominal_voltage_v = power_quality.nominal_voltage_v,
+                nominal_frequency_hz = power_quality.nominal_frequency_hz,
+                "Capturing power-quality events"
+            );
+            storage_task = storage_task.with_event_capture(power_quality);
         }
-    });
+        tokio::spawn(async move {
+            if let Err(e) = storage_task.run().await {
+                error!(error = %e, "RTAC storage task stopped");
+            }
+        });
+
+        // Alarm task: log alarm transitions.
+        let mut alarm_task = AlarmHandlerTask::new(AlarmConfig::default(), alarm_rx);
+        tokio::spawn(async move {
+            if let Err(e) = alarm_task.run().await {
+                error!(error = %e, "RTAC alarm task stopped");
+            }
+        });
+
+        // Active-command poller: fetch the current schedule command from
+        // neems-api into a shared cache that the control logic reads.
+        let command_cache = Arc::new(Mutex::new(None));
+        tokio::spawn(run_active_command_poller(api_config, command_cache.clone()));
 
-    // Worker loop: reads status, samples to storage, forwards alarm changes,
-    // and writes the active command to the RTAC.
-    let mut worker = ModbusWorker::new(config, channels);
-    worker.run().await?;
+        // Control logic: turn the active command into RTAC commands (with
+        // reactive SoC/alarm safety overrides) and write them via the command
+        // channel.
+        let schedule_provider = HttpScheduleProvider::new(command_cache);
+        let latency = ControlLatency::new(latency_budget);
+        let mut control_task =
+            ControlLogicTask::new(control_config, schedule_provider, state.clone(), command_tx)
+                .with_latency(latency.clone());
+        tokio::spawn(async move {
+            if let Err(e) = control_task.run().await {
+                error!(error = %e, "RTAC control logic task stopped");
+            }
+        });
 
+        // Latency publisher: share the control path's latency with neems-api.
+        tokio::spawn(run_latency_publisher(database_url, site_id, latency.clone()));
+
+        Ok(Self {
+            source_id,
+            worker: ModbusWorker::new(rtac_config, channels).with_latency(latency),
+            state,
+            commands,
+            _shutdown_tx: shutdown_tx,
+        })
+    }
+
+    /// Id of the source readings are stored under.
+    pub fn source_id(&self) -> i32 {
+        self.source_id
+    }
+
+    /// The RTAC state the worker updates after each read.
+    pub fn state(&self) -> Arc<RwLock<RtacState>> {
+        self.state.clone()
+    }
+
+    /// The command the control logic last handed the worker.
+    pub fn commands(&self) -> watch::Receiver<Option<PendingCommand>> {
+        self.commands.clone()
+    }
+
+    /// Run the worker loop: reads status, samples to storage, forwards alarm
+    /// changes, and writes the active command to the RTAC.
+    pub async fn run(mut self) -> Result<ShutdownReason, DynError> {
+        self.worker.run().await
+    }
+}
+
+/// Run the RTAC collector until the worker stops.
+///
+/// `database_url` is the site database URL (e.g. `sqlite:///app/data/...`).
+/// Everything else comes from the environment via
+/// [`RtacCollectorConfig::from_env`].
+pub async fn run_rtac_collector(database_url: String) -> Result<(), DynError> {
+    RtacCollector::start(RtacCollectorConfig::from_env(database_url))
+        .await?
+        .run()
+        .await?;
     Ok(())
 }
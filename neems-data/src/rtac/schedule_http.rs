@@ -99,6 +99,9 @@ impl ScheduleProvider for HttpScheduleProvider {
 #[derive(Debug, Deserialize)]
 struct ActiveCommandResponse {
     command: Option<WireCommand>,
+    /// "schedule" or "maintenance"; absent from older servers.
+    #[serde(default)]
+    source: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -188,6 +191,9 @@ async fn fetch_active_command(
             return Err(false);
         }
     };
+    if parsed.source.as_deref() == Some("maintenance") {
+        debug!("Site is under maintenance; holding in standby");
+    }
     Ok(parsed.command.and_then(WireCommand::into_scheduled))
 }
 
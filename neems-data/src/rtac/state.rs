@@ -5,6 +5,7 @@
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
 
 use super::{
     alarm_definitions::{
@@ -49,6 +50,37 @@ impl AlarmFlags {
         self.registers
     }
 
+    /// Parse the `alarm_registers` array of a stored reading's data, as
+    /// written by the RTAC collector and the simulator
+    pub fn from_reading(data: &JsonValue) -> Option<Self> {
+        let values = data.get("alarm_registers")?.as_array()?;
+        if values.len() != ALARM_REGISTER_COUNT {
+            return None;
+        }
+        let mut registers = [0u16; ALARM_REGISTER_COUNT];
+        for (register, value) in registers.iter_mut().zip(values) {
+            *register = u16::try_from(value.as_u64()?).ok()?;
+        }
+        Some(Self::from_registers(&registers))
+    }
+
+    /// Alarms raised or cleared since `last`, each with whether it's now
+    /// active. Without a `last` (a source seen for the first time) only the
+    /// active alarms are listed.
+    pub fn changes_since(
+        &self,
+        last: Option<&AlarmFlags>,
+    ) -> Vec<(&'static AlarmDefinition, bool)> {
+        ALARM_DEFINITIONS
+            .iter()
+            .filter_map(|def| {
+                let active = self.is_alarm_active(def);
+                let was_active = last.map(|last| last.is_alarm_active(def));
+                (was_active.unwrap_or(false) != active).then_some((def, active))
+            })
+            .collect()
+    }
+
     /// Check whether a specific alarm definition is active
     pub fn is_alarm_active(&self, def: &AlarmDefinition) -> bool {
         (self.registers[def.register_index] >> def.bit) & 1 != 0
@@ -150,6 +182,11 @@ pub struct RtacState {
     pub temperature_c: f32,
     /// Grid frequency in Hz
     pub grid_frequency_hz: f32,
+    /// When the worker's watchdog declared communication with the RTAC lost.
+    ///
+    /// While set, the site's actual state is unknown: the RTAC falls back to
+    /// its own failsafe behaviour and the values above are stale.
+    pub failsafe_since: Option<DateTime<Utc>>,
 }
 
 impl Default for RtacState {
@@ -166,6 +203,7 @@ impl Default for RtacState {
             current_a: 0.0,
             temperature_c: 0.0,
             grid_frequency_hz: 0.0,
+            failsafe_since: None,
         }
     }
 }
@@ -176,10 +214,18 @@ impl RtacState {
         Self::default()
     }
 
-    /// Check if the state indicates the system is healthy (connected, no
-    /// critical alarms)
+    /// Check if the site is in an unknown (failsafe) state after losing
+    /// communication with the RTAC
+    pub fn is_failsafe(&self) -> bool {
+        self.failsafe_since.is_some()
+    }
+
+    /// Check if the state indicates the system is healthy (connected, not in
+    /// failsafe, no critical alarms)
     pub fn is_healthy(&self) -> bool {
-        self.connection_status == ConnectionStatus::Connected && !self.alarms.has_critical_alarm()
+        self.connection_status == ConnectionStatus::Connected
+            && !self.is_failsafe()
+            && !self.alarms.has_critical_alarm()
     }
 
     /// Check if the system is available for commands
@@ -420,6 +466,12 @@ mod tests {
 
         state.connection_status = ConnectionStatus::Disconnected;
         assert!(!state.is_healthy());
+
+        // Reconnected but still in failsafe until the watchdog clears it
+        state.connection_status = ConnectionStatus::Connected;
+        state.failsafe_since = Some(Utc::now());
+        assert!(state.is_failsafe());
+        assert!(!state.is_available_for_commands());
     }
 
     #[test]
@@ -4,17 +4,25 @@
 //! - Receives readings from the Modbus worker via mpsc channel
 //! - Batches readings for efficient database writes
 //! - Supports configurable sample rates and decimation
+//! - Keeps a ring buffer of recent full-rate readings in memory, so 10Hz
+//!   collection can be decimated to 1-second averages (with min/max) on write
+//!   without losing sight of short excursions
+//! - Optionally captures power-quality events from that buffer (see
+//!   [`power_quality`](super::power_quality))
 
 use std::{collections::VecDeque, time::Duration};
 
 use chrono::{DateTime, Utc};
-use diesel::{Connection, sqlite::SqliteConnection};
 use serde_json::json;
 use tokio::sync::mpsc;
 use tracing::{debug, error, info};
 
-use super::{alarm_definitions::ALARM_REGISTER_COUNT, state::RtacReading};
-use crate::{insert_readings_batch, models::NewReading};
+use super::{
+    alarm_definitions::ALARM_REGISTER_COUNT,
+    power_quality::{self, CapturedEvent, EventCapture, PqCaptureConfig},
+    state::RtacReading,
+};
+use crate::{encryption, insert_readings_batch, models::NewReading};
 
 /// Configuration for the storage writer task
 #[derive(Debug, Clone)]
@@ -23,6 +31,9 @@ pub struct StorageConfig {
     pub flush_interval: Duration,
     /// Maximum batch size before forcing a flush
     pub max_batch_size: usize,
+    /// Number of full-rate readings kept in the in-memory ring buffer
+    /// (default: 600 = one minute at 10Hz)
+    pub high_res_buffer_size: usize,
 }
 
 impl Default for StorageConfig {
@@ -30,6 +41,7 @@ impl Default for StorageConfig {
         Self {
             flush_interval: Duration::from_secs(1),
             max_batch_size: 100,
+            high_res_buffer_size: 600,
         }
     }
 }
@@ -45,6 +57,8 @@ pub struct StorageStats {
     pub batch_writes: u64,
     /// Number of failed writes
     pub failed_writes: u64,
+    /// Power-quality events captured
+    pub events_captured: u64,
 }
 
 /// Trait for storage backends
@@ -61,6 +75,17 @@ pub trait StorageBackend: Send + Sync {
         &mut self,
         readings: Vec<StorageReading>,
     ) -> impl std::future::Future<Output = Result<(), Box<dyn std::error::Error + Send + Sync>>> + Send;
+
+    /// Store a captured power-quality event asynchronously. Backends that
+    /// don't keep events drop it.
+    fn write_event(
+        &mut self,
+        event: CapturedEvent,
+    ) -> impl std::future::Future<Output = Result<(), Box<dyn std::error::Error + Send + Sync>>> + Send
+    {
+        drop(event);
+        async { Ok(()) }
+    }
 }
 
 /// A reading prepared for storage
@@ -104,12 +129,25 @@ impl From<RtacReading> for StorageReading {
     }
 }
 
+impl From<DecimatedReading> for StorageReading {
+    fn from(decimated: DecimatedReading) -> Self {
+        let mut storage = StorageReading::from(decimated.average);
+        if let Some(data) = storage.data.as_object_mut() {
+            data.insert("sample_count".to_string(), json!(decimated.sample_count));
+            data.insert("min".to_string(), decimated.min.to_json());
+            data.insert("max".to_string(), decimated.max.to_json());
+        }
+        storage
+    }
+}
+
 /// In-memory storage backend for testing
 ///
 /// Uses `VecDeque` for O(1) removal of oldest readings when at capacity.
 pub struct InMemoryStorage {
     readings: VecDeque<StorageReading>,
     max_readings: usize,
+    events: Vec<CapturedEvent>,
 }
 
 impl InMemoryStorage {
@@ -117,6 +155,7 @@ impl InMemoryStorage {
         Self {
             readings: VecDeque::with_capacity(max_readings),
             max_readings,
+            events: Vec::new(),
         }
     }
 
@@ -124,6 +163,10 @@ impl InMemoryStorage {
         self.readings.iter()
     }
 
+    pub fn events(&self) -> &[CapturedEvent] {
+        &self.events
+    }
+
     pub fn len(&self) -> usize {
         self.readings.len()
     }
@@ -151,6 +194,14 @@ impl StorageBackend for InMemoryStorage {
         }
         Ok(())
     }
+
+    async fn write_event(
+        &mut self,
+        event: CapturedEvent,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.events.push(event);
+        Ok(())
+    }
 }
 
 /// Database-backed storage that writes RTAC readings to the site SQLite DB.
@@ -192,10 +243,11 @@ impl StorageBackend for DatabaseStorageBackend {
                         timestamp: Some(r.timestamp.naive_utc()),
                         data: r.data.to_string(),
                         quality_flags: None,
+                        device_timestamp: Some(r.timestamp.naive_utc()),
                     })
                     .collect();
 
-                let mut conn = SqliteConnection::establish(&database_url)?;
+                let mut conn = encryption::establish(&database_url)?;
                 insert_readings_batch(&mut conn, new_readings)?;
                 Ok(())
             },
@@ -204,6 +256,82 @@ impl StorageBackend for DatabaseStorageBackend {
 
         Ok(())
     }
+
+    async fn write_event(
+        &mut self,
+        event: CapturedEvent,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let database_url = self.database_url.clone();
+        let source_id = self.source_id;
+
+        tokio::task::spawn_blocking(
+            move || -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+                let mut conn = encryption::establish(&database_url)?;
+                power_quality::insert_event(&mut conn, source_id, &event)?;
+                Ok(())
+            },
+        )
+        .await??;
+
+        Ok(())
+    }
+}
+
+/// Per-field extremes of the numeric RTAC values within a decimation window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReadingExtremes {
+    pub soc_percent: f32,
+    pub power_kw: f32,
+    pub voltage_v: f32,
+    pub current_a: f32,
+    pub temperature_c: f32,
+    pub grid_frequency_hz: f32,
+}
+
+impl ReadingExtremes {
+    fn from_reading(r: &RtacReading) -> Self {
+        Self {
+            soc_percent: r.soc_percent,
+            power_kw: r.power_kw,
+            voltage_v: r.voltage_v,
+            current_a: r.current_a,
+            temperature_c: r.temperature_c,
+            grid_frequency_hz: r.grid_frequency_hz,
+        }
+    }
+
+    fn combine(self, r: &RtacReading, pick: fn(f32, f32) -> f32) -> Self {
+        Self {
+            soc_percent: pick(self.soc_percent, r.soc_percent),
+            power_kw: pick(self.power_kw, r.power_kw),
+            voltage_v: pick(self.voltage_v, r.voltage_v),
+            current_a: pick(self.current_a, r.current_a),
+            temperature_c: pick(self.temperature_c, r.temperature_c),
+            grid_frequency_hz: pick(self.grid_frequency_hz, r.grid_frequency_hz),
+        }
+    }
+
+    fn to_json(self) -> serde_json::Value {
+        json!({
+            "soc_percent": self.soc_percent,
+            "power_kw": self.power_kw,
+            "voltage_v": self.voltage_v,
+            "current_a": self.current_a,
+            "temperature_c": self.temperature_c,
+            "grid_frequency_hz": self.grid_frequency_hz,
+        })
+    }
+}
+
+/// The result of decimating a window of readings: the averaged reading plus
+/// the min/max of each numeric field, so excursions shorter than the window
+/// remain visible after storage.
+#[derive(Debug, Clone)]
+pub struct DecimatedReading {
+    pub average: RtacReading,
+    pub min: ReadingExtremes,
+    pub max: ReadingExtremes,
+    pub sample_count: usize,
 }
 
 /// Data sampler for decimation
@@ -235,11 +363,23 @@ impl DataSampler {
 
     /// Get the averaged/decimated reading and reset the sampler
     pub fn take_averaged(&mut self) -> Option<RtacReading> {
-        if self.readings.is_empty() {
-            return None;
-        }
+        self.take_decimated().map(|d| d.average)
+    }
 
-        let count = self.readings.len() as f32;
+    /// Get the averaged reading together with per-field min/max and reset the
+    /// sampler
+    pub fn take_decimated(&mut self) -> Option<DecimatedReading> {
+        let first = self.readings.first()?;
+        let min = self
+            .readings
+            .iter()
+            .fold(ReadingExtremes::from_reading(first), |acc, r| acc.combine(r, f32::min));
+        let max = self
+            .readings
+            .iter()
+            .fold(ReadingExtremes::from_reading(first), |acc, r| acc.combine(r, f32::max));
+        let sample_count = self.readings.len();
+        let count = sample_count as f32;
 
         // Use the timestamp of the last reading
         let timestamp = self.readings.last()?.timestamp;
@@ -270,17 +410,22 @@ impl DataSampler {
 
         self.readings.clear();
 
-        Some(RtacReading {
-            timestamp,
-            soc_percent,
-            power_kw,
-            mode,
-            voltage_v,
-            current_a,
-            temperature_c,
-            grid_frequency_hz,
-            alarm_registers,
-            sequence,
+        Some(DecimatedReading {
+            average: RtacReading {
+                timestamp,
+                soc_percent,
+                power_kw,
+                mode,
+                voltage_v,
+                current_a,
+                temperature_c,
+                grid_frequency_hz,
+                alarm_registers,
+                sequence,
+            },
+            min,
+            max,
+            sample_count,
         })
     }
 }
@@ -293,6 +438,12 @@ pub struct StorageWriterTask<B: StorageBackend> {
     batch: Vec<StorageReading>,
     stats: StorageStats,
     sampler: Option<DataSampler>,
+    /// Ring buffer of the most recent full-rate readings, independent of
+    /// decimation
+    high_res: VecDeque<RtacReading>,
+    capture: Option<EventCapture>,
+    /// Captured events waiting for the next flush
+    events: Vec<CapturedEvent>,
 }
 
 impl<B: StorageBackend> StorageWriterTask<B> {
@@ -303,7 +454,9 @@ impl<B: StorageBackend> StorageWriterTask<B> {
         rx: mpsc::Receiver<RtacReading>,
         decimation_factor: Option<usize>,
     ) -> Self {
-        let sampler = decimation_factor.map(DataSampler::new);
+        // A factor of 0 or 1 means every reading is stored as-is.
+        let sampler = decimation_factor.filter(|&n| n > 1).map(DataSampler::new);
+        let high_res = VecDeque::with_capacity(config.high_res_buffer_size);
 
         Self {
             config,
@@ -312,9 +465,19 @@ impl<B: StorageBackend> StorageWriterTask<B> {
             batch: Vec::new(),
             stats: StorageStats::default(),
             sampler,
+            high_res,
+            capture: None,
+            events: Vec::new(),
         }
     }
 
+    /// Also capture power-quality events, with their pre-trigger readings
+    /// taken from the full-rate ring buffer.
+    pub fn with_event_capture(mut self, config: PqCaptureConfig) -> Self {
+        self.capture = Some(EventCapture::new(config));
+        self
+    }
+
     /// Run the storage writer loop
     pub async fn run(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         info!("Starting storage writer task");
@@ -325,7 +488,7 @@ impl<B: StorageBackend> StorageWriterTask<B> {
             tokio::select! {
                 _ = interval.tick() => {
                     // Periodic flush
-                    if !self.batch.is_empty() {
+                    if !self.batch.is_empty() || !self.events.is_empty() {
                         self.flush_batch().await;
                     }
                 }
@@ -343,6 +506,16 @@ impl<B: StorageBackend> StorageWriterTask<B> {
                         None => {
                             // Channel closed, flush remaining and exit
                             info!("Storage channel closed, flushing remaining readings");
+                            // Don't drop a partially filled decimation window
+                            if let Some(decimated) =
+                                self.sampler.as_mut().and_then(DataSampler::take_decimated)
+                            {
+                                self.batch.push(StorageReading::from(decimated));
+                            }
+                            // Nor an event still collecting its post-trigger window
+                            if let Some(event) = self.capture.as_mut().and_then(EventCapture::finish) {
+                                self.events.push(event);
+                            }
                             self.flush_batch().await;
                             break;
                         }
@@ -356,12 +529,35 @@ impl<B: StorageBackend> StorageWriterTask<B> {
 
     /// Process a reading (potentially with decimation)
     fn process_reading(&mut self, reading: RtacReading) {
+        if let Some(event) = self
+            .capture
+            .as_mut()
+            .and_then(|capture| capture.observe(&reading, &self.high_res))
+        {
+            info!(
+                kind = event.kind.as_str(),
+                triggered_at = %event.triggered_at,
+                extreme_value = event.extreme_value,
+                samples = event.samples.len(),
+                "Captured power-quality event"
+            );
+            self.stats.events_captured += 1;
+            self.events.push(event);
+        }
+
+        if self.config.high_res_buffer_size > 0 {
+            if self.high_res.len() >= self.config.high_res_buffer_size {
+                self.high_res.pop_front();
+            }
+            self.high_res.push_back(reading.clone());
+        }
+
         if let Some(ref mut sampler) = self.sampler {
             sampler.add(reading);
 
             if sampler.is_ready() {
-                if let Some(averaged) = sampler.take_averaged() {
-                    self.batch.push(StorageReading::from(averaged));
+                if let Some(decimated) = sampler.take_decimated() {
+                    self.batch.push(StorageReading::from(decimated));
                 }
             }
         } else {
@@ -371,6 +567,12 @@ impl<B: StorageBackend> StorageWriterTask<B> {
 
     /// Flush the current batch to storage
     async fn flush_batch(&mut self) {
+        for event in std::mem::take(&mut self.events) {
+            if let Err(e) = self.backend.write_event(event).await {
+                error!(error = %e, "Failed to store power-quality event");
+            }
+        }
+
         if self.batch.is_empty() {
             return;
         }
@@ -397,6 +599,11 @@ impl<B: StorageBackend> StorageWriterTask<B> {
     pub fn stats(&self) -> &StorageStats {
         &self.stats
     }
+
+    /// Recent full-rate readings, oldest first
+    pub fn recent_high_res(&self) -> impl Iterator<Item = &RtacReading> {
+        self.high_res.iter()
+    }
 }
 
 /// Create a channel for sending readings to the storage task
@@ -446,6 +653,93 @@ mod tests {
         assert_eq!(averaged.sequence, 3);
     }
 
+    #[test]
+    fn test_data_sampler_keeps_min_max() {
+        let mut sampler = DataSampler::new(3);
+        sampler.add(make_test_reading(50.0, 100.0, 1));
+        // A short power excursion inside the window
+        sampler.add(make_test_reading(50.0, 400.0, 2));
+        sampler.add(make_test_reading(50.0, -200.0, 3));
+
+        let decimated = sampler.take_decimated().unwrap();
+        assert_eq!(decimated.sample_count, 3);
+        assert!((decimated.average.power_kw - 100.0).abs() < 0.01);
+        assert_eq!(decimated.min.power_kw, -200.0);
+        assert_eq!(decimated.max.power_kw, 400.0);
+        assert!(sampler.take_decimated().is_none(), "sampler should reset");
+
+        let stored = StorageReading::from(decimated);
+        assert_eq!(stored.data["sample_count"], 3);
+        assert_eq!(stored.data["min"]["power_kw"], -200.0);
+        assert_eq!(stored.data["max"]["power_kw"], 400.0);
+        assert_eq!(stored.data["level"], 50.0);
+    }
+
+    #[tokio::test]
+    async fn test_writer_decimates_and_buffers_high_res() {
+        let config = StorageConfig {
+            high_res_buffer_size: 5,
+            ..StorageConfig::default()
+        };
+        let (tx, rx) = create_storage_channel(64);
+        let mut task = StorageWriterTask::new(config, InMemoryStorage::new(100), rx, Some(10));
+
+        // 2.5 seconds of 10Hz reads
+        for i in 0..25 {
+            tx.send(make_test_reading(50.0, i as f32, i)).await.unwrap();
+        }
+        drop(tx);
+        task.run().await.unwrap();
+
+        assert_eq!(task.stats().readings_received, 25);
+        // Two full windows plus the partial one flushed on shutdown
+        assert_eq!(task.stats().readings_written, 3);
+        let recent: Vec<u64> = task.recent_high_res().map(|r| r.sequence).collect();
+        assert_eq!(recent, vec![20, 21, 22, 23, 24]);
+
+        let stored: Vec<_> = task.backend.readings().collect();
+        assert_eq!(stored[0].data["min"]["power_kw"], 0.0);
+        assert_eq!(stored[0].data["max"]["power_kw"], 9.0);
+        assert_eq!(stored[2].data["sample_count"], 5);
+    }
+
+    #[tokio::test]
+    async fn test_writer_captures_power_quality_events() {
+        let config = PqCaptureConfig {
+            pre_trigger: Duration::from_millis(200),
+            post_trigger: Duration::from_millis(300),
+            ..PqCaptureConfig::new(480.0)
+        };
+        let (tx, rx) = create_storage_channel(64);
+        let mut task = StorageWriterTask::new(
+            StorageConfig::default(),
+            InMemoryStorage::new(100),
+            rx,
+            Some(10),
+        )
+        .with_event_capture(config);
+
+        for i in 0..20 {
+            let mut reading = make_test_reading(50.0, 100.0, i);
+            reading.timestamp += chrono::Duration::milliseconds(i as i64 * 100);
+            if i == 5 {
+                reading.voltage_v = 300.0;
+            }
+            tx.send(reading).await.unwrap();
+        }
+        drop(tx);
+        task.run().await.unwrap();
+
+        assert_eq!(task.stats().events_captured, 1);
+        let events = task.backend.events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].extreme_value, 300.0);
+        // Two readings before, the trigger and three after
+        assert_eq!(events[0].samples.len(), 6);
+        // Decimated storage is unaffected
+        assert_eq!(task.stats().readings_written, 2);
+    }
+
     #[tokio::test]
     async fn test_in_memory_storage() {
         let mut storage = InMemoryStorage::new(100);
@@ -2,17 +2,22 @@
 //!
 //! This module implements the main worker task that handles:
 //! - 10Hz read operations for data collection and alarm monitoring
-//! - 2Hz write operations for schedule command execution
+//! - 2Hz write operations for schedule command execution, with a new command
+//!   written on the next tick rather than waiting for the next write slot
 //! - Time-slotted single worker pattern (reads every tick, writes every 5th
 //!   tick)
+//! - A communication watchdog that puts the site into a failsafe state after
+//!   repeated failures and re-syncs the commanded state once comms recover
+//! - Timing command writes and acknowledgements against the control path's
+//!   latency budget (see [`super::latency`])
 
 use std::{
     net::{SocketAddr, ToSocketAddrs},
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use tokio::{
     sync::{RwLock, mpsc, watch},
     time::{Interval, MissedTickBehavior, interval},
@@ -33,6 +38,7 @@ pub enum ShutdownReason {
 use super::{
     alarm_definitions::ALARM_DEFINITIONS,
     alarms::Alarm,
+    latency::{BudgetAlarm, ControlLatency},
     modbus_client::{ModbusClient, ModbusClientConfig},
     protocol::ParsedStatus,
     state::{AlarmFlags, ConnectionStatus, PendingCommand, RtacReading, RtacState},
@@ -61,6 +67,9 @@ pub struct RtacConfig {
     pub storage_sample_rate: u32,
     /// Log read values every N reads (default: 10 = 1Hz logging)
     pub log_sample_rate: u32,
+    /// Consecutive failed reads/writes before communication with the RTAC is
+    /// declared lost (default: 30 = 3s at 10Hz)
+    pub watchdog_failure_threshold: u32,
 }
 
 impl Default for RtacConfig {
@@ -76,6 +85,7 @@ impl Default for RtacConfig {
             reconnect_delay: Duration::from_secs(1),
             storage_sample_rate: 10, // 1Hz storage
             log_sample_rate: 10,     // 1Hz logging
+            watchdog_failure_threshold: 30,
         }
     }
 }
@@ -175,6 +185,59 @@ pub struct WorkerStats {
     pub failed_writes: u64,
     /// Number of reconnection attempts
     pub reconnect_attempts: u64,
+    /// Number of times the watchdog declared communication lost
+    pub watchdog_trips: u64,
+    /// Number of times the commanded state was re-synced after recovery
+    pub resyncs: u64,
+}
+
+/// Watchdog on communication with the RTAC
+///
+/// Counts consecutive failed operations. Once `threshold` of them fail in a
+/// row the watchdog trips, and it stays tripped until a read succeeds.
+#[derive(Debug, Clone)]
+pub struct CommsWatchdog {
+    threshold: u32,
+    consecutive_failures: u32,
+    tripped_at: Option<DateTime<Utc>>,
+}
+
+impl CommsWatchdog {
+    /// Create a watchdog tripping after `threshold` consecutive failures
+    pub fn new(threshold: u32) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            consecutive_failures: 0,
+            tripped_at: None,
+        }
+    }
+
+    /// Record a failed operation, returning true if it tripped the watchdog
+    pub fn record_failure(&mut self, now: DateTime<Utc>) -> bool {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        if self.tripped_at.is_none() && self.consecutive_failures >= self.threshold {
+            self.tripped_at = Some(now);
+            return true;
+        }
+        false
+    }
+
+    /// Record a successful read, returning when the watchdog tripped if this
+    /// recovers it
+    pub fn record_success(&mut self) -> Option<DateTime<Utc>> {
+        self.consecutive_failures = 0;
+        self.tripped_at.take()
+    }
+
+    /// Number of operations that have failed in a row
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures
+    }
+
+    /// When the watchdog tripped, if it is tripped
+    pub fn tripped_at(&self) -> Option<DateTime<Utc>> {
+        self.tripped_at
+    }
 }
 
 /// The main Modbus worker task
@@ -186,6 +249,10 @@ pub struct ModbusWorker {
     tick_count: u64,
     sequence: u64,
     last_alarm_flags: AlarmFlags,
+    watchdog: CommsWatchdog,
+    /// Set when comms recover, until the commanded state is written again
+    resync_pending: bool,
+    latency: ControlLatency,
 }
 
 impl ModbusWorker {
@@ -201,6 +268,7 @@ impl ModbusWorker {
         };
 
         Self {
+            watchdog: CommsWatchdog::new(config.watchdog_failure_threshold),
             config,
             client: ModbusClient::new(client_config),
             channels,
@@ -208,9 +276,18 @@ impl ModbusWorker {
             tick_count: 0,
             sequence: 0,
             last_alarm_flags: AlarmFlags::default(),
+            resync_pending: false,
+            latency: ControlLatency::default(),
         }
     }
 
+    /// Record command writes and acknowledgements in `latency`, shared with
+    /// the control logic.
+    pub fn with_latency(mut self, latency: ControlLatency) -> Self {
+        self.latency = latency;
+        self
+    }
+
     /// Run the worker loop
     ///
     /// This is the main entry point that runs the 10Hz loop. The loop will
@@ -257,8 +334,15 @@ impl ModbusWorker {
                     // Perform read operation (every tick = 10Hz)
                     let read_success = self.perform_read().await;
 
-                    // Perform write operation (every Nth tick = 2Hz)
-                    if self.tick_count.is_multiple_of(self.config.write_every_n_ticks as u64) {
+                    // Perform write operation (every Nth tick = 2Hz, or straight
+                    // away when re-syncing after comms recovered or when the
+                    // control logic has issued a new command)
+                    let command_changed =
+                        self.channels.command_rx.has_changed().unwrap_or(false);
+                    if self.resync_pending
+                        || command_changed
+                        || self.tick_count.is_multiple_of(self.config.write_every_n_ticks as u64)
+                    {
                         self.perform_write().await;
                     }
 
@@ -294,6 +378,12 @@ impl ModbusWorker {
 
                 // Update shared state
                 self.update_state(&status).await;
+                self.check_latency_budget(&status);
+
+                // Leave failsafe if comms had been lost
+                if let Some(since) = self.watchdog.record_success() {
+                    self.recover_from_failsafe(since).await;
+                }
 
                 // Check for alarm changes
                 self.check_alarms(&status).await;
@@ -333,6 +423,7 @@ impl ModbusWorker {
 
                 // Update connection status in shared state
                 self.update_connection_status(self.client.connection_status()).await;
+                self.record_comms_failure().await;
 
                 false
             }
@@ -343,22 +434,32 @@ impl ModbusWorker {
     async fn perform_write(&mut self) {
         // Check if there's a new command
         let command = {
-            let command_ref = self.channels.command_rx.borrow();
+            let command_ref = self.channels.command_rx.borrow_and_update();
             command_ref.clone()
         };
 
         if let Some(ref cmd) = command {
             self.stats.total_writes += 1;
 
+            let started = Instant::now();
             match self.client.write_command(cmd).await {
                 Ok(()) => {
                     self.stats.successful_writes += 1;
+                    self.latency.command_written(cmd.command_type, started, Instant::now());
                     info!(
                         command_type = %cmd.command_type,
                         target_soc = ?cmd.target_soc_percent,
                         duration = ?cmd.duration_seconds,
                         "Command executed successfully"
                     );
+                    if self.resync_pending {
+                        self.resync_pending = false;
+                        self.stats.resyncs += 1;
+                        info!(
+                            command_type = %cmd.command_type,
+                            "Re-synced commanded state after RTAC communication recovered"
+                        );
+                    }
                 }
                 Err(e) => {
                     self.stats.failed_writes += 1;
@@ -367,6 +468,7 @@ impl ModbusWorker {
                         command_type = %cmd.command_type,
                         "Command execution failed"
                     );
+                    self.record_comms_failure().await;
                 }
             }
         }
@@ -388,6 +490,25 @@ impl ModbusWorker {
         state.sequence = self.sequence;
     }
 
+    /// Check whether the status read acknowledges the command in flight, and
+    /// raise or clear the latency budget alarm
+    fn check_latency_budget(&self, status: &ParsedStatus) {
+        let alarm = match self.latency.status_read(status.mode, Instant::now()) {
+            Some(BudgetAlarm::Raised(elapsed)) => {
+                Alarm::latency_budget_exceeded().with_message(&format!(
+                    "Command not acknowledged within the {}ms budget ({}ms)",
+                    self.latency.budget().as_millis(),
+                    elapsed.as_millis()
+                ))
+            }
+            Some(BudgetAlarm::Cleared(_)) => Alarm::latency_budget_restored(),
+            None => return,
+        };
+        if let Err(e) = self.channels.alarm_tx.send(alarm) {
+            error!(error = %e, "Failed to send latency budget alarm");
+        }
+    }
+
     /// Update only the connection status in shared state
     async fn update_connection_status(&self, status: ConnectionStatus) {
         let mut state = self.channels.state.write().await;
@@ -395,6 +516,51 @@ impl ModbusWorker {
         state.timestamp = Utc::now();
     }
 
+    /// Count a failed read or write towards the watchdog, entering failsafe
+    /// when it trips
+    async fn record_comms_failure(&mut self) {
+        let now = Utc::now();
+        if !self.watchdog.record_failure(now) {
+            return;
+        }
+
+        self.stats.watchdog_trips += 1;
+        let failures = self.watchdog.consecutive_failures();
+        error!(failures, "RTAC communication lost, site state unknown (failsafe)");
+
+        {
+            let mut state = self.channels.state.write().await;
+            state.failsafe_since = Some(now);
+        }
+        self.resync_pending = false;
+
+        let alarm = Alarm::comms_lost()
+            .with_message(&format!("{} consecutive Modbus operations failed", failures));
+        if let Err(e) = self.channels.alarm_tx.send(alarm) {
+            error!(error = %e, "Failed to send communication loss alarm");
+        }
+    }
+
+    /// Leave failsafe after comms recover, clearing the alarm and scheduling a
+    /// re-sync of the commanded state
+    async fn recover_from_failsafe(&mut self, since: DateTime<Utc>) {
+        let outage = Utc::now() - since;
+        info!(
+            outage_seconds = outage.num_seconds(),
+            "RTAC communication recovered, re-syncing commanded state"
+        );
+
+        {
+            let mut state = self.channels.state.write().await;
+            state.failsafe_since = None;
+        }
+        self.resync_pending = true;
+
+        if let Err(e) = self.channels.alarm_tx.send(Alarm::comms_restored()) {
+            error!(error = %e, "Failed to send communication restored notification");
+        }
+    }
+
     /// Check for alarm changes and send new alarms to the handler
     async fn check_alarms(&mut self, status: &ParsedStatus) {
         let new_flags = AlarmFlags::from_registers(&status.alarm_registers);
@@ -480,6 +646,8 @@ impl ModbusWorker {
             successful_writes = self.stats.successful_writes,
             write_success_rate = format!("{:.1}%", write_success_rate),
             reconnect_attempts = self.stats.reconnect_attempts,
+            watchdog_trips = self.stats.watchdog_trips,
+            resyncs = self.stats.resyncs,
             "Worker statistics"
         );
     }
@@ -488,6 +656,11 @@ impl ModbusWorker {
     pub fn stats(&self) -> &WorkerStats {
         &self.stats
     }
+
+    /// Get the communication watchdog
+    pub fn watchdog(&self) -> &CommsWatchdog {
+        &self.watchdog
+    }
 }
 
 /// Create the channels needed for the worker
@@ -553,6 +726,38 @@ mod tests {
         assert_eq!(config.slave_id, RtacConfig::default().slave_id);
     }
 
+    #[test]
+    fn test_comms_watchdog_trips_and_recovers() {
+        let now = Utc::now();
+        let mut watchdog = CommsWatchdog::new(3);
+
+        // Failures below the threshold don't trip it, and a success resets
+        // the count
+        assert!(!watchdog.record_failure(now));
+        assert!(!watchdog.record_failure(now));
+        assert_eq!(watchdog.record_success(), None);
+        assert_eq!(watchdog.consecutive_failures(), 0);
+
+        assert!(!watchdog.record_failure(now));
+        assert!(!watchdog.record_failure(now));
+        assert!(watchdog.record_failure(now));
+        assert_eq!(watchdog.tripped_at(), Some(now));
+
+        // Further failures don't trip it again
+        assert!(!watchdog.record_failure(now));
+        assert_eq!(watchdog.consecutive_failures(), 4);
+
+        assert_eq!(watchdog.record_success(), Some(now));
+        assert_eq!(watchdog.tripped_at(), None);
+        assert_eq!(watchdog.record_success(), None);
+    }
+
+    #[test]
+    fn test_comms_watchdog_minimum_threshold() {
+        let mut watchdog = CommsWatchdog::new(0);
+        assert!(watchdog.record_failure(Utc::now()));
+    }
+
     #[test]
     fn test_worker_stats_default() {
         let stats = WorkerStats::default();
@@ -0,0 +1,409 @@
+//! Settings that can change while `monitor` runs.
+//!
+//! Restarting the aggregator drops a polling cycle, so the log level, the
+//! writer's batch size and flush interval, and the collector concurrency
+//! limits ([`Tunables`]) are applied live instead. They change in two ways:
+//!
+//! - SIGHUP re-reads the config file (see [`crate::config`]). Environment
+//!   variables still override it, so only settings taken from the file can
+//!   change this way. Other settings in the file need a restart.
+//! - [`RuntimeOverrides`] saved in the `runtime_overrides` table, normally
+//!   through neems-api's `PUT /api/1/DataRuntime`, take precedence over the
+//!   configured values until cleared. The monitor polls the table every
+//!   [`OVERRIDES_POLL_INTERVAL`].
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{mpsc, watch},
+    task,
+};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use ts_rs::TS;
+
+use crate::{
+    DataResult, collectors::TestType, concurrency::ConcurrencyConfig, config::Settings, encryption,
+    logs::BufferLayer, schema, writer::WriterConfig,
+};
+
+/// How often the monitor checks `runtime_overrides` for changes.
+pub const OVERRIDES_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The settings `monitor` applies without restarting.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tunables {
+    /// `tracing` filter directives
+    pub log_level: String,
+    pub flush_interval: Duration,
+    pub batch_size: usize,
+    pub concurrency: ConcurrencyConfig,
+}
+
+impl Tunables {
+    pub fn new(log_level: &str, writer: &WriterConfig, concurrency: &ConcurrencyConfig) -> Self {
+        Self {
+            log_level: log_level.to_string(),
+            flush_interval: writer.flush_interval,
+            batch_size: writer.batch_size,
+            concurrency: concurrency.clone(),
+        }
+    }
+
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self::new(&settings.log_level, &settings.writer, &settings.reader.concurrency)
+    }
+
+    /// These tunables with `overrides` applied.
+    pub fn with_overrides(mut self, overrides: &RuntimeOverrides) -> Self {
+        if let Some(log_level) = &overrides.log_level {
+            self.log_level = log_level.clone();
+        }
+        if let Some(ms) = overrides.flush_interval_ms {
+            self.flush_interval = Duration::from_millis(ms.into());
+        }
+        if let Some(batch_size) = overrides.batch_size {
+            self.batch_size = batch_size as usize;
+        }
+        if let Some(max) = overrides.max_concurrent_collectors {
+            self.concurrency.max_concurrent = max as usize;
+        }
+        if let Some(limits) = &overrides.test_type_concurrency {
+            self.concurrency.per_test_type = limits
+                .iter()
+                .map(|(test_type, &limit)| (test_type.clone(), limit as usize))
+                .collect();
+        }
+        self
+    }
+
+    /// Human-readable descriptions of what differs in `other`, for the log.
+    pub fn changes(&self, other: &Self) -> Vec<String> {
+        let mut changes = Vec::new();
+        if self.log_level != other.log_level {
+            changes.push(format!("log level {} -> {}", self.log_level, other.log_level));
+        }
+        if self.flush_interval != other.flush_interval {
+            changes.push(format!(
+                "flush interval {}ms -> {}ms",
+                self.flush_interval.as_millis(),
+                other.flush_interval.as_millis()
+            ));
+        }
+        if self.batch_size != other.batch_size {
+            changes.push(format!("batch size {} -> {}", self.batch_size, other.batch_size));
+        }
+        if self.concurrency.max_concurrent != other.concurrency.max_concurrent {
+            changes.push(format!(
+                "max concurrent collectors {} -> {}",
+                self.concurrency.max_concurrent, other.concurrency.max_concurrent
+            ));
+        }
+        if self.concurrency.per_test_type != other.concurrency.per_test_type {
+            changes.push(format!(
+                "per-test-type concurrency {:?} -> {:?}",
+                sorted(&self.concurrency.per_test_type),
+                sorted(&other.concurrency.per_test_type)
+            ));
+        }
+        changes
+    }
+}
+
+fn sorted(limits: &HashMap<String, usize>) -> BTreeMap<&str, usize> {
+    limits.iter().map(|(test_type, &limit)| (test_type.as_str(), limit)).collect()
+}
+
+/// Tunables set at runtime; `None` leaves the configured value in place.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RuntimeOverrides {
+    /// `tracing` filter directives, e.g. `info,neems_data=debug`
+    pub log_level: Option<String>,
+    pub flush_interval_ms: Option<u32>,
+    pub batch_size: Option<u32>,
+    pub max_concurrent_collectors: Option<u32>,
+    /// Replaces the configured per-test-type limits as a whole; an empty map
+    /// removes them
+    pub test_type_concurrency: Option<HashMap<String, u32>>,
+    /// When the overrides were last saved (ignored on input)
+    #[serde(default)]
+    pub updated_at: Option<NaiveDateTime>,
+    /// Who last saved them (ignored on input)
+    #[serde(default)]
+    pub updated_by: Option<String>,
+}
+
+impl RuntimeOverrides {
+    /// Every problem with these overrides, or `Ok` if there are none.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if let Some(log_level) = &self.log_level {
+            if let Err(e) = tracing_subscriber::EnvFilter::try_new(log_level) {
+                problems.push(format!("log_level: invalid filter '{}': {}", log_level, e));
+            }
+        }
+        for (name, value) in [
+            ("flush_interval_ms", self.flush_interval_ms),
+            ("batch_size", self.batch_size),
+            ("max_concurrent_collectors", self.max_concurrent_collectors),
+        ] {
+            if value == Some(0) {
+                problems.push(format!("{}: must be at least 1", name));
+            }
+        }
+        for (test_type, &limit) in self.test_type_concurrency.iter().flatten() {
+            if let Err(e) = test_type.parse::<TestType>() {
+                problems.push(format!("test_type_concurrency: {}", e));
+            } else if limit == 0 {
+                problems.push(format!(
+                    "test_type_concurrency: limit for {} must be at least 1",
+                    test_type
+                ));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+#[derive(Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = schema::runtime_overrides)]
+#[diesel(treat_none_as_null = true)]
+struct OverridesRow {
+    id: i32,
+    log_level: Option<String>,
+    flush_interval_ms: Option<i32>,
+    batch_size: Option<i32>,
+    max_concurrent_collectors: Option<i32>,
+    test_type_concurrency: Option<String>,
+    updated_at: NaiveDateTime,
+    updated_by: Option<String>,
+}
+
+/// The saved overrides; all `None` if none have been saved.
+pub fn load_overrides(conn: &mut SqliteConnection) -> DataResult<RuntimeOverrides> {
+    let row: Option<OverridesRow> =
+        schema::runtime_overrides::table.find(1).first(conn).optional()?;
+    let Some(row) = row else {
+        return Ok(RuntimeOverrides::default());
+    };
+    let count = |v: Option<i32>| v.and_then(|v| u32::try_from(v).ok());
+    Ok(RuntimeOverrides {
+        log_level: row.log_level,
+        flush_interval_ms: count(row.flush_interval_ms),
+        batch_size: count(row.batch_size),
+        max_concurrent_collectors: count(row.max_concurrent_collectors),
+        test_type_concurrency: row.test_type_concurrency.map(|spec| {
+            ConcurrencyConfig::parse_per_test_type(&spec)
+                .into_iter()
+                .map(|(test_type, limit)| (test_type, limit as u32))
+                .collect()
+        }),
+        updated_at: Some(row.updated_at),
+        updated_by: row.updated_by,
+    })
+}
+
+/// Validate and save `overrides`, replacing any saved before.
+pub fn save_overrides(
+    conn: &mut SqliteConnection,
+    overrides: &RuntimeOverrides,
+    updated_by: &str,
+) -> DataResult<RuntimeOverrides> {
+    overrides.validate().map_err(|problems| problems.join("; "))?;
+
+    let count = |v: Option<u32>| v.map(|v| v.min(i32::MAX as u32) as i32);
+    let row = OverridesRow {
+        id: 1,
+        log_level: overrides.log_level.clone(),
+        flush_interval_ms: count(overrides.flush_interval_ms),
+        batch_size: count(overrides.batch_size),
+        max_concurrent_collectors: count(overrides.max_concurrent_collectors),
+        test_type_concurrency: overrides.test_type_concurrency.as_ref().map(|limits| {
+            limits
+                .iter()
+                .collect::<BTreeMap<_, _>>()
+                .into_iter()
+                .map(|(test_type, limit)| format!("{}={}", test_type, limit))
+                .collect::<Vec<_>>()
+                .join(",")
+        }),
+        updated_at: Utc::now().naive_utc(),
+        updated_by: Some(updated_by.to_string()),
+    };
+    diesel::insert_into(schema::runtime_overrides::table)
+        .values(&row)
+        .on_conflict(schema::runtime_overrides::id)
+        .do_update()
+        .set(&row)
+        .execute(conn)?;
+    load_overrides(conn)
+}
+
+type SetLogLevel = dyn Fn(&str) -> Result<(), String> + Send + Sync;
+
+/// Changes the active `tracing` filter.
+#[derive(Clone)]
+pub struct LogControl(Arc<SetLogLevel>);
+
+impl LogControl {
+    pub fn new(set: impl Fn(&str) -> Result<(), String> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(set))
+    }
+
+    pub fn set(&self, log_level: &str) -> Result<(), String> {
+        (self.0)(log_level)
+    }
+}
+
+/// Install the global `tracing` subscriber with a filter that can be changed
+/// later, or `None` if a subscriber is already installed.
+pub fn init_tracing(log_level: &str) -> Option<LogControl> {
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::new(log_level))
+        .with_filter_reloading();
+    let handle = builder.reload_handle();
+    // Also keep recent events for the log listener (see [`crate::logs`])
+    builder.finish().with(BufferLayer).try_init().ok()?;
+    Some(LogControl::new(move |log_level| {
+        let filter =
+            tracing_subscriber::EnvFilter::try_new(log_level).map_err(|e| e.to_string())?;
+        handle.reload(filter).map_err(|e| e.to_string())
+    }))
+}
+
+/// Keeps `tunables` current for the rest of the monitor: re-reads the config
+/// file at `config_path` whenever `reload_rx` fires (SIGHUP), and polls the
+/// saved overrides. Runs until the process exits.
+pub async fn run_tunables_loop(
+    database_url: String,
+    config_path: Option<PathBuf>,
+    log_control: Option<LogControl>,
+    tunables: watch::Sender<Tunables>,
+    mut reload_rx: mpsc::Receiver<()>,
+) {
+    let mut configured = tunables.borrow().clone();
+    let mut overrides = RuntimeOverrides::default();
+    let mut poll = tokio::time::interval(OVERRIDES_POLL_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = poll.tick() => {
+                let database_url = database_url.clone();
+                let loaded = task::spawn_blocking(move || {
+                    load_overrides(&mut encryption::establish(&database_url)?)
+                })
+                .await;
+                match loaded {
+                    Ok(Ok(loaded)) => overrides = loaded,
+                    Ok(Err(e)) => eprintln!("Error loading runtime overrides: {}", e),
+                    Err(e) => eprintln!("Runtime overrides task failed: {}", e),
+                }
+            }
+            Some(_) = reload_rx.recv() => {
+                match Settings::load(config_path.as_deref()) {
+                    Ok(settings) => configured = Tunables::from_settings(&settings),
+                    Err(e) => eprintln!("Keeping the current settings: {}", e),
+                }
+            }
+        }
+
+        let effective = configured.clone().with_overrides(&overrides);
+        let current = tunables.borrow().clone();
+        if effective == current {
+            continue;
+        }
+        println!("Applying runtime settings: {}", current.changes(&effective).join(", "));
+        if effective.log_level != current.log_level {
+            if let Some(log_control) = &log_control {
+                if let Err(e) = log_control.set(&effective.log_level) {
+                    eprintln!("Error changing log level: {}", e);
+                }
+            }
+        }
+        tunables.send_replace(effective);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DataAggregator;
+
+    #[test]
+    fn test_overrides_take_precedence() {
+        let configured = Tunables::new(
+            "info",
+            &WriterConfig::default(),
+            &ConcurrencyConfig {
+                max_concurrent: 32,
+                per_test_type: HashMap::from([("ping".to_string(), 8)]),
+            },
+        );
+        let overrides = RuntimeOverrides {
+            log_level: Some("debug".to_string()),
+            batch_size: Some(50),
+            test_type_concurrency: Some(HashMap::new()),
+            ..Default::default()
+        };
+
+        let effective = configured.clone().with_overrides(&overrides);
+        assert_eq!(effective.log_level, "debug");
+        assert_eq!(effective.batch_size, 50);
+        assert_eq!(effective.flush_interval, configured.flush_interval);
+        assert_eq!(effective.concurrency.max_concurrent, 32);
+        assert!(effective.concurrency.per_test_type.is_empty());
+        assert_eq!(configured.changes(&effective).len(), 3);
+        assert_eq!(configured.clone().with_overrides(&RuntimeOverrides::default()), configured);
+    }
+
+    #[test]
+    fn test_save_and_load_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("site.sqlite");
+        let mut conn = DataAggregator::new(Some(&path.to_string_lossy()))
+            .establish_connection()
+            .unwrap();
+        assert_eq!(load_overrides(&mut conn).unwrap(), RuntimeOverrides::default());
+
+        let overrides = RuntimeOverrides {
+            flush_interval_ms: Some(250),
+            test_type_concurrency: Some(HashMap::from([
+                ("ping".to_string(), 4),
+                ("exec".to_string(), 1),
+            ])),
+            ..Default::default()
+        };
+        let saved = save_overrides(&mut conn, &overrides, "admin@example.com").unwrap();
+        assert_eq!(saved.flush_interval_ms, Some(250));
+        assert_eq!(saved.test_type_concurrency, overrides.test_type_concurrency);
+        assert_eq!(saved.updated_by.as_deref(), Some("admin@example.com"));
+        assert!(saved.updated_at.is_some());
+
+        // Saving again replaces the row, clearing what isn't set
+        let cleared = save_overrides(&mut conn, &RuntimeOverrides::default(), "ops").unwrap();
+        assert_eq!(cleared.flush_interval_ms, None);
+        assert_eq!(cleared.test_type_concurrency, None);
+
+        let invalid = RuntimeOverrides {
+            log_level: Some("info,[".to_string()),
+            batch_size: Some(0),
+            test_type_concurrency: Some(HashMap::from([("pnig".to_string(), 2)])),
+            ..Default::default()
+        };
+        assert_eq!(invalid.validate().unwrap_err().len(), 3);
+        assert!(save_overrides(&mut conn, &invalid, "ops").is_err());
+        assert_eq!(load_overrides(&mut conn).unwrap().updated_by.as_deref(), Some("ops"));
+    }
+}
@@ -0,0 +1,286 @@
+//! Dispatch ordering for the reader loop.
+//!
+//! Each pass of the reader loop collects the sources that are due and starts
+//! at most [`ReaderConfig::max_dispatch_per_tick`] of them. When more are due
+//! than that, sources are started in order of *effective* priority:
+//!
+//! - the source's configured `priority` (higher first), plus
+//! - one level for every [`ReaderConfig::aging_interval`] the source has been
+//!   waiting past its due time.
+//!
+//! Aging is the starvation protection: a low-priority daily collector that
+//! keeps losing to a busy (or misconfigured 1-second) high-priority source
+//! climbs in rank until it wins a slot. Ties go to the source that was served
+//! least recently, which round-robins sources of equal rank.
+//!
+//! Due times are measured on the monotonic clock, so a stepped wall clock
+//! neither bursts nor stalls polling; see [`crate::clock`] for how the step
+//! itself is reported.
+
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    time::{Duration, Instant},
+};
+
+use chrono::NaiveDateTime;
+
+use crate::{Source, concurrency::ConcurrencyConfig};
+
+/// Tuning for the reader loop.
+///
+/// Read from the environment by [`ReaderConfig::from_env`]:
+/// - `NEEMS_DATA_MAX_DISPATCH_PER_TICK`: sources started per loop pass
+/// - `NEEMS_DATA_PRIORITY_AGING_SECS`: wait past due time that raises a
+///   source's effective priority by one
+/// - `NEEMS_DATA_CLOCK_SKEW_THRESHOLD_SECS`: device or host clock skew that
+///   triggers a warning
+/// - collector concurrency limits, see [`ConcurrencyConfig::from_env`]
+#[derive(Debug, Clone)]
+pub struct ReaderConfig {
+    pub max_dispatch_per_tick: usize,
+    pub aging_interval: Duration,
+    pub clock_skew_threshold: Duration,
+    pub concurrency: ConcurrencyConfig,
+}
+
+impl Default for ReaderConfig {
+    fn default() -> Self {
+        Self {
+            max_dispatch_per_tick: 64,
+            aging_interval: Duration::from_secs(30),
+            clock_skew_threshold: Duration::from_secs(30),
+            concurrency: ConcurrencyConfig::default(),
+        }
+    }
+}
+
+impl ReaderConfig {
+    /// Build a config from the environment, falling back to [`Default`] for
+    /// anything missing or invalid.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let var =
+            |key: &str| env::var(key).ok().and_then(|v| v.parse::<u64>().ok()).filter(|&v| v > 0);
+
+        Self {
+            max_dispatch_per_tick: var("NEEMS_DATA_MAX_DISPATCH_PER_TICK")
+                .map(|v| v as usize)
+                .unwrap_or(defaults.max_dispatch_per_tick),
+            aging_interval: var("NEEMS_DATA_PRIORITY_AGING_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.aging_interval),
+            clock_skew_threshold: var("NEEMS_DATA_CLOCK_SKEW_THRESHOLD_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.clock_skew_threshold),
+            concurrency: ConcurrencyConfig::from_env(),
+        }
+    }
+}
+
+/// Time since a source last ran, or `None` if it has never run.
+///
+/// Dispatches made by this process are measured on the monotonic clock.
+/// Otherwise the `last_run` loaded from the database is compared with the wall
+/// clock; a `last_run` in the future (the clock was stepped back) is treated
+/// like never having run, so the source isn't stalled until the clock catches
+/// up.
+fn time_since_last_run(
+    source: &Source,
+    last_dispatch: Option<Instant>,
+    now: Instant,
+    wall_now: NaiveDateTime,
+) -> Option<Duration> {
+    match last_dispatch {
+        Some(dispatched) => Some(now.saturating_duration_since(dispatched)),
+        None => source.last_run.and_then(|last_run| (wall_now - last_run).to_std().ok()),
+    }
+}
+
+/// Seconds a source has been waiting past its due time, or `None` if it is
+/// not due yet. Sources that have never run are treated as due since their
+/// interval.
+fn overdue_seconds(source: &Source, since_last_run: Option<Duration>) -> Option<i64> {
+    match since_last_run {
+        Some(since) => {
+            let overdue = since.as_secs() as i64 - source.interval_seconds as i64;
+            (overdue >= 0).then_some(overdue)
+        }
+        None => Some(source.interval_seconds.max(0) as i64),
+    }
+}
+
+/// A source's configured priority raised by how long it has been overdue.
+pub fn effective_priority(source: &Source, overdue_seconds: i64, config: &ReaderConfig) -> i64 {
+    let aging = config.aging_interval.as_secs().max(1) as i64;
+    source.priority as i64 + overdue_seconds / aging
+}
+
+/// Pick the sources to start this pass, in dispatch order.
+///
+/// `last_dispatch` holds the monotonic time each source was last started by
+/// this process, which takes precedence over the `last_run` loaded from the
+/// database. `now` is the current monotonic time and `wall_now` the current
+/// wall-clock time. Sources in `pending` still have a reading in flight and are
+/// skipped.
+pub fn select_due_sources<'a>(
+    sources: &'a [Source],
+    last_dispatch: &HashMap<i32, Instant>,
+    pending: &HashSet<i32>,
+    now: Instant,
+    wall_now: NaiveDateTime,
+    config: &ReaderConfig,
+) -> Vec<&'a Source> {
+    let mut due: Vec<(i64, Option<Duration>, &Source)> = sources
+        .iter()
+        .filter_map(|source| {
+            let id = source.id?;
+            if pending.contains(&id) {
+                return None;
+            }
+            let since = time_since_last_run(source, last_dispatch.get(&id).copied(), now, wall_now);
+            let overdue = overdue_seconds(source, since)?;
+            Some((effective_priority(source, overdue, config), since, source))
+        })
+        .collect();
+
+    // Highest effective priority first; within a rank, least recently run
+    // first (never-run sources sort before everything).
+    due.sort_by(|a, b| {
+        b.0.cmp(&a.0).then_with(|| match (a.1, b.1) {
+            (None, None) => std::cmp::Ordering::Equal,
+            (None, Some(_)) => std::cmp::Ordering::Less,
+            (Some(_), None) => std::cmp::Ordering::Greater,
+            (Some(a), Some(b)) => b.cmp(&a),
+        })
+    });
+    due.truncate(config.max_dispatch_per_tick);
+    due.into_iter().map(|(_, _, source)| source).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn at(seconds: i64) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 8, 4).unwrap().and_hms_opt(0, 0, 0).unwrap()
+            + chrono::Duration::seconds(seconds)
+    }
+
+    fn source(id: i32, interval_seconds: i32, priority: i32, last_run: Option<i64>) -> Source {
+        Source {
+            id: Some(id),
+            name: format!("source_{}", id),
+            description: None,
+            active: true,
+            created_at: at(0),
+            updated_at: at(0),
+            interval_seconds,
+            last_run: last_run.map(at),
+            test_type: None,
+            arguments: None,
+            site_id: None,
+            company_id: None,
+            priority,
+            point_fields: None,
+        }
+    }
+
+    fn ids(selected: Vec<&Source>) -> Vec<i32> {
+        selected.into_iter().filter_map(|s| s.id).collect()
+    }
+
+    #[test]
+    fn test_only_due_and_idle_sources_are_selected() {
+        let sources = vec![
+            source(1, 60, 0, Some(1000)), // not due until 1060
+            source(2, 60, 0, Some(900)),  // due
+            source(3, 60, 0, None),       // never run
+            source(4, 1, 0, Some(900)),   // due but in flight
+        ];
+        let pending = HashSet::from([4]);
+        let config = ReaderConfig::default();
+
+        let now = Instant::now();
+
+        let selected =
+            select_due_sources(&sources, &HashMap::new(), &pending, now, at(1030), &config);
+        assert_eq!(ids(selected), vec![3, 2]);
+
+        // A recorded dispatch overrides the stale last_run from the database
+        let last_dispatch = HashMap::from([(2, now - Duration::from_secs(10))]);
+        let selected =
+            select_due_sources(&sources, &last_dispatch, &pending, now, at(1030), &config);
+        assert_eq!(ids(selected), vec![3]);
+    }
+
+    #[test]
+    fn test_wall_clock_steps_do_not_affect_scheduling() {
+        let sources = vec![source(1, 60, 0, Some(1000)), source(2, 60, 0, Some(1000))];
+        let config = ReaderConfig::default();
+        let now = Instant::now();
+
+        // Source 1 was dispatched 5s ago by this process. The wall clock then
+        // jumped forward an hour; only the monotonic time counts for it.
+        let last_dispatch = HashMap::from([(1, now - Duration::from_secs(5))]);
+        let selected =
+            select_due_sources(&sources, &last_dispatch, &HashSet::new(), now, at(4600), &config);
+        assert_eq!(ids(selected), vec![2]);
+
+        // The wall clock was stepped back before the stored last_run: the
+        // source is treated as never run rather than stalling for an hour.
+        let selected =
+            select_due_sources(&sources, &HashMap::new(), &HashSet::new(), now, at(0), &config);
+        assert_eq!(ids(selected), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_priority_wins_when_saturated() {
+        let sources = vec![
+            source(1, 10, 0, Some(990)),
+            source(2, 10, 5, Some(990)),
+            source(3, 10, 1, Some(990)),
+        ];
+        let config = ReaderConfig {
+            max_dispatch_per_tick: 2,
+            ..ReaderConfig::default()
+        };
+
+        let selected = select_due_sources(
+            &sources,
+            &HashMap::new(),
+            &HashSet::new(),
+            Instant::now(),
+            at(1000),
+            &config,
+        );
+        assert_eq!(ids(selected), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_aging_prevents_starvation() {
+        // A 1-second high-priority source is always due; a daily collector
+        // has been waiting 95 seconds past its due time.
+        let sources = vec![source(1, 1, 3, Some(9_999)), source(2, 86_400, 0, Some(-76_495))];
+        let config = ReaderConfig {
+            max_dispatch_per_tick: 1,
+            aging_interval: Duration::from_secs(30),
+            ..ReaderConfig::default()
+        };
+
+        let selected = select_due_sources(
+            &sources,
+            &HashMap::new(),
+            &HashSet::new(),
+            Instant::now(),
+            at(10_000),
+            &config,
+        );
+        // 95s overdue / 30s aging = +3, tying priority 3; the daily source was
+        // served less recently so it goes first.
+        assert_eq!(ids(selected), vec![2]);
+        assert_eq!(effective_priority(&sources[1], 95, &config), 3);
+    }
+}
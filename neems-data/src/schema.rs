@@ -9,6 +9,175 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    config_agents (origin) {
+        origin -> Text,
+        target_version -> Nullable<Integer>,
+        previous_version -> Nullable<Integer>,
+        rollout_id -> Nullable<Integer>,
+        applied_version -> Nullable<Integer>,
+        last_error -> Nullable<Text>,
+        last_seen_at -> Nullable<Timestamp>,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    config_applied (id) {
+        id -> Integer,
+        version -> Integer,
+        checksum -> Text,
+        source_names -> Text,
+        secret_names -> Text,
+        applied_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    config_bundles (version) {
+        version -> Integer,
+        description -> Nullable<Text>,
+        contents -> Text,
+        checksum -> Text,
+        created_by -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    config_rollouts (id) {
+        id -> Integer,
+        version -> Integer,
+        stages -> Text,
+        current_stage -> Integer,
+        status -> Text,
+        created_by -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    control_latency (site_id) {
+        site_id -> Integer,
+        report -> Text,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    edge_endpoints (site_id) {
+        site_id -> Integer,
+        url -> Text,
+        token -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    forwarders (id) {
+        id -> Integer,
+        site_id -> Integer,
+        name -> Text,
+        kind -> Text,
+        url -> Text,
+        organization -> Nullable<Text>,
+        destination -> Text,
+        token -> Nullable<Text>,
+        enabled -> Bool,
+        batch_size -> Integer,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    maintenance_windows (id) {
+        id -> Integer,
+        site_id -> Integer,
+        device_id -> Nullable<Integer>,
+        starts_at -> Timestamp,
+        ends_at -> Timestamp,
+        reason -> Text,
+        created_by -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    monitor_status (id) {
+        id -> Integer,
+        pid -> Integer,
+        version -> Text,
+        started_at -> Timestamp,
+        last_tick_at -> Nullable<Timestamp>,
+        active_sources -> Integer,
+        collectors_running -> Integer,
+        collectors_queued -> Integer,
+        writer_queue_depth -> Integer,
+        writer_high_water_mark -> Integer,
+        readings_dropped -> BigInt,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    notification_channels (id) {
+        id -> Integer,
+        company_id -> Integer,
+        name -> Text,
+        kind -> Text,
+        destination -> Text,
+        severities -> Text,
+        send_cleared -> Bool,
+        enabled -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        locale -> Text,
+    }
+}
+
+diesel::table! {
+    point_rollups (source_id, field, resolution, bucket) {
+        source_id -> Integer,
+        field -> Text,
+        resolution -> Integer,
+        bucket -> Timestamp,
+        count -> Integer,
+        sum -> Double,
+        min -> Double,
+        max -> Double,
+    }
+}
+
+diesel::table! {
+    power_quality_events (id) {
+        id -> Integer,
+        source_id -> Integer,
+        kind -> Text,
+        triggered_at -> Timestamp,
+        window_start -> Timestamp,
+        window_end -> Timestamp,
+        nominal_value -> Double,
+        threshold -> Double,
+        extreme_value -> Double,
+        sample_count -> Integer,
+        samples -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    reading_points (reading_id, field) {
+        reading_id -> Integer,
+        source_id -> Integer,
+        field -> Text,
+        ts -> Timestamp,
+        value -> Double,
+    }
+}
+
 diesel::table! {
     readings (id) {
         id -> Nullable<Integer>,
@@ -16,6 +185,7 @@ diesel::table! {
         timestamp -> Timestamp,
         data -> Text,
         quality_flags -> Integer,
+        device_timestamp -> Nullable<Timestamp>,
     }
 }
 
@@ -27,6 +197,29 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    runtime_overrides (id) {
+        id -> Integer,
+        log_level -> Nullable<Text>,
+        flush_interval_ms -> Nullable<Integer>,
+        batch_size -> Nullable<Integer>,
+        max_concurrent_collectors -> Nullable<Integer>,
+        test_type_concurrency -> Nullable<Text>,
+        updated_at -> Timestamp,
+        updated_by -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    secrets (name) {
+        name -> Text,
+        nonce -> Binary,
+        ciphertext -> Binary,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     sessions (id) {
         id -> Text,
@@ -64,6 +257,40 @@ diesel::table! {
         arguments -> Nullable<Text>,
         site_id -> Nullable<Integer>,
         company_id -> Nullable<Integer>,
+        priority -> Integer,
+        point_fields -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    sync_cursors (target) {
+        target -> Text,
+        last_reading_id -> Integer,
+        last_synced_at -> Nullable<Timestamp>,
+        last_error -> Nullable<Text>,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    sync_receipts (origin, origin_reading_id) {
+        origin -> Text,
+        origin_reading_id -> Integer,
+        received_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    sync_reconciliations (origin, source_name, day) {
+        origin -> Text,
+        source_name -> Text,
+        day -> Date,
+        edge_count -> Integer,
+        edge_checksum -> Nullable<Text>,
+        central_count -> Integer,
+        central_checksum -> Nullable<Text>,
+        matched -> Bool,
+        checked_at -> Timestamp,
     }
 }
 
@@ -86,6 +313,7 @@ diesel::table! {
     }
 }
 
+diesel::joinable!(config_rollouts -> config_bundles (version));
 diesel::joinable!(readings -> sources (source_id));
 diesel::joinable!(sessions -> users (user_id));
 diesel::joinable!(sites -> companies (company_id));
@@ -94,5 +322,30 @@ diesel::joinable!(user_roles -> users (user_id));
 diesel::joinable!(users -> companies (company_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
-    companies, readings, roles, sessions, sites, sources, user_roles, users,
+    companies,
+    config_agents,
+    config_applied,
+    config_bundles,
+    config_rollouts,
+    control_latency,
+    edge_endpoints,
+    forwarders,
+    maintenance_windows,
+    monitor_status,
+    notification_channels,
+    point_rollups,
+    power_quality_events,
+    reading_points,
+    readings,
+    roles,
+    runtime_overrides,
+    secrets,
+    sessions,
+    sites,
+    sources,
+    sync_cursors,
+    sync_receipts,
+    sync_reconciliations,
+    user_roles,
+    users,
 );
@@ -0,0 +1,454 @@
+//! Encrypted credentials for source arguments.
+//!
+//! Modbus, SNMP and HTTP sources need passwords, community strings and API
+//! keys. Rather than storing them in a source's plaintext `arguments`, they
+//! are kept in the `secrets` table, sealed with AES-256-GCM under a master
+//! key, and referenced by name:
+//!
+//! ```json
+//! {"host": "10.0.0.5", "password": "secret://rtac-main"}
+//! ```
+//!
+//! The master key is 32 bytes, base64-encoded, from `NEEMS_SECRETS_KEY` or a
+//! file named by `NEEMS_SECRETS_KEY_FILE` (see
+//! [`encryption`](crate::encryption) for the file rules). Generate one with
+//! `neems-data secret generate-key`.
+//!
+//! References are resolved only when a collector is built
+//! ([`SecretStore::resolve`]); the resolved values are then redacted from
+//! everything the collector returns, and [`redact_arguments`] masks
+//! credential-looking arguments that were stored in plaintext anyway.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    env,
+    sync::OnceLock,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chrono::{NaiveDateTime, Utc};
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use ring::{
+    aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    rand::{SecureRandom, SystemRandom},
+};
+use serde_json::Value as JsonValue;
+
+use crate::{DataResult, encryption, models::Source, schema};
+
+pub const KEY_ENV: &str = "NEEMS_SECRETS_KEY";
+pub const KEY_FILE_ENV: &str = "NEEMS_SECRETS_KEY_FILE";
+
+/// Prefix of an argument value that refers to a stored secret.
+pub const REFERENCE_PREFIX: &str = "secret://";
+
+/// What redacted values are replaced with.
+pub const REDACTED: &str = "***";
+
+/// Argument names that hold credentials when stored in plaintext.
+const SENSITIVE_KEYS: &[&str] = &[
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "api_key",
+    "apikey",
+    "community",
+    "credential",
+];
+
+/// The AES-256-GCM master key that seals stored secrets.
+pub struct MasterKey(LessSafeKey);
+
+impl std::fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MasterKey(***)")
+    }
+}
+
+impl MasterKey {
+    /// Parse a base64-encoded 32-byte key.
+    pub fn parse(encoded: &str) -> DataResult<Self> {
+        let bytes = BASE64
+            .decode(encoded.trim())
+            .map_err(|_| "Secrets master key is not valid base64")?;
+        let key = UnboundKey::new(&AES_256_GCM, &bytes)
+            .map_err(|_| format!("Secrets master key must be 32 bytes, got {}", bytes.len()))?;
+        Ok(Self(LessSafeKey::new(key)))
+    }
+
+    /// The configured master key, read once per process; `None` when neither
+    /// `NEEMS_SECRETS_KEY` nor `NEEMS_SECRETS_KEY_FILE` is set.
+    pub fn from_env() -> DataResult<Option<&'static Self>> {
+        static KEY: OnceLock<Result<Option<MasterKey>, String>> = OnceLock::new();
+        let key = KEY.get_or_init(|| {
+            let encoded = encryption::read_key(
+                env::var(KEY_ENV).ok(),
+                env::var(KEY_FILE_ENV).ok().as_deref(),
+                KEY_FILE_ENV,
+            )
+            .map_err(|e| e.to_string())?;
+            encoded.map(|e| Self::parse(&e)).transpose().map_err(|e| e.to_string())
+        });
+        match key {
+            Ok(key) => Ok(key.as_ref()),
+            Err(e) => Err(e.clone().into()),
+        }
+    }
+
+    pub(crate) fn seal(&self, name: &str, value: &str) -> DataResult<(Vec<u8>, Vec<u8>)> {
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new().fill(&mut nonce).map_err(|_| "Failed to generate a nonce")?;
+        let mut sealed = value.as_bytes().to_vec();
+        self.0
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(name.as_bytes()),
+                &mut sealed,
+            )
+            .map_err(|_| "Failed to encrypt secret")?;
+        Ok((nonce.to_vec(), sealed))
+    }
+
+    pub(crate) fn open(
+        &self,
+        name: &str,
+        nonce: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<String, String> {
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| format!("Secret '{}' has a malformed nonce", name))?;
+        let mut buffer = ciphertext.to_vec();
+        let plaintext = self
+            .0
+            .open_in_place(nonce, Aad::from(name.as_bytes()), &mut buffer)
+            .map_err(|_| format!("Secret '{}' can't be decrypted with this master key", name))?;
+        String::from_utf8(plaintext.to_vec())
+            .map_err(|_| format!("Secret '{}' is not valid UTF-8", name))
+    }
+}
+
+/// A new random master key, base64-encoded.
+pub fn generate_key() -> DataResult<String> {
+    let mut key = [0u8; 32];
+    SystemRandom::new().fill(&mut key).map_err(|_| "Failed to generate a key")?;
+    Ok(BASE64.encode(key))
+}
+
+/// The secret name an argument value refers to, if it is a reference.
+pub fn reference_name(value: &str) -> Option<&str> {
+    value.strip_prefix(REFERENCE_PREFIX).filter(|name| !name.is_empty())
+}
+
+/// Secret names are limited to characters that are safe in a reference.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 128
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Store `value` under `name`, replacing any existing value.
+pub fn set_secret(
+    connection: &mut SqliteConnection,
+    key: &MasterKey,
+    name: &str,
+    value: &str,
+) -> DataResult<()> {
+    use schema::secrets::dsl;
+
+    if !is_valid_name(name) {
+        return Err(format!("Invalid secret name: {:?}", name).into());
+    }
+    let (nonce, ciphertext) = key.seal(name, value)?;
+    let now = Utc::now().naive_utc();
+    diesel::insert_into(dsl::secrets)
+        .values((
+            dsl::name.eq(name),
+            dsl::nonce.eq(&nonce),
+            dsl::ciphertext.eq(&ciphertext),
+            dsl::created_at.eq(now),
+            dsl::updated_at.eq(now),
+        ))
+        .on_conflict(dsl::name)
+        .do_update()
+        .set((dsl::nonce.eq(&nonce), dsl::ciphertext.eq(&ciphertext), dsl::updated_at.eq(now)))
+        .execute(connection)?;
+    Ok(())
+}
+
+/// Delete a secret. Returns whether it existed.
+pub fn delete_secret(connection: &mut SqliteConnection, name: &str) -> DataResult<bool> {
+    use schema::secrets::dsl;
+
+    let deleted = diesel::delete(dsl::secrets.filter(dsl::name.eq(name))).execute(connection)?;
+    Ok(deleted > 0)
+}
+
+/// Names and timestamps of stored secrets, without their values.
+pub fn list_secrets(
+    connection: &mut SqliteConnection,
+) -> DataResult<Vec<(String, NaiveDateTime, NaiveDateTime)>> {
+    use schema::secrets::dsl;
+
+    Ok(dsl::secrets
+        .select((dsl::name, dsl::created_at, dsl::updated_at))
+        .order(dsl::name.asc())
+        .load(connection)?)
+}
+
+/// Decrypt one secret.
+pub fn get_secret(
+    connection: &mut SqliteConnection,
+    key: &MasterKey,
+    name: &str,
+) -> DataResult<Option<String>> {
+    use schema::secrets::dsl;
+
+    let row = dsl::secrets
+        .filter(dsl::name.eq(name))
+        .select((dsl::nonce, dsl::ciphertext))
+        .first::<(Vec<u8>, Vec<u8>)>(connection)
+        .optional()?;
+    match row {
+        Some((nonce, ciphertext)) => Ok(Some(key.open(name, &nonce, &ciphertext)?)),
+        None => Ok(None),
+    }
+}
+
+/// Decrypted values of the secrets some set of sources refers to.
+///
+/// A secret that is missing or can't be decrypted only fails the sources
+/// that use it, when they are resolved.
+#[derive(Default)]
+pub struct SecretStore {
+    values: HashMap<String, Result<String, String>>,
+}
+
+impl std::fmt::Debug for SecretStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SecretStore").field("names", &self.values.keys()).finish()
+    }
+}
+
+impl SecretStore {
+    /// Load the secrets referenced by `sources`, using the master key from
+    /// the environment. Reads nothing when no source uses a reference.
+    pub fn for_sources(connection: &mut SqliteConnection, sources: &[Source]) -> DataResult<Self> {
+        let names = referenced_names(sources);
+        if names.is_empty() {
+            return Ok(Self::default());
+        }
+        match MasterKey::from_env() {
+            Ok(key) => Self::load(connection, &names, key),
+            Err(e) => {
+                let values = names.into_iter().map(|name| (name, Err(e.to_string()))).collect();
+                Ok(Self { values })
+            }
+        }
+    }
+
+    /// Decrypt the named secrets with `key`. Only a database error fails
+    /// the whole load.
+    pub fn load(
+        connection: &mut SqliteConnection,
+        names: &BTreeSet<String>,
+        key: Option<&MasterKey>,
+    ) -> DataResult<Self> {
+        let mut values = HashMap::new();
+        for name in names {
+            let value = match key {
+                Some(key) => get_secret(connection, key, name)
+                    .map_err(|e| e.to_string())
+                    .and_then(|v| v.ok_or_else(|| format!("Secret '{}' does not exist", name))),
+                None => Err(format!("Secret '{}' is referenced but {} is not set", name, KEY_ENV)),
+            };
+            values.insert(name.clone(), value);
+        }
+        Ok(Self { values })
+    }
+
+    /// A store holding the given values, e.g. for tests.
+    pub fn from_values(values: impl IntoIterator<Item = (String, String)>) -> Self {
+        Self {
+            values: values.into_iter().map(|(name, value)| (name, Ok(value))).collect(),
+        }
+    }
+
+    /// Replace each reference in `arguments` with its value. Returns the
+    /// resolved arguments and the values that were substituted, so they
+    /// can be redacted from output.
+    pub fn resolve(
+        &self,
+        arguments: &HashMap<String, String>,
+    ) -> Result<(HashMap<String, String>, Vec<String>), String> {
+        let mut resolved = arguments.clone();
+        let mut substituted = Vec::new();
+        for (argument, value) in resolved.iter_mut() {
+            let Some(name) = reference_name(value) else {
+                continue;
+            };
+            match self.values.get(name) {
+                Some(Ok(secret)) => {
+                    substituted.push(secret.clone());
+                    *value = secret.clone();
+                }
+                Some(Err(e)) => return Err(format!("Argument '{}': {}", argument, e)),
+                None => {
+                    return Err(format!("Argument '{}': secret '{}' not loaded", argument, name));
+                }
+            }
+        }
+        Ok((resolved, substituted))
+    }
+}
+
+/// Names of the secrets referenced by the arguments of `sources`.
+pub fn referenced_names(sources: &[Source]) -> BTreeSet<String> {
+    sources
+        .iter()
+        .filter_map(|source| source.get_arguments().ok())
+        .flat_map(|arguments| {
+            arguments.into_values().filter_map(|v| reference_name(&v).map(str::to_string))
+        })
+        .collect()
+}
+
+/// Whether an argument name suggests it holds a credential.
+pub fn is_sensitive_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    SENSITIVE_KEYS.iter().any(|sensitive| key.contains(sensitive))
+}
+
+/// `arguments` with credential-looking values masked. References are left
+/// as they are, since they don't reveal the value.
+pub fn redact_arguments(arguments: &HashMap<String, String>) -> HashMap<String, String> {
+    arguments
+        .iter()
+        .map(|(key, value)| {
+            let value = if is_sensitive_key(key) && reference_name(value).is_none() {
+                REDACTED.to_string()
+            } else {
+                value.clone()
+            };
+            (key.clone(), value)
+        })
+        .collect()
+}
+
+/// [`redact_arguments`] for a source's stored arguments JSON.
+pub fn redact_arguments_json(arguments: &str) -> String {
+    match serde_json::from_str::<HashMap<String, String>>(arguments) {
+        Ok(parsed) => serde_json::to_string(&redact_arguments(&parsed))
+            .unwrap_or_else(|_| REDACTED.to_string()),
+        Err(_) => arguments.to_string(),
+    }
+}
+
+/// Masks known secret values wherever they appear in text or JSON.
+#[derive(Clone, Default)]
+pub struct Redactor {
+    values: Vec<String>,
+}
+
+impl std::fmt::Debug for Redactor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Redactor({} values)", self.values.len())
+    }
+}
+
+impl Redactor {
+    /// Values shorter than this aren't redacted: masking every `1` or `on`
+    /// would mangle unrelated output without hiding anything useful.
+    pub const MIN_LENGTH: usize = 4;
+
+    pub fn new(values: Vec<String>) -> Self {
+        let mut values: Vec<String> =
+            values.into_iter().filter(|v| v.len() >= Self::MIN_LENGTH).collect();
+        // Longest first, so a secret containing another is masked whole
+        values.sort_by_key(|v| std::cmp::Reverse(v.len()));
+        values.dedup();
+        Self { values }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn redact_str(&self, text: &str) -> String {
+        self.values
+            .iter()
+            .fold(text.to_string(), |text, value| text.replace(value, REDACTED))
+    }
+
+    /// Redact every string in `value`, including object keys.
+    pub fn redact_json(&self, value: &mut JsonValue) {
+        if self.is_empty() {
+            return;
+        }
+        match value {
+            JsonValue::String(s) => *s = self.redact_str(s),
+            JsonValue::Array(items) => items.iter_mut().for_each(|item| self.redact_json(item)),
+            JsonValue::Object(object) => {
+                let entries = std::mem::take(object);
+                for (key, mut item) in entries {
+                    self.redact_json(&mut item);
+                    object.insert(self.redact_str(&key), item);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> MasterKey {
+        MasterKey::parse(&generate_key().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_seal_round_trip_is_bound_to_name() {
+        let key = key();
+        let (nonce, ciphertext) = key.seal("rtac-main", "hunter22").unwrap();
+        assert_eq!(key.open("rtac-main", &nonce, &ciphertext).unwrap(), "hunter22");
+        assert!(key.open("other", &nonce, &ciphertext).is_err(), "name is authenticated");
+        assert!(self::key().open("rtac-main", &nonce, &ciphertext).is_err(), "wrong key fails");
+        assert!(MasterKey::parse("c2hvcnQ=").is_err(), "short keys are rejected");
+    }
+
+    #[test]
+    fn test_resolve_substitutes_references() {
+        let store = SecretStore::from_values([("rtac-main".to_string(), "hunter22".to_string())]);
+        let arguments = HashMap::from([
+            ("host".to_string(), "10.0.0.5".to_string()),
+            ("password".to_string(), "secret://rtac-main".to_string()),
+        ]);
+        let (resolved, substituted) = store.resolve(&arguments).unwrap();
+        assert_eq!(resolved["password"], "hunter22");
+        assert_eq!(resolved["host"], "10.0.0.5");
+        assert_eq!(substituted, vec!["hunter22"]);
+
+        let missing = HashMap::from([("token".to_string(), "secret://nope".to_string())]);
+        assert!(store.resolve(&missing).is_err());
+    }
+
+    #[test]
+    fn test_redaction() {
+        let arguments = HashMap::from([
+            ("host".to_string(), "10.0.0.5".to_string()),
+            ("snmp_community".to_string(), "public".to_string()),
+            ("api_key".to_string(), "secret://weather".to_string()),
+        ]);
+        let redacted = redact_arguments(&arguments);
+        assert_eq!(redacted["host"], "10.0.0.5");
+        assert_eq!(redacted["snmp_community"], REDACTED);
+        assert_eq!(redacted["api_key"], "secret://weather");
+
+        let redactor = Redactor::new(vec!["hunter22".to_string(), "on".to_string()]);
+        let mut data = serde_json::json!({"error": "login hunter22 failed", "mode": "on"});
+        redactor.redact_json(&mut data);
+        assert_eq!(data, serde_json::json!({"error": "login *** failed", "mode": "on"}));
+    }
+}
@@ -112,6 +112,8 @@ where
                 arguments: Some("{}".to_string()),
                 site_id: Some(site_id),
                 company_id: None,
+                priority: None,
+                point_fields: None,
             };
             let created = create_source(conn, new_source)?;
             let id = created.id.ok_or("create_source returned a row with no id")?;
@@ -153,6 +155,7 @@ where
                 timestamp: Some(cursor),
                 data: make_blob(source_id, cursor.and_utc()),
                 quality_flags: Some(0),
+                device_timestamp: None,
             });
         }
         cursor += interval;
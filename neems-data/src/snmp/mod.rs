@@ -0,0 +1,794 @@
+//! SNMP trap emitter for alarms and offline devices.
+//!
+//! When `NEEMS_SNMP_TARGETS` is set, `monitor` sends SNMPv2c or SNMPv3 traps
+//! to the listed receivers, for NOCs that only consume traps. The traps are
+//! defined by the NEEMS MIB in `neems-data/mibs/NEEMS-MIB.txt`:
+//!
+//! - `neemsAlarmRaised` / `neemsAlarmCleared`: an alarm decoded from a
+//!   reading's `alarm_registers`, as published by [`crate::mqtt`]
+//! - `neemsDeviceOffline` / `neemsDeviceOnline`: a source's collector has
+//!   stopped (or started again) producing readings, judged like the admin
+//!   status page (see [`crate::status::source_health`])
+//!
+//! Alarms follow the readings table with a cursor in `sync_cursors` (under
+//! the target `snmp:<targets>`), so alarms raised while a receiver's address
+//! didn't resolve are sent in order once it does. Traps are unacknowledged
+//! UDP, so a receiver that's down simply misses them; NOC tools are expected
+//! to poll or reconcile for that. A new target starts from the latest
+//! reading, sending each source's active alarms with its next reading, and
+//! every start reports the devices that are offline at the time.
+
+pub mod pdu;
+
+use std::{
+    collections::HashMap,
+    env,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use chrono::{Local, NaiveDateTime, SecondsFormat, Utc};
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use pdu::{AuthProtocol, Oid, TrapPdu, Usm, Value};
+use serde_json::Value as JsonValue;
+use tokio::net::{UdpSocket, lookup_host};
+
+use crate::{
+    DataResult, encryption,
+    mqtt::{latest_reading_id, readings_at},
+    rtac::{AlarmFlags, AlarmSeverity, sld_meta_for},
+    schema,
+    status::{CollectorState, SourceHealth, source_health},
+    sync::{SyncReading, load_cursor, pending_batch, save_cursor},
+};
+
+/// `neemsMIB`, under the Net-SNMP experimental arc (`netSnmpPlaypen`) until
+/// NEEMS has an enterprise number of its own.
+pub const NEEMS_MIB: &[u32] = &[1, 3, 6, 1, 4, 1, 8072, 9, 9999, 1];
+
+/// Enterprise number in the default engine id.
+const ENGINE_ID_ENTERPRISE: u32 = 8072;
+
+/// Trap receivers' port when a target doesn't give one.
+pub const DEFAULT_PORT: u16 = 162;
+
+/// How often sources are checked for going offline.
+const HEALTH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Shortest USM password (RFC 3414 11.2).
+const MIN_PASSWORD_LEN: usize = 8;
+
+/// `neemsNotifications` members.
+const ALARM_RAISED: u32 = 1;
+const ALARM_CLEARED: u32 = 2;
+const DEVICE_OFFLINE: u32 = 3;
+const DEVICE_ONLINE: u32 = 4;
+
+/// `neemsObjects` members.
+const SITE_ID: u32 = 1;
+const COMPANY_ID: u32 = 2;
+const SOURCE: u32 = 3;
+const EVENT_TIME: u32 = 4;
+const ALARM_NUM: u32 = 5;
+const ALARM_NAME: u32 = 6;
+const ALARM_ZONE: u32 = 7;
+const ALARM_SEVERITY: u32 = 8;
+const ALARM_MESSAGE: u32 = 9;
+const LAST_READING_TIME: u32 = 10;
+
+/// SNMP versions traps can be sent as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnmpVersion {
+    V2c,
+    V3,
+}
+
+pub fn parse_version(value: &str) -> Result<SnmpVersion, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "2c" | "v2c" | "2" => Ok(SnmpVersion::V2c),
+        "3" | "v3" => Ok(SnmpVersion::V3),
+        other => Err(format!("version must be 2c or 3, got '{}'", other)),
+    }
+}
+
+/// Split `host[:port],...`, checking each target.
+pub fn parse_targets(spec: &str) -> Result<Vec<String>, String> {
+    let targets = crate::mqtt::parse_list(spec);
+    if targets.is_empty() {
+        return Err("no trap receivers given".to_string());
+    }
+    for target in &targets {
+        let (host, port) = split_target(target);
+        if host.is_empty() || port.parse::<u16>().is_err() {
+            return Err(format!("'{}' is not host or host:port", target));
+        }
+    }
+    Ok(targets)
+}
+
+/// A target's host and port, which may be `[v6 address]:port`.
+fn split_target(target: &str) -> (&str, String) {
+    if let Some(rest) = target.strip_prefix('[') {
+        return match rest.split_once(']') {
+            Some((host, "")) => (host, DEFAULT_PORT.to_string()),
+            Some((host, port)) => (host, port.trim_start_matches(':').to_string()),
+            None => ("", String::new()),
+        };
+    }
+    match target.split_once(':') {
+        Some((host, port)) if !port.contains(':') => (host, port.to_string()),
+        // A bare IPv6 address
+        _ => (target, DEFAULT_PORT.to_string()),
+    }
+}
+
+/// The default engine id: the RFC 3411 text format with the host name.
+fn default_engine_id() -> Vec<u8> {
+    let host = sysinfo::System::host_name().unwrap_or_else(|| "edge".to_string());
+    let mut engine_id = (ENGINE_ID_ENTERPRISE | 0x8000_0000).to_be_bytes().to_vec();
+    engine_id.push(0x04);
+    engine_id.extend(host.bytes().take(27));
+    engine_id
+}
+
+/// Settings for sending traps.
+///
+/// Read from the environment by [`SnmpConfig::from_env`]:
+/// - `NEEMS_SNMP_TARGETS`: trap receivers as `host[:port],...` (port 162 by
+///   default; traps are disabled when unset)
+/// - `NEEMS_SNMP_VERSION`: `2c` (default) or `3`
+/// - `NEEMS_SNMP_COMMUNITY`: community for v2c (default `public`)
+/// - `NEEMS_SNMP_USER`: USM user for v3
+/// - `NEEMS_SNMP_AUTH_PROTOCOL`: `sha` (default) or `sha256`
+/// - `NEEMS_SNMP_AUTH_PASSWORD`: authenticates v3 traps when set
+/// - `NEEMS_SNMP_PRIV_PASSWORD`: also encrypts them, with AES-128
+/// - `NEEMS_SNMP_ENGINE_ID`: v3 engine id in hex (default from the host name;
+///   receivers need it to check v3 traps)
+/// - `NEEMS_SNMP_BATCH_SIZE`: readings per batch
+/// - `NEEMS_SNMP_INTERVAL_SECS`: pause between passes once caught up
+#[derive(Debug, Clone)]
+pub struct SnmpConfig {
+    /// Trap receivers as `host[:port]`
+    pub targets: Vec<String>,
+    pub version: SnmpVersion,
+    pub community: String,
+    pub user: Option<String>,
+    pub auth_protocol: AuthProtocol,
+    pub auth_password: Option<String>,
+    pub priv_password: Option<String>,
+    pub engine_id: Vec<u8>,
+    pub batch_size: usize,
+    pub interval: Duration,
+    /// Longest wait between retries after repeated failures
+    pub max_backoff: Duration,
+}
+
+impl SnmpConfig {
+    pub fn new(targets: Vec<String>) -> Self {
+        Self {
+            targets,
+            version: SnmpVersion::V2c,
+            community: "public".to_string(),
+            user: None,
+            auth_protocol: AuthProtocol::Sha,
+            auth_password: None,
+            priv_password: None,
+            engine_id: default_engine_id(),
+            batch_size: 500,
+            interval: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5 * 60),
+        }
+    }
+
+    /// Build a config from the environment, or `None` if `NEEMS_SNMP_TARGETS`
+    /// is not set.
+    pub fn from_env() -> Option<Self> {
+        let targets = env::var("NEEMS_SNMP_TARGETS").ok().and_then(|v| parse_targets(&v).ok())?;
+        let var =
+            |key: &str| env::var(key).ok().and_then(|v| v.parse::<u64>().ok()).filter(|&v| v > 0);
+
+        let mut config = Self::new(targets);
+        if let Some(version) =
+            env::var("NEEMS_SNMP_VERSION").ok().and_then(|v| parse_version(&v).ok())
+        {
+            config.version = version;
+        }
+        if let Ok(community) = env::var("NEEMS_SNMP_COMMUNITY") {
+            config.community = community;
+        }
+        config.user = env::var("NEEMS_SNMP_USER").ok();
+        if let Some(protocol) =
+            env::var("NEEMS_SNMP_AUTH_PROTOCOL").ok().and_then(|v| v.parse().ok())
+        {
+            config.auth_protocol = protocol;
+        }
+        config.auth_password = env::var("NEEMS_SNMP_AUTH_PASSWORD").ok();
+        config.priv_password = env::var("NEEMS_SNMP_PRIV_PASSWORD").ok();
+        if let Some(engine_id) = env::var("NEEMS_SNMP_ENGINE_ID")
+            .ok()
+            .and_then(|v| pdu::parse_hex(&v).ok().filter(|id| check_engine_id(id).is_ok()))
+        {
+            config.engine_id = engine_id;
+        }
+        if let Some(batch_size) = var("NEEMS_SNMP_BATCH_SIZE") {
+            config.batch_size = batch_size as usize;
+        }
+        if let Some(interval) = var("NEEMS_SNMP_INTERVAL_SECS") {
+            config.interval = Duration::from_secs(interval);
+        }
+        Some(config)
+    }
+
+    /// The emitter's key in `sync_cursors`.
+    pub fn target(&self) -> String {
+        format!("snmp:{}", self.targets.join(","))
+    }
+
+    /// Problems that would stop v3 traps being sent.
+    pub fn check_v3(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.version != SnmpVersion::V3 {
+            return problems;
+        }
+        if self.user.as_deref().is_none_or(str::is_empty) {
+            problems.push("SNMPv3 needs a user".to_string());
+        }
+        if self.priv_password.is_some() && self.auth_password.is_none() {
+            problems.push("a privacy password needs an auth password".to_string());
+        }
+        for (name, password) in [("auth", &self.auth_password), ("privacy", &self.priv_password)] {
+            if password.as_ref().is_some_and(|p| p.len() < MIN_PASSWORD_LEN) {
+                problems.push(format!(
+                    "the {} password must be at least {} characters",
+                    name, MIN_PASSWORD_LEN
+                ));
+            }
+        }
+        problems
+    }
+
+    /// The USM user for v3 traps.
+    fn usm(&self) -> DataResult<Option<Usm>> {
+        if self.version != SnmpVersion::V3 {
+            return Ok(None);
+        }
+        let problems = self.check_v3();
+        if !problems.is_empty() {
+            return Err(problems.join("; ").into());
+        }
+        Ok(Some(Usm::new(
+            &self.engine_id,
+            self.user.as_deref().unwrap_or_default(),
+            self.auth_password.as_deref().map(|password| (self.auth_protocol, password)),
+            self.priv_password.as_deref(),
+        )))
+    }
+}
+
+/// Check an engine id's length (RFC 3411: 5 to 32 octets).
+pub fn check_engine_id(engine_id: &[u8]) -> Result<(), String> {
+    if (5..=32).contains(&engine_id.len()) {
+        Ok(())
+    } else {
+        Err(format!("engine id must be 5 to 32 bytes, got {}", engine_id.len()))
+    }
+}
+
+fn notification(member: u32) -> Oid {
+    Oid::new(NEEMS_MIB).child(&[0, member])
+}
+
+fn object(member: u32, value: Value) -> (Oid, Value) {
+    (Oid::new(NEEMS_MIB).child(&[1, member, 0]), value)
+}
+
+/// `neemsAlarmSeverity` values.
+fn severity_value(severity: AlarmSeverity) -> i64 {
+    match severity {
+        AlarmSeverity::Info => 1,
+        AlarmSeverity::Warning => 2,
+        AlarmSeverity::Critical => 3,
+        AlarmSeverity::Emergency => 4,
+    }
+}
+
+/// The varbinds identifying a source; 0 stands for no site or company.
+fn source_objects(
+    source: &str,
+    site_id: Option<i32>,
+    company_id: Option<i32>,
+) -> Vec<(Oid, Value)> {
+    vec![
+        object(SITE_ID, Value::Integer(site_id.unwrap_or(0).into())),
+        object(COMPANY_ID, Value::Integer(company_id.unwrap_or(0).into())),
+        object(SOURCE, Value::string(source)),
+    ]
+}
+
+/// The alarms sent and sources found offline so far, turning readings and
+/// health checks into traps for what changed.
+#[derive(Debug, Default)]
+pub struct Tracker {
+    /// Per source; absent until a reading with alarm registers has been seen
+    alarms: HashMap<String, AlarmFlags>,
+    /// Per source; absent until its first health check
+    offline: HashMap<String, bool>,
+}
+
+impl Tracker {
+    /// Take `reading`'s alarms as already sent, e.g. the last reading before
+    /// the cursor.
+    pub fn seed(&mut self, reading: &SyncReading) {
+        let data: JsonValue = serde_json::from_str(&reading.data).unwrap_or_default();
+        if let Some(alarms) = AlarmFlags::from_reading(&data) {
+            self.alarms.insert(reading.source_name.clone(), alarms);
+        }
+    }
+
+    /// The traps for alarms `reading` raises or clears.
+    pub fn alarm_traps(&mut self, reading: &SyncReading) -> Vec<TrapPdu> {
+        let data: JsonValue = serde_json::from_str(&reading.data).unwrap_or_default();
+        let Some(alarms) = AlarmFlags::from_reading(&data) else {
+            return Vec::new();
+        };
+        let timestamp = reading.device_timestamp.unwrap_or(reading.timestamp).and_utc();
+        let last = self.alarms.insert(reading.source_name.clone(), alarms);
+
+        let mut traps = Vec::new();
+        for (def, active) in alarms.changes_since(last.as_ref()) {
+            let mut varbinds =
+                source_objects(&reading.source_name, reading.site_id, reading.company_id);
+            varbinds.extend([
+                object(EVENT_TIME, Value::string(timestamp.to_rfc3339())),
+                object(ALARM_NUM, Value::Integer(def.alarm_num.into())),
+                object(ALARM_NAME, Value::string(def.name)),
+                object(ALARM_ZONE, Value::string(def.zone.to_string())),
+                object(
+                    ALARM_SEVERITY,
+                    Value::Integer(severity_value(AlarmSeverity::from_level(def.level))),
+                ),
+                object(
+                    ALARM_MESSAGE,
+                    Value::string(
+                        sld_meta_for(def.alarm_num)
+                            .and_then(|meta| meta.message_opt())
+                            .unwrap_or_default(),
+                    ),
+                ),
+            ]);
+            traps.push(TrapPdu {
+                trap_oid: notification(if active { ALARM_RAISED } else { ALARM_CLEARED }),
+                varbinds,
+            });
+        }
+        traps
+    }
+
+    /// The traps for sources that went offline or came back. A source seen
+    /// for the first time is reported only if it's offline.
+    pub fn health_traps(&mut self, health: &[SourceHealth], now: NaiveDateTime) -> Vec<TrapPdu> {
+        let mut traps = Vec::new();
+        for source in health {
+            let offline = match source.state {
+                CollectorState::Ok => false,
+                CollectorState::Failing | CollectorState::Late => true,
+                CollectorState::NeverRun => continue,
+            };
+            let was_offline = self.offline.insert(source.source.name.clone(), offline);
+            if was_offline == Some(offline) || (was_offline.is_none() && !offline) {
+                continue;
+            }
+            let mut varbinds = source_objects(
+                &source.source.name,
+                source.source.site_id,
+                source.source.company_id,
+            );
+            varbinds.extend([
+                object(
+                    EVENT_TIME,
+                    Value::string(now.and_utc().to_rfc3339_opts(SecondsFormat::Secs, false)),
+                ),
+                object(
+                    LAST_READING_TIME,
+                    Value::string(
+                        source
+                            .last_reading_at
+                            .map(|at| at.and_utc().to_rfc3339())
+                            .unwrap_or_default(),
+                    ),
+                ),
+            ]);
+            traps.push(TrapPdu {
+                trap_oid: notification(if offline {
+                    DEVICE_OFFLINE
+                } else {
+                    DEVICE_ONLINE
+                }),
+                varbinds,
+            });
+        }
+        traps
+    }
+}
+
+/// Sends traps to every configured receiver.
+pub struct TrapSender {
+    config: SnmpConfig,
+    usm: Option<Usm>,
+    started: Instant,
+    /// `snmpEngineBoots`: minutes since the epoch at startup, so it grows
+    /// across restarts without being stored
+    boots: u32,
+    request_id: i32,
+    salt: u64,
+    v4: Option<UdpSocket>,
+    v6: Option<UdpSocket>,
+}
+
+impl TrapSender {
+    pub fn new(config: SnmpConfig) -> DataResult<Self> {
+        let usm = config.usm()?;
+        let now = Utc::now().timestamp();
+        Ok(Self {
+            config,
+            usm,
+            started: Instant::now(),
+            boots: (now / 60) as u32,
+            request_id: 0,
+            // Distinct from the salts used before a restart within the minute
+            salt: (now as u64) << 32,
+            v4: None,
+            v6: None,
+        })
+    }
+
+    /// The message for `trap`, numbered and timestamped.
+    fn encode(&mut self, trap: &TrapPdu) -> Vec<u8> {
+        self.request_id = self.request_id.wrapping_add(1) & i32::MAX;
+        let elapsed = self.started.elapsed();
+        match &self.usm {
+            Some(usm) => {
+                self.salt = self.salt.wrapping_add(1);
+                usm.message(self.request_id, self.boots, elapsed.as_secs() as u32, self.salt, trap)
+            }
+            None => {
+                let uptime = (elapsed.as_millis() / 10) as u32;
+                pdu::v2c_message(&self.config.community, self.request_id, uptime, trap)
+            }
+        }
+    }
+
+    async fn socket(&mut self, addr: &SocketAddr) -> DataResult<&UdpSocket> {
+        let (slot, bind) = if addr.is_ipv4() {
+            (&mut self.v4, "0.0.0.0:0")
+        } else {
+            (&mut self.v6, "[::]:0")
+        };
+        if slot.is_none() {
+            *slot = Some(UdpSocket::bind(bind).await?);
+        }
+        Ok(slot.as_ref().expect("socket was just bound"))
+    }
+
+    /// Send `trap` to every receiver.
+    pub async fn send(&mut self, trap: &TrapPdu) -> DataResult<()> {
+        let message = self.encode(trap);
+        for target in self.config.targets.clone() {
+            let (host, port) = split_target(&target);
+            let addr = lookup_host((host, port.parse::<u16>()?))
+                .await
+                .map_err(|e| format!("Cannot resolve {}: {}", target, e))?
+                .next()
+                .ok_or_else(|| format!("{} has no address", target))?;
+            self.socket(&addr)
+                .await?
+                .send_to(&message, addr)
+                .await
+                .map_err(|e| format!("Cannot send to {}: {}", target, e))?;
+        }
+        Ok(())
+    }
+
+    /// Send alarm traps for batches from `cursor` until caught up. Returns
+    /// the new cursor and the number of traps sent.
+    pub async fn send_from(
+        &mut self,
+        database_url: &str,
+        tracker: &mut Tracker,
+        mut cursor: i32,
+    ) -> DataResult<(i32, usize)> {
+        let target = self.config.target();
+        let mut sent = 0;
+        loop {
+            let readings = {
+                let database_url = database_url.to_string();
+                let batch_size = self.config.batch_size;
+                tokio::task::spawn_blocking(move || {
+                    let mut connection = encryption::establish(&database_url)?;
+                    pending_batch(&mut connection, "", cursor, batch_size)
+                })
+                .await??
+                .readings
+            };
+            let Some(last_id) = readings.last().map(|reading| reading.origin_reading_id) else {
+                return Ok((cursor, sent));
+            };
+
+            for reading in &readings {
+                for trap in tracker.alarm_traps(reading) {
+                    self.send(&trap).await?;
+                    sent += 1;
+                }
+            }
+            cursor = last_id;
+
+            let database_url = database_url.to_string();
+            let target = target.clone();
+            tokio::task::spawn_blocking(move || -> DataResult<()> {
+                let mut connection = encryption::establish(&database_url)?;
+                save_cursor(&mut connection, &target, cursor, None)
+            })
+            .await??;
+        }
+    }
+
+    /// Send traps for sources that went offline or came back.
+    pub async fn send_health(
+        &mut self,
+        database_url: &str,
+        tracker: &mut Tracker,
+    ) -> DataResult<usize> {
+        let now = Utc::now().naive_utc();
+        let database_url = database_url.to_string();
+        let health = tokio::task::spawn_blocking(move || {
+            let mut connection = encryption::establish(&database_url)?;
+            source_health(&mut connection, now)
+        })
+        .await??;
+        let traps = tracker.health_traps(&health, now);
+        for trap in &traps {
+            self.send(trap).await?;
+        }
+        Ok(traps.len())
+    }
+}
+
+/// The stored cursor for `target`, or the latest reading for a new target,
+/// with the tracker seeded from the readings before it.
+fn start_cursor(database_url: &str, target: &str) -> DataResult<(i32, Tracker)> {
+    use schema::sync_cursors::dsl;
+
+    let mut connection = encryption::establish(database_url)?;
+    let stored = dsl::sync_cursors
+        .filter(dsl::target.eq(target))
+        .select(dsl::last_reading_id)
+        .first::<i32>(&mut connection)
+        .optional()?;
+    let mut tracker = Tracker::default();
+    let cursor = match stored {
+        Some(cursor) => {
+            seed(&mut connection, &mut tracker, cursor)?;
+            cursor
+        }
+        None => {
+            let latest = latest_reading_id(&mut connection)?;
+            save_cursor(&mut connection, target, latest, None)?;
+            latest
+        }
+    };
+    Ok((cursor, tracker))
+}
+
+fn seed(connection: &mut SqliteConnection, tracker: &mut Tracker, cursor: i32) -> DataResult<()> {
+    for reading in readings_at(connection, cursor)? {
+        tracker.seed(&reading);
+    }
+    Ok(())
+}
+
+/// Send traps forever, backing off exponentially while receivers can't be
+/// reached.
+pub async fn run_snmp_loop(database_url: String, config: SnmpConfig) {
+    let interval = config.interval;
+    let max_backoff = config.max_backoff;
+    let target = config.target();
+    let mut sender = match TrapSender::new(config) {
+        Ok(sender) => sender,
+        Err(e) => {
+            eprintln!("SNMP traps disabled: {}", e);
+            return;
+        }
+    };
+
+    let mut position: Option<(i32, Tracker)> = None;
+    let mut last_health: Option<Instant> = None;
+    let mut backoff = interval;
+    loop {
+        let result = async {
+            let (cursor, mut tracker) = match position.take() {
+                Some(position) => position,
+                None => {
+                    // A fresh tracker reports offline devices again
+                    last_health = None;
+                    let database_url = database_url.clone();
+                    let target = target.clone();
+                    tokio::task::spawn_blocking(move || start_cursor(&database_url, &target))
+                        .await??
+                }
+            };
+            let (cursor, mut sent) = sender.send_from(&database_url, &mut tracker, cursor).await?;
+            if last_health.is_none_or(|at| at.elapsed() >= HEALTH_INTERVAL) {
+                sent += sender.send_health(&database_url, &mut tracker).await?;
+                last_health = Some(Instant::now());
+            }
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>((cursor, tracker, sent))
+        }
+        .await;
+
+        let wait = match result {
+            Ok((cursor, tracker, sent)) => {
+                if sent > 0 {
+                    tracing::debug!(target = %target, sent, cursor, "Sent SNMP traps");
+                }
+                position = Some((cursor, tracker));
+                backoff = interval;
+                interval
+            }
+            Err(e) => {
+                eprintln!(
+                    "{} - Sending traps to {} failed: {}",
+                    Local::now().to_rfc3339(),
+                    target,
+                    e
+                );
+                let database_url = database_url.clone();
+                let target = target.clone();
+                let error = e.to_string();
+                let _ = tokio::task::spawn_blocking(move || -> DataResult<()> {
+                    let mut connection = encryption::establish(&database_url)?;
+                    let last = load_cursor(&mut connection, &target)?;
+                    save_cursor(&mut connection, &target, last, Some(&error))
+                })
+                .await;
+                let wait = backoff;
+                backoff = (backoff * 2).min(max_backoff);
+                wait
+            }
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::models::Source;
+
+    fn reading(id: i32, active: &[u16]) -> SyncReading {
+        let mut flags = AlarmFlags::default();
+        for alarm_num in active {
+            flags.set_alarm_num(*alarm_num, true);
+        }
+        SyncReading {
+            origin_reading_id: id,
+            source_name: "rtac".to_string(),
+            test_type: Some("charging_state".to_string()),
+            site_id: Some(3),
+            company_id: Some(2),
+            timestamp: chrono::DateTime::from_timestamp(1_800_000_000 + i64::from(id), 0)
+                .unwrap()
+                .naive_utc(),
+            device_timestamp: None,
+            data: json!({ "mode": "Standby", "alarm_registers": flags.to_registers() }).to_string(),
+            quality_flags: 0,
+        }
+    }
+
+    fn health(name: &str, state: CollectorState) -> SourceHealth {
+        let now = Utc::now().naive_utc();
+        SourceHealth {
+            source: Source {
+                id: Some(1),
+                name: name.to_string(),
+                description: None,
+                active: true,
+                created_at: now,
+                updated_at: now,
+                interval_seconds: 10,
+                last_run: Some(now),
+                test_type: None,
+                arguments: None,
+                site_id: Some(3),
+                company_id: None,
+                priority: 0,
+                point_fields: None,
+            },
+            last_reading_at: None,
+            state,
+        }
+    }
+
+    fn value(trap: &TrapPdu, member: u32) -> &Value {
+        let oid = Oid::new(NEEMS_MIB).child(&[1, member, 0]);
+        &trap.varbinds.iter().find(|(name, _)| *name == oid).expect("varbind").1
+    }
+
+    #[test]
+    fn test_targets_and_versions() {
+        assert_eq!(parse_targets("nms, 10.0.0.5:1162").unwrap(), vec!["nms", "10.0.0.5:1162"]);
+        assert!(parse_targets("nms:trap").is_err());
+        assert!(parse_targets(" , ").is_err());
+        assert_eq!(split_target("[fd00::5]:1162"), ("fd00::5", "1162".to_string()));
+        assert_eq!(split_target("fd00::5"), ("fd00::5", "162".to_string()));
+        assert_eq!(parse_version("v3").unwrap(), SnmpVersion::V3);
+        assert!(parse_version("1").is_err());
+
+        let mut config = SnmpConfig::new(vec!["nms".to_string()]);
+        assert_eq!(config.target(), "snmp:nms");
+        assert!(check_engine_id(&config.engine_id).is_ok());
+        config.version = SnmpVersion::V3;
+        config.priv_password = Some("short".to_string());
+        let problems = config.check_v3().join("; ");
+        assert!(problems.contains("needs a user"), "{}", problems);
+        assert!(problems.contains("needs an auth password"), "{}", problems);
+        assert!(problems.contains("privacy password must be at least 8"), "{}", problems);
+    }
+
+    #[test]
+    fn test_tracker_traps_changes() {
+        let mut tracker = Tracker::default();
+
+        // A new source reports only its active alarms
+        let traps = tracker.alarm_traps(&reading(1, &[1]));
+        assert_eq!(traps.len(), 1);
+        assert_eq!(traps[0].trap_oid, notification(ALARM_RAISED));
+        assert_eq!(value(&traps[0], ALARM_NAME), &Value::string("loss_fiber"));
+        assert_eq!(value(&traps[0], ALARM_SEVERITY), &Value::Integer(2));
+        assert_eq!(value(&traps[0], SITE_ID), &Value::Integer(3));
+        assert!(tracker.alarm_traps(&reading(2, &[1])).is_empty());
+
+        let traps = tracker.alarm_traps(&reading(3, &[2]));
+        let oids: Vec<_> = traps.iter().map(|trap| trap.trap_oid.clone()).collect();
+        assert_eq!(oids, vec![notification(ALARM_CLEARED), notification(ALARM_RAISED)]);
+        assert_eq!(value(&traps[1], ALARM_NUM), &Value::Integer(2));
+
+        // A seeded tracker carries on from the last reading sent
+        let mut seeded = Tracker::default();
+        seeded.seed(&reading(3, &[2]));
+        assert_eq!(seeded.alarm_traps(&reading(4, &[])).len(), 1);
+
+        // Devices going offline and coming back
+        let now = Utc::now().naive_utc();
+        let first = [health("ping", CollectorState::Ok), health("rtac", CollectorState::Failing)];
+        let traps = tracker.health_traps(&first, now);
+        assert_eq!(traps.len(), 1);
+        assert_eq!(traps[0].trap_oid, notification(DEVICE_OFFLINE));
+        assert_eq!(value(&traps[0], SOURCE), &Value::string("rtac"));
+        assert!(tracker.health_traps(&first, now).is_empty());
+
+        let next = [health("ping", CollectorState::Late), health("rtac", CollectorState::Ok)];
+        let oids: Vec<_> =
+            tracker.health_traps(&next, now).into_iter().map(|trap| trap.trap_oid).collect();
+        assert_eq!(oids, vec![notification(DEVICE_OFFLINE), notification(DEVICE_ONLINE)]);
+    }
+
+    #[tokio::test]
+    async fn test_sender_delivers_to_receiver() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let target = receiver.local_addr().unwrap().to_string();
+        let mut config = SnmpConfig::new(vec![target]);
+        config.community = "noc".to_string();
+        let mut sender = TrapSender::new(config).unwrap();
+
+        let trap = Tracker::default().alarm_traps(&reading(1, &[1])).remove(0);
+        sender.send(&trap).await.unwrap();
+        let mut buf = [0u8; 2048];
+        let (len, _) = receiver.recv_from(&mut buf).await.unwrap();
+        let message = &buf[..len];
+        let header = [0x02, 0x01, 0x01, 0x04, 0x03, b'n', b'o', b'c']; // v2c, community
+        assert!(message.windows(header.len()).any(|window| window == header));
+        assert!(message.windows(10).any(|window| window == b"loss_fiber"));
+    }
+}
@@ -0,0 +1,353 @@
+//! Diagnostics for the running monitor and the site database.
+//!
+//! neems-api runs in another process and can't see the monitor's scheduler or
+//! queues, so `monitor` publishes a [`MonitorStatus`] snapshot to the
+//! single-row `monitor_status` table every [`STATUS_INTERVAL`]. A stale
+//! `updated_at` means the monitor isn't running; a stale `last_tick_at` with
+//! a fresh `updated_at` means it's running but the reader loop is stuck (see
+//! [`MonitorStatus::state`]).
+//!
+//! [`database_info`] and [`source_health`] describe a database and its
+//! collectors for the same diagnostics.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::{
+    prelude::*,
+    sql_query,
+    sql_types::BigInt,
+    sqlite::{Sqlite, SqliteConnection},
+};
+use diesel_migrations::{EmbeddedMigrations, MigrationHarness};
+use tokio::task;
+
+use crate::{
+    DataResult, concurrency::CollectorMetrics, encryption, models::Source, schema,
+    writer::WriterMetrics,
+};
+
+/// How often the monitor publishes its status.
+pub const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Missed status updates (or scheduler ticks) before the monitor counts as
+/// stopped (or stalled).
+const STALE_AFTER_INTERVALS: u32 = 3;
+
+/// A snapshot of the monitor's scheduler and queues.
+#[derive(Debug, Clone, PartialEq, Queryable, Selectable, Insertable, AsChangeset)]
+#[diesel(table_name = schema::monitor_status)]
+#[diesel(treat_none_as_null = true)]
+pub struct MonitorStatus {
+    pub pid: i32,
+    pub version: String,
+    pub started_at: NaiveDateTime,
+    /// The last pass of the reader loop
+    pub last_tick_at: Option<NaiveDateTime>,
+    pub active_sources: i32,
+    pub collectors_running: i32,
+    /// Collectors waiting for a concurrency permit
+    pub collectors_queued: i32,
+    /// Readings waiting for the writer
+    pub writer_queue_depth: i32,
+    pub writer_high_water_mark: i32,
+    /// Readings dropped because the writer queue was full, since startup
+    pub readings_dropped: i64,
+    pub updated_at: NaiveDateTime,
+}
+
+/// What a [`MonitorStatus`] says about the monitor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MonitorState {
+    Running,
+    /// Publishing status, but the reader loop hasn't come round
+    Stalled,
+    /// No status published recently
+    NotReporting,
+}
+
+impl MonitorStatus {
+    pub fn state(&self, now: NaiveDateTime) -> MonitorState {
+        let stale = |at: NaiveDateTime| {
+            (now - at).to_std().unwrap_or_default() > STATUS_INTERVAL * STALE_AFTER_INTERVALS
+        };
+        if stale(self.updated_at) {
+            MonitorState::NotReporting
+        } else if self.last_tick_at.is_none_or(stale) {
+            MonitorState::Stalled
+        } else {
+            MonitorState::Running
+        }
+    }
+}
+
+/// The monitor's current status, updated by the reader loop and published by
+/// [`run_status_loop`].
+#[derive(Clone)]
+pub struct StatusBoard(Arc<Mutex<MonitorStatus>>);
+
+impl Default for StatusBoard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatusBoard {
+    pub fn new() -> Self {
+        let now = Utc::now().naive_utc();
+        Self(Arc::new(Mutex::new(MonitorStatus {
+            pid: std::process::id() as i32,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            started_at: now,
+            last_tick_at: None,
+            active_sources: 0,
+            collectors_running: 0,
+            collectors_queued: 0,
+            writer_queue_depth: 0,
+            writer_high_water_mark: 0,
+            readings_dropped: 0,
+            updated_at: now,
+        })))
+    }
+
+    /// Record a pass of the reader loop.
+    pub fn tick(
+        &self,
+        active_sources: usize,
+        collectors: &CollectorMetrics,
+        writer: &WriterMetrics,
+    ) {
+        let count = |n: usize| i32::try_from(n).unwrap_or(i32::MAX);
+        let mut status = self.0.lock().unwrap();
+        status.last_tick_at = Some(Utc::now().naive_utc());
+        status.active_sources = count(active_sources);
+        status.collectors_running = count(collectors.running);
+        status.collectors_queued = count(collectors.queued);
+        status.writer_queue_depth = count(writer.queue_depth);
+        status.writer_high_water_mark = count(writer.high_water_mark);
+        status.readings_dropped = i64::try_from(writer.dropped).unwrap_or(i64::MAX);
+    }
+
+    pub fn snapshot(&self) -> MonitorStatus {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// The status the monitor last published, if it ever has.
+pub fn load_status(conn: &mut SqliteConnection) -> DataResult<Option<MonitorStatus>> {
+    Ok(schema::monitor_status::table
+        .find(1)
+        .select(MonitorStatus::as_select())
+        .first(conn)
+        .optional()?)
+}
+
+/// Publish `status`, stamped with the current time.
+pub fn save_status(conn: &mut SqliteConnection, status: &MonitorStatus) -> DataResult<()> {
+    use schema::monitor_status::dsl::*;
+
+    let status = MonitorStatus {
+        updated_at: Utc::now().naive_utc(),
+        ..status.clone()
+    };
+    diesel::insert_into(monitor_status)
+        .values((id.eq(1), &status))
+        .on_conflict(id)
+        .do_update()
+        .set(&status)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Publish the board's status every [`STATUS_INTERVAL`]. Runs until the
+/// process exits.
+pub async fn run_status_loop(database_url: String, board: StatusBoard) {
+    let mut interval = tokio::time::interval(STATUS_INTERVAL);
+    loop {
+        interval.tick().await;
+        let database_url = database_url.clone();
+        let status = board.snapshot();
+        let saved = task::spawn_blocking(move || {
+            save_status(&mut encryption::establish(&database_url)?, &status)
+        })
+        .await;
+        match saved {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => eprintln!("Error publishing monitor status: {}", e),
+            Err(e) => eprintln!("Monitor status task failed: {}", e),
+        }
+    }
+}
+
+/// Size and migration state of a database.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatabaseInfo {
+    /// Size of the main database file, excluding the WAL
+    pub size_bytes: i64,
+    /// Unused pages that a VACUUM would reclaim
+    pub free_bytes: i64,
+    /// The latest applied migration
+    pub migration_version: Option<String>,
+    /// Embedded migrations not yet applied
+    pub pending_migrations: Vec<String>,
+}
+
+#[derive(QueryableByName)]
+struct PageCounts {
+    #[diesel(sql_type = BigInt)]
+    size_bytes: i64,
+    #[diesel(sql_type = BigInt)]
+    free_bytes: i64,
+}
+
+/// Describe the database `conn` is open on, against the `migrations` it
+/// should have.
+pub fn database_info(
+    conn: &mut SqliteConnection,
+    migrations: EmbeddedMigrations,
+) -> DataResult<DatabaseInfo> {
+    let pages: PageCounts = sql_query(
+        "SELECT page_count * page_size AS size_bytes, freelist_count * page_size AS free_bytes \
+         FROM pragma_page_count(), pragma_page_size(), pragma_freelist_count()",
+    )
+    .get_result(conn)?;
+    let migration_version = MigrationHarness::<Sqlite>::applied_migrations(conn)?
+        .into_iter()
+        .max()
+        .map(|version| version.to_string());
+    let pending_migrations = MigrationHarness::<Sqlite>::pending_migrations(conn, migrations)?
+        .iter()
+        .map(|migration| migration.name().to_string())
+        .collect();
+    Ok(DatabaseInfo {
+        size_bytes: pages.size_bytes,
+        free_bytes: pages.free_bytes,
+        migration_version,
+        pending_migrations,
+    })
+}
+
+/// How a source's collector is doing, judged by its interval.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CollectorState {
+    /// A reading arrived within the last two intervals
+    Ok,
+    /// Being run, but no reading has arrived lately: the collector is failing
+    Failing,
+    /// Not run lately: the monitor is down or the source can't get a slot
+    Late,
+    /// Never run
+    NeverRun,
+}
+
+/// An active source and when it last ran and produced a reading.
+#[derive(Debug, Clone)]
+pub struct SourceHealth {
+    pub source: Source,
+    pub last_reading_at: Option<NaiveDateTime>,
+    pub state: CollectorState,
+}
+
+/// Health of every active source as of `now`.
+pub fn source_health(
+    conn: &mut SqliteConnection,
+    now: NaiveDateTime,
+) -> DataResult<Vec<SourceHealth>> {
+    use schema::{readings, sources};
+
+    let active: Vec<Source> = sources::table
+        .filter(sources::active.eq(true))
+        .order(sources::name)
+        .select(Source::as_select())
+        .load(conn)?;
+
+    let mut health = Vec::with_capacity(active.len());
+    for source in active {
+        let last_reading_at: Option<NaiveDateTime> = readings::table
+            .filter(readings::source_id.eq(source.id.unwrap_or_default()))
+            .select(diesel::dsl::max(readings::timestamp))
+            .first(conn)?;
+        let state = collector_state(&source, last_reading_at, now);
+        health.push(SourceHealth { source, last_reading_at, state });
+    }
+    Ok(health)
+}
+
+fn collector_state(
+    source: &Source,
+    last_reading_at: Option<NaiveDateTime>,
+    now: NaiveDateTime,
+) -> CollectorState {
+    // Two intervals, with some slack for slow collectors and the writer's
+    // flush delay
+    let window = chrono::Duration::seconds(2 * i64::from(source.interval_seconds.max(1)) + 30);
+    let recent = |at: Option<NaiveDateTime>| at.is_some_and(|at| now - at <= window);
+    match source.last_run {
+        None => CollectorState::NeverRun,
+        _ if recent(last_reading_at) => CollectorState::Ok,
+        last_run if recent(last_run) => CollectorState::Failing,
+        _ => CollectorState::Late,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DataAggregator, MIGRATIONS};
+
+    #[test]
+    fn test_monitor_state_from_status_age() {
+        let now = Utc::now().naive_utc();
+        let ago = |secs| now - chrono::Duration::seconds(secs);
+        let mut status = StatusBoard::new().snapshot();
+        status.updated_at = ago(2);
+        status.last_tick_at = Some(ago(1));
+        assert_eq!(status.state(now), MonitorState::Running);
+
+        status.last_tick_at = Some(ago(60));
+        assert_eq!(status.state(now), MonitorState::Stalled);
+
+        status.updated_at = ago(60);
+        assert_eq!(status.state(now), MonitorState::NotReporting);
+    }
+
+    #[test]
+    fn test_status_and_database_info() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("site.sqlite");
+        let mut conn = DataAggregator::new(Some(&path.to_string_lossy()))
+            .establish_connection()
+            .unwrap();
+        assert_eq!(load_status(&mut conn).unwrap(), None);
+
+        let board = StatusBoard::new();
+        let collectors = CollectorMetrics {
+            running: 2,
+            queued: 5,
+            ..Default::default()
+        };
+        let writer = WriterMetrics {
+            queue_depth: 40,
+            dropped: 3,
+            ..Default::default()
+        };
+        board.tick(7, &collectors, &writer);
+        save_status(&mut conn, &board.snapshot()).unwrap();
+        save_status(&mut conn, &board.snapshot()).unwrap();
+
+        let status = load_status(&mut conn).unwrap().expect("published status");
+        assert_eq!(status.pid, std::process::id() as i32);
+        assert_eq!(status.active_sources, 7);
+        assert_eq!(status.collectors_queued, 5);
+        assert_eq!(status.writer_queue_depth, 40);
+        assert_eq!(status.readings_dropped, 3);
+
+        let info = database_info(&mut conn, MIGRATIONS).unwrap();
+        assert!(info.size_bytes > 0);
+        assert!(info.pending_migrations.is_empty());
+        assert!(info.migration_version.is_some());
+    }
+}
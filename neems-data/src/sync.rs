@@ -0,0 +1,652 @@
+//! Store-and-forward sync of readings to a central neems-api.
+//!
+//! Edge instances keep collecting into their local database whether or not
+//! the uplink is up. When `NEEMS_SYNC_URL` is set, a sync task uploads the
+//! readings in batches to the central server's `POST /api/1/Sync/Readings`:
+//!
+//! - **Cursor tracking**: the edge remembers, per target, the highest local
+//!   reading id the central server has acknowledged (`sync_cursors`). At
+//!   startup it asks the server for its cursor (`GET
+//!   /api/1/Sync/Origins/<origin>/Cursor`), so a lost or stale local cursor
+//!   doesn't cause gaps or resends.
+//! - **Append-only, conflict-free ingest**: the central server records every
+//!   `(origin, origin_reading_id)` it has accepted (`sync_receipts`) and skips
+//!   ones it has already seen, so a batch resent after a dropped response is
+//!   harmless. Readings land under a central source named `<origin>/<source
+//!   name>`, created on first sight and left inactive so the central collector
+//!   never polls it.
+//! - **Resumable transfer**: each batch commits on its own. After an outage the
+//!   edge resumes from the last acknowledged reading, backing off between
+//!   failed attempts.
+//! - **Integrity checks**: once a day the edge sends per-source, per-day row
+//!   counts and checksums of what it has synced to `POST
+//!   /api/1/Sync/Reconcile`, and the server records any mismatch with its own
+//!   copy (see [`crate::reconcile`]).
+
+use std::{collections::HashMap, env, time::Duration};
+
+use chrono::{Days, Local, NaiveDateTime, Utc};
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    DataResult, NewSource, create_source, encryption, get_source_by_name, points,
+    reconcile::{ReconcileReport, ReconcileRequest, edge_digests},
+    schema,
+};
+
+/// How often the sync loop runs an integrity check.
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Settings for uploading readings to a central server.
+///
+/// Read from the environment by [`SyncConfig::from_env`]:
+/// - `NEEMS_SYNC_URL`: base URL of the central neems-api (sync is disabled when
+///   unset)
+/// - `NEEMS_SYNC_ORIGIN`: name identifying this site to the server (default the
+///   host name)
+/// - `NEEMS_SYNC_EMAIL` / `NEEMS_SYNC_PASSWORD`: account used to log in
+/// - `NEEMS_SYNC_BATCH_SIZE`: readings per upload
+/// - `NEEMS_SYNC_INTERVAL_SECS`: pause between sync passes once caught up
+/// - `NEEMS_SYNC_RECONCILE_DAYS`: days checked by the daily integrity check (0
+///   disables it)
+/// - `NEEMS_SYNC_CONFIG_INTERVAL_SECS`: pause between checks for a new
+///   configuration bundle (0 disables them; see [`crate::edge_config`])
+#[derive(Debug, Clone)]
+pub struct SyncConfig {
+    pub base_url: String,
+    pub origin: String,
+    pub email: Option<String>,
+    pub password: Option<String>,
+    pub batch_size: usize,
+    pub interval: Duration,
+    /// Longest wait between retries after repeated failures
+    pub max_backoff: Duration,
+    /// Days, ending today, covered by each integrity check (0 disables it)
+    pub reconcile_days: u32,
+    /// Pause between checks for a new configuration bundle (`None` disables
+    /// them)
+    pub config_interval: Option<Duration>,
+}
+
+impl SyncConfig {
+    pub fn new(base_url: impl Into<String>, origin: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            origin: origin.into(),
+            email: None,
+            password: None,
+            batch_size: 500,
+            interval: Duration::from_secs(60),
+            max_backoff: Duration::from_secs(15 * 60),
+            reconcile_days: 7,
+            config_interval: Some(Duration::from_secs(5 * 60)),
+        }
+    }
+
+    /// Build a config from the environment, or `None` if `NEEMS_SYNC_URL` is
+    /// not set.
+    pub fn from_env() -> Option<Self> {
+        let base_url = env::var("NEEMS_SYNC_URL").ok().filter(|url| !url.trim().is_empty())?;
+        let origin = env::var("NEEMS_SYNC_ORIGIN")
+            .ok()
+            .or_else(sysinfo::System::host_name)
+            .unwrap_or_else(|| "edge".to_string());
+        let var =
+            |key: &str| env::var(key).ok().and_then(|v| v.parse::<u64>().ok()).filter(|&v| v > 0);
+
+        let mut config = Self::new(base_url, origin);
+        config.email = env::var("NEEMS_SYNC_EMAIL").ok();
+        config.password = env::var("NEEMS_SYNC_PASSWORD").ok();
+        if let Some(batch_size) = var("NEEMS_SYNC_BATCH_SIZE") {
+            config.batch_size = batch_size as usize;
+        }
+        if let Some(interval) = var("NEEMS_SYNC_INTERVAL_SECS") {
+            config.interval = Duration::from_secs(interval);
+        }
+        if let Ok(days) = env::var("NEEMS_SYNC_RECONCILE_DAYS") {
+            config.reconcile_days = days.parse().unwrap_or(config.reconcile_days);
+        }
+        if let Ok(secs) = env::var("NEEMS_SYNC_CONFIG_INTERVAL_SECS") {
+            if let Ok(secs) = secs.parse::<u64>() {
+                config.config_interval = (secs > 0).then(|| Duration::from_secs(secs));
+            }
+        }
+        Some(config)
+    }
+}
+
+/// A reading as uploaded by an edge instance.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncReading {
+    /// The reading's id in the edge database
+    pub origin_reading_id: i32,
+    pub source_name: String,
+    pub test_type: Option<String>,
+    pub site_id: Option<i32>,
+    pub company_id: Option<i32>,
+    pub timestamp: NaiveDateTime,
+    pub device_timestamp: Option<NaiveDateTime>,
+    pub data: String,
+    pub quality_flags: i32,
+}
+
+/// Body of `POST /api/1/Sync/Readings`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncBatch {
+    pub origin: String,
+    pub readings: Vec<SyncReading>,
+}
+
+/// Response to an uploaded batch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncAck {
+    pub origin: String,
+    /// Readings appended by this batch
+    pub accepted: usize,
+    /// Readings that had already been received
+    pub duplicates: usize,
+    /// Highest origin reading id the server holds for this origin
+    pub cursor: i32,
+}
+
+/// Response of `GET /api/1/Sync/Origins/<origin>/Cursor`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncCursor {
+    pub origin: String,
+    pub cursor: i32,
+}
+
+/// Last acknowledged local reading id for `target` (0 if never synced).
+pub fn load_cursor(connection: &mut SqliteConnection, target: &str) -> DataResult<i32> {
+    use schema::sync_cursors::dsl;
+
+    let cursor = dsl::sync_cursors
+        .filter(dsl::target.eq(target))
+        .select(dsl::last_reading_id)
+        .first::<i32>(connection)
+        .optional()?;
+    Ok(cursor.unwrap_or(0))
+}
+
+/// Record the outcome of a sync attempt for `target`. `last_reading_id` is
+/// only advanced on success; an error is kept until the next success.
+pub fn save_cursor(
+    connection: &mut SqliteConnection,
+    target: &str,
+    last_reading_id: i32,
+    error: Option<&str>,
+) -> DataResult<()> {
+    use schema::sync_cursors::dsl;
+
+    let now = Utc::now().naive_utc();
+    let synced_at = error.is_none().then_some(now);
+    diesel::insert_into(dsl::sync_cursors)
+        .values((
+            dsl::target.eq(target),
+            dsl::last_reading_id.eq(last_reading_id),
+            dsl::last_synced_at.eq(synced_at),
+            dsl::last_error.eq(error),
+            dsl::updated_at.eq(now),
+        ))
+        .on_conflict(dsl::target)
+        .do_update()
+        .set((
+            dsl::last_reading_id.eq(last_reading_id),
+            dsl::last_error.eq(error),
+            dsl::updated_at.eq(now),
+        ))
+        .execute(connection)?;
+
+    if let Some(synced_at) = synced_at {
+        diesel::update(dsl::sync_cursors.filter(dsl::target.eq(target)))
+            .set(dsl::last_synced_at.eq(synced_at))
+            .execute(connection)?;
+    }
+    Ok(())
+}
+
+/// The next batch of local readings after `after_id`, oldest first.
+pub fn pending_batch(
+    connection: &mut SqliteConnection,
+    origin: &str,
+    after_id: i32,
+    limit: usize,
+) -> DataResult<SyncBatch> {
+    use schema::{readings, sources};
+
+    let rows = readings::table
+        .inner_join(sources::table)
+        .filter(readings::id.gt(after_id))
+        .order(readings::id.asc())
+        .limit(limit as i64)
+        .select((
+            readings::id,
+            sources::name,
+            sources::test_type,
+            sources::site_id,
+            sources::company_id,
+            readings::timestamp,
+            readings::device_timestamp,
+            readings::data,
+            readings::quality_flags,
+        ))
+        .load::<(
+            Option<i32>,
+            String,
+            Option<String>,
+            Option<i32>,
+            Option<i32>,
+            NaiveDateTime,
+            Option<NaiveDateTime>,
+            String,
+            i32,
+        )>(connection)?;
+
+    let readings = rows
+        .into_iter()
+        .filter_map(
+            |(
+                id,
+                source_name,
+                test_type,
+                site_id,
+                company_id,
+                timestamp,
+                device_ts,
+                data,
+                flags,
+            )| {
+                Some(SyncReading {
+                    origin_reading_id: id?,
+                    source_name,
+                    test_type,
+                    site_id,
+                    company_id,
+                    timestamp,
+                    device_timestamp: device_ts,
+                    data,
+                    quality_flags: flags,
+                })
+            },
+        )
+        .collect();
+
+    Ok(SyncBatch { origin: origin.to_string(), readings })
+}
+
+/// Highest origin reading id received from `origin` (0 if none).
+pub fn origin_cursor(connection: &mut SqliteConnection, origin: &str) -> DataResult<i32> {
+    use schema::sync_receipts::dsl;
+
+    let cursor = dsl::sync_receipts
+        .filter(dsl::origin.eq(origin))
+        .select(diesel::dsl::max(dsl::origin_reading_id))
+        .first::<Option<i32>>(connection)?;
+    Ok(cursor.unwrap_or(0))
+}
+
+/// Find or create the central source that holds `reading`'s data.
+fn central_source(
+    connection: &mut SqliteConnection,
+    origin: &str,
+    reading: &SyncReading,
+) -> DataResult<i32> {
+    let name = format!("{}/{}", origin, reading.source_name);
+    let source = match get_source_by_name(connection, &name)? {
+        Some(source) => source,
+        None => create_source(
+            connection,
+            NewSource {
+                name,
+                description: Some(format!("Synced from {}", origin)),
+                // Only the edge polls this source
+                active: Some(false),
+                interval_seconds: None,
+                test_type: reading.test_type.clone(),
+                arguments: None,
+                site_id: reading.site_id,
+                company_id: reading.company_id,
+                priority: None,
+                point_fields: None,
+            },
+        )?,
+    };
+    source.id.ok_or_else(|| "source loaded from database is missing its id".into())
+}
+
+/// Append an uploaded batch on the central server, skipping readings that
+/// were already received. The batch is applied in one transaction.
+pub fn ingest_batch(connection: &mut SqliteConnection, batch: &SyncBatch) -> DataResult<SyncAck> {
+    use schema::{readings, sync_receipts};
+
+    if batch.origin.trim().is_empty() || batch.origin.contains('/') {
+        return Err(format!("Invalid sync origin: {:?}", batch.origin).into());
+    }
+
+    let (accepted, duplicates) = connection
+        .transaction::<_, Box<dyn std::error::Error + Send + Sync>, _>(|conn| {
+            let mut source_ids: HashMap<String, i32> = HashMap::new();
+            let mut point_fields: HashMap<i32, Vec<String>> = HashMap::new();
+            let mut accepted = 0;
+            let mut duplicates = 0;
+
+            for reading in &batch.readings {
+                let received = diesel::insert_into(sync_receipts::table)
+                    .values((
+                        sync_receipts::origin.eq(&batch.origin),
+                        sync_receipts::origin_reading_id.eq(reading.origin_reading_id),
+                    ))
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+                if received == 0 {
+                    duplicates += 1;
+                    continue;
+                }
+
+                let source_id = match source_ids.get(&reading.source_name) {
+                    Some(&id) => id,
+                    None => {
+                        let id = central_source(conn, &batch.origin, reading)?;
+                        source_ids.insert(reading.source_name.clone(), id);
+                        point_fields.extend(points::fields_by_source(conn, &[id])?);
+                        id
+                    }
+                };
+
+                let inserted = diesel::insert_into(readings::table)
+                    .values((
+                        readings::source_id.eq(source_id),
+                        readings::timestamp.eq(reading.timestamp),
+                        readings::data.eq(&reading.data),
+                        readings::quality_flags.eq(reading.quality_flags),
+                        readings::device_timestamp.eq(reading.device_timestamp),
+                    ))
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+                if let (1, Some(fields)) = (inserted, point_fields.get(&source_id)) {
+                    points::record_points(conn, source_id, reading.device_timestamp, fields)?;
+                }
+                accepted += 1;
+            }
+            Ok((accepted, duplicates))
+        })?;
+
+    Ok(SyncAck {
+        origin: batch.origin.clone(),
+        accepted,
+        duplicates,
+        cursor: origin_cursor(connection, &batch.origin)?,
+    })
+}
+
+/// HTTP client for the central server's sync endpoints.
+pub struct SyncClient {
+    config: SyncConfig,
+    http: reqwest::Client,
+    logged_in: bool,
+}
+
+impl SyncClient {
+    pub fn new(config: SyncConfig) -> DataResult<Self> {
+        let http = reqwest::Client::builder()
+            .cookie_store(true)
+            .timeout(Duration::from_secs(60))
+            .build()?;
+        Ok(Self { config, http, logged_in: false })
+    }
+
+    pub(crate) fn origin(&self) -> &str {
+        &self.config.origin
+    }
+
+    pub(crate) fn url(&self, path: &str) -> String {
+        format!("{}/api/1/{}", self.config.base_url, path)
+    }
+
+    async fn login(&mut self) -> DataResult<()> {
+        let (Some(email), Some(password)) = (&self.config.email, &self.config.password) else {
+            return Ok(());
+        };
+        let response = self
+            .http
+            .post(self.url("login"))
+            .json(&serde_json::json!({ "email": email, "password": password }))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(format!("sync login failed with HTTP {}", response.status()).into());
+        }
+        self.logged_in = true;
+        Ok(())
+    }
+
+    /// Send a request, logging in first and once more if the session has
+    /// expired.
+    pub(crate) async fn send(
+        &mut self,
+        build: impl Fn(&reqwest::Client) -> reqwest::RequestBuilder,
+    ) -> DataResult<reqwest::Response> {
+        if !self.logged_in {
+            self.login().await?;
+        }
+        let mut response = build(&self.http).send().await?;
+        if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+            self.login().await?;
+            response = build(&self.http).send().await?;
+        }
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("sync request failed with HTTP {}", status).into());
+        }
+        Ok(response)
+    }
+
+    /// The server's cursor for this origin.
+    pub async fn remote_cursor(&mut self) -> DataResult<i32> {
+        let url = self.url(&format!("Sync/Origins/{}/Cursor", self.config.origin));
+        let response = self.send(|http| http.get(&url)).await?;
+        Ok(response.json::<SyncCursor>().await?.cursor)
+    }
+
+    /// Upload one batch.
+    pub async fn push(&mut self, batch: &SyncBatch) -> DataResult<SyncAck> {
+        let url = self.url("Sync/Readings");
+        let response = self.send(|http| http.post(&url).json(batch)).await?;
+        Ok(response.json::<SyncAck>().await?)
+    }
+
+    /// Upload batches from `cursor` until the server has everything. Returns
+    /// the new cursor and the number of readings the server accepted.
+    pub async fn sync_from(
+        &mut self,
+        database_url: &str,
+        mut cursor: i32,
+    ) -> DataResult<(i32, usize)> {
+        let mut accepted = 0;
+        loop {
+            let batch = {
+                let database_url = database_url.to_string();
+                let origin = self.config.origin.clone();
+                let batch_size = self.config.batch_size;
+                tokio::task::spawn_blocking(move || -> DataResult<SyncBatch> {
+                    let mut connection = encryption::establish(&database_url)?;
+                    pending_batch(&mut connection, &origin, cursor, batch_size)
+                })
+                .await??
+            };
+            if batch.readings.is_empty() {
+                return Ok((cursor, accepted));
+            }
+
+            let last_id = batch.readings.last().map(|r| r.origin_reading_id).unwrap_or(cursor);
+            let ack = self.push(&batch).await?;
+            accepted += ack.accepted;
+            cursor = ack.cursor.max(last_id);
+
+            let database_url = database_url.to_string();
+            let target = self.config.base_url.clone();
+            tokio::task::spawn_blocking(move || -> DataResult<()> {
+                let mut connection = encryption::establish(&database_url)?;
+                save_cursor(&mut connection, &target, cursor, None)
+            })
+            .await??;
+        }
+    }
+
+    /// Send digests of everything synced up to `cursor` over the last
+    /// `reconcile_days` days and return the server's discrepancy report.
+    pub async fn reconcile(
+        &mut self,
+        database_url: &str,
+        cursor: i32,
+    ) -> DataResult<ReconcileReport> {
+        let to = Utc::now().date_naive();
+        let days = u64::from(self.config.reconcile_days.max(1) - 1);
+        let from = to.checked_sub_days(Days::new(days)).unwrap_or(to);
+        let digests = {
+            let database_url = database_url.to_string();
+            tokio::task::spawn_blocking(move || {
+                let mut connection = encryption::establish(&database_url)?;
+                edge_digests(&mut connection, cursor, from, to)
+            })
+            .await??
+        };
+
+        let request = ReconcileRequest {
+            origin: self.config.origin.clone(),
+            from,
+            to,
+            digests,
+        };
+        let url = self.url("Sync/Reconcile");
+        let response = self.send(|http| http.post(&url).json(&request)).await?;
+        Ok(response.json::<ReconcileReport>().await?)
+    }
+}
+
+/// Run sync passes forever, backing off exponentially while the server is
+/// unreachable.
+pub async fn run_sync_loop(database_url: String, config: SyncConfig) {
+    let interval = config.interval;
+    let max_backoff = config.max_backoff;
+    let reconcile_enabled = config.reconcile_days > 0;
+    let target = config.base_url.clone();
+    let mut client = match SyncClient::new(config) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Sync disabled: {}", e);
+            return;
+        }
+    };
+
+    let mut cursor: Option<i32> = None;
+    let mut backoff = interval;
+    let mut last_reconcile: Option<tokio::time::Instant> = None;
+
+    loop {
+        let result = async {
+            // Resume from the server's view of what it has, the first time
+            // and after any failure
+            let start = match cursor {
+                Some(cursor) => cursor,
+                None => client.remote_cursor().await?,
+            };
+            client.sync_from(&database_url, start).await
+        }
+        .await;
+
+        let wait = match result {
+            Ok((new_cursor, accepted)) => {
+                if accepted > 0 {
+                    println!(
+                        "{} - Synced {} readings to {} (cursor {})",
+                        Local::now().to_rfc3339(),
+                        accepted,
+                        target,
+                        new_cursor
+                    );
+                }
+                cursor = Some(new_cursor);
+                backoff = interval;
+
+                let reconcile_due =
+                    last_reconcile.is_none_or(|t| t.elapsed() >= RECONCILE_INTERVAL);
+                if reconcile_enabled && reconcile_due {
+                    match client.reconcile(&database_url, new_cursor).await {
+                        Ok(report) => {
+                            last_reconcile = Some(tokio::time::Instant::now());
+                            if !report.discrepancies.is_empty() {
+                                eprintln!(
+                                    "{} - Integrity check with {} found {} mismatched source-days",
+                                    Local::now().to_rfc3339(),
+                                    target,
+                                    report.discrepancies.len()
+                                );
+                            }
+                        }
+                        // Retried after the next successful pass
+                        Err(e) => eprintln!(
+                            "{} - Integrity check with {} failed: {}",
+                            Local::now().to_rfc3339(),
+                            target,
+                            e
+                        ),
+                    }
+                }
+                interval
+            }
+            Err(e) => {
+                eprintln!("{} - Sync to {} failed: {}", Local::now().to_rfc3339(), target, e);
+                let database_url = database_url.clone();
+                let target = target.clone();
+                let error = e.to_string();
+                let _ = tokio::task::spawn_blocking(move || -> DataResult<()> {
+                    let mut connection = encryption::establish(&database_url)?;
+                    let last = load_cursor(&mut connection, &target)?;
+                    save_cursor(&mut connection, &target, last, Some(&error))
+                })
+                .await;
+                cursor = None;
+                let wait = backoff;
+                backoff = (backoff * 2).min(max_backoff);
+                wait
+            }
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Where an edge instance stands with one sync target.
+#[derive(Debug, Clone, Queryable)]
+pub struct SyncTargetStatus {
+    pub target: String,
+    pub last_reading_id: i32,
+    pub last_synced_at: Option<NaiveDateTime>,
+    pub last_error: Option<String>,
+}
+
+/// Load the sync status of every target this instance has synced to.
+pub fn sync_status(connection: &mut SqliteConnection) -> DataResult<Vec<SyncTargetStatus>> {
+    use schema::sync_cursors::dsl;
+
+    Ok(dsl::sync_cursors
+        .select((dsl::target, dsl::last_reading_id, dsl::last_synced_at, dsl::last_error))
+        .order(dsl::target.asc())
+        .load(connection)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_trims_trailing_slash() {
+        let config = SyncConfig::new("https://central.example.com/", "site-a");
+        assert_eq!(config.base_url, "https://central.example.com");
+        assert_eq!(
+            SyncClient::new(config).unwrap().url("Sync/Readings"),
+            "https://central.example.com/api/1/Sync/Readings"
+        );
+    }
+}
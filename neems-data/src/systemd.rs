@@ -0,0 +1,187 @@
+//! systemd service notifications.
+//!
+//! With the `systemd` feature, neems-data (and neems-api, which reuses these
+//! helpers) can run as `Type=notify` services: they report `READY=1` once
+//! they're serving and `STOPPING=1` on the way out. When the unit sets
+//! `WatchdogSec=`, they also send `WATCHDOG=1` keep-alives, but only while the
+//! loops that do the work are making progress (see [`Liveness`]), so a hung
+//! reader or writer gets the service restarted instead of silently stalling.
+//!
+//! Without the feature, or when not started by systemd, every function here
+//! is a no-op.
+
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// Tell systemd the service is up.
+pub fn notify_ready() {
+    notify("READY=1");
+}
+
+/// Tell systemd the service is shutting down.
+pub fn notify_stopping() {
+    notify("STOPPING=1");
+}
+
+/// Show `status` in `systemctl status`.
+pub fn notify_status(status: &str) {
+    notify(&format!("STATUS={}", status));
+}
+
+/// Reset systemd's watchdog timer.
+pub fn notify_watchdog() {
+    notify("WATCHDOG=1");
+}
+
+#[cfg(feature = "systemd")]
+fn notify(state: &str) {
+    if let Err(e) = sd_notify::notify(false, &[sd_notify::NotifyState::Custom(state)]) {
+        eprintln!("Failed to notify systemd ({}): {}", state, e);
+    }
+}
+
+#[cfg(not(feature = "systemd"))]
+fn notify(_state: &str) {}
+
+/// The unit's `WatchdogSec=`, if systemd expects keep-alives from this
+/// process.
+pub fn watchdog_timeout() -> Option<Duration> {
+    #[cfg(feature = "systemd")]
+    {
+        let mut usec = 0;
+        if sd_notify::watchdog_enabled(false, &mut usec) && usec > 0 {
+            return Some(Duration::from_micros(usec));
+        }
+    }
+    None
+}
+
+/// Tracks whether the service's long-running loops are still turning over.
+///
+/// Each loop [`register`](Liveness::register)s a [`Heartbeat`] and beats it
+/// every time it comes round; a loop is stalled when its last beat is older
+/// than the watchdog allows.
+#[derive(Clone)]
+pub struct Liveness {
+    started: Instant,
+    heartbeats: Arc<Mutex<Vec<Heartbeat>>>,
+}
+
+/// A single loop's heartbeat, see [`Liveness`].
+#[derive(Clone)]
+pub struct Heartbeat {
+    name: &'static str,
+    started: Instant,
+    last_beat_ms: Arc<AtomicU64>,
+}
+
+impl Default for Liveness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Liveness {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            heartbeats: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Start tracking the loop called `name`; it counts as alive from now.
+    pub fn register(&self, name: &'static str) -> Heartbeat {
+        let heartbeat = Heartbeat {
+            name,
+            started: self.started,
+            last_beat_ms: Arc::new(AtomicU64::new(0)),
+        };
+        heartbeat.beat();
+        self.heartbeats.lock().unwrap().push(heartbeat.clone());
+        heartbeat
+    }
+
+    /// Loops that haven't beaten within `max_age`, with how long it's been.
+    pub fn stalled(&self, max_age: Duration) -> Vec<(&'static str, Duration)> {
+        self.heartbeats
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|heartbeat| (heartbeat.name, heartbeat.age()))
+            .filter(|(_, age)| *age > max_age)
+            .collect()
+    }
+}
+
+impl Heartbeat {
+    /// Record that the loop is making progress.
+    pub fn beat(&self) {
+        let elapsed = self.started.elapsed().as_millis() as u64;
+        self.last_beat_ms.store(elapsed, Ordering::Relaxed);
+    }
+
+    fn age(&self) -> Duration {
+        let last_beat = Duration::from_millis(self.last_beat_ms.load(Ordering::Relaxed));
+        self.started.elapsed().saturating_sub(last_beat)
+    }
+}
+
+/// Send `WATCHDOG=1` every half `timeout` for as long as no loop in
+/// `liveness` has gone half `timeout` without a heartbeat.
+///
+/// Once a loop stalls the keep-alives stop, and systemd restarts the service
+/// when its watchdog expires (with `Restart=on-watchdog` or `on-failure`).
+pub async fn run_watchdog(liveness: Liveness, timeout: Duration) {
+    let mut interval = tokio::time::interval(timeout / 2);
+    let mut reported = false;
+    loop {
+        interval.tick().await;
+        let stalled = liveness.stalled(timeout / 2);
+        if stalled.is_empty() {
+            if reported {
+                println!("Watchdog: all loops are making progress again");
+                reported = false;
+            }
+            notify_watchdog();
+        } else if !reported {
+            let loops: Vec<String> = stalled
+                .iter()
+                .map(|(name, age)| format!("{} ({:.0}s)", name, age.as_secs_f64()))
+                .collect();
+            eprintln!(
+                "Watchdog: no progress from {}; withholding keep-alives so systemd restarts \
+                 the service",
+                loops.join(", ")
+            );
+            reported = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stalled_loops_are_reported_until_they_beat() {
+        let liveness = Liveness::new();
+        let reader = liveness.register("reader");
+        let writer = liveness.register("writer");
+        assert!(liveness.stalled(Duration::from_millis(50)).is_empty());
+
+        std::thread::sleep(Duration::from_millis(80));
+        writer.beat();
+        let stalled = liveness.stalled(Duration::from_millis(50));
+        assert_eq!(stalled.len(), 1);
+        assert_eq!(stalled[0].0, "reader");
+        assert!(stalled[0].1 >= Duration::from_millis(80));
+
+        reader.beat();
+        assert!(liveness.stalled(Duration::from_millis(50)).is_empty());
+    }
+}
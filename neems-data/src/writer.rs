@@ -0,0 +1,362 @@
+//! Bounded reading queue between the reader tasks and the database writer.
+//!
+//! Collected readings wait here until the writer flushes them to SQLite. The
+//! queue has a fixed capacity so a stalled database can't grow memory without
+//! bound. When it is full:
+//! - the oldest queued reading from a non-critical source is dropped to make
+//!   room (and counted per source), or
+//! - if everything queued is critical, a non-critical reading is dropped on
+//!   arrival, while a critical one waits for space (backpressure).
+//!
+//! Queue depth, high-water mark, drops and backpressure waits are tracked in
+//! [`WriterMetrics`].
+
+use std::{
+    collections::{HashMap, VecDeque},
+    env,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::sync::Notify;
+
+use crate::PendingReading;
+
+/// Tuning for the reading queue and batch writer.
+///
+/// Read from the environment by [`WriterConfig::from_env`]:
+/// - `NEEMS_DATA_QUEUE_CAPACITY`: readings held before dropping/backpressure
+/// - `NEEMS_DATA_BATCH_SIZE`: readings per database transaction; a full batch
+///   is written without waiting for the flush interval
+/// - `NEEMS_DATA_FLUSH_INTERVAL_MS`: maximum time a reading waits to be written
+#[derive(Debug, Clone)]
+pub struct WriterConfig {
+    pub queue_capacity: usize,
+    pub batch_size: usize,
+    pub flush_interval: Duration,
+}
+
+impl Default for WriterConfig {
+    fn default() -> Self {
+        Self {
+            queue_capacity: 1024,
+            batch_size: 500,
+            flush_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl WriterConfig {
+    /// Build a config from the environment, falling back to [`Default`] for
+    /// anything missing or invalid.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let var =
+            |key: &str| env::var(key).ok().and_then(|v| v.parse::<u64>().ok()).filter(|&v| v > 0);
+
+        Self {
+            queue_capacity: var("NEEMS_DATA_QUEUE_CAPACITY")
+                .map(|v| v as usize)
+                .unwrap_or(defaults.queue_capacity),
+            batch_size: var("NEEMS_DATA_BATCH_SIZE")
+                .map(|v| v as usize)
+                .unwrap_or(defaults.batch_size),
+            flush_interval: var("NEEMS_DATA_FLUSH_INTERVAL_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.flush_interval),
+        }
+    }
+}
+
+/// Counters describing queue pressure.
+#[derive(Debug, Default, Clone)]
+pub struct WriterMetrics {
+    /// Readings currently queued
+    pub queue_depth: usize,
+    /// Largest queue depth seen
+    pub high_water_mark: usize,
+    /// Readings accepted into the queue
+    pub enqueued: u64,
+    /// Readings dropped because the queue was full
+    pub dropped: u64,
+    /// Dropped readings by source id
+    pub dropped_by_source: HashMap<i32, u64>,
+    /// Times a critical reading had to wait for space
+    pub backpressure_waits: u64,
+}
+
+/// What happened to a reading handed to [`ReadingSender::send`].
+#[derive(Debug, Default)]
+pub struct SendOutcome {
+    /// Readings dropped to make room (possibly the one just sent). Their
+    /// sources are no longer waiting on a write.
+    pub dropped: Vec<PendingReading>,
+}
+
+#[derive(Debug)]
+pub struct QueueClosed;
+
+impl std::fmt::Display for QueueClosed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "reading queue closed")
+    }
+}
+
+impl std::error::Error for QueueClosed {}
+
+struct QueueState {
+    items: VecDeque<PendingReading>,
+    senders: usize,
+    /// Set when the receiver is dropped
+    closed: bool,
+    metrics: WriterMetrics,
+    /// Drops since the writer last reported them
+    unreported_drops: HashMap<i32, u64>,
+}
+
+struct Shared {
+    state: Mutex<QueueState>,
+    capacity: usize,
+    not_empty: Notify,
+    not_full: Notify,
+}
+
+impl Shared {
+    fn record_drop(state: &mut QueueState, reading: &PendingReading) {
+        let source_id = reading.reading.source_id;
+        state.metrics.dropped += 1;
+        *state.metrics.dropped_by_source.entry(source_id).or_default() += 1;
+        *state.unreported_drops.entry(source_id).or_default() += 1;
+    }
+}
+
+/// Create a bounded reading queue with the given capacity.
+pub fn reading_queue(capacity: usize) -> (ReadingSender, ReadingReceiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(QueueState {
+            items: VecDeque::with_capacity(capacity),
+            senders: 1,
+            closed: false,
+            metrics: WriterMetrics::default(),
+            unreported_drops: HashMap::new(),
+        }),
+        capacity: capacity.max(1),
+        not_empty: Notify::new(),
+        not_full: Notify::new(),
+    });
+
+    (ReadingSender { shared: shared.clone() }, ReadingReceiver { shared })
+}
+
+/// Producer half of the reading queue. The queue closes when every sender has
+/// been dropped.
+pub struct ReadingSender {
+    shared: Arc<Shared>,
+}
+
+impl Clone for ReadingSender {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().unwrap().senders += 1;
+        Self { shared: self.shared.clone() }
+    }
+}
+
+impl Drop for ReadingSender {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.shared.not_empty.notify_one();
+        }
+    }
+}
+
+impl ReadingSender {
+    /// Queue a reading, applying the drop-oldest policy when full. Critical
+    /// readings wait for space rather than being dropped.
+    pub async fn send(&self, reading: PendingReading) -> Result<SendOutcome, QueueClosed> {
+        let mut outcome = SendOutcome::default();
+        let mut waited = false;
+
+        loop {
+            let notified = self.shared.not_full.notified();
+            tokio::pin!(notified);
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                if state.closed {
+                    return Err(QueueClosed);
+                }
+
+                if state.items.len() >= self.shared.capacity {
+                    if let Some(pos) = state.items.iter().position(|r| !r.critical) {
+                        let evicted = state.items.remove(pos).expect("position is in bounds");
+                        Shared::record_drop(&mut state, &evicted);
+                        outcome.dropped.push(evicted);
+                    } else if !reading.critical {
+                        Shared::record_drop(&mut state, &reading);
+                        outcome.dropped.push(reading);
+                        return Ok(outcome);
+                    } else {
+                        if !waited {
+                            state.metrics.backpressure_waits += 1;
+                            waited = true;
+                        }
+                        notified.as_mut().enable();
+                    }
+                }
+
+                if state.items.len() < self.shared.capacity {
+                    state.items.push_back(reading);
+                    state.metrics.enqueued += 1;
+                    state.metrics.queue_depth = state.items.len();
+                    state.metrics.high_water_mark =
+                        state.metrics.high_water_mark.max(state.items.len());
+                    drop(state);
+                    self.shared.not_empty.notify_one();
+                    return Ok(outcome);
+                }
+            }
+            notified.await;
+        }
+    }
+
+    /// Current queue metrics.
+    pub fn metrics(&self) -> WriterMetrics {
+        self.shared.state.lock().unwrap().metrics.clone()
+    }
+}
+
+/// Consumer half of the reading queue, owned by the writer task.
+pub struct ReadingReceiver {
+    shared: Arc<Shared>,
+}
+
+impl Drop for ReadingReceiver {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.not_full.notify_waiters();
+    }
+}
+
+impl ReadingReceiver {
+    /// Wait until at least `batch_size` readings are queued or every sender
+    /// is gone.
+    pub async fn wait_for_batch(&self, batch_size: usize) {
+        loop {
+            let notified = self.shared.not_empty.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            {
+                let state = self.shared.state.lock().unwrap();
+                if state.items.len() >= batch_size || state.senders == 0 {
+                    return;
+                }
+            }
+            notified.await;
+        }
+    }
+
+    /// Take up to `max` readings from the front of the queue.
+    pub fn drain(&self, max: usize) -> Vec<PendingReading> {
+        let mut state = self.shared.state.lock().unwrap();
+        let count = state.items.len().min(max);
+        let batch: Vec<PendingReading> = state.items.drain(..count).collect();
+        state.metrics.queue_depth = state.items.len();
+        drop(state);
+        if !batch.is_empty() {
+            self.shared.not_full.notify_waiters();
+        }
+        batch
+    }
+
+    /// True once every sender is gone and the queue is empty.
+    pub fn is_finished(&self) -> bool {
+        let state = self.shared.state.lock().unwrap();
+        state.senders == 0 && state.items.is_empty()
+    }
+
+    /// Drops recorded since the last call, by source id.
+    pub fn take_unreported_drops(&self) -> HashMap<i32, u64> {
+        std::mem::take(&mut self.shared.state.lock().unwrap().unreported_drops)
+    }
+
+    /// Current queue metrics.
+    pub fn metrics(&self) -> WriterMetrics {
+        self.shared.state.lock().unwrap().metrics.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NewReading;
+
+    fn pending(source_id: i32, critical: bool) -> PendingReading {
+        PendingReading {
+            reading: NewReading::with_json_data(source_id, &serde_json::json!({})).unwrap(),
+            source_name: format!("source_{}", source_id),
+            critical,
+        }
+    }
+
+    fn queued_sources(rx: &ReadingReceiver) -> Vec<i32> {
+        rx.drain(usize::MAX).iter().map(|r| r.reading.source_id).collect()
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_non_critical_when_full() {
+        let (tx, rx) = reading_queue(3);
+        tx.send(pending(1, false)).await.unwrap();
+        tx.send(pending(2, true)).await.unwrap();
+        tx.send(pending(3, false)).await.unwrap();
+
+        let outcome = tx.send(pending(4, false)).await.unwrap();
+        assert_eq!(outcome.dropped.len(), 1);
+        assert_eq!(outcome.dropped[0].reading.source_id, 1);
+
+        // A critical reading also evicts the oldest non-critical one
+        let outcome = tx.send(pending(5, true)).await.unwrap();
+        assert_eq!(outcome.dropped[0].reading.source_id, 3);
+
+        let metrics = rx.metrics();
+        assert_eq!(metrics.dropped, 2);
+        assert_eq!(metrics.high_water_mark, 3);
+        assert_eq!(rx.take_unreported_drops(), HashMap::from([(1, 1), (3, 1)]));
+        assert!(rx.take_unreported_drops().is_empty());
+        assert_eq!(queued_sources(&rx), vec![2, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_non_critical_dropped_when_queue_is_all_critical() {
+        let (tx, rx) = reading_queue(2);
+        tx.send(pending(1, true)).await.unwrap();
+        tx.send(pending(2, true)).await.unwrap();
+
+        let outcome = tx.send(pending(3, false)).await.unwrap();
+        assert_eq!(outcome.dropped[0].reading.source_id, 3);
+        assert_eq!(queued_sources(&rx), vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_critical_reading_waits_for_space() {
+        let (tx, rx) = reading_queue(1);
+        tx.send(pending(1, true)).await.unwrap();
+
+        let blocked = tokio::spawn(async move {
+            tx.send(pending(2, true)).await.unwrap();
+            tx
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!blocked.is_finished(), "send should wait while the queue is full");
+        assert_eq!(rx.metrics().backpressure_waits, 1);
+
+        assert_eq!(queued_sources(&rx), vec![1]);
+        let tx = blocked.await.unwrap();
+        assert_eq!(queued_sources(&rx), vec![2]);
+
+        drop(tx);
+        rx.wait_for_batch(100).await;
+        assert!(rx.is_finished());
+    }
+}
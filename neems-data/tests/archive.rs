@@ -0,0 +1,68 @@
+//! tests/archive.rs
+
+use diesel::{prelude::*, sql_types::BigInt, sqlite::SqliteConnection};
+use diesel_migrations::MigrationHarness;
+use neems_data::{
+    MIGRATIONS,
+    archive::{archive_site_history, purge_site_history},
+    get_recent_readings, get_source_by_name, seed_soc_history,
+};
+use tempfile::TempDir;
+
+fn setup_test_db() -> SqliteConnection {
+    let mut connection =
+        SqliteConnection::establish(":memory:").expect("Failed to create in-memory db");
+    connection.run_pending_migrations(MIGRATIONS).expect("Failed to run migrations");
+    connection
+}
+
+#[derive(QueryableByName)]
+struct Count {
+    #[diesel(sql_type = BigInt)]
+    count: i64,
+}
+
+fn count(conn: &mut SqliteConnection, sql: &str) -> i64 {
+    diesel::sql_query(sql).get_result::<Count>(conn).unwrap().count
+}
+
+#[test]
+fn test_archive_then_purge_site_history() {
+    let mut conn = setup_test_db();
+    let archived = seed_soc_history(&mut conn, 3, 1, 60).unwrap();
+    let kept = seed_soc_history(&mut conn, 4, 1, 60).unwrap();
+
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("site-3.sqlite");
+    let tables = archive_site_history(&mut conn, 3, &path).unwrap();
+    let rows = |name: &str| tables.iter().find(|t| t.table == name).unwrap().rows;
+    assert_eq!(rows("sources"), 1);
+    assert_eq!(rows("readings"), archived.written);
+
+    // The archive stands alone, with only the archived site's rows
+    let mut archive = SqliteConnection::establish(path.to_str().unwrap()).unwrap();
+    assert_eq!(
+        count(&mut archive, "SELECT COUNT(*) AS count FROM readings") as usize,
+        archived.written
+    );
+    assert_eq!(
+        count(&mut archive, "SELECT COUNT(*) AS count FROM sources WHERE site_id != 3"),
+        0
+    );
+
+    // Seeded sources aren't polled; make them look live
+    diesel::sql_query("UPDATE sources SET active = 1").execute(&mut conn).unwrap();
+    let purged = purge_site_history(&mut conn, 3).unwrap();
+    assert_eq!(purged.readings_deleted, archived.written);
+    let source = get_source_by_name(&mut conn, &archived.source_name).unwrap().unwrap();
+    assert!(!source.active);
+    assert!(get_recent_readings(&mut conn, source.id.unwrap(), 10).unwrap().is_empty());
+
+    // Other sites are untouched
+    let other = get_source_by_name(&mut conn, &kept.source_name).unwrap().unwrap();
+    assert!(other.active);
+    assert_eq!(
+        get_recent_readings(&mut conn, other.id.unwrap(), 100).unwrap().len(),
+        kept.written
+    );
+}
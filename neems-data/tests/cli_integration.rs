@@ -31,6 +31,8 @@ fn test_new_source_creation_with_test_type() {
         arguments: Some(serde_json::to_string(&args).unwrap()),
         site_id: None,
         company_id: None,
+        priority: None,
+        point_fields: None,
     };
 
     let created = create_source(&mut conn, new_source).expect("Failed to create source");
@@ -59,6 +61,8 @@ fn test_charging_state_source_with_battery_id() {
         arguments: Some(serde_json::to_string(&args).unwrap()),
         site_id: None,
         company_id: None,
+        priority: None,
+        point_fields: None,
     };
 
     let created = create_source(&mut conn, new_source).expect("Failed to create source");
@@ -84,6 +88,8 @@ fn test_disk_space_source_no_args() {
         arguments: Some(serde_json::to_string(&args).unwrap()),
         site_id: None,
         company_id: None,
+        priority: None,
+        point_fields: None,
     };
 
     let created = create_source(&mut conn, new_source).expect("Failed to create source");
@@ -120,6 +126,8 @@ fn test_list_sources_shows_test_type_and_args() {
             arguments: Some(serde_json::to_string(&args).unwrap()),
             site_id: None,
             company_id: None,
+            priority: None,
+            point_fields: None,
         };
 
         create_source(&mut conn, new_source).expect("Failed to create source");
@@ -171,6 +179,8 @@ fn test_source_get_arguments_helper() {
         arguments: Some(serde_json::to_string(&expected_args).unwrap()),
         site_id: None,
         company_id: None,
+        priority: None,
+        point_fields: None,
     };
 
     let created = create_source(&mut conn, new_source).expect("Failed to create source");
@@ -193,6 +203,8 @@ fn test_invalid_json_arguments_handling() {
         arguments: Some("invalid json".to_string()),
         site_id: None,
         company_id: None,
+        priority: None,
+        point_fields: None,
     };
 
     let created = create_source(&mut conn, new_source).expect("Failed to create source");
@@ -216,6 +228,8 @@ fn test_legacy_and_new_format_coexistence() {
         arguments: None,
         site_id: None,
         company_id: None,
+        priority: None,
+        point_fields: None,
     };
 
     let legacy_created =
@@ -236,6 +250,8 @@ fn test_legacy_and_new_format_coexistence() {
         arguments: Some(serde_json::to_string(&args).unwrap()),
         site_id: None,
         company_id: None,
+        priority: None,
+        point_fields: None,
     };
 
     let new_created = create_source(&mut conn, new_source).expect("Failed to create new source");
@@ -1,7 +1,13 @@
 //! tests/collectors.rs
 
 use chrono::{NaiveDate, TimeZone, Timelike, Utc};
-use neems_data::collectors::{DataCollector, data_sources};
+use neems_data::collectors::{
+    DataCollector, TestType, carbon_intensity, data_sources,
+    replay::Recording,
+    simulator::{ROUND_TRIP_EFFICIENCY, Simulation, SimulatorConfig},
+    weather,
+};
+use serde_json::json;
 
 #[tokio::test]
 async fn test_ping_localhost_collector() {
@@ -117,3 +123,366 @@ async fn test_disk_space_collector() {
     assert!(total > 0, "total_bytes should be greater than 0");
     assert!(used <= total, "used_bytes should be less than or equal to total_bytes");
 }
+
+#[tokio::test]
+async fn test_cpu_load_collector() {
+    let json = data_sources::cpu_load(1).await.expect("cpu_load collector should not fail");
+    assert_eq!(json["source_id"], 1);
+    assert!(json["cpu_count"].as_u64().unwrap() > 0, "host should report at least one CPU");
+    let usage = json["usage_percent"].as_f64().unwrap();
+    assert!((0.0..=100.0).contains(&usage), "usage_percent out of range: {}", usage);
+    assert!(json["load_average_1m"].is_number());
+}
+
+#[tokio::test]
+async fn test_memory_collector() {
+    let json = data_sources::memory(1).await.expect("memory collector should not fail");
+    assert_eq!(json["source_id"], 1);
+    let total = json["total_bytes"].as_u64().unwrap();
+    let used = json["used_bytes"].as_u64().unwrap();
+    assert!(total > 0, "total_bytes should be greater than 0");
+    assert!(used <= total, "used_bytes should be less than or equal to total_bytes");
+}
+
+#[tokio::test]
+async fn test_thermal_collector() {
+    // Sensors vary by host (VMs usually have none), so only check the shape.
+    let json = data_sources::thermal(1).await.expect("thermal collector should not fail");
+    assert_eq!(json["source_id"], 1);
+    assert!(json["sensors"].is_array());
+    assert!(json["timestamp_utc"].is_string());
+
+    let collector = DataCollector::new("thermal".to_string(), 1);
+    assert_eq!(collector.test_type, TestType::Thermal);
+}
+
+#[test]
+fn test_parse_nws_observation() {
+    let body = json!({
+        "properties": {
+            "station": "https://api.weather.gov/stations/KDCA",
+            "timestamp": "2025-08-04T16:52:00+00:00",
+            "textDescription": "Mostly Cloudy",
+            "temperature": { "unitCode": "wmoUnit:degC", "value": 31.1 },
+            "relativeHumidity": { "unitCode": "wmoUnit:percent", "value": 52.3 },
+            "windSpeed": { "unitCode": "wmoUnit:km_h-1", "value": 18.0 },
+            "windGust": { "unitCode": "wmoUnit:km_h-1", "value": null },
+            "windDirection": { "unitCode": "wmoUnit:degree_(angle)", "value": 200 },
+            "cloudLayers": [
+                { "base": { "value": 1200 }, "amount": "SCT" },
+                { "base": { "value": 3000 }, "amount": "BKN" }
+            ]
+        }
+    });
+
+    let obs = weather::parse_nws_observation(&body).expect("observation should parse");
+    assert_eq!(obs.station.as_deref(), Some("KDCA"));
+    assert_eq!(obs.temperature_c, Some(31.1));
+    assert_eq!(obs.relative_humidity_percent, Some(52.3));
+    assert!((obs.wind_speed_mps.unwrap() - 5.0).abs() < 1e-9, "km/h should convert to m/s");
+    assert_eq!(obs.wind_gust_mps, None, "null values should be absent");
+    assert_eq!(obs.wind_direction_deg, Some(200.0));
+    assert_eq!(obs.cloud_cover_percent, Some(75.0), "densest layer wins");
+    assert_eq!(obs.irradiance_proxy(), Some(0.25));
+    assert_eq!(obs.conditions.as_deref(), Some("Mostly Cloudy"));
+}
+
+#[test]
+fn test_parse_openweather_observation() {
+    let body = json!({
+        "name": "Arlington",
+        "dt": 1754326320,
+        "main": { "temp": 29.4, "humidity": 61 },
+        "wind": { "speed": 4.1, "deg": 190, "gust": 7.2 },
+        "clouds": { "all": 20 },
+        "weather": [{ "main": "Clouds", "description": "few clouds" }]
+    });
+
+    let obs = weather::parse_openweather_observation(&body).expect("observation should parse");
+    assert_eq!(obs.station.as_deref(), Some("Arlington"));
+    assert_eq!(obs.temperature_c, Some(29.4));
+    assert_eq!(obs.wind_speed_mps, Some(4.1));
+    assert_eq!(obs.wind_gust_mps, Some(7.2));
+    assert_eq!(obs.cloud_cover_percent, Some(20.0));
+    assert_eq!(obs.irradiance_proxy(), Some(0.8));
+    assert_eq!(obs.conditions.as_deref(), Some("few clouds"));
+
+    let json = obs.to_json(7, weather::WeatherProvider::OpenWeather, 38.88, -77.1);
+    assert_eq!(json["source_id"], 7);
+    assert_eq!(json["provider"], "openweather");
+    assert_eq!(json["temperature_c"], 29.4);
+    assert!(json["timestamp_utc"].is_string());
+}
+
+#[tokio::test]
+async fn test_weather_collector_requires_coordinates() {
+    assert_eq!("weather".parse::<TestType>(), Ok(TestType::Weather));
+
+    let collector = DataCollector::new_with_test_type(TestType::Weather, 1, Default::default());
+    let err = collector.collect().await.expect_err("missing coordinates should fail");
+    assert!(err.to_string().contains("latitude"));
+}
+
+#[test]
+fn test_parse_carbon_intensity() {
+    let body = json!({
+        "zone": "US-CAL-CISO",
+        "carbonIntensity": 212,
+        "datetime": "2026-07-01T12:00:00.000Z",
+        "updatedAt": "2026-07-01T11:48:00.000Z",
+        "isEstimated": true
+    });
+    let intensity = carbon_intensity::parse_electricitymaps(&body).expect("should parse");
+    assert_eq!(intensity.zone.as_deref(), Some("US-CAL-CISO"));
+    assert_eq!(intensity.gco2_per_kwh, 212.0);
+    assert_eq!(intensity.estimated, Some(true));
+
+    let json = intensity.to_json(4, carbon_intensity::CarbonProvider::ElectricityMaps);
+    assert_eq!(json["carbon_intensity_gco2_per_kwh"], 212.0);
+    assert_eq!(json["signal"], "average");
+
+    // WattTime reports pounds per MWh
+    let body = json!({
+        "data": [
+            { "point_time": "2026-07-01T12:00:00+00:00", "value": 1000.0 },
+            { "point_time": "2026-07-01T12:05:00+00:00", "value": 900.0 }
+        ],
+        "meta": { "region": "CAISO_NORTH", "signal_type": "co2_moer", "units": "lbs_co2_per_mwh" }
+    });
+    let intensity = carbon_intensity::parse_watttime(&body).expect("should parse");
+    assert_eq!(intensity.zone.as_deref(), Some("CAISO_NORTH"));
+    assert!((intensity.gco2_per_kwh - 453.59237).abs() < 1e-9);
+    assert_eq!(intensity.observed_at.as_deref(), Some("2026-07-01T12:00:00+00:00"));
+
+    assert!(carbon_intensity::parse_watttime(&json!({ "data": [] })).is_none());
+    assert!(carbon_intensity::parse_electricitymaps(&json!({ "zone": "DE" })).is_none());
+}
+
+#[tokio::test]
+async fn test_carbon_intensity_collector_requires_zone() {
+    assert_eq!("carbon_intensity".parse::<TestType>(), Ok(TestType::CarbonIntensity));
+
+    let collector =
+        DataCollector::new_with_test_type(TestType::CarbonIntensity, 1, Default::default());
+    let err = collector.collect().await.expect_err("missing zone should fail");
+    assert!(err.to_string().contains("zone"));
+}
+
+/// Write an executable shell script to a temp dir for the exec collector.
+#[test]
+fn test_simulator_model() {
+    let config = SimulatorConfig {
+        capacity_kwh: 1000.0,
+        power_kw: 250.0,
+        initial_soc_percent: 50.0,
+        ..Default::default()
+    };
+    let at = |hour: u32| Utc.with_ymd_and_hms(2025, 6, 2, hour, 0, 0).unwrap();
+
+    assert_eq!(config.commanded_power_kw(at(2)), -250.0);
+    assert_eq!(config.commanded_power_kw(at(12)), 0.0);
+    assert_eq!(config.commanded_power_kw(at(17)), 250.0);
+    assert!(config.load_kw(at(18)) > config.load_kw(at(12)));
+    assert!(config.load_kw(at(6)) < config.load_kw(at(12)));
+
+    // Two hours of charging at 250 kW into 1000 kWh, less charging losses
+    let mut simulation = Simulation::new(&config, at(1));
+    simulation.advance(&config, at(3));
+    let expected = 50.0 + 50.0 * ROUND_TRIP_EFFICIENCY.sqrt();
+    assert!((simulation.soc_percent - expected).abs() < 1e-6, "{}", simulation.soc_percent);
+
+    // Charging stops once full; holding doesn't change SoC
+    simulation.advance(&config, at(12));
+    assert_eq!(simulation.soc_percent, 100.0);
+    assert_eq!(config.battery_power_kw(-250.0, 100.0), 0.0);
+
+    // Four hours of discharge at 250 kW empties it, and it stays empty
+    simulation.advance(&config, at(21));
+    assert_eq!(simulation.soc_percent, 0.0);
+    assert_eq!(config.battery_power_kw(250.0, 0.0), 0.0);
+}
+
+#[tokio::test]
+async fn test_simulator_collector() {
+    let json = DataCollector::new_simulator(7)
+        .collect()
+        .await
+        .expect("simulator should succeed");
+    assert_eq!(json["source_id"], 7);
+    assert_eq!(json["simulated"], true);
+    let soc = json["soc_percent"].as_f64().unwrap();
+    assert!((0.0..=100.0).contains(&soc));
+    let load = json["load_kw"].as_f64().unwrap();
+    let battery = json["battery_power_kw"].as_f64().unwrap();
+    assert!((json["grid_power_kw"].as_f64().unwrap() - (load - battery)).abs() < 1e-9);
+
+    let mut collector = DataCollector::new_simulator(7);
+    collector.arguments.insert("capacity_kwh".to_string(), "lots".to_string());
+    assert!(collector.collect().await.is_err());
+}
+
+#[test]
+fn test_replay_recording() {
+    let csv = "timestamp,soc,state\n\
+               2025-06-01T00:00:00Z,50,hold\n\
+               2025-06-01T00:01:00Z,51.5,charging\n\
+               2025-06-01T00:02:00Z,,charging\n";
+    let recording = Recording::parse(csv, "timestamp").unwrap();
+    assert_eq!(recording.columns, vec!["soc", "state"]);
+    assert_eq!(recording.rows.len(), 3);
+    assert_eq!(recording.row_data(1)["soc"], 51.5);
+    assert_eq!(recording.row_data(0)["state"], "hold");
+    assert!(recording.row_data(2)["soc"].is_null());
+
+    let seconds = chrono::Duration::seconds;
+    assert_eq!(recording.row_at(seconds(0), true), (0, false));
+    assert_eq!(recording.row_at(seconds(59), true), (0, false));
+    assert_eq!(recording.row_at(seconds(90), true), (1, false));
+    // Looping holds the last row for one average interval, then starts over
+    assert_eq!(recording.row_at(seconds(150), true), (2, false));
+    assert_eq!(recording.row_at(seconds(185), true), (0, false));
+    assert_eq!(recording.row_at(seconds(185), false), (2, true));
+
+    // Unix seconds and naive timestamps are accepted
+    let csv = "t,v\n1748736000,1\n2025-06-01 00:00:30,2\n";
+    let recording = Recording::parse(csv, "t").unwrap();
+    assert_eq!(recording.rows[1].recorded_at.timestamp(), 1748736030);
+
+    assert!(
+        Recording::parse(csv, "timestamp")
+            .unwrap_err()
+            .contains("no 'timestamp' column")
+    );
+    assert!(Recording::parse("t,v\n1,2,3\n", "t").unwrap_err().contains("line 2"));
+    assert!(Recording::parse("t,v\n20,1\n10,2\n", "t").unwrap_err().contains("earlier"));
+    assert!(Recording::parse("t,v\nnot-a-time,1\n", "t").is_err());
+}
+
+#[tokio::test]
+async fn test_replay_collector() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("recording.csv");
+    std::fs::write(
+        &path,
+        "timestamp,power_kw\n2025-06-01T00:00:00Z,12.5\n2025-06-01T01:00:00Z,-3\n",
+    )
+    .unwrap();
+
+    let json = DataCollector::new_replay(8, path.to_str().unwrap())
+        .collect()
+        .await
+        .expect("replay should succeed");
+    assert_eq!(json["source_id"], 8);
+    assert_eq!(json["power_kw"], 12.5);
+    assert_eq!(json["row"], 1);
+    assert_eq!(json["recorded_at"], "2025-06-01T00:00:00+00:00");
+
+    let err = DataCollector::new_replay(9, "/nonexistent/recording.csv")
+        .collect()
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("Failed to read recording"), "{}", err);
+}
+
+#[cfg(unix)]
+fn exec_script(dir: &tempfile::TempDir, body: &str) -> String {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = dir.path().join("collector.sh");
+    std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_exec_collector_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    // Echo the request back so we can check what the program was given.
+    let command = exec_script(&dir, r#"printf '{"request": %s, "argv1": "%s"}' "$(cat)" "$1""#);
+
+    let mut collector = DataCollector::new_exec(42, &command);
+    collector.arguments.insert("args".to_string(), "hello".to_string());
+    collector.arguments.insert("host".to_string(), "10.0.0.5".to_string());
+
+    let json = collector.collect().await.expect("exec collector should succeed");
+    assert_eq!(json["source_id"], 42, "source_id should be filled in");
+    assert!(json["timestamp_utc"].is_string());
+    assert_eq!(json["argv1"], "hello");
+    assert_eq!(json["request"]["source_id"], 42);
+    assert_eq!(json["request"]["arguments"]["host"], "10.0.0.5");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_exec_collector_failures() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let command = exec_script(&dir, "echo 'boom' >&2; exit 3");
+    let err = DataCollector::new_exec(1, &command).collect().await.unwrap_err();
+    assert!(err.to_string().contains("boom"), "stderr should be reported: {}", err);
+
+    let command = exec_script(&dir, "echo 'not json'");
+    assert!(DataCollector::new_exec(1, &command).collect().await.is_err());
+
+    let command = exec_script(&dir, "echo '[1, 2, 3]'");
+    let err = DataCollector::new_exec(1, &command).collect().await.unwrap_err();
+    assert!(err.to_string().contains("JSON object"));
+
+    let command = exec_script(&dir, "head -c 5000 /dev/zero");
+    let mut collector = DataCollector::new_exec(1, &command);
+    collector.arguments.insert("max_output_bytes".to_string(), "1024".to_string());
+    let err = collector.collect().await.unwrap_err();
+    assert!(err.to_string().contains("more than 1024 bytes"), "{}", err);
+
+    let command = exec_script(&dir, "sleep 5; echo '{}'");
+    let mut collector = DataCollector::new_exec(1, &command);
+    collector.arguments.insert("timeout_seconds".to_string(), "1".to_string());
+    let started = std::time::Instant::now();
+    let err = collector.collect().await.unwrap_err();
+    assert!(err.to_string().contains("timed out"), "{}", err);
+    assert!(started.elapsed() < std::time::Duration::from_secs(4));
+
+    let err = DataCollector::new_with_test_type(TestType::Exec, 1, Default::default())
+        .collect()
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("command"));
+}
+
+#[test]
+fn test_argument_schemas() {
+    let args = |pairs: &[(&str, &str)]| -> std::collections::HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    };
+
+    assert!(TestType::Ping.validate_arguments(&args(&[])).is_ok());
+    assert!(TestType::Ping.validate_arguments(&args(&[("target", "10.0.0.1")])).is_ok());
+    assert!(TestType::Memory.validate_arguments(&args(&[("target", "x")])).is_err());
+
+    let weather = args(&[("latitude", "38.9"), ("longitude", "-77.0")]);
+    assert!(TestType::Weather.validate_arguments(&weather).is_ok());
+    let mut openweather = weather.clone();
+    openweather.insert("provider".into(), "openweather".into());
+    let errors = TestType::Weather.validate_arguments(&openweather).unwrap_err();
+    assert_eq!(errors.to_string(), "'api_key' is required when 'provider' is openweather");
+    openweather.insert("api_key".into(), "secret://openweather".into());
+    assert!(TestType::Weather.validate_arguments(&openweather).is_ok());
+
+    let watttime = args(&[("provider", "watttime"), ("region", "CAISO_NORTH")]);
+    let errors = TestType::CarbonIntensity.validate_arguments(&watttime).unwrap_err();
+    assert_eq!(errors.0.len(), 2, "{}", errors);
+    let maps = args(&[("zone", "DE"), ("api_key", "secret://electricitymaps")]);
+    assert!(TestType::CarbonIntensity.validate_arguments(&maps).is_ok());
+
+    // exec passes unknown arguments through to the program
+    let exec = args(&[("command", "/opt/poll"), ("host", "10.0.0.5"), ("timeout_seconds", "0")]);
+    let errors = TestType::Exec.validate_arguments(&exec).unwrap_err();
+    assert_eq!(errors.0, vec!["'timeout_seconds' must be at least 1: 0"]);
+
+    for test_type in TestType::ALL {
+        let schema = test_type.argument_json_schema();
+        assert_eq!(schema["title"], test_type.as_str());
+        assert_eq!(schema["type"], "object");
+    }
+}
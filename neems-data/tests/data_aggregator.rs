@@ -5,7 +5,8 @@ use diesel_migrations::MigrationHarness;
 use neems_data::{
     MIGRATIONS,
     collectors::DataCollector,
-    create_source, get_recent_readings, get_source_by_name, insert_reading, list_sources,
+    create_source, get_recent_readings, get_source_by_name, insert_reading, insert_readings_batch,
+    insert_readings_each, list_sources,
     models::{NewReading, NewSource, UpdateSource},
     update_source,
 };
@@ -32,6 +33,8 @@ fn test_create_and_list_sources() {
         arguments: Some("{}".to_string()),
         site_id: None,
         company_id: None,
+        priority: None,
+        point_fields: None,
     };
 
     // Create a source
@@ -61,6 +64,8 @@ fn test_get_source_by_name() {
         arguments: Some("{}".to_string()),
         site_id: None,
         company_id: None,
+        priority: None,
+        point_fields: None,
     };
     create_source(&mut conn, new_source).unwrap();
 
@@ -89,6 +94,8 @@ fn test_update_source() {
         arguments: Some("{}".to_string()),
         site_id: None,
         company_id: None,
+        priority: None,
+        point_fields: None,
     };
     let source = create_source(&mut conn, initial_source).unwrap();
     let source_id = source.id.unwrap();
@@ -106,6 +113,8 @@ fn test_update_source() {
         arguments: None,
         site_id: None,
         company_id: None,
+        priority: None,
+        point_fields: None,
     };
 
     let updated_source =
@@ -131,6 +140,8 @@ fn test_insert_and_get_reading() {
         arguments: Some("{}".to_string()),
         site_id: None,
         company_id: None,
+        priority: None,
+        point_fields: None,
     };
     let source = create_source(&mut conn, new_source).expect("Failed to create source");
     let source_id = source.id.unwrap();
@@ -154,6 +165,94 @@ fn test_insert_and_get_reading() {
     assert_eq!(parsed_data, data);
 }
 
+#[test]
+fn test_insert_readings_batch_is_idempotent_by_device_timestamp() {
+    let mut conn = setup_test_db();
+
+    let new_source = NewSource {
+        name: "gateway_meter".to_string(),
+        description: None,
+        active: Some(true),
+        interval_seconds: Some(1),
+        test_type: Some("ping".to_string()),
+        arguments: Some("{}".to_string()),
+        site_id: None,
+        company_id: None,
+        priority: None,
+        point_fields: None,
+    };
+    let source_id = create_source(&mut conn, new_source).unwrap().id.unwrap();
+
+    let t1 = chrono::NaiveDate::from_ymd_opt(2025, 8, 4)
+        .unwrap()
+        .and_hms_opt(12, 0, 0)
+        .unwrap();
+    let t2 = t1 + chrono::Duration::seconds(1);
+    let reading = |ts, kwh: f64| {
+        NewReading::with_json_data(source_id, &serde_json::json!({ "kwh": kwh }))
+            .unwrap()
+            .with_device_timestamp(ts)
+    };
+    let unkeyed =
+        || NewReading::with_json_data(source_id, &serde_json::json!({ "kwh": 0.0 })).unwrap();
+
+    insert_readings_batch(&mut conn, vec![reading(t1, 1.0), reading(t2, 2.0), unkeyed()]).unwrap();
+    // A gateway retry resends the same device timestamps (with a corrected
+    // value for t2); readings without a device timestamp are never merged.
+    insert_readings_batch(&mut conn, vec![reading(t1, 1.0), reading(t2, 2.5), unkeyed()]).unwrap();
+
+    let readings = get_recent_readings(&mut conn, source_id, 10).unwrap();
+    assert_eq!(readings.len(), 4, "keyed readings should be upserted, not duplicated");
+
+    let keyed: Vec<_> = readings.iter().filter(|r| r.device_timestamp.is_some()).collect();
+    assert_eq!(keyed.len(), 2);
+    let at_t2 = keyed.iter().find(|r| r.device_timestamp == Some(t2)).unwrap();
+    assert_eq!(at_t2.parse_data().unwrap()["kwh"], 2.5, "retry should replace the value");
+}
+
+#[test]
+fn test_insert_readings_each_keeps_the_readings_that_can_be_stored() {
+    use diesel::connection::SimpleConnection;
+
+    let mut conn = setup_test_db();
+    conn.batch_execute("PRAGMA foreign_keys = ON").unwrap();
+
+    let new_source = NewSource {
+        name: "buffered_meter".to_string(),
+        description: None,
+        active: Some(false),
+        interval_seconds: Some(1),
+        test_type: Some("ping".to_string()),
+        arguments: Some("{}".to_string()),
+        site_id: None,
+        company_id: None,
+        priority: None,
+        point_fields: None,
+    };
+    let source_id = create_source(&mut conn, new_source).unwrap().id.unwrap();
+
+    let t1 = chrono::NaiveDate::from_ymd_opt(2025, 8, 4)
+        .unwrap()
+        .and_hms_opt(12, 0, 0)
+        .unwrap();
+    let reading = |source_id, kwh: f64| {
+        NewReading::with_json_data(source_id, &serde_json::json!({ "kwh": kwh }))
+            .unwrap()
+            .with_device_timestamp(t1)
+    };
+
+    // The reading of a source that doesn't exist breaks its foreign key
+    let outcomes =
+        insert_readings_each(&mut conn, &[reading(source_id, 1.0), reading(source_id + 1, 2.0)])
+            .unwrap();
+    assert!(outcomes[0].is_ok());
+    assert!(outcomes[1].is_err());
+
+    let readings = get_recent_readings(&mut conn, source_id, 10).unwrap();
+    assert_eq!(readings.len(), 1);
+    assert_eq!(readings[0].parse_data().unwrap()["kwh"], 1.0);
+}
+
 #[tokio::test]
 async fn test_charging_state_source_integration() {
     let mut conn = setup_test_db();
@@ -169,6 +268,8 @@ async fn test_charging_state_source_integration() {
         arguments: Some("{}".to_string()),
         site_id: None,
         company_id: None,
+        priority: None,
+        point_fields: None,
     };
     let source = create_source(&mut conn, new_source).expect("Failed to create source");
     let source_id = source.id.unwrap();
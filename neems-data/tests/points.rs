@@ -0,0 +1,239 @@
+//! tests/points.rs
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use diesel_migrations::MigrationHarness;
+use neems_data::{
+    MIGRATIONS, UpdateSource, create_source, insert_reading, insert_readings_batch,
+    models::{NewReading, NewSource, Source},
+    points::{AggregateBackend, AggregateBucket, aggregate, backfill_points},
+    schema::reading_points,
+    update_source,
+};
+
+fn setup_test_db() -> SqliteConnection {
+    let mut connection =
+        SqliteConnection::establish(":memory:").expect("Failed to create in-memory db");
+    connection.run_pending_migrations(MIGRATIONS).expect("Failed to run migrations");
+    connection
+}
+
+fn source(conn: &mut SqliteConnection, name: &str, test_type: &str) -> Source {
+    create_source(
+        conn,
+        NewSource {
+            name: name.to_string(),
+            description: None,
+            active: Some(true),
+            interval_seconds: Some(1),
+            test_type: Some(test_type.to_string()),
+            arguments: None,
+            site_id: None,
+            company_id: None,
+            priority: None,
+            point_fields: None,
+        },
+    )
+    .unwrap()
+}
+
+fn reading(source_id: i32, minute: u32, data: serde_json::Value) -> NewReading {
+    let ts = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(0, minute, 0).unwrap();
+    let mut reading = NewReading::with_json_data(source_id, &data).unwrap();
+    reading.timestamp = Some(ts);
+    reading
+}
+
+fn point_count(conn: &mut SqliteConnection, source_id: i32) -> i64 {
+    reading_points::table
+        .filter(reading_points::source_id.eq(source_id))
+        .count()
+        .get_result(conn)
+        .unwrap()
+}
+
+fn day() -> (chrono::NaiveDateTime, chrono::NaiveDateTime) {
+    let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+    (start, start + chrono::Duration::days(1))
+}
+
+#[test]
+fn test_writer_populates_declared_fields() {
+    let mut conn = setup_test_db();
+    let soc = source(&mut conn, "soc", "charging_state");
+    let soc_id = soc.id.unwrap();
+
+    insert_readings_batch(
+        &mut conn,
+        vec![
+            reading(soc_id, 0, serde_json::json!({ "level": 40, "state": "charging" })),
+            reading(soc_id, 1, serde_json::json!({ "level": "n/a" })),
+        ],
+    )
+    .unwrap();
+    insert_reading(&mut conn, reading(soc_id, 2, serde_json::json!({ "level": 60 }))).unwrap();
+    assert_eq!(point_count(&mut conn, soc_id), 2, "non-numeric values are skipped");
+
+    // A device-timestamped reading that is re-sent replaces its point
+    let device_ts = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(0, 3, 0).unwrap();
+    for level in [10, 20] {
+        let r = reading(soc_id, 3, serde_json::json!({ "level": level }))
+            .with_device_timestamp(device_ts);
+        insert_readings_batch(&mut conn, vec![r]).unwrap();
+    }
+    assert_eq!(point_count(&mut conn, soc_id), 3);
+
+    let (from, to) = day();
+    let (backend, buckets) = aggregate(&mut conn, &soc, "level", from, to, 86400).unwrap();
+    assert_eq!(backend, AggregateBackend::DailyRollup);
+    assert_eq!(buckets.len(), 1);
+    assert_eq!((buckets[0].count, buckets[0].min, buckets[0].max), (3, 20.0, 60.0));
+    assert_eq!(buckets[0].avg, 40.0);
+}
+
+#[test]
+fn test_declaring_fields_backfills_and_matches_json_fallback() {
+    let mut conn = setup_test_db();
+    let host = source(&mut conn, "host", "cpu_load");
+    let host_id = host.id.unwrap();
+    let readings = (0..6)
+        .map(|m| reading(host_id, m * 10, serde_json::json!({ "load": m })))
+        .collect();
+    insert_readings_batch(&mut conn, readings).unwrap();
+    assert_eq!(point_count(&mut conn, host_id), 0, "nothing declared for cpu_load");
+
+    let (from, to) = day();
+    let (backend, from_json) = aggregate(&mut conn, &host, "load", from, to, 1800).unwrap();
+    assert_eq!(backend, AggregateBackend::Json);
+
+    let host = update_source(
+        &mut conn,
+        host_id,
+        UpdateSource {
+            name: None,
+            description: None,
+            active: None,
+            interval_seconds: None,
+            last_run: None,
+            test_type: None,
+            arguments: None,
+            site_id: None,
+            company_id: None,
+            priority: None,
+            point_fields: Some(Some(r#"["load"]"#.to_string())),
+        },
+    )
+    .unwrap();
+    assert_eq!(backfill_points(&mut conn, &host).unwrap(), 6);
+
+    let (backend, from_points) = aggregate(&mut conn, &host, "load", from, to, 1800).unwrap();
+    assert_eq!(backend, AggregateBackend::Points);
+    assert_eq!(from_points, from_json);
+    assert_eq!(from_points.len(), 2);
+    assert_eq!((from_points[0].count, from_points[0].avg), (3, 1.0));
+}
+
+/// Buckets computed by hand from `(ts, value)` points.
+fn expected_buckets(
+    points: &[(NaiveDateTime, f64)],
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    bucket_seconds: i64,
+) -> Vec<AggregateBucket> {
+    let mut buckets: Vec<AggregateBucket> = Vec::new();
+    for &(ts, value) in points.iter().filter(|(ts, _)| *ts >= from && *ts < to) {
+        let seconds = ts.and_utc().timestamp();
+        let start = chrono::DateTime::from_timestamp(seconds - seconds % bucket_seconds, 0)
+            .unwrap()
+            .naive_utc();
+        match buckets.last_mut().filter(|b| b.start == start) {
+            Some(b) => {
+                b.avg = (b.avg * b.count as f64 + value) / (b.count + 1) as f64;
+                b.count += 1;
+                b.min = b.min.min(value);
+                b.max = b.max.max(value);
+            }
+            None => buckets.push(AggregateBucket {
+                start,
+                count: 1,
+                min: value,
+                max: value,
+                avg: value,
+            }),
+        }
+    }
+    buckets
+}
+
+#[test]
+fn test_rollups_serve_whole_hours_and_days() {
+    let mut conn = setup_test_db();
+    let soc = source(&mut conn, "soc", "charging_state");
+    let soc_id = soc.id.unwrap();
+    let (start, _) = day();
+
+    // Three days of readings every 20 minutes
+    let points: Vec<(NaiveDateTime, f64)> = (0..216)
+        .map(|i| (start + Duration::minutes(20 * i), (i * 7 % 100) as f64))
+        .collect();
+    let readings = points
+        .iter()
+        .map(|&(ts, level)| {
+            let mut r =
+                NewReading::with_json_data(soc_id, &serde_json::json!({ "level": level })).unwrap();
+            r.timestamp = Some(ts);
+            r
+        })
+        .collect();
+    insert_readings_batch(&mut conn, readings).unwrap();
+
+    let check = |conn: &mut SqliteConnection, from, to, bucket_seconds, backend| {
+        let (used, buckets) = aggregate(conn, &soc, "level", from, to, bucket_seconds).unwrap();
+        assert_eq!(used, backend, "{} to {} by {}s", from, to, bucket_seconds);
+        let expected = expected_buckets(&points, from, to, bucket_seconds);
+        assert_eq!(buckets.len(), expected.len());
+        for (got, want) in buckets.iter().zip(&expected) {
+            assert_eq!(
+                (got.start, got.count, got.min, got.max),
+                (want.start, want.count, want.min, want.max)
+            );
+            assert!((got.avg - want.avg).abs() < 1e-9);
+        }
+    };
+
+    // Days, with partial days at both ends read from the points
+    let from = start + Duration::minutes(30);
+    let to = start + Duration::hours(60) + Duration::minutes(10);
+    check(&mut conn, from, to, 86400, AggregateBackend::DailyRollup);
+    // Hours; less than a whole day falls back to hourly rollups
+    check(
+        &mut conn,
+        from,
+        start + Duration::hours(5),
+        3600,
+        AggregateBackend::HourlyRollup,
+    );
+    check(
+        &mut conn,
+        from,
+        start + Duration::hours(5),
+        86400,
+        AggregateBackend::HourlyRollup,
+    );
+    // Sub-hour buckets, or no whole hour in range, read the points
+    check(&mut conn, from, to, 1800, AggregateBackend::Points);
+    check(&mut conn, from, start + Duration::minutes(50), 3600, AggregateBackend::Points);
+
+    // A re-sent reading replaces its value in the rollups too
+    let ts = start + Duration::hours(30);
+    let mut r = reading(soc_id, 0, serde_json::json!({ "level": 40 })).with_device_timestamp(ts);
+    r.timestamp = Some(ts);
+    insert_readings_batch(&mut conn, vec![r.clone()]).unwrap();
+    let mut replaced = r;
+    replaced.data = serde_json::json!({ "level": 1000 }).to_string();
+    insert_readings_batch(&mut conn, vec![replaced]).unwrap();
+    let (_, buckets) =
+        aggregate(&mut conn, &soc, "level", start + Duration::days(1), to, 86400).unwrap();
+    assert_eq!(buckets[0].max, 1000.0);
+    assert_eq!(buckets[0].count, 73);
+}
@@ -0,0 +1,84 @@
+//! tests/secrets.rs
+
+use std::collections::{BTreeSet, HashMap};
+
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use diesel_migrations::MigrationHarness;
+use neems_data::{
+    MIGRATIONS,
+    collectors::DataCollector,
+    create_source,
+    models::{NewSource, NewSourceConfig},
+    schema::secrets,
+    secrets::{MasterKey, REDACTED, SecretStore, generate_key, list_secrets, set_secret},
+};
+
+fn setup_test_db() -> SqliteConnection {
+    let mut connection =
+        SqliteConnection::establish(":memory:").expect("Failed to create in-memory db");
+    connection.run_pending_migrations(MIGRATIONS).expect("Failed to run migrations");
+    connection
+}
+
+#[test]
+fn test_secrets_are_stored_encrypted() {
+    let mut conn = setup_test_db();
+    let key = MasterKey::parse(&generate_key().unwrap()).unwrap();
+    set_secret(&mut conn, &key, "rtac-main", "hunter22").unwrap();
+    set_secret(&mut conn, &key, "rtac-main", "hunter23").unwrap();
+    assert!(set_secret(&mut conn, &key, "bad name", "x").is_err());
+
+    let stored: Vec<u8> = secrets::table
+        .filter(secrets::name.eq("rtac-main"))
+        .select(secrets::ciphertext)
+        .first(&mut conn)
+        .unwrap();
+    assert!(!String::from_utf8_lossy(&stored).contains("hunter"));
+    assert_eq!(list_secrets(&mut conn).unwrap().len(), 1, "set replaces");
+
+    let names = BTreeSet::from(["rtac-main".to_string(), "missing".to_string()]);
+    let store = SecretStore::load(&mut conn, &names, Some(&key)).unwrap();
+    let arguments = HashMap::from([("password".to_string(), "secret://rtac-main".to_string())]);
+    assert_eq!(store.resolve(&arguments).unwrap().0["password"], "hunter23");
+    let missing = HashMap::from([("password".to_string(), "secret://missing".to_string())]);
+    assert!(store.resolve(&missing).is_err());
+
+    let other_key = MasterKey::parse(&generate_key().unwrap()).unwrap();
+    let store = SecretStore::load(&mut conn, &names, Some(&other_key)).unwrap();
+    assert!(store.resolve(&arguments).is_err(), "a wrong master key fails only this source");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_collector_output_redacts_resolved_secrets() {
+    let mut conn = setup_test_db();
+    let key = MasterKey::parse(&generate_key().unwrap()).unwrap();
+    set_secret(&mut conn, &key, "rtac-main", "hunter22").unwrap();
+
+    // `cat` echoes the request, arguments included, back as the reading
+    let arguments = HashMap::from([
+        ("command".to_string(), "cat".to_string()),
+        ("password".to_string(), "secret://rtac-main".to_string()),
+    ]);
+    let source = create_source(
+        &mut conn,
+        NewSource::with_arguments(
+            "rtac".to_string(),
+            "exec".to_string(),
+            &arguments,
+            NewSourceConfig::default(),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+
+    let sources = vec![source];
+    let names = neems_data::secrets::referenced_names(&sources);
+    let store = SecretStore::load(&mut conn, &names, Some(&key)).unwrap();
+    let collector = DataCollector::from_source(&sources[0], &store).unwrap();
+    assert_eq!(collector.arguments["password"], "hunter22");
+
+    let data = collector.collect().await.unwrap();
+    assert_eq!(data["arguments"]["password"], REDACTED);
+    assert!(!data.to_string().contains("hunter22"));
+}
@@ -24,6 +24,8 @@ fn setup_test_db_for_signal_test() -> (DataAggregator, NamedTempFile) {
             arguments: Some("{}".to_string()),
             site_id: None,
             company_id: None,
+            priority: None,
+            point_fields: None,
         },
     )
     .unwrap();
@@ -63,6 +65,8 @@ async fn test_sighup_reloads_sources_and_collects_data() {
             arguments: Some("{\"battery_id\":\"battery2\"}".to_string()),
             site_id: None,
             company_id: None,
+            priority: None,
+            point_fields: None,
         },
     )
     .unwrap();
@@ -0,0 +1,154 @@
+//! tests/sync.rs
+
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use diesel_migrations::MigrationHarness;
+use neems_data::{
+    MIGRATIONS, create_source, get_recent_readings, get_source_by_name, insert_readings_batch,
+    models::{NewReading, NewSource},
+    reconcile::{ReconcileRequest, discrepancies, edge_digests, reconcile},
+    sync::{ingest_batch, load_cursor, origin_cursor, pending_batch, save_cursor},
+};
+
+fn setup_test_db() -> SqliteConnection {
+    let mut connection =
+        SqliteConnection::establish(":memory:").expect("Failed to create in-memory db");
+    connection.run_pending_migrations(MIGRATIONS).expect("Failed to run migrations");
+    connection
+}
+
+/// An edge database with `count` readings from one source.
+fn edge_db(count: usize) -> SqliteConnection {
+    let mut conn = setup_test_db();
+    let source = create_source(
+        &mut conn,
+        NewSource {
+            name: "charging_state".to_string(),
+            description: None,
+            active: Some(true),
+            interval_seconds: Some(1),
+            test_type: Some("charging_state".to_string()),
+            arguments: Some(r#"{"api_key": "edge-only"}"#.to_string()),
+            site_id: Some(3),
+            company_id: Some(2),
+            priority: None,
+            point_fields: None,
+        },
+    )
+    .unwrap();
+    let readings = (0..count)
+        .map(|i| {
+            NewReading::with_json_data(source.id.unwrap(), &serde_json::json!({ "level": i }))
+                .unwrap()
+        })
+        .collect();
+    insert_readings_batch(&mut conn, readings).unwrap();
+    conn
+}
+
+#[test]
+fn test_batches_follow_the_cursor() {
+    let mut edge = edge_db(5);
+
+    let first = pending_batch(&mut edge, "site-a", 0, 3).unwrap();
+    let ids: Vec<i32> = first.readings.iter().map(|r| r.origin_reading_id).collect();
+    assert_eq!(ids, vec![1, 2, 3]);
+    assert_eq!(first.readings[0].source_name, "charging_state");
+    assert_eq!(first.readings[0].site_id, Some(3));
+
+    let rest = pending_batch(&mut edge, "site-a", 3, 3).unwrap();
+    assert_eq!(rest.readings.len(), 2);
+    assert!(pending_batch(&mut edge, "site-a", 5, 3).unwrap().readings.is_empty());
+}
+
+#[test]
+fn test_ingest_is_append_only_and_idempotent() {
+    let mut edge = edge_db(4);
+    let mut central = setup_test_db();
+
+    let batch = pending_batch(&mut edge, "site-a", 0, 3).unwrap();
+    let ack = ingest_batch(&mut central, &batch).unwrap();
+    assert_eq!((ack.accepted, ack.duplicates, ack.cursor), (3, 0, 3));
+
+    // The response was lost and the edge resends an overlapping batch
+    let resend = pending_batch(&mut edge, "site-a", 1, 3).unwrap();
+    let ack = ingest_batch(&mut central, &resend).unwrap();
+    assert_eq!((ack.accepted, ack.duplicates, ack.cursor), (1, 2, 4));
+    assert_eq!(origin_cursor(&mut central, "site-a").unwrap(), 4);
+    assert_eq!(origin_cursor(&mut central, "site-b").unwrap(), 0);
+
+    // Readings land under an inactive, origin-qualified source
+    let source = get_source_by_name(&mut central, "site-a/charging_state").unwrap().unwrap();
+    assert!(!source.active);
+    assert_eq!(source.arguments, None);
+    assert_eq!((source.site_id, source.company_id), (Some(3), Some(2)));
+    let readings = get_recent_readings(&mut central, source.id.unwrap(), 10).unwrap();
+    assert_eq!(readings.len(), 4);
+}
+
+#[test]
+fn test_ingest_rejects_invalid_origin() {
+    let mut edge = edge_db(1);
+    let mut central = setup_test_db();
+
+    let batch = pending_batch(&mut edge, "bad/origin", 0, 10).unwrap();
+    assert!(ingest_batch(&mut central, &batch).is_err());
+}
+
+#[test]
+fn test_cursor_persists_and_keeps_errors() {
+    let mut conn = setup_test_db();
+    let target = "https://central.example.com";
+
+    assert_eq!(load_cursor(&mut conn, target).unwrap(), 0);
+    save_cursor(&mut conn, target, 42, None).unwrap();
+    save_cursor(&mut conn, target, 42, Some("connection refused")).unwrap();
+    assert_eq!(load_cursor(&mut conn, target).unwrap(), 42);
+
+    let status = neems_data::sync::sync_status(&mut conn).unwrap();
+    assert_eq!(status.len(), 1);
+    assert!(status[0].last_synced_at.is_some(), "last successful sync is kept");
+    assert_eq!(status[0].last_error.as_deref(), Some("connection refused"));
+}
+
+#[test]
+fn test_reconcile_reports_lost_readings_until_repaired() {
+    let mut edge = edge_db(4);
+    let mut central = setup_test_db();
+    let today = chrono::Utc::now().date_naive();
+
+    // Only three of the four readings made it to the server
+    ingest_batch(&mut central, &pending_batch(&mut edge, "site-a", 0, 3).unwrap()).unwrap();
+    diesel::delete(neems_data::schema::sync_receipts::table)
+        .filter(neems_data::schema::sync_receipts::origin_reading_id.eq(3))
+        .execute(&mut central)
+        .unwrap();
+    diesel::delete(neems_data::schema::readings::table)
+        .filter(neems_data::schema::readings::id.eq(3))
+        .execute(&mut central)
+        .unwrap();
+
+    let request = ReconcileRequest {
+        origin: "site-a".to_string(),
+        from: today,
+        to: today,
+        digests: edge_digests(&mut edge, 3, today, today).unwrap(),
+    };
+    assert_eq!(request.digests.len(), 1);
+    assert_eq!(request.digests[0].row_count, 3, "unsynced readings are left out");
+
+    let report = reconcile(&mut central, &request).unwrap();
+    assert_eq!(report.checked, 1);
+    assert_eq!(report.discrepancies.len(), 1);
+    assert_eq!(report.discrepancies[0].source_name, "charging_state");
+    assert_eq!(
+        (report.discrepancies[0].edge_count, report.discrepancies[0].central_count),
+        (3, 2)
+    );
+    assert_eq!(discrepancies(&mut central, Some("site-a"), None).unwrap().len(), 1);
+
+    // Re-sending the lost reading clears the discrepancy on the next check
+    ingest_batch(&mut central, &pending_batch(&mut edge, "site-a", 2, 1).unwrap()).unwrap();
+    let report = reconcile(&mut central, &request).unwrap();
+    assert!(report.discrepancies.is_empty());
+    assert!(discrepancies(&mut central, None, Some(today)).unwrap().is_empty());
+}
@@ -0,0 +1,603 @@
+//! End-to-end scenario harness for the RTAC control loop.
+//!
+//! A [`Harness`] runs the whole closed loop in one process:
+//!
+//! - neems-api (see [`test_rocket`]) served over HTTP on a local port, with its
+//!   in-memory main and site databases
+//! - the simulated RTAC from `neems-rtac-sim`, ticking fast enough that a
+//!   scenario plays out in seconds
+//! - the RTAC collector from `neems-data` (Modbus worker, schedule poller,
+//!   control logic and storage), connected to the simulator through a [`Link`]
+//!   that can be cut to simulate a communications loss
+//!
+//! Scenarios set the site's schedule through the API, poke the simulator,
+//! and wait for the resulting [`Observation`] to match. See
+//! `tests/scenarios.rs`.
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use neems_api::orm::testing::test_rocket;
+use neems_data::rtac::{
+    RtacCollector, RtacCollectorConfig, RtacConfig,
+    control::ControlConfig,
+    latency,
+    protocol::{CommandType, RegisterMap},
+    schedule_http::ApiClientConfig,
+    state::{PendingCommand, RtacState},
+};
+use neems_rtac_sim::{
+    config::SimConfig,
+    server::{self, SharedState},
+    state::SimState,
+};
+use serde_json::{Value as JsonValue, json};
+use tokio::{
+    io::copy_bidirectional,
+    net::{TcpListener, TcpSocket, TcpStream},
+    sync::{RwLock, oneshot, watch},
+    task::{JoinHandle, JoinSet},
+};
+
+type DynError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Login the harness uses; created by neems-api's admin initialization.
+const ADMIN_EMAIL: &str = "superadmin@example.com";
+const ADMIN_PASSWORD: &str = "admin";
+
+/// How long [`Harness::start`] waits for neems-api and the collector.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often [`Harness::wait_for`] re-checks its condition.
+const OBSERVE_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Tuning for a harness run.
+///
+/// The defaults speed everything up so a scenario takes seconds: the
+/// simulator ticks every 50ms, and the collector reads every 50ms, polls
+/// neems-api every 100ms and evaluates the control logic every 50ms.
+#[derive(Debug, Clone)]
+pub struct HarnessConfig {
+    pub sim: SimConfig,
+    pub rtac: RtacConfig,
+    pub control: ControlConfig,
+    pub poll_interval: Duration,
+}
+
+impl Default for HarnessConfig {
+    fn default() -> Self {
+        Self {
+            sim: SimConfig {
+                bind_address: "127.0.0.1:0".parse().unwrap(),
+                tick_interval: Duration::from_millis(50),
+                charge_rate_pct: 0.25,
+                discharge_rate_pct: 0.25,
+                trickle_rate_pct: 0.05,
+                ..SimConfig::default()
+            },
+            rtac: RtacConfig {
+                read_interval: Duration::from_millis(50),
+                write_every_n_ticks: 2,
+                connect_timeout: Duration::from_millis(500),
+                operation_timeout: Duration::from_millis(500),
+                max_reconnect_attempts: u32::MAX,
+                reconnect_delay: Duration::from_millis(50),
+                storage_sample_rate: 10,
+                ..RtacConfig::default()
+            },
+            control: ControlConfig {
+                evaluation_interval: Duration::from_millis(50),
+                ..ControlConfig::default()
+            },
+            poll_interval: Duration::from_millis(100),
+        }
+    }
+}
+
+impl HarnessConfig {
+    /// Start the simulated battery at `soc_percent`.
+    pub fn with_initial_soc(mut self, soc_percent: f32) -> Self {
+        self.sim.initial_soc_percent = soc_percent;
+        self
+    }
+}
+
+/// Everything a scenario can assert on, captured at one moment.
+#[derive(Debug, Clone)]
+pub struct Observation {
+    /// The simulator's own state: what the "hardware" is doing
+    pub sim: SimState,
+    /// The collector's view of the RTAC, as of its last read
+    pub rtac: RtacState,
+    /// The command the control logic last handed the Modbus worker
+    pub command: Option<PendingCommand>,
+}
+
+impl Observation {
+    /// The command type the control logic last handed the worker, if any.
+    pub fn command_type(&self) -> Option<CommandType> {
+        self.command.as_ref().map(|c| c.command_type)
+    }
+}
+
+/// One command in a schedule set through the API.
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub execution_offset_seconds: i32,
+    /// `charge`, `discharge` or `trickle_charge`
+    pub command_type: &'static str,
+    pub target_soc_percent: Option<i32>,
+}
+
+impl Step {
+    /// A step that runs all day (from midnight UTC).
+    pub fn all_day(command_type: &'static str, target_soc_percent: Option<i32>) -> Self {
+        Self {
+            execution_offset_seconds: 0,
+            command_type,
+            target_soc_percent,
+        }
+    }
+}
+
+/// A TCP relay between the collector and the simulator.
+///
+/// [`cut`](Self::cut) closes the listener and every relayed connection, so the
+/// collector sees its connection drop and reconnects are refused;
+/// [`restore`](Self::restore) listens on the same address again.
+pub struct Link {
+    addr: SocketAddr,
+    upstream: SocketAddr,
+    relay: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Link {
+    /// Relay connections on a free local port to `upstream`.
+    pub async fn open(upstream: SocketAddr) -> std::io::Result<Self> {
+        let listener = Self::listen("127.0.0.1:0".parse().unwrap())?;
+        let link = Self {
+            addr: listener.local_addr()?,
+            upstream,
+            relay: Mutex::new(None),
+        };
+        link.spawn(listener);
+        Ok(link)
+    }
+
+    /// Address the collector connects to.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Drop every connection and refuse new ones.
+    pub fn cut(&self) {
+        if let Some(relay) = self.relay.lock().unwrap().take() {
+            relay.abort();
+        }
+    }
+
+    /// Accept connections again after a [`cut`](Self::cut).
+    pub async fn restore(&self) -> std::io::Result<()> {
+        // Aborting the relay only takes effect at its next await point; keep
+        // trying until the old listener has been dropped.
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        loop {
+            match Self::listen(self.addr) {
+                Ok(listener) => {
+                    self.spawn(listener);
+                    return Ok(());
+                }
+                Err(e) if tokio::time::Instant::now() >= deadline => return Err(e),
+                Err(_) => tokio::time::sleep(OBSERVE_INTERVAL).await,
+            }
+        }
+    }
+
+    fn listen(addr: SocketAddr) -> std::io::Result<TcpListener> {
+        let socket = TcpSocket::new_v4()?;
+        socket.set_reuseaddr(true)?;
+        socket.bind(addr)?;
+        socket.listen(16)
+    }
+
+    fn spawn(&self, listener: TcpListener) {
+        let upstream = self.upstream;
+        let relay = tokio::spawn(async move {
+            // Connections live in the set, so aborting the relay closes them.
+            let mut connections = JoinSet::new();
+            while let Ok((mut inbound, _)) = listener.accept().await {
+                connections.spawn(async move {
+                    if let Ok(mut outbound) = TcpStream::connect(upstream).await {
+                        let _ = copy_bidirectional(&mut inbound, &mut outbound).await;
+                    }
+                });
+            }
+        });
+        *self.relay.lock().unwrap() = Some(relay);
+    }
+}
+
+/// The RTAC collector, running on its own thread with a current-thread
+/// runtime as it does in `neems-data monitor` (the Modbus client context is
+/// not `Send`).
+struct CollectorThread {
+    state: Arc<RwLock<RtacState>>,
+    commands: watch::Receiver<Option<PendingCommand>>,
+    stop: oneshot::Sender<()>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl CollectorThread {
+    async fn start(config: RtacCollectorConfig) -> Result<Self, DynError> {
+        let (ready_tx, ready_rx) = oneshot::channel();
+        let (stop, stop_rx) = oneshot::channel::<()>();
+        let thread =
+            thread::Builder::new().name("rtac-collector".to_string()).spawn(move || {
+                let rt = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                    Ok(rt) => rt,
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(e.to_string()));
+                        return;
+                    }
+                };
+                rt.block_on(async move {
+                    let collector = match RtacCollector::start(config).await {
+                        Ok(collector) => collector,
+                        Err(e) => {
+                            let _ = ready_tx.send(Err(e.to_string()));
+                            return;
+                        }
+                    };
+                    let _ = ready_tx.send(Ok((collector.state(), collector.commands())));
+                    tokio::select! {
+                        result = collector.run() => {
+                            if let Err(e) = result {
+                                eprintln!("RTAC collector stopped: {}", e);
+                            }
+                        }
+                        _ = stop_rx => {}
+                    }
+                });
+            })?;
+
+        let (state, commands) = ready_rx.await.map_err(|_| "RTAC collector thread exited")??;
+        Ok(Self { state, commands, stop, thread })
+    }
+
+    /// Stop the collector; its tasks end with its runtime.
+    async fn stop(self) {
+        let _ = self.stop.send(());
+        let _ = tokio::task::spawn_blocking(move || self.thread.join()).await;
+    }
+}
+
+/// neems-api, the simulated RTAC and the RTAC collector, wired together.
+pub struct Harness {
+    base_url: String,
+    http: reqwest::Client,
+    session: String,
+    site_id: i32,
+    sim: SharedState,
+    link: Link,
+    collector: Option<CollectorThread>,
+    api_shutdown: rocket::Shutdown,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Harness {
+    /// Start everything with `config`, and create a site for the scenario.
+    ///
+    /// The site starts with no schedule, so the collector leaves the
+    /// simulator in standby until a scenario sets one.
+    pub async fn start(config: HarnessConfig) -> Result<Self, DynError> {
+        let mut tasks = Vec::new();
+
+        // neems-api, on a free local port.
+        let port = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        let rocket = test_rocket();
+        let site_database_url: String = rocket.figment().extract_inner("databases.site_db.url")?;
+        let figment = rocket
+            .figment()
+            .clone()
+            .merge(("address", "127.0.0.1"))
+            .merge(("port", port))
+            .merge(("log_level", "off"))
+            .merge(("shutdown.ctrlc", false));
+        let rocket = rocket.configure(figment).ignite().await?;
+        let api_shutdown = rocket.shutdown();
+        tasks.push(tokio::spawn(async move {
+            if let Err(e) = rocket.launch().await {
+                eprintln!("neems-api stopped: {}", e);
+            }
+        }));
+        let base_url = format!("http://127.0.0.1:{}", port);
+
+        // The simulated RTAC and its physics loop.
+        let tick_interval = config.sim.tick_interval;
+        let (listener, sim_addr) = server::bind(config.sim.bind_address).await?;
+        let sim: SharedState = Arc::new(Mutex::new(SimState::new(config.sim)));
+        let tick_state = sim.clone();
+        tasks.push(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(tick_interval);
+            loop {
+                interval.tick().await;
+                tick_state.lock().unwrap().tick();
+            }
+        }));
+        let server_state = sim.clone();
+        tasks.push(tokio::spawn(async move {
+            let _ = server::serve(listener, server_state).await;
+        }));
+        let link = Link::open(sim_addr).await?;
+
+        // Log in and create the scenario's site.
+        let http = reqwest::Client::builder().timeout(Duration::from_secs(10)).build()?;
+        let (session, company_name) = login(&http, &base_url).await?;
+        let mut harness = Self {
+            base_url,
+            http,
+            session,
+            site_id: 0,
+            sim,
+            link,
+            collector: None,
+            api_shutdown,
+            tasks,
+        };
+        let companies = harness.get("/api/1/Companies").await?;
+        let company_id = companies["value"]
+            .as_array()
+            .and_then(|companies| companies.iter().find(|c| c["name"] == json!(company_name)))
+            .and_then(|c| c["id"].as_i64())
+            .ok_or("admin's company not found")? as i32;
+        let site = harness
+            .post(
+                "/api/1/Sites",
+                json!({
+                    "name": "Scenario Site",
+                    "address": "1 Test Way",
+                    "latitude": 40.0,
+                    "longitude": -75.0,
+                    "company_id": company_id,
+                }),
+            )
+            .await?;
+        harness.site_id = site["id"].as_i64().ok_or("created site has no id")? as i32;
+
+        // The collector, pointed at the link and following the site's schedule.
+        let collector = CollectorThread::start(RtacCollectorConfig {
+            database_url: site_database_url,
+            site_id: harness.site_id,
+            company_id,
+            decimation: 1,
+            rtac: config.rtac.with_address(harness.link.addr()),
+            api: ApiClientConfig {
+                base_url: harness.base_url.clone(),
+                email: ADMIN_EMAIL.to_string(),
+                password: ADMIN_PASSWORD.to_string(),
+                site_id: harness.site_id,
+                poll_interval: config.poll_interval,
+            },
+            control: config.control,
+            power_quality: None,
+            latency_budget: latency::DEFAULT_BUDGET,
+        })
+        .await?;
+        harness.collector = Some(collector);
+
+        harness
+            .wait_for("the collector to connect", STARTUP_TIMEOUT, |o| o.rtac.is_healthy())
+            .await;
+        Ok(harness)
+    }
+
+    /// The scenario's site.
+    pub fn site_id(&self) -> i32 {
+        self.site_id
+    }
+
+    /// The link between the collector and the simulator.
+    pub fn link(&self) -> &Link {
+        &self.link
+    }
+
+    /// Change the simulator's state directly, as an operator at the device
+    /// would.
+    pub fn with_sim<R>(&self, f: impl FnOnce(&mut SimState) -> R) -> R {
+        f(&mut self.sim.lock().unwrap())
+    }
+
+    /// Put the simulator in standby, as if its command was reset locally.
+    pub fn reset_sim_to_standby(&self) {
+        self.with_sim(|sim| {
+            sim.write_registers(RegisterMap::CMD_COMMAND, &[CommandType::Standby.to_register()])
+        });
+    }
+
+    /// Capture the current state of the loop.
+    ///
+    /// # Panics
+    ///
+    /// If the harness has been shut down.
+    pub async fn observe(&self) -> Observation {
+        let collector = self.collector.as_ref().expect("harness is running");
+        let sim = self.sim.lock().unwrap().clone();
+        Observation {
+            sim,
+            rtac: collector.state.read().await.clone(),
+            command: collector.commands.borrow().clone(),
+        }
+    }
+
+    /// Wait until `condition` holds, returning the observation that met it.
+    ///
+    /// # Panics
+    ///
+    /// If `timeout` elapses first, with `label` and the last observation.
+    pub async fn wait_for(
+        &self,
+        label: &str,
+        timeout: Duration,
+        mut condition: impl FnMut(&Observation) -> bool,
+    ) -> Observation {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let observation = self.observe().await;
+            if condition(&observation) {
+                return observation;
+            }
+            if tokio::time::Instant::now() >= deadline {
+                panic!("timed out waiting for {}; last observation: {:#?}", label, observation);
+            }
+            tokio::time::sleep(OBSERVE_INTERVAL).await;
+        }
+    }
+
+    /// Create a schedule library item with `steps` and return it.
+    pub async fn create_schedule(&self, name: &str, steps: &[Step]) -> Result<JsonValue, DynError> {
+        let commands: Vec<JsonValue> = steps
+            .iter()
+            .map(|step| {
+                json!({
+                    "execution_offset_seconds": step.execution_offset_seconds,
+                    "command_type": step.command_type,
+                    "target_soc_percent": step.target_soc_percent,
+                })
+            })
+            .collect();
+        self.post(
+            &format!("/api/1/Sites/{}/ScheduleLibraryItems", self.site_id),
+            json!({ "name": name, "commands": commands }),
+        )
+        .await
+    }
+
+    /// Make `steps` the site's default schedule and return the library item.
+    pub async fn set_default_schedule(&self, steps: &[Step]) -> Result<JsonValue, DynError> {
+        let item = self.create_schedule("Scenario default", steps).await?;
+        self.post(
+            &format!("/api/1/ScheduleLibraryItems/{}/ApplicationRules", item["id"]),
+            json!({ "rule_type": "default" }),
+        )
+        .await?;
+        Ok(item)
+    }
+
+    /// Override today's schedule (UTC) with `steps` and return the library
+    /// item. Tomorrow is overridden too, so a run across midnight keeps it.
+    pub async fn override_today(
+        &self,
+        name: &str,
+        reason: &str,
+        steps: &[Step],
+    ) -> Result<JsonValue, DynError> {
+        let item = self.create_schedule(name, steps).await?;
+        let today = chrono::Utc::now().date_naive();
+        let dates: Vec<String> = [today, today.succ_opt().unwrap_or(today)]
+            .iter()
+            .map(|d| d.format("%Y-%m-%d").to_string())
+            .collect();
+        self.post(
+            &format!("/api/1/ScheduleLibraryItems/{}/ApplicationRules", item["id"]),
+            json!({
+                "rule_type": "specific_date",
+                "specific_dates": dates,
+                "override_reason": reason,
+            }),
+        )
+        .await?;
+        Ok(item)
+    }
+
+    /// The site's SoC history as served by neems-api.
+    pub async fn soc_history(&self) -> Result<Vec<JsonValue>, DynError> {
+        let history = self.get(&format!("/api/1/Sites/{}/SocHistory", self.site_id)).await?;
+        Ok(history["points"].as_array().cloned().unwrap_or_default())
+    }
+
+    /// Stop the collector, leaving neems-api and the simulator running so the
+    /// results can be inspected.
+    pub async fn stop_collector(&mut self) {
+        if let Some(collector) = self.collector.take() {
+            collector.stop().await;
+        }
+    }
+
+    /// Stop everything.
+    pub async fn shutdown(mut self) {
+        self.stop_collector().await;
+        self.link.cut();
+        self.api_shutdown.clone().notify();
+        for task in self.tasks.drain(..) {
+            task.abort();
+        }
+    }
+
+    async fn get(&self, path: &str) -> Result<JsonValue, DynError> {
+        let response = self
+            .http
+            .get(format!("{}{}", self.base_url, path))
+            .header(reqwest::header::COOKIE, format!("session={}", self.session))
+            .send()
+            .await?;
+        json_body(path, response).await
+    }
+
+    async fn post(&self, path: &str, body: JsonValue) -> Result<JsonValue, DynError> {
+        let response = self
+            .http
+            .post(format!("{}{}", self.base_url, path))
+            .header(reqwest::header::COOKIE, format!("session={}", self.session))
+            .json(&body)
+            .send()
+            .await?;
+        json_body(path, response).await
+    }
+}
+
+async fn json_body(path: &str, response: reqwest::Response) -> Result<JsonValue, DynError> {
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!("{} returned HTTP {}: {}", path, status, body).into());
+    }
+    Ok(response.json().await?)
+}
+
+/// Log in as the admin once neems-api is up, returning the session token and
+/// the admin's company name.
+///
+/// The session cookie is flagged `Secure`, so it's taken from the response
+/// rather than left to a cookie store (see `neems_data::rtac::schedule_http`).
+async fn login(http: &reqwest::Client, base_url: &str) -> Result<(String, String), DynError> {
+    let deadline = tokio::time::Instant::now() + STARTUP_TIMEOUT;
+    loop {
+        let response = http
+            .post(format!("{}/api/1/login", base_url))
+            .json(&json!({ "email": ADMIN_EMAIL, "password": ADMIN_PASSWORD }))
+            .send()
+            .await;
+        match response {
+            Ok(response) if response.status().is_success() => {
+                let session = response
+                    .cookies()
+                    .find(|c| c.name() == "session")
+                    .map(|c| c.value().to_string())
+                    .ok_or("login response had no session cookie")?;
+                let body: JsonValue = response.json().await?;
+                let company_name =
+                    body["company_name"].as_str().ok_or("login response has no company")?;
+                return Ok((session, company_name.to_string()));
+            }
+            Ok(response) if tokio::time::Instant::now() >= deadline => {
+                return Err(format!("login returned HTTP {}", response.status()).into());
+            }
+            Err(e) if tokio::time::Instant::now() >= deadline => return Err(e.into()),
+            _ => tokio::time::sleep(OBSERVE_INTERVAL).await,
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! Scripted scenarios driving the full control loop: schedule in neems-api,
+//! active-command poller, control logic, Modbus worker and simulated RTAC.
+
+use std::time::Duration;
+
+use neems_data::rtac::{
+    protocol::{CommandType, OperatingMode},
+    state::ConnectionStatus,
+};
+use neems_scenarios::{Harness, HarnessConfig, Step};
+
+/// Generous bound on how long any one transition may take; most take well
+/// under a second at the harness's default timings.
+const TIMEOUT: Duration = Duration::from_secs(15);
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn price_spike_override_switches_to_discharge() {
+    let mut harness = Harness::start(HarnessConfig::default().with_initial_soc(50.0))
+        .await
+        .expect("harness");
+
+    // The site normally charges all day.
+    harness
+        .set_default_schedule(&[Step::all_day("charge", Some(90))])
+        .await
+        .expect("schedule");
+    let charging = harness
+        .wait_for("the battery to charge", TIMEOUT, |o| {
+            o.sim.mode == OperatingMode::Charging && o.sim.soc_percent > 50.0
+        })
+        .await;
+    assert_eq!(charging.command_type(), Some(CommandType::Charge));
+
+    // Prices spike: the operator overrides today with a discharge.
+    let spike = harness
+        .override_today("Price spike", "Evening price spike", &[Step::all_day("discharge", None)])
+        .await
+        .expect("override");
+    let spike_command_id = spike["commands"][0]["id"].as_i64().expect("command id");
+    let discharging = harness
+        .wait_for("the override to reach the RTAC", TIMEOUT, |o| {
+            o.sim.mode == OperatingMode::Discharging
+        })
+        .await;
+    let command = discharging.command.clone().expect("a command");
+    assert_eq!(command.command_type, CommandType::Discharge);
+    assert_eq!(command.source_id, Some(spike_command_id));
+
+    let peak = discharging.sim.soc_percent;
+    let drained = harness
+        .wait_for("the battery to discharge", TIMEOUT, |o| o.sim.soc_percent < peak - 2.0)
+        .await;
+    assert!(drained.sim.power_kw > 0.0, "discharging reports positive power");
+
+    // The collector stored what it read, and neems-api serves it.
+    harness.stop_collector().await;
+    let history = harness.soc_history().await.expect("SoC history");
+    assert!(!history.is_empty(), "RTAC readings should be stored");
+
+    harness.shutdown().await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn comm_loss_clears_command_and_recovers() {
+    let harness = Harness::start(HarnessConfig::default().with_initial_soc(80.0))
+        .await
+        .expect("harness");
+
+    harness
+        .set_default_schedule(&[Step::all_day("discharge", None)])
+        .await
+        .expect("schedule");
+    harness
+        .wait_for("the battery to discharge", TIMEOUT, |o| {
+            o.sim.mode == OperatingMode::Discharging
+        })
+        .await;
+
+    // Lose the link: the collector notices and stops commanding the RTAC.
+    harness.link().cut();
+    let lost = harness
+        .wait_for("the collector to drop the command", TIMEOUT, |o| {
+            o.rtac.connection_status != ConnectionStatus::Connected && o.command.is_none()
+        })
+        .await;
+    // The RTAC itself carries on with its last command.
+    assert_eq!(lost.sim.mode, OperatingMode::Discharging);
+
+    // While the link is down the RTAC is put in standby locally.
+    harness.reset_sim_to_standby();
+    harness
+        .wait_for("the simulator to stand by", TIMEOUT, |o| o.sim.mode == OperatingMode::Standby)
+        .await;
+
+    // Once the link is back the schedule is applied again.
+    harness.link().restore().await.expect("restore link");
+    let recovered = harness
+        .wait_for("the schedule to be re-applied", TIMEOUT, |o| {
+            o.rtac.connection_status == ConnectionStatus::Connected
+                && o.sim.mode == OperatingMode::Discharging
+        })
+        .await;
+    assert_eq!(recovered.command_type(), Some(CommandType::Discharge));
+
+    harness.shutdown().await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn soc_depletion_holds_standby_at_low_threshold() {
+    let mut config = HarnessConfig::default().with_initial_soc(16.0);
+    config.sim.discharge_rate_pct = 0.1;
+    let low_soc_threshold = config.control.low_soc_threshold;
+    let harness = Harness::start(config).await.expect("harness");
+
+    // A discharge scheduled for the whole day would empty the battery.
+    harness
+        .set_default_schedule(&[Step::all_day("discharge", None)])
+        .await
+        .expect("schedule");
+    harness
+        .wait_for("the battery to discharge", TIMEOUT, |o| {
+            o.sim.mode == OperatingMode::Discharging
+        })
+        .await;
+
+    // Reaching the low threshold forces standby.
+    let stopped = harness
+        .wait_for("the low-SoC standby", TIMEOUT, |o| {
+            o.sim.mode == OperatingMode::Standby && o.sim.soc_percent <= low_soc_threshold
+        })
+        .await;
+    let command = stopped.command.clone().expect("a command");
+    assert_eq!(command.command_type, CommandType::Standby);
+    assert_eq!(command.source_id, None, "standby is reactive, not scheduled");
+    assert!(
+        stopped.sim.soc_percent > low_soc_threshold - 2.0,
+        "standby should come promptly, stopped at {}%",
+        stopped.sim.soc_percent
+    );
+
+    // The schedule still asks for a discharge, but standby holds.
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    let held = harness.observe().await;
+    assert_eq!(held.sim.mode, OperatingMode::Standby);
+    assert_eq!(held.command_type(), Some(CommandType::Standby));
+    assert_eq!(held.sim.soc_percent, stopped.sim.soc_percent, "SoC should not keep falling");
+
+    harness.shutdown().await;
+}